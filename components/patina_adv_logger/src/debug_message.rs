@@ -0,0 +1,484 @@
+//! Debug Message Formatting
+//!
+//! Support for routing debug messages produced by non-Rust drivers to the [log] crate, so they end up in the same
+//! log sink, with the same formatting, as `log::*` output.
+//!
+//! Messages from the EDKII `DEBUG` macro are reported with a `printf` style format string and the arguments
+//! flattened into a fixed size argument list (a `BASE_LIST`). [format_message] expands such a message following the
+//! EDKII `PrintLib` conventions without a C variable argument list: arguments are only ever read from the provided
+//! slice, and missing arguments are treated as zero.
+//!
+//! Formatting and buffering use fixed size buffers only, as messages can be reported at `TPL_HIGH_LEVEL` where
+//! memory allocation is not allowed. Messages longer than [MAX_MESSAGE_SIZE] are truncated.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use core::fmt::{self, Write};
+
+use log::Level;
+use patina::error::EfiError;
+use r_efi::efi;
+use spin::Mutex;
+
+use crate::memory_log;
+
+/// The maximum size of a single line of a debug message, in bytes.
+pub const MAX_MESSAGE_SIZE: usize = 256;
+
+/// Converts an EDKII debug error level to a [log::Level].
+pub const fn debug_level_to_log_level(error_level: u32) -> Level {
+    if error_level & memory_log::DEBUG_LEVEL_ERROR != 0 {
+        Level::Error
+    } else if error_level & memory_log::DEBUG_LEVEL_WARNING != 0 {
+        Level::Warn
+    } else if error_level & memory_log::DEBUG_LEVEL_INFO != 0 {
+        Level::Info
+    } else if error_level & memory_log::DEBUG_LEVEL_VERBOSE != 0 {
+        Level::Trace
+    } else {
+        Level::Debug
+    }
+}
+
+/// A fixed size buffer for a message, truncating anything that does not fit.
+pub(crate) struct MessageBuffer {
+    buffer: [u8; MAX_MESSAGE_SIZE],
+    len: usize,
+}
+
+impl MessageBuffer {
+    /// Creates an empty message buffer.
+    pub(crate) const fn new() -> Self {
+        Self { buffer: [0; MAX_MESSAGE_SIZE], len: 0 }
+    }
+
+    /// Appends the bytes that fit in the buffer, returning the number of bytes appended.
+    pub(crate) fn push_bytes(&mut self, bytes: &[u8]) -> usize {
+        let count = bytes.len().min(MAX_MESSAGE_SIZE - self.len);
+        self.buffer[self.len..self.len + count].copy_from_slice(&bytes[..count]);
+        self.len += count;
+        count
+    }
+
+    /// Returns the bytes of the message.
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+
+    /// Returns true if the buffer is empty.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Empties the buffer.
+    pub(crate) fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+impl Write for MessageBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// Displays the bytes of a message as text, replacing anything that is not printable ASCII.
+struct Ascii<'a>(&'a [u8]);
+
+impl fmt::Display for Ascii<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|&byte| match byte {
+            b' '..=b'~' | b'\t' => f.write_char(byte as char),
+            _ => f.write_char('?'),
+        })
+    }
+}
+
+/// Splits a stream of message bytes into lines, logging each complete line as a separate log record.
+///
+/// Writers may produce a line with multiple writes, so incomplete lines are buffered until the end of the line is
+/// written. A line longer than [MAX_MESSAGE_SIZE] is logged in multiple records.
+pub(crate) struct LineWriter {
+    target: &'static str,
+    line: Mutex<MessageBuffer>,
+}
+
+impl LineWriter {
+    /// Creates a line writer logging to the provided target.
+    pub(crate) const fn new(target: &'static str) -> Self {
+        Self { target, line: Mutex::new(MessageBuffer::new()) }
+    }
+
+    /// Writes message bytes, logging every completed line with the provided level.
+    pub(crate) fn write(&self, level: Level, bytes: &[u8]) {
+        // A message reported while a message is being logged (e.g. by the logger itself) is dropped rather than
+        // deadlocking.
+        let Some(mut line) = self.line.try_lock() else {
+            return;
+        };
+        split_lines(&mut line, bytes, |text| log::log!(target: self.target, level, "{}", Ascii(text)));
+    }
+
+    /// Logs any buffered incomplete line with the provided level.
+    pub(crate) fn flush(&self, level: Level) {
+        if let Some(mut line) = self.line.try_lock()
+            && !line.is_empty()
+        {
+            log::log!(target: self.target, level, "{}", Ascii(line.as_bytes()));
+            line.clear();
+        }
+    }
+}
+
+/// Appends `bytes` to the buffered `line`, calling `emit` for every completed (or full) line.
+fn split_lines(line: &mut MessageBuffer, mut bytes: &[u8], mut emit: impl FnMut(&[u8])) {
+    while !bytes.is_empty() {
+        let end = bytes.iter().position(|&byte| byte == b'\n');
+        let chunk = &bytes[..end.unwrap_or(bytes.len())];
+        let written = line.push_bytes(chunk);
+
+        if written < chunk.len() || end.is_some() {
+            let text = line.as_bytes();
+            emit(text.strip_suffix(b"\r").unwrap_or(text));
+            line.clear();
+        }
+
+        bytes = &bytes[written..];
+        if written == chunk.len() && end.is_some() {
+            bytes = &bytes[1..];
+        }
+    }
+}
+
+/// A parsed `%` conversion specification.
+#[derive(Default)]
+struct Spec {
+    left_justify: bool,
+    zero_pad: bool,
+    plus: bool,
+    blank: bool,
+    long: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+/// Expands a `PrintLib` style format string with the provided arguments.
+///
+/// Each argument occupies one entry of `args`, matching a 64-bit `BASE_LIST`. Formatting stops at the end of
+/// `format` or at the first NUL byte. The following conversions are supported: `%a` (ASCII string), `%s` / `%S`
+/// (UCS-2 string), `%c`, `%d` / `%i`, `%u`, `%x` / `%X`, `%p`, `%g` (GUID), `%r` (status) and `%%`, along with the
+/// `-`, `+`, ` `, `0`, `l` / `L`, width, `*` and precision flags.
+///
+/// ## Safety
+///
+/// Arguments consumed by a `%a`, `%s`, `%S` or `%g` conversion must either be null or valid pointers to the type
+/// expected by the conversion. Strings are read up to their NUL terminator or [MAX_MESSAGE_SIZE] characters.
+pub unsafe fn format_message(out: &mut impl Write, format: &[u8], args: &[u64]) -> fmt::Result {
+    let mut args = args.iter().copied();
+    let mut next_arg = move || args.next().unwrap_or(0);
+    let mut format = format.iter().copied().take_while(|&c| c != 0).peekable();
+
+    while let Some(c) = format.next() {
+        if c != b'%' {
+            out.write_char(c as char)?;
+            continue;
+        }
+
+        let mut spec = Spec::default();
+        let mut in_precision = false;
+        while let Some(&flag) = format.peek() {
+            match flag {
+                b'-' => spec.left_justify = true,
+                b'+' => spec.plus = true,
+                b' ' => spec.blank = true,
+                b',' => (),
+                b'l' | b'L' => spec.long = true,
+                b'.' => {
+                    in_precision = true;
+                    spec.precision = Some(0);
+                }
+                b'*' if in_precision => spec.precision = Some((next_arg() as usize).min(MAX_MESSAGE_SIZE)),
+                b'*' => spec.width = (next_arg() as usize).min(MAX_MESSAGE_SIZE),
+                b'0' if !in_precision && spec.width == 0 => spec.zero_pad = true,
+                b'0'..=b'9' => {
+                    let digit = (flag - b'0') as usize;
+                    let value = if in_precision { spec.precision.get_or_insert(0) } else { &mut spec.width };
+                    *value = value.saturating_mul(10).saturating_add(digit).min(MAX_MESSAGE_SIZE);
+                }
+                _ => break,
+            }
+            format.next();
+        }
+
+        let Some(conversion) = format.next() else {
+            break;
+        };
+
+        match conversion {
+            b'd' | b'i' => {
+                let value = if spec.long { next_arg() as i64 } else { next_arg() as u32 as i32 as i64 };
+                write_number(out, value < 0, value.unsigned_abs(), 10, &spec)?;
+            }
+            b'u' => {
+                let value = if spec.long { next_arg() } else { next_arg() as u32 as u64 };
+                write_number(out, false, value, 10, &Spec { plus: false, blank: false, ..spec })?;
+            }
+            b'x' | b'X' => {
+                let value = if spec.long { next_arg() } else { next_arg() as u32 as u64 };
+                write_number(out, false, value, 16, &Spec { plus: false, blank: false, ..spec })?;
+            }
+            b'p' => {
+                let spec = Spec { plus: false, blank: false, zero_pad: false, ..spec };
+                write_number(out, false, next_arg(), 16, &spec)?;
+            }
+            b'c' => {
+                let c = char::from_u32(next_arg() as u16 as u32).unwrap_or('?');
+                write_padded(out, &spec, 1, |out| out.write_char(c))?;
+            }
+            b'a' => {
+                // SAFETY: The caller guarantees that the argument is a valid ASCII string pointer, or null.
+                let string = unsafe { ascii_string(next_arg(), spec.precision) };
+                match string {
+                    Some(string) => write_padded(out, &spec, string.len(), |out| write!(out, "{}", Ascii(string)))?,
+                    None => write_padded(out, &spec, 13, |out| out.write_str("<null string>"))?,
+                }
+            }
+            b's' | b'S' => {
+                // SAFETY: The caller guarantees that the argument is a valid UCS-2 string pointer, or null.
+                let string = unsafe { ucs2_string(next_arg(), spec.precision) };
+                match string {
+                    Some(string) => write_padded(out, &spec, string.len(), |out| {
+                        string.iter().try_for_each(|&c| out.write_char(char::from_u32(c as u32).unwrap_or('?')))
+                    })?,
+                    None => write_padded(out, &spec, 13, |out| out.write_str("<null string>"))?,
+                }
+            }
+            b'g' => {
+                let guid = next_arg() as *const efi::Guid;
+                // SAFETY: The caller guarantees that the argument is a valid GUID pointer, or null.
+                match unsafe { guid.as_ref() } {
+                    Some(guid) => write_padded(out, &spec, 36, |out| write_guid(out, guid))?,
+                    None => write_padded(out, &spec, 11, |out| out.write_str("<null guid>"))?,
+                }
+            }
+            b'r' => {
+                let status = efi::Status::from_usize(next_arg() as usize);
+                let mut name = MessageBuffer::new();
+                match EfiError::status_to_result(status) {
+                    Ok(()) => write!(name, "Success")?,
+                    Err(_) if !status.is_error() => write!(name, "Warning {:#x}", status.as_usize())?,
+                    Err(err) => write!(name, "{err:?}")?,
+                }
+                let len = name.as_bytes().len();
+                write_padded(out, &spec, len, |out| write!(out, "{}", Ascii(name.as_bytes())))?;
+            }
+            b'%' => out.write_char('%')?,
+            other => {
+                out.write_char('%')?;
+                out.write_char(other as char)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes a number with the requested sign, padding and justification.
+fn write_number(out: &mut impl Write, negative: bool, magnitude: u64, radix: u64, spec: &Spec) -> fmt::Result {
+    const DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+
+    let mut digits = [0_u8; 20];
+    let mut start = digits.len();
+    let mut value = magnitude;
+    loop {
+        start -= 1;
+        digits[start] = DIGITS[(value % radix) as usize];
+        value /= radix;
+        if value == 0 {
+            break;
+        }
+    }
+    let digits = &digits[start..];
+
+    let sign = match (negative, spec.plus, spec.blank) {
+        (true, _, _) => "-",
+        (false, true, _) => "+",
+        (false, false, true) => " ",
+        _ => "",
+    };
+    let padding = spec.width.saturating_sub(sign.len() + digits.len());
+
+    if spec.left_justify {
+        out.write_str(sign)?;
+        write!(out, "{}", Ascii(digits))?;
+        write_repeated(out, ' ', padding)
+    } else if spec.zero_pad {
+        out.write_str(sign)?;
+        write_repeated(out, '0', padding)?;
+        write!(out, "{}", Ascii(digits))
+    } else {
+        write_repeated(out, ' ', padding)?;
+        out.write_str(sign)?;
+        write!(out, "{}", Ascii(digits))
+    }
+}
+
+/// Writes a value of `len` characters, padded with spaces to the requested width.
+fn write_padded<W: Write>(
+    out: &mut W,
+    spec: &Spec,
+    len: usize,
+    write: impl FnOnce(&mut W) -> fmt::Result,
+) -> fmt::Result {
+    let padding = spec.width.saturating_sub(len);
+    if !spec.left_justify {
+        write_repeated(out, ' ', padding)?;
+    }
+    write(out)?;
+    if spec.left_justify {
+        write_repeated(out, ' ', padding)?;
+    }
+    Ok(())
+}
+
+fn write_repeated(out: &mut impl Write, c: char, count: usize) -> fmt::Result {
+    (0..count).try_for_each(|_| out.write_char(c))
+}
+
+fn write_guid(out: &mut impl Write, guid: &efi::Guid) -> fmt::Result {
+    let (time_low, time_mid, time_hi, clk_seq_hi, clk_seq_low, node) = guid.as_fields();
+    write!(out, "{time_low:08X}-{time_mid:04X}-{time_hi:04X}-{clk_seq_hi:02X}{clk_seq_low:02X}-")?;
+    node.iter().try_for_each(|byte| write!(out, "{byte:02X}"))
+}
+
+/// Returns the bytes of a NUL terminated ASCII string, limited to `precision` and [MAX_MESSAGE_SIZE] characters.
+///
+/// ## Safety
+///
+/// `address` must be null or point to a valid NUL terminated string.
+unsafe fn ascii_string<'a>(address: u64, precision: Option<usize>) -> Option<&'a [u8]> {
+    let string = address as *const u8;
+    if string.is_null() {
+        return None;
+    }
+
+    let max = precision.unwrap_or(MAX_MESSAGE_SIZE).min(MAX_MESSAGE_SIZE);
+    // SAFETY: The caller guarantees the string is NUL terminated, and it is not read past its terminator.
+    let len = (0..max).find(|&i| unsafe { string.add(i).read() } == 0).unwrap_or(max);
+    // SAFETY: All `len` bytes were read above.
+    Some(unsafe { core::slice::from_raw_parts(string, len) })
+}
+
+/// Returns the characters of a NUL terminated UCS-2 string, limited to `precision` and [MAX_MESSAGE_SIZE] characters.
+///
+/// ## Safety
+///
+/// `address` must be null or point to a valid, aligned NUL terminated string.
+unsafe fn ucs2_string<'a>(address: u64, precision: Option<usize>) -> Option<&'a [u16]> {
+    let string = address as *const u16;
+    if string.is_null() {
+        return None;
+    }
+
+    let max = precision.unwrap_or(MAX_MESSAGE_SIZE).min(MAX_MESSAGE_SIZE);
+    // SAFETY: The caller guarantees the string is NUL terminated, and it is not read past its terminator.
+    let len = (0..max).find(|&i| unsafe { string.add(i).read_unaligned() } == 0).unwrap_or(max);
+    // SAFETY: All `len` characters were read above.
+    Some(unsafe { core::slice::from_raw_parts(string, len) })
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::string::String;
+
+    fn format(format: &str, args: &[u64]) -> String {
+        let mut out = String::new();
+        unsafe { format_message(&mut out, format.as_bytes(), args) }.unwrap();
+        out
+    }
+
+    #[test]
+    fn test_format_integers() {
+        assert_eq!(format("%d %d %ld", &[5, 0xFFFF_FFFF, u64::MAX]), "5 -1 -1");
+        assert_eq!(format("%u %lu", &[0xFFFF_FFFF_0000_0001, 0xFFFF_FFFF_0000_0001]), "1 18446744069414584321");
+        assert_eq!(format("%x %X %lx", &[0xab, 0xab, 0x1_0000_00ab]), "AB AB 1000000AB");
+        assert_eq!(format("%08x|%-4d|%4d|%+d|%-+5d|", &[0xbeef, 7, 7, 7, 7]), "0000BEEF|7   |   7|+7|+7   |");
+        assert_eq!(format("%05d %*d", &[(-42_i32) as u32 as u64, 3, 1]), "-0042   1");
+        assert_eq!(format("%p", &[0x7EE3_A018]), "7EE3A018");
+    }
+
+    #[test]
+    fn test_format_strings() {
+        let ascii = b"ascii\0";
+        let ucs2: std::vec::Vec<u16> = "ucs2\0".encode_utf16().collect();
+        let args = [ascii.as_ptr() as u64, ucs2.as_ptr() as u64, 0, ascii.as_ptr() as u64, 'c' as u64];
+
+        assert_eq!(format("%a %s %a %.3a %c", &args), "ascii ucs2 <null string> asc c");
+        assert_eq!(format("[%8a][%-8a]", &[ascii.as_ptr() as u64, ascii.as_ptr() as u64]), "[   ascii][ascii   ]");
+    }
+
+    #[test]
+    fn test_format_guid_and_status() {
+        let guid =
+            efi::Guid::from_fields(0x9A4E9246, 0xD553, 0x11D5, 0x87, 0xE2, &[0x00, 0x06, 0x29, 0x45, 0xC3, 0xB9]);
+        assert_eq!(format("%g", &[&guid as *const efi::Guid as u64]), "9A4E9246-D553-11D5-87E2-00062945C3B9");
+        assert_eq!(format("%g", &[0]), "<null guid>");
+
+        let statuses = [efi::Status::SUCCESS, efi::Status::NOT_FOUND, efi::Status::WARN_UNKNOWN_GLYPH];
+        let args = statuses.map(|status| status.as_usize() as u64);
+        assert_eq!(format("%r, %r, %r", &args), "Success, NotFound, Warning 0x1");
+    }
+
+    #[test]
+    fn test_format_is_bounded() {
+        // Missing arguments are read as zero, instead of reading past the argument list.
+        assert_eq!(format("%d %x %a", &[1]), "1 0 <null string>");
+        // Formatting stops at the NUL terminator.
+        assert_eq!(format("abc\0%a", &[1]), "abc");
+        assert_eq!(format("100%% %q %", &[]), "100% %q ");
+
+        // Output longer than the message buffer is truncated.
+        let mut buffer = MessageBuffer::new();
+        unsafe { format_message(&mut buffer, b"%300d", &[1]) }.unwrap();
+        assert_eq!(buffer.as_bytes().len(), MAX_MESSAGE_SIZE);
+    }
+
+    #[test]
+    fn test_split_lines() {
+        let mut line = MessageBuffer::new();
+        let mut lines = std::vec::Vec::new();
+        let mut emit = |text: &[u8]| lines.push(String::from_utf8(text.to_vec()).unwrap());
+
+        split_lines(&mut line, b"first ", &mut emit);
+        split_lines(&mut line, b"line\r\nsecond line\n\nthird", &mut emit);
+        assert_eq!(line.as_bytes(), b"third");
+
+        let long = [b'a'; MAX_MESSAGE_SIZE + 10];
+        line.clear();
+        split_lines(&mut line, &long, &mut emit);
+        assert_eq!(lines[..3], ["first line", "second line", ""]);
+        assert_eq!(lines[3].len(), MAX_MESSAGE_SIZE);
+        assert_eq!(line.as_bytes().len(), 10);
+    }
+
+    #[test]
+    fn test_debug_level_conversion() {
+        assert_eq!(
+            debug_level_to_log_level(memory_log::DEBUG_LEVEL_ERROR | memory_log::DEBUG_LEVEL_INFO),
+            Level::Error
+        );
+        assert_eq!(debug_level_to_log_level(memory_log::DEBUG_LEVEL_WARNING), Level::Warn);
+        assert_eq!(debug_level_to_log_level(memory_log::DEBUG_LEVEL_INFO), Level::Info);
+        assert_eq!(debug_level_to_log_level(memory_log::DEBUG_LEVEL_VERBOSE), Level::Trace);
+        assert_eq!(debug_level_to_log_level(0x4), Level::Debug);
+    }
+}
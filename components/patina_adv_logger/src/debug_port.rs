@@ -0,0 +1,187 @@
+//! UEFI Debug Port Protocol Support
+//!
+//! This module provides a component that installs the UEFI Debug Port Protocol. Bytes written to the debug port are
+//! split into lines and logged through the [log] crate, so output from drivers that write to the debug port ends up
+//! in the same log, with the same formatting, as `log::*` output.
+//!
+//! The debug port is output only: reads time out without returning data and polls report that no data is available.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::boxed::Box;
+use core::{ffi::c_void, slice};
+use log::Level;
+use patina::{
+    boot_services::{BootServices, StandardBootServices},
+    component::IntoComponent,
+    error::{EfiError, Result},
+    uefi_protocol::ProtocolInterface,
+};
+use r_efi::efi;
+
+use crate::debug_message::LineWriter;
+
+/// The log target of messages written to the debug port.
+pub const DEBUG_PORT_LOG_TARGET: &str = patina::log::target::DEBUG_PORT;
+
+static WRITER: LineWriter = LineWriter::new(DEBUG_PORT_LOG_TARGET);
+
+/// Function definition for the reset and poll functions of the Debug Port protocol.
+type DebugPortControl = extern "efiapi" fn(*mut DebugPortProtocol) -> efi::Status;
+
+/// Function definition for the read and write functions of the Debug Port protocol.
+type DebugPortTransfer = extern "efiapi" fn(*mut DebugPortProtocol, u32, *mut usize, *mut c_void) -> efi::Status;
+
+/// C struct for the UEFI Debug Port protocol.
+///
+/// <https://uefi.org/specs/UEFI/2.10/18_Protocols_Debugger_Support.html#efi-debugport-protocol>
+#[repr(C)]
+pub struct DebugPortProtocol {
+    /// Resets the debug port.
+    pub reset: DebugPortControl,
+    /// Writes bytes to the debug port.
+    pub write: DebugPortTransfer,
+    /// Reads bytes from the debug port.
+    pub read: DebugPortTransfer,
+    /// Checks if there is any data available to be read from the debug port.
+    pub poll: DebugPortControl,
+}
+
+unsafe impl ProtocolInterface for DebugPortProtocol {
+    const PROTOCOL_GUID: efi::Guid = efi::protocols::debugport::PROTOCOL_GUID;
+}
+
+impl DebugPortProtocol {
+    /// Creates a Debug Port protocol that logs written bytes.
+    const fn new() -> Self {
+        Self { reset: debug_port_reset, write: debug_port_write, read: debug_port_read, poll: debug_port_poll }
+    }
+}
+
+/// The component that will install the Debug Port protocol.
+#[derive(IntoComponent, Default)]
+pub struct DebugPortComponent;
+
+impl DebugPortComponent {
+    /// Entry point to the DebugPortComponent.
+    ///
+    /// Installs the Debug Port Protocol for use by non-local components.
+    ///
+    fn entry_point(self, bs: StandardBootServices) -> Result<()> {
+        match bs.install_protocol_interface(None, Box::new(DebugPortProtocol::new())) {
+            Err(status) => {
+                log::error!("Failed to install Debug Port protocol! Status = {status:#x?}");
+                Err(EfiError::ProtocolError)
+            }
+            Ok(_) => {
+                log::info!("Debug Port protocol installed.");
+                Ok(())
+            }
+        }
+    }
+}
+
+/// EFI API to reset the debug port. Logs any incomplete line written to the port.
+extern "efiapi" fn debug_port_reset(_this: *mut DebugPortProtocol) -> efi::Status {
+    WRITER.flush(Level::Info);
+    efi::Status::SUCCESS
+}
+
+/// EFI API to write to the debug port. All bytes are always written.
+extern "efiapi" fn debug_port_write(
+    _this: *mut DebugPortProtocol,
+    _timeout: u32,
+    buffer_size: *mut usize,
+    buffer: *mut c_void,
+) -> efi::Status {
+    if buffer_size.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+
+    // SAFETY: buffer_size was checked for null, the rest must be trusted from the caller.
+    let size = unsafe { buffer_size.read_unaligned() };
+    if size == 0 {
+        return efi::Status::SUCCESS;
+    }
+    if buffer.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+
+    // SAFETY: We have no choice but to trust the caller on the buffer size.
+    let data = unsafe { slice::from_raw_parts(buffer as *const u8, size) };
+    WRITER.write(Level::Info, data);
+    efi::Status::SUCCESS
+}
+
+/// EFI API to read from the debug port. No data is ever available.
+extern "efiapi" fn debug_port_read(
+    _this: *mut DebugPortProtocol,
+    _timeout: u32,
+    buffer_size: *mut usize,
+    _buffer: *mut c_void,
+) -> efi::Status {
+    if buffer_size.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+
+    // SAFETY: buffer_size was checked for null.
+    unsafe { buffer_size.write_unaligned(0) };
+    efi::Status::TIMEOUT
+}
+
+/// EFI API to poll the debug port. No data is ever available.
+extern "efiapi" fn debug_port_poll(_this: *mut DebugPortProtocol) -> efi::Status {
+    efi::Status::NOT_READY
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use core::ptr;
+
+    use super::*;
+
+    #[test]
+    fn test_debug_port_write() {
+        let mut protocol = DebugPortProtocol::new();
+        let mut message = *b"debug port message\r\n";
+        let mut size = message.len();
+
+        let status = (protocol.write)(&mut protocol, 0, &mut size, message.as_mut_ptr() as *mut c_void);
+        assert_eq!(status, efi::Status::SUCCESS);
+        assert_eq!(size, message.len());
+
+        let mut size = 0;
+        assert_eq!((protocol.write)(&mut protocol, 0, &mut size, ptr::null_mut()), efi::Status::SUCCESS);
+
+        let mut size = 1;
+        assert_eq!((protocol.write)(&mut protocol, 0, &mut size, ptr::null_mut()), efi::Status::INVALID_PARAMETER);
+        assert_eq!(
+            (protocol.write)(&mut protocol, 0, ptr::null_mut(), message.as_mut_ptr() as *mut c_void),
+            efi::Status::INVALID_PARAMETER
+        );
+
+        assert_eq!((protocol.reset)(&mut protocol), efi::Status::SUCCESS);
+    }
+
+    #[test]
+    fn test_debug_port_has_no_input() {
+        let mut protocol = DebugPortProtocol::new();
+        let mut buffer = [0_u8; 8];
+        let mut size = buffer.len();
+
+        let status = (protocol.read)(&mut protocol, 100, &mut size, buffer.as_mut_ptr() as *mut c_void);
+        assert_eq!(status, efi::Status::TIMEOUT);
+        assert_eq!(size, 0);
+        assert_eq!(
+            (protocol.read)(&mut protocol, 100, ptr::null_mut(), ptr::null_mut()),
+            efi::Status::INVALID_PARAMETER
+        );
+
+        assert_eq!((protocol.poll)(&mut protocol), efi::Status::NOT_READY);
+    }
+}
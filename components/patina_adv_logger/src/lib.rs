@@ -44,6 +44,21 @@
 //! For the protocol to be created for use of by external components, the platform
 //! should invoke patina_dxe_core.start with the advanced logger component.
 //!
+//! Output from components that do not use the log crate can be routed to the same
+//! log with the [DebugPortComponent](debug_port::DebugPortComponent), which installs
+//! the UEFI Debug Port protocol, and the [StatusCodeLogComponent](status_code::StatusCodeLogComponent),
+//! which logs debug messages reported as status codes (e.g. EDKII DEBUG() output
+//! using DebugLibReportStatusCode).
+//!
+//! The level of selected log targets can be changed at runtime with a [LevelTable](patina::log::LevelTable)
+//! given to the logger with [with_level_table](logger::AdvancedLogger::with_level_table). The
+//! [LogLevelVariableComponent](log_level::LogLevelVariableComponent) sets the levels of the table from a variable,
+//! so verbose logging can be turned on in the field without rebuilding the firmware.
+//!
+//! Logging from ExitBootServices on is kept in a runtime log ring published by the
+//! [RuntimeLogComponent](runtime_log::RuntimeLogComponent), which the OS can retrieve
+//! through a configuration table.
+//!
 //! ## License
 //!
 //! Copyright (c) Microsoft Corporation.
@@ -56,11 +71,16 @@
 extern crate alloc;
 
 pub mod component;
+pub mod debug_port;
+pub mod log_level;
 pub mod logger;
 pub mod protocol;
+pub mod runtime_log;
+pub mod status_code;
 
 #[cfg(feature = "std")]
 pub mod parser;
 
+mod debug_message;
 mod integration_test;
 mod memory_log;
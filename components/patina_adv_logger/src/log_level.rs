@@ -0,0 +1,223 @@
+//! Runtime Log Level Support
+//!
+//! This module provides a component that sets the levels of a [LevelTable] from the `PatinaLogLevels` variable, so
+//! verbose logging (e.g. of the GCD or the dispatcher) can be turned on in the field without rebuilding the firmware.
+//!
+//! The variable holds comma separated `target=level` settings as an ASCII string, in the format accepted by
+//! [LevelTable::apply], e.g. `patina_dxe_core::gcd=debug,patina_dxe_core::dispatcher=trace`. It is read once the
+//! Variable Architectural Protocol is installed, so logging that happens before variable services are available is
+//! filtered by the target filters of the logger only.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::vec::Vec;
+use core::ptr;
+use patina::{
+    Ucs2Str,
+    boot_services::{
+        BootServices, StandardBootServices,
+        event::{EventBuilder, EventType},
+        tpl::Tpl,
+    },
+    component::IntoComponent,
+    error::Result,
+    log::LevelTable,
+    runtime_services::{RuntimeServices, StandardRuntimeServices},
+    ucs2,
+};
+use patina_pi::protocols::variable_arch;
+use r_efi::efi;
+
+/// The vendor GUID of the [LOG_LEVELS_VARIABLE_NAME] variable.
+pub const LOG_LEVELS_VARIABLE_GUID: efi::Guid = patina::guid!("425C7066-B017-449B-8347-669E4ED9F6B5");
+
+/// The name of the variable holding the log level settings.
+pub const LOG_LEVELS_VARIABLE_NAME: &Ucs2Str = ucs2!("PatinaLogLevels");
+
+/// The component that will set the levels of a [LevelTable] from the log levels variable.
+///
+/// The same table must be given to the logger, e.g. with
+/// [AdvancedLogger::with_level_table](crate::logger::AdvancedLogger::with_level_table).
+#[derive(IntoComponent)]
+pub struct LogLevelVariableComponent {
+    level_table: &'static LevelTable<'static>,
+}
+
+/// Context of the event signaled when the Variable Architectural Protocol is installed.
+struct LogLevelContext<RR> {
+    level_table: &'static LevelTable<'static>,
+    runtime_services: RR,
+}
+
+impl LogLevelVariableComponent {
+    /// Creates a new LogLevelVariableComponent setting the levels of `level_table`.
+    pub const fn new(level_table: &'static LevelTable<'static>) -> Self {
+        Self { level_table }
+    }
+
+    /// Entry point to the LogLevelVariableComponent.
+    ///
+    /// Applies the log levels variable, or registers for notification of the installation of the Variable
+    /// Architectural Protocol if variables cannot be read yet.
+    ///
+    fn entry_point(self, bs: StandardBootServices, rs: StandardRuntimeServices) -> Result<()> {
+        self._entry_point(bs, rs)
+    }
+
+    /// Entry point that has generic parameters.
+    fn _entry_point<BB, B, RR, R>(self, boot_services: BB, runtime_services: RR) -> Result<()>
+    where
+        BB: AsRef<B> + Clone + 'static,
+        B: BootServices + 'static,
+        RR: AsRef<R> + Clone + 'static,
+        R: RuntimeServices + 'static,
+    {
+        // SAFETY: The Variable Architectural Protocol has no interface, only its presence is checked.
+        match unsafe {
+            boot_services.as_ref().locate_protocol_unchecked(&variable_arch::PROTOCOL_GUID, ptr::null_mut())
+        } {
+            Ok(_) => {
+                apply_log_levels(self.level_table, runtime_services.as_ref());
+                return Ok(());
+            }
+            Err(efi::Status::NOT_FOUND) => (),
+            Err(status) => return Err(status.into()),
+        }
+
+        let context = LogLevelContext { level_table: self.level_table, runtime_services };
+        let event = EventBuilder::new(BB::clone(&boot_services), EventType::NOTIFY_SIGNAL, Tpl::CALLBACK)
+            .one_shot()
+            .create(on_variable_arch_installed::<RR, R>, context)?;
+        boot_services.as_ref().register_protocol_notify(&variable_arch::PROTOCOL_GUID, event)?;
+        Ok(())
+    }
+}
+
+/// Notify function of the event signaled when the Variable Architectural Protocol is installed.
+fn on_variable_arch_installed<RR, R>(_event: efi::Event, context: &mut LogLevelContext<RR>)
+where
+    RR: AsRef<R>,
+    R: RuntimeServices,
+{
+    apply_log_levels(context.level_table, context.runtime_services.as_ref());
+}
+
+/// Applies the settings of the log levels variable, if it exists, to `level_table`.
+fn apply_log_levels<R: RuntimeServices>(level_table: &LevelTable, runtime_services: &R) {
+    let settings = match runtime_services.get_variable::<Vec<u8>>(
+        LOG_LEVELS_VARIABLE_NAME.as_slice_with_nul(),
+        &LOG_LEVELS_VARIABLE_GUID,
+        None,
+    ) {
+        Ok((settings, _attributes)) => settings,
+        Err(efi::Status::NOT_FOUND) => return,
+        Err(status) => {
+            log::error!("Failed to read the log levels variable: {status:#x?}");
+            return;
+        }
+    };
+
+    // The settings may be NUL terminated when written as a C string.
+    let settings = settings.strip_suffix(&[0]).unwrap_or(&settings);
+    let Ok(settings) = core::str::from_utf8(settings) else {
+        log::error!("The log levels variable is not an ASCII string.");
+        return;
+    };
+    match level_table.apply(settings) {
+        Ok(()) => log::info!("Log levels set from variable: {settings}"),
+        Err(err) => log::error!("Invalid log levels variable {settings:?}: {err:?}"),
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use log::LevelFilter;
+    use patina::{
+        boot_services::{MockBootServices, event::EventContext},
+        log::{TargetLevel, target},
+        runtime_services::MockRuntimeServices,
+    };
+    use std::{boxed::Box, rc::Rc};
+
+    fn runtime_services(settings: &'static [u8]) -> MockRuntimeServices {
+        let mut runtime_services = MockRuntimeServices::new();
+        runtime_services.expect_get_variable::<Vec<u8>>().once().returning(|name, namespace, _| {
+            assert_eq!(name, LOG_LEVELS_VARIABLE_NAME.as_slice_with_nul());
+            assert_eq!(namespace, &LOG_LEVELS_VARIABLE_GUID);
+            Ok((settings.to_vec(), 0))
+        });
+        runtime_services
+    }
+
+    #[test]
+    fn test_levels_applied_when_variables_available() {
+        static LEVELS: [TargetLevel; 2] = [TargetLevel::new(target::GCD), TargetLevel::new(target::DISPATCHER)];
+        static LEVEL_TABLE: LevelTable = LevelTable::new(&LEVELS);
+
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_locate_protocol_unchecked().once().returning(|_, _| Ok(ptr::null_mut()));
+        boot_services.expect_register_protocol_notify().never();
+
+        let component = LogLevelVariableComponent::new(&LEVEL_TABLE);
+        let runtime_services = runtime_services(b"patina_dxe_core::gcd=debug,patina_dxe_core::dispatcher=trace\0");
+        assert_eq!(component._entry_point(Rc::new(boot_services), Rc::new(runtime_services)), Ok(()));
+        assert_eq!(LEVEL_TABLE.level(target::GCD), Some(LevelFilter::Debug));
+        assert_eq!(LEVEL_TABLE.level(target::DISPATCHER), Some(LevelFilter::Trace));
+    }
+
+    #[test]
+    fn test_levels_applied_once_variables_available() {
+        type Context = Box<EventContext<Rc<MockBootServices>, LogLevelContext<Rc<MockRuntimeServices>>>>;
+
+        static LEVELS: [TargetLevel; 1] = [TargetLevel::new(target::GCD)];
+        static LEVEL_TABLE: LevelTable = LevelTable::new(&LEVELS);
+
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_locate_protocol_unchecked().once().returning(|_, _| Err(efi::Status::NOT_FOUND));
+        boot_services.expect_create_event::<Context>().once().returning(|event_type, notify_tpl, notify, _| {
+            assert_eq!(event_type, EventType::NOTIFY_SIGNAL);
+            assert_eq!(notify_tpl, Tpl::CALLBACK);
+            assert!(notify.is_some());
+            Ok(1_usize as efi::Event)
+        });
+        boot_services.expect_register_protocol_notify().once().returning(|protocol, event| {
+            assert_eq!(protocol, &variable_arch::PROTOCOL_GUID);
+            assert_eq!(event, 1_usize as efi::Event);
+            Ok(ptr::NonNull::dangling())
+        });
+
+        let component = LogLevelVariableComponent::new(&LEVEL_TABLE);
+        assert_eq!(component._entry_point(Rc::new(boot_services), Rc::new(MockRuntimeServices::new())), Ok(()));
+        assert_eq!(LEVEL_TABLE.level(target::GCD), None);
+
+        let mut context = LogLevelContext {
+            level_table: &LEVEL_TABLE,
+            runtime_services: Rc::new(runtime_services(b"patina_dxe_core::gcd=off")),
+        };
+        on_variable_arch_installed(1_usize as efi::Event, &mut context);
+        assert_eq!(LEVEL_TABLE.level(target::GCD), Some(LevelFilter::Off));
+    }
+
+    #[test]
+    fn test_invalid_or_missing_variable_is_ignored() {
+        static LEVELS: [TargetLevel; 1] = [TargetLevel::new(target::GCD)];
+        static LEVEL_TABLE: LevelTable = LevelTable::new(&LEVELS);
+
+        apply_log_levels(&LEVEL_TABLE, &runtime_services(b"patina_dxe_core::gcd=loud"));
+        apply_log_levels(&LEVEL_TABLE, &runtime_services(&[0xFF, 0xFE]));
+        assert_eq!(LEVEL_TABLE.level(target::GCD), None);
+
+        let mut runtime_services = MockRuntimeServices::new();
+        runtime_services.expect_get_variable::<Vec<u8>>().once().returning(|_, _, _| Err(efi::Status::NOT_FOUND));
+        apply_log_levels(&LEVEL_TABLE, &runtime_services);
+        assert_eq!(LEVEL_TABLE.level(target::GCD), None);
+    }
+}
@@ -9,11 +9,17 @@
 //!
 //! SPDX-License-Identifier: Apache-2.0
 //!
-use crate::memory_log::{self, AdvancedLog, LogEntry};
+use crate::{
+    memory_log::{self, AdvancedLog, LogEntry},
+    runtime_log::RuntimeLog,
+};
 use core::marker::Send;
 use log::Level;
 use mu_rust_helpers::perf_timer::{Arch, ArchFunctionality};
-use patina::{log::Format, serial::SerialIO};
+use patina::{
+    log::{Format, LevelTable},
+    serial::SerialIO,
+};
 use r_efi::efi;
 use spin::Once;
 
@@ -28,9 +34,11 @@ where
 {
     hardware_port: S,
     target_filters: &'a [(&'a str, log::LevelFilter)],
+    level_table: Option<&'a LevelTable<'a>>,
     max_level: log::LevelFilter,
     format: Format,
     memory_log: Once<AdvancedLog<'static>>,
+    runtime_log: Once<&'static RuntimeLog>,
 }
 
 impl<'a, S> AdvancedLogger<'a, S>
@@ -52,11 +60,37 @@ where
         max_level: log::LevelFilter,
         hardware_port: S,
     ) -> Self {
-        Self { hardware_port, target_filters, max_level, format, memory_log: Once::new() }
+        Self {
+            hardware_port,
+            target_filters,
+            level_table: None,
+            max_level,
+            format,
+            memory_log: Once::new(),
+            runtime_log: Once::new(),
+        }
+    }
+
+    /// Uses `level_table` to change the level of its targets at runtime, over the target filters.
+    ///
+    /// The levels of the table can be set from a variable with the
+    /// [LogLevelVariableComponent](crate::log_level::LogLevelVariableComponent).
+    ///
+    pub const fn with_level_table(mut self, level_table: &'a LevelTable<'a>) -> Self {
+        self.level_table = Some(level_table);
+        self
     }
 
     /// Writes a log entry to the hardware port and memory log if available.
+    ///
+    /// After ExitBootServices, the entry is only written to the runtime log ring, as the memory log and the hardware
+    /// port belong to the boot services.
     pub(crate) fn log_write(&self, error_level: u32, data: &[u8]) {
+        if let Some(runtime_log) = self.runtime_log.get().filter(|runtime_log| runtime_log.at_runtime()) {
+            runtime_log.write(data);
+            return;
+        }
+
         let mut hw_write = true;
         if let Some(memory_log) = self.memory_log.get() {
             hw_write = memory_log.hardware_write_enabled(error_level);
@@ -99,6 +133,15 @@ where
     pub(crate) fn get_log_address(&self) -> Option<efi::PhysicalAddress> {
         self.memory_log.get().map(|log| log.get_address())
     }
+
+    /// Sets the runtime log ring the log entries are written to after ExitBootServices.
+    pub(crate) fn set_runtime_log(&self, runtime_log: &'static RuntimeLog) {
+        if self.runtime_log.is_completed() {
+            log::error!("Runtime log ring already set!");
+            return;
+        }
+        self.runtime_log.call_once(|| runtime_log);
+    }
 }
 
 impl<S> log::Log for AdvancedLogger<'_, S>
@@ -107,12 +150,7 @@ where
 {
     fn enabled(&self, metadata: &log::Metadata) -> bool {
         metadata.level().to_level_filter()
-            <= *self
-                .target_filters
-                .iter()
-                .find(|(name, _)| metadata.target().starts_with(name))
-                .map(|(_, level)| level)
-                .unwrap_or(&self.max_level)
+            <= patina::log::target_level(metadata.target(), self.target_filters, self.level_table, self.max_level)
     }
 
     fn log(&self, record: &log::Record) {
@@ -0,0 +1,417 @@
+//! Runtime Log Support
+//!
+//! This module provides a log ring in runtime services data memory, and a component that publishes it and switches
+//! the [AdvancedLogger] to it at ExitBootServices.
+//!
+//! The advanced logger memory log and the hardware port belong to the boot services, and the memory log may be
+//! reclaimed by the OS once ExitBootServices completes. From the ExitBootServices notifications on, log entries are
+//! instead written to the runtime log ring, which the OS finds through the [RUNTIME_LOG_TABLE_GUID] configuration
+//! table. Failures in the late boot, e.g. in ExitBootServices callbacks, can then be diagnosed from the OS.
+//!
+//! The ring starts with a [RuntimeLogHeader] followed by the ring buffer. The header counts the bytes ever written,
+//! so the oldest byte still in the ring is at `written % buffer_size` once the ring has wrapped around.
+//!
+//! The [RuntimeLog] state the logger writes through is allocated in runtime services data memory next to the ring,
+//! and its pointer to the ring is converted at SetVirtualAddressMap. After ExitBootServices the hardware port is no
+//! longer written, as it may not be mapped or owned by the firmware anymore. The logger itself lives in the image
+//! that declares it, so logging after SetVirtualAddressMap also requires that image to be a runtime image.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::vec::Vec;
+use core::{
+    ffi::c_void,
+    mem::size_of,
+    ptr, slice,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, Ordering},
+};
+use patina::{
+    base::UEFI_PAGE_SIZE,
+    boot_services::{
+        BootServices, StandardBootServices,
+        allocation::{AllocType, MemoryType},
+        event::{EventBuilder, EventType},
+        tpl::Tpl,
+    },
+    component::IntoComponent,
+    error::{EfiError, Result},
+    runtime_services::{RuntimeServices, StandardRuntimeServices},
+    serial::SerialIO,
+};
+use r_efi::efi;
+
+use crate::logger::AdvancedLogger;
+
+/// GUID of the configuration table holding the physical address of the runtime log ring.
+// { 0x6c2a4e1b, 0x83f5, 0x4d97, { 0xa1, 0x3c, 0x5e, 0x0b, 0x92, 0x7d, 0x44, 0xe8 } }
+pub const RUNTIME_LOG_TABLE_GUID: efi::Guid =
+    efi::Guid::from_fields(0x6c2a4e1b, 0x83f5, 0x4d97, 0xa1, 0x3c, &[0x5e, 0x0b, 0x92, 0x7d, 0x44, 0xe8]);
+
+/// Header of the runtime log ring, followed by the ring buffer.
+#[derive(Debug)]
+#[repr(C)]
+pub struct RuntimeLogHeader {
+    /// Signature 'RLOG'
+    pub signature: u32,
+    /// Current Version
+    pub version: u16,
+    /// Reserved for future
+    pub reserved: u16,
+    /// Offset from the header to the start of the ring buffer.
+    pub buffer_offset: u32,
+    /// Size of the ring buffer.
+    pub buffer_size: u32,
+    /// Number of bytes written to the ring since it was initialized.
+    pub written: AtomicU64,
+}
+
+impl RuntimeLogHeader {
+    /// Signature for the RuntimeLogHeader structure.
+    pub const SIGNATURE: u32 = 0x474F4C52; // "RLOG"
+
+    /// Version of the current RuntimeLogHeader structure.
+    pub const VERSION: u16 = 1;
+}
+
+/// A log ring in memory, overwriting its oldest bytes once it is full.
+///
+/// The ring is only referenced through a pointer converted at SetVirtualAddressMap, so the state must be kept in
+/// runtime memory for the ring to be written after it.
+pub struct RuntimeLog {
+    header: AtomicPtr<RuntimeLogHeader>,
+    at_runtime: AtomicBool,
+    runtime_services: StandardRuntimeServices,
+}
+
+// SAFETY: The ring buffer is only written through the header pointer. Writers reserve the bytes they write with an
+//         atomic increment of the written count, so concurrent writers do not write the same bytes unless they wrap
+//         around the whole ring.
+unsafe impl Send for RuntimeLog {}
+unsafe impl Sync for RuntimeLog {}
+
+impl RuntimeLog {
+    /// Initializes an empty runtime log ring at the provided address with the specified length.
+    ///
+    /// `runtime_services` are used to convert the address of the ring at SetVirtualAddressMap.
+    ///
+    /// ### Safety
+    ///
+    /// The caller is responsible for ensuring that the provided address is appropriately allocated, accessible and
+    /// not used for anything else.
+    pub unsafe fn initialize(
+        address: efi::PhysicalAddress,
+        length: usize,
+        runtime_services: StandardRuntimeServices,
+    ) -> Option<Self> {
+        if length <= size_of::<RuntimeLogHeader>()
+            || length > u32::MAX as usize
+            || !address.is_multiple_of(core::mem::align_of::<RuntimeLogHeader>() as u64)
+        {
+            return None;
+        }
+
+        let header = address as *mut RuntimeLogHeader;
+        // SAFETY: The caller ensures the memory is valid and writable for `length` bytes.
+        unsafe {
+            header.write(RuntimeLogHeader {
+                signature: RuntimeLogHeader::SIGNATURE,
+                version: RuntimeLogHeader::VERSION,
+                reserved: 0,
+                buffer_offset: size_of::<RuntimeLogHeader>() as u32,
+                buffer_size: (length - size_of::<RuntimeLogHeader>()) as u32,
+                written: AtomicU64::new(0),
+            });
+        }
+        Some(Self { header: AtomicPtr::new(header), at_runtime: AtomicBool::new(false), runtime_services })
+    }
+
+    /// Returns the header of the ring, unless the ring has been dropped because its pointer could not be converted.
+    fn header(&self) -> Option<&RuntimeLogHeader> {
+        // SAFETY: The header was initialized in `initialize`, and its pointer is only replaced by its virtual address.
+        unsafe { self.header.load(Ordering::Relaxed).as_ref() }
+    }
+
+    /// Returns the ring buffer following `header`.
+    fn buffer(header: &RuntimeLogHeader) -> *mut u8 {
+        // SAFETY: The ring buffer is `buffer_offset` bytes after the header, in the same allocation.
+        unsafe { (header as *const RuntimeLogHeader as *mut u8).add(header.buffer_offset as usize) }
+    }
+
+    /// Writes `data` to the ring, overwriting its oldest bytes if it is full.
+    pub fn write(&self, data: &[u8]) {
+        let Some(header) = self.header() else {
+            return;
+        };
+        let size = header.buffer_size as usize;
+        // Only the last bytes of the data fit in the ring.
+        let data = &data[data.len().saturating_sub(size)..];
+        let start = header.written.fetch_add(data.len() as u64, Ordering::Relaxed);
+
+        let offset = (start % size as u64) as usize;
+        let (head, tail) = data.split_at(data.len().min(size - offset));
+        // SAFETY: The bytes have been reserved by the atomic increment above, and the writes stay in the ring buffer.
+        unsafe {
+            let buffer = Self::buffer(header);
+            ptr::copy_nonoverlapping(head.as_ptr(), buffer.add(offset), head.len());
+            ptr::copy_nonoverlapping(tail.as_ptr(), buffer, tail.len());
+        }
+    }
+
+    /// Returns the bytes in the ring, from the oldest to the newest.
+    pub fn contents(&self) -> Vec<u8> {
+        let Some(header) = self.header() else {
+            return Vec::new();
+        };
+        // SAFETY: The buffer is only read, entries being written concurrently may be partially returned.
+        let buffer = unsafe { slice::from_raw_parts(Self::buffer(header), header.buffer_size as usize) };
+        let written = header.written.load(Ordering::Relaxed);
+        if written <= buffer.len() as u64 {
+            return buffer[..written as usize].to_vec();
+        }
+        let offset = (written % buffer.len() as u64) as usize;
+        [&buffer[offset..], &buffer[..offset]].concat()
+    }
+
+    /// Returns the address of the ring, which is its virtual address after SetVirtualAddressMap.
+    pub fn get_address(&self) -> efi::PhysicalAddress {
+        self.header.load(Ordering::Relaxed) as efi::PhysicalAddress
+    }
+
+    /// Returns whether ExitBootServices has been called, after which log entries only go to the ring.
+    pub fn at_runtime(&self) -> bool {
+        self.at_runtime.load(Ordering::Relaxed)
+    }
+
+    /// Switches the log entries to the ring, at ExitBootServices.
+    fn enter_runtime(&self) {
+        self.at_runtime.store(true, Ordering::Relaxed);
+    }
+
+    /// Converts the pointer to the ring to its virtual address, at SetVirtualAddressMap.
+    ///
+    /// The ring is dropped if the pointer cannot be converted, rather than written through its physical address.
+    fn convert_pointers<R: RuntimeServices>(&self, runtime_services: &R) {
+        let mut header = self.header.load(Ordering::Relaxed) as *mut c_void;
+        // SAFETY: The ring is in runtime services data memory and is only accessed through this pointer afterwards.
+        if unsafe { runtime_services.convert_pointer(&mut header) }.is_err() {
+            header = ptr::null_mut();
+        }
+        self.header.store(header as *mut RuntimeLogHeader, Ordering::Relaxed);
+    }
+}
+
+/// The component that will publish the runtime log ring.
+#[derive(IntoComponent)]
+pub struct RuntimeLogComponent<S>
+where
+    S: SerialIO + Send + 'static,
+{
+    adv_logger: &'static AdvancedLogger<'static, S>,
+    pages: usize,
+}
+
+impl<S> RuntimeLogComponent<S>
+where
+    S: SerialIO + Send + 'static,
+{
+    /// Creates a new RuntimeLogComponent publishing a runtime log ring of `pages` pages for `adv_logger`.
+    pub const fn new(adv_logger: &'static AdvancedLogger<S>, pages: usize) -> Self {
+        Self { adv_logger, pages }
+    }
+
+    /// Entry point to the RuntimeLogComponent.
+    ///
+    /// Allocates the runtime log ring and its state, installs its configuration table and registers the
+    /// ExitBootServices event switching the logger to it and the VirtualAddressChange event converting it.
+    ///
+    fn entry_point(self, bs: StandardBootServices, rs: StandardRuntimeServices) -> Result<()> {
+        let address = bs.allocate_pages(AllocType::AnyPage, MemoryType::RUNTIME_SERVICES_DATA, self.pages)?;
+
+        // SAFETY: The pages have just been allocated for the ring.
+        let Some(runtime_log) = (unsafe { RuntimeLog::initialize(address as u64, self.pages * UEFI_PAGE_SIZE, rs) })
+        else {
+            log::error!("Failed to initialize the runtime log ring at {address:#x}!");
+            let _ = bs.free_pages(address, self.pages);
+            return Err(EfiError::InvalidParameter);
+        };
+
+        // The state is used after ExitBootServices, so it is kept in runtime memory as well.
+        let state = match bs.allocate_pool_for_type::<RuntimeLog>(MemoryType::RUNTIME_SERVICES_DATA) {
+            Ok(state) => state,
+            Err(status) => {
+                let _ = bs.free_pages(address, self.pages);
+                return Err(status.into());
+            }
+        };
+        // SAFETY: The pool has just been allocated for the state, which is never freed.
+        let runtime_log: &'static RuntimeLog = unsafe {
+            state.write(runtime_log);
+            &*state
+        };
+        self.adv_logger.set_runtime_log(runtime_log);
+
+        // SAFETY: The table is the runtime log ring, which is never freed.
+        unsafe { bs.install_configuration_table_unchecked(&RUNTIME_LOG_TABLE_GUID, address as *mut c_void)? };
+
+        // Notified before the ExitBootServices notifications at lower TPLs, so that their logs end up in the ring.
+        EventBuilder::new(bs.clone(), EventType::SIGNAL_EXIT_BOOT_SERVICES, Tpl::NOTIFY)
+            .one_shot()
+            .create(enter_runtime, runtime_log)?;
+
+        // The event context is the state itself, as the event is notified after ExitBootServices.
+        bs.create_event(
+            EventType::SIGNAL_VIRTUAL_ADDRESS_CHANGE,
+            Tpl::NOTIFY,
+            Some(virtual_address_change),
+            runtime_log,
+        )?;
+
+        log::info!("Runtime log ring published. Address = {address:#x}");
+        Ok(())
+    }
+}
+
+/// Notify function of the ExitBootServices event, switching the logger to the runtime log ring.
+fn enter_runtime(_event: efi::Event, runtime_log: &mut &'static RuntimeLog) {
+    runtime_log.enter_runtime();
+}
+
+/// Notify function of the VirtualAddressChange event, converting the pointer to the runtime log ring.
+extern "efiapi" fn virtual_address_change(_event: efi::Event, runtime_log: &'static RuntimeLog) {
+    runtime_log.convert_pointers(&runtime_log.runtime_services);
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+    use alloc::boxed::Box;
+    use patina::runtime_services::MockRuntimeServices;
+    use std::sync::Mutex;
+
+    use super::*;
+
+    fn ring(length: usize) -> RuntimeLog {
+        let buffer = Box::leak(alloc::vec![0_u64; length / 8].into_boxed_slice());
+        let address = buffer.as_mut_ptr() as efi::PhysicalAddress;
+        unsafe { RuntimeLog::initialize(address, length, StandardRuntimeServices::new_uninit()) }.unwrap()
+    }
+
+    fn written(log: &RuntimeLog) -> u64 {
+        log.header().unwrap().written.load(Ordering::Relaxed)
+    }
+
+    #[test]
+    fn initialize_should_validate_the_buffer() {
+        let mut buffer = Box::new([0_u64; 8]);
+        let address = buffer.as_mut_ptr() as efi::PhysicalAddress;
+        let initialize =
+            |address, length| unsafe { RuntimeLog::initialize(address, length, StandardRuntimeServices::new_uninit()) };
+        assert!(initialize(address, size_of::<RuntimeLogHeader>()).is_none());
+        assert!(initialize(address + 1, 32).is_none());
+
+        let log = initialize(address, 64).unwrap();
+        let header = log.header().unwrap();
+        assert_eq!(log.get_address(), address);
+        assert_eq!(header.signature, RuntimeLogHeader::SIGNATURE);
+        assert_eq!(header.buffer_offset as usize, size_of::<RuntimeLogHeader>());
+        assert_eq!(header.buffer_size as usize, 64 - size_of::<RuntimeLogHeader>());
+        assert!(log.contents().is_empty());
+        assert!(!log.at_runtime());
+    }
+
+    #[test]
+    fn ring_should_keep_the_newest_bytes() {
+        let log = ring(size_of::<RuntimeLogHeader>() + 16);
+
+        log.write(b"0123456789");
+        assert_eq!(log.contents(), b"0123456789");
+
+        // Wrapping around overwrites the oldest bytes.
+        log.write(b"abcdefghij");
+        assert_eq!(log.contents(), b"456789abcdefghij");
+        assert_eq!(written(&log), 20);
+
+        // Only the end of data larger than the ring is kept.
+        log.write(b"ABCDEFGHIJKLMNOPQRSTUVWXYZ");
+        assert_eq!(log.contents(), b"KLMNOPQRSTUVWXYZ");
+    }
+
+    #[test]
+    fn ring_should_be_written_through_its_converted_address() {
+        const LENGTH: usize = size_of::<RuntimeLogHeader>() + 16;
+        let log = ring(LENGTH);
+        log.write(b"physical");
+
+        // The "virtual" mapping of the ring is a copy of it at another address.
+        let mapping = Box::leak(alloc::vec![0_u64; LENGTH / 8].into_boxed_slice());
+        unsafe { ptr::copy_nonoverlapping(log.get_address() as *const u8, mapping.as_mut_ptr() as *mut u8, LENGTH) };
+        let virtual_address = mapping.as_mut_ptr() as usize;
+
+        let mut runtime_services = MockRuntimeServices::new();
+        runtime_services.expect_convert_pointer().once().returning(move |address| {
+            *address = virtual_address as *mut c_void;
+            Ok(())
+        });
+        log.convert_pointers(&runtime_services);
+        assert_eq!(log.get_address(), virtual_address as efi::PhysicalAddress);
+
+        log.write(b"virtual");
+        assert_eq!(log.contents(), b"physicalvirtual");
+        assert_eq!(unsafe { &*(virtual_address as *const RuntimeLogHeader) }.written.load(Ordering::Relaxed), 15);
+    }
+
+    #[test]
+    fn ring_should_be_dropped_if_its_address_cannot_be_converted() {
+        let log = ring(size_of::<RuntimeLogHeader>() + 16);
+
+        let mut runtime_services = MockRuntimeServices::new();
+        runtime_services.expect_convert_pointer().once().returning(|_| Err(efi::Status::NOT_FOUND));
+        log.convert_pointers(&runtime_services);
+
+        log.write(b"dropped");
+        assert!(log.contents().is_empty());
+    }
+
+    static PORT: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+
+    struct RecordingPort;
+
+    impl SerialIO for RecordingPort {
+        fn init(&self) {}
+
+        fn write(&self, buffer: &[u8]) {
+            PORT.lock().unwrap().extend_from_slice(buffer);
+        }
+
+        fn read(&self) -> u8 {
+            0
+        }
+
+        fn try_read(&self) -> Option<u8> {
+            None
+        }
+    }
+
+    #[test]
+    fn logger_should_only_write_to_the_ring_after_exit_boot_services() {
+        static LOGGER: AdvancedLogger<RecordingPort> =
+            AdvancedLogger::new(patina::log::Format::Standard, &[], log::LevelFilter::Trace, RecordingPort);
+
+        let runtime_log: &'static RuntimeLog = Box::leak(Box::new(ring(size_of::<RuntimeLogHeader>() + 64)));
+        LOGGER.set_runtime_log(runtime_log);
+
+        LOGGER.log_write(0, b"boot");
+        assert_eq!(*PORT.lock().unwrap(), b"boot");
+        assert!(runtime_log.contents().is_empty());
+
+        enter_runtime(ptr::null_mut(), &mut { runtime_log });
+        LOGGER.log_write(0, b"runtime");
+        assert_eq!(*PORT.lock().unwrap(), b"boot");
+        assert_eq!(runtime_log.contents(), b"runtime");
+    }
+}
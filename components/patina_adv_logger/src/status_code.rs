@@ -0,0 +1,259 @@
+//! Status Code Debug Message Support
+//!
+//! This module provides a component that registers a status code handler with the Report Status Code Handler
+//! Protocol. Debug messages reported as status codes (e.g. by drivers using the EDKII `DebugLibReportStatusCode`
+//! library) are formatted and logged through the [log] crate, so they end up in the same log, with the same
+//! formatting, as `log::*` output.
+//!
+//! If the Report Status Code Handler Protocol is not installed when the component is dispatched, the handler is
+//! registered once the protocol is installed.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use core::{ptr, slice};
+use patina::{
+    boot_services::{
+        BootServices, StandardBootServices,
+        event::{EventBuilder, EventType},
+        tpl::Tpl,
+    },
+    component::IntoComponent,
+    error::{EfiError, Result},
+};
+use patina_pi::{
+    protocols::{
+        rsc_handler,
+        status_code::{EfiStatusCodeData, EfiStatusCodeType, EfiStatusCodeValue},
+    },
+    status_code::{
+        EFI_DEBUG_CODE, EFI_DEBUG_INFO_MAX_ARGUMENTS, EFI_STATUS_CODE_DATA_TYPE_DEBUG_GUID, EFI_STATUS_CODE_TYPE_MASK,
+    },
+};
+use r_efi::efi;
+
+use crate::debug_message::{LineWriter, MessageBuffer, debug_level_to_log_level, format_message};
+
+/// The log target of debug messages reported as status codes.
+pub const STATUS_CODE_LOG_TARGET: &str = patina::log::target::STATUS_CODE;
+
+static WRITER: LineWriter = LineWriter::new(STATUS_CODE_LOG_TARGET);
+
+/// Size of a `BASE_LIST` argument, which is the size of a `UINTN` on the 64-bit architectures supported.
+const ARGUMENT_SIZE: usize = size_of::<u64>();
+
+/// The component that will register the status code handler logging debug messages.
+#[derive(IntoComponent, Default)]
+pub struct StatusCodeLogComponent;
+
+impl StatusCodeLogComponent {
+    /// Entry point to the StatusCodeLogComponent.
+    ///
+    /// Registers the status code handler, or registers for notification of the installation of the Report Status
+    /// Code Handler protocol if it is not installed yet.
+    ///
+    fn entry_point(self, bs: StandardBootServices) -> Result<()> {
+        Self::_entry_point(bs)
+    }
+
+    /// Entry point that has generic parameters.
+    fn _entry_point<BB, B>(boot_services: BB) -> Result<()>
+    where
+        BB: AsRef<B> + Clone + 'static,
+        B: BootServices + 'static,
+    {
+        match register_handler(boot_services.as_ref()) {
+            Err(EfiError::NotFound) => (),
+            result => return result,
+        }
+
+        log::info!("Report Status Code Handler protocol not found, handler will be registered once it is installed.");
+        let event = EventBuilder::new(BB::clone(&boot_services), EventType::NOTIFY_SIGNAL, Tpl::CALLBACK)
+            .one_shot()
+            .create(on_rsc_handler_installed::<BB, B>, BB::clone(&boot_services))?;
+        boot_services.as_ref().register_protocol_notify(&rsc_handler::PROTOCOL_GUID, event)?;
+        Ok(())
+    }
+}
+
+/// Notify function of the event signaled when the Report Status Code Handler protocol is installed.
+fn on_rsc_handler_installed<BB, B>(_event: efi::Event, boot_services: &mut BB)
+where
+    BB: AsRef<B>,
+    B: BootServices,
+{
+    if let Err(err) = register_handler(boot_services.as_ref()) {
+        log::error!("Failed to register status code handler: {err:?}");
+    }
+}
+
+/// Registers [status_code_handler] with the Report Status Code Handler protocol.
+fn register_handler<B: BootServices>(boot_services: &B) -> Result<()> {
+    // SAFETY: The interface installed for the protocol GUID is a Report Status Code Handler protocol.
+    let protocol = unsafe { boot_services.locate_protocol_unchecked(&rsc_handler::PROTOCOL_GUID, ptr::null_mut()) }?
+        as *const rsc_handler::Protocol;
+
+    // SAFETY: The protocol was located above and is not null.
+    let status = unsafe { ((*protocol).register)(status_code_handler, efi::TPL_HIGH_LEVEL) };
+    EfiError::status_to_result(status)?;
+
+    log::info!("Status code handler registered.");
+    Ok(())
+}
+
+/// Status code handler logging debug messages reported as status codes.
+extern "efiapi" fn status_code_handler(
+    code_type: EfiStatusCodeType,
+    _value: EfiStatusCodeValue,
+    _instance: u32,
+    _caller_id: *const efi::Guid,
+    data: *const EfiStatusCodeData,
+) -> efi::Status {
+    if code_type & EFI_STATUS_CODE_TYPE_MASK != EFI_DEBUG_CODE {
+        return efi::Status::SUCCESS;
+    }
+
+    // SAFETY: A non-null data pointer points to a status code data header.
+    let Some(header) = (unsafe { data.as_ref() }) else {
+        return efi::Status::SUCCESS;
+    };
+    if header.r#type != EFI_STATUS_CODE_DATA_TYPE_DEBUG_GUID {
+        return efi::Status::SUCCESS;
+    }
+
+    // SAFETY: The status code data is followed by `size` bytes of data, starting `header_size` bytes after the
+    // header.
+    let debug_info =
+        unsafe { slice::from_raw_parts((data as *const u8).add(header.header_size as usize), header.size as usize) };
+
+    // SAFETY: The pointer arguments of a debug message are valid for the format string of the message.
+    unsafe { log_debug_info(debug_info) };
+    efi::Status::SUCCESS
+}
+
+/// Logs an `EFI_DEBUG_INFO` debug message.
+///
+/// `EFI_DEBUG_INFO` is the 32-bit error level, followed by [EFI_DEBUG_INFO_MAX_ARGUMENTS] `BASE_LIST` arguments and
+/// the NUL terminated ASCII format string.
+///
+/// ## Safety
+///
+/// The pointer arguments of the message must be valid for the conversions of the format string.
+unsafe fn log_debug_info(debug_info: &[u8]) {
+    let Some((error_level, rest)) = debug_info.split_first_chunk::<4>() else {
+        return;
+    };
+    let Some((arguments, format)) = rest.split_at_checked(EFI_DEBUG_INFO_MAX_ARGUMENTS * ARGUMENT_SIZE) else {
+        return;
+    };
+
+    let mut args = [0_u64; EFI_DEBUG_INFO_MAX_ARGUMENTS];
+    for (arg, bytes) in args.iter_mut().zip(arguments.chunks_exact(ARGUMENT_SIZE)) {
+        *arg = u64::from_ne_bytes(bytes.try_into().unwrap_or_default());
+    }
+
+    let mut message = MessageBuffer::new();
+    // SAFETY: The caller guarantees the pointer arguments are valid for the format string.
+    let _ = unsafe { format_message(&mut message, format, &args) };
+    WRITER.write(debug_level_to_log_level(u32::from_ne_bytes(*error_level)), message.as_bytes());
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use core::{
+        mem::size_of,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+    use patina::boot_services::{MockBootServices, event::EventContext};
+    use std::{boxed::Box, rc::Rc, vec::Vec};
+
+    use crate::memory_log::DEBUG_LEVEL_INFO;
+
+    static REGISTERED: AtomicUsize = AtomicUsize::new(0);
+
+    extern "efiapi" fn register(callback: rsc_handler::RscHandlerCallback, tpl: efi::Tpl) -> efi::Status {
+        assert_eq!(callback as usize, status_code_handler as usize);
+        assert_eq!(tpl, efi::TPL_HIGH_LEVEL);
+        REGISTERED.fetch_add(1, Ordering::SeqCst);
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn unregister(_callback: rsc_handler::RscHandlerCallback) -> efi::Status {
+        efi::Status::SUCCESS
+    }
+
+    static PROTOCOL: rsc_handler::Protocol = rsc_handler::Protocol { register, unregister };
+
+    fn debug_status_code(error_level: u32, args: &[u64], format: &str) -> Vec<u8> {
+        let mut data = Vec::new();
+        let header_size = size_of::<EfiStatusCodeData>();
+        let size = 4 + EFI_DEBUG_INFO_MAX_ARGUMENTS * ARGUMENT_SIZE + format.len() + 1;
+        data.extend_from_slice(&(header_size as u16).to_ne_bytes());
+        data.extend_from_slice(&(size as u16).to_ne_bytes());
+        data.extend_from_slice(EFI_STATUS_CODE_DATA_TYPE_DEBUG_GUID.as_bytes());
+        data.resize(header_size, 0);
+        data.extend_from_slice(&error_level.to_ne_bytes());
+        for index in 0..EFI_DEBUG_INFO_MAX_ARGUMENTS {
+            data.extend_from_slice(&args.get(index).copied().unwrap_or_default().to_ne_bytes());
+        }
+        data.extend_from_slice(format.as_bytes());
+        data.push(0);
+        data
+    }
+
+    #[test]
+    fn test_handler_registered_when_protocol_installed() {
+        let mut boot_services = MockBootServices::new();
+        boot_services
+            .expect_locate_protocol_unchecked()
+            .once()
+            .returning(|_, _| Ok(&PROTOCOL as *const rsc_handler::Protocol as *mut core::ffi::c_void));
+        boot_services.expect_register_protocol_notify().never();
+
+        let registered = REGISTERED.load(Ordering::SeqCst);
+        assert_eq!(StatusCodeLogComponent::_entry_point(Rc::new(boot_services)), Ok(()));
+        assert!(REGISTERED.load(Ordering::SeqCst) > registered);
+    }
+
+    #[test]
+    fn test_handler_registered_once_protocol_is_installed() {
+        type Context = Box<EventContext<Rc<MockBootServices>, Rc<MockBootServices>>>;
+
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_locate_protocol_unchecked().once().returning(|_, _| Err(efi::Status::NOT_FOUND));
+        boot_services.expect_create_event::<Context>().once().returning(|event_type, notify_tpl, notify, _| {
+            assert_eq!(event_type, EventType::NOTIFY_SIGNAL);
+            assert_eq!(notify_tpl, Tpl::CALLBACK);
+            assert!(notify.is_some());
+            Ok(1_usize as efi::Event)
+        });
+        boot_services.expect_register_protocol_notify().once().returning(|protocol, event| {
+            assert_eq!(protocol, &rsc_handler::PROTOCOL_GUID);
+            assert_eq!(event, 1_usize as efi::Event);
+            Ok(ptr::NonNull::dangling())
+        });
+
+        assert_eq!(StatusCodeLogComponent::_entry_point(Rc::new(boot_services)), Ok(()));
+    }
+
+    #[test]
+    fn test_status_code_handler() {
+        let name = b"driver\0";
+        let data = debug_status_code(DEBUG_LEVEL_INFO, &[name.as_ptr() as u64, 42], "Loading %a: %d\n");
+        let data = data.as_ptr() as *const EfiStatusCodeData;
+
+        assert_eq!(status_code_handler(EFI_DEBUG_CODE, 0, 0, ptr::null(), data), efi::Status::SUCCESS);
+        assert_eq!(status_code_handler(EFI_DEBUG_CODE, 0, 0, ptr::null(), ptr::null()), efi::Status::SUCCESS);
+        assert_eq!(status_code_handler(0x1, 0, 0, ptr::null(), data), efi::Status::SUCCESS);
+
+        // Truncated debug info is ignored.
+        unsafe { log_debug_info(&[0; 12]) };
+    }
+}
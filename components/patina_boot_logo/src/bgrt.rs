@@ -0,0 +1,142 @@
+//! Boot Graphics Resource Table
+//!
+//! The ACPI table describing the logo displayed by the firmware: the address of the BMP image of the logo, its
+//! position on the screen, and whether it is still displayed.
+//!
+//! See <https://uefi.org/specs/ACPI/6.5/05_ACPI_Software_Programming_Model.html#boot-graphics-resource-table-bgrt>
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use core::mem;
+
+/// The signature of the BGRT.
+pub const BGRT_SIGNATURE: [u8; 4] = *b"BGRT";
+/// The revision of the BGRT.
+pub const BGRT_REVISION: u8 = 1;
+/// The version of the BGRT.
+pub const BGRT_VERSION: u16 = 1;
+/// The status bit set while the logo is displayed on the screen.
+pub const BGRT_STATUS_DISPLAYED: u8 = 0x01;
+/// The image type of BMP images, the only type defined.
+pub const BGRT_IMAGE_TYPE_BMP: u8 = 0;
+
+/// The creator ID of the tables built by Patina.
+const CREATOR_ID: [u8; 4] = *b"PTNA";
+/// The creator revision of the tables built by Patina.
+const CREATOR_REVISION: u32 = 1;
+
+/// The header of ACPI tables.
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AcpiDescriptionHeader {
+    /// The signature of the table.
+    pub signature: [u8; 4],
+    /// The length of the table, including the header.
+    pub length: u32,
+    /// The revision of the table.
+    pub revision: u8,
+    /// The checksum of the table, for all its bytes to sum to zero.
+    pub checksum: u8,
+    /// The OEM ID.
+    pub oem_id: [u8; 6],
+    /// The OEM table ID.
+    pub oem_table_id: [u8; 8],
+    /// The OEM revision.
+    pub oem_revision: u32,
+    /// The vendor ID of the tool that created the table.
+    pub creator_id: [u8; 4],
+    /// The revision of the tool that created the table.
+    pub creator_revision: u32,
+}
+
+/// The Boot Graphics Resource Table.
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bgrt {
+    /// The header of the table.
+    pub header: AcpiDescriptionHeader,
+    /// The version of the table, [BGRT_VERSION].
+    pub version: u16,
+    /// The status of the logo, [BGRT_STATUS_DISPLAYED] while it is displayed.
+    pub status: u8,
+    /// The type of the image, [BGRT_IMAGE_TYPE_BMP].
+    pub image_type: u8,
+    /// The physical address of the BMP image of the logo.
+    pub image_address: u64,
+    /// The horizontal offset of the upper left corner of the logo on the screen.
+    pub image_offset_x: u32,
+    /// The vertical offset of the upper left corner of the logo on the screen.
+    pub image_offset_y: u32,
+}
+
+impl Bgrt {
+    /// Builds the BGRT for the logo at `image_address`, displayed at the given offset, with its checksum.
+    pub fn new(
+        oem_id: [u8; 6],
+        oem_table_id: [u8; 8],
+        oem_revision: u32,
+        displayed: bool,
+        image_address: u64,
+        (image_offset_x, image_offset_y): (u32, u32),
+    ) -> Self {
+        let mut bgrt = Self {
+            header: AcpiDescriptionHeader {
+                signature: BGRT_SIGNATURE,
+                length: mem::size_of::<Self>() as u32,
+                revision: BGRT_REVISION,
+                checksum: 0,
+                oem_id,
+                oem_table_id,
+                oem_revision,
+                creator_id: CREATOR_ID,
+                creator_revision: CREATOR_REVISION,
+            },
+            version: BGRT_VERSION,
+            status: if displayed { BGRT_STATUS_DISPLAYED } else { 0 },
+            image_type: BGRT_IMAGE_TYPE_BMP,
+            image_address,
+            image_offset_x,
+            image_offset_y,
+        };
+        let sum = bgrt.as_bytes().iter().fold(0_u8, |sum, byte| sum.wrapping_add(*byte));
+        bgrt.header.checksum = 0_u8.wrapping_sub(sum);
+        bgrt
+    }
+
+    /// Returns the bytes of the table.
+    pub fn as_bytes(&self) -> &[u8] {
+        // SAFETY: The table is packed, so it has no padding bytes.
+        unsafe { core::slice::from_raw_parts(self as *const Self as *const u8, mem::size_of::<Self>()) }
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bgrt_layout_and_checksum() {
+        assert_eq!(mem::size_of::<AcpiDescriptionHeader>(), 36);
+        assert_eq!(mem::size_of::<Bgrt>(), 56);
+
+        let bgrt = Bgrt::new(*b"OEMID ", *b"OEMTABLE", 2, true, 0x1234_5000, (100, 200));
+        let bytes = bgrt.as_bytes();
+        assert_eq!(&bytes[0..4], b"BGRT");
+        assert_eq!(u32::from_le_bytes(bytes[4..8].try_into().unwrap()), 56);
+        assert_eq!(bytes.iter().fold(0_u8, |sum, byte| sum.wrapping_add(*byte)), 0);
+        assert_eq!(bytes[38], BGRT_STATUS_DISPLAYED);
+        assert_eq!(u64::from_le_bytes(bytes[40..48].try_into().unwrap()), 0x1234_5000);
+        assert_eq!(u32::from_le_bytes(bytes[48..52].try_into().unwrap()), 100);
+        assert_eq!(u32::from_le_bytes(bytes[52..56].try_into().unwrap()), 200);
+
+        let bgrt = Bgrt::new(*b"OEMID ", *b"OEMTABLE", 2, false, 0x1234_5000, (100, 200));
+        assert_eq!(bgrt.status, 0);
+        assert_eq!(bgrt.as_bytes().iter().fold(0_u8, |sum, byte| sum.wrapping_add(*byte)), 0);
+    }
+}
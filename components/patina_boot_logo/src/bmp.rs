@@ -0,0 +1,254 @@
+//! BMP Images
+//!
+//! Decodes the logo from an uncompressed 24 or 32 bits per pixel BMP image into GOP pixels, and encodes the pixels
+//! back into the 24 bits per pixel BMP image referenced by the BGRT.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use alloc::vec::Vec;
+use core::fmt;
+use r_efi::efi::protocols::graphics_output::BltPixel;
+
+/// The size of the BMP file header (`BITMAPFILEHEADER`).
+const FILE_HEADER_SIZE: usize = 14;
+/// The size of the BMP info header (`BITMAPINFOHEADER`), the oldest header version supported.
+const INFO_HEADER_SIZE: usize = 40;
+/// The `BI_RGB` compression type, for uncompressed images.
+const BI_RGB: u32 = 0;
+
+/// An error decoding a BMP image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BmpError {
+    /// The image is not a BMP image, or its headers are invalid.
+    InvalidHeader,
+    /// The image is compressed, or its number of bits per pixel is not supported.
+    UnsupportedFormat,
+    /// The pixel data is outside of the image.
+    Truncated,
+}
+
+impl fmt::Display for BmpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidHeader => write!(f, "invalid BMP header"),
+            Self::UnsupportedFormat => write!(f, "unsupported BMP format"),
+            Self::Truncated => write!(f, "truncated BMP pixel data"),
+        }
+    }
+}
+
+impl core::error::Error for BmpError {}
+
+/// A decoded image, with its pixels in the order expected by the GOP `Blt` function.
+#[derive(Debug, Clone)]
+pub struct Bitmap {
+    width: u32,
+    height: u32,
+    pixels: Vec<BltPixel>,
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, BmpError> {
+    data.get(offset..offset + 2).map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]])).ok_or(BmpError::InvalidHeader)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, BmpError> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .ok_or(BmpError::InvalidHeader)
+}
+
+/// Returns the size of a row of pixels, which are padded to 4 bytes.
+fn row_size(width: u32, bits_per_pixel: u16) -> Option<usize> {
+    (width as usize).checked_mul(bits_per_pixel as usize)?.checked_add(31).map(|bits| bits / 32 * 4)
+}
+
+impl Bitmap {
+    /// Decodes a BMP image.
+    ///
+    /// Only uncompressed images with 24 or 32 bits per pixel are supported. The alpha channel of 32 bits per pixel
+    /// images is ignored.
+    pub fn from_bmp(data: &[u8]) -> Result<Self, BmpError> {
+        if data.get(0..2) != Some(b"BM".as_slice()) {
+            return Err(BmpError::InvalidHeader);
+        }
+        let pixel_offset = read_u32(data, 10)? as usize;
+        let info_header_size = read_u32(data, FILE_HEADER_SIZE)? as usize;
+        let width = read_u32(data, FILE_HEADER_SIZE + 4)? as i32;
+        let height = read_u32(data, FILE_HEADER_SIZE + 8)? as i32;
+        let planes = read_u16(data, FILE_HEADER_SIZE + 12)?;
+        let bits_per_pixel = read_u16(data, FILE_HEADER_SIZE + 14)?;
+        let compression = read_u32(data, FILE_HEADER_SIZE + 16)?;
+
+        if info_header_size < INFO_HEADER_SIZE || planes != 1 || width <= 0 || height == 0 {
+            return Err(BmpError::InvalidHeader);
+        }
+        if compression != BI_RGB || !matches!(bits_per_pixel, 24 | 32) {
+            return Err(BmpError::UnsupportedFormat);
+        }
+
+        // Images are stored bottom-up, unless the height is negative.
+        let top_down = height < 0;
+        let width = width as u32;
+        let height = height.unsigned_abs();
+        let row_size = row_size(width, bits_per_pixel).ok_or(BmpError::Truncated)?;
+        let end = row_size.checked_mul(height as usize).and_then(|size| size.checked_add(pixel_offset));
+        let pixel_data = data.get(pixel_offset..end.ok_or(BmpError::Truncated)?).ok_or(BmpError::Truncated)?;
+
+        let bytes_per_pixel = bits_per_pixel as usize / 8;
+        let mut pixels = Vec::with_capacity(width as usize * height as usize);
+        for y in 0..height as usize {
+            let row = if top_down { y } else { height as usize - 1 - y };
+            let row = &pixel_data[row * row_size..][..width as usize * bytes_per_pixel];
+            pixels.extend(row.chunks_exact(bytes_per_pixel).map(|pixel| BltPixel {
+                blue: pixel[0],
+                green: pixel[1],
+                red: pixel[2],
+                reserved: 0,
+            }));
+        }
+
+        Ok(Self { width, height, pixels })
+    }
+
+    /// Returns the width of the image, in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the height of the image, in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the pixels of the image, row by row from the top.
+    pub fn pixels(&self) -> &[BltPixel] {
+        &self.pixels
+    }
+
+    /// Encodes the image as an uncompressed, bottom-up, 24 bits per pixel BMP image.
+    pub fn to_bmp(&self) -> Vec<u8> {
+        let row_size = row_size(self.width, 24).unwrap_or(0);
+        let pixel_offset = FILE_HEADER_SIZE + INFO_HEADER_SIZE;
+        let image_size = row_size * self.height as usize;
+        let file_size = pixel_offset + image_size;
+
+        let mut bmp = Vec::with_capacity(file_size);
+        bmp.extend_from_slice(b"BM");
+        bmp.extend_from_slice(&(file_size as u32).to_le_bytes());
+        bmp.extend_from_slice(&0_u32.to_le_bytes());
+        bmp.extend_from_slice(&(pixel_offset as u32).to_le_bytes());
+        bmp.extend_from_slice(&(INFO_HEADER_SIZE as u32).to_le_bytes());
+        bmp.extend_from_slice(&self.width.to_le_bytes());
+        bmp.extend_from_slice(&self.height.to_le_bytes());
+        bmp.extend_from_slice(&1_u16.to_le_bytes());
+        bmp.extend_from_slice(&24_u16.to_le_bytes());
+        bmp.extend_from_slice(&BI_RGB.to_le_bytes());
+        bmp.extend_from_slice(&(image_size as u32).to_le_bytes());
+        // Resolution and palette fields are not used.
+        bmp.extend_from_slice(&[0; 16]);
+
+        for row in self.pixels.chunks_exact(self.width as usize).rev() {
+            for pixel in row {
+                bmp.extend_from_slice(&[pixel.blue, pixel.green, pixel.red]);
+            }
+            bmp.resize(bmp.len() + row_size - self.width as usize * 3, 0);
+        }
+        bmp
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::vec;
+
+    fn rgb(pixel: &BltPixel) -> (u8, u8, u8) {
+        (pixel.red, pixel.green, pixel.blue)
+    }
+
+    /// Builds a 2x2 BMP image with the given bits per pixel, and negative height if `top_down`.
+    fn bmp(bits_per_pixel: u16, top_down: bool) -> Vec<u8> {
+        // Rows from the top: red, green / blue, white.
+        let mut rows = vec![vec![(0xFF, 0, 0), (0, 0xFF, 0)], vec![(0, 0, 0xFF), (0xFF, 0xFF, 0xFF)]];
+        if !top_down {
+            rows.reverse();
+        }
+        let bytes_per_pixel = bits_per_pixel as usize / 8;
+        let row_size = (2 * bytes_per_pixel).div_ceil(4) * 4;
+
+        let mut data = vec![0; FILE_HEADER_SIZE + INFO_HEADER_SIZE];
+        data[0..2].copy_from_slice(b"BM");
+        data[10..14].copy_from_slice(&((FILE_HEADER_SIZE + INFO_HEADER_SIZE) as u32).to_le_bytes());
+        data[14..18].copy_from_slice(&(INFO_HEADER_SIZE as u32).to_le_bytes());
+        data[18..22].copy_from_slice(&2_i32.to_le_bytes());
+        data[22..26].copy_from_slice(&(if top_down { -2_i32 } else { 2 }).to_le_bytes());
+        data[26..28].copy_from_slice(&1_u16.to_le_bytes());
+        data[28..30].copy_from_slice(&bits_per_pixel.to_le_bytes());
+        for row in rows {
+            let start = data.len();
+            for (red, green, blue) in row {
+                data.extend_from_slice(&[blue, green, red]);
+                if bytes_per_pixel == 4 {
+                    data.push(0xFF);
+                }
+            }
+            data.resize(start + row_size, 0);
+        }
+        data
+    }
+
+    fn assert_pixels(bitmap: &Bitmap) {
+        assert_eq!((bitmap.width(), bitmap.height()), (2, 2));
+        let pixels: Vec<_> = bitmap.pixels().iter().map(rgb).collect();
+        assert_eq!(pixels, [(0xFF, 0, 0), (0, 0xFF, 0), (0, 0, 0xFF), (0xFF, 0xFF, 0xFF)]);
+    }
+
+    #[test]
+    fn test_decode_bmp() {
+        assert_pixels(&Bitmap::from_bmp(&bmp(24, false)).unwrap());
+        assert_pixels(&Bitmap::from_bmp(&bmp(24, true)).unwrap());
+        assert_pixels(&Bitmap::from_bmp(&bmp(32, false)).unwrap());
+    }
+
+    #[test]
+    fn test_decode_invalid_bmp() {
+        assert_eq!(Bitmap::from_bmp(b"PNG").unwrap_err(), BmpError::InvalidHeader);
+
+        let mut data = bmp(24, false);
+        data[28..30].copy_from_slice(&8_u16.to_le_bytes());
+        assert_eq!(Bitmap::from_bmp(&data).unwrap_err(), BmpError::UnsupportedFormat);
+
+        let mut data = bmp(24, false);
+        data[30..34].copy_from_slice(&1_u32.to_le_bytes());
+        assert_eq!(Bitmap::from_bmp(&data).unwrap_err(), BmpError::UnsupportedFormat);
+
+        let mut data = bmp(24, false);
+        data.truncate(data.len() - 1);
+        assert_eq!(Bitmap::from_bmp(&data).unwrap_err(), BmpError::Truncated);
+
+        let mut data = bmp(24, false);
+        data[18..22].copy_from_slice(&0x4000_0000_i32.to_le_bytes());
+        assert_eq!(Bitmap::from_bmp(&data).unwrap_err(), BmpError::Truncated);
+    }
+
+    #[test]
+    fn test_encode_bmp() {
+        let bitmap = Bitmap::from_bmp(&bmp(32, true)).unwrap();
+        let encoded = bitmap.to_bmp();
+        let expected = bmp(24, false);
+        assert_eq!(encoded.len(), expected.len());
+        assert_eq!(u32::from_le_bytes(encoded[2..6].try_into().unwrap()) as usize, encoded.len());
+        assert_eq!(encoded[28..30], 24_u16.to_le_bytes());
+        // The pixel data is bottom-up, with rows padded to 4 bytes.
+        assert_eq!(encoded[FILE_HEADER_SIZE + INFO_HEADER_SIZE..], expected[FILE_HEADER_SIZE + INFO_HEADER_SIZE..]);
+        assert_pixels(&Bitmap::from_bmp(&encoded).unwrap());
+    }
+}
@@ -0,0 +1,331 @@
+//! Patina Boot Logo Component
+//!
+//! Draws the logo as soon as the Graphics Output Protocol (GOP) is installed, and publishes the BGRT with the ACPI
+//! Table Protocol each time the platform signals that it is ready to boot.
+//!
+//! The BGRT reports the logo as displayed the first time it is published. If boot is attempted again, e.g. after a
+//! boot option failed, the screen may have been modified in between, so the BGRT is published again with the
+//! displayed status cleared, and the operating system draws its own logo.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use alloc::{rc::Rc, vec::Vec};
+use core::{cell::RefCell, clone::Clone, convert::AsRef, ptr, slice};
+use patina::{
+    boot_services::{
+        BootServices, StandardBootServices,
+        allocation::MemoryType,
+        event::{EventBuilder, EventType},
+        protocol_handler::HandleSearchType,
+        tpl::Tpl,
+    },
+    component::{IntoComponent, params::Config},
+    error::EfiError,
+    uefi_protocol::acpi_table::AcpiTableProtocol,
+};
+use patina_pi::{fw_fs::ffs::section::raw_type, protocols::firmware_volume};
+use r_efi::{
+    efi::{self, protocols::graphics_output},
+    system::EVENT_GROUP_READY_TO_BOOT,
+};
+
+use crate::{
+    bgrt::Bgrt,
+    bmp::Bitmap,
+    config::{BootLogoConfig, LogoPlacement, LogoSource},
+};
+
+/// Boot Logo Component.
+#[derive(IntoComponent)]
+pub struct BootLogo;
+
+/// The logo drawn on the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct DisplayedLogo {
+    /// The address of the BMP image of the logo, in boot services data.
+    image_address: u64,
+    /// The offset of the logo on the screen.
+    offset: (u32, u32),
+}
+
+/// The state of the [BootLogo] component, shared by its events.
+#[derive(Default)]
+struct BootLogoState {
+    logo: Option<DisplayedLogo>,
+    table_key: Option<usize>,
+}
+
+/// Context of the events of the [BootLogo] component.
+pub struct BootLogoContext<BB> {
+    boot_services: BB,
+    config: Rc<BootLogoConfig>,
+    state: Rc<RefCell<BootLogoState>>,
+}
+
+impl<BB: Clone> Clone for BootLogoContext<BB> {
+    fn clone(&self) -> Self {
+        Self { boot_services: self.boot_services.clone(), config: self.config.clone(), state: self.state.clone() }
+    }
+}
+
+impl BootLogo {
+    /// Entry point of [`BootLogo`]
+    #[coverage(off)] // This is tested via the generic version, see _entry_point.
+    pub fn entry_point(
+        self,
+        config: Config<BootLogoConfig>,
+        boot_services: StandardBootServices,
+    ) -> Result<(), EfiError> {
+        self._entry_point(boot_services, (*config).clone())
+    }
+
+    /// Entry point that have generic parameter.
+    fn _entry_point<BB, B>(self, boot_services: BB, config: BootLogoConfig) -> Result<(), EfiError>
+    where
+        BB: AsRef<B> + Clone + 'static,
+        B: BootServices + 'static,
+    {
+        if config.logo == LogoSource::None {
+            log::info!("Boot logo: no logo configured.");
+            return Ok(());
+        }
+
+        let context = BootLogoContext {
+            boot_services: BB::clone(&boot_services),
+            config: Rc::new(config),
+            state: Rc::new(RefCell::new(BootLogoState::default())),
+        };
+
+        EventBuilder::new(BB::clone(&boot_services), EventType::NOTIFY_SIGNAL, Tpl::CALLBACK)
+            .event_group(&EVENT_GROUP_READY_TO_BOOT)
+            .create(on_ready_to_boot::<BB, B>, context.clone())?;
+
+        let event = EventBuilder::new(BB::clone(&boot_services), EventType::NOTIFY_SIGNAL, Tpl::CALLBACK)
+            .create(on_gop_installed::<BB, B>, context)?;
+        boot_services.as_ref().register_protocol_notify(&graphics_output::PROTOCOL_GUID, event.event())?;
+        // The GOP may already be installed.
+        boot_services.as_ref().signal_event(event.event())?;
+        Ok(())
+    }
+}
+
+/// Notify function of the event signaled when a GOP is installed, drawing the logo if it is not drawn yet.
+fn on_gop_installed<BB, B>(_event: efi::Event, context: &mut BootLogoContext<BB>)
+where
+    BB: AsRef<B>,
+    B: BootServices,
+{
+    if context.state.borrow().logo.is_some() {
+        return;
+    }
+
+    let boot_services = context.boot_services.as_ref();
+    // SAFETY: The GOP is only used for the duration of this call.
+    let Ok(gop) = (unsafe { boot_services.locate_protocol::<graphics_output::Protocol>(None) }) else {
+        return;
+    };
+
+    match draw_logo(boot_services, gop, &context.config) {
+        Ok(logo) => context.state.borrow_mut().logo = Some(logo),
+        Err(err) => log::error!("Boot logo: failed to draw the logo: {err:?}"),
+    }
+}
+
+/// Ready to boot notify function, publishing the BGRT.
+fn on_ready_to_boot<BB, B>(_event: efi::Event, context: &mut BootLogoContext<BB>)
+where
+    BB: AsRef<B>,
+    B: BootServices,
+{
+    let mut state = context.state.borrow_mut();
+    let Some(logo) = state.logo else {
+        log::warn!("Boot logo: the logo was not drawn, the BGRT is not published.");
+        return;
+    };
+
+    let boot_services = context.boot_services.as_ref();
+    // SAFETY: The ACPI Table Protocol is only used for the duration of this call.
+    let acpi_table = match unsafe { boot_services.locate_protocol::<AcpiTableProtocol>(None) } {
+        Ok(acpi_table) => acpi_table,
+        Err(status) => {
+            log::error!("Boot logo: ACPI Table Protocol not found, the BGRT is not published: {status:#x?}");
+            return;
+        }
+    };
+
+    // The logo is only known to be displayed until the first boot attempt.
+    let displayed = match state.table_key.take() {
+        Some(table_key) => {
+            if let Err(status) = acpi_table.uninstall_acpi_table(table_key) {
+                log::warn!("Boot logo: failed to uninstall the previous BGRT: {status:#x?}");
+            }
+            false
+        }
+        None => true,
+    };
+
+    let config = &context.config;
+    let bgrt =
+        Bgrt::new(config.oem_id, config.oem_table_id, config.oem_revision, displayed, logo.image_address, logo.offset);
+    match acpi_table.install_acpi_table(bgrt.as_bytes()) {
+        Ok(table_key) => {
+            log::info!("Boot logo: BGRT published, logo displayed: {displayed}.");
+            state.table_key = Some(table_key);
+        }
+        Err(status) => log::error!("Boot logo: failed to install the BGRT: {status:#x?}"),
+    }
+}
+
+/// Draws the logo with `gop`, and copies its BMP image to boot services data for the BGRT.
+fn draw_logo<B: BootServices>(
+    boot_services: &B,
+    gop: &mut graphics_output::Protocol,
+    config: &BootLogoConfig,
+) -> Result<DisplayedLogo, EfiError> {
+    let bmp = match &config.logo {
+        LogoSource::None => return Err(EfiError::NotFound),
+        LogoSource::Bitmap(bmp) => bmp.clone(),
+        LogoSource::File(file) => read_logo_file(boot_services, file)?,
+    };
+    let bitmap = Bitmap::from_bmp(&bmp).map_err(|err| {
+        log::error!("Boot logo: {err}");
+        EfiError::Unsupported
+    })?;
+
+    // SAFETY: The mode and mode information of a GOP are valid while it is installed.
+    let info = unsafe { *(*gop.mode).info };
+    let screen = (info.horizontal_resolution, info.vertical_resolution);
+    let offset = logo_offset(config.placement, screen, (bitmap.width(), bitmap.height())).ok_or_else(|| {
+        log::error!(
+            "Boot logo: the {}x{} logo does not fit on the {screen:?} screen.",
+            bitmap.width(),
+            bitmap.height()
+        );
+        EfiError::BadBufferSize
+    })?;
+
+    let status = (gop.blt)(
+        gop,
+        bitmap.pixels().as_ptr() as *mut graphics_output::BltPixel,
+        graphics_output::BLT_BUFFER_TO_VIDEO,
+        0,
+        0,
+        offset.0 as usize,
+        offset.1 as usize,
+        bitmap.width() as usize,
+        bitmap.height() as usize,
+        0,
+    );
+    EfiError::status_to_result(status)?;
+
+    // The image referenced by the BGRT must remain in memory for the operating system to copy it.
+    let image = bitmap.to_bmp();
+    let image_address = boot_services.allocate_pool(MemoryType::BOOT_SERVICES_DATA, image.len())?;
+    // SAFETY: The buffer was allocated with the size of the image.
+    unsafe { ptr::copy_nonoverlapping(image.as_ptr(), image_address, image.len()) };
+
+    Ok(DisplayedLogo { image_address: image_address as u64, offset })
+}
+
+/// Returns the offset of a logo of the given size on the screen, or `None` if it does not fit.
+fn logo_offset(
+    placement: LogoPlacement,
+    (screen_width, screen_height): (u32, u32),
+    (width, height): (u32, u32),
+) -> Option<(u32, u32)> {
+    let (x, y) = match placement {
+        LogoPlacement::Center => (screen_width.checked_sub(width)? / 2, screen_height.checked_sub(height)? / 2),
+        LogoPlacement::Offset { x, y } => (x, y),
+    };
+    (x.checked_add(width)? <= screen_width && y.checked_add(height)? <= screen_height).then_some((x, y))
+}
+
+/// Reads the raw section of the logo file from the first firmware volume containing it.
+fn read_logo_file<B: BootServices>(boot_services: &B, file: &efi::Guid) -> Result<Vec<u8>, EfiError> {
+    let handles = boot_services.locate_handle_buffer(HandleSearchType::ByProtocol(&firmware_volume::PROTOCOL_GUID))?;
+    for &handle in handles.iter() {
+        // SAFETY: The handle was returned for the Firmware Volume Protocol GUID.
+        let fv = unsafe { boot_services.handle_protocol_unchecked(handle, &firmware_volume::PROTOCOL_GUID) }?
+            as *const firmware_volume::Protocol;
+
+        let mut buffer = ptr::null_mut();
+        let mut size = 0;
+        let mut authentication_status = 0;
+        // SAFETY: The protocol was located above. A null buffer is allocated by the firmware volume driver.
+        let status = unsafe {
+            ((*fv).read_section)(fv, file, raw_type::RAW, 0, &mut buffer, &mut size, &mut authentication_status)
+        };
+        if status.is_error() || buffer.is_null() {
+            continue;
+        }
+
+        // SAFETY: The firmware volume driver returned a buffer of `size` bytes.
+        let data = unsafe { slice::from_raw_parts(buffer as *const u8, size) }.to_vec();
+        let _ = boot_services.free_pool(buffer as *mut u8);
+        return Ok(data);
+    }
+
+    log::error!("Boot logo: logo file {file:?} not found.");
+    Err(EfiError::NotFound)
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use patina::boot_services::{MockBootServices, event::EventContext};
+    use std::boxed::Box;
+
+    type TestEventContext = EventContext<Rc<MockBootServices>, BootLogoContext<Rc<MockBootServices>>>;
+
+    #[test]
+    fn test_entry_point_without_logo_does_nothing() {
+        let boot_services = MockBootServices::new();
+        assert_eq!(BootLogo._entry_point(Rc::new(boot_services), BootLogoConfig::default()), Ok(()));
+    }
+
+    #[test]
+    fn test_entry_point_registers_events() {
+        let mut boot_services = MockBootServices::new();
+        boot_services
+            .expect_create_event_ex::<Box<TestEventContext>>()
+            .once()
+            .withf_st(|event_type, notify_tpl, notify_function, _notify_context, event_group| {
+                assert_eq!(&EventType::NOTIFY_SIGNAL, event_type);
+                assert_eq!(&Tpl::CALLBACK, notify_tpl);
+                assert!(notify_function.is_some());
+                assert_eq!(&EVENT_GROUP_READY_TO_BOOT, event_group);
+                true
+            })
+            .return_const_st(Ok(1_usize as efi::Event));
+        boot_services.expect_create_event::<Box<TestEventContext>>().once().return_const_st(Ok(2_usize as efi::Event));
+        boot_services.expect_register_protocol_notify().once().returning(|protocol, event| {
+            assert_eq!(protocol, &graphics_output::PROTOCOL_GUID);
+            assert_eq!(event, 2_usize as efi::Event);
+            Ok(core::ptr::NonNull::dangling())
+        });
+        boot_services.expect_signal_event().once().returning(|event| {
+            assert_eq!(event, 2_usize as efi::Event);
+            Ok(())
+        });
+
+        let config = BootLogoConfig { logo: LogoSource::File(efi::Guid::from_bytes(&[1; 16])), ..Default::default() };
+        assert_eq!(BootLogo._entry_point(Rc::new(boot_services), config), Ok(()));
+    }
+
+    #[test]
+    fn test_logo_offset() {
+        assert_eq!(logo_offset(LogoPlacement::Center, (800, 600), (200, 100)), Some((300, 250)));
+        assert_eq!(logo_offset(LogoPlacement::Center, (800, 600), (801, 100)), None);
+        assert_eq!(logo_offset(LogoPlacement::Offset { x: 600, y: 500 }, (800, 600), (200, 100)), Some((600, 500)));
+        assert_eq!(logo_offset(LogoPlacement::Offset { x: 601, y: 0 }, (800, 600), (200, 100)), None);
+        assert_eq!(logo_offset(LogoPlacement::Offset { x: u32::MAX, y: 0 }, (800, 600), (200, 100)), None);
+    }
+}
@@ -0,0 +1,69 @@
+//! Patina Boot Logo Component Configuration
+//!
+//! The configuration can be set statically with `.with_config()` or produced dynamically during boot. If no
+//! configuration is provided, no logo is drawn and the BGRT is not published.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use alloc::vec::Vec;
+use r_efi::efi;
+
+/// The source of the logo, a BMP image.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum LogoSource {
+    /// No logo is drawn.
+    #[default]
+    None,
+    /// The logo is the given BMP image.
+    Bitmap(Vec<u8>),
+    /// The logo is the raw section of the FFS file with the given name, in any firmware volume.
+    File(efi::Guid),
+}
+
+/// The position of the logo on the screen.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LogoPlacement {
+    /// The logo is centered on the screen.
+    #[default]
+    Center,
+    /// The upper left corner of the logo is at the given offset, in pixels, from the upper left corner of the
+    /// screen.
+    Offset {
+        /// The horizontal offset.
+        x: u32,
+        /// The vertical offset.
+        y: u32,
+    },
+}
+
+/// The configuration for the Patina Boot Logo component.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BootLogoConfig {
+    /// The logo drawn during boot.
+    pub logo: LogoSource,
+    /// The position of the logo on the screen.
+    pub placement: LogoPlacement,
+    /// The OEM ID of the BGRT.
+    pub oem_id: [u8; 6],
+    /// The OEM table ID of the BGRT.
+    pub oem_table_id: [u8; 8],
+    /// The OEM revision of the BGRT.
+    pub oem_revision: u32,
+}
+
+impl Default for BootLogoConfig {
+    fn default() -> Self {
+        Self {
+            logo: LogoSource::None,
+            placement: LogoPlacement::Center,
+            oem_id: *b"PATINA",
+            oem_table_id: *b"PATINA  ",
+            oem_revision: 1,
+        }
+    }
+}
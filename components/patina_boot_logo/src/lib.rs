@@ -0,0 +1,44 @@
+//! Boot logo and Boot Graphics Resource Table (BGRT) support for Patina platforms.
+//!
+//! The BGRT is the ACPI table describing the logo displayed by the firmware during boot, so that the operating system
+//! can keep the logo on screen while it starts, for a seamless transition. This crate provides:
+//!
+//! - [bmp]: decoding the logo from a BMP image, and encoding the image referenced by the BGRT.
+//! - [bgrt]: the BGRT format.
+//! - [component::BootLogo]: a component that draws the logo with the Graphics Output Protocol (GOP) once it is
+//!   installed, and publishes the BGRT with the ACPI Table Protocol when the platform is ready to boot.
+//!
+//! ## Integration Example
+//!
+//! ```rust,ignore
+//! // ...
+//!
+//! Core::default()
+//!  // ...
+//!  .with_config(patina_boot_logo::config::BootLogoConfig {
+//!      logo: patina_boot_logo::config::LogoSource::File(LOGO_FILE_GUID),
+//!      ..Default::default()
+//!  })
+//!  .with_component(patina_boot_logo::component::BootLogo)
+//!  .start()
+//!  .unwrap();
+//!
+//! // ...
+//! ```
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+#![cfg_attr(not(test), no_std)]
+#![feature(coverage_attribute)]
+
+extern crate alloc;
+
+pub mod bgrt;
+pub mod bmp;
+pub mod component;
+pub mod config;
@@ -0,0 +1,190 @@
+//! Integration tests drawing the boot logo and publishing the BGRT against the host DXE core boot services.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use core::{ffi::c_void, mem, ptr, slice};
+use std::sync::Mutex;
+
+use patina::{
+    boot_services::{
+        BootServices,
+        event::{EventBuilder, EventType},
+        tpl::Tpl,
+    },
+    uefi_protocol::acpi_table,
+};
+use patina_boot_logo::{
+    bgrt::{BGRT_STATUS_DISPLAYED, Bgrt},
+    bmp::Bitmap,
+    component::BootLogo,
+    config::{BootLogoConfig, LogoSource},
+};
+use patina_test::TestHarness;
+use r_efi::{
+    efi::{self, protocols::graphics_output},
+    system::EVENT_GROUP_READY_TO_BOOT,
+};
+
+/// A blt operation performed on the mock GOP: operation, destination and size.
+type Blt = (u32, usize, usize, usize, usize);
+
+/// The blt operations performed on the mock GOP.
+static BLTS: Mutex<Vec<Blt>> = Mutex::new(Vec::new());
+/// The tables installed with the mock ACPI Table Protocol, by key.
+static TABLES: Mutex<Vec<(usize, Vec<u8>)>> = Mutex::new(Vec::new());
+
+extern "efiapi" fn query_mode(
+    _: *mut graphics_output::Protocol,
+    _: u32,
+    _: *mut usize,
+    _: *mut *mut graphics_output::ModeInformation,
+) -> efi::Status {
+    efi::Status::UNSUPPORTED
+}
+
+extern "efiapi" fn set_mode(_: *mut graphics_output::Protocol, _: u32) -> efi::Status {
+    efi::Status::UNSUPPORTED
+}
+
+extern "efiapi" fn blt(
+    _this: *mut graphics_output::Protocol,
+    _blt_buffer: *mut graphics_output::BltPixel,
+    blt_operation: graphics_output::BltOperation,
+    _source_x: usize,
+    _source_y: usize,
+    destination_x: usize,
+    destination_y: usize,
+    width: usize,
+    height: usize,
+    _delta: usize,
+) -> efi::Status {
+    BLTS.lock().unwrap().push((blt_operation, destination_x, destination_y, width, height));
+    efi::Status::SUCCESS
+}
+
+extern "efiapi" fn install_acpi_table(
+    _this: *mut acpi_table::Protocol,
+    acpi_table_buffer: *const c_void,
+    acpi_table_buffer_size: usize,
+    table_key: *mut usize,
+) -> efi::Status {
+    let mut tables = TABLES.lock().unwrap();
+    let key = tables.len() + 1;
+    // SAFETY: The buffer is provided by the caller with its size.
+    let table = unsafe { slice::from_raw_parts(acpi_table_buffer as *const u8, acpi_table_buffer_size) };
+    tables.push((key, table.to_vec()));
+    // SAFETY: The key is provided by the caller.
+    unsafe { table_key.write(key) };
+    efi::Status::SUCCESS
+}
+
+extern "efiapi" fn uninstall_acpi_table(_this: *mut acpi_table::Protocol, table_key: usize) -> efi::Status {
+    let mut tables = TABLES.lock().unwrap();
+    match tables.iter().position(|(key, _)| *key == table_key) {
+        Some(index) => {
+            tables.remove(index);
+            efi::Status::SUCCESS
+        }
+        None => efi::Status::NOT_FOUND,
+    }
+}
+
+/// Builds a 4x2 24 bits per pixel BMP image.
+fn logo() -> Vec<u8> {
+    let mut data = vec![0; 54];
+    data[0..2].copy_from_slice(b"BM");
+    data[10..14].copy_from_slice(&54_u32.to_le_bytes());
+    data[14..18].copy_from_slice(&40_u32.to_le_bytes());
+    data[18..22].copy_from_slice(&4_i32.to_le_bytes());
+    data[22..26].copy_from_slice(&2_i32.to_le_bytes());
+    data[26..28].copy_from_slice(&1_u16.to_le_bytes());
+    data[28..30].copy_from_slice(&24_u16.to_le_bytes());
+    data.extend((0..24).map(|byte| byte as u8));
+    data
+}
+
+/// Returns the BGRT installed with the mock ACPI Table Protocol.
+fn installed_bgrt() -> Bgrt {
+    let tables = TABLES.lock().unwrap();
+    assert_eq!(tables.len(), 1);
+    let table = &tables[0].1;
+    assert_eq!(table.len(), mem::size_of::<Bgrt>());
+    // SAFETY: The table has the size of a BGRT.
+    unsafe { ptr::read_unaligned(table.as_ptr() as *const Bgrt) }
+}
+
+fn ready_to_boot(_event: efi::Event, _context: &mut ()) {}
+
+#[test]
+fn test_logo_is_drawn_and_bgrt_published() {
+    let config = BootLogoConfig { logo: LogoSource::Bitmap(logo()), ..Default::default() };
+    let mut harness = TestHarness::new().with_config(config).with_component(BootLogo);
+    harness.run().unwrap();
+    assert!(harness.pending_components().is_empty());
+    let boot_services = harness.boot_services();
+
+    // The logo is drawn once the GOP is installed.
+    assert!(BLTS.lock().unwrap().is_empty());
+    let mut info = graphics_output::ModeInformation {
+        version: 0,
+        horizontal_resolution: 800,
+        vertical_resolution: 600,
+        pixel_format: graphics_output::PIXEL_BLUE_GREEN_RED_RESERVED_8_BIT_PER_COLOR,
+        pixel_information: graphics_output::PixelBitmask { red_mask: 0, green_mask: 0, blue_mask: 0, reserved_mask: 0 },
+        pixels_per_scan_line: 800,
+    };
+    let mut mode = graphics_output::Mode {
+        max_mode: 1,
+        mode: 0,
+        info: &mut info,
+        size_of_info: mem::size_of::<graphics_output::ModeInformation>(),
+        frame_buffer_base: 0,
+        frame_buffer_size: 0,
+    };
+    let mut gop = graphics_output::Protocol { query_mode, set_mode, blt, mode: &mut mode };
+    let mut acpi_table = acpi_table::Protocol { install_acpi_table, uninstall_acpi_table };
+    // SAFETY: The protocols outlive the test, which uninstalls nothing.
+    unsafe {
+        boot_services
+            .install_protocol_interface_unchecked(
+                None,
+                &graphics_output::PROTOCOL_GUID,
+                &mut gop as *mut _ as *mut c_void,
+            )
+            .unwrap();
+        boot_services
+            .install_protocol_interface_unchecked(
+                None,
+                &acpi_table::PROTOCOL_GUID,
+                &mut acpi_table as *mut _ as *mut c_void,
+            )
+            .unwrap();
+    }
+    assert_eq!(*BLTS.lock().unwrap(), [(graphics_output::BLT_BUFFER_TO_VIDEO, 398, 299, 4, 2)]);
+
+    // Signal ready to boot, as BDS does before starting a boot option.
+    let event = EventBuilder::new(boot_services.clone(), EventType::NOTIFY_SIGNAL, Tpl::CALLBACK)
+        .event_group(&EVENT_GROUP_READY_TO_BOOT)
+        .create(ready_to_boot, ())
+        .unwrap();
+    boot_services.signal_event(event.event()).unwrap();
+
+    let bgrt = installed_bgrt();
+    assert_eq!(bgrt.status, BGRT_STATUS_DISPLAYED);
+    assert_eq!({ bgrt.image_offset_x }, 398);
+    assert_eq!({ bgrt.image_offset_y }, 299);
+    // The image referenced by the BGRT is the logo.
+    let image = unsafe { slice::from_raw_parts(bgrt.image_address as *const u8, logo().len()) };
+    let decoded = Bitmap::from_bmp(image).unwrap();
+    assert_eq!((decoded.width(), decoded.height()), (4, 2));
+
+    // A second boot attempt republishes the BGRT, as the logo may no longer be displayed.
+    boot_services.signal_event(event.event()).unwrap();
+    assert_eq!(installed_bgrt().status, 0);
+
+    event.close().unwrap();
+}
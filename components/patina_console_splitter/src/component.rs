@@ -0,0 +1,400 @@
+//! Patina Console Splitter Component
+//!
+//! Installs the virtual consoles of the platform on dedicated handles, and publishes them in the system table:
+//!
+//! - `ConIn`: a Simple Text Input Protocol reading from all the console input devices.
+//! - `ConOut`: a Simple Text Output Protocol writing to all the console output devices, and a Graphics Output
+//!   Protocol (GOP) drawing on all of them while at least one of them has a GOP.
+//! - `StdErr`: a Simple Text Output Protocol writing to all the standard error devices.
+//!
+//! Devices are added to a console by a driver binding when they are connected, and removed when they are
+//! disconnected. A device is used as a console once it is tagged with the [CONSOLE_IN_DEVICE],
+//! [CONSOLE_OUT_DEVICE] or [STANDARD_ERROR_DEVICE] GUID, usually by the boot manager for the devices listed in the
+//! `ConIn`, `ConOut` and `ErrOut` variables, and connected.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::boxed::Box;
+use core::{ffi::c_void, ptr::NonNull};
+
+use patina::{
+    boot_services::{BootServices, StandardBootServices},
+    component::IntoComponent,
+    driver_binding::{DriverBinding, UefiDriverBinding},
+    error::EfiError,
+    guids::{CONSOLE_IN_DEVICE, CONSOLE_OUT_DEVICE, STANDARD_ERROR_DEVICE},
+    uefi_protocol::loaded_image::LoadedImage,
+};
+use r_efi::efi::{
+    self,
+    protocols::{device_path, graphics_output, simple_text_input, simple_text_output},
+};
+
+use crate::{graphics_output::GraphicsOutputSplitter, text_in::TextInSplitter, text_out::TextOutSplitter};
+
+/// Console Splitter Component.
+#[derive(IntoComponent)]
+pub struct ConsoleSplitter;
+
+/// The virtual console a [ConsoleDriver] adds devices to.
+enum VirtualConsole<B: BootServices + 'static> {
+    ConIn(*mut TextInSplitter<B>),
+    ConOut { text_out: *mut TextOutSplitter, graphics_output: *mut GraphicsOutputSplitter<B> },
+    StdErr(*mut TextOutSplitter),
+}
+
+/// Driver binding adding the tagged devices to a virtual console.
+///
+/// The driver binding is installed on the handle of the virtual console, which is the agent opening the protocols of
+/// the devices.
+struct ConsoleDriver<B: BootServices + 'static> {
+    handle: efi::Handle,
+    console: VirtualConsole<B>,
+}
+
+impl<B: BootServices + 'static> ConsoleDriver<B> {
+    /// Returns the GUID tagging the devices of the console.
+    fn tag(&self) -> &'static efi::Guid {
+        match self.console {
+            VirtualConsole::ConIn(_) => &CONSOLE_IN_DEVICE,
+            VirtualConsole::ConOut { .. } => &CONSOLE_OUT_DEVICE,
+            VirtualConsole::StdErr(_) => &STANDARD_ERROR_DEVICE,
+        }
+    }
+
+    /// Returns the protocols of the console devices: the first one is required unless the second one is present.
+    fn protocols(&self) -> (&'static efi::Guid, Option<&'static efi::Guid>) {
+        match self.console {
+            VirtualConsole::ConIn(_) => (&simple_text_input::PROTOCOL_GUID, None),
+            VirtualConsole::ConOut { .. } => {
+                (&simple_text_output::PROTOCOL_GUID, Some(&graphics_output::PROTOCOL_GUID))
+            }
+            VirtualConsole::StdErr(_) => (&simple_text_output::PROTOCOL_GUID, None),
+        }
+    }
+
+    /// Returns the interface of `protocol` on `controller`, if present.
+    fn get_protocol<T: BootServices>(
+        &self,
+        boot_services: &T,
+        controller: efi::Handle,
+        protocol: &efi::Guid,
+    ) -> Option<*mut c_void> {
+        // SAFETY: The interface is only returned, its type is known by the caller from the protocol GUID.
+        unsafe {
+            boot_services.open_protocol_unchecked(
+                controller,
+                protocol,
+                self.handle,
+                controller,
+                efi::OPEN_PROTOCOL_GET_PROTOCOL,
+            )
+        }
+        .ok()
+        .filter(|interface| !interface.is_null())
+    }
+
+    /// Adds the console protocols of `controller` to the virtual console.
+    fn add_device<T: BootServices>(&mut self, boot_services: &T, controller: efi::Handle) -> Result<(), efi::Status> {
+        let (protocol, other_protocol) = self.protocols();
+        let interface = self.get_protocol(boot_services, controller, protocol);
+        let other_interface =
+            other_protocol.and_then(|protocol| self.get_protocol(boot_services, controller, protocol));
+
+        // SAFETY: The splitters are leaked by the component, and the interfaces match their protocol GUIDs.
+        unsafe {
+            match self.console {
+                VirtualConsole::ConIn(text_in) => {
+                    (*text_in).add_device(interface.ok_or(efi::Status::UNSUPPORTED)? as *mut _)
+                }
+                VirtualConsole::StdErr(text_out) => {
+                    (*text_out).add_device(interface.ok_or(efi::Status::UNSUPPORTED)? as *mut _)
+                }
+                VirtualConsole::ConOut { text_out, graphics_output } => {
+                    if interface.is_none() && other_interface.is_none() {
+                        return Err(efi::Status::UNSUPPORTED);
+                    }
+                    if let Some(interface) = interface {
+                        (*text_out).add_device(interface as *mut _)?;
+                    }
+                    if let Some(other_interface) = other_interface
+                        && let Err(status) =
+                            self.add_graphics_output(boot_services, graphics_output, other_interface as *mut _)
+                    {
+                        if let Some(interface) = interface {
+                            let _ = (*text_out).remove_device(interface as *mut _);
+                        }
+                        return Err(status);
+                    }
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    /// Adds a GOP to the virtual console, installing the GOP of the console with the first one.
+    ///
+    /// # Safety
+    ///
+    /// `splitter` must be the leaked GOP splitter of the console and `protocol` a GOP.
+    unsafe fn add_graphics_output<T: BootServices>(
+        &self,
+        boot_services: &T,
+        splitter: *mut GraphicsOutputSplitter<B>,
+        protocol: *mut graphics_output::Protocol,
+    ) -> Result<(), efi::Status> {
+        // SAFETY: Per the caller.
+        let splitter = unsafe { &mut *splitter };
+        splitter.add_device(protocol)?;
+        if splitter.device_count() == 1 {
+            // SAFETY: The splitter is leaked and produces a GOP.
+            let result = unsafe {
+                boot_services.install_protocol_interface_unchecked(
+                    Some(self.handle),
+                    &graphics_output::PROTOCOL_GUID,
+                    splitter.protocol() as *mut c_void,
+                )
+            };
+            if let Err(status) = result {
+                let _ = splitter.remove_device(protocol);
+                return Err(status);
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the console protocols of `controller` from the virtual console.
+    fn remove_device<T: BootServices>(&mut self, boot_services: &T, controller: efi::Handle) {
+        let (protocol, other_protocol) = self.protocols();
+        let interface = self.get_protocol(boot_services, controller, protocol);
+        let other_interface =
+            other_protocol.and_then(|protocol| self.get_protocol(boot_services, controller, protocol));
+
+        // SAFETY: The splitters are leaked by the component, and the interfaces match their protocol GUIDs. Removing
+        // an interface that was not added to a splitter does nothing.
+        unsafe {
+            match self.console {
+                VirtualConsole::ConIn(text_in) => {
+                    if let Some(interface) = interface {
+                        let _ = (*text_in).remove_device(interface as *mut _);
+                    }
+                }
+                VirtualConsole::StdErr(text_out) => {
+                    if let Some(interface) = interface {
+                        let _ = (*text_out).remove_device(interface as *mut _);
+                    }
+                }
+                VirtualConsole::ConOut { text_out, graphics_output } => {
+                    if let Some(interface) = interface {
+                        let _ = (*text_out).remove_device(interface as *mut _);
+                    }
+                    if let Some(interface) = other_interface
+                        && (*graphics_output).remove_device(interface as *mut _).is_ok()
+                        && (*graphics_output).device_count() == 0
+                    {
+                        let _ = boot_services.uninstall_protocol_interface_unchecked(
+                            self.handle,
+                            &graphics_output::PROTOCOL_GUID,
+                            (*graphics_output).protocol() as *mut c_void,
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<B: BootServices + 'static> DriverBinding for ConsoleDriver<B> {
+    fn driver_binding_supported<T: BootServices + 'static>(
+        &self,
+        boot_services: &'static T,
+        controller: efi::Handle,
+        _remaining_device_path: Option<NonNull<device_path::Protocol>>,
+    ) -> Result<bool, efi::Status> {
+        let test = |protocol: &efi::Guid| {
+            // SAFETY: The protocol is only tested for, the interface is not used.
+            unsafe {
+                boot_services.open_protocol_unchecked(
+                    controller,
+                    protocol,
+                    self.handle,
+                    controller,
+                    efi::OPEN_PROTOCOL_TEST_PROTOCOL,
+                )
+            }
+            .is_ok()
+        };
+        let (protocol, other_protocol) = self.protocols();
+        Ok(test(self.tag()) && (test(protocol) || other_protocol.is_some_and(test)))
+    }
+
+    fn driver_binding_start<T: BootServices + 'static>(
+        &mut self,
+        boot_services: &'static T,
+        controller: efi::Handle,
+        _remaining_device_path: Option<NonNull<device_path::Protocol>>,
+    ) -> Result<(), efi::Status> {
+        // The tag is opened by driver, so that the device is removed from the console when it is disconnected.
+        // SAFETY: The tag has no interface.
+        unsafe {
+            boot_services.open_protocol_unchecked(
+                controller,
+                self.tag(),
+                self.handle,
+                controller,
+                efi::OPEN_PROTOCOL_BY_DRIVER,
+            )
+        }?;
+
+        if let Err(status) = self.add_device(boot_services, controller) {
+            log::error!("Console splitter: failed to add console device {controller:?}: {status:#x?}");
+            let _ = boot_services.close_protocol(controller, self.tag(), self.handle, controller);
+            return Err(status);
+        }
+        Ok(())
+    }
+
+    fn driver_binding_stop<T: BootServices + 'static>(
+        &mut self,
+        boot_services: &'static T,
+        controller: efi::Handle,
+        _number_of_children: usize,
+        _child_handle_buffer: Option<NonNull<efi::Handle>>,
+    ) -> Result<(), efi::Status> {
+        self.remove_device(boot_services, controller);
+        boot_services.close_protocol(controller, self.tag(), self.handle, controller)
+    }
+}
+
+impl ConsoleSplitter {
+    /// Entry point of [`ConsoleSplitter`]
+    #[coverage(off)] // This is tested via the generic version, see _entry_point.
+    pub fn entry_point(self, boot_services: StandardBootServices) -> Result<(), EfiError> {
+        self._entry_point(boot_services)
+    }
+
+    /// Entry point that have generic parameter.
+    fn _entry_point<BB, B>(self, boot_services: BB) -> Result<(), EfiError>
+    where
+        BB: AsRef<B> + Clone + 'static,
+        B: BootServices + 'static,
+    {
+        // The splitters and driver bindings live until the end of boot services.
+        let boot_services: &'static BB = Box::leak(Box::new(boot_services));
+        let boot_services: &'static B = boot_services.as_ref();
+
+        let con_in = TextInSplitter::new(boot_services)?;
+        let con_out = Box::leak(TextOutSplitter::new());
+        let std_err = Box::leak(TextOutSplitter::new());
+        // SAFETY: The splitters are leaked and produce the protocols they are installed as.
+        let (con_in_handle, con_out_handle, std_err_handle) = unsafe {
+            (
+                boot_services.install_protocol_interface_unchecked(
+                    None,
+                    &simple_text_input::PROTOCOL_GUID,
+                    con_in.protocol() as *mut c_void,
+                )?,
+                boot_services.install_protocol_interface_unchecked(
+                    None,
+                    &simple_text_output::PROTOCOL_GUID,
+                    con_out.protocol() as *mut c_void,
+                )?,
+                boot_services.install_protocol_interface_unchecked(
+                    None,
+                    &simple_text_output::PROTOCOL_GUID,
+                    std_err.protocol() as *mut c_void,
+                )?,
+            )
+        };
+
+        // SAFETY: The Loaded Image Protocol of the DXE core references the system table.
+        let loaded_image = unsafe { boot_services.locate_protocol::<LoadedImage>(None) }?;
+        // SAFETY: The system table lives until the end of boot services.
+        let system_table = unsafe { loaded_image.system_table().as_mut() }.ok_or(EfiError::NotFound)?;
+        system_table.console_in_handle = con_in_handle;
+        system_table.con_in = con_in.protocol();
+        system_table.console_out_handle = con_out_handle;
+        system_table.con_out = con_out.protocol();
+        system_table.standard_error_handle = std_err_handle;
+        system_table.std_err = std_err.protocol();
+        system_table.hdr.crc32 = 0;
+        system_table.hdr.crc32 = boot_services.calculate_crc_32(system_table)?;
+
+        let drivers = [
+            ConsoleDriver { handle: con_in_handle, console: VirtualConsole::ConIn(con_in) },
+            ConsoleDriver {
+                handle: con_out_handle,
+                console: VirtualConsole::ConOut {
+                    text_out: con_out,
+                    graphics_output: Box::leak(GraphicsOutputSplitter::new(boot_services)),
+                },
+            },
+            ConsoleDriver { handle: std_err_handle, console: VirtualConsole::StdErr(std_err) },
+        ];
+        for driver in drivers {
+            let handle = driver.handle;
+            UefiDriverBinding::new(driver, handle, boot_services).install()?;
+        }
+
+        log::info!("Console splitter: ConIn {con_in_handle:?}, ConOut {con_out_handle:?}, StdErr {std_err_handle:?}.");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use core::ptr;
+    use patina::boot_services::MockBootServices;
+
+    fn driver(boot_services: &'static MockBootServices, console: u8) -> ConsoleDriver<MockBootServices> {
+        let console = match console {
+            0 => VirtualConsole::ConIn(ptr::null_mut()),
+            1 => VirtualConsole::ConOut {
+                text_out: ptr::null_mut(),
+                graphics_output: Box::leak(GraphicsOutputSplitter::new(boot_services)),
+            },
+            _ => VirtualConsole::StdErr(ptr::null_mut()),
+        };
+        ConsoleDriver { handle: 1_usize as efi::Handle, console }
+    }
+
+    fn boot_services(protocols: &'static [&'static efi::Guid]) -> &'static MockBootServices {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_open_protocol_unchecked().returning(move |controller, protocol, agent, _, attributes| {
+            assert_eq!(controller, 2_usize as efi::Handle);
+            assert_eq!(agent, 1_usize as efi::Handle);
+            assert_eq!(attributes, efi::OPEN_PROTOCOL_TEST_PROTOCOL);
+            match protocols.contains(&protocol) {
+                true => Ok(ptr::null_mut()),
+                false => Err(efi::Status::UNSUPPORTED),
+            }
+        });
+        Box::leak(Box::new(boot_services))
+    }
+
+    #[test]
+    fn test_supported_requires_the_tag_and_a_console_protocol() {
+        let controller = 2_usize as efi::Handle;
+        let supported = |protocols: &'static [&'static efi::Guid], console| {
+            let boot_services = boot_services(protocols);
+            driver(boot_services, console).driver_binding_supported(boot_services, controller, None)
+        };
+
+        assert_eq!(supported(&[&CONSOLE_IN_DEVICE, &simple_text_input::PROTOCOL_GUID], 0), Ok(true));
+        assert_eq!(supported(&[&simple_text_input::PROTOCOL_GUID], 0), Ok(false));
+        assert_eq!(supported(&[&CONSOLE_IN_DEVICE], 0), Ok(false));
+        assert_eq!(supported(&[&CONSOLE_OUT_DEVICE, &simple_text_output::PROTOCOL_GUID], 1), Ok(true));
+        assert_eq!(supported(&[&CONSOLE_OUT_DEVICE, &graphics_output::PROTOCOL_GUID], 1), Ok(true));
+        assert_eq!(supported(&[&CONSOLE_OUT_DEVICE, &simple_text_output::PROTOCOL_GUID], 2), Ok(false));
+        assert_eq!(supported(&[&STANDARD_ERROR_DEVICE, &graphics_output::PROTOCOL_GUID], 2), Ok(false));
+        assert_eq!(supported(&[&STANDARD_ERROR_DEVICE, &simple_text_output::PROTOCOL_GUID], 2), Ok(true));
+    }
+}
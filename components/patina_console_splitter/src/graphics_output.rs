@@ -0,0 +1,571 @@
+//! Graphics Output Splitter
+//!
+//! [GraphicsOutputSplitter] produces a Graphics Output Protocol (GOP) that draws on any number of graphics devices.
+//! Blt operations writing to the screen are forwarded to all the devices, reads are done from the first device, and
+//! the modes reported are the resolutions supported by all of them.
+//!
+//! The frame buffer of a single device is exposed as is. With multiple devices, the GOP of the splitter is blt only,
+//! as a write to the frame buffer of one device would not be replicated to the others.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::{boxed::Box, vec::Vec};
+use core::{mem, ptr};
+
+use patina::boot_services::{BootServices, allocation::MemoryType};
+use r_efi::efi::{self, protocols::graphics_output};
+
+/// A graphics device of the splitter.
+struct GraphicsOutputDevice {
+    protocol: *mut graphics_output::Protocol,
+    /// The resolutions of the modes of the device, by mode number, or `None` for invalid modes.
+    modes: Vec<Option<(u32, u32)>>,
+}
+
+impl GraphicsOutputDevice {
+    fn new<B: BootServices>(boot_services: &B, protocol: *mut graphics_output::Protocol) -> Self {
+        // SAFETY: The protocol is valid while the device is in the splitter, its mode pointer is null or valid.
+        let max_mode = unsafe { (*protocol).mode.as_ref() }.map_or(0, |mode| mode.max_mode);
+        let modes = (0..max_mode)
+            .map(|mode_number| {
+                let mut size = 0;
+                let mut info = ptr::null_mut();
+                // SAFETY: The protocol is valid while the device is in the splitter.
+                let status = unsafe { ((*protocol).query_mode)(protocol, mode_number, &mut size, &mut info) };
+                if status != efi::Status::SUCCESS || info.is_null() {
+                    return None;
+                }
+                // SAFETY: The information was allocated from pool by the device, and is freed by the caller.
+                let resolution = unsafe { ((*info).horizontal_resolution, (*info).vertical_resolution) };
+                let _ = boot_services.free_pool(info as *mut u8);
+                Some(resolution)
+            })
+            .collect();
+        Self { protocol, modes }
+    }
+
+    /// Returns the mode number of the device with the given resolution.
+    fn mode_number(&self, resolution: (u32, u32)) -> Option<u32> {
+        self.modes.iter().position(|mode| *mode == Some(resolution)).map(|mode_number| mode_number as u32)
+    }
+
+    /// Returns the current mode of the device.
+    fn mode(&self) -> Option<&graphics_output::Mode> {
+        // SAFETY: The protocol is valid while the device is in the splitter, its mode pointer is null or valid.
+        unsafe { (*self.protocol).mode.as_ref() }
+    }
+
+    /// Returns the resolution of the current mode of the device.
+    fn resolution(&self) -> Option<(u32, u32)> {
+        // SAFETY: The mode information of a device is null or valid.
+        let info = unsafe { self.mode()?.info.as_ref() }?;
+        Some((info.horizontal_resolution, info.vertical_resolution))
+    }
+}
+
+/// Returns the information of a blt only mode with the given resolution.
+fn blt_only_info((horizontal_resolution, vertical_resolution): (u32, u32)) -> graphics_output::ModeInformation {
+    graphics_output::ModeInformation {
+        version: 0,
+        horizontal_resolution,
+        vertical_resolution,
+        pixel_format: graphics_output::PIXEL_BLT_ONLY,
+        pixel_information: graphics_output::PixelBitmask { red_mask: 0, green_mask: 0, blue_mask: 0, reserved_mask: 0 },
+        pixels_per_scan_line: horizontal_resolution,
+    }
+}
+
+/// Graphics Output Protocol drawing on multiple graphics devices.
+#[repr(C)]
+pub(crate) struct GraphicsOutputSplitter<B: BootServices + 'static> {
+    // The protocol must be the first field, the protocol functions cast the protocol pointer to the splitter.
+    protocol: graphics_output::Protocol,
+    mode: graphics_output::Mode,
+    info: graphics_output::ModeInformation,
+    devices: Vec<GraphicsOutputDevice>,
+    /// The resolutions of the modes of the splitter, by mode number.
+    modes: Vec<(u32, u32)>,
+    boot_services: &'static B,
+}
+
+impl<B: BootServices + 'static> GraphicsOutputSplitter<B> {
+    /// Creates a splitter without devices.
+    pub(crate) fn new(boot_services: &'static B) -> Box<Self> {
+        let mut splitter = Box::new(Self {
+            protocol: graphics_output::Protocol {
+                query_mode: query_mode::<B>,
+                set_mode: set_mode::<B>,
+                blt: blt::<B>,
+                mode: ptr::null_mut(),
+            },
+            mode: graphics_output::Mode {
+                max_mode: 0,
+                mode: 0,
+                info: ptr::null_mut(),
+                size_of_info: mem::size_of::<graphics_output::ModeInformation>(),
+                frame_buffer_base: 0,
+                frame_buffer_size: 0,
+            },
+            info: blt_only_info((0, 0)),
+            devices: Vec::new(),
+            modes: Vec::new(),
+            boot_services,
+        });
+        splitter.mode.info = &mut splitter.info;
+        splitter.protocol.mode = &mut splitter.mode;
+        splitter
+    }
+
+    /// Returns the Graphics Output Protocol of the splitter.
+    pub(crate) fn protocol(&mut self) -> *mut graphics_output::Protocol {
+        &mut self.protocol
+    }
+
+    /// Adds a device to the splitter.
+    ///
+    /// The first device is kept in its current mode, so that what it displays (e.g. the boot logo) is not cleared.
+    /// The following devices are set to the current mode of the splitter, or all devices are set to mode 0 if the new
+    /// device does not support it.
+    pub(crate) fn add_device(&mut self, protocol: *mut graphics_output::Protocol) -> Result<(), efi::Status> {
+        if self.devices.iter().any(|device| device.protocol == protocol) {
+            return Err(efi::Status::ALREADY_STARTED);
+        }
+
+        let device = GraphicsOutputDevice::new(self.boot_services, protocol);
+        let current = match self.devices.is_empty() {
+            true => device.resolution(),
+            false => self.modes.get(self.mode.mode as usize).copied(),
+        };
+        self.devices.push(device);
+        self.update_modes();
+        let mode_number = current.and_then(|current| self.modes.iter().position(|mode| *mode == current));
+
+        let status = self.apply_mode(mode_number.unwrap_or(0) as u32, false);
+        if status.is_error() {
+            let _ = self.remove_device(protocol);
+            return Err(status);
+        }
+        Ok(())
+    }
+
+    /// Removes a device from the splitter, keeping the current mode.
+    pub(crate) fn remove_device(&mut self, protocol: *mut graphics_output::Protocol) -> Result<(), efi::Status> {
+        let index = self.devices.iter().position(|device| device.protocol == protocol).ok_or(efi::Status::NOT_FOUND)?;
+        self.devices.remove(index);
+
+        let current = self.modes.get(self.mode.mode as usize).copied();
+        self.update_modes();
+        if self.devices.is_empty() {
+            self.mode.mode = 0;
+            self.info = blt_only_info((0, 0));
+            return Ok(());
+        }
+        // The remaining devices are in the current mode, unless no common mode was found.
+        let mode_number = current.and_then(|current| self.modes.iter().position(|mode| *mode == current));
+        match self.apply_mode(mode_number.unwrap_or(0) as u32, false) {
+            status if status.is_error() => Err(status),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the number of devices of the splitter.
+    pub(crate) fn device_count(&self) -> usize {
+        self.devices.len()
+    }
+
+    /// Updates the modes of the splitter to the resolutions supported by all the devices, in the order of the first
+    /// device. The modes of the first device are used if the devices have no resolution in common.
+    fn update_modes(&mut self) {
+        let mut modes = Vec::new();
+        if let Some((first, others)) = self.devices.split_first() {
+            for &mode in first.modes.iter().flatten() {
+                if !modes.contains(&mode) && others.iter().all(|device| device.mode_number(mode).is_some()) {
+                    modes.push(mode);
+                }
+            }
+            if modes.is_empty() {
+                first.modes.iter().flatten().for_each(|mode| modes.push(*mode));
+            }
+        }
+        self.mode.max_mode = modes.len() as u32;
+        self.modes = modes;
+    }
+
+    /// Calls `function` for each device, returning the first error, or else the first warning, reported.
+    fn for_each_device(
+        &self,
+        mut function: impl FnMut(&GraphicsOutputDevice, *mut graphics_output::Protocol) -> efi::Status,
+    ) -> efi::Status {
+        let mut status = efi::Status::SUCCESS;
+        for device in &self.devices {
+            let device_status = function(device, device.protocol);
+            if status == efi::Status::SUCCESS || (device_status.is_error() && !status.is_error()) {
+                status = device_status;
+            }
+        }
+        status
+    }
+
+    /// Sets the devices to the resolution of `mode_number`. Devices already in that mode are only set again if
+    /// `force` is true, as setting a mode clears the screen.
+    fn apply_mode(&mut self, mode_number: u32, force: bool) -> efi::Status {
+        let Some(&resolution) = self.modes.get(mode_number as usize) else {
+            return efi::Status::UNSUPPORTED;
+        };
+        let status = self.for_each_device(|device, protocol| match device.mode_number(resolution) {
+            Some(device_mode_number) if force || device.mode().map(|mode| mode.mode) != Some(device_mode_number) => {
+                // SAFETY: The protocols are valid while the devices are in the splitter.
+                unsafe { ((*protocol).set_mode)(protocol, device_mode_number) }
+            }
+            Some(_) => efi::Status::SUCCESS,
+            None => efi::Status::UNSUPPORTED,
+        });
+        self.mode.mode = mode_number;
+        self.update_info(resolution);
+        status
+    }
+
+    /// Updates the information of the current mode, exposing the frame buffer of the device if there is only one.
+    fn update_info(&mut self, resolution: (u32, u32)) {
+        if let [device] = self.devices.as_slice()
+            && let Some(mode) = device.mode()
+            // SAFETY: The mode information of a device is null or valid.
+            && let Some(info) = unsafe { mode.info.as_ref() }
+        {
+            self.info = *info;
+            self.mode.frame_buffer_base = mode.frame_buffer_base;
+            self.mode.frame_buffer_size = mode.frame_buffer_size;
+            return;
+        }
+        self.info = blt_only_info(resolution);
+        self.mode.frame_buffer_base = 0;
+        self.mode.frame_buffer_size = 0;
+    }
+
+    fn query_mode(
+        &mut self,
+        mode_number: u32,
+        size_of_info: *mut usize,
+        info: *mut *mut graphics_output::ModeInformation,
+    ) -> efi::Status {
+        let Some(&resolution) = self.modes.get(mode_number as usize) else {
+            return efi::Status::INVALID_PARAMETER;
+        };
+        let mode_info = match mode_number == self.mode.mode {
+            true => self.info,
+            false => blt_only_info(resolution),
+        };
+        let buffer = match self
+            .boot_services
+            .allocate_pool_for_type::<graphics_output::ModeInformation>(MemoryType::BOOT_SERVICES_DATA)
+        {
+            Ok(buffer) => buffer,
+            Err(status) => return status,
+        };
+        // SAFETY: The buffer was allocated for the mode information, the pointers are provided by the caller.
+        unsafe {
+            buffer.write(mode_info);
+            size_of_info.write(mem::size_of::<graphics_output::ModeInformation>());
+            info.write(buffer);
+        }
+        efi::Status::SUCCESS
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn blt(
+        &mut self,
+        blt_buffer: *mut graphics_output::BltPixel,
+        blt_operation: graphics_output::BltOperation,
+        source: (usize, usize),
+        destination: (usize, usize),
+        width: usize,
+        height: usize,
+        delta: usize,
+    ) -> efi::Status {
+        let blt = |protocol: *mut graphics_output::Protocol| {
+            // SAFETY: The protocols are valid while the devices are in the splitter.
+            unsafe {
+                ((*protocol).blt)(
+                    protocol,
+                    blt_buffer,
+                    blt_operation,
+                    source.0,
+                    source.1,
+                    destination.0,
+                    destination.1,
+                    width,
+                    height,
+                    delta,
+                )
+            }
+        };
+        match blt_operation {
+            graphics_output::BLT_VIDEO_TO_BLT_BUFFER => {
+                self.devices.first().map_or(efi::Status::DEVICE_ERROR, |device| blt(device.protocol))
+            }
+            _ => self.for_each_device(|_, protocol| blt(protocol)),
+        }
+    }
+}
+
+/// Returns the splitter owning the protocol `this`.
+///
+/// # Safety
+///
+/// `this` must be null or the protocol of a [GraphicsOutputSplitter].
+unsafe fn splitter<'a, B: BootServices + 'static>(
+    this: *mut graphics_output::Protocol,
+) -> Option<&'a mut GraphicsOutputSplitter<B>> {
+    // SAFETY: The protocol is the first field of the repr(C) splitter, per the caller.
+    unsafe { (this as *mut GraphicsOutputSplitter<B>).as_mut() }
+}
+
+extern "efiapi" fn query_mode<B: BootServices + 'static>(
+    this: *mut graphics_output::Protocol,
+    mode_number: u32,
+    size_of_info: *mut usize,
+    info: *mut *mut graphics_output::ModeInformation,
+) -> efi::Status {
+    if size_of_info.is_null() || info.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    // SAFETY: The function is only installed in the protocol of a splitter.
+    unsafe { splitter::<B>(this) }
+        .map_or(efi::Status::INVALID_PARAMETER, |splitter| splitter.query_mode(mode_number, size_of_info, info))
+}
+
+extern "efiapi" fn set_mode<B: BootServices + 'static>(
+    this: *mut graphics_output::Protocol,
+    mode_number: u32,
+) -> efi::Status {
+    // SAFETY: The function is only installed in the protocol of a splitter.
+    unsafe { splitter::<B>(this) }
+        .map_or(efi::Status::INVALID_PARAMETER, |splitter| splitter.apply_mode(mode_number, true))
+}
+
+#[allow(clippy::too_many_arguments)]
+extern "efiapi" fn blt<B: BootServices + 'static>(
+    this: *mut graphics_output::Protocol,
+    blt_buffer: *mut graphics_output::BltPixel,
+    blt_operation: graphics_output::BltOperation,
+    source_x: usize,
+    source_y: usize,
+    destination_x: usize,
+    destination_y: usize,
+    width: usize,
+    height: usize,
+    delta: usize,
+) -> efi::Status {
+    if blt_operation >= graphics_output::BLT_OPERATION_MAX {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    // SAFETY: The function is only installed in the protocol of a splitter.
+    unsafe { splitter::<B>(this) }.map_or(efi::Status::INVALID_PARAMETER, |splitter| {
+        splitter.blt(
+            blt_buffer,
+            blt_operation,
+            (source_x, source_y),
+            (destination_x, destination_y),
+            width,
+            height,
+            delta,
+        )
+    })
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use patina::boot_services::MockBootServices;
+    use std::{boxed::Box, vec, vec::Vec};
+
+    /// A graphics device recording the blt operations performed on it.
+    #[repr(C)]
+    struct MockGop {
+        protocol: graphics_output::Protocol,
+        mode: graphics_output::Mode,
+        info: graphics_output::ModeInformation,
+        modes: Vec<(u32, u32)>,
+        blts: Vec<graphics_output::BltOperation>,
+        set_modes: usize,
+    }
+
+    impl MockGop {
+        fn new(modes: &[(u32, u32)], frame_buffer_base: u64) -> Box<Self> {
+            let mut device = Box::new(Self {
+                protocol: graphics_output::Protocol {
+                    query_mode: mock_query_mode,
+                    set_mode: mock_set_mode,
+                    blt: mock_blt,
+                    mode: ptr::null_mut(),
+                },
+                mode: graphics_output::Mode {
+                    max_mode: modes.len() as u32,
+                    mode: 0,
+                    info: ptr::null_mut(),
+                    size_of_info: mem::size_of::<graphics_output::ModeInformation>(),
+                    frame_buffer_base,
+                    frame_buffer_size: 0x1000,
+                },
+                info: graphics_output::ModeInformation {
+                    pixel_format: graphics_output::PIXEL_BLUE_GREEN_RED_RESERVED_8_BIT_PER_COLOR,
+                    ..blt_only_info(modes[0])
+                },
+                modes: modes.to_vec(),
+                blts: Vec::new(),
+                set_modes: 0,
+            });
+            device.mode.info = &mut device.info;
+            device.protocol.mode = &mut device.mode;
+            device
+        }
+    }
+
+    fn mock(this: *mut graphics_output::Protocol) -> &'static mut MockGop {
+        unsafe { &mut *(this as *mut MockGop) }
+    }
+
+    extern "efiapi" fn mock_query_mode(
+        this: *mut graphics_output::Protocol,
+        mode_number: u32,
+        size_of_info: *mut usize,
+        info: *mut *mut graphics_output::ModeInformation,
+    ) -> efi::Status {
+        let Some(&resolution) = mock(this).modes.get(mode_number as usize) else {
+            return efi::Status::INVALID_PARAMETER;
+        };
+        unsafe {
+            size_of_info.write(mem::size_of::<graphics_output::ModeInformation>());
+            info.write(Box::into_raw(Box::new(blt_only_info(resolution))));
+        }
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn mock_set_mode(this: *mut graphics_output::Protocol, mode_number: u32) -> efi::Status {
+        let device = mock(this);
+        let Some(&(horizontal_resolution, vertical_resolution)) = device.modes.get(mode_number as usize) else {
+            return efi::Status::UNSUPPORTED;
+        };
+        device.mode.mode = mode_number;
+        device.info.horizontal_resolution = horizontal_resolution;
+        device.info.vertical_resolution = vertical_resolution;
+        device.set_modes += 1;
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn mock_blt(
+        this: *mut graphics_output::Protocol,
+        _: *mut graphics_output::BltPixel,
+        blt_operation: graphics_output::BltOperation,
+        _: usize,
+        _: usize,
+        _: usize,
+        _: usize,
+        _: usize,
+        _: usize,
+        _: usize,
+    ) -> efi::Status {
+        mock(this).blts.push(blt_operation);
+        efi::Status::SUCCESS
+    }
+
+    fn boot_services() -> &'static MockBootServices {
+        let mut boot_services = MockBootServices::new();
+        // The mode information returned by the mock devices is boxed.
+        boot_services.expect_free_pool().returning(|buffer| {
+            drop(unsafe { Box::from_raw(buffer as *mut graphics_output::ModeInformation) });
+            Ok(())
+        });
+        boot_services
+            .expect_allocate_pool_for_type::<graphics_output::ModeInformation>()
+            .returning(|_| Ok(Box::into_raw(Box::new(blt_only_info((0, 0))))));
+        Box::leak(Box::new(boot_services))
+    }
+
+    fn modes(splitter: &mut GraphicsOutputSplitter<MockBootServices>) -> Vec<(u32, u32)> {
+        let protocol = splitter.protocol();
+        (0..splitter.mode.max_mode)
+            .map(|mode_number| {
+                let mut size = 0;
+                let mut info = ptr::null_mut();
+                assert_eq!(
+                    query_mode::<MockBootServices>(protocol, mode_number, &mut size, &mut info),
+                    efi::Status::SUCCESS
+                );
+                assert_eq!(size, mem::size_of::<graphics_output::ModeInformation>());
+                let info = unsafe { Box::from_raw(info) };
+                (info.horizontal_resolution, info.vertical_resolution)
+            })
+            .collect()
+    }
+
+    fn blt_operation(splitter: &mut GraphicsOutputSplitter<MockBootServices>, operation: u32) -> efi::Status {
+        blt::<MockBootServices>(splitter.protocol(), ptr::null_mut(), operation, 0, 0, 0, 0, 1, 1, 0)
+    }
+
+    #[test]
+    fn test_single_device_is_exposed_as_is() {
+        let mut splitter = GraphicsOutputSplitter::new(boot_services());
+        let mut device = MockGop::new(&[(1024, 768), (800, 600)], 0x8000_0000);
+        assert_eq!(mock_set_mode(&mut device.protocol, 1), efi::Status::SUCCESS);
+
+        splitter.add_device(&mut device.protocol).unwrap();
+        assert_eq!(splitter.add_device(&mut device.protocol), Err(efi::Status::ALREADY_STARTED));
+        assert_eq!(splitter.device_count(), 1);
+        // The device is kept in its current mode.
+        assert_eq!((splitter.mode.mode, device.set_modes), (1, 1));
+        assert_eq!(splitter.info.pixel_format, graphics_output::PIXEL_BLUE_GREEN_RED_RESERVED_8_BIT_PER_COLOR);
+        assert_eq!(splitter.mode.frame_buffer_base, 0x8000_0000);
+        assert_eq!(modes(&mut splitter), [(1024, 768), (800, 600)]);
+
+        // Setting a mode sets the device even if it already is in that mode, clearing the screen.
+        assert_eq!(set_mode::<MockBootServices>(splitter.protocol(), 1), efi::Status::SUCCESS);
+        assert_eq!(device.set_modes, 2);
+        assert_eq!(set_mode::<MockBootServices>(splitter.protocol(), 2), efi::Status::UNSUPPORTED);
+
+        assert_eq!(blt_operation(&mut splitter, graphics_output::BLT_VIDEO_TO_BLT_BUFFER), efi::Status::SUCCESS);
+        assert_eq!(blt_operation(&mut splitter, graphics_output::BLT_OPERATION_MAX), efi::Status::INVALID_PARAMETER);
+        assert_eq!(device.blts, [graphics_output::BLT_VIDEO_TO_BLT_BUFFER]);
+
+        splitter.remove_device(&mut device.protocol).unwrap();
+        assert_eq!(splitter.device_count(), 0);
+        assert_eq!(splitter.mode.max_mode, 0);
+        assert_eq!(blt_operation(&mut splitter, graphics_output::BLT_VIDEO_TO_BLT_BUFFER), efi::Status::DEVICE_ERROR);
+    }
+
+    #[test]
+    fn test_multiple_devices_are_blt_only() {
+        let mut splitter = GraphicsOutputSplitter::new(boot_services());
+        let mut first = MockGop::new(&[(1024, 768), (800, 600)], 0x8000_0000);
+        let mut second = MockGop::new(&[(800, 600), (640, 480)], 0x9000_0000);
+        splitter.add_device(&mut first.protocol).unwrap();
+
+        // The first device does not stay in 1024x768 as the second device does not support it.
+        splitter.add_device(&mut second.protocol).unwrap();
+        assert_eq!(modes(&mut splitter), [(800, 600)]);
+        assert_eq!((first.mode.mode, second.mode.mode), (1, 0));
+        assert_eq!(splitter.info.pixel_format, graphics_output::PIXEL_BLT_ONLY);
+        assert_eq!((splitter.info.horizontal_resolution, splitter.info.vertical_resolution), (800, 600));
+        assert_eq!(splitter.mode.frame_buffer_base, 0);
+
+        // Writes are done on all devices, reads on the first one.
+        assert_eq!(blt_operation(&mut splitter, graphics_output::BLT_BUFFER_TO_VIDEO), efi::Status::SUCCESS);
+        assert_eq!(blt_operation(&mut splitter, graphics_output::BLT_VIDEO_TO_BLT_BUFFER), efi::Status::SUCCESS);
+        assert_eq!(first.blts, [graphics_output::BLT_BUFFER_TO_VIDEO, graphics_output::BLT_VIDEO_TO_BLT_BUFFER]);
+        assert_eq!(second.blts, vec![graphics_output::BLT_BUFFER_TO_VIDEO]);
+
+        // Once the first device is removed, the frame buffer of the second one is exposed.
+        splitter.remove_device(&mut first.protocol).unwrap();
+        assert_eq!(modes(&mut splitter), [(800, 600), (640, 480)]);
+        assert_eq!(splitter.mode.mode, 0);
+        assert_eq!(splitter.mode.frame_buffer_base, 0x9000_0000);
+        assert_eq!(splitter.remove_device(&mut first.protocol), Err(efi::Status::NOT_FOUND));
+    }
+}
@@ -0,0 +1,42 @@
+//! Console splitter for Patina platforms.
+//!
+//! Platforms usually have multiple consoles, e.g. a serial terminal and a graphics display with a USB keyboard, while
+//! the system table only references one console input, console output and standard error protocol. The console
+//! splitter installs virtual consoles reading from and writing to all the console devices, so that the boot manager
+//! and applications use them all transparently. This crate provides:
+//!
+//! - [component::ConsoleSplitter]: a component installing the virtual `ConIn`, `ConOut` and `StdErr` consoles in the
+//!   system table, and the driver bindings adding console devices to them as they are connected.
+//!
+//! The Simple Text Input Ex and Simple Pointer protocols of the devices are not multiplexed.
+//!
+//! ## Integration Example
+//!
+//! ```rust,ignore
+//! // ...
+//!
+//! Core::default()
+//!  // ...
+//!  .with_component(patina_console_splitter::component::ConsoleSplitter)
+//!  .start()
+//!  .unwrap();
+//!
+//! // ...
+//! ```
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+#![cfg_attr(not(test), no_std)]
+#![feature(coverage_attribute)]
+
+extern crate alloc;
+
+pub mod component;
+mod graphics_output;
+mod text_in;
+mod text_out;
@@ -0,0 +1,266 @@
+//! Text Input Splitter
+//!
+//! [TextInSplitter] produces a Simple Text Input Protocol that reads from any number of text input devices. A key
+//! stroke is read from the first device that has one, and the `WaitForKey` event of the splitter is signaled when the
+//! event of any device is.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::{boxed::Box, vec::Vec};
+use core::ptr;
+
+use patina::boot_services::{BootServices, event::EventType, tpl::Tpl};
+use r_efi::efi::{self, protocols::simple_text_input};
+
+/// Simple Text Input Protocol reading from multiple text input devices.
+#[repr(C)]
+pub(crate) struct TextInSplitter<B: BootServices + 'static> {
+    // The protocol must be the first field, the protocol functions cast the protocol pointer to the splitter.
+    protocol: simple_text_input::Protocol,
+    devices: Vec<*mut simple_text_input::Protocol>,
+    boot_services: &'static B,
+}
+
+impl<B: BootServices + 'static> TextInSplitter<B> {
+    /// Creates a splitter without devices.
+    ///
+    /// The splitter is leaked, as the `WaitForKey` event of its protocol refers to it.
+    #[allow(clippy::mut_from_ref)] // The splitter is leaked, not borrowed from the boot services.
+    pub(crate) fn new(boot_services: &'static B) -> Result<&'static mut Self, efi::Status> {
+        let splitter = Box::leak(Box::new(Self {
+            protocol: simple_text_input::Protocol {
+                reset: reset::<B>,
+                read_key_stroke: read_key_stroke::<B>,
+                wait_for_key: ptr::null_mut(),
+            },
+            devices: Vec::new(),
+            boot_services,
+        }));
+        // SAFETY: The context of the event is the leaked splitter, which is valid for the lifetime of the event.
+        splitter.protocol.wait_for_key = unsafe {
+            boot_services.create_event_unchecked(
+                EventType::NOTIFY_WAIT,
+                Tpl::NOTIFY,
+                Some(wait_for_key::<B>),
+                splitter as *mut Self,
+            )
+        }?;
+        Ok(splitter)
+    }
+
+    /// Returns the Simple Text Input Protocol of the splitter.
+    pub(crate) fn protocol(&mut self) -> *mut simple_text_input::Protocol {
+        &mut self.protocol
+    }
+
+    /// Adds a device to the splitter.
+    pub(crate) fn add_device(&mut self, protocol: *mut simple_text_input::Protocol) -> Result<(), efi::Status> {
+        if self.devices.contains(&protocol) {
+            return Err(efi::Status::ALREADY_STARTED);
+        }
+        self.devices.push(protocol);
+        Ok(())
+    }
+
+    /// Removes a device from the splitter.
+    pub(crate) fn remove_device(&mut self, protocol: *mut simple_text_input::Protocol) -> Result<(), efi::Status> {
+        let index = self.devices.iter().position(|device| *device == protocol).ok_or(efi::Status::NOT_FOUND)?;
+        self.devices.remove(index);
+        Ok(())
+    }
+
+    fn reset(&mut self, extended_verification: efi::Boolean) -> efi::Status {
+        let mut status = efi::Status::SUCCESS;
+        for &device in &self.devices {
+            // SAFETY: The protocols are valid while the devices are in the splitter.
+            let device_status = unsafe { ((*device).reset)(device, extended_verification) };
+            if device_status.is_error() && !status.is_error() {
+                status = device_status;
+            }
+        }
+        status
+    }
+
+    fn read_key_stroke(&mut self, key: *mut simple_text_input::InputKey) -> efi::Status {
+        for &device in &self.devices {
+            // SAFETY: The protocols are valid while the devices are in the splitter.
+            if unsafe { ((*device).read_key_stroke)(device, key) } == efi::Status::SUCCESS {
+                return efi::Status::SUCCESS;
+            }
+        }
+        efi::Status::NOT_READY
+    }
+
+    fn wait_for_key(&mut self, event: efi::Event) {
+        // SAFETY: The protocols are valid while the devices are in the splitter.
+        if self.devices.iter().any(|&device| self.boot_services.check_event(unsafe { (*device).wait_for_key }).is_ok())
+        {
+            let _ = self.boot_services.signal_event(event);
+        }
+    }
+}
+
+/// Returns the splitter owning the protocol `this`.
+///
+/// # Safety
+///
+/// `this` must be null or the protocol of a [TextInSplitter].
+unsafe fn splitter<'a, B: BootServices + 'static>(
+    this: *mut simple_text_input::Protocol,
+) -> Option<&'a mut TextInSplitter<B>> {
+    // SAFETY: The protocol is the first field of the repr(C) splitter, per the caller.
+    unsafe { (this as *mut TextInSplitter<B>).as_mut() }
+}
+
+extern "efiapi" fn reset<B: BootServices + 'static>(
+    this: *mut simple_text_input::Protocol,
+    extended_verification: efi::Boolean,
+) -> efi::Status {
+    // SAFETY: The function is only installed in the protocol of a splitter.
+    unsafe { splitter::<B>(this) }
+        .map_or(efi::Status::INVALID_PARAMETER, |splitter| splitter.reset(extended_verification))
+}
+
+extern "efiapi" fn read_key_stroke<B: BootServices + 'static>(
+    this: *mut simple_text_input::Protocol,
+    key: *mut simple_text_input::InputKey,
+) -> efi::Status {
+    if key.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    // SAFETY: The function is only installed in the protocol of a splitter.
+    unsafe { splitter::<B>(this) }.map_or(efi::Status::INVALID_PARAMETER, |splitter| splitter.read_key_stroke(key))
+}
+
+/// Notify function of the `WaitForKey` event of the splitter.
+extern "efiapi" fn wait_for_key<B: BootServices + 'static>(event: efi::Event, splitter: *mut TextInSplitter<B>) {
+    // SAFETY: The context of the event is the leaked splitter.
+    if let Some(splitter) = unsafe { splitter.as_mut() } {
+        splitter.wait_for_key(event);
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use patina::boot_services::MockBootServices;
+    use std::boxed::Box;
+
+    /// A text input device returning the keys it holds.
+    #[repr(C)]
+    struct MockTextIn {
+        protocol: simple_text_input::Protocol,
+        keys: Vec<u16>,
+        resets: usize,
+    }
+
+    impl MockTextIn {
+        fn new(keys: &[u16], wait_for_key: usize) -> Box<Self> {
+            Box::new(Self {
+                protocol: simple_text_input::Protocol {
+                    reset: mock_reset,
+                    read_key_stroke: mock_read_key_stroke,
+                    wait_for_key: wait_for_key as efi::Event,
+                },
+                keys: keys.to_vec(),
+                resets: 0,
+            })
+        }
+    }
+
+    extern "efiapi" fn mock_reset(this: *mut simple_text_input::Protocol, _: efi::Boolean) -> efi::Status {
+        unsafe { (*(this as *mut MockTextIn)).resets += 1 };
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn mock_read_key_stroke(
+        this: *mut simple_text_input::Protocol,
+        key: *mut simple_text_input::InputKey,
+    ) -> efi::Status {
+        let device = unsafe { &mut *(this as *mut MockTextIn) };
+        if device.keys.is_empty() {
+            return efi::Status::NOT_READY;
+        }
+        unsafe { key.write(simple_text_input::InputKey { scan_code: 0, unicode_char: device.keys.remove(0) }) };
+        efi::Status::SUCCESS
+    }
+
+    fn boot_services(expect: impl FnOnce(&mut MockBootServices)) -> &'static MockBootServices {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_create_event_unchecked::<TextInSplitter<MockBootServices>>().once().returning(
+            |event_type, notify_tpl, notify_function, _| {
+                assert_eq!(event_type, EventType::NOTIFY_WAIT);
+                assert_eq!(notify_tpl, Tpl::NOTIFY);
+                assert!(notify_function.is_some());
+                Ok(1_usize as efi::Event)
+            },
+        );
+        expect(&mut boot_services);
+        Box::leak(Box::new(boot_services))
+    }
+
+    fn read_key(protocol: *mut simple_text_input::Protocol) -> Result<u16, efi::Status> {
+        let mut key = simple_text_input::InputKey { scan_code: 0, unicode_char: 0 };
+        match read_key_stroke::<MockBootServices>(protocol, &mut key) {
+            efi::Status::SUCCESS => Ok(key.unicode_char),
+            status => Err(status),
+        }
+    }
+
+    #[test]
+    fn test_keys_are_read_from_all_devices() {
+        let splitter = TextInSplitter::new(boot_services(|_| ())).unwrap();
+        let protocol = splitter.protocol();
+        assert_eq!(unsafe { (*protocol).wait_for_key }, 1_usize as efi::Event);
+        assert_eq!(read_key(protocol), Err(efi::Status::NOT_READY));
+
+        let mut first = MockTextIn::new(&[b'a' as u16], 2);
+        let mut second = MockTextIn::new(&[b'b' as u16, b'c' as u16], 3);
+        splitter.add_device(&mut first.protocol).unwrap();
+        splitter.add_device(&mut second.protocol).unwrap();
+        assert_eq!(splitter.add_device(&mut first.protocol), Err(efi::Status::ALREADY_STARTED));
+
+        assert_eq!(read_key(protocol), Ok(b'a' as u16));
+        assert_eq!(read_key(protocol), Ok(b'b' as u16));
+        assert_eq!(read_key_stroke::<MockBootServices>(protocol, ptr::null_mut()), efi::Status::INVALID_PARAMETER);
+
+        assert_eq!(reset::<MockBootServices>(protocol, efi::Boolean::FALSE), efi::Status::SUCCESS);
+        assert_eq!((first.resets, second.resets), (1, 1));
+
+        splitter.remove_device(&mut second.protocol).unwrap();
+        assert_eq!(splitter.remove_device(&mut second.protocol), Err(efi::Status::NOT_FOUND));
+        assert_eq!(read_key(protocol), Err(efi::Status::NOT_READY));
+    }
+
+    #[test]
+    fn test_wait_for_key_is_signaled_with_device_events() {
+        let boot_services = boot_services(|boot_services| {
+            boot_services.expect_check_event().times(3).returning(|event| match event as usize {
+                3 => Ok(()),
+                _ => Err(efi::Status::NOT_READY),
+            });
+            boot_services.expect_signal_event().once().returning(|event| {
+                assert_eq!(event, 1_usize as efi::Event);
+                Ok(())
+            });
+        });
+        let splitter = TextInSplitter::new(boot_services).unwrap();
+        let mut first = MockTextIn::new(&[], 2);
+        let mut second = MockTextIn::new(&[], 3);
+        splitter.add_device(&mut first.protocol).unwrap();
+
+        // Only the event of the first device is checked, which is not signaled.
+        wait_for_key::<MockBootServices>(1_usize as efi::Event, splitter);
+
+        // The event of the second device is signaled.
+        splitter.add_device(&mut second.protocol).unwrap();
+        wait_for_key::<MockBootServices>(1_usize as efi::Event, splitter);
+    }
+}
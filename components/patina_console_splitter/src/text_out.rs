@@ -0,0 +1,578 @@
+//! Text Output Splitter
+//!
+//! [TextOutSplitter] produces a Simple Text Output Protocol that writes to any number of text output devices. Every
+//! call is forwarded to all the devices, and the modes reported are the text resolutions supported by all of them.
+//! The cursor position is tracked from the first device, as devices may wrap or scroll differently.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::{boxed::Box, vec, vec::Vec};
+use core::ptr;
+
+use r_efi::efi::{self, protocols::simple_text_output};
+
+/// The mode reported when no device is present. Mode 0 of every device must be 80x25.
+const DEFAULT_MODE: (usize, usize) = (80, 25);
+
+/// The attribute of the splitter when it is created: light gray on black.
+const DEFAULT_ATTRIBUTE: usize = 0x07;
+
+/// The highest valid attribute: the foreground color in bits 0 to 3 and the background color in bits 4 to 6.
+const MAX_ATTRIBUTE: usize = 0x7F;
+
+/// A text output device of the splitter.
+struct TextOutDevice {
+    protocol: *mut simple_text_output::Protocol,
+    /// The (columns, rows) of the modes of the device, by mode number, or `None` for invalid modes.
+    modes: Vec<Option<(usize, usize)>>,
+}
+
+impl TextOutDevice {
+    fn new(protocol: *mut simple_text_output::Protocol) -> Self {
+        // SAFETY: The protocol is valid while the device is in the splitter, its mode pointer is null or valid.
+        let max_mode = unsafe { (*protocol).mode.as_ref() }.map_or(0, |mode| mode.max_mode.max(0) as usize);
+        let modes = (0..max_mode)
+            .map(|mode_number| {
+                let (mut columns, mut rows) = (0, 0);
+                // SAFETY: The protocol is valid while the device is in the splitter.
+                let status = unsafe { ((*protocol).query_mode)(protocol, mode_number, &mut columns, &mut rows) };
+                (status == efi::Status::SUCCESS).then_some((columns, rows))
+            })
+            .collect();
+        Self { protocol, modes }
+    }
+
+    /// Returns the mode number of the device with the given (columns, rows).
+    fn mode_number(&self, dimensions: (usize, usize)) -> Option<usize> {
+        self.modes.iter().position(|mode| *mode == Some(dimensions))
+    }
+}
+
+/// Simple Text Output Protocol writing to multiple text output devices.
+#[repr(C)]
+pub(crate) struct TextOutSplitter {
+    // The protocol must be the first field, the protocol functions cast the protocol pointer to the splitter.
+    protocol: simple_text_output::Protocol,
+    mode: simple_text_output::Mode,
+    devices: Vec<TextOutDevice>,
+    /// The (columns, rows) of the modes of the splitter, by mode number.
+    modes: Vec<(usize, usize)>,
+}
+
+impl TextOutSplitter {
+    /// Creates a splitter without devices, in the 80x25 mode.
+    pub(crate) fn new() -> Box<Self> {
+        let mut splitter = Box::new(Self {
+            protocol: simple_text_output::Protocol {
+                reset,
+                output_string,
+                test_string,
+                query_mode,
+                set_mode,
+                set_attribute,
+                clear_screen,
+                set_cursor_position,
+                enable_cursor,
+                mode: ptr::null_mut(),
+            },
+            mode: simple_text_output::Mode {
+                max_mode: 1,
+                mode: 0,
+                attribute: DEFAULT_ATTRIBUTE as i32,
+                cursor_column: 0,
+                cursor_row: 0,
+                cursor_visible: efi::Boolean::TRUE,
+            },
+            devices: Vec::new(),
+            modes: vec![DEFAULT_MODE],
+        });
+        splitter.protocol.mode = &mut splitter.mode;
+        splitter
+    }
+
+    /// Returns the Simple Text Output Protocol of the splitter.
+    pub(crate) fn protocol(&mut self) -> *mut simple_text_output::Protocol {
+        &mut self.protocol
+    }
+
+    /// Adds a device to the splitter.
+    ///
+    /// All devices are set to the current mode, attribute and cursor visibility of the splitter, or to mode 0 if the
+    /// new device does not support the current mode. This clears the screens, as changing the mode does.
+    pub(crate) fn add_device(&mut self, protocol: *mut simple_text_output::Protocol) -> Result<(), efi::Status> {
+        if self.devices.iter().any(|device| device.protocol == protocol) {
+            return Err(efi::Status::ALREADY_STARTED);
+        }
+
+        let current = self.modes[self.mode.mode as usize];
+        self.devices.push(TextOutDevice::new(protocol));
+        self.update_modes();
+        let mode_number = self.modes.iter().position(|mode| *mode == current).unwrap_or(0);
+
+        let attribute = self.mode.attribute as usize;
+        let cursor_visible = self.mode.cursor_visible;
+        // Devices that cannot hide the cursor or do not support the attribute are still usable.
+        let _ = self.set_attribute(attribute);
+        let _ = self.enable_cursor(cursor_visible);
+        match self.set_mode(mode_number) {
+            status if status.is_error() => Err(status),
+            _ => Ok(()),
+        }
+    }
+
+    /// Removes a device from the splitter, keeping the current mode.
+    pub(crate) fn remove_device(&mut self, protocol: *mut simple_text_output::Protocol) -> Result<(), efi::Status> {
+        let index = self.devices.iter().position(|device| device.protocol == protocol).ok_or(efi::Status::NOT_FOUND)?;
+        self.devices.remove(index);
+
+        // The remaining devices support the current mode, which may have a different number now.
+        let current = self.modes[self.mode.mode as usize];
+        self.update_modes();
+        match self.modes.iter().position(|mode| *mode == current) {
+            Some(mode_number) => self.mode.mode = mode_number as i32,
+            None => {
+                let status = self.set_mode(0);
+                if status.is_error() {
+                    return Err(status);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Updates the modes of the splitter to the modes supported by all the devices, in the order of the first device.
+    fn update_modes(&mut self) {
+        let mut modes = Vec::new();
+        if let Some((first, others)) = self.devices.split_first() {
+            for &mode in first.modes.iter().flatten() {
+                if !modes.contains(&mode) && others.iter().all(|device| device.mode_number(mode).is_some()) {
+                    modes.push(mode);
+                }
+            }
+        }
+        if modes.is_empty() {
+            modes.push(DEFAULT_MODE);
+        }
+        self.mode.max_mode = modes.len() as i32;
+        self.modes = modes;
+    }
+
+    /// Calls `function` for each device, returning the first error, or else the first warning, reported.
+    fn for_each_device(
+        &self,
+        mut function: impl FnMut(&TextOutDevice, *mut simple_text_output::Protocol) -> efi::Status,
+    ) -> efi::Status {
+        let mut status = efi::Status::SUCCESS;
+        for device in &self.devices {
+            let device_status = function(device, device.protocol);
+            if status == efi::Status::SUCCESS || (device_status.is_error() && !status.is_error()) {
+                status = device_status;
+            }
+        }
+        status
+    }
+
+    /// Updates the cursor position of the splitter from the first device.
+    fn sync_cursor(&mut self) {
+        // SAFETY: The protocols are valid while the devices are in the splitter, their mode pointers null or valid.
+        if let Some(mode) = self.devices.first().and_then(|device| unsafe { (*device.protocol).mode.as_ref() }) {
+            self.mode.cursor_column = mode.cursor_column;
+            self.mode.cursor_row = mode.cursor_row;
+        }
+    }
+
+    fn reset(&mut self, extended_verification: efi::Boolean) -> efi::Status {
+        // SAFETY: The protocols are valid while the devices are in the splitter.
+        let status = self.for_each_device(|_, device| unsafe { ((*device).reset)(device, extended_verification) });
+        if status.is_error() {
+            return status;
+        }
+        self.set_mode(0)
+    }
+
+    fn output_string(&mut self, string: *mut efi::Char16) -> efi::Status {
+        // SAFETY: The protocols are valid while the devices are in the splitter.
+        let status = self.for_each_device(|_, device| unsafe { ((*device).output_string)(device, string) });
+        self.sync_cursor();
+        status
+    }
+
+    fn test_string(&mut self, string: *mut efi::Char16) -> efi::Status {
+        // SAFETY: The protocols are valid while the devices are in the splitter.
+        self.for_each_device(|_, device| unsafe { ((*device).test_string)(device, string) })
+    }
+
+    fn set_mode(&mut self, mode_number: usize) -> efi::Status {
+        let Some(&dimensions) = self.modes.get(mode_number) else {
+            return efi::Status::UNSUPPORTED;
+        };
+        let status = self.for_each_device(|device, protocol| match device.mode_number(dimensions) {
+            // SAFETY: The protocols are valid while the devices are in the splitter.
+            Some(device_mode_number) => unsafe { ((*protocol).set_mode)(protocol, device_mode_number) },
+            None => efi::Status::UNSUPPORTED,
+        });
+        self.mode.mode = mode_number as i32;
+        self.mode.cursor_column = 0;
+        self.mode.cursor_row = 0;
+        status
+    }
+
+    fn set_attribute(&mut self, attribute: usize) -> efi::Status {
+        if attribute > MAX_ATTRIBUTE {
+            return efi::Status::UNSUPPORTED;
+        }
+        // SAFETY: The protocols are valid while the devices are in the splitter.
+        let status = self.for_each_device(|_, device| unsafe { ((*device).set_attribute)(device, attribute) });
+        self.mode.attribute = attribute as i32;
+        status
+    }
+
+    fn clear_screen(&mut self) -> efi::Status {
+        // SAFETY: The protocols are valid while the devices are in the splitter.
+        let status = self.for_each_device(|_, device| unsafe { ((*device).clear_screen)(device) });
+        self.mode.cursor_column = 0;
+        self.mode.cursor_row = 0;
+        status
+    }
+
+    fn set_cursor_position(&mut self, column: usize, row: usize) -> efi::Status {
+        let (columns, rows) = self.modes[self.mode.mode as usize];
+        if column >= columns || row >= rows {
+            return efi::Status::UNSUPPORTED;
+        }
+        // SAFETY: The protocols are valid while the devices are in the splitter.
+        let status = self.for_each_device(|_, device| unsafe { ((*device).set_cursor_position)(device, column, row) });
+        self.mode.cursor_column = column as i32;
+        self.mode.cursor_row = row as i32;
+        status
+    }
+
+    fn enable_cursor(&mut self, visible: efi::Boolean) -> efi::Status {
+        // SAFETY: The protocols are valid while the devices are in the splitter.
+        let status = self.for_each_device(|_, device| unsafe { ((*device).enable_cursor)(device, visible) });
+        self.mode.cursor_visible = visible;
+        status
+    }
+}
+
+/// Returns the splitter owning the protocol `this`.
+///
+/// # Safety
+///
+/// `this` must be null or the protocol of a [TextOutSplitter].
+unsafe fn splitter<'a>(this: *mut simple_text_output::Protocol) -> Option<&'a mut TextOutSplitter> {
+    // SAFETY: The protocol is the first field of the repr(C) splitter, per the caller.
+    unsafe { (this as *mut TextOutSplitter).as_mut() }
+}
+
+extern "efiapi" fn reset(this: *mut simple_text_output::Protocol, extended_verification: efi::Boolean) -> efi::Status {
+    // SAFETY: The function is only installed in the protocol of a splitter.
+    unsafe { splitter(this) }.map_or(efi::Status::INVALID_PARAMETER, |splitter| splitter.reset(extended_verification))
+}
+
+extern "efiapi" fn output_string(this: *mut simple_text_output::Protocol, string: *mut efi::Char16) -> efi::Status {
+    if string.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    // SAFETY: The function is only installed in the protocol of a splitter.
+    unsafe { splitter(this) }.map_or(efi::Status::INVALID_PARAMETER, |splitter| splitter.output_string(string))
+}
+
+extern "efiapi" fn test_string(this: *mut simple_text_output::Protocol, string: *mut efi::Char16) -> efi::Status {
+    if string.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    // SAFETY: The function is only installed in the protocol of a splitter.
+    unsafe { splitter(this) }.map_or(efi::Status::INVALID_PARAMETER, |splitter| splitter.test_string(string))
+}
+
+extern "efiapi" fn query_mode(
+    this: *mut simple_text_output::Protocol,
+    mode_number: usize,
+    columns: *mut usize,
+    rows: *mut usize,
+) -> efi::Status {
+    // SAFETY: The function is only installed in the protocol of a splitter.
+    let Some(splitter) = (unsafe { splitter(this) }) else {
+        return efi::Status::INVALID_PARAMETER;
+    };
+    if columns.is_null() || rows.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    let Some(&(mode_columns, mode_rows)) = splitter.modes.get(mode_number) else {
+        return efi::Status::UNSUPPORTED;
+    };
+    // SAFETY: The pointers are provided by the caller and were checked for null.
+    unsafe {
+        columns.write(mode_columns);
+        rows.write(mode_rows);
+    }
+    efi::Status::SUCCESS
+}
+
+extern "efiapi" fn set_mode(this: *mut simple_text_output::Protocol, mode_number: usize) -> efi::Status {
+    // SAFETY: The function is only installed in the protocol of a splitter.
+    unsafe { splitter(this) }.map_or(efi::Status::INVALID_PARAMETER, |splitter| splitter.set_mode(mode_number))
+}
+
+extern "efiapi" fn set_attribute(this: *mut simple_text_output::Protocol, attribute: usize) -> efi::Status {
+    // SAFETY: The function is only installed in the protocol of a splitter.
+    unsafe { splitter(this) }.map_or(efi::Status::INVALID_PARAMETER, |splitter| splitter.set_attribute(attribute))
+}
+
+extern "efiapi" fn clear_screen(this: *mut simple_text_output::Protocol) -> efi::Status {
+    // SAFETY: The function is only installed in the protocol of a splitter.
+    unsafe { splitter(this) }.map_or(efi::Status::INVALID_PARAMETER, |splitter| splitter.clear_screen())
+}
+
+extern "efiapi" fn set_cursor_position(
+    this: *mut simple_text_output::Protocol,
+    column: usize,
+    row: usize,
+) -> efi::Status {
+    // SAFETY: The function is only installed in the protocol of a splitter.
+    unsafe { splitter(this) }
+        .map_or(efi::Status::INVALID_PARAMETER, |splitter| splitter.set_cursor_position(column, row))
+}
+
+extern "efiapi" fn enable_cursor(this: *mut simple_text_output::Protocol, visible: efi::Boolean) -> efi::Status {
+    // SAFETY: The function is only installed in the protocol of a splitter.
+    unsafe { splitter(this) }.map_or(efi::Status::INVALID_PARAMETER, |splitter| splitter.enable_cursor(visible))
+}
+
+#[cfg(test)]
+#[coverage(off)]
+pub(crate) mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::{string::String, vec::Vec};
+
+    /// A text output device recording the text written to it.
+    #[repr(C)]
+    pub(crate) struct MockTextOut {
+        pub(crate) protocol: simple_text_output::Protocol,
+        pub(crate) mode: simple_text_output::Mode,
+        pub(crate) modes: Vec<(usize, usize)>,
+        pub(crate) output: String,
+    }
+
+    impl MockTextOut {
+        pub(crate) fn new(modes: &[(usize, usize)]) -> Box<Self> {
+            let mut device = Box::new(Self {
+                protocol: simple_text_output::Protocol {
+                    reset: mock_reset,
+                    output_string: mock_output_string,
+                    test_string: mock_test_string,
+                    query_mode: mock_query_mode,
+                    set_mode: mock_set_mode,
+                    set_attribute: mock_set_attribute,
+                    clear_screen: mock_clear_screen,
+                    set_cursor_position: mock_set_cursor_position,
+                    enable_cursor: mock_enable_cursor,
+                    mode: ptr::null_mut(),
+                },
+                mode: simple_text_output::Mode {
+                    max_mode: modes.len() as i32,
+                    mode: 0,
+                    attribute: 0,
+                    cursor_column: 0,
+                    cursor_row: 0,
+                    cursor_visible: efi::Boolean::FALSE,
+                },
+                modes: modes.to_vec(),
+                output: String::new(),
+            });
+            device.protocol.mode = &mut device.mode;
+            device
+        }
+    }
+
+    fn mock(this: *mut simple_text_output::Protocol) -> &'static mut MockTextOut {
+        unsafe { &mut *(this as *mut MockTextOut) }
+    }
+
+    extern "efiapi" fn mock_reset(this: *mut simple_text_output::Protocol, _: efi::Boolean) -> efi::Status {
+        mock(this).output.clear();
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn mock_output_string(this: *mut simple_text_output::Protocol, string: *mut u16) -> efi::Status {
+        let device = mock(this);
+        let mut index = 0;
+        while unsafe { *string.add(index) } != 0 {
+            device.output.push(char::from_u32(unsafe { *string.add(index) } as u32).unwrap());
+            device.mode.cursor_column += 1;
+            index += 1;
+        }
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn mock_test_string(_: *mut simple_text_output::Protocol, _: *mut u16) -> efi::Status {
+        efi::Status::UNSUPPORTED
+    }
+
+    extern "efiapi" fn mock_query_mode(
+        this: *mut simple_text_output::Protocol,
+        mode_number: usize,
+        columns: *mut usize,
+        rows: *mut usize,
+    ) -> efi::Status {
+        match mock(this).modes.get(mode_number) {
+            Some(&(mode_columns, mode_rows)) => {
+                unsafe {
+                    columns.write(mode_columns);
+                    rows.write(mode_rows);
+                }
+                efi::Status::SUCCESS
+            }
+            None => efi::Status::UNSUPPORTED,
+        }
+    }
+
+    extern "efiapi" fn mock_set_mode(this: *mut simple_text_output::Protocol, mode_number: usize) -> efi::Status {
+        let device = mock(this);
+        device.mode.mode = mode_number as i32;
+        device.mode.cursor_column = 0;
+        device.output.clear();
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn mock_set_attribute(this: *mut simple_text_output::Protocol, attribute: usize) -> efi::Status {
+        mock(this).mode.attribute = attribute as i32;
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn mock_clear_screen(this: *mut simple_text_output::Protocol) -> efi::Status {
+        let device = mock(this);
+        device.output.clear();
+        device.mode.cursor_column = 0;
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn mock_set_cursor_position(
+        this: *mut simple_text_output::Protocol,
+        column: usize,
+        row: usize,
+    ) -> efi::Status {
+        let device = mock(this);
+        device.mode.cursor_column = column as i32;
+        device.mode.cursor_row = row as i32;
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn mock_enable_cursor(
+        this: *mut simple_text_output::Protocol,
+        visible: efi::Boolean,
+    ) -> efi::Status {
+        mock(this).mode.cursor_visible = visible;
+        efi::Status::SUCCESS
+    }
+
+    fn modes(splitter: &mut TextOutSplitter) -> Vec<(usize, usize)> {
+        let protocol = splitter.protocol();
+        let max_mode = unsafe { (*(*protocol).mode).max_mode } as usize;
+        (0..max_mode)
+            .map(|mode_number| {
+                let (mut columns, mut rows) = (0, 0);
+                assert_eq!(query_mode(protocol, mode_number, &mut columns, &mut rows), efi::Status::SUCCESS);
+                (columns, rows)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_splitter_without_devices() {
+        let mut splitter = TextOutSplitter::new();
+        let protocol = splitter.protocol();
+        assert_eq!(modes(&mut splitter), [DEFAULT_MODE]);
+        assert_eq!(query_mode(protocol, 1, &mut 0, &mut 0), efi::Status::UNSUPPORTED);
+
+        let mut string = [b'A' as u16, 0];
+        assert_eq!(output_string(protocol, string.as_mut_ptr()), efi::Status::SUCCESS);
+        assert_eq!(output_string(protocol, ptr::null_mut()), efi::Status::INVALID_PARAMETER);
+        assert_eq!(set_cursor_position(protocol, 79, 24), efi::Status::SUCCESS);
+        assert_eq!(set_cursor_position(protocol, 80, 0), efi::Status::UNSUPPORTED);
+        assert_eq!(set_attribute(protocol, 0x80), efi::Status::UNSUPPORTED);
+    }
+
+    #[test]
+    fn test_output_is_written_to_all_devices() {
+        let mut splitter = TextOutSplitter::new();
+        let protocol = splitter.protocol();
+        let mut first = MockTextOut::new(&[(80, 25), (100, 31)]);
+        let mut second = MockTextOut::new(&[(80, 25)]);
+        splitter.add_device(&mut first.protocol).unwrap();
+        splitter.add_device(&mut second.protocol).unwrap();
+        assert_eq!(splitter.add_device(&mut second.protocol), Err(efi::Status::ALREADY_STARTED));
+
+        let mut string = [b'H' as u16, b'i' as u16, 0];
+        assert_eq!(output_string(protocol, string.as_mut_ptr()), efi::Status::SUCCESS);
+        assert_eq!(first.output, "Hi");
+        assert_eq!(second.output, "Hi");
+        assert_eq!(splitter.mode.cursor_column, 2);
+
+        assert_eq!(set_attribute(protocol, 0x1F), efi::Status::SUCCESS);
+        assert_eq!((first.mode.attribute, second.mode.attribute), (0x1F, 0x1F));
+        assert_eq!(set_cursor_position(protocol, 10, 5), efi::Status::SUCCESS);
+        assert_eq!((second.mode.cursor_column, second.mode.cursor_row), (10, 5));
+        assert_eq!(test_string(protocol, string.as_mut_ptr()), efi::Status::UNSUPPORTED);
+
+        assert_eq!(clear_screen(protocol), efi::Status::SUCCESS);
+        assert!(first.output.is_empty() && second.output.is_empty());
+        assert_eq!((splitter.mode.cursor_column, splitter.mode.cursor_row), (0, 0));
+    }
+
+    #[test]
+    fn test_modes_are_common_to_all_devices() {
+        let mut splitter = TextOutSplitter::new();
+        let protocol = splitter.protocol();
+        let mut first = MockTextOut::new(&[(80, 25), (80, 50), (100, 31)]);
+        let mut second = MockTextOut::new(&[(80, 25), (100, 31)]);
+        splitter.add_device(&mut first.protocol).unwrap();
+        assert_eq!(modes(&mut splitter), [(80, 25), (80, 50), (100, 31)]);
+        assert_eq!(set_mode(protocol, 1), efi::Status::SUCCESS);
+        assert_eq!(first.mode.mode, 1);
+
+        // The second device does not support 80x50, so both devices are switched to mode 0.
+        splitter.add_device(&mut second.protocol).unwrap();
+        assert_eq!(modes(&mut splitter), [(80, 25), (100, 31)]);
+        assert_eq!((splitter.mode.mode, first.mode.mode, second.mode.mode), (0, 0, 0));
+        // The new device is synchronized with the attribute and cursor visibility of the splitter.
+        assert_eq!(second.mode.attribute, DEFAULT_ATTRIBUTE as i32);
+        assert_eq!(second.mode.cursor_visible, efi::Boolean::TRUE);
+
+        // Modes are mapped to the mode numbers of each device.
+        assert_eq!(set_mode(protocol, 1), efi::Status::SUCCESS);
+        assert_eq!((first.mode.mode, second.mode.mode), (2, 1));
+        assert_eq!(set_mode(protocol, 2), efi::Status::UNSUPPORTED);
+
+        // Removing the first device keeps the current mode, which may have a different number.
+        splitter.remove_device(&mut first.protocol).unwrap();
+        assert_eq!(modes(&mut splitter), [(80, 25), (100, 31)]);
+        assert_eq!(splitter.mode.mode, 1);
+        assert_eq!(splitter.remove_device(&mut first.protocol), Err(efi::Status::NOT_FOUND));
+
+        splitter.remove_device(&mut second.protocol).unwrap();
+        assert_eq!(modes(&mut splitter), [DEFAULT_MODE]);
+        assert_eq!(splitter.mode.mode, 0);
+    }
+
+    #[test]
+    fn test_reset_sets_mode_zero() {
+        let mut splitter = TextOutSplitter::new();
+        let protocol = splitter.protocol();
+        let mut device = MockTextOut::new(&[(80, 25), (100, 31)]);
+        splitter.add_device(&mut device.protocol).unwrap();
+        assert_eq!(set_mode(protocol, 1), efi::Status::SUCCESS);
+
+        assert_eq!(reset(protocol, efi::Boolean::FALSE), efi::Status::SUCCESS);
+        assert_eq!((splitter.mode.mode, device.mode.mode), (0, 0));
+        assert_eq!(enable_cursor(protocol, efi::Boolean::FALSE), efi::Status::SUCCESS);
+        assert_eq!(device.mode.cursor_visible, efi::Boolean::FALSE);
+    }
+}
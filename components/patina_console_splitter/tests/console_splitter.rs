@@ -0,0 +1,333 @@
+//! Integration tests connecting console devices to the console splitter against the host DXE core boot services.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use core::{ffi::c_void, mem, ptr};
+
+use patina::{
+    boot_services::{BootServices, StandardBootServices, allocation::MemoryType},
+    guids::{CONSOLE_IN_DEVICE, CONSOLE_OUT_DEVICE},
+};
+use patina_console_splitter::component::ConsoleSplitter;
+use patina_test::TestHarness;
+use r_efi::efi::{
+    self,
+    protocols::{graphics_output, loaded_image, simple_text_input, simple_text_output},
+};
+
+/// A text output device recording the text written to it.
+#[repr(C)]
+struct TestTextOut {
+    protocol: simple_text_output::Protocol,
+    mode: simple_text_output::Mode,
+    output: String,
+}
+
+extern "efiapi" fn reset(_: *mut simple_text_output::Protocol, _: efi::Boolean) -> efi::Status {
+    efi::Status::SUCCESS
+}
+
+extern "efiapi" fn output_string(this: *mut simple_text_output::Protocol, string: *mut efi::Char16) -> efi::Status {
+    // SAFETY: The protocol is the first field of a TestTextOut.
+    let device = unsafe { &mut *(this as *mut TestTextOut) };
+    let mut index = 0;
+    // SAFETY: The string is NUL terminated.
+    while unsafe { *string.add(index) } != 0 {
+        device.output.push(char::from_u32(unsafe { *string.add(index) } as u32).unwrap());
+        index += 1;
+    }
+    efi::Status::SUCCESS
+}
+
+extern "efiapi" fn test_string(_: *mut simple_text_output::Protocol, _: *mut efi::Char16) -> efi::Status {
+    efi::Status::SUCCESS
+}
+
+extern "efiapi" fn query_mode(
+    _: *mut simple_text_output::Protocol,
+    mode_number: usize,
+    columns: *mut usize,
+    rows: *mut usize,
+) -> efi::Status {
+    if mode_number != 0 {
+        return efi::Status::UNSUPPORTED;
+    }
+    // SAFETY: The pointers are provided by the caller.
+    unsafe {
+        columns.write(80);
+        rows.write(25);
+    }
+    efi::Status::SUCCESS
+}
+
+extern "efiapi" fn set_mode(_: *mut simple_text_output::Protocol, _: usize) -> efi::Status {
+    efi::Status::SUCCESS
+}
+
+extern "efiapi" fn set_attribute(_: *mut simple_text_output::Protocol, _: usize) -> efi::Status {
+    efi::Status::SUCCESS
+}
+
+extern "efiapi" fn clear_screen(_: *mut simple_text_output::Protocol) -> efi::Status {
+    efi::Status::SUCCESS
+}
+
+extern "efiapi" fn set_cursor_position(_: *mut simple_text_output::Protocol, _: usize, _: usize) -> efi::Status {
+    efi::Status::SUCCESS
+}
+
+extern "efiapi" fn enable_cursor(_: *mut simple_text_output::Protocol, _: efi::Boolean) -> efi::Status {
+    efi::Status::SUCCESS
+}
+
+/// A graphics device counting the blt operations performed on it.
+#[repr(C)]
+struct TestGop {
+    protocol: graphics_output::Protocol,
+    mode: graphics_output::Mode,
+    info: graphics_output::ModeInformation,
+    blts: usize,
+    boot_services: StandardBootServices,
+}
+
+extern "efiapi" fn gop_query_mode(
+    this: *mut graphics_output::Protocol,
+    mode_number: u32,
+    size_of_info: *mut usize,
+    info: *mut *mut graphics_output::ModeInformation,
+) -> efi::Status {
+    if mode_number != 0 {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    // SAFETY: The protocol is the first field of a TestGop. The information is freed from pool by the caller.
+    unsafe {
+        let device = &*(this as *const TestGop);
+        let buffer = device
+            .boot_services
+            .allocate_pool_for_type::<graphics_output::ModeInformation>(MemoryType::BOOT_SERVICES_DATA)
+            .unwrap();
+        buffer.write(device.info);
+        size_of_info.write(mem::size_of::<graphics_output::ModeInformation>());
+        info.write(buffer);
+    }
+    efi::Status::SUCCESS
+}
+
+extern "efiapi" fn gop_set_mode(_: *mut graphics_output::Protocol, _: u32) -> efi::Status {
+    efi::Status::SUCCESS
+}
+
+extern "efiapi" fn gop_blt(
+    this: *mut graphics_output::Protocol,
+    _: *mut graphics_output::BltPixel,
+    _: graphics_output::BltOperation,
+    _: usize,
+    _: usize,
+    _: usize,
+    _: usize,
+    _: usize,
+    _: usize,
+    _: usize,
+) -> efi::Status {
+    // SAFETY: The protocol is the first field of a TestGop.
+    unsafe { (*(this as *mut TestGop)).blts += 1 };
+    efi::Status::SUCCESS
+}
+
+/// A text input device returning the keys it holds.
+#[repr(C)]
+struct TestTextIn {
+    protocol: simple_text_input::Protocol,
+    keys: Vec<u16>,
+}
+
+extern "efiapi" fn text_in_reset(_: *mut simple_text_input::Protocol, _: efi::Boolean) -> efi::Status {
+    efi::Status::SUCCESS
+}
+
+extern "efiapi" fn read_key_stroke(
+    this: *mut simple_text_input::Protocol,
+    key: *mut simple_text_input::InputKey,
+) -> efi::Status {
+    // SAFETY: The protocol is the first field of a TestTextIn.
+    let device = unsafe { &mut *(this as *mut TestTextIn) };
+    if device.keys.is_empty() {
+        return efi::Status::NOT_READY;
+    }
+    // SAFETY: The key is provided by the caller.
+    unsafe { key.write(simple_text_input::InputKey { scan_code: 0, unicode_char: device.keys.remove(0) }) };
+    efi::Status::SUCCESS
+}
+
+/// Installs `protocols` on a new handle.
+fn install(boot_services: &StandardBootServices, protocols: &[(&'static efi::Guid, *mut c_void)]) -> efi::Handle {
+    let mut handle = None;
+    for &(protocol, interface) in protocols {
+        // SAFETY: The interfaces are leaked and match their protocol GUIDs.
+        handle =
+            Some(unsafe { boot_services.install_protocol_interface_unchecked(handle, protocol, interface) }.unwrap());
+    }
+    handle.unwrap()
+}
+
+fn text_out() -> &'static mut TestTextOut {
+    let device = Box::leak(Box::new(TestTextOut {
+        protocol: simple_text_output::Protocol {
+            reset,
+            output_string,
+            test_string,
+            query_mode,
+            set_mode,
+            set_attribute,
+            clear_screen,
+            set_cursor_position,
+            enable_cursor,
+            mode: ptr::null_mut(),
+        },
+        mode: simple_text_output::Mode {
+            max_mode: 1,
+            mode: 0,
+            attribute: 0,
+            cursor_column: 0,
+            cursor_row: 0,
+            cursor_visible: efi::Boolean::TRUE,
+        },
+        output: String::new(),
+    }));
+    device.protocol.mode = &mut device.mode;
+    device
+}
+
+fn output(system_table: *mut efi::SystemTable, text: &str) {
+    let mut string: Vec<u16> = text.encode_utf16().chain([0]).collect();
+    // SAFETY: The console output protocol of the system table is installed by the console splitter.
+    let status = unsafe { ((*(*system_table).con_out).output_string)((*system_table).con_out, string.as_mut_ptr()) };
+    assert_eq!(status, efi::Status::SUCCESS);
+}
+
+#[test]
+fn test_consoles_are_multiplexed() {
+    let mut harness = TestHarness::new().with_component(ConsoleSplitter);
+    let boot_services = harness.boot_services();
+    let system_table = harness.system_table();
+
+    // The console splitter finds the system table with the Loaded Image Protocol of the DXE core.
+    // SAFETY: The Loaded Image Protocol is all integers and pointers, which can be zero.
+    let loaded_image = Box::leak(Box::new(unsafe { mem::zeroed::<loaded_image::Protocol>() }));
+    loaded_image.system_table = system_table;
+    install(&boot_services, &[(&loaded_image::PROTOCOL_GUID, loaded_image as *mut _ as *mut c_void)]);
+
+    harness.run().unwrap();
+    assert!(harness.pending_components().is_empty());
+
+    // The virtual consoles are published in the system table, and output is discarded until a device is connected.
+    // SAFETY: The system table is valid for the lifetime of the host environment.
+    let (con_out_handle, con_out, std_err) =
+        unsafe { ((*system_table).console_out_handle, (*system_table).con_out, (*system_table).std_err) };
+    assert!(!con_out.is_null() && !std_err.is_null() && con_out != std_err);
+    assert!(unsafe { !(*system_table).con_in.is_null() });
+    output(system_table, "Discarded");
+
+    // A serial terminal and a graphics console are connected as console output devices.
+    let serial = text_out();
+    let serial_handle = install(
+        &boot_services,
+        &[
+            (&CONSOLE_OUT_DEVICE, ptr::null_mut()),
+            (&simple_text_output::PROTOCOL_GUID, &mut serial.protocol as *mut _ as *mut c_void),
+        ],
+    );
+    let graphics = text_out();
+    let gop = Box::leak(Box::new(TestGop {
+        protocol: graphics_output::Protocol {
+            query_mode: gop_query_mode,
+            set_mode: gop_set_mode,
+            blt: gop_blt,
+            mode: ptr::null_mut(),
+        },
+        mode: graphics_output::Mode {
+            max_mode: 1,
+            mode: 0,
+            info: ptr::null_mut(),
+            size_of_info: mem::size_of::<graphics_output::ModeInformation>(),
+            frame_buffer_base: 0x8000_0000,
+            frame_buffer_size: 800 * 600 * 4,
+        },
+        info: graphics_output::ModeInformation {
+            version: 0,
+            horizontal_resolution: 800,
+            vertical_resolution: 600,
+            pixel_format: graphics_output::PIXEL_BLUE_GREEN_RED_RESERVED_8_BIT_PER_COLOR,
+            pixel_information: graphics_output::PixelBitmask {
+                red_mask: 0,
+                green_mask: 0,
+                blue_mask: 0,
+                reserved_mask: 0,
+            },
+            pixels_per_scan_line: 800,
+        },
+        blts: 0,
+        boot_services: boot_services.clone(),
+    }));
+    gop.mode.info = &mut gop.info;
+    gop.protocol.mode = &mut gop.mode;
+    let graphics_handle = install(
+        &boot_services,
+        &[
+            (&CONSOLE_OUT_DEVICE, ptr::null_mut()),
+            (&simple_text_output::PROTOCOL_GUID, &mut graphics.protocol as *mut _ as *mut c_void),
+            (&graphics_output::PROTOCOL_GUID, &mut gop.protocol as *mut _ as *mut c_void),
+        ],
+    );
+    for handle in [serial_handle, graphics_handle] {
+        // SAFETY: No remaining device path is given.
+        unsafe { boot_services.connect_controller(handle, Vec::new(), ptr::null_mut(), false) }.unwrap();
+    }
+
+    output(system_table, "Hello");
+    assert_eq!(serial.output, "Hello");
+    assert_eq!(graphics.output, "Hello");
+
+    // The GOP of the graphics console is available on the console output handle, with its frame buffer.
+    // SAFETY: The GOP is installed by the console splitter.
+    let virtual_gop = unsafe { boot_services.handle_protocol::<graphics_output::Protocol>(con_out_handle) }.unwrap();
+    assert_eq!(unsafe { (*virtual_gop.mode).frame_buffer_base }, 0x8000_0000);
+    let status = (virtual_gop.blt)(virtual_gop, ptr::null_mut(), graphics_output::BLT_VIDEO_FILL, 0, 0, 0, 0, 1, 1, 0);
+    assert_eq!(status, efi::Status::SUCCESS);
+    assert_eq!(gop.blts, 1);
+
+    // Disconnected devices are removed from the console, and the GOP with the last graphics device.
+    boot_services.disconnect_controller(graphics_handle, None, None).unwrap();
+    output(system_table, ", world");
+    assert_eq!(serial.output, "Hello, world");
+    assert_eq!(graphics.output, "Hello");
+    assert_eq!(
+        unsafe { boot_services.handle_protocol::<graphics_output::Protocol>(con_out_handle) }.err(),
+        Some(efi::Status::UNSUPPORTED)
+    );
+
+    // Keys are read from the console input devices.
+    let keyboard = Box::leak(Box::new(TestTextIn {
+        protocol: simple_text_input::Protocol { reset: text_in_reset, read_key_stroke, wait_for_key: ptr::null_mut() },
+        keys: vec![b'y' as u16],
+    }));
+    let keyboard_handle = install(
+        &boot_services,
+        &[
+            (&CONSOLE_IN_DEVICE, ptr::null_mut()),
+            (&simple_text_input::PROTOCOL_GUID, &mut keyboard.protocol as *mut _ as *mut c_void),
+        ],
+    );
+    // SAFETY: No remaining device path is given.
+    unsafe { boot_services.connect_controller(keyboard_handle, Vec::new(), ptr::null_mut(), false) }.unwrap();
+    // SAFETY: The console input protocol of the system table is installed by the console splitter.
+    let con_in = unsafe { (*system_table).con_in };
+    let mut key = simple_text_input::InputKey { scan_code: 0, unicode_char: 0 };
+    assert_eq!(unsafe { ((*con_in).read_key_stroke)(con_in, &mut key) }, efi::Status::SUCCESS);
+    assert_eq!(key.unicode_char, b'y' as u16);
+    assert_eq!(unsafe { ((*con_in).read_key_stroke)(con_in, &mut key) }, efi::Status::NOT_READY);
+}
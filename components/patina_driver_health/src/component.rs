@@ -0,0 +1,213 @@
+//! Patina Driver Health Component
+//!
+//! Checks the health of all drivers producing the Driver Health Protocol each time the platform signals that it is
+//! ready to boot, i.e. after BDS has connected the boot devices. Requested repairs are performed, the remaining
+//! unhealthy controllers are logged, and the critical failures that remain are reported as a status code and handed to
+//! the platform [DriverHealthPolicy], if one is produced. Boot is never blocked by this component.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use alloc::vec::Vec;
+use core::{clone::Clone, convert::AsRef};
+use patina::{
+    boot_services::{
+        BootServices, StandardBootServices,
+        event::{EventBuilder, EventType},
+        tpl::Tpl,
+    },
+    component::{IntoComponent, params::Config, service::Service},
+    error::EfiError,
+    guids,
+    uefi_protocol::{driver_health::HealthStatus, status_code::StatusCodeRuntimeProtocol},
+};
+use patina_pi::status_code::{
+    EFI_ERROR_CODE, EFI_ERROR_MAJOR, EFI_SOFTWARE_DXE_BS_DRIVER, EFI_SW_EC_ILLEGAL_HARDWARE_STATE,
+};
+use r_efi::{efi, system::EVENT_GROUP_READY_TO_BOOT};
+
+use crate::{
+    config::DriverHealthConfig,
+    health::{self, DriverHealthRecord},
+    policy::DriverHealthPolicy,
+};
+
+/// Driver Health Component.
+#[derive(IntoComponent)]
+pub struct DriverHealth;
+
+/// Context of the ready to boot event of the [DriverHealth] component.
+pub struct DriverHealthContext<BB> {
+    boot_services: BB,
+    config: DriverHealthConfig,
+    policy: Option<Service<dyn DriverHealthPolicy>>,
+}
+
+impl DriverHealth {
+    /// Entry point of [`DriverHealth`]
+    #[coverage(off)] // This is tested via the generic version, see _entry_point.
+    pub fn entry_point(
+        self,
+        config: Config<DriverHealthConfig>,
+        policy: Option<Service<dyn DriverHealthPolicy>>,
+        boot_services: StandardBootServices,
+    ) -> Result<(), EfiError> {
+        self._entry_point(boot_services, *config, policy)
+    }
+
+    /// Entry point that have generic parameter.
+    fn _entry_point<BB, B>(
+        self,
+        boot_services: BB,
+        config: DriverHealthConfig,
+        policy: Option<Service<dyn DriverHealthPolicy>>,
+    ) -> Result<(), EfiError>
+    where
+        BB: AsRef<B> + Clone + 'static,
+        B: BootServices + 'static,
+    {
+        // Boot can be attempted more than once, so the health is checked every time ready to boot is signaled.
+        EventBuilder::new(BB::clone(&boot_services), EventType::NOTIFY_SIGNAL, Tpl::CALLBACK)
+            .event_group(&EVENT_GROUP_READY_TO_BOOT)
+            .create(check_driver_health::<BB, B>, DriverHealthContext { boot_services, config, policy })?;
+        Ok(())
+    }
+}
+
+/// Ready to boot notify function, checking and repairing driver health.
+fn check_driver_health<BB, B>(_event: efi::Event, context: &mut DriverHealthContext<BB>)
+where
+    BB: AsRef<B>,
+    B: BootServices,
+{
+    let boot_services = context.boot_services.as_ref();
+
+    let mut records = match health::collect_driver_health(boot_services) {
+        Ok(records) => records,
+        Err(err) => {
+            log::error!("Driver health: failed to collect driver health: {err:?}");
+            return;
+        }
+    };
+
+    if !context.config.skip_repair {
+        health::repair_driver_health(boot_services, &mut records);
+    }
+
+    log_driver_health(&records);
+
+    let critical = critical_records(&records);
+    if critical.is_empty() {
+        return;
+    }
+
+    log::error!("Driver health: {} critical driver health failure(s) remain after repair.", critical.len());
+    report_critical_failure(boot_services);
+
+    // The notify function runs at TPL_CALLBACK, so the platform policy decides how to proceed (e.g. by resetting the
+    // system) rather than waiting here.
+    match &context.policy {
+        Some(policy) => policy.critical_failure(&critical),
+        None => log::warn!("Driver health: no platform driver health policy, continuing to boot."),
+    }
+}
+
+/// Logs the records of unhealthy drivers and controllers.
+fn log_driver_health(records: &[DriverHealthRecord]) {
+    for record in records.iter().filter(|record| record.report.status != HealthStatus::Healthy) {
+        log::warn!(
+            "Driver health: driver {:?} controller {:?} reported {:?}",
+            record.driver,
+            record.controller,
+            record.report.status
+        );
+        for message in &record.report.messages {
+            log::warn!(
+                "Driver health:   message code {:#x} (HII handle {:?}, string {})",
+                message.message_code,
+                message.hii_handle,
+                message.string_id
+            );
+        }
+    }
+}
+
+/// Returns the records of the critical driver health failures.
+fn critical_records(records: &[DriverHealthRecord]) -> Vec<&DriverHealthRecord> {
+    records.iter().filter(|record| record.is_critical()).collect()
+}
+
+/// Reports a critical driver health failure through the Status Code Runtime Protocol, if available.
+fn report_critical_failure<B: BootServices>(boot_services: &B) {
+    // SAFETY: The Status Code Runtime Protocol interface matches [StatusCodeRuntimeProtocol].
+    let Ok(status_code) = (unsafe { boot_services.locate_protocol::<StatusCodeRuntimeProtocol>(None) }) else {
+        log::warn!("Driver health: Status Code Runtime Protocol not found, critical failure not reported.");
+        return;
+    };
+
+    if let Err(status) = status_code.report_status_code(
+        EFI_ERROR_CODE | EFI_ERROR_MAJOR,
+        EFI_SOFTWARE_DXE_BS_DRIVER | EFI_SW_EC_ILLEGAL_HARDWARE_STATE,
+        0,
+        &guids::DXE_CORE,
+    ) {
+        log::error!("Driver health: failed to report the critical failure as a status code: {status:?}");
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use patina::{
+        boot_services::{MockBootServices, event::EventContext},
+        uefi_protocol::driver_health::HealthReport,
+    };
+    use std::{boxed::Box, rc::Rc, vec::Vec};
+
+    fn record(status: HealthStatus) -> DriverHealthRecord {
+        DriverHealthRecord {
+            driver: 1_usize as efi::Handle,
+            controller: Some(2_usize as efi::Handle),
+            report: HealthReport { status, messages: Vec::new(), form_hii_handle: None },
+        }
+    }
+
+    #[test]
+    fn test_entry_point_registers_ready_to_boot_event() {
+        let mut boot_services = MockBootServices::new();
+        boot_services
+            .expect_create_event_ex::<Box<EventContext<Rc<MockBootServices>, DriverHealthContext<Rc<MockBootServices>>>>>()
+            .once()
+            .withf_st(|event_type, notify_tpl, notify_function, _notify_context, event_group| {
+                assert_eq!(&EventType::NOTIFY_SIGNAL, event_type);
+                assert_eq!(&Tpl::CALLBACK, notify_tpl);
+                assert!(notify_function.is_some());
+                assert_eq!(&EVENT_GROUP_READY_TO_BOOT, event_group);
+                true
+            })
+            .return_const_st(Ok(1_usize as efi::Event));
+
+        assert_eq!(DriverHealth._entry_point(Rc::new(boot_services), DriverHealthConfig::default(), None), Ok(()));
+    }
+
+    #[test]
+    fn test_critical_records() {
+        let records = [record(HealthStatus::Healthy), record(HealthStatus::ConfigurationRequired)];
+        assert!(critical_records(&records).is_empty());
+
+        let records = [record(HealthStatus::Healthy), record(HealthStatus::Failed)];
+        let critical = critical_records(&records);
+        assert_eq!(critical.len(), 1);
+        assert_eq!(critical[0].report.status, HealthStatus::Failed);
+
+        assert_eq!(critical_records(&[record(HealthStatus::RepairRequired)]).len(), 1);
+        assert!(critical_records(&[record(HealthStatus::RebootRequired)]).is_empty());
+    }
+}
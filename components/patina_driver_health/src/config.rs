@@ -0,0 +1,18 @@
+//! Patina Driver Health Component Configuration
+//!
+//! The configuration can be set statically with `.with_config()` or produced dynamically during boot. If no
+//! configuration is provided, driver health is checked and repaired.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+/// The configuration for the Patina Driver Health component.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DriverHealthConfig {
+    /// Skips the repair and reconnect operations requested by drivers, only reporting the health status.
+    pub skip_repair: bool,
+}
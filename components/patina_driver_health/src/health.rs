@@ -0,0 +1,128 @@
+//! Driver Health Collection
+//!
+//! Helpers to query all producers of the UEFI Driver Health Protocol and to perform the repair operations they
+//! request.
+//!
+//! The health of each driver is first queried without a controller. Drivers that report a healthy status are
+//! recorded as is, for the others every controller in the system is queried to find the unhealthy ones. Child
+//! controllers are not queried individually; a driver is expected to report the health of its children through the
+//! parent controller.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use alloc::vec::Vec;
+use core::ptr;
+use patina::{
+    boot_services::{BootServices, protocol_handler::HandleSearchType},
+    error::EfiError,
+    uefi_protocol::driver_health::{self, DriverHealthProtocol, HealthReport, HealthStatus},
+};
+use r_efi::efi;
+
+/// The health reported by a driver, either for itself or for one of its controllers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DriverHealthRecord {
+    /// The handle the Driver Health Protocol is installed on.
+    pub driver: efi::Handle,
+    /// The controller the report is for, or `None` if it is the overall health of the driver.
+    pub controller: Option<efi::Handle>,
+    /// The reported health.
+    pub report: HealthReport,
+}
+
+impl DriverHealthRecord {
+    /// Returns whether the record is a failure that prevents the controller from being used.
+    pub fn is_critical(&self) -> bool {
+        matches!(self.report.status, HealthStatus::Failed | HealthStatus::RepairRequired)
+    }
+}
+
+/// Queries every instance of the Driver Health Protocol.
+///
+/// Returns an empty list if no driver produces the Driver Health Protocol. Drivers that fail to report their health
+/// are skipped.
+pub fn collect_driver_health<B: BootServices>(boot_services: &B) -> Result<Vec<DriverHealthRecord>, EfiError> {
+    let drivers = match boot_services.locate_handle_buffer(HandleSearchType::ByProtocol(&driver_health::PROTOCOL_GUID))
+    {
+        Ok(drivers) => drivers,
+        Err(efi::Status::NOT_FOUND) => return Ok(Vec::new()),
+        Err(status) => return Err(status.into()),
+    };
+    let controllers = boot_services.locate_handle_buffer(HandleSearchType::AllHandle)?;
+
+    let mut records = Vec::new();
+    for &driver in drivers.iter() {
+        // SAFETY: The handle was returned for the Driver Health Protocol GUID.
+        let protocol = unsafe { boot_services.handle_protocol::<DriverHealthProtocol>(driver) }?;
+
+        let report = match protocol.get_health_status(boot_services, None, None) {
+            Ok(report) => report,
+            Err(status) => {
+                log::warn!("Driver health: failed to get the health of driver {driver:?}: {status:#x?}");
+                continue;
+            }
+        };
+        if report.status == HealthStatus::Healthy {
+            records.push(DriverHealthRecord { driver, controller: None, report });
+            continue;
+        }
+
+        let driver_records = records.len();
+        for &controller in controllers.iter() {
+            match protocol.get_health_status(boot_services, Some(controller), None) {
+                Ok(report) if report.status == HealthStatus::Healthy => (),
+                Ok(report) => records.push(DriverHealthRecord { driver, controller: Some(controller), report }),
+                Err(efi::Status::UNSUPPORTED) => (),
+                Err(status) => {
+                    log::warn!("Driver health: failed to get the health of controller {controller:?}: {status:#x?}")
+                }
+            }
+        }
+
+        // The driver is unhealthy, but did not attribute it to a controller.
+        if records.len() == driver_records {
+            records.push(DriverHealthRecord { driver, controller: None, report });
+        }
+    }
+
+    Ok(records)
+}
+
+/// Performs the repair and reconnect operations requested in `records`, and updates them with the resulting health.
+///
+/// Only records for a controller can be repaired. If a repair operation fails, the record is left unchanged.
+pub fn repair_driver_health<B: BootServices>(boot_services: &B, records: &mut [DriverHealthRecord]) {
+    for record in records.iter_mut() {
+        let Some(controller) = record.controller else {
+            continue;
+        };
+
+        // SAFETY: The record was created for a handle with the Driver Health Protocol.
+        let Ok(protocol) = (unsafe { boot_services.handle_protocol::<DriverHealthProtocol>(record.driver) }) else {
+            continue;
+        };
+
+        let result = match record.report.status {
+            HealthStatus::RepairRequired => protocol.repair(controller, None),
+            HealthStatus::ReconnectRequired => {
+                let _ = boot_services.disconnect_controller(controller, None, None);
+                // SAFETY: No remaining device path is provided, all children are connected.
+                unsafe { boot_services.connect_controller(controller, Vec::new(), ptr::null_mut(), true) }
+            }
+            _ => continue,
+        };
+
+        match result.and_then(|_| protocol.get_health_status(boot_services, Some(controller), None)) {
+            Ok(report) => record.report = report,
+            Err(status) => log::warn!(
+                "Driver health: failed to recover controller {controller:?} ({:?}): {status:#x?}",
+                record.report.status
+            ),
+        }
+    }
+}
@@ -0,0 +1,44 @@
+//! Driver health support for Patina platforms.
+//!
+//! Drivers report the health of the controllers they manage with the UEFI Driver Health Protocol (e.g. a RAID
+//! controller with a degraded volume, or a storage controller that must be reconfigured). This crate provides:
+//!
+//! - [health]: helpers that query every Driver Health Protocol instance, collect the reported status and messages,
+//!   and perform the repair operations that drivers request.
+//! - [component::DriverHealth]: a component that checks driver health when the platform is ready to boot, and reports
+//!   the critical health failures that remain after repairs.
+//! - [policy::DriverHealthPolicy]: a service the platform can produce to decide how critical health failures are
+//!   handled, e.g. by resetting the system.
+//!
+//! ## Integration Example
+//!
+//! ```rust,ignore
+//! // ...
+//!
+//! Core::default()
+//!  // ...
+//!  .with_config(patina_driver_health::config::DriverHealthConfig::default())
+//!  .with_service(PlatformDriverHealthPolicy) // Produces `dyn DriverHealthPolicy`, e.g. resetting the system.
+//!  .with_component(patina_driver_health::component::DriverHealth)
+//!  .start()
+//!  .unwrap();
+//!
+//! // ...
+//! ```
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+#![cfg_attr(not(test), no_std)]
+#![feature(coverage_attribute)]
+
+extern crate alloc;
+
+pub mod component;
+pub mod config;
+pub mod health;
+pub mod policy;
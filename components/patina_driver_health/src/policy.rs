@@ -0,0 +1,23 @@
+//! Patina Driver Health Platform Policy
+//!
+//! A platform decides how critical driver health failures are handled by producing a [DriverHealthPolicy] service.
+//! If no policy is produced, critical failures are reported and boot continues.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use crate::health::DriverHealthRecord;
+
+/// The platform policy for critical driver health failures.
+pub trait DriverHealthPolicy {
+    /// Handles the critical driver health failures that remain after repairs, e.g. by resetting the system.
+    ///
+    /// This is called from the ready to boot notify function at `TPL_CALLBACK`, so it must return promptly and must
+    /// not wait for user input. A platform presenting a repair UI must do so from BDS at `TPL_APPLICATION`, e.g. by
+    /// calling [collect_driver_health](crate::health::collect_driver_health) before starting a boot option.
+    fn critical_failure(&self, records: &[&DriverHealthRecord]);
+}
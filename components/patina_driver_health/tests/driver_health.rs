@@ -0,0 +1,208 @@
+//! Integration tests collecting and repairing driver health against the host DXE core boot services.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use core::{
+    ffi::c_void,
+    ptr,
+    sync::atomic::{AtomicU32, Ordering},
+};
+use std::sync::Mutex;
+
+use patina::{
+    boot_services::{
+        BootServices, StandardBootServices,
+        event::{EventBuilder, EventType},
+        tpl::Tpl,
+    },
+    component::service::IntoService,
+    uefi_protocol::driver_health::{
+        self, EFI_DRIVER_HEALTH_STATUS_FAILED, EFI_DRIVER_HEALTH_STATUS_HEALTHY,
+        EFI_DRIVER_HEALTH_STATUS_REPAIR_REQUIRED, EfiDriverHealthHiiMessage, EfiDriverHealthStatus, HealthStatus,
+        RepairNotify,
+    },
+};
+use patina_driver_health::{
+    component::DriverHealth,
+    config::DriverHealthConfig,
+    health::{self, DriverHealthRecord},
+    policy::DriverHealthPolicy,
+};
+use patina_test::TestHarness;
+use r_efi::{efi, system::EVENT_GROUP_READY_TO_BOOT};
+
+static CONTROLLER_GUID: efi::Guid =
+    efi::Guid::from_fields(0x5d0c27a4, 0x8e1b, 0x4c3f, 0xa6, 0x92, &[0x1f, 0x3b, 0x7e, 0x40, 0xd5, 0x8c]);
+
+/// A driver producing the Driver Health Protocol for a single controller.
+#[repr(C)]
+struct TestDriver {
+    protocol: driver_health::Protocol,
+    controller: efi::Handle,
+    status: AtomicU32,
+}
+
+impl TestDriver {
+    fn install(boot_services: &StandardBootServices, status: EfiDriverHealthStatus) -> (efi::Handle, efi::Handle) {
+        // SAFETY: The controller is identified by a marker protocol without an interface.
+        let controller = unsafe {
+            boot_services.install_protocol_interface_unchecked(None, &CONTROLLER_GUID, ptr::null_mut()).unwrap()
+        };
+        let driver = Box::leak(Box::new(TestDriver {
+            protocol: driver_health::Protocol { get_health_status, repair },
+            controller,
+            status: AtomicU32::new(status),
+        }));
+        // SAFETY: The interface starts with the Driver Health Protocol and is leaked.
+        let handle = unsafe {
+            boot_services
+                .install_protocol_interface_unchecked(
+                    None,
+                    &driver_health::PROTOCOL_GUID,
+                    driver as *mut TestDriver as *mut c_void,
+                )
+                .unwrap()
+        };
+        (handle, controller)
+    }
+}
+
+extern "efiapi" fn get_health_status(
+    this: *mut driver_health::Protocol,
+    controller_handle: efi::Handle,
+    _child_handle: efi::Handle,
+    health_status: *mut EfiDriverHealthStatus,
+    _message_list: *mut *mut EfiDriverHealthHiiMessage,
+    _form_hii_handle: *mut efi::Handle,
+) -> efi::Status {
+    // SAFETY: The protocol is the first field of a TestDriver.
+    let driver = unsafe { &*(this as *const TestDriver) };
+    let status = driver.status.load(Ordering::SeqCst);
+    if !controller_handle.is_null() && controller_handle != driver.controller {
+        return efi::Status::UNSUPPORTED;
+    }
+    // SAFETY: health_status is provided by the caller.
+    unsafe { health_status.write(status) };
+    efi::Status::SUCCESS
+}
+
+extern "efiapi" fn repair(
+    this: *mut driver_health::Protocol,
+    controller_handle: efi::Handle,
+    _child_handle: efi::Handle,
+    _repair_notify: Option<RepairNotify>,
+) -> efi::Status {
+    // SAFETY: The protocol is the first field of a TestDriver.
+    let driver = unsafe { &*(this as *const TestDriver) };
+    if controller_handle != driver.controller {
+        return efi::Status::UNSUPPORTED;
+    }
+    let _ = driver.status.compare_exchange(
+        EFI_DRIVER_HEALTH_STATUS_REPAIR_REQUIRED,
+        EFI_DRIVER_HEALTH_STATUS_HEALTHY,
+        Ordering::SeqCst,
+        Ordering::SeqCst,
+    );
+    efi::Status::SUCCESS
+}
+
+fn driver_records(records: &[DriverHealthRecord], driver: efi::Handle) -> Vec<&DriverHealthRecord> {
+    records.iter().filter(|record| record.driver == driver).collect()
+}
+
+#[test]
+fn test_collect_and_repair_driver_health() {
+    let harness = TestHarness::new();
+    let boot_services = harness.boot_services();
+
+    let (healthy, _) = TestDriver::install(&boot_services, EFI_DRIVER_HEALTH_STATUS_HEALTHY);
+    let (repairable, repairable_controller) =
+        TestDriver::install(&boot_services, EFI_DRIVER_HEALTH_STATUS_REPAIR_REQUIRED);
+    let (failed, failed_controller) = TestDriver::install(&boot_services, EFI_DRIVER_HEALTH_STATUS_FAILED);
+
+    let mut records = health::collect_driver_health(&boot_services).unwrap();
+
+    let healthy_records = driver_records(&records, healthy);
+    assert_eq!(healthy_records.len(), 1);
+    assert_eq!(healthy_records[0].controller, None);
+    assert_eq!(healthy_records[0].report.status, HealthStatus::Healthy);
+
+    let repairable_records = driver_records(&records, repairable);
+    assert_eq!(repairable_records.len(), 1);
+    assert_eq!(repairable_records[0].controller, Some(repairable_controller));
+    assert!(repairable_records[0].is_critical());
+
+    health::repair_driver_health(&boot_services, &mut records);
+
+    let repairable_records = driver_records(&records, repairable);
+    assert_eq!(repairable_records[0].report.status, HealthStatus::Healthy);
+    let failed_records = driver_records(&records, failed);
+    assert_eq!(failed_records[0].controller, Some(failed_controller));
+    assert_eq!(failed_records[0].report.status, HealthStatus::Failed);
+}
+
+#[test]
+fn test_component_repairs_when_ready_to_boot() {
+    let mut harness = TestHarness::new().with_config(DriverHealthConfig::default()).with_component(DriverHealth);
+    harness.run().unwrap();
+    assert!(harness.pending_components().is_empty());
+
+    let boot_services = harness.boot_services();
+    let (driver, _) = TestDriver::install(&boot_services, EFI_DRIVER_HEALTH_STATUS_REPAIR_REQUIRED);
+
+    signal_ready_to_boot(&boot_services);
+
+    let records = health::collect_driver_health(&boot_services).unwrap();
+    assert!(driver_records(&records, driver).iter().all(|record| record.report.status == HealthStatus::Healthy));
+}
+
+/// Signals ready to boot, as BDS does before starting a boot option.
+fn signal_ready_to_boot(boot_services: &StandardBootServices) {
+    let event = EventBuilder::new(boot_services.clone(), EventType::NOTIFY_SIGNAL, Tpl::CALLBACK)
+        .event_group(&EVENT_GROUP_READY_TO_BOOT)
+        .create(ready_to_boot, ())
+        .unwrap();
+    boot_services.signal_event(event.event()).unwrap();
+    event.close().unwrap();
+}
+
+fn ready_to_boot(_event: efi::Event, _context: &mut ()) {}
+
+/// The drivers reported to the [RecordingPolicy] with a critical failure.
+static CRITICAL_DRIVERS: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+
+/// A platform policy recording the drivers with a critical failure.
+#[derive(IntoService)]
+#[service(dyn DriverHealthPolicy)]
+struct RecordingPolicy;
+
+impl DriverHealthPolicy for RecordingPolicy {
+    fn critical_failure(&self, records: &[&DriverHealthRecord]) {
+        CRITICAL_DRIVERS.lock().unwrap().extend(records.iter().map(|record| record.driver as usize));
+    }
+}
+
+#[test]
+fn test_component_hands_critical_failures_to_the_platform_policy() {
+    let mut harness = TestHarness::new()
+        .with_config(DriverHealthConfig::default())
+        .with_service(RecordingPolicy)
+        .with_component(DriverHealth);
+    harness.run().unwrap();
+    assert!(harness.pending_components().is_empty());
+
+    let boot_services = harness.boot_services();
+    let (failed, _) = TestDriver::install(&boot_services, EFI_DRIVER_HEALTH_STATUS_FAILED);
+    let (repairable, _) = TestDriver::install(&boot_services, EFI_DRIVER_HEALTH_STATUS_REPAIR_REQUIRED);
+
+    // The notify function returns after handing the failure to the policy, rather than blocking boot.
+    signal_ready_to_boot(&boot_services);
+
+    let critical_drivers = CRITICAL_DRIVERS.lock().unwrap();
+    assert!(critical_drivers.contains(&(failed as usize)));
+    assert!(!critical_drivers.contains(&(repairable as usize)));
+}
@@ -0,0 +1,190 @@
+//! Patina ESRT Manager Component
+//!
+//! Publishes the ESRT each time the platform signals that it is ready to boot, once the drivers producing the
+//! Firmware Management Protocol have been connected. Once published, the ESRT is rebuilt whenever another Firmware
+//! Management Protocol instance is installed, so that it stays in sync with the images of the platform.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use alloc::{rc::Rc, vec::Vec};
+use core::{cell::RefCell, clone::Clone, convert::AsRef};
+use patina::{
+    boot_services::{
+        BootServices, StandardBootServices,
+        event::{EventBuilder, EventType},
+        tpl::Tpl,
+    },
+    component::{IntoComponent, params::Config},
+    error::EfiError,
+    uefi_protocol::firmware_management,
+};
+use r_efi::{efi, system::EVENT_GROUP_READY_TO_BOOT};
+
+use crate::{
+    config::EsrtConfig,
+    esrt::{self, EsrtEntry, EsrtHeader},
+};
+
+/// ESRT Manager Component.
+#[derive(IntoComponent)]
+pub struct EsrtManager;
+
+/// The ESRT published by the [EsrtManager] component.
+#[derive(Default)]
+struct PublishedTable {
+    table: Option<*mut EsrtHeader>,
+    entries: Vec<EsrtEntry>,
+}
+
+/// Context of the events of the [EsrtManager] component.
+pub struct EsrtContext<BB> {
+    boot_services: BB,
+    config: Rc<EsrtConfig>,
+    published: Rc<RefCell<PublishedTable>>,
+}
+
+impl<BB: Clone> Clone for EsrtContext<BB> {
+    fn clone(&self) -> Self {
+        Self {
+            boot_services: self.boot_services.clone(),
+            config: self.config.clone(),
+            published: self.published.clone(),
+        }
+    }
+}
+
+impl EsrtManager {
+    /// Entry point of [`EsrtManager`]
+    #[coverage(off)] // This is tested via the generic version, see _entry_point.
+    pub fn entry_point(self, config: Config<EsrtConfig>, boot_services: StandardBootServices) -> Result<(), EfiError> {
+        self._entry_point(boot_services, (*config).clone())
+    }
+
+    /// Entry point that have generic parameter.
+    fn _entry_point<BB, B>(self, boot_services: BB, config: EsrtConfig) -> Result<(), EfiError>
+    where
+        BB: AsRef<B> + Clone + 'static,
+        B: BootServices + 'static,
+    {
+        let context = EsrtContext {
+            boot_services: BB::clone(&boot_services),
+            config: Rc::new(config),
+            published: Rc::new(RefCell::new(PublishedTable::default())),
+        };
+
+        // Boot can be attempted more than once, and the images may be updated in between, so the ESRT is refreshed
+        // every time ready to boot is signaled.
+        EventBuilder::new(BB::clone(&boot_services), EventType::NOTIFY_SIGNAL, Tpl::CALLBACK)
+            .event_group(&EVENT_GROUP_READY_TO_BOOT)
+            .create(on_ready_to_boot::<BB, B>, context.clone())?;
+
+        let event = EventBuilder::new(BB::clone(&boot_services), EventType::NOTIFY_SIGNAL, Tpl::CALLBACK)
+            .create(on_fmp_installed::<BB, B>, context)?;
+        boot_services.as_ref().register_protocol_notify(&firmware_management::PROTOCOL_GUID, event.event())?;
+        Ok(())
+    }
+}
+
+/// Ready to boot notify function, publishing the ESRT.
+fn on_ready_to_boot<BB, B>(_event: efi::Event, context: &mut EsrtContext<BB>)
+where
+    BB: AsRef<B>,
+    B: BootServices,
+{
+    sync_table(context);
+}
+
+/// Notify function of the event signaled when a Firmware Management Protocol is installed, updating the ESRT if it
+/// has been published.
+fn on_fmp_installed<BB, B>(_event: efi::Event, context: &mut EsrtContext<BB>)
+where
+    BB: AsRef<B>,
+    B: BootServices,
+{
+    if context.published.borrow().table.is_some() {
+        sync_table(context);
+    }
+}
+
+/// Rebuilds the ESRT entries, and publishes the ESRT if they changed since it was last published.
+fn sync_table<BB, B>(context: &EsrtContext<BB>)
+where
+    BB: AsRef<B>,
+    B: BootServices,
+{
+    let boot_services = context.boot_services.as_ref();
+    let entries = match esrt::collect_entries(boot_services, &context.config) {
+        Ok(entries) => entries,
+        Err(err) => {
+            log::error!("ESRT: failed to collect the firmware resources: {err:?}");
+            return;
+        }
+    };
+
+    let mut published = context.published.borrow_mut();
+    if published.table.is_some() && published.entries == entries {
+        return;
+    }
+
+    match esrt::publish(boot_services, &entries) {
+        Ok(table) => {
+            log::info!("ESRT: published {} firmware resources.", entries.len());
+            if let Some(previous) = published.table.replace(table) {
+                let _ = boot_services.free_pool(previous as *mut u8);
+            }
+            published.entries = entries;
+        }
+        Err(err) => log::error!("ESRT: failed to publish the ESRT: {err:?}"),
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use core::ptr::NonNull;
+    use patina::boot_services::{MockBootServices, event::EventContext};
+    use std::boxed::Box;
+
+    type TestEventContext = EventContext<Rc<MockBootServices>, EsrtContext<Rc<MockBootServices>>>;
+
+    #[test]
+    fn test_entry_point_registers_events() {
+        let mut boot_services = MockBootServices::new();
+        boot_services
+            .expect_create_event_ex::<Box<TestEventContext>>()
+            .once()
+            .withf_st(|event_type, notify_tpl, notify_function, _notify_context, event_group| {
+                assert_eq!(&EventType::NOTIFY_SIGNAL, event_type);
+                assert_eq!(&Tpl::CALLBACK, notify_tpl);
+                assert!(notify_function.is_some());
+                assert_eq!(&EVENT_GROUP_READY_TO_BOOT, event_group);
+                true
+            })
+            .return_const_st(Ok(1_usize as efi::Event));
+        boot_services
+            .expect_create_event::<Box<TestEventContext>>()
+            .once()
+            .withf_st(|event_type, notify_tpl, notify_function, _notify_context| {
+                assert_eq!(&EventType::NOTIFY_SIGNAL, event_type);
+                assert_eq!(&Tpl::CALLBACK, notify_tpl);
+                assert!(notify_function.is_some());
+                true
+            })
+            .return_const_st(Ok(2_usize as efi::Event));
+        boot_services.expect_register_protocol_notify().once().returning(|protocol, event| {
+            assert_eq!(protocol, &firmware_management::PROTOCOL_GUID);
+            assert_eq!(event, 2_usize as efi::Event);
+            Ok(NonNull::dangling())
+        });
+
+        assert_eq!(EsrtManager._entry_point(Rc::new(boot_services), EsrtConfig::default()), Ok(()));
+    }
+}
@@ -0,0 +1,28 @@
+//! Patina ESRT Component Configuration
+//!
+//! The configuration can be set statically with `.with_config()` or produced dynamically during boot. If no
+//! configuration is provided, the ESRT only describes the images reported by the Firmware Management Protocol
+//! instances, all as device firmware.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use alloc::vec::Vec;
+use r_efi::efi;
+
+use crate::esrt::EsrtEntry;
+
+/// The configuration for the Patina ESRT component.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct EsrtConfig {
+    /// Entries published in the ESRT in addition to the images reported by the Firmware Management Protocol
+    /// instances, e.g. for firmware updated by a capsule handled outside of DXE. An entry replaces the entry built
+    /// for an image of the same firmware class.
+    pub entries: Vec<EsrtEntry>,
+    /// The image type IDs of the system firmware images, which are described with the system firmware type.
+    pub system_firmware: Vec<efi::Guid>,
+}
@@ -0,0 +1,343 @@
+//! EFI System Resource Table
+//!
+//! The ESRT format, and helpers to build its entries from the images reported by every instance of the Firmware
+//! Management Protocol (FMP) and to publish it as a configuration table.
+//!
+//! An entry is built for each image in use, with the image type ID as firmware class. The images of the same type
+//! reported for several hardware instances are described by a single entry, with the lowest version, the highest
+//! lowest supported version, and the last failed update attempt, if any. The entries of the [EsrtConfig] are added
+//! last, replacing the entries built for the same firmware class.
+//!
+//! See <https://uefi.org/specs/UEFI/2.10/23_Firmware_Update_and_Reporting.html#efi-system-resource-table>
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use alloc::vec::Vec;
+use core::{mem, ptr};
+use patina::{
+    boot_services::{BootServices, allocation::MemoryType, protocol_handler::HandleSearchType},
+    error::EfiError,
+    guids::SYSTEM_RESOURCE_TABLE,
+    uefi_protocol::firmware_management::{
+        self, FirmwareImageDescriptor, FirmwareManagementProtocol, IMAGE_ATTRIBUTE_RESET_REQUIRED,
+        IMAGE_ATTRIBUTE_UEFI_IMAGE, LAST_ATTEMPT_STATUS_SUCCESS,
+    },
+};
+use r_efi::efi;
+
+use crate::config::EsrtConfig;
+
+/// The version of the ESRT entries defined by the UEFI specification.
+pub const ESRT_FIRMWARE_RESOURCE_VERSION: u64 = 1;
+
+/// The type of the firmware resource is unknown.
+pub const ESRT_FW_TYPE_UNKNOWN: u32 = 0;
+/// The firmware resource is the system firmware.
+pub const ESRT_FW_TYPE_SYSTEM_FIRMWARE: u32 = 1;
+/// The firmware resource is the firmware of a device.
+pub const ESRT_FW_TYPE_DEVICE_FIRMWARE: u32 = 2;
+/// The firmware resource is a UEFI driver.
+pub const ESRT_FW_TYPE_UEFI_DRIVER: u32 = 3;
+
+/// The capsule updating the firmware resource must persist across a reset.
+pub const CAPSULE_FLAGS_PERSIST_ACROSS_RESET: u32 = 0x0001_0000;
+
+/// The header of the ESRT (`EFI_SYSTEM_RESOURCE_TABLE`), followed by the entries.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EsrtHeader {
+    /// The number of entries in the table.
+    pub fw_resource_count: u32,
+    /// The number of entries the table has room for.
+    pub fw_resource_count_max: u32,
+    /// The version of the entries, [ESRT_FIRMWARE_RESOURCE_VERSION].
+    pub fw_resource_version: u64,
+}
+
+/// An entry of the ESRT (`EFI_SYSTEM_RESOURCE_ENTRY`), describing an updatable firmware resource.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EsrtEntry {
+    /// The firmware class, which identifies the capsules that update the resource.
+    pub fw_class: efi::Guid,
+    /// The type of the resource, one of the `ESRT_FW_TYPE_*` values.
+    pub fw_type: u32,
+    /// The version of the resource.
+    pub fw_version: u32,
+    /// The lowest version the resource can be updated to.
+    pub lowest_supported_fw_version: u32,
+    /// The capsule flags required to update the resource.
+    pub capsule_flags: u32,
+    /// The version of the last update attempt.
+    pub last_attempt_version: u32,
+    /// The status of the last update attempt.
+    pub last_attempt_status: u32,
+}
+
+impl EsrtEntry {
+    /// Builds the entry describing `image`.
+    pub fn from_image(image: &FirmwareImageDescriptor, config: &EsrtConfig) -> Self {
+        let fw_type = if config.system_firmware.contains(&image.image_type_id) {
+            ESRT_FW_TYPE_SYSTEM_FIRMWARE
+        } else if image.attributes_supported & IMAGE_ATTRIBUTE_UEFI_IMAGE != 0 {
+            ESRT_FW_TYPE_UEFI_DRIVER
+        } else {
+            ESRT_FW_TYPE_DEVICE_FIRMWARE
+        };
+        let capsule_flags =
+            if image.has_attribute(IMAGE_ATTRIBUTE_RESET_REQUIRED) { CAPSULE_FLAGS_PERSIST_ACROSS_RESET } else { 0 };
+
+        Self {
+            fw_class: image.image_type_id,
+            fw_type,
+            fw_version: image.version,
+            lowest_supported_fw_version: image.lowest_supported_image_version,
+            capsule_flags,
+            last_attempt_version: image.last_attempt_version,
+            last_attempt_status: image.last_attempt_status,
+        }
+    }
+
+    /// Merges `other`, describing another hardware instance of the same firmware class, into the entry.
+    fn merge(&mut self, other: &EsrtEntry) {
+        self.fw_version = self.fw_version.min(other.fw_version);
+        self.lowest_supported_fw_version = self.lowest_supported_fw_version.max(other.lowest_supported_fw_version);
+        self.capsule_flags |= other.capsule_flags;
+        // A failure on any of the instances is reported.
+        if self.last_attempt_status == LAST_ATTEMPT_STATUS_SUCCESS {
+            self.last_attempt_version = other.last_attempt_version;
+            self.last_attempt_status = other.last_attempt_status;
+        }
+    }
+}
+
+/// Builds the ESRT entries describing `images`, followed by the entries of `config`.
+pub fn build_entries<'a>(
+    images: impl IntoIterator<Item = &'a FirmwareImageDescriptor>,
+    config: &EsrtConfig,
+) -> Vec<EsrtEntry> {
+    let mut entries: Vec<EsrtEntry> = Vec::new();
+    for image in images.into_iter().filter(|image| image.is_in_use()) {
+        let entry = EsrtEntry::from_image(image, config);
+        match entries.iter_mut().find(|existing| existing.fw_class == entry.fw_class) {
+            Some(existing) => existing.merge(&entry),
+            None => entries.push(entry),
+        }
+    }
+
+    for entry in &config.entries {
+        match entries.iter_mut().find(|existing| existing.fw_class == entry.fw_class) {
+            Some(existing) => *existing = *entry,
+            None => entries.push(*entry),
+        }
+    }
+    entries
+}
+
+/// Builds the ESRT entries from the images reported by every Firmware Management Protocol instance, and the
+/// entries of `config`.
+///
+/// Instances that fail to report their images are skipped.
+pub fn collect_entries<B: BootServices>(boot_services: &B, config: &EsrtConfig) -> Result<Vec<EsrtEntry>, EfiError> {
+    let handles =
+        match boot_services.locate_handle_buffer(HandleSearchType::ByProtocol(&firmware_management::PROTOCOL_GUID)) {
+            Ok(handles) => handles.to_vec(),
+            Err(efi::Status::NOT_FOUND) => Vec::new(),
+            Err(status) => return Err(status.into()),
+        };
+
+    let mut images = Vec::new();
+    for handle in handles {
+        // SAFETY: The handle was returned for the Firmware Management Protocol GUID.
+        let protocol = unsafe { boot_services.handle_protocol::<FirmwareManagementProtocol>(handle) }?;
+        match protocol.get_image_info(boot_services) {
+            Ok(info) => images.extend(info.descriptors),
+            Err(status) => log::warn!("ESRT: failed to get the image information of FMP {handle:?}: {status:#x?}"),
+        }
+    }
+
+    Ok(build_entries(&images, config))
+}
+
+/// Publishes an ESRT with `entries` as a configuration table, replacing the ESRT previously published, if any.
+///
+/// Returns the published table, allocated from boot services data as required by the UEFI specification. The caller
+/// owns the table, and frees it once it has been replaced.
+pub fn publish<B: BootServices>(boot_services: &B, entries: &[EsrtEntry]) -> Result<*mut EsrtHeader, EfiError> {
+    let count = u32::try_from(entries.len()).map_err(|_| EfiError::OutOfResources)?;
+    let size = mem::size_of::<EsrtHeader>() + mem::size_of_val(entries);
+    let table = boot_services.allocate_pool(MemoryType::BOOT_SERVICES_DATA, size)? as *mut EsrtHeader;
+
+    let header = EsrtHeader {
+        fw_resource_count: count,
+        fw_resource_count_max: count,
+        fw_resource_version: ESRT_FIRMWARE_RESOURCE_VERSION,
+    };
+    // SAFETY: The table was allocated with room for the header followed by the entries, and pool allocations are
+    // 8 byte aligned.
+    unsafe {
+        table.write(header);
+        ptr::copy_nonoverlapping(entries.as_ptr(), table.add(1) as *mut EsrtEntry, entries.len());
+    }
+
+    // SAFETY: The table is an ESRT.
+    if let Err(status) =
+        unsafe { boot_services.install_configuration_table_unchecked(&SYSTEM_RESOURCE_TABLE, table as _) }
+    {
+        let _ = boot_services.free_pool(table as *mut u8);
+        return Err(status.into());
+    }
+    Ok(table)
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use patina::{
+        boot_services::MockBootServices,
+        uefi_protocol::firmware_management::{IMAGE_ATTRIBUTE_IN_USE, LAST_ATTEMPT_STATUS_ERROR_UNSUCCESSFUL},
+    };
+    use std::{boxed::Box, vec};
+
+    const SYSTEM: efi::Guid = patina::guid!("1B3C5D7E-9F01-4A2B-8C3D-4E5F60718293");
+    const DEVICE: efi::Guid = patina::guid!("A4B5C6D7-E8F9-4A0B-9C1D-2E3F40516273");
+    const STATIC: efi::Guid = patina::guid!("0F1E2D3C-4B5A-4697-8877-66554433221A");
+
+    fn image(image_type_id: efi::Guid, version: u32) -> FirmwareImageDescriptor {
+        FirmwareImageDescriptor {
+            image_index: 1,
+            image_type_id,
+            image_id: 1,
+            version,
+            attributes_supported: 0,
+            attributes_setting: 0,
+            compatibilities: 0,
+            lowest_supported_image_version: 1,
+            last_attempt_version: version,
+            last_attempt_status: LAST_ATTEMPT_STATUS_SUCCESS,
+            hardware_instance: 0,
+        }
+    }
+
+    fn static_entry(fw_class: efi::Guid, fw_version: u32) -> EsrtEntry {
+        EsrtEntry {
+            fw_class,
+            fw_type: ESRT_FW_TYPE_DEVICE_FIRMWARE,
+            fw_version,
+            lowest_supported_fw_version: 0,
+            capsule_flags: 0,
+            last_attempt_version: 0,
+            last_attempt_status: LAST_ATTEMPT_STATUS_SUCCESS,
+        }
+    }
+
+    #[test]
+    fn test_entry_from_image() {
+        let config = EsrtConfig { system_firmware: vec![SYSTEM], ..Default::default() };
+
+        let mut system = image(SYSTEM, 3);
+        system.attributes_supported = IMAGE_ATTRIBUTE_RESET_REQUIRED;
+        system.attributes_setting = IMAGE_ATTRIBUTE_RESET_REQUIRED;
+        let entry = EsrtEntry::from_image(&system, &config);
+        assert_eq!(entry.fw_type, ESRT_FW_TYPE_SYSTEM_FIRMWARE);
+        assert_eq!(entry.fw_version, 3);
+        assert_eq!(entry.lowest_supported_fw_version, 1);
+        assert_eq!(entry.capsule_flags, CAPSULE_FLAGS_PERSIST_ACROSS_RESET);
+
+        let mut driver = image(DEVICE, 1);
+        driver.attributes_supported = IMAGE_ATTRIBUTE_UEFI_IMAGE | IMAGE_ATTRIBUTE_RESET_REQUIRED;
+        let entry = EsrtEntry::from_image(&driver, &config);
+        assert_eq!(entry.fw_type, ESRT_FW_TYPE_UEFI_DRIVER);
+        assert_eq!(entry.capsule_flags, 0);
+
+        assert_eq!(EsrtEntry::from_image(&image(DEVICE, 1), &config).fw_type, ESRT_FW_TYPE_DEVICE_FIRMWARE);
+    }
+
+    #[test]
+    fn test_hardware_instances_are_merged() {
+        let mut second = image(DEVICE, 2);
+        second.hardware_instance = 1;
+        second.lowest_supported_image_version = 2;
+        second.last_attempt_status = LAST_ATTEMPT_STATUS_ERROR_UNSUCCESSFUL;
+        let mut third = image(DEVICE, 5);
+        third.hardware_instance = 2;
+
+        let entries = build_entries(&[image(DEVICE, 4), second, third], &EsrtConfig::default());
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].fw_version, 2);
+        assert_eq!(entries[0].lowest_supported_fw_version, 2);
+        assert_eq!(entries[0].last_attempt_version, 2);
+        assert_eq!(entries[0].last_attempt_status, LAST_ATTEMPT_STATUS_ERROR_UNSUCCESSFUL);
+    }
+
+    #[test]
+    fn test_images_not_in_use_are_skipped() {
+        let mut backup = image(SYSTEM, 1);
+        backup.attributes_supported = IMAGE_ATTRIBUTE_IN_USE;
+        let mut active = image(DEVICE, 1);
+        active.attributes_supported = IMAGE_ATTRIBUTE_IN_USE;
+        active.attributes_setting = IMAGE_ATTRIBUTE_IN_USE;
+
+        let entries = build_entries(&[backup, active], &EsrtConfig::default());
+        assert_eq!(entries.iter().map(|entry| entry.fw_class).collect::<Vec<_>>(), [DEVICE]);
+    }
+
+    #[test]
+    fn test_config_entries_replace_image_entries() {
+        let config =
+            EsrtConfig { entries: vec![static_entry(STATIC, 9), static_entry(DEVICE, 8)], ..Default::default() };
+
+        let entries = build_entries(&[image(SYSTEM, 1), image(DEVICE, 1)], &config);
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].fw_class, SYSTEM);
+        assert_eq!(entries[1], static_entry(DEVICE, 8));
+        assert_eq!(entries[2], static_entry(STATIC, 9));
+    }
+
+    #[test]
+    fn test_publish() {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_allocate_pool().once().returning(|memory_type, size| {
+            assert_eq!(memory_type, MemoryType::BOOT_SERVICES_DATA);
+            assert_eq!(size, mem::size_of::<EsrtHeader>() + 2 * mem::size_of::<EsrtEntry>());
+            Ok(Box::leak(vec![0_u64; size / 8].into_boxed_slice()).as_mut_ptr() as *mut u8)
+        });
+        boot_services.expect_install_configuration_table_unchecked().once().returning(|guid, _| {
+            assert_eq!(guid, &SYSTEM_RESOURCE_TABLE);
+            Ok(())
+        });
+
+        let entries = [static_entry(SYSTEM, 1), static_entry(DEVICE, 2)];
+        let table = publish(&boot_services, &entries).unwrap();
+        let header = unsafe { table.read() };
+        assert_eq!(header.fw_resource_count, 2);
+        assert_eq!(header.fw_resource_count_max, 2);
+        assert_eq!(header.fw_resource_version, ESRT_FIRMWARE_RESOURCE_VERSION);
+        let published = unsafe { core::slice::from_raw_parts(table.add(1) as *const EsrtEntry, 2) };
+        assert_eq!(published, entries);
+    }
+
+    #[test]
+    fn test_publish_frees_the_table_on_error() {
+        let mut boot_services = MockBootServices::new();
+        boot_services
+            .expect_allocate_pool()
+            .once()
+            .returning(|_, size| Ok(Box::leak(vec![0_u64; size / 8].into_boxed_slice()).as_mut_ptr() as *mut u8));
+        boot_services
+            .expect_install_configuration_table_unchecked()
+            .once()
+            .returning(|_, _| Err(efi::Status::OUT_OF_RESOURCES));
+        boot_services.expect_free_pool().once().returning(|_| Ok(()));
+
+        assert_eq!(publish(&boot_services, &[static_entry(SYSTEM, 1)]), Err(EfiError::OutOfResources));
+    }
+}
@@ -0,0 +1,43 @@
+//! EFI System Resource Table (ESRT) support for Patina platforms.
+//!
+//! The ESRT describes the firmware resources of the platform that can be updated with capsules, so that an operating
+//! system can report their versions and deliver firmware updates. This crate provides:
+//!
+//! - [esrt]: the ESRT format, and helpers that build the ESRT entries from the images reported by every Firmware
+//!   Management Protocol (FMP) instance and publish the table.
+//! - [component::EsrtManager]: a component that publishes the ESRT when the platform is ready to boot, and updates
+//!   it when FMP instances are installed later.
+//!
+//! ## Integration Example
+//!
+//! ```rust,ignore
+//! // ...
+//!
+//! Core::default()
+//!  // ...
+//!  .with_config(patina_esrt::config::EsrtConfig {
+//!      system_firmware: vec![SYSTEM_FIRMWARE_IMAGE_TYPE_ID],
+//!      ..Default::default()
+//!  })
+//!  .with_component(patina_esrt::component::EsrtManager)
+//!  .start()
+//!  .unwrap();
+//!
+//! // ...
+//! ```
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+#![cfg_attr(not(test), no_std)]
+#![feature(coverage_attribute)]
+
+extern crate alloc;
+
+pub mod component;
+pub mod config;
+pub mod esrt;
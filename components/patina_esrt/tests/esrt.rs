@@ -0,0 +1,213 @@
+//! Integration tests publishing the ESRT against the host DXE core boot services.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use core::{ffi::c_void, mem, ptr, slice};
+
+use patina::{
+    boot_services::{
+        BootServices, StandardBootServices,
+        event::{EventBuilder, EventType},
+        tpl::Tpl,
+    },
+    guids::SYSTEM_RESOURCE_TABLE,
+    uefi_protocol::firmware_management::{
+        self, EFI_FIRMWARE_IMAGE_DESCRIPTOR_VERSION, EfiFirmwareImageDescriptor, IMAGE_ATTRIBUTE_IMAGE_UPDATABLE,
+        LAST_ATTEMPT_STATUS_SUCCESS, Progress,
+    },
+};
+use patina_esrt::{
+    component::EsrtManager,
+    config::EsrtConfig,
+    esrt::{ESRT_FW_TYPE_DEVICE_FIRMWARE, ESRT_FW_TYPE_SYSTEM_FIRMWARE, EsrtEntry, EsrtHeader},
+};
+use patina_test::TestHarness;
+use r_efi::{efi, system::EVENT_GROUP_READY_TO_BOOT};
+
+const SYSTEM_FIRMWARE: efi::Guid = patina::guid!("6E2B9A41-3C7D-4F58-9B16-0D4E8A2C5F73");
+const DEVICE_FIRMWARE: efi::Guid = patina::guid!("C9D13E57-8A2F-4B60-A4E7-53F1B08D6C2A");
+
+/// A driver producing the Firmware Management Protocol for a single image.
+#[repr(C)]
+struct TestFmp {
+    protocol: firmware_management::Protocol,
+    image_type_id: efi::Guid,
+    version: u32,
+}
+
+impl TestFmp {
+    fn install(boot_services: &StandardBootServices, image_type_id: efi::Guid, version: u32) {
+        let fmp = Box::leak(Box::new(TestFmp {
+            protocol: firmware_management::Protocol {
+                get_image_info,
+                get_image,
+                set_image,
+                check_image,
+                get_package_info,
+                set_package_info,
+            },
+            image_type_id,
+            version,
+        }));
+        // SAFETY: The interface starts with the Firmware Management Protocol and is leaked.
+        unsafe {
+            boot_services
+                .install_protocol_interface_unchecked(
+                    None,
+                    &firmware_management::PROTOCOL_GUID,
+                    fmp as *mut TestFmp as *mut c_void,
+                )
+                .unwrap();
+        }
+    }
+}
+
+extern "efiapi" fn get_image_info(
+    this: *mut firmware_management::Protocol,
+    image_info_size: *mut usize,
+    image_info: *mut EfiFirmwareImageDescriptor,
+    descriptor_version: *mut u32,
+    descriptor_count: *mut u8,
+    descriptor_size: *mut usize,
+    package_version: *mut u32,
+    package_version_name: *mut *mut u16,
+) -> efi::Status {
+    // SAFETY: The protocol is the first field of a TestFmp.
+    let fmp = unsafe { &*(this as *const TestFmp) };
+    let size = mem::size_of::<EfiFirmwareImageDescriptor>();
+    // SAFETY: The pointers are provided by the caller.
+    unsafe {
+        descriptor_version.write(EFI_FIRMWARE_IMAGE_DESCRIPTOR_VERSION);
+        descriptor_count.write(1);
+        descriptor_size.write(size);
+        package_version.write(0xFFFFFFFF);
+        package_version_name.write(ptr::null_mut());
+        if image_info.is_null() || *image_info_size < size {
+            image_info_size.write(size);
+            return efi::Status::BUFFER_TOO_SMALL;
+        }
+        image_info.write(EfiFirmwareImageDescriptor {
+            image_index: 1,
+            image_type_id: fmp.image_type_id,
+            image_id: 1,
+            image_id_name: ptr::null_mut(),
+            version: fmp.version,
+            version_name: ptr::null_mut(),
+            size: 0x1000,
+            attributes_supported: IMAGE_ATTRIBUTE_IMAGE_UPDATABLE,
+            attributes_setting: IMAGE_ATTRIBUTE_IMAGE_UPDATABLE,
+            compatibilities: 0,
+            lowest_supported_image_version: 1,
+            last_attempt_version: fmp.version,
+            last_attempt_status: LAST_ATTEMPT_STATUS_SUCCESS,
+            hardware_instance: 0,
+            dependencies: ptr::null_mut(),
+        });
+    }
+    efi::Status::SUCCESS
+}
+
+extern "efiapi" fn get_image(
+    _: *mut firmware_management::Protocol,
+    _: u8,
+    _: *mut c_void,
+    _: *mut usize,
+) -> efi::Status {
+    efi::Status::UNSUPPORTED
+}
+
+extern "efiapi" fn set_image(
+    _: *mut firmware_management::Protocol,
+    _: u8,
+    _: *const c_void,
+    _: usize,
+    _: *const c_void,
+    _: Option<Progress>,
+    _: *mut *mut u16,
+) -> efi::Status {
+    efi::Status::UNSUPPORTED
+}
+
+extern "efiapi" fn check_image(
+    _: *mut firmware_management::Protocol,
+    _: u8,
+    _: *const c_void,
+    _: usize,
+    _: *mut u32,
+) -> efi::Status {
+    efi::Status::UNSUPPORTED
+}
+
+extern "efiapi" fn get_package_info(
+    _: *mut firmware_management::Protocol,
+    _: *mut u32,
+    _: *mut *mut u16,
+    _: *mut u32,
+    _: *mut u64,
+    _: *mut u64,
+) -> efi::Status {
+    efi::Status::UNSUPPORTED
+}
+
+extern "efiapi" fn set_package_info(
+    _: *mut firmware_management::Protocol,
+    _: *const c_void,
+    _: usize,
+    _: *const c_void,
+    _: u32,
+    _: *const u16,
+) -> efi::Status {
+    efi::Status::UNSUPPORTED
+}
+
+/// Returns the entries of the ESRT installed in the system table, if any.
+fn published_entries(harness: &TestHarness) -> Option<Vec<EsrtEntry>> {
+    let table = harness.configuration_table(&SYSTEM_RESOURCE_TABLE)? as *const EsrtHeader;
+    // SAFETY: The ESRT header is followed by its entries.
+    unsafe {
+        let count = (*table).fw_resource_count as usize;
+        Some(slice::from_raw_parts(table.add(1) as *const EsrtEntry, count).to_vec())
+    }
+}
+
+fn ready_to_boot(_event: efi::Event, _context: &mut ()) {}
+
+#[test]
+fn test_esrt_is_published_and_updated_with_late_fmp_instances() {
+    let config = EsrtConfig { system_firmware: vec![SYSTEM_FIRMWARE], ..Default::default() };
+    let mut harness = TestHarness::new().with_config(config).with_component(EsrtManager);
+    harness.run().unwrap();
+    assert!(harness.pending_components().is_empty());
+
+    let boot_services = harness.boot_services();
+    TestFmp::install(&boot_services, SYSTEM_FIRMWARE, 3);
+    // The ESRT is not published until the platform is ready to boot.
+    assert_eq!(published_entries(&harness), None);
+
+    // Signal ready to boot, as BDS does before starting a boot option.
+    let event = EventBuilder::new(boot_services.clone(), EventType::NOTIFY_SIGNAL, Tpl::CALLBACK)
+        .event_group(&EVENT_GROUP_READY_TO_BOOT)
+        .create(ready_to_boot, ())
+        .unwrap();
+    boot_services.signal_event(event.event()).unwrap();
+
+    let entries = published_entries(&harness).unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].fw_class, SYSTEM_FIRMWARE);
+    assert_eq!(entries[0].fw_type, ESRT_FW_TYPE_SYSTEM_FIRMWARE);
+    assert_eq!(entries[0].fw_version, 3);
+
+    // An FMP instance installed after the ESRT is published is added to it.
+    TestFmp::install(&boot_services, DEVICE_FIRMWARE, 7);
+    let entries = published_entries(&harness).unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[1].fw_class, DEVICE_FIRMWARE);
+    assert_eq!(entries[1].fw_type, ESRT_FW_TYPE_DEVICE_FIRMWARE);
+    assert_eq!(entries[1].fw_version, 7);
+
+    event.close().unwrap();
+}
@@ -0,0 +1,311 @@
+//! Patina Device Tree Component
+//!
+//! Parses the device tree blob of the platform and applies the fixups of the configuration to it. The fixups known at
+//! dispatch (the command line and the MAC addresses) are applied by the entry point, so that a device tree that does
+//! not match the configuration fails the component. The fixups depending on the state of the platform (the system
+//! memory and the random seed) are applied each time the platform signals that it is ready to boot, and the device
+//! tree is then validated and installed as a configuration table for the OS loader.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use alloc::{vec, vec::Vec};
+use core::{clone::Clone, convert::AsRef, ptr};
+use patina::{
+    boot_services::{
+        BootServices, StandardBootServices,
+        allocation::MemoryType,
+        event::{EventBuilder, EventType},
+        tpl::Tpl,
+    },
+    component::{IntoComponent, params::Config},
+    dxe_services::{DxeServices, GcdMemoryType, StandardDxeServices},
+    error::EfiError,
+    guids::DEVICE_TREE_TABLE,
+};
+use r_efi::{efi, protocols::rng, system::EVENT_GROUP_READY_TO_BOOT};
+
+use crate::{
+    config::DeviceTreeConfig,
+    fdt::{DeviceTree, FdtError},
+    fixup,
+};
+
+/// Device Tree Handoff Component.
+#[derive(IntoComponent)]
+pub struct DeviceTreeHandoff;
+
+/// Context of the ready to boot event of the [DeviceTreeHandoff] component.
+pub struct DeviceTreeContext<BB, DD> {
+    boot_services: BB,
+    dxe_services: DD,
+    config: DeviceTreeConfig,
+    tree: DeviceTree,
+    published: Option<(*mut u8, usize)>,
+}
+
+impl DeviceTreeHandoff {
+    /// Entry point of [`DeviceTreeHandoff`]
+    #[coverage(off)] // This is tested via the generic version, see _entry_point.
+    pub fn entry_point(
+        self,
+        config: Config<DeviceTreeConfig>,
+        boot_services: StandardBootServices,
+        dxe_services: StandardDxeServices,
+    ) -> Result<(), EfiError> {
+        self._entry_point(boot_services, dxe_services, DeviceTreeConfig::clone(&config))
+    }
+
+    /// Entry point that have generic parameter.
+    fn _entry_point<BB, B, DD, D>(
+        self,
+        boot_services: BB,
+        dxe_services: DD,
+        config: DeviceTreeConfig,
+    ) -> Result<(), EfiError>
+    where
+        BB: AsRef<B> + Clone + 'static,
+        B: BootServices + 'static,
+        DD: AsRef<D> + 'static,
+        D: DxeServices + 'static,
+    {
+        if config.blob.is_empty() {
+            log::info!("Device tree: no device tree blob, none is handed to the OS loader.");
+            return Ok(());
+        }
+
+        let tree = parse_and_fixup(&config)?;
+        let context =
+            DeviceTreeContext { boot_services: BB::clone(&boot_services), dxe_services, config, tree, published: None };
+        EventBuilder::new(boot_services, EventType::NOTIFY_SIGNAL, Tpl::CALLBACK)
+            .event_group(&EVENT_GROUP_READY_TO_BOOT)
+            .create(on_ready_to_boot::<BB, B, DD, D>, context)?;
+        Ok(())
+    }
+}
+
+impl From<FdtError> for EfiError {
+    fn from(_: FdtError) -> Self {
+        EfiError::InvalidParameter
+    }
+}
+
+/// Parses the device tree blob of the configuration, and applies the fixups known at dispatch.
+fn parse_and_fixup(config: &DeviceTreeConfig) -> Result<DeviceTree, EfiError> {
+    let mut tree = DeviceTree::parse(config.blob).inspect_err(|err| log::error!("Device tree: invalid blob: {err}"))?;
+    fixup::fixup_chosen(&mut tree, config.bootargs.as_deref(), None);
+    for mac_address in &config.mac_addresses {
+        fixup::fixup_mac_address(&mut tree, &mac_address.path, &mac_address.address)
+            .inspect_err(|err| log::error!("Device tree: cannot set the MAC address of {}: {err}", mac_address.path))?;
+    }
+    Ok(tree)
+}
+
+/// Returns the regions of system memory of the GCD, merging the adjacent descriptors.
+fn system_memory<D: DxeServices>(dxe_services: &D) -> Vec<(u64, u64)> {
+    let mut regions: Vec<(u64, u64)> = Vec::new();
+    let mut address = 0;
+    while let Ok(descriptor) = dxe_services.get_memory_space_descriptor(address) {
+        if descriptor.memory_type == GcdMemoryType::SystemMemory {
+            match regions.last_mut() {
+                Some((base, length)) if *base + *length == descriptor.base_address => *length += descriptor.length,
+                _ => regions.push((descriptor.base_address, descriptor.length)),
+            }
+        }
+        match descriptor.base_address.checked_add(descriptor.length) {
+            Some(next) if descriptor.length != 0 => address = next,
+            _ => break,
+        }
+    }
+    regions
+}
+
+/// Returns `size` bytes of entropy from the RNG protocol.
+fn rng_seed<B: BootServices>(boot_services: &B, size: usize) -> Result<Vec<u8>, EfiError> {
+    // SAFETY: The RNG protocol interface matches the protocol GUID.
+    let rng = unsafe { boot_services.locate_protocol::<rng::Protocol>(None) }?;
+    let mut seed = vec![0; size];
+    let status = (rng.get_rng)(rng, ptr::null_mut(), size, seed.as_mut_ptr());
+    if status.is_error() {
+        return Err(status.into());
+    }
+    Ok(seed)
+}
+
+/// Applies the fixups depending on the state of the platform to a copy of the device tree, and returns the resulting
+/// blob once validated.
+fn build_blob<B: BootServices, D: DxeServices>(
+    boot_services: &B,
+    dxe_services: &D,
+    config: &DeviceTreeConfig,
+    tree: &DeviceTree,
+) -> Result<Vec<u8>, EfiError> {
+    let mut tree = tree.clone();
+    if config.memory {
+        fixup::fixup_memory(&mut tree, &system_memory(dxe_services))?;
+    }
+    if config.rng_seed_size != 0 {
+        match rng_seed(boot_services, config.rng_seed_size) {
+            Ok(seed) => fixup::fixup_chosen(&mut tree, None, Some(&seed)),
+            Err(err) => log::warn!("Device tree: failed to generate a random seed, the OS loader gets none: {err:?}"),
+        }
+    }
+
+    let blob = tree.to_bytes();
+    // The blob is parsed back, so that the OS loader never gets a device tree the firmware cannot read itself.
+    DeviceTree::parse(&blob)?;
+    Ok(blob)
+}
+
+/// Clears a device tree blob installed by [on_ready_to_boot], which may hold a random seed, and frees it.
+fn release<B: BootServices>(boot_services: &B, (table, size): (*mut u8, usize)) {
+    // SAFETY: The table was allocated by on_ready_to_boot with room for `size` bytes.
+    unsafe { ptr::write_bytes(table, 0, size) };
+    let _ = boot_services.free_pool(table);
+}
+
+/// Installs `blob` as the device tree configuration table, and returns the table.
+fn install<B: BootServices>(boot_services: &B, blob: &[u8]) -> Result<(*mut u8, usize), EfiError> {
+    let table = boot_services.allocate_pool(MemoryType::ACPI_RECLAIM_MEMORY, blob.len())?;
+    // SAFETY: The table was allocated with room for the blob.
+    unsafe { ptr::copy_nonoverlapping(blob.as_ptr(), table, blob.len()) };
+
+    // SAFETY: The table is a device tree blob.
+    if let Err(status) = unsafe { boot_services.install_configuration_table_unchecked(&DEVICE_TREE_TABLE, table as _) }
+    {
+        release(boot_services, (table, blob.len()));
+        return Err(status.into());
+    }
+    Ok((table, blob.len()))
+}
+
+/// Ready to boot notify function, installing the device tree.
+fn on_ready_to_boot<BB, B, DD, D>(_event: efi::Event, context: &mut DeviceTreeContext<BB, DD>)
+where
+    BB: AsRef<B>,
+    B: BootServices,
+    DD: AsRef<D>,
+    D: DxeServices,
+{
+    let boot_services = context.boot_services.as_ref();
+    let result = build_blob(boot_services, context.dxe_services.as_ref(), &context.config, &context.tree)
+        .and_then(|blob| install(boot_services, &blob));
+    match result {
+        Ok(table) => {
+            log::info!("Device tree: installed a {} byte device tree.", table.1);
+            if let Some(previous) = context.published.replace(table) {
+                release(boot_services, previous);
+            }
+        }
+        Err(err) => log::error!("Device tree: failed to install the device tree, the OS loader gets none: {err:?}"),
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::{config::MacAddress, fdt::Node};
+    use alloc::{rc::Rc, string::ToString};
+    use patina::{
+        boot_services::{MockBootServices, event::EventContext},
+        dxe_services::{MemorySpaceDescriptor, MockDxeServices},
+    };
+    use std::boxed::Box;
+
+    type TestEventContext =
+        EventContext<Rc<MockBootServices>, DeviceTreeContext<Rc<MockBootServices>, Rc<MockDxeServices>>>;
+
+    fn sample_blob() -> &'static [u8] {
+        let mut root = Node::new("");
+        root.set_property("#address-cells", &2_u32.to_be_bytes());
+        root.set_property("#size-cells", &2_u32.to_be_bytes());
+        root.children.push(Node::new("ethernet@10000"));
+        DeviceTree { root, ..Default::default() }.to_bytes().leak()
+    }
+
+    fn descriptor(base_address: u64, length: u64, memory_type: GcdMemoryType) -> MemorySpaceDescriptor {
+        MemorySpaceDescriptor { base_address, length, memory_type, ..Default::default() }
+    }
+
+    #[test]
+    fn test_entry_point_registers_ready_to_boot_event() {
+        let mut boot_services = MockBootServices::new();
+        boot_services
+            .expect_create_event_ex::<Box<TestEventContext>>()
+            .once()
+            .withf_st(|event_type, notify_tpl, notify_function, _notify_context, event_group| {
+                assert_eq!(&EventType::NOTIFY_SIGNAL, event_type);
+                assert_eq!(&Tpl::CALLBACK, notify_tpl);
+                assert!(notify_function.is_some());
+                assert_eq!(&EVENT_GROUP_READY_TO_BOOT, event_group);
+                true
+            })
+            .return_const_st(Ok(1_usize as efi::Event));
+
+        let config = DeviceTreeConfig { blob: sample_blob(), ..Default::default() };
+        assert_eq!(
+            DeviceTreeHandoff._entry_point(Rc::new(boot_services), Rc::new(MockDxeServices::new()), config),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_fixups_known_at_dispatch() {
+        let config = DeviceTreeConfig {
+            blob: sample_blob(),
+            bootargs: Some("console=ttyS0".to_string()),
+            mac_addresses: vec![MacAddress { path: "/ethernet@10000".to_string(), address: [7; 6] }],
+            ..Default::default()
+        };
+        let tree = parse_and_fixup(&config).unwrap();
+        assert_eq!(tree.node("/chosen").unwrap().property_str("bootargs"), Some("console=ttyS0"));
+        assert_eq!(tree.node("/ethernet").unwrap().property("local-mac-address"), Some([7; 6].as_slice()));
+    }
+
+    #[test]
+    fn test_entry_point_without_or_with_invalid_blob() {
+        let boot_services = Rc::new(MockBootServices::new());
+        let dxe_services = Rc::new(MockDxeServices::new());
+        assert_eq!(
+            DeviceTreeHandoff._entry_point(boot_services.clone(), dxe_services.clone(), DeviceTreeConfig::default()),
+            Ok(())
+        );
+
+        let config = DeviceTreeConfig { blob: &[0; 64], ..Default::default() };
+        assert_eq!(
+            DeviceTreeHandoff._entry_point(boot_services.clone(), dxe_services.clone(), config),
+            Err(EfiError::InvalidParameter)
+        );
+
+        let config = DeviceTreeConfig {
+            blob: sample_blob(),
+            mac_addresses: vec![MacAddress { path: "/soc/ethernet".to_string(), address: [7; 6] }],
+            ..Default::default()
+        };
+        assert_eq!(
+            DeviceTreeHandoff._entry_point(boot_services, dxe_services, config),
+            Err(EfiError::InvalidParameter)
+        );
+    }
+
+    #[test]
+    fn test_system_memory_merges_adjacent_descriptors() {
+        let mut dxe_services = MockDxeServices::new();
+        dxe_services.expect_get_memory_space_descriptor().returning(|address| match address {
+            0 => Ok(descriptor(0, 0x1000, GcdMemoryType::Reserved)),
+            0x1000 => Ok(descriptor(0x1000, 0x1000, GcdMemoryType::SystemMemory)),
+            0x2000 => Ok(descriptor(0x2000, 0x2000, GcdMemoryType::SystemMemory)),
+            0x4000 => Ok(descriptor(0x4000, 0x1000, GcdMemoryType::MemoryMappedIo)),
+            0x5000 => Ok(descriptor(0x5000, 0x3000, GcdMemoryType::SystemMemory)),
+            _ => Err(efi::Status::NOT_FOUND),
+        });
+        assert_eq!(system_memory(&dxe_services), vec![(0x1000, 0x3000), (0x5000, 0x3000)]);
+    }
+}
@@ -0,0 +1,67 @@
+//! Patina Device Tree and Handoff Policy Component Configuration
+//!
+//! The configuration can be set statically with `.with_config()` or produced dynamically during boot. If no
+//! configuration is provided, the platform has no device tree and none is handed to the OS loader, and the handoff
+//! policy only hands off the ACPI tables.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use alloc::{string::String, vec::Vec};
+
+/// The MAC address of a network device of the device tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MacAddress {
+    /// The path of the network device node, e.g. `/soc/ethernet@10000`.
+    pub path: String,
+    /// The MAC address of the network device.
+    pub address: [u8; 6],
+}
+
+/// The configuration for the Patina Device Tree component.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceTreeConfig {
+    /// The device tree blob of the platform, or an empty slice if the platform has no device tree.
+    pub blob: &'static [u8],
+    /// Replaces the memory nodes of the device tree with the system memory of the GCD.
+    pub memory: bool,
+    /// The command line of the OS, set as the `bootargs` property of the `/chosen` node.
+    pub bootargs: Option<String>,
+    /// The MAC addresses of the network devices, set as their `local-mac-address` property.
+    pub mac_addresses: Vec<MacAddress>,
+    /// The number of bytes of the random seed set as the `rng-seed` property of the `/chosen` node, generated from the
+    /// RNG protocol, or 0 for no seed.
+    pub rng_seed_size: usize,
+}
+
+impl Default for DeviceTreeConfig {
+    fn default() -> Self {
+        Self { blob: &[], memory: true, bootargs: None, mac_addresses: Vec::new(), rng_seed_size: 0 }
+    }
+}
+
+/// The description of the hardware handed off to the OS loader.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum HardwareDescription {
+    /// The ACPI tables only.
+    #[default]
+    Acpi,
+    /// The device tree only.
+    DeviceTree,
+    /// Both the ACPI tables and the device tree, leaving the choice to the OS.
+    Both,
+}
+
+/// The configuration for the Patina Handoff Policy component.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct HandoffPolicyConfig {
+    /// The description of the hardware handed off to the OS loader.
+    pub description: HardwareDescription,
+    /// Lets the [HARDWARE_DESCRIPTION_VARIABLE_NAME](crate::policy::HARDWARE_DESCRIPTION_VARIABLE_NAME) variable
+    /// override [description](Self::description), e.g. to boot an OS that only supports one of them during bring-up.
+    pub variable_override: bool,
+}
@@ -0,0 +1,536 @@
+//! Flattened Device Tree
+//!
+//! Parses a flattened device tree blob (FDT, or DTB) into a tree of nodes that can be modified, and serializes it back
+//! into a blob. The format is described in the [Devicetree Specification](https://www.devicetree.org/specifications/),
+//! chapter 5: a header, followed by the memory reservation block, the structure block and the strings block, with all
+//! integers in big endian.
+//!
+//! Blobs of version 16 and 17 are parsed, and blobs are serialized as version 17.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt;
+
+/// The magic number at the start of a device tree blob.
+pub const FDT_MAGIC: u32 = 0xD00D_FEED;
+
+/// The version of the blobs serialized by [DeviceTree::to_bytes].
+const FDT_VERSION: u32 = 17;
+/// The oldest version the serialized blobs are compatible with.
+const FDT_LAST_COMPATIBLE_VERSION: u32 = 16;
+/// The size of the header of a device tree blob.
+const HEADER_SIZE: usize = 40;
+
+const FDT_BEGIN_NODE: u32 = 0x1;
+const FDT_END_NODE: u32 = 0x2;
+const FDT_PROP: u32 = 0x3;
+const FDT_NOP: u32 = 0x4;
+const FDT_END: u32 = 0x9;
+
+/// The maximum depth of the nodes, so that a corrupted blob does not exhaust the stack.
+const MAX_DEPTH: usize = 64;
+
+/// An error parsing or modifying a device tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FdtError {
+    /// The blob does not start with the FDT magic number, or its header is invalid.
+    InvalidHeader,
+    /// The version of the blob is not supported.
+    UnsupportedVersion,
+    /// A block or a value is outside of the blob.
+    Truncated,
+    /// The structure block is malformed.
+    InvalidStructure,
+    /// A node or property name is not a valid string.
+    InvalidName,
+    /// The node does not exist.
+    NodeNotFound,
+    /// The value does not fit in the number of cells of the device tree.
+    ValueTooLarge,
+}
+
+impl fmt::Display for FdtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidHeader => write!(f, "invalid FDT header"),
+            Self::UnsupportedVersion => write!(f, "unsupported FDT version"),
+            Self::Truncated => write!(f, "truncated FDT"),
+            Self::InvalidStructure => write!(f, "invalid FDT structure block"),
+            Self::InvalidName => write!(f, "invalid FDT node or property name"),
+            Self::NodeNotFound => write!(f, "FDT node not found"),
+            Self::ValueTooLarge => write!(f, "value too large for the FDT cells"),
+        }
+    }
+}
+
+impl core::error::Error for FdtError {}
+
+/// A region of memory reserved from the OS, described in the memory reservation block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryReservation {
+    /// The physical address of the region.
+    pub address: u64,
+    /// The size of the region, in bytes.
+    pub size: u64,
+}
+
+/// A property of a node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Property {
+    /// The name of the property.
+    pub name: String,
+    /// The value of the property.
+    pub value: Vec<u8>,
+}
+
+/// A node of a device tree.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Node {
+    /// The name of the node, including its unit address, e.g. `memory@80000000`. The root node has an empty name.
+    pub name: String,
+    /// The properties of the node.
+    pub properties: Vec<Property>,
+    /// The children of the node.
+    pub children: Vec<Node>,
+}
+
+impl Node {
+    /// Creates a node without properties or children.
+    pub fn new(name: &str) -> Self {
+        Self { name: name.to_string(), ..Default::default() }
+    }
+
+    /// Returns the value of the property `name`, if any.
+    pub fn property(&self, name: &str) -> Option<&[u8]> {
+        self.properties.iter().find(|property| property.name == name).map(|property| property.value.as_slice())
+    }
+
+    /// Returns the value of the property `name` as a string, if it is a NUL terminated string.
+    pub fn property_str(&self, name: &str) -> Option<&str> {
+        let value = self.property(name)?.strip_suffix(&[0])?;
+        core::str::from_utf8(value).ok()
+    }
+
+    /// Returns the value of the property `name` as a 32 bits integer, if it is one.
+    pub fn property_u32(&self, name: &str) -> Option<u32> {
+        Some(u32::from_be_bytes(self.property(name)?.try_into().ok()?))
+    }
+
+    /// Sets the value of the property `name`, adding the property if needed.
+    pub fn set_property(&mut self, name: &str, value: &[u8]) {
+        match self.properties.iter_mut().find(|property| property.name == name) {
+            Some(property) => property.value = value.to_vec(),
+            None => self.properties.push(Property { name: name.to_string(), value: value.to_vec() }),
+        }
+    }
+
+    /// Sets the value of the property `name` to a NUL terminated string, adding the property if needed.
+    pub fn set_property_str(&mut self, name: &str, value: &str) {
+        let mut bytes = value.as_bytes().to_vec();
+        bytes.push(0);
+        self.set_property(name, &bytes);
+    }
+
+    /// Removes the property `name`, if any.
+    pub fn remove_property(&mut self, name: &str) {
+        self.properties.retain(|property| property.name != name);
+    }
+
+    /// Returns the child `name`. If `name` has no unit address, a child with the same name and any unit address
+    /// matches as well.
+    pub fn child(&self, name: &str) -> Option<&Node> {
+        let index = self.child_index(name)?;
+        Some(&self.children[index])
+    }
+
+    /// Returns the child `name`, as with [Node::child].
+    pub fn child_mut(&mut self, name: &str) -> Option<&mut Node> {
+        let index = self.child_index(name)?;
+        Some(&mut self.children[index])
+    }
+
+    /// Returns the child `name`, as with [Node::child], adding an empty child named `name` if there is none.
+    pub fn child_or_insert(&mut self, name: &str) -> &mut Node {
+        let index = match self.child_index(name) {
+            Some(index) => index,
+            None => {
+                self.children.push(Node::new(name));
+                self.children.len() - 1
+            }
+        };
+        &mut self.children[index]
+    }
+
+    fn child_index(&self, name: &str) -> Option<usize> {
+        self.children.iter().position(|child| child.name == name).or_else(|| {
+            if name.contains('@') {
+                return None;
+            }
+            self.children.iter().position(|child| child.name.split('@').next() == Some(name))
+        })
+    }
+}
+
+/// A device tree, parsed from a blob with [DeviceTree::parse] and serialized with [DeviceTree::to_bytes].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DeviceTree {
+    /// The root node.
+    pub root: Node,
+    /// The regions of memory reserved from the OS.
+    pub reservations: Vec<MemoryReservation>,
+    /// The physical ID of the boot CPU.
+    pub boot_cpuid_phys: u32,
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, FdtError> {
+    data.get(offset..offset.checked_add(4).ok_or(FdtError::Truncated)?)
+        .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .ok_or(FdtError::Truncated)
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64, FdtError> {
+    Ok(((read_u32(data, offset)? as u64) << 32) | read_u32(data, offset + 4)? as u64)
+}
+
+/// Returns the block of `size` bytes at `offset` of the blob.
+fn block(blob: &[u8], offset: u32, size: u32) -> Result<&[u8], FdtError> {
+    let end = (offset as usize).checked_add(size as usize).ok_or(FdtError::Truncated)?;
+    blob.get(offset as usize..end).ok_or(FdtError::Truncated)
+}
+
+/// Returns the NUL terminated string at `offset`, and the offset following it.
+fn read_str(data: &[u8], offset: usize) -> Result<(&str, usize), FdtError> {
+    let bytes = data.get(offset..).ok_or(FdtError::Truncated)?;
+    let len = bytes.iter().position(|&byte| byte == 0).ok_or(FdtError::InvalidName)?;
+    let name = core::str::from_utf8(&bytes[..len]).map_err(|_| FdtError::InvalidName)?;
+    Ok((name, offset + len + 1))
+}
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+/// The parser of the structure block.
+struct StructureParser<'a> {
+    structure: &'a [u8],
+    strings: &'a [u8],
+    offset: usize,
+}
+
+impl StructureParser<'_> {
+    /// Returns the next token, skipping the NOP tokens.
+    fn next_token(&mut self) -> Result<u32, FdtError> {
+        loop {
+            let token = read_u32(self.structure, self.offset)?;
+            self.offset += 4;
+            if token != FDT_NOP {
+                return Ok(token);
+            }
+        }
+    }
+
+    /// Parses a node, once its begin token has been read.
+    fn parse_node(&mut self, depth: usize) -> Result<Node, FdtError> {
+        if depth > MAX_DEPTH {
+            return Err(FdtError::InvalidStructure);
+        }
+        let (name, end) = read_str(self.structure, self.offset)?;
+        let mut node = Node::new(name);
+        self.offset = align4(end);
+
+        loop {
+            match self.next_token()? {
+                FDT_PROP => {
+                    let len = read_u32(self.structure, self.offset)? as usize;
+                    let name_offset = read_u32(self.structure, self.offset + 4)? as usize;
+                    let start = self.offset + 8;
+                    let value = self.structure.get(start..start + len).ok_or(FdtError::Truncated)?;
+                    let (name, _) = read_str(self.strings, name_offset)?;
+                    node.properties.push(Property { name: name.to_string(), value: value.to_vec() });
+                    self.offset = align4(start + len);
+                }
+                FDT_BEGIN_NODE => node.children.push(self.parse_node(depth + 1)?),
+                FDT_END_NODE => return Ok(node),
+                _ => return Err(FdtError::InvalidStructure),
+            }
+        }
+    }
+}
+
+/// The serializer of the structure and strings blocks.
+#[derive(Default)]
+struct StructureWriter {
+    structure: Vec<u8>,
+    strings: Vec<u8>,
+}
+
+impl StructureWriter {
+    fn write_u32(&mut self, value: u32) {
+        self.structure.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn pad(&mut self) {
+        self.structure.resize(align4(self.structure.len()), 0);
+    }
+
+    /// Returns the offset of `name` in the strings block, adding it if needed.
+    fn string_offset(&mut self, name: &str) -> u32 {
+        let mut offset = 0;
+        while offset < self.strings.len() {
+            let end = offset + self.strings[offset..].iter().position(|&byte| byte == 0).unwrap_or(0);
+            if &self.strings[offset..end] == name.as_bytes() {
+                return offset as u32;
+            }
+            offset = end + 1;
+        }
+        self.strings.extend_from_slice(name.as_bytes());
+        self.strings.push(0);
+        offset as u32
+    }
+
+    fn write_node(&mut self, node: &Node) {
+        self.write_u32(FDT_BEGIN_NODE);
+        self.structure.extend_from_slice(node.name.as_bytes());
+        self.structure.push(0);
+        self.pad();
+        for property in &node.properties {
+            let name_offset = self.string_offset(&property.name);
+            self.write_u32(FDT_PROP);
+            self.write_u32(property.value.len() as u32);
+            self.write_u32(name_offset);
+            self.structure.extend_from_slice(&property.value);
+            self.pad();
+        }
+        for child in &node.children {
+            self.write_node(child);
+        }
+        self.write_u32(FDT_END_NODE);
+    }
+}
+
+impl DeviceTree {
+    /// Parses a device tree blob.
+    pub fn parse(blob: &[u8]) -> Result<Self, FdtError> {
+        if blob.len() < HEADER_SIZE || read_u32(blob, 0)? != FDT_MAGIC {
+            return Err(FdtError::InvalidHeader);
+        }
+        let header = |field: usize| read_u32(blob, field * 4);
+        let total_size = header(1)? as usize;
+        let blob = blob.get(..total_size).ok_or(FdtError::Truncated)?;
+        if total_size < HEADER_SIZE {
+            return Err(FdtError::InvalidHeader);
+        }
+        let (version, last_compatible_version) = (header(5)?, header(6)?);
+        if version < FDT_LAST_COMPATIBLE_VERSION || last_compatible_version > FDT_VERSION {
+            return Err(FdtError::UnsupportedVersion);
+        }
+
+        let structure = block(blob, header(2)?, header(9)?)?;
+        let strings = block(blob, header(3)?, header(8)?)?;
+
+        let mut reservations = Vec::new();
+        let mut offset = header(4)? as usize;
+        loop {
+            let reservation = MemoryReservation { address: read_u64(blob, offset)?, size: read_u64(blob, offset + 8)? };
+            if reservation == (MemoryReservation { address: 0, size: 0 }) {
+                break;
+            }
+            reservations.push(reservation);
+            offset += 16;
+        }
+
+        let mut parser = StructureParser { structure, strings, offset: 0 };
+        if parser.next_token()? != FDT_BEGIN_NODE {
+            return Err(FdtError::InvalidStructure);
+        }
+        let root = parser.parse_node(0)?;
+        if parser.next_token()? != FDT_END {
+            return Err(FdtError::InvalidStructure);
+        }
+
+        Ok(Self { root, reservations, boot_cpuid_phys: header(7)? })
+    }
+
+    /// Serializes the device tree into a blob.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = StructureWriter::default();
+        writer.write_node(&self.root);
+        writer.write_u32(FDT_END);
+
+        let mut reservations = Vec::new();
+        for reservation in self.reservations.iter().chain([&MemoryReservation { address: 0, size: 0 }]) {
+            reservations.extend_from_slice(&reservation.address.to_be_bytes());
+            reservations.extend_from_slice(&reservation.size.to_be_bytes());
+        }
+
+        // The memory reservation block is 8 byte aligned, right after the header.
+        let reservations_offset = HEADER_SIZE.next_multiple_of(8);
+        let structure_offset = reservations_offset + reservations.len();
+        let strings_offset = structure_offset + writer.structure.len();
+        let total_size = strings_offset + writer.strings.len();
+
+        let mut blob = Vec::with_capacity(total_size);
+        for value in [
+            FDT_MAGIC,
+            total_size as u32,
+            structure_offset as u32,
+            strings_offset as u32,
+            reservations_offset as u32,
+            FDT_VERSION,
+            FDT_LAST_COMPATIBLE_VERSION,
+            self.boot_cpuid_phys,
+            writer.strings.len() as u32,
+            writer.structure.len() as u32,
+        ] {
+            blob.extend_from_slice(&value.to_be_bytes());
+        }
+        blob.resize(reservations_offset, 0);
+        blob.extend_from_slice(&reservations);
+        blob.extend_from_slice(&writer.structure);
+        blob.extend_from_slice(&writer.strings);
+        blob
+    }
+
+    /// Returns the node at `path`, e.g. `/chosen` or `/soc/ethernet@10000`. The root node is at `/`.
+    pub fn node(&self, path: &str) -> Option<&Node> {
+        path.split('/').filter(|name| !name.is_empty()).try_fold(&self.root, |node, name| node.child(name))
+    }
+
+    /// Returns the node at `path`, as with [DeviceTree::node].
+    pub fn node_mut(&mut self, path: &str) -> Option<&mut Node> {
+        path.split('/').filter(|name| !name.is_empty()).try_fold(&mut self.root, |node, name| node.child_mut(name))
+    }
+
+    /// Encodes `values` as cells of the root node, with `#address-cells` cells for the addresses and `#size-cells`
+    /// cells for the sizes, e.g. for the `reg` property of a child of the root node.
+    pub fn encode_reg(&self, regions: &[(u64, u64)]) -> Result<Vec<u8>, FdtError> {
+        // The default number of cells, if the properties are missing.
+        let address_cells = self.root.property_u32("#address-cells").unwrap_or(2);
+        let size_cells = self.root.property_u32("#size-cells").unwrap_or(1);
+
+        let mut reg = Vec::new();
+        for &(address, size) in regions {
+            for (value, cells) in [(address, address_cells), (size, size_cells)] {
+                match cells {
+                    1 => {
+                        reg.extend_from_slice(&u32::try_from(value).map_err(|_| FdtError::ValueTooLarge)?.to_be_bytes())
+                    }
+                    2 => reg.extend_from_slice(&value.to_be_bytes()),
+                    _ => return Err(FdtError::ValueTooLarge),
+                }
+            }
+        }
+        Ok(reg)
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::vec;
+
+    fn sample_tree() -> DeviceTree {
+        let mut root = Node::new("");
+        root.set_property("#address-cells", &2_u32.to_be_bytes());
+        root.set_property("#size-cells", &2_u32.to_be_bytes());
+        root.set_property_str("compatible", "patina,test");
+        let mut soc = Node::new("soc");
+        let mut ethernet = Node::new("ethernet@10000");
+        ethernet.set_property_str("compatible", "patina,eth");
+        ethernet.set_property("local-mac-address", &[0; 6]);
+        soc.children.push(ethernet);
+        root.children.push(soc);
+        root.children.push(Node::new("chosen"));
+        DeviceTree {
+            root,
+            reservations: vec![MemoryReservation { address: 0x8000_0000, size: 0x1000 }],
+            boot_cpuid_phys: 1,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let tree = sample_tree();
+        let blob = tree.to_bytes();
+        assert_eq!(read_u32(&blob, 0), Ok(FDT_MAGIC));
+        assert_eq!(read_u32(&blob, 4), Ok(blob.len() as u32));
+        assert_eq!(DeviceTree::parse(&blob), Ok(tree));
+
+        // Property names are shared in the strings block.
+        let strings_offset = read_u32(&blob, 12).unwrap() as usize;
+        let strings = &blob[strings_offset..];
+        assert_eq!(strings.windows(b"compatible\0".len()).filter(|window| *window == b"compatible\0").count(), 1);
+    }
+
+    #[test]
+    fn test_node_lookup_and_properties() {
+        let mut tree = sample_tree();
+        assert_eq!(tree.node("/").map(|node| node.name.as_str()), Some(""));
+        assert_eq!(tree.node("/soc/ethernet").and_then(|node| node.property_str("compatible")), Some("patina,eth"));
+        assert!(tree.node("/soc/ethernet@10000").is_some());
+        assert!(tree.node("/soc/ethernet@20000").is_none());
+        assert!(tree.node("/missing").is_none());
+
+        let chosen = tree.node_mut("/chosen").unwrap();
+        chosen.set_property_str("bootargs", "console=ttyAMA0");
+        chosen.set_property_str("bootargs", "console=ttyS0");
+        assert_eq!(chosen.properties.len(), 1);
+        assert_eq!(chosen.property_str("bootargs"), Some("console=ttyS0"));
+        chosen.remove_property("bootargs");
+        assert_eq!(chosen.property("bootargs"), None);
+
+        tree.root.child_or_insert("memory@0").set_property_str("device_type", "memory");
+        assert_eq!(tree.root.child_or_insert("memory").property_str("device_type"), Some("memory"));
+        assert_eq!(tree.root.children.len(), 3);
+    }
+
+    #[test]
+    fn test_encode_reg() {
+        let mut tree = sample_tree();
+        assert_eq!(
+            tree.encode_reg(&[(0x1_0000_0000, 0x2000)]),
+            Ok([0x1_0000_0000_u64.to_be_bytes(), 0x2000_u64.to_be_bytes()].concat())
+        );
+
+        tree.root.set_property("#address-cells", &1_u32.to_be_bytes());
+        tree.root.set_property("#size-cells", &1_u32.to_be_bytes());
+        assert_eq!(
+            tree.encode_reg(&[(0x4000, 0x2000)]),
+            Ok([0x4000_u32.to_be_bytes(), 0x2000_u32.to_be_bytes()].concat())
+        );
+        assert_eq!(tree.encode_reg(&[(0x1_0000_0000, 0x2000)]), Err(FdtError::ValueTooLarge));
+    }
+
+    #[test]
+    fn test_parse_rejects_malformed_blobs() {
+        let blob = sample_tree().to_bytes();
+        assert_eq!(DeviceTree::parse(&blob[..HEADER_SIZE - 1]), Err(FdtError::InvalidHeader));
+        assert_eq!(DeviceTree::parse(&blob[..blob.len() - 1]), Err(FdtError::Truncated));
+
+        let mut bad_magic = blob.clone();
+        bad_magic[0] = 0;
+        assert_eq!(DeviceTree::parse(&bad_magic), Err(FdtError::InvalidHeader));
+
+        let mut bad_version = blob.clone();
+        bad_version[20..24].copy_from_slice(&15_u32.to_be_bytes());
+        assert_eq!(DeviceTree::parse(&bad_version), Err(FdtError::UnsupportedVersion));
+
+        // Replace the END token with an END_NODE token.
+        let mut bad_structure = blob.clone();
+        let strings_offset = read_u32(&blob, 12).unwrap() as usize;
+        bad_structure[strings_offset - 4..strings_offset].copy_from_slice(&FDT_END_NODE.to_be_bytes());
+        assert_eq!(DeviceTree::parse(&bad_structure), Err(FdtError::InvalidStructure));
+    }
+}
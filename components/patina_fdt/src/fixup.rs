@@ -0,0 +1,119 @@
+//! Device Tree Fixups
+//!
+//! The device tree of a platform is usually built with the firmware, and some of its content is only known at boot:
+//! the system memory discovered by the firmware, the command line of the OS, or the MAC addresses provisioned in the
+//! platform configuration. These fixups update the device tree with it before it is handed to the OS loader.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use alloc::{format, vec::Vec};
+
+use crate::fdt::{DeviceTree, FdtError, Node};
+
+/// Replaces the memory nodes of the device tree with one node per region of system memory, given as
+/// `(base address, length)` pairs.
+///
+/// The `reg` property of the nodes is encoded with the `#address-cells` and `#size-cells` of the root node.
+pub fn fixup_memory(tree: &mut DeviceTree, regions: &[(u64, u64)]) -> Result<(), FdtError> {
+    let mut nodes = Vec::with_capacity(regions.len());
+    for &region in regions {
+        let mut node = Node::new(&format!("memory@{:x}", region.0));
+        node.set_property_str("device_type", "memory");
+        node.set_property("reg", &tree.encode_reg(&[region])?);
+        nodes.push(node);
+    }
+
+    tree.root.children.retain(|node| node.property_str("device_type") != Some("memory"));
+    tree.root.children.extend(nodes);
+    Ok(())
+}
+
+/// Sets the properties of the `/chosen` node, adding the node if needed: the command line of the OS (`bootargs`), if
+/// any, and the random seed of the OS (`rng-seed`), if any.
+pub fn fixup_chosen(tree: &mut DeviceTree, bootargs: Option<&str>, rng_seed: Option<&[u8]>) {
+    let chosen = tree.root.child_or_insert("chosen");
+    if let Some(bootargs) = bootargs {
+        chosen.set_property_str("bootargs", bootargs);
+    }
+    if let Some(rng_seed) = rng_seed {
+        chosen.set_property("rng-seed", rng_seed);
+    }
+}
+
+/// Sets the MAC address (`local-mac-address`) of the network device node at `path`.
+pub fn fixup_mac_address(tree: &mut DeviceTree, path: &str, address: &[u8; 6]) -> Result<(), FdtError> {
+    tree.node_mut(path).ok_or(FdtError::NodeNotFound)?.set_property("local-mac-address", address);
+    Ok(())
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+
+    fn sample_tree() -> DeviceTree {
+        let mut root = Node::new("");
+        root.set_property("#address-cells", &2_u32.to_be_bytes());
+        root.set_property("#size-cells", &1_u32.to_be_bytes());
+        let mut memory = Node::new("memory@40000000");
+        memory.set_property_str("device_type", "memory");
+        memory.set_property("reg", &[0; 12]);
+        root.children.push(memory);
+        let mut ethernet = Node::new("ethernet@10000");
+        ethernet.set_property_str("compatible", "patina,eth");
+        root.children.push(ethernet);
+        DeviceTree { root, ..Default::default() }
+    }
+
+    #[test]
+    fn test_fixup_memory() {
+        let mut tree = sample_tree();
+        fixup_memory(&mut tree, &[(0x8000_0000, 0x4000_0000), (0x1_0000_0000, 0x1000_0000)]).unwrap();
+
+        assert!(tree.node("/memory@40000000").is_none());
+        assert!(tree.node("/ethernet").is_some());
+        let memory = tree.node("/memory@80000000").unwrap();
+        assert_eq!(memory.property_str("device_type"), Some("memory"));
+        assert_eq!(
+            memory.property("reg"),
+            Some([0_u32, 0x8000_0000, 0x4000_0000].map(u32::to_be_bytes).concat().as_slice())
+        );
+        let memory = tree.node("/memory@100000000").unwrap();
+        assert_eq!(memory.property("reg"), Some([1_u32, 0, 0x1000_0000].map(u32::to_be_bytes).concat().as_slice()));
+
+        // A region larger than the size cells is rejected, and the tree is left untouched.
+        let mut tree = sample_tree();
+        assert_eq!(fixup_memory(&mut tree, &[(0, 0x1_0000_0000)]), Err(FdtError::ValueTooLarge));
+        assert_eq!(tree, sample_tree());
+    }
+
+    #[test]
+    fn test_fixup_chosen() {
+        let mut tree = sample_tree();
+        fixup_chosen(&mut tree, None, None);
+        assert_eq!(tree.node("/chosen").map(|chosen| chosen.properties.len()), Some(0));
+
+        fixup_chosen(&mut tree, Some("console=ttyS0"), Some([0xA5; 8].as_slice()));
+        let chosen = tree.node("/chosen").unwrap();
+        assert_eq!(chosen.property_str("bootargs"), Some("console=ttyS0"));
+        assert_eq!(chosen.property("rng-seed"), Some([0xA5; 8].as_slice()));
+        assert_eq!(tree.root.children.iter().filter(|node| node.name == "chosen").count(), 1);
+    }
+
+    #[test]
+    fn test_fixup_mac_address() {
+        let mut tree = sample_tree();
+        let address = [0x02, 0x00, 0x5E, 0x10, 0x00, 0x01];
+        fixup_mac_address(&mut tree, "/ethernet@10000", &address).unwrap();
+        assert_eq!(tree.node("/ethernet").unwrap().property("local-mac-address"), Some(address.as_slice()));
+
+        assert_eq!(fixup_mac_address(&mut tree, "/soc/ethernet", &address), Err(FdtError::NodeNotFound));
+    }
+}
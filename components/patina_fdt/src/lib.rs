@@ -0,0 +1,49 @@
+//! Device tree (FDT) fixup and handoff to the OS loader for Patina platforms.
+//!
+//! Platforms describing their hardware with a device tree build the device tree blob with the firmware, while part of
+//! its content is only known at boot. This crate provides:
+//!
+//! - [fdt]: a parser and serializer of flattened device tree blobs, with the nodes and properties of the device tree
+//!   exposed for modification.
+//! - [fixup]: the fixups updating the device tree with the system memory, the `/chosen` node and the MAC addresses.
+//! - [component::DeviceTreeHandoff]: a component that applies the fixups to the device tree blob of the platform, and
+//!   installs it as a configuration table each time the platform is ready to boot.
+//! - [policy::HandoffPolicy]: a component that decides whether the ACPI tables, the device tree or both are handed off
+//!   to the OS loader, for platforms that can provide both.
+//!
+//! ## Integration Example
+//!
+//! ```rust,ignore
+//! // ...
+//!
+//! Core::default()
+//!  // ...
+//!  .with_config(patina_fdt::config::DeviceTreeConfig {
+//!      blob: include_bytes!("platform.dtb"),
+//!      bootargs: Some("console=ttyAMA0".to_string()),
+//!      ..Default::default()
+//!  })
+//!  .with_component(patina_fdt::component::DeviceTreeHandoff)
+//!  .start()
+//!  .unwrap();
+//!
+//! // ...
+//! ```
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+#![cfg_attr(not(test), no_std)]
+#![feature(coverage_attribute)]
+
+extern crate alloc;
+
+pub mod component;
+pub mod config;
+pub mod fdt;
+pub mod fixup;
+pub mod policy;
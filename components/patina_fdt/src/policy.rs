@@ -0,0 +1,312 @@
+//! Patina Handoff Policy Component
+//!
+//! Platforms that can describe their hardware both with ACPI tables and with a device tree must not hand off both by
+//! accident: an OS finding both picks one of them on its own terms (Linux prefers the device tree on Arm unless told
+//! otherwise), so a platform validated with one of them may boot with the other. This component decides which of them
+//! is exposed to the OS loader, from the [HandoffPolicyConfig] and optionally the [HARDWARE_DESCRIPTION_VARIABLE_NAME]
+//! variable.
+//!
+//! The ACPI tables are published by the ACPI table driver, and the device tree by the
+//! [DeviceTreeHandoff](crate::component::DeviceTreeHandoff) component, both possibly at ready to boot and again each
+//! time they change. Rather than relying on the order of these notifications, the component resolves the policy when
+//! the platform signals ready to boot, removes the configuration tables of the excluded description, and from then on
+//! removes them again each time they are installed, as installing a configuration table signals its GUID as an event
+//! group.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use alloc::vec::Vec;
+use core::{clone::Clone, convert::AsRef, ptr};
+use patina::{
+    Guid, Ucs2Str,
+    boot_services::{
+        BootServices, StandardBootServices,
+        event::{EventBuilder, EventType},
+        tpl::Tpl,
+    },
+    component::{IntoComponent, params::Config},
+    error::EfiError,
+    guids::{ACPI_10_TABLE, ACPI_20_TABLE, DEVICE_TREE_TABLE},
+    runtime_services::{RuntimeServices, StandardRuntimeServices},
+    ucs2,
+};
+use r_efi::{efi, system::EVENT_GROUP_READY_TO_BOOT};
+
+use crate::config::{HandoffPolicyConfig, HardwareDescription};
+
+/// The vendor GUID of the [HARDWARE_DESCRIPTION_VARIABLE_NAME] variable.
+pub const HARDWARE_DESCRIPTION_VARIABLE_GUID: efi::Guid = patina::guid!("6E3B4F0A-92C1-4D7B-A8E5-3F1C2D9B7A64");
+
+/// The name of the variable overriding the description of the hardware handed off to the OS loader, if the
+/// [HandoffPolicyConfig] allows it. The variable holds `acpi`, `devicetree` or `both` as an ASCII string.
+pub const HARDWARE_DESCRIPTION_VARIABLE_NAME: &Ucs2Str = ucs2!("PatinaHardwareDescription");
+
+/// The configuration tables of the ACPI tables.
+static ACPI_TABLES: [&efi::Guid; 2] = [&ACPI_20_TABLE, &ACPI_10_TABLE];
+
+/// The configuration tables of the device tree.
+static DEVICE_TREE_TABLES: [&efi::Guid; 1] = [&DEVICE_TREE_TABLE];
+
+/// Handoff Policy Component.
+#[derive(IntoComponent)]
+pub struct HandoffPolicy;
+
+/// Context of the ready to boot event of the [HandoffPolicy] component.
+pub struct HandoffPolicyContext<BB, RR> {
+    boot_services: BB,
+    runtime_services: RR,
+    config: HandoffPolicyConfig,
+    description: Option<HardwareDescription>,
+}
+
+/// Context of the events signaled when an excluded configuration table is installed.
+pub struct ExcludedTableContext<BB> {
+    boot_services: BB,
+    guid: &'static efi::Guid,
+}
+
+impl HandoffPolicy {
+    /// Entry point of [`HandoffPolicy`]
+    #[coverage(off)] // This is tested via the generic version, see _entry_point.
+    pub fn entry_point(
+        self,
+        config: Config<HandoffPolicyConfig>,
+        boot_services: StandardBootServices,
+        runtime_services: StandardRuntimeServices,
+    ) -> Result<(), EfiError> {
+        self._entry_point(boot_services, runtime_services, *config)
+    }
+
+    /// Entry point that have generic parameter.
+    fn _entry_point<BB, B, RR, R>(
+        self,
+        boot_services: BB,
+        runtime_services: RR,
+        config: HandoffPolicyConfig,
+    ) -> Result<(), EfiError>
+    where
+        BB: AsRef<B> + Clone + 'static,
+        B: BootServices + 'static,
+        RR: AsRef<R> + 'static,
+        R: RuntimeServices + 'static,
+    {
+        if config.description == HardwareDescription::Both && !config.variable_override {
+            log::info!("Handoff policy: both the ACPI tables and the device tree are handed off.");
+            return Ok(());
+        }
+
+        let context = HandoffPolicyContext {
+            boot_services: BB::clone(&boot_services),
+            runtime_services,
+            config,
+            description: None,
+        };
+        EventBuilder::new(boot_services, EventType::NOTIFY_SIGNAL, Tpl::CALLBACK)
+            .event_group(&EVENT_GROUP_READY_TO_BOOT)
+            .create(on_ready_to_boot::<BB, B, RR, R>, context)?;
+        Ok(())
+    }
+}
+
+/// Returns the configuration tables that must not be handed off with `description`.
+fn excluded_tables(description: HardwareDescription) -> &'static [&'static efi::Guid] {
+    match description {
+        HardwareDescription::Acpi => &DEVICE_TREE_TABLES,
+        HardwareDescription::DeviceTree => &ACPI_TABLES,
+        HardwareDescription::Both => &[],
+    }
+}
+
+/// Returns the description of the hardware handed off to the OS loader, from the variable if the configuration allows
+/// it and the variable is valid, or from the configuration.
+fn resolve_description<R: RuntimeServices>(config: &HandoffPolicyConfig, runtime_services: &R) -> HardwareDescription {
+    if !config.variable_override {
+        return config.description;
+    }
+    let value = match runtime_services.get_variable::<Vec<u8>>(
+        HARDWARE_DESCRIPTION_VARIABLE_NAME.as_slice_with_nul(),
+        &HARDWARE_DESCRIPTION_VARIABLE_GUID,
+        None,
+    ) {
+        Ok((value, _attributes)) => value,
+        Err(efi::Status::NOT_FOUND) => return config.description,
+        Err(status) => {
+            log::error!("Handoff policy: failed to read the hardware description variable: {status:#x?}");
+            return config.description;
+        }
+    };
+
+    // The value may be NUL terminated when written as a C string.
+    match value.strip_suffix(&[0]).unwrap_or(&value) {
+        b"acpi" => HardwareDescription::Acpi,
+        b"devicetree" => HardwareDescription::DeviceTree,
+        b"both" => HardwareDescription::Both,
+        _ => {
+            log::error!("Handoff policy: invalid hardware description variable {value:?}, using the configuration.");
+            config.description
+        }
+    }
+}
+
+/// Removes the configuration table `guid`, if it is installed.
+fn remove_table<B: BootServices>(boot_services: &B, guid: &efi::Guid) {
+    // SAFETY: A null table removes the configuration table.
+    match unsafe { boot_services.install_configuration_table_unchecked(guid, ptr::null_mut()) } {
+        Ok(()) => log::info!(
+            "Handoff policy: removed the {:?} configuration table, excluded by the policy.",
+            Guid::from_ref(guid)
+        ),
+        Err(efi::Status::NOT_FOUND) => (),
+        Err(status) => {
+            log::error!(
+                "Handoff policy: failed to remove the {:?} configuration table: {status:#x?}",
+                Guid::from_ref(guid)
+            )
+        }
+    }
+}
+
+/// Ready to boot notify function, enforcing the policy.
+fn on_ready_to_boot<BB, B, RR, R>(_event: efi::Event, context: &mut HandoffPolicyContext<BB, RR>)
+where
+    BB: AsRef<B> + Clone + 'static,
+    B: BootServices + 'static,
+    RR: AsRef<R>,
+    R: RuntimeServices,
+{
+    // The tables installed after the first ready to boot are removed by the events registered then.
+    if context.description.is_some() {
+        return;
+    }
+    let description = resolve_description(&context.config, context.runtime_services.as_ref());
+    context.description = Some(description);
+    log::info!("Handoff policy: {description:?} handed off to the OS loader.");
+
+    for &guid in excluded_tables(description) {
+        let excluded = ExcludedTableContext { boot_services: BB::clone(&context.boot_services), guid };
+        if let Err(err) = EventBuilder::new(BB::clone(&context.boot_services), EventType::NOTIFY_SIGNAL, Tpl::CALLBACK)
+            .event_group(guid)
+            .create(on_excluded_table_installed::<BB, B>, excluded)
+        {
+            log::error!("Handoff policy: failed to watch the {:?} configuration table: {err:?}", Guid::from_ref(guid));
+        }
+        remove_table(context.boot_services.as_ref(), guid);
+    }
+}
+
+/// Notify function of the events signaled when an excluded configuration table is installed, removing it.
+fn on_excluded_table_installed<BB, B>(_event: efi::Event, context: &mut ExcludedTableContext<BB>)
+where
+    BB: AsRef<B>,
+    B: BootServices,
+{
+    remove_table(context.boot_services.as_ref(), context.guid);
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use alloc::rc::Rc;
+    use patina::{
+        boot_services::{MockBootServices, event::EventContext},
+        runtime_services::MockRuntimeServices,
+    };
+    use std::boxed::Box;
+
+    type TestEventContext =
+        EventContext<Rc<MockBootServices>, HandoffPolicyContext<Rc<MockBootServices>, Rc<MockRuntimeServices>>>;
+    type TestExcludedTableContext = EventContext<Rc<MockBootServices>, ExcludedTableContext<Rc<MockBootServices>>>;
+
+    fn runtime_services(value: &'static [u8]) -> MockRuntimeServices {
+        let mut runtime_services = MockRuntimeServices::new();
+        runtime_services.expect_get_variable::<Vec<u8>>().once().returning(|name, namespace, _| {
+            assert_eq!(name, HARDWARE_DESCRIPTION_VARIABLE_NAME.as_slice_with_nul());
+            assert_eq!(namespace, &HARDWARE_DESCRIPTION_VARIABLE_GUID);
+            Ok((value.to_vec(), 0))
+        });
+        runtime_services
+    }
+
+    #[test]
+    fn test_entry_point_registers_ready_to_boot_event() {
+        let mut boot_services = MockBootServices::new();
+        boot_services
+            .expect_create_event_ex::<Box<TestEventContext>>()
+            .once()
+            .withf_st(|event_type, notify_tpl, notify_function, _notify_context, event_group| {
+                assert_eq!(&EventType::NOTIFY_SIGNAL, event_type);
+                assert_eq!(&Tpl::CALLBACK, notify_tpl);
+                assert!(notify_function.is_some());
+                assert_eq!(&EVENT_GROUP_READY_TO_BOOT, event_group);
+                true
+            })
+            .return_const_st(Ok(1_usize as efi::Event));
+
+        let runtime_services = Rc::new(MockRuntimeServices::new());
+        assert_eq!(
+            HandoffPolicy._entry_point(
+                Rc::new(boot_services),
+                runtime_services.clone(),
+                HandoffPolicyConfig::default()
+            ),
+            Ok(())
+        );
+
+        // Nothing is enforced when both descriptions are handed off.
+        let config = HandoffPolicyConfig { description: HardwareDescription::Both, variable_override: false };
+        assert_eq!(HandoffPolicy._entry_point(Rc::new(MockBootServices::new()), runtime_services, config), Ok(()));
+    }
+
+    #[test]
+    fn test_resolve_description() {
+        let config = HandoffPolicyConfig { description: HardwareDescription::DeviceTree, variable_override: false };
+        assert_eq!(resolve_description(&config, &MockRuntimeServices::new()), HardwareDescription::DeviceTree);
+
+        let config = HandoffPolicyConfig { variable_override: true, ..config };
+        assert_eq!(resolve_description(&config, &runtime_services(b"acpi")), HardwareDescription::Acpi);
+        assert_eq!(resolve_description(&config, &runtime_services(b"both\0")), HardwareDescription::Both);
+        assert_eq!(resolve_description(&config, &runtime_services(b"dt")), HardwareDescription::DeviceTree);
+
+        let mut runtime_services = MockRuntimeServices::new();
+        runtime_services.expect_get_variable::<Vec<u8>>().once().returning(|_, _, _| Err(efi::Status::NOT_FOUND));
+        assert_eq!(resolve_description(&config, &runtime_services), HardwareDescription::DeviceTree);
+    }
+
+    #[test]
+    fn test_excluded_tables_are_removed_and_watched() {
+        let mut boot_services = MockBootServices::new();
+        boot_services
+            .expect_create_event_ex::<Box<TestExcludedTableContext>>()
+            .times(2)
+            .withf_st(|_, _, notify_function, _, event_group| {
+                assert!(notify_function.is_some());
+                assert!(ACPI_TABLES.contains(event_group));
+                true
+            })
+            .return_const_st(Ok(2_usize as efi::Event));
+        boot_services.expect_install_configuration_table_unchecked().times(2).returning(|guid, table| {
+            assert!(table.is_null());
+            if guid == &ACPI_20_TABLE { Ok(()) } else { Err(efi::Status::NOT_FOUND) }
+        });
+
+        let mut context = HandoffPolicyContext {
+            boot_services: Rc::new(boot_services),
+            runtime_services: Rc::new(runtime_services(b"devicetree")),
+            config: HandoffPolicyConfig { description: HardwareDescription::Acpi, variable_override: true },
+            description: None,
+        };
+        on_ready_to_boot(1_usize as efi::Event, &mut context);
+        assert_eq!(context.description, Some(HardwareDescription::DeviceTree));
+
+        // The policy is enforced once, the events registered above handle the tables installed later.
+        on_ready_to_boot(1_usize as efi::Event, &mut context);
+    }
+}
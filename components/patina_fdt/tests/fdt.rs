@@ -0,0 +1,103 @@
+//! Integration tests handing off the device tree against the host DXE core boot and DXE services.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use core::slice;
+
+use patina::{
+    boot_services::{
+        BootServices,
+        event::{EventBuilder, EventType},
+        tpl::Tpl,
+    },
+    dxe_services::{DxeServices, GcdMemoryType},
+    guids::DEVICE_TREE_TABLE,
+};
+use patina_fdt::{
+    component::DeviceTreeHandoff,
+    config::{DeviceTreeConfig, MacAddress},
+    fdt::{DeviceTree, Node},
+};
+use patina_test::TestHarness;
+use r_efi::{efi, system::EVENT_GROUP_READY_TO_BOOT};
+
+const MAC_ADDRESS: [u8; 6] = [0x02, 0x00, 0x5E, 0x10, 0x00, 0x01];
+
+/// Returns the device tree installed in the system table, if any.
+fn installed_tree(harness: &TestHarness) -> Option<DeviceTree> {
+    let table = harness.configuration_table(&DEVICE_TREE_TABLE)? as *const u8;
+    // SAFETY: The table is a device tree blob, which starts with its header holding the size of the blob at offset 4.
+    let blob = unsafe {
+        let size = u32::from_be_bytes(slice::from_raw_parts(table.add(4), 4).try_into().unwrap());
+        slice::from_raw_parts(table, size as usize)
+    };
+    DeviceTree::parse(blob).ok()
+}
+
+/// Returns a device tree blob, as built with the firmware of a platform.
+fn platform_blob() -> &'static [u8] {
+    let mut root = Node::new("");
+    root.set_property("#address-cells", &2_u32.to_be_bytes());
+    root.set_property("#size-cells", &2_u32.to_be_bytes());
+    root.set_property_str("compatible", "patina,host");
+    let mut memory = Node::new("memory@80000000");
+    memory.set_property_str("device_type", "memory");
+    memory.set_property("reg", &[0x8000_0000_u64.to_be_bytes(), 0x1000_u64.to_be_bytes()].concat());
+    root.children.push(memory);
+    let mut soc = Node::new("soc");
+    soc.children.push(Node::new("ethernet@10000"));
+    root.children.push(soc);
+    DeviceTree { root, ..Default::default() }.to_bytes().leak()
+}
+
+fn ready_to_boot(_event: efi::Event, _context: &mut ()) {}
+
+#[test]
+fn test_the_fixed_up_device_tree_is_installed_at_ready_to_boot() {
+    let config = DeviceTreeConfig {
+        blob: platform_blob(),
+        bootargs: Some("console=ttyS0 quiet".to_string()),
+        mac_addresses: vec![MacAddress { path: "/soc/ethernet@10000".to_string(), address: MAC_ADDRESS }],
+        ..Default::default()
+    };
+    let mut harness = TestHarness::new().with_config(config).with_component(DeviceTreeHandoff);
+    harness.run().unwrap();
+    assert!(harness.pending_components().is_empty());
+
+    // The device tree is not installed until the platform is ready to boot.
+    assert_eq!(installed_tree(&harness), None);
+
+    // Signal ready to boot, as BDS does before starting a boot option.
+    let boot_services = harness.boot_services();
+    let event = EventBuilder::new(boot_services.clone(), EventType::NOTIFY_SIGNAL, Tpl::CALLBACK)
+        .event_group(&EVENT_GROUP_READY_TO_BOOT)
+        .create(ready_to_boot, ())
+        .unwrap();
+    boot_services.signal_event(event.event()).unwrap();
+    event.close().unwrap();
+
+    let tree = installed_tree(&harness).unwrap();
+    assert_eq!(tree.node("/").unwrap().property_str("compatible"), Some("patina,host"));
+    assert_eq!(tree.node("/chosen").unwrap().property_str("bootargs"), Some("console=ttyS0 quiet"));
+    assert_eq!(tree.node("/soc/ethernet").unwrap().property("local-mac-address"), Some(MAC_ADDRESS.as_slice()));
+
+    // The memory nodes of the platform are replaced with the system memory of the GCD.
+    assert!(tree.node("/memory@80000000").is_none());
+    let memory: Vec<&Node> =
+        tree.root.children.iter().filter(|node| node.property_str("device_type") == Some("memory")).collect();
+    assert!(!memory.is_empty());
+    let dxe_services = harness.dxe_services();
+    for node in memory {
+        let reg = node.property("reg").unwrap();
+        let base_address = u64::from_be_bytes(reg[..8].try_into().unwrap());
+        let length = u64::from_be_bytes(reg[8..].try_into().unwrap());
+        assert_eq!(node.name, format!("memory@{base_address:x}"));
+        let descriptor = dxe_services.get_memory_space_descriptor(base_address).unwrap();
+        assert_eq!(descriptor.memory_type, GcdMemoryType::SystemMemory);
+        assert!(length >= descriptor.length);
+    }
+}
@@ -0,0 +1,63 @@
+//! Integration tests enforcing the handoff policy against the host DXE core boot services.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use core::ffi::c_void;
+
+use patina::{
+    boot_services::{
+        BootServices,
+        event::{EventBuilder, EventType},
+        tpl::Tpl,
+    },
+    guids::{ACPI_20_TABLE, DEVICE_TREE_TABLE},
+};
+use patina_fdt::{
+    component::DeviceTreeHandoff,
+    config::{DeviceTreeConfig, HandoffPolicyConfig, HardwareDescription},
+    fdt::{DeviceTree, Node},
+    policy::HandoffPolicy,
+};
+use patina_test::TestHarness;
+use r_efi::{efi, system::EVENT_GROUP_READY_TO_BOOT};
+
+fn ready_to_boot(_event: efi::Event, _context: &mut ()) {}
+
+#[test]
+fn test_excluded_acpi_tables_are_not_handed_off() {
+    let blob = DeviceTree { root: Node::new(""), ..Default::default() }.to_bytes().leak();
+    let mut harness = TestHarness::new()
+        .with_config(DeviceTreeConfig { blob, ..Default::default() })
+        .with_config(HandoffPolicyConfig { description: HardwareDescription::DeviceTree, variable_override: false })
+        .with_component(DeviceTreeHandoff)
+        .with_component(HandoffPolicy);
+    harness.run().unwrap();
+    assert!(harness.pending_components().is_empty());
+
+    // Stand in for the ACPI table driver, which publishes the RSDP as soon as a table is installed.
+    let boot_services = harness.boot_services();
+    let rsdp = Box::leak(Box::new([0_u8; 36])) as *mut [u8; 36] as *mut c_void;
+    // SAFETY: The table is leaked, and is not read by the host environment.
+    unsafe { boot_services.install_configuration_table_unchecked(&ACPI_20_TABLE, rsdp).unwrap() };
+    assert!(harness.configuration_table(&ACPI_20_TABLE).is_some());
+
+    // Signal ready to boot, as BDS does before starting a boot option.
+    let event = EventBuilder::new(boot_services.clone(), EventType::NOTIFY_SIGNAL, Tpl::CALLBACK)
+        .event_group(&EVENT_GROUP_READY_TO_BOOT)
+        .create(ready_to_boot, ())
+        .unwrap();
+    boot_services.signal_event(event.event()).unwrap();
+    event.close().unwrap();
+
+    assert!(harness.configuration_table(&DEVICE_TREE_TABLE).is_some());
+    assert!(harness.configuration_table(&ACPI_20_TABLE).is_none());
+
+    // An ACPI table published later, e.g. by a ready to boot notification of another driver, is removed as well.
+    // SAFETY: The table is leaked, and is not read by the host environment.
+    unsafe { boot_services.install_configuration_table_unchecked(&ACPI_20_TABLE, rsdp).unwrap() };
+    assert!(harness.configuration_table(&ACPI_20_TABLE).is_none());
+}
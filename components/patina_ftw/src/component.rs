@@ -0,0 +1,493 @@
+//! Patina FTW Manager Component
+//!
+//! Waits for the Firmware Volume Block (FVB) of the NV storage, opens the journal on it, then installs the EDK II
+//! Fault Tolerant Write protocol. Opening the journal completes or abandons an update interrupted by a power loss, so
+//! the NV storage is consistent before anything reads it.
+//!
+//! The [FaultTolerantWrite] service is produced by the entry point, and fails with
+//! [EfiError::NotReady] until the journal is opened.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use alloc::boxed::Box;
+use core::{
+    ffi::c_void,
+    ptr::{self, NonNull},
+    sync::atomic::{AtomicPtr, Ordering},
+};
+use patina::{
+    boot_services::{
+        BootServices, StandardBootServices,
+        event::{EventBuilder, EventType},
+        protocol_handler::HandleSearchType,
+        tpl::Tpl,
+    },
+    component::{
+        IntoComponent,
+        params::{Commands, Config},
+        service::IntoService,
+    },
+    error::{EfiError, Result},
+    firmware_storage::{FirmwareStorage, ftw::FaultTolerantStorage, fvb::FvbStorage},
+    tpl_mutex::TplMutex,
+};
+use patina_pi::protocols::firmware_volume_block;
+use r_efi::efi;
+
+use crate::{config::FtwConfig, journal::Journal, protocol, service::FaultTolerantWrite};
+
+/// FTW Manager Component.
+#[derive(IntoComponent, Default)]
+pub struct FaultTolerantWriteManager;
+
+/// The state of the component, the protocol is the first field so the instance can be found from the protocol pointer.
+#[repr(C)]
+struct FtwInstance<S: FirmwareStorage = FvbStorage, B: BootServices + 'static = StandardBootServices> {
+    protocol: protocol::Protocol,
+    /// The handle of the FVB of the NV storage, null until the journal is opened.
+    fvb_handle: AtomicPtr<c_void>,
+    journal: TplMutex<'static, Journal<S>, B>,
+}
+
+impl<S: FirmwareStorage, B: BootServices + 'static> FtwInstance<S, B> {
+    /// Creates an instance waiting for the journal to be opened.
+    fn new(boot_services: &'static B) -> Self {
+        Self {
+            protocol: protocol::Protocol {
+                get_max_block_size: get_max_block_size::<S, B>,
+                allocate: allocate::<S, B>,
+                write: write::<S, B>,
+                restart: restart::<S, B>,
+                abort: abort::<S, B>,
+                get_last_write: get_last_write::<S, B>,
+            },
+            fvb_handle: AtomicPtr::new(ptr::null_mut()),
+            journal: TplMutex::new(boot_services, Tpl::NOTIFY, Journal::new()),
+        }
+    }
+}
+
+/// The [FaultTolerantWrite] service over the journal of the component.
+#[derive(IntoService)]
+#[service(dyn FaultTolerantWrite)]
+struct FtwService {
+    instance: &'static FtwInstance,
+}
+
+impl FaultTolerantWrite for FtwService {
+    fn max_write_size(&self) -> Result<usize> {
+        self.instance.journal.lock().max_write_size()
+    }
+
+    fn read(&self, offset: usize, buffer: &mut [u8]) -> Result<()> {
+        self.instance.journal.lock().read(offset, buffer)
+    }
+
+    fn write(&self, offset: usize, data: &[u8]) -> Result<()> {
+        self.instance.journal.lock().write(offset, data)
+    }
+}
+
+/// Context of the FVB notify event.
+struct FvbNotifyContext {
+    boot_services: &'static StandardBootServices,
+    config: FtwConfig,
+    instance: *mut FtwInstance,
+}
+
+impl FaultTolerantWriteManager {
+    /// Entry point of [`FaultTolerantWriteManager`]
+    #[coverage(off)] // The component only wires boot services, the journal is tested on its own.
+    fn entry_point(
+        self,
+        config: Config<FtwConfig>,
+        boot_services: StandardBootServices,
+        mut commands: Commands,
+    ) -> Result<()> {
+        if config.nv_storage_base == 0 {
+            log::error!("FTW: the base address of the NV storage is not configured.");
+            return Err(EfiError::InvalidParameter);
+        }
+
+        let boot_services: &'static StandardBootServices = Box::leak(Box::new(boot_services));
+        let instance = Box::into_raw(Box::new(FtwInstance::new(boot_services)));
+        // SAFETY: the instance is leaked, and only ever accessed through shared references and its mutex.
+        commands.add_service(FtwService { instance: unsafe { &*instance } });
+
+        let context = FvbNotifyContext { boot_services, config: *config, instance };
+        let event = EventBuilder::new(boot_services.clone(), EventType::NOTIFY_SIGNAL, Tpl::CALLBACK)
+            .create::<StandardBootServices, _>(on_fvb_installed, context)?;
+        boot_services.register_protocol_notify(&firmware_volume_block::PROTOCOL_GUID, event.event())?;
+        // The FVB of the NV storage may already be installed.
+        boot_services.signal_event(event.event())?;
+        Ok(())
+    }
+}
+
+/// Notify function of the event signaled when an FVB is installed, opening the journal on the NV storage.
+#[coverage(off)]
+fn on_fvb_installed(_event: efi::Event, context: &mut FvbNotifyContext) {
+    // SAFETY: the instance is leaked by the entry point.
+    let instance = unsafe { &*context.instance };
+    if instance.journal.lock().is_open() {
+        return;
+    }
+    let Some((handle, fvb)) = find_fvb(context.boot_services, context.config.nv_storage_base) else {
+        return;
+    };
+
+    // SAFETY: the FVB of the NV storage is never uninstalled.
+    let storage = unsafe { FvbStorage::new(fvb) }
+        .and_then(|storage| FaultTolerantStorage::with_spare_blocks(storage, context.config.spare_blocks));
+    let storage = match storage {
+        Ok(storage) => storage,
+        Err(err) => {
+            log::error!("FTW: failed to open the journal on the NV storage: {err:?}");
+            return;
+        }
+    };
+    instance.fvb_handle.store(handle, Ordering::SeqCst);
+    instance.journal.lock().open(storage);
+    log::info!("FTW: opened the journal on the NV storage at {:#x}.", context.config.nv_storage_base);
+
+    // SAFETY: the protocol is the first field of the leaked instance.
+    let installed = unsafe {
+        context.boot_services.install_protocol_interface_unchecked(
+            None,
+            &protocol::PROTOCOL_GUID,
+            context.instance as *mut c_void,
+        )
+    };
+    if let Err(status) = installed {
+        log::error!("FTW: failed to install the Fault Tolerant Write protocol: {status:#x?}");
+    }
+}
+
+/// Returns the handle and protocol of the FVB at `base`.
+#[coverage(off)]
+fn find_fvb(
+    boot_services: &StandardBootServices,
+    base: u64,
+) -> Option<(efi::Handle, NonNull<firmware_volume_block::Protocol>)> {
+    let handles =
+        boot_services.locate_handle_buffer(HandleSearchType::ByProtocol(&firmware_volume_block::PROTOCOL_GUID)).ok()?;
+    handles.iter().find_map(|&handle| {
+        // SAFETY: the handle supports the FVB protocol.
+        let fvb = unsafe { boot_services.handle_protocol_unchecked(handle, &firmware_volume_block::PROTOCOL_GUID) };
+        let fvb = NonNull::new(fvb.ok()? as *mut firmware_volume_block::Protocol)?;
+        let mut address = 0;
+        // SAFETY: the protocol pointer comes from the protocol database.
+        let status = (unsafe { fvb.as_ref() }.get_physical_address)(fvb.as_ptr(), &mut address);
+        (!status.is_error() && address == base).then_some((handle, fvb))
+    })
+}
+
+/// Returns the instance installing `this`.
+fn instance<'a, S: FirmwareStorage, B: BootServices + 'static>(
+    this: *mut protocol::Protocol,
+) -> Option<&'a FtwInstance<S, B>> {
+    // SAFETY: the protocol is only installed as the first field of a leaked instance.
+    unsafe { (this as *const FtwInstance<S, B>).as_ref() }
+}
+
+fn to_status(result: Result<()>) -> efi::Status {
+    result.map_or_else(efi::Status::from, |_| efi::Status::SUCCESS)
+}
+
+extern "efiapi" fn get_max_block_size<S: FirmwareStorage, B: BootServices + 'static>(
+    this: *mut protocol::Protocol,
+    block_size: *mut usize,
+) -> efi::Status {
+    let Some(instance) = instance::<S, B>(this) else {
+        return efi::Status::INVALID_PARAMETER;
+    };
+    if block_size.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    match instance.journal.lock().max_write_size() {
+        Ok(size) => {
+            // SAFETY: block_size is not null, and the caller guarantees it is valid.
+            unsafe { block_size.write(size) };
+            efi::Status::SUCCESS
+        }
+        Err(err) => err.into(),
+    }
+}
+
+extern "efiapi" fn allocate<S: FirmwareStorage, B: BootServices + 'static>(
+    this: *mut protocol::Protocol,
+    caller_id: *mut efi::Guid,
+    private_data_size: usize,
+    number_of_writes: usize,
+) -> efi::Status {
+    let Some(instance) = instance::<S, B>(this) else {
+        return efi::Status::INVALID_PARAMETER;
+    };
+    // SAFETY: the caller guarantees caller_id is valid if not null.
+    let Some(caller_id) = (unsafe { caller_id.as_ref() }) else {
+        return efi::Status::INVALID_PARAMETER;
+    };
+    to_status(instance.journal.lock().allocate(*caller_id, private_data_size, number_of_writes))
+}
+
+extern "efiapi" fn write<S: FirmwareStorage, B: BootServices + 'static>(
+    this: *mut protocol::Protocol,
+    lba: efi::Lba,
+    offset: usize,
+    length: usize,
+    private_data: *mut c_void,
+    fvb_handle: efi::Handle,
+    buffer: *mut c_void,
+) -> efi::Status {
+    let Some(instance) = instance::<S, B>(this) else {
+        return efi::Status::INVALID_PARAMETER;
+    };
+    if buffer.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    if fvb_handle != instance.fvb_handle.load(Ordering::SeqCst) {
+        log::error!("FTW: writes are only supported on the FVB of the NV storage.");
+        return efi::Status::UNSUPPORTED;
+    }
+
+    let mut journal = instance.journal.lock();
+    // SAFETY: the caller guarantees buffer holds length bytes, and private_data the private data size given to
+    // Allocate() if not null.
+    let data = unsafe { core::slice::from_raw_parts(buffer as *const u8, length) };
+    let private_data = match private_data.is_null() {
+        true => &[][..],
+        false => unsafe { core::slice::from_raw_parts(private_data as *const u8, journal.private_data_size()) },
+    };
+    to_status(journal.write_allocated(lba, offset, data, private_data))
+}
+
+/// Writes complete before `Write()` returns, and an interrupted one is completed or abandoned when the journal is
+/// opened, so there is never a started write to restart. Succeeds while writes of the last allocation are left.
+extern "efiapi" fn restart<S: FirmwareStorage, B: BootServices + 'static>(
+    this: *mut protocol::Protocol,
+    fvb_handle: efi::Handle,
+) -> efi::Status {
+    let Some(instance) = instance::<S, B>(this) else {
+        return efi::Status::INVALID_PARAMETER;
+    };
+    if fvb_handle != instance.fvb_handle.load(Ordering::SeqCst) {
+        return efi::Status::UNSUPPORTED;
+    }
+    to_status(instance.journal.lock().restart())
+}
+
+extern "efiapi" fn abort<S: FirmwareStorage, B: BootServices + 'static>(this: *mut protocol::Protocol) -> efi::Status {
+    let Some(instance) = instance::<S, B>(this) else {
+        return efi::Status::INVALID_PARAMETER;
+    };
+    to_status(instance.journal.lock().abort())
+}
+
+#[allow(clippy::too_many_arguments)]
+extern "efiapi" fn get_last_write<S: FirmwareStorage, B: BootServices + 'static>(
+    this: *mut protocol::Protocol,
+    caller_id: *mut efi::Guid,
+    lba: *mut efi::Lba,
+    offset: *mut usize,
+    length: *mut usize,
+    private_data_size: *mut usize,
+    private_data: *mut c_void,
+    complete: *mut efi::Boolean,
+) -> efi::Status {
+    let Some(instance) = instance::<S, B>(this) else {
+        return efi::Status::INVALID_PARAMETER;
+    };
+    if [caller_id as *mut c_void, lba as _, offset as _, length as _, private_data_size as _, complete as _]
+        .iter()
+        .any(|pointer| pointer.is_null())
+    {
+        return efi::Status::INVALID_PARAMETER;
+    }
+
+    let last_write = match instance.journal.lock().last_write() {
+        Ok(Some(last_write)) => last_write,
+        Ok(None) => return efi::Status::NOT_FOUND,
+        Err(err) => return err.into(),
+    };
+    // SAFETY: the pointers are not null, and the caller guarantees they are valid, with private_data_size bytes at
+    // private_data.
+    unsafe {
+        caller_id.write(last_write.caller_id);
+        lba.write(last_write.lba);
+        offset.write(last_write.offset);
+        length.write(last_write.length);
+        complete.write(last_write.complete.into());
+
+        let available = private_data_size.read();
+        private_data_size.write(last_write.private_data.len());
+        if available < last_write.private_data.len() {
+            return efi::Status::BUFFER_TOO_SMALL;
+        }
+        if !private_data.is_null() {
+            ptr::copy_nonoverlapping(
+                last_write.private_data.as_ptr(),
+                private_data as *mut u8,
+                last_write.private_data.len(),
+            );
+        }
+    }
+    efi::Status::SUCCESS
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use patina::{boot_services::MockBootServices, firmware_storage::RamStorage};
+
+    const BLOCK_SIZE: usize = 0x100;
+    const FVB_HANDLE: efi::Handle = 0x1000 as efi::Handle;
+
+    type TestInstance = FtwInstance<RamStorage, MockBootServices>;
+
+    fn test_instance(open: bool) -> &'static TestInstance {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_raise_tpl().return_const(Tpl::APPLICATION);
+        boot_services.expect_restore_tpl().return_const(());
+        let instance: &'static TestInstance = Box::leak(Box::new(FtwInstance::new(Box::leak(Box::new(boot_services)))));
+        if open {
+            let storage = FaultTolerantStorage::with_spare_blocks(RamStorage::new(BLOCK_SIZE, 8), 2).unwrap();
+            instance.fvb_handle.store(FVB_HANDLE, Ordering::SeqCst);
+            instance.journal.lock().open(storage);
+        }
+        instance
+    }
+
+    fn this(instance: &TestInstance) -> *mut protocol::Protocol {
+        &instance.protocol as *const protocol::Protocol as *mut _
+    }
+
+    #[test]
+    fn get_max_block_size_should_report_the_spare_size() {
+        let instance = test_instance(true);
+        let mut block_size = 0;
+        assert_eq!((instance.protocol.get_max_block_size)(this(instance), &mut block_size), efi::Status::SUCCESS);
+        assert_eq!(block_size, 2 * BLOCK_SIZE);
+        assert_eq!(
+            (instance.protocol.get_max_block_size)(this(instance), ptr::null_mut()),
+            efi::Status::INVALID_PARAMETER
+        );
+        assert_eq!(
+            (instance.protocol.get_max_block_size)(ptr::null_mut(), &mut block_size),
+            efi::Status::INVALID_PARAMETER
+        );
+
+        let closed = test_instance(false);
+        assert_eq!((closed.protocol.get_max_block_size)(this(closed), &mut block_size), efi::Status::NOT_READY);
+    }
+
+    #[test]
+    fn allocated_writes_should_be_reported_by_get_last_write() {
+        let instance = test_instance(true);
+        let protocol = &instance.protocol;
+        let mut caller_id = efi::Guid::from_fields(1, 2, 3, 4, 5, &[6; 6]);
+        let (mut lba, mut offset, mut length, mut complete) = (0, 0, 0, efi::Boolean::FALSE);
+        let mut private_data_size = 0;
+        let mut private_data = [0u8; 2];
+        let mut get_last_write = |caller_id: &mut efi::Guid, private_data_size: &mut usize| {
+            (protocol.get_last_write)(
+                this(instance),
+                caller_id,
+                &mut lba,
+                &mut offset,
+                &mut length,
+                private_data_size,
+                private_data.as_mut_ptr() as *mut c_void,
+                &mut complete,
+            )
+        };
+        assert_eq!(get_last_write(&mut caller_id, &mut private_data_size), efi::Status::NOT_FOUND);
+
+        assert_eq!((protocol.allocate)(this(instance), ptr::null_mut(), 2, 1), efi::Status::INVALID_PARAMETER);
+        assert_eq!((protocol.allocate)(this(instance), &mut caller_id, 2, 1), efi::Status::SUCCESS);
+        assert_eq!((protocol.allocate)(this(instance), &mut caller_id, 2, 1), efi::Status::ACCESS_DENIED);
+
+        let mut data = [0x5Au8; 4];
+        let mut record = [0xAu8, 0xB];
+        let buffer = data.as_mut_ptr() as *mut c_void;
+        let record = record.as_mut_ptr() as *mut c_void;
+        let other_fvb = 0x2000 as efi::Handle;
+        assert_eq!((protocol.write)(this(instance), 1, 0x10, 4, record, other_fvb, buffer), efi::Status::UNSUPPORTED);
+        assert_eq!(
+            (protocol.write)(this(instance), 1, 0x10, 4, record, FVB_HANDLE, ptr::null_mut()),
+            efi::Status::INVALID_PARAMETER
+        );
+        assert_eq!((protocol.write)(this(instance), 1, 0x10, 4, record, FVB_HANDLE, buffer), efi::Status::SUCCESS);
+        assert_eq!((protocol.write)(this(instance), 1, 0x10, 4, record, FVB_HANDLE, buffer), efi::Status::NOT_READY);
+
+        let mut reported_caller_id = efi::Guid::from_fields(0, 0, 0, 0, 0, &[0; 6]);
+        assert_eq!(get_last_write(&mut reported_caller_id, &mut private_data_size), efi::Status::BUFFER_TOO_SMALL);
+        assert_eq!(private_data_size, 2);
+        assert_eq!(get_last_write(&mut reported_caller_id, &mut private_data_size), efi::Status::SUCCESS);
+        assert_eq!(reported_caller_id, caller_id);
+        assert_eq!((lba, offset, length, complete), (1, 0x10, 4, efi::Boolean::TRUE));
+        assert_eq!(private_data, [0xA, 0xB]);
+
+        let mut buffer = [0; 4];
+        instance.journal.lock().read(BLOCK_SIZE + 0x10, &mut buffer).unwrap();
+        assert_eq!(buffer, [0x5A; 4]);
+    }
+
+    #[test]
+    fn get_last_write_should_check_its_pointers() {
+        let instance = test_instance(true);
+        let mut caller_id = efi::Guid::from_fields(0, 0, 0, 0, 0, &[0; 6]);
+        let (mut lba, mut offset, mut length, mut size, mut complete) = (0, 0, 0, 0, efi::Boolean::FALSE);
+        assert_eq!(
+            (instance.protocol.get_last_write)(
+                this(instance),
+                &mut caller_id,
+                &mut lba,
+                &mut offset,
+                &mut length,
+                &mut size,
+                ptr::null_mut(),
+                ptr::null_mut(),
+            ),
+            efi::Status::INVALID_PARAMETER
+        );
+        assert_eq!(
+            (instance.protocol.get_last_write)(
+                ptr::null_mut(),
+                &mut caller_id,
+                &mut lba,
+                &mut offset,
+                &mut length,
+                &mut size,
+                ptr::null_mut(),
+                &mut complete,
+            ),
+            efi::Status::INVALID_PARAMETER
+        );
+    }
+
+    #[test]
+    fn restart_and_abort_should_follow_the_allocated_writes() {
+        let instance = test_instance(true);
+        let protocol = &instance.protocol;
+        let mut caller_id = efi::Guid::from_fields(1, 2, 3, 4, 5, &[6; 6]);
+        assert_eq!((protocol.restart)(this(instance), 0x2000 as efi::Handle), efi::Status::UNSUPPORTED);
+        assert_eq!((protocol.restart)(this(instance), FVB_HANDLE), efi::Status::ABORTED);
+        assert_eq!((protocol.abort)(this(instance)), efi::Status::ABORTED);
+
+        assert_eq!((protocol.allocate)(this(instance), &mut caller_id, 0, 2), efi::Status::SUCCESS);
+        assert_eq!((protocol.restart)(this(instance), FVB_HANDLE), efi::Status::SUCCESS);
+        assert_eq!((protocol.abort)(this(instance)), efi::Status::SUCCESS);
+        assert_eq!((protocol.restart)(this(instance), FVB_HANDLE), efi::Status::ABORTED);
+        assert_eq!((protocol.abort)(this(instance)), efi::Status::ABORTED);
+        assert_eq!((protocol.abort)(ptr::null_mut()), efi::Status::INVALID_PARAMETER);
+        assert_eq!((protocol.restart)(ptr::null_mut(), FVB_HANDLE), efi::Status::INVALID_PARAMETER);
+    }
+}
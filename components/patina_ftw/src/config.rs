@@ -0,0 +1,24 @@
+//! Patina FTW Component Configuration
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+/// The configuration for the Patina FTW component.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FtwConfig {
+    /// The physical address of the Firmware Volume Block holding the NV storage. Its last blocks hold the FTW working
+    /// block followed by the spare blocks.
+    pub nv_storage_base: u64,
+    /// The number of spare blocks at the end of the FVB, which bounds the size of an atomic write.
+    pub spare_blocks: usize,
+}
+
+impl Default for FtwConfig {
+    fn default() -> Self {
+        Self { nv_storage_base: 0, spare_blocks: 1 }
+    }
+}
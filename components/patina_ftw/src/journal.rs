@@ -0,0 +1,202 @@
+//! Fault Tolerant Write Journal State
+//!
+//! The state shared by the [FaultTolerantWrite](crate::service::FaultTolerantWrite) service and the EDK II protocol:
+//! the journaled NV storage once it is opened.
+//!
+//! The writes allocated through the protocol and their records are kept in the working block of the journal, so the
+//! last write reported to EDK II callers and the writes left of an allocation survive a reset.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use patina::{
+    error::{EfiError, Result},
+    firmware_storage::{
+        FirmwareStorage, StorageError,
+        ftw::{FaultTolerantStorage, LastWrite},
+    },
+};
+use r_efi::efi;
+
+/// The journaled NV storage.
+pub(crate) struct Journal<S: FirmwareStorage> {
+    storage: Option<FaultTolerantStorage<S>>,
+}
+
+impl<S: FirmwareStorage> Journal<S> {
+    /// Creates a journal waiting for its storage.
+    pub const fn new() -> Self {
+        Self { storage: None }
+    }
+
+    /// Makes the journal usable over `storage`.
+    pub fn open(&mut self, storage: FaultTolerantStorage<S>) {
+        self.storage = Some(storage);
+    }
+
+    /// Returns true once the journal is opened.
+    pub fn is_open(&self) -> bool {
+        self.storage.is_some()
+    }
+
+    fn storage(&self) -> Result<&FaultTolerantStorage<S>> {
+        self.storage.as_ref().ok_or(EfiError::NotReady)
+    }
+
+    fn storage_mut(&mut self) -> Result<&mut FaultTolerantStorage<S>> {
+        self.storage.as_mut().ok_or(EfiError::NotReady)
+    }
+
+    pub fn max_write_size(&self) -> Result<usize> {
+        Ok(self.storage()?.spare_size())
+    }
+
+    pub fn read(&self, offset: usize, buffer: &mut [u8]) -> Result<()> {
+        Ok(self.storage()?.read(offset, buffer)?)
+    }
+
+    /// Returns an error if `data` at `offset` from a block boundary does not fit the spare blocks.
+    fn check_write_size(&self, offset: usize, data: &[u8]) -> Result<()> {
+        let storage = self.storage()?;
+        let within_block = offset % storage.geometry().block_size;
+        match within_block.saturating_add(data.len()) > storage.spare_size() {
+            true => Err(EfiError::BadBufferSize),
+            false => Ok(()),
+        }
+    }
+
+    /// Writes `data` at `offset` as a single update of the journal.
+    pub fn write(&mut self, offset: usize, data: &[u8]) -> Result<()> {
+        self.check_write_size(offset, data)?;
+        Ok(self.storage_mut()?.write(offset, data)?)
+    }
+
+    /// Allocates `number_of_writes` writes for `caller_id`, each with `private_data_size` bytes of private data.
+    pub fn allocate(&mut self, caller_id: efi::Guid, private_data_size: usize, number_of_writes: usize) -> Result<()> {
+        let storage = self.storage_mut()?;
+        if number_of_writes == 0 {
+            return Err(EfiError::InvalidParameter);
+        }
+        match storage.allocate(&caller_id, private_data_size, number_of_writes) {
+            Err(StorageError::OutOfBounds) => Err(EfiError::BufferTooSmall),
+            result => Ok(result?),
+        }
+    }
+
+    /// Returns the size of the private data of the allocated writes, or 0 if none are allocated.
+    pub fn private_data_size(&self) -> usize {
+        self.storage().map_or(0, |storage| storage.pending_private_data_size())
+    }
+
+    /// Performs the next allocated write, of `data` at `offset` from the start of `lba`.
+    pub fn write_allocated(&mut self, lba: efi::Lba, offset: usize, data: &[u8], private_data: &[u8]) -> Result<()> {
+        self.check_write_size(offset, data)?;
+        Ok(self.storage_mut()?.write_allocated(lba, offset, data, private_data)?)
+    }
+
+    /// Succeeds if writes of the last allocation are left, there is nothing to restart in them as an interrupted write
+    /// is completed or abandoned when the journal is opened.
+    pub fn restart(&self) -> Result<()> {
+        match self.storage()?.has_pending_writes() {
+            true => Ok(()),
+            false => Err(EfiError::Aborted),
+        }
+    }
+
+    /// Abandons the allocated writes left.
+    pub fn abort(&mut self) -> Result<()> {
+        match self.storage_mut()?.abort() {
+            Err(StorageError::NotAllocated) => Err(EfiError::Aborted),
+            result => Ok(result?),
+        }
+    }
+
+    pub fn last_write(&self) -> Result<Option<LastWrite>> {
+        Ok(self.storage()?.last_write()?)
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use patina::firmware_storage::RamStorage;
+
+    const BLOCK_SIZE: usize = 0x100;
+    const CALLER: efi::Guid = efi::Guid::from_fields(1, 2, 3, 4, 5, &[6; 6]);
+
+    fn open_journal() -> Journal<RamStorage> {
+        let mut journal = Journal::new();
+        journal.open(FaultTolerantStorage::with_spare_blocks(RamStorage::new(BLOCK_SIZE, 8), 2).unwrap());
+        journal
+    }
+
+    #[test]
+    fn a_closed_journal_should_not_be_ready() {
+        let mut journal = Journal::<RamStorage>::new();
+        assert!(!journal.is_open());
+        assert_eq!(journal.max_write_size(), Err(EfiError::NotReady));
+        assert_eq!(journal.read(0, &mut [0]), Err(EfiError::NotReady));
+        assert_eq!(journal.write(0, &[0]), Err(EfiError::NotReady));
+        assert_eq!(journal.allocate(CALLER, 0, 1), Err(EfiError::NotReady));
+        assert_eq!(journal.last_write(), Err(EfiError::NotReady));
+        assert_eq!(journal.private_data_size(), 0);
+    }
+
+    #[test]
+    fn writes_should_be_bounded_by_the_spare_blocks() {
+        let mut journal = open_journal();
+        assert_eq!(journal.max_write_size(), Ok(2 * BLOCK_SIZE));
+
+        journal.write(BLOCK_SIZE, &[0x5A; 2 * BLOCK_SIZE]).unwrap();
+        let mut buffer = [0; 2 * BLOCK_SIZE];
+        journal.read(BLOCK_SIZE, &mut buffer).unwrap();
+        assert_eq!(buffer, [0x5A; 2 * BLOCK_SIZE]);
+
+        // The same size off a block boundary touches three blocks.
+        assert_eq!(journal.write(BLOCK_SIZE + 1, &[0; 2 * BLOCK_SIZE]), Err(EfiError::BadBufferSize));
+        assert_eq!(journal.write(5 * BLOCK_SIZE, &[0]), Err(EfiError::InvalidParameter));
+    }
+
+    #[test]
+    fn allocated_writes_should_be_tracked() {
+        let mut journal = open_journal();
+        assert_eq!(journal.write_allocated(0, 0, &[0], &[]), Err(EfiError::NotReady));
+        assert_eq!(journal.abort(), Err(EfiError::Aborted));
+        assert_eq!(journal.restart(), Err(EfiError::Aborted));
+        assert_eq!(journal.last_write(), Ok(None));
+        assert_eq!(journal.allocate(CALLER, 0, 0), Err(EfiError::InvalidParameter));
+        assert_eq!(journal.allocate(CALLER, BLOCK_SIZE, 1), Err(EfiError::BufferTooSmall));
+
+        journal.allocate(CALLER, 2, 2).unwrap();
+        assert_eq!(journal.allocate(CALLER, 2, 1), Err(EfiError::AccessDenied));
+        assert_eq!(journal.restart(), Ok(()));
+        assert_eq!(journal.private_data_size(), 2);
+
+        assert_eq!(journal.write_allocated(0, 1, &[0; 2 * BLOCK_SIZE], &[]), Err(EfiError::BadBufferSize));
+        journal.write_allocated(1, 0x10, &[0x11; 4], &[0xA, 0xB, 0xC]).unwrap();
+        let last_write = journal.last_write().unwrap().unwrap();
+        assert_eq!((last_write.lba, last_write.offset, last_write.length), (1, 0x10, 4));
+        assert_eq!(last_write.private_data, [0xA, 0xB]);
+        assert!(!last_write.complete);
+
+        journal.write_allocated(2, 0, &[0x22; 4], &[]).unwrap();
+        assert!(journal.last_write().unwrap().unwrap().complete);
+        assert_eq!(journal.write_allocated(2, 0, &[0x22; 4], &[]), Err(EfiError::NotReady));
+
+        let mut buffer = [0; 4];
+        journal.read(BLOCK_SIZE + 0x10, &mut buffer).unwrap();
+        assert_eq!(buffer, [0x11; 4]);
+
+        // Once all the writes are done, new ones can be allocated.
+        journal.allocate(CALLER, 0, 3).unwrap();
+        journal.write_allocated(0, 0, &[0x33], &[]).unwrap();
+        journal.abort().unwrap();
+        assert!(journal.last_write().unwrap().unwrap().complete);
+        assert_eq!(journal.write_allocated(0, 0, &[0x33], &[]), Err(EfiError::NotReady));
+    }
+}
@@ -0,0 +1,48 @@
+//! Fault Tolerant Write (FTW) support for Patina platforms.
+//!
+//! Updates of the non-volatile (NV) storage, such as the reclaim of the variable store, erase and rewrite whole flash
+//! blocks. A power loss in the middle of such an update would leave the blocks partially erased. This crate makes
+//! these updates atomic, with a journal and spare blocks reserved at the end of the NV storage:
+//!
+//! - [service::FaultTolerantWrite]: the service Patina components use to read and atomically write the NV storage.
+//! - [protocol]: the EDK II Fault Tolerant Write protocol, for the variable driver and other EDK II drivers.
+//! - [component::FaultTolerantWriteManager]: a component that opens the journal on the Firmware Volume Block (FVB)
+//!   of the NV storage, completing an update interrupted by a power loss, then produces both interfaces.
+//!
+//! The journal format is described in [patina::firmware_storage::ftw].
+//!
+//! ## Integration Example
+//!
+//! ```rust,ignore
+//! // ...
+//!
+//! Core::default()
+//!  // ...
+//!  .with_config(patina_ftw::config::FtwConfig {
+//!      nv_storage_base: NV_STORAGE_BASE,
+//!      spare_blocks: 4,
+//!  })
+//!  .with_component(patina_ftw::component::FaultTolerantWriteManager)
+//!  .start()
+//!  .unwrap();
+//!
+//! // ...
+//! ```
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+#![cfg_attr(all(not(feature = "std"), not(test), not(feature = "mockall")), no_std)]
+#![feature(coverage_attribute)]
+
+extern crate alloc;
+
+pub mod component;
+pub mod config;
+mod journal;
+pub mod protocol;
+pub mod service;
@@ -0,0 +1,54 @@
+//! EDK II Fault Tolerant Write Protocol
+//!
+//! The protocol the EDK II variable driver uses to update its store. A caller allocates a number of writes with
+//! `Allocate()`, then performs them one by one with `Write()`. Each write is atomic, and covers at most the size
+//! returned by `GetMaxBlockSize()` from the start of its LBA.
+//!
+//! See `MdeModulePkg/Include/Protocol/FaultTolerantWrite.h` in EDK II.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use core::ffi::c_void;
+use r_efi::efi;
+
+/// EDK II Fault Tolerant Write Protocol GUID
+pub const PROTOCOL_GUID: efi::Guid =
+    efi::Guid::from_fields(0x3ebd9e82, 0x2c78, 0x4de6, 0x97, 0x86, &[0x8d, 0x4b, 0xfc, 0xb7, 0xc8, 0x81]);
+
+pub type GetMaxBlockSize = extern "efiapi" fn(*mut Protocol, *mut usize) -> efi::Status;
+
+pub type Allocate = extern "efiapi" fn(*mut Protocol, *mut efi::Guid, usize, usize) -> efi::Status;
+
+pub type Write =
+    extern "efiapi" fn(*mut Protocol, efi::Lba, usize, usize, *mut c_void, efi::Handle, *mut c_void) -> efi::Status;
+
+pub type Restart = extern "efiapi" fn(*mut Protocol, efi::Handle) -> efi::Status;
+
+pub type Abort = extern "efiapi" fn(*mut Protocol) -> efi::Status;
+
+pub type GetLastWrite = extern "efiapi" fn(
+    *mut Protocol,
+    *mut efi::Guid,
+    *mut efi::Lba,
+    *mut usize,
+    *mut usize,
+    *mut usize,
+    *mut c_void,
+    *mut efi::Boolean,
+) -> efi::Status;
+
+/// The EDK II Fault Tolerant Write protocol.
+#[repr(C)]
+pub struct Protocol {
+    pub get_max_block_size: GetMaxBlockSize,
+    pub allocate: Allocate,
+    pub write: Write,
+    pub restart: Restart,
+    pub abort: Abort,
+    pub get_last_write: GetLastWrite,
+}
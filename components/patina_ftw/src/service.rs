@@ -0,0 +1,37 @@
+//! Fault Tolerant Write Service Definition
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use patina::error::Result;
+
+#[cfg(any(test, feature = "mockall"))]
+use mockall::automock;
+
+/// Reads and atomically writes the NV storage.
+///
+/// Offsets are in bytes from the start of the Firmware Volume Block of the NV storage. Until the journal is opened on
+/// it, every call fails with [EfiError::NotReady](patina::error::EfiError::NotReady).
+#[cfg_attr(any(test, feature = "mockall"), automock)]
+pub trait FaultTolerantWrite {
+    /// Returns the size of the largest atomic write, from a block boundary.
+    fn max_write_size(&self) -> Result<usize>;
+
+    /// Reads `buffer.len()` bytes at `offset`.
+    fn read(&self, offset: usize, buffer: &mut [u8]) -> Result<()>;
+
+    /// Writes `data` at `offset`. After a power loss, the NV storage holds either all of `data` or none of it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [EfiError::BadBufferSize](patina::error::EfiError::BadBufferSize) if the write touches more blocks than
+    /// there are spare blocks, it could not be atomic.
+    ///
+    /// Returns [EfiError::AccessDenied](patina::error::EfiError::AccessDenied) while writes allocated through the
+    /// EDK II protocol are left.
+    fn write(&self, offset: usize, data: &[u8]) -> Result<()>;
+}
@@ -0,0 +1,244 @@
+//! Patina Graphics Console Component
+//!
+//! Installs a driver binding producing a Simple Text Output Protocol on the devices with a Graphics Output Protocol
+//! (GOP) and a device path, as the EDK II `GraphicsConsoleDxe` driver does. The device path requirement excludes the
+//! virtual GOP of a console splitter, which has none.
+//!
+//! The GOP of a device is opened by driver while the console is started, and the Simple Text Output Protocol is
+//! uninstalled when the device is disconnected.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::{boxed::Box, vec::Vec};
+use core::{ffi::c_void, ptr::NonNull};
+
+use patina::{
+    boot_services::{BootServices, StandardBootServices},
+    component::IntoComponent,
+    driver_binding::{DriverBinding, UefiDriverBinding},
+    error::EfiError,
+};
+use r_efi::efi::{
+    self,
+    protocols::{device_path, graphics_output, simple_text_output},
+};
+
+use crate::console::GopTextOutput;
+
+/// Tag installed on the handle of the driver binding of the graphics console.
+const GRAPHICS_CONSOLE_DRIVER: efi::Guid =
+    efi::Guid::from_fields(0x01881162, 0x9424, 0x4318, 0xb3, 0x7e, &[0x65, 0x00, 0x9d, 0x09, 0x3f, 0xa5]);
+
+/// Graphics Console Component.
+#[derive(IntoComponent)]
+pub struct GraphicsConsole;
+
+/// Driver binding starting a console on the devices with a GOP.
+struct GraphicsConsoleDriver {
+    handle: efi::Handle,
+    /// The started consoles, by controller.
+    consoles: Vec<(efi::Handle, Box<GopTextOutput>)>,
+}
+
+impl DriverBinding for GraphicsConsoleDriver {
+    fn driver_binding_supported<T: BootServices + 'static>(
+        &self,
+        boot_services: &'static T,
+        controller: efi::Handle,
+        _remaining_device_path: Option<NonNull<device_path::Protocol>>,
+    ) -> Result<bool, efi::Status> {
+        let test = |protocol: &efi::Guid| {
+            // SAFETY: The protocol is only tested for, the interface is not used.
+            unsafe {
+                boot_services.open_protocol_unchecked(
+                    controller,
+                    protocol,
+                    self.handle,
+                    controller,
+                    efi::OPEN_PROTOCOL_TEST_PROTOCOL,
+                )
+            }
+            .is_ok()
+        };
+        Ok(test(&graphics_output::PROTOCOL_GUID) && test(&device_path::PROTOCOL_GUID))
+    }
+
+    fn driver_binding_start<T: BootServices + 'static>(
+        &mut self,
+        boot_services: &'static T,
+        controller: efi::Handle,
+        _remaining_device_path: Option<NonNull<device_path::Protocol>>,
+    ) -> Result<(), efi::Status> {
+        // SAFETY: The interface of the GOP GUID is a GOP.
+        let gop = unsafe {
+            boot_services.open_protocol_unchecked(
+                controller,
+                &graphics_output::PROTOCOL_GUID,
+                self.handle,
+                controller,
+                efi::OPEN_PROTOCOL_BY_DRIVER,
+            )
+        }? as *mut graphics_output::Protocol;
+
+        // SAFETY: The GOP is opened by driver until the console is stopped.
+        let result = unsafe { GopTextOutput::new(gop) }.and_then(|mut console| {
+            // SAFETY: The console is kept until its protocol is uninstalled.
+            unsafe {
+                boot_services.install_protocol_interface_unchecked(
+                    Some(controller),
+                    &simple_text_output::PROTOCOL_GUID,
+                    console.protocol() as *mut c_void,
+                )
+            }?;
+            Ok(console)
+        });
+        match result {
+            Ok(console) => {
+                log::info!("Graphics console: started on {controller:?}.");
+                self.consoles.push((controller, console));
+                Ok(())
+            }
+            Err(status) => {
+                log::error!("Graphics console: failed to start on {controller:?}: {status:#x?}");
+                let _ =
+                    boot_services.close_protocol(controller, &graphics_output::PROTOCOL_GUID, self.handle, controller);
+                Err(status)
+            }
+        }
+    }
+
+    fn driver_binding_stop<T: BootServices + 'static>(
+        &mut self,
+        boot_services: &'static T,
+        controller: efi::Handle,
+        _number_of_children: usize,
+        _child_handle_buffer: Option<NonNull<efi::Handle>>,
+    ) -> Result<(), efi::Status> {
+        let index =
+            self.consoles.iter().position(|(handle, _)| *handle == controller).ok_or(efi::Status::NOT_STARTED)?;
+        let console = &mut self.consoles[index].1;
+        // SAFETY: The protocol was installed by start.
+        unsafe {
+            boot_services.uninstall_protocol_interface_unchecked(
+                controller,
+                &simple_text_output::PROTOCOL_GUID,
+                console.protocol() as *mut c_void,
+            )
+        }?;
+        self.consoles.remove(index);
+        boot_services.close_protocol(controller, &graphics_output::PROTOCOL_GUID, self.handle, controller)
+    }
+}
+
+impl GraphicsConsole {
+    /// Entry point of [`GraphicsConsole`]
+    #[coverage(off)] // This is tested via the generic version, see _entry_point.
+    pub fn entry_point(self, boot_services: StandardBootServices) -> Result<(), EfiError> {
+        self._entry_point(boot_services)
+    }
+
+    /// Entry point that have generic parameter.
+    fn _entry_point<BB, B>(self, boot_services: BB) -> Result<(), EfiError>
+    where
+        BB: AsRef<B> + Clone + 'static,
+        B: BootServices + 'static,
+    {
+        // The driver binding lives until the end of boot services.
+        let boot_services: &'static BB = Box::leak(Box::new(boot_services));
+        let boot_services: &'static B = boot_services.as_ref();
+
+        // SAFETY: The tag has no interface.
+        let handle = unsafe {
+            boot_services.install_protocol_interface_unchecked(None, &GRAPHICS_CONSOLE_DRIVER, core::ptr::null_mut())
+        }?;
+        let driver = GraphicsConsoleDriver { handle, consoles: Vec::new() };
+        UefiDriverBinding::new(driver, handle, boot_services).install()?;
+
+        log::info!("Graphics console: driver binding installed on {handle:?}.");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::console::tests::MockGop;
+    use core::ptr;
+    use patina::boot_services::MockBootServices;
+
+    const CONTROLLER: efi::Handle = 2_usize as efi::Handle;
+
+    fn driver() -> GraphicsConsoleDriver {
+        GraphicsConsoleDriver { handle: 1_usize as efi::Handle, consoles: Vec::new() }
+    }
+
+    #[test]
+    fn test_supported_requires_a_gop_and_a_device_path() {
+        let supported = |protocols: &'static [&'static efi::Guid]| {
+            let mut boot_services = MockBootServices::new();
+            boot_services.expect_open_protocol_unchecked().returning(
+                move |controller, protocol, agent, _, attributes| {
+                    assert_eq!((controller, agent), (CONTROLLER, 1_usize as efi::Handle));
+                    assert_eq!(attributes, efi::OPEN_PROTOCOL_TEST_PROTOCOL);
+                    match protocols.contains(&protocol) {
+                        true => Ok(ptr::null_mut()),
+                        false => Err(efi::Status::UNSUPPORTED),
+                    }
+                },
+            );
+            driver().driver_binding_supported(Box::leak(Box::new(boot_services)), CONTROLLER, None)
+        };
+
+        assert_eq!(supported(&[&graphics_output::PROTOCOL_GUID, &device_path::PROTOCOL_GUID]), Ok(true));
+        assert_eq!(supported(&[&graphics_output::PROTOCOL_GUID]), Ok(false));
+        assert_eq!(supported(&[&device_path::PROTOCOL_GUID]), Ok(false));
+    }
+
+    #[test]
+    fn test_start_and_stop_install_the_text_output() {
+        let gop = Box::leak(MockGop::new(640, 480));
+        let gop_interface = &mut gop.protocol as *mut graphics_output::Protocol as usize;
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_open_protocol_unchecked().times(2).returning(move |_, protocol, _, _, attributes| {
+            assert_eq!(protocol, &graphics_output::PROTOCOL_GUID);
+            assert_eq!(attributes, efi::OPEN_PROTOCOL_BY_DRIVER);
+            Ok(gop_interface as *mut c_void)
+        });
+        let mut installs = 0;
+        boot_services.expect_install_protocol_interface_unchecked().times(2).returning(move |handle, protocol, _| {
+            assert_eq!((handle, protocol), (Some(CONTROLLER), &simple_text_output::PROTOCOL_GUID));
+            installs += 1;
+            match installs {
+                1 => Err(efi::Status::INVALID_PARAMETER),
+                _ => Ok(CONTROLLER),
+            }
+        });
+        boot_services.expect_uninstall_protocol_interface_unchecked().once().returning(|handle, protocol, _| {
+            assert_eq!((handle, protocol), (CONTROLLER, &simple_text_output::PROTOCOL_GUID));
+            Ok(())
+        });
+        boot_services.expect_close_protocol().times(2).returning(|_, protocol, _, _| {
+            assert_eq!(protocol, &graphics_output::PROTOCOL_GUID);
+            Ok(())
+        });
+        let boot_services: &'static MockBootServices = Box::leak(Box::new(boot_services));
+
+        // The GOP is closed if the text output cannot be installed.
+        let mut driver = driver();
+        assert_eq!(driver.driver_binding_start(boot_services, CONTROLLER, None), Err(efi::Status::INVALID_PARAMETER));
+        assert!(driver.consoles.is_empty());
+
+        assert_eq!(driver.driver_binding_start(boot_services, CONTROLLER, None), Ok(()));
+        assert_eq!(driver.consoles.len(), 1);
+        assert_eq!(driver.driver_binding_stop(boot_services, CONTROLLER, 0, None), Ok(()));
+        assert!(driver.consoles.is_empty());
+        assert_eq!(driver.driver_binding_stop(boot_services, CONTROLLER, 0, None), Err(efi::Status::NOT_STARTED));
+    }
+}
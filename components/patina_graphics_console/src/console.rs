@@ -0,0 +1,719 @@
+//! Graphics Console
+//!
+//! [GopTextOutput] produces a Simple Text Output Protocol drawing text on a Graphics Output Protocol (GOP) with the
+//! embedded [font](crate::font). The text area of the mode is centered on the screen, and scrolls up when a line feed
+//! is output on its last row. The cursor is drawn by inverting the bottom rows of its cell.
+//!
+//! The GOP is kept in its current mode, the text modes are the ones that fit on its screen.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::{boxed::Box, vec::Vec};
+use core::ptr;
+
+use r_efi::efi::{
+    self,
+    protocols::{graphics_output, simple_text_output},
+};
+
+use crate::font::{GLYPH_HEIGHT, GLYPH_WIDTH, Glyph, glyph};
+
+/// The (columns, rows) of mode 0, which must be supported.
+const DEFAULT_MODE: (usize, usize) = (80, 25);
+
+/// The (columns, rows) of mode 1, supported if it fits on the screen.
+const TALL_MODE: (usize, usize) = (80, 50);
+
+/// The attribute of the console when it is reset: light gray on black.
+const DEFAULT_ATTRIBUTE: usize = 0x07;
+
+/// The highest valid attribute: the foreground color in bits 0 to 3 and the background color in bits 4 to 6.
+const MAX_ATTRIBUTE: usize = 0x7F;
+
+/// The number of pixel rows inverted at the bottom of the cell of the cursor.
+const CURSOR_HEIGHT: usize = 2;
+
+const CHAR_BACKSPACE: u16 = 0x08;
+const CHAR_LINEFEED: u16 = 0x0A;
+const CHAR_CARRIAGE_RETURN: u16 = 0x0D;
+
+const fn color(red: u8, green: u8, blue: u8) -> graphics_output::BltPixel {
+    graphics_output::BltPixel { blue, green, red, reserved: 0 }
+}
+
+/// The colors of the attributes, by `EFI_BLACK` to `EFI_WHITE` value.
+const PALETTE: [graphics_output::BltPixel; 16] = [
+    color(0x00, 0x00, 0x00), // Black
+    color(0x00, 0x00, 0x98), // Blue
+    color(0x00, 0x98, 0x00), // Green
+    color(0x00, 0x98, 0x98), // Cyan
+    color(0x98, 0x00, 0x00), // Red
+    color(0x98, 0x00, 0x98), // Magenta
+    color(0x98, 0x98, 0x00), // Brown
+    color(0x98, 0x98, 0x98), // Light gray
+    color(0x30, 0x30, 0x30), // Dark gray
+    color(0x00, 0x00, 0xFF), // Light blue
+    color(0x00, 0xFF, 0x00), // Light green
+    color(0x00, 0xFF, 0xFF), // Light cyan
+    color(0xFF, 0x00, 0x00), // Light red
+    color(0xFF, 0x00, 0xFF), // Light magenta
+    color(0xFF, 0xFF, 0x00), // Yellow
+    color(0xFF, 0xFF, 0xFF), // White
+];
+
+/// Returns the (columns, rows) of the text modes on a screen of the given size, by mode number, or `None` for modes
+/// that do not fit.
+///
+/// Modes 0 and 1 are 80x25 and 80x50, as required by the UEFI specification, and the last mode fills the screen.
+fn text_modes((width, height): (usize, usize)) -> Vec<Option<(usize, usize)>> {
+    let fits = |(columns, rows): (usize, usize)| columns * GLYPH_WIDTH <= width && rows * GLYPH_HEIGHT <= height;
+    let mut modes: Vec<_> = [DEFAULT_MODE, TALL_MODE].into_iter().map(|mode| fits(mode).then_some(mode)).collect();
+    let full_screen = (width / GLYPH_WIDTH, height / GLYPH_HEIGHT);
+    if fits(DEFAULT_MODE) && !modes.contains(&Some(full_screen)) {
+        modes.push(Some(full_screen));
+    }
+    modes
+}
+
+/// Simple Text Output Protocol drawing on a GOP.
+#[repr(C)]
+pub(crate) struct GopTextOutput {
+    // The protocol must be the first field, the protocol functions cast the protocol pointer to the console.
+    protocol: simple_text_output::Protocol,
+    mode: simple_text_output::Mode,
+    gop: *mut graphics_output::Protocol,
+    /// The (width, height) of the screen, in pixels.
+    screen: (usize, usize),
+    /// The (columns, rows) of the modes of the console, by mode number, or `None` for modes that do not fit.
+    modes: Vec<Option<(usize, usize)>>,
+    /// The position of the upper left corner of the text area on the screen, in pixels.
+    origin: (usize, usize),
+    /// Whether the cursor is currently drawn on the screen.
+    cursor_drawn: bool,
+}
+
+impl GopTextOutput {
+    /// Creates a console drawing on `gop`, in the 80x25 mode.
+    ///
+    /// The screen is left as is, e.g. with the boot logo, until the console is reset, cleared or written to.
+    ///
+    /// # Safety
+    ///
+    /// `gop` must be a valid GOP for the lifetime of the console.
+    pub(crate) unsafe fn new(gop: *mut graphics_output::Protocol) -> Result<Box<Self>, efi::Status> {
+        // SAFETY: The mode and mode information of a GOP are null or valid while it is installed.
+        let info =
+            unsafe { (*gop).mode.as_ref().and_then(|mode| mode.info.as_ref()) }.ok_or(efi::Status::DEVICE_ERROR)?;
+        let screen = (info.horizontal_resolution as usize, info.vertical_resolution as usize);
+        let modes = text_modes(screen);
+        if modes[0].is_none() {
+            log::warn!("Graphics console: the {}x{} screen is too small for an 80x25 console.", screen.0, screen.1);
+            return Err(efi::Status::UNSUPPORTED);
+        }
+
+        let mut console = Box::new(Self {
+            protocol: simple_text_output::Protocol {
+                reset,
+                output_string,
+                test_string,
+                query_mode,
+                set_mode,
+                set_attribute,
+                clear_screen,
+                set_cursor_position,
+                enable_cursor,
+                mode: ptr::null_mut(),
+            },
+            mode: simple_text_output::Mode {
+                max_mode: modes.len() as i32,
+                mode: 0,
+                attribute: DEFAULT_ATTRIBUTE as i32,
+                cursor_column: 0,
+                cursor_row: 0,
+                cursor_visible: efi::Boolean::TRUE,
+            },
+            gop,
+            screen,
+            modes,
+            origin: (0, 0),
+            cursor_drawn: false,
+        });
+        console.origin = console.origin_of(DEFAULT_MODE);
+        console.protocol.mode = &mut console.mode;
+        Ok(console)
+    }
+
+    /// Returns the Simple Text Output Protocol of the console.
+    pub(crate) fn protocol(&mut self) -> *mut simple_text_output::Protocol {
+        &mut self.protocol
+    }
+
+    /// Returns the (columns, rows) of the current mode.
+    fn dimensions(&self) -> (usize, usize) {
+        self.modes[self.mode.mode as usize].unwrap_or(DEFAULT_MODE)
+    }
+
+    /// Returns the origin of the text area of a mode, centered on the screen.
+    fn origin_of(&self, (columns, rows): (usize, usize)) -> (usize, usize) {
+        ((self.screen.0 - columns * GLYPH_WIDTH) / 2, (self.screen.1 - rows * GLYPH_HEIGHT) / 2)
+    }
+
+    /// Returns the position of the upper left corner of a cell on the screen, in pixels.
+    fn cell(&self, column: usize, row: usize) -> (usize, usize) {
+        (self.origin.0 + column * GLYPH_WIDTH, self.origin.1 + row * GLYPH_HEIGHT)
+    }
+
+    /// Returns the (foreground, background) colors of the current attribute.
+    fn colors(&self) -> (graphics_output::BltPixel, graphics_output::BltPixel) {
+        let attribute = self.mode.attribute as usize;
+        (PALETTE[attribute & 0x0F], PALETTE[(attribute >> 4) & 0x07])
+    }
+
+    /// Performs a blt operation on the GOP, with the buffer rows the width of the rectangle.
+    fn blt(
+        &self,
+        buffer: *mut graphics_output::BltPixel,
+        operation: graphics_output::BltOperation,
+        (source_x, source_y): (usize, usize),
+        (destination_x, destination_y): (usize, usize),
+        (width, height): (usize, usize),
+    ) -> Result<(), efi::Status> {
+        // SAFETY: The GOP is valid for the lifetime of the console.
+        let status = unsafe {
+            ((*self.gop).blt)(
+                self.gop,
+                buffer,
+                operation,
+                source_x,
+                source_y,
+                destination_x,
+                destination_y,
+                width,
+                height,
+                0,
+            )
+        };
+        match status {
+            efi::Status::SUCCESS => Ok(()),
+            _ => Err(efi::Status::DEVICE_ERROR),
+        }
+    }
+
+    /// Fills a rectangle of the screen with the background color.
+    fn fill(&self, position: (usize, usize), size: (usize, usize)) -> Result<(), efi::Status> {
+        let mut background = self.colors().1;
+        self.blt(&mut background, graphics_output::BLT_VIDEO_FILL, (0, 0), position, size)
+    }
+
+    /// Draws a glyph in a cell, with the colors of the current attribute.
+    fn draw_glyph(&self, glyph: &Glyph, column: usize, row: usize) -> Result<(), efi::Status> {
+        let (foreground, background) = self.colors();
+        let mut pixels = [background; GLYPH_WIDTH * GLYPH_HEIGHT];
+        for (y, bits) in glyph.iter().enumerate() {
+            for x in (0..GLYPH_WIDTH).filter(|x| bits & (0x80 >> x) != 0) {
+                pixels[y * GLYPH_WIDTH + x] = foreground;
+            }
+        }
+        self.blt(
+            pixels.as_mut_ptr(),
+            graphics_output::BLT_BUFFER_TO_VIDEO,
+            (0, 0),
+            self.cell(column, row),
+            (GLYPH_WIDTH, GLYPH_HEIGHT),
+        )
+    }
+
+    /// Inverts the bottom rows of the cell of the cursor, drawing or erasing the cursor.
+    fn flip_cursor(&mut self) -> Result<(), efi::Status> {
+        let (x, y) = self.cell(self.mode.cursor_column as usize, self.mode.cursor_row as usize);
+        let position = (x, y + GLYPH_HEIGHT - CURSOR_HEIGHT);
+        let size = (GLYPH_WIDTH, CURSOR_HEIGHT);
+        let mut pixels = [color(0, 0, 0); GLYPH_WIDTH * CURSOR_HEIGHT];
+        self.blt(pixels.as_mut_ptr(), graphics_output::BLT_VIDEO_TO_BLT_BUFFER, position, (0, 0), size)?;
+        for pixel in &mut pixels {
+            *pixel = color(!pixel.red, !pixel.green, !pixel.blue);
+        }
+        self.blt(pixels.as_mut_ptr(), graphics_output::BLT_BUFFER_TO_VIDEO, (0, 0), position, size)?;
+        self.cursor_drawn = !self.cursor_drawn;
+        Ok(())
+    }
+
+    /// Erases the cursor from the screen, if it is drawn.
+    fn hide_cursor(&mut self) -> Result<(), efi::Status> {
+        match self.cursor_drawn {
+            true => self.flip_cursor(),
+            false => Ok(()),
+        }
+    }
+
+    /// Draws the cursor on the screen, if it is visible and not drawn yet.
+    fn show_cursor(&mut self) -> Result<(), efi::Status> {
+        match self.mode.cursor_visible.into() && !self.cursor_drawn {
+            true => self.flip_cursor(),
+            false => Ok(()),
+        }
+    }
+
+    /// Moves the cursor to the next row, scrolling the text area up one row if the cursor is on the last row.
+    fn line_feed(&mut self) -> Result<(), efi::Status> {
+        let (columns, rows) = self.dimensions();
+        if (self.mode.cursor_row as usize) < rows - 1 {
+            self.mode.cursor_row += 1;
+            return Ok(());
+        }
+        let width = columns * GLYPH_WIDTH;
+        self.blt(
+            ptr::null_mut(),
+            graphics_output::BLT_VIDEO_TO_VIDEO,
+            self.cell(0, 1),
+            self.cell(0, 0),
+            (width, (rows - 1) * GLYPH_HEIGHT),
+        )?;
+        self.fill(self.cell(0, rows - 1), (width, GLYPH_HEIGHT))
+    }
+
+    fn reset(&mut self) -> efi::Status {
+        self.mode.attribute = DEFAULT_ATTRIBUTE as i32;
+        self.set_mode(0)
+    }
+
+    fn output_string(&mut self, string: *const efi::Char16) -> efi::Status {
+        self.write(string).unwrap_or_else(|status| status)
+    }
+
+    /// Outputs the characters of the NUL terminated `string`, returning `WARN_UNKNOWN_GLYPH` if some were skipped.
+    fn write(&mut self, string: *const efi::Char16) -> Result<efi::Status, efi::Status> {
+        self.hide_cursor()?;
+        let columns = self.dimensions().0;
+        let mut status = efi::Status::SUCCESS;
+        // SAFETY: The string is NUL terminated, per the caller.
+        for character in (0..).map(|index| unsafe { *string.add(index) }).take_while(|character| *character != 0) {
+            match character {
+                CHAR_BACKSPACE => self.mode.cursor_column = (self.mode.cursor_column - 1).max(0),
+                CHAR_LINEFEED => self.line_feed()?,
+                CHAR_CARRIAGE_RETURN => self.mode.cursor_column = 0,
+                _ => match glyph(character) {
+                    Some(glyph) => {
+                        self.draw_glyph(glyph, self.mode.cursor_column as usize, self.mode.cursor_row as usize)?;
+                        self.mode.cursor_column += 1;
+                        if self.mode.cursor_column as usize == columns {
+                            self.mode.cursor_column = 0;
+                            self.line_feed()?;
+                        }
+                    }
+                    None => status = efi::Status::WARN_UNKNOWN_GLYPH,
+                },
+            }
+        }
+        self.show_cursor()?;
+        Ok(status)
+    }
+
+    fn test_string(&self, string: *const efi::Char16) -> efi::Status {
+        // SAFETY: The string is NUL terminated, per the caller.
+        let supported =
+            (0..).map(|index| unsafe { *string.add(index) }).take_while(|character| *character != 0).all(|character| {
+                matches!(character, CHAR_BACKSPACE | CHAR_LINEFEED | CHAR_CARRIAGE_RETURN) || glyph(character).is_some()
+            });
+        match supported {
+            true => efi::Status::SUCCESS,
+            false => efi::Status::UNSUPPORTED,
+        }
+    }
+
+    fn set_mode(&mut self, mode_number: usize) -> efi::Status {
+        let Some(Some(dimensions)) = self.modes.get(mode_number).copied() else {
+            return efi::Status::UNSUPPORTED;
+        };
+        self.mode.mode = mode_number as i32;
+        self.origin = self.origin_of(dimensions);
+        self.clear_screen()
+    }
+
+    fn set_attribute(&mut self, attribute: usize) -> efi::Status {
+        if attribute > MAX_ATTRIBUTE {
+            return efi::Status::UNSUPPORTED;
+        }
+        self.mode.attribute = attribute as i32;
+        efi::Status::SUCCESS
+    }
+
+    fn clear_screen(&mut self) -> efi::Status {
+        // The whole screen is cleared, as the text area of the previous mode may be larger.
+        let result = self.fill((0, 0), self.screen);
+        self.cursor_drawn = false;
+        self.mode.cursor_column = 0;
+        self.mode.cursor_row = 0;
+        match result.and_then(|()| self.show_cursor()) {
+            Ok(()) => efi::Status::SUCCESS,
+            Err(status) => status,
+        }
+    }
+
+    fn set_cursor_position(&mut self, column: usize, row: usize) -> efi::Status {
+        let (columns, rows) = self.dimensions();
+        if column >= columns || row >= rows {
+            return efi::Status::UNSUPPORTED;
+        }
+        let result = self.hide_cursor().and_then(|()| {
+            self.mode.cursor_column = column as i32;
+            self.mode.cursor_row = row as i32;
+            self.show_cursor()
+        });
+        match result {
+            Ok(()) => efi::Status::SUCCESS,
+            Err(status) => status,
+        }
+    }
+
+    fn enable_cursor(&mut self, visible: efi::Boolean) -> efi::Status {
+        let result = self.hide_cursor().and_then(|()| {
+            self.mode.cursor_visible = visible;
+            self.show_cursor()
+        });
+        match result {
+            Ok(()) => efi::Status::SUCCESS,
+            Err(status) => status,
+        }
+    }
+}
+
+/// Returns the console owning the protocol `this`.
+///
+/// # Safety
+///
+/// `this` must be null or the protocol of a [GopTextOutput].
+unsafe fn console<'a>(this: *mut simple_text_output::Protocol) -> Option<&'a mut GopTextOutput> {
+    // SAFETY: The protocol is the first field of the repr(C) console, per the caller.
+    unsafe { (this as *mut GopTextOutput).as_mut() }
+}
+
+extern "efiapi" fn reset(this: *mut simple_text_output::Protocol, _extended_verification: efi::Boolean) -> efi::Status {
+    // SAFETY: The function is only installed in the protocol of a console.
+    unsafe { console(this) }.map_or(efi::Status::INVALID_PARAMETER, |console| console.reset())
+}
+
+extern "efiapi" fn output_string(this: *mut simple_text_output::Protocol, string: *mut efi::Char16) -> efi::Status {
+    if string.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    // SAFETY: The function is only installed in the protocol of a console.
+    unsafe { console(this) }.map_or(efi::Status::INVALID_PARAMETER, |console| console.output_string(string))
+}
+
+extern "efiapi" fn test_string(this: *mut simple_text_output::Protocol, string: *mut efi::Char16) -> efi::Status {
+    if string.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    // SAFETY: The function is only installed in the protocol of a console.
+    unsafe { console(this) }.map_or(efi::Status::INVALID_PARAMETER, |console| console.test_string(string))
+}
+
+extern "efiapi" fn query_mode(
+    this: *mut simple_text_output::Protocol,
+    mode_number: usize,
+    columns: *mut usize,
+    rows: *mut usize,
+) -> efi::Status {
+    // SAFETY: The function is only installed in the protocol of a console.
+    let Some(console) = (unsafe { console(this) }) else {
+        return efi::Status::INVALID_PARAMETER;
+    };
+    if columns.is_null() || rows.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    let Some(Some((mode_columns, mode_rows))) = console.modes.get(mode_number).copied() else {
+        return efi::Status::UNSUPPORTED;
+    };
+    // SAFETY: The pointers are provided by the caller and were checked for null.
+    unsafe {
+        columns.write(mode_columns);
+        rows.write(mode_rows);
+    }
+    efi::Status::SUCCESS
+}
+
+extern "efiapi" fn set_mode(this: *mut simple_text_output::Protocol, mode_number: usize) -> efi::Status {
+    // SAFETY: The function is only installed in the protocol of a console.
+    unsafe { console(this) }.map_or(efi::Status::INVALID_PARAMETER, |console| console.set_mode(mode_number))
+}
+
+extern "efiapi" fn set_attribute(this: *mut simple_text_output::Protocol, attribute: usize) -> efi::Status {
+    // SAFETY: The function is only installed in the protocol of a console.
+    unsafe { console(this) }.map_or(efi::Status::INVALID_PARAMETER, |console| console.set_attribute(attribute))
+}
+
+extern "efiapi" fn clear_screen(this: *mut simple_text_output::Protocol) -> efi::Status {
+    // SAFETY: The function is only installed in the protocol of a console.
+    unsafe { console(this) }.map_or(efi::Status::INVALID_PARAMETER, |console| console.clear_screen())
+}
+
+extern "efiapi" fn set_cursor_position(
+    this: *mut simple_text_output::Protocol,
+    column: usize,
+    row: usize,
+) -> efi::Status {
+    // SAFETY: The function is only installed in the protocol of a console.
+    unsafe { console(this) }.map_or(efi::Status::INVALID_PARAMETER, |console| console.set_cursor_position(column, row))
+}
+
+extern "efiapi" fn enable_cursor(this: *mut simple_text_output::Protocol, visible: efi::Boolean) -> efi::Status {
+    // SAFETY: The function is only installed in the protocol of a console.
+    unsafe { console(this) }.map_or(efi::Status::INVALID_PARAMETER, |console| console.enable_cursor(visible))
+}
+
+#[cfg(test)]
+#[coverage(off)]
+pub(crate) mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::vec;
+
+    /// A GOP drawing in memory.
+    #[repr(C)]
+    pub(crate) struct MockGop {
+        pub(crate) protocol: graphics_output::Protocol,
+        mode: graphics_output::Mode,
+        info: graphics_output::ModeInformation,
+        pub(crate) frame_buffer: Vec<graphics_output::BltPixel>,
+    }
+
+    impl MockGop {
+        pub(crate) fn new(width: u32, height: u32) -> Box<Self> {
+            let mut gop = Box::new(Self {
+                protocol: graphics_output::Protocol {
+                    query_mode: mock_query_mode,
+                    set_mode: mock_set_mode,
+                    blt: mock_blt,
+                    mode: ptr::null_mut(),
+                },
+                mode: graphics_output::Mode {
+                    max_mode: 1,
+                    mode: 0,
+                    info: ptr::null_mut(),
+                    size_of_info: size_of::<graphics_output::ModeInformation>(),
+                    frame_buffer_base: 0,
+                    frame_buffer_size: 0,
+                },
+                info: graphics_output::ModeInformation {
+                    version: 0,
+                    horizontal_resolution: width,
+                    vertical_resolution: height,
+                    pixel_format: graphics_output::PIXEL_BLT_ONLY,
+                    pixel_information: graphics_output::PixelBitmask {
+                        red_mask: 0,
+                        green_mask: 0,
+                        blue_mask: 0,
+                        reserved_mask: 0,
+                    },
+                    pixels_per_scan_line: width,
+                },
+                frame_buffer: vec![color(0x12, 0x34, 0x56); (width * height) as usize],
+            });
+            gop.mode.info = &mut gop.info;
+            gop.protocol.mode = &mut gop.mode;
+            gop
+        }
+
+        /// Returns the (red, green, blue) of a pixel of the screen.
+        pub(crate) fn pixel(&self, x: usize, y: usize) -> (u8, u8, u8) {
+            let pixel = self.frame_buffer[y * self.info.horizontal_resolution as usize + x];
+            (pixel.red, pixel.green, pixel.blue)
+        }
+    }
+
+    extern "efiapi" fn mock_query_mode(
+        _: *mut graphics_output::Protocol,
+        _: u32,
+        _: *mut usize,
+        _: *mut *mut graphics_output::ModeInformation,
+    ) -> efi::Status {
+        efi::Status::UNSUPPORTED
+    }
+
+    extern "efiapi" fn mock_set_mode(_: *mut graphics_output::Protocol, _: u32) -> efi::Status {
+        efi::Status::UNSUPPORTED
+    }
+
+    extern "efiapi" fn mock_blt(
+        this: *mut graphics_output::Protocol,
+        buffer: *mut graphics_output::BltPixel,
+        operation: graphics_output::BltOperation,
+        source_x: usize,
+        source_y: usize,
+        destination_x: usize,
+        destination_y: usize,
+        width: usize,
+        height: usize,
+        delta: usize,
+    ) -> efi::Status {
+        let gop = unsafe { &mut *(this as *mut MockGop) };
+        let screen_width = gop.info.horizontal_resolution as usize;
+        let buffer_width = if delta == 0 { width } else { delta / size_of::<graphics_output::BltPixel>() };
+        let source = match operation {
+            graphics_output::BLT_VIDEO_TO_VIDEO => gop.frame_buffer.clone(),
+            _ => Vec::new(),
+        };
+        for y in 0..height {
+            for x in 0..width {
+                match operation {
+                    graphics_output::BLT_VIDEO_FILL => {
+                        gop.frame_buffer[(destination_y + y) * screen_width + destination_x + x] = unsafe { *buffer }
+                    }
+                    graphics_output::BLT_VIDEO_TO_BLT_BUFFER => unsafe {
+                        *buffer.add((destination_y + y) * buffer_width + destination_x + x) =
+                            gop.frame_buffer[(source_y + y) * screen_width + source_x + x]
+                    },
+                    graphics_output::BLT_BUFFER_TO_VIDEO => {
+                        gop.frame_buffer[(destination_y + y) * screen_width + destination_x + x] =
+                            unsafe { *buffer.add((source_y + y) * buffer_width + source_x + x) }
+                    }
+                    graphics_output::BLT_VIDEO_TO_VIDEO => {
+                        gop.frame_buffer[(destination_y + y) * screen_width + destination_x + x] =
+                            source[(source_y + y) * screen_width + source_x + x]
+                    }
+                    _ => return efi::Status::INVALID_PARAMETER,
+                }
+            }
+        }
+        efi::Status::SUCCESS
+    }
+
+    fn output(console: &mut GopTextOutput, text: &str) -> efi::Status {
+        let mut string: Vec<u16> = text.encode_utf16().chain([0]).collect();
+        output_string(console.protocol(), string.as_mut_ptr())
+    }
+
+    /// Returns whether the cell shows `glyph` with the given (red, green, blue) colors.
+    fn cell_shows(
+        gop: &MockGop,
+        console: &GopTextOutput,
+        (column, row): (usize, usize),
+        glyph: &Glyph,
+        (foreground, background): ((u8, u8, u8), (u8, u8, u8)),
+    ) -> bool {
+        let (cell_x, cell_y) = console.cell(column, row);
+        (0..GLYPH_HEIGHT).all(|y| {
+            (0..GLYPH_WIDTH).all(|x| {
+                let expected = if glyph[y] & (0x80 >> x) != 0 { foreground } else { background };
+                gop.pixel(cell_x + x, cell_y + y) == expected
+            })
+        })
+    }
+
+    const LIGHT_GRAY: (u8, u8, u8) = (0x98, 0x98, 0x98);
+    const BLACK: (u8, u8, u8) = (0, 0, 0);
+    const WHITE: (u8, u8, u8) = (0xFF, 0xFF, 0xFF);
+
+    #[test]
+    fn test_modes_fit_on_the_screen() {
+        assert_eq!(text_modes((640, 480)), vec![Some((80, 25)), None]);
+        assert_eq!(text_modes((800, 600)), vec![Some((80, 25)), None, Some((100, 31))]);
+        assert_eq!(text_modes((1024, 1024)), vec![Some((80, 25)), Some((80, 50)), Some((128, 53))]);
+        assert_eq!(text_modes((320, 200)), vec![None, None]);
+
+        let mut gop = MockGop::new(320, 200);
+        assert_eq!(unsafe { GopTextOutput::new(&mut gop.protocol) }.err(), Some(efi::Status::UNSUPPORTED));
+
+        let mut gop = MockGop::new(800, 600);
+        let mut console = unsafe { GopTextOutput::new(&mut gop.protocol) }.unwrap();
+        let protocol = console.protocol();
+        let (mut columns, mut rows) = (0, 0);
+        assert_eq!(query_mode(protocol, 2, &mut columns, &mut rows), efi::Status::SUCCESS);
+        assert_eq!((columns, rows), (100, 31));
+        assert_eq!(query_mode(protocol, 1, &mut columns, &mut rows), efi::Status::UNSUPPORTED);
+        assert_eq!(query_mode(protocol, 3, &mut columns, &mut rows), efi::Status::UNSUPPORTED);
+        assert_eq!(set_mode(protocol, 1), efi::Status::UNSUPPORTED);
+
+        // The text area is centered, and the whole screen is cleared.
+        assert_eq!(set_mode(protocol, 0), efi::Status::SUCCESS);
+        assert_eq!(console.cell(0, 0), (80, 62));
+        assert_eq!(gop.pixel(0, 0), BLACK);
+        assert_eq!(set_mode(protocol, 2), efi::Status::SUCCESS);
+        assert_eq!(console.cell(0, 0), (0, 5));
+        assert_eq!((console.mode.mode, console.mode.max_mode), (2, 3));
+    }
+
+    #[test]
+    fn test_output_draws_glyphs_with_the_attribute_colors() {
+        let mut gop = MockGop::new(640, 480);
+        let mut console = unsafe { GopTextOutput::new(&mut gop.protocol) }.unwrap();
+        let protocol = console.protocol();
+        assert_eq!(enable_cursor(protocol, efi::Boolean::FALSE), efi::Status::SUCCESS);
+        assert_eq!(reset(protocol, efi::Boolean::FALSE), efi::Status::SUCCESS);
+
+        assert_eq!(output(&mut console, "A"), efi::Status::SUCCESS);
+        assert!(cell_shows(&gop, &console, (0, 0), glyph(b'A' as u16).unwrap(), (LIGHT_GRAY, BLACK)));
+
+        // White on blue.
+        assert_eq!(set_attribute(protocol, 0x1F), efi::Status::SUCCESS);
+        assert_eq!(set_attribute(protocol, 0x80), efi::Status::UNSUPPORTED);
+        assert_eq!(output(&mut console, "g\u{2500}"), efi::Status::SUCCESS);
+        assert!(cell_shows(&gop, &console, (1, 0), glyph(b'g' as u16).unwrap(), (WHITE, (0, 0, 0x98))));
+        assert!(cell_shows(&gop, &console, (2, 0), glyph(0x2500).unwrap(), (WHITE, (0, 0, 0x98))));
+        assert_eq!((console.mode.cursor_column, console.mode.cursor_row), (3, 0));
+
+        // Characters without glyphs are skipped.
+        assert_eq!(output(&mut console, "\u{4E2D}B"), efi::Status::WARN_UNKNOWN_GLYPH);
+        assert!(cell_shows(&gop, &console, (3, 0), glyph(b'B' as u16).unwrap(), (WHITE, (0, 0, 0x98))));
+        let mut string = [0x4E2D, 0];
+        assert_eq!(test_string(protocol, string.as_mut_ptr()), efi::Status::UNSUPPORTED);
+        let mut string = [b'B' as u16, CHAR_CARRIAGE_RETURN, CHAR_LINEFEED, 0];
+        assert_eq!(test_string(protocol, string.as_mut_ptr()), efi::Status::SUCCESS);
+        assert_eq!(output_string(protocol, ptr::null_mut()), efi::Status::INVALID_PARAMETER);
+    }
+
+    #[test]
+    fn test_control_characters_wrapping_and_scrolling() {
+        let mut gop = MockGop::new(640, 480);
+        let mut console = unsafe { GopTextOutput::new(&mut gop.protocol) }.unwrap();
+        let protocol = console.protocol();
+        assert_eq!(enable_cursor(protocol, efi::Boolean::FALSE), efi::Status::SUCCESS);
+        assert_eq!(clear_screen(protocol), efi::Status::SUCCESS);
+
+        assert_eq!(output(&mut console, "\nab\x08\r\n"), efi::Status::SUCCESS);
+        assert_eq!((console.mode.cursor_column, console.mode.cursor_row), (0, 2));
+        assert_eq!(output(&mut console, "\x08"), efi::Status::SUCCESS);
+        assert_eq!((console.mode.cursor_column, console.mode.cursor_row), (0, 2));
+
+        // Output wraps at the end of the row, and the text area scrolls up after the last row.
+        assert_eq!(set_cursor_position(protocol, 80, 0), efi::Status::UNSUPPORTED);
+        assert_eq!(set_cursor_position(protocol, 79, 24), efi::Status::SUCCESS);
+        assert_eq!(output(&mut console, "XY"), efi::Status::SUCCESS);
+        assert_eq!((console.mode.cursor_column, console.mode.cursor_row), (1, 24));
+        let colors = (LIGHT_GRAY, BLACK);
+        assert!(cell_shows(&gop, &console, (79, 23), glyph(b'X' as u16).unwrap(), colors));
+        assert!(cell_shows(&gop, &console, (0, 24), glyph(b'Y' as u16).unwrap(), colors));
+        assert!(cell_shows(&gop, &console, (1, 0), glyph(b'b' as u16).unwrap(), colors));
+        assert!(cell_shows(&gop, &console, (79, 24), glyph(b' ' as u16).unwrap(), colors));
+    }
+
+    #[test]
+    fn test_cursor_is_drawn_inverted() {
+        let mut gop = MockGop::new(640, 480);
+        let mut console = unsafe { GopTextOutput::new(&mut gop.protocol) }.unwrap();
+        let protocol = console.protocol();
+        assert_eq!(clear_screen(protocol), efi::Status::SUCCESS);
+
+        let bottom = |gop: &MockGop, console: &GopTextOutput, column| {
+            let (x, y) = console.cell(column, 0);
+            (gop.pixel(x, y + GLYPH_HEIGHT - 1), gop.pixel(x, y + GLYPH_HEIGHT - 1 - CURSOR_HEIGHT))
+        };
+        assert_eq!(bottom(&gop, &console, 0), (WHITE, BLACK));
+
+        // The cursor follows the output, and is erased from its previous cell.
+        assert_eq!(output(&mut console, "a"), efi::Status::SUCCESS);
+        assert_eq!(bottom(&gop, &console, 0), (BLACK, BLACK));
+        assert_eq!(bottom(&gop, &console, 1), (WHITE, BLACK));
+
+        assert_eq!(enable_cursor(protocol, efi::Boolean::FALSE), efi::Status::SUCCESS);
+        assert_eq!(bottom(&gop, &console, 1), (BLACK, BLACK));
+        assert_eq!(output(&mut console, "a"), efi::Status::SUCCESS);
+        assert_eq!(bottom(&gop, &console, 2), (BLACK, BLACK));
+    }
+}
@@ -0,0 +1,41 @@
+//! Graphics console for Patina platforms.
+//!
+//! Platforms with a display but no serial port need text output drawn on the screen, for the boot messages and the
+//! boot manager menus. This crate provides:
+//!
+//! - [component::GraphicsConsole]: a component installing a driver binding that produces a Simple Text Output
+//!   Protocol on each device with a Graphics Output Protocol (GOP), drawing text with an embedded bitmap font.
+//!
+//! The text output of the devices is added to the console output of the system table by a console splitter, e.g.
+//! `patina_console_splitter`, once the boot manager tags them as console output devices.
+//!
+//! ## Integration Example
+//!
+//! ```rust,ignore
+//! // ...
+//!
+//! Core::default()
+//!  // ...
+//!  .with_component(patina_graphics_console::component::GraphicsConsole)
+//!  .with_component(patina_console_splitter::component::ConsoleSplitter)
+//!  .start()
+//!  .unwrap();
+//!
+//! // ...
+//! ```
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+#![cfg_attr(not(test), no_std)]
+#![feature(coverage_attribute)]
+
+extern crate alloc;
+
+pub mod component;
+mod console;
+mod font;
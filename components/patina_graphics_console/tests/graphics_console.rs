@@ -0,0 +1,191 @@
+//! Integration tests connecting a graphics device to the graphics console against the host DXE core boot services.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use core::{ffi::c_void, mem, ptr};
+
+use patina::boot_services::{BootServices, StandardBootServices};
+use patina_graphics_console::component::GraphicsConsole;
+use patina_test::TestHarness;
+use r_efi::efi::{
+    self,
+    protocols::{device_path, graphics_output, simple_text_output},
+};
+
+const WIDTH: usize = 640;
+const HEIGHT: usize = 480;
+
+/// A graphics device drawing in memory.
+#[repr(C)]
+struct TestGop {
+    protocol: graphics_output::Protocol,
+    mode: graphics_output::Mode,
+    info: graphics_output::ModeInformation,
+    frame_buffer: Vec<u32>,
+}
+
+extern "efiapi" fn gop_query_mode(
+    _: *mut graphics_output::Protocol,
+    _: u32,
+    _: *mut usize,
+    _: *mut *mut graphics_output::ModeInformation,
+) -> efi::Status {
+    efi::Status::UNSUPPORTED
+}
+
+extern "efiapi" fn gop_set_mode(_: *mut graphics_output::Protocol, _: u32) -> efi::Status {
+    efi::Status::UNSUPPORTED
+}
+
+/// Supports the fill and buffer operations used by the graphics console, with the buffer rows the rectangle width.
+extern "efiapi" fn gop_blt(
+    this: *mut graphics_output::Protocol,
+    buffer: *mut graphics_output::BltPixel,
+    operation: graphics_output::BltOperation,
+    source_x: usize,
+    source_y: usize,
+    destination_x: usize,
+    destination_y: usize,
+    width: usize,
+    height: usize,
+    _: usize,
+) -> efi::Status {
+    // SAFETY: The protocol is the first field of a TestGop.
+    let device = unsafe { &mut *(this as *mut TestGop) };
+    let pack = |pixel: graphics_output::BltPixel| u32::from_le_bytes([pixel.blue, pixel.green, pixel.red, 0]);
+    for y in 0..height {
+        for x in 0..width {
+            // SAFETY: The buffer is provided by the caller, with a pixel for each pixel of the rectangle.
+            unsafe {
+                match operation {
+                    graphics_output::BLT_VIDEO_FILL => {
+                        device.frame_buffer[(destination_y + y) * WIDTH + destination_x + x] = pack(*buffer)
+                    }
+                    graphics_output::BLT_BUFFER_TO_VIDEO => {
+                        device.frame_buffer[(destination_y + y) * WIDTH + destination_x + x] =
+                            pack(*buffer.add((source_y + y) * width + source_x + x))
+                    }
+                    graphics_output::BLT_VIDEO_TO_BLT_BUFFER => {
+                        let [blue, green, red, _] =
+                            device.frame_buffer[(source_y + y) * WIDTH + source_x + x].to_le_bytes();
+                        buffer.add((destination_y + y) * width + destination_x + x).write(graphics_output::BltPixel {
+                            blue,
+                            green,
+                            red,
+                            reserved: 0,
+                        });
+                    }
+                    _ => return efi::Status::UNSUPPORTED,
+                }
+            }
+        }
+    }
+    efi::Status::SUCCESS
+}
+
+/// Installs `protocols` on a new handle.
+fn install(boot_services: &StandardBootServices, protocols: &[(&'static efi::Guid, *mut c_void)]) -> efi::Handle {
+    let mut handle = None;
+    for &(protocol, interface) in protocols {
+        // SAFETY: The interfaces are leaked and match their protocol GUIDs.
+        handle =
+            Some(unsafe { boot_services.install_protocol_interface_unchecked(handle, protocol, interface) }.unwrap());
+    }
+    handle.unwrap()
+}
+
+#[test]
+fn test_text_is_drawn_on_the_gop() {
+    let mut harness = TestHarness::new().with_component(GraphicsConsole);
+    let boot_services = harness.boot_services();
+    harness.run().unwrap();
+    assert!(harness.pending_components().is_empty());
+
+    let gop = Box::leak(Box::new(TestGop {
+        protocol: graphics_output::Protocol {
+            query_mode: gop_query_mode,
+            set_mode: gop_set_mode,
+            blt: gop_blt,
+            mode: ptr::null_mut(),
+        },
+        mode: graphics_output::Mode {
+            max_mode: 1,
+            mode: 0,
+            info: ptr::null_mut(),
+            size_of_info: mem::size_of::<graphics_output::ModeInformation>(),
+            frame_buffer_base: 0,
+            frame_buffer_size: 0,
+        },
+        info: graphics_output::ModeInformation {
+            version: 0,
+            horizontal_resolution: WIDTH as u32,
+            vertical_resolution: HEIGHT as u32,
+            pixel_format: graphics_output::PIXEL_BLT_ONLY,
+            pixel_information: graphics_output::PixelBitmask {
+                red_mask: 0,
+                green_mask: 0,
+                blue_mask: 0,
+                reserved_mask: 0,
+            },
+            pixels_per_scan_line: WIDTH as u32,
+        },
+        frame_buffer: vec![0x00FF_FFFF; WIDTH * HEIGHT],
+    }));
+    gop.mode.info = &mut gop.info;
+    gop.protocol.mode = &mut gop.mode;
+    let device_path = Box::leak(Box::new(device_path::Protocol {
+        r#type: device_path::TYPE_END,
+        sub_type: device_path::End::SUBTYPE_ENTIRE,
+        length: [4, 0],
+    }));
+
+    // The virtual GOP of a console splitter has no device path, and is not supported.
+    let virtual_handle =
+        install(&boot_services, &[(&graphics_output::PROTOCOL_GUID, &mut gop.protocol as *mut _ as *mut c_void)]);
+    // SAFETY: No remaining device path is given.
+    let _ = unsafe { boot_services.connect_controller(virtual_handle, Vec::new(), ptr::null_mut(), false) };
+    assert!(unsafe { boot_services.handle_protocol::<simple_text_output::Protocol>(virtual_handle) }.is_err());
+
+    let handle = install(
+        &boot_services,
+        &[
+            (&graphics_output::PROTOCOL_GUID, &mut gop.protocol as *mut _ as *mut c_void),
+            (&device_path::PROTOCOL_GUID, device_path as *mut _ as *mut c_void),
+        ],
+    );
+    // SAFETY: No remaining device path is given.
+    unsafe { boot_services.connect_controller(handle, Vec::new(), ptr::null_mut(), false) }.unwrap();
+
+    // The screen is left as is until the console is used.
+    // SAFETY: The text output is installed by the graphics console.
+    let text_out = unsafe { boot_services.handle_protocol::<simple_text_output::Protocol>(handle) }.unwrap();
+    assert!(gop.frame_buffer.iter().all(|pixel| *pixel == 0x00FF_FFFF));
+
+    assert_eq!((text_out.reset)(text_out, efi::Boolean::FALSE), efi::Status::SUCCESS);
+    let mut string: Vec<u16> = "Hi\r\n".encode_utf16().chain([0]).collect();
+    assert_eq!((text_out.output_string)(text_out, string.as_mut_ptr()), efi::Status::SUCCESS);
+    // SAFETY: The mode of the text output is valid while it is installed.
+    let mode = unsafe { &*text_out.mode };
+    assert_eq!((mode.cursor_column, mode.cursor_row, mode.max_mode), (0, 1, 2));
+
+    // The 80x25 text area is centered on the screen, and the text is drawn light gray on black.
+    let (origin_x, origin_y) = ((WIDTH - 80 * 8) / 2, (HEIGHT - 25 * 19) / 2);
+    let lit = |column: usize| {
+        (0..19)
+            .flat_map(|y| (0..8).map(move |x| (x, y)))
+            .filter(|(x, y)| gop.frame_buffer[(origin_y + y) * WIDTH + origin_x + column * 8 + x] == 0x0098_9898)
+            .count()
+    };
+    assert!(lit(0) > 0 && lit(1) > 0 && lit(0) != lit(1));
+    assert_eq!(lit(2), 0);
+    // The rest of the screen is cleared, except for the cursor, drawn white by inverting black.
+    assert!(gop.frame_buffer.iter().all(|pixel| [0, 0x0098_9898, 0x00FF_FFFF].contains(pixel)));
+
+    // The text output is uninstalled when the device is disconnected.
+    boot_services.disconnect_controller(handle, None, None).unwrap();
+    assert!(unsafe { boot_services.handle_protocol::<simple_text_output::Protocol>(handle) }.is_err());
+}
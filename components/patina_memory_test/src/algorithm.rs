@@ -0,0 +1,261 @@
+//! Memory Test Algorithms
+//!
+//! The algorithms read and write the memory through [TestMemory], one 64-bit word at a time, and collect the pages in
+//! which a word did not hold the value last written to it.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::collections::BTreeSet;
+use core::{mem, ptr};
+
+use patina::base::UEFI_PAGE_SIZE;
+
+use crate::config::MemoryTestCoverage;
+
+/// The number of words in a page.
+const WORDS_PER_PAGE: usize = UEFI_PAGE_SIZE / mem::size_of::<u64>();
+
+/// Alternating ones and zeros, so that every bit differs from its neighbours.
+const CHECKERBOARD: u64 = 0x5555_5555_5555_5555;
+
+/// Memory under test, accessed as an array of 64-bit words.
+pub(crate) trait TestMemory {
+    /// Returns the number of words of the memory.
+    fn len(&self) -> usize;
+
+    /// Reads the word at `index`.
+    fn read(&self, index: usize) -> u64;
+
+    /// Writes the word at `index`.
+    fn write(&mut self, index: usize, value: u64);
+}
+
+/// A range of physical memory, accessed with volatile reads and writes so that every access reaches the memory.
+pub(crate) struct PhysicalMemory {
+    base: *mut u64,
+    len: usize,
+}
+
+impl PhysicalMemory {
+    /// Creates the memory for `pages` pages at `address`.
+    ///
+    /// # Safety
+    ///
+    /// `address` must be a non-null page aligned address, and the range must be owned by the caller (e.g. allocated
+    /// by it) and hold no data in use while the [PhysicalMemory] exists.
+    pub(crate) unsafe fn new(address: usize, pages: usize) -> Self {
+        Self { base: address as *mut u64, len: pages * WORDS_PER_PAGE }
+    }
+}
+
+impl TestMemory for PhysicalMemory {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn read(&self, index: usize) -> u64 {
+        assert!(index < self.len);
+        // SAFETY: The word is in the range owned by the caller of new().
+        unsafe { ptr::read_volatile(self.base.add(index)) }
+    }
+
+    fn write(&mut self, index: usize, value: u64) {
+        assert!(index < self.len);
+        // SAFETY: The word is in the range owned by the caller of new().
+        unsafe { ptr::write_volatile(self.base.add(index), value) }
+    }
+}
+
+/// Tests the memory with the given coverage, and returns the indices of the pages in which a fault was detected.
+///
+/// The previous content of the memory is lost.
+pub(crate) fn test_memory(memory: &mut impl TestMemory, coverage: MemoryTestCoverage) -> BTreeSet<usize> {
+    let mut failures = BTreeSet::new();
+    match coverage {
+        MemoryTestCoverage::Disabled => (),
+        MemoryTestCoverage::Quick => quick_test(memory, &mut failures),
+        MemoryTestCoverage::Full => {
+            for background in [0, CHECKERBOARD] {
+                march_c_minus(memory, background, &mut failures);
+            }
+        }
+    }
+    failures
+}
+
+/// Records the page of the word at `index` if it does not hold `expected`.
+fn verify(memory: &impl TestMemory, index: usize, expected: u64, failures: &mut BTreeSet<usize>) {
+    if memory.read(index) != expected {
+        failures.insert(index / WORDS_PER_PAGE);
+    }
+}
+
+/// Writes and verifies the checkerboard patterns, which detect stuck bits and shorts between adjacent bits, then the
+/// index of each word, which detects words aliased by a faulty address line.
+fn quick_test(memory: &mut impl TestMemory, failures: &mut BTreeSet<usize>) {
+    let len = memory.len();
+    for pattern in [CHECKERBOARD, !CHECKERBOARD] {
+        (0..len).for_each(|index| memory.write(index, pattern));
+        (0..len).for_each(|index| verify(memory, index, pattern, failures));
+    }
+    (0..len).for_each(|index| memory.write(index, index as u64));
+    (0..len).for_each(|index| verify(memory, index, index as u64, failures));
+}
+
+/// The March C- test: ⇕(w0); ⇑(r0, w1); ⇑(r1, w0); ⇓(r0, w1); ⇓(r1, w0); ⇕(r0), where 0 is the data `background`
+/// and 1 its complement.
+///
+/// Besides stuck and address faults, it detects transition faults and the coupling faults between any two words,
+/// whichever their order in memory.
+fn march_c_minus(memory: &mut impl TestMemory, background: u64, failures: &mut BTreeSet<usize>) {
+    let len = memory.len();
+    let (zero, one) = (background, !background);
+    (0..len).for_each(|index| memory.write(index, zero));
+    for (expected, value) in [(zero, one), (one, zero)] {
+        for index in 0..len {
+            verify(memory, index, expected, failures);
+            memory.write(index, value);
+        }
+    }
+    for (expected, value) in [(zero, one), (one, zero)] {
+        for index in (0..len).rev() {
+            verify(memory, index, expected, failures);
+            memory.write(index, value);
+        }
+    }
+    (0..len).for_each(|index| verify(memory, index, zero, failures));
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use alloc::{vec, vec::Vec};
+
+    /// Memory with an optional fault.
+    struct FaultyMemory {
+        words: Vec<u64>,
+        fault: Fault,
+    }
+
+    enum Fault {
+        None,
+        /// The bit of the word always reads as the given value.
+        StuckAt {
+            index: usize,
+            bit: u32,
+            value: bool,
+        },
+        /// Accesses to the first word reach the second word, as if an address line was stuck.
+        Alias {
+            index: usize,
+            alias: usize,
+        },
+        /// A 0 to 1 transition of bit 0 of the aggressor inverts bit 0 of the victim.
+        Coupling {
+            aggressor: usize,
+            victim: usize,
+        },
+    }
+
+    impl FaultyMemory {
+        fn new(pages: usize, fault: Fault) -> Self {
+            Self { words: vec![0; pages * WORDS_PER_PAGE], fault }
+        }
+
+        /// Returns the word accessed for `index`.
+        fn decode(&self, index: usize) -> usize {
+            match self.fault {
+                Fault::Alias { index: aliased, alias } if aliased == index => alias,
+                _ => index,
+            }
+        }
+    }
+
+    impl TestMemory for FaultyMemory {
+        fn len(&self) -> usize {
+            self.words.len()
+        }
+
+        fn read(&self, index: usize) -> u64 {
+            let word = self.words[self.decode(index)];
+            match self.fault {
+                Fault::StuckAt { index: stuck, bit, value: true } if stuck == index => word | 1 << bit,
+                Fault::StuckAt { index: stuck, bit, value: false } if stuck == index => word & !(1 << bit),
+                _ => word,
+            }
+        }
+
+        fn write(&mut self, index: usize, value: u64) {
+            let index = self.decode(index);
+            if let Fault::Coupling { aggressor, victim } = self.fault
+                && aggressor == index
+                && self.words[index] & 1 == 0
+                && value & 1 == 1
+            {
+                self.words[victim] ^= 1;
+            }
+            self.words[index] = value;
+        }
+    }
+
+    #[test]
+    fn test_healthy_memory_passes() {
+        for coverage in [MemoryTestCoverage::Disabled, MemoryTestCoverage::Quick, MemoryTestCoverage::Full] {
+            assert!(test_memory(&mut FaultyMemory::new(2, Fault::None), coverage).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_stuck_bits_are_detected() {
+        for value in [false, true] {
+            let fault = || Fault::StuckAt { index: WORDS_PER_PAGE + 3, bit: 17, value };
+            for coverage in [MemoryTestCoverage::Quick, MemoryTestCoverage::Full] {
+                let failures = test_memory(&mut FaultyMemory::new(3, fault()), coverage);
+                assert_eq!(failures.into_iter().collect::<Vec<_>>(), [1]);
+            }
+            assert!(test_memory(&mut FaultyMemory::new(3, fault()), MemoryTestCoverage::Disabled).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_address_faults_are_detected() {
+        let fault = || Fault::Alias { index: 2, alias: 2 * WORDS_PER_PAGE + 2 };
+        let failures = test_memory(&mut FaultyMemory::new(3, fault()), MemoryTestCoverage::Quick);
+        assert_eq!(failures.into_iter().collect::<Vec<_>>(), [0]);
+        // The March test also finds the alias changing when the aliased word is written.
+        let failures = test_memory(&mut FaultyMemory::new(3, fault()), MemoryTestCoverage::Full);
+        assert_eq!(failures.into_iter().collect::<Vec<_>>(), [0, 2]);
+    }
+
+    #[test]
+    fn test_coupling_faults_are_detected_by_the_full_test() {
+        // The victim is written after the aggressor in each pass of the quick test, which masks the fault.
+        let fault = || Fault::Coupling { aggressor: 1, victim: WORDS_PER_PAGE };
+        assert!(test_memory(&mut FaultyMemory::new(2, fault()), MemoryTestCoverage::Quick).is_empty());
+        let failures = test_memory(&mut FaultyMemory::new(2, fault()), MemoryTestCoverage::Full);
+        assert_eq!(failures.into_iter().collect::<Vec<_>>(), [1]);
+
+        let fault = Fault::Coupling { aggressor: WORDS_PER_PAGE, victim: 1 };
+        let failures = test_memory(&mut FaultyMemory::new(2, fault), MemoryTestCoverage::Full);
+        assert_eq!(failures.into_iter().collect::<Vec<_>>(), [0]);
+    }
+
+    #[test]
+    fn test_physical_memory_accesses_the_range() {
+        let mut buffer = vec![0_u64; 2 * WORDS_PER_PAGE];
+        let mut memory = unsafe { PhysicalMemory::new(buffer.as_mut_ptr() as usize, 2) };
+        assert_eq!(memory.len(), 2 * WORDS_PER_PAGE);
+        memory.write(WORDS_PER_PAGE, 0x1234);
+        assert_eq!(memory.read(WORDS_PER_PAGE), 0x1234);
+        assert!(test_memory(&mut memory, MemoryTestCoverage::Full).is_empty());
+        // The test leaves the last data background in the memory.
+        assert_eq!(buffer[0], CHECKERBOARD);
+    }
+}
@@ -0,0 +1,449 @@
+//! Patina Memory Test Component
+//!
+//! Tests the free system memory once during boot, with the coverage selected by the [MemoryTestConfig]. The free
+//! conventional memory of the memory map is tested in chunks: each chunk is taken offline by allocating it at its
+//! address, which removes it from the free memory of the GCD, then tested and freed.
+//!
+//! The pages in which a fault is found are allocated again as unusable memory, so that they are never used and are
+//! reported as unusable in the memory map handed to the operating system, and are described by CPER records saved as
+//! hardware error record variables. The progress of the test and the failures are reported as status codes.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use alloc::{collections::BTreeSet, format, vec::Vec};
+use patina::{
+    base::{UEFI_PAGE_MASK, UEFI_PAGE_SIZE, ucs2::Ucs2String},
+    boot_services::{
+        BootServices, StandardBootServices,
+        allocation::{AllocType, MemoryType},
+    },
+    component::{IntoComponent, params::Config},
+    error::EfiError,
+    runtime_services::{
+        RuntimeServices, StandardRuntimeServices,
+        typed_variable::{TypedVariableServices, VariableAttributes},
+    },
+    uefi_protocol::status_code::StatusCodeRuntimeProtocol,
+};
+use patina_pi::status_code::{
+    EFI_COMPUTING_UNIT_MEMORY, EFI_CU_MEMORY_EC_UNCORRECTABLE, EFI_CU_MEMORY_PC_TEST, EFI_ERROR_CODE, EFI_ERROR_MAJOR,
+    EFI_PROGRESS_CODE,
+};
+use r_efi::{efi, system::HARDWARE_ERROR_VARIABLE_GUID};
+
+use crate::{
+    algorithm::{self, PhysicalMemory},
+    config::{MemoryTestConfig, MemoryTestCoverage},
+    cper::{CPER_SEVERITY_RECOVERABLE, MEMORY_ERROR_SCRUB_UNCORRECTED, MemoryErrorRecord},
+};
+
+/// The caller ID of the status codes reported by the memory test, and the creator ID of its error records.
+pub const MEMORY_TEST_GUID: efi::Guid = patina::guid!("4964370B-F07F-4657-82A9-C5EFA29D803D");
+
+/// The data type of the status codes reported by the memory test, whose data is a [MemoryRangeData].
+pub const MEMORY_RANGE_DATA_GUID: efi::Guid = patina::guid!("4CB21389-F4A6-4BD0-B41A-85D7AB250D60");
+
+/// The maximum number of error records saved during a boot, as the storage of the hardware error records is small.
+const MAX_ERROR_RECORDS: usize = 16;
+
+/// The data of the status codes reported by the memory test: the range being tested for progress codes, and the
+/// failing range for error codes.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryRangeData {
+    /// The address of the range.
+    pub start: u64,
+    /// The size of the range in bytes.
+    pub length: u64,
+}
+
+/// Memory Test Component.
+#[derive(IntoComponent)]
+pub struct MemoryTest;
+
+impl MemoryTest {
+    /// Entry point of [`MemoryTest`]
+    #[coverage(off)] // This is tested via the generic version, see _entry_point.
+    pub fn entry_point(
+        self,
+        config: Config<MemoryTestConfig>,
+        boot_services: StandardBootServices,
+        runtime_services: StandardRuntimeServices,
+    ) -> Result<(), EfiError> {
+        self._entry_point(&boot_services, &runtime_services, &config)
+    }
+
+    /// Entry point that have generic parameter.
+    fn _entry_point<B, R>(
+        self,
+        boot_services: &B,
+        runtime_services: &R,
+        config: &MemoryTestConfig,
+    ) -> Result<(), EfiError>
+    where
+        B: BootServices,
+        R: RuntimeServices,
+    {
+        if config.coverage == MemoryTestCoverage::Disabled {
+            log::info!("Memory test: disabled.");
+            return Ok(());
+        }
+        let chunk_pages = config.chunk_size / UEFI_PAGE_SIZE;
+        if chunk_pages == 0 {
+            log::error!("Memory test: the chunk size {:#x} is smaller than a page.", config.chunk_size);
+            return Err(EfiError::InvalidParameter);
+        }
+
+        // The memory map is released before testing, so that its buffer is not in use while the ranges are tested.
+        let ranges = {
+            let memory_map = boot_services.get_memory_map().map_err(|(status, _)| EfiError::from(status))?;
+            free_ranges(&memory_map.descriptors, chunk_pages)
+        };
+
+        let mut reporter = Reporter::new(boot_services, runtime_services);
+        let (mut tested, mut skipped, mut failed) = (0, 0, 0);
+        for (address, pages) in ranges {
+            reporter.report_progress(address, pages);
+            match test_range(boot_services, address, pages, config.coverage) {
+                Ok(failures) => {
+                    tested += pages;
+                    failed += failures.len();
+                    retire_pages(boot_services, &mut reporter, address, &failures);
+                }
+                Err(status) => {
+                    // The range was allocated since the memory map was retrieved, e.g. by the memory map itself.
+                    log::debug!("Memory test: skipping {pages:#x} pages at {address:#x}: {status:?}.");
+                    skipped += pages;
+                }
+            }
+        }
+
+        log::info!(
+            "Memory test: {:?} test of {tested:#x} pages complete, {failed} failed, {skipped:#x} skipped.",
+            config.coverage
+        );
+        Ok(())
+    }
+}
+
+/// Splits the free conventional memory of the memory map into ranges of at most `chunk_pages` pages, as
+/// `(address, pages)` pairs.
+///
+/// The first page of memory is never tested, as it cannot be accessed through a pointer.
+fn free_ranges(descriptors: &[efi::MemoryDescriptor], chunk_pages: usize) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    for descriptor in descriptors.iter().filter(|descriptor| descriptor.r#type == efi::CONVENTIONAL_MEMORY) {
+        let mut address = descriptor.physical_start as usize;
+        let mut pages = descriptor.number_of_pages as usize;
+        if address == 0 && pages > 0 {
+            address += UEFI_PAGE_SIZE;
+            pages -= 1;
+        }
+        while pages > 0 {
+            let chunk = pages.min(chunk_pages);
+            ranges.push((address, chunk));
+            address += chunk * UEFI_PAGE_SIZE;
+            pages -= chunk;
+        }
+    }
+    ranges
+}
+
+/// Takes the range offline, tests it and frees it, returning the indices of the failing pages in the range.
+fn test_range<B: BootServices>(
+    boot_services: &B,
+    address: usize,
+    pages: usize,
+    coverage: MemoryTestCoverage,
+) -> Result<BTreeSet<usize>, efi::Status> {
+    boot_services.allocate_pages(AllocType::Address(address), MemoryType::BOOT_SERVICES_DATA, pages)?;
+    // SAFETY: The range has just been allocated at a non-null page aligned address, and holds no data.
+    let failures = algorithm::test_memory(&mut unsafe { PhysicalMemory::new(address, pages) }, coverage);
+    boot_services.free_pages(address, pages)?;
+    Ok(failures)
+}
+
+/// Allocates the failing pages of the range at `address` as unusable memory, and reports them.
+fn retire_pages<B: BootServices, R: RuntimeServices>(
+    boot_services: &B,
+    reporter: &mut Reporter<R>,
+    address: usize,
+    failures: &BTreeSet<usize>,
+) {
+    let mut failures = failures.iter().copied().peekable();
+    while let Some(first) = failures.next() {
+        let mut last = first;
+        while failures.next_if_eq(&(last + 1)).is_some() {
+            last += 1;
+        }
+        let start = address + first * UEFI_PAGE_SIZE;
+        let pages = last - first + 1;
+        log::error!("Memory test: {pages:#x} pages failed at {start:#x}.");
+        if let Err(status) = boot_services.allocate_pages(AllocType::Address(start), MemoryType::UNUSABLE_MEMORY, pages)
+        {
+            log::error!("Memory test: failed to take {pages:#x} pages at {start:#x} out of use: {status:?}.");
+        }
+        reporter.report_failure(start, pages);
+    }
+}
+
+/// Reports the progress and failures of the memory test as status codes and error records.
+struct Reporter<'a, R> {
+    status_code: Option<&'static StatusCodeRuntimeProtocol>,
+    runtime_services: &'a R,
+    /// The index of the next hardware error record variable to try, if records can still be saved.
+    next_record: Option<u16>,
+    records: usize,
+}
+
+impl<'a, R: RuntimeServices> Reporter<'a, R> {
+    fn new<B: BootServices>(boot_services: &B, runtime_services: &'a R) -> Self {
+        // SAFETY: The Status Code Runtime Protocol interface matches [StatusCodeRuntimeProtocol].
+        let status_code = unsafe { boot_services.locate_protocol::<StatusCodeRuntimeProtocol>(None) }.ok();
+        if status_code.is_none() {
+            log::trace!("Status Code Runtime Protocol not found, the memory test is not reported as status codes.");
+        }
+        Self { status_code: status_code.map(|protocol| &*protocol), runtime_services, next_record: Some(0), records: 0 }
+    }
+
+    /// Reports that the range is being tested.
+    fn report_progress(&self, address: usize, pages: usize) {
+        self.report(EFI_PROGRESS_CODE, EFI_COMPUTING_UNIT_MEMORY | EFI_CU_MEMORY_PC_TEST, address, pages);
+    }
+
+    /// Reports that the range failed, and saves an error record for each of its pages.
+    fn report_failure(&mut self, address: usize, pages: usize) {
+        self.report(
+            EFI_ERROR_CODE | EFI_ERROR_MAJOR,
+            EFI_COMPUTING_UNIT_MEMORY | EFI_CU_MEMORY_EC_UNCORRECTABLE,
+            address,
+            pages,
+        );
+        for page in 0..pages {
+            self.save_record(address + page * UEFI_PAGE_SIZE);
+        }
+    }
+
+    fn report(&self, code_type: u32, value: u32, address: usize, pages: usize) {
+        let Some(status_code) = self.status_code else {
+            return;
+        };
+        let data = MemoryRangeData { start: address as u64, length: (pages * UEFI_PAGE_SIZE) as u64 };
+        if let Err(status) = status_code.report_status_code_with_data(
+            code_type,
+            value,
+            0,
+            &MEMORY_TEST_GUID,
+            MEMORY_RANGE_DATA_GUID,
+            data,
+        ) {
+            log::error!("Memory test: failed to report a status code: {status:?}.");
+        }
+    }
+
+    /// Saves an error record for the page at `address` in the first free `HwErrRec####` variable.
+    fn save_record(&mut self, address: usize) {
+        debug_assert_eq!(address & UEFI_PAGE_MASK, 0);
+        if self.records == MAX_ERROR_RECORDS {
+            log::warn!("Memory test: no error record saved for {address:#x}, the maximum was reached.");
+            return;
+        }
+        let attributes = VariableAttributes::NON_VOLATILE
+            | VariableAttributes::BOOTSERVICE_ACCESS
+            | VariableAttributes::RUNTIME_ACCESS
+            | VariableAttributes::HARDWARE_ERROR_RECORD;
+        while let Some(index) = self.next_record {
+            self.next_record = index.checked_add(1);
+            let Ok(name) = Ucs2String::try_from(format!("HwErrRec{index:04X}").as_str()) else {
+                continue;
+            };
+            match self.runtime_services.get_variable_slice::<u8>(&name, &HARDWARE_ERROR_VARIABLE_GUID) {
+                Ok(_) => continue,
+                Err(efi::Status::NOT_FOUND) => (),
+                Err(status) => {
+                    log::error!("Memory test: error records cannot be saved: {status:?}.");
+                    self.next_record = None;
+                    return;
+                }
+            }
+            let record = MemoryErrorRecord::new(
+                MEMORY_TEST_GUID,
+                index as u64,
+                CPER_SEVERITY_RECOVERABLE,
+                address as u64,
+                MEMORY_ERROR_SCRUB_UNCORRECTED,
+            );
+            match self.runtime_services.set_variable_slice(
+                &name,
+                &HARDWARE_ERROR_VARIABLE_GUID,
+                attributes,
+                record.as_bytes(),
+            ) {
+                Ok(()) => self.records += 1,
+                Err(status) => {
+                    log::error!("Memory test: failed to save the error record {name}: {status:?}.");
+                    self.next_record = None;
+                }
+            }
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use patina::{boot_services::MockBootServices, runtime_services::MockRuntimeServices};
+    use patina_pi::protocols::status_code::EfiStatusCodeData;
+    use std::{
+        alloc::{Layout, alloc_zeroed},
+        boxed::Box,
+        vec,
+    };
+
+    fn descriptor(r#type: u32, physical_start: u64, number_of_pages: u64) -> efi::MemoryDescriptor {
+        efi::MemoryDescriptor { r#type, physical_start, virtual_start: 0, number_of_pages, attribute: 0 }
+    }
+
+    #[test]
+    fn test_free_ranges_are_split_into_chunks() {
+        let descriptors = [
+            descriptor(efi::CONVENTIONAL_MEMORY, 0, 3),
+            descriptor(efi::BOOT_SERVICES_DATA, 0x3000, 1),
+            descriptor(efi::CONVENTIONAL_MEMORY, 0x10_0000, 5),
+            descriptor(efi::UNUSABLE_MEMORY, 0x20_0000, 5),
+            descriptor(efi::CONVENTIONAL_MEMORY, 0x30_0000, 2),
+        ];
+        assert_eq!(
+            free_ranges(&descriptors, 2),
+            [(0x1000, 2), (0x10_0000, 2), (0x10_2000, 2), (0x10_4000, 1), (0x30_0000, 2)]
+        );
+    }
+
+    #[test]
+    fn test_disabled_test_does_nothing() {
+        let config = MemoryTestConfig::default();
+        assert_eq!(MemoryTest._entry_point(&MockBootServices::new(), &MockRuntimeServices::new(), &config), Ok(()));
+
+        let config = MemoryTestConfig { coverage: MemoryTestCoverage::Quick, chunk_size: 0x800 };
+        assert_eq!(
+            MemoryTest._entry_point(&MockBootServices::new(), &MockRuntimeServices::new(), &config),
+            Err(EfiError::InvalidParameter)
+        );
+    }
+
+    #[test]
+    fn test_range_is_taken_offline_while_tested() {
+        let layout = Layout::from_size_align(2 * UEFI_PAGE_SIZE, UEFI_PAGE_SIZE).unwrap();
+        let address = unsafe { alloc_zeroed(layout) } as usize;
+
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_allocate_pages().once().returning(move |alloc_type, memory_type, pages| {
+            assert_eq!(alloc_type, AllocType::Address(address));
+            assert_eq!(memory_type, MemoryType::BOOT_SERVICES_DATA);
+            assert_eq!(pages, 2);
+            Ok(address)
+        });
+        boot_services.expect_free_pages().once().returning(move |freed, pages| {
+            assert_eq!((freed, pages), (address, 2));
+            Ok(())
+        });
+        assert!(test_range(&boot_services, address, 2, MemoryTestCoverage::Full).unwrap().is_empty());
+
+        // A range that is no longer free is not tested.
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_allocate_pages().once().returning(|_, _, _| Err(efi::Status::NOT_FOUND));
+        assert_eq!(test_range(&boot_services, address, 2, MemoryTestCoverage::Quick), Err(efi::Status::NOT_FOUND));
+    }
+
+    static REPORTED: AtomicUsize = AtomicUsize::new(0);
+
+    extern "efiapi" fn mock_report_status_code(
+        code_type: u32,
+        value: u32,
+        _instance: u32,
+        caller_id: *const efi::Guid,
+        data: *const EfiStatusCodeData,
+    ) -> efi::Status {
+        assert_eq!(code_type, EFI_ERROR_CODE | EFI_ERROR_MAJOR);
+        assert_eq!(value, EFI_COMPUTING_UNIT_MEMORY | EFI_CU_MEMORY_EC_UNCORRECTABLE);
+        assert_eq!(unsafe { *caller_id }, MEMORY_TEST_GUID);
+        // The data is reported in a byte buffer, so it may not be aligned.
+        let header = unsafe { data.read_unaligned() };
+        assert_eq!(header.r#type, MEMORY_RANGE_DATA_GUID);
+        let range = unsafe { (data.byte_add(header.header_size as usize) as *const MemoryRangeData).read_unaligned() };
+        assert_eq!(range.start & UEFI_PAGE_MASK as u64, 0);
+        REPORTED.fetch_add(range.length as usize / UEFI_PAGE_SIZE, Ordering::SeqCst);
+        efi::Status::SUCCESS
+    }
+
+    #[test]
+    fn test_failing_pages_are_retired_and_reported() {
+        let mut boot_services = MockBootServices::new();
+        boot_services
+            .expect_locate_protocol::<StatusCodeRuntimeProtocol>()
+            .once()
+            .returning(|_| Ok(Box::leak(Box::new(StatusCodeRuntimeProtocol::new(mock_report_status_code)))));
+        let mut allocations = vec![(0x10_1000, 2), (0x10_5000, 1)].into_iter();
+        boot_services.expect_allocate_pages().times(2).returning(move |alloc_type, memory_type, pages| {
+            let (address, expected_pages) = allocations.next().unwrap();
+            assert_eq!(alloc_type, AllocType::Address(address));
+            assert_eq!(memory_type, MemoryType::UNUSABLE_MEMORY);
+            assert_eq!(pages, expected_pages);
+            Ok(address)
+        });
+
+        // HwErrRec0000 is already used, the records are saved in the next variables.
+        let mut runtime_services = MockRuntimeServices::new();
+        runtime_services.expect_get_variable::<Vec<u8>>().times(4).returning(|name, namespace, _| {
+            assert_eq!(namespace, &HARDWARE_ERROR_VARIABLE_GUID);
+            match Ucs2String::from_vec_with_nul(name.to_vec()).unwrap() {
+                name if name == "HwErrRec0000" => Ok((vec![0; 8], 0xF)),
+                _ => Err(efi::Status::NOT_FOUND),
+            }
+        });
+        let mut saved = Vec::new();
+        runtime_services.expect_set_variable::<Vec<u8>>().times(3).returning(move |name, _, attributes, data| {
+            assert_eq!(attributes, 0xF);
+            assert_eq!(data.len(), 280);
+            let address = u64::from_le_bytes(data[216..224].try_into().unwrap());
+            saved.push((Ucs2String::from_vec_with_nul(name.to_vec()).unwrap().to_string(), address));
+            let expected = [
+                ("HwErrRec0001".to_string(), 0x10_1000),
+                ("HwErrRec0002".to_string(), 0x10_2000),
+                ("HwErrRec0003".to_string(), 0x10_5000),
+            ];
+            assert_eq!(saved[..], expected[..saved.len()]);
+            Ok(())
+        });
+
+        let mut reporter = Reporter::new(&boot_services, &runtime_services);
+        retire_pages(&boot_services, &mut reporter, 0x10_0000, &BTreeSet::from([1, 2, 5]));
+        assert_eq!(REPORTED.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_error_records_stop_when_they_cannot_be_saved() {
+        let mut boot_services = MockBootServices::new();
+        boot_services
+            .expect_locate_protocol::<StatusCodeRuntimeProtocol>()
+            .once()
+            .returning(|_| Err(efi::Status::NOT_FOUND));
+        let mut runtime_services = MockRuntimeServices::new();
+        runtime_services.expect_get_variable::<Vec<u8>>().once().returning(|_, _, _| Err(efi::Status::UNSUPPORTED));
+
+        let mut reporter = Reporter::new(&boot_services, &runtime_services);
+        reporter.report_failure(0x10_0000, 4);
+        assert_eq!(reporter.next_record, None);
+        assert_eq!(reporter.records, 0);
+    }
+}
@@ -0,0 +1,49 @@
+//! Patina Memory Test Component Configuration
+//!
+//! The configuration can be set statically with `.with_config()` or produced dynamically during boot, e.g. by a
+//! component selecting the full test when the platform is in manufacturing mode. If no configuration is provided, the
+//! memory is not tested.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use patina::base::SIZE_16MB;
+
+/// The coverage of the memory test.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryTestCoverage {
+    /// The memory is not tested.
+    #[default]
+    Disabled,
+    /// Each word is written and verified with checkerboard patterns and a value unique to it, which detects stuck bits
+    /// and address line faults. Suited to every boot.
+    Quick,
+    /// A March C- test with solid and checkerboard data backgrounds, which also detects transition and coupling
+    /// faults. It takes several times longer than the quick test, and is meant for manufacturing or diagnostic boots.
+    Full,
+}
+
+/// The configuration for the Patina memory test component.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryTestConfig {
+    /// The coverage of the test.
+    pub coverage: MemoryTestCoverage,
+    /// The size in bytes of the ranges taken offline and tested at once, rounded down to a multiple of the page size.
+    /// A progress status code is reported for each range.
+    pub chunk_size: usize,
+}
+
+impl MemoryTestConfig {
+    /// The default size of the ranges tested at once.
+    pub const DEFAULT_CHUNK_SIZE: usize = SIZE_16MB;
+}
+
+impl Default for MemoryTestConfig {
+    fn default() -> Self {
+        Self { coverage: MemoryTestCoverage::default(), chunk_size: Self::DEFAULT_CHUNK_SIZE }
+    }
+}
@@ -0,0 +1,267 @@
+//! Common Platform Error Record (CPER)
+//!
+//! Definitions of the CPER format for records holding a single platform memory error section, which describe the
+//! memory failures found by the memory test. The records are saved as `HwErrRec####` hardware error record variables,
+//! where the operating system retrieves them.
+//!
+//! See <https://uefi.org/specs/UEFI/2.10/Apx_N_Common_Platform_Error_Record.html>.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use core::{mem, slice};
+
+use r_efi::efi;
+
+/// The signature at the start of a record, "CPER".
+pub const CPER_SIGNATURE_START: u32 = u32::from_le_bytes(*b"CPER");
+/// The signature ending the signature field of a record.
+pub const CPER_SIGNATURE_END: u32 = 0xFFFF_FFFF;
+/// The revision of the record format.
+pub const CPER_RECORD_REVISION: u16 = 0x0101;
+/// The revision of the section descriptor format.
+pub const CPER_SECTION_REVISION: u16 = 0x0100;
+
+/// Error severity: recoverable, i.e. uncorrected but contained.
+pub const CPER_SEVERITY_RECOVERABLE: u32 = 0;
+/// Error severity: fatal.
+pub const CPER_SEVERITY_FATAL: u32 = 1;
+/// Error severity: corrected.
+pub const CPER_SEVERITY_CORRECTED: u32 = 2;
+/// Error severity: informational.
+pub const CPER_SEVERITY_INFORMATIONAL: u32 = 3;
+
+/// Section descriptor flag: the section is the primary section of the record.
+pub const CPER_SECTION_FLAG_PRIMARY: u32 = 0x1;
+
+/// The notification type of errors found during boot.
+pub const CPER_NOTIFY_BOOT: efi::Guid = patina::guid!("3D61A466-AB40-409A-A698-F362D464B38F");
+/// The section type of platform memory errors.
+pub const CPER_SECTION_PLATFORM_MEMORY: efi::Guid = patina::guid!("A5BC1114-6F64-4EDE-B863-3E83ED7C83B1");
+
+/// Memory error section validation bit: [MemoryErrorSection::physical_address] is valid.
+pub const MEMORY_VALID_PHYSICAL_ADDRESS: u64 = 1 << 1;
+/// Memory error section validation bit: [MemoryErrorSection::physical_address_mask] is valid.
+pub const MEMORY_VALID_PHYSICAL_ADDRESS_MASK: u64 = 1 << 2;
+/// Memory error section validation bit: [MemoryErrorSection::error_type] is valid.
+pub const MEMORY_VALID_ERROR_TYPE: u64 = 1 << 14;
+
+/// Memory error type: an uncorrected error found by a scrub of the memory.
+pub const MEMORY_ERROR_SCRUB_UNCORRECTED: u8 = 14;
+
+/// `EFI_COMMON_ERROR_RECORD_HEADER` in the specification.
+///
+/// The GUIDs are in their binary representation, as the record is packed.
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+pub struct RecordHeader {
+    /// [CPER_SIGNATURE_START].
+    pub signature_start: u32,
+    /// [CPER_RECORD_REVISION].
+    pub revision: u16,
+    /// [CPER_SIGNATURE_END].
+    pub signature_end: u32,
+    /// The number of sections following the header.
+    pub section_count: u16,
+    /// The most severe severity of the sections.
+    pub error_severity: u32,
+    /// The fields that are valid among the platform ID, timestamp and partition ID.
+    pub validation_bits: u32,
+    /// The size of the record in bytes, including the header.
+    pub record_length: u32,
+    /// The time the error was found, if valid.
+    pub timestamp: u64,
+    /// The platform ID, if valid.
+    pub platform_id: [u8; 16],
+    /// The partition ID, if valid.
+    pub partition_id: [u8; 16],
+    /// The creator of the record.
+    pub creator_id: [u8; 16],
+    /// The type of the notification of the error, e.g. [CPER_NOTIFY_BOOT].
+    pub notification_type: [u8; 16],
+    /// A value identifying the record.
+    pub record_id: u64,
+    /// The record flags.
+    pub flags: u32,
+    /// Reserved for the operating system.
+    pub persistence_info: u64,
+    /// Reserved, must be zero.
+    pub reserved: [u8; 12],
+}
+
+/// `EFI_ERROR_SECTION_DESCRIPTOR` in the specification.
+///
+/// The GUIDs are in their binary representation, as the descriptor is packed.
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+pub struct SectionDescriptor {
+    /// The offset of the section from the start of the record.
+    pub section_offset: u32,
+    /// The size of the section in bytes.
+    pub section_length: u32,
+    /// [CPER_SECTION_REVISION].
+    pub revision: u16,
+    /// The fields that are valid among the FRU ID and FRU string.
+    pub validation_bits: u8,
+    /// Reserved, must be zero.
+    pub reserved: u8,
+    /// The section flags, e.g. [CPER_SECTION_FLAG_PRIMARY].
+    pub flags: u32,
+    /// The type of the section, e.g. [CPER_SECTION_PLATFORM_MEMORY].
+    pub section_type: [u8; 16],
+    /// The field replaceable unit ID, if valid.
+    pub fru_id: [u8; 16],
+    /// The severity of the section.
+    pub section_severity: u32,
+    /// The field replaceable unit description, if valid.
+    pub fru_string: [u8; 20],
+}
+
+/// `EFI_PLATFORM_MEMORY_ERROR_DATA` in the specification.
+#[repr(C, packed)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MemoryErrorSection {
+    /// The valid fields, e.g. [MEMORY_VALID_PHYSICAL_ADDRESS].
+    pub validation_bits: u64,
+    /// The error status, if valid.
+    pub error_status: u64,
+    /// The physical address of the error.
+    pub physical_address: u64,
+    /// The bits of [Self::physical_address] that are valid, e.g. `!0xFFF` for an error located to a page.
+    pub physical_address_mask: u64,
+    /// The node of the error, if valid.
+    pub node: u16,
+    /// The card of the error, if valid.
+    pub card: u16,
+    /// The module of the error, if valid.
+    pub module: u16,
+    /// The bank of the error, if valid.
+    pub bank: u16,
+    /// The device of the error, if valid.
+    pub device: u16,
+    /// The row of the error, if valid.
+    pub row: u16,
+    /// The column of the error, if valid.
+    pub column: u16,
+    /// The bit position of the error, if valid.
+    pub bit_position: u16,
+    /// The hardware address of the requestor, if valid.
+    pub requestor_id: u64,
+    /// The hardware address of the responder, if valid.
+    pub responder_id: u64,
+    /// The hardware address of the target, if valid.
+    pub target_id: u64,
+    /// The type of the error, e.g. [MEMORY_ERROR_SCRUB_UNCORRECTED].
+    pub error_type: u8,
+    /// Extended bits of the row, if valid.
+    pub extended: u8,
+    /// The rank number of the error, if valid.
+    pub rank_number: u16,
+    /// The SMBIOS handle of the memory array of the error, if valid.
+    pub card_handle: u16,
+    /// The SMBIOS handle of the memory device of the error, if valid.
+    pub module_handle: u16,
+}
+
+/// A record holding a single platform memory error section.
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryErrorRecord {
+    /// The record header.
+    pub header: RecordHeader,
+    /// The descriptor of the memory error section.
+    pub descriptor: SectionDescriptor,
+    /// The memory error section.
+    pub section: MemoryErrorSection,
+}
+
+impl MemoryErrorRecord {
+    /// Creates a record for an error in the memory page at `address`.
+    pub fn new(creator_id: efi::Guid, record_id: u64, severity: u32, address: u64, error_type: u8) -> Self {
+        Self {
+            header: RecordHeader {
+                signature_start: CPER_SIGNATURE_START,
+                revision: CPER_RECORD_REVISION,
+                signature_end: CPER_SIGNATURE_END,
+                section_count: 1,
+                error_severity: severity,
+                validation_bits: 0,
+                record_length: mem::size_of::<Self>() as u32,
+                timestamp: 0,
+                platform_id: [0; 16],
+                partition_id: [0; 16],
+                creator_id: *creator_id.as_bytes(),
+                notification_type: *CPER_NOTIFY_BOOT.as_bytes(),
+                record_id,
+                flags: 0,
+                persistence_info: 0,
+                reserved: [0; 12],
+            },
+            descriptor: SectionDescriptor {
+                section_offset: mem::offset_of!(Self, section) as u32,
+                section_length: mem::size_of::<MemoryErrorSection>() as u32,
+                revision: CPER_SECTION_REVISION,
+                validation_bits: 0,
+                reserved: 0,
+                flags: CPER_SECTION_FLAG_PRIMARY,
+                section_type: *CPER_SECTION_PLATFORM_MEMORY.as_bytes(),
+                fru_id: [0; 16],
+                section_severity: severity,
+                fru_string: [0; 20],
+            },
+            section: MemoryErrorSection {
+                validation_bits: MEMORY_VALID_PHYSICAL_ADDRESS
+                    | MEMORY_VALID_PHYSICAL_ADDRESS_MASK
+                    | MEMORY_VALID_ERROR_TYPE,
+                physical_address: address,
+                physical_address_mask: !(patina::base::UEFI_PAGE_MASK as u64),
+                error_type,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Returns the record in its binary representation.
+    pub fn as_bytes(&self) -> &[u8] {
+        // SAFETY: The record is a packed structure of plain data, without padding.
+        unsafe { slice::from_raw_parts(self as *const Self as *const u8, mem::size_of::<Self>()) }
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+
+    const CREATOR: efi::Guid = patina::guid!("8F0B4B7E-1D2C-4E5A-9B3F-6A7C8D9E0F12");
+
+    #[test]
+    fn test_layout_matches_the_specification() {
+        assert_eq!(mem::size_of::<RecordHeader>(), 128);
+        assert_eq!(mem::size_of::<SectionDescriptor>(), 72);
+        assert_eq!(mem::size_of::<MemoryErrorSection>(), 80);
+        assert_eq!(mem::offset_of!(MemoryErrorSection, error_type), 72);
+    }
+
+    #[test]
+    fn test_memory_error_record() {
+        let record = MemoryErrorRecord::new(CREATOR, 7, CPER_SEVERITY_RECOVERABLE, 0x1234_5000, 14);
+        let bytes = record.as_bytes();
+        assert_eq!(bytes.len(), 280);
+        assert_eq!(&bytes[0..4], b"CPER");
+        assert_eq!(&bytes[4..6], &[0x01, 0x01]);
+        assert_eq!(&bytes[6..10], &[0xFF; 4]);
+        // The record length, then the section offset and length in the descriptor.
+        assert_eq!(&bytes[20..24], &280_u32.to_le_bytes());
+        assert_eq!(&bytes[128..132], &200_u32.to_le_bytes());
+        assert_eq!(&bytes[132..136], &80_u32.to_le_bytes());
+        assert_eq!(&bytes[144..160], CPER_SECTION_PLATFORM_MEMORY.as_bytes());
+        // The physical address and its mask in the section.
+        assert_eq!(&bytes[216..224], &0x1234_5000_u64.to_le_bytes());
+        assert_eq!(&bytes[224..232], &0xFFFF_FFFF_FFFF_F000_u64.to_le_bytes());
+        assert_eq!(bytes[272], 14);
+    }
+}
@@ -0,0 +1,44 @@
+//! Boot time memory test for Patina platforms.
+//!
+//! The memory test writes and verifies the free system memory during boot, so that faulty memory is found before the
+//! operating system uses it. This crate provides:
+//!
+//! - [component::MemoryTest]: a component that tests the free conventional memory with the coverage of the
+//!   [config::MemoryTestConfig], takes the failing pages out of use as unusable memory, and reports the failures as
+//!   status codes and CPER records.
+//! - [cper]: the Common Platform Error Record format of the memory error records.
+//!
+//! ## Integration Example
+//!
+//! ```rust,ignore
+//! // ...
+//!
+//! Core::default()
+//!  // ...
+//!  .with_config(patina_memory_test::config::MemoryTestConfig {
+//!      coverage: patina_memory_test::config::MemoryTestCoverage::Quick,
+//!      ..Default::default()
+//!  })
+//!  .with_component(patina_memory_test::component::MemoryTest)
+//!  .start()
+//!  .unwrap();
+//!
+//! // ...
+//! ```
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+#![cfg_attr(not(test), no_std)]
+#![feature(coverage_attribute)]
+
+extern crate alloc;
+
+mod algorithm;
+pub mod component;
+pub mod config;
+pub mod cper;
@@ -0,0 +1,101 @@
+//! Integration tests of the memory test against the host DXE core boot services.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use patina::{
+    base::{SIZE_4MB, UEFI_PAGE_SIZE},
+    boot_services::{
+        BootServices, StandardBootServices,
+        allocation::{AllocType, MemoryType},
+    },
+};
+use patina_memory_test::{
+    component::MemoryTest,
+    config::{MemoryTestConfig, MemoryTestCoverage},
+};
+use patina_test::TestHarness;
+use r_efi::efi;
+
+/// Returns the number of pages of the given type in the memory map.
+fn pages_of_type(boot_services: &StandardBootServices, memory_type: u32) -> u64 {
+    let memory_map = boot_services.get_memory_map().unwrap();
+    memory_map
+        .descriptors
+        .iter()
+        .filter(|descriptor| descriptor.r#type == memory_type)
+        .map(|descriptor| descriptor.number_of_pages)
+        .sum()
+}
+
+/// Writes a marker in a free page, away from the pages allocated next, and returns the address of the page.
+fn mark_free_page(boot_services: &StandardBootServices) -> usize {
+    let address = {
+        let memory_map = boot_services.get_memory_map().unwrap();
+        let largest = memory_map
+            .descriptors
+            .iter()
+            .filter(|descriptor| descriptor.r#type == efi::CONVENTIONAL_MEMORY)
+            .max_by_key(|descriptor| descriptor.number_of_pages)
+            .unwrap();
+        largest.physical_start as usize + largest.number_of_pages as usize / 2 * UEFI_PAGE_SIZE
+    };
+    boot_services.allocate_pages(AllocType::Address(address), MemoryType::BOOT_SERVICES_DATA, 1).unwrap();
+    // SAFETY: The page was just allocated.
+    unsafe { (address as *mut u64).write_volatile(0x1234_5678) };
+    boot_services.free_pages(address, 1).unwrap();
+    address
+}
+
+/// Returns the first word of the free page at `address`.
+fn read_free_page(boot_services: &StandardBootServices, address: usize) -> u64 {
+    boot_services.allocate_pages(AllocType::Address(address), MemoryType::BOOT_SERVICES_DATA, 1).unwrap();
+    // SAFETY: The page was just allocated.
+    let value = unsafe { (address as *const u64).read_volatile() };
+    boot_services.free_pages(address, 1).unwrap();
+    value
+}
+
+#[test]
+fn test_disabled_memory_test_leaves_the_memory_untouched() {
+    let mut harness = TestHarness::new().with_component(MemoryTest);
+    let boot_services = harness.boot_services();
+    let page = mark_free_page(&boot_services);
+
+    harness.run().unwrap();
+    assert!(harness.pending_components().is_empty());
+    assert_eq!(read_free_page(&boot_services, page), 0x1234_5678);
+}
+
+#[test]
+fn test_free_memory_is_tested_and_returned() {
+    for (coverage, expected) in
+        [(MemoryTestCoverage::Quick, None), (MemoryTestCoverage::Full, Some(0x5555_5555_5555_5555))]
+    {
+        let mut harness = TestHarness::new()
+            .with_config(MemoryTestConfig { coverage, chunk_size: SIZE_4MB })
+            .with_component(MemoryTest);
+        let boot_services = harness.boot_services();
+        let page = mark_free_page(&boot_services);
+        let free_pages = pages_of_type(&boot_services, efi::CONVENTIONAL_MEMORY);
+
+        harness.run().unwrap();
+        assert!(harness.pending_components().is_empty());
+
+        // The free page was overwritten by the test: the quick test leaves the index of each word in its chunk, and
+        // the full test its last data background.
+        let value = read_free_page(&boot_services, page);
+        assert_ne!(value, 0x1234_5678);
+        if let Some(expected) = expected {
+            assert_eq!(value, expected);
+        }
+
+        // Healthy memory is returned to the free memory, and none is taken out of use.
+        assert_eq!(pages_of_type(&boot_services, efi::UNUSABLE_MEMORY), 0);
+        let remaining = pages_of_type(&boot_services, efi::CONVENTIONAL_MEMORY);
+        assert!(free_pages - remaining < (SIZE_4MB / UEFI_PAGE_SIZE) as u64, "{free_pages:#x} -> {remaining:#x}");
+    }
+}
@@ -4,7 +4,7 @@
 //!
 //! ## Logging
 //!
-//! Detailed logging is available for this component using the `mm_comm` log target.
+//! Detailed logging is available for this component using the `mm_comm` log target ([target::MM_COMM]).
 //!
 //! ## License
 //!
@@ -18,6 +18,7 @@ use patina::component::{
     IntoComponent, Storage,
     service::{IntoService, Service},
 };
+use patina::log::target;
 use r_efi::efi;
 extern crate alloc;
 use alloc::vec::Vec;
@@ -112,7 +113,7 @@ impl MmCommunicator {
         storage: &mut Storage,
         sw_mmi_trigger: Service<dyn SwMmiTrigger>,
     ) -> patina::error::Result<()> {
-        log::info!(target: "mm_comm", "MM Communicator entry...");
+        log::info!(target: target::MM_COMM, "MM Communicator entry...");
 
         self.sw_mmi_trigger_service = Some(sw_mmi_trigger);
 
@@ -121,12 +122,12 @@ impl MmCommunicator {
                 .get_config::<MmCommunicationConfiguration>()
                 .expect("Failed to get MM Configuration Config from storage");
 
-            log::trace!(target: "mm_comm", "Retrieved MM configuration: comm_buffers_count={}", config.comm_buffers.len());
+            log::trace!(target: target::MM_COMM, "Retrieved MM configuration: comm_buffers_count={}", config.comm_buffers.len());
             config.comm_buffers.clone()
         };
 
         self.comm_buffers = RefCell::new(comm_buffers);
-        log::info!(target: "mm_comm", "MM Communicator initialized with {} communication buffers", self.comm_buffers.borrow().len());
+        log::info!(target: target::MM_COMM, "MM Communicator initialized with {} communication buffers", self.comm_buffers.borrow().len());
 
         storage.add_service(self);
 
@@ -147,67 +148,67 @@ impl Debug for MmCommunicator {
 
 impl MmCommunication for MmCommunicator {
     fn communicate(&self, id: u8, data_buffer: &[u8], recipient: efi::Guid) -> Result<Vec<u8>, Status> {
-        log::debug!(target: "mm_comm", "Starting MM communication: buffer_id={}, data_size={}, recipient={:?}", id, data_buffer.len(), recipient);
+        log::debug!(target: target::MM_COMM, "Starting MM communication: buffer_id={}, data_size={}, recipient={:?}", id, data_buffer.len(), recipient);
 
         if self.comm_buffers.borrow().is_empty() {
-            log::warn!(target: "mm_comm", "No communication buffers available");
+            log::warn!(target: target::MM_COMM, "No communication buffers available");
             return Err(Status::NoCommBuffer);
         }
 
         if data_buffer.is_empty() {
-            log::warn!(target: "mm_comm", "Invalid data buffer: empty");
+            log::warn!(target: target::MM_COMM, "Invalid data buffer: empty");
             return Err(Status::InvalidDataBuffer);
         }
 
         let sw_smi_trigger_service = self.sw_mmi_trigger_service.as_ref().ok_or_else(|| {
-            log::error!(target: "mm_comm", "SW MMI Trigger service not available");
+            log::error!(target: target::MM_COMM, "SW MMI Trigger service not available");
             Status::SwMmiServiceNotAvailable
         })?;
 
         let mut comm_buffers = self.comm_buffers.borrow_mut();
         let comm_buffer: &mut CommunicateBuffer = comm_buffers.iter_mut().find(|x| x.id() == id).ok_or_else(|| {
-            log::warn!(target: "mm_comm", "Communication buffer not found: id={}", id);
+            log::warn!(target: target::MM_COMM, "Communication buffer not found: id={}", id);
             Status::CommBufferNotFound
         })?;
 
         let total_required_comm_buffer_length = EfiMmCommunicateHeader::size() + data_buffer.len();
-        log::trace!(target: "mm_comm", "Buffer validation: buffer_len={}, required_len={}", comm_buffer.len(), total_required_comm_buffer_length);
+        log::trace!(target: target::MM_COMM, "Buffer validation: buffer_len={}, required_len={}", comm_buffer.len(), total_required_comm_buffer_length);
 
         if comm_buffer.len() < total_required_comm_buffer_length {
-            log::warn!(target: "mm_comm", "Communication buffer too small: available={}, required={}", comm_buffer.len(), total_required_comm_buffer_length);
+            log::warn!(target: target::MM_COMM, "Communication buffer too small: available={}, required={}", comm_buffer.len(), total_required_comm_buffer_length);
             return Err(Status::CommBufferTooSmall);
         }
 
-        log::trace!(target: "mm_comm", "Setting up communication buffer for MM request");
+        log::trace!(target: target::MM_COMM, "Setting up communication buffer for MM request");
         comm_buffer.set_message_info(recipient).map_err(|err| {
-            log::error!(target: "mm_comm", "Failed to set message info: {:?}", err);
+            log::error!(target: target::MM_COMM, "Failed to set message info: {:?}", err);
             Status::CommBufferInitError
         })?;
         comm_buffer.set_message(data_buffer).map_err(|err| {
-            log::error!(target: "mm_comm", "Failed to set message data: {:?}", err);
+            log::error!(target: target::MM_COMM, "Failed to set message data: {:?}", err);
             Status::CommBufferInitError
         })?;
 
-        log::debug!(target: "mm_comm", "Outgoing MM communication request: buffer_id={}, data_size={}, recipient={:?}", id, data_buffer.len(), recipient);
-        log::debug!(target: "mm_comm", "Request Data (hex): {:02X?}", &data_buffer[..core::cmp::min(data_buffer.len(), 64)]);
-        log::trace!(target: "mm_comm", "Comm buffer before request: {:?}", comm_buffer);
+        log::debug!(target: target::MM_COMM, "Outgoing MM communication request: buffer_id={}, data_size={}, recipient={:?}", id, data_buffer.len(), recipient);
+        log::debug!(target: target::MM_COMM, "Request Data (hex): {:02X?}", &data_buffer[..core::cmp::min(data_buffer.len(), 64)]);
+        log::trace!(target: target::MM_COMM, "Comm buffer before request: {:?}", comm_buffer);
 
-        log::debug!(target: "mm_comm", "Triggering SW MMI for MM communication");
+        log::debug!(target: target::MM_COMM, "Triggering SW MMI for MM communication");
         // SAFETY: The SW MMI trigger service will use configuration that requires
         //         the user to have upheld the safety requirements for the service.
         unsafe {
             sw_smi_trigger_service.trigger_sw_mmi(0xFF, 0).map_err(|err| {
-                log::error!(target: "mm_comm", "SW MMI trigger failed: {:?}", err);
+                log::error!(target: target::MM_COMM, "SW MMI trigger failed: {:?}", err);
                 Status::SwMmiFailed
             })?
         };
 
-        log::trace!(target: "mm_comm", "MM communication completed successfully, retrieving response");
+        log::trace!(target: target::MM_COMM, "MM communication completed successfully, retrieving response");
         let response = comm_buffer.get_message().map_err(|_| {
-            log::error!(target: "mm_comm", "Failed to retrieve response from communication buffer");
+            log::error!(target: target::MM_COMM, "Failed to retrieve response from communication buffer");
             Status::InvalidResponse
         })?;
-        log::debug!(target: "mm_comm", "MM communication response received: size={}", response.len());
+        log::debug!(target: target::MM_COMM, "MM communication response received: size={}", response.len());
 
         Ok(response)
     }
@@ -4,7 +4,7 @@
 //!
 //! ## Logging
 //!
-//! Detailed logging is available for this component using the `sw_mmi` log target.
+//! Detailed logging is available for this component using the `sw_mmi` log target ([target::SW_MMI]).
 //!
 //! ## License
 //!
@@ -19,6 +19,7 @@ use patina::component::{
     params::{Commands, Config},
     service::{IntoService, Service},
 };
+use patina::log::target;
 
 #[cfg(any(feature = "doc", all(target_os = "uefi", target_arch = "x86_64")))]
 use x86_64::instructions::port;
@@ -73,25 +74,25 @@ impl SwMmiManager {
         platform_mm_control: Option<Service<dyn PlatformMmControl>>,
         mut commands: Commands,
     ) -> patina::error::Result<()> {
-        log::info!(target: "sw_mmi", "Initializing SwMmiManager...");
-        log::debug!(target: "sw_mmi", "MM config - cmd_port: {:?}, data_port: {:?}, acpi_base: {:?}",
+        log::info!(target: target::SW_MMI, "Initializing SwMmiManager...");
+        log::debug!(target: target::SW_MMI, "MM config - cmd_port: {:?}, data_port: {:?}, acpi_base: {:?}",
             config.cmd_port, config.data_port, config.acpi_base);
 
         if platform_mm_control.is_some() {
-            log::debug!(target: "sw_mmi", "Platform MM Control is available. Calling platform-specific init...");
+            log::debug!(target: target::SW_MMI, "Platform MM Control is available. Calling platform-specific init...");
             platform_mm_control.unwrap().init().inspect_err(|&err| {
-                log::error!(target: "sw_mmi", "Platform MM Control initialization failed: {:?}", err);
+                log::error!(target: target::SW_MMI, "Platform MM Control initialization failed: {:?}", err);
             })?;
-            log::trace!(target: "sw_mmi", "Platform MM Control initialization completed successfully");
+            log::trace!(target: target::SW_MMI, "Platform MM Control initialization completed successfully");
         } else {
-            log::trace!(target: "sw_mmi", "No platform MM Control service available - using default initialization");
+            log::trace!(target: target::SW_MMI, "No platform MM Control service available - using default initialization");
         }
 
         self.inner_config = config.clone();
-        log::debug!(target: "sw_mmi", "SwMmiManager configuration applied successfully");
+        log::debug!(target: target::SW_MMI, "SwMmiManager configuration applied successfully");
 
         commands.add_service(self);
-        log::info!(target: "sw_mmi", "SwMmiManager service registered and ready");
+        log::info!(target: target::SW_MMI, "SwMmiManager service registered and ready");
 
         Ok(())
     }
@@ -99,49 +100,49 @@ impl SwMmiManager {
 
 unsafe impl SwMmiTrigger for SwMmiManager {
     unsafe fn trigger_sw_mmi(&self, _cmd_port_value: u8, _data_port_value: u8) -> patina::error::Result<()> {
-        log::debug!(target: "sw_mmi", "Triggering SW MMI with cmd_port_value=0x{:02X}, data_port_value=0x{:02X}", _cmd_port_value, _data_port_value);
+        log::debug!(target: target::SW_MMI, "Triggering SW MMI with cmd_port_value=0x{:02X}, data_port_value=0x{:02X}", _cmd_port_value, _data_port_value);
 
-        log::trace!(target: "sw_mmi", "Writing to MMI command port...");
+        log::trace!(target: target::SW_MMI, "Writing to MMI command port...");
         match self.inner_config.cmd_port {
             MmiPort::Smi(_port) => {
-                log::trace!(target: "sw_mmi", "Using SMI command port: 0x{:04X}", _port);
+                log::trace!(target: target::SW_MMI, "Using SMI command port: 0x{:04X}", _port);
                 cfg_if::cfg_if! {
                     if #[cfg(any(feature = "doc", all(target_os = "uefi", target_arch = "x86_64")))] {
-                        log::trace!(target: "sw_mmi", "Writing SMI command port: {_port:#X}");
+                        log::trace!(target: target::SW_MMI, "Writing SMI command port: {_port:#X}");
                         unsafe { port::Port::new(_port).write(_cmd_port_value); }
-                        log::trace!(target: "sw_mmi", "SMI command port write completed");
+                        log::trace!(target: target::SW_MMI, "SMI command port write completed");
                     } else {
-                        log::trace!(target: "sw_mmi", "SMI command port write skipped (not on target platform)");
+                        log::trace!(target: target::SW_MMI, "SMI command port write skipped (not on target platform)");
                     }
                 }
             }
             MmiPort::Smc(_smc_port) => {
-                log::warn!(target: "sw_mmi", "SMC communication not implemented yet for port: 0x{:08X}", _smc_port);
+                log::warn!(target: target::SW_MMI, "SMC communication not implemented yet for port: 0x{:08X}", _smc_port);
                 todo!("SMC communication not implemented yet.");
             }
         }
 
-        log::trace!(target: "sw_mmi", "Writing to MMI data port...");
+        log::trace!(target: target::SW_MMI, "Writing to MMI data port...");
         match self.inner_config.data_port {
             MmiPort::Smi(_port) => {
-                log::trace!(target: "sw_mmi", "Using SMI data port: 0x{:04X}", _port);
+                log::trace!(target: target::SW_MMI, "Using SMI data port: 0x{:04X}", _port);
                 cfg_if::cfg_if! {
                     if #[cfg(any(feature = "doc", all(target_os = "uefi", target_arch = "x86_64")))] {
-                        log::trace!(target: "sw_mmi", "Writing SMI data port: {_port:#X}");
+                        log::trace!(target: target::SW_MMI, "Writing SMI data port: {_port:#X}");
                         unsafe { port::Port::new(_port).write(_data_port_value); }
-                        log::trace!(target: "sw_mmi", "SMI data port write completed");
+                        log::trace!(target: target::SW_MMI, "SMI data port write completed");
                     } else {
-                        log::trace!(target: "sw_mmi", "SMI data port write skipped (not on target platform)");
+                        log::trace!(target: target::SW_MMI, "SMI data port write skipped (not on target platform)");
                     }
                 }
             }
             MmiPort::Smc(_smc_port) => {
-                log::warn!(target: "sw_mmi", "SMC communication not implemented yet for port: 0x{:08X}", _smc_port);
+                log::warn!(target: target::SW_MMI, "SMC communication not implemented yet for port: 0x{:08X}", _smc_port);
                 todo!("SMC communication not implemented yet.");
             }
         }
 
-        log::debug!(target: "sw_mmi", "SW MMI triggered successfully");
+        log::debug!(target: target::SW_MMI, "SW MMI triggered successfully");
         Ok(())
     }
 }
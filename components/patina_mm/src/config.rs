@@ -25,6 +25,7 @@ use core::pin::Pin;
 use core::ptr::NonNull;
 
 use patina::base::UEFI_PAGE_MASK;
+use patina::log::target;
 use r_efi::efi;
 
 /// Management Mode (MM) Configuration
@@ -177,12 +178,12 @@ impl CommunicateBuffer {
     /// Creates a new `CommunicateBuffer` with the given buffer and ID.
     pub fn new(mut buffer: Pin<&'static mut [u8]>, id: u8) -> Self {
         let length = buffer.len();
-        log::debug!(target: "mm_comm", "Creating new CommunicateBuffer: id={}, size=0x{:X}", id, length);
+        log::debug!(target: target::MM_COMM, "Creating new CommunicateBuffer: id={}, size=0x{:X}", id, length);
         buffer.fill(0);
 
         let ptr: NonNull<[u8]> = NonNull::from_mut(Pin::into_inner(buffer));
 
-        log::trace!(target: "mm_comm", "CommunicateBuffer {} created successfully at address {:p}", id, ptr);
+        log::trace!(target: target::MM_COMM, "CommunicateBuffer {} created successfully at address {:p}", id, ptr);
         Self { buffer: ptr, id, length, private_recipient: None, private_message_length: 0 }
     }
 
@@ -214,29 +215,29 @@ impl CommunicateBuffer {
     /// - The buffer must be page (4k) aligned so paging attributes can be applied to it.
     /// - The buffer size must be sufficient to hold at least the MM communication header.
     pub unsafe fn from_raw_parts(buffer: *mut u8, size: usize, id: u8) -> Result<Self, CommunicateBufferStatus> {
-        log::trace!(target: "mm_comm", "Creating CommunicateBuffer from raw parts: id={}, ptr={:p}, size=0x{:X}", id, buffer, size);
+        log::trace!(target: target::MM_COMM, "Creating CommunicateBuffer from raw parts: id={}, ptr={:p}, size=0x{:X}", id, buffer, size);
 
         if size < Self::MINIMUM_BUFFER_SIZE {
-            log::error!(target: "mm_comm", "Buffer {} too small: size=0x{:X}, minimum=0x{:X}", id, size, Self::MINIMUM_BUFFER_SIZE);
+            log::error!(target: target::MM_COMM, "Buffer {} too small: size=0x{:X}, minimum=0x{:X}", id, size, Self::MINIMUM_BUFFER_SIZE);
             return Err(CommunicateBufferStatus::TooSmallForHeader);
         }
 
         if buffer.is_null() {
-            log::error!(target: "mm_comm", "Buffer {} has null pointer", id);
+            log::error!(target: target::MM_COMM, "Buffer {} has null pointer", id);
             return Err(CommunicateBufferStatus::NoBuffer);
         }
 
         if (buffer as usize) & UEFI_PAGE_MASK != 0 {
-            log::error!(target: "mm_comm", "Buffer {} not page aligned: address=0x{:X}, mask=0x{:X}", id, buffer as usize, UEFI_PAGE_MASK);
+            log::error!(target: target::MM_COMM, "Buffer {} not page aligned: address=0x{:X}, mask=0x{:X}", id, buffer as usize, UEFI_PAGE_MASK);
             return Err(CommunicateBufferStatus::NotAligned);
         }
 
         if buffer as usize > usize::MAX - size {
-            log::error!(target: "mm_comm", "Buffer {} address overflow: ptr=0x{:X}, size=0x{:X}", id, buffer as usize, size);
+            log::error!(target: target::MM_COMM, "Buffer {} address overflow: ptr=0x{:X}, size=0x{:X}", id, buffer as usize, size);
             return Err(CommunicateBufferStatus::AddressValidationFailed);
         }
 
-        log::debug!(target: "mm_comm", "CommunicateBuffer {} validation passed, creating buffer", id);
+        log::debug!(target: target::MM_COMM, "CommunicateBuffer {} validation passed, creating buffer", id);
         // SAFETY: Safety is upheld by the caller to this function (the function is marked unsafe)
         unsafe { Ok(Self::new(Pin::new(core::slice::from_raw_parts_mut(buffer, size)), id)) }
     }
@@ -280,7 +281,7 @@ impl CommunicateBuffer {
         let ptr = address as *mut u8;
 
         log::info!(
-            target: "mm_comm",
+            target: target::MM_COMM,
             "Creating CommunicateBuffer from firmware region: addr=0x{:X}, size=0x{:X}, id={}",
             address,
             size_bytes,
@@ -337,7 +338,7 @@ impl CommunicateBuffer {
     /// Returns `Ok(())` if state verification passes, otherwise returns the appropriate error.
     fn verify_state_consistency(&self) -> Result<(), CommunicateBufferStatus> {
         if self.len() < Self::MESSAGE_START_OFFSET {
-            log::error!(target: "mm_comm", "Buffer {} is too small for the communicate header", self.id);
+            log::error!(target: target::MM_COMM, "Buffer {} is too small for the communicate header", self.id);
             return Err(CommunicateBufferStatus::TooSmallForHeader);
         }
 
@@ -358,7 +359,7 @@ impl CommunicateBuffer {
         match self.private_recipient {
             Some(expected_guid) => {
                 if memory_guid != expected_guid {
-                    log::error!(target: "mm_comm", "Buffer {} GUID mismatch: private={:?}, memory={:?}",
+                    log::error!(target: target::MM_COMM, "Buffer {} GUID mismatch: private={:?}, memory={:?}",
                         self.id, expected_guid, memory_guid);
                     return Err(CommunicateBufferStatus::InvalidRecipient);
                 }
@@ -367,7 +368,7 @@ impl CommunicateBuffer {
                 // If no recipient is set privately, the memory should contain all zeros for the GUID
                 let zero_guid = efi::Guid::from_fields(0, 0, 0, 0, 0, &[0; 6]);
                 if memory_guid != zero_guid {
-                    log::error!(target: "mm_comm", "Buffer {} unexpected GUID in memory when none set privately", self.id);
+                    log::error!(target: target::MM_COMM, "Buffer {} unexpected GUID in memory when none set privately", self.id);
                     return Err(CommunicateBufferStatus::InvalidRecipient);
                 }
             }
@@ -375,12 +376,12 @@ impl CommunicateBuffer {
 
         // Verify message length matches
         if memory_message_length != self.private_message_length {
-            log::error!(target: "mm_comm", "Buffer {} message length mismatch: private={}, memory={}",
+            log::error!(target: target::MM_COMM, "Buffer {} message length mismatch: private={}, memory={}",
                 self.id, self.private_message_length, memory_message_length);
             return Err(CommunicateBufferStatus::TooSmallForMessage);
         }
 
-        log::trace!(target: "mm_comm", "Buffer {} state consistency was verified successfully", self.id);
+        log::trace!(target: target::MM_COMM, "Buffer {} state consistency was verified successfully", self.id);
         Ok(())
     }
 
@@ -393,12 +394,12 @@ impl CommunicateBuffer {
     /// - `Ok(())` - The buffer can safely hold the header and message
     /// - `Err(status)` - Buffer validation failed
     fn validate_capacity(&self, message_size: usize) -> Result<(), CommunicateBufferStatus> {
-        log::trace!(target: "mm_comm", "Validating capacity for buffer {}: buffer_size={}, message_size={}",
+        log::trace!(target: target::MM_COMM, "Validating capacity for buffer {}: buffer_size={}, message_size={}",
             self.id, self.len(), message_size);
 
         // First check if buffer can hold the header
         if self.len() < Self::MESSAGE_START_OFFSET {
-            log::error!(target: "mm_comm", "Buffer {} too small for header: size={}, header_size={}",
+            log::error!(target: target::MM_COMM, "Buffer {} too small for header: size={}, header_size={}",
                 self.id, self.len(), Self::MESSAGE_START_OFFSET);
             return Err(CommunicateBufferStatus::TooSmallForHeader);
         }
@@ -406,12 +407,12 @@ impl CommunicateBuffer {
         // Then check if remaining space can hold the message
         let available_message_space = self.len() - Self::MESSAGE_START_OFFSET;
         if message_size > available_message_space {
-            log::error!(target: "mm_comm", "Buffer {} too small for message: available_space={}, message_size={}",
+            log::error!(target: target::MM_COMM, "Buffer {} too small for message: available_space={}, message_size={}",
                 self.id, available_message_space, message_size);
             return Err(CommunicateBufferStatus::TooSmallForMessage);
         }
 
-        log::trace!(target: "mm_comm", "Buffer {} capacity validation passed", self.id);
+        log::trace!(target: target::MM_COMM, "Buffer {} capacity validation passed", self.id);
         Ok(())
     }
 
@@ -422,7 +423,7 @@ impl CommunicateBuffer {
     ///
     /// - `recipient`: The GUID of the recipient MM handler.
     pub fn set_message_info(&mut self, recipient: efi::Guid) -> Result<(), CommunicateBufferStatus> {
-        log::trace!(target: "mm_comm", "Setting message info for buffer {}: recipient={:?}", self.id, recipient);
+        log::trace!(target: target::MM_COMM, "Setting message info for buffer {}: recipient={:?}", self.id, recipient);
 
         // Validate capacity first
         self.validate_capacity(0)?;
@@ -438,7 +439,7 @@ impl CommunicateBuffer {
         // Verify state consistency after update
         self.verify_state_consistency()?;
 
-        log::trace!(target: "mm_comm", "Message info set successfully for buffer {}", self.id);
+        log::trace!(target: target::MM_COMM, "Message info set successfully for buffer {}", self.id);
         Ok(())
     }
 
@@ -450,19 +451,19 @@ impl CommunicateBuffer {
     /// - `message`: The message to be sent to the MM handler. The message length in the communicate header is
     ///   set to the length of this slice.
     pub fn set_message(&mut self, message: &[u8]) -> Result<(), CommunicateBufferStatus> {
-        log::trace!(target: "mm_comm", "Setting message for buffer {}: message_size={}", self.id, message.len());
+        log::trace!(target: target::MM_COMM, "Setting message for buffer {}: message_size={}", self.id, message.len());
 
         self.validate_capacity(message.len())?;
 
         let recipient = self.private_recipient.ok_or_else(|| {
-            log::error!(target: "mm_comm", "Buffer {} has no recipient set", self.id);
+            log::error!(target: target::MM_COMM, "Buffer {} has no recipient set", self.id);
             CommunicateBufferStatus::InvalidRecipient
         })?;
 
         // Update private state
         self.private_message_length = message.len();
 
-        log::trace!(target: "mm_comm", "Buffer {}: writing header and message data", self.id);
+        log::trace!(target: target::MM_COMM, "Buffer {}: writing header and message data", self.id);
 
         // Update memory buffer using safe byte operations for header
         let header = EfiMmCommunicateHeader::new(recipient, message.len());
@@ -476,7 +477,7 @@ impl CommunicateBuffer {
         // Verify state consistency after update
         self.verify_state_consistency()?;
 
-        log::debug!(target: "mm_comm", "Buffer {} message set successfully: header_size={}, message_size={}",
+        log::debug!(target: target::MM_COMM, "Buffer {} message set successfully: header_size={}, message_size={}",
             self.id, Self::MESSAGE_START_OFFSET, message.len());
         Ok(())
     }
@@ -490,7 +491,7 @@ impl CommunicateBuffer {
         self.verify_state_consistency()?;
 
         if self.private_message_length == 0 {
-            log::trace!(target: "mm_comm", "Buffer {} has zero-length message", self.id);
+            log::trace!(target: target::MM_COMM, "Buffer {} has zero-length message", self.id);
             return Ok(Vec::new());
         }
 
@@ -499,13 +500,13 @@ impl CommunicateBuffer {
 
         // Ensure we don't read beyond the buffer
         if end_offset > self.len() {
-            log::error!(target: "mm_comm", "Buffer {} message extends beyond buffer: end_offset={}, buffer_len={}",
+            log::error!(target: target::MM_COMM, "Buffer {} message extends beyond buffer: end_offset={}, buffer_len={}",
                 self.id, end_offset, self.len());
             return Err(CommunicateBufferStatus::TooSmallForMessage);
         }
 
         let message = self.as_slice()[start_offset..end_offset].to_vec();
-        log::trace!(target: "mm_comm", "Retrieved message from buffer {}: message_size={}", self.id, message.len());
+        log::trace!(target: target::MM_COMM, "Retrieved message from buffer {}: message_size={}", self.id, message.len());
         Ok(message)
     }
 
@@ -517,7 +518,7 @@ impl CommunicateBuffer {
         // Verify state consistency first
         self.verify_state_consistency()?;
 
-        log::trace!(target: "mm_comm", "Buffer {} header GUID retrieved from private state", self.id);
+        log::trace!(target: target::MM_COMM, "Buffer {} header GUID retrieved from private state", self.id);
         Ok(self.private_recipient)
     }
 
@@ -527,7 +528,7 @@ impl CommunicateBuffer {
         // Verify state consistency first
         self.verify_state_consistency()?;
 
-        log::trace!(target: "mm_comm", "Buffer {} message length retrieved from private state: len={}",
+        log::trace!(target: target::MM_COMM, "Buffer {} message length retrieved from private state: len={}",
             self.id, self.private_message_length);
         Ok(self.private_message_length)
     }
@@ -9,6 +9,8 @@
 
 pub mod performance;
 pub mod performance_config_provider;
+pub mod timestamp;
 
-// Re-export the Performance component for easier access.
+// Re-export the Performance and Timestamp components for easier access.
 pub use performance::Performance;
+pub use timestamp::Timestamp;
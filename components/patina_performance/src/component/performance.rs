@@ -16,39 +16,68 @@ use alloc::boxed::Box;
 use core::{clone::Clone, convert::AsRef};
 use mu_rust_helpers::perf_timer::{Arch, ArchFunctionality};
 use patina::{
-    boot_services::{BootServices, StandardBootServices, event::EventType, tpl::Tpl},
-    component::{IntoComponent, hob::Hob, params::Config},
+    boot_services::{
+        BootServices, StandardBootServices,
+        event::{EventBuilder, EventType},
+        tpl::Tpl,
+    },
+    component::{
+        IntoComponent,
+        hob::{Hob, ValidatedHob},
+        params::{Commands, Config},
+        service::IntoService,
+    },
     error::EfiError,
     guids::{EVENT_GROUP_END_OF_DXE, PERFORMANCE_PROTOCOL},
     performance::{
         _smm::MmCommRegion,
-        globals::{get_static_state, set_load_image_count, set_perf_measurement_mask, set_static_state},
-        measurement::{PerformanceProperty, create_performance_measurement, event_callback},
-        record::hob::{HobPerformanceData, HobPerformanceDataExtractor},
-        table::FirmwareBasicBootPerfTable,
+        error::Error,
+        globals::{
+            get_perf_id_registry, get_static_state, set_load_image_count, set_perf_measurement_mask, set_static_state,
+        },
+        id_registry::{PerfIdRegistration, PerfIdRegistry},
+        measurement::{
+            PerformanceProperty, create_performance_measurement,
+            event_callback::{self, MmPerformanceRecordsContext, ReportFbptContext},
+            get_measurement_mask, set_measurement_mask,
+        },
+        record::{
+            extended::PerfIdRangeRecord,
+            hob::{HobPerformanceData, HobPerformanceDataExtractor},
+        },
+        table::{FbptReservedBuffer, FirmwareBasicBootPerfTable, FirmwareSecPerformance},
     },
     runtime_services::{RuntimeServices, StandardRuntimeServices},
     tpl_mutex::TplMutex,
-    uefi_protocol::performance_measurement::EdkiiPerformanceMeasurement,
+    uefi_protocol::performance_measurement::{EdkiiPerformanceMeasurement, PerformanceMeasurementMask},
 };
-use r_efi::system::EVENT_GROUP_READY_TO_BOOT;
+use r_efi::{efi, system::EVENT_GROUP_READY_TO_BOOT};
 
 pub use mu_rust_helpers::function;
 
+pub mod basic_boot;
+
 /// Performance Component.
 #[derive(IntoComponent)]
 pub struct Performance;
 
 impl Performance {
     /// Entry point of [`Performance`]
+    ///
+    /// The HOBs are grouped in a single tuple parameter, as an entry point takes at most 5 parameters.
     #[coverage(off)] // This is tested via the generic version, see _entry_point.
     pub fn entry_point(
         self,
         config: Config<config::PerfConfig>,
         boot_services: StandardBootServices,
         runtime_services: StandardRuntimeServices,
-        records_buffers_hobs: Option<Hob<HobPerformanceData>>,
-        mm_comm_region_hobs: Option<Hob<MmCommRegion>>,
+        (records_buffers_hobs, mm_comm_region_hobs, fbpt_reserved_buffer_hob, sec_performance_hob): (
+            Option<ValidatedHob<HobPerformanceData>>,
+            Option<Hob<MmCommRegion>>,
+            Option<Hob<FbptReservedBuffer>>,
+            Option<Hob<FirmwareSecPerformance>>,
+        ),
+        mut commands: Commands,
     ) -> Result<(), EfiError> {
         if !config.enable_component {
             log::warn!("Patina Performance Component is not enabled, skipping entry point.");
@@ -69,25 +98,43 @@ impl Performance {
             return Err(EfiError::Aborted);
         };
 
-        let Some(mm_comm_region_hobs) = mm_comm_region_hobs else {
-            // If no MM communication region is provided, we can skip the SMM performance records.
-            return self._entry_point(boot_services, runtime_services, records_buffers_hobs, None, fbpt);
-        };
+        commands.add_service(PerfIdRegistryService);
 
-        let Some(mm_comm_region) = mm_comm_region_hobs.iter().find(|r| r.is_user_type()) else {
-            return Ok(());
-        };
+        // MM performance records can only be fetched through a user MM communication region. Platforms without MM
+        // (e.g. most ARM platforms) have none, and still publish the DXE performance records.
+        let mm_comm_region = mm_comm_region_hobs.and_then(|hobs| hobs.iter().find(|r| r.is_user_type()).copied());
+
+        let fbpt_reserved_buffer = fbpt_reserved_buffer_hob.map(|hob| *hob);
+
+        // The progress code handler has no context, so the basic boot record is filled through the static state.
+        basic_boot::register(&boot_services, fbpt, sec_performance_hob.map(|hob| hob.reset_end));
 
-        self._entry_point(boot_services, runtime_services, records_buffers_hobs, Some(*mm_comm_region), fbpt)
+        self._entry_point(
+            boot_services,
+            runtime_services,
+            records_buffers_hobs,
+            mm_comm_region,
+            fbpt_reserved_buffer,
+            !config.disable_fbpt_address_reuse,
+            fbpt,
+        )
     }
 
     /// Entry point that have generic parameter.
+    ///
+    /// FBPT publication and DXE performance recording are always set up, fetching the MM performance records is only
+    /// added when `mm_comm_region` is provided. When `fbpt_reserved_buffer` is provided, the FBPT is written in it from
+    /// now on instead of being allocated and copied at EndOfDxe. `reuse_fbpt_address` allocates the FBPT at the address
+    /// of the previous boot.
+    #[allow(clippy::too_many_arguments)]
     fn _entry_point<BB, B, RR, R, P, F>(
         self,
         boot_services: BB,
         runtime_services: RR,
         records_buffers_hobs: Option<P>,
         mm_comm_region: Option<MmCommRegion>,
+        fbpt_reserved_buffer: Option<FbptReservedBuffer>,
+        reuse_fbpt_address: bool,
         fbpt: &'static TplMutex<'static, F, B>,
     ) -> Result<(), EfiError>
     where
@@ -99,13 +146,18 @@ impl Performance {
         F: FirmwareBasicBootPerfTable,
     {
         // Register EndOfDxe event to allocate the boot performance table and report the table address through status code.
-        boot_services.as_ref().create_event_ex(
-            EventType::NOTIFY_SIGNAL,
-            Tpl::CALLBACK,
-            Some(event_callback::report_fbpt_record_buffer),
-            Box::new((BB::clone(&boot_services), RR::clone(&runtime_services), fbpt)),
-            &EVENT_GROUP_END_OF_DXE,
-        )?;
+        EventBuilder::new(BB::clone(&boot_services), EventType::NOTIFY_SIGNAL, Tpl::CALLBACK)
+            .event_group(&EVENT_GROUP_END_OF_DXE)
+            .one_shot()
+            .create(
+                event_callback::report_fbpt_record_buffer,
+                ReportFbptContext {
+                    boot_services: BB::clone(&boot_services),
+                    runtime_services: RR::clone(&runtime_services),
+                    fbpt,
+                    reuse_previous_address: reuse_fbpt_address,
+                },
+            )?;
 
         // Handle optional `records_buffers_hobs`
         if let Some(records_buffers_hobs) = records_buffers_hobs {
@@ -129,25 +181,32 @@ impl Performance {
             log::info!("Performance: No Hob performance records provided.");
         }
 
+        if let Some(reserved_buffer) = fbpt_reserved_buffer {
+            // SAFETY: The platform reserved this memory for the FBPT.
+            match fbpt.lock().use_reserved_buffer(unsafe { reserved_buffer.as_buffer() }) {
+                Ok(()) => log::info!("Performance: FBPT written in reserved buffer at {:#x}.", reserved_buffer.address),
+                Err(err) => log::error!(
+                    "Performance: Fail to use reserved FBPT buffer at {:#x} ({err}), allocating it at EndOfDxe.",
+                    reserved_buffer.address
+                ),
+            }
+        }
+
         // Install the protocol interfaces for DXE performance.
         boot_services.as_ref().install_protocol_interface(
             None,
             Box::new(EdkiiPerformanceMeasurement { create_performance_measurement }),
         )?;
+        boot_services.as_ref().install_protocol_interface(
+            None,
+            Box::new(PerformanceMeasurementMask { get_measurement_mask, set_measurement_mask }),
+        )?;
 
-        // Register ReadyToBoot event to update the boot performance table for SMM performance data.
-        // Only register if mm_comm_region is available
         if let Some(mm_comm_region) = mm_comm_region {
-            boot_services.as_ref().create_event_ex(
-                EventType::NOTIFY_SIGNAL,
-                Tpl::CALLBACK,
-                Some(event_callback::fetch_and_add_mm_performance_records),
-                Box::new((BB::clone(&boot_services), mm_comm_region, fbpt)),
-                &EVENT_GROUP_READY_TO_BOOT,
-            )?;
+            Self::register_mm_performance_records(&boot_services, mm_comm_region, fbpt)?;
         } else {
             log::info!(
-                "Performance: No MM communication region available, skipping SMM performance event registration."
+                "Performance: No MM communication region available, MM performance records will not be fetched."
             );
         }
 
@@ -165,6 +224,67 @@ impl Performance {
 
         Ok(())
     }
+
+    /// Registers the ReadyToBoot event adding the performance records logged in MM to the boot performance table.
+    ///
+    /// The records are fetched through the MM Communication protocol if it is installed by then.
+    fn register_mm_performance_records<BB, B, F>(
+        boot_services: &BB,
+        mm_comm_region: MmCommRegion,
+        fbpt: &'static TplMutex<'static, F, B>,
+    ) -> Result<(), EfiError>
+    where
+        BB: AsRef<B> + Clone + 'static,
+        B: BootServices + 'static,
+        F: FirmwareBasicBootPerfTable,
+    {
+        EventBuilder::new(BB::clone(boot_services), EventType::NOTIFY_SIGNAL, Tpl::CALLBACK)
+            .event_group(&EVENT_GROUP_READY_TO_BOOT)
+            .one_shot()
+            .create(
+                event_callback::fetch_and_add_mm_performance_records,
+                MmPerformanceRecordsContext { boot_services: BB::clone(boot_services), mm_comm_region, fbpt },
+            )?;
+        Ok(())
+    }
+}
+
+/// Service reserving ranges of performance IDs, produced by the [Performance] component.
+///
+/// Each newly reserved range is added to the FBPT as a [PerfIdRangeRecord].
+#[derive(IntoService)]
+#[service(dyn PerfIdRegistration)]
+pub struct PerfIdRegistryService;
+
+impl PerfIdRegistryService {
+    /// Reserves the performance IDs `start..=end` for `namespace` in `registry`, adding the record of the range to
+    /// `fbpt` if it is newly reserved.
+    fn register<B, F>(
+        registry: &'static TplMutex<'static, PerfIdRegistry, B>,
+        fbpt: &'static TplMutex<'static, F, B>,
+        namespace: &efi::Guid,
+        start: u16,
+        end: u16,
+    ) -> Result<(), Error>
+    where
+        B: BootServices + 'static,
+        F: FirmwareBasicBootPerfTable,
+    {
+        if registry.lock().register(namespace, start, end)? {
+            fbpt.lock().add_record(PerfIdRangeRecord::new(*namespace, start, end))?;
+        }
+        Ok(())
+    }
+}
+
+impl PerfIdRegistration for PerfIdRegistryService {
+    #[coverage(off)] // This is tested via the generic version, see register.
+    fn register_perf_id_range(&self, namespace: &efi::Guid, start: u16, end: u16) -> Result<(), Error> {
+        let (Some((_, fbpt)), Some(registry)) = (get_static_state(), get_perf_id_registry()) else {
+            return Err(EfiError::NotReady.into());
+        };
+        Self::register(registry, fbpt, namespace, start, end)
+    }
 }
 
 #[cfg(test)]
@@ -174,47 +294,53 @@ mod tests {
 
     use alloc::rc::Rc;
     use core::{assert_eq, ptr};
-    use r_efi::efi;
 
     use patina::{
-        boot_services::{MockBootServices, c_ptr::CPtr},
+        boot_services::{MockBootServices, c_ptr::CPtr, event::EventContext},
+        mock_env::MockEnv,
         runtime_services::MockRuntimeServices,
-        uefi_protocol::{ProtocolInterface, performance_measurement::EDKII_PERFORMANCE_MEASUREMENT_PROTOCOL_GUID},
     };
 
     use patina::performance::{
-        measurement::event_callback, record::PerformanceRecordBuffer, record::hob::MockHobPerformanceDataExtractor,
+        record::{PerformanceRecord, PerformanceRecordBuffer, hob::MockHobPerformanceDataExtractor},
         table::MockFirmwareBasicBootPerfTable,
     };
 
+    type ReportFbptEvent = Box<
+        EventContext<
+            Rc<MockBootServices>,
+            ReportFbptContext<
+                Rc<MockBootServices>,
+                Rc<MockRuntimeServices>,
+                MockFirmwareBasicBootPerfTable,
+                MockBootServices,
+            >,
+        >,
+    >;
+    type MmPerformanceRecordsEvent = Box<
+        EventContext<
+            Rc<MockBootServices>,
+            MmPerformanceRecordsContext<Rc<MockBootServices>, MockFirmwareBasicBootPerfTable, MockBootServices>,
+        >,
+    >;
+
     #[test]
     fn test_entry_point() {
-        let mut boot_services = MockBootServices::new();
-        boot_services.expect_raise_tpl().return_const(Tpl::APPLICATION);
-        boot_services.expect_restore_tpl().return_const(());
-
-        // Test that the protocol in installed.
-        boot_services
-            .expect_install_protocol_interface::<EdkiiPerformanceMeasurement, Box<_>>()
-            .once()
-            .withf_st(|handle, _protocol_interface| {
-                assert_eq!(&None, handle);
-                assert_eq!(EDKII_PERFORMANCE_MEASUREMENT_PROTOCOL_GUID, EdkiiPerformanceMeasurement::PROTOCOL_GUID);
-                true
-            })
-            .returning(|_, protocol_interface| Ok((1 as efi::Handle, protocol_interface.metadata())));
+        // The protocols are installed, the fbpt is reported at the end of dxe and updated with the smm data when ready
+        // to boot, and its address is installed to the configuration table.
+        let mut env = MockEnv::new()
+            .expect_protocol_install::<EdkiiPerformanceMeasurement>()
+            .expect_protocol_install::<PerformanceMeasurementMask>()
+            .expect_configuration_table::<Box<PerformanceProperty>>(PERFORMANCE_PROTOCOL);
 
         // Test that an event to report the fbpt at the end of dxe is created.
-        boot_services
-            .expect_create_event_ex::<Box<(
-                Rc<MockBootServices>,
-                Rc<MockRuntimeServices>,
-                &TplMutex<'static, MockFirmwareBasicBootPerfTable, MockBootServices>,
-            )>>()
+        env.boot_services()
+            .expect_create_event_ex::<ReportFbptEvent>()
             .once()
-            .withf_st(|event_type, notify_tpl, notify_function, _notify_context, event_group| {
+            .withf_st(|event_type, notify_tpl, notify_function, notify_context, event_group| {
                 assert_eq!(&EventType::NOTIFY_SIGNAL, event_type);
                 assert_eq!(&Tpl::CALLBACK, notify_tpl);
+                assert!(notify_function.is_some());
                 assert_eq!(
                     event_callback::report_fbpt_record_buffer::<
                         Rc<_>,
@@ -223,7 +349,7 @@ mod tests {
                         MockRuntimeServices,
                         MockFirmwareBasicBootPerfTable,
                     > as usize,
-                    notify_function.unwrap() as usize
+                    notify_context.notify() as usize
                 );
                 assert_eq!(&EVENT_GROUP_END_OF_DXE, event_group);
                 true
@@ -231,40 +357,26 @@ mod tests {
             .return_const_st(Ok(1_usize as efi::Event));
 
         // Test that an event to update the fbpt with smm data when ready to boot is created.
-        boot_services
-            .expect_create_event_ex::<Box<(
-                Rc<MockBootServices>,
-                MmCommRegion,
-                &TplMutex<'static, MockFirmwareBasicBootPerfTable, MockBootServices>,
-            )>>()
+        env.boot_services()
+            .expect_create_event_ex::<MmPerformanceRecordsEvent>()
             .once()
-            .withf_st(|event_type, notify_tpl, notify_function, _notify_context, event_group| {
+            .withf_st(|event_type, notify_tpl, notify_function, notify_context, event_group| {
                 assert_eq!(&EventType::NOTIFY_SIGNAL, event_type);
                 assert_eq!(&Tpl::CALLBACK, notify_tpl);
+                assert!(notify_function.is_some());
                 assert_eq!(
                     event_callback::fetch_and_add_mm_performance_records::<
                         Rc<_>,
                         MockBootServices,
                         MockFirmwareBasicBootPerfTable,
                     > as usize,
-                    notify_function.unwrap() as usize
+                    notify_context.notify() as usize
                 );
                 assert_eq!(&EVENT_GROUP_READY_TO_BOOT, event_group);
                 true
             })
-            .return_const_st(Ok(1_usize as efi::Event));
-
-        // Test that the address of the fbpt is installed to the configuration table.
-        boot_services
-            .expect_install_configuration_table::<Box<PerformanceProperty>>()
-            .once()
-            .withf(|guid, _data| {
-                assert_eq!(&PERFORMANCE_PROTOCOL, guid);
-                true
-            })
-            .return_const(Ok(()));
-
-        let runtime_services = MockRuntimeServices::new();
+            .return_const_st(Ok(2_usize as efi::Event));
+        let (boot_services, runtime_services) = env.into_services();
 
         let mut hob_perf_data_extractor = MockHobPerformanceDataExtractor::new();
         hob_perf_data_extractor
@@ -285,7 +397,140 @@ mod tests {
             Rc::new(runtime_services),
             Some(hob_perf_data_extractor),
             Some(mm_comm_region),
+            None,
+            true,
             fbpt,
         );
     }
+
+    #[test]
+    fn test_entry_point_without_mm() {
+        // The fbpt is still reported at the end of dxe, but no event fetching the MM performance records is created.
+        let mut env = MockEnv::new()
+            .expect_protocol_install::<EdkiiPerformanceMeasurement>()
+            .expect_protocol_install::<PerformanceMeasurementMask>()
+            .expect_event_group::<ReportFbptEvent>(EventType::NOTIFY_SIGNAL, Tpl::CALLBACK, &EVENT_GROUP_END_OF_DXE)
+            .expect_configuration_table::<Box<PerformanceProperty>>(PERFORMANCE_PROTOCOL);
+        env.boot_services().expect_create_event_ex::<MmPerformanceRecordsEvent>().never();
+        let (boot_services, runtime_services) = env.into_services();
+
+        let fbpt = TplMutex::new(
+            unsafe { &*ptr::addr_of!(boot_services) },
+            Tpl::NOTIFY,
+            MockFirmwareBasicBootPerfTable::new(),
+        );
+        let fbpt = unsafe { &*ptr::addr_of!(fbpt) };
+
+        assert_eq!(
+            Performance._entry_point(
+                Rc::new(boot_services),
+                Rc::new(runtime_services),
+                None::<MockHobPerformanceDataExtractor>,
+                None,
+                None,
+                true,
+                fbpt,
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_entry_point_with_reserved_fbpt_buffer() {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_raise_tpl().return_const(Tpl::APPLICATION);
+        boot_services.expect_restore_tpl().return_const(());
+        boot_services
+            .expect_install_protocol_interface::<EdkiiPerformanceMeasurement, Box<_>>()
+            .once()
+            .returning(|_, protocol_interface| Ok((1 as efi::Handle, protocol_interface.metadata())));
+        boot_services
+            .expect_install_protocol_interface::<PerformanceMeasurementMask, Box<_>>()
+            .once()
+            .returning(|_, protocol_interface| Ok((1 as efi::Handle, protocol_interface.metadata())));
+        boot_services
+            .expect_create_event_ex::<Box<
+                EventContext<
+                    Rc<MockBootServices>,
+                    ReportFbptContext<
+                        Rc<MockBootServices>,
+                        Rc<MockRuntimeServices>,
+                        MockFirmwareBasicBootPerfTable,
+                        MockBootServices,
+                    >,
+                >,
+            >>()
+            .once()
+            .return_const_st(Ok(1_usize as efi::Event));
+        boot_services.expect_install_configuration_table::<Box<PerformanceProperty>>().once().return_const(Ok(()));
+
+        let memory_buffer = Box::leak(alloc::vec![0_u8; 0x1000].into_boxed_slice());
+        let address = memory_buffer.as_ptr() as u64;
+
+        // The FBPT is moved into the reserved buffer, after the HOB records are set.
+        let mut sequence = mockall::Sequence::new();
+        let mut fbpt = MockFirmwareBasicBootPerfTable::new();
+        fbpt.expect_set_perf_records().once().in_sequence(&mut sequence).return_const(());
+        fbpt.expect_use_reserved_buffer()
+            .once()
+            .in_sequence(&mut sequence)
+            .withf(move |buffer| buffer.as_ptr() as u64 == address && buffer.len() == 0x1000)
+            .returning(|_| Ok(()));
+
+        let mut hob_perf_data_extractor = MockHobPerformanceDataExtractor::new();
+        hob_perf_data_extractor
+            .expect_extract_hob_perf_data()
+            .once()
+            .returning(|| Ok((0, PerformanceRecordBuffer::new())));
+
+        let fbpt = TplMutex::new(unsafe { &*ptr::addr_of!(boot_services) }, Tpl::NOTIFY, fbpt);
+        let fbpt = unsafe { &*ptr::addr_of!(fbpt) };
+
+        assert_eq!(
+            Performance._entry_point(
+                Rc::new(boot_services),
+                Rc::new(MockRuntimeServices::new()),
+                Some(hob_perf_data_extractor),
+                None,
+                Some(FbptReservedBuffer { address, size: 0x1000 }),
+                true,
+                fbpt,
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_register_perf_id_range() {
+        const NAMESPACE: efi::Guid = efi::Guid::from_fields(0x1, 0x2, 0x3, 0x4, 0x5, &[0x6, 0x7, 0x8, 0x9, 0xA, 0xB]);
+
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_raise_tpl().return_const(Tpl::APPLICATION);
+        boot_services.expect_restore_tpl().return_const(());
+
+        // Only the newly reserved range is added to the FBPT.
+        let mut fbpt = MockFirmwareBasicBootPerfTable::new();
+        let mut expected = [0_u8; 32];
+        let expected_size =
+            PerfIdRangeRecord::new(NAMESPACE, 0x1000, 0x10FF).write_into(&mut expected, &mut 0).unwrap();
+        fbpt.expect_add_record()
+            .once()
+            .withf(move |record| {
+                let mut bytes = [0_u8; 32];
+                record.write_into(&mut bytes, &mut 0).ok() == Some(expected_size) && bytes == expected
+            })
+            .returning(|_| Ok(()));
+
+        let fbpt = TplMutex::new(unsafe { &*ptr::addr_of!(boot_services) }, Tpl::NOTIFY, fbpt);
+        let fbpt = unsafe { &*ptr::addr_of!(fbpt) };
+        let registry = TplMutex::new(unsafe { &*ptr::addr_of!(boot_services) }, Tpl::NOTIFY, PerfIdRegistry::new());
+        let registry = unsafe { &*ptr::addr_of!(registry) };
+
+        assert!(PerfIdRegistryService::register(registry, fbpt, &NAMESPACE, 0x1000, 0x10FF).is_ok());
+        assert!(PerfIdRegistryService::register(registry, fbpt, &NAMESPACE, 0x1000, 0x10FF).is_ok());
+        assert!(matches!(
+            PerfIdRegistryService::register(registry, fbpt, &efi::Guid::from_bytes(&[0; 16]), 0x1080, 0x11FF),
+            Err(Error::PerfIdRangeCollision { start: 0x1000, end: 0x10FF })
+        ));
+    }
 }
@@ -0,0 +1,234 @@
+//! Firmware Basic Boot Performance Record Timestamps
+//!
+//! Fills the basic boot record of the FBPT, which boot performance tools read to split the boot between the firmware,
+//! the OS loader and ExitBootServices():
+//! - ResetEnd, from the SEC performance HOB.
+//! - The OS loader LoadImage() and StartImage() starts, from the progress codes BDS reports for the selected boot
+//!   option, [OS_LOADER_LOAD_PROGRESS_CODE] and [OS_LOADER_START_PROGRESS_CODE].
+//! - The ExitBootServices() entry, from the ExitBootServices event group, and exit, from the progress code the core
+//!   reports once the group is notified.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use core::{
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+use patina::{
+    boot_services::{
+        BootServices,
+        event::{EventBuilder, EventType},
+        tpl::Tpl,
+    },
+    error::{EfiError, Result},
+    performance::{
+        globals::get_static_state,
+        table::{BasicBootTimestamp, FirmwareBasicBootPerfTable},
+        timer::{ArchPerfTimer, PerfTimer, ticker_to_timestamp},
+    },
+    tpl_mutex::TplMutex,
+};
+use patina_pi::{
+    protocols::{
+        rsc_handler,
+        status_code::{EfiStatusCodeData, EfiStatusCodeType, EfiStatusCodeValue},
+    },
+    status_code::{
+        EFI_OEM_SPECIFIC, EFI_PROGRESS_CODE, EFI_SOFTWARE_DXE_BS_DRIVER, EFI_SOFTWARE_EFI_BOOT_SERVICE,
+        EFI_STATUS_CODE_TYPE_MASK, EFI_SW_BS_PC_EXIT_BOOT_SERVICES,
+    },
+};
+use r_efi::efi;
+
+/// Progress code reported by BDS just before loading the OS loader, `PcdProgressCodeOsLoaderLoad` in EDK II.
+pub const OS_LOADER_LOAD_PROGRESS_CODE: EfiStatusCodeValue = EFI_SOFTWARE_DXE_BS_DRIVER | EFI_OEM_SPECIFIC;
+
+/// Progress code reported by BDS just before starting the OS loader, `PcdProgressCodeOsLoaderStart` in EDK II.
+pub const OS_LOADER_START_PROGRESS_CODE: EfiStatusCodeValue = EFI_SOFTWARE_DXE_BS_DRIVER | EFI_OEM_SPECIFIC | 1;
+
+/// The Report Status Code Handler protocol [progress_code_handler] is registered with, to unregister it.
+static RSC_HANDLER: AtomicPtr<rsc_handler::Protocol> = AtomicPtr::new(ptr::null_mut());
+
+/// Sets ResetEnd, and registers the handlers recording the other timestamps of the basic boot record of `fbpt`.
+///
+/// The progress code handler is registered once the Report Status Code Handler protocol is installed, if it is not
+/// yet.
+pub(super) fn register<BB, B, F>(boot_services: &BB, fbpt: &'static TplMutex<'static, F, B>, reset_end: Option<u64>)
+where
+    BB: AsRef<B> + Clone + 'static,
+    B: BootServices + 'static,
+    F: FirmwareBasicBootPerfTable,
+{
+    match reset_end {
+        Some(reset_end) => fbpt.lock().set_basic_boot_timestamp(BasicBootTimestamp::ResetEnd, reset_end),
+        None => log::warn!("Performance: No SEC performance HOB, ResetEnd of the FBPT is 0."),
+    }
+
+    if let Err(err) = EventBuilder::new(BB::clone(boot_services), EventType::SIGNAL_EXIT_BOOT_SERVICES, Tpl::NOTIFY)
+        .one_shot()
+        .create(on_exit_boot_services::<F, B>, fbpt)
+    {
+        log::error!("Performance: Fail to create the ExitBootServices event: {err:?}.");
+    }
+
+    match register_handler(boot_services.as_ref()) {
+        Err(EfiError::NotFound) => (),
+        Err(err) => {
+            log::error!("Performance: Fail to register the progress code handler: {err:?}.");
+            return;
+        }
+        Ok(()) => return,
+    }
+
+    let result = EventBuilder::new(BB::clone(boot_services), EventType::NOTIFY_SIGNAL, Tpl::CALLBACK)
+        .one_shot()
+        .create(on_rsc_handler_installed::<BB, B>, BB::clone(boot_services))
+        .and_then(|event| boot_services.as_ref().register_protocol_notify(&rsc_handler::PROTOCOL_GUID, event));
+    if let Err(err) = result {
+        log::error!("Performance: Fail to wait for the Report Status Code Handler protocol: {err:?}.");
+    }
+}
+
+/// Notify function of the event signaled when the Report Status Code Handler protocol is installed.
+fn on_rsc_handler_installed<BB, B>(_event: efi::Event, boot_services: &mut BB)
+where
+    BB: AsRef<B>,
+    B: BootServices,
+{
+    if let Err(err) = register_handler(boot_services.as_ref()) {
+        log::error!("Performance: Fail to register the progress code handler: {err:?}.");
+    }
+}
+
+/// Registers [progress_code_handler] with the Report Status Code Handler protocol.
+fn register_handler<B: BootServices>(boot_services: &B) -> Result<()> {
+    // SAFETY: The interface installed for the protocol GUID is a Report Status Code Handler protocol.
+    let protocol = unsafe { boot_services.locate_protocol_unchecked(&rsc_handler::PROTOCOL_GUID, ptr::null_mut()) }?
+        as *mut rsc_handler::Protocol;
+
+    // The handler locks the FBPT, so it must not be called above the TPL of the lock.
+    // SAFETY: The protocol was located above and is not null.
+    let status = unsafe { ((*protocol).register)(progress_code_handler, efi::TPL_NOTIFY) };
+    EfiError::status_to_result(status)?;
+    RSC_HANDLER.store(protocol, Ordering::Release);
+    Ok(())
+}
+
+/// Notify function of the ExitBootServices event, recording the ExitBootServices() entry.
+fn on_exit_boot_services<F, B>(_event: efi::Event, fbpt: &mut &'static TplMutex<'static, F, B>)
+where
+    F: FirmwareBasicBootPerfTable,
+    B: BootServices + 'static,
+{
+    record_timestamp(fbpt, BasicBootTimestamp::ExitBootServicesEntry, &ArchPerfTimer);
+}
+
+/// Status code handler recording the timestamps of the progress codes of the basic boot record.
+#[coverage(off)] // This is tested via the generic version, see record_timestamp.
+extern "efiapi" fn progress_code_handler(
+    code_type: EfiStatusCodeType,
+    value: EfiStatusCodeValue,
+    _instance: u32,
+    _caller_id: *const efi::Guid,
+    _data: *const EfiStatusCodeData,
+) -> efi::Status {
+    let Some(field) = progress_code_timestamp(code_type, value) else {
+        return efi::Status::SUCCESS;
+    };
+    if let Some((_, fbpt)) = get_static_state() {
+        record_timestamp(fbpt, field, &ArchPerfTimer);
+    }
+
+    if field == BasicBootTimestamp::ExitBootServicesExit {
+        // The handler is boot services code, it must not be called at runtime.
+        let protocol = RSC_HANDLER.swap(ptr::null_mut(), Ordering::AcqRel);
+        // SAFETY: The protocol was stored when the handler was registered with it.
+        if let Some(protocol) = unsafe { protocol.as_ref() } {
+            (protocol.unregister)(progress_code_handler);
+        }
+    }
+    efi::Status::SUCCESS
+}
+
+/// Returns the timestamp of the basic boot record the status code is recorded as, if any.
+fn progress_code_timestamp(code_type: EfiStatusCodeType, value: EfiStatusCodeValue) -> Option<BasicBootTimestamp> {
+    if code_type & EFI_STATUS_CODE_TYPE_MASK != EFI_PROGRESS_CODE {
+        return None;
+    }
+    match value {
+        OS_LOADER_LOAD_PROGRESS_CODE => Some(BasicBootTimestamp::OsLoaderLoadImageStart),
+        OS_LOADER_START_PROGRESS_CODE => Some(BasicBootTimestamp::OsLoaderStartImageStart),
+        value if value == EFI_SOFTWARE_EFI_BOOT_SERVICE | EFI_SW_BS_PC_EXIT_BOOT_SERVICES => {
+            Some(BasicBootTimestamp::ExitBootServicesExit)
+        }
+        _ => None,
+    }
+}
+
+/// Records the current time of `timer` as `field` of the basic boot record of `fbpt`.
+///
+/// BDS reports the OS loader progress codes for every boot option it attempts, so the timestamps are those of the
+/// last attempted boot option.
+fn record_timestamp<F, B>(fbpt: &TplMutex<'_, F, B>, field: BasicBootTimestamp, timer: &impl PerfTimer)
+where
+    F: FirmwareBasicBootPerfTable,
+    B: BootServices,
+{
+    fbpt.lock().set_basic_boot_timestamp(field, ticker_to_timestamp(0, timer));
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+
+    use patina::{
+        boot_services::MockBootServices,
+        performance::{table::MockFirmwareBasicBootPerfTable, timer::MockPerfTimer},
+    };
+    use patina_pi::status_code::{EFI_ERROR_CODE, EFI_SOFTWARE_DXE_CORE, EFI_SW_EC_START_ERROR};
+
+    #[test]
+    fn test_progress_code_timestamp() {
+        assert_eq!(
+            progress_code_timestamp(EFI_PROGRESS_CODE, OS_LOADER_LOAD_PROGRESS_CODE),
+            Some(BasicBootTimestamp::OsLoaderLoadImageStart)
+        );
+        assert_eq!(
+            progress_code_timestamp(EFI_PROGRESS_CODE, OS_LOADER_START_PROGRESS_CODE),
+            Some(BasicBootTimestamp::OsLoaderStartImageStart)
+        );
+        assert_eq!(
+            progress_code_timestamp(EFI_PROGRESS_CODE, EFI_SOFTWARE_EFI_BOOT_SERVICE | EFI_SW_BS_PC_EXIT_BOOT_SERVICES),
+            Some(BasicBootTimestamp::ExitBootServicesExit)
+        );
+        // Only progress codes are recorded.
+        assert_eq!(progress_code_timestamp(EFI_ERROR_CODE, OS_LOADER_LOAD_PROGRESS_CODE), None);
+        assert_eq!(progress_code_timestamp(EFI_PROGRESS_CODE, EFI_SOFTWARE_DXE_CORE | EFI_SW_EC_START_ERROR), None);
+    }
+
+    #[test]
+    fn test_record_timestamp() {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_raise_tpl().return_const(Tpl::APPLICATION);
+        boot_services.expect_restore_tpl().return_const(());
+
+        let mut timer = MockPerfTimer::new();
+        timer.expect_cpu_count().return_const(3_000_u64);
+        timer.expect_perf_frequency().return_const(1_000_000_u64);
+
+        let mut fbpt = MockFirmwareBasicBootPerfTable::new();
+        fbpt.expect_set_basic_boot_timestamp()
+            .once()
+            .withf(|field, timestamp| *field == BasicBootTimestamp::OsLoaderStartImageStart && *timestamp == 3_000_000)
+            .return_const(());
+
+        let fbpt = TplMutex::new(&boot_services, Tpl::NOTIFY, fbpt);
+        record_timestamp(&fbpt, BasicBootTimestamp::OsLoaderStartImageStart, &timer);
+    }
+}
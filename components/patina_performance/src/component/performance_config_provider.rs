@@ -17,10 +17,13 @@
 extern crate alloc;
 
 use crate::config;
-use patina::component::{
-    IntoComponent,
-    hob::{FromHob, Hob},
-    params::ConfigMut,
+use patina::{
+    component::{
+        IntoComponent,
+        hob::{FromHob, Hob},
+        params::ConfigMut,
+    },
+    performance::Measurement,
 };
 
 /// Responsible for providing performance configuration information to other performance components.
@@ -45,6 +48,14 @@ pub struct PerformanceConfigHob {
     enabled_measurements: u32,
 }
 
+/// Returns the measurement mask configured by a `PerformanceConfigHob` mask.
+///
+/// HOB producers predating the function span and event signal measurements do not set their bits, so a mask without
+/// either of them keeps them enabled, as they were recorded unconditionally before.
+fn hob_measurement_mask(mask: u32) -> u32 {
+    if mask & Measurement::DEFAULT == 0 { mask | Measurement::DEFAULT } else { mask }
+}
+
 impl PerformanceConfigurationProvider {
     /// Entry point for the Patina Performance Configuration Provider.
     ///
@@ -73,7 +84,7 @@ impl PerformanceConfigurationProvider {
             log::trace!("The Patina Performance component is disabled per HOB configuration.");
         } else {
             log::trace!("The Patina Performance component is enabled per HOB configuration.");
-            config_mut.enabled_measurements = perf_config_hob.enabled_measurements;
+            config_mut.enabled_measurements = hob_measurement_mask(perf_config_hob.enabled_measurements);
         }
 
         log::trace!("Outgoing MM Configuration: {:?}", *config_mut);
@@ -83,3 +94,19 @@ impl PerformanceConfigurationProvider {
         Ok(())
     }
 }
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hob_measurement_mask_enables_new_measurements_for_older_hobs() {
+        let legacy_mask = Measurement::LoadImage | Measurement::StartImage;
+        assert_eq!(hob_measurement_mask(legacy_mask), legacy_mask | Measurement::DEFAULT);
+        assert_eq!(hob_measurement_mask(0), Measurement::DEFAULT);
+
+        let mask = Measurement::LoadImage | Measurement::FunctionSpan;
+        assert_eq!(hob_measurement_mask(mask), mask);
+    }
+}
@@ -0,0 +1,122 @@
+//! UEFI Timestamp Protocol Support
+//!
+//! This module provides a component that installs the UEFI Timestamp protocol, exposing the invariant architecture
+//! performance counter that timestamps the performance records as a standard high-resolution time source for OS
+//! loaders and applications.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use patina::{
+    boot_services::{BootServices, StandardBootServices},
+    component::IntoComponent,
+    error::EfiError,
+    performance::timer::{ArchPerfTimer, PerfTimer},
+};
+use r_efi::{efi, protocols::timestamp};
+
+/// The last value of the performance counter before it rolls over. The architecture counters are 64 bits wide.
+const END_VALUE: u64 = u64::MAX;
+
+/// The component that installs the UEFI Timestamp protocol.
+#[derive(IntoComponent, Default)]
+pub struct Timestamp;
+
+impl Timestamp {
+    /// Entry point of [`Timestamp`]
+    ///
+    /// Installs the Timestamp protocol, unless the frequency of the performance counter is unknown.
+    fn entry_point(self, boot_services: StandardBootServices) -> Result<(), EfiError> {
+        if counter_properties(&ArchPerfTimer).is_none() {
+            log::error!("The performance counter frequency is unknown, the Timestamp protocol is not installed.");
+            return Err(EfiError::Unsupported);
+        }
+
+        let protocol = Box::new(timestamp::Protocol { get_timestamp, get_properties });
+        match boot_services.install_protocol_interface(None, protocol) {
+            Err(status) => {
+                log::error!("Failed to install the Timestamp protocol! Status = {status:#x?}");
+                Err(EfiError::ProtocolError)
+            }
+            Ok(_) => {
+                log::info!("Timestamp protocol installed.");
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Returns the properties of the counter of `timer`, or `None` if its frequency is unknown.
+fn counter_properties(timer: &impl PerfTimer) -> Option<timestamp::Properties> {
+    match timer.perf_frequency() {
+        0 => None,
+        frequency => Some(timestamp::Properties { frequency, end_value: END_VALUE }),
+    }
+}
+
+/// EFI API returning the current value of the performance counter.
+extern "efiapi" fn get_timestamp() -> u64 {
+    ArchPerfTimer.cpu_count()
+}
+
+/// EFI API returning the frequency and end value of the performance counter.
+extern "efiapi" fn get_properties(properties: *mut timestamp::Properties) -> efi::Status {
+    write_properties(&ArchPerfTimer, properties)
+}
+
+fn write_properties(timer: &impl PerfTimer, properties: *mut timestamp::Properties) -> efi::Status {
+    if properties.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    match counter_properties(timer) {
+        Some(value) => {
+            // SAFETY: properties was checked for null, the rest must be trusted from the caller.
+            unsafe { properties.write_unaligned(value) };
+            efi::Status::SUCCESS
+        }
+        None => efi::Status::DEVICE_ERROR,
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use patina::performance::timer::MockPerfTimer;
+
+    fn mock_timer(frequency: u64) -> MockPerfTimer {
+        let mut timer = MockPerfTimer::new();
+        timer.expect_cpu_count().never();
+        timer.expect_perf_frequency().return_const(frequency);
+        timer
+    }
+
+    #[test]
+    fn test_get_properties_reports_the_counter() {
+        let mut properties = timestamp::Properties { frequency: 0, end_value: 0 };
+        assert_eq!(write_properties(&mock_timer(3_000_000_000), &mut properties), efi::Status::SUCCESS);
+        assert_eq!(properties.frequency, 3_000_000_000);
+        assert_eq!(properties.end_value, u64::MAX);
+    }
+
+    #[test]
+    fn test_get_properties_fails_without_a_frequency() {
+        let mut properties = timestamp::Properties { frequency: 0, end_value: 0 };
+        assert_eq!(write_properties(&mock_timer(0), &mut properties), efi::Status::DEVICE_ERROR);
+        assert!(counter_properties(&mock_timer(0)).is_none());
+    }
+
+    #[test]
+    fn test_get_properties_rejects_null() {
+        let mut timer = MockPerfTimer::new();
+        timer.expect_perf_frequency().never();
+        assert_eq!(write_properties(&timer, core::ptr::null_mut()), efi::Status::INVALID_PARAMETER);
+    }
+}
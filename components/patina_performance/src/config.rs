@@ -20,7 +20,10 @@
 //!        | patina::performance::Measurement::DriverBindingSupport     // Adds driver binding support measurements.
 //!        | patina::performance::Measurement::LoadImage                // Adds load image measurements.
 //!        | patina::performance::Measurement::StartImage               // Adds start image measurements.
-//!     }
+//!        | patina::performance::Measurement::FunctionSpan             // Adds function begin/end measurements.
+//!        | patina::performance::Measurement::EventSignal              // Adds event signal and callback measurements.
+//!     },
+//!     disable_fbpt_address_reuse: false,
 //! })
 //! .with_component(patina_performance::component::Performance)
 //! .start()
@@ -29,6 +32,18 @@
 //! // ...
 //! ```
 //!
+//! Records belonging to a measurement not in `enabled_measurements` are dropped, whether they are created by the core
+//! or through the performance measurement protocol, so verbose measurements such as function spans can be disabled
+//! on production builds without losing module timing. The mask can be changed at runtime with the
+//! `PerformanceMeasurementMask` protocol installed by the component.
+//!
+//! Function spans and events were recorded unconditionally before they could be masked, so they are enabled in the
+//! default mask, and a `PerformanceConfigHob` mask without either of their bits keeps them enabled.
+//!
+//! The FBPT is allocated at the address it had in the previous boot, saved in the `FirmwarePerformanceVariable`, so
+//! that it is found at the same address across S3 and S4 cycles. Platforms randomizing their memory layout can set
+//! `disable_fbpt_address_reuse` to allocate it anywhere below 4GB instead.
+//!
 //! ## License
 //!
 //! Copyright (C) Microsoft Corporation.
@@ -37,10 +52,23 @@
 //!
 
 /// The configuration for the Patina Performance component.
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct PerfConfig {
     /// Indicates whether the Patina Performance component is enabled.
     pub enable_component: bool,
-    /// A wrapper to generate a mask of all enabled measurements.
+    /// The mask of the enabled measurements, built from [patina::performance::Measurement]. Defaults to
+    /// [Measurement::DEFAULT](patina::performance::Measurement::DEFAULT).
     pub enabled_measurements: u32,
+    /// Disables allocating the FBPT at the address of the previous boot, and saving its address for the next boot.
+    pub disable_fbpt_address_reuse: bool,
+}
+
+impl Default for PerfConfig {
+    fn default() -> Self {
+        Self {
+            enable_component: false,
+            enabled_measurements: patina::performance::Measurement::DEFAULT,
+            disable_fbpt_address_reuse: false,
+        }
+    }
 }
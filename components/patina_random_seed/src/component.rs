@@ -0,0 +1,134 @@
+//! Patina Random Seed Component
+//!
+//! Publishes a fresh random seed each time the platform signals that it is ready to boot, once the drivers producing
+//! the RNG protocol have been connected. Each boot attempt gets its own seed, and the seed of the previous attempt is
+//! cleared, so that the same entropy is never handed to two OS loaders.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use core::{clone::Clone, convert::AsRef};
+use patina::{
+    boot_services::{
+        BootServices, StandardBootServices,
+        event::{EventBuilder, EventType},
+        tpl::Tpl,
+    },
+    component::{IntoComponent, params::Config},
+    error::EfiError,
+};
+use r_efi::{efi, system::EVENT_GROUP_READY_TO_BOOT};
+
+use crate::{
+    config::RandomSeedConfig,
+    seed::{self, RANDOM_SEED_MAX_SIZE, RandomSeedHeader},
+};
+
+/// Random Seed Component.
+#[derive(IntoComponent)]
+pub struct RandomSeed;
+
+/// Context of the ready to boot event of the [RandomSeed] component.
+pub struct RandomSeedContext<BB> {
+    boot_services: BB,
+    config: RandomSeedConfig,
+    published: Option<*mut RandomSeedHeader>,
+}
+
+impl RandomSeed {
+    /// Entry point of [`RandomSeed`]
+    #[coverage(off)] // This is tested via the generic version, see _entry_point.
+    pub fn entry_point(
+        self,
+        config: Config<RandomSeedConfig>,
+        boot_services: StandardBootServices,
+    ) -> Result<(), EfiError> {
+        self._entry_point(boot_services, *config)
+    }
+
+    /// Entry point that have generic parameter.
+    fn _entry_point<BB, B>(self, boot_services: BB, config: RandomSeedConfig) -> Result<(), EfiError>
+    where
+        BB: AsRef<B> + Clone + 'static,
+        B: BootServices + 'static,
+    {
+        if !config.enabled {
+            log::info!("Random seed: disabled, no seed is handed to the OS loader.");
+            return Ok(());
+        }
+        if config.size == 0 || config.size > RANDOM_SEED_MAX_SIZE {
+            log::error!("Random seed: invalid size {}, expected 1 to {RANDOM_SEED_MAX_SIZE} bytes.", config.size);
+            return Err(EfiError::InvalidParameter);
+        }
+
+        let context = RandomSeedContext { boot_services: BB::clone(&boot_services), config, published: None };
+        EventBuilder::new(boot_services, EventType::NOTIFY_SIGNAL, Tpl::CALLBACK)
+            .event_group(&EVENT_GROUP_READY_TO_BOOT)
+            .create(on_ready_to_boot::<BB, B>, context)?;
+        Ok(())
+    }
+}
+
+/// Ready to boot notify function, publishing a fresh random seed.
+fn on_ready_to_boot<BB, B>(_event: efi::Event, context: &mut RandomSeedContext<BB>)
+where
+    BB: AsRef<B>,
+    B: BootServices,
+{
+    let boot_services = context.boot_services.as_ref();
+    match seed::publish(boot_services, context.config.size, context.config.algorithm) {
+        Ok(table) => {
+            log::info!("Random seed: published {} bytes of entropy.", context.config.size);
+            if let Some(previous) = context.published.replace(table) {
+                // SAFETY: The previous table was returned by publish, and the new table replaced it.
+                unsafe { seed::release(boot_services, previous) };
+            }
+        }
+        Err(err) => log::warn!("Random seed: failed to publish a seed, the OS loader gets none: {err:?}"),
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use alloc::rc::Rc;
+    use patina::boot_services::{MockBootServices, event::EventContext};
+    use std::boxed::Box;
+
+    type TestEventContext = EventContext<Rc<MockBootServices>, RandomSeedContext<Rc<MockBootServices>>>;
+
+    #[test]
+    fn test_entry_point_registers_ready_to_boot_event() {
+        let mut boot_services = MockBootServices::new();
+        boot_services
+            .expect_create_event_ex::<Box<TestEventContext>>()
+            .once()
+            .withf_st(|event_type, notify_tpl, notify_function, _notify_context, event_group| {
+                assert_eq!(&EventType::NOTIFY_SIGNAL, event_type);
+                assert_eq!(&Tpl::CALLBACK, notify_tpl);
+                assert!(notify_function.is_some());
+                assert_eq!(&EVENT_GROUP_READY_TO_BOOT, event_group);
+                true
+            })
+            .return_const_st(Ok(1_usize as efi::Event));
+
+        assert_eq!(RandomSeed._entry_point(Rc::new(boot_services), RandomSeedConfig::default()), Ok(()));
+    }
+
+    #[test]
+    fn test_entry_point_disabled_or_invalid() {
+        let boot_services = Rc::new(MockBootServices::new());
+        let config = RandomSeedConfig { enabled: false, ..Default::default() };
+        assert_eq!(RandomSeed._entry_point(boot_services.clone(), config), Ok(()));
+
+        let config = RandomSeedConfig { size: RANDOM_SEED_MAX_SIZE + 1, ..Default::default() };
+        assert_eq!(RandomSeed._entry_point(boot_services, config), Err(EfiError::InvalidParameter));
+    }
+}
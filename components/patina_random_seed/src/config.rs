@@ -0,0 +1,30 @@
+//! Patina Random Seed Component Configuration
+//!
+//! The configuration can be set statically with `.with_config()` or produced dynamically during boot. If no
+//! configuration is provided, a 32 byte seed is generated with the default algorithm of the RNG protocol.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use r_efi::efi;
+
+/// The configuration for the Patina Random Seed component.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RandomSeedConfig {
+    /// Publishes the random seed. Platforms may disable it, e.g. for reproducible test runs.
+    pub enabled: bool,
+    /// The number of bytes of entropy in the seed, at most [RANDOM_SEED_MAX_SIZE](crate::seed::RANDOM_SEED_MAX_SIZE).
+    pub size: usize,
+    /// The RNG algorithm used to generate the seed, or `None` for the default algorithm of the RNG protocol.
+    pub algorithm: Option<efi::Guid>,
+}
+
+impl Default for RandomSeedConfig {
+    fn default() -> Self {
+        Self { enabled: true, size: 32, algorithm: None }
+    }
+}
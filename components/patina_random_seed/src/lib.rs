@@ -0,0 +1,39 @@
+//! Random seed handoff to the OS loader for Patina platforms.
+//!
+//! Operating systems randomize the layout of their kernel (KASLR) and seed their random number generator early in
+//! boot, before they can use the hardware random number generators themselves. This crate provides:
+//!
+//! - [seed]: the format of the random seed configuration table, and a helper that fills it from the RNG protocol and
+//!   publishes it.
+//! - [component::RandomSeed]: a component that publishes a fresh random seed each time the platform is ready to boot.
+//!
+//! ## Integration Example
+//!
+//! ```rust,ignore
+//! // ...
+//!
+//! Core::default()
+//!  // ...
+//!  .with_config(patina_random_seed::config::RandomSeedConfig { size: 64, ..Default::default() })
+//!  .with_component(patina_random_seed::component::RandomSeed)
+//!  .start()
+//!  .unwrap();
+//!
+//! // ...
+//! ```
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+#![cfg_attr(not(test), no_std)]
+#![feature(coverage_attribute)]
+
+extern crate alloc;
+
+pub mod component;
+pub mod config;
+pub mod seed;
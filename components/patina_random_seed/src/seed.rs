@@ -0,0 +1,194 @@
+//! Random Seed Table
+//!
+//! The random seed is handed off to the OS loader as a configuration table, with the format defined by Linux
+//! (`LINUX_EFI_RANDOM_SEED_TABLE_GUID`): a [RandomSeedHeader] holding the number of bytes of entropy, followed by
+//! these bytes. The table is allocated as ACPI reclaim memory, so that it is not reused before the OS consumes it.
+//!
+//! See `drivers/firmware/efi/efi.c` in Linux for the consumer side.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use core::{mem, ptr};
+use patina::{
+    boot_services::{BootServices, allocation::MemoryType},
+    error::EfiError,
+    guids::RANDOM_SEED_TABLE,
+};
+use r_efi::{efi, protocols::rng};
+
+/// The maximum number of bytes of entropy in the seed, above which Linux ignores the seed.
+pub const RANDOM_SEED_MAX_SIZE: usize = 512;
+
+/// The header of the random seed configuration table, followed by `size` bytes of entropy.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RandomSeedHeader {
+    /// The number of bytes of entropy following the header.
+    pub size: u32,
+}
+
+/// Fills a random seed table of `size` bytes of entropy from the RNG protocol, with `algorithm` or the default
+/// algorithm of the RNG protocol, and publishes it as a configuration table.
+///
+/// Returns the table, which must be released with [release] once it is replaced.
+pub fn publish<B: BootServices>(
+    boot_services: &B,
+    size: usize,
+    algorithm: Option<efi::Guid>,
+) -> Result<*mut RandomSeedHeader, EfiError> {
+    if size == 0 || size > RANDOM_SEED_MAX_SIZE {
+        return Err(EfiError::InvalidParameter);
+    }
+
+    // SAFETY: The RNG protocol interface matches the protocol GUID.
+    let rng = unsafe { boot_services.locate_protocol::<rng::Protocol>(None) }?;
+
+    let table_size = mem::size_of::<RandomSeedHeader>() + size;
+    let table = boot_services.allocate_pool(MemoryType::ACPI_RECLAIM_MEMORY, table_size)? as *mut RandomSeedHeader;
+    // SAFETY: The table was allocated with room for the header followed by the seed.
+    unsafe { table.write(RandomSeedHeader { size: size as u32 }) };
+
+    let mut algorithm = algorithm;
+    let algorithm_ptr = algorithm.as_mut().map_or(ptr::null_mut(), |algorithm| algorithm as *mut efi::Guid);
+    // SAFETY: The seed follows the header in the table.
+    let status = (rng.get_rng)(rng, algorithm_ptr, size, unsafe { table.add(1) } as *mut u8);
+    if status.is_error() {
+        // SAFETY: The table was allocated above and is not published.
+        unsafe { release(boot_services, table) };
+        return Err(status.into());
+    }
+
+    // SAFETY: The table is a random seed table.
+    if let Err(status) = unsafe { boot_services.install_configuration_table_unchecked(&RANDOM_SEED_TABLE, table as _) }
+    {
+        // SAFETY: The table was allocated above and failed to be published.
+        unsafe { release(boot_services, table) };
+        return Err(status.into());
+    }
+    Ok(table)
+}
+
+/// Clears the entropy of a random seed table returned by [publish], and frees it.
+///
+/// # Safety
+///
+/// `table` must have been returned by [publish], must no longer be the published configuration table, and must not
+/// be used after this call.
+pub unsafe fn release<B: BootServices>(boot_services: &B, table: *mut RandomSeedHeader) {
+    // SAFETY: The caller guarantees the table was allocated by publish, with room for the header followed by the seed.
+    unsafe {
+        let size = mem::size_of::<RandomSeedHeader>() + (*table).size as usize;
+        for offset in 0..size {
+            // The seed must not linger in memory once it is no longer published.
+            ptr::write_volatile((table as *mut u8).add(offset), 0);
+        }
+    }
+    let _ = boot_services.free_pool(table as *mut u8);
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use patina::boot_services::MockBootServices;
+    use std::{alloc, boxed::Box, vec::Vec};
+
+    const ALGORITHM: efi::Guid = patina::guid!("A7AF67CB-603B-4D42-BA21-70BFB6293F96");
+
+    extern "efiapi" fn get_info(_: *mut rng::Protocol, _: *mut usize, _: *mut efi::Guid) -> efi::Status {
+        efi::Status::UNSUPPORTED
+    }
+
+    extern "efiapi" fn get_rng(
+        _: *mut rng::Protocol,
+        algorithm: *mut efi::Guid,
+        size: usize,
+        value: *mut u8,
+    ) -> efi::Status {
+        // SAFETY: The algorithm is either null or a valid GUID.
+        match unsafe { algorithm.as_ref() } {
+            Some(algorithm) if *algorithm != ALGORITHM => efi::Status::UNSUPPORTED,
+            // SAFETY: The buffer has room for `size` bytes.
+            _ => unsafe {
+                ptr::write_bytes(value, 0xA5, size);
+                efi::Status::SUCCESS
+            },
+        }
+    }
+
+    fn layout(size: usize) -> alloc::Layout {
+        alloc::Layout::from_size_align(size, 8).unwrap()
+    }
+
+    fn mock_boot_services() -> MockBootServices {
+        let mut boot_services = MockBootServices::new();
+        boot_services
+            .expect_locate_protocol::<rng::Protocol>()
+            .returning(|_| Ok(Box::leak(Box::new(rng::Protocol { get_info, get_rng }))));
+        boot_services.expect_allocate_pool().returning(|memory_type, size| {
+            assert_eq!(memory_type, MemoryType::ACPI_RECLAIM_MEMORY);
+            // SAFETY: The layout has a non-zero size.
+            Ok(unsafe { alloc::alloc(layout(size)) })
+        });
+        boot_services
+    }
+
+    #[test]
+    fn test_publish_seed() {
+        let mut boot_services = mock_boot_services();
+        boot_services.expect_install_configuration_table_unchecked().once().returning(|guid, table| {
+            assert_eq!(guid, &RANDOM_SEED_TABLE);
+            // SAFETY: The table is the random seed table published above.
+            let seed = unsafe {
+                let table = table as *const RandomSeedHeader;
+                std::slice::from_raw_parts(table.add(1) as *const u8, (*table).size as usize).to_vec()
+            };
+            assert_eq!(seed, [0xA5; 64].to_vec());
+            Ok(())
+        });
+
+        let table = publish(&boot_services, 64, Some(ALGORITHM)).unwrap();
+        // SAFETY: The table was published above.
+        assert_eq!(unsafe { *table }, RandomSeedHeader { size: 64 });
+
+        // The seed is cleared before the table is freed.
+        boot_services.expect_free_pool().once().returning(|buffer| {
+            // SAFETY: The table was allocated with room for the header followed by the seed.
+            let bytes: Vec<u8> = unsafe { std::slice::from_raw_parts(buffer, 4 + 64).to_vec() };
+            assert!(bytes.iter().all(|&byte| byte == 0));
+            // SAFETY: The buffer was allocated by the mock allocate_pool with this layout.
+            unsafe { alloc::dealloc(buffer, layout(4 + 64)) };
+            Ok(())
+        });
+        // SAFETY: The table was returned by publish and is not used afterwards.
+        unsafe { release(&boot_services, table) };
+    }
+
+    #[test]
+    fn test_publish_seed_failures() {
+        let boot_services = MockBootServices::new();
+        assert_eq!(publish(&boot_services, 0, None), Err(EfiError::InvalidParameter));
+        assert_eq!(publish(&boot_services, RANDOM_SEED_MAX_SIZE + 1, None), Err(EfiError::InvalidParameter));
+
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_locate_protocol::<rng::Protocol>().returning(|_| Err(efi::Status::NOT_FOUND));
+        assert_eq!(publish(&boot_services, 32, None), Err(EfiError::NotFound));
+
+        // The table is freed if the algorithm is not supported.
+        let mut boot_services = mock_boot_services();
+        boot_services.expect_free_pool().once().returning(|buffer| {
+            // SAFETY: The buffer was allocated by the mock allocate_pool with this layout.
+            unsafe { alloc::dealloc(buffer, layout(4 + 32)) };
+            Ok(())
+        });
+        let other_algorithm = patina::guid!("0B4D4E6F-17C1-4F7D-B5D4-7C6E0E3A2B1F");
+        assert_eq!(publish(&boot_services, 32, Some(other_algorithm)), Err(EfiError::Unsupported));
+    }
+}
@@ -0,0 +1,84 @@
+//! Integration tests publishing the random seed against the host DXE core boot services.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use core::{
+    ffi::c_void,
+    ptr, slice,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use patina::{
+    boot_services::{
+        BootServices,
+        event::{EventBuilder, EventType},
+        tpl::Tpl,
+    },
+    guids::RANDOM_SEED_TABLE,
+};
+use patina_random_seed::{component::RandomSeed, config::RandomSeedConfig, seed::RandomSeedHeader};
+use patina_test::TestHarness;
+use r_efi::{efi, protocols::rng, system::EVENT_GROUP_READY_TO_BOOT};
+
+/// The value of the bytes returned by the next call to the RNG protocol.
+static NEXT_VALUE: AtomicU8 = AtomicU8::new(1);
+
+extern "efiapi" fn get_info(_: *mut rng::Protocol, _: *mut usize, _: *mut efi::Guid) -> efi::Status {
+    efi::Status::UNSUPPORTED
+}
+
+extern "efiapi" fn get_rng(_: *mut rng::Protocol, _: *mut efi::Guid, size: usize, value: *mut u8) -> efi::Status {
+    // SAFETY: The buffer has room for `size` bytes.
+    unsafe { ptr::write_bytes(value, NEXT_VALUE.fetch_add(1, Ordering::SeqCst), size) };
+    efi::Status::SUCCESS
+}
+
+/// Returns the random seed table installed in the system table, if any.
+fn published_seed(harness: &TestHarness) -> Option<(*const RandomSeedHeader, Vec<u8>)> {
+    let table = harness.configuration_table(&RANDOM_SEED_TABLE)? as *const RandomSeedHeader;
+    // SAFETY: The header is followed by the seed.
+    unsafe { Some((table, slice::from_raw_parts(table.add(1) as *const u8, (*table).size as usize).to_vec())) }
+}
+
+fn ready_to_boot(_event: efi::Event, _context: &mut ()) {}
+
+#[test]
+fn test_a_fresh_seed_is_published_for_each_boot_attempt() {
+    let config = RandomSeedConfig { size: 48, ..Default::default() };
+    let mut harness = TestHarness::new().with_config(config).with_component(RandomSeed);
+    harness.run().unwrap();
+    assert!(harness.pending_components().is_empty());
+
+    let boot_services = harness.boot_services();
+    let rng = Box::leak(Box::new(rng::Protocol { get_info, get_rng }));
+    // SAFETY: The interface is an RNG protocol, and is leaked.
+    unsafe {
+        boot_services
+            .install_protocol_interface_unchecked(None, &rng::PROTOCOL_GUID, rng as *mut rng::Protocol as *mut c_void)
+            .unwrap();
+    }
+    // The seed is not published until the platform is ready to boot.
+    assert_eq!(published_seed(&harness), None);
+
+    // Signal ready to boot, as BDS does before starting a boot option.
+    let event = EventBuilder::new(boot_services.clone(), EventType::NOTIFY_SIGNAL, Tpl::CALLBACK)
+        .event_group(&EVENT_GROUP_READY_TO_BOOT)
+        .create(ready_to_boot, ())
+        .unwrap();
+    boot_services.signal_event(event.event()).unwrap();
+
+    let (first_table, first_seed) = published_seed(&harness).unwrap();
+    assert_eq!(first_seed, vec![1; 48]);
+
+    // A second boot attempt gets a new seed, and the previous one is cleared.
+    boot_services.signal_event(event.event()).unwrap();
+    let (second_table, second_seed) = published_seed(&harness).unwrap();
+    assert_ne!(first_table, second_table);
+    assert_eq!(second_seed, vec![2; 48]);
+
+    event.close().unwrap();
+}
@@ -0,0 +1,521 @@
+//! Commands of the Patina Shell
+//!
+//! Each command writes its output to the console and reports its own failures, e.g. a missing protocol, as text. Only
+//! a failure to write to the console is returned as an error, which ends the shell.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use alloc::vec::Vec;
+use core::{
+    fmt::{self, Write},
+    iter, ptr, slice,
+};
+use fallible_streaming_iterator::FallibleStreamingIterator;
+use patina::{
+    Guid,
+    base::UEFI_PAGE_SIZE,
+    boot_services::{BootServices, protocol_handler::HandleSearchType},
+    efi_types::EfiMemoryType,
+    guids::EDKII_FPDT_EXTENDED_FIRMWARE_PERFORMANCE,
+    performance::record::extended::{
+        DualGuidStringEventRecord, DynamicStringEventRecord, GuidEventRecord, GuidQwordEventRecord,
+        GuidQwordStringEventRecord, PerfIdRangeRecord,
+    },
+    runtime_services::{RuntimeServices, variable_services::VariableNameIterator},
+    uefi_protocol::{device_path::DevicePath, loaded_image::LoadedImage},
+};
+use r_efi::efi::{self, protocols};
+
+/// Size of the FBPT header: the signature and the length of the table.
+const FBPT_HEADER_SIZE: usize = 8;
+/// Size of the header of a performance record: the type, the length and the revision.
+const RECORD_HEADER_SIZE: usize = 4;
+/// Type of the Firmware Basic Boot Performance Data record, the first record of the FBPT.
+const BASIC_BOOT_RECORD_TYPE: u16 = 2;
+
+/// Protocols named by the `dh` command, other protocols are shown by GUID.
+const KNOWN_PROTOCOLS: &[(efi::Guid, &str)] = &[
+    (protocols::block_io::PROTOCOL_GUID, "BlockIo"),
+    (protocols::device_path::PROTOCOL_GUID, "DevicePath"),
+    (protocols::disk_io::PROTOCOL_GUID, "DiskIo"),
+    (protocols::driver_binding::PROTOCOL_GUID, "DriverBinding"),
+    (protocols::graphics_output::PROTOCOL_GUID, "GraphicsOutput"),
+    (protocols::load_file::PROTOCOL_GUID, "LoadFile"),
+    (protocols::loaded_image::PROTOCOL_GUID, "LoadedImage"),
+    (protocols::loaded_image_device_path::PROTOCOL_GUID, "LoadedImageDevicePath"),
+    (protocols::pci_io::PROTOCOL_GUID, "PciIo"),
+    (protocols::simple_file_system::PROTOCOL_GUID, "SimpleFileSystem"),
+    (protocols::simple_network::PROTOCOL_GUID, "SimpleNetwork"),
+    (protocols::simple_text_input::PROTOCOL_GUID, "SimpleTextInput"),
+    (protocols::simple_text_input_ex::PROTOCOL_GUID, "SimpleTextInputEx"),
+    (protocols::simple_text_output::PROTOCOL_GUID, "SimpleTextOutput"),
+    (crate::protocol::PROTOCOL_GUID, "PatinaShell"),
+];
+
+/// The services available to the commands.
+pub(crate) struct ShellContext<'a, B, R> {
+    pub(crate) boot_services: &'a B,
+    pub(crate) runtime_services: &'a R,
+    /// The system table, used by the commands needing the configuration tables or the runtime services table.
+    pub(crate) system_table: Option<&'a efi::SystemTable>,
+}
+
+/// What the shell does after a command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Flow {
+    /// Reads the next command line.
+    Continue,
+    /// Leaves the shell, returning to the caller of the protocol.
+    Exit,
+}
+
+type Run<B, R> = fn(&ShellContext<B, R>, &[&str], &mut dyn Write) -> fmt::Result;
+
+struct Command<B, R> {
+    name: &'static str,
+    usage: &'static str,
+    help: &'static str,
+    run: Run<B, R>,
+}
+
+fn commands<B: BootServices, R: RuntimeServices>() -> [Command<B, R>; 7] {
+    [
+        Command { name: "memmap", usage: "memmap", help: "Displays the memory map.", run: memmap },
+        Command { name: "dh", usage: "dh", help: "Displays the handles and their protocols.", run: dh },
+        Command { name: "drivers", usage: "drivers", help: "Displays the UEFI drivers.", run: drivers },
+        Command { name: "devices", usage: "devices", help: "Displays the device paths of the handles.", run: devices },
+        Command {
+            name: "dmpstore",
+            usage: "dmpstore [name]",
+            help: "Displays the variables, or the content of the named variables.",
+            run: dmpstore,
+        },
+        Command {
+            name: "perf",
+            usage: "perf [records]",
+            help: "Summarizes the performance records, or displays each of them.",
+            run: perf,
+        },
+        Command { name: "reset", usage: "reset [cold|warm|shutdown]", help: "Resets the system.", run: reset },
+    ]
+}
+
+/// Executes a command line.
+pub(crate) fn execute<B: BootServices, R: RuntimeServices>(
+    context: &ShellContext<B, R>,
+    line: &str,
+    out: &mut dyn Write,
+) -> Result<Flow, fmt::Error> {
+    let args = line.split_whitespace().collect::<Vec<_>>();
+    let Some((&name, args)) = args.split_first() else {
+        return Ok(Flow::Continue);
+    };
+
+    match name {
+        "exit" => return Ok(Flow::Exit),
+        "help" => {
+            for command in commands::<B, R>() {
+                writeln!(out, "{:<28}{}", command.usage, command.help)?;
+            }
+            writeln!(out, "{:<28}{}", "help", "Displays this help.")?;
+            writeln!(out, "{:<28}{}", "exit", "Leaves the shell.")?;
+        }
+        _ => match commands::<B, R>().into_iter().find(|command| command.name == name) {
+            Some(command) => (command.run)(context, args, out)?,
+            None => writeln!(out, "'{name}' is not a command, type 'help' for the list of commands.")?,
+        },
+    }
+    Ok(Flow::Continue)
+}
+
+fn memmap<B: BootServices, R>(context: &ShellContext<B, R>, _args: &[&str], out: &mut dyn Write) -> fmt::Result {
+    let memory_map = match context.boot_services.get_memory_map() {
+        Ok(memory_map) => memory_map,
+        Err((status, _)) => return writeln!(out, "memmap: failed to get the memory map: {status:?}."),
+    };
+
+    writeln!(out, "{:<24}{:<20}{:<20}{:<12}{}", "Type", "Start", "End", "Pages", "Attributes")?;
+    let mut pages_per_type = Vec::<(efi::MemoryType, u64)>::new();
+    for descriptor in memory_map.descriptors.iter() {
+        let end = descriptor.physical_start + descriptor.number_of_pages * UEFI_PAGE_SIZE as u64 - 1;
+        write!(out, "{:<24}", MemoryTypeName(descriptor.r#type))?;
+        writeln!(
+            out,
+            "{:<#20x}{:<#20x}{:<#12x}{:#x}",
+            descriptor.physical_start, end, descriptor.number_of_pages, descriptor.attribute
+        )?;
+        match pages_per_type.iter_mut().find(|(memory_type, _)| *memory_type == descriptor.r#type) {
+            Some((_, pages)) => *pages += descriptor.number_of_pages,
+            None => pages_per_type.push((descriptor.r#type, descriptor.number_of_pages)),
+        }
+    }
+
+    writeln!(out)?;
+    for (memory_type, pages) in pages_per_type {
+        write!(out, "{:<24}", MemoryTypeName(memory_type))?;
+        writeln!(out, "{pages:>10} pages ({} KiB)", pages * UEFI_PAGE_SIZE as u64 / 1024)?;
+    }
+    Ok(())
+}
+
+fn dh<B: BootServices, R>(context: &ShellContext<B, R>, _args: &[&str], out: &mut dyn Write) -> fmt::Result {
+    let handles = match context.boot_services.locate_handle_buffer(HandleSearchType::AllHandle) {
+        Ok(handles) => handles,
+        Err(status) => return writeln!(out, "dh: failed to locate the handles: {status:?}."),
+    };
+
+    for &handle in handles.iter() {
+        write!(out, "{handle:p}:")?;
+        match context.boot_services.protocols_per_handle(handle) {
+            Ok(protocols) => {
+                for &protocol in protocols.iter() {
+                    match KNOWN_PROTOCOLS.iter().find(|(guid, _)| guid == protocol) {
+                        Some((_, name)) => write!(out, " {name}")?,
+                        None => write!(out, " {}", Guid::from_ref(protocol))?,
+                    }
+                }
+                writeln!(out)?;
+            }
+            Err(status) => writeln!(out, " failed to get the protocols: {status:?}.")?,
+        }
+    }
+    writeln!(out, "{} handles.", handles.len())
+}
+
+fn drivers<B: BootServices, R>(context: &ShellContext<B, R>, _args: &[&str], out: &mut dyn Write) -> fmt::Result {
+    let search_type = HandleSearchType::ByProtocol(&protocols::driver_binding::PROTOCOL_GUID);
+    let handles = match context.boot_services.locate_handle_buffer(search_type) {
+        Ok(handles) => handles,
+        Err(efi::Status::NOT_FOUND) => return writeln!(out, "No drivers."),
+        Err(status) => return writeln!(out, "drivers: failed to locate the drivers: {status:?}."),
+    };
+
+    writeln!(out, "{:<20}{:<12}{}", "Handle", "Version", "File")?;
+    for &handle in handles.iter() {
+        // SAFETY: The handle was located by the driver binding protocol, so its interface is a driver binding protocol.
+        let Ok(driver_binding) =
+            (unsafe { context.boot_services.handle_protocol::<protocols::driver_binding::Protocol>(handle) })
+        else {
+            continue;
+        };
+        // SAFETY: The loaded image interface is a loaded image protocol.
+        let file_name = unsafe { context.boot_services.handle_protocol::<LoadedImage>(driver_binding.image_handle) }
+            .ok()
+            .and_then(|loaded_image| loaded_image.firmware_file_name());
+
+        write!(out, "{:<20}{:<#12x}", alloc::format!("{handle:p}"), driver_binding.version)?;
+        match file_name {
+            Some(file_name) => writeln!(out, "{}", Guid::from_ref(&file_name))?,
+            None => writeln!(out, "-")?,
+        }
+    }
+    Ok(())
+}
+
+fn devices<B: BootServices, R>(context: &ShellContext<B, R>, _args: &[&str], out: &mut dyn Write) -> fmt::Result {
+    let search_type = HandleSearchType::ByProtocol(&protocols::device_path::PROTOCOL_GUID);
+    let handles = match context.boot_services.locate_handle_buffer(search_type) {
+        Ok(handles) => handles,
+        Err(efi::Status::NOT_FOUND) => return writeln!(out, "No devices."),
+        Err(status) => return writeln!(out, "devices: failed to locate the devices: {status:?}."),
+    };
+
+    for &handle in handles.iter() {
+        // SAFETY: The handle was located by the device path protocol, so its interface is a device path.
+        let Ok(device_path) =
+            (unsafe { context.boot_services.handle_protocol::<protocols::device_path::Protocol>(handle) })
+        else {
+            continue;
+        };
+        // SAFETY: The device path protocol points to a device path terminated by an end node.
+        match unsafe { DevicePath::try_from_ptr(device_path as *const _ as *const u8) } {
+            Ok(device_path) => writeln!(out, "{handle:p}: {device_path}")?,
+            Err(err) => writeln!(out, "{handle:p}: invalid device path: {err}.")?,
+        }
+    }
+    Ok(())
+}
+
+fn dmpstore<B, R: RuntimeServices>(context: &ShellContext<B, R>, args: &[&str], out: &mut dyn Write) -> fmt::Result {
+    let filter = args.first().copied();
+    let mut variables = VariableNameIterator::new_from_first(context.runtime_services);
+    let mut count = 0;
+    loop {
+        let variable = match variables.next() {
+            Ok(Some(variable)) => variable,
+            Ok(None) => break,
+            Err(status) => return writeln!(out, "dmpstore: failed to get the next variable: {status:?}."),
+        };
+        let Ok(name) = variable.name() else {
+            continue;
+        };
+        if filter.is_some_and(|filter| name != filter) {
+            continue;
+        }
+        count += 1;
+
+        let namespace = variable.namespace();
+        write!(out, "{}:{name}", Guid::from_ref(namespace))?;
+        if filter.is_none() {
+            match context.runtime_services.get_variable_size_and_attributes(name.as_slice_with_nul(), namespace) {
+                Ok((size, attributes)) => writeln!(out, " attributes {attributes:#x}, {size} bytes")?,
+                Err(status) => writeln!(out, " {status:?}")?,
+            }
+            continue;
+        }
+        match context.runtime_services.get_variable::<Vec<u8>>(name.as_slice_with_nul(), namespace, None) {
+            Ok((data, attributes)) => {
+                writeln!(out, " attributes {attributes:#x}, {} bytes", data.len())?;
+                hex_dump(&data, out)?;
+            }
+            Err(status) => writeln!(out, " {status:?}")?,
+        }
+    }
+    writeln!(out, "{count} variables.")
+}
+
+fn perf<B, R>(context: &ShellContext<B, R>, args: &[&str], out: &mut dyn Write) -> fmt::Result {
+    // SAFETY: The configuration table of the system table has the given number of entries.
+    let Some(fbpt) = context.system_table.and_then(|system_table| unsafe { find_fbpt(system_table) }) else {
+        return writeln!(out, "perf: the FBPT is not published.");
+    };
+
+    if args.first() == Some(&"records") {
+        writeln!(out, "{:<20}{:<10}{:<40}{}", "Timestamp (ns)", "Progress", "GUID", "Type")?;
+        for (record_type, data) in fbpt_records(fbpt) {
+            let Some((progress_id, timestamp, guid)) = parse_guid_record(record_type, data) else {
+                continue;
+            };
+            write!(out, "{timestamp:<20}{progress_id:<#10x}")?;
+            writeln!(out, "{:<40}{}", alloc::format!("{}", Guid::from_ref(&guid)), RecordTypeName(record_type))?;
+        }
+        return Ok(());
+    }
+
+    let mut records_per_type = Vec::<(u16, usize)>::new();
+    for (record_type, _) in fbpt_records(fbpt) {
+        match records_per_type.iter_mut().find(|(t, _)| *t == record_type) {
+            Some((_, count)) => *count += 1,
+            None => records_per_type.push((record_type, 1)),
+        }
+    }
+    writeln!(out, "FBPT at {:p}, {} bytes.", fbpt.as_ptr(), fbpt.len())?;
+    for (record_type, count) in records_per_type {
+        writeln!(out, "{:<24}{count:>8} records", RecordTypeName(record_type))?;
+    }
+    Ok(())
+}
+
+fn reset<B, R>(context: &ShellContext<B, R>, args: &[&str], out: &mut dyn Write) -> fmt::Result {
+    let reset_type = match args.first().copied() {
+        None | Some("cold") => efi::RESET_COLD,
+        Some("warm") => efi::RESET_WARM,
+        Some("shutdown") => efi::RESET_SHUTDOWN,
+        Some(other) => return writeln!(out, "reset: unknown reset type '{other}'."),
+    };
+    // SAFETY: The runtime services table of the system table is valid during boot.
+    let Some(runtime_services) =
+        context.system_table.and_then(|system_table| unsafe { system_table.runtime_services.as_ref() })
+    else {
+        return writeln!(out, "reset: the runtime services are not available.");
+    };
+
+    (runtime_services.reset_system)(reset_type, efi::Status::SUCCESS, 0, ptr::null_mut());
+    writeln!(out, "reset: the system did not reset.")
+}
+
+/// Returns the FBPT published in the configuration table, if any.
+///
+/// # Safety
+///
+/// The configuration table of the system table must have `number_of_table_entries` entries.
+unsafe fn find_fbpt(system_table: &efi::SystemTable) -> Option<&[u8]> {
+    if system_table.configuration_table.is_null() {
+        return None;
+    }
+    // SAFETY: The configuration table has `number_of_table_entries` entries, as guaranteed by the caller.
+    let configuration_table =
+        unsafe { slice::from_raw_parts(system_table.configuration_table, system_table.number_of_table_entries) };
+    let fbpt = configuration_table
+        .iter()
+        .find(|entry| entry.vendor_guid == EDKII_FPDT_EXTENDED_FIRMWARE_PERFORMANCE)?
+        .vendor_table as *const u8;
+    if fbpt.is_null() {
+        return None;
+    }
+
+    // SAFETY: The FBPT configuration table points to the FBPT, which starts with its signature and its length.
+    let (signature, length) =
+        unsafe { (ptr::read_unaligned(fbpt as *const u32), ptr::read_unaligned(fbpt.add(4) as *const u32) as usize) };
+    if signature != u32::from_le_bytes(*b"FBPT") || length < FBPT_HEADER_SIZE {
+        return None;
+    }
+    // SAFETY: The length of the FBPT covers the header and the records.
+    Some(unsafe { slice::from_raw_parts(fbpt, length) })
+}
+
+/// Iterates over the type and data of the records of an FBPT, stopping at the first malformed record.
+fn fbpt_records(fbpt: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    let mut records = fbpt.get(FBPT_HEADER_SIZE..).unwrap_or_default();
+    iter::from_fn(move || {
+        let record_type = u16::from_le_bytes([*records.first()?, *records.get(1)?]);
+        let length = *records.get(2)? as usize;
+        if length < RECORD_HEADER_SIZE {
+            return None;
+        }
+        let record = records.get(..length)?;
+        records = &records[length..];
+        Some((record_type, &record[RECORD_HEADER_SIZE..]))
+    })
+}
+
+/// Returns the progress id, the timestamp and the GUID of an extended record.
+///
+/// All the extended records with a GUID start with these fields, the other records return `None`.
+fn parse_guid_record(record_type: u16, data: &[u8]) -> Option<(u16, u64, efi::Guid)> {
+    if !matches!(
+        record_type,
+        GuidEventRecord::TYPE
+            | DynamicStringEventRecord::TYPE
+            | DualGuidStringEventRecord::TYPE
+            | GuidQwordEventRecord::TYPE
+            | GuidQwordStringEventRecord::TYPE
+    ) {
+        return None;
+    }
+    let progress_id = u16::from_le_bytes(data.get(0..2)?.try_into().ok()?);
+    let timestamp = u64::from_le_bytes(data.get(6..14)?.try_into().ok()?);
+    let guid = efi::Guid::from_bytes(data.get(14..30)?.try_into().ok()?);
+    Some((progress_id, timestamp, guid))
+}
+
+fn hex_dump(data: &[u8], out: &mut dyn Write) -> fmt::Result {
+    for (line, chunk) in data.chunks(16).enumerate() {
+        write!(out, "  {:08x}:", line * 16)?;
+        for byte in chunk {
+            write!(out, " {byte:02x}")?;
+        }
+        write!(out, "{:width$}  ", "", width = (16 - chunk.len()) * 3)?;
+        for &byte in chunk {
+            out.write_char(if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' })?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+struct MemoryTypeName(efi::MemoryType);
+
+impl fmt::Display for MemoryTypeName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match EfiMemoryType::from_efi(self.0) {
+            Ok(EfiMemoryType::OemMemoryType(_)) => f.pad(&alloc::format!("Oem({:#x})", self.0)),
+            Ok(EfiMemoryType::OsMemoryType(_)) => f.pad(&alloc::format!("Os({:#x})", self.0)),
+            Ok(memory_type) => f.pad(&alloc::format!("{memory_type:?}")),
+            Err(_) => f.pad(&alloc::format!("Invalid({:#x})", self.0)),
+        }
+    }
+}
+
+struct RecordTypeName(u16);
+
+impl fmt::Display for RecordTypeName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self.0 {
+            BASIC_BOOT_RECORD_TYPE => "BasicBoot",
+            GuidEventRecord::TYPE => "GuidEvent",
+            DynamicStringEventRecord::TYPE => "DynamicString",
+            DualGuidStringEventRecord::TYPE => "DualGuidString",
+            GuidQwordEventRecord::TYPE => "GuidQword",
+            GuidQwordStringEventRecord::TYPE => "GuidQwordString",
+            PerfIdRangeRecord::TYPE => "PerfIdRange",
+            _ => "Unknown",
+        })
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use patina::{boot_services::MockBootServices, runtime_services::MockRuntimeServices};
+    use std::string::String;
+
+    fn run(line: &str) -> (Flow, String) {
+        let boot_services = MockBootServices::new();
+        let runtime_services = MockRuntimeServices::new();
+        let context =
+            ShellContext { boot_services: &boot_services, runtime_services: &runtime_services, system_table: None };
+        let mut out = String::new();
+        let flow = execute(&context, line, &mut out).unwrap();
+        (flow, out)
+    }
+
+    #[test]
+    fn test_execute_help_lists_the_commands() {
+        let (flow, out) = run("help");
+        assert_eq!(flow, Flow::Continue);
+        for command in ["memmap", "dh", "drivers", "devices", "dmpstore [name]", "perf [records]", "reset", "exit"] {
+            assert!(out.lines().any(|line| line.starts_with(command)), "{command} missing from:\n{out}");
+        }
+    }
+
+    #[test]
+    fn test_execute_handles_empty_unknown_and_exit() {
+        assert_eq!(run("   "), (Flow::Continue, String::new()));
+        assert_eq!(run("exit"), (Flow::Exit, String::new()));
+        assert_eq!(
+            run("ls -l"),
+            (Flow::Continue, String::from("'ls' is not a command, type 'help' for the list of commands.\n"))
+        );
+    }
+
+    #[test]
+    fn test_perf_and_reset_need_the_system_table() {
+        assert_eq!(run("perf").1, "perf: the FBPT is not published.\n");
+        assert_eq!(run("reset warm").1, "reset: the runtime services are not available.\n");
+        assert_eq!(run("reset hard").1, "reset: unknown reset type 'hard'.\n");
+    }
+
+    #[test]
+    fn test_fbpt_records_parses_the_guid_records() {
+        let guid = patina::guid!("11111111-2222-3333-4444-555555555555");
+        let mut fbpt = Vec::new();
+        fbpt.extend_from_slice(b"FBPT");
+        fbpt.extend_from_slice(&0u32.to_le_bytes());
+        // Basic boot record, with its data zeroed.
+        fbpt.extend_from_slice(&[0x02, 0x00, 0x08, 0x02, 0x00, 0x00, 0x00, 0x00]);
+        // GUID event record.
+        fbpt.extend_from_slice(&GuidEventRecord::TYPE.to_le_bytes());
+        fbpt.extend_from_slice(&[34, 1]);
+        fbpt.extend_from_slice(&0x11u16.to_le_bytes());
+        fbpt.extend_from_slice(&0u32.to_le_bytes());
+        fbpt.extend_from_slice(&1234u64.to_le_bytes());
+        fbpt.extend_from_slice(guid.as_bytes());
+        // Truncated record, ending the iteration.
+        fbpt.extend_from_slice(&[0x10, 0x10, 0x40, 0x01]);
+
+        let records = fbpt_records(&fbpt).collect::<Vec<_>>();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].0, BASIC_BOOT_RECORD_TYPE);
+        assert_eq!(parse_guid_record(records[0].0, records[0].1), None);
+        assert_eq!(parse_guid_record(records[1].0, records[1].1), Some((0x11, 1234, guid)));
+    }
+
+    #[test]
+    fn test_hex_dump_shows_the_bytes_and_characters() {
+        let mut out = String::new();
+        hex_dump(b"Patina shell\0\x01\x02\x03\x04", &mut out).unwrap();
+        assert_eq!(
+            out,
+            "  00000000: 50 61 74 69 6e 61 20 73 68 65 6c 6c 00 01 02 03  Patina shell....\n  00000010: 04                                               .\n"
+        );
+    }
+}
@@ -0,0 +1,197 @@
+//! Patina Shell Component
+//!
+//! Installs the [Patina Shell Protocol](crate::protocol) when the [ShellConfig] enables the shell. The shell itself
+//! runs when BDS calls the protocol, as it waits for key presses, which is only allowed at `TPL_APPLICATION`.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use alloc::boxed::Box;
+use core::{convert::AsRef, ffi::c_void};
+use patina::{
+    boot_services::{BootServices, StandardBootServices},
+    component::{IntoComponent, params::Config},
+    error::EfiError,
+    runtime_services::{RuntimeServices, StandardRuntimeServices},
+    uefi_protocol::loaded_image::LoadedImage,
+};
+use r_efi::efi;
+
+use crate::{
+    commands::{self, Flow, ShellContext},
+    config::ShellConfig,
+    console::{self, Console, SystemConsole},
+    protocol::{PROTOCOL_GUID, Protocol},
+};
+
+const PROMPT: &str = "Shell> ";
+
+/// Patina Shell Component.
+#[derive(IntoComponent)]
+pub struct Shell;
+
+/// Interface of the Patina Shell Protocol, with the services used by the shell.
+#[repr(C)]
+struct ShellInstance<BB, RR> {
+    protocol: Protocol,
+    boot_services: BB,
+    runtime_services: RR,
+}
+
+impl Shell {
+    /// Entry point of [`Shell`]
+    #[coverage(off)] // This is tested via the generic version, see _entry_point.
+    pub fn entry_point(
+        self,
+        config: Config<ShellConfig>,
+        boot_services: StandardBootServices,
+        runtime_services: StandardRuntimeServices,
+    ) -> Result<(), EfiError> {
+        self._entry_point(boot_services, runtime_services, *config)
+    }
+
+    /// Entry point that have generic parameter.
+    fn _entry_point<BB, B, RR, R>(
+        self,
+        boot_services: BB,
+        runtime_services: RR,
+        config: ShellConfig,
+    ) -> Result<(), EfiError>
+    where
+        BB: AsRef<B> + 'static,
+        B: BootServices + 'static,
+        RR: AsRef<R> + 'static,
+        R: RuntimeServices + 'static,
+    {
+        if !config.enabled {
+            log::info!("Shell: disabled.");
+            return Ok(());
+        }
+
+        let instance = Box::leak(Box::new(ShellInstance {
+            protocol: Protocol { run: run::<BB, B, RR, R> },
+            boot_services,
+            runtime_services,
+        }));
+        let interface = instance as *mut ShellInstance<BB, RR> as *mut c_void;
+        // SAFETY: The interface starts with the Patina Shell Protocol and is leaked.
+        unsafe {
+            instance.boot_services.as_ref().install_protocol_interface_unchecked(None, &PROTOCOL_GUID, interface)
+        }?;
+        Ok(())
+    }
+}
+
+/// Implementation of [Protocol::run].
+extern "efiapi" fn run<BB, B, RR, R>(this: *mut Protocol) -> efi::Status
+where
+    BB: AsRef<B>,
+    B: BootServices,
+    RR: AsRef<R>,
+    R: RuntimeServices,
+{
+    // SAFETY: The protocol is the first field of the ShellInstance installed by the component.
+    let Some(instance) = (unsafe { (this as *const ShellInstance<BB, RR>).as_ref() }) else {
+        return efi::Status::INVALID_PARAMETER;
+    };
+    let boot_services = instance.boot_services.as_ref();
+
+    // SAFETY: The Loaded Image Protocol of the DXE core references the system table, which lives until the end of
+    // boot services.
+    let Some(system_table) = unsafe { boot_services.locate_protocol::<LoadedImage>(None) }
+        .ok()
+        .and_then(|loaded_image| unsafe { loaded_image.system_table().as_ref() })
+    else {
+        return efi::Status::NOT_FOUND;
+    };
+    // SAFETY: The consoles of the system table are not replaced while the shell waits for a key press.
+    let Some(mut console) = (unsafe { SystemConsole::new(boot_services, system_table) }) else {
+        return efi::Status::NOT_FOUND;
+    };
+
+    let context = ShellContext {
+        boot_services,
+        runtime_services: instance.runtime_services.as_ref(),
+        system_table: Some(system_table),
+    };
+    match run_shell(&context, &mut console) {
+        Ok(()) => efi::Status::SUCCESS,
+        Err(status) => status,
+    }
+}
+
+/// Reads and executes command lines until the `exit` command.
+fn run_shell<B, R, C>(context: &ShellContext<B, R>, console: &mut C) -> Result<(), efi::Status>
+where
+    B: BootServices,
+    R: RuntimeServices,
+    C: Console,
+{
+    writeln!(console, "Patina Shell, type 'help' for the list of commands.").map_err(|_| efi::Status::DEVICE_ERROR)?;
+    loop {
+        console.write_str(PROMPT).map_err(|_| efi::Status::DEVICE_ERROR)?;
+        let line = console::read_line(console)?;
+        if commands::execute(context, &line, console).map_err(|_| efi::Status::DEVICE_ERROR)? == Flow::Exit {
+            log::info!("Shell: exited.");
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::console::tests::ScriptedConsole;
+    use patina::{boot_services::MockBootServices, runtime_services::MockRuntimeServices};
+    use std::rc::Rc;
+
+    #[test]
+    fn test_entry_point_disabled_installs_nothing() {
+        let boot_services = MockBootServices::new();
+        let runtime_services = MockRuntimeServices::new();
+        assert_eq!(
+            Shell._entry_point(Rc::new(boot_services), Rc::new(runtime_services), ShellConfig::default()),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_entry_point_installs_the_protocol() {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_install_protocol_interface_unchecked().once().returning(|handle, protocol, interface| {
+            assert_eq!((handle, protocol), (None, &PROTOCOL_GUID));
+            assert!(!interface.is_null());
+            Ok(1_usize as efi::Handle)
+        });
+
+        let config = ShellConfig { enabled: true };
+        assert_eq!(Shell._entry_point(Rc::new(boot_services), Rc::new(MockRuntimeServices::new()), config), Ok(()));
+    }
+
+    #[test]
+    fn test_run_shell_until_exit() {
+        let boot_services = MockBootServices::new();
+        let runtime_services = MockRuntimeServices::new();
+        let context =
+            ShellContext { boot_services: &boot_services, runtime_services: &runtime_services, system_table: None };
+
+        let mut console = ScriptedConsole::new("\rfoo\rexit\rhelp\r");
+        assert_eq!(run_shell(&context, &mut console), Ok(()));
+        assert!(console.output.ends_with(
+            "Shell> \nShell> foo\n'foo' is not a command, type 'help' for the list of commands.\nShell> exit\n"
+        ));
+        assert_eq!(console.keys.len(), "help\r".len());
+
+        // The shell ends when the console fails.
+        let mut console = ScriptedConsole::new("help\r");
+        assert_eq!(run_shell(&context, &mut console), Err(efi::Status::DEVICE_ERROR));
+        assert!(console.output.contains("Displays the memory map."));
+    }
+}
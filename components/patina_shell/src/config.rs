@@ -0,0 +1,18 @@
+//! Patina Shell Component Configuration
+//!
+//! The configuration can be set statically with `.with_config()` or produced dynamically during boot, e.g. by a
+//! component enabling the shell in debug builds only. If no configuration is provided, the shell is not available.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+/// The configuration for the Patina Shell component.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ShellConfig {
+    /// Installs the Patina Shell Protocol, so that BDS can start the shell.
+    pub enabled: bool,
+}
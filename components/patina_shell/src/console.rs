@@ -0,0 +1,141 @@
+//! Console of the Patina Shell
+//!
+//! Reads the command lines from the console input of the system table, echoing them on the console output.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use alloc::string::String;
+use core::fmt::{self, Write};
+use patina::{boot_services::BootServices, uefi_protocol::simple_text_output::SimpleTextOutput};
+use r_efi::efi::{
+    self,
+    protocols::simple_text_input::{self, InputKey},
+};
+
+const BACKSPACE: char = '\u{8}';
+
+/// A console the shell reads keys from and writes text to.
+pub(crate) trait Console: Write {
+    /// Waits for a key press and returns it.
+    fn read_key(&mut self) -> Result<InputKey, efi::Status>;
+}
+
+/// The console input and output of the system table.
+pub(crate) struct SystemConsole<'a, B> {
+    boot_services: &'a B,
+    con_in: *mut simple_text_input::Protocol,
+    con_out: &'a mut SimpleTextOutput,
+}
+
+impl<'a, B: BootServices> SystemConsole<'a, B> {
+    /// Returns the console of the system table, or `None` if it has no console input or output.
+    ///
+    /// # Safety
+    ///
+    /// The console protocols of the system table must remain valid for the lifetime `'a`.
+    pub(crate) unsafe fn new(boot_services: &'a B, system_table: &efi::SystemTable) -> Option<Self> {
+        if system_table.con_in.is_null() {
+            return None;
+        }
+        // SAFETY: The console output is valid for the lifetime `'a`, as guaranteed by the caller.
+        let con_out = unsafe { SimpleTextOutput::from_ptr(system_table.con_out) }?;
+        Some(Self { boot_services, con_in: system_table.con_in, con_out })
+    }
+}
+
+impl<B: BootServices> Console for SystemConsole<'_, B> {
+    fn read_key(&mut self) -> Result<InputKey, efi::Status> {
+        let mut key = InputKey { scan_code: 0, unicode_char: 0 };
+        loop {
+            // SAFETY: The console input is valid, as guaranteed when the console was created.
+            let con_in = unsafe { &*self.con_in };
+            match (con_in.read_key_stroke)(self.con_in, &mut key) {
+                efi::Status::SUCCESS => return Ok(key),
+                efi::Status::NOT_READY => {
+                    self.boot_services.wait_for_event(&mut [con_in.wait_for_key])?;
+                }
+                status => return Err(status),
+            }
+        }
+    }
+}
+
+impl<B> Write for SystemConsole<'_, B> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.con_out.write_str(s)
+    }
+}
+
+/// Reads a line from the console, echoing it, until enter is pressed.
+///
+/// Backspace erases the last character. Keys without a printable character, e.g. the arrow keys, are ignored.
+pub(crate) fn read_line<C: Console>(console: &mut C) -> Result<String, efi::Status> {
+    let mut line = String::new();
+    loop {
+        let key = console.read_key()?;
+        let echo = match char::from_u32(u32::from(key.unicode_char)) {
+            Some('\r' | '\n') => {
+                console.write_char('\n').map_err(|_| efi::Status::DEVICE_ERROR)?;
+                return Ok(line);
+            }
+            Some(BACKSPACE) if line.pop().is_some() => "\u{8} \u{8}",
+            Some(c) if !c.is_control() => {
+                line.push(c);
+                &line[line.len() - c.len_utf8()..]
+            }
+            _ => continue,
+        };
+        console.write_str(echo).map_err(|_| efi::Status::DEVICE_ERROR)?;
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+pub(crate) mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::{collections::VecDeque, string::String};
+
+    /// A console reading keys from a script and recording its output.
+    pub(crate) struct ScriptedConsole {
+        pub(crate) keys: VecDeque<InputKey>,
+        pub(crate) output: String,
+    }
+
+    impl ScriptedConsole {
+        pub(crate) fn new(input: &str) -> Self {
+            let keys = input.encode_utf16().map(|unicode_char| InputKey { scan_code: 0, unicode_char }).collect();
+            Self { keys, output: String::new() }
+        }
+    }
+
+    impl Console for ScriptedConsole {
+        fn read_key(&mut self) -> Result<InputKey, efi::Status> {
+            self.keys.pop_front().ok_or(efi::Status::DEVICE_ERROR)
+        }
+    }
+
+    impl Write for ScriptedConsole {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            self.output.push_str(s);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_read_line_echoes_and_edits_the_line() {
+        let mut console = ScriptedConsole::new("dh\u{8}\u{8}\u{8}memx\u{8}map\r");
+        // An arrow key, without a character, is ignored.
+        console.keys.push_front(InputKey { scan_code: 0x01, unicode_char: 0 });
+
+        assert_eq!(read_line(&mut console).unwrap(), "memmap");
+        assert_eq!(console.output, "dh\u{8} \u{8}\u{8} \u{8}memx\u{8} \u{8}map\n");
+        assert_eq!(read_line(&mut console), Err(efi::Status::DEVICE_ERROR));
+    }
+}
@@ -0,0 +1,44 @@
+//! Minimal interactive shell for Patina platforms.
+//!
+//! Bringing up a board often requires looking at the state of the firmware before any boot option works, e.g. the
+//! memory map, the handles and their protocols, or the variables. The shell provides the most useful commands for this
+//! without porting the full EDK II shell. This crate provides:
+//!
+//! - [component::Shell]: a component installing the [protocol::Protocol] when the [config::ShellConfig] enables it, so
+//!   that BDS can start the shell, e.g. on a hotkey or when no boot option succeeds.
+//! - [protocol]: the Patina Shell Protocol, running the shell on the console of the system table.
+//!
+//! The commands are `memmap`, `dh`, `drivers`, `devices`, `dmpstore`, `perf` and `reset`, along with `help` and `exit`.
+//!
+//! ## Integration Example
+//!
+//! ```rust,ignore
+//! // ...
+//!
+//! Core::default()
+//!  // ...
+//!  .with_config(patina_shell::config::ShellConfig { enabled: true })
+//!  .with_component(patina_shell::component::Shell)
+//!  .start()
+//!  .unwrap();
+//!
+//! // ...
+//! ```
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+#![cfg_attr(not(test), no_std)]
+#![feature(coverage_attribute)]
+
+extern crate alloc;
+
+mod commands;
+pub mod component;
+pub mod config;
+mod console;
+pub mod protocol;
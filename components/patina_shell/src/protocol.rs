@@ -0,0 +1,34 @@
+//! Patina Shell Protocol
+//!
+//! Installed by the [Shell](crate::component::Shell) component when the shell is enabled. BDS locates the protocol and
+//! calls [Protocol::run] to start the shell, e.g. when a hotkey is pressed or when no boot option succeeds.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use patina::uefi_protocol::ProtocolInterface;
+use r_efi::efi;
+
+/// Patina Shell Protocol GUID.
+pub const PROTOCOL_GUID: efi::Guid = patina::guid!("B4E6A1D2-7C3F-4F58-9A21-3E8D5C6B7F90");
+
+/// Runs the shell on the console input and output of the system table, until the `exit` command is entered.
+///
+/// Must be called at `TPL_APPLICATION`, as the shell waits for key presses. Returns `NOT_FOUND` if the system table
+/// has no console input or output.
+pub type Run = extern "efiapi" fn(this: *mut Protocol) -> efi::Status;
+
+/// Patina Shell Protocol interface.
+#[repr(C)]
+pub struct Protocol {
+    /// Runs the shell.
+    pub run: Run,
+}
+
+unsafe impl ProtocolInterface for Protocol {
+    const PROTOCOL_GUID: efi::Guid = PROTOCOL_GUID;
+}
@@ -0,0 +1,151 @@
+//! Integration tests running the shell on scripted consoles against the host DXE core boot services.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use core::ptr;
+
+use patina::boot_services::BootServices;
+use patina_shell::{component::Shell, config::ShellConfig, protocol};
+use patina_test::TestHarness;
+use r_efi::efi::{
+    self,
+    protocols::{simple_text_input, simple_text_output},
+};
+
+/// A text output device recording the text written to it.
+#[repr(C)]
+struct TestTextOut {
+    protocol: simple_text_output::Protocol,
+    output: String,
+}
+
+extern "efiapi" fn reset(_: *mut simple_text_output::Protocol, _: efi::Boolean) -> efi::Status {
+    efi::Status::SUCCESS
+}
+
+extern "efiapi" fn output_string(this: *mut simple_text_output::Protocol, string: *mut efi::Char16) -> efi::Status {
+    // SAFETY: The protocol is the first field of a TestTextOut.
+    let device = unsafe { &mut *(this as *mut TestTextOut) };
+    let mut index = 0;
+    // SAFETY: The string is NUL terminated.
+    while unsafe { *string.add(index) } != 0 {
+        device.output.push(char::from_u32(unsafe { *string.add(index) } as u32).unwrap());
+        index += 1;
+    }
+    efi::Status::SUCCESS
+}
+
+extern "efiapi" fn test_string(_: *mut simple_text_output::Protocol, _: *mut efi::Char16) -> efi::Status {
+    efi::Status::SUCCESS
+}
+
+extern "efiapi" fn query_mode(
+    _: *mut simple_text_output::Protocol,
+    _: usize,
+    _: *mut usize,
+    _: *mut usize,
+) -> efi::Status {
+    efi::Status::UNSUPPORTED
+}
+
+extern "efiapi" fn set_mode(_: *mut simple_text_output::Protocol, _: usize) -> efi::Status {
+    efi::Status::UNSUPPORTED
+}
+
+extern "efiapi" fn set_attribute(_: *mut simple_text_output::Protocol, _: usize) -> efi::Status {
+    efi::Status::SUCCESS
+}
+
+extern "efiapi" fn clear_screen(_: *mut simple_text_output::Protocol) -> efi::Status {
+    efi::Status::SUCCESS
+}
+
+extern "efiapi" fn set_cursor_position(_: *mut simple_text_output::Protocol, _: usize, _: usize) -> efi::Status {
+    efi::Status::SUCCESS
+}
+
+extern "efiapi" fn enable_cursor(_: *mut simple_text_output::Protocol, _: efi::Boolean) -> efi::Status {
+    efi::Status::SUCCESS
+}
+
+/// A text input device returning the keys it holds, and failing once they are all read.
+#[repr(C)]
+struct TestTextIn {
+    protocol: simple_text_input::Protocol,
+    keys: Vec<u16>,
+}
+
+extern "efiapi" fn text_in_reset(_: *mut simple_text_input::Protocol, _: efi::Boolean) -> efi::Status {
+    efi::Status::SUCCESS
+}
+
+extern "efiapi" fn read_key_stroke(
+    this: *mut simple_text_input::Protocol,
+    key: *mut simple_text_input::InputKey,
+) -> efi::Status {
+    // SAFETY: The protocol is the first field of a TestTextIn.
+    let device = unsafe { &mut *(this as *mut TestTextIn) };
+    if device.keys.is_empty() {
+        return efi::Status::DEVICE_ERROR;
+    }
+    // SAFETY: The key is provided by the caller.
+    unsafe { key.write(simple_text_input::InputKey { scan_code: 0, unicode_char: device.keys.remove(0) }) };
+    efi::Status::SUCCESS
+}
+
+#[test]
+fn test_shell_runs_commands_from_the_console() {
+    let mut harness = TestHarness::new().with_config(ShellConfig { enabled: true }).with_component(Shell);
+    harness.run().unwrap();
+    assert!(harness.pending_components().is_empty());
+
+    let text_in = Box::leak(Box::new(TestTextIn {
+        protocol: simple_text_input::Protocol { reset: text_in_reset, read_key_stroke, wait_for_key: ptr::null_mut() },
+        keys: "memmap\rdh\rperf\rexit\r".encode_utf16().collect(),
+    }));
+    let text_out = Box::leak(Box::new(TestTextOut {
+        protocol: simple_text_output::Protocol {
+            reset,
+            output_string,
+            test_string,
+            query_mode,
+            set_mode,
+            set_attribute,
+            clear_screen,
+            set_cursor_position,
+            enable_cursor,
+            mode: ptr::null_mut(),
+        },
+        output: String::new(),
+    }));
+
+    // BDS starts the shell with the consoles of the system table.
+    let system_table = harness.system_table();
+    // SAFETY: The system table is valid for the lifetime of the host environment, the consoles are restored below.
+    let consoles = unsafe {
+        let consoles = ((*system_table).con_in, (*system_table).con_out);
+        (*system_table).con_in = &mut text_in.protocol;
+        (*system_table).con_out = &mut text_out.protocol;
+        consoles
+    };
+
+    // SAFETY: The Patina Shell Protocol is installed by the component.
+    let shell = unsafe { harness.boot_services().locate_protocol::<protocol::Protocol>(None) }.unwrap();
+    let status = (shell.run)(shell);
+
+    // SAFETY: See above.
+    unsafe { ((*system_table).con_in, (*system_table).con_out) = consoles };
+
+    assert_eq!(status, efi::Status::SUCCESS);
+    assert!(text_in.keys.is_empty());
+    let output = &text_out.output;
+    assert!(output.starts_with("Patina Shell, type 'help' for the list of commands.\r\nShell> memmap\r\n"));
+    assert!(output.contains("ConventionalMemory"), "{output}");
+    assert!(output.contains("PatinaShell"), "{output}");
+    assert!(output.contains("Shell> perf\r\n"), "{output}");
+    assert!(output.ends_with("Shell> exit\r\n"), "{output}");
+}
@@ -0,0 +1,205 @@
+//! Time Source Arbitration
+//!
+//! [TimeArbiter] keeps the registered time sources sorted by order of preference, and falls back to the next source
+//! when one cannot provide a valid time. A source returning a time out of the ranges of the UEFI specification is
+//! skipped, so that callers never see an uninitialized clock.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use alloc::{boxed::Box, vec::Vec};
+use patina::error::{EfiError, Result};
+use r_efi::efi;
+
+use crate::source::{TimeSource, TimeSourceKind, is_valid_time};
+
+/// Chooses the time source answering each request.
+pub struct TimeArbiter {
+    order: Vec<TimeSourceKind>,
+    sources: Vec<Box<dyn TimeSource>>,
+}
+
+impl TimeArbiter {
+    /// Creates an arbiter preferring the kinds of sources in `order`.
+    pub fn new(order: Vec<TimeSourceKind>) -> Self {
+        Self { order, sources: Vec::new() }
+    }
+
+    /// Returns the rank of `kind` in the order of preference, kinds not listed come last.
+    fn rank(&self, kind: TimeSourceKind) -> usize {
+        self.order.iter().position(|preferred| *preferred == kind).unwrap_or(self.order.len())
+    }
+
+    /// Adds `source`, after the sources registered before it with the same rank.
+    pub fn register(&mut self, source: Box<dyn TimeSource>) {
+        let rank = self.rank(source.kind());
+        let index = self.sources.partition_point(|registered| self.rank(registered.kind()) <= rank);
+        log::info!("Registered a {:?} time source with rank {rank}.", source.kind());
+        self.sources.insert(index, source);
+    }
+
+    /// Returns the kinds of the registered sources, from the most preferred.
+    pub fn source_kinds(&self) -> impl Iterator<Item = TimeSourceKind> + '_ {
+        self.sources.iter().map(|source| source.kind())
+    }
+
+    /// Returns the time of the most preferred source providing a valid time, with the capabilities of that source.
+    ///
+    /// # Errors
+    ///
+    /// - [EfiError::Unsupported] if no source can provide the time, e.g. none is registered.
+    /// - [EfiError::DeviceError] if the sources able to provide the time all failed or returned an invalid time.
+    pub fn get_time(&self) -> Result<(efi::Time, efi::TimeCapabilities)> {
+        let mut error = EfiError::Unsupported;
+        for source in &self.sources {
+            match source.get_time() {
+                Ok((time, capabilities)) if is_valid_time(&time) => return Ok((time, capabilities)),
+                Ok((time, _)) => {
+                    log::warn!("The {:?} time source returned an invalid time: {time:?}", source.kind());
+                    error = EfiError::DeviceError;
+                }
+                Err(EfiError::Unsupported) => {}
+                Err(err) => {
+                    log::warn!("The {:?} time source failed to get the time: {err:?}", source.kind());
+                    error = EfiError::DeviceError;
+                }
+            }
+        }
+        Err(error)
+    }
+
+    /// Sets the time of the most preferred source supporting it.
+    ///
+    /// # Errors
+    ///
+    /// - [EfiError::InvalidParameter] if `time` is out of the ranges of the UEFI specification.
+    /// - [EfiError::Unsupported] if no source supports setting the time.
+    pub fn set_time(&self, time: &efi::Time) -> Result<()> {
+        if !is_valid_time(time) {
+            return Err(EfiError::InvalidParameter);
+        }
+        self.sources
+            .iter()
+            .map(|source| source.set_time(time))
+            .find(|result| *result != Err(EfiError::Unsupported))
+            .unwrap_or(Err(EfiError::Unsupported))
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use alloc::vec;
+    use core::cell::Cell;
+    use std::rc::Rc;
+
+    /// A source returning a fixed result, and recording the last time it was set to.
+    struct FakeSource {
+        kind: TimeSourceKind,
+        time: Result<efi::Time>,
+        resolution: u32,
+        set: Option<Rc<Cell<Option<u16>>>>,
+    }
+
+    impl TimeSource for FakeSource {
+        fn kind(&self) -> TimeSourceKind {
+            self.kind
+        }
+
+        fn get_time(&self) -> Result<(efi::Time, efi::TimeCapabilities)> {
+            let capabilities =
+                efi::TimeCapabilities { resolution: self.resolution, accuracy: 0, sets_to_zero: false.into() };
+            self.time.map(|time| (time, capabilities))
+        }
+
+        fn set_time(&self, time: &efi::Time) -> Result<()> {
+            let set = self.set.as_ref().ok_or(EfiError::Unsupported)?;
+            set.set(Some(time.year));
+            Ok(())
+        }
+    }
+
+    fn time(year: u16) -> efi::Time {
+        efi::Time { year, month: 1, day: 1, timezone: efi::UNSPECIFIED_TIMEZONE, ..Default::default() }
+    }
+
+    fn source(kind: TimeSourceKind, time: Result<efi::Time>, resolution: u32) -> Box<dyn TimeSource> {
+        Box::new(FakeSource { kind, time, resolution, set: None })
+    }
+
+    fn arbiter() -> TimeArbiter {
+        TimeArbiter::new(vec![TimeSourceKind::Rtc, TimeSourceKind::AcpiTad])
+    }
+
+    #[test]
+    fn sources_should_be_sorted_by_preference() {
+        let mut arbiter = arbiter();
+        arbiter.register(source(TimeSourceKind::Virtual, Ok(time(2003)), 3));
+        arbiter.register(source(TimeSourceKind::AcpiTad, Ok(time(2002)), 2));
+        arbiter.register(source(TimeSourceKind::Rtc, Ok(time(2001)), 1));
+        arbiter.register(source(TimeSourceKind::Rtc, Ok(time(2004)), 4));
+        assert_eq!(
+            arbiter.source_kinds().collect::<Vec<_>>(),
+            [TimeSourceKind::Rtc, TimeSourceKind::Rtc, TimeSourceKind::AcpiTad, TimeSourceKind::Virtual]
+        );
+
+        let (time, capabilities) = arbiter.get_time().unwrap();
+        assert_eq!((time.year, capabilities.resolution), (2001, 1));
+    }
+
+    #[test]
+    fn get_time_should_fall_back_to_the_next_source() {
+        let mut arbiter = arbiter();
+        arbiter.register(source(TimeSourceKind::Rtc, Ok(efi::Time::default()), 1));
+        arbiter.register(source(TimeSourceKind::Rtc, Err(EfiError::Unsupported), 2));
+        arbiter.register(source(TimeSourceKind::AcpiTad, Err(EfiError::DeviceError), 3));
+        arbiter.register(source(TimeSourceKind::Virtual, Ok(time(2025)), 4));
+
+        let (time, capabilities) = arbiter.get_time().unwrap();
+        assert_eq!((time.year, capabilities.resolution), (2025, 4));
+    }
+
+    #[test]
+    fn get_time_should_report_the_time_as_unavailable() {
+        let mut arbiter = arbiter();
+        assert!(matches!(arbiter.get_time(), Err(EfiError::Unsupported)));
+
+        arbiter.register(source(TimeSourceKind::Rtc, Err(EfiError::Unsupported), 1));
+        assert!(matches!(arbiter.get_time(), Err(EfiError::Unsupported)));
+
+        arbiter.register(source(TimeSourceKind::AcpiTad, Ok(time(1899)), 2));
+        assert!(matches!(arbiter.get_time(), Err(EfiError::DeviceError)));
+    }
+
+    #[test]
+    fn set_time_should_use_the_most_preferred_source_supporting_it() {
+        let mut arbiter = arbiter();
+        assert_eq!(arbiter.set_time(&time(2025)), Err(EfiError::Unsupported));
+
+        let (virtual_set, tad_set) = (Rc::new(Cell::new(None)), Rc::new(Cell::new(None)));
+        arbiter.register(source(TimeSourceKind::Rtc, Ok(time(2025)), 1));
+        arbiter.register(Box::new(FakeSource {
+            kind: TimeSourceKind::Virtual,
+            time: Ok(time(2025)),
+            resolution: 1,
+            set: Some(virtual_set.clone()),
+        }));
+        arbiter.register(Box::new(FakeSource {
+            kind: TimeSourceKind::AcpiTad,
+            time: Ok(time(2025)),
+            resolution: 1,
+            set: Some(tad_set.clone()),
+        }));
+
+        arbiter.set_time(&time(2030)).unwrap();
+        assert_eq!((tad_set.get(), virtual_set.get()), (Some(2030), None));
+        assert_eq!(arbiter.set_time(&time(1800)), Err(EfiError::InvalidParameter));
+    }
+}
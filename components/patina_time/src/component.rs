@@ -0,0 +1,392 @@
+//! Patina Time Manager Component
+//!
+//! Produces the [TimeService] over a [TimeArbiter] ordered by the [TimeConfig]. Components driving a time source
+//! register it through the service.
+//!
+//! GetTime() of the runtime services goes through the arbiter until ExitBootServices. The GetTime() it replaces, the
+//! one of the core or of the runtime driver installing the Real Time Clock architectural protocol, is kept as the
+//! runtime services source of the arbiter. As the arbiter and its sources are in boot services memory, the replaced
+//! GetTime() is put back at ExitBootServices.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use alloc::boxed::Box;
+use core::{
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+use patina::{
+    boot_services::{
+        BootServices, StandardBootServices,
+        event::{EventBuilder, EventType},
+        tpl::Tpl,
+    },
+    component::{
+        IntoComponent,
+        params::{Commands, Config},
+        service::IntoService,
+    },
+    error::Result,
+    guids,
+    runtime_services::StandardRuntimeServices,
+    tpl_mutex::TplMutex,
+};
+use r_efi::efi;
+
+use crate::{
+    arbiter::TimeArbiter,
+    config::TimeConfig,
+    service::TimeService,
+    source::{RuntimeServicesTimeSource, TimeSource},
+};
+
+/// Time Manager Component.
+#[derive(IntoComponent, Default)]
+pub struct TimeManager;
+
+/// The [TimeService] over the arbiter of the component.
+#[derive(IntoService)]
+#[service(dyn TimeService)]
+struct ArbitratedTimeService {
+    arbiter: &'static TplMutex<'static, TimeArbiter>,
+}
+
+impl TimeService for ArbitratedTimeService {
+    fn register_source(&self, source: Box<dyn TimeSource>) {
+        self.arbiter.lock().register(source);
+    }
+
+    fn get_time(&self) -> Result<(efi::Time, efi::TimeCapabilities)> {
+        self.arbiter.lock().get_time()
+    }
+
+    fn set_time(&self, time: &efi::Time) -> Result<()> {
+        self.arbiter.lock().set_time(time)
+    }
+}
+
+/// The arbiter answering GetTime() of the runtime services, null once boot services are exited.
+static RUNTIME_ARBITER: AtomicPtr<TplMutex<'static, TimeArbiter>> = AtomicPtr::new(ptr::null_mut());
+
+/// The runtime services table whose GetTime() goes through the arbiter, and a copy of it holding the GetTime()
+/// replaced.
+#[derive(Clone, Copy)]
+struct RuntimeGetTime {
+    boot_services: &'static StandardBootServices,
+    table: *mut efi::RuntimeServices,
+    replaced: *mut efi::RuntimeServices,
+}
+
+impl RuntimeGetTime {
+    /// Routes GetTime() of the runtime services through the arbiter.
+    #[coverage(off)]
+    fn route(&self) {
+        // SAFETY: the runtime services table is valid, and the copy is leaked.
+        let (table, replaced) = unsafe { (&mut *self.table, &mut *self.replaced) };
+        route_get_time(table, replaced);
+        checksum(self.boot_services, table);
+    }
+
+    /// Puts back the GetTime() replaced by the arbiter.
+    #[coverage(off)]
+    fn restore(&self) {
+        // SAFETY: the runtime services table is valid, and the copy is leaked.
+        let (table, replaced) = unsafe { (&mut *self.table, &*self.replaced) };
+        restore_get_time(table, replaced);
+        checksum(self.boot_services, table);
+    }
+}
+
+impl TimeManager {
+    /// Entry point of [`TimeManager`]
+    #[coverage(off)] // The component only wires the arbiter, which is tested on its own.
+    fn entry_point(
+        self,
+        config: Config<TimeConfig>,
+        boot_services: StandardBootServices,
+        runtime_services: StandardRuntimeServices,
+        mut commands: Commands,
+    ) -> Result<()> {
+        let table = runtime_services.as_mut_ptr();
+        // SAFETY: the runtime services table is valid, the copy only keeps its time services.
+        let replaced = Box::into_raw(Box::new(unsafe { ptr::read(table) }));
+
+        let mut arbiter = TimeArbiter::new(config.source_order.clone());
+        if config.use_runtime_services {
+            // SAFETY: the copy is leaked.
+            let replaced_services = StandardRuntimeServices::new(unsafe { &*replaced });
+            arbiter.register(Box::new(RuntimeServicesTimeSource::new(replaced_services)));
+        }
+
+        let boot_services: &'static StandardBootServices = Box::leak(Box::new(boot_services));
+        let arbiter: &'static TplMutex<'static, TimeArbiter> =
+            Box::leak(Box::new(TplMutex::new(boot_services, Tpl::NOTIFY, arbiter)));
+        commands.add_service(ArbitratedTimeService { arbiter });
+
+        let context = RuntimeGetTime { boot_services, table, replaced };
+        RUNTIME_ARBITER.store(arbiter as *const _ as *mut _, Ordering::SeqCst);
+        context.route();
+
+        let event = EventBuilder::new(boot_services.clone(), EventType::NOTIFY_SIGNAL, Tpl::CALLBACK)
+            .create::<StandardBootServices, _>(on_rtc_installed, context)?;
+        boot_services.register_protocol_notify(&guids::REAL_TIME_CLOCK_ARCH_PROTOCOL, event.event())?;
+        EventBuilder::new(boot_services.clone(), EventType::SIGNAL_EXIT_BOOT_SERVICES, Tpl::NOTIFY)
+            .one_shot()
+            .create::<StandardBootServices, _>(on_exit_boot_services, context)?;
+        Ok(())
+    }
+}
+
+/// Routes GetTime() of `table` through the arbiter, keeping the GetTime() it replaces in `replaced`.
+fn route_get_time(table: &mut efi::RuntimeServices, replaced: &mut efi::RuntimeServices) {
+    if table.get_time as usize != arbitrated_get_time as usize {
+        replaced.get_time = table.get_time;
+        table.get_time = arbitrated_get_time;
+    }
+}
+
+/// Puts back in `table` the GetTime() kept in `replaced`.
+fn restore_get_time(table: &mut efi::RuntimeServices, replaced: &efi::RuntimeServices) {
+    if table.get_time as usize == arbitrated_get_time as usize {
+        table.get_time = replaced.get_time;
+    }
+}
+
+/// Updates the CRC32 of the runtime services `table` after one of its services is replaced.
+fn checksum(boot_services: &impl BootServices, table: &mut efi::RuntimeServices) {
+    table.hdr.crc32 = 0;
+    match boot_services.calculate_crc_32(&*table) {
+        Ok(crc32) => table.hdr.crc32 = crc32,
+        Err(status) => log::error!("Failed to update the CRC32 of the runtime services table: {status:#x?}"),
+    }
+}
+
+/// Notify function of the event signaled when the Real Time Clock architectural protocol is installed, taking back
+/// GetTime() of the runtime services from the runtime driver installing it.
+#[coverage(off)]
+fn on_rtc_installed(_event: efi::Event, context: &mut RuntimeGetTime) {
+    context.route();
+}
+
+/// Notify function of the ExitBootServices event, putting back the GetTime() replaced by the arbiter.
+#[coverage(off)]
+fn on_exit_boot_services(_event: efi::Event, context: &mut RuntimeGetTime) {
+    RUNTIME_ARBITER.store(ptr::null_mut(), Ordering::SeqCst);
+    context.restore();
+}
+
+/// Writes the time of `arbiter` to `time`, and the capabilities of its source to `capabilities` if not null.
+fn get_time_from(arbiter: &TimeArbiter, time: *mut efi::Time, capabilities: *mut efi::TimeCapabilities) -> efi::Status {
+    if time.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    match arbiter.get_time() {
+        Ok((value, source_capabilities)) => {
+            // SAFETY: time is not null, and the caller guarantees both pointers are valid if not null.
+            unsafe {
+                time.write(value);
+                if let Some(capabilities) = capabilities.as_mut() {
+                    *capabilities = source_capabilities;
+                }
+            }
+            efi::Status::SUCCESS
+        }
+        Err(err) => err.into(),
+    }
+}
+
+/// GetTime() of the runtime services until ExitBootServices.
+#[coverage(off)]
+extern "efiapi" fn arbitrated_get_time(time: *mut efi::Time, capabilities: *mut efi::TimeCapabilities) -> efi::Status {
+    // SAFETY: the arbiter is leaked, and the pointer is cleared at ExitBootServices.
+    match unsafe { RUNTIME_ARBITER.load(Ordering::SeqCst).as_ref() } {
+        Some(arbiter) => get_time_from(&arbiter.lock(), time, capabilities),
+        None => efi::Status::UNSUPPORTED,
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use alloc::vec;
+    use core::{ffi::c_void, mem};
+    use patina::{boot_services::MockBootServices, error::EfiError};
+
+    use crate::source::{MockTimeSource, TimeSourceKind};
+
+    extern "efiapi" fn driver_get_time(_: *mut efi::Time, _: *mut efi::TimeCapabilities) -> efi::Status {
+        efi::Status::DEVICE_ERROR
+    }
+
+    extern "efiapi" fn set_time(_: *mut efi::Time) -> efi::Status {
+        unimplemented!()
+    }
+
+    extern "efiapi" fn get_wakeup_time(_: *mut efi::Boolean, _: *mut efi::Boolean, _: *mut efi::Time) -> efi::Status {
+        unimplemented!()
+    }
+
+    extern "efiapi" fn set_wakeup_time(_: efi::Boolean, _: *mut efi::Time) -> efi::Status {
+        unimplemented!()
+    }
+
+    extern "efiapi" fn set_virtual_address_map(
+        _: usize,
+        _: usize,
+        _: u32,
+        _: *mut efi::MemoryDescriptor,
+    ) -> efi::Status {
+        unimplemented!()
+    }
+
+    extern "efiapi" fn convert_pointer(_: usize, _: *mut *mut c_void) -> efi::Status {
+        unimplemented!()
+    }
+
+    extern "efiapi" fn get_variable(
+        _: *mut efi::Char16,
+        _: *mut efi::Guid,
+        _: *mut u32,
+        _: *mut usize,
+        _: *mut c_void,
+    ) -> efi::Status {
+        unimplemented!()
+    }
+
+    extern "efiapi" fn get_next_variable_name(_: *mut usize, _: *mut efi::Char16, _: *mut efi::Guid) -> efi::Status {
+        unimplemented!()
+    }
+
+    extern "efiapi" fn set_variable(
+        _: *mut efi::Char16,
+        _: *mut efi::Guid,
+        _: u32,
+        _: usize,
+        _: *mut c_void,
+    ) -> efi::Status {
+        unimplemented!()
+    }
+
+    extern "efiapi" fn get_next_high_mono_count(_: *mut u32) -> efi::Status {
+        unimplemented!()
+    }
+
+    extern "efiapi" fn reset_system(_: efi::ResetType, _: efi::Status, _: usize, _: *mut c_void) {
+        unimplemented!()
+    }
+
+    extern "efiapi" fn update_capsule(
+        _: *mut *mut efi::CapsuleHeader,
+        _: usize,
+        _: efi::PhysicalAddress,
+    ) -> efi::Status {
+        unimplemented!()
+    }
+
+    extern "efiapi" fn query_capsule_capabilities(
+        _: *mut *mut efi::CapsuleHeader,
+        _: usize,
+        _: *mut u64,
+        _: *mut efi::ResetType,
+    ) -> efi::Status {
+        unimplemented!()
+    }
+
+    extern "efiapi" fn query_variable_info(_: u32, _: *mut u64, _: *mut u64, _: *mut u64) -> efi::Status {
+        unimplemented!()
+    }
+
+    /// A runtime services table whose GetTime() is the one of a driver, and whose other services are never called.
+    fn table() -> efi::RuntimeServices {
+        efi::RuntimeServices {
+            hdr: efi::TableHeader {
+                signature: efi::RUNTIME_SERVICES_SIGNATURE,
+                revision: efi::RUNTIME_SERVICES_REVISION,
+                header_size: mem::size_of::<efi::RuntimeServices>() as u32,
+                crc32: 0,
+                reserved: 0,
+            },
+            get_time: driver_get_time,
+            set_time,
+            get_wakeup_time,
+            set_wakeup_time,
+            set_virtual_address_map,
+            convert_pointer,
+            get_variable,
+            get_next_variable_name,
+            set_variable,
+            get_next_high_mono_count,
+            reset_system,
+            update_capsule,
+            query_capsule_capabilities,
+            query_variable_info,
+        }
+    }
+
+    fn arbiter(time: efi::Time) -> TimeArbiter {
+        let mut source = MockTimeSource::new();
+        source.expect_kind().return_const(TimeSourceKind::Rtc);
+        source.expect_get_time().returning(move || {
+            Ok((time, efi::TimeCapabilities { resolution: 1, accuracy: 50_000_000, sets_to_zero: false.into() }))
+        });
+        let mut arbiter = TimeArbiter::new(vec![TimeSourceKind::Rtc]);
+        arbiter.register(Box::new(source));
+        arbiter
+    }
+
+    #[test]
+    fn get_time_should_be_routed_through_the_arbiter_until_restored() {
+        let (mut table, mut replaced) = (table(), table());
+
+        route_get_time(&mut table, &mut replaced);
+        assert_eq!(table.get_time as usize, arbitrated_get_time as usize);
+        assert_eq!(replaced.get_time as usize, driver_get_time as usize);
+
+        // Routing again keeps the GetTime() of the driver.
+        route_get_time(&mut table, &mut replaced);
+        assert_eq!(replaced.get_time as usize, driver_get_time as usize);
+
+        restore_get_time(&mut table, &replaced);
+        assert_eq!(table.get_time as usize, driver_get_time as usize);
+    }
+
+    #[test]
+    fn checksum_should_cover_the_table() {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_calculate_crc_32::<efi::RuntimeServices>().returning(|table| {
+            assert_eq!(table.hdr.crc32, 0);
+            Ok(0x1234_5678)
+        });
+        let mut table = table();
+        table.hdr.crc32 = 1;
+        checksum(&boot_services, &mut table);
+        assert_eq!(table.hdr.crc32, 0x1234_5678);
+    }
+
+    #[test]
+    fn get_time_from_should_report_the_time_of_the_arbiter() {
+        let time =
+            efi::Time { year: 2025, month: 6, day: 30, timezone: efi::UNSPECIFIED_TIMEZONE, ..Default::default() };
+        let arbiter = arbiter(time);
+
+        let mut reported = efi::Time::default();
+        let mut capabilities = efi::TimeCapabilities { resolution: 0, accuracy: 0, sets_to_zero: false.into() };
+        assert_eq!(get_time_from(&arbiter, &mut reported, &mut capabilities), efi::Status::SUCCESS);
+        assert_eq!((reported.year, reported.month, reported.day), (2025, 6, 30));
+        assert_eq!(capabilities.resolution, 1);
+
+        assert_eq!(get_time_from(&arbiter, &mut reported, ptr::null_mut()), efi::Status::SUCCESS);
+        assert_eq!(get_time_from(&arbiter, ptr::null_mut(), &mut capabilities), efi::Status::INVALID_PARAMETER);
+
+        let empty = TimeArbiter::new(vec![]);
+        assert_eq!(get_time_from(&empty, &mut reported, &mut capabilities), EfiError::Unsupported.into());
+    }
+}
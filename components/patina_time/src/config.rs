@@ -0,0 +1,32 @@
+//! Patina Time Component Configuration
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use alloc::{vec, vec::Vec};
+
+use crate::source::TimeSourceKind;
+
+/// The configuration for the Patina time component.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeConfig {
+    /// The kinds of time sources, from the most preferred to the least preferred. Sources of a kind not listed are
+    /// only used after all the others.
+    pub source_order: Vec<TimeSourceKind>,
+    /// Whether the GetTime() of the runtime services replaced by the arbitrated one is registered as an RTC source, for
+    /// platforms whose RTC is driven by a runtime driver.
+    pub use_runtime_services: bool,
+}
+
+impl Default for TimeConfig {
+    fn default() -> Self {
+        Self {
+            source_order: vec![TimeSourceKind::Rtc, TimeSourceKind::AcpiTad, TimeSourceKind::Virtual],
+            use_runtime_services: true,
+        }
+    }
+}
@@ -0,0 +1,46 @@
+//! Time source arbitration for Patina platforms.
+//!
+//! A platform can have several sources of the current time, e.g. an RTC, an ACPI Time and Alarm Device (TAD) or a
+//! clock provided by a hypervisor. This crate chooses between them:
+//!
+//! - [source::TimeSource]: the interface of a time source, registered by the component driving it.
+//! - [service::TimeService]: the service components use to register time sources and to get or set the time.
+//! - [component::TimeManager]: a component producing the service over the sources registered, in the order of
+//!   preference of the [config::TimeConfig]. The time is read from the most preferred source able to provide a valid
+//!   time, along with the capabilities of that source.
+//!
+//! ## Integration Example
+//!
+//! ```rust,ignore
+//! // ...
+//!
+//! Core::default()
+//!  // ...
+//!  .with_config(patina_time::config::TimeConfig {
+//!      source_order: vec![TimeSourceKind::Virtual, TimeSourceKind::Rtc],
+//!      ..Default::default()
+//!  })
+//!  .with_component(patina_time::component::TimeManager)
+//!  .start()
+//!  .unwrap();
+//!
+//! // ...
+//! ```
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+#![cfg_attr(all(not(feature = "std"), not(test), not(feature = "mockall")), no_std)]
+#![feature(coverage_attribute)]
+
+extern crate alloc;
+
+pub mod arbiter;
+pub mod component;
+pub mod config;
+pub mod service;
+pub mod source;
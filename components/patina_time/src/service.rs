@@ -0,0 +1,32 @@
+//! Time Service Definition
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use alloc::boxed::Box;
+use patina::error::Result;
+use r_efi::efi;
+
+use crate::source::TimeSource;
+
+#[cfg(any(test, feature = "mockall"))]
+use mockall::automock;
+
+/// Registers time sources and gets or sets the time through the most preferred of them.
+///
+/// See [TimeArbiter](crate::arbiter::TimeArbiter) for how the source answering each call is chosen.
+#[cfg_attr(any(test, feature = "mockall"), automock)]
+pub trait TimeService {
+    /// Adds `source` to the sources the time is read from.
+    fn register_source(&self, source: Box<dyn TimeSource>);
+
+    /// Returns the current time and the capabilities of the source it was read from.
+    fn get_time(&self) -> Result<(efi::Time, efi::TimeCapabilities)>;
+
+    /// Sets the current time.
+    fn set_time(&self, time: &efi::Time) -> Result<()>;
+}
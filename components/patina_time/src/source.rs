@@ -0,0 +1,145 @@
+//! Time Sources
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use patina::{
+    error::{EfiError, Result},
+    runtime_services::RuntimeServices,
+};
+use r_efi::efi;
+
+#[cfg(any(test, feature = "mockall"))]
+use mockall::automock;
+
+/// Valid bits of [efi::Time::daylight].
+const DAYLIGHT_MASK: u8 = efi::TIME_ADJUST_DAYLIGHT | efi::TIME_IN_DAYLIGHT;
+
+/// The largest offset of a time zone from UTC, in minutes.
+const MAX_TIMEZONE_OFFSET: i16 = 1440;
+
+/// The kind of hardware, or firmware, behind a time source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeSourceKind {
+    /// A real time clock.
+    Rtc,
+    /// An ACPI Time and Alarm Device.
+    AcpiTad,
+    /// A clock provided by a hypervisor.
+    Virtual,
+}
+
+/// A source of the current time.
+#[cfg_attr(any(test, feature = "mockall"), automock)]
+pub trait TimeSource {
+    /// Returns the kind of the source, which decides its order of preference.
+    fn kind(&self) -> TimeSourceKind;
+
+    /// Returns the current time and the capabilities of the source.
+    ///
+    /// # Errors
+    ///
+    /// Returns [EfiError::Unsupported] if the source cannot provide the time yet, e.g. its device is not started.
+    fn get_time(&self) -> Result<(efi::Time, efi::TimeCapabilities)>;
+
+    /// Sets the current time.
+    ///
+    /// # Errors
+    ///
+    /// Returns [EfiError::Unsupported] if the time of the source cannot be set.
+    fn set_time(&self, time: &efi::Time) -> Result<()>;
+}
+
+/// A [TimeSource] over GetTime() of the runtime services, backed by the RTC driver of the platform if any.
+pub struct RuntimeServicesTimeSource<R: RuntimeServices> {
+    runtime_services: R,
+}
+
+impl<R: RuntimeServices> RuntimeServicesTimeSource<R> {
+    /// Creates a time source over `runtime_services`.
+    pub fn new(runtime_services: R) -> Self {
+        Self { runtime_services }
+    }
+}
+
+impl<R: RuntimeServices> TimeSource for RuntimeServicesTimeSource<R> {
+    fn kind(&self) -> TimeSourceKind {
+        TimeSourceKind::Rtc
+    }
+
+    fn get_time(&self) -> Result<(efi::Time, efi::TimeCapabilities)> {
+        self.runtime_services.get_time().map_err(EfiError::from)
+    }
+
+    /// The time of the RTC driver is set through SetTime() of the runtime services by the OS or the setup
+    /// application, not through the arbitration.
+    fn set_time(&self, _time: &efi::Time) -> Result<()> {
+        Err(EfiError::Unsupported)
+    }
+}
+
+/// Returns the number of days in `month` of `year`.
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Returns true if every field of `time` is in the range allowed by the UEFI specification.
+pub fn is_valid_time(time: &efi::Time) -> bool {
+    (1900..=9999).contains(&time.year)
+        && (1..=12).contains(&time.month)
+        && (1..=days_in_month(time.year, time.month)).contains(&time.day)
+        && time.hour < 24
+        && time.minute < 60
+        && time.second < 60
+        && time.nanosecond < 1_000_000_000
+        && (time.timezone == efi::UNSPECIFIED_TIMEZONE
+            || (-MAX_TIMEZONE_OFFSET..=MAX_TIMEZONE_OFFSET).contains(&time.timezone))
+        && time.daylight & !DAYLIGHT_MASK == 0
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use patina::runtime_services::MockRuntimeServices;
+
+    fn time(year: u16, month: u8, day: u8) -> efi::Time {
+        efi::Time { year, month, day, timezone: efi::UNSPECIFIED_TIMEZONE, ..Default::default() }
+    }
+
+    #[test]
+    fn times_out_of_range_should_be_invalid() {
+        assert!(is_valid_time(&time(2024, 2, 29)));
+        assert!(is_valid_time(&efi::Time { timezone: -480, daylight: efi::TIME_IN_DAYLIGHT, ..time(2000, 2, 29) }));
+
+        assert!(!is_valid_time(&time(2023, 2, 29)));
+        assert!(!is_valid_time(&time(1900, 2, 29)));
+        assert!(!is_valid_time(&time(2025, 4, 31)));
+        assert!(!is_valid_time(&time(2025, 13, 1)));
+        assert!(!is_valid_time(&efi::Time::default()));
+        assert!(!is_valid_time(&efi::Time { hour: 24, ..time(2025, 1, 1) }));
+        assert!(!is_valid_time(&efi::Time { nanosecond: 1_000_000_000, ..time(2025, 1, 1) }));
+        assert!(!is_valid_time(&efi::Time { timezone: 1441, ..time(2025, 1, 1) }));
+        assert!(!is_valid_time(&efi::Time { daylight: 0x04, ..time(2025, 1, 1) }));
+    }
+
+    #[test]
+    fn runtime_services_source_should_forward_get_time() {
+        let mut runtime_services = MockRuntimeServices::new();
+        runtime_services.expect_get_time().once().returning(|| Err(efi::Status::UNSUPPORTED));
+        let source = RuntimeServicesTimeSource::new(runtime_services);
+
+        assert_eq!(source.kind(), TimeSourceKind::Rtc);
+        assert!(matches!(source.get_time(), Err(EfiError::Unsupported)));
+        assert_eq!(source.set_time(&time(2025, 1, 1)), Err(EfiError::Unsupported));
+    }
+}
@@ -6,7 +6,10 @@ use core::{
 };
 
 use gdbstub::arch::{RegId, Registers};
-use patina_internal_cpu::interrupts::ExceptionContext;
+use patina_internal_cpu::{
+    cpu::{psci::Psci, smccc::Conduit},
+    interrupts::ExceptionContext,
+};
 
 use crate::{ExceptionInfo, ExceptionType, memory};
 
@@ -235,12 +238,9 @@ impl DebuggerArch for Aarch64Arch {
     }
 
     fn reboot() {
-        // reboot through PSCI SYSTEM_RESET
-        // this directly loads a value into x0, but this is safe here because we are rebooting anyway
-        // so this doesn't matter if we clobber x0
-        unsafe {
-            asm!("ldr x0, =0x84000009", "smc 0");
-        }
+        // reboot through PSCI SYSTEM_RESET, on the conduit selected by the platform
+        let err = Psci::new(Conduit::platform()).system_reset();
+        log::error!("PSCI SYSTEM_RESET failed: {err:?}");
     }
 
     fn get_page_table() -> Result<Self::PageTable, ()> {
@@ -555,29 +555,8 @@ where
 
     /// Removes a node in the tree.
     fn remove_node_from_tree<'b>(root: &'b AtomicPtr<Node<D>>, to_delete: &'b Node<D>) {
-        //} -> &'b Node<D> {
-        // if both children are null, fixup the tree first so rotates work as expected,
-        // then remove the node.
-        if to_delete.left().is_none() && to_delete.right().is_none() {
-            Self::fixup_delete(root, Some(to_delete));
-            Self::remove_node_with_zero_or_one_child(to_delete);
-            if to_delete.parent().is_none() {
-                root.store(ptr::null_mut(), atomic::Ordering::SeqCst);
-            }
-            return;
-        }
-
-        let moved_up;
-        // If one child exists, simply remove the node.
-        if to_delete.left().is_none() || to_delete.right().is_none() {
-            moved_up = Self::remove_node_with_zero_or_one_child(to_delete);
-            if to_delete.parent().is_none() {
-                root.store(moved_up.as_mut_ptr(), atomic::Ordering::SeqCst);
-                moved_up.set_parent(None);
-            }
-        }
-        // if two children exist, find the successor and replace the value of the node, then removing the successor.
-        else {
+        // if two children exist, swap the node with its successor, so that the node has at most one child.
+        if to_delete.left().is_some() && to_delete.right().is_some() {
             let successor = Node::successor(to_delete).expect("to_delete has both children");
 
             Node::swap(to_delete, successor);
@@ -585,15 +564,29 @@ where
                 root.store(successor.as_mut_ptr(), atomic::Ordering::SeqCst);
                 successor.set_parent(None);
             }
+        }
 
-            // to_delete must have a parent due to the successor swap, no need
-            // to check if we need to update the head.
-            moved_up = Self::remove_node_with_zero_or_one_child(to_delete);
+        // if both children are null, fixup the tree first so rotates work as expected,
+        // then remove the node. Removing a red leaf does not change the black height.
+        if to_delete.left().is_none() && to_delete.right().is_none() {
+            if to_delete.is_black() {
+                Self::fixup_delete(root, Some(to_delete));
+            }
+            Self::remove_node_with_zero_or_one_child(to_delete);
+            if to_delete.parent().is_none() {
+                root.store(ptr::null_mut(), atomic::Ordering::SeqCst);
+            }
+            return;
         }
 
-        if to_delete.is_black() {
-            Self::fixup_delete(root, moved_up);
+        // If one child exists, the node is black and the child is red. Replace the node with the child and recolor
+        // the child black to keep the black height.
+        let moved_up = Self::remove_node_with_zero_or_one_child(to_delete);
+        if to_delete.parent().is_none() {
+            root.store(moved_up.as_mut_ptr(), atomic::Ordering::SeqCst);
+            moved_up.set_parent(None);
         }
+        moved_up.set_black();
     }
 
     /// Removes a node with zero or one child from the tree.
@@ -1179,6 +1172,25 @@ mod tests {
         assert_eq!(rbt.storage.len(), 0);
     }
 
+    #[test]
+    fn test_delete_keeps_tree_balanced() {
+        // Repeatedly deleting the largest value and adding values on both sides used to leave a degenerate tree.
+        let mut mem = [0; 0x1000 * node_size::<u64>()];
+        let mut rbt = Rbt::<u64>::with_capacity(&mut mem);
+        rbt.add(u64::MAX).unwrap();
+        let mut largest = u64::MAX;
+        for i in 0..0x7FF {
+            rbt.delete(&largest).unwrap();
+            rbt.add(i).unwrap();
+            largest = u64::MAX - i - 1;
+            rbt.add(largest).unwrap();
+        }
+        assert_eq!(rbt.len(), 0x800);
+        assert!(rbt.height() <= 22);
+        assert_eq!(rbt.first(), Some(&0));
+        assert_eq!(rbt.last(), Some(&largest));
+    }
+
     #[test]
     fn test_delete_simple() {
         /* Verifies that deleting a node with a single child or no child works as expected.
@@ -1888,6 +1900,46 @@ mod fuzz_tests {
         }
     }
 
+    #[test]
+    fn fuzz_delete_and_add() {
+        for _ in 0..100 {
+            let mut mem = [0; RBT_MAX_SIZE * node_size::<u32>()];
+            let mut rbt: Rbt<u32> = Rbt::with_capacity(&mut mem);
+            let mut rng = rand::thread_rng();
+            let min = 1;
+            let max = 100_000;
+
+            let mut random_numbers = HashSet::new();
+            while random_numbers.len() < RBT_MAX_SIZE {
+                let num = rng.gen_range(min..=max);
+                random_numbers.insert(num);
+            }
+
+            let mut random_numbers: Vec<_> = random_numbers.into_iter().collect();
+            random_numbers.shuffle(&mut rng);
+            let (present, absent) = random_numbers.split_at(RBT_MAX_SIZE / 2);
+            let (mut present, mut absent) = (present.to_vec(), absent.to_vec());
+            for num in present.iter() {
+                assert!(rbt.add(*num).is_ok());
+            }
+
+            // Interleave deletes and adds, the tree must stay balanced.
+            for _ in 0..RBT_MAX_SIZE * 4 {
+                let num = present.swap_remove(rng.gen_range(0..present.len()));
+                assert!(rbt.delete(&num).is_ok());
+                absent.push(num);
+
+                let num = absent.swap_remove(rng.gen_range(0..absent.len()));
+                assert!(rbt.add(num).is_ok());
+                present.push(num);
+            }
+            assert!(rbt.height() < 25);
+
+            present.sort();
+            assert_eq!(rbt.dfs(), present);
+        }
+    }
+
     #[test]
     fn fuzz_search() {
         let mut mem = [0; RBT_MAX_SIZE * node_size::<u32>()];
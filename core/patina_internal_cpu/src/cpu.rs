@@ -17,13 +17,14 @@ cfg_if::cfg_if! {
         pub type EfiCpu = x64::EfiCpuX64;
     } else if #[cfg(all(target_os = "uefi", target_arch = "aarch64"))] {
         mod aarch64;
+        pub use aarch64::{psci, smccc};
         pub type EfiCpu = aarch64::EfiCpuAarch64;
     } else if #[cfg(feature = "doc")] {
         mod x64;
         mod aarch64;
         mod null;
         pub use x64::EfiCpuX64;
-        pub use aarch64::EfiCpuAarch64;
+        pub use aarch64::{EfiCpuAarch64, psci, smccc};
         pub use null::EfiCpuNull;
 
         /// Type alias whose implementation is [EfiCpuX64], [EfiCpuAarch64], or [EfiCpuNull] depending on the compilation target.
@@ -36,7 +37,7 @@ cfg_if::cfg_if! {
         mod null;
         pub type EfiCpu = null::EfiCpuNull;
         pub use x64::EfiCpuX64;
-        pub use aarch64::EfiCpuAarch64;
+        pub use aarch64::{EfiCpuAarch64, psci, smccc};
         pub use null::EfiCpuNull;
     }
 }
@@ -7,5 +7,7 @@
 //! SPDX-License-Identifier: Apache-2.0
 //!
 mod cpu;
+pub mod psci;
+pub mod smccc;
 
 pub use cpu::EfiCpuAarch64;
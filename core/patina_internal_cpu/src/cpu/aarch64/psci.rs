@@ -0,0 +1,265 @@
+//! AArch64 Power State Coordination Interface (PSCI)
+//!
+//! This module provides typed helpers for the PSCI functions used by the firmware, such as starting secondary cores
+//! for MP services and resetting or powering off the system. All calls are issued through an [Smccc] implementation,
+//! which is a [Conduit] in production.
+//!
+//! ## Example
+//!
+//! ```rust,ignore
+//! use patina_internal_cpu::cpu::{psci::Psci, smccc::Conduit};
+//!
+//! let psci = Psci::new(Conduit::platform());
+//! if psci.version().is_ok() {
+//!     psci.system_reset();
+//! }
+//! ```
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use super::smccc::{Conduit, Smccc};
+use patina::error::EfiError;
+
+/// PSCI_VERSION function identifier.
+pub const PSCI_VERSION: u32 = 0x8400_0000;
+/// CPU_OFF function identifier.
+pub const PSCI_CPU_OFF: u32 = 0x8400_0002;
+/// CPU_ON (SMC64) function identifier.
+pub const PSCI_CPU_ON: u32 = 0xC400_0003;
+/// AFFINITY_INFO (SMC64) function identifier.
+pub const PSCI_AFFINITY_INFO: u32 = 0xC400_0004;
+/// SYSTEM_OFF function identifier.
+pub const PSCI_SYSTEM_OFF: u32 = 0x8400_0008;
+/// SYSTEM_RESET function identifier.
+pub const PSCI_SYSTEM_RESET: u32 = 0x8400_0009;
+/// PSCI_FEATURES function identifier.
+pub const PSCI_FEATURES: u32 = 0x8400_000A;
+/// SYSTEM_RESET2 (SMC64) function identifier.
+pub const PSCI_SYSTEM_RESET2: u32 = 0xC400_0012;
+
+/// Errors returned by PSCI functions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PsciError {
+    /// The function is not implemented (NOT_SUPPORTED, -1).
+    NotSupported,
+    /// A parameter was invalid (INVALID_PARAMETERS, -2).
+    InvalidParameters,
+    /// The call was denied by the implementation (DENIED, -3).
+    Denied,
+    /// The target core is already on (ALREADY_ON, -4).
+    AlreadyOn,
+    /// A CPU_ON request for the target core is already pending (ON_PENDING, -5).
+    OnPending,
+    /// The implementation encountered an internal failure (INTERNAL_FAILURE, -6).
+    InternalFailure,
+    /// The target core is not present (NOT_PRESENT, -7).
+    NotPresent,
+    /// The target core is disabled (DISABLED, -8).
+    Disabled,
+    /// The entry point address is invalid (INVALID_ADDRESS, -9).
+    InvalidAddress,
+    /// The call returned a value not defined by the specification.
+    Unknown(i32),
+}
+
+impl PsciError {
+    /// Converts a PSCI return value to a result, treating all non-negative values as success.
+    pub fn from_return_value(value: i32) -> Result<i32, PsciError> {
+        match value {
+            value if value >= 0 => Ok(value),
+            -1 => Err(PsciError::NotSupported),
+            -2 => Err(PsciError::InvalidParameters),
+            -3 => Err(PsciError::Denied),
+            -4 => Err(PsciError::AlreadyOn),
+            -5 => Err(PsciError::OnPending),
+            -6 => Err(PsciError::InternalFailure),
+            -7 => Err(PsciError::NotPresent),
+            -8 => Err(PsciError::Disabled),
+            -9 => Err(PsciError::InvalidAddress),
+            value => Err(PsciError::Unknown(value)),
+        }
+    }
+}
+
+impl From<PsciError> for EfiError {
+    fn from(err: PsciError) -> Self {
+        match err {
+            PsciError::NotSupported => EfiError::Unsupported,
+            PsciError::InvalidParameters | PsciError::InvalidAddress => EfiError::InvalidParameter,
+            PsciError::Denied => EfiError::AccessDenied,
+            PsciError::AlreadyOn => EfiError::AlreadyStarted,
+            PsciError::OnPending => EfiError::NotReady,
+            PsciError::NotPresent => EfiError::NotFound,
+            PsciError::Disabled => EfiError::NotStarted,
+            PsciError::InternalFailure | PsciError::Unknown(_) => EfiError::DeviceError,
+        }
+    }
+}
+
+/// The power state of a core as reported by AFFINITY_INFO.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AffinityState {
+    /// At least one core in the affinity instance is on.
+    On,
+    /// All cores in the affinity instance are off.
+    Off,
+    /// The affinity instance is transitioning to the on state.
+    OnPending,
+}
+
+/// Typed PSCI interface.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Psci<S: Smccc = Conduit> {
+    smccc: S,
+}
+
+impl<S: Smccc> Psci<S> {
+    /// Creates a new PSCI interface issuing calls through the provided SMCCC implementation.
+    pub const fn new(smccc: S) -> Self {
+        Self { smccc }
+    }
+
+    fn call(&self, function_id: u32, args: [u64; 6]) -> Result<i32, PsciError> {
+        PsciError::from_return_value(self.smccc.call(function_id, args)[0] as i32)
+    }
+
+    /// Returns the implemented PSCI version as a `(major, minor)` tuple.
+    ///
+    /// This can be used to probe for the presence of PSCI, as PSCI_VERSION is mandatory in all versions.
+    pub fn version(&self) -> Result<(u16, u16), PsciError> {
+        let version = self.call(PSCI_VERSION, [0; 6])?;
+        Ok(((version >> 16) as u16, version as u16))
+    }
+
+    /// Returns the feature flags of the provided PSCI or SMCCC function, or [PsciError::NotSupported] if the
+    /// function is not implemented.
+    pub fn features(&self, function_id: u32) -> Result<u32, PsciError> {
+        self.call(PSCI_FEATURES, [function_id as u64, 0, 0, 0, 0, 0]).map(|flags| flags as u32)
+    }
+
+    /// Powers on the core identified by `target_mpidr`, which begins execution at `entry_point` with `context_id` in
+    /// x0.
+    pub fn cpu_on(&self, target_mpidr: u64, entry_point: u64, context_id: u64) -> Result<(), PsciError> {
+        self.call(PSCI_CPU_ON, [target_mpidr, entry_point, context_id, 0, 0, 0]).map(|_| ())
+    }
+
+    /// Powers off the calling core. This only returns on failure.
+    pub fn cpu_off(&self) -> PsciError {
+        self.call(PSCI_CPU_OFF, [0; 6]).err().unwrap_or(PsciError::InternalFailure)
+    }
+
+    /// Returns the power state of the core identified by `target_mpidr`.
+    pub fn affinity_info(&self, target_mpidr: u64) -> Result<AffinityState, PsciError> {
+        match self.call(PSCI_AFFINITY_INFO, [target_mpidr, 0, 0, 0, 0, 0])? {
+            0 => Ok(AffinityState::On),
+            1 => Ok(AffinityState::Off),
+            2 => Ok(AffinityState::OnPending),
+            value => Err(PsciError::Unknown(value)),
+        }
+    }
+
+    /// Powers off the system. This only returns on failure.
+    pub fn system_off(&self) -> PsciError {
+        self.call(PSCI_SYSTEM_OFF, [0; 6]).err().unwrap_or(PsciError::InternalFailure)
+    }
+
+    /// Performs a cold reset of the system. This only returns on failure.
+    pub fn system_reset(&self) -> PsciError {
+        self.call(PSCI_SYSTEM_RESET, [0; 6]).err().unwrap_or(PsciError::InternalFailure)
+    }
+
+    /// Performs a warm or vendor specific reset of the system using SYSTEM_RESET2. This only returns on failure.
+    pub fn system_reset2(&self, reset_type: u32, cookie: u64) -> PsciError {
+        self.call(PSCI_SYSTEM_RESET2, [reset_type as u64, cookie, 0, 0, 0, 0])
+            .err()
+            .unwrap_or(PsciError::InternalFailure)
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use mockall::{mock, predicate::eq};
+
+    mock! {
+        Smccc {}
+        impl Smccc for Smccc {
+            fn call(&self, function_id: u32, args: [u64; 6]) -> [u64; 4];
+        }
+    }
+
+    fn ret(value: i64) -> [u64; 4] {
+        [value as u64, 0, 0, 0]
+    }
+
+    #[test]
+    fn test_version() {
+        let mut smccc = MockSmccc::new();
+        smccc.expect_call().with(eq(PSCI_VERSION), eq([0; 6])).return_const(ret(0x0001_0002));
+        assert_eq!(Psci::new(smccc).version(), Ok((1, 2)));
+    }
+
+    #[test]
+    fn test_version_not_supported() {
+        let mut smccc = MockSmccc::new();
+        smccc.expect_call().return_const(ret(-1));
+        assert_eq!(Psci::new(smccc).version(), Err(PsciError::NotSupported));
+    }
+
+    #[test]
+    fn test_cpu_on() {
+        let mut smccc = MockSmccc::new();
+        smccc.expect_call().with(eq(PSCI_CPU_ON), eq([0x100, 0x8000_0000, 0x42, 0, 0, 0])).return_const(ret(0));
+        assert_eq!(Psci::new(smccc).cpu_on(0x100, 0x8000_0000, 0x42), Ok(()));
+
+        let mut smccc = MockSmccc::new();
+        smccc.expect_call().return_const(ret(-4));
+        assert_eq!(Psci::new(smccc).cpu_on(0x100, 0x8000_0000, 0), Err(PsciError::AlreadyOn));
+    }
+
+    #[test]
+    fn test_affinity_info() {
+        let mut smccc = MockSmccc::new();
+        smccc.expect_call().with(eq(PSCI_AFFINITY_INFO), eq([0x1, 0, 0, 0, 0, 0])).return_const(ret(1));
+        assert_eq!(Psci::new(smccc).affinity_info(0x1), Ok(AffinityState::Off));
+    }
+
+    #[test]
+    fn test_system_reset_and_off_return_errors() {
+        let mut smccc = MockSmccc::new();
+        smccc.expect_call().with(eq(PSCI_SYSTEM_RESET), eq([0; 6])).return_const(ret(-6));
+        assert_eq!(Psci::new(smccc).system_reset(), PsciError::InternalFailure);
+
+        let mut smccc = MockSmccc::new();
+        smccc.expect_call().with(eq(PSCI_SYSTEM_OFF), eq([0; 6])).return_const(ret(-3));
+        assert_eq!(Psci::new(smccc).system_off(), PsciError::Denied);
+    }
+
+    #[test]
+    fn test_features() {
+        let mut smccc = MockSmccc::new();
+        smccc
+            .expect_call()
+            .with(eq(PSCI_FEATURES), eq([PSCI_SYSTEM_RESET2 as u64, 0, 0, 0, 0, 0]))
+            .return_const(ret(0));
+        assert_eq!(Psci::new(smccc).features(PSCI_SYSTEM_RESET2), Ok(0));
+    }
+
+    #[test]
+    fn test_error_conversion() {
+        assert_eq!(PsciError::from_return_value(-10), Err(PsciError::Unknown(-10)));
+        assert_eq!(EfiError::from(PsciError::NotSupported), EfiError::Unsupported);
+        assert_eq!(EfiError::from(PsciError::InvalidAddress), EfiError::InvalidParameter);
+        assert_eq!(EfiError::from(PsciError::Unknown(-10)), EfiError::DeviceError);
+    }
+
+    #[test]
+    fn test_default_conduit_off_target() {
+        assert_eq!(Psci::new(Conduit::Smc).version(), Err(PsciError::NotSupported));
+    }
+}
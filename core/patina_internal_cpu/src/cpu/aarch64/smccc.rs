@@ -0,0 +1,169 @@
+//! AArch64 SMC Calling Convention (SMCCC)
+//!
+//! This module provides a typed abstraction over the Arm SMC Calling Convention. Calls are issued through a
+//! [Conduit], which selects between the Secure Monitor Call (SMC) and Hypervisor Call (HVC) instructions. The conduit
+//! is typically chosen by the platform through configuration, or discovered from the device tree PSCI node `method`
+//! property or the ACPI FADT `ARM_BOOT_ARCH` flags, and selected once at init with [Conduit::set_platform] for the
+//! calls issued by the core.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+#[cfg(all(not(test), target_arch = "aarch64"))]
+use core::arch::asm;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// SMCCC_VERSION function identifier.
+pub const SMCCC_VERSION: u32 = 0x8000_0000;
+/// SMCCC_ARCH_FEATURES function identifier.
+pub const SMCCC_ARCH_FEATURES: u32 = 0x8000_0001;
+
+/// Value returned in w0 for an unknown or unsupported function identifier.
+pub const SMCCC_NOT_SUPPORTED: i32 = -1;
+
+/// FADT `ARM_BOOT_ARCH` flag indicating that PSCI is implemented.
+pub const ARM_BOOT_ARCH_PSCI_COMPLIANT: u16 = 1 << 0;
+/// FADT `ARM_BOOT_ARCH` flag indicating that HVC must be used instead of SMC as the PSCI conduit.
+pub const ARM_BOOT_ARCH_PSCI_USE_HVC: u16 = 1 << 1;
+
+// Whether the platform selected HVC as the conduit of the core, see Conduit::set_platform.
+static PLATFORM_USES_HVC: AtomicBool = AtomicBool::new(false);
+
+/// A trait for issuing SMCCC calls.
+///
+/// The trait exists so that users of the calling convention (e.g. [Psci](super::psci::Psci)) can be tested without
+/// trapping to a higher exception level.
+pub trait Smccc {
+    /// Issues the call identified by `function_id` with up to six arguments (x1 - x6), returning x0 - x3.
+    fn call(&self, function_id: u32, args: [u64; 6]) -> [u64; 4];
+}
+
+/// The instruction used to issue SMCCC calls.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Conduit {
+    /// Secure Monitor Call, handled at EL3.
+    #[default]
+    Smc,
+    /// Hypervisor Call, handled at EL2.
+    Hvc,
+}
+
+impl Conduit {
+    /// Selects the conduit of the SMCCC calls issued by the core, e.g. the PSCI SYSTEM_RESET of the debugger.
+    ///
+    /// This should be called once at init, before the core issues any call. SMC is used until then.
+    pub fn set_platform(conduit: Conduit) {
+        PLATFORM_USES_HVC.store(conduit == Conduit::Hvc, Ordering::Relaxed);
+    }
+
+    /// Returns the conduit selected with [Conduit::set_platform], or SMC if none was selected.
+    pub fn platform() -> Self {
+        if PLATFORM_USES_HVC.load(Ordering::Relaxed) { Conduit::Hvc } else { Conduit::Smc }
+    }
+
+    /// Returns the conduit described by a device tree PSCI node `method` property ("smc" or "hvc").
+    pub fn from_dt_method(method: &str) -> Option<Self> {
+        match method {
+            "smc" => Some(Conduit::Smc),
+            "hvc" => Some(Conduit::Hvc),
+            _ => None,
+        }
+    }
+
+    /// Returns the conduit described by the ACPI FADT `ARM_BOOT_ARCH` flags, or `None` if PSCI is not implemented.
+    pub fn from_fadt_arm_boot_arch(flags: u16) -> Option<Self> {
+        if flags & ARM_BOOT_ARCH_PSCI_COMPLIANT == 0 {
+            return None;
+        }
+        if flags & ARM_BOOT_ARCH_PSCI_USE_HVC != 0 { Some(Conduit::Hvc) } else { Some(Conduit::Smc) }
+    }
+
+    /// Returns the SMCCC version as a `(major, minor)` tuple, or `None` if SMCCC_VERSION is not implemented, which
+    /// indicates an SMCCC v1.0 implementation.
+    pub fn version(&self) -> Option<(u16, u16)> {
+        let version = self.call(SMCCC_VERSION, [0; 6])[0] as i32;
+        if version < 0 {
+            return None;
+        }
+        Some(((version >> 16) as u16, version as u16))
+    }
+}
+
+impl Smccc for Conduit {
+    fn call(&self, function_id: u32, args: [u64; 6]) -> [u64; 4] {
+        #[cfg(all(not(test), target_arch = "aarch64"))]
+        {
+            let mut x0 = function_id as u64;
+            let [mut x1, mut x2, mut x3, x4, x5, x6] = args;
+            // Safety: SMCCC calls only clobber x0 - x17. x4 - x17 are marked as clobbered below.
+            unsafe {
+                match self {
+                    Conduit::Smc => asm!(
+                        "smc #0",
+                        inout("x0") x0, inout("x1") x1, inout("x2") x2, inout("x3") x3,
+                        inout("x4") x4 => _, inout("x5") x5 => _, inout("x6") x6 => _,
+                        out("x7") _, out("x8") _, out("x9") _, out("x10") _, out("x11") _, out("x12") _,
+                        out("x13") _, out("x14") _, out("x15") _, out("x16") _, out("x17") _,
+                        options(nostack)
+                    ),
+                    Conduit::Hvc => asm!(
+                        "hvc #0",
+                        inout("x0") x0, inout("x1") x1, inout("x2") x2, inout("x3") x3,
+                        inout("x4") x4 => _, inout("x5") x5 => _, inout("x6") x6 => _,
+                        out("x7") _, out("x8") _, out("x9") _, out("x10") _, out("x11") _, out("x12") _,
+                        out("x13") _, out("x14") _, out("x15") _, out("x16") _, out("x17") _,
+                        options(nostack)
+                    ),
+                }
+            }
+            [x0, x1, x2, x3]
+        }
+        #[cfg(not(all(not(test), target_arch = "aarch64")))]
+        {
+            let _ = (function_id, args);
+            [SMCCC_NOT_SUPPORTED as u64, 0, 0, 0]
+        }
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conduit_from_dt_method() {
+        assert_eq!(Conduit::from_dt_method("smc"), Some(Conduit::Smc));
+        assert_eq!(Conduit::from_dt_method("hvc"), Some(Conduit::Hvc));
+        assert_eq!(Conduit::from_dt_method("svc"), None);
+    }
+
+    #[test]
+    fn test_conduit_from_fadt_arm_boot_arch() {
+        assert_eq!(Conduit::from_fadt_arm_boot_arch(0), None);
+        assert_eq!(Conduit::from_fadt_arm_boot_arch(ARM_BOOT_ARCH_PSCI_USE_HVC), None);
+        assert_eq!(Conduit::from_fadt_arm_boot_arch(ARM_BOOT_ARCH_PSCI_COMPLIANT), Some(Conduit::Smc));
+        assert_eq!(
+            Conduit::from_fadt_arm_boot_arch(ARM_BOOT_ARCH_PSCI_COMPLIANT | ARM_BOOT_ARCH_PSCI_USE_HVC),
+            Some(Conduit::Hvc)
+        );
+    }
+
+    #[test]
+    fn test_platform_conduit() {
+        assert_eq!(Conduit::platform(), Conduit::Smc);
+        Conduit::set_platform(Conduit::Hvc);
+        assert_eq!(Conduit::platform(), Conduit::Hvc);
+        Conduit::set_platform(Conduit::Smc);
+        assert_eq!(Conduit::platform(), Conduit::Smc);
+    }
+
+    #[test]
+    fn test_version_not_supported_off_target() {
+        assert_eq!(Conduit::Smc.version(), None);
+        assert_eq!(Conduit::Hvc.version(), None);
+    }
+}
@@ -19,6 +19,7 @@ use patina::error::EfiError;
 use patina_pi::protocols::cpu_arch::EfiSystemContext;
 
 mod exception_handling;
+pub mod statistics;
 
 cfg_if::cfg_if! {
     if #[cfg(all(target_os = "uefi", target_arch = "x86_64"))] {
@@ -83,6 +84,18 @@ pub(crate) trait EfiSystemContextFactory {
 pub(crate) trait EfiExceptionStackTrace {
     /// Dump the stack trace for architecture specific context.
     fn dump_stack_trace(&self);
+
+    /// Returns the address of the instruction that raised the exception.
+    fn instruction_pointer(&self) -> u64;
+}
+
+/// Callback reporting the loaded image containing an address, used to symbolize unhandled exceptions. It is invoked
+/// from the exception handler, so it must not block.
+pub type ImageLocator = fn(address: u64);
+
+/// Sets the callback used to report the loaded image containing the faulting instruction of an unhandled exception.
+pub fn set_image_locator(locator: ImageLocator) {
+    exception_handling::set_image_locator(locator);
 }
 
 /// Trait for structs that implement and manage interrupts.
@@ -38,6 +38,10 @@ impl super::EfiExceptionStackTrace for ExceptionContextAArch64 {
             log::error!("StackTrace: {err}");
         }
     }
+
+    fn instruction_pointer(&self) -> u64 {
+        self.elr
+    }
 }
 
 #[allow(unused)]
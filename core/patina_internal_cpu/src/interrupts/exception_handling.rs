@@ -14,10 +14,13 @@ use spin::rwlock::RwLock;
 
 use crate::interrupts::EfiExceptionStackTrace;
 
-use super::{EfiSystemContextFactory, ExceptionContext, ExceptionType, HandlerType};
+use super::{
+    EfiSystemContextFactory, ExceptionContext, ExceptionType, HandlerType, ImageLocator,
+    statistics::{self, HandlerEntry},
+};
 
 // Different architecture have a different number of exception types.
-const NUM_EXCEPTION_TYPES: ExceptionType = if cfg!(test) {
+pub(crate) const NUM_EXCEPTION_TYPES: ExceptionType = if cfg!(test) {
     8
 } else if cfg!(target_arch = "x86_64") {
     256
@@ -36,6 +39,22 @@ static EXCEPTION_HANDLERS: [RwLock<HandlerType>; NUM_EXCEPTION_TYPES] = {
     [INIT; NUM_EXCEPTION_TYPES]
 };
 
+// The callback used to report the loaded image containing the faulting instruction of an unhandled exception.
+static IMAGE_LOCATOR: RwLock<Option<ImageLocator>> = RwLock::new(None);
+
+/// Sets the callback used to report the loaded image containing the faulting instruction of an unhandled exception.
+pub(crate) fn set_image_locator(locator: ImageLocator) {
+    *IMAGE_LOCATOR.write() = Some(locator);
+}
+
+// Reports the loaded image containing the faulting instruction of `context`, if an image locator is set.
+fn locate_faulting_image(context: &ExceptionContext) {
+    // The lock is only contended if the exception was raised while setting the locator.
+    if let Some(locate_image) = IMAGE_LOCATOR.try_read().and_then(|locator| *locator) {
+        locate_image(context.instruction_pointer());
+    }
+}
+
 /// Registers a handler callback for the provided exception type.
 ///
 /// # Errors
@@ -87,6 +106,9 @@ pub(crate) fn unregister_exception_handler(exception_type: ExceptionType) -> Res
 /// This will be invoked by the architectures assembly entry and so requires
 /// EFIAPI for a consistent calling convention.
 ///
+/// An interrupt taken without a handler is counted as spurious before the panic, so that the statistics read from
+/// the debugger show the vector.
+///
 /// # Panics
 ///
 /// Panics if no callback has been registered for a given exception or the handler
@@ -94,6 +116,7 @@ pub(crate) fn unregister_exception_handler(exception_type: ExceptionType) -> Res
 ///
 #[unsafe(no_mangle)]
 extern "efiapi" fn exception_handler(exception_type: usize, context: &mut ExceptionContext) {
+    let _entry = HandlerEntry::enter(exception_type);
     let handler_lock =
         EXCEPTION_HANDLERS[exception_type].try_read().expect("Failed to read lock in exception handler!");
 
@@ -106,8 +129,13 @@ extern "efiapi" fn exception_handler(exception_type: usize, context: &mut Except
             handler.handle_interrupt(exception_type, context);
         }
         HandlerType::None => {
+            if statistics::is_interrupt_vector(exception_type) {
+                statistics::record_spurious(exception_type);
+            }
+
             log::error!("Unhandled Exception! 0x{exception_type:x}");
             log::error!("Exception Context: {context:#x?}");
+            locate_faulting_image(context);
             context.dump_stack_trace();
             panic!("Unhandled Exception! 0x{exception_type:x}");
         }
@@ -122,7 +150,7 @@ mod tests {
     use patina_pi::protocols::cpu_arch::EfiSystemContext;
 
     use super::*;
-    use core::sync::atomic::AtomicBool;
+    use core::sync::atomic::{AtomicBool, AtomicU64};
 
     const CALLBACK_EXCEPTION: usize = 0;
     const HANDLER_EXCEPTION: usize = 1;
@@ -180,6 +208,35 @@ mod tests {
         unregister_exception_handler(HANDLER_EXCEPTION).expect_err("Allowed double unregister!");
     }
 
+    #[test]
+    fn test_unhandled_interrupt_is_spurious() {
+        const SPURIOUS_INTERRUPT: usize = 6;
+        let mut context = crate::interrupts::null::ExceptionContextNull {};
+        let before = statistics::vector_statistics(SPURIOUS_INTERRUPT).unwrap();
+
+        // The interrupt is counted, then handled as any unhandled exception.
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            exception_handler(SPURIOUS_INTERRUPT, &mut context);
+        }));
+        assert!(result.is_err());
+
+        let after = statistics::vector_statistics(SPURIOUS_INTERRUPT).unwrap();
+        assert_eq!(after.taken, before.taken + 1);
+        assert_eq!(after.spurious, before.spurious + 1);
+    }
+
+    #[test]
+    fn test_image_locator_receives_faulting_address() {
+        static LOCATED_ADDRESS: AtomicU64 = AtomicU64::new(u64::MAX);
+        fn locate_image(address: u64) {
+            LOCATED_ADDRESS.store(address, core::sync::atomic::Ordering::SeqCst);
+        }
+
+        set_image_locator(locate_image);
+        locate_faulting_image(&crate::interrupts::null::ExceptionContextNull {});
+        assert_eq!(LOCATED_ADDRESS.load(core::sync::atomic::Ordering::SeqCst), 0);
+    }
+
     #[test]
     fn test_invalid_input() {
         register_exception_handler(NUM_EXCEPTION_TYPES, HandlerType::UefiRoutine(test_callback))
@@ -26,6 +26,10 @@ impl super::EfiSystemContextFactory for ExceptionContextNull {
 
 impl super::EfiExceptionStackTrace for ExceptionContextNull {
     fn dump_stack_trace(&self) {}
+
+    fn instruction_pointer(&self) -> u64 {
+        0
+    }
 }
 
 /// A function that does nothing as this is a null implementation.
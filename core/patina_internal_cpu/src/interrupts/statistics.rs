@@ -0,0 +1,148 @@
+//! Exception and interrupt vector statistics.
+//!
+//! The exception handler counts every vector it is entered for, the interrupts taken without a handler, and the
+//! deepest nesting of the handler. The counters are atomics so that they can be updated from the exception handler,
+//! and read at any time, e.g. by a diagnostic protocol or the debugger.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use core::{
+    ops::Range,
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+};
+
+use patina::uefi_protocol::interrupt_statistics::{InterruptSummary, VectorStatistics};
+
+use super::{ExceptionType, exception_handling::NUM_EXCEPTION_TYPES};
+
+// The vectors of the interrupts, the other vectors are exceptions.
+#[cfg(test)]
+const INTERRUPT_VECTORS: Range<ExceptionType> = 4..NUM_EXCEPTION_TYPES;
+#[cfg(all(not(test), target_arch = "x86_64"))]
+const INTERRUPT_VECTORS: Range<ExceptionType> = 32..NUM_EXCEPTION_TYPES;
+// IRQ and FIQ on AArch64, SError is an exception.
+#[cfg(all(not(test), not(target_arch = "x86_64")))]
+const INTERRUPT_VECTORS: Range<ExceptionType> = 1..3;
+
+struct VectorCounters {
+    taken: AtomicU64,
+    spurious: AtomicU64,
+}
+
+static VECTOR_COUNTERS: [VectorCounters; NUM_EXCEPTION_TYPES] = {
+    // This clippy warning can be ignored. We are purposefully generating a different `INIT` const for each element.
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: VectorCounters = VectorCounters { taken: AtomicU64::new(0), spurious: AtomicU64::new(0) };
+    [INIT; NUM_EXCEPTION_TYPES]
+};
+
+static NESTING_DEPTH: AtomicU32 = AtomicU32::new(0);
+static MAX_NESTING_DEPTH: AtomicU32 = AtomicU32::new(0);
+
+/// Returns true if `exception_type` is an interrupt vector rather than an exception.
+pub fn is_interrupt_vector(exception_type: ExceptionType) -> bool {
+    INTERRUPT_VECTORS.contains(&exception_type)
+}
+
+/// Marks the exception handler as entered for `exception_type` until the guard is dropped.
+pub(crate) struct HandlerEntry(());
+
+impl HandlerEntry {
+    /// Counts `exception_type` as taken, and the handler as nested one level deeper.
+    pub(crate) fn enter(exception_type: ExceptionType) -> Self {
+        VECTOR_COUNTERS[exception_type].taken.fetch_add(1, Ordering::Relaxed);
+        let depth = NESTING_DEPTH.fetch_add(1, Ordering::Relaxed) + 1;
+        MAX_NESTING_DEPTH.fetch_max(depth, Ordering::Relaxed);
+        Self(())
+    }
+}
+
+impl Drop for HandlerEntry {
+    fn drop(&mut self) {
+        NESTING_DEPTH.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Counts an interrupt taken on `exception_type` without a handler. Returns the number of such interrupts so far.
+pub(crate) fn record_spurious(exception_type: ExceptionType) -> u64 {
+    VECTOR_COUNTERS[exception_type].spurious.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+/// Returns the counters of `exception_type`, or `None` if it is past the last vector.
+pub fn vector_statistics(exception_type: ExceptionType) -> Option<VectorStatistics> {
+    let counters = VECTOR_COUNTERS.get(exception_type)?;
+    Some(VectorStatistics {
+        taken: counters.taken.load(Ordering::Relaxed),
+        spurious: counters.spurious.load(Ordering::Relaxed),
+    })
+}
+
+/// Returns the counters of all the vectors.
+pub fn interrupt_summary() -> InterruptSummary {
+    let mut summary = InterruptSummary {
+        vector_count: NUM_EXCEPTION_TYPES as u64,
+        max_nesting_depth: MAX_NESTING_DEPTH.load(Ordering::Relaxed),
+        ..Default::default()
+    };
+    for (exception_type, counters) in VECTOR_COUNTERS.iter().enumerate() {
+        let taken = counters.taken.load(Ordering::Relaxed);
+        if is_interrupt_vector(exception_type) {
+            summary.interrupts += taken;
+            summary.spurious += counters.spurious.load(Ordering::Relaxed);
+        } else {
+            summary.faults += taken;
+        }
+    }
+    summary
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+
+    // Vectors only used by these tests, as the counters are shared with the other tests of the crate.
+    const FAULT_VECTOR: ExceptionType = 3;
+    const INTERRUPT_VECTOR: ExceptionType = 7;
+    const NESTED_VECTOR: ExceptionType = 5;
+
+    #[test]
+    fn vectors_should_be_counted() {
+        let (before, summary_before) = (vector_statistics(INTERRUPT_VECTOR).unwrap(), interrupt_summary());
+
+        drop(HandlerEntry::enter(FAULT_VECTOR));
+        drop(HandlerEntry::enter(INTERRUPT_VECTOR));
+        assert_eq!(record_spurious(INTERRUPT_VECTOR), before.spurious + 1);
+
+        let after = vector_statistics(INTERRUPT_VECTOR).unwrap();
+        assert_eq!((after.taken, after.spurious), (before.taken + 1, before.spurious + 1));
+
+        let summary = interrupt_summary();
+        assert_eq!(summary.vector_count, NUM_EXCEPTION_TYPES as u64);
+        assert!(summary.faults > summary_before.faults);
+        assert!(summary.interrupts > summary_before.interrupts);
+        assert!(summary.spurious > summary_before.spurious);
+        assert_eq!(vector_statistics(NUM_EXCEPTION_TYPES), None);
+    }
+
+    #[test]
+    fn nesting_should_be_tracked() {
+        let outer = HandlerEntry::enter(FAULT_VECTOR);
+        let inner = HandlerEntry::enter(NESTED_VECTOR);
+        assert!(interrupt_summary().max_nesting_depth >= 2);
+        drop(inner);
+        drop(outer);
+    }
+
+    #[test]
+    fn interrupt_vectors_should_follow_the_exceptions() {
+        assert!(!is_interrupt_vector(FAULT_VECTOR));
+        assert!(is_interrupt_vector(INTERRUPT_VECTORS.start));
+        assert!(!is_interrupt_vector(INTERRUPT_VECTORS.end));
+    }
+}
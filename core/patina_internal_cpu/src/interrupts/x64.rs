@@ -36,6 +36,10 @@ impl super::EfiExceptionStackTrace for ExceptionContextX64 {
             log::error!("StackTrace: {err}");
         }
     }
+
+    fn instruction_pointer(&self) -> u64 {
+        self.rip
+    }
 }
 
 #[allow(unused)]
@@ -15,4 +15,6 @@ extern crate alloc;
 
 pub mod cpu;
 pub mod interrupts;
+#[cfg(target_arch = "x86_64")]
+pub mod mtrr;
 pub mod paging;
@@ -0,0 +1,238 @@
+//! X64 MTRR Management
+//!
+//! This module provides helpers for working with the x86_64 Memory Type Range Registers (MTRRs). It is responsible for
+//! translating between MTRR cache types and [MemoryAttributes], reporting the current MTRR layout, and replicating
+//! the bootstrap processor (BSP) MTRR settings to the application processors (APs).
+//!
+//! MTRRs are per-processor registers. Any cacheability change made on the BSP must be replicated to every AP, otherwise
+//! the APs will continue to access the region with the stale memory type. Once the MP Services protocol has been
+//! registered with [register_mp_services], every successful MTRR update made through the CPU paging implementation is
+//! replicated to all enabled APs by [synchronize_pending_aps]. The update is made while the memory map locks are held,
+//! so the APs are only started once the caller released them, as an AP accessing the memory map would deadlock.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::vec::Vec;
+use core::{
+    ffi::c_void,
+    ptr,
+    sync::atomic::{AtomicBool, AtomicPtr, Ordering},
+};
+use patina::error::EfiError;
+use patina_mtrr::{
+    Mtrr, create_mtrr_lib,
+    error::MtrrError,
+    structs::{MtrrMemoryCacheType, MtrrMemoryRange, MtrrSettings},
+};
+use patina_paging::MemoryAttributes;
+use r_efi::{efi, protocols::mp_services};
+
+/// The MP Services protocol used to replicate MTRR settings to the APs. Null until registered.
+static MP_SERVICES: AtomicPtr<mp_services::Protocol> = AtomicPtr::new(ptr::null_mut());
+
+/// Set when the BSP MTRR settings changed since the APs were last synchronized.
+static AP_SYNCHRONIZATION_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// Converts an MTRR cache type to the equivalent cache [MemoryAttributes].
+///
+/// Reserved and invalid cache types are reported as an empty set of attributes.
+pub fn cache_type_to_attributes(cache_type: MtrrMemoryCacheType) -> MemoryAttributes {
+    match cache_type {
+        MtrrMemoryCacheType::Uncacheable => MemoryAttributes::Uncacheable,
+        MtrrMemoryCacheType::WriteCombining => MemoryAttributes::WriteCombining,
+        MtrrMemoryCacheType::WriteThrough => MemoryAttributes::WriteThrough,
+        MtrrMemoryCacheType::WriteProtected => MemoryAttributes::WriteProtect,
+        MtrrMemoryCacheType::WriteBack => MemoryAttributes::Writeback,
+        _ => MemoryAttributes::empty(),
+    }
+}
+
+/// Converts a single cache [MemoryAttributes] value to the equivalent MTRR cache type.
+///
+/// ## Errors
+///
+/// Unsupported - `attributes` is not exactly one of the supported cache attributes.
+pub fn attributes_to_cache_type(attributes: MemoryAttributes) -> Result<MtrrMemoryCacheType, EfiError> {
+    match attributes {
+        MemoryAttributes::Uncacheable => Ok(MtrrMemoryCacheType::Uncacheable),
+        MemoryAttributes::WriteCombining => Ok(MtrrMemoryCacheType::WriteCombining),
+        MemoryAttributes::WriteThrough => Ok(MtrrMemoryCacheType::WriteThrough),
+        MemoryAttributes::WriteProtect => Ok(MtrrMemoryCacheType::WriteProtected),
+        MemoryAttributes::Writeback => Ok(MtrrMemoryCacheType::WriteBack),
+        _ => Err(EfiError::Unsupported),
+    }
+}
+
+/// Converts an [MtrrError] to the equivalent [EfiError].
+pub fn mtrr_err_to_efi_error(err: MtrrError) -> EfiError {
+    match err {
+        MtrrError::MtrrNotSupported => EfiError::Unsupported,
+        MtrrError::VariableRangeMtrrExhausted => EfiError::OutOfResources,
+        MtrrError::FixedRangeMtrrBaseAddressNotAligned => EfiError::InvalidParameter,
+        MtrrError::FixedRangeMtrrLengthNotAligned => EfiError::InvalidParameter,
+        MtrrError::InvalidParameter => EfiError::InvalidParameter,
+        MtrrError::BufferTooSmall => EfiError::BufferTooSmall,
+        MtrrError::OutOfResources => EfiError::OutOfResources,
+        MtrrError::AlreadyStarted => EfiError::AlreadyStarted,
+    }
+}
+
+/// Returns the memory ranges currently described by the provided MTRR instance.
+///
+/// ## Errors
+///
+/// Unsupported - MTRRs are not supported on this processor.
+pub fn get_memory_ranges<M: Mtrr>(mtrr: &M) -> Result<Vec<MtrrMemoryRange>, EfiError> {
+    if !mtrr.is_supported() {
+        return Err(EfiError::Unsupported);
+    }
+    mtrr.get_memory_ranges().map(|ranges| ranges.into_iter().collect()).map_err(mtrr_err_to_efi_error)
+}
+
+/// Returns the memory ranges currently programmed in the MTRRs of the executing processor.
+///
+/// ## Errors
+///
+/// Unsupported - MTRRs are not supported on this processor.
+pub fn get_current_memory_ranges() -> Result<Vec<MtrrMemoryRange>, EfiError> {
+    get_memory_ranges(&create_mtrr_lib(0))
+}
+
+/// Registers the MP Services protocol used to replicate MTRR settings to the APs.
+///
+/// The current BSP MTRR settings are immediately replicated to all enabled APs so that any change made before the
+/// APs were started is not lost.
+///
+/// ## Errors
+///
+/// InvalidParameter - `protocol` is null.
+///
+/// Returns any error produced while replicating the MTRR settings to the APs.
+pub fn register_mp_services(protocol: *mut mp_services::Protocol) -> Result<(), EfiError> {
+    if protocol.is_null() {
+        return Err(EfiError::InvalidParameter);
+    }
+    MP_SERVICES.store(protocol, Ordering::SeqCst);
+    synchronize_aps(&create_mtrr_lib(0))
+}
+
+/// Replicates the MTRR settings of the provided (BSP) MTRR instance to all enabled APs.
+///
+/// This is a no-op if MTRRs are not supported or the MP Services protocol has not been registered.
+///
+/// ## Errors
+///
+/// Returns the error produced by the MP Services protocol if the APs could not be started.
+pub fn synchronize_aps<M: Mtrr>(mtrr: &M) -> Result<(), EfiError> {
+    let mp_services = MP_SERVICES.load(Ordering::SeqCst);
+    if mp_services.is_null() || !mtrr.is_supported() {
+        return Ok(());
+    }
+
+    let settings = mtrr.get_all_mtrrs().map_err(mtrr_err_to_efi_error)?;
+
+    // Safety: mp_services was registered through register_mp_services and was checked for null above. The settings
+    // buffer outlives the call as the APs are run in blocking mode (null wait event).
+    let status = unsafe {
+        ((*mp_services).startup_all_aps)(
+            mp_services,
+            ap_synchronize_mtrrs,
+            efi::Boolean::FALSE,
+            ptr::null_mut(),
+            0,
+            &settings as *const MtrrSettings as *mut c_void,
+            ptr::null_mut(),
+        )
+    };
+
+    match status {
+        // No enabled APs to synchronize.
+        efi::Status::NOT_STARTED => Ok(()),
+        status => EfiError::status_to_result(status).inspect_err(|err| {
+            log::error!("Failed to synchronize MTRRs to APs: {err:?}");
+        }),
+    }
+}
+
+/// Records that the BSP MTRR settings changed and must be replicated to the APs by [synchronize_pending_aps].
+pub fn request_ap_synchronization() {
+    AP_SYNCHRONIZATION_PENDING.store(true, Ordering::SeqCst);
+}
+
+/// Replicates the BSP MTRR settings to all enabled APs if they changed since the last synchronization.
+///
+/// This must not be called while holding the GCD or page table locks.
+///
+/// ## Errors
+///
+/// Returns the error produced by the MP Services protocol if the APs could not be started.
+pub fn synchronize_pending_aps() -> Result<(), EfiError> {
+    // Without the MP Services protocol, the APs are synchronized once it is registered.
+    if !AP_SYNCHRONIZATION_PENDING.swap(false, Ordering::SeqCst) || MP_SERVICES.load(Ordering::SeqCst).is_null() {
+        return Ok(());
+    }
+    synchronize_aps(&create_mtrr_lib(0))
+}
+
+/// AP procedure that programs the executing processor with the MTRR settings pointed to by `buffer`.
+extern "efiapi" fn ap_synchronize_mtrrs(buffer: *mut c_void) {
+    if buffer.is_null() {
+        return;
+    }
+    // Safety: buffer is the MtrrSettings provided by synchronize_aps, which remains valid while the APs run.
+    let settings = unsafe { &*(buffer as *const MtrrSettings) };
+    create_mtrr_lib(0).set_all_mtrrs(settings);
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_type_attribute_round_trip() {
+        for cache_type in [
+            MtrrMemoryCacheType::Uncacheable,
+            MtrrMemoryCacheType::WriteCombining,
+            MtrrMemoryCacheType::WriteThrough,
+            MtrrMemoryCacheType::WriteProtected,
+            MtrrMemoryCacheType::WriteBack,
+        ] {
+            let attributes = cache_type_to_attributes(cache_type);
+            assert_eq!(attributes_to_cache_type(attributes).unwrap(), cache_type);
+        }
+    }
+
+    #[test]
+    fn test_reserved_cache_types_have_no_attributes() {
+        assert_eq!(cache_type_to_attributes(MtrrMemoryCacheType::Reserved1), MemoryAttributes::empty());
+        assert_eq!(cache_type_to_attributes(MtrrMemoryCacheType::Invalid), MemoryAttributes::empty());
+    }
+
+    #[test]
+    fn test_non_cache_attributes_are_unsupported() {
+        assert_eq!(attributes_to_cache_type(MemoryAttributes::ReadOnly), Err(EfiError::Unsupported));
+        assert_eq!(
+            attributes_to_cache_type(MemoryAttributes::Uncacheable | MemoryAttributes::Writeback),
+            Err(EfiError::Unsupported)
+        );
+    }
+
+    #[test]
+    fn test_synchronize_pending_aps_consumes_the_request() {
+        // Without the MP Services protocol, there is no AP to start.
+        request_ap_synchronization();
+        assert_eq!(synchronize_pending_aps(), Ok(()));
+        assert!(!AP_SYNCHRONIZATION_PENDING.load(Ordering::SeqCst));
+        assert_eq!(synchronize_pending_aps(), Ok(()));
+    }
+
+    #[test]
+    fn test_register_null_mp_services_fails() {
+        assert_eq!(register_mp_services(ptr::null_mut()), Err(EfiError::InvalidParameter));
+    }
+}
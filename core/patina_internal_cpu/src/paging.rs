@@ -9,6 +9,9 @@
 //! SPDX-License-Identifier: Apache-2.0
 //!
 
+pub mod granule;
+pub mod large_pages;
+
 cfg_if::cfg_if! {
     if #[cfg(all(target_os = "uefi", target_arch = "x86_64"))] {
         mod x64;
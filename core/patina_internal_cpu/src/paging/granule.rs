@@ -0,0 +1,138 @@
+//! Page Granule Support
+//!
+//! UEFI defines memory in units of 4KB pages, but AArch64 platforms may run with a 16KB or 64KB translation granule.
+//! With a larger granule, the page table cannot express different attributes for the UEFI pages sharing a granule,
+//! so page table changes must be made on granule boundaries.
+//!
+//! The granule used by the core is selected at compile time with the `aarch64_granule_16k` and
+//! `aarch64_granule_64k` features, and can be detected at runtime from the translation control register with
+//! [detect_page_granule]. Other architectures always use 4KB pages.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use patina::base::{SIZE_4KB, SIZE_16KB, SIZE_64KB};
+
+/// The translation granule of the page table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageGranule {
+    /// 4KB pages, matching UEFI pages.
+    Size4KB,
+    /// 16KB pages (AArch64 only).
+    Size16KB,
+    /// 64KB pages (AArch64 only).
+    Size64KB,
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(all(target_arch = "aarch64", feature = "aarch64_granule_64k"))] {
+        /// The page granule selected at compile time.
+        pub const DEFAULT_PAGE_GRANULE: PageGranule = PageGranule::Size64KB;
+    } else if #[cfg(all(target_arch = "aarch64", feature = "aarch64_granule_16k"))] {
+        /// The page granule selected at compile time.
+        pub const DEFAULT_PAGE_GRANULE: PageGranule = PageGranule::Size16KB;
+    } else {
+        /// The page granule selected at compile time.
+        pub const DEFAULT_PAGE_GRANULE: PageGranule = PageGranule::Size4KB;
+    }
+}
+
+impl Default for PageGranule {
+    fn default() -> Self {
+        DEFAULT_PAGE_GRANULE
+    }
+}
+
+impl PageGranule {
+    /// Returns the size of a page in bytes.
+    pub const fn size(self) -> u64 {
+        match self {
+            Self::Size4KB => SIZE_4KB as u64,
+            Self::Size16KB => SIZE_16KB as u64,
+            Self::Size64KB => SIZE_64KB as u64,
+        }
+    }
+
+    /// Returns the granule encoded in the `TG0` field of the AArch64 `TCR_ELx` register, or `None` if the encoding is
+    /// reserved.
+    pub const fn from_tcr_tg0(tg0: u64) -> Option<Self> {
+        match tg0 & 0b11 {
+            0b00 => Some(Self::Size4KB),
+            0b01 => Some(Self::Size64KB),
+            0b10 => Some(Self::Size16KB),
+            _ => None,
+        }
+    }
+
+    /// Rounds `address` down to a page boundary.
+    pub const fn align_down(self, address: u64) -> u64 {
+        address & !(self.size() - 1)
+    }
+
+    /// Rounds `address` up to a page boundary.
+    pub const fn align_up(self, address: u64) -> u64 {
+        self.align_down(address + self.size() - 1)
+    }
+
+    /// Returns whether the range starts and ends on page boundaries.
+    pub const fn is_range_aligned(self, base_address: u64, length: u64) -> bool {
+        (base_address | length) & (self.size() - 1) == 0
+    }
+}
+
+/// Detects the granule of the active translation regime.
+///
+/// On AArch64 the granule is read from `TCR_ELx.TG0` of the current exception level. If the granule cannot be
+/// detected (e.g. on other architectures), [DEFAULT_PAGE_GRANULE] is returned.
+pub fn detect_page_granule() -> PageGranule {
+    cfg_if::cfg_if! {
+        if #[cfg(all(target_os = "uefi", target_arch = "aarch64"))] {
+            let current_el: u64;
+            let tcr: u64;
+            // SAFETY: Reading the current exception level and its translation control register has no side effects.
+            unsafe {
+                core::arch::asm!("mrs {}, CurrentEL", out(reg) current_el, options(nomem, nostack));
+                if current_el >> 2 == 2 {
+                    core::arch::asm!("mrs {}, tcr_el2", out(reg) tcr, options(nomem, nostack));
+                } else {
+                    core::arch::asm!("mrs {}, tcr_el1", out(reg) tcr, options(nomem, nostack));
+                }
+            }
+            PageGranule::from_tcr_tg0(tcr >> 14).unwrap_or(DEFAULT_PAGE_GRANULE)
+        } else {
+            DEFAULT_PAGE_GRANULE
+        }
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_granule_alignment() {
+        let granule = PageGranule::Size64KB;
+        assert_eq!(granule.size(), 0x10000);
+        assert_eq!(granule.align_down(0x12345), 0x10000);
+        assert_eq!(granule.align_up(0x12345), 0x20000);
+        assert_eq!(granule.align_up(0x20000), 0x20000);
+        assert!(granule.is_range_aligned(0x20000, 0x30000));
+        assert!(!granule.is_range_aligned(0x20000, 0x1000));
+        assert!(!granule.is_range_aligned(0x21000, 0x10000));
+        assert!(PageGranule::Size4KB.is_range_aligned(0x21000, 0x1000));
+        assert_eq!(PageGranule::Size16KB.align_up(0x1000), 0x4000);
+    }
+
+    #[test]
+    fn test_granule_from_tcr() {
+        assert_eq!(PageGranule::from_tcr_tg0(0b00), Some(PageGranule::Size4KB));
+        assert_eq!(PageGranule::from_tcr_tg0(0b01), Some(PageGranule::Size64KB));
+        assert_eq!(PageGranule::from_tcr_tg0(0b10), Some(PageGranule::Size16KB));
+        assert_eq!(PageGranule::from_tcr_tg0(0b11), None);
+        assert_eq!(detect_page_granule(), DEFAULT_PAGE_GRANULE);
+    }
+}
@@ -0,0 +1,550 @@
+//! Large Page Support
+//!
+//! The page table identity maps memory with the largest blocks a range allows: 1GB and 2MB pages with a 4KB granule,
+//! 32MB blocks with a 16KB granule and 512MB blocks with a 64KB granule, for the parts of a range aligned to them, and
+//! pages of the granule otherwise. Large conventional memory ranges then need few page table pages and TLB entries. A
+//! block is split lazily, when an attribute change or unmap only covers part of it.
+//!
+//! [LargePageTable] wraps the CPU page table to count the splits, and walks the translation tables to count the blocks
+//! mapped in [PagingStatistics]. It can also disable large pages, mapping every range with pages of the granule only,
+//! which makes attribute issues easier to debug.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use patina::base::{SIZE_1GB, SIZE_2MB, SIZE_4KB, SIZE_32MB, SIZE_512MB};
+use patina_paging::{MemoryAttributes, PageTable, PtResult, page_allocator::PageAllocator};
+use r_efi::efi;
+
+use super::{create_cpu_paging, granule::PageGranule};
+
+const PAGE_1GB: u64 = SIZE_1GB as u64;
+const PAGE_2MB: u64 = SIZE_2MB as u64;
+const PAGE_4KB: u64 = SIZE_4KB as u64;
+const BLOCK_32MB: u64 = SIZE_32MB as u64;
+const BLOCK_512MB: u64 = SIZE_512MB as u64;
+
+/// The number of address bits translated by the page table.
+const ADDRESS_BITS: u32 = 48;
+
+/// The format of the translation tables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableFormat {
+    /// x64 4-level paging, with 1GB and 2MB pages.
+    X64,
+    /// AArch64 VMSAv8-64 translation tables with the given granule.
+    AArch64(PageGranule),
+}
+
+impl TableFormat {
+    /// Returns the format of the translation tables of the current architecture, with the given granule on AArch64.
+    pub fn current(granule: PageGranule) -> Self {
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "x86_64")] {
+                let _ = granule;
+                Self::X64
+            } else {
+                Self::AArch64(granule)
+            }
+        }
+    }
+
+    /// Returns the size of a page in bytes.
+    pub const fn page_size(self) -> u64 {
+        match self {
+            Self::X64 => PAGE_4KB,
+            Self::AArch64(granule) => granule.size(),
+        }
+    }
+
+    /// Returns the sizes of the blocks larger than a page, largest first.
+    pub const fn block_sizes(self) -> &'static [u64] {
+        match self {
+            Self::X64 | Self::AArch64(PageGranule::Size4KB) => &[PAGE_1GB, PAGE_2MB],
+            Self::AArch64(PageGranule::Size16KB) => &[BLOCK_32MB],
+            Self::AArch64(PageGranule::Size64KB) => &[BLOCK_512MB],
+        }
+    }
+
+    /// Returns the lowest address bit translated by each level, from the root level down to the page level.
+    const fn level_shifts(self) -> &'static [u32] {
+        match self {
+            Self::X64 | Self::AArch64(PageGranule::Size4KB) => &[39, 30, 21, 12],
+            Self::AArch64(PageGranule::Size16KB) => &[47, 36, 25, 14],
+            Self::AArch64(PageGranule::Size64KB) => &[42, 29, 16],
+        }
+    }
+
+    /// Returns the number of entries of the tables of `level`.
+    fn entry_count(self, level: usize) -> u64 {
+        let shifts = self.level_shifts();
+        let upper = if level == 0 { ADDRESS_BITS } else { shifts[level - 1] };
+        1 << (upper - shifts[level])
+    }
+
+    /// Returns the size mapped by `entry` of `level` if it maps memory, or `None` if it is invalid or points to a
+    /// next level table.
+    fn leaf_size(self, entry: u64, level: usize) -> Option<u64> {
+        let size = 1 << self.level_shifts()[level];
+        let is_last_level = level + 1 == self.level_shifts().len();
+        let is_block = match self {
+            Self::X64 => entry & (1 << 7) != 0,
+            Self::AArch64(_) => entry & 0b10 == 0,
+        };
+        (is_last_level || (is_block && self.block_sizes().contains(&size))).then_some(size)
+    }
+
+    /// Returns the address of the next level table `entry` points to.
+    fn table_address(self, entry: u64) -> u64 {
+        match self {
+            Self::X64 => entry & 0x000F_FFFF_FFFF_F000,
+            Self::AArch64(granule) => entry & 0x0000_FFFF_FFFF_FFFF & !(granule.size() - 1),
+        }
+    }
+}
+
+/// Reads the entry at `index` of the translation table at `table`.
+fn read_entry(table: u64, index: u64) -> u64 {
+    // SAFETY: The translation tables are identity mapped, and `index` is within the table.
+    unsafe { core::ptr::read_volatile((table as *const u64).add(index as usize)) }
+}
+
+/// Statistics on the blocks of one size.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BlockStatistics {
+    /// The size of the blocks in bytes.
+    pub size: u64,
+    /// The number of blocks mapped by the page table.
+    pub mapped: u64,
+    /// The number of blocks split into smaller blocks or pages.
+    pub split: u64,
+}
+
+/// Statistics on the large pages of the page table.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PagingStatistics {
+    /// The size of a page in bytes.
+    pub page_size: u64,
+    /// The statistics of each block size larger than a page, largest first.
+    pub blocks: Vec<BlockStatistics>,
+    /// Whether large pages are disabled, all memory being mapped with pages.
+    pub large_pages_disabled: bool,
+}
+
+/// Formats a size in bytes in the largest unit dividing it.
+struct Size(u64);
+
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            size if size >= PAGE_1GB && size.is_multiple_of(PAGE_1GB) => write!(f, "{}GB", size / PAGE_1GB),
+            size if size >= 0x10_0000 && size.is_multiple_of(0x10_0000) => write!(f, "{}MB", size / 0x10_0000),
+            size => write!(f, "{}KB", size / 0x400),
+        }
+    }
+}
+
+impl fmt::Display for PagingStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.large_pages_disabled {
+            writeln!(f, "Large pages disabled, all memory is mapped with {} pages", Size(self.page_size))?;
+        }
+        for block in &self.blocks {
+            writeln!(f, "{} blocks: {} ({} split)", Size(block.size), block.mapped, block.split)?;
+        }
+        Ok(())
+    }
+}
+
+/// A page allocator recording the address of the root table allocated by the page table.
+struct RootRecordingAllocator<A: PageAllocator> {
+    allocator: A,
+    root: Arc<AtomicU64>,
+}
+
+impl<A: PageAllocator> PageAllocator for RootRecordingAllocator<A> {
+    fn allocate_page(&mut self, align: u64, size: u64, is_root: bool) -> PtResult<u64> {
+        let page = self.allocator.allocate_page(align, size, is_root)?;
+        if is_root {
+            self.root.store(page, Ordering::Relaxed);
+        }
+        Ok(page)
+    }
+}
+
+/// A page table tracking the large pages used to map memory.
+pub struct LargePageTable {
+    paging: Box<dyn PageTable>,
+    format: TableFormat,
+    // The address of the root translation table, or 0 if it is not allocated yet.
+    root: Arc<AtomicU64>,
+    large_pages_disabled: bool,
+    // The number of splits of each block size of the format.
+    splits: Vec<u64>,
+}
+
+impl LargePageTable {
+    /// Creates the CPU page table, using large pages unless `large_pages_disabled` is set.
+    pub fn create<A: PageAllocator + 'static>(
+        page_allocator: A,
+        format: TableFormat,
+        large_pages_disabled: bool,
+    ) -> Result<Self, efi::Status> {
+        let root = Arc::new(AtomicU64::new(0));
+        let paging = create_cpu_paging(RootRecordingAllocator { allocator: page_allocator, root: root.clone() })?;
+        Ok(Self::new(paging, format, root, large_pages_disabled))
+    }
+
+    /// Creates a page table on top of `paging`, whose root translation table address is stored in `root` once
+    /// allocated.
+    pub fn new(
+        paging: Box<dyn PageTable>,
+        format: TableFormat,
+        root: Arc<AtomicU64>,
+        large_pages_disabled: bool,
+    ) -> Self {
+        Self { paging, format, root, large_pages_disabled, splits: alloc::vec![0; format.block_sizes().len()] }
+    }
+
+    /// Returns the statistics on the large pages of the page table, counting the blocks mapped by its tables.
+    pub fn statistics(&self) -> PagingStatistics {
+        let mut mapped = alloc::vec![0; self.format.block_sizes().len()];
+        let root = self.root.load(Ordering::Relaxed);
+        if root != 0 {
+            self.count_blocks(root, 0, &mut mapped);
+        }
+        PagingStatistics {
+            page_size: self.format.page_size(),
+            blocks: self
+                .format
+                .block_sizes()
+                .iter()
+                .zip(mapped)
+                .zip(&self.splits)
+                .map(|((size, mapped), split)| BlockStatistics { size: *size, mapped, split: *split })
+                .collect(),
+            large_pages_disabled: self.large_pages_disabled,
+        }
+    }
+
+    /// Counts the blocks mapped by the table at `table` of `level` and its next level tables.
+    fn count_blocks(&self, table: u64, level: usize, mapped: &mut [u64]) {
+        for index in 0..self.format.entry_count(level) {
+            let entry = read_entry(table, index);
+            if entry & 1 == 0 {
+                continue;
+            }
+            match self.format.leaf_size(entry, level) {
+                Some(size) => {
+                    if let Some(block) = self.format.block_sizes().iter().position(|block| *block == size) {
+                        mapped[block] += 1;
+                    }
+                }
+                None => self.count_blocks(self.format.table_address(entry), level + 1, mapped),
+            }
+        }
+    }
+
+    /// Walks the tables to return the size of the block or page mapping `address`, or `None` if it is not mapped.
+    fn mapping_size(&self, address: u64) -> Option<u64> {
+        let mut table = self.root.load(Ordering::Relaxed);
+        if table == 0 {
+            return None;
+        }
+        for (level, shift) in self.format.level_shifts().iter().enumerate() {
+            let entry = read_entry(table, (address >> shift) & (self.format.entry_count(level) - 1));
+            if entry & 1 == 0 {
+                return None;
+            }
+            match self.format.leaf_size(entry, level) {
+                Some(size) => return Some(size),
+                None => table = self.format.table_address(entry),
+            }
+        }
+        None
+    }
+
+    /// Returns the blocks the range only partially covers, as the index of their size and an address in each block
+    /// outside of the range.
+    fn partially_covered_blocks(&self, address: u64, size: u64) -> Vec<(usize, u64)> {
+        let end = address + size;
+        let mut blocks: Vec<(usize, u64)> = Vec::new();
+        // The first and last addresses of the range, and the boundaries they are next to.
+        for (inside, boundary) in [(address, address), (end - 1, end)] {
+            let Some(mapped) = self.mapping_size(inside) else {
+                continue;
+            };
+            for (index, block) in self.format.block_sizes().iter().enumerate() {
+                if *block > mapped || boundary.is_multiple_of(*block) {
+                    continue;
+                }
+                let probe = if boundary == end { end } else { address / block * block };
+                if !blocks.iter().any(|(i, p)| *i == index && p / block == probe / block) {
+                    blocks.push((index, probe));
+                }
+            }
+        }
+        blocks
+    }
+
+    /// Counts the splits of the blocks the range partially covered before a change, from the sizes mapping them now.
+    fn count_splits(&mut self, blocks: Vec<(usize, u64)>) {
+        for (index, probe) in blocks {
+            if self.mapping_size(probe).is_some_and(|size| size < self.format.block_sizes()[index]) {
+                self.splits[index] += 1;
+            }
+        }
+    }
+
+    /// Maps the range with pages, in chunks that never hold a whole aligned block.
+    fn map_pages(&mut self, address: u64, size: u64, attributes: MemoryAttributes) -> PtResult<()> {
+        let page = self.format.page_size();
+        let block = *self.format.block_sizes().last().unwrap_or(&page);
+        let end = address + size;
+        let mut base = address;
+        while base < end {
+            // Chunks end a page past a block boundary, so chunks starting on a block boundary are a single page.
+            let chunk_end = match base.checked_sub(page) {
+                Some(offset) => offset / block * block + block + page,
+                None => page,
+            }
+            .min(end);
+            self.paging.map_memory_region(base, chunk_end - base, attributes)?;
+            base = chunk_end;
+        }
+        Ok(())
+    }
+}
+
+impl PageTable for LargePageTable {
+    fn map_memory_region(&mut self, address: u64, size: u64, attributes: MemoryAttributes) -> PtResult<()> {
+        if self.large_pages_disabled {
+            return self.map_pages(address, size, attributes);
+        }
+        let blocks = self.partially_covered_blocks(address, size);
+        self.paging.map_memory_region(address, size, attributes)?;
+        self.count_splits(blocks);
+        Ok(())
+    }
+
+    fn unmap_memory_region(&mut self, address: u64, size: u64) -> PtResult<()> {
+        let blocks = self.partially_covered_blocks(address, size);
+        self.paging.unmap_memory_region(address, size)?;
+        self.count_splits(blocks);
+        Ok(())
+    }
+
+    fn install_page_table(&mut self) -> PtResult<()> {
+        self.paging.install_page_table()
+    }
+
+    fn query_memory_region(&self, address: u64, size: u64) -> PtResult<MemoryAttributes> {
+        self.paging.query_memory_region(address, size)
+    }
+
+    fn dump_page_tables(&self, address: u64, size: u64) -> PtResult<()> {
+        self.paging.dump_page_tables(address, size)
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::{
+        alloc::{Layout, alloc_zeroed},
+        sync::Mutex,
+    };
+
+    const PRESENT: u64 = 1;
+    const X64_PAGE_SIZE: u64 = 1 << 7;
+    const AARCH64_TABLE: u64 = 0b10;
+    const SIZE_64KB: u64 = 0x10000;
+
+    /// Allocates a zeroed translation table of the format.
+    fn table(format: TableFormat) -> u64 {
+        let size = format.page_size() as usize;
+        // SAFETY: The layout has a non-zero size. The table is leaked for the duration of the test.
+        unsafe { alloc_zeroed(Layout::from_size_align(size, size).unwrap()) as u64 }
+    }
+
+    fn set_entry(table: u64, index: u64, entry: u64) {
+        // SAFETY: The tables are allocated by [table] and `index` is within the table.
+        unsafe { (table as *mut u64).add(index as usize).write(entry) }
+    }
+
+    type Change = Box<dyn FnOnce() + Send>;
+
+    /// Page table recording the ranges mapped, and applying a scripted change to the translation tables on each call.
+    struct ScriptedPageTable {
+        calls: Arc<Mutex<Vec<(u64, u64)>>>,
+        changes: Arc<Mutex<Vec<Change>>>,
+    }
+
+    impl ScriptedPageTable {
+        fn apply_change(&self) {
+            let mut changes = self.changes.lock().unwrap();
+            if !changes.is_empty() {
+                changes.remove(0)();
+            }
+        }
+    }
+
+    impl PageTable for ScriptedPageTable {
+        fn map_memory_region(&mut self, address: u64, size: u64, _attributes: MemoryAttributes) -> PtResult<()> {
+            self.calls.lock().unwrap().push((address, size));
+            self.apply_change();
+            Ok(())
+        }
+
+        fn unmap_memory_region(&mut self, _address: u64, _size: u64) -> PtResult<()> {
+            self.apply_change();
+            Ok(())
+        }
+
+        fn install_page_table(&mut self) -> PtResult<()> {
+            Ok(())
+        }
+
+        fn query_memory_region(&self, _address: u64, _size: u64) -> PtResult<MemoryAttributes> {
+            Ok(MemoryAttributes::empty())
+        }
+
+        fn dump_page_tables(&self, _address: u64, _size: u64) -> PtResult<()> {
+            Ok(())
+        }
+    }
+
+    struct TestPageTable {
+        paging: LargePageTable,
+        calls: Arc<Mutex<Vec<(u64, u64)>>>,
+        changes: Arc<Mutex<Vec<Change>>>,
+    }
+
+    fn page_table(format: TableFormat, root: u64, large_pages_disabled: bool) -> TestPageTable {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let scripted = ScriptedPageTable { calls: calls.clone(), changes: changes.clone() };
+        let paging =
+            LargePageTable::new(Box::new(scripted), format, Arc::new(AtomicU64::new(root)), large_pages_disabled);
+        TestPageTable { paging, calls, changes }
+    }
+
+    fn counts(statistics: &PagingStatistics) -> Vec<(u64, u64)> {
+        statistics.blocks.iter().map(|block| (block.mapped, block.split)).collect()
+    }
+
+    #[test]
+    fn large_pages_should_be_counted_from_the_tables_and_split_lazily() {
+        let format = TableFormat::X64;
+        let root = table(format);
+        let pdpt = table(format);
+        set_entry(root, 0, pdpt | PRESENT);
+
+        // 2MB + 1GB + 2MB + 4KB, starting 2MB below a 1GB boundary.
+        let low_pd = table(format);
+        set_entry(pdpt, 0, low_pd | PRESENT);
+        set_entry(low_pd, 511, (PAGE_1GB - PAGE_2MB) | X64_PAGE_SIZE | PRESENT);
+        set_entry(pdpt, 1, PAGE_1GB | X64_PAGE_SIZE | PRESENT);
+        let high_pd = table(format);
+        let high_pt = table(format);
+        set_entry(pdpt, 2, high_pd | PRESENT);
+        set_entry(high_pd, 0, (2 * PAGE_1GB) | X64_PAGE_SIZE | PRESENT);
+        set_entry(high_pd, 1, high_pt | PRESENT);
+        set_entry(high_pt, 0, (2 * PAGE_1GB + PAGE_2MB) | PRESENT);
+
+        let mut test = page_table(format, root, false);
+        assert_eq!(counts(&test.paging.statistics()), [(1, 0), (2, 0)]);
+
+        // Changing a 4KB page in the 1GB page splits it, and the 2MB page holding the 4KB page.
+        test.changes.lock().unwrap().push(Box::new(move || {
+            let pd = table(format);
+            let pt = table(format);
+            set_entry(pd, 0, pt | PRESENT);
+            for index in 0..512 {
+                set_entry(pt, index, (PAGE_1GB + index * PAGE_4KB) | PRESENT);
+            }
+            for index in 1..512 {
+                set_entry(pd, index, (PAGE_1GB + index * PAGE_2MB) | X64_PAGE_SIZE | PRESENT);
+            }
+            set_entry(pdpt, 1, pd | PRESENT);
+        }));
+        test.paging.map_memory_region(PAGE_1GB + PAGE_4KB, PAGE_4KB, MemoryAttributes::ReadOnly).unwrap();
+        assert_eq!(*test.calls.lock().unwrap(), [(PAGE_1GB + PAGE_4KB, PAGE_4KB)]);
+        assert_eq!(counts(&test.paging.statistics()), [(0, 1), (2 + 511, 1)]);
+
+        // Replacing or unmapping whole large pages does not split them.
+        test.paging.map_memory_region(PAGE_1GB + PAGE_2MB, 2 * PAGE_2MB, MemoryAttributes::ExecuteProtect).unwrap();
+        test.changes.lock().unwrap().push(Box::new(move || set_entry(low_pd, 511, 0)));
+        test.paging.unmap_memory_region(PAGE_1GB - PAGE_2MB, PAGE_2MB).unwrap();
+        let statistics = test.paging.statistics();
+        assert_eq!(counts(&statistics), [(0, 1), (512, 1)]);
+        assert!(!statistics.large_pages_disabled);
+    }
+
+    #[test]
+    fn large_pages_should_follow_the_64kb_granule() {
+        let format = TableFormat::AArch64(PageGranule::Size64KB);
+        assert_eq!(format.block_sizes(), [BLOCK_512MB]);
+
+        let root = table(format);
+        let level2 = table(format);
+        set_entry(root, 0, level2 | AARCH64_TABLE | PRESENT);
+        set_entry(level2, 0, PRESENT);
+        set_entry(level2, 1, BLOCK_512MB | PRESENT);
+
+        let mut test = page_table(format, root, false);
+        assert_eq!(counts(&test.paging.statistics()), [(2, 0)]);
+
+        // Unmapping a 64KB page of a 512MB block splits it.
+        test.changes.lock().unwrap().push(Box::new(move || {
+            let level3 = table(format);
+            for index in 1..8192 {
+                set_entry(level3, index, (BLOCK_512MB + index * SIZE_64KB) | AARCH64_TABLE | PRESENT);
+            }
+            set_entry(level2, 1, level3 | AARCH64_TABLE | PRESENT);
+        }));
+        test.paging.unmap_memory_region(BLOCK_512MB, 0x10000).unwrap();
+        assert_eq!(counts(&test.paging.statistics()), [(1, 1)]);
+        assert_eq!(test.paging.statistics().to_string(), "512MB blocks: 1 (1 split)\n");
+    }
+
+    #[test]
+    fn disabled_large_pages_should_never_map_a_whole_block() {
+        let mut test = page_table(TableFormat::X64, 0, true);
+
+        test.paging.map_memory_region(0, 2 * PAGE_2MB + PAGE_4KB, MemoryAttributes::Writeback).unwrap();
+        assert_eq!(*test.calls.lock().unwrap(), [(0, PAGE_4KB), (PAGE_4KB, PAGE_2MB), (PAGE_2MB + PAGE_4KB, PAGE_2MB)]);
+
+        test.calls.lock().unwrap().clear();
+        test.paging.map_memory_region(PAGE_2MB - PAGE_4KB, 3 * PAGE_4KB, MemoryAttributes::Writeback).unwrap();
+        assert_eq!(*test.calls.lock().unwrap(), [(PAGE_2MB - PAGE_4KB, 2 * PAGE_4KB), (PAGE_2MB + PAGE_4KB, PAGE_4KB)]);
+
+        let statistics = test.paging.statistics();
+        assert!(statistics.large_pages_disabled);
+        assert_eq!(counts(&statistics), [(0, 0), (0, 0)]);
+
+        // With a 64KB granule, chunks never hold a whole 512MB block.
+        let mut test = page_table(TableFormat::AArch64(PageGranule::Size64KB), 0, true);
+        test.paging.map_memory_region(0, 2 * BLOCK_512MB + SIZE_64KB, MemoryAttributes::Writeback).unwrap();
+        assert_eq!(
+            *test.calls.lock().unwrap(),
+            [(0, SIZE_64KB), (SIZE_64KB, BLOCK_512MB), (BLOCK_512MB + SIZE_64KB, BLOCK_512MB)]
+        );
+        assert_eq!(
+            test.paging.statistics().to_string(),
+            "Large pages disabled, all memory is mapped with 64KB pages\n512MB blocks: 0 (0 split)\n"
+        );
+    }
+}
@@ -8,9 +8,12 @@
 //!
 //! SPDX-License-Identifier: Apache-2.0
 //!
+use crate::mtrr::{
+    attributes_to_cache_type, cache_type_to_attributes, mtrr_err_to_efi_error, request_ap_synchronization,
+};
 use alloc::boxed::Box;
 use patina::error::EfiError;
-use patina_mtrr::{Mtrr, create_mtrr_lib, error::MtrrError, structs::MtrrMemoryCacheType};
+use patina_mtrr::{Mtrr, create_mtrr_lib};
 use patina_paging::{
     MemoryAttributes, PageTable, PagingType, PtError, PtResult, page_allocator::PageAllocator, x64::X64PageTable,
 };
@@ -70,14 +73,7 @@ where
     fn query_memory_region(&self, address: u64, size: u64) -> Result<MemoryAttributes, PtError> {
         self.paging.query_memory_region(address, size).map(|attr|
         // We need to add the cache attributes to the memory attributes
-        attr | match self.mtrr.get_memory_attribute(address) {
-            MtrrMemoryCacheType::Uncacheable => MemoryAttributes::Uncacheable,
-            MtrrMemoryCacheType::WriteCombining => MemoryAttributes::WriteCombining,
-            MtrrMemoryCacheType::WriteThrough => MemoryAttributes::WriteThrough,
-            MtrrMemoryCacheType::WriteProtected => MemoryAttributes::WriteProtect,
-            MtrrMemoryCacheType::WriteBack => MemoryAttributes::Writeback,
-            _ => MemoryAttributes::empty(),
-        })
+        attr | cache_type_to_attributes(self.mtrr.get_memory_attribute(address)))
     }
 
     fn dump_page_tables(&self, address: u64, size: u64) -> PtResult<()> {
@@ -96,25 +92,14 @@ fn apply_caching_attributes<M: Mtrr>(
             return Err(EfiError::Unsupported);
         }
 
-        let cache_type = match cache_attributes {
-            MemoryAttributes::Uncacheable => MtrrMemoryCacheType::Uncacheable,
-            MemoryAttributes::WriteCombining => MtrrMemoryCacheType::WriteCombining,
-            MemoryAttributes::WriteThrough => MtrrMemoryCacheType::WriteThrough,
-            MemoryAttributes::WriteProtect => MtrrMemoryCacheType::WriteProtected,
-            MemoryAttributes::Writeback => MtrrMemoryCacheType::WriteBack,
-            _ => return Err(EfiError::Unsupported),
-        };
+        let cache_type = attributes_to_cache_type(cache_attributes)?;
 
         let curr_attribute = mtrr.get_memory_attribute(base_address);
         if curr_attribute != cache_type {
             // cache attributes are not already set
-            match mtrr.set_memory_attribute(base_address, length, cache_type) {
-                Ok(_) => {
-                    // now we need to program the APs with the update, if they are up
-                    return Ok(());
-                }
-                Err(err) => return Err(mtrr_err_to_efi_status(err)),
-            }
+            mtrr.set_memory_attribute(base_address, length, cache_type).map_err(mtrr_err_to_efi_error)?;
+            // the APs need the update too, but are only started once the page table lock is released
+            request_ap_synchronization();
         }
     }
 
@@ -128,19 +113,6 @@ pub fn create_cpu_x64_paging<A: PageAllocator + 'static>(page_allocator: A) -> R
     }))
 }
 
-fn mtrr_err_to_efi_status(err: MtrrError) -> EfiError {
-    match err {
-        MtrrError::MtrrNotSupported => EfiError::Unsupported,
-        MtrrError::VariableRangeMtrrExhausted => EfiError::OutOfResources,
-        MtrrError::FixedRangeMtrrBaseAddressNotAligned => EfiError::InvalidParameter,
-        MtrrError::FixedRangeMtrrLengthNotAligned => EfiError::InvalidParameter,
-        MtrrError::InvalidParameter => EfiError::InvalidParameter,
-        MtrrError::BufferTooSmall => EfiError::BufferTooSmall,
-        MtrrError::OutOfResources => EfiError::OutOfResources,
-        MtrrError::AlreadyStarted => EfiError::AlreadyStarted,
-    }
-}
-
 #[cfg(test)]
 #[coverage(off)]
 mod tests {
@@ -148,7 +120,7 @@ mod tests {
     use mockall::mock;
     use patina_mtrr::{
         error::MtrrResult,
-        structs::{MtrrMemoryRange, MtrrSettings},
+        structs::{MtrrMemoryCacheType, MtrrMemoryRange, MtrrSettings},
     };
 
     mock! {
@@ -106,6 +106,14 @@ impl<'a> From<&'a [u8]> for Opcode {
 }
 
 impl Opcode {
+    /// Returns the GUID of a BEFORE, AFTER or PUSH opcode.
+    pub fn guid(&self) -> Option<efi::Guid> {
+        match self {
+            Opcode::Before(uuid) | Opcode::After(uuid) | Opcode::Push(uuid, _) => guid_from_uuid(uuid),
+            _ => None,
+        }
+    }
+
     fn byte_size(&self) -> usize {
         match *self {
             Opcode::Before(_) | Opcode::After(_) | Opcode::Push(_, _) => 1 + GUID_SIZE,
@@ -288,6 +296,11 @@ impl Depex {
         }
     }
 
+    /// Returns the opcodes of the expression, in postfix order.
+    pub fn opcodes(&self) -> &[Opcode] {
+        &self.expression
+    }
+
     /// indicates that this is a "schedule on request" depex.
     pub fn is_sor(&self) -> bool {
         self.expression.first() == Some(&Opcode::Sor)
@@ -731,4 +744,15 @@ mod tests {
         let mut depex = Depex::from(opcodes.as_slice());
         depex.eval(&[]);
     }
+
+    #[test]
+    fn opcodes_should_expose_the_expression_and_guids() {
+        let uuid = Uuid::from_str("76b6bdfa-2acd-4462-9e3f-cb58c969d937").unwrap();
+        let opcodes = [Opcode::Push(uuid, false), Opcode::Not, Opcode::End];
+        let depex = Depex::from(opcodes.as_slice());
+        assert_eq!(depex.opcodes(), opcodes);
+        assert_eq!(depex.opcodes()[0].guid(), guid_from_uuid(&uuid));
+        assert_eq!(Opcode::After(uuid).guid(), guid_from_uuid(&uuid));
+        assert_eq!(Opcode::Not.guid(), None);
+    }
 }
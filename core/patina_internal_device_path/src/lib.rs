@@ -20,6 +20,10 @@ use r_efi::protocols::device_path::{End, Hardware, Media};
 
 use r_efi::efi;
 
+mod text;
+
+pub use text::DevicePathText;
+
 /// Returns the count of nodes and size (in bytes) of the given device path.
 ///
 /// count and size outputs both include the terminating end node.
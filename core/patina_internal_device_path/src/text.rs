@@ -0,0 +1,294 @@
+//! Device Path to Text
+//!
+//! Formats device paths in the text representation of the UEFI specification (UEFI 2.11 section 10.6), e.g.
+//! `Fv(7CB8BDC9-F8EB-4F34-AAEA-3EE4AF6516A1)/FvFile(80CF7257-87AB-47F9-A3FE-D50B76D89541)`, so that diagnostics can
+//! name the image or device a message is about. The nodes the core deals with (hardware, ACPI and firmware volume
+//! media nodes) have their specific text form; other nodes use the generic `Path(type,subtype,data)` form.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use core::fmt::{self, Display, Formatter, Write};
+
+use r_efi::{
+    efi,
+    protocols::device_path::{End, Hardware, Media, TYPE_ACPI, TYPE_END, TYPE_HARDWARE, TYPE_MEDIA},
+};
+
+use crate::{DevicePathNode, DevicePathWalker};
+
+// Subtype of the ACPI device path node.
+const ACPI_SUBTYPE_ACPI: u8 = 0x01;
+
+// Compressed EISA ID of the `PNP` vendor prefix of ACPI `_HID` values.
+const PNP_EISA_ID: u32 = 0x41D0;
+const PCI_ROOT_PNP_ID: u32 = 0x0A03;
+const PCIE_ROOT_PNP_ID: u32 = 0x0A08;
+
+/// Displays a device path in the text representation of the UEFI specification.
+///
+/// A null device path is displayed as `<no device path>`.
+///
+/// ## Examples
+///
+/// ```
+/// use patina_internal_device_path::DevicePathText;
+/// use r_efi::efi;
+///
+/// let device_path_bytes = [
+///   efi::protocols::device_path::TYPE_HARDWARE,
+///   efi::protocols::device_path::Hardware::SUBTYPE_PCI,
+///   0x6, //length[0]
+///   0x0, //length[1]
+///   0x2, //func
+///   0x1F, //device
+///   efi::protocols::device_path::TYPE_END,
+///   efi::protocols::device_path::End::SUBTYPE_ENTIRE,
+///   0x4, //length[0]
+///   0x0, //length[1]
+/// ];
+/// let device_path_ptr = device_path_bytes.as_ptr() as *const efi::protocols::device_path::Protocol;
+/// let text = unsafe { DevicePathText::new(device_path_ptr) };
+/// assert_eq!(format!("{text}"), "Pci(0x1F,0x2)");
+/// ```
+///
+#[derive(Debug, Clone, Copy)]
+pub struct DevicePathText {
+    device_path: *const efi::protocols::device_path::Protocol,
+}
+
+impl DevicePathText {
+    /// Creates a displayable device path for the given raw device path pointer.
+    ///
+    /// ## Safety
+    ///
+    /// Caller must ensure that the raw pointer is null or points to a valid device path structure, including a proper
+    /// device path end node, that outlives the returned value.
+    pub unsafe fn new(device_path: *const efi::protocols::device_path::Protocol) -> Self {
+        Self { device_path }
+    }
+}
+
+impl Display for DevicePathText {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.device_path.is_null() {
+            return f.write_str("<no device path>");
+        }
+
+        let mut separator = None;
+        // Safety: the caller of `new` guarantees that the device path is valid.
+        for node in unsafe { DevicePathWalker::new(self.device_path) } {
+            let header = node.header();
+            if header.r#type == TYPE_END {
+                if header.sub_type == End::SUBTYPE_ENTIRE {
+                    break;
+                }
+                separator = Some(',');
+                continue;
+            }
+            if let Some(separator) = separator {
+                f.write_char(separator)?;
+            }
+            write_node(f, &node)?;
+            separator = Some('/');
+        }
+        Ok(())
+    }
+}
+
+/// Writes the text representation of a device path node.
+fn write_node(f: &mut Formatter<'_>, node: &DevicePathNode) -> fmt::Result {
+    let header = node.header();
+    let data = node.data();
+    match (header.r#type, header.sub_type) {
+        (TYPE_HARDWARE, Hardware::SUBTYPE_PCI) if data.len() == 2 => write!(f, "Pci({:#X},{:#X})", data[1], data[0]),
+        (TYPE_HARDWARE, Hardware::SUBTYPE_MMAP) if data.len() == 20 => {
+            write!(f, "MemoryMapped({:#X},{:#X},{:#X})", read_u32(data, 0), read_u64(data, 4), read_u64(data, 12))
+        }
+        (TYPE_HARDWARE, Hardware::SUBTYPE_VENDOR) if data.len() >= 16 => {
+            f.write_str("VenHw(")?;
+            write_guid(f, data)?;
+            write_vendor_data(f, &data[16..])?;
+            f.write_char(')')
+        }
+        (TYPE_HARDWARE, Hardware::SUBTYPE_CONTROLLER) if data.len() == 4 => write!(f, "Ctrl({:#X})", read_u32(data, 0)),
+        (TYPE_ACPI, ACPI_SUBTYPE_ACPI) if data.len() == 8 => {
+            let (hid, uid) = (read_u32(data, 0), read_u32(data, 4));
+            match (hid & 0xFFFF, hid >> 16) {
+                (PNP_EISA_ID, PCI_ROOT_PNP_ID) => write!(f, "PciRoot({uid:#X})"),
+                (PNP_EISA_ID, PCIE_ROOT_PNP_ID) => write!(f, "PcieRoot({uid:#X})"),
+                _ => {
+                    f.write_str("Acpi(")?;
+                    write_eisa_id(f, hid)?;
+                    write!(f, ",{uid:#X})")
+                }
+            }
+        }
+        (TYPE_MEDIA, Media::SUBTYPE_VENDOR) if data.len() >= 16 => {
+            f.write_str("VenMedia(")?;
+            write_guid(f, data)?;
+            write_vendor_data(f, &data[16..])?;
+            f.write_char(')')
+        }
+        (TYPE_MEDIA, Media::SUBTYPE_FILE_PATH) => {
+            let chars = data.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).take_while(|&c| c != 0);
+            char::decode_utf16(chars).try_for_each(|c| f.write_char(c.unwrap_or(char::REPLACEMENT_CHARACTER)))
+        }
+        (TYPE_MEDIA, Media::SUBTYPE_PIWG_FIRMWARE_FILE) if data.len() == 16 => {
+            f.write_str("FvFile(")?;
+            write_guid(f, data)?;
+            f.write_char(')')
+        }
+        (TYPE_MEDIA, Media::SUBTYPE_PIWG_FIRMWARE_VOLUME) if data.len() == 16 => {
+            f.write_str("Fv(")?;
+            write_guid(f, data)?;
+            f.write_char(')')
+        }
+        (TYPE_MEDIA, Media::SUBTYPE_RELATIVE_OFFSET_RANGE) if data.len() == 20 => {
+            write!(f, "Offset({:#X},{:#X})", read_u64(data, 4), read_u64(data, 12))
+        }
+        (r#type, sub_type) => {
+            write!(f, "Path({type},{sub_type}")?;
+            if !data.is_empty() {
+                f.write_char(',')?;
+                data.iter().try_for_each(|byte| write!(f, "{byte:02X}"))?;
+            }
+            f.write_char(')')
+        }
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().expect("slice has the size of a u32"))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().expect("slice has the size of a u64"))
+}
+
+/// Writes the GUID in the first 16 bytes of `data` in registry format.
+fn write_guid(f: &mut Formatter<'_>, data: &[u8]) -> fmt::Result {
+    write!(
+        f,
+        "{:08X}-{:04X}-{:04X}-",
+        read_u32(data, 0),
+        u16::from_le_bytes([data[4], data[5]]),
+        u16::from_le_bytes([data[6], data[7]])
+    )?;
+    data[8..10].iter().try_for_each(|byte| write!(f, "{byte:02X}"))?;
+    f.write_char('-')?;
+    data[10..16].iter().try_for_each(|byte| write!(f, "{byte:02X}"))
+}
+
+/// Writes the optional vendor defined data of a vendor node.
+fn write_vendor_data(f: &mut Formatter<'_>, data: &[u8]) -> fmt::Result {
+    if !data.is_empty() {
+        f.write_char(',')?;
+        data.iter().try_for_each(|byte| write!(f, "{byte:02X}"))?;
+    }
+    Ok(())
+}
+
+/// Writes a compressed EISA ID, e.g. `PNP0501`.
+fn write_eisa_id(f: &mut Formatter<'_>, eisa_id: u32) -> fmt::Result {
+    for shift in [10, 5, 0] {
+        f.write_char((b'@' + ((eisa_id >> shift) & 0x1F) as u8) as char)?;
+    }
+    write!(f, "{:04X}", eisa_id >> 16)
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use alloc::{format, vec, vec::Vec};
+
+    use r_efi::protocols::device_path::TYPE_MESSAGING;
+
+    use super::*;
+
+    fn node(r#type: u8, sub_type: u8, data: &[u8]) -> Vec<u8> {
+        let mut node = vec![r#type, sub_type];
+        node.extend(((data.len() + 4) as u16).to_le_bytes());
+        node.extend(data);
+        node
+    }
+
+    fn text(nodes: &[Vec<u8>]) -> alloc::string::String {
+        let mut device_path = nodes.concat();
+        device_path.extend(node(TYPE_END, End::SUBTYPE_ENTIRE, &[]));
+        let text = unsafe { DevicePathText::new(device_path.as_ptr() as *const efi::protocols::device_path::Protocol) };
+        format!("{text}")
+    }
+
+    const GUID: [u8; 16] =
+        [0xC9, 0xBD, 0xB8, 0x7C, 0xEB, 0xF8, 0x34, 0x4F, 0xAA, 0xEA, 0x3E, 0xE4, 0xAF, 0x65, 0x16, 0xA1];
+
+    #[test]
+    fn firmware_volume_file_paths_should_be_displayed() {
+        let fv = node(TYPE_MEDIA, Media::SUBTYPE_PIWG_FIRMWARE_VOLUME, &GUID);
+        let file = node(TYPE_MEDIA, Media::SUBTYPE_PIWG_FIRMWARE_FILE, &GUID);
+        assert_eq!(
+            text(&[fv, file]),
+            "Fv(7CB8BDC9-F8EB-4F34-AAEA-3EE4AF6516A1)/FvFile(7CB8BDC9-F8EB-4F34-AAEA-3EE4AF6516A1)"
+        );
+
+        let mut range = vec![0; 4];
+        range.extend(0x1000_u64.to_le_bytes());
+        range.extend(0x1FFF_u64.to_le_bytes());
+        let mut memory_mapped = 11_u32.to_le_bytes().to_vec();
+        memory_mapped.extend(0xFF00_0000_u64.to_le_bytes());
+        memory_mapped.extend(0xFFFF_FFFF_u64.to_le_bytes());
+        assert_eq!(
+            text(&[
+                node(TYPE_HARDWARE, Hardware::SUBTYPE_MMAP, &memory_mapped),
+                node(TYPE_MEDIA, Media::SUBTYPE_RELATIVE_OFFSET_RANGE, &range)
+            ]),
+            "MemoryMapped(0xB,0xFF000000,0xFFFFFFFF)/Offset(0x1000,0x1FFF)"
+        );
+    }
+
+    #[test]
+    fn hardware_and_acpi_nodes_should_be_displayed() {
+        let pci_root = [&(PNP_EISA_ID | (PCI_ROOT_PNP_ID << 16)).to_le_bytes()[..], &0_u32.to_le_bytes()].concat();
+        let serial = [&(PNP_EISA_ID | (0x0501 << 16)).to_le_bytes()[..], &1_u32.to_le_bytes()].concat();
+        assert_eq!(
+            text(&[
+                node(TYPE_ACPI, ACPI_SUBTYPE_ACPI, &pci_root),
+                node(TYPE_HARDWARE, Hardware::SUBTYPE_PCI, &[0x0, 0x1C]),
+                node(TYPE_HARDWARE, Hardware::SUBTYPE_CONTROLLER, &2_u32.to_le_bytes()),
+                node(TYPE_ACPI, ACPI_SUBTYPE_ACPI, &serial),
+            ]),
+            "PciRoot(0x0)/Pci(0x1C,0x0)/Ctrl(0x2)/Acpi(PNP0501,0x1)"
+        );
+
+        let vendor = [&GUID[..], &[0xAB, 0xCD]].concat();
+        assert_eq!(
+            text(&[node(TYPE_HARDWARE, Hardware::SUBTYPE_VENDOR, &vendor)]),
+            "VenHw(7CB8BDC9-F8EB-4F34-AAEA-3EE4AF6516A1,ABCD)"
+        );
+    }
+
+    #[test]
+    fn other_nodes_should_use_the_generic_form() {
+        let file_name: Vec<u8> = "\\EFI\\BOOT\\BOOTX64.EFI\0".encode_utf16().flat_map(u16::to_le_bytes).collect();
+        assert_eq!(
+            text(&[node(TYPE_MESSAGING, 5, &[0x1, 0x0]), node(TYPE_MEDIA, Media::SUBTYPE_FILE_PATH, &file_name)]),
+            "Path(3,5,0100)/\\EFI\\BOOT\\BOOTX64.EFI"
+        );
+        // malformed nodes are displayed with the generic form too.
+        assert_eq!(text(&[node(TYPE_HARDWARE, Hardware::SUBTYPE_PCI, &[0x1])]), "Path(1,1,01)");
+    }
+
+    #[test]
+    fn device_path_instances_should_be_separated() {
+        let pci = node(TYPE_HARDWARE, Hardware::SUBTYPE_PCI, &[0x0, 0x2]);
+        let end_instance = node(TYPE_END, End::SUBTYPE_INSTANCE, &[]);
+        assert_eq!(text(&[pci.clone(), end_instance, pci]), "Pci(0x2,0x0),Pci(0x2,0x0)");
+
+        let text = unsafe { DevicePathText::new(core::ptr::null()) };
+        assert_eq!(format!("{text}"), "<no device path>");
+    }
+}
@@ -0,0 +1,225 @@
+//! Host based integration test harness for Patina components
+//!
+//! [TestHarness] runs components against the real DXE core boot services on the host. The GCD, allocators, protocol
+//! database and event subsystem of the DXE core are compiled with `std` and initialized inside a block of host
+//! memory (see [patina_dxe_core::host]), so a component can be exercised end to end without mocking every
+//! [BootServices](patina::boot_services::BootServices) call individually.
+//!
+//! Components are registered and dispatched the same way the core does it: configuration and services are added to
+//! the component storage, guided HOBs are parsed by the registered [FromHob](patina::component::hob::FromHob)
+//! parsers, and components are run until no further component can be dispatched. Components opted out of the boot
+//! mode, set with `.with_config(BootMode::...)` and defaulting to a full configuration boot, are skipped.
+//!
+//! DXE drivers built as PE images can also be loaded from disk with [TestHarness::load_image], without building a
+//! firmware volume, and started with the entry point of the same driver compiled for the host with
+//! [TestHarness::start_image].
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use patina::{
+//!     boot_services::{BootServices, StandardBootServices, allocation::MemoryType},
+//!     component::{IntoComponent, params::Config},
+//!     error::{EfiError, Result},
+//! };
+//! use patina_test::TestHarness;
+//!
+//! #[derive(IntoComponent)]
+//! struct MyComponent;
+//!
+//! impl MyComponent {
+//!     fn entry_point(self, size: Config<usize>, boot_services: StandardBootServices) -> Result<()> {
+//!         let buffer = boot_services.allocate_pool(MemoryType::BOOT_SERVICES_DATA, *size)?;
+//!         boot_services.free_pool(buffer).map_err(EfiError::from)
+//!     }
+//! }
+//!
+//! let mut harness = TestHarness::new().with_config(0x100_usize).with_component(MyComponent);
+//! harness.run().unwrap();
+//! assert!(harness.pending_components().is_empty());
+//! ```
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use core::{ffi::c_void, slice};
+use patina::{
+    OwnedGuid,
+    boot_services::StandardBootServices,
+    component::{BootMode, Component, IntoComponent, Storage, service::IntoService},
+    dxe_services::StandardDxeServices,
+    error::Result,
+    runtime_services::StandardRuntimeServices,
+};
+use patina_dxe_core::host::HostEnvironment;
+use r_efi::efi;
+use std::path::Path;
+
+/// Runs Patina components against the DXE core boot services on the host.
+///
+/// Only one [TestHarness] can exist at a time. Creating a second one blocks until the first is dropped, which
+/// serializes tests in the same test binary. State left in the DXE core (e.g. installed protocols) by a previous
+/// harness remains visible.
+pub struct TestHarness {
+    env: HostEnvironment,
+    storage: Storage,
+    components: Vec<Box<dyn Component>>,
+    hobs: Vec<(OwnedGuid, Vec<u8>)>,
+}
+
+impl Default for TestHarness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TestHarness {
+    /// Creates a new harness, initializing the host environment if needed.
+    pub fn new() -> Self {
+        let env = HostEnvironment::acquire();
+        let mut storage = Storage::new();
+        storage.set_boot_services(env.boot_services());
+        storage.set_runtime_services(env.runtime_services());
+        storage.set_dxe_services(env.dxe_services());
+        Self { env, storage, components: Vec::new(), hobs: Vec::new() }
+    }
+
+    /// Adds a configuration value to the component storage.
+    pub fn with_config<C: Default + 'static>(mut self, config: C) -> Self {
+        self.storage.add_config(config);
+        self
+    }
+
+    /// Adds a service to the component storage.
+    pub fn with_service(mut self, service: impl IntoService + 'static) -> Self {
+        self.storage.add_service(service);
+        self
+    }
+
+    /// Adds a guided HOB, which is parsed by the registered HOB parsers before components are dispatched.
+    pub fn with_hob(mut self, guid: OwnedGuid, data: &[u8]) -> Self {
+        self.hobs.push((guid, data.to_vec()));
+        self
+    }
+
+    /// Registers a component to be dispatched by [TestHarness::run].
+    pub fn with_component<I>(mut self, component: impl IntoComponent<I>) -> Self {
+        let mut component = component.into_component();
+        component.initialize(&mut self.storage);
+        self.components.push(component);
+        self
+    }
+
+    /// Returns the boot services of the host environment.
+    pub fn boot_services(&self) -> StandardBootServices {
+        self.env.boot_services()
+    }
+
+    /// Returns the runtime services of the host environment.
+    pub fn runtime_services(&self) -> StandardRuntimeServices {
+        self.env.runtime_services()
+    }
+
+    /// Returns the DXE services of the host environment.
+    pub fn dxe_services(&self) -> StandardDxeServices {
+        self.env.dxe_services()
+    }
+
+    /// Returns the system table of the host environment, e.g. to inspect the consoles installed by a component.
+    pub fn system_table(&self) -> *mut efi::SystemTable {
+        self.env.system_table()
+    }
+
+    /// Returns the configuration table `guid` installed in the system table, if any.
+    pub fn configuration_table(&self, guid: &efi::Guid) -> Option<*mut c_void> {
+        // SAFETY: The system table of the host environment is valid, and so are its configuration tables.
+        let system_table = unsafe { &*self.system_table() };
+        if system_table.configuration_table.is_null() {
+            return None;
+        }
+        // SAFETY: The configuration table array has `number_of_table_entries` entries.
+        let tables =
+            unsafe { slice::from_raw_parts(system_table.configuration_table, system_table.number_of_table_entries) };
+        tables.iter().find(|table| table.vendor_guid == *guid).map(|table| table.vendor_table)
+    }
+
+    /// Loads the PE image at `path`, e.g. a DXE driver produced by the build system, and returns its image handle.
+    ///
+    /// See [HostEnvironment::load_image_from_path].
+    pub fn load_image(&self, path: impl AsRef<Path>) -> Result<efi::Handle> {
+        self.env.load_image_from_path(path)
+    }
+
+    /// Starts the loaded image `image_handle` with `entry_point`, the entry point of the driver compiled for the host.
+    ///
+    /// See [HostEnvironment::start_image].
+    pub fn start_image(&self, image_handle: efi::Handle, entry_point: efi::ImageEntryPoint) -> Result<()> {
+        self.env.start_image(image_handle, entry_point)
+    }
+
+    /// Returns the component storage, e.g. to inspect services produced by a component.
+    pub fn storage(&mut self) -> &mut Storage {
+        &mut self.storage
+    }
+
+    /// Dispatches the registered components until no further component can be dispatched.
+    ///
+    /// Components that could not be dispatched (e.g. because a parameter is unavailable) remain pending and are
+    /// reported by [TestHarness::pending_components]. Components opted out of the boot mode are removed without being
+    /// dispatched. Once dispatching is complete, the callbacks registered with
+    /// [Storage::add_dispatch_complete_callback] are executed, as the DXE core does after dispatching.
+    ///
+    /// ## Errors
+    ///
+    /// Returns the error of the first component that failed. The failed component is not retried. Once dispatching is
+    /// complete, returns the error of the first dispatch complete callback that failed, e.g. if an on-target test run
+    /// by the [TestRunner](patina::test::TestRunner) failed.
+    pub fn run(&mut self) -> Result<()> {
+        for (guid, data) in self.hobs.drain(..) {
+            for parser in self.storage.get_hob_parsers(&guid) {
+                parser(&data, &mut self.storage);
+            }
+        }
+
+        let boot_mode = self.storage.get_config::<BootMode>().map(|mode| *mode).unwrap_or_default();
+        self.components.retain(|component| {
+            let skipped = component.metadata().skips_boot_mode(boot_mode);
+            if skipped {
+                log::info!("Skipped: Id = [{:?}] Boot Mode = [{boot_mode}]", component.metadata().name());
+            }
+            !skipped
+        });
+
+        loop {
+            let mut dispatched = false;
+            let mut index = 0;
+            while index < self.components.len() {
+                let component = &mut self.components[index];
+                match component.run(&mut self.storage) {
+                    Ok(true) => {
+                        log::info!("Dispatched: Id = [{:?}]", component.metadata().name());
+                        self.components.remove(index);
+                        dispatched = true;
+                    }
+                    Ok(false) => index += 1,
+                    Err(err) => {
+                        log::error!("Dispatched: Id = [{:?}] Error = [{err:?}]", component.metadata().name());
+                        self.components.remove(index);
+                        return Err(err);
+                    }
+                }
+            }
+            if !dispatched {
+                return self.storage.dispatch_complete();
+            }
+        }
+    }
+
+    /// Returns the names of the components that have not been dispatched.
+    pub fn pending_components(&self) -> Vec<&'static str> {
+        self.components.iter().map(|component| component.metadata().name()).collect()
+    }
+}
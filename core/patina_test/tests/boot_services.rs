@@ -0,0 +1,198 @@
+//! Integration tests running components against the host DXE core boot services.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use patina::{
+    Guid, OwnedGuid,
+    boot_services::{
+        BootServices, StandardBootServices,
+        allocation::MemoryType,
+        event::{EventBuilder, EventType},
+        tpl::Tpl,
+    },
+    component::{BootMode, IntoComponent, hob::FromHob, hob::Hob, params::Config},
+    dxe_services::{DxeServices, GcdMemoryType, StandardDxeServices},
+    error::{EfiError, Result},
+};
+use patina_test::TestHarness;
+use r_efi::efi;
+
+const TEST_HOB_GUID: OwnedGuid =
+    Guid::from_fields(0x3a8e2f6b, 0x5c1d, 0x4f27, 0x9b, 0x40, [0x61, 0x0e, 0x7d, 0x2a, 0xc4, 0x93]);
+
+#[derive(FromHob, Default, Clone, Copy)]
+#[hob = "3a8e2f6b-5c1d-4f27-9b40-610e7d2ac493"]
+#[repr(C)]
+struct TestHob {
+    value: u32,
+}
+
+static HOB_VALUE: AtomicUsize = AtomicUsize::new(0);
+static NOTIFY_COUNT: AtomicUsize = AtomicUsize::new(0);
+static FULL_BOOT_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(IntoComponent)]
+struct PoolComponent;
+
+impl PoolComponent {
+    fn entry_point(self, size: Config<usize>, boot_services: StandardBootServices) -> Result<()> {
+        let buffer = boot_services.allocate_pool(MemoryType::BOOT_SERVICES_DATA, *size)?;
+        // SAFETY: The buffer was just allocated with the requested size.
+        unsafe { core::ptr::write_bytes(buffer, 0xA5, *size) };
+        boot_services.free_pool(buffer).map_err(EfiError::from)
+    }
+}
+
+#[derive(IntoComponent)]
+struct MmioComponent;
+
+impl MmioComponent {
+    const BASE_ADDRESS: u64 = 0xFED0_0000;
+    const LENGTH: u64 = 0x1000;
+
+    fn entry_point(self, dxe_services: StandardDxeServices) -> Result<()> {
+        dxe_services.add_memory_space(
+            GcdMemoryType::MemoryMappedIo,
+            Self::BASE_ADDRESS,
+            Self::LENGTH,
+            efi::MEMORY_UC,
+        )?;
+        dxe_services.set_memory_space_attributes(Self::BASE_ADDRESS, Self::LENGTH, efi::MEMORY_UC)?;
+        Ok(())
+    }
+}
+
+#[derive(IntoComponent)]
+struct HobComponent;
+
+impl HobComponent {
+    fn entry_point(self, hob: Hob<TestHob>) -> Result<()> {
+        HOB_VALUE.store(hob.value as usize, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+#[derive(IntoComponent)]
+struct FailingComponent;
+
+impl FailingComponent {
+    fn entry_point(self) -> Result<()> {
+        Err(EfiError::Aborted)
+    }
+}
+
+#[derive(IntoComponent)]
+#[skip_boot_modes(BootOnS3Resume)]
+struct FullBootComponent;
+
+impl FullBootComponent {
+    fn entry_point(self, boot_mode: Config<BootMode>) -> Result<()> {
+        assert_ne!(*boot_mode, BootMode::BootOnS3Resume);
+        FULL_BOOT_COUNT.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+fn on_signal(_event: efi::Event, count: &mut usize) {
+    *count += 1;
+    NOTIFY_COUNT.fetch_add(1, Ordering::SeqCst);
+}
+
+#[test]
+fn test_allocate_and_free_pool() {
+    let harness = TestHarness::new();
+    let boot_services = harness.boot_services();
+
+    let buffer = boot_services.allocate_pool(MemoryType::BOOT_SERVICES_DATA, 0x1000).unwrap();
+    assert!(!buffer.is_null());
+    boot_services.free_pool(buffer).unwrap();
+}
+
+#[test]
+fn test_component_uses_boot_services() {
+    let mut harness = TestHarness::new().with_config(0x100_usize).with_component(PoolComponent);
+    harness.run().unwrap();
+    assert!(harness.pending_components().is_empty());
+}
+
+#[test]
+fn test_component_uses_dxe_services() {
+    let mut harness = TestHarness::new().with_component(MmioComponent);
+    harness.run().unwrap();
+    assert!(harness.pending_components().is_empty());
+
+    let descriptor = harness.dxe_services().get_memory_space_descriptor(MmioComponent::BASE_ADDRESS).unwrap();
+    assert_eq!(descriptor.memory_type, GcdMemoryType::MemoryMappedIo);
+    assert_eq!((descriptor.base_address, descriptor.length), (MmioComponent::BASE_ADDRESS, MmioComponent::LENGTH));
+    assert_eq!(descriptor.attributes & efi::MEMORY_UC, efi::MEMORY_UC);
+}
+
+#[test]
+fn test_component_waits_for_hob() {
+    {
+        let mut harness = TestHarness::new().with_component(HobComponent);
+        harness.run().unwrap();
+        assert_eq!(harness.pending_components().len(), 1);
+    }
+
+    let mut harness =
+        TestHarness::new().with_hob(TEST_HOB_GUID, &0x1234_u32.to_le_bytes()).with_component(HobComponent);
+    harness.run().unwrap();
+    assert!(harness.pending_components().is_empty());
+    assert_eq!(HOB_VALUE.load(Ordering::SeqCst), 0x1234);
+}
+
+#[test]
+fn test_component_error_is_returned() {
+    let mut harness = TestHarness::new().with_component(FailingComponent);
+    assert_eq!(harness.run(), Err(EfiError::Aborted));
+    assert!(harness.pending_components().is_empty());
+}
+
+#[test]
+fn test_component_is_skipped_in_opted_out_boot_mode() {
+    let mut harness = TestHarness::new().with_config(BootMode::BootOnS3Resume).with_component(FullBootComponent);
+    harness.run().unwrap();
+    assert!(harness.pending_components().is_empty());
+    assert_eq!(FULL_BOOT_COUNT.load(Ordering::SeqCst), 0);
+
+    let mut harness =
+        TestHarness::new().with_config(BootMode::BootWithFullConfiguration).with_component(FullBootComponent);
+    harness.run().unwrap();
+    assert!(harness.pending_components().is_empty());
+    assert_eq!(FULL_BOOT_COUNT.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_signaled_event_is_notified() {
+    let harness = TestHarness::new();
+    let boot_services = harness.boot_services();
+
+    let event = EventBuilder::new(boot_services.clone(), EventType::NOTIFY_SIGNAL, Tpl::CALLBACK)
+        .create(on_signal, 0_usize)
+        .unwrap();
+
+    boot_services.signal_event(event.event()).unwrap();
+    assert_eq!(NOTIFY_COUNT.load(Ordering::SeqCst), 1);
+
+    event.close().unwrap();
+}
+
+#[test]
+fn test_dispatch_complete_callbacks_run() {
+    static RAN: AtomicUsize = AtomicUsize::new(0);
+
+    let mut harness = TestHarness::new();
+    harness.storage().add_dispatch_complete_callback(|_| {
+        RAN.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    });
+    harness.run().unwrap();
+    assert_eq!(RAN.load(Ordering::SeqCst), 1);
+}
@@ -0,0 +1,74 @@
+//! Integration tests loading PE images from disk into the host DXE core.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use std::{
+    ffi::c_void,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use patina::{boot_services::BootServices, error::EfiError, uefi_protocol::loaded_image::LoadedImage};
+use patina_dxe_core::loaded_images;
+use patina_test::TestHarness;
+use r_efi::efi;
+
+const TEST_IMAGE: &str =
+    concat!(env!("CARGO_MANIFEST_DIR"), "/../../patina_dxe_core/resources/test/RustImageTestDxe.efi");
+
+static STARTED_IMAGE: AtomicPtr<c_void> = AtomicPtr::new(core::ptr::null_mut());
+
+extern "efiapi" fn host_entry_point(image_handle: efi::Handle, system_table: *mut efi::SystemTable) -> efi::Status {
+    if system_table.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    STARTED_IMAGE.store(image_handle, Ordering::SeqCst);
+    efi::Status::SUCCESS
+}
+
+extern "efiapi" fn failing_entry_point(
+    _image_handle: efi::Handle,
+    _system_table: *mut efi::SystemTable,
+) -> efi::Status {
+    efi::Status::DEVICE_ERROR
+}
+
+fn is_loaded(image_handle: efi::Handle) -> bool {
+    loaded_images().any(|image| image.info.image_handle == image_handle)
+}
+
+#[test]
+fn test_load_and_start_image_from_path() {
+    let harness = TestHarness::new();
+    let image_handle = harness.load_image(TEST_IMAGE).unwrap();
+
+    // The image is published as if the core dispatched it.
+    // SAFETY: The loaded image protocol is installed by the core on the image handle.
+    let loaded_image = unsafe { harness.boot_services().handle_protocol::<LoadedImage>(image_handle) }.unwrap();
+    assert_ne!(loaded_image.image_size(), 0);
+    assert!(is_loaded(image_handle));
+
+    harness.start_image(image_handle, host_entry_point).unwrap();
+    assert_eq!(STARTED_IMAGE.load(Ordering::SeqCst), image_handle);
+
+    // An image can only be started once.
+    assert_eq!(harness.start_image(image_handle, host_entry_point), Err(EfiError::InvalidParameter));
+}
+
+#[test]
+fn test_failing_image_is_unloaded() {
+    let harness = TestHarness::new();
+    let image_handle = harness.load_image(TEST_IMAGE).unwrap();
+
+    assert_eq!(harness.start_image(image_handle, failing_entry_point), Err(EfiError::DeviceError));
+    assert!(!is_loaded(image_handle));
+}
+
+#[test]
+fn test_missing_image_is_not_found() {
+    let harness = TestHarness::new();
+    assert_eq!(harness.load_image("does/not/exist.efi"), Err(EfiError::NotFound));
+}
@@ -23,7 +23,7 @@ static LOGGER: patina::log::SerialLogger<patina::serial::Terminal> = patina::log
     &[
         ("goblin", log::LevelFilter::Off),
         ("patina_internal_depex", log::LevelFilter::Off),
-        ("gcd_measure", log::LevelFilter::Off),
+        (patina::log::target::GCD_MEASURE, log::LevelFilter::Off),
     ],
     log::LevelFilter::Trace,
     patina::serial::Terminal {},
@@ -7,6 +7,8 @@
 //! SPDX-License-Identifier: Apache-2.0
 //!
 mod fixed_size_block_allocator;
+mod memory_map_cap;
+mod poison;
 mod uefi_allocator;
 
 use core::{
@@ -14,7 +16,7 @@ use core::{
     fmt::Debug,
     mem,
     ops::Range,
-    ptr::NonNull,
+    ptr::{self, NonNull},
     slice::{self, from_raw_parts_mut},
 };
 
@@ -23,7 +25,10 @@ use alloc::{boxed::Box, collections::BTreeMap, vec::Vec};
 use mu_rust_helpers::function;
 
 use crate::{
-    GCD, config_tables,
+    GCD, component_lifecycle,
+    config_tables::{self, allocation_attribution_table},
+    core_context::{self, ContextStatic, GLOBAL_CONTEXT},
+    error::{CoreError, ErrorContext, Module},
     gcd::{self, AllocateType as AllocationStrategy},
     memory_attributes_table::MemoryAttributesTable,
     protocol_db::{self, INVALID_HANDLE},
@@ -31,17 +36,24 @@ use crate::{
     systemtables::EfiSystemTable,
     tpl_lock,
 };
+pub use memory_map_cap::MemoryMapCapPolicy;
+pub(crate) use memory_map_cap::set_memory_map_cap_policy;
 use patina_pi::{
     dxe_services::{self, GcdMemoryType, MemorySpaceDescriptor},
-    hob::{self, EFiMemoryTypeInformation, Hob, HobList, MEMORY_TYPE_INFO_HOB_GUID},
+    hob::{self, EFiMemoryTypeInformation, Hob, HobList, MEMORY_TYPE_INFO_HOB_GUID, PhaseHandoffInformationTable},
 };
+pub use poison::FreePoisoningPolicy;
+pub(crate) use poison::{check_reallocated_range, set_free_poisoning_policy};
 use r_efi::{efi, system::TPL_HIGH_LEVEL};
+use uefi_allocator::UEFI_POOL_ALIGN;
 pub use uefi_allocator::UefiAllocator;
 
 use patina::{
     base::{SIZE_4KB, UEFI_PAGE_MASK, UEFI_PAGE_SIZE},
     error::EfiError,
-    guids, uefi_size_to_pages,
+    guids,
+    log::target,
+    uefi_size_to_pages,
 };
 
 // Allocation Strategy when not specified by caller.
@@ -54,6 +66,11 @@ const PRIVATE_ALLOCATOR_TRACKING_GUID: efi::Guid =
 
 pub(crate) const DEFAULT_PAGE_ALLOCATION_GRANULARITY: usize = SIZE_4KB;
 
+// Memory types 0x70000000..=0x7FFFFFFF are reserved for use by OEMs, and 0x80000000..=0xFFFFFFFF for use by OS
+// vendors (UEFI Spec 2.10, section 7.2.1). Types between the last spec defined type and the OEM range are illegal.
+const OEM_MEMORY_TYPE_START: efi::MemoryType = 0x7000_0000;
+const OS_MEMORY_TYPE_START: efi::MemoryType = 0x8000_0000;
+
 // Per the UEFI spec, AARCH64 runtime pages need to be allocated on 64KB boundaries in units of 64KB to accommodate
 // OSes that use 16KB or 64KB page sizes. Other architectures use 4KB pages, so we don't have any additional
 // granularity requirements for them.
@@ -68,11 +85,11 @@ cfg_if::cfg_if! {
 // The boot services data allocator is special as it is used as the GlobalAllocator instance for the DXE Rust core.
 // This means that any rust heap allocations (e.g. Box::new()) will come from this allocator unless explicitly directed
 // to a different allocator. This allocator does not need to be public since all dynamic allocations will implicitly
-// allocate from it.
+// allocate from it. Like the other static allocators, it is bound to the GCD of the global core context.
 #[cfg_attr(target_os = "uefi", global_allocator)]
 pub(crate) static EFI_BOOT_SERVICES_DATA_ALLOCATOR: UefiAllocator = UefiAllocator::new(
-    &GCD,
-    NonNull::from_ref(GCD.memory_type_info(efi::BOOT_SERVICES_DATA)),
+    &GLOBAL_CONTEXT.gcd,
+    NonNull::from_ref(GLOBAL_CONTEXT.gcd.memory_type_info(efi::BOOT_SERVICES_DATA)),
     protocol_db::EFI_BOOT_SERVICES_DATA_ALLOCATOR_HANDLE,
     DEFAULT_PAGE_ALLOCATION_GRANULARITY,
 );
@@ -81,33 +98,33 @@ pub(crate) static EFI_BOOT_SERVICES_DATA_ALLOCATOR: UefiAllocator = UefiAllocato
 // be used in the core without e.g. the overhead of acquiring a lock to retrieve them from the allocator map that all
 // the other allocators use.
 pub static EFI_LOADER_CODE_ALLOCATOR: UefiAllocator = UefiAllocator::new(
-    &GCD,
-    NonNull::from_ref(GCD.memory_type_info(efi::LOADER_CODE)),
+    &GLOBAL_CONTEXT.gcd,
+    NonNull::from_ref(GLOBAL_CONTEXT.gcd.memory_type_info(efi::LOADER_CODE)),
     protocol_db::EFI_LOADER_CODE_ALLOCATOR_HANDLE,
     DEFAULT_PAGE_ALLOCATION_GRANULARITY,
 );
 
 pub static EFI_BOOT_SERVICES_CODE_ALLOCATOR: UefiAllocator = UefiAllocator::new(
-    &GCD,
-    NonNull::from_ref(GCD.memory_type_info(efi::BOOT_SERVICES_CODE)),
+    &GLOBAL_CONTEXT.gcd,
+    NonNull::from_ref(GLOBAL_CONTEXT.gcd.memory_type_info(efi::BOOT_SERVICES_CODE)),
     protocol_db::EFI_BOOT_SERVICES_CODE_ALLOCATOR_HANDLE,
     DEFAULT_PAGE_ALLOCATION_GRANULARITY,
 );
 
-// This needs to call MemoryAttributesTable::install on allocation/deallocation, hence having the real callback
+// This needs to call MemoryAttributesTable::update on allocation/deallocation, hence having the real callback
 // passed in
 pub static EFI_RUNTIME_SERVICES_CODE_ALLOCATOR: UefiAllocator = UefiAllocator::new(
-    &GCD,
-    NonNull::from_ref(GCD.memory_type_info(efi::RUNTIME_SERVICES_CODE)),
+    &GLOBAL_CONTEXT.gcd,
+    NonNull::from_ref(GLOBAL_CONTEXT.gcd.memory_type_info(efi::RUNTIME_SERVICES_CODE)),
     protocol_db::EFI_RUNTIME_SERVICES_CODE_ALLOCATOR_HANDLE,
     RUNTIME_PAGE_ALLOCATION_GRANULARITY,
 );
 
-// This needs to call MemoryAttributesTable::install on allocation/deallocation, hence having the real callback
+// This needs to call MemoryAttributesTable::update on allocation/deallocation, hence having the real callback
 // passed in
 pub static EFI_RUNTIME_SERVICES_DATA_ALLOCATOR: UefiAllocator = UefiAllocator::new(
-    &GCD,
-    NonNull::from_ref(GCD.memory_type_info(efi::RUNTIME_SERVICES_DATA)),
+    &GLOBAL_CONTEXT.gcd,
+    NonNull::from_ref(GLOBAL_CONTEXT.gcd.memory_type_info(efi::RUNTIME_SERVICES_DATA)),
     protocol_db::EFI_RUNTIME_SERVICES_DATA_ALLOCATOR_HANDLE,
     RUNTIME_PAGE_ALLOCATION_GRANULARITY,
 );
@@ -120,6 +137,12 @@ static STATIC_ALLOCATORS: &[&UefiAllocator] = &[
     &EFI_RUNTIME_SERVICES_DATA_ALLOCATOR,
 ];
 
+// Returns the static allocators of the current core context. They are bound to the GCD of the global context, so an
+// independent context has none and creates allocators bound to its own GCD for their memory types instead.
+fn static_allocators() -> &'static [&'static UefiAllocator] {
+    if core_context::is_global() { STATIC_ALLOCATORS } else { &[] }
+}
+
 fn memory_attributes_to_str(f: &mut core::fmt::Formatter<'_>, attributes: u64) -> core::fmt::Result {
     let mut attrs = Vec::new();
     let mut string_len = 0;
@@ -206,6 +229,9 @@ fn memory_type_to_str(f: &mut core::fmt::Formatter<'_>, memory_type: efi::Memory
         efi::MEMORY_MAPPED_IO_PORT_SPACE => "Memory Mapped IO Port Space",
         efi::PAL_CODE => "PAL Code",
         efi::PERSISTENT_MEMORY => "Persistent Memory",
+        efi::UNACCEPTED_MEMORY_TYPE => "Unaccepted Memory",
+        OEM_MEMORY_TYPE_START..OS_MEMORY_TYPE_START => return write!(f, "OEM Reserved {memory_type:<#12X}"),
+        OS_MEMORY_TYPE_START.. => return write!(f, "OS Reserved {memory_type:<#13X}"),
         _ => "Unknown Memory Type",
     };
 
@@ -255,15 +281,24 @@ pub(crate) fn get_memory_ranges_for_memory_type(memory_type: efi::MemoryType) ->
     Vec::new()
 }
 
+/// Returns the memory ranges owned by each allocator, with the memory type of the allocator.
+pub(crate) fn get_allocator_memory_ranges() -> Vec<(efi::MemoryType, Range<efi::PhysicalAddress>)> {
+    ALLOCATORS
+        .lock()
+        .iter()
+        .flat_map(|allocator| allocator.get_memory_ranges().map(move |range| (allocator.memory_type(), range)))
+        .collect()
+}
+
 // The following structure is used to track additional allocators that are created in response to allocation requests
 // that are not satisfied by the static allocators.
-static ALLOCATORS: tpl_lock::TplMutex<AllocatorMap> = AllocatorMap::new();
-struct AllocatorMap {
+static ALLOCATORS: ContextStatic<tpl_lock::TplMutex<AllocatorMap>> = ContextStatic::new(|context| &context.allocators);
+pub(crate) struct AllocatorMap {
     map: BTreeMap<efi::MemoryType, &'static UefiAllocator>,
 }
 
 impl AllocatorMap {
-    const fn new() -> tpl_lock::TplMutex<Self> {
+    pub(crate) const fn new() -> tpl_lock::TplMutex<Self> {
         tpl_lock::TplMutex::new(TPL_HIGH_LEVEL, AllocatorMap { map: BTreeMap::new() }, "AllocatorMapLock")
     }
 }
@@ -271,7 +306,7 @@ impl AllocatorMap {
 impl AllocatorMap {
     // Returns an iterator that returns references to the static allocators followed by the custom allocators.
     fn iter(&self) -> impl Iterator<Item = &'static UefiAllocator> {
-        STATIC_ALLOCATORS.iter().copied().chain(self.map.values().copied())
+        static_allocators().iter().copied().chain(self.map.values().copied())
     }
 
     // Retrieves an allocator for the given memory type, creating one if it doesn't already exist.
@@ -289,7 +324,7 @@ impl AllocatorMap {
         memory_type: efi::MemoryType,
         handle: efi::Handle,
     ) -> Result<&'static UefiAllocator, EfiError> {
-        if let Some(allocator) = STATIC_ALLOCATORS.iter().find(|x| x.memory_type() == memory_type) {
+        if let Some(allocator) = static_allocators().iter().find(|x| x.memory_type() == memory_type) {
             return Ok(allocator);
         }
         Ok(self.get_or_create_dynamic_allocator(memory_type, handle))
@@ -315,9 +350,10 @@ impl AllocatorMap {
             };
 
             // If this is one of the memory types tracked by the system table, we will use the memory type info struct
-            // from the GCD. Otherwise, we will just leak a new memory type info struct with the given memory type and
-            // have the allocator use it.
-            let memory_type_info = if (memory_type as usize) <= GCD.memory_type_info_table().len() {
+            // from the GCD. Otherwise (e.g. OEM and OS memory types), we will just leak a new memory type info struct
+            // with the given memory type and have the allocator use it. The last entry of the table is the
+            // EfiMaxMemoryType terminator, which is not a memory type.
+            let memory_type_info = if (memory_type as usize) < GCD.memory_type_info_table().len() - 1 {
                 NonNull::from_ref(GCD.memory_type_info(memory_type))
             } else {
                 NonNull::from_ref(Box::leak(Box::new(EFiMemoryTypeInformation { memory_type, number_of_pages: 0 })))
@@ -351,10 +387,11 @@ impl AllocatorMap {
             efi::BOOT_SERVICES_DATA => Ok(protocol_db::EFI_BOOT_SERVICES_DATA_ALLOCATOR_HANDLE),
             efi::ACPI_RECLAIM_MEMORY => Ok(protocol_db::EFI_ACPI_RECLAIM_MEMORY_ALLOCATOR_HANDLE),
             efi::ACPI_MEMORY_NVS => Ok(protocol_db::EFI_ACPI_MEMORY_NVS_ALLOCATOR_HANDLE),
-            // Check to see if it is an invalid type. Memory types efi::PERSISTENT_MEMORY and above to 0x6FFFFFFF are illegal.
-            efi::PERSISTENT_MEMORY..=0x6FFFFFFF => Err(EfiError::InvalidParameter)?,
-            // not a well known handle or illegal memory type - check the active allocators and create a handle if it doesn't
-            // already exist.
+            // Check to see if it is an invalid type. Memory types efi::PERSISTENT_MEMORY and above up to the OEM range
+            // are illegal.
+            efi::PERSISTENT_MEMORY..OEM_MEMORY_TYPE_START => Err(EfiError::InvalidParameter)?,
+            // not a well known handle or illegal memory type (including the OEM and OS ranges) - check the active
+            // allocators and create a handle if it doesn't already exist.
             _ => {
                 if let Some(handle) = ALLOCATORS
                     .lock()
@@ -409,6 +446,18 @@ extern "efiapi" fn allocate_pool(pool_type: efi::MemoryType, size: usize, buffer
 }
 
 pub fn core_allocate_pool(pool_type: efi::MemoryType, size: usize) -> Result<*mut c_void, EfiError> {
+    core_allocate_pool_aligned(pool_type, size, UEFI_POOL_ALIGN)
+}
+
+/// Allocates pool memory of the given type aligned to `alignment`.
+///
+/// `alignment` must be a power of two no larger than a page (e.g. 64 bytes for DMA descriptors). The buffer is freed
+/// with [core_free_pool] like any other pool allocation.
+pub fn core_allocate_pool_aligned(
+    pool_type: efi::MemoryType,
+    size: usize,
+    alignment: usize,
+) -> Result<*mut c_void, EfiError> {
     // It is not valid to attempt to allocate these memory types
     if matches!(pool_type, efi::CONVENTIONAL_MEMORY | efi::PERSISTENT_MEMORY | efi::UNACCEPTED_MEMORY_TYPE) {
         return Err(EfiError::InvalidParameter);
@@ -419,7 +468,7 @@ pub fn core_allocate_pool(pool_type: efi::MemoryType, size: usize) -> Result<*mu
         Ok(allocator) => {
             let mut buffer: *mut c_void = core::ptr::null_mut();
 
-            unsafe { allocator.allocate_pool(size, core::ptr::addr_of_mut!(buffer)).map(|_| buffer) }
+            unsafe { allocator.allocate_pool_aligned(size, alignment, core::ptr::addr_of_mut!(buffer)).map(|_| buffer) }
         }
         Err(err) => Err(err),
     }
@@ -464,17 +513,17 @@ pub fn core_allocate_pages(
     pages: usize,
     memory: *mut efi::PhysicalAddress,
     alignment: Option<usize>,
-) -> Result<(), EfiError> {
+) -> Result<(), CoreError> {
     if memory.is_null() {
-        return Err(EfiError::InvalidParameter);
+        return Err(CoreError::new(Module::Allocator, "allocate pages", EfiError::InvalidParameter));
     }
 
     // It is not valid to attempt to allocate these memory types
     if matches!(memory_type, efi::CONVENTIONAL_MEMORY | efi::PERSISTENT_MEMORY | efi::UNACCEPTED_MEMORY_TYPE) {
-        return Err(EfiError::InvalidParameter);
+        return Err(CoreError::new(Module::Allocator, "allocate pages", EfiError::InvalidParameter));
     }
 
-    let handle = AllocatorMap::handle_for_memory_type(memory_type)?;
+    let handle = AllocatorMap::handle_for_memory_type(memory_type).context(Module::Allocator, "allocate pages")?;
     let alignment = alignment.unwrap_or(UEFI_PAGE_SIZE);
 
     let res = match ALLOCATORS.lock().get_or_create_allocator(memory_type, handle) {
@@ -505,18 +554,34 @@ pub fn core_allocate_pages(
         Err(err) => Err(err),
     };
 
+    if res.is_ok() {
+        // Safety: caller must ensure that "memory" is a valid pointer. It is null-checked above.
+        let address = unsafe { memory.read_unaligned() };
+        allocation_attribution_table::record_allocation(address, pages, memory_type);
+        component_lifecycle::record_allocation(address, pages, memory_type);
+    }
+
     // If the memory type is runtime services code or data, we need to install the memory attributes table to reflect
     // the update. The MAT logic will decide if it is a proper time to install the MAT or not.
     match memory_type {
         efi::RUNTIME_SERVICES_CODE | efi::RUNTIME_SERVICES_DATA => {
             if res.is_ok() {
-                MemoryAttributesTable::install();
+                // Safety: caller must ensure that "memory" is a valid pointer. It is null-checked above.
+                let address = unsafe { memory.read_unaligned() };
+                MemoryAttributesTable::update(address, (pages * UEFI_PAGE_SIZE) as u64);
             }
         }
         _ => {}
     }
 
-    res
+    res.map_err(|err| {
+        let err = CoreError::new(Module::Allocator, "allocate pages", err);
+        match allocation_type {
+            // Safety: caller must ensure that "memory" is a valid pointer. It is null-checked above.
+            efi::ALLOCATE_ADDRESS | efi::ALLOCATE_MAX_ADDRESS => err.with_address(unsafe { memory.read_unaligned() }),
+            _ => err,
+        }
+    })
 }
 
 pub fn core_get_allocator(memory_type: efi::MemoryType) -> Result<&'static UefiAllocator, EfiError> {
@@ -531,18 +596,20 @@ extern "efiapi" fn free_pages(memory: efi::PhysicalAddress, pages: usize) -> efi
     }
 }
 
-pub fn core_free_pages(memory: efi::PhysicalAddress, pages: usize) -> Result<(), EfiError> {
+pub fn core_free_pages(memory: efi::PhysicalAddress, pages: usize) -> Result<(), CoreError> {
+    let invalid_parameter =
+        CoreError::new(Module::Allocator, "free pages", EfiError::InvalidParameter).with_address(memory);
     let size = match pages.checked_mul(UEFI_PAGE_SIZE) {
         Some(size) => size,
-        None => return Err(EfiError::InvalidParameter),
+        None => return Err(invalid_parameter),
     };
 
     if memory.checked_add(size as u64).is_none() {
-        return Err(EfiError::InvalidParameter);
+        return Err(invalid_parameter);
     }
 
     if memory.checked_rem(UEFI_PAGE_SIZE as efi::PhysicalAddress) != Some(0) {
-        return Err(EfiError::InvalidParameter);
+        return Err(invalid_parameter);
     }
 
     let allocators = ALLOCATORS.lock();
@@ -556,7 +623,7 @@ pub fn core_free_pages(memory: efi::PhysicalAddress, pages: usize) -> Result<(),
         }) {
             Ok(())
         } else {
-            Err(EfiError::NotFound)
+            Err(CoreError::new(Module::Allocator, "free pages", EfiError::NotFound).with_address(memory))
         }
     };
 
@@ -565,12 +632,17 @@ pub fn core_free_pages(memory: efi::PhysicalAddress, pages: usize) -> Result<(),
     // tables are locked at TPL_NOTIFY
     drop(allocators);
 
+    if res.is_ok() {
+        allocation_attribution_table::record_free(memory, pages);
+        component_lifecycle::record_free(memory, pages);
+    }
+
     // If the memory type is runtime services code or data, we need to install the memory attributes table to reflect
     // the update. The MAT logic will decide if it is a proper time to install the MAT or not.
     match memory_type {
         efi::RUNTIME_SERVICES_CODE | efi::RUNTIME_SERVICES_DATA => {
             if res.is_ok() {
-                MemoryAttributesTable::install();
+                MemoryAttributesTable::update(memory, size as u64);
             }
         }
         _ => {}
@@ -635,70 +707,125 @@ pub(crate) fn get_memory_map_descriptors(active_attributes: bool) -> Result<Vec<
 
     Ok(descriptors
         .iter()
-        .filter_map(|descriptor| {
-            let memory_type = ALLOCATORS.lock().memory_type_for_handle(descriptor.image_handle).or({
-                match descriptor.memory_type {
-                    // free memory not tracked by any allocator.
-                    GcdMemoryType::SystemMemory => Some(efi::CONVENTIONAL_MEMORY),
-
-                    // MMIO. Note: there could also be MMIO tracked by the allocators which would not hit this case.
-                    GcdMemoryType::MemoryMappedIo => {
-                        if (descriptor.attributes & efi::MEMORY_ISA_VALID) == efi::MEMORY_ISA_VALID {
-                            Some(efi::MEMORY_MAPPED_IO_PORT_SPACE)
-                        } else {
-                            Some(efi::MEMORY_MAPPED_IO)
-                        }
-                    }
-
-                    // Persistent. Note: this type is not allocatable, but might be created by agents other than the core directly
-                    // in the GCD.
-                    GcdMemoryType::Persistent => Some(efi::PERSISTENT_MEMORY),
-
-                    // Unaccepted. Note: this type is not allocatable, but might be created by agents other than the core directly
-                    // in the GCD.
-                    GcdMemoryType::Unaccepted => Some(efi::UNACCEPTED_MEMORY_TYPE),
+        .filter_map(|descriptor| memory_map_descriptor(descriptor, active_attributes))
+        .fold(merged_descriptors, merge_blocks))
+}
 
-                    // Reserved.
-                    GcdMemoryType::Reserved => Some(efi::RESERVED_MEMORY_TYPE),
+/// Get the memory map descriptors from the GCD for the GCD descriptors overlapping the range `base_address` to
+/// `base_address + length`.
+///
+/// Returns the range covered by the overlapping GCD descriptors, which may extend past the requested range, with the
+/// memory map descriptors for it. See [get_memory_map_descriptors] for `active_attributes`.
+///
+pub(crate) fn get_memory_map_descriptors_for_range(
+    base_address: u64,
+    length: u64,
+    active_attributes: bool,
+) -> Result<(Range<u64>, Vec<efi::MemoryDescriptor>), EfiError> {
+    let end = base_address.checked_add(length).ok_or(EfiError::InvalidParameter)?;
+    let mut descriptors = Vec::new();
+    let mut address = base_address;
+    while address < end {
+        let descriptor = GCD.get_memory_descriptor_for_address(address)?;
+        address = descriptor.base_address + descriptor.length;
+        descriptors.push(descriptor);
+    }
+    let covered = match (descriptors.first(), descriptors.last()) {
+        (Some(first), Some(last)) => first.base_address..last.base_address + last.length,
+        _ => base_address..base_address,
+    };
 
-                    // Other memory types are ignored for purposes of the memory map
-                    _ => None,
-                }
-            })?;
+    Ok((
+        covered,
+        descriptors
+            .iter()
+            .filter_map(|descriptor| memory_map_descriptor(descriptor, active_attributes))
+            .fold(Vec::new(), merge_blocks),
+    ))
+}
 
-            let number_of_pages = descriptor.length >> 12;
-            if number_of_pages == 0 {
-                return None; //skip entries for things smaller than a page
+/// Returns the memory map type of a GCD descriptor that is not owned by an allocator, or `None` if the descriptor is
+/// not reported in the memory map.
+fn gcd_memory_map_type(descriptor: &MemorySpaceDescriptor) -> Option<efi::MemoryType> {
+    match descriptor.memory_type {
+        // free memory not tracked by any allocator.
+        GcdMemoryType::SystemMemory if descriptor.image_handle == INVALID_HANDLE => Some(efi::CONVENTIONAL_MEMORY),
+
+        // memory allocated directly in the GCD by agents other than the allocators, e.g. by drivers calling
+        // AllocateMemorySpace(). It is in use, so it must not be reported as free; the runtime attribute marks memory
+        // that must be preserved at runtime.
+        GcdMemoryType::SystemMemory => {
+            if (descriptor.attributes & efi::MEMORY_RUNTIME) == efi::MEMORY_RUNTIME {
+                Some(efi::RUNTIME_SERVICES_DATA)
+            } else {
+                Some(efi::BOOT_SERVICES_DATA)
             }
-            if (descriptor.base_address % 0x1000) != 0 {
-                return None; //skip entries not page aligned.
+        }
+
+        // MMIO. Note: there could also be MMIO tracked by the allocators which would not hit this case.
+        GcdMemoryType::MemoryMappedIo => {
+            if (descriptor.attributes & efi::MEMORY_ISA_VALID) == efi::MEMORY_ISA_VALID {
+                Some(efi::MEMORY_MAPPED_IO_PORT_SPACE)
+            } else {
+                Some(efi::MEMORY_MAPPED_IO)
             }
+        }
 
-            let mut attributes = match active_attributes {
-                true => descriptor.attributes,
-                false => {
-                    // When using the capabilities, drop the runtime attribute and
-                    // pick it up from the active attributes.
-                    (descriptor.capabilities & !(efi::MEMORY_ACCESS_MASK | efi::MEMORY_RUNTIME))
-                        | (descriptor.attributes & efi::MEMORY_RUNTIME)
-                }
-            };
+        // Persistent. Note: this type is not allocatable, but might be created by agents other than the core directly
+        // in the GCD.
+        GcdMemoryType::Persistent => Some(efi::PERSISTENT_MEMORY),
 
-            if matches!(memory_type, efi::RUNTIME_SERVICES_CODE | efi::RUNTIME_SERVICES_DATA) {
-                // Add the runtime attribute for runtime services code and data as
-                // higher level code will expect this but it is not explicitly tracked.
-                attributes |= efi::MEMORY_RUNTIME;
-            }
+        // Unaccepted. Note: this type is not allocatable, but might be created by agents other than the core directly
+        // in the GCD.
+        GcdMemoryType::Unaccepted => Some(efi::UNACCEPTED_MEMORY_TYPE),
 
-            Some(efi::MemoryDescriptor {
-                r#type: memory_type,
-                physical_start: descriptor.base_address,
-                virtual_start: 0,
-                number_of_pages,
-                attribute: attributes,
-            })
-        })
-        .fold(merged_descriptors, merge_blocks))
+        // Reserved.
+        GcdMemoryType::Reserved => Some(efi::RESERVED_MEMORY_TYPE),
+
+        // Other memory types are ignored for purposes of the memory map
+        _ => None,
+    }
+}
+
+/// Converts a GCD descriptor to a memory map descriptor, or returns `None` if the descriptor is not reported in the
+/// memory map. See [get_memory_map_descriptors] for `active_attributes`.
+fn memory_map_descriptor(descriptor: &MemorySpaceDescriptor, active_attributes: bool) -> Option<efi::MemoryDescriptor> {
+    let memory_type = ALLOCATORS
+        .lock()
+        .memory_type_for_handle(descriptor.image_handle)
+        .or_else(|| gcd_memory_map_type(descriptor))?;
+
+    let number_of_pages = descriptor.length >> 12;
+    if number_of_pages == 0 {
+        return None; //skip entries for things smaller than a page
+    }
+    if !descriptor.base_address.is_multiple_of(0x1000) {
+        return None; //skip entries not page aligned.
+    }
+
+    let mut attributes = match active_attributes {
+        true => descriptor.attributes,
+        false => {
+            // When using the capabilities, drop the runtime attribute and
+            // pick it up from the active attributes.
+            (descriptor.capabilities & !(efi::MEMORY_ACCESS_MASK | efi::MEMORY_RUNTIME))
+                | (descriptor.attributes & efi::MEMORY_RUNTIME)
+        }
+    };
+
+    if matches!(memory_type, efi::RUNTIME_SERVICES_CODE | efi::RUNTIME_SERVICES_DATA) {
+        // Add the runtime attribute for runtime services code and data as
+        // higher level code will expect this but it is not explicitly tracked.
+        attributes |= efi::MEMORY_RUNTIME;
+    }
+
+    Some(efi::MemoryDescriptor {
+        r#type: memory_type,
+        physical_start: descriptor.base_address,
+        virtual_start: 0,
+        number_of_pages,
+        attribute: attributes,
+    })
 }
 
 extern "efiapi" fn get_memory_map(
@@ -725,10 +852,11 @@ extern "efiapi" fn get_memory_map(
     // Safety: caller must ensure that memory_map_size is a valid pointer. It is null-checked above.
     let map_size = unsafe { memory_map_size.read_unaligned() };
 
-    let efi_descriptors = match get_memory_map_descriptors(false) {
+    let mut efi_descriptors = match get_memory_map_descriptors(false) {
         Ok(descriptors) => descriptors,
         Err(status) => return status.into(),
     };
+    memory_map_cap::cap_memory_map(&mut efi_descriptors);
 
     assert_ne!(efi_descriptors.len(), 0);
 
@@ -760,13 +888,14 @@ extern "efiapi" fn get_memory_map(
         }
     }
 
-    log::debug!(target: "efi_memory_map", "EFI_MEMORY_MAP: \n{:?}", MemoryDescriptorSlice(&efi_descriptors));
+    log::debug!(target: target::EFI_MEMORY_MAP, "EFI_MEMORY_MAP: \n{:?}", MemoryDescriptorSlice(&efi_descriptors));
 
     efi::Status::SUCCESS
 }
 
 pub fn terminate_memory_map(map_key: usize) -> Result<(), EfiError> {
-    let mm_desc = get_memory_map_descriptors(false)?;
+    let mut mm_desc = get_memory_map_descriptors(false)?;
+    memory_map_cap::cap_memory_map(&mut mm_desc);
     let mm_desc_size = mm_desc.len() * mem::size_of::<efi::MemoryDescriptor>();
     let mm_desc_bytes: &[u8] = unsafe { slice::from_raw_parts(mm_desc.as_ptr() as *const u8, mm_desc_size) };
 
@@ -851,7 +980,8 @@ fn process_hob_allocations(hob_list: &HobList) {
                                 uefi_size_to_pages!(desc.memory_length as usize),
                                 &mut address as *mut efi::PhysicalAddress,
                                 None,
-                            ),
+                            )
+                            .map_err(EfiError::from),
                             GcdMemoryType::NonExistent | GcdMemoryType::Unaccepted => {
                                 // we can't allocate memory in a non-existent or unaccepted memory type
                                 log::error!(
@@ -1011,6 +1141,9 @@ pub fn init_memory_support(hob_list: &HobList) {
     // process pre-DXE allocations from the Hob list
     process_hob_allocations(hob_list);
 
+    // keep the handoff HOB list from being reused until it is relocated.
+    reserve_handoff_hob_list(hob_list);
+
     // After this point the GCD and existing allocations are fully processed and it is safe to arbitrarily allocate.
 
     // If memory type info HOB is available, then pre-allocate the corresponding buckets.
@@ -1064,6 +1197,72 @@ pub fn init_memory_support(hob_list: &HobList) {
     }
 }
 
+// The pages of the HOB list handed off from pre-DXE, reserved until the HOB list is relocated.
+static HANDOFF_HOB_LIST_PAGES: tpl_lock::TplMutex<Option<(efi::PhysicalAddress, usize)>> =
+    tpl_lock::TplMutex::new(TPL_HIGH_LEVEL, None, "HobListPagesLock");
+
+// Reserves the pages of the HOB list handed off from pre-DXE. The HOB list is not described by a memory allocation HOB,
+// so its pages would otherwise be free in the GCD and could be reused before the HOB list is relocated.
+fn reserve_handoff_hob_list(hob_list: &HobList) {
+    let mut reserved_pages = HANDOFF_HOB_LIST_PAGES.lock();
+    *reserved_pages = None;
+
+    let Some(phit) = hob_list.iter().find_map(|hob| match hob {
+        Hob::Handoff(phit) => Some(ptr::from_ref(*phit)),
+        _ => None,
+    }) else {
+        return;
+    };
+
+    let base = phit as efi::PhysicalAddress;
+    // Safety: the PHIT is the start of the HOB list handed off from pre-DXE, which was discovered above.
+    let len = unsafe { hob::get_c_hob_list_size(phit as *const c_void) };
+    let mut address = base & !(UEFI_PAGE_MASK as u64);
+    let pages = uefi_size_to_pages!((base - address) as usize + len);
+    match core_allocate_pages(efi::ALLOCATE_ADDRESS, efi::BOOT_SERVICES_DATA, pages, &mut address, None) {
+        Ok(()) => *reserved_pages = Some((address, pages)),
+        Err(err) => log::warn!("Unable to reserve the pages of the handoff HOB list at {base:#x?}: {err}"),
+    }
+}
+
+/// Relocates the HOB list handed off from pre-DXE at `physical_hob_list` into new pages of `memory_type`, and
+/// releases the pages of the original HOB list if they were reserved.
+///
+/// The PHIT of the relocated HOB list describes the new pages: the free memory range spans from the end of the copied
+/// HOB list to the end of the last page, i.e. the unused tail of the allocation. Returns a pointer to the relocated HOB
+/// list.
+pub fn relocate_hob_list(
+    physical_hob_list: *const c_void,
+    memory_type: efi::MemoryType,
+) -> Result<*mut c_void, CoreError> {
+    // Safety: physical_hob_list is the HOB list handed off to the core, which starts with the PHIT.
+    let len = unsafe { hob::get_c_hob_list_size(physical_hob_list) };
+    let pages = uefi_size_to_pages!(len);
+    let mut address = 0;
+    core_allocate_pages(efi::ALLOCATE_ANY_PAGES, memory_type, pages, &mut address, None)?;
+
+    let relocated_hob_list = address as usize as *mut PhaseHandoffInformationTable;
+    // Safety: the new pages are large enough to hold the HOB list, and do not overlap the reserved original pages.
+    let phit = unsafe {
+        ptr::copy_nonoverlapping(physical_hob_list as *const u8, relocated_hob_list as *mut u8, len);
+        &mut *relocated_hob_list
+    };
+    phit.memory_bottom = address;
+    phit.memory_top = address + (pages * UEFI_PAGE_SIZE) as u64;
+    phit.free_memory_bottom = address + len as u64;
+    phit.free_memory_top = phit.memory_top;
+    phit.end_of_hob_list = address + (len - mem::size_of::<hob::header::Hob>()) as u64;
+
+    let reserved_pages = HANDOFF_HOB_LIST_PAGES.lock().take();
+    if let Some((original, original_pages)) = reserved_pages
+        && let Err(err) = core_free_pages(original, original_pages)
+    {
+        log::error!("Failed to release the pages of the handoff HOB list: {err}");
+    }
+
+    Ok(relocated_hob_list as *mut c_void)
+}
+
 pub fn install_memory_services(bs: &mut efi::BootServices) {
     bs.allocate_pages = allocate_pages;
     bs.free_pages = free_pages;
@@ -1161,6 +1360,89 @@ mod tests {
         .unwrap();
     }
 
+    #[test]
+    fn relocate_hob_list_should_copy_hob_list_and_update_phit() {
+        test_support::with_global_lock(|| {
+            let physical_hob_list = build_test_hob_list(0x400000);
+            unsafe {
+                GCD.reset();
+                gcd::init_gcd(physical_hob_list);
+                test_support::init_test_protocol_db();
+                ALLOCATORS.lock().reset();
+            }
+
+            let mut hob_list = HobList::default();
+            hob_list.discover_hobs(physical_hob_list);
+            init_memory_support(&hob_list);
+
+            let len = unsafe { hob::get_c_hob_list_size(physical_hob_list) };
+            let relocated = relocate_hob_list(physical_hob_list, efi::RUNTIME_SERVICES_DATA).unwrap();
+            assert_ne!(relocated as *const c_void, physical_hob_list);
+
+            // The HOBs after the PHIT are copied as is.
+            let phit_len = mem::size_of::<PhaseHandoffInformationTable>();
+            let original = unsafe { slice::from_raw_parts(physical_hob_list as *const u8, len) };
+            let copy = unsafe { slice::from_raw_parts(relocated as *const u8, len) };
+            assert_eq!(original[phit_len..], copy[phit_len..]);
+
+            // The PHIT describes the new pages.
+            let address = relocated as efi::PhysicalAddress;
+            let phit = unsafe { &*(relocated as *const PhaseHandoffInformationTable) };
+            assert_eq!(phit.memory_bottom, address);
+            assert_eq!(phit.memory_top, address + (uefi_size_to_pages!(len) * UEFI_PAGE_SIZE) as u64);
+            assert_eq!(phit.free_memory_bottom, address + len as u64);
+            assert_eq!(phit.free_memory_top, phit.memory_top);
+            assert_eq!(phit.end_of_hob_list, address + len as u64 - mem::size_of::<header::Hob>() as u64);
+            let mut relocated_hob_list = HobList::default();
+            relocated_hob_list.discover_hobs(relocated);
+            assert_eq!(relocated_hob_list.iter().count(), hob_list.iter().count());
+
+            // The pages are claimed in addition to the runtime services data allocation HOB.
+            let allocators = ALLOCATORS.lock();
+            let allocator = allocators.get_allocator(efi::RUNTIME_SERVICES_DATA).unwrap();
+            assert_eq!(allocator.stats().claimed_pages, 1 + uefi_size_to_pages!(len));
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn relocate_hob_list_should_release_reserved_pages() {
+        test_support::with_global_lock(|| {
+            let physical_hob_list = build_test_hob_list(0x400000);
+            unsafe {
+                GCD.reset();
+                gcd::init_gcd(physical_hob_list);
+                test_support::init_test_protocol_db();
+                ALLOCATORS.lock().reset();
+            }
+
+            let mut hob_list = HobList::default();
+            hob_list.discover_hobs(physical_hob_list);
+            init_memory_support(&hob_list);
+
+            // Place a HOB list in free system memory, as pre-DXE does.
+            let handoff = relocate_hob_list(physical_hob_list, efi::BOOT_SERVICES_DATA).unwrap();
+            let handoff_address = handoff as efi::PhysicalAddress;
+            let handoff_pages = uefi_size_to_pages!(unsafe { hob::get_c_hob_list_size(handoff) });
+            core_free_pages(handoff_address, handoff_pages).unwrap();
+
+            let mut handoff_hob_list = HobList::default();
+            handoff_hob_list.discover_hobs(handoff);
+            reserve_handoff_hob_list(&handoff_hob_list);
+            assert_eq!(*HANDOFF_HOB_LIST_PAGES.lock(), Some((handoff_address, handoff_pages)));
+            let mut address = handoff_address;
+            assert!(
+                core_allocate_pages(efi::ALLOCATE_ADDRESS, efi::LOADER_DATA, handoff_pages, &mut address, None)
+                    .is_err()
+            );
+
+            relocate_hob_list(handoff, efi::BOOT_SERVICES_DATA).unwrap();
+            assert_eq!(*HANDOFF_HOB_LIST_PAGES.lock(), None);
+            core_allocate_pages(efi::ALLOCATE_ADDRESS, efi::LOADER_DATA, handoff_pages, &mut address, None).unwrap();
+        })
+        .unwrap();
+    }
+
     #[test]
     fn init_memory_support_should_process_resource_allocations() {
         test_support::with_global_lock(|| {
@@ -1293,6 +1575,56 @@ mod tests {
         });
     }
 
+    #[test]
+    fn oem_and_os_memory_types_should_be_valid_up_to_the_range_boundaries() {
+        with_locked_state(0x4000000, || {
+            // types between the last spec defined type and the OEM range are illegal.
+            for mem_type in [efi::UNACCEPTED_MEMORY_TYPE + 1, OEM_MEMORY_TYPE_START - 1] {
+                assert_eq!(AllocatorMap::handle_for_memory_type(mem_type), Err(EfiError::InvalidParameter));
+                let mut address: efi::PhysicalAddress = 0;
+                assert_eq!(
+                    allocate_pages(efi::ALLOCATE_ANY_PAGES, mem_type, 1, core::ptr::addr_of_mut!(address)),
+                    efi::Status::INVALID_PARAMETER
+                );
+            }
+
+            for mem_type in
+                [OEM_MEMORY_TYPE_START, OS_MEMORY_TYPE_START - 1, OS_MEMORY_TYPE_START, efi::MemoryType::MAX]
+            {
+                let mut address: efi::PhysicalAddress = 0;
+                assert_eq!(
+                    allocate_pages(efi::ALLOCATE_ANY_PAGES, mem_type, 2, core::ptr::addr_of_mut!(address)),
+                    efi::Status::SUCCESS
+                );
+
+                let allocators = ALLOCATORS.lock();
+                let allocator = allocators.get_allocator(mem_type).unwrap();
+                let handle = allocator.handle();
+                assert_eq!(allocators.memory_type_for_handle(handle), Some(mem_type));
+                drop(allocators);
+                assert_eq!(AllocatorMap::handle_for_memory_type(mem_type).unwrap(), handle);
+
+                // the allocation is reported with its own type in the memory map.
+                let (_, descriptors) =
+                    get_memory_map_descriptors_for_range(address, 2 * UEFI_PAGE_SIZE as u64, true).unwrap();
+                descriptors
+                    .iter()
+                    .find(|x| {
+                        x.physical_start <= address
+                            && x.physical_start + x.number_of_pages * UEFI_PAGE_SIZE as u64
+                                >= address + 2 * UEFI_PAGE_SIZE as u64
+                            && x.r#type == mem_type
+                    })
+                    .expect("Failed to find the OEM/OS allocation.");
+
+                assert_eq!(free_pages(address, 2), efi::Status::SUCCESS);
+            }
+
+            // the memory type information of the system table only tracks spec defined types.
+            assert!(GCD.memory_type_info_table().iter().all(|info| info.memory_type < OEM_MEMORY_TYPE_START));
+        });
+    }
+
     // This test uses an allocation request size of 0x2b2fa0 to test a specific edge case.
     //
     // When core_allocate_pool attempts to allocate 0x2b2fa0 bytes (aligned to 0x2b2fb8 bytes), the allocation
@@ -1371,6 +1703,36 @@ mod tests {
         });
     }
 
+    #[test]
+    fn allocate_pool_aligned_should_honor_alignment() {
+        with_locked_state(0x1000000, || {
+            for alignment in [1, 8, 16, 64, 512, 0x1000] {
+                let buffers: Vec<*mut c_void> = [1, 0x18, 0x100, 0x2000]
+                    .into_iter()
+                    .map(|size| core_allocate_pool_aligned(efi::BOOT_SERVICES_DATA, size, alignment).unwrap())
+                    .collect();
+                for buffer in buffers {
+                    assert!(
+                        (buffer as usize).is_multiple_of(alignment.max(8)),
+                        "{buffer:?} not aligned to {alignment}"
+                    );
+                    assert_eq!(core_free_pool(buffer), Ok(()));
+                }
+            }
+
+            let stats = core_get_allocator(efi::BOOT_SERVICES_DATA).unwrap().stats();
+            assert_eq!(stats.pool_used, 0);
+            assert!(stats.pool_used_high_water_mark >= 0x2000);
+
+            for alignment in [0, 3, 0x2000] {
+                assert_eq!(
+                    core_allocate_pool_aligned(efi::BOOT_SERVICES_DATA, 0x10, alignment),
+                    Err(EfiError::InvalidParameter)
+                );
+            }
+        });
+    }
+
     #[test]
     fn allocate_pages_should_allocate_pages() {
         with_locked_state(0x1000000, || {
@@ -1665,6 +2027,75 @@ mod tests {
         })
     }
 
+    #[test]
+    fn get_memory_map_should_report_memory_allocated_directly_in_the_gcd_as_in_use() {
+        with_locked_state(0x1000000, || {
+            // a driver allocating memory space with AllocateMemorySpace() owns it with its image handle, which the
+            // allocators do not know.
+            let driver_handle = 0x1234 as efi::Handle;
+            let address = GCD
+                .allocate_memory_space(
+                    AllocationStrategy::BottomUp(None),
+                    GcdMemoryType::SystemMemory,
+                    12,
+                    0x4000,
+                    driver_handle,
+                    None,
+                )
+                .unwrap() as u64;
+
+            let (_, descriptors) = get_memory_map_descriptors_for_range(address, 0x4000, true).unwrap();
+            let descriptor = descriptors
+                .iter()
+                .find(|x| {
+                    x.physical_start <= address
+                        && x.physical_start + x.number_of_pages * UEFI_PAGE_SIZE as u64 >= address + 0x4000
+                })
+                .expect("Failed to find the GCD allocation.");
+            assert_eq!(descriptor.r#type, efi::BOOT_SERVICES_DATA);
+
+            // once freed, the memory is reported as free again.
+            GCD.free_memory_space(address as usize, 0x4000).unwrap();
+            let (_, descriptors) = get_memory_map_descriptors_for_range(address, 0x4000, true).unwrap();
+            assert!(descriptors.iter().all(|x| x.r#type == efi::CONVENTIONAL_MEMORY));
+        })
+    }
+
+    #[test]
+    fn gcd_memory_map_type_should_classify_by_gcd_type_and_owner() {
+        let descriptor = |memory_type, image_handle, attributes| MemorySpaceDescriptor {
+            memory_type,
+            image_handle,
+            attributes,
+            base_address: 0x1000,
+            length: 0x1000,
+            ..Default::default()
+        };
+        let owner = 0x1234 as efi::Handle;
+
+        assert_eq!(
+            gcd_memory_map_type(&descriptor(GcdMemoryType::SystemMemory, INVALID_HANDLE, 0)),
+            Some(efi::CONVENTIONAL_MEMORY)
+        );
+        assert_eq!(
+            gcd_memory_map_type(&descriptor(GcdMemoryType::SystemMemory, owner, 0)),
+            Some(efi::BOOT_SERVICES_DATA)
+        );
+        assert_eq!(
+            gcd_memory_map_type(&descriptor(GcdMemoryType::SystemMemory, owner, efi::MEMORY_RUNTIME)),
+            Some(efi::RUNTIME_SERVICES_DATA)
+        );
+        assert_eq!(
+            gcd_memory_map_type(&descriptor(GcdMemoryType::MemoryMappedIo, owner, efi::MEMORY_ISA_VALID)),
+            Some(efi::MEMORY_MAPPED_IO_PORT_SPACE)
+        );
+        assert_eq!(
+            gcd_memory_map_type(&descriptor(GcdMemoryType::Reserved, owner, 0)),
+            Some(efi::RESERVED_MEMORY_TYPE)
+        );
+        assert_eq!(gcd_memory_map_type(&descriptor(GcdMemoryType::NonExistent, INVALID_HANDLE, 0)), None);
+    }
+
     #[test]
     fn terminate_map_should_validate_the_map_key() {
         with_locked_state(0x1000000, || {
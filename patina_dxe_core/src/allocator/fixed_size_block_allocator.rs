@@ -11,7 +11,7 @@
 //!
 
 extern crate alloc;
-use super::{AllocationStrategy, DEFAULT_ALLOCATION_STRATEGY};
+use super::{AllocationStrategy, DEFAULT_ALLOCATION_STRATEGY, poison};
 
 use crate::{gcd::SpinLockedGcd, tpl_lock};
 
@@ -66,6 +66,11 @@ fn list_index(layout: &Layout) -> Option<usize> {
     BLOCK_SIZES.iter().position(|&s| s >= required_block_size)
 }
 
+// Returns the number of pool bytes consumed by an allocation with the given layout.
+fn pool_usage(layout: &Layout) -> usize {
+    list_index(layout).map_or(layout.size(), |index| BLOCK_SIZES[index])
+}
+
 /// Converts the given alignment to a shift value.
 const fn page_shift_from_alignment(alignment: usize) -> Result<usize, EfiError> {
     let shift = alignment.trailing_zeros() as usize;
@@ -106,6 +111,16 @@ impl Iterator for AllocatorIterator {
     }
 }
 
+/// Statistics for one of the fixed-size block lists of the allocator.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BinStatistics {
+    /// The size of the blocks in this bin.
+    pub block_size: usize,
+
+    /// The number of free blocks in this bin.
+    pub free_blocks: usize,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct AllocationStatistics {
     /// The number of calls to `alloc()`.
@@ -131,6 +146,26 @@ pub struct AllocationStatistics {
 
     /// The number of pages claimed for use by this allocator.
     pub claimed_pages: usize,
+
+    /// The number of times the allocator was expanded with additional memory.
+    pub expansion_count: usize,
+
+    /// The number of pool bytes currently allocated, including the rounding up to the fixed-size block sizes.
+    pub pool_used: usize,
+
+    /// The highest value `pool_used` reached.
+    pub pool_used_high_water_mark: usize,
+
+    /// The number of bytes available for pool allocations, in the backing allocators and in the fixed-size block
+    /// lists.
+    ///
+    /// Note: computed when the statistics are queried through [FixedSizeBlockAllocator::stats()].
+    pub free_bytes: usize,
+
+    /// The free blocks of each fixed-size block list.
+    ///
+    /// Note: computed when the statistics are queried through [FixedSizeBlockAllocator::stats()].
+    pub bins: [BinStatistics; BLOCK_SIZES.len()],
 }
 
 impl AllocationStatistics {
@@ -143,8 +178,23 @@ impl AllocationStatistics {
             reserved_size: 0,
             reserved_used: 0,
             claimed_pages: 0,
+            expansion_count: 0,
+            pool_used: 0,
+            pool_used_high_water_mark: 0,
+            free_bytes: 0,
+            bins: Self::empty_bins(),
         }
     }
+
+    const fn empty_bins() -> [BinStatistics; BLOCK_SIZES.len()] {
+        let mut bins = [BinStatistics { block_size: 0, free_blocks: 0 }; BLOCK_SIZES.len()];
+        let mut index = 0;
+        while index < BLOCK_SIZES.len() {
+            bins[index].block_size = BLOCK_SIZES[index];
+            index += 1;
+        }
+        bins
+    }
 }
 
 /// Fixed Size Block Allocator
@@ -244,6 +294,7 @@ impl FixedSizeBlockAllocator {
         }
 
         self.allocators = Some(alloc_node_ptr);
+        self.stats.expansion_count += 1;
 
         if self.in_reserved_range(alloc_node_ptr.addr() as efi::PhysicalAddress) {
             self.stats.reserved_used += new_region.len();
@@ -293,7 +344,7 @@ impl FixedSizeBlockAllocator {
     pub fn alloc(&mut self, layout: Layout) -> Result<NonNull<[u8]>, FixedSizeBlockAllocatorError> {
         self.stats.pool_allocation_calls += 1;
 
-        match list_index(&layout) {
+        let allocation = match list_index(&layout) {
             Some(index) => {
                 match self.list_heads[index].take() {
                     Some(node) => {
@@ -315,7 +366,11 @@ impl FixedSizeBlockAllocator {
                 }
             }
             None => self.fallback_alloc(layout),
-        }
+        }?;
+
+        self.stats.pool_used += pool_usage(&layout);
+        self.stats.pool_used_high_water_mark = max(self.stats.pool_used_high_water_mark, self.stats.pool_used);
+        Ok(allocation)
     }
 
     // deallocates back to the linked-list backing allocator if the size of
@@ -336,6 +391,7 @@ impl FixedSizeBlockAllocator {
     /// Caller must ensure that `ptr` was created by a call to [`Self::alloc`] with the same `layout`.
     pub unsafe fn dealloc(&mut self, ptr: NonNull<u8>, layout: Layout) {
         self.stats.pool_free_calls += 1;
+        self.stats.pool_used = self.stats.pool_used.saturating_sub(pool_usage(&layout));
         match list_index(&layout) {
             Some(index) => {
                 let new_node = BlockListNode { next: self.list_heads[index].take() };
@@ -457,14 +513,29 @@ impl FixedSizeBlockAllocator {
         self.memory_type_info().memory_type
     }
 
-    /// Returns a reference to the allocation stats for this allocator.
-    pub fn stats(&self) -> &AllocationStatistics {
-        &self.stats
+    /// Returns the allocation stats for this allocator, including the current free bytes and free blocks per bin.
+    pub fn stats(&self) -> AllocationStatistics {
+        let mut stats = self.stats;
+        for (bin, head) in stats.bins.iter_mut().zip(self.list_heads.iter()) {
+            let mut node = head.as_deref();
+            while let Some(block) = node {
+                bin.free_blocks += 1;
+                node = block.next.as_deref();
+            }
+        }
+        let heap_free: usize = AllocatorIterator::new(self.allocators)
+            .map(|node| {
+                // This is safe because the node is a valid pointer to an AllocatorListNode
+                unsafe { (*node).allocator.free() }
+            })
+            .sum();
+        stats.free_bytes = heap_free + stats.bins.iter().map(|bin| bin.block_size * bin.free_blocks).sum::<usize>();
+        stats
     }
 
     /// Re-calculates the number of pages allocated for this memory type and updates the memory type info.
     fn update_memory_type_info(&mut self) {
-        let stats = self.stats();
+        let stats = &self.stats;
         let reserved_free = uefi_size_to_pages!(stats.reserved_size - stats.reserved_used);
         let page_count = (stats.claimed_pages - reserved_free) as u32;
         self.memory_type_info_mut().number_of_pages = page_count;
@@ -496,6 +567,9 @@ impl Display for FixedSizeBlockAllocator {
         writeln!(f, "  reserved_size: {}", self.stats.reserved_size)?;
         writeln!(f, "  reserved_used: {}", self.stats.reserved_used)?;
         writeln!(f, "  claimed_pages: {}", self.stats.claimed_pages)?;
+        writeln!(f, "  expansion_count: {}", self.stats.expansion_count)?;
+        writeln!(f, "  pool_used: {}", self.stats.pool_used)?;
+        writeln!(f, "  pool_used_high_water_mark: {}", self.stats.pool_used_high_water_mark)?;
         Ok(())
     }
 }
@@ -647,7 +721,8 @@ impl SpinLockedFixedSizeBlockAllocator {
             Err(EfiError::NotFound)?;
         }
 
-        if self.lock().in_reserved_range(address as efi::PhysicalAddress) {
+        let in_reserved_range = self.lock().in_reserved_range(address as efi::PhysicalAddress);
+        if in_reserved_range {
             self.gcd.free_memory_space_preserving_ownership(address, uefi_pages_to_size!(required_pages)).map_err(
                 |err| match err {
                     EfiError::NotFound => err,
@@ -661,6 +736,11 @@ impl SpinLockedFixedSizeBlockAllocator {
             })?;
         }
 
+        // Pages freed back to the GCD are unmapped once paging is initialized, so they cannot be poisoned.
+        if poison::poison_pages_enabled() && (in_reserved_range || !self.gcd.is_paging_initialized()) {
+            unsafe { poison::poison_freed_range(address..address + uefi_pages_to_size!(required_pages), false) };
+        }
+
         // Notify the FSB that pages were freed for record keeping
         self.lock().notify_pages_freed(address as efi::PhysicalAddress, required_pages);
 
@@ -738,10 +818,17 @@ impl SpinLockedFixedSizeBlockAllocator {
     /// Returns allocation statistics for this allocator.
     #[allow(dead_code)]
     pub fn stats(&self) -> AllocationStatistics {
-        *self.inner.lock().stats()
+        self.inner.lock().stats()
     }
 }
 
+// Checks that the memory of the allocation was not written while it was free, if it was poisoned when freed.
+fn checked_reallocation(allocation: NonNull<[u8]>) -> NonNull<[u8]> {
+    let start = allocation.cast::<u8>().as_ptr() as usize;
+    poison::check_reallocated_range(start..start + allocation.len());
+    allocation
+}
+
 unsafe impl GlobalAlloc for SpinLockedFixedSizeBlockAllocator {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
         match self.allocate(layout) {
@@ -760,7 +847,7 @@ unsafe impl Allocator for SpinLockedFixedSizeBlockAllocator {
     fn allocate(&self, layout: Layout) -> core::result::Result<NonNull<[u8]>, AllocError> {
         let allocation = self.lock().alloc(layout);
         match allocation {
-            Ok(alloc) => Ok(alloc),
+            Ok(alloc) => Ok(checked_reallocation(alloc)),
             Err(FixedSizeBlockAllocatorError::OutOfMemory(additional_mem_required)) => {
                 // Compile-time check to ensure ALIGNMENT is compatible with the alignment requirements
                 // of `expand()` and `page_shift_from_alignment()`
@@ -817,7 +904,7 @@ unsafe impl Allocator for SpinLockedFixedSizeBlockAllocator {
 
                 // Try the allocation one more time
                 match self.lock().alloc(layout) {
-                    Ok(alloc) => Ok(alloc),
+                    Ok(alloc) => Ok(checked_reallocation(alloc)),
                     Err(_) => {
                         debug_assert!(false);
                         Err(AllocError)
@@ -1545,6 +1632,52 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_pool_stats() {
+        with_locked_state(|| {
+            static GCD: SpinLockedGcd = SpinLockedGcd::new(None);
+            let _ = init_gcd(&GCD, 0x1000000);
+
+            let fsb = SpinLockedFixedSizeBlockAllocator::new(
+                &GCD,
+                1 as _,
+                memory_type_info(efi::BOOT_SERVICES_DATA),
+                DEFAULT_PAGE_ALLOCATION_GRANULARITY,
+            );
+
+            let stats = fsb.stats();
+            assert_eq!(stats.expansion_count, 0);
+            assert_eq!(stats.free_bytes, 0);
+            assert!(stats.bins.iter().map(|bin| bin.block_size).eq(BLOCK_SIZES.iter().copied()));
+            assert!(stats.bins.iter().all(|bin| bin.free_blocks == 0));
+
+            // a 64-byte aligned allocation is served from the 64-byte bin.
+            let small = Layout::from_size_align(0x18, 64).unwrap();
+            let large = Layout::from_size_align(0x3000, 0x1000).unwrap();
+            let small_ptr = fsb.allocate(small).unwrap().cast::<u8>();
+            let large_ptr = fsb.allocate(large).unwrap().cast::<u8>();
+            assert!(small_ptr.addr().get().is_multiple_of(64));
+            assert!(large_ptr.addr().get().is_multiple_of(0x1000));
+
+            let stats = fsb.stats();
+            assert_eq!(stats.expansion_count, 1);
+            assert_eq!(stats.pool_used, 64 + 0x3000);
+            assert_eq!(stats.pool_used_high_water_mark, 64 + 0x3000);
+            let free_bytes = stats.free_bytes;
+
+            unsafe {
+                fsb.deallocate(small_ptr, small);
+                fsb.deallocate(large_ptr, large);
+            }
+
+            let stats = fsb.stats();
+            assert_eq!(stats.pool_used, 0);
+            assert_eq!(stats.pool_used_high_water_mark, 64 + 0x3000);
+            assert_eq!(stats.bins[list_index(&small).unwrap()].free_blocks, 1);
+            assert_eq!(stats.free_bytes, free_bytes + 64 + 0x3000);
+        });
+    }
+
     #[test]
     fn test_allocation_stats() {
         with_locked_state(|| {
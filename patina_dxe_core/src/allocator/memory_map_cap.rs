@@ -0,0 +1,192 @@
+//! Memory Map Descriptor Cap
+//!
+//! Some OS loaders read the memory map into a buffer of a fixed size. When the [MemoryMapCapPolicy] sets a maximum
+//! descriptor count, the memory map returned by GetMemoryMap() is coalesced until it fits: adjacent boot services code,
+//! boot services data and conventional memory descriptors with the same attributes are merged, as the OS reclaims all
+//! of them after ExitBootServices(). Free memory merged into boot services memory cannot be allocated by the OS loader
+//! until then, so the merges hiding the fewest free pages are made first, and only as many as needed.
+//!
+//! Allocations are never moved, since their owners hold their addresses, so the other descriptors are reported as they
+//! are. If the memory map still exceeds the cap, it is returned complete and the overflow is logged as an error.
+//!
+//! The memory map used internally, e.g. by the memory map consistency check or for the Memory Attributes Table, is not
+//! coalesced.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use patina::base::UEFI_PAGE_SIZE;
+use r_efi::efi;
+
+/// A configuration struct setting the maximum number of descriptors in the memory map returned by GetMemoryMap().
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use patina_dxe_core::{Core, MemoryMapCapPolicy};
+/// # let physical_hob_list = core::ptr::null();
+///
+/// let core = Core::default()
+///    .init_memory(physical_hob_list)
+///    .with_config(MemoryMapCapPolicy { max_descriptors: 128 })
+///    .start()
+///    .unwrap();
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryMapCapPolicy {
+    /// The maximum number of memory map descriptors. Zero leaves the memory map uncapped.
+    pub max_descriptors: usize,
+}
+
+static MAX_DESCRIPTORS: AtomicUsize = AtomicUsize::new(0);
+// The descriptor count of the last memory map reported over the cap, to log each overflow once.
+static REPORTED_OVERFLOW: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) fn set_memory_map_cap_policy(policy: MemoryMapCapPolicy) {
+    MAX_DESCRIPTORS.store(policy.max_descriptors, Ordering::Relaxed);
+}
+
+/// Coalesces the memory map `descriptors` to the maximum descriptor count of the policy, if any.
+///
+/// The descriptors are merged in place, so this does not allocate and can be used on the memory map returned to the
+/// caller of GetMemoryMap().
+pub(crate) fn cap_memory_map(descriptors: &mut Vec<efi::MemoryDescriptor>) {
+    let max_descriptors = MAX_DESCRIPTORS.load(Ordering::Relaxed);
+    if max_descriptors == 0 || descriptors.len() <= max_descriptors {
+        return;
+    }
+
+    let count = descriptors.len();
+    if coalesce(descriptors, max_descriptors) {
+        return;
+    }
+    if REPORTED_OVERFLOW.swap(descriptors.len(), Ordering::Relaxed) != descriptors.len() {
+        log::error!(
+            "Memory map of {count} descriptors coalesced to {}, exceeding the cap of {max_descriptors} descriptors.",
+            descriptors.len()
+        );
+    }
+}
+
+/// Merges adjacent reclaimable descriptors, hiding the fewest free pages first, until at most `max_descriptors`
+/// remain. Returns whether the descriptors fit.
+fn coalesce(descriptors: &mut Vec<efi::MemoryDescriptor>, max_descriptors: usize) -> bool {
+    while descriptors.len() > max_descriptors {
+        let Some((index, _)) = descriptors
+            .windows(2)
+            .enumerate()
+            .filter_map(|(index, pair)| merge_cost(&pair[0], &pair[1]).map(|cost| (index, cost)))
+            .min_by_key(|&(_, cost)| cost)
+        else {
+            return false;
+        };
+        let next = descriptors.remove(index + 1);
+        let merged = &mut descriptors[index];
+        merged.r#type = merged_type(merged.r#type, next.r#type);
+        merged.number_of_pages += next.number_of_pages;
+    }
+    true
+}
+
+fn is_reclaimable(memory_type: efi::MemoryType) -> bool {
+    matches!(memory_type, efi::BOOT_SERVICES_CODE | efi::BOOT_SERVICES_DATA | efi::CONVENTIONAL_MEMORY)
+}
+
+/// Returns the number of free pages hidden by merging `first` with the following `second` descriptor, or `None` if
+/// they cannot be merged.
+fn merge_cost(first: &efi::MemoryDescriptor, second: &efi::MemoryDescriptor) -> Option<u64> {
+    let contiguous = first.physical_start + first.number_of_pages * UEFI_PAGE_SIZE as u64 == second.physical_start;
+    if !contiguous
+        || first.attribute != second.attribute
+        || !is_reclaimable(first.r#type)
+        || !is_reclaimable(second.r#type)
+    {
+        return None;
+    }
+    match (first.r#type, second.r#type) {
+        (efi::CONVENTIONAL_MEMORY, efi::CONVENTIONAL_MEMORY) => Some(0),
+        (efi::CONVENTIONAL_MEMORY, _) => Some(first.number_of_pages),
+        (_, efi::CONVENTIONAL_MEMORY) => Some(second.number_of_pages),
+        _ => Some(0),
+    }
+}
+
+/// Returns the type reported for two merged reclaimable descriptors.
+fn merged_type(first: efi::MemoryType, second: efi::MemoryType) -> efi::MemoryType {
+    match (first, second) {
+        _ if first == second => first,
+        (efi::CONVENTIONAL_MEMORY, other) | (other, efi::CONVENTIONAL_MEMORY) => other,
+        _ => efi::BOOT_SERVICES_DATA,
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::vec;
+
+    fn descriptor(r#type: efi::MemoryType, physical_start: u64, number_of_pages: u64) -> efi::MemoryDescriptor {
+        efi::MemoryDescriptor { r#type, physical_start, virtual_start: 0, number_of_pages, attribute: efi::MEMORY_WB }
+    }
+
+    // Returns the type, start and page count of each descriptor.
+    fn ranges(descriptors: &[efi::MemoryDescriptor]) -> Vec<(efi::MemoryType, u64, u64)> {
+        descriptors.iter().map(|d| (d.r#type, d.physical_start, d.number_of_pages)).collect()
+    }
+
+    #[test]
+    fn test_coalesce_hides_the_fewest_free_pages() {
+        let mut descriptors = vec![
+            descriptor(efi::BOOT_SERVICES_DATA, 0x1000, 1),
+            descriptor(efi::CONVENTIONAL_MEMORY, 0x2000, 0x10),
+            descriptor(efi::BOOT_SERVICES_CODE, 0x12000, 2),
+            descriptor(efi::CONVENTIONAL_MEMORY, 0x14000, 1),
+            descriptor(efi::BOOT_SERVICES_DATA, 0x15000, 1),
+            descriptor(efi::RUNTIME_SERVICES_DATA, 0x16000, 1),
+            descriptor(efi::CONVENTIONAL_MEMORY, 0x17000, 0x100),
+        ];
+
+        assert!(coalesce(&mut descriptors, 5));
+        assert_eq!(
+            ranges(&descriptors),
+            vec![
+                (efi::BOOT_SERVICES_DATA, 0x1000, 1),
+                (efi::CONVENTIONAL_MEMORY, 0x2000, 0x10),
+                (efi::BOOT_SERVICES_DATA, 0x12000, 4),
+                (efi::RUNTIME_SERVICES_DATA, 0x16000, 1),
+                (efi::CONVENTIONAL_MEMORY, 0x17000, 0x100),
+            ]
+        );
+
+        assert!(coalesce(&mut descriptors, 4));
+        assert_eq!(ranges(&descriptors[..1]), vec![(efi::BOOT_SERVICES_DATA, 0x1000, 0x11)]);
+    }
+
+    #[test]
+    fn test_coalesce_keeps_other_descriptors() {
+        let mut uncached = descriptor(efi::BOOT_SERVICES_DATA, 0x5000, 1);
+        uncached.attribute = efi::MEMORY_UC;
+        let mut descriptors = vec![
+            descriptor(efi::BOOT_SERVICES_DATA, 0x1000, 1),
+            descriptor(efi::LOADER_DATA, 0x2000, 1),
+            descriptor(efi::RUNTIME_SERVICES_CODE, 0x3000, 1),
+            descriptor(efi::CONVENTIONAL_MEMORY, 0x4000, 1),
+            uncached,
+            descriptor(efi::BOOT_SERVICES_DATA, 0x7000, 1),
+        ];
+        let original = ranges(&descriptors);
+
+        // Descriptors of other types, with other attributes or that are not contiguous are never merged.
+        assert!(!coalesce(&mut descriptors, 3));
+        assert_eq!(ranges(&descriptors), original);
+    }
+}
@@ -0,0 +1,218 @@
+//! Freed Memory Poisoning
+//!
+//! Catches use-after-free bugs in the core, components and drivers early. When the [FreePoisoningPolicy] enables it,
+//! freed pool buffers and pages are filled with [POISON], and the pattern is checked when the memory is allocated
+//! again. A write to the memory while it was free panics, reporting the address that was written.
+//!
+//! The most recently freed ranges are tracked, up to [TRACKED_RANGES]. A freed pool buffer that stops being tracked is
+//! checked at that point, as it stays mapped; freed pages are only checked when they are allocated again.
+//!
+//! Once paging is initialized, the pages freed back to the GCD are unmapped (i.e. marked `EFI_MEMORY_RP`), so that an
+//! access to them faults right away. The pages freed back to the reserved range of an allocator stay mapped, so only
+//! these are poisoned once paging is initialized.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use core::{
+    ops::Range,
+    ptr, slice,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use r_efi::efi;
+
+use crate::tpl_lock::TplMutex;
+
+/// The pattern freed memory is filled with.
+const POISON: u8 = 0xaf;
+
+/// The number of freed ranges tracked for a check on reallocation.
+const TRACKED_RANGES: usize = 256;
+
+/// A configuration struct enabling the poisoning of freed memory, to catch use-after-free bugs.
+///
+/// Filling and checking freed memory adds to the time taken by the allocation services, so this is meant for debug
+/// builds of a platform.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use patina_dxe_core::{Core, FreePoisoningPolicy};
+/// # let physical_hob_list = core::ptr::null();
+///
+/// let core = Core::default()
+///    .init_memory(physical_hob_list)
+///    .with_config(FreePoisoningPolicy { pool: true, pages: true })
+///    .start()
+///    .unwrap();
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FreePoisoningPolicy {
+    /// Poison the buffers freed with FreePool().
+    pub pool: bool,
+    /// Poison the pages freed with FreePages() that stay mapped once freed.
+    pub pages: bool,
+}
+
+static POISON_POOL: AtomicBool = AtomicBool::new(false);
+static POISON_PAGES: AtomicBool = AtomicBool::new(false);
+
+static FREED_RANGES: TplMutex<FreedRanges> = TplMutex::new(efi::TPL_HIGH_LEVEL, FreedRanges::new(), "FreedRangesLock");
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FreedRange {
+    range: Range<usize>,
+    // Whether the range stays mapped once it is no longer tracked.
+    check_on_eviction: bool,
+}
+
+// A fixed size ring of freed ranges, as it is updated from within the allocator.
+struct FreedRanges {
+    ranges: [Option<FreedRange>; TRACKED_RANGES],
+    next: usize,
+}
+
+impl FreedRanges {
+    const fn new() -> Self {
+        Self { ranges: [const { None }; TRACKED_RANGES], next: 0 }
+    }
+
+    // Tracks the range, returning the oldest range if it is no longer tracked.
+    fn track(&mut self, freed: FreedRange) -> Option<FreedRange> {
+        let evicted = self.ranges[self.next].replace(freed);
+        self.next = (self.next + 1) % TRACKED_RANGES;
+        evicted
+    }
+
+    // Stops tracking a range overlapping `range`, returning it.
+    fn take_overlapping(&mut self, range: &Range<usize>) -> Option<FreedRange> {
+        self.ranges
+            .iter_mut()
+            .find(|freed| {
+                freed.as_ref().is_some_and(|freed| freed.range.start < range.end && range.start < freed.range.end)
+            })
+            .and_then(Option::take)
+    }
+}
+
+pub(crate) fn set_free_poisoning_policy(policy: FreePoisoningPolicy) {
+    POISON_POOL.store(policy.pool, Ordering::Relaxed);
+    POISON_PAGES.store(policy.pages, Ordering::Relaxed);
+}
+
+/// Returns whether freed pool buffers are poisoned.
+pub(crate) fn poison_pool_enabled() -> bool {
+    POISON_POOL.load(Ordering::Relaxed)
+}
+
+/// Returns whether freed pages are poisoned.
+pub(crate) fn poison_pages_enabled() -> bool {
+    POISON_PAGES.load(Ordering::Relaxed)
+}
+
+/// Fills the freed range with [POISON] and tracks it, so that it is checked when it is allocated again.
+///
+/// `stays_mapped` indicates whether the range can still be accessed once it is no longer tracked, e.g. pool buffers.
+///
+/// ## Safety
+///
+/// `range` must be writable memory that is no longer in use.
+pub(crate) unsafe fn poison_freed_range(range: Range<usize>, stays_mapped: bool) {
+    if range.is_empty() {
+        return;
+    }
+    unsafe { ptr::write_bytes(range.start as *mut u8, POISON, range.len()) };
+
+    let evicted = FREED_RANGES.lock().track(FreedRange { range, check_on_eviction: stays_mapped });
+    if let Some(evicted) = evicted
+        && evicted.check_on_eviction
+    {
+        unsafe { check_poison(&evicted.range, evicted.range.clone()) };
+    }
+}
+
+/// Checks that the freed memory in `range` was not written while it was free, now that it is allocated again.
+///
+/// ## Panics
+///
+/// Panics if the freed memory was written.
+pub(crate) fn check_reallocated_range(range: Range<usize>) {
+    if !poison_pool_enabled() && !poison_pages_enabled() {
+        return;
+    }
+    // The lock is released before checking, as the check panics on a write.
+    while let Some(freed) = FREED_RANGES.lock().take_overlapping(&range) {
+        let overlap = freed.range.start.max(range.start)..freed.range.end.min(range.end);
+        // Safety: the overlap was poisoned when freed, and has just been allocated so it is mapped.
+        unsafe { check_poison(&freed.range, overlap) };
+    }
+}
+
+// Panics if `checked`, part of the `freed` range, does not hold the poison pattern.
+unsafe fn check_poison(freed: &Range<usize>, checked: Range<usize>) {
+    let bytes = unsafe { slice::from_raw_parts(checked.start as *const u8, checked.len()) };
+    if let Some(offset) = bytes.iter().position(|byte| *byte != POISON) {
+        let address = checked.start + offset;
+        panic!(
+            "Use after free: {address:#x} was written to {:#x?} while free, {:#x} bytes into the range {:#x}..{:#x}.",
+            bytes[offset],
+            address - freed.start,
+            freed.start,
+            freed.end
+        );
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use std::vec;
+
+    fn freed(range: Range<usize>) -> FreedRange {
+        FreedRange { range, check_on_eviction: true }
+    }
+
+    #[test]
+    fn freed_ranges_should_evict_the_oldest_range() {
+        let mut ranges = FreedRanges::new();
+        for index in 0..TRACKED_RANGES {
+            assert_eq!(ranges.track(freed(index * 0x10..index * 0x10 + 0x10)), None);
+        }
+        assert_eq!(ranges.track(freed(0x10000..0x10010)), Some(freed(0..0x10)));
+        assert_eq!(ranges.track(freed(0x10010..0x10020)), Some(freed(0x10..0x20)));
+    }
+
+    #[test]
+    fn freed_ranges_should_return_each_overlapping_range_once() {
+        let mut ranges = FreedRanges::new();
+        ranges.track(freed(0x1000..0x1100));
+        ranges.track(freed(0x1100..0x1200));
+        ranges.track(freed(0x2000..0x2100));
+
+        assert_eq!(ranges.take_overlapping(&(0x1200..0x2000)), None);
+        assert_eq!(ranges.take_overlapping(&(0x10f0..0x1110)), Some(freed(0x1000..0x1100)));
+        assert_eq!(ranges.take_overlapping(&(0x10f0..0x1110)), Some(freed(0x1100..0x1200)));
+        assert_eq!(ranges.take_overlapping(&(0x10f0..0x1110)), None);
+        assert_eq!(ranges.take_overlapping(&(0x20ff..0x2100)), Some(freed(0x2000..0x2100)));
+    }
+
+    #[test]
+    fn check_poison_should_panic_on_a_write_after_free() {
+        let mut buffer = vec![POISON; 0x100];
+        let range = buffer.as_ptr() as usize..buffer.as_ptr() as usize + buffer.len();
+        unsafe { check_poison(&range, range.clone()) };
+
+        buffer[0x80] = 0;
+        let range = buffer.as_ptr() as usize..buffer.as_ptr() as usize + buffer.len();
+        // Only the part of the range being reallocated is checked.
+        unsafe { check_poison(&range, range.start..range.start + 0x80) };
+        let result = std::panic::catch_unwind(|| unsafe { check_poison(&range, range.clone()) });
+        assert!(result.is_err());
+    }
+}
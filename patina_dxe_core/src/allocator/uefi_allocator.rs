@@ -9,24 +9,27 @@
 //! SPDX-License-Identifier: Apache-2.0
 //!
 use crate::gcd::SpinLockedGcd;
-use patina::error::EfiError;
+use patina::{base::UEFI_PAGE_SIZE, error::EfiError};
 use patina_pi::hob::EFiMemoryTypeInformation;
 use r_efi::efi;
 
 use super::{
-    AllocationStrategy,
+    AllocationStrategy, OEM_MEMORY_TYPE_START, OS_MEMORY_TYPE_START,
     fixed_size_block_allocator::{AllocationStatistics, SpinLockedFixedSizeBlockAllocator},
+    poison,
 };
 use core::{
     alloc::{Allocator, GlobalAlloc, Layout},
     ffi::c_void,
     fmt::{self, Display},
+    mem::size_of,
     ops::Range,
     ptr::NonNull,
 };
 
 const POOL_SIG: u32 = 0x04151980; //arbitrary number.
-const UEFI_POOL_ALIGN: usize = 8; //per UEFI spec.
+pub(crate) const UEFI_POOL_ALIGN: usize = 8; //per UEFI spec.
+const MAX_POOL_ALIGN: usize = UEFI_PAGE_SIZE; //larger alignments should use page allocations.
 
 struct AllocationInfo {
     signature: u32,
@@ -106,33 +109,45 @@ impl UefiAllocator {
             .map(|range| range.start as efi::PhysicalAddress..range.end as efi::PhysicalAddress)
     }
 
-    /// Allocates a buffer to satisfy `size` and returns in `buffer`.
+    /// Allocates a buffer to satisfy `size` aligned to `alignment` and returns in `buffer`.
+    ///
+    /// `alignment` must be a power of two no larger than a page; alignments smaller than the UEFI pool alignment (8)
+    /// are raised to it. The pool header is placed immediately before the buffer, so [`Self::free_pool`] frees
+    /// aligned and unaligned allocations alike.
+    ///
+    /// ## Errors
+    ///
+    /// Returns [`EfiError::InvalidParameter`] if `alignment` is not a power of two or is larger than a page.
+    /// Returns [`EfiError::OutOfResources`] if the allocation cannot be satisfied.
     ///
     /// # Safety
     /// Buffer input must be a valid memory location to write the allocation to.
     ///
     /// Memory allocated by this routine should be freed by [`Self::free_pool`]
-    pub unsafe fn allocate_pool(&self, size: usize, buffer: *mut *mut c_void) -> Result<(), EfiError> {
-        let mut allocation_info = AllocationInfo {
-            signature: POOL_SIG,
-            memory_type: self.memory_type(),
-            layout: Layout::new::<AllocationInfo>(),
-        };
-        let offset: usize;
-        (allocation_info.layout, offset) = allocation_info
-            .layout
+    pub unsafe fn allocate_pool_aligned(
+        &self,
+        size: usize,
+        alignment: usize,
+        buffer: *mut *mut c_void,
+    ) -> Result<(), EfiError> {
+        if !alignment.is_power_of_two() || alignment > MAX_POOL_ALIGN {
+            return Err(EfiError::InvalidParameter);
+        }
+
+        let (layout, offset) = Layout::new::<AllocationInfo>()
             .extend(
-                Layout::from_size_align(size, UEFI_POOL_ALIGN)
-                    .unwrap_or_else(|err| panic!("Allocation layout error: {err:#?}")),
+                Layout::from_size_align(size, alignment.max(UEFI_POOL_ALIGN)).map_err(|_| EfiError::OutOfResources)?,
             )
-            .unwrap_or_else(|err| panic!("Allocation layout error: {err:#?}"));
+            .map_err(|_| EfiError::OutOfResources)?;
+        let allocation_info = AllocationInfo { signature: POOL_SIG, memory_type: self.memory_type(), layout };
 
         match self.allocator.allocate(allocation_info.layout) {
             Ok(ptr) => {
-                let alloc_info_ptr = ptr.cast::<AllocationInfo>().as_ptr();
+                let buffer_ptr = ptr.as_ptr() as *mut u8 as usize + offset;
+                let alloc_info_ptr = (buffer_ptr - size_of::<AllocationInfo>()) as *mut AllocationInfo;
                 unsafe {
                     alloc_info_ptr.write(allocation_info);
-                    buffer.write((ptr.as_ptr() as *mut u8 as usize + offset) as *mut c_void);
+                    buffer.write(buffer_ptr as *mut c_void);
                 }
                 Ok(())
             }
@@ -140,22 +155,16 @@ impl UefiAllocator {
         }
     }
 
-    /// Frees a buffer allocated by [`Self::allocate_pool`]
+    /// Frees a buffer allocated by [`Self::allocate_pool_aligned`]
     ///
     /// ## Safety
     ///
-    /// Caller must guarantee that `buffer` was originally allocated by [`Self::allocate_pool`]
+    /// Caller must guarantee that `buffer` was originally allocated by [`Self::allocate_pool_aligned`]
     pub unsafe fn free_pool(&self, buffer: *mut c_void) -> Result<(), EfiError> {
-        let (_, offset) = Layout::new::<AllocationInfo>()
-            .extend(
-                Layout::from_size_align(0, UEFI_POOL_ALIGN)
-                    .unwrap_or_else(|err| panic!("Allocation layout error: {err:#?}")),
-            )
-            .unwrap_or_else(|err| panic!("Allocation layout error: {err:#?}"));
-
         //TODO: trusting that "buffer" is legit is pretty naive - but performant. Presently the allocator doesn't have
         //tracking mechanisms that permit the validation of the pointer (hence the unsafe).
-        let allocation_info: *mut AllocationInfo = ((buffer as usize) - offset) as *mut AllocationInfo;
+        let allocation_info: *mut AllocationInfo =
+            ((buffer as usize) - size_of::<AllocationInfo>()) as *mut AllocationInfo;
 
         //must be true for any pool allocation
         if unsafe { (*allocation_info).signature } != POOL_SIG {
@@ -170,8 +179,18 @@ impl UefiAllocator {
         unsafe {
             (*allocation_info).signature = 0;
         }
-        if let Some(non_null_ptr) = NonNull::new(allocation_info as *mut u8) {
-            unsafe { self.allocator.deallocate(non_null_ptr, (*allocation_info).layout) };
+        // the allocation starts at the header, padded to the alignment of the buffer.
+        let layout = unsafe { (*allocation_info).layout };
+        let (_, offset) = Layout::new::<AllocationInfo>()
+            .extend(Layout::from_size_align(0, layout.align()).map_err(|_| EfiError::InvalidParameter)?)
+            .map_err(|_| EfiError::InvalidParameter)?;
+        if poison::poison_pool_enabled() {
+            // the header is left as is, as the allocator keeps its free list in the start of freed blocks.
+            let end = (buffer as usize) - offset + layout.size();
+            unsafe { poison::poison_freed_range(buffer as usize..end, true) };
+        }
+        if let Some(non_null_ptr) = NonNull::new(((buffer as usize) - offset) as *mut u8) {
+            unsafe { self.allocator.deallocate(non_null_ptr, layout) };
         } else {
             return Err(EfiError::InvalidParameter);
         }
@@ -253,6 +272,9 @@ fn string_for_memory_type(memory_type: efi::MemoryType) -> &'static str {
         efi::RUNTIME_SERVICES_DATA => "RuntimeServices Data",
         efi::ACPI_RECLAIM_MEMORY => "ACPI Reclaim",
         efi::ACPI_MEMORY_NVS => "ACPI NVS",
+        efi::RESERVED_MEMORY_TYPE => "Reserved",
+        OEM_MEMORY_TYPE_START..OS_MEMORY_TYPE_START => "OEM Reserved",
+        OS_MEMORY_TYPE_START.. => "OS Reserved",
         _ => "Unknown",
     }
 }
@@ -337,7 +359,10 @@ mod tests {
                 );
 
                 let mut buffer: *mut c_void = core::ptr::null_mut();
-                assert!(unsafe { ua.allocate_pool(0x1000, core::ptr::addr_of_mut!(buffer)) }.is_ok());
+                assert!(
+                    unsafe { ua.allocate_pool_aligned(0x1000, UEFI_POOL_ALIGN, core::ptr::addr_of_mut!(buffer)) }
+                        .is_ok()
+                );
                 assert!(buffer as u64 > base);
                 assert!((buffer as u64) < base + 0x400000);
 
@@ -359,6 +384,49 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_allocate_pool_aligned() {
+        with_granularity_modulation(|granularity| {
+            with_locked_state(|| {
+                static GCD: SpinLockedGcd = SpinLockedGcd::new(None);
+
+                init_gcd(&GCD, 0x400000);
+
+                let ua = UefiAllocator::new(
+                    &GCD,
+                    NonNull::from_ref(GCD.memory_type_info(efi::BOOT_SERVICES_DATA)),
+                    1 as _,
+                    granularity,
+                );
+
+                let mut buffer: *mut c_void = core::ptr::null_mut();
+                for alignment in [16, 64, 256, SIZE_4KB] {
+                    assert!(
+                        unsafe { ua.allocate_pool_aligned(0x40, alignment, core::ptr::addr_of_mut!(buffer)) }.is_ok()
+                    );
+                    assert!((buffer as usize).is_multiple_of(alignment));
+
+                    let allocation_info =
+                        unsafe { &*((buffer as usize - size_of::<AllocationInfo>()) as *const AllocationInfo) };
+                    assert_eq!(allocation_info.signature, POOL_SIG);
+                    assert_eq!(allocation_info.layout.align(), alignment);
+
+                    assert!(unsafe { ua.free_pool(buffer) }.is_ok());
+                }
+                assert_eq!(ua.stats().pool_used, 0);
+
+                assert_eq!(
+                    unsafe { ua.allocate_pool_aligned(0x40, 48, core::ptr::addr_of_mut!(buffer)) },
+                    Err(EfiError::InvalidParameter)
+                );
+                assert_eq!(
+                    unsafe { ua.allocate_pool_aligned(0x40, SIZE_4KB * 2, core::ptr::addr_of_mut!(buffer)) },
+                    Err(EfiError::InvalidParameter)
+                );
+            });
+        });
+    }
+
     #[test]
     fn test_free_pool() {
         with_granularity_modulation(|granularity| {
@@ -375,7 +443,10 @@ mod tests {
                 );
 
                 let mut buffer: *mut c_void = core::ptr::null_mut();
-                assert!(unsafe { ua.allocate_pool(0x1000, core::ptr::addr_of_mut!(buffer)) }.is_ok());
+                assert!(
+                    unsafe { ua.allocate_pool_aligned(0x1000, UEFI_POOL_ALIGN, core::ptr::addr_of_mut!(buffer)) }
+                        .is_ok()
+                );
 
                 assert!(unsafe { ua.free_pool(buffer) }.is_ok());
 
@@ -393,7 +464,10 @@ mod tests {
                 }
 
                 let prev_buffer = buffer;
-                assert!(unsafe { ua.allocate_pool(0x1000, core::ptr::addr_of_mut!(buffer)) }.is_ok());
+                assert!(
+                    unsafe { ua.allocate_pool_aligned(0x1000, UEFI_POOL_ALIGN, core::ptr::addr_of_mut!(buffer)) }
+                        .is_ok()
+                );
                 assert!(buffer as u64 > base);
                 assert!((buffer as u64) < base + 0x400000);
                 assert_eq!(buffer, prev_buffer);
@@ -558,7 +632,10 @@ mod tests {
                     "  page_free_calls: 0\n",
                     "  reserved_size: 0\n",
                     "  reserved_used: 0\n",
-                    "  claimed_pages: 0\n"
+                    "  claimed_pages: 0\n",
+                    "  expansion_count: 0\n",
+                    "  pool_used: 0\n",
+                    "  pool_used_high_water_mark: 0\n"
                 )
             );
         });
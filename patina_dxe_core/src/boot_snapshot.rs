@@ -0,0 +1,538 @@
+//! DXE Core Boot Snapshot
+//!
+//! Summarizes each boot in a [BootSnapshot] at ReadyToBoot when the platform provides a [BootSnapshotPolicy]: the size
+//! of the handle database, the device paths installed on the handles, and the time spent loading and starting each
+//! driver dispatched from the firmware volumes. If a [BootSnapshotStore] service is registered, the snapshot of the
+//! previous boot is loaded and compared with the current one, so that drivers that became slower and devices that
+//! disappeared are flagged, e.g. to detect boot time regressions across a fleet, and the current snapshot replaces it.
+//!
+//! The flagged [BootRegression]s are logged and returned by [boot_regressions]. [BootSnapshot::to_bytes] serializes a
+//! snapshot, so that a store can keep it in a buffer preserved across warm resets or in a variable.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::{string::String, vec::Vec};
+use core::{ffi::c_void, time::Duration};
+
+use mu_rust_helpers::guid::guid_fmt;
+use patina::component::service::Service;
+use patina_internal_device_path::{DevicePathWalker, device_path_as_slice};
+use r_efi::efi;
+use scroll::{LE, Pread};
+
+use crate::{
+    dispatcher::{DriverDispatchRecord, DriverOutcome},
+    events::EVENT_DB,
+    protocols::PROTOCOL_DB,
+    tpl_lock::TplMutex,
+};
+
+/// The signature of a serialized [BootSnapshot], `"BSNP"`.
+pub const BOOT_SNAPSHOT_SIGNATURE: u32 = u32::from_le_bytes(*b"BSNP");
+
+/// The revision of the serialized [BootSnapshot] layout.
+pub const BOOT_SNAPSHOT_REVISION: u32 = 1;
+
+/// The size of the header of a serialized [BootSnapshot]: the signature, the revision, the handle and protocol counts,
+/// and the number of drivers and devices.
+const HEADER_SIZE: usize = 6 * size_of::<u32>();
+
+/// The size of the header of a device path node: the type, the sub-type and the length.
+const NODE_HEADER_SIZE: usize = 4;
+
+/// A configuration struct enabling the boot snapshot taken at ReadyToBoot, and selecting when a driver is flagged as
+/// slower than during the previous boot. The snapshot is not taken unless this configuration is provided.
+///
+/// A driver is flagged when the time spent loading and starting it increased by more than `slowdown_percent` of the
+/// time of the previous boot, and by at least `min_slowdown`, so that the jitter of short drivers is not flagged.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use core::time::Duration;
+/// use patina_dxe_core::{BootSnapshotPolicy, Core};
+/// # let physical_hob_list = core::ptr::null();
+///
+/// let policy = BootSnapshotPolicy { slowdown_percent: 25, min_slowdown: Duration::from_millis(5) };
+/// let core = Core::default()
+///    .init_memory(physical_hob_list)
+///    .with_config(policy)
+///    .start()
+///    .unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BootSnapshotPolicy {
+    /// The increase of the time spent on a driver, in percent of the time of the previous boot, above which the driver
+    /// is flagged as slower.
+    pub slowdown_percent: u32,
+    /// The minimum increase of the time spent on a driver for it to be flagged as slower.
+    pub min_slowdown: Duration,
+}
+
+impl Default for BootSnapshotPolicy {
+    fn default() -> Self {
+        Self { slowdown_percent: 50, min_slowdown: Duration::from_millis(10) }
+    }
+}
+
+impl BootSnapshotPolicy {
+    /// Returns whether a driver that took `previous` during the previous boot and `current` during this boot is slower.
+    fn is_slowdown(&self, previous: Duration, current: Duration) -> bool {
+        let slowdown = current.saturating_sub(previous);
+        slowdown >= self.min_slowdown && slowdown.as_nanos() * 100 > previous.as_nanos() * self.slowdown_percent as u128
+    }
+}
+
+/// A persistent store for the [BootSnapshot] of the previous boot.
+///
+/// The snapshot is loaded and saved once, at ReadyToBoot, so the store may use boot services available at TPL_CALLBACK,
+/// e.g. the variable services.
+pub trait BootSnapshotStore {
+    /// Returns the snapshot saved during the previous boot, or `None` if there is none.
+    fn load(&self) -> Option<BootSnapshot>;
+    /// Saves `snapshot`, replacing the previous one.
+    fn save(&self, snapshot: &BootSnapshot) -> patina::error::Result<()>;
+}
+
+/// The time spent loading and starting a driver dispatched from a firmware volume.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DriverTiming {
+    /// The file name of the driver.
+    pub file_name: efi::Guid,
+    /// The time spent loading and starting the driver.
+    pub elapsed: Duration,
+}
+
+/// A summary of the handle database and of the driver dispatch of a boot, taken at ReadyToBoot.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct BootSnapshot {
+    /// The number of handles in the handle database.
+    pub handle_count: u32,
+    /// The number of protocol interfaces installed on the handles.
+    pub protocol_count: u32,
+    /// The drivers whose entry point returned success, in the order they were started.
+    pub drivers: Vec<DriverTiming>,
+    /// The device paths installed on the handles, in binary form.
+    pub devices: Vec<Vec<u8>>,
+}
+
+/// A difference from the previous boot flagged by the comparison of the boot snapshots.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BootRegression {
+    /// The driver took longer to load and start than during the previous boot, as selected by the [BootSnapshotPolicy].
+    SlowDriver {
+        /// The file name of the driver.
+        file_name: efi::Guid,
+        /// The time spent on the driver during the previous boot.
+        previous: Duration,
+        /// The time spent on the driver during this boot.
+        current: Duration,
+    },
+    /// The driver was started successfully during the previous boot, but not during this boot.
+    MissingDriver(efi::Guid),
+    /// The device path was installed during the previous boot, but not during this boot.
+    MissingDevice(Vec<u8>),
+}
+
+impl BootSnapshot {
+    /// Serializes the snapshot, in little endian.
+    ///
+    /// The header holds [BOOT_SNAPSHOT_SIGNATURE], [BOOT_SNAPSHOT_REVISION], the handle and protocol counts, and the
+    /// number of drivers and devices, as `u32`. It is followed by the file name and the elapsed nanoseconds (`u64`) of
+    /// each driver, then by the size (`u32`) and the bytes of each device path.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for value in [
+            BOOT_SNAPSHOT_SIGNATURE,
+            BOOT_SNAPSHOT_REVISION,
+            self.handle_count,
+            self.protocol_count,
+            self.drivers.len() as u32,
+            self.devices.len() as u32,
+        ] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        for driver in &self.drivers {
+            bytes.extend_from_slice(driver.file_name.as_bytes());
+            let elapsed = u64::try_from(driver.elapsed.as_nanos()).unwrap_or(u64::MAX);
+            bytes.extend_from_slice(&elapsed.to_le_bytes());
+        }
+        for device in &self.devices {
+            bytes.extend_from_slice(&(device.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(device);
+        }
+        bytes
+    }
+
+    /// Deserializes a snapshot serialized by [BootSnapshot::to_bytes].
+    ///
+    /// Returns `None` if `bytes` is not a snapshot of the current revision, or if a device path is malformed.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let offset = &mut 0;
+        let read_u32 = |offset: &mut usize| bytes.gread_with::<u32>(offset, LE).ok();
+        if read_u32(offset)? != BOOT_SNAPSHOT_SIGNATURE || read_u32(offset)? != BOOT_SNAPSHOT_REVISION {
+            return None;
+        }
+        let handle_count = read_u32(offset)?;
+        let protocol_count = read_u32(offset)?;
+        let driver_count = read_u32(offset)? as usize;
+        let device_count = read_u32(offset)? as usize;
+
+        let mut drivers = Vec::new();
+        for _ in 0..driver_count {
+            let file_name = efi::Guid::from_bytes(bytes.get(*offset..*offset + 16)?.try_into().ok()?);
+            *offset += 16;
+            let elapsed = Duration::from_nanos(bytes.gread_with::<u64>(offset, LE).ok()?);
+            drivers.push(DriverTiming { file_name, elapsed });
+        }
+
+        let mut devices = Vec::new();
+        for _ in 0..device_count {
+            let size = read_u32(offset)? as usize;
+            let device = bytes.get(*offset..offset.checked_add(size)?)?;
+            if !is_well_formed_device_path(device) {
+                return None;
+            }
+            *offset += size;
+            devices.push(device.to_vec());
+        }
+
+        Some(Self { handle_count, protocol_count, drivers, devices })
+    }
+
+    /// Returns the differences from the `previous` snapshot flagged with `policy`: the drivers that became slower or
+    /// are no longer started, then the devices that disappeared.
+    pub fn regressions(&self, previous: &BootSnapshot, policy: &BootSnapshotPolicy) -> Vec<BootRegression> {
+        let mut regressions = Vec::new();
+        for driver in &previous.drivers {
+            match self.drivers.iter().find(|current| current.file_name == driver.file_name) {
+                Some(current) if policy.is_slowdown(driver.elapsed, current.elapsed) => {
+                    regressions.push(BootRegression::SlowDriver {
+                        file_name: driver.file_name,
+                        previous: driver.elapsed,
+                        current: current.elapsed,
+                    });
+                }
+                Some(_) => (),
+                None => regressions.push(BootRegression::MissingDriver(driver.file_name)),
+            }
+        }
+        regressions.extend(
+            previous
+                .devices
+                .iter()
+                .filter(|device| !self.devices.contains(device))
+                .map(|device| BootRegression::MissingDevice(device.clone())),
+        );
+        regressions
+    }
+}
+
+/// Returns whether `bytes` is exactly one device path, with nodes of valid lengths and ending with an end node.
+fn is_well_formed_device_path(bytes: &[u8]) -> bool {
+    let mut offset = 0;
+    while let Some(header) = bytes.get(offset..offset + NODE_HEADER_SIZE) {
+        let length = u16::from_le_bytes([header[2], header[3]]) as usize;
+        if length < NODE_HEADER_SIZE {
+            return false;
+        }
+        offset += length;
+        if header[0] == efi::protocols::device_path::TYPE_END
+            && header[1] == efi::protocols::device_path::End::SUBTYPE_ENTIRE
+        {
+            return offset == bytes.len();
+        }
+    }
+    false
+}
+
+/// Formats a well-formed device path for the log.
+fn device_path_string(device: &[u8]) -> String {
+    // Safety: the device paths of a snapshot are well-formed, they are checked when a snapshot is deserialized.
+    String::from(unsafe { DevicePathWalker::new(device.as_ptr() as *const efi::protocols::device_path::Protocol) })
+}
+
+struct SnapshotState {
+    policy: BootSnapshotPolicy,
+    store: Option<Service<dyn BootSnapshotStore>>,
+    drivers: Vec<DriverTiming>,
+    regressions: Vec<BootRegression>,
+}
+
+unsafe impl Send for SnapshotState {}
+
+static SNAPSHOT_STATE: TplMutex<Option<SnapshotState>> = TplMutex::new(efi::TPL_NOTIFY, None, "Boot Snapshot");
+
+/// Enables the boot snapshot with `policy`, comparing it with the snapshot of the previous boot loaded from `store`.
+pub fn init_boot_snapshot_support(policy: BootSnapshotPolicy, store: Option<Service<dyn BootSnapshotStore>>) {
+    *SNAPSHOT_STATE.lock() = Some(SnapshotState { policy, store, drivers: Vec::new(), regressions: Vec::new() });
+
+    if let Err(status) = EVENT_DB.create_event(
+        efi::EVT_NOTIFY_SIGNAL,
+        efi::TPL_CALLBACK,
+        Some(capture_boot_snapshot_event_wrapper),
+        None,
+        Some(efi::EVENT_GROUP_READY_TO_BOOT),
+    ) {
+        log::error!("Failed to register an event at Ready to Boot to take the boot snapshot! {status:#X?}");
+    }
+}
+
+/// Records the time spent on the drivers whose entry point returned success, from the records of the dispatch report.
+pub fn record_driver_timings(records: &[DriverDispatchRecord]) {
+    let mut state = SNAPSHOT_STATE.lock();
+    let Some(state) = state.as_mut() else {
+        return;
+    };
+    for record in records {
+        if matches!(record.outcome, DriverOutcome::Started(status) if !status.is_error()) {
+            state.drivers.push(DriverTiming { file_name: record.file_name, elapsed: record.elapsed });
+        }
+    }
+}
+
+/// Returns the differences from the previous boot flagged at ReadyToBoot, empty until then.
+pub fn boot_regressions() -> Vec<BootRegression> {
+    SNAPSHOT_STATE.lock().as_ref().map(|state| state.regressions.clone()).unwrap_or_default()
+}
+
+extern "efiapi" fn capture_boot_snapshot_event_wrapper(event: efi::Event, _context: *mut c_void) {
+    capture_boot_snapshot();
+
+    if let Err(status) = EVENT_DB.close_event(event) {
+        log::error!("Failed to close boot snapshot ready to boot event with status {status:#X?}.");
+    }
+}
+
+/// Summarizes the handle database.
+fn snapshot_handle_database(drivers: Vec<DriverTiming>) -> BootSnapshot {
+    let handles = PROTOCOL_DB.locate_handles(None).unwrap_or_default();
+    let mut snapshot = BootSnapshot { handle_count: handles.len() as u32, drivers, ..Default::default() };
+    for handle in handles {
+        snapshot.protocol_count +=
+            PROTOCOL_DB.get_protocols_on_handle(handle).map_or(0, |protocols| protocols.len()) as u32;
+        if let Ok(device_path) =
+            PROTOCOL_DB.get_interface_for_handle(handle, efi::protocols::device_path::PROTOCOL_GUID)
+            && !device_path.is_null()
+            && let Ok(device_path) = device_path_as_slice(device_path as *const efi::protocols::device_path::Protocol)
+        {
+            snapshot.devices.push(device_path.to_vec());
+        }
+    }
+    snapshot
+}
+
+/// Takes the snapshot of this boot, compares it with the snapshot of the previous boot, and saves it.
+fn capture_boot_snapshot() {
+    let (policy, store, drivers) = {
+        let mut state = SNAPSHOT_STATE.lock();
+        let Some(state) = state.as_mut() else {
+            return;
+        };
+        (state.policy, state.store.clone(), core::mem::take(&mut state.drivers))
+    };
+
+    let snapshot = snapshot_handle_database(drivers);
+    log::info!(
+        "Boot snapshot: {} handles, {} protocols, {} drivers started, {} devices.",
+        snapshot.handle_count,
+        snapshot.protocol_count,
+        snapshot.drivers.len(),
+        snapshot.devices.len()
+    );
+    let Some(store) = store else {
+        log::debug!("Boot snapshot: no store registered, the snapshot is not compared with the previous boot.");
+        return;
+    };
+
+    let regressions = match store.load() {
+        Some(previous) => snapshot.regressions(&previous, &policy),
+        None => {
+            log::info!("Boot snapshot: no snapshot of the previous boot to compare with.");
+            Vec::new()
+        }
+    };
+    for regression in &regressions {
+        match regression {
+            BootRegression::SlowDriver { file_name, previous, current } => log::warn!(
+                "Boot snapshot: driver {:?} took {current:?}, {previous:?} during the previous boot.",
+                guid_fmt!(file_name)
+            ),
+            BootRegression::MissingDriver(file_name) => {
+                log::warn!("Boot snapshot: driver {:?} started during the previous boot only.", guid_fmt!(file_name))
+            }
+            BootRegression::MissingDevice(device) => {
+                log::warn!("Boot snapshot: device {} found during the previous boot only.", device_path_string(device))
+            }
+        }
+    }
+
+    if let Err(err) = store.save(&snapshot) {
+        log::error!("Failed to save the boot snapshot: {err:?}");
+    }
+    if let Some(state) = SNAPSHOT_STATE.lock().as_mut() {
+        state.regressions = regressions;
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use alloc::{boxed::Box, vec};
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::test_support;
+
+    const DRIVER_A: efi::Guid = efi::Guid::from_fields(0xa, 0, 0, 0, 0, &[0; 6]);
+    const DRIVER_B: efi::Guid = efi::Guid::from_fields(0xb, 0, 0, 0, 0, &[0; 6]);
+
+    // PciRoot(0x0)/Pci(0x1,0x0)
+    const DEVICE_A: [u8; 22] = [
+        0x02, 0x01, 0x0c, 0x00, 0xd0, 0x41, 0x03, 0x0a, 0x00, 0x00, 0x00, 0x00, 0x01, 0x01, 0x06, 0x00, 0x00, 0x01,
+        0x7f, 0xff, 0x04, 0x00,
+    ];
+    // PciRoot(0x0)/Pci(0x2,0x0)
+    const DEVICE_B: [u8; 22] = [
+        0x02, 0x01, 0x0c, 0x00, 0xd0, 0x41, 0x03, 0x0a, 0x00, 0x00, 0x00, 0x00, 0x01, 0x01, 0x06, 0x00, 0x00, 0x02,
+        0x7f, 0xff, 0x04, 0x00,
+    ];
+
+    #[derive(Default)]
+    struct MockStore {
+        snapshot: Mutex<Option<BootSnapshot>>,
+    }
+
+    impl BootSnapshotStore for &'static MockStore {
+        fn load(&self) -> Option<BootSnapshot> {
+            // Snapshots go through their serialized form, as with a store backed by a buffer.
+            self.snapshot
+                .lock()
+                .unwrap()
+                .as_ref()
+                .map(|snapshot| BootSnapshot::from_bytes(&snapshot.to_bytes()).unwrap())
+        }
+
+        fn save(&self, snapshot: &BootSnapshot) -> patina::error::Result<()> {
+            *self.snapshot.lock().unwrap() = Some(snapshot.clone());
+            Ok(())
+        }
+    }
+
+    fn timing(file_name: efi::Guid, millis: u64) -> DriverTiming {
+        DriverTiming { file_name, elapsed: Duration::from_millis(millis) }
+    }
+
+    fn snapshot(drivers: Vec<DriverTiming>, devices: Vec<Vec<u8>>) -> BootSnapshot {
+        BootSnapshot { handle_count: 2, protocol_count: 5, drivers, devices }
+    }
+
+    #[test]
+    fn test_snapshot_serialization_round_trip() {
+        let snapshot = snapshot(vec![timing(DRIVER_A, 12), timing(DRIVER_B, 3)], vec![DEVICE_A.to_vec()]);
+        let bytes = snapshot.to_bytes();
+        assert_eq!(bytes.len(), HEADER_SIZE + 2 * 24 + 4 + DEVICE_A.len());
+        assert_eq!(BootSnapshot::from_bytes(&bytes), Some(snapshot));
+
+        // Truncated buffers, other signatures and malformed device paths are rejected.
+        assert_eq!(BootSnapshot::from_bytes(&bytes[..bytes.len() - 1]), None);
+        assert_eq!(BootSnapshot::from_bytes(&[0; HEADER_SIZE]), None);
+        let mut malformed = bytes.clone();
+        let length = malformed.len();
+        malformed[length - 2] = 0x08;
+        assert_eq!(BootSnapshot::from_bytes(&malformed), None);
+    }
+
+    #[test]
+    fn test_regressions_flag_slow_drivers_and_missing_devices() {
+        let policy = BootSnapshotPolicy::default();
+        let previous =
+            snapshot(vec![timing(DRIVER_A, 20), timing(DRIVER_B, 100)], vec![DEVICE_A.to_vec(), DEVICE_B.to_vec()]);
+
+        // Within the policy: +10ms is below 50% of DRIVER_A, and +40ms is below 50% of DRIVER_B.
+        let current =
+            snapshot(vec![timing(DRIVER_B, 140), timing(DRIVER_A, 30)], vec![DEVICE_B.to_vec(), DEVICE_A.to_vec()]);
+        assert_eq!(current.regressions(&previous, &policy), vec![]);
+
+        let current = snapshot(vec![timing(DRIVER_A, 35)], vec![DEVICE_B.to_vec()]);
+        assert_eq!(
+            current.regressions(&previous, &policy),
+            vec![
+                BootRegression::SlowDriver {
+                    file_name: DRIVER_A,
+                    previous: Duration::from_millis(20),
+                    current: Duration::from_millis(35)
+                },
+                BootRegression::MissingDriver(DRIVER_B),
+                BootRegression::MissingDevice(DEVICE_A.to_vec()),
+            ]
+        );
+
+        // Short drivers are not flagged below the minimum slowdown.
+        let previous = snapshot(vec![timing(DRIVER_A, 1)], vec![]);
+        let current = snapshot(vec![timing(DRIVER_A, 9)], vec![]);
+        assert_eq!(current.regressions(&previous, &policy), vec![]);
+    }
+
+    #[test]
+    fn test_snapshot_compared_with_previous_boot_at_ready_to_boot() {
+        test_support::with_global_lock(|| {
+            unsafe { test_support::init_test_protocol_db() };
+            let device = Box::leak(Box::new(DEVICE_B));
+            PROTOCOL_DB
+                .install_protocol_interface(
+                    None,
+                    efi::protocols::device_path::PROTOCOL_GUID,
+                    device.as_mut_ptr() as *mut c_void,
+                )
+                .unwrap();
+
+            let store: &'static MockStore = Box::leak(Box::default());
+            store.save(&snapshot(vec![timing(DRIVER_A, 10)], vec![DEVICE_A.to_vec(), DEVICE_B.to_vec()])).unwrap();
+            *SNAPSHOT_STATE.lock() = Some(SnapshotState {
+                policy: BootSnapshotPolicy::default(),
+                store: Some(Service::mock(Box::new(store))),
+                drivers: Vec::new(),
+                regressions: Vec::new(),
+            });
+
+            record_driver_timings(&[
+                DriverDispatchRecord {
+                    file_name: DRIVER_A,
+                    outcome: DriverOutcome::Started(efi::Status::SUCCESS),
+                    elapsed: Duration::from_millis(50),
+                },
+                DriverDispatchRecord {
+                    file_name: DRIVER_B,
+                    outcome: DriverOutcome::Started(efi::Status::UNSUPPORTED),
+                    elapsed: Duration::from_millis(1),
+                },
+            ]);
+            capture_boot_snapshot();
+
+            let saved = store.snapshot.lock().unwrap().clone().unwrap();
+            assert_eq!(saved.drivers, vec![timing(DRIVER_A, 50)]);
+            assert_eq!(saved.devices, vec![DEVICE_B.to_vec()]);
+            assert!(saved.handle_count >= 1 && saved.protocol_count >= 1);
+            assert_eq!(
+                boot_regressions(),
+                vec![
+                    BootRegression::SlowDriver {
+                        file_name: DRIVER_A,
+                        previous: Duration::from_millis(10),
+                        current: Duration::from_millis(50)
+                    },
+                    BootRegression::MissingDevice(DEVICE_A.to_vec()),
+                ]
+            );
+            *SNAPSHOT_STATE.lock() = None;
+        })
+        .unwrap();
+    }
+}
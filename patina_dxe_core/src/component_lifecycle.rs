@@ -0,0 +1,315 @@
+//! DXE Core Component Lifecycle
+//!
+//! Tracks the resources components acquire while they are dispatched, and tears the components down at
+//! ExitBootServices.
+//!
+//! While a component is dispatched, the events it creates and the boot services pages it allocates are attributed to
+//! it. When ExitBootServices is first called, the callbacks registered with
+//! [Commands::on_exit_boot_services](patina::component::params::Commands::on_exit_boot_services) are executed in the
+//! reverse order the components were dispatched. The events the components did not close are then closed, so that
+//! their notification functions are not called once the OS has taken control, and the components still owning boot
+//! services pages are reported as leaking them. Runtime events and the events of the ExitBootServices and
+//! VirtualAddressChange groups are left open, as they are meant to be notified after the teardown.
+//!
+//! Resources acquired by a component outside of its dispatch, e.g. in an event notification function or a protocol
+//! function, are not attributed to it.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+extern crate alloc;
+use alloc::{collections::BTreeMap, vec::Vec};
+
+use patina::component::ExitBootServicesCallback;
+use r_efi::efi;
+
+use crate::{config_tables::allocation_attribution_table::AllocationTracker, events::EVENT_DB, tpl_lock};
+
+/// The resources acquired by components while they were dispatched.
+#[derive(Debug, Default)]
+struct ComponentResources {
+    /// The names of the components that acquired resources. Resources are attributed to an index in this list.
+    components: Vec<&'static str>,
+    /// The component being dispatched, if any.
+    dispatching: Option<usize>,
+    /// The live events created by components, with the component that created them.
+    events: BTreeMap<usize, usize>,
+    /// The live boot services page allocations made by components.
+    allocations: AllocationTracker,
+}
+
+impl ComponentResources {
+    const fn new() -> Self {
+        Self {
+            components: Vec::new(),
+            dispatching: None,
+            events: BTreeMap::new(),
+            allocations: AllocationTracker::new(),
+        }
+    }
+
+    /// Attributes the resources acquired from now on to `component`.
+    fn begin_dispatch(&mut self, component: &'static str) {
+        let index = match self.components.iter().position(|name| *name == component) {
+            Some(index) => index,
+            None => {
+                self.components.push(component);
+                self.components.len() - 1
+            }
+        };
+        self.dispatching = Some(index);
+    }
+
+    /// Returns the number of boot services pages each component still owns, for the components owning any.
+    fn leaked_pages(&self) -> Vec<(&'static str, u64)> {
+        let mut pages = BTreeMap::new();
+        for (_, allocation_pages, _, owner) in self.allocations.iter() {
+            *pages.entry(owner).or_insert(0) += allocation_pages;
+        }
+        pages.into_iter().map(|(owner, pages)| (self.components[owner], pages)).collect()
+    }
+}
+
+/// The callbacks to execute at ExitBootServices, in the order they must be executed.
+struct Callbacks(Vec<ExitBootServicesCallback>);
+
+// SAFETY: The callbacks are only accessed by the boot processor, which executes the DXE core.
+unsafe impl Send for Callbacks {}
+
+static RESOURCES: tpl_lock::TplMutex<ComponentResources> =
+    tpl_lock::TplMutex::new(efi::TPL_HIGH_LEVEL, ComponentResources::new(), "ComponentResourcesLock");
+
+static CALLBACKS: tpl_lock::TplMutex<Callbacks> =
+    tpl_lock::TplMutex::new(efi::TPL_HIGH_LEVEL, Callbacks(Vec::new()), "ComponentCallbacksLock");
+
+/// Attributes the events created and the pages allocated from now on to `component`, until [end_dispatch].
+pub(crate) fn begin_dispatch(component: &'static str) {
+    RESOURCES.lock().begin_dispatch(component);
+}
+
+/// Stops attributing resources to the component being dispatched.
+pub(crate) fn end_dispatch() {
+    RESOURCES.lock().dispatching = None;
+}
+
+/// Returns whether an event of `event_type` in `event_group` is meant to be notified at or after ExitBootServices.
+fn outlives_exit_boot_services(event_type: u32, event_group: Option<&efi::Guid>) -> bool {
+    event_type & efi::EVT_RUNTIME != 0
+        || event_group.is_some_and(|group| {
+            *group == efi::EVENT_GROUP_EXIT_BOOT_SERVICES || *group == efi::EVENT_GROUP_VIRTUAL_ADDRESS_CHANGE
+        })
+}
+
+/// Records the creation of an event, attributed to the component being dispatched, if any.
+///
+/// Runtime events and the members of the ExitBootServices and VirtualAddressChange groups are not recorded, as they
+/// are notified after the components are torn down.
+pub(crate) fn record_event(event: efi::Event, event_type: u32, event_group: Option<&efi::Guid>) {
+    if outlives_exit_boot_services(event_type, event_group) {
+        return;
+    }
+    let mut resources = RESOURCES.lock();
+    if let Some(owner) = resources.dispatching {
+        resources.events.insert(event as usize, owner);
+    }
+}
+
+/// Records an event being closed.
+pub(crate) fn record_event_close(event: efi::Event) {
+    RESOURCES.lock().events.remove(&(event as usize));
+}
+
+/// Records a page allocation, attributed to the component being dispatched, if any. Only boot services memory is
+/// tracked, as other memory types are meant to outlive the boot services.
+pub(crate) fn record_allocation(base: efi::PhysicalAddress, pages: usize, memory_type: efi::MemoryType) {
+    if !matches!(memory_type, efi::BOOT_SERVICES_CODE | efi::BOOT_SERVICES_DATA) {
+        return;
+    }
+    let mut resources = RESOURCES.lock();
+    if let Some(owner) = resources.dispatching {
+        resources.allocations.allocate(base, pages as u64, memory_type, owner);
+    }
+}
+
+/// Records pages being freed.
+pub(crate) fn record_free(base: efi::PhysicalAddress, pages: usize) {
+    RESOURCES.lock().allocations.free(base, pages as u64);
+}
+
+/// Sets the callbacks to execute at ExitBootServices, in the order they must be executed.
+pub(crate) fn set_exit_boot_services_callbacks(callbacks: Vec<ExitBootServicesCallback>) {
+    CALLBACKS.lock().0 = callbacks;
+}
+
+/// Executes the ExitBootServices callbacks of the components, closes the events they left open and reports the boot
+/// services pages they still own.
+///
+/// This must only be called on the first call to ExitBootServices.
+pub(crate) fn exit_boot_services() {
+    let callbacks = core::mem::take(&mut CALLBACKS.lock().0);
+    for callback in callbacks {
+        log::info!("ExitBootServices: Id = [{:?}]", callback.component());
+        callback.invoke();
+    }
+
+    let (events, components) = {
+        let mut resources = RESOURCES.lock();
+        (core::mem::take(&mut resources.events), resources.components.clone())
+    };
+    for (event, owner) in events {
+        log::debug!("Closing event {event:#x} left open by component [{:?}].", components[owner]);
+        if let Err(err) = EVENT_DB.close_event(event as efi::Event) {
+            log::warn!("Failed to close event {event:#x} of component [{:?}]: {err:?}", components[owner]);
+        }
+    }
+
+    for (component, pages) in RESOURCES.lock().leaked_pages() {
+        log::warn!("Component [{component:?}] leaked {pages:#x} pages of boot services memory at ExitBootServices.");
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+    use super::*;
+
+    use crate::{
+        allocator::{core_allocate_pages, core_free_pages},
+        events, test_support,
+    };
+    use core::sync::atomic::{AtomicBool, Ordering};
+    use std::{sync::Mutex, vec};
+
+    fn with_locked_state<F: Fn() + std::panic::RefUnwindSafe>(f: F) {
+        test_support::with_global_lock(|| {
+            *RESOURCES.lock() = ComponentResources::new();
+            CALLBACKS.lock().0.clear();
+            unsafe {
+                test_support::init_test_gcd(None);
+                test_support::reset_allocators();
+            }
+            f();
+        })
+        .unwrap();
+    }
+
+    fn allocate_pages(memory_type: efi::MemoryType, pages: usize) -> efi::PhysicalAddress {
+        let mut address = 0;
+        core_allocate_pages(efi::ALLOCATE_ANY_PAGES, memory_type, pages, &mut address, None).unwrap();
+        address
+    }
+
+    extern "efiapi" fn notify(_event: efi::Event, _context: *mut core::ffi::c_void) {}
+
+    fn create_event() -> efi::Event {
+        let event = EVENT_DB.create_event(efi::EVT_NOTIFY_SIGNAL, efi::TPL_NOTIFY, Some(notify), None, None).unwrap();
+        record_event(event, efi::EVT_NOTIFY_SIGNAL, None);
+        event
+    }
+
+    #[test]
+    fn resources_should_be_attributed_to_the_dispatched_component() {
+        with_locked_state(|| {
+            begin_dispatch("first");
+            let leaked = allocate_pages(efi::BOOT_SERVICES_DATA, 2);
+            let freed = allocate_pages(efi::BOOT_SERVICES_CODE, 1);
+            let _runtime = allocate_pages(efi::RUNTIME_SERVICES_DATA, 1);
+            let closed = create_event();
+            let open = create_event();
+            end_dispatch();
+
+            begin_dispatch("second");
+            let partially_freed = allocate_pages(efi::BOOT_SERVICES_DATA, 4);
+            end_dispatch();
+
+            // Resources acquired outside of a dispatch are not attributed.
+            let _unattributed = allocate_pages(efi::BOOT_SERVICES_DATA, 1);
+            let unattributed_event = create_event();
+
+            core_free_pages(freed, 1).unwrap();
+            core_free_pages(partially_freed, 1).unwrap();
+            EVENT_DB.close_event(closed).unwrap();
+            record_event_close(closed);
+
+            let resources = RESOURCES.lock();
+            assert_eq!(resources.events.keys().copied().collect::<Vec<_>>(), [open as usize]);
+            let mut leaks = resources.leaked_pages();
+            leaks.sort();
+            assert_eq!(leaks, [("first", 2), ("second", 3)]);
+            assert!(resources.allocations.iter().any(|(base, ..)| base == leaked));
+            assert!(EVENT_DB.is_valid(unattributed_event));
+        });
+    }
+
+    #[test]
+    fn exit_boot_services_should_tear_down_components() {
+        static ORDER: Mutex<Vec<&str>> = Mutex::new(Vec::new());
+
+        with_locked_state(|| {
+            ORDER.lock().unwrap().clear();
+            let mut storage = patina::component::Storage::new();
+            for component in ["first", "second", "third"] {
+                begin_dispatch(component);
+                create_event();
+                end_dispatch();
+                storage.add_exit_boot_services_callback(component, move || ORDER.lock().unwrap().push(component));
+            }
+            let events = RESOURCES.lock().events.keys().copied().collect::<Vec<_>>();
+            assert_eq!(events.len(), 3);
+
+            set_exit_boot_services_callbacks(storage.take_exit_boot_services_callbacks());
+            exit_boot_services();
+
+            assert_eq!(*ORDER.lock().unwrap(), vec!["third", "second", "first"]);
+            assert!(events.iter().all(|&event| !EVENT_DB.is_valid(event as efi::Event)));
+            assert!(RESOURCES.lock().events.is_empty());
+
+            // The callbacks are only executed once.
+            exit_boot_services();
+            assert_eq!(ORDER.lock().unwrap().len(), 3);
+        });
+    }
+
+    #[test]
+    fn exit_boot_services_should_keep_the_events_notified_after_it() {
+        static EBS_NOTIFIED: AtomicBool = AtomicBool::new(false);
+
+        extern "efiapi" fn ebs_notify(_event: efi::Event, _context: *mut core::ffi::c_void) {
+            EBS_NOTIFIED.store(true, Ordering::SeqCst);
+        }
+
+        with_locked_state(|| {
+            EBS_NOTIFIED.store(false, Ordering::SeqCst);
+
+            begin_dispatch("component");
+            let ebs_group = efi::EVENT_GROUP_EXIT_BOOT_SERVICES;
+            let ebs_event =
+                EVENT_DB.create_event(efi::EVT_NOTIFY_SIGNAL, efi::TPL_NOTIFY, Some(ebs_notify), None, Some(ebs_group));
+            let ebs_event = ebs_event.unwrap();
+            record_event(ebs_event, efi::EVT_NOTIFY_SIGNAL, Some(&ebs_group));
+            let vac_group = efi::EVENT_GROUP_VIRTUAL_ADDRESS_CHANGE;
+            let vac_event =
+                EVENT_DB.create_event(efi::EVT_NOTIFY_SIGNAL, efi::TPL_NOTIFY, Some(notify), None, Some(vac_group));
+            let vac_event = vac_event.unwrap();
+            record_event(vac_event, efi::EVT_NOTIFY_SIGNAL, Some(&vac_group));
+            let boot_event = create_event();
+            end_dispatch();
+
+            exit_boot_services();
+
+            assert!(!EVENT_DB.is_valid(boot_event));
+            assert!(EVENT_DB.is_valid(vac_event));
+            assert!(EVENT_DB.is_valid(ebs_event));
+
+            // The ExitBootServices group is signaled after the teardown, and the component event is notified.
+            EVENT_DB.signal_group(efi::EVENT_GROUP_EXIT_BOOT_SERVICES);
+            events::raise_tpl(efi::TPL_HIGH_LEVEL);
+            events::restore_tpl(efi::TPL_APPLICATION);
+            assert!(EBS_NOTIFIED.load(Ordering::SeqCst));
+        });
+    }
+}
@@ -0,0 +1,131 @@
+//! DXE Core Component Measurement
+//!
+//! The drivers dispatched from firmware volumes are authenticated and measured by the Security Architectural
+//! Protocols when they are loaded, but the components registered with [Core::with_component](crate::Core) are part
+//! of the core image and bypass them. A platform can register a [ComponentMeasurementHandler] service so that its
+//! attestation covers the components too: every component is passed to the service, in registration order, before
+//! any component or driver is dispatched, and the components the service rejects are not dispatched.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use core::fmt;
+
+use patina::component::{Dependency, MetaData};
+
+use crate::component_report;
+
+/// Measures the components registered with the core, e.g. into the TPM event log and the audit trail of the platform.
+pub trait ComponentMeasurementHandler {
+    /// Measures the component `identity`. Returning an error rejects the component, which is then not dispatched.
+    ///
+    /// Called before any driver is dispatched, so the TCG2 protocol is not installed yet: the measurements are
+    /// typically extended into the TPM directly, or logged for a later driver to replay.
+    fn measure_component(&self, identity: &ComponentIdentity) -> patina::error::Result<()>;
+}
+
+/// The identity of a component registered with the core.
+///
+/// Its [Display](fmt::Display) representation, e.g. `crate::Component(Config<u32>, Service<dyn crate::Service>)`,
+/// only depends on the component and its params, not on where the core is loaded, and is intended as the data of
+/// the measured event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ComponentIdentity<'a> {
+    /// The name of the component, including the module path.
+    pub name: &'static str,
+    /// The configs, HOBs and services consumed by the component, in the order of its params.
+    pub consumed: &'a [Dependency],
+}
+
+impl fmt::Display for ComponentIdentity<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}(", self.name)?;
+        for (index, dependency) in self.consumed.iter().enumerate() {
+            if index > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{dependency}")?;
+        }
+        f.write_str(")")
+    }
+}
+
+/// Measures the component described by `metadata` with `handler`, returning whether it may be dispatched.
+pub(crate) fn measure_component(handler: &dyn ComponentMeasurementHandler, metadata: &MetaData) -> bool {
+    let identity = ComponentIdentity { name: metadata.name(), consumed: metadata.consumed() };
+    match handler.measure_component(&identity) {
+        Ok(()) => {
+            log::info!("Measured: Id = [{:?}]", identity.name);
+            true
+        }
+        Err(err) => {
+            log::error!("Rejected: Id = [{:?}] Error = [{err:?}]", identity.name);
+            component_report::record_rejection(metadata, err);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use core::cell::RefCell;
+    use patina::{component::DependencyKind, error::EfiError};
+    use std::{format, string::String, vec::Vec};
+
+    struct TrustedComponent;
+    struct UntrustedComponent;
+
+    #[derive(Default)]
+    struct TestHandler {
+        measured: RefCell<Vec<String>>,
+    }
+
+    impl ComponentMeasurementHandler for TestHandler {
+        fn measure_component(&self, identity: &ComponentIdentity) -> patina::error::Result<()> {
+            if identity.name.ends_with("UntrustedComponent") {
+                return Err(EfiError::SecurityViolation);
+            }
+            self.measured.borrow_mut().push(format!("{identity}"));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn identity_should_include_the_consumed_params() {
+        let mut metadata = MetaData::new::<u32>();
+        assert_eq!(format!("{}", ComponentIdentity { name: metadata.name(), consumed: metadata.consumed() }), "u32()");
+
+        metadata.add_consumed::<u64>(DependencyKind::Config);
+        metadata.add_consumed::<u8>(DependencyKind::Hob);
+        assert_eq!(
+            format!("{}", ComponentIdentity { name: metadata.name(), consumed: metadata.consumed() }),
+            "u32(Config<u64>, Hob<u8>)"
+        );
+    }
+
+    #[test]
+    fn rejected_components_should_not_be_dispatched() {
+        crate::test_support::with_global_lock(|| {
+            let handler = TestHandler::default();
+            let trusted = MetaData::new::<TrustedComponent>();
+            let untrusted = MetaData::new::<UntrustedComponent>();
+            component_report::register(&untrusted);
+
+            assert!(measure_component(&handler, &trusted));
+            assert!(!measure_component(&handler, &untrusted));
+            assert_eq!(*handler.measured.borrow(), [format!("{}()", trusted.name())]);
+            assert!(
+                component_report::component_report().records().iter().any(|record| record.name == untrusted.name()
+                    && record.state == component_report::ComponentState::Rejected(EfiError::SecurityViolation))
+            );
+        })
+        .unwrap();
+    }
+}
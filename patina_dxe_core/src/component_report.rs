@@ -0,0 +1,269 @@
+//! DXE Core Component Report
+//!
+//! Records every component registered with the core, with the configs, HOBs and services it consumes and produces,
+//! its dispatch state and the time spent in its entry point, so that integrators can see why a component did not run
+//! or what it consumed.
+//!
+//! The report is updated as components are dispatched. It is returned by [component_report], and printed by the
+//! `components` debugger monitor command.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::vec::Vec;
+use core::{fmt, time::Duration};
+
+use patina::{
+    component::{BootMode, Dependency, MetaData},
+    error::EfiError,
+};
+use r_efi::efi;
+
+use crate::tpl_lock;
+
+/// The dispatch state of a component.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComponentState {
+    /// The component has not been dispatched, with the last param that could not be retrieved, if it was attempted.
+    NotDispatched(Option<&'static str>),
+    /// The entry point of the component returned success.
+    Dispatched,
+    /// The entry point of the component returned the error.
+    Failed(EfiError),
+    /// The component was not dispatched, as it opted out of the boot mode.
+    Skipped(BootMode),
+    /// The component was not dispatched, as the [ComponentMeasurementHandler](crate::ComponentMeasurementHandler)
+    /// service rejected it with the error.
+    Rejected(EfiError),
+}
+
+/// A component registered with the core.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComponentRecord {
+    /// The name of the component, including the module path.
+    pub name: &'static str,
+    /// The configs, HOBs and services consumed by the component, in the order of its params.
+    pub consumed: Vec<Dependency>,
+    /// The configs and services produced by the component, once its commands are applied.
+    pub produced: Vec<Dependency>,
+    /// The dispatch state of the component.
+    pub state: ComponentState,
+    /// The time spent in the entry point of the component, zero if it was not dispatched.
+    pub elapsed: Duration,
+}
+
+/// The components registered with the core, in the order they were registered.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ComponentReport {
+    records: Vec<ComponentRecord>,
+}
+
+impl ComponentReport {
+    const fn new() -> Self {
+        Self { records: Vec::new() }
+    }
+
+    /// Returns the records of the report.
+    pub fn records(&self) -> &[ComponentRecord] {
+        &self.records
+    }
+
+    /// Returns the records of the components that have not been dispatched.
+    pub fn not_dispatched(&self) -> impl Iterator<Item = &ComponentRecord> {
+        self.records.iter().filter(|record| matches!(record.state, ComponentState::NotDispatched(_)))
+    }
+
+    fn register(&mut self, metadata: &MetaData) {
+        self.records.push(ComponentRecord {
+            name: metadata.name(),
+            consumed: metadata.consumed().to_vec(),
+            produced: Vec::new(),
+            state: ComponentState::NotDispatched(None),
+            elapsed: Duration::ZERO,
+        });
+    }
+
+    /// Returns the first record of `name` not dispatched yet. Components registered more than once have a record per
+    /// registration.
+    fn pending_mut(&mut self, name: &str) -> Option<&mut ComponentRecord> {
+        self.records
+            .iter_mut()
+            .find(|record| record.name == name && matches!(record.state, ComponentState::NotDispatched(_)))
+    }
+
+    fn record_attempt(&mut self, metadata: &MetaData, result: &Result<bool, EfiError>, elapsed: Duration) {
+        let Some(record) = self.pending_mut(metadata.name()) else {
+            return;
+        };
+        match result {
+            Ok(true) => record.state = ComponentState::Dispatched,
+            Ok(false) => record.state = ComponentState::NotDispatched(metadata.failed_param()),
+            Err(err) => record.state = ComponentState::Failed(*err),
+        }
+        if !matches!(record.state, ComponentState::NotDispatched(_)) {
+            record.elapsed = elapsed;
+        }
+    }
+
+    fn record_skip(&mut self, metadata: &MetaData, boot_mode: BootMode) {
+        if let Some(record) = self.pending_mut(metadata.name()) {
+            record.state = ComponentState::Skipped(boot_mode);
+        }
+    }
+
+    fn record_rejection(&mut self, metadata: &MetaData, err: EfiError) {
+        if let Some(record) = self.pending_mut(metadata.name()) {
+            record.state = ComponentState::Rejected(err);
+        }
+    }
+
+    fn set_produced(&mut self, produced: &[(&'static str, Dependency)]) {
+        for record in self.records.iter_mut() {
+            record.produced =
+                produced.iter().filter(|(name, _)| *name == record.name).map(|(_, dependency)| *dependency).collect();
+        }
+    }
+}
+
+impl fmt::Display for ComponentReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for record in &self.records {
+            match record.state {
+                ComponentState::NotDispatched(Some(param)) => {
+                    writeln!(f, "{} [NotDispatched] missing {param}", record.name)?
+                }
+                ComponentState::NotDispatched(None) => writeln!(f, "{} [NotDispatched]", record.name)?,
+                ComponentState::Dispatched => writeln!(f, "{} [Dispatched] {:?}", record.name, record.elapsed)?,
+                ComponentState::Failed(err) => writeln!(f, "{} [Failed: {err:?}] {:?}", record.name, record.elapsed)?,
+                ComponentState::Skipped(boot_mode) => writeln!(f, "{} [Skipped] in {boot_mode}", record.name)?,
+                ComponentState::Rejected(err) => writeln!(f, "{} [Rejected: {err:?}]", record.name)?,
+            }
+            for dependency in &record.consumed {
+                writeln!(f, "  consumes {dependency}")?;
+            }
+            for dependency in &record.produced {
+                writeln!(f, "  produces {dependency}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+static COMPONENT_REPORT: tpl_lock::TplMutex<ComponentReport> =
+    tpl_lock::TplMutex::new(efi::TPL_NOTIFY, ComponentReport::new(), "ComponentReportLock");
+
+/// Registers the `components` debugger monitor command printing the report.
+pub(crate) fn init_component_report() {
+    patina_debugger::add_monitor_command("components", "Prints the registered components", |_, out| {
+        let _ = write!(out, "{}", *COMPONENT_REPORT.lock());
+    });
+}
+
+/// Records the registration of the component described by `metadata`, once its params are initialized.
+pub(crate) fn register(metadata: &MetaData) {
+    COMPONENT_REPORT.lock().register(metadata);
+}
+
+/// Records an attempt to dispatch the component described by `metadata`, which took `elapsed`.
+pub(crate) fn record_attempt(metadata: &MetaData, result: &Result<bool, EfiError>, elapsed: Duration) {
+    COMPONENT_REPORT.lock().record_attempt(metadata, result, elapsed);
+}
+
+/// Records that the component described by `metadata` is skipped in `boot_mode`.
+pub(crate) fn record_skip(metadata: &MetaData, boot_mode: BootMode) {
+    COMPONENT_REPORT.lock().record_skip(metadata, boot_mode);
+}
+
+/// Records that the component described by `metadata` is rejected by the measurement handler with `err`.
+pub(crate) fn record_rejection(metadata: &MetaData, err: EfiError) {
+    COMPONENT_REPORT.lock().record_rejection(metadata, err);
+}
+
+/// Updates the configs and services produced by the components, from the ones recorded in the storage.
+pub(crate) fn set_produced(produced: &[(&'static str, Dependency)]) {
+    COMPONENT_REPORT.lock().set_produced(produced);
+}
+
+/// Returns the components registered with the core, with their dispatch state.
+///
+/// The report is updated as components are dispatched, so it can be inspected at any time, e.g. from a component
+/// that depends on other components.
+pub fn component_report() -> ComponentReport {
+    COMPONENT_REPORT.lock().clone()
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use patina::component::DependencyKind;
+    use std::format;
+
+    struct TestComponent;
+
+    #[test]
+    fn report_should_track_the_dispatch_state() {
+        let mut metadata = MetaData::new::<TestComponent>();
+        metadata.add_consumed::<u32>(DependencyKind::Config);
+        let mut report = ComponentReport::new();
+        report.register(&metadata);
+        report.register(&metadata);
+        assert_eq!(report.not_dispatched().count(), 2);
+        assert_eq!(report.records()[0].consumed, [Dependency { kind: DependencyKind::Config, type_name: "u32" }]);
+
+        metadata.set_failed_param("patina::component::params::Config<u32>");
+        report.record_attempt(&metadata, &Ok(false), Duration::from_millis(1));
+        assert_eq!(
+            report.records()[0].state,
+            ComponentState::NotDispatched(Some("patina::component::params::Config<u32>"))
+        );
+        assert_eq!(report.records()[0].elapsed, Duration::ZERO);
+
+        // Each dispatch updates the first record not dispatched yet.
+        report.record_attempt(&metadata, &Ok(true), Duration::from_millis(2));
+        report.record_attempt(&metadata, &Err(EfiError::NotReady), Duration::from_millis(3));
+        assert_eq!(report.records()[0].state, ComponentState::Dispatched);
+        assert_eq!(report.records()[0].elapsed, Duration::from_millis(2));
+        assert_eq!(report.records()[1].state, ComponentState::Failed(EfiError::NotReady));
+        assert_eq!(report.not_dispatched().count(), 0);
+
+        let produced = Dependency { kind: DependencyKind::Service, type_name: "TestService" };
+        report.set_produced(&[(metadata.name(), produced), ("Other", produced)]);
+        assert_eq!(report.records()[1].produced, [produced]);
+
+        let display = format!("{report}");
+        assert!(display.contains("[Dispatched] 2ms"));
+        assert!(display.contains("[Failed: NotReady] 3ms"));
+        assert!(display.contains("  consumes Config<u32>"));
+        assert!(display.contains("  produces Service<TestService>"));
+    }
+
+    #[test]
+    fn report_should_track_skipped_components() {
+        let metadata = MetaData::new::<TestComponent>();
+        let mut report = ComponentReport::new();
+        report.register(&metadata);
+
+        report.record_skip(&metadata, BootMode::BootOnS3Resume);
+        assert_eq!(report.records()[0].state, ComponentState::Skipped(BootMode::BootOnS3Resume));
+        assert_eq!(report.not_dispatched().count(), 0);
+        assert!(format!("{report}").contains("[Skipped] in Boot On S3 Resume (0x11)"));
+    }
+
+    #[test]
+    fn report_should_track_rejected_components() {
+        let metadata = MetaData::new::<TestComponent>();
+        let mut report = ComponentReport::new();
+        report.register(&metadata);
+
+        report.record_rejection(&metadata, EfiError::SecurityViolation);
+        assert_eq!(report.records()[0].state, ComponentState::Rejected(EfiError::SecurityViolation));
+        assert_eq!(report.not_dispatched().count(), 0);
+        assert!(format!("{report}").contains("[Rejected: SecurityViolation]"));
+    }
+}
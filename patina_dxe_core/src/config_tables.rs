@@ -6,11 +6,18 @@
 //!
 //! SPDX-License-Identifier: Apache-2.0
 //!
+pub(crate) mod allocation_attribution_table;
+pub(crate) mod conformance_profiles_table;
 pub(crate) mod debug_image_info_table;
 pub(crate) mod memory_attributes_table;
+pub(crate) mod memory_map_snapshot;
 
-use alloc::{boxed::Box, vec};
-use core::{ffi::c_void, slice::from_raw_parts_mut};
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    ffi::c_void,
+    ptr,
+    slice::{self, from_raw_parts_mut},
+};
 use patina::error::EfiError;
 use r_efi::efi;
 
@@ -18,6 +25,7 @@ use crate::{
     allocator::EFI_RUNTIME_SERVICES_DATA_ALLOCATOR,
     events::EVENT_DB,
     systemtables::{EfiSystemTable, SYSTEM_TABLE},
+    tpl_lock,
 };
 
 extern "efiapi" fn install_configuration_table(table_guid: *mut efi::Guid, table: *mut c_void) -> efi::Status {
@@ -28,6 +36,13 @@ extern "efiapi" fn install_configuration_table(table_guid: *mut efi::Guid, table
     // Safety: caller must ensure that table_guid is a valid pointer. It is null-checked above.
     let table_guid = unsafe { table_guid.read_unaligned() };
 
+    if table_guid == memory_attributes_table::PROPERTIES_TABLE_GUID {
+        log::warn!(
+            "The EFI Properties Table is deprecated in favor of the Memory Attributes Table, not installing it."
+        );
+        return efi::Status::UNSUPPORTED;
+    }
+
     let mut st_guard = SYSTEM_TABLE.lock();
     let st = match st_guard.as_mut() {
         Some(st) => st,
@@ -40,90 +55,319 @@ extern "efiapi" fn install_configuration_table(table_guid: *mut efi::Guid, table
     }
 }
 
+/// The number of entries of the first configuration table allocated by the core.
+const INITIAL_CAPACITY: usize = 8;
+
+/// The configuration table allocated by the core, with the number of entries it can hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct TableAllocation {
+    address: usize,
+    capacity: usize,
+}
+
+static TABLE_ALLOCATION: tpl_lock::TplMutex<TableAllocation> = tpl_lock::TplMutex::new(
+    efi::TPL_HIGH_LEVEL,
+    TableAllocation { address: 0, capacity: 0 },
+    "ConfigTableAllocationLock",
+);
+
+const EMPTY_ENTRY: efi::ConfigurationTable = efi::ConfigurationTable {
+    vendor_guid: efi::Guid::from_fields(0, 0, 0, 0, 0, &[0; 6]),
+    vendor_table: ptr::null_mut(),
+};
+
+/// Installs, replaces or removes the configuration table of `vendor_guid` in the system table.
+///
+/// A GUID is present at most once in the system table:
+///
+/// - A table installed for a GUID already present replaces its entry in place, without reallocating the list.
+/// - A null `vendor_table` removes the entry of the GUID, or returns [EfiError::NotFound] if it is not present.
+/// - A table installed for a new GUID is appended. The list is allocated in runtime services data memory, and grows by
+///   doubling its capacity once it is full, so that its address rarely changes.
+///
+/// The system table CRC is updated and the GUID is signaled as an event group after every change.
 pub fn core_install_configuration_table(
     vendor_guid: efi::Guid,
     vendor_table: *mut c_void,
     efi_system_table: &mut EfiSystemTable,
+) -> Result<(), EfiError> {
+    core_install_configuration_table_unsignaled(vendor_guid, vendor_table, efi_system_table)?;
+
+    //signal the table guid as an event group
+    EVENT_DB.signal_group(vendor_guid);
+
+    Ok(())
+}
+
+/// Installs, replaces or removes the configuration table of `vendor_guid` like [core_install_configuration_table],
+/// without signaling the GUID as an event group, for placeholder tables whose consumers must not be notified yet.
+pub(crate) fn core_install_configuration_table_unsignaled(
+    vendor_guid: efi::Guid,
+    vendor_table: *mut c_void,
+    efi_system_table: &mut EfiSystemTable,
 ) -> Result<(), EfiError> {
     let system_table = efi_system_table.as_mut();
-    //if a table is already present, reconstruct it from the pointer and length in the st.
-    let old_cfg_table = if system_table.configuration_table.is_null() {
-        assert_eq!(system_table.number_of_table_entries, 0);
-        None
+    let mut allocation = TABLE_ALLOCATION.lock();
+
+    let table = system_table.configuration_table;
+    let count = system_table.number_of_table_entries;
+    let entries = if table.is_null() {
+        assert_eq!(count, 0);
+        &mut [][..]
     } else {
-        let ct_slice_box = unsafe {
-            Box::from_raw_in(
-                from_raw_parts_mut(system_table.configuration_table, system_table.number_of_table_entries),
-                &EFI_RUNTIME_SERVICES_DATA_ALLOCATOR,
-            )
-        };
-        Some(ct_slice_box)
+        // A list the core did not allocate has room for its entries only.
+        if allocation.address != table as usize {
+            *allocation = TableAllocation { address: table as usize, capacity: count };
+        }
+        // Safety: the system table holds `count` entries at `table`.
+        unsafe { from_raw_parts_mut(table, count) }
     };
+    let capacity = if table.is_null() { 0 } else { allocation.capacity };
 
-    // construct the new table contents as a vector.
-    let new_table = match old_cfg_table {
-        Some(cfg_table) => {
-            // a configuration table list is already present.
-            let mut current_table = cfg_table.to_vec();
-            let existing_entry = current_table.iter_mut().find(|x| x.vendor_guid == vendor_guid);
-            if !vendor_table.is_null() {
-                // vendor_table is not null; we are adding or modifying an entry.
-                if let Some(entry) = existing_entry {
-                    //entry exists, modify it.
-                    entry.vendor_table = vendor_table;
-                } else {
-                    //entry doesn't exist, add it.
-                    current_table.push(efi::ConfigurationTable { vendor_guid, vendor_table });
-                }
-            } else {
-                //vendor_table is none; we are deleting an entry.
-                if let Some(_entry) = existing_entry {
-                    //entry exists, we can delete it
-                    current_table.retain(|x| x.vendor_guid != vendor_guid);
-                } else {
-                    //entry does not exist, we can't delete it. We have to put the original box back
-                    //in the config table so it doesn't get dropped though. Pointer should be the same
-                    //so we should not need to recompute CRC.
-                    system_table.configuration_table =
-                        Box::into_raw_with_allocator(cfg_table).0 as *mut efi::ConfigurationTable;
-                    return Err(EfiError::NotFound);
-                }
+    match (entries.iter().position(|entry| entry.vendor_guid == vendor_guid), vendor_table.is_null()) {
+        (None, true) => return Err(EfiError::NotFound),
+        (Some(index), false) => {
+            entries[index].vendor_table = vendor_table;
+            let kept = index + 1 + remove_entries(&mut entries[index + 1..], vendor_guid);
+            entries[kept..].fill(EMPTY_ENTRY);
+            system_table.number_of_table_entries = kept;
+        }
+        (Some(index), true) => {
+            let kept = index + remove_entries(&mut entries[index..], vendor_guid);
+            entries[kept..].fill(EMPTY_ENTRY);
+            system_table.number_of_table_entries = kept;
+            if kept == 0 {
+                // Safety: the list holds `capacity` entries allocated in runtime services data memory.
+                unsafe { free_table(table, capacity) };
+                system_table.configuration_table = ptr::null_mut();
+                *allocation = TableAllocation { address: 0, capacity: 0 };
             }
-            current_table
         }
-        None => {
-            // config table list doesn't exist.
-            if !vendor_table.is_null() {
-                // table is some, meaning we should create the list and add this as the new entry.
-                vec![efi::ConfigurationTable { vendor_guid, vendor_table }]
+        (None, false) => {
+            let entry = efi::ConfigurationTable { vendor_guid, vendor_table };
+            if count < capacity {
+                // Safety: the list has room for `capacity` entries.
+                unsafe { table.add(count).write(entry) };
             } else {
-                // table is null, but can't delete a table entry in a list that doesn't exist.
-                //since the list doesn't exist, we can leave the (null) pointer in the st alone.
-                return Err(EfiError::NotFound);
+                let new_capacity = (capacity * 2).max(INITIAL_CAPACITY);
+                let mut new_table = Vec::with_capacity_in(new_capacity, &EFI_RUNTIME_SERVICES_DATA_ALLOCATOR);
+                new_table.extend_from_slice(entries);
+                new_table.push(entry);
+                new_table.resize(new_capacity, EMPTY_ENTRY);
+                let new_table =
+                    Box::into_raw_with_allocator(new_table.into_boxed_slice()).0 as *mut efi::ConfigurationTable;
+                if !table.is_null() {
+                    // Safety: the list holds `capacity` entries allocated in runtime services data memory.
+                    unsafe { free_table(table, capacity) };
+                }
+                system_table.configuration_table = new_table;
+                *allocation = TableAllocation { address: new_table as usize, capacity: new_capacity };
             }
+            system_table.number_of_table_entries = count + 1;
         }
-    };
-
-    if new_table.is_empty() {
-        // if empty, just set config table ptr to null
-        system_table.number_of_table_entries = 0;
-        system_table.configuration_table = core::ptr::null_mut();
-    } else {
-        //Box up the new table and put it in the system table. The old table (if any) will be dropped
-        //when old_cfg_table goes out of scope at the end of the function.
-        system_table.number_of_table_entries = new_table.len();
-        let new_table = new_table.to_vec_in(&EFI_RUNTIME_SERVICES_DATA_ALLOCATOR).into_boxed_slice();
-        system_table.configuration_table = Box::into_raw_with_allocator(new_table).0 as *mut efi::ConfigurationTable;
     }
+    drop(allocation);
+
     //since we modified the system table, re-calculate CRC.
     efi_system_table.checksum();
 
-    //signal the table guid as an event group
-    EVENT_DB.signal_group(vendor_guid);
-
     Ok(())
 }
 
+/// Moves the entries not matching `vendor_guid` to the start of `entries`, in order, returning their count.
+fn remove_entries(entries: &mut [efi::ConfigurationTable], vendor_guid: efi::Guid) -> usize {
+    let mut kept = 0;
+    for index in 0..entries.len() {
+        if entries[index].vendor_guid != vendor_guid {
+            entries.swap(kept, index);
+            kept += 1;
+        }
+    }
+    kept
+}
+
+/// Frees a configuration table list.
+///
+/// # Safety
+///
+/// `table` must be a list of `capacity` entries allocated in runtime services data memory, not used afterwards.
+unsafe fn free_table(table: *mut efi::ConfigurationTable, capacity: usize) {
+    drop(unsafe {
+        Box::from_raw_in(ptr::slice_from_raw_parts_mut(table, capacity), &EFI_RUNTIME_SERVICES_DATA_ALLOCATOR)
+    });
+}
+
+/// Returns the configuration tables installed in the system table, in order.
+pub fn configuration_tables() -> Vec<efi::ConfigurationTable> {
+    let st = SYSTEM_TABLE.lock();
+    let Some(st) = st.as_ref() else {
+        return Vec::new();
+    };
+    let st = st.as_ref();
+    if st.configuration_table.is_null() {
+        return Vec::new();
+    }
+    // Safety: the system table holds `number_of_table_entries` entries at `configuration_table`.
+    unsafe { slice::from_raw_parts(st.configuration_table, st.number_of_table_entries) }.to_vec()
+}
+
 pub fn init_config_tables_support(bs: &mut efi::BootServices) {
     bs.install_configuration_table = install_configuration_table;
 }
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+    use super::*;
+
+    use crate::{systemtables::init_system_table, test_support};
+
+    const GUID_A: efi::Guid = efi::Guid::from_fields(0xa, 0, 0, 0, 0, &[0; 6]);
+    const GUID_B: efi::Guid = efi::Guid::from_fields(0xb, 0, 0, 0, 0, &[0; 6]);
+
+    fn with_locked_state<F: Fn() + std::panic::RefUnwindSafe>(f: F) {
+        test_support::with_global_lock(|| {
+            unsafe {
+                test_support::init_test_gcd(None);
+                test_support::reset_allocators();
+                init_system_table();
+            }
+            f();
+        })
+        .unwrap();
+    }
+
+    fn table(value: usize) -> *mut c_void {
+        value as *mut c_void
+    }
+
+    fn install(guid: efi::Guid, vendor_table: *mut c_void) -> Result<(), EfiError> {
+        core_install_configuration_table(guid, vendor_table, SYSTEM_TABLE.lock().as_mut().unwrap())
+    }
+
+    fn installed() -> Vec<(efi::Guid, *mut c_void)> {
+        configuration_tables().iter().map(|entry| (entry.vendor_guid, entry.vendor_table)).collect()
+    }
+
+    /// Returns the address of the list in the system table, and whether the CRC of the system table is valid.
+    fn system_table_state() -> (*mut efi::ConfigurationTable, bool) {
+        let st = SYSTEM_TABLE.lock();
+        let st = st.as_ref().unwrap().as_ref();
+        // Safety: the system table is a plain structure, read as bytes to compute its CRC.
+        let mut bytes =
+            unsafe { slice::from_raw_parts(st as *const efi::SystemTable as *const u8, size_of::<efi::SystemTable>()) }
+                .to_vec();
+        let crc_offset = core::mem::offset_of!(efi::TableHeader, crc32);
+        bytes[crc_offset..crc_offset + 4].fill(0);
+        (st.configuration_table, crc32fast::hash(&bytes) == st.hdr.crc32)
+    }
+
+    #[test]
+    fn install_should_append_and_replace_in_place() {
+        with_locked_state(|| {
+            assert!(configuration_tables().is_empty());
+            install(GUID_A, table(0x1000)).unwrap();
+            install(GUID_B, table(0x2000)).unwrap();
+            assert_eq!(installed(), [(GUID_A, table(0x1000)), (GUID_B, table(0x2000))]);
+            let (list, crc_valid) = system_table_state();
+            assert!(crc_valid);
+
+            install(GUID_A, table(0x3000)).unwrap();
+            assert_eq!(installed(), [(GUID_A, table(0x3000)), (GUID_B, table(0x2000))]);
+            assert_eq!(system_table_state(), (list, true));
+        });
+    }
+
+    #[test]
+    fn install_should_reject_the_properties_table() {
+        with_locked_state(|| {
+            let mut guid = memory_attributes_table::PROPERTIES_TABLE_GUID;
+            assert_eq!(install_configuration_table(&mut guid, table(0x1000)), efi::Status::UNSUPPORTED);
+            assert!(installed().is_empty());
+
+            let mut guid = GUID_A;
+            assert_eq!(install_configuration_table(&mut guid, table(0x1000)), efi::Status::SUCCESS);
+            assert_eq!(installed(), [(GUID_A, table(0x1000))]);
+        });
+    }
+
+    #[test]
+    fn install_null_should_remove_the_entry() {
+        with_locked_state(|| {
+            assert_eq!(install(GUID_A, ptr::null_mut()), Err(EfiError::NotFound));
+
+            install(GUID_A, table(0x1000)).unwrap();
+            install(GUID_B, table(0x2000)).unwrap();
+            install(GUID_A, ptr::null_mut()).unwrap();
+            assert_eq!(installed(), [(GUID_B, table(0x2000))]);
+            assert!(system_table_state().1);
+            assert_eq!(install(GUID_A, ptr::null_mut()), Err(EfiError::NotFound));
+
+            // removing the last entry frees the list.
+            install(GUID_B, ptr::null_mut()).unwrap();
+            assert!(installed().is_empty());
+            assert_eq!(system_table_state(), (ptr::null_mut(), true));
+        });
+    }
+
+    #[test]
+    fn install_should_grow_the_list_when_full() {
+        with_locked_state(|| {
+            let guid = |index: usize| efi::Guid::from_fields(index as u32, 0, 0, 0, 0, &[0; 6]);
+            install(guid(0), table(0x1000)).unwrap();
+            let (list, _) = system_table_state();
+            for index in 1..INITIAL_CAPACITY {
+                install(guid(index), table(0x1000 * (index + 1))).unwrap();
+            }
+            assert_eq!(system_table_state().0, list);
+
+            install(guid(INITIAL_CAPACITY), table(0x1000)).unwrap();
+            let (grown_list, crc_valid) = system_table_state();
+            assert_ne!(grown_list, list);
+            assert!(crc_valid);
+            assert_eq!(
+                *TABLE_ALLOCATION.lock(),
+                TableAllocation { address: grown_list as usize, capacity: 2 * INITIAL_CAPACITY }
+            );
+
+            let expected = (0..=INITIAL_CAPACITY)
+                .map(|index| {
+                    (guid(index), table(if index == INITIAL_CAPACITY { 0x1000 } else { 0x1000 * (index + 1) }))
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(installed(), expected);
+        });
+    }
+
+    #[test]
+    fn install_should_remove_duplicate_guids() {
+        with_locked_state(|| {
+            // a list with duplicates, installed without the core.
+            let duplicates =
+                [(GUID_A, 0x1000), (GUID_B, 0x2000), (GUID_A, 0x3000)].map(|(vendor_guid, vendor_table)| {
+                    efi::ConfigurationTable { vendor_guid, vendor_table: table(vendor_table) }
+                });
+            let list = duplicates.to_vec_in(&EFI_RUNTIME_SERVICES_DATA_ALLOCATOR).into_boxed_slice();
+            {
+                let mut st = SYSTEM_TABLE.lock();
+                let st = st.as_mut().unwrap().as_mut();
+                st.number_of_table_entries = list.len();
+                st.configuration_table = Box::into_raw_with_allocator(list).0 as *mut efi::ConfigurationTable;
+            }
+
+            install(GUID_A, table(0x4000)).unwrap();
+            assert_eq!(installed(), [(GUID_A, table(0x4000)), (GUID_B, table(0x2000))]);
+
+            install(GUID_A, table(0x5000)).unwrap();
+            install(GUID_A, ptr::null_mut()).unwrap();
+            assert_eq!(installed(), [(GUID_B, table(0x2000))]);
+
+            // the list keeps its original capacity.
+            install(GUID_A, table(0x1000)).unwrap();
+            install(efi::Guid::from_fields(0xc, 0, 0, 0, 0, &[0; 6]), table(0x1000)).unwrap();
+            assert_eq!(installed().len(), 3);
+            assert_eq!(TABLE_ALLOCATION.lock().capacity, 3);
+        });
+    }
+}
@@ -0,0 +1,468 @@
+//! DXE Core Allocation Attribution Table
+//!
+//! Records which image made each page allocation, so that the owner of a range of the memory map can be identified
+//! from the OS when debugging, e.g. to find the driver leaking boot services memory or owning a reserved region. The
+//! memory map only describes memory types, as the GCD attributes allocations to the allocator of each memory type.
+//!
+//! When the [AllocationAttributionPolicy] is provided, the allocator records the pages allocated with
+//! `AllocatePages()` and freed with `FreePages()`, with the image running when they were allocated (the DXE core if
+//! none). At ReadyToBoot, a reserved buffer is allocated with room for the live allocations plus a margin, filled and
+//! published as the [ALLOCATION_ATTRIBUTION_TABLE](patina::guids::ALLOCATION_ATTRIBUTION_TABLE) configuration table.
+//! It is filled again at ExitBootServices, without allocating, so that it describes the allocations handed off to the
+//! OS.
+//!
+//! The buffer starts with an [AllocationAttributionHeader], followed by the [AllocationAttributionEntry] array. Images
+//! are identified by the name of the firmware volume file they were loaded from; allocations of images that were not
+//! loaded from a firmware volume, or that were unloaded, are reported with the zero GUID.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+extern crate alloc;
+use alloc::collections::BTreeMap;
+
+use core::{
+    ffi::c_void,
+    mem::size_of,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering},
+};
+
+use patina::{base::UEFI_PAGE_SIZE, guids, uefi_size_to_pages};
+use r_efi::efi;
+
+use crate::{
+    allocator::core_allocate_pages, config_tables::core_install_configuration_table, events::EVENT_DB, image,
+    protocol_db::DXE_CORE_HANDLE, systemtables, tpl_lock,
+};
+
+/// The signature of an [AllocationAttributionHeader], `"ALAT"`.
+pub const ALLOCATION_ATTRIBUTION_SIGNATURE: u32 = u32::from_le_bytes(*b"ALAT");
+
+/// The revision of the allocation attribution table layout described by [AllocationAttributionHeader].
+pub const ALLOCATION_ATTRIBUTION_REVISION: u32 = 1;
+
+/// The [AllocationAttributionHeader::flags] of an allocation attribution table.
+pub mod attribution_flags {
+    /// The entries were filled at ExitBootServices. Until then, they describe the allocations at ReadyToBoot.
+    pub const CAPTURED_AT_EXIT_BOOT_SERVICES: u32 = 0x1;
+    /// Some allocations did not fit in the table, and were dropped.
+    pub const TRUNCATED: u32 = 0x2;
+}
+
+/// The header of an allocation attribution table buffer, followed by the entries.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AllocationAttributionHeader {
+    /// [ALLOCATION_ATTRIBUTION_SIGNATURE].
+    pub signature: u32,
+    /// [ALLOCATION_ATTRIBUTION_REVISION].
+    pub revision: u32,
+    /// The size of an entry, in bytes.
+    pub entry_size: u32,
+    /// The number of entries the buffer has room for.
+    pub capacity: u32,
+    /// The number of entries in the buffer.
+    pub count: u32,
+    /// The [attribution_flags] of the table.
+    pub flags: u32,
+}
+
+/// A page allocation in an allocation attribution table.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AllocationAttributionEntry {
+    /// The physical address of the first page of the allocation.
+    pub physical_start: u64,
+    /// The number of pages of the allocation.
+    pub number_of_pages: u64,
+    /// The memory type of the allocation.
+    pub memory_type: u32,
+    /// Reserved, zero.
+    pub reserved: u32,
+    /// The name of the firmware volume file of the image that made the allocation, or the zero GUID if unknown.
+    pub owner: efi::Guid,
+}
+
+/// A configuration struct enabling the attribution of page allocations to images, published as the
+/// [ALLOCATION_ATTRIBUTION_TABLE](patina::guids::ALLOCATION_ATTRIBUTION_TABLE) configuration table. Allocations are
+/// not tracked unless this configuration is provided.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use patina_dxe_core::{AllocationAttributionPolicy, Core};
+/// # let physical_hob_list = core::ptr::null();
+///
+/// let core = Core::default()
+///    .init_memory(physical_hob_list)
+///    .with_config(AllocationAttributionPolicy { capacity_margin: 128 })
+///    .start()
+///    .unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AllocationAttributionPolicy {
+    /// The number of entries the table has room for, in addition to the allocations live at ReadyToBoot.
+    pub capacity_margin: usize,
+}
+
+impl Default for AllocationAttributionPolicy {
+    fn default() -> Self {
+        Self { capacity_margin: 64 }
+    }
+}
+
+/// A tracked page allocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Allocation {
+    pages: u64,
+    memory_type: efi::MemoryType,
+    // The owner, as an integer so the tracker can be shared, e.g. the handle of the owning image.
+    owner: usize,
+}
+
+/// The live page allocations, by base address.
+#[derive(Debug, Default)]
+pub(crate) struct AllocationTracker {
+    allocations: BTreeMap<u64, Allocation>,
+}
+
+impl AllocationTracker {
+    pub(crate) const fn new() -> Self {
+        Self { allocations: BTreeMap::new() }
+    }
+
+    /// Records the allocation of `pages` pages at `base`.
+    pub(crate) fn allocate(&mut self, base: u64, pages: u64, memory_type: efi::MemoryType, owner: usize) {
+        self.allocations.insert(base, Allocation { pages, memory_type, owner });
+    }
+
+    /// Records the freeing of `pages` pages at `base`. Allocations that are partially freed keep their remaining
+    /// pages.
+    pub(crate) fn free(&mut self, base: u64, pages: u64) {
+        let end = base + pages * UEFI_PAGE_SIZE as u64;
+        while let Some((&start, &allocation)) = self.allocations.range(..end).next_back() {
+            let allocation_end = start + allocation.pages * UEFI_PAGE_SIZE as u64;
+            if allocation_end <= base {
+                break;
+            }
+            self.allocations.remove(&start);
+            if start < base {
+                let pages = (base - start) / UEFI_PAGE_SIZE as u64;
+                self.allocations.insert(start, Allocation { pages, ..allocation });
+            }
+            if allocation_end > end {
+                let pages = (allocation_end - end) / UEFI_PAGE_SIZE as u64;
+                self.allocations.insert(end, Allocation { pages, ..allocation });
+            }
+            if start <= base {
+                break;
+            }
+        }
+    }
+
+    /// Returns the base, number of pages, memory type and owner of the live allocations, in address order.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (u64, u64, efi::MemoryType, usize)> + '_ {
+        self.allocations
+            .iter()
+            .map(|(&base, allocation)| (base, allocation.pages, allocation.memory_type, allocation.owner))
+    }
+
+    /// Returns the entries of the live allocations, in address order.
+    fn entries(&self) -> impl Iterator<Item = AllocationAttributionEntry> + '_ {
+        self.allocations.iter().map(|(&physical_start, allocation)| AllocationAttributionEntry {
+            physical_start,
+            number_of_pages: allocation.pages,
+            memory_type: allocation.memory_type,
+            reserved: 0,
+            owner: image::file_guid_for_handle(allocation.owner as efi::Handle).unwrap_or(guids::ZERO),
+        })
+    }
+}
+
+// Whether allocations are tracked, set when the [AllocationAttributionPolicy] is provided.
+static TRACKING_ENABLED: AtomicBool = AtomicBool::new(false);
+
+static TRACKER: tpl_lock::TplMutex<AllocationTracker> =
+    tpl_lock::TplMutex::new(efi::TPL_HIGH_LEVEL, AllocationTracker::new(), "AllocationAttributionLock");
+
+// The table buffer, allocated at ReadyToBoot.
+static TABLE: AtomicPtr<AllocationAttributionHeader> = AtomicPtr::new(core::ptr::null_mut());
+
+// The capacity margin of the table, from the [AllocationAttributionPolicy].
+static CAPACITY_MARGIN: AtomicUsize = AtomicUsize::new(0);
+
+/// Starts tracking page allocations, and registers the event allocating and publishing the table at ReadyToBoot.
+pub fn init_allocation_attribution_support(policy: AllocationAttributionPolicy) {
+    CAPACITY_MARGIN.store(policy.capacity_margin, Ordering::Relaxed);
+    TRACKING_ENABLED.store(true, Ordering::Relaxed);
+    if let Err(status) = EVENT_DB.create_event(
+        efi::EVT_NOTIFY_SIGNAL,
+        efi::TPL_CALLBACK,
+        Some(install_allocation_attribution_table_event_wrapper),
+        None,
+        Some(efi::EVENT_GROUP_READY_TO_BOOT),
+    ) {
+        log::error!(
+            "Failed to register an event at Ready to Boot to allocate the allocation attribution table! {status:#X?}"
+        );
+    }
+}
+
+/// Records a page allocation made by the running image, if allocations are tracked.
+pub(crate) fn record_allocation(base: efi::PhysicalAddress, pages: usize, memory_type: efi::MemoryType) {
+    if TRACKING_ENABLED.load(Ordering::Relaxed) {
+        let owner = image::current_running_image().unwrap_or(DXE_CORE_HANDLE);
+        TRACKER.lock().allocate(base, pages as u64, memory_type, owner as usize);
+    }
+}
+
+/// Records pages being freed, if allocations are tracked.
+pub(crate) fn record_free(base: efi::PhysicalAddress, pages: usize) {
+    if TRACKING_ENABLED.load(Ordering::Relaxed) {
+        TRACKER.lock().free(base, pages as u64);
+    }
+}
+
+extern "efiapi" fn install_allocation_attribution_table_event_wrapper(event: efi::Event, _context: *mut c_void) {
+    install_allocation_attribution_table();
+
+    if let Err(status) = EVENT_DB.close_event(event) {
+        log::error!("Failed to close allocation attribution ready to boot event with status {status:#X?}.");
+    }
+}
+
+/// Allocates the table buffer, fills it and installs it as the allocation attribution configuration table.
+fn install_allocation_attribution_table() {
+    if !TABLE.load(Ordering::Relaxed).is_null() {
+        return;
+    }
+
+    let capacity = TRACKER.lock().allocations.len() + CAPACITY_MARGIN.load(Ordering::Relaxed);
+    let size = size_of::<AllocationAttributionHeader>() + capacity * size_of::<AllocationAttributionEntry>();
+
+    let mut address: efi::PhysicalAddress = 0;
+    if let Err(err) = core_allocate_pages(
+        efi::ALLOCATE_ANY_PAGES,
+        efi::RESERVED_MEMORY_TYPE,
+        uefi_size_to_pages!(size),
+        &mut address,
+        None,
+    ) {
+        log::error!("Failed to allocate the allocation attribution table: {err:?}");
+        return;
+    }
+
+    let table = address as *mut AllocationAttributionHeader;
+    // Safety: the buffer was just allocated with room for the header and `capacity` entries.
+    unsafe {
+        table.write(AllocationAttributionHeader {
+            signature: ALLOCATION_ATTRIBUTION_SIGNATURE,
+            revision: ALLOCATION_ATTRIBUTION_REVISION,
+            entry_size: size_of::<AllocationAttributionEntry>() as u32,
+            capacity: capacity as u32,
+            ..Default::default()
+        });
+        fill_table(table, 0);
+    }
+
+    let mut st_guard = systemtables::SYSTEM_TABLE.lock();
+    let st = st_guard.as_mut().expect("System table support not initialized");
+    if let Err(status) = core_install_configuration_table(guids::ALLOCATION_ATTRIBUTION_TABLE, table as *mut c_void, st)
+    {
+        log::error!("Failed to install the allocation attribution configuration table: {status:#X?}");
+        return;
+    }
+    TABLE.store(table, Ordering::Relaxed);
+    log::info!("Allocation attribution table of {capacity} entries installed at {address:#x}.");
+}
+
+/// Fills the table with the allocations live at ExitBootServices, if it was installed.
+///
+/// Must be called at ExitBootServices, once the memory map is final. Does not allocate.
+pub fn capture_allocation_attribution_table() {
+    let table = TABLE.load(Ordering::Relaxed);
+    if !table.is_null() {
+        // Safety: the table buffer was allocated with the layout described by its header, and is never freed.
+        unsafe { fill_table(table, attribution_flags::CAPTURED_AT_EXIT_BOOT_SERVICES) };
+    }
+}
+
+/// Writes as many live allocations as fit in the table at `table`, and sets its `flags`.
+///
+/// ## Safety
+///
+/// `table` must point to a buffer with room for the header and the capacity of entries it describes.
+unsafe fn fill_table(table: *mut AllocationAttributionHeader, mut flags: u32) {
+    // Safety: the caller guarantees the buffer holds a header followed by `capacity` entries.
+    unsafe {
+        let mut header = table.read();
+        let entries = table.add(1) as *mut AllocationAttributionEntry;
+        let mut count = 0;
+        for entry in TRACKER.lock().entries() {
+            if count == header.capacity {
+                flags |= attribution_flags::TRUNCATED;
+                break;
+            }
+            entries.add(count as usize).write(entry);
+            count += 1;
+        }
+        if flags & attribution_flags::TRUNCATED != 0 {
+            log::warn!("The allocation attribution table is truncated, increase the capacity margin of its policy.");
+        }
+        header.count = count;
+        header.flags = flags;
+        table.write(header);
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+    use super::*;
+
+    use crate::{allocator::core_free_pages, systemtables::init_system_table, test_support};
+    use core::slice;
+    use std::vec::Vec;
+
+    const PAGE: u64 = UEFI_PAGE_SIZE as u64;
+
+    fn with_locked_state<F: Fn() + std::panic::RefUnwindSafe>(f: F) {
+        test_support::with_global_lock(|| {
+            TABLE.store(core::ptr::null_mut(), Ordering::Relaxed);
+            TRACKING_ENABLED.store(false, Ordering::Relaxed);
+            *TRACKER.lock() = AllocationTracker::new();
+            unsafe {
+                test_support::init_test_gcd(None);
+                test_support::reset_allocators();
+                init_system_table();
+            }
+            f();
+        })
+        .unwrap();
+    }
+
+    fn installed_table() -> *mut AllocationAttributionHeader {
+        let st = systemtables::SYSTEM_TABLE.lock();
+        let st = st.as_ref().expect("System table is initialized").as_ref();
+        let tables = unsafe { slice::from_raw_parts(st.configuration_table, st.number_of_table_entries) };
+        let table = tables.iter().find(|table| table.vendor_guid == guids::ALLOCATION_ATTRIBUTION_TABLE);
+        table.expect("table is installed").vendor_table as *mut AllocationAttributionHeader
+    }
+
+    fn table_entries(table: *mut AllocationAttributionHeader) -> Vec<AllocationAttributionEntry> {
+        unsafe {
+            let header = table.read();
+            slice::from_raw_parts(table.add(1) as *const AllocationAttributionEntry, header.count as usize).to_vec()
+        }
+    }
+
+    fn ranges(tracker: &AllocationTracker) -> Vec<(u64, u64)> {
+        tracker.allocations.iter().map(|(&base, allocation)| (base, allocation.pages)).collect()
+    }
+
+    #[test]
+    fn tracker_should_split_partially_freed_allocations() {
+        let mut tracker = AllocationTracker::new();
+        tracker.allocate(0x10000, 4, efi::BOOT_SERVICES_DATA, DXE_CORE_HANDLE as usize);
+        tracker.allocate(0x20000, 2, efi::RUNTIME_SERVICES_DATA, DXE_CORE_HANDLE as usize);
+        tracker.allocate(0x30000, 1, efi::ACPI_RECLAIM_MEMORY, DXE_CORE_HANDLE as usize);
+
+        // freeing the middle of an allocation keeps its head and tail.
+        tracker.free(0x10000 + PAGE, 2);
+        assert_eq!(ranges(&tracker), [(0x10000, 1), (0x10000 + 3 * PAGE, 1), (0x20000, 2), (0x30000, 1)]);
+        assert_eq!(tracker.allocations[&(0x10000 + 3 * PAGE)].memory_type, efi::BOOT_SERVICES_DATA);
+
+        // freeing across allocations removes all the pages in the range.
+        tracker.free(0x20000 + PAGE, 0x10);
+        assert_eq!(ranges(&tracker), [(0x10000, 1), (0x10000 + 3 * PAGE, 1), (0x20000, 1)]);
+
+        // freeing untracked pages does nothing.
+        tracker.free(0x50000, 1);
+        assert_eq!(ranges(&tracker).len(), 3);
+
+        tracker.free(0x10000, 0x10);
+        assert_eq!(ranges(&tracker), [(0x20000, 1)]);
+    }
+
+    #[test]
+    fn allocations_should_not_be_tracked_without_policy() {
+        with_locked_state(|| {
+            let mut address = 0;
+            core_allocate_pages(efi::ALLOCATE_ANY_PAGES, efi::BOOT_SERVICES_DATA, 1, &mut address, None).unwrap();
+            assert!(TRACKER.lock().allocations.is_empty());
+            capture_allocation_attribution_table();
+            assert!(TABLE.load(Ordering::Relaxed).is_null());
+        });
+    }
+
+    #[test]
+    fn table_should_attribute_live_allocations() {
+        with_locked_state(|| {
+            init_allocation_attribution_support(AllocationAttributionPolicy { capacity_margin: 4 });
+
+            let mut data = 0;
+            core_allocate_pages(efi::ALLOCATE_ANY_PAGES, efi::RUNTIME_SERVICES_DATA, 2, &mut data, None).unwrap();
+            let mut freed = 0;
+            core_allocate_pages(efi::ALLOCATE_ANY_PAGES, efi::BOOT_SERVICES_DATA, 3, &mut freed, None).unwrap();
+            core_free_pages(freed, 3).unwrap();
+
+            install_allocation_attribution_table();
+            let table = installed_table();
+            assert_eq!(table, TABLE.load(Ordering::Relaxed));
+
+            let header = unsafe { table.read() };
+            assert_eq!(header.signature, ALLOCATION_ATTRIBUTION_SIGNATURE);
+            assert_eq!(header.revision, ALLOCATION_ATTRIBUTION_REVISION);
+            assert_eq!(header.entry_size as usize, size_of::<AllocationAttributionEntry>());
+            assert_eq!(header.flags, 0);
+            // the capacity is computed before the table buffer is allocated, but the buffer is listed.
+            assert_eq!(header.capacity, 5);
+            let data_entry = AllocationAttributionEntry {
+                physical_start: data,
+                number_of_pages: 2,
+                memory_type: efi::RUNTIME_SERVICES_DATA,
+                reserved: 0,
+                owner: guids::ZERO,
+            };
+            let entries = table_entries(table);
+            assert_eq!(entries.len(), 2);
+            assert!(entries.contains(&data_entry));
+            assert!(
+                entries
+                    .iter()
+                    .any(|entry| entry.physical_start == table as u64 && entry.memory_type == efi::RESERVED_MEMORY_TYPE)
+            );
+
+            // at ExitBootServices, the table also lists later allocations.
+            let mut late = 0;
+            core_allocate_pages(efi::ALLOCATE_ANY_PAGES, efi::ACPI_RECLAIM_MEMORY, 1, &mut late, None).unwrap();
+            capture_allocation_attribution_table();
+            let header = unsafe { table.read() };
+            assert_eq!(header.flags, attribution_flags::CAPTURED_AT_EXIT_BOOT_SERVICES);
+            let entries = table_entries(table);
+            assert_eq!(entries.len(), 3);
+            assert!(entries.contains(&data_entry));
+            assert!(entries.iter().any(|entry| entry.physical_start == late && entry.number_of_pages == 1));
+            assert!(entries.windows(2).all(|pair| pair[0].physical_start < pair[1].physical_start));
+        });
+    }
+
+    #[test]
+    fn table_should_be_truncated_when_allocations_do_not_fit() {
+        with_locked_state(|| {
+            init_allocation_attribution_support(AllocationAttributionPolicy { capacity_margin: 0 });
+            install_allocation_attribution_table();
+            let table = installed_table();
+            assert_eq!(unsafe { table.read() }.capacity, 0);
+
+            capture_allocation_attribution_table();
+            let header = unsafe { table.read() };
+            assert_eq!(header.count, 0);
+            assert_eq!(header.flags, attribution_flags::CAPTURED_AT_EXIT_BOOT_SERVICES | attribution_flags::TRUNCATED);
+        });
+    }
+}
@@ -0,0 +1,183 @@
+//! EFI Conformance Profiles Table
+//!
+//! Publishes the EFI Conformance Profiles Table (ECPT), listing the conformance profiles the platform implements, which
+//! OS requirements such as EBBR or Arm SystemReady check at boot. Without the table, the platform is assumed to conform
+//! to the complete UEFI specification, so the core always publishes it when it starts.
+//!
+//! The core lists the [UEFI specification profile](guids::CONFORMANCE_PROFILE_UEFI_SPEC). With the
+//! [ConformanceProfilesPolicy], the platform adds the profiles it implements, e.g. EBBR, and can leave out the UEFI
+//! specification profile if it only implements a reduced profile.
+//!
+//! The table is allocated in runtime services data, so that the OS can still read it after ExitBootServices().
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+use patina::guids;
+use r_efi::efi;
+
+use crate::{allocator::core_allocate_pool, config_tables::core_install_configuration_table, systemtables};
+
+/// The version of the conformance profiles table layout described by [ConformanceProfilesTableHeader].
+pub const CONFORMANCE_PROFILES_TABLE_VERSION: u16 = 1;
+
+/// The header of the conformance profiles table, followed by the profile GUIDs.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ConformanceProfilesTableHeader {
+    /// [CONFORMANCE_PROFILES_TABLE_VERSION].
+    pub version: u16,
+    /// The number of profile GUIDs following the header.
+    pub number_of_profiles: u16,
+}
+
+/// A configuration struct selecting the profiles listed in the
+/// [CONFORMANCE_PROFILES_TABLE](patina::guids::CONFORMANCE_PROFILES_TABLE) configuration table.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use patina_dxe_core::{ConformanceProfilesPolicy, Core};
+/// use r_efi::efi;
+/// # let physical_hob_list = core::ptr::null();
+///
+/// // EFI_CONFORMANCE_PROFILE_EBBR_2_1_GUID
+/// let ebbr = efi::Guid::from_fields(0xcce33c35, 0x74ac, 0x4087, 0xbc, 0xe7, &[0x8b, 0x29, 0xb0, 0x2e, 0xeb, 0x27]);
+/// let core = Core::default()
+///    .init_memory(physical_hob_list)
+///    .with_config(ConformanceProfilesPolicy { uefi_specification: false, profiles: vec![ebbr] })
+///    .start()
+///    .unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConformanceProfilesPolicy {
+    /// List the UEFI specification profile, for platforms conforming to the complete UEFI specification.
+    pub uefi_specification: bool,
+    /// The additional profiles the platform implements.
+    pub profiles: Vec<efi::Guid>,
+}
+
+impl Default for ConformanceProfilesPolicy {
+    fn default() -> Self {
+        Self { uefi_specification: true, profiles: Vec::new() }
+    }
+}
+
+/// Returns the profiles listed for `policy`, without duplicates.
+fn listed_profiles(policy: &ConformanceProfilesPolicy) -> Vec<efi::Guid> {
+    let mut profiles = Vec::with_capacity(policy.profiles.len() + 1);
+    if policy.uefi_specification {
+        profiles.push(guids::CONFORMANCE_PROFILE_UEFI_SPEC);
+    }
+    for profile in &policy.profiles {
+        if !profiles.contains(profile) {
+            profiles.push(*profile);
+        }
+    }
+    profiles
+}
+
+/// Allocates the conformance profiles table for `policy` and installs it as a configuration table.
+pub fn install_conformance_profiles_table(policy: &ConformanceProfilesPolicy) {
+    let mut profiles = listed_profiles(policy);
+    if profiles.len() > u16::MAX as usize {
+        log::error!("Only the first {} of the {} conformance profiles are listed.", u16::MAX, profiles.len());
+        profiles.truncate(u16::MAX as usize);
+    }
+
+    let size = size_of::<ConformanceProfilesTableHeader>() + profiles.len() * size_of::<efi::Guid>();
+    let table = match core_allocate_pool(efi::RUNTIME_SERVICES_DATA, size) {
+        Ok(table) => table as *mut ConformanceProfilesTableHeader,
+        Err(err) => {
+            log::error!("Failed to allocate the conformance profiles table: {err:?}");
+            return;
+        }
+    };
+    // Safety: the buffer was just allocated with room for the header and the profiles. The GUIDs are 4-byte aligned
+    // like the header, so they directly follow it.
+    unsafe {
+        table.write(ConformanceProfilesTableHeader {
+            version: CONFORMANCE_PROFILES_TABLE_VERSION,
+            number_of_profiles: profiles.len() as u16,
+        });
+        core::ptr::copy_nonoverlapping(profiles.as_ptr(), table.add(1) as *mut efi::Guid, profiles.len());
+    }
+
+    let mut st = systemtables::SYSTEM_TABLE.lock();
+    let Some(st) = st.as_mut() else {
+        log::error!("Failed to install the conformance profiles table: the system table is not initialized.");
+        return;
+    };
+    if let Err(err) = core_install_configuration_table(guids::CONFORMANCE_PROFILES_TABLE, table as *mut _, st) {
+        log::error!("Failed to install the conformance profiles table: {err:?}");
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+
+    use crate::{systemtables::init_system_table, test_support};
+    use core::slice;
+    use std::vec;
+
+    const EBBR_2_1: efi::Guid =
+        efi::Guid::from_fields(0xcce33c35, 0x74ac, 0x4087, 0xbc, 0xe7, &[0x8b, 0x29, 0xb0, 0x2e, 0xeb, 0x27]);
+
+    fn with_locked_state<F: Fn() + std::panic::RefUnwindSafe>(f: F) {
+        test_support::with_global_lock(|| {
+            unsafe {
+                test_support::init_test_gcd(None);
+                test_support::reset_allocators();
+                init_system_table();
+            }
+            f();
+        })
+        .unwrap();
+    }
+
+    // Returns the profiles of the installed table.
+    fn installed_profiles() -> Vec<efi::Guid> {
+        let st = systemtables::SYSTEM_TABLE.lock();
+        let st = st.as_ref().expect("System table is initialized").as_ref();
+        let tables = unsafe { slice::from_raw_parts(st.configuration_table, st.number_of_table_entries) };
+        let table = tables.iter().find(|table| table.vendor_guid == guids::CONFORMANCE_PROFILES_TABLE);
+        let table = table.expect("table is installed").vendor_table as *const ConformanceProfilesTableHeader;
+        unsafe {
+            let header = table.read();
+            assert_eq!(header.version, CONFORMANCE_PROFILES_TABLE_VERSION);
+            slice::from_raw_parts(table.add(1) as *const efi::Guid, header.number_of_profiles as usize).to_vec()
+        }
+    }
+
+    #[test]
+    fn table_should_list_the_uefi_specification_profile_by_default() {
+        with_locked_state(|| {
+            install_conformance_profiles_table(&ConformanceProfilesPolicy::default());
+            assert_eq!(installed_profiles(), [guids::CONFORMANCE_PROFILE_UEFI_SPEC]);
+        });
+    }
+
+    #[test]
+    fn table_should_list_the_platform_profiles() {
+        with_locked_state(|| {
+            let policy = ConformanceProfilesPolicy { uefi_specification: true, profiles: vec![EBBR_2_1, EBBR_2_1] };
+            install_conformance_profiles_table(&policy);
+            assert_eq!(installed_profiles(), [guids::CONFORMANCE_PROFILE_UEFI_SPEC, EBBR_2_1]);
+
+            // The table is replaced, and can list no profile at all.
+            let policy = ConformanceProfilesPolicy { uefi_specification: false, profiles: Vec::new() };
+            install_conformance_profiles_table(&policy);
+            assert!(installed_profiles().is_empty());
+        });
+    }
+}
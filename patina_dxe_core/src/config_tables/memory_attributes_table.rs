@@ -1,5 +1,18 @@
 //! DXE Core Memory Attributes Table (MAT)
 //!
+//! The MAT is first published at ReadyToBoot, from the complete memory map. After that, every runtime memory
+//! allocation or deallocation republishes it. To keep the cost of a republication independent of the number of
+//! runtime allocations, the runtime descriptors of the MAT are cached, and only the descriptors of the range that
+//! changed are converted from the GCD and replaced in the cache.
+//!
+//! The attributes of runtime memory can also change after the MAT is published, e.g. when the protections of a
+//! runtime image are applied, so attribute changes of the runtime memory described by the MAT republish it as well.
+//!
+//! Consumers are notified through the MAT GUID event group, which is signaled each time a complete MAT is published.
+//! Runtime images loaded after ReadyToBoot would change the runtime memory the OS loader expects to be described by
+//! the published MAT, so they are rejected unless the [LateRuntimeImagePolicy] allows them. The deprecated EFI
+//! Properties Table is never produced, and drivers are not allowed to install it.
+//!
 //! ## License
 //!
 //! Copyright (c) Microsoft Corporation.
@@ -7,27 +20,38 @@
 //! SPDX-License-Identifier: Apache-2.0
 //!
 extern crate alloc;
-use alloc::vec::Vec;
+use alloc::{collections::BTreeMap, vec::Vec};
 
 use core::{
     ffi::c_void,
     fmt::Debug,
     mem::size_of,
+    ops::Range,
     slice,
     sync::atomic::{AtomicBool, AtomicPtr, Ordering},
 };
 
 use crate::{
-    allocator::{MemoryDescriptorSlice, core_allocate_pool, core_free_pool, get_memory_map_descriptors},
-    config_tables::core_install_configuration_table,
+    LateRuntimeImagePolicy,
+    allocator::{
+        MemoryDescriptorSlice, core_allocate_pool, core_free_pool, get_memory_map_descriptors,
+        get_memory_map_descriptors_for_range,
+    },
+    config_tables::{core_install_configuration_table, core_install_configuration_table_unsignaled},
     events::EVENT_DB,
-    systemtables,
+    systemtables::{self, EfiSystemTable},
+    tpl_lock::TplMutex,
 };
+use patina::base::UEFI_PAGE_SIZE;
 use r_efi::efi;
 
 // We cache the MAT here because we need to free it in whenever we get a new runtime code/data allocation
 static MEMORY_ATTRIBUTES_TABLE: AtomicPtr<c_void> = AtomicPtr::new(core::ptr::null_mut());
 
+// The descriptors of the published MAT, updated incrementally on runtime memory allocations/deallocations.
+static MAT_DESCRIPTORS: TplMutex<MatDescriptors> =
+    TplMutex::new(efi::TPL_NOTIFY, MatDescriptors::new(), "MatDescriptorsLock");
+
 // create a wrapper struct so that we can create an install method on it. That way, we can have the install function
 // be a no-op until after ReadyToBoot
 pub struct MemoryAttributesTable(*mut efi::MemoryAttributesTable);
@@ -36,11 +60,22 @@ pub struct MemoryAttributesTable(*mut efi::MemoryAttributesTable);
 // allocation/deallocation
 static POST_RTB: AtomicBool = AtomicBool::new(false);
 
+// whether runtime images may be loaded after ReadyToBoot, per the LateRuntimeImagePolicy.
+static ALLOW_LATE_RUNTIME_IMAGES: AtomicBool = AtomicBool::new(false);
+
+/// The GUID of the EFI Properties Table, deprecated in favor of the MAT.
+///
+/// (`880AACA3-4ADC-4A04-9079-B747340825E5`)
+pub(crate) const PROPERTIES_TABLE_GUID: efi::Guid =
+    efi::Guid::from_fields(0x880aaca3, 0x4adc, 0x4a04, 0x90, 0x79, &[0xb7, 0x47, 0x34, 0x08, 0x25, 0xe5]);
+
 impl MemoryAttributesTable {
     ///
-    /// Install the Memory Attributes Table
-    /// This function is intended to be called by the DXE Core to install the Memory Attributes Table for runtime memory
-    /// allocations/deallocations after ReadyToBoot has occurred. This function will be a no-op until after ReadyToBoot.
+    /// Update the Memory Attributes Table for a runtime memory allocation/deallocation
+    /// This function is intended to be called by the DXE Core to update the Memory Attributes Table for runtime memory
+    /// allocations/deallocations after ReadyToBoot has occurred. This function will be a no-op until after ReadyToBoot,
+    /// as the MAT is built from the complete memory map at ReadyToBoot. Only the descriptors of the range
+    /// `base_address` to `base_address + length` are updated.
     /// Callers of the function are not expected to check return status as it is immaterial to the caller whether it
     /// succeeds or not and they will take no different action based on return status.
     ///
@@ -49,13 +84,32 @@ impl MemoryAttributesTable {
     /// ```ignore
     /// use patina_dxe_core::memory_attributes_table::MemoryAttributesTable;
     /// // do a runtime memory allocation/deallocation here that succeeds in getting a new page or freeing a page
-    /// MemoryAttributesTable::install();
+    /// MemoryAttributesTable::update(base_address, length);
     /// // continue allocator logic
     /// ```
     ///
-    pub fn install() {
+    pub fn update(base_address: efi::PhysicalAddress, length: u64) {
         if POST_RTB.load(Ordering::Relaxed) {
-            core_install_memory_attributes_table()
+            core_update_memory_attributes_table(base_address, length)
+        }
+    }
+
+    /// Update the Memory Attributes Table for an attribute change of the range `base_address` to
+    /// `base_address + length`. This is a no-op until after ReadyToBoot, and for ranges that do not overlap the runtime
+    /// memory described by the MAT, which includes the memory allocated while the MAT is being published.
+    pub fn attributes_changed(base_address: efi::PhysicalAddress, length: u64) {
+        if !POST_RTB.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let range = base_address..base_address.saturating_add(length);
+        let overlaps = match MAT_DESCRIPTORS.try_lock() {
+            Some(mat_descriptors) => mat_descriptors.overlaps(&range),
+            // the MAT is being published, so the change is to the memory allocated for it.
+            None => false,
+        };
+        if overlaps {
+            core_update_memory_attributes_table(base_address, length)
         }
     }
 }
@@ -79,6 +133,116 @@ impl Debug for MemoryAttributesTable {
     }
 }
 
+/// The runtime descriptors of the MAT, keyed by physical start address.
+///
+/// Adjacent descriptors with the same type and attributes are merged.
+#[derive(Debug, Default)]
+struct MatDescriptors(BTreeMap<u64, efi::MemoryDescriptor>);
+
+impl MatDescriptors {
+    const fn new() -> Self {
+        Self(BTreeMap::new())
+    }
+
+    /// Builds the MAT descriptors from the complete memory map.
+    fn from_memory_map(memory_map: &[efi::MemoryDescriptor]) -> Self {
+        let mut descriptors = Self::new();
+        memory_map.iter().filter_map(mat_descriptor).for_each(|descriptor| descriptors.insert(descriptor));
+        descriptors
+    }
+
+    fn end(descriptor: &efi::MemoryDescriptor) -> u64 {
+        descriptor.physical_start + descriptor.number_of_pages * UEFI_PAGE_SIZE as u64
+    }
+
+    fn mergeable(first: &efi::MemoryDescriptor, second: &efi::MemoryDescriptor) -> bool {
+        first.r#type == second.r#type
+            && first.attribute == second.attribute
+            && Self::end(first) == second.physical_start
+    }
+
+    /// Returns whether a descriptor overlaps `range`.
+    fn overlaps(&self, range: &Range<u64>) -> bool {
+        self.0.range(..range.end).next_back().is_some_and(|(_, descriptor)| Self::end(descriptor) > range.start)
+    }
+
+    /// Replaces the descriptors of `range` with the MAT descriptors of the memory map descriptors `memory_map`, which
+    /// must describe `range`.
+    fn replace_range(&mut self, range: Range<u64>, memory_map: &[efi::MemoryDescriptor]) {
+        self.remove_range(&range);
+        memory_map.iter().filter_map(mat_descriptor).for_each(|descriptor| self.insert(descriptor));
+    }
+
+    /// Removes `range` from the descriptors, splitting the descriptors partially in the range.
+    fn remove_range(&mut self, range: &Range<u64>) {
+        let overlapping: Vec<efi::MemoryDescriptor> = self
+            .0
+            .range(..range.end)
+            .rev()
+            .take_while(|(_, descriptor)| Self::end(descriptor) > range.start)
+            .map(|(_, descriptor)| *descriptor)
+            .collect();
+
+        for descriptor in overlapping {
+            self.0.remove(&descriptor.physical_start);
+            if descriptor.physical_start < range.start {
+                let number_of_pages = (range.start - descriptor.physical_start) / UEFI_PAGE_SIZE as u64;
+                self.0.insert(descriptor.physical_start, efi::MemoryDescriptor { number_of_pages, ..descriptor });
+            }
+            if Self::end(&descriptor) > range.end {
+                let number_of_pages = (Self::end(&descriptor) - range.end) / UEFI_PAGE_SIZE as u64;
+                self.0.insert(
+                    range.end,
+                    efi::MemoryDescriptor { physical_start: range.end, number_of_pages, ..descriptor },
+                );
+            }
+        }
+    }
+
+    /// Inserts a descriptor that does not overlap the existing ones, merging it with its neighbors.
+    fn insert(&mut self, mut descriptor: efi::MemoryDescriptor) {
+        if let Some((_, previous)) = self.0.range(..descriptor.physical_start).next_back()
+            && Self::mergeable(previous, &descriptor)
+        {
+            let previous = self.0.remove(&previous.physical_start.clone()).expect("previous descriptor exists");
+            descriptor.physical_start = previous.physical_start;
+            descriptor.number_of_pages += previous.number_of_pages;
+        }
+        if let Some(next) = self.0.get(&Self::end(&descriptor)).copied()
+            && Self::mergeable(&descriptor, &next)
+        {
+            self.0.remove(&next.physical_start);
+            descriptor.number_of_pages += next.number_of_pages;
+        }
+        self.0.insert(descriptor.physical_start, descriptor);
+    }
+}
+
+/// Converts a memory map descriptor to a MAT descriptor, or returns `None` if it is not runtime memory.
+fn mat_descriptor(descriptor: &efi::MemoryDescriptor) -> Option<efi::MemoryDescriptor> {
+    let mat_allowed_attrs = efi::MEMORY_RO | efi::MEMORY_XP | efi::MEMORY_RUNTIME;
+
+    // we only want the EfiRuntimeServicesCode and EfiRuntimeServicesData sections in the MAT
+    match descriptor.r#type {
+        efi::RUNTIME_SERVICES_CODE | efi::RUNTIME_SERVICES_DATA => {
+            Some(efi::MemoryDescriptor {
+                attribute: match descriptor.attribute & (efi::MEMORY_RO | efi::MEMORY_XP) {
+                    // if we don't have any attributes set here, we should mark code as RO and XP. These are
+                    // likely extra sections in the memory bins and so should not be used
+                    // Data we will mark as XP only, as likely the caching attributes were changed, which
+                    // dropped the XP attribute, so we need to set it here.
+                    0 if descriptor.r#type == efi::RUNTIME_SERVICES_CODE => mat_allowed_attrs,
+                    0 if descriptor.r#type == efi::RUNTIME_SERVICES_DATA => efi::MEMORY_RUNTIME | efi::MEMORY_XP,
+                    _ => descriptor.attribute & mat_allowed_attrs,
+                },
+                // use all other fields from the GCD descriptor
+                ..*descriptor
+            })
+        }
+        _ => None,
+    }
+}
+
 // this function is intended to be called by dxe_main to set up the event to create the MAT for the first time
 // on Ready to Boot.
 pub fn init_memory_attributes_table_support() {
@@ -94,11 +258,11 @@ pub fn init_memory_attributes_table_support() {
 }
 
 // this callback is invoked on ready to boot to install the memory attributes table for the first time.
-// After this point, subsequent runtime memory allocations/deallocations will create new MAT tables
+// After this point, subsequent runtime memory allocations/deallocations will update the MAT
 extern "efiapi" fn core_install_memory_attributes_table_event_wrapper(event: efi::Event, _context: *mut c_void) {
     core_install_memory_attributes_table();
     // now we want to capture any future runtime memory changes, so we will mark that ReadyToBoot has occurred
-    // and the install callback will be invoked on the next runtime memory allocation
+    // and the update callback will be invoked on the next runtime memory allocation
     POST_RTB.store(true, Ordering::Relaxed);
 
     if let Err(status) = EVENT_DB.close_event(event) {
@@ -106,10 +270,100 @@ extern "efiapi" fn core_install_memory_attributes_table_event_wrapper(event: efi
     }
 }
 
+/// Sets the policy applied to the runtime images loaded after the MAT is published at ReadyToBoot.
+pub fn set_late_runtime_image_policy(policy: LateRuntimeImagePolicy) {
+    ALLOW_LATE_RUNTIME_IMAGES.store(policy == LateRuntimeImagePolicy::Allow, Ordering::Relaxed);
+}
+
+/// Returns whether a runtime image may be loaded: always before ReadyToBoot, afterwards only if the
+/// [LateRuntimeImagePolicy] allows it.
+pub(crate) fn runtime_image_load_allowed() -> bool {
+    !POST_RTB.load(Ordering::Relaxed) || ALLOW_LATE_RUNTIME_IMAGES.load(Ordering::Relaxed)
+}
+
+/// Builds the MAT from the complete memory map and publishes it.
 pub fn core_install_memory_attributes_table() {
     let mut st_guard = systemtables::SYSTEM_TABLE.lock();
     let st = st_guard.as_mut().expect("System table support not initialized");
 
+    if !install_empty_memory_attributes_table(st) {
+        return;
+    }
+
+    // get the GCD memory map descriptors and filter out the non-runtime sections
+    let desc_list = match get_memory_map_descriptors(true) {
+        Ok(descriptors) => descriptors,
+        Err(_) => {
+            log::error!("Failed to get memory map descriptors.");
+            return;
+        }
+    };
+
+    if desc_list.is_empty() {
+        log::error!("Failed to install memory attributes table! Could not get memory map descriptors.");
+        return;
+    }
+
+    let mut mat_descriptors = MAT_DESCRIPTORS.lock();
+    *mat_descriptors = MatDescriptors::from_memory_map(&desc_list);
+    publish_memory_attributes_table(st, &mat_descriptors);
+}
+
+/// Updates the descriptors of the range `base_address` to `base_address + length` in the MAT, and republishes it.
+pub fn core_update_memory_attributes_table(base_address: efi::PhysicalAddress, length: u64) {
+    let mut st_guard = systemtables::SYSTEM_TABLE.lock();
+    let st = st_guard.as_mut().expect("System table support not initialized");
+
+    if MEMORY_ATTRIBUTES_TABLE.load(Ordering::Relaxed).is_null() {
+        // the MAT has not been built yet, build it from the complete memory map
+        drop(st_guard);
+        return core_install_memory_attributes_table();
+    }
+
+    let (range, desc_list) = match get_memory_map_descriptors_for_range(base_address, length, true) {
+        Ok(descriptors) => descriptors,
+        Err(err) => {
+            log::error!(
+                "Failed to get memory map descriptors for {base_address:#x?} of length {length:#x?}. Status {err:#X?}"
+            );
+            return;
+        }
+    };
+
+    let mut mat_descriptors = MAT_DESCRIPTORS.lock();
+    mat_descriptors.replace_range(range, &desc_list);
+    publish_memory_attributes_table(st, &mat_descriptors);
+}
+
+/// Returns the descriptors of the published MAT, if any.
+pub(crate) fn published_descriptors() -> Vec<efi::MemoryDescriptor> {
+    let mat_ptr = MEMORY_ATTRIBUTES_TABLE.load(Ordering::Relaxed) as *const efi::MemoryAttributesTable;
+    // Safety: the published MAT is only freed when it is replaced, and has `number_of_entries` entries.
+    match unsafe { mat_ptr.as_ref() } {
+        Some(mat) => unsafe { slice::from_raw_parts(mat.entry.as_ptr(), mat.number_of_entries as usize) }.to_vec(),
+        None => Vec::new(),
+    }
+}
+
+/// Marks ReadyToBoot as passed without publishing the MAT, for tests of the runtime image policy.
+#[cfg(test)]
+pub(crate) fn set_post_ready_to_boot() {
+    POST_RTB.store(true, Ordering::Relaxed);
+}
+
+/// Forgets the published MAT, for tests that reinitialize the GCD.
+#[cfg(test)]
+pub(crate) fn reset_memory_attributes_table() {
+    POST_RTB.store(false, Ordering::Relaxed);
+    ALLOW_LATE_RUNTIME_IMAGES.store(false, Ordering::Relaxed);
+    MEMORY_ATTRIBUTES_TABLE.store(core::ptr::null_mut(), Ordering::Relaxed);
+    *MAT_DESCRIPTORS.lock() = MatDescriptors::new();
+}
+
+/// Installs an empty MAT the first time the MAT is published. Returns false if it failed.
+///
+/// The empty MAT does not signal the MAT GUID event group, consumers are only notified of the complete MAT.
+fn install_empty_memory_attributes_table(st: &mut EfiSystemTable) -> bool {
     let current_ptr = MEMORY_ATTRIBUTES_TABLE.load(Ordering::Relaxed);
     if current_ptr.is_null() {
         // we need to install an empty configuration table the first time here, because core_install_configuration_table
@@ -129,73 +383,34 @@ pub fn core_install_memory_attributes_table() {
                     MEMORY_ATTRIBUTES_TABLE.store(empty_ptr, Ordering::Relaxed);
 
                     if let Err(status) =
-                        core_install_configuration_table(efi::MEMORY_ATTRIBUTES_TABLE_GUID, empty_ptr, st)
+                        core_install_configuration_table_unsignaled(efi::MEMORY_ATTRIBUTES_TABLE_GUID, empty_ptr, st)
                     {
                         log::error!("Failed to create a null MAT table with status {status:#X?}, cannot create MAT");
-                        return;
+                        return false;
                     }
                 }
             }
             Err(err) => {
                 log::error!("Failed to allocate memory for a null MAT! Status {err:#X?}");
-                return;
+                return false;
             }
         }
     }
+    true
+}
 
-    // get the GCD memory map descriptors and filter out the non-runtime sections
-    let desc_list = match get_memory_map_descriptors(true) {
-        Ok(descriptors) => descriptors,
-        Err(_) => {
-            log::error!("Failed to get memory map descriptors.");
-            return;
-        }
-    };
-    let mat_allowed_attrs = efi::MEMORY_RO | efi::MEMORY_XP | efi::MEMORY_RUNTIME;
-
-    if desc_list.is_empty() {
-        log::error!("Failed to install memory attributes table! Could not get memory map descriptors.");
-        return;
-    }
-
-    // this allocates memory to do the collect, but that's okay because it is boot services memory
-    let mat_desc_list: Vec<efi::MemoryDescriptor> = desc_list
-        .iter()
-        .filter_map(|descriptor| {
-            // we only want the EfiRuntimeServicesCode and EfiRuntimeServicesData sections in the MAT
-            match descriptor.r#type {
-                efi::RUNTIME_SERVICES_CODE | efi::RUNTIME_SERVICES_DATA => {
-                    Some(efi::MemoryDescriptor {
-                        attribute: match descriptor.attribute & (efi::MEMORY_RO | efi::MEMORY_XP) {
-                            // if we don't have any attributes set here, we should mark code as RO and XP. These are
-                            // likely extra sections in the memory bins and so should not be used
-                            // Data we will mark as XP only, as likely the caching attributes were changed, which
-                            // dropped the XP attribute, so we need to set it here.
-                            0 if descriptor.r#type == efi::RUNTIME_SERVICES_CODE => mat_allowed_attrs,
-                            0 if descriptor.r#type == efi::RUNTIME_SERVICES_DATA => {
-                                efi::MEMORY_RUNTIME | efi::MEMORY_XP
-                            }
-                            _ => descriptor.attribute & mat_allowed_attrs,
-                        },
-                        // use all other fields from the GCD descriptor
-                        ..*descriptor
-                    })
-                }
-                _ => None,
-            }
-        })
-        .collect();
+/// Allocates a MAT for `mat_descriptors`, installs it and frees the previous one.
+fn publish_memory_attributes_table(st: &mut EfiSystemTable, mat_descriptors: &MatDescriptors) {
+    let entry_count = mat_descriptors.0.len();
 
     // allocate memory for the MAT and publish it
-    let buffer_size =
-        mat_desc_list.len() * size_of::<efi::MemoryDescriptor>() + size_of::<efi::MemoryAttributesTable>();
+    let buffer_size = entry_count * size_of::<efi::MemoryDescriptor>() + size_of::<efi::MemoryAttributesTable>();
     match core_allocate_pool(efi::BOOT_SERVICES_DATA, buffer_size) {
         Err(err) => {
             log::error!("Failed to allocate memory for the MAT! Status {err:#X?}");
             return;
         }
         Ok(void_ptr) => {
-            let mat_descriptors_ptr = mat_desc_list.as_ptr() as *mut u8;
             let mat_ptr = void_ptr as *mut efi::MemoryAttributesTable;
             if mat_ptr.is_null() {
                 log::error!("Got a null ptr in successful return from allocate_pool. Failed to create MAT.");
@@ -207,17 +422,14 @@ pub fn core_install_memory_attributes_table() {
             unsafe {
                 let mat = &mut *mat_ptr;
                 mat.version = efi::MEMORY_ATTRIBUTES_TABLE_VERSION;
-                mat.number_of_entries = mat_desc_list.len() as u32;
+                mat.number_of_entries = entry_count as u32;
                 mat.descriptor_size = size_of::<efi::MemoryDescriptor>() as u32;
                 mat.reserved = 0;
 
-                let copy_ptr = core::ptr::from_ref(&mat.entry) as *mut u8;
-
-                core::ptr::copy(
-                    mat_descriptors_ptr,
-                    copy_ptr,
-                    mat_desc_list.len() * size_of::<efi::MemoryDescriptor>(),
-                );
+                let entries_ptr = core::ptr::from_ref(&mat.entry) as *mut efi::MemoryDescriptor;
+                for (index, descriptor) in mat_descriptors.0.values().enumerate() {
+                    entries_ptr.add(index).write_unaligned(*descriptor);
+                }
 
                 match core_install_configuration_table(efi::MEMORY_ATTRIBUTES_TABLE_GUID, void_ptr, st) {
                     Err(status) => {
@@ -254,7 +466,7 @@ mod tests {
     use super::*;
 
     use crate::{
-        allocator::core_allocate_pages,
+        allocator::{core_allocate_pages, core_free_pages},
         dxe_services::{core_set_memory_space_attributes, core_set_memory_space_capabilities},
         systemtables::init_system_table,
         test_support,
@@ -263,8 +475,7 @@ mod tests {
 
     fn with_locked_state<F: Fn() + std::panic::RefUnwindSafe>(f: F) {
         test_support::with_global_lock(|| {
-            POST_RTB.store(false, Ordering::Relaxed);
-            MEMORY_ATTRIBUTES_TABLE.store(core::ptr::null_mut(), Ordering::Relaxed);
+            reset_memory_attributes_table();
 
             unsafe {
                 test_support::init_test_gcd(None);
@@ -276,6 +487,76 @@ mod tests {
         .unwrap();
     }
 
+    fn descriptor(r#type: u32, physical_start: u64, number_of_pages: u64, attribute: u64) -> efi::MemoryDescriptor {
+        efi::MemoryDescriptor { r#type, physical_start, virtual_start: 0, number_of_pages, attribute }
+    }
+
+    fn entries(descriptors: &MatDescriptors) -> Vec<(u32, u64, u64, u64)> {
+        descriptors.0.values().map(|d| (d.r#type, d.physical_start, d.number_of_pages, d.attribute)).collect()
+    }
+
+    fn published_entries() -> Vec<(u32, u64, u64, u64)> {
+        let mat_ptr = MEMORY_ATTRIBUTES_TABLE.load(Ordering::Relaxed) as *const efi::MemoryAttributesTable;
+        let mat = unsafe { mat_ptr.as_ref().expect("MAT is published") };
+        let entries = unsafe { slice::from_raw_parts(mat.entry.as_ptr(), mat.number_of_entries as usize) };
+        entries.iter().map(|d| (d.r#type, d.physical_start, d.number_of_pages, d.attribute)).collect()
+    }
+
+    #[test]
+    fn test_mat_descriptors_replace_range() {
+        const CODE_ATTRS: u64 = efi::MEMORY_RO | efi::MEMORY_RUNTIME;
+        const DATA_ATTRS: u64 = efi::MEMORY_XP | efi::MEMORY_RUNTIME;
+        let page = UEFI_PAGE_SIZE as u64;
+
+        // adjacent descriptors with the same type and attributes are merged, boot services memory is dropped.
+        let mut descriptors = MatDescriptors::from_memory_map(&[
+            descriptor(efi::RUNTIME_SERVICES_CODE, 0x10000, 2, CODE_ATTRS),
+            descriptor(efi::RUNTIME_SERVICES_CODE, 0x12000, 2, CODE_ATTRS),
+            descriptor(efi::BOOT_SERVICES_DATA, 0x14000, 1, efi::MEMORY_XP),
+            descriptor(efi::RUNTIME_SERVICES_DATA, 0x15000, 1, DATA_ATTRS),
+        ]);
+        assert_eq!(
+            entries(&descriptors),
+            [
+                (efi::RUNTIME_SERVICES_CODE, 0x10000, 4, CODE_ATTRS),
+                (efi::RUNTIME_SERVICES_DATA, 0x15000, 1, DATA_ATTRS)
+            ]
+        );
+
+        // replacing the middle of a descriptor splits it.
+        descriptors.replace_range(0x11000..0x12000, &[descriptor(efi::RUNTIME_SERVICES_DATA, 0x11000, 1, DATA_ATTRS)]);
+        assert_eq!(
+            entries(&descriptors),
+            [
+                (efi::RUNTIME_SERVICES_CODE, 0x10000, 1, CODE_ATTRS),
+                (efi::RUNTIME_SERVICES_DATA, 0x11000, 1, DATA_ATTRS),
+                (efi::RUNTIME_SERVICES_CODE, 0x12000, 2, CODE_ATTRS),
+                (efi::RUNTIME_SERVICES_DATA, 0x15000, 1, DATA_ATTRS)
+            ]
+        );
+
+        // a range spanning several descriptors is replaced, and the new descriptors merge with their neighbors.
+        descriptors.replace_range(
+            0x11000..0x15000,
+            &[
+                descriptor(efi::RUNTIME_SERVICES_CODE, 0x11000, 1, CODE_ATTRS),
+                descriptor(efi::CONVENTIONAL_MEMORY, 0x12000, 2, 0),
+                descriptor(efi::RUNTIME_SERVICES_DATA, 0x14000, 1, 0),
+            ],
+        );
+        assert_eq!(
+            entries(&descriptors),
+            [
+                (efi::RUNTIME_SERVICES_CODE, 0x10000, 2, CODE_ATTRS),
+                (efi::RUNTIME_SERVICES_DATA, 0x14000, 2, DATA_ATTRS)
+            ]
+        );
+
+        // freeing everything leaves no descriptors.
+        descriptors.replace_range(0x10000..0x10000 + 6 * page, &[]);
+        assert!(descriptors.0.is_empty());
+    }
+
     #[test]
     fn test_mat_init() {
         with_locked_state(|| {
@@ -399,4 +680,130 @@ mod tests {
             }
         });
     }
+
+    extern "efiapi" fn mat_notify(_event: efi::Event, _context: *mut c_void) {}
+
+    #[test]
+    fn test_mat_group_is_signaled_once_the_mat_is_complete() {
+        with_locked_state(|| {
+            let event = EVENT_DB
+                .create_event(
+                    efi::EVT_NOTIFY_SIGNAL,
+                    efi::TPL_CALLBACK,
+                    Some(mat_notify),
+                    None,
+                    Some(efi::MEMORY_ATTRIBUTES_TABLE_GUID),
+                )
+                .unwrap();
+
+            // the empty MAT installed first does not notify the consumers.
+            let mut st_guard = systemtables::SYSTEM_TABLE.lock();
+            assert!(install_empty_memory_attributes_table(st_guard.as_mut().unwrap()));
+            drop(st_guard);
+            assert!(!EVENT_DB.is_signaled(event));
+
+            core_install_memory_attributes_table_event_wrapper(core::ptr::null_mut(), core::ptr::null_mut());
+            assert!(EVENT_DB.read_and_clear_signaled(event).unwrap());
+            assert!(!published_entries().is_empty());
+
+            EVENT_DB.close_event(event).unwrap();
+        });
+    }
+
+    #[test]
+    fn test_late_runtime_images_follow_the_policy() {
+        with_locked_state(|| {
+            assert!(runtime_image_load_allowed());
+
+            core_install_memory_attributes_table_event_wrapper(core::ptr::null_mut(), core::ptr::null_mut());
+            assert!(!runtime_image_load_allowed());
+
+            set_late_runtime_image_policy(LateRuntimeImagePolicy::Allow);
+            assert!(runtime_image_load_allowed());
+
+            set_late_runtime_image_policy(LateRuntimeImagePolicy::Reject);
+            assert!(!runtime_image_load_allowed());
+        });
+    }
+
+    #[test]
+    fn test_mat_is_updated_when_runtime_attributes_change_after_install() {
+        with_locked_state(|| {
+            let pages = 2;
+            let len = (pages * UEFI_PAGE_SIZE) as u64;
+            let mut address: efi::PhysicalAddress = 0;
+            core_allocate_pages(efi::ALLOCATE_ANY_PAGES, efi::RUNTIME_SERVICES_CODE, pages, &mut address, None)
+                .unwrap();
+            let _ = core_set_memory_space_capabilities(address, len, u64::MAX);
+            let _ = core_set_memory_space_attributes(address, len, efi::MEMORY_XP | efi::MEMORY_RUNTIME);
+
+            core_install_memory_attributes_table_event_wrapper(core::ptr::null_mut(), core::ptr::null_mut());
+            // the type and attributes of the MAT entry describing the page at `page`.
+            let entry = |page: u64| {
+                published_entries()
+                    .into_iter()
+                    .find(|(_, start, count, _)| *start <= page && page < start + count * UEFI_PAGE_SIZE as u64)
+                    .map(|(r#type, _, _, attribute)| (r#type, attribute))
+            };
+            let second_page = address + UEFI_PAGE_SIZE as u64;
+            assert_eq!(entry(address), Some((efi::RUNTIME_SERVICES_CODE, efi::MEMORY_XP | efi::MEMORY_RUNTIME)));
+
+            // apply the protections of a runtime image to its first page after the MAT is published.
+            let _ =
+                core_set_memory_space_attributes(address, UEFI_PAGE_SIZE as u64, efi::MEMORY_RO | efi::MEMORY_RUNTIME);
+            assert_eq!(entry(address), Some((efi::RUNTIME_SERVICES_CODE, efi::MEMORY_RO | efi::MEMORY_RUNTIME)));
+            assert_eq!(entry(second_page), Some((efi::RUNTIME_SERVICES_CODE, efi::MEMORY_XP | efi::MEMORY_RUNTIME)));
+
+            // the republished MAT matches a MAT built from the complete memory map.
+            let full = MatDescriptors::from_memory_map(&get_memory_map_descriptors(true).unwrap());
+            assert_eq!(published_entries(), entries(&full));
+        });
+    }
+
+    #[test]
+    fn test_mat_is_updated_incrementally_for_many_runtime_images() {
+        with_locked_state(|| {
+            core_install_memory_attributes_table_event_wrapper(core::ptr::null_mut(), core::ptr::null_mut());
+            assert!(POST_RTB.load(Ordering::Relaxed));
+
+            // emulate loading many runtime images after ReadyToBoot, and unloading some of them.
+            let mut allocations = Vec::new();
+            for i in 0..256usize {
+                let (memory_type, attributes) = match i % 2 {
+                    0 => (efi::RUNTIME_SERVICES_CODE, efi::MEMORY_RO),
+                    _ => (efi::RUNTIME_SERVICES_DATA, efi::MEMORY_XP),
+                };
+                let pages = 1 + i % 3;
+                let mut address: efi::PhysicalAddress = 0;
+                core_allocate_pages(efi::ALLOCATE_ANY_PAGES, memory_type, pages, &mut address, None).unwrap();
+                let len = (pages * UEFI_PAGE_SIZE) as u64;
+                let _ = core_set_memory_space_capabilities(address, len, u64::MAX);
+                let _ = core_set_memory_space_attributes(address, len, attributes);
+                MemoryAttributesTable::update(address, len);
+
+                // each update only converts the descriptors of the changed range, regardless of the image count.
+                let (_, descriptors) = get_memory_map_descriptors_for_range(address, len, true).unwrap();
+                assert!(descriptors.len() <= 3, "update converted {} descriptors", descriptors.len());
+
+                allocations.push((address, pages));
+            }
+
+            for (address, pages) in allocations.iter().step_by(3) {
+                core_free_pages(*address, *pages).unwrap();
+            }
+
+            // the incrementally maintained MAT matches a MAT built from the complete memory map.
+            let full = MatDescriptors::from_memory_map(&get_memory_map_descriptors(true).unwrap());
+            assert_eq!(entries(&MAT_DESCRIPTORS.lock()), entries(&full));
+            assert_eq!(published_entries(), entries(&full));
+
+            for (address, pages) in allocations.iter().skip(1).step_by(3) {
+                assert!(
+                    published_entries().iter().any(|(_, start, count, _)| *start <= *address
+                        && address + (*pages * UEFI_PAGE_SIZE) as u64 <= start + count * UEFI_PAGE_SIZE as u64),
+                    "runtime allocation at {address:#x?} missing from the MAT"
+                );
+            }
+        });
+    }
 }
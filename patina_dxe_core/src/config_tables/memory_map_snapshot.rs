@@ -0,0 +1,718 @@
+//! DXE Core Memory Map Snapshot
+//!
+//! Records the final memory map, GCD memory space map and Memory Attributes Table (MAT) at ExitBootServices, so that
+//! post-boot tooling can verify invariants of the OS handoff, such as runtime regions not overlapping and the MAT
+//! describing exactly the runtime regions of the memory map.
+//!
+//! The snapshot cannot allocate memory at ExitBootServices, as the memory map must not change once the OS loader got
+//! its map key. The buffer is therefore allocated as reserved memory at ReadyToBoot, with room for the descriptors
+//! present then plus a margin, and published as the [MEMORY_MAP_SNAPSHOT](patina::guids::MEMORY_MAP_SNAPSHOT)
+//! configuration table. The descriptors that do not fit at ExitBootServices are dropped, and the snapshot is flagged
+//! as truncated.
+//!
+//! The buffer starts with a [MemoryMapSnapshotHeader], describing where each [SnapshotArray] of descriptors is. With
+//! the `std` feature, [MemoryMapSnapshot] decodes a copy of the buffer and checks its invariants.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+extern crate alloc;
+use alloc::vec::Vec;
+
+use core::{
+    ffi::c_void,
+    mem::size_of,
+    slice,
+    sync::atomic::{AtomicPtr, AtomicUsize, Ordering},
+};
+
+use patina::{guids, uefi_size_to_pages};
+use patina_pi::dxe_services::MemorySpaceDescriptor;
+use r_efi::efi;
+
+use crate::{
+    GCD,
+    allocator::{core_allocate_pages, get_memory_map_descriptors},
+    config_tables::{core_install_configuration_table, memory_attributes_table},
+    events::EVENT_DB,
+    systemtables,
+};
+
+/// The signature of a [MemoryMapSnapshotHeader], `"MMSS"`.
+pub const MEMORY_MAP_SNAPSHOT_SIGNATURE: u32 = u32::from_le_bytes(*b"MMSS");
+
+/// The revision of the memory map snapshot layout described by [MemoryMapSnapshotHeader].
+pub const MEMORY_MAP_SNAPSHOT_REVISION: u32 = 1;
+
+/// The [MemoryMapSnapshotHeader::flags] of a memory map snapshot.
+pub mod snapshot_flags {
+    /// The snapshot was captured at ExitBootServices. Until then, the arrays of the snapshot are empty.
+    pub const CAPTURED: u32 = 0x1;
+    /// Some descriptors did not fit in the arrays of the snapshot, and were dropped.
+    pub const TRUNCATED: u32 = 0x2;
+}
+
+/// An array of descriptors in a memory map snapshot buffer.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SnapshotArray {
+    /// The offset of the array from the start of the buffer, in bytes.
+    pub offset: u32,
+    /// The number of entries the array has room for.
+    pub capacity: u32,
+    /// The number of entries in the array.
+    pub count: u32,
+    /// The size of an entry, in bytes.
+    pub entry_size: u32,
+}
+
+/// The header of a memory map snapshot buffer.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryMapSnapshotHeader {
+    /// [MEMORY_MAP_SNAPSHOT_SIGNATURE].
+    pub signature: u32,
+    /// [MEMORY_MAP_SNAPSHOT_REVISION].
+    pub revision: u32,
+    /// The size of the buffer, including the header, in bytes.
+    pub buffer_size: u32,
+    /// The [snapshot_flags] of the snapshot.
+    pub flags: u32,
+    /// The memory map descriptors, as `efi::MemoryDescriptor`.
+    pub memory_map: SnapshotArray,
+    /// The GCD memory space descriptors, as [GcdMemorySpaceEntry].
+    pub gcd: SnapshotArray,
+    /// The MAT descriptors, as `efi::MemoryDescriptor`.
+    pub mat: SnapshotArray,
+}
+
+/// A GCD memory space descriptor in a memory map snapshot.
+///
+/// The image and device handles of the GCD descriptor are not recorded, as they are meaningless after boot.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GcdMemorySpaceEntry {
+    /// The physical address of the first byte in the memory region.
+    pub base_address: u64,
+    /// The number of bytes in the memory region.
+    pub length: u64,
+    /// The bit mask of attributes that the memory region is capable of supporting.
+    pub capabilities: u64,
+    /// The bit mask of attributes that the memory region is currently using.
+    pub attributes: u64,
+    /// The `GcdMemoryType` of the memory region.
+    pub memory_type: u32,
+    /// Reserved, zero.
+    pub reserved: u32,
+}
+
+impl From<&MemorySpaceDescriptor> for GcdMemorySpaceEntry {
+    fn from(descriptor: &MemorySpaceDescriptor) -> Self {
+        Self {
+            base_address: descriptor.base_address,
+            length: descriptor.length,
+            capabilities: descriptor.capabilities,
+            attributes: descriptor.attributes,
+            memory_type: descriptor.memory_type as u32,
+            reserved: 0,
+        }
+    }
+}
+
+/// A configuration struct enabling the memory map snapshot taken at ExitBootServices, published as the
+/// [MEMORY_MAP_SNAPSHOT](patina::guids::MEMORY_MAP_SNAPSHOT) configuration table. The snapshot is not taken unless
+/// this configuration is provided.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use patina_dxe_core::{Core, MemoryMapSnapshotPolicy};
+/// # let physical_hob_list = core::ptr::null();
+///
+/// let core = Core::default()
+///    .init_memory(physical_hob_list)
+///    .with_config(MemoryMapSnapshotPolicy { capacity_margin: 128 })
+///    .start()
+///    .unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryMapSnapshotPolicy {
+    /// The number of descriptors each array of the snapshot has room for, in addition to the GCD descriptors present
+    /// at ReadyToBoot.
+    pub capacity_margin: usize,
+}
+
+impl Default for MemoryMapSnapshotPolicy {
+    fn default() -> Self {
+        Self { capacity_margin: 64 }
+    }
+}
+
+// The snapshot buffer, allocated at ReadyToBoot.
+static SNAPSHOT: AtomicPtr<MemoryMapSnapshotHeader> = AtomicPtr::new(core::ptr::null_mut());
+
+// The capacity margin of the snapshot arrays, from the [MemoryMapSnapshotPolicy].
+static CAPACITY_MARGIN: AtomicUsize = AtomicUsize::new(0);
+
+/// Registers the event allocating and publishing the snapshot buffer at ReadyToBoot.
+pub fn init_memory_map_snapshot_support(policy: MemoryMapSnapshotPolicy) {
+    CAPACITY_MARGIN.store(policy.capacity_margin, Ordering::Relaxed);
+    if let Err(status) = EVENT_DB.create_event(
+        efi::EVT_NOTIFY_SIGNAL,
+        efi::TPL_CALLBACK,
+        Some(install_memory_map_snapshot_event_wrapper),
+        None,
+        Some(efi::EVENT_GROUP_READY_TO_BOOT),
+    ) {
+        log::error!("Failed to register an event at Ready to Boot to allocate the memory map snapshot! {status:#X?}");
+    }
+}
+
+extern "efiapi" fn install_memory_map_snapshot_event_wrapper(event: efi::Event, _context: *mut c_void) {
+    install_memory_map_snapshot();
+
+    if let Err(status) = EVENT_DB.close_event(event) {
+        log::error!("Failed to close memory map snapshot ready to boot event with status {status:#X?}.");
+    }
+}
+
+/// Allocates the snapshot buffer and installs it as the memory map snapshot configuration table.
+fn install_memory_map_snapshot() {
+    if !SNAPSHOT.load(Ordering::Relaxed).is_null() {
+        return;
+    }
+
+    let capacity = GCD.memory_descriptor_count() + CAPACITY_MARGIN.load(Ordering::Relaxed);
+    let mut header = MemoryMapSnapshotHeader {
+        signature: MEMORY_MAP_SNAPSHOT_SIGNATURE,
+        revision: MEMORY_MAP_SNAPSHOT_REVISION,
+        ..Default::default()
+    };
+    let mut offset = size_of::<MemoryMapSnapshotHeader>();
+    for (array, entry_size) in [
+        (&mut header.memory_map, size_of::<efi::MemoryDescriptor>()),
+        (&mut header.gcd, size_of::<GcdMemorySpaceEntry>()),
+        (&mut header.mat, size_of::<efi::MemoryDescriptor>()),
+    ] {
+        *array =
+            SnapshotArray { offset: offset as u32, capacity: capacity as u32, count: 0, entry_size: entry_size as u32 };
+        offset += capacity * entry_size;
+    }
+    header.buffer_size = offset as u32;
+
+    let mut address: efi::PhysicalAddress = 0;
+    if let Err(err) = core_allocate_pages(
+        efi::ALLOCATE_ANY_PAGES,
+        efi::RESERVED_MEMORY_TYPE,
+        uefi_size_to_pages!(offset),
+        &mut address,
+        None,
+    ) {
+        log::error!("Failed to allocate the memory map snapshot buffer: {err:?}");
+        return;
+    }
+
+    let snapshot = address as *mut MemoryMapSnapshotHeader;
+    // Safety: the buffer was just allocated with room for the header.
+    unsafe { snapshot.write(header) };
+
+    let mut st_guard = systemtables::SYSTEM_TABLE.lock();
+    let st = st_guard.as_mut().expect("System table support not initialized");
+    if let Err(status) = core_install_configuration_table(guids::MEMORY_MAP_SNAPSHOT, snapshot as *mut c_void, st) {
+        log::error!("Failed to install the memory map snapshot configuration table: {status:#X?}");
+        return;
+    }
+    SNAPSHOT.store(snapshot, Ordering::Relaxed);
+    log::info!("Memory map snapshot buffer of {offset:#x} bytes installed at {address:#x}.");
+}
+
+/// Records the memory map, GCD memory space map and MAT in the snapshot buffer, if it was allocated.
+///
+/// Must be called at ExitBootServices, once the memory map is final.
+pub fn capture_memory_map_snapshot() {
+    let snapshot = SNAPSHOT.load(Ordering::Relaxed);
+    if snapshot.is_null() {
+        return;
+    }
+
+    let memory_map = match get_memory_map_descriptors(false) {
+        Ok(descriptors) => descriptors,
+        Err(err) => {
+            log::error!("Failed to get the memory map for the memory map snapshot: {err:?}");
+            return;
+        }
+    };
+    let mut gcd_descriptors = Vec::with_capacity(GCD.memory_descriptor_count());
+    if let Err(err) = GCD.get_memory_descriptors(&mut gcd_descriptors) {
+        log::error!("Failed to get the GCD descriptors for the memory map snapshot: {err:?}");
+        return;
+    }
+    let mat = memory_attributes_table::published_descriptors();
+
+    // Safety: the snapshot buffer was allocated with the layout described by its header, and is never freed.
+    unsafe {
+        let mut header = snapshot.read();
+        let base = snapshot as *mut u8;
+        let mut truncated = write_array(base, &mut header.memory_map, &memory_map);
+        let gcd_entries: Vec<GcdMemorySpaceEntry> = gcd_descriptors.iter().map(GcdMemorySpaceEntry::from).collect();
+        truncated |= write_array(base, &mut header.gcd, &gcd_entries);
+        truncated |= write_array(base, &mut header.mat, &mat);
+
+        header.flags = snapshot_flags::CAPTURED;
+        if truncated {
+            log::warn!("The memory map snapshot is truncated, increase the capacity margin of its policy.");
+            header.flags |= snapshot_flags::TRUNCATED;
+        }
+        snapshot.write(header);
+    }
+}
+
+/// Writes as many `entries` as fit in `array` of the buffer at `base`. Returns whether some entries did not fit.
+///
+/// ## Safety
+///
+/// `base` must point to a buffer with room for `array`.
+unsafe fn write_array<T: Copy>(base: *mut u8, array: &mut SnapshotArray, entries: &[T]) -> bool {
+    let count = entries.len().min(array.capacity as usize);
+    // Safety: the caller guarantees that the buffer has room for `array.capacity` entries at `array.offset`.
+    unsafe {
+        let destination = slice::from_raw_parts_mut(base.add(array.offset as usize) as *mut T, count);
+        destination.copy_from_slice(&entries[..count]);
+    }
+    array.count = count as u32;
+    count < entries.len()
+}
+
+#[cfg(feature = "std")]
+pub use decoder::{MemoryMapSnapshot, SnapshotDecodeError, SnapshotViolation};
+
+#[cfg(feature = "std")]
+mod decoder {
+    use super::*;
+    use core::fmt;
+    use patina::base::UEFI_PAGE_SIZE;
+
+    /// The error returned when a buffer is not a valid memory map snapshot.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum SnapshotDecodeError {
+        /// The buffer is smaller than the header, or than the size recorded in the header.
+        BufferTooSmall,
+        /// The buffer does not start with [MEMORY_MAP_SNAPSHOT_SIGNATURE].
+        InvalidSignature(u32),
+        /// The revision of the snapshot is not supported.
+        UnsupportedRevision(u32),
+        /// An array of the snapshot does not fit in the buffer, or its entries are too small.
+        InvalidArray,
+    }
+
+    impl fmt::Display for SnapshotDecodeError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Self::BufferTooSmall => write!(f, "buffer too small for the memory map snapshot"),
+                Self::InvalidSignature(signature) => write!(f, "invalid memory map snapshot signature {signature:#x}"),
+                Self::UnsupportedRevision(revision) => {
+                    write!(f, "unsupported memory map snapshot revision {revision}")
+                }
+                Self::InvalidArray => write!(f, "invalid memory map snapshot array"),
+            }
+        }
+    }
+
+    impl std::error::Error for SnapshotDecodeError {}
+
+    /// An invariant of the OS handoff violated by a memory map snapshot.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum SnapshotViolation {
+        /// The snapshot was not captured, ExitBootServices did not complete.
+        NotCaptured,
+        /// Some descriptors were dropped from the snapshot, so the other checks are not conclusive.
+        Truncated,
+        /// The memory map descriptors starting at these addresses overlap.
+        OverlappingMemoryMapEntries(u64, u64),
+        /// The MAT descriptors starting at these addresses overlap.
+        OverlappingMatEntries(u64, u64),
+        /// The MAT descriptor starting at this address is not within a runtime memory map descriptor of its type.
+        MatEntryNotRuntime(u64),
+        /// The runtime memory map descriptor starting at this address is not entirely described by the MAT.
+        RuntimeRegionNotInMat(u64),
+    }
+
+    /// A memory map snapshot decoded from a copy of its buffer.
+    #[derive(Debug, Clone)]
+    pub struct MemoryMapSnapshot {
+        /// The [snapshot_flags] of the snapshot.
+        pub flags: u32,
+        /// The memory map descriptors.
+        pub memory_map: Vec<efi::MemoryDescriptor>,
+        /// The GCD memory space descriptors.
+        pub gcd: Vec<GcdMemorySpaceEntry>,
+        /// The MAT descriptors.
+        pub mat: Vec<efi::MemoryDescriptor>,
+    }
+
+    fn end(descriptor: &efi::MemoryDescriptor) -> u64 {
+        descriptor.physical_start.saturating_add(descriptor.number_of_pages.saturating_mul(UEFI_PAGE_SIZE as u64))
+    }
+
+    fn is_runtime(descriptor: &efi::MemoryDescriptor) -> bool {
+        matches!(descriptor.r#type, efi::RUNTIME_SERVICES_CODE | efi::RUNTIME_SERVICES_DATA)
+    }
+
+    fn read_array<T: Copy>(buffer: &[u8], array: &SnapshotArray) -> Result<Vec<T>, SnapshotDecodeError> {
+        let entry_size = array.entry_size as usize;
+        let size = entry_size.checked_mul(array.count as usize).ok_or(SnapshotDecodeError::InvalidArray)?;
+        let entries = (array.offset as usize)
+            .checked_add(size)
+            .and_then(|end| buffer.get(array.offset as usize..end))
+            .ok_or(SnapshotDecodeError::InvalidArray)?;
+        if entry_size < size_of::<T>() || array.count > array.capacity {
+            return Err(SnapshotDecodeError::InvalidArray);
+        }
+        // Safety: each entry is within `entries`, which is read without assuming its alignment.
+        Ok(entries
+            .chunks_exact(entry_size)
+            .map(|entry| unsafe { (entry.as_ptr() as *const T).read_unaligned() })
+            .collect())
+    }
+
+    /// Returns the start addresses of the overlapping descriptors, by pairs of descriptors adjacent in address order.
+    fn find_overlaps(descriptors: &[efi::MemoryDescriptor]) -> Vec<(u64, u64)> {
+        let mut sorted = descriptors.to_vec();
+        sorted.sort_by_key(|descriptor| descriptor.physical_start);
+        sorted
+            .windows(2)
+            .filter(|pair| end(&pair[0]) > pair[1].physical_start)
+            .map(|pair| (pair[0].physical_start, pair[1].physical_start))
+            .collect()
+    }
+
+    impl MemoryMapSnapshot {
+        /// Decodes a copy of a memory map snapshot buffer.
+        pub fn decode(buffer: &[u8]) -> Result<Self, SnapshotDecodeError> {
+            if buffer.len() < size_of::<MemoryMapSnapshotHeader>() {
+                return Err(SnapshotDecodeError::BufferTooSmall);
+            }
+            // Safety: the buffer has room for the header, which is read without assuming its alignment.
+            let header = unsafe { (buffer.as_ptr() as *const MemoryMapSnapshotHeader).read_unaligned() };
+            if header.signature != MEMORY_MAP_SNAPSHOT_SIGNATURE {
+                return Err(SnapshotDecodeError::InvalidSignature(header.signature));
+            }
+            if header.revision != MEMORY_MAP_SNAPSHOT_REVISION {
+                return Err(SnapshotDecodeError::UnsupportedRevision(header.revision));
+            }
+            let buffer = buffer.get(..header.buffer_size as usize).ok_or(SnapshotDecodeError::BufferTooSmall)?;
+
+            Ok(Self {
+                flags: header.flags,
+                memory_map: read_array(buffer, &header.memory_map)?,
+                gcd: read_array(buffer, &header.gcd)?,
+                mat: read_array(buffer, &header.mat)?,
+            })
+        }
+
+        /// Returns whether the snapshot was captured at ExitBootServices.
+        pub fn is_captured(&self) -> bool {
+            self.flags & snapshot_flags::CAPTURED != 0
+        }
+
+        /// Returns whether descriptors were dropped from the snapshot.
+        pub fn is_truncated(&self) -> bool {
+            self.flags & snapshot_flags::TRUNCATED != 0
+        }
+
+        /// Checks the invariants of the OS handoff, returning the violations found.
+        ///
+        /// The memory map and the MAT must not have overlapping descriptors, and the MAT must describe exactly the
+        /// runtime services code and data descriptors of the memory map.
+        pub fn validate(&self) -> Vec<SnapshotViolation> {
+            let mut violations = Vec::new();
+            if !self.is_captured() {
+                violations.push(SnapshotViolation::NotCaptured);
+            }
+            if self.is_truncated() {
+                violations.push(SnapshotViolation::Truncated);
+            }
+
+            violations.extend(
+                find_overlaps(&self.memory_map)
+                    .into_iter()
+                    .map(|(first, second)| SnapshotViolation::OverlappingMemoryMapEntries(first, second)),
+            );
+            violations.extend(
+                find_overlaps(&self.mat)
+                    .into_iter()
+                    .map(|(first, second)| SnapshotViolation::OverlappingMatEntries(first, second)),
+            );
+
+            for entry in &self.mat {
+                let within_runtime_region = self.memory_map.iter().any(|descriptor| {
+                    is_runtime(descriptor)
+                        && descriptor.r#type == entry.r#type
+                        && descriptor.physical_start <= entry.physical_start
+                        && end(entry) <= end(descriptor)
+                });
+                if !within_runtime_region {
+                    violations.push(SnapshotViolation::MatEntryNotRuntime(entry.physical_start));
+                }
+            }
+
+            for region in self.memory_map.iter().filter(|descriptor| is_runtime(descriptor)) {
+                let mut entries: Vec<_> = self
+                    .mat
+                    .iter()
+                    .filter(|entry| {
+                        entry.r#type == region.r#type
+                            && entry.physical_start < end(region)
+                            && end(entry) > region.physical_start
+                    })
+                    .collect();
+                entries.sort_by_key(|entry| entry.physical_start);
+
+                let mut covered = region.physical_start;
+                for entry in entries {
+                    if entry.physical_start > covered {
+                        break;
+                    }
+                    covered = covered.max(end(entry));
+                }
+                if covered < end(region) {
+                    violations.push(SnapshotViolation::RuntimeRegionNotInMat(region.physical_start));
+                }
+            }
+
+            violations
+        }
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+    use super::*;
+
+    use crate::{systemtables::init_system_table, test_support};
+    use patina::base::UEFI_PAGE_SIZE;
+
+    fn with_locked_state<F: Fn() + std::panic::RefUnwindSafe>(f: F) {
+        test_support::with_global_lock(|| {
+            SNAPSHOT.store(core::ptr::null_mut(), Ordering::Relaxed);
+            memory_attributes_table::reset_memory_attributes_table();
+            unsafe {
+                test_support::init_test_gcd(None);
+                test_support::reset_allocators();
+                init_system_table();
+            }
+            f();
+        })
+        .unwrap();
+    }
+
+    fn installed_snapshot() -> *mut MemoryMapSnapshotHeader {
+        let st = systemtables::SYSTEM_TABLE.lock();
+        let st = st.as_ref().expect("System table is initialized").as_ref();
+        let tables = unsafe { slice::from_raw_parts(st.configuration_table, st.number_of_table_entries) };
+        let table = tables.iter().find(|table| table.vendor_guid == guids::MEMORY_MAP_SNAPSHOT);
+        table.expect("snapshot is installed").vendor_table as *mut MemoryMapSnapshotHeader
+    }
+
+    #[test]
+    fn test_snapshot_init() {
+        with_locked_state(|| {
+            init_memory_map_snapshot_support(MemoryMapSnapshotPolicy::default());
+        });
+    }
+
+    #[test]
+    fn snapshot_should_not_be_captured_until_installed() {
+        with_locked_state(|| {
+            capture_memory_map_snapshot();
+            assert!(SNAPSHOT.load(Ordering::Relaxed).is_null());
+        });
+    }
+
+    #[test]
+    fn snapshot_should_record_the_memory_map_at_capture() {
+        with_locked_state(|| {
+            CAPACITY_MARGIN.store(MemoryMapSnapshotPolicy::default().capacity_margin, Ordering::Relaxed);
+            install_memory_map_snapshot();
+            let snapshot = installed_snapshot();
+            assert_eq!(snapshot, SNAPSHOT.load(Ordering::Relaxed));
+
+            let header = unsafe { snapshot.read() };
+            assert_eq!(header.signature, MEMORY_MAP_SNAPSHOT_SIGNATURE);
+            assert_eq!(header.flags, 0);
+            assert_eq!(header.memory_map.count, 0);
+            assert!(header.memory_map.capacity as usize > GCD.memory_descriptor_count());
+
+            memory_attributes_table::core_install_memory_attributes_table();
+            capture_memory_map_snapshot();
+            let header = unsafe { snapshot.read() };
+            assert_eq!(header.flags, snapshot_flags::CAPTURED);
+            assert_eq!(header.memory_map.count as usize, get_memory_map_descriptors(false).unwrap().len());
+            assert_eq!(header.gcd.count as usize, GCD.memory_descriptor_count());
+
+            // the snapshot buffer is reported in the memory map as reserved memory.
+            let memory_map = unsafe {
+                slice::from_raw_parts(
+                    (snapshot as *const u8).add(header.memory_map.offset as usize) as *const efi::MemoryDescriptor,
+                    header.memory_map.count as usize,
+                )
+            };
+            assert!(memory_map.iter().any(|descriptor| descriptor.r#type == efi::RESERVED_MEMORY_TYPE
+                && descriptor.physical_start <= snapshot as u64
+                && snapshot as u64 + header.buffer_size as u64
+                    <= descriptor.physical_start + descriptor.number_of_pages * UEFI_PAGE_SIZE as u64));
+
+            #[cfg(feature = "std")]
+            {
+                let buffer = unsafe { slice::from_raw_parts(snapshot as *const u8, header.buffer_size as usize) };
+                let decoded = MemoryMapSnapshot::decode(buffer).unwrap();
+                assert_eq!(decoded.memory_map.len(), memory_map.len());
+                assert_eq!(decoded.validate(), []);
+            }
+        });
+    }
+
+    #[test]
+    fn snapshot_should_be_truncated_when_descriptors_do_not_fit() {
+        with_locked_state(|| {
+            let mut entries = [1u64, 2, 3];
+            let mut array = SnapshotArray { offset: 0, capacity: 2, count: 0, entry_size: size_of::<u64>() as u32 };
+            let mut buffer = [0u64; 2];
+            assert!(unsafe { write_array(buffer.as_mut_ptr() as *mut u8, &mut array, &entries) });
+            assert_eq!(array.count, 2);
+            assert_eq!(buffer, [1, 2]);
+
+            entries[0] = 4;
+            assert!(!unsafe { write_array(buffer.as_mut_ptr() as *mut u8, &mut array, &entries[..1]) });
+            assert_eq!(array.count, 1);
+            assert_eq!(buffer, [4, 2]);
+        });
+    }
+
+    #[cfg(feature = "std")]
+    mod decoder {
+        use super::*;
+
+        const CODE: u32 = efi::RUNTIME_SERVICES_CODE;
+        const DATA: u32 = efi::RUNTIME_SERVICES_DATA;
+
+        fn descriptor(r#type: u32, physical_start: u64, number_of_pages: u64) -> efi::MemoryDescriptor {
+            efi::MemoryDescriptor { r#type, physical_start, virtual_start: 0, number_of_pages, attribute: 0 }
+        }
+
+        /// Builds a captured snapshot buffer of `memory_map` and `mat`.
+        fn buffer(memory_map: &[efi::MemoryDescriptor], mat: &[efi::MemoryDescriptor], flags: u32) -> Vec<u8> {
+            let descriptor_size = size_of::<efi::MemoryDescriptor>();
+            let header_size = size_of::<MemoryMapSnapshotHeader>();
+            let array = |offset: usize, count: usize| SnapshotArray {
+                offset: offset as u32,
+                capacity: count as u32,
+                count: count as u32,
+                entry_size: descriptor_size as u32,
+            };
+            let mat_offset = header_size + size_of_val(memory_map);
+            let buffer_size = mat_offset + size_of_val(mat);
+            let header = MemoryMapSnapshotHeader {
+                signature: MEMORY_MAP_SNAPSHOT_SIGNATURE,
+                revision: MEMORY_MAP_SNAPSHOT_REVISION,
+                buffer_size: buffer_size as u32,
+                flags,
+                memory_map: array(header_size, memory_map.len()),
+                gcd: SnapshotArray {
+                    offset: buffer_size as u32,
+                    entry_size: size_of::<GcdMemorySpaceEntry>() as u32,
+                    ..Default::default()
+                },
+                mat: array(mat_offset, mat.len()),
+            };
+
+            let mut buffer = vec![0u8; buffer_size];
+            unsafe {
+                (buffer.as_mut_ptr() as *mut MemoryMapSnapshotHeader).write_unaligned(header);
+                let entries = buffer.as_mut_ptr().add(header_size) as *mut efi::MemoryDescriptor;
+                for (index, descriptor) in memory_map.iter().chain(mat).enumerate() {
+                    entries.add(index).write_unaligned(*descriptor);
+                }
+            }
+            buffer
+        }
+
+        #[test]
+        fn invalid_buffers_should_not_decode() {
+            let valid = buffer(&[descriptor(CODE, 0x1000, 1)], &[], snapshot_flags::CAPTURED);
+
+            assert_eq!(MemoryMapSnapshot::decode(&valid[..8]).unwrap_err(), SnapshotDecodeError::BufferTooSmall);
+            assert_eq!(
+                MemoryMapSnapshot::decode(&valid[..valid.len() - 1]).unwrap_err(),
+                SnapshotDecodeError::BufferTooSmall
+            );
+
+            let mut invalid = valid.clone();
+            invalid[0] = 0;
+            assert!(matches!(MemoryMapSnapshot::decode(&invalid), Err(SnapshotDecodeError::InvalidSignature(_))));
+
+            let mut invalid = valid.clone();
+            invalid[4] = 2;
+            assert_eq!(MemoryMapSnapshot::decode(&invalid).unwrap_err(), SnapshotDecodeError::UnsupportedRevision(2));
+
+            // the count of the memory map array exceeds its capacity.
+            let mut invalid = valid.clone();
+            invalid[24] = 2;
+            assert_eq!(MemoryMapSnapshot::decode(&invalid).unwrap_err(), SnapshotDecodeError::InvalidArray);
+
+            let decoded = MemoryMapSnapshot::decode(&valid).unwrap();
+            assert!(decoded.is_captured());
+            assert!(!decoded.is_truncated());
+            assert_eq!(decoded.memory_map.len(), 1);
+            assert_eq!(decoded.memory_map[0].physical_start, 0x1000);
+        }
+
+        #[test]
+        fn consistent_snapshot_should_have_no_violations() {
+            let memory_map = [
+                descriptor(efi::BOOT_SERVICES_DATA, 0x0, 1),
+                descriptor(CODE, 0x1000, 2),
+                descriptor(DATA, 0x3000, 1),
+                descriptor(efi::CONVENTIONAL_MEMORY, 0x4000, 4),
+            ];
+            // a runtime region may be described by several MAT descriptors.
+            let mat = [descriptor(CODE, 0x1000, 1), descriptor(CODE, 0x2000, 1), descriptor(DATA, 0x3000, 1)];
+            let snapshot = MemoryMapSnapshot::decode(&buffer(&memory_map, &mat, snapshot_flags::CAPTURED)).unwrap();
+            assert_eq!(snapshot.validate(), []);
+        }
+
+        #[test]
+        fn inconsistent_snapshot_should_report_violations() {
+            let memory_map = [
+                descriptor(CODE, 0x1000, 2),
+                descriptor(DATA, 0x2000, 2),
+                descriptor(efi::BOOT_SERVICES_DATA, 0x4000, 1),
+                descriptor(DATA, 0x5000, 2),
+            ];
+            let mat = [
+                descriptor(CODE, 0x1000, 1),
+                descriptor(DATA, 0x4000, 1),
+                descriptor(DATA, 0x5000, 2),
+                descriptor(DATA, 0x6000, 1),
+            ];
+            let snapshot = MemoryMapSnapshot::decode(&buffer(&memory_map, &mat, snapshot_flags::TRUNCATED)).unwrap();
+            assert_eq!(
+                snapshot.validate(),
+                [
+                    SnapshotViolation::NotCaptured,
+                    SnapshotViolation::Truncated,
+                    SnapshotViolation::OverlappingMemoryMapEntries(0x1000, 0x2000),
+                    SnapshotViolation::OverlappingMatEntries(0x5000, 0x6000),
+                    SnapshotViolation::MatEntryNotRuntime(0x4000),
+                    SnapshotViolation::RuntimeRegionNotInMat(0x1000),
+                    SnapshotViolation::RuntimeRegionNotInMat(0x2000),
+                ]
+            );
+        }
+    }
+}
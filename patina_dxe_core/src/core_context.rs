@@ -0,0 +1,225 @@
+//! DXE Core Context
+//!
+//! Groups the state of the core subsystems that used to be independent global statics: the GCD, the protocol
+//! database, the allocator map, the event database, the image data and the system table. The core runs with a single
+//! global context, but the statics through which the modules reach that state ([GCD](crate::GCD),
+//! [PROTOCOL_DB](crate::protocols::PROTOCOL_DB), [EVENT_DB](crate::events::EVENT_DB),
+//! [SYSTEM_TABLE](crate::systemtables::SYSTEM_TABLE), the allocator map and the image data) are thin [ContextStatic]
+//! shims resolving to the state of the current context. This keeps the FFI entry points, which cannot be handed a
+//! context, working unchanged, and lets the tests of the crate construct independent contexts and run code against one
+//! of them with [with_core_context].
+//!
+//! The context is selected for the whole process, so a single context is current at a time and the host environment
+//! used by the integration tests runs on the global context. The following state is not part of a context:
+//!
+//! - The static UEFI allocators (e.g. the global allocator of the core) are bound to the GCD of the global context
+//!   when they are declared. The allocator map of an independent context creates its own allocators for their memory
+//!   types, bound to the GCD of the context, so the boot services allocations of a context come from its GCD. The
+//!   memory the core allocates directly from the static allocators, e.g. Rust heap allocations, comes from the global
+//!   context.
+//! - The current TPL and the system time, which model the processor the core runs on.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use core::{
+    ffi::c_void,
+    ops::Deref,
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use r_efi::efi;
+use spin::RwLock;
+
+use crate::{
+    allocator::AllocatorMap,
+    event_db::SpinLockedEventDb,
+    events,
+    gcd::SpinLockedGcd,
+    image::{DxeCoreGlobalImageData, ImageDatabase},
+    protocol_db::SpinLockedProtocolDb,
+    systemtables::EfiSystemTable,
+    tpl_lock,
+};
+
+/// The state of an instance of the core.
+pub(crate) struct CoreContext {
+    /// The Global Coherency Domain.
+    pub(crate) gcd: SpinLockedGcd,
+    /// The protocol database.
+    pub(crate) protocol_db: SpinLockedProtocolDb,
+    /// The allocators created on demand, for the memory types without a static allocator in the global context and
+    /// for all memory types in an independent context.
+    pub(crate) allocators: tpl_lock::TplMutex<AllocatorMap>,
+    /// The event database.
+    pub(crate) event_db: SpinLockedEventDb,
+    /// The private data of the loaded images.
+    pub(crate) image_data: tpl_lock::TplMutex<DxeCoreGlobalImageData>,
+    /// The handle of the image currently running, mirrored from the image data to be read without the image lock.
+    pub(crate) running_image: AtomicPtr<c_void>,
+    /// The loaded image database, used by diagnostics.
+    pub(crate) image_database: RwLock<ImageDatabase>,
+    /// The EFI System Table, once created.
+    pub(crate) system_table: tpl_lock::TplMutex<Option<EfiSystemTable>>,
+}
+
+impl CoreContext {
+    /// Creates the state of a core that has not been initialized yet.
+    pub(crate) const fn new() -> Self {
+        Self {
+            gcd: SpinLockedGcd::new(Some(events::gcd_map_change)),
+            protocol_db: SpinLockedProtocolDb::new(),
+            allocators: AllocatorMap::new(),
+            event_db: SpinLockedEventDb::new(),
+            image_data: tpl_lock::TplMutex::new(efi::TPL_NOTIFY, DxeCoreGlobalImageData::new(), "ImageLock"),
+            running_image: AtomicPtr::new(ptr::null_mut()),
+            image_database: RwLock::new(ImageDatabase::new()),
+            system_table: tpl_lock::TplMutex::new(efi::TPL_NOTIFY, None, "StLock"),
+        }
+    }
+}
+
+/// The context of the core, used unless [with_core_context] selects another one.
+pub(crate) static GLOBAL_CONTEXT: CoreContext = CoreContext::new();
+
+// The context selected by [with_core_context], or null for the global context.
+static CURRENT_CONTEXT: AtomicPtr<CoreContext> = AtomicPtr::new(ptr::null_mut());
+
+/// Returns the current context of the core.
+pub(crate) fn current() -> &'static CoreContext {
+    // Safety: the pointer is either null or set from a 'static reference by with_core_context.
+    unsafe { CURRENT_CONTEXT.load(Ordering::Acquire).as_ref() }.unwrap_or(&GLOBAL_CONTEXT)
+}
+
+/// Returns true if the current context of the core is the global context.
+pub(crate) fn is_global() -> bool {
+    ptr::eq(current(), &GLOBAL_CONTEXT)
+}
+
+/// A static resolving to a part of the current context of the core.
+///
+/// Dereferences to the part of [current] selected by its accessor, so that the modules and the FFI entry points use
+/// it as they would use the static it replaces.
+pub(crate) struct ContextStatic<T: 'static> {
+    accessor: fn(&'static CoreContext) -> &'static T,
+}
+
+impl<T: 'static> ContextStatic<T> {
+    /// Creates a static resolving to the part of the current context returned by `accessor`.
+    pub(crate) const fn new(accessor: fn(&'static CoreContext) -> &'static T) -> Self {
+        Self { accessor }
+    }
+}
+
+impl<T: 'static> Deref for ContextStatic<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        (self.accessor)(current())
+    }
+}
+
+/// Runs `f` with `context` as the current context of the core, restoring the previous one afterwards.
+///
+/// The context is global to the process, so tests using it must hold the global test lock.
+#[cfg(test)]
+pub(crate) fn with_core_context<R>(context: &'static CoreContext, f: impl FnOnce() -> R) -> R {
+    struct RestoreContext(*mut CoreContext);
+
+    impl Drop for RestoreContext {
+        fn drop(&mut self) {
+            CURRENT_CONTEXT.store(self.0, Ordering::Release);
+        }
+    }
+
+    let _restore =
+        RestoreContext(CURRENT_CONTEXT.swap(context as *const CoreContext as *mut CoreContext, Ordering::AcqRel));
+    f()
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::{
+        allocator::{EFI_BOOT_SERVICES_DATA_ALLOCATOR, core_allocate_pages, core_get_allocator},
+        events::EVENT_DB,
+        protocols::PROTOCOL_DB,
+        systemtables::SYSTEM_TABLE,
+        test_support,
+    };
+    use std::boxed::Box;
+
+    const TEST_PROTOCOL: efi::Guid =
+        efi::Guid::from_fields(0x8c1b6a34, 0x2f1e, 0x4f7b, 0x9a, 0x5d, &[0x1e, 0x3c, 0x6b, 0x2d, 0x7a, 0x40]);
+
+    #[test]
+    fn contexts_should_be_independent() {
+        test_support::with_global_lock(|| {
+            let first: &'static CoreContext = Box::leak(Box::new(CoreContext::new()));
+            let second: &'static CoreContext = Box::leak(Box::new(CoreContext::new()));
+
+            let event = with_core_context(first, || {
+                assert!(ptr::eq(current(), first));
+                assert!(!is_global());
+                PROTOCOL_DB.init_protocol_db();
+                PROTOCOL_DB.install_protocol_interface(None, TEST_PROTOCOL, ptr::null_mut()).unwrap();
+                assert_eq!(PROTOCOL_DB.locate_handles(Some(TEST_PROTOCOL)).unwrap().len(), 1);
+                EVENT_DB.create_event(0, efi::TPL_NOTIFY, None, None, None).unwrap()
+            });
+
+            with_core_context(second, || {
+                PROTOCOL_DB.init_protocol_db();
+                assert!(PROTOCOL_DB.locate_handles(Some(TEST_PROTOCOL)).unwrap_or_default().is_empty());
+                assert!(!EVENT_DB.is_valid(event));
+                assert!(SYSTEM_TABLE.lock().is_none());
+            });
+
+            assert!(ptr::eq(current(), &GLOBAL_CONTEXT));
+            assert!(is_global());
+            assert_eq!(first.protocol_db.locate_handles(Some(TEST_PROTOCOL)).unwrap().len(), 1);
+            assert!(first.event_db.is_valid(event));
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn allocations_should_come_from_the_gcd_of_the_context() {
+        test_support::with_global_lock(|| {
+            let context: &'static CoreContext = Box::leak(Box::new(CoreContext::new()));
+            with_core_context(context, || {
+                unsafe {
+                    test_support::init_test_gcd(None);
+                    test_support::init_test_protocol_db();
+                }
+                // The static allocator of the memory type is bound to the GCD of the global context.
+                let allocator = core_get_allocator(efi::BOOT_SERVICES_DATA).unwrap();
+                assert!(!ptr::eq(allocator, &EFI_BOOT_SERVICES_DATA_ALLOCATOR));
+
+                let mut address = 0;
+                core_allocate_pages(efi::ALLOCATE_ANY_PAGES, efi::BOOT_SERVICES_DATA, 1, &mut address, None).unwrap();
+                assert!(!context.gcd.get_memory_descriptor_for_address(address).unwrap().image_handle.is_null());
+            });
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn context_should_be_restored_on_panic() {
+        test_support::with_global_lock(|| {
+            let context: &'static CoreContext = Box::leak(Box::new(CoreContext::new()));
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                with_core_context(context, || panic!("test panic"))
+            }));
+            assert!(result.is_err());
+            assert!(ptr::eq(current(), &GLOBAL_CONTEXT));
+        })
+        .unwrap();
+    }
+}
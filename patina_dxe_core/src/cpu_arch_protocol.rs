@@ -15,7 +15,10 @@ use patina::{
     boot_services::{BootServices, StandardBootServices},
     component::{IntoComponent, service::Service},
     error::{EfiError, Result},
-    uefi_protocol::ProtocolInterface,
+    uefi_protocol::{
+        ProtocolInterface,
+        interrupt_statistics::{self, InterruptSummary, VectorStatistics},
+    },
 };
 use patina_internal_cpu::{
     cpu::Cpu,
@@ -164,6 +167,38 @@ extern "efiapi" fn set_memory_attributes(
     }
 }
 
+// Interrupt Statistics protocol function implementations.
+
+extern "efiapi" fn get_vector_statistics(
+    _this: *const interrupt_statistics::Protocol,
+    vector: usize,
+    statistics: *mut VectorStatistics,
+) -> efi::Status {
+    if statistics.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    match interrupts::statistics::vector_statistics(vector) {
+        Some(vector_statistics) => {
+            // Safety: caller must ensure that statistics is a valid pointer. It is null-checked above.
+            unsafe { statistics.write_unaligned(vector_statistics) };
+            efi::Status::SUCCESS
+        }
+        None => efi::Status::NOT_FOUND,
+    }
+}
+
+extern "efiapi" fn get_interrupt_summary(
+    _this: *const interrupt_statistics::Protocol,
+    summary: *mut InterruptSummary,
+) -> efi::Status {
+    if summary.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    // Safety: caller must ensure that summary is a valid pointer. It is null-checked above.
+    unsafe { summary.write_unaligned(interrupts::statistics::interrupt_summary()) };
+    efi::Status::SUCCESS
+}
+
 impl EfiCpuArchProtocolImpl {
     fn new(cpu: Service<dyn Cpu>, interrupt_manager: Service<dyn InterruptManager>) -> Self {
         Self {
@@ -207,6 +242,15 @@ impl CpuArchProtocolInstaller {
             .inspect_err(|_| log::error!("Failed to install EFI_CPU_ARCH_PROTOCOL"))?;
         log::info!("installed EFI_CPU_ARCH_PROTOCOL_GUID");
 
+        let statistics = Box::leak(Box::new(interrupt_statistics::Protocol {
+            get_vector_statistics,
+            get_summary: get_interrupt_summary,
+        }));
+        if let Err(err) = bs.install_protocol_interface(None, statistics) {
+            // The statistics are diagnostics only, the CPU is usable without them.
+            log::error!("Failed to install the interrupt statistics protocol: {err:?}");
+        }
+
         Ok(())
     }
 }
@@ -345,4 +389,17 @@ mod tests {
         let status = get_timer_value(&protocol.protocol, 0, &mut timer_value as *mut _, &mut timer_period as *mut _);
         assert_eq!(status, efi::Status::SUCCESS);
     }
+
+    #[test]
+    fn test_interrupt_statistics() {
+        let mut statistics = VectorStatistics::default();
+        assert_eq!(get_vector_statistics(core::ptr::null(), 0, &mut statistics), efi::Status::SUCCESS);
+        assert_eq!(get_vector_statistics(core::ptr::null(), usize::MAX, &mut statistics), efi::Status::NOT_FOUND);
+        assert_eq!(get_vector_statistics(core::ptr::null(), 0, core::ptr::null_mut()), efi::Status::INVALID_PARAMETER);
+
+        let mut summary = InterruptSummary::default();
+        assert_eq!(get_interrupt_summary(core::ptr::null(), &mut summary), efi::Status::SUCCESS);
+        assert_ne!(summary.vector_count, 0);
+        assert_eq!(get_interrupt_summary(core::ptr::null(), core::ptr::null_mut()), efi::Status::INVALID_PARAMETER);
+    }
 }
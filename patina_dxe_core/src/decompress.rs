@@ -61,38 +61,14 @@ impl CoreExtractor {
     fn uefi_decompress_extract(
         section: &patina_ffs::section::Section,
     ) -> Result<vec::Vec<u8>, FirmwareFileSystemError> {
-        let (src, algo) = match section.header() {
-            SectionHeader::GuidDefined(guid_header, _, _)
-                if guid_header.section_definition_guid == fw_fs::guid::TIANO_DECOMPRESS_SECTION =>
-            {
-                (section.try_content_as_slice()?, DecompressionAlgorithm::TianoDecompress)
+        let (src, algo, decompressed_size) = match uefi_compression(section)? {
+            UefiCompression::NotCompressed(data) => return Ok(data.to_vec()),
+            UefiCompression::Compressed { source, algorithm, decompressed_size } => {
+                (source, algorithm, decompressed_size)
             }
-            SectionHeader::Compression(compression_header, _) => {
-                match compression_header.compression_type {
-                    ffs::section::header::NOT_COMPRESSED => return Ok(section.try_content_as_slice()?.to_vec()), //not compressed, so just return section data
-                    ffs::section::header::STANDARD_COMPRESSION => {
-                        (section.try_content_as_slice()?, DecompressionAlgorithm::UefiDecompress)
-                    }
-                    _ => Err(FirmwareFileSystemError::Unsupported)?,
-                }
-            }
-            _ => return Err(FirmwareFileSystemError::Unsupported),
         };
 
-        //sanity check the src data
-        if src.len() < 8 {
-            Err(FirmwareFileSystemError::DataCorrupt)?;
-        }
-
-        let compressed_size =
-            u32::from_le_bytes(src[0..4].try_into().map_err(|_| FirmwareFileSystemError::DataCorrupt)?) as usize;
-        if compressed_size > src.len() {
-            Err(FirmwareFileSystemError::DataCorrupt)?;
-        }
-
         // allocate a buffer to hold the decompressed data
-        let decompressed_size =
-            u32::from_le_bytes(src[4..8].try_into().map_err(|_| FirmwareFileSystemError::DataCorrupt)?) as usize;
         let mut decompressed_buffer = vec![0u8; decompressed_size];
 
         // execute decompress
@@ -102,13 +78,73 @@ impl CoreExtractor {
     }
 }
 
+/// The UEFI compression of a section.
+pub(crate) enum UefiCompression<'a> {
+    /// A compression section using no compression.
+    NotCompressed(&'a [u8]),
+    /// A section compressed with one of the UEFI decompression algorithms.
+    Compressed { source: &'a [u8], algorithm: DecompressionAlgorithm, decompressed_size: usize },
+}
+
+/// Returns how the section is compressed with the UEFI decompression algorithms, after sanity checking the sizes
+/// in the compressed data.
+///
+/// Returns [FirmwareFileSystemError::Unsupported] if the section does not use UEFI compression.
+pub(crate) fn uefi_compression(
+    section: &patina_ffs::section::Section,
+) -> Result<UefiCompression<'_>, FirmwareFileSystemError> {
+    let (src, algo) = match section.header() {
+        SectionHeader::GuidDefined(guid_header, _, _)
+            if guid_header.section_definition_guid == fw_fs::guid::TIANO_DECOMPRESS_SECTION =>
+        {
+            (section.try_content_as_slice()?, DecompressionAlgorithm::TianoDecompress)
+        }
+        SectionHeader::Compression(compression_header, _) => {
+            match compression_header.compression_type {
+                ffs::section::header::NOT_COMPRESSED => {
+                    return Ok(UefiCompression::NotCompressed(section.try_content_as_slice()?));
+                } //not compressed, so just return section data
+                ffs::section::header::STANDARD_COMPRESSION => {
+                    (section.try_content_as_slice()?, DecompressionAlgorithm::UefiDecompress)
+                }
+                _ => Err(FirmwareFileSystemError::Unsupported)?,
+            }
+        }
+        _ => return Err(FirmwareFileSystemError::Unsupported),
+    };
+
+    //sanity check the src data
+    if src.len() < 8 {
+        Err(FirmwareFileSystemError::DataCorrupt)?;
+    }
+
+    let compressed_size =
+        u32::from_le_bytes(src[0..4].try_into().map_err(|_| FirmwareFileSystemError::DataCorrupt)?) as usize;
+    if compressed_size > src.len() {
+        Err(FirmwareFileSystemError::DataCorrupt)?;
+    }
+
+    let decompressed_size =
+        u32::from_le_bytes(src[4..8].try_into().map_err(|_| FirmwareFileSystemError::DataCorrupt)?) as usize;
+    Ok(UefiCompression::Compressed { source: src, algorithm: algo, decompressed_size })
+}
+
 impl SectionExtractor for CoreExtractor {
     fn extract(&self, section: &patina_ffs::section::Section) -> Result<vec::Vec<u8>, FirmwareFileSystemError> {
+        self.extract_with_auth_status(section).map(|(buffer, _)| buffer)
+    }
+
+    fn extract_with_auth_status(
+        &self,
+        section: &patina_ffs::section::Section,
+    ) -> Result<(vec::Vec<u8>, u32), FirmwareFileSystemError> {
         match Self::uefi_decompress_extract(section) {
             Err(FirmwareFileSystemError::Unsupported) => (),
             Err(err) => return Err(err),
-            Ok(buffer) => return Ok(buffer),
+            Ok(buffer) => return Ok((buffer, 0)),
         }
-        self.0.as_ref().map_or(Err(FirmwareFileSystemError::Unsupported), |extractor| extractor.extract(section))
+        self.0
+            .as_ref()
+            .map_or(Err(FirmwareFileSystemError::Unsupported), |extractor| extractor.extract_with_auth_status(section))
     }
 }
@@ -6,12 +6,19 @@
 //!
 //! SPDX-License-Identifier: Apache-2.0
 //!
+mod graph;
+mod guard;
+mod report;
+mod section_cache;
+mod section_prefetch;
+
 use alloc::{
     boxed::Box,
     collections::{BTreeMap, BTreeSet},
+    string::ToString,
     vec::Vec,
 };
-use core::{cmp::Ordering, ffi::c_void};
+use core::{cmp::Ordering, ffi::c_void, mem, time::Duration};
 use mu_rust_helpers::{function, guid::guid_fmt};
 use patina::{
     component::service::Service,
@@ -22,26 +29,37 @@ use patina::{
     },
 };
 use patina_ffs::{
+    file::FileRef,
     section::{Section, SectionExtractor},
     volume::VolumeRef,
 };
 use patina_internal_depex::{AssociatedDependency, Depex, Opcode};
-use patina_internal_device_path::concat_device_path_to_boxed_slice;
+use patina_internal_device_path::{DevicePathText, concat_device_path_to_boxed_slice};
 use patina_pi::{fw_fs::ffs, protocols::firmware_volume_block};
-use r_efi::efi;
+use r_efi::{efi, protocols::mp_services};
 
 use mu_rust_helpers::guid::CALLER_ID;
 
 use crate::{
+    DispatchPolicy, GuardedDispatchPolicy,
     decompress::CoreExtractor,
+    error::{CoreError, Module},
     events::EVENT_DB,
     fv::{core_install_firmware_volume, device_path_bytes_for_fv_file},
-    image::{core_load_image, core_start_image},
+    image::{core_load_fv_image, core_trust_deferred_image},
     protocol_db::DXE_CORE_HANDLE,
     protocols::PROTOCOL_DB,
     tpl_lock::TplMutex,
 };
 
+use section_cache::SectionCache;
+use section_prefetch::SectionPrefetch;
+
+pub use graph::{Dependency, DependencyGraph, DriverNode, DriverResolution};
+pub use guard::{DriverFailureLog, DriverFailureStore};
+pub use report::{DispatchReport, DispatchReportHandler, DriverDispatchRecord, DriverOutcome};
+pub(crate) use report::{elapsed_since, timestamp};
+
 // Default Dependency expression per PI spec v1.2 Vol 2 section 10.9.
 const ALL_ARCH_DEPEX: &[Opcode] = &[
     Opcode::Push(uuid::Uuid::from_u128(0x665e3ff6_46cc_11d4_9a38_0090273fc14d), false), //BDS Arch
@@ -70,26 +88,55 @@ const ALL_ARCH_DEPEX: &[Opcode] = &[
     Opcode::End,
 ];
 
+// Dispatch state of a driver that has been discovered but not yet started, per PI spec v1.8 Vol 2 section 10.3.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DriverState {
+    // The driver has a SOR depex and will not be evaluated until scheduled with the Schedule() DXE service.
+    Unrequested,
+    // The driver depex is evaluated on each dispatch pass.
+    Dependent,
+    // The driver was deferred by the Security Architectural Protocol and will not be started until promoted with the
+    // Trust() DXE service.
+    Untrusted,
+}
+
 struct PendingDriver {
     firmware_volume_handle: efi::Handle,
     device_path: *mut efi::protocols::device_path::Protocol,
     file_name: efi::Guid,
     depex: Option<Depex>,
-    pe32: Section,
+    // The driver file, whose PE32 section is only extracted when the driver is loaded.
+    file: FileRef<'static>,
     image_handle: Option<efi::Handle>,
     security_status: efi::Status,
+    state: DriverState,
+    // Dispatch priority of the firmware volume of the driver per the platform dispatch policy, lower first.
+    fv_priority: usize,
+}
+
+impl PendingDriver {
+    // Returns the device path of the driver file for diagnostics, e.g. `Fv(...)/FvFile(...)`.
+    fn path_text(&self) -> DevicePathText {
+        // Safety: the device path of a pending driver is built when it is discovered and never freed.
+        unsafe { DevicePathText::new(self.device_path) }
+    }
+
+    fn matches(&self, firmware_volume_handle: efi::Handle, file_name: &efi::Guid) -> bool {
+        self.firmware_volume_handle == firmware_volume_handle && OrdGuid(self.file_name) == OrdGuid(*file_name)
+    }
 }
 
 struct PendingFirmwareVolumeImage {
     parent_fv_handle: efi::Handle,
     file_name: efi::Guid,
     depex: Option<Depex>,
-    fv_sections: Vec<Section>,
+    // The firmware volume image file, whose sections are only extracted when its depex is satisfied.
+    file: FileRef<'static>,
 }
 
 impl PendingFirmwareVolumeImage {
     // authenticate the pending firmware volume via the Security Architectural Protocol
-    fn evaluate_auth(&self) -> Result<(), EfiError> {
+    fn evaluate_auth(&self, fv_sections: &[Section]) -> Result<(), EfiError> {
         let security_protocol = unsafe {
             match PROTOCOL_DB.locate_protocol(patina_pi::protocols::security::PROTOCOL_GUID) {
                 Ok(protocol) => (protocol as *mut patina_pi::protocols::security::Protocol)
@@ -103,12 +150,12 @@ impl PendingFirmwareVolumeImage {
         let file_path = device_path_bytes_for_fv_file(self.parent_fv_handle, self.file_name)
             .map_err(|status| EfiError::status_to_result(status).unwrap_err())?;
 
-        //Important Note: the present section extraction implementation does not support section extraction-based
-        //authentication status, so it is hard-coded to zero here. The primary security handlers for the main usage
-        //scenarios (TPM measurement and UEFI Secure Boot) do not use it.
+        // The authentication status aggregated by the section extractors for the firmware volume image sections.
+        let authentication_status =
+            fv_sections.iter().fold(0, |status, section| status | section.authentication_status());
         let status = (security_protocol.file_authentication_state)(
             security_protocol as *const _ as *mut patina_pi::protocols::security::Protocol,
-            0,
+            authentication_status,
             file_path.as_ptr() as *const _ as *mut efi::protocols::device_path::Protocol,
         );
         EfiError::status_to_result(status)
@@ -140,6 +187,9 @@ struct DispatcherContext {
     associated_after: BTreeMap<OrdGuid, Vec<PendingDriver>>,
     processed_fvs: BTreeSet<efi::Handle>,
     section_extractor: CoreExtractor,
+    section_cache: SectionCache,
+    policy: Option<DispatchPolicy>,
+    report: DispatchReport,
 }
 
 impl DispatcherContext {
@@ -154,8 +204,21 @@ impl DispatcherContext {
             associated_after: BTreeMap::new(),
             processed_fvs: BTreeSet::new(),
             section_extractor: CoreExtractor::new(),
+            section_cache: SectionCache::new(),
+            policy: None,
+            report: DispatchReport::new(),
         }
     }
+
+    // Returns the sections of type `section_type` of `file`, from the section cache or extracted now.
+    fn extract_sections(&mut self, file: &FileRef, section_type: ffs::section::Type) -> Result<Vec<Section>, EfiError> {
+        Ok(self.section_cache.take_or_extract(file, section_type, &self.section_extractor)?)
+    }
+
+    // Returns the PE32 section of the driver `file`, from the section cache or extracted now.
+    fn driver_image(&mut self, file: &FileRef) -> Result<Section, EfiError> {
+        self.extract_sections(file, ffs::section::Type::Pe32)?.into_iter().next().ok_or(EfiError::NotFound)
+    }
 }
 
 unsafe impl Send for DispatcherContext {}
@@ -163,9 +226,9 @@ unsafe impl Send for DispatcherContext {}
 static DISPATCHER_CONTEXT: TplMutex<DispatcherContext> =
     TplMutex::new(efi::TPL_NOTIFY, DispatcherContext::new(), "Dispatcher Context");
 
-pub fn dispatch() -> Result<bool, EfiError> {
+pub fn dispatch() -> Result<bool, CoreError> {
     if DISPATCHER_CONTEXT.lock().executing {
-        return Err(EfiError::AlreadyStarted);
+        return Err(CoreError::new(Module::Dispatcher, "dispatch", EfiError::AlreadyStarted));
     }
 
     let scheduled: Vec<PendingDriver>;
@@ -178,9 +241,10 @@ pub fn dispatch() -> Result<bool, EfiError> {
         let mut scheduled_driver_candidates = Vec::new();
         for mut candidate in driver_candidates {
             log::trace!("Evaluating depex for candidate: {:?}", guid_fmt!(candidate.file_name));
-            let depex_satisfied = match candidate.depex {
-                Some(ref mut depex) => depex.eval(&PROTOCOL_DB.registered_protocols()),
-                None => dispatcher.arch_protocols_available,
+            let depex_satisfied = match (candidate.state, candidate.depex.as_mut()) {
+                (DriverState::Unrequested | DriverState::Untrusted, _) => false,
+                (_, Some(depex)) => depex.eval(&PROTOCOL_DB.registered_protocols()),
+                (_, None) => dispatcher.arch_protocols_available,
             };
 
             if depex_satisfied {
@@ -198,6 +262,9 @@ pub fn dispatch() -> Result<bool, EfiError> {
             }
         }
 
+        // dispatch the drivers in the order of the priority of their firmware volumes, preserving the discovery order.
+        scheduled_driver_candidates.sort_by_key(|driver: &PendingDriver| driver.fv_priority);
+
         // insert contents of associated_before/after at the appropriate point in the schedule if the associated driver is present.
         scheduled = scheduled_driver_candidates
             .into_iter()
@@ -215,18 +282,41 @@ pub fn dispatch() -> Result<bool, EfiError> {
 
     let mut dispatch_attempted = false;
     for mut driver in scheduled {
+        let start = report::timestamp();
         if driver.image_handle.is_none() {
             log::info!("Loading file: {:?}", guid_fmt!(driver.file_name));
-            let data = driver.pe32.try_content_as_slice()?;
-            match core_load_image(false, DXE_CORE_HANDLE, driver.device_path, Some(data)) {
+            // The PE32 section is dropped once the image is loaded, so that only the scheduled driver is decompressed.
+            let pe32 = DISPATCHER_CONTEXT.lock().driver_image(&driver.file);
+            let image = pe32
+                .as_ref()
+                .map_err(|err| *err)
+                .and_then(|pe32| Ok((pe32.try_content_as_slice()?, pe32.authentication_status())));
+            let loaded = match image {
+                Ok((data, authentication_status)) => {
+                    core_load_fv_image(DXE_CORE_HANDLE, driver.device_path, data, authentication_status)
+                }
+                Err(err) => Err(CoreError::new(Module::Dispatcher, "read driver image section", err)),
+            };
+            match loaded {
                 Ok((image_handle, security_status)) => {
                     driver.image_handle = Some(image_handle);
                     driver.security_status = match security_status {
                         Ok(_) => efi::Status::SUCCESS,
                         Err(err) => err.into(),
                     };
+                    if driver.security_status == efi::Status::SECURITY_VIOLATION {
+                        driver.state = DriverState::Untrusted;
+                    }
+                }
+                Err(err) => {
+                    log::error!(
+                        "Failed to load driver {:?} ({}): {err}",
+                        guid_fmt!(driver.file_name),
+                        driver.path_text()
+                    );
+                    let status = efi::Status::from(err);
+                    record(driver.file_name, DriverOutcome::LoadFailed(status), report::elapsed_since(start));
                 }
-                Err(err) => log::error!("Failed to load: load_image returned {err:x?}"),
             }
         }
 
@@ -234,24 +324,33 @@ pub fn dispatch() -> Result<bool, EfiError> {
             match driver.security_status {
                 efi::Status::SUCCESS => {
                     dispatch_attempted = true;
-                    // Note: ignore error result of core_start_image here - an image returning an error code is expected in some
-                    // cases, and a debug output for that is already implemented in core_start_image.
-                    let _status = core_start_image(image_handle);
+                    // Note: an image returning an error code is expected in some cases, so the status is only
+                    // recorded in the dispatch report; a debug output for it is already implemented in
+                    // core_start_image.
+                    let status = match guard::start_driver(driver.file_name, image_handle) {
+                        Ok(()) => efi::Status::SUCCESS,
+                        Err(status) => status,
+                    };
+                    record(driver.file_name, DriverOutcome::Started(status), report::elapsed_since(start));
                 }
                 efi::Status::SECURITY_VIOLATION => {
                     log::info!(
-                        "Deferring driver: {:?} due to security status: {:x?}",
+                        "Deferring driver: {:?} ({}) due to security status: {:x?}",
                         guid_fmt!(driver.file_name),
+                        driver.path_text(),
                         efi::Status::SECURITY_VIOLATION
                     );
+                    record(driver.file_name, DriverOutcome::Deferred, report::elapsed_since(start));
                     DISPATCHER_CONTEXT.lock().pending_drivers.push(driver);
                 }
                 unexpected_status => {
                     log::info!(
-                        "Dropping driver: {:?} due to security status: {:x?}",
+                        "Dropping driver: {:?} ({}) due to security status: {:x?}",
                         guid_fmt!(driver.file_name),
+                        driver.path_text(),
                         unexpected_status
                     );
+                    record(driver.file_name, DriverOutcome::Rejected(unexpected_status), report::elapsed_since(start));
                 }
             }
         }
@@ -267,9 +366,28 @@ pub fn dispatch() -> Result<bool, EfiError> {
                 None => true,
             };
 
-            if depex_satisfied && candidate.evaluate_auth().is_ok() {
-                for section in candidate.fv_sections {
-                    let fv_data = Box::from(section.try_content_as_slice()?);
+            if !depex_satisfied {
+                dispatcher.pending_firmware_volume_images.push(candidate);
+                continue;
+            }
+
+            let fv_sections =
+                match dispatcher.extract_sections(&candidate.file, ffs::section::Type::FirmwareVolumeImage) {
+                    Ok(fv_sections) => fv_sections,
+                    Err(err) => {
+                        log::error!(
+                            "Failed to extract firmware volume image {:?}: {err:?}",
+                            guid_fmt!(candidate.file_name)
+                        );
+                        continue;
+                    }
+                };
+            if candidate.evaluate_auth(&fv_sections).is_ok() {
+                for section in fv_sections {
+                    let fv_data = Box::from(section.try_content_as_slice().map_err(|err| {
+                        CoreError::new(Module::Dispatcher, "read firmware volume image section", err.into())
+                            .with_guid(candidate.file_name)
+                    })?);
                     dispatcher.fv_section_data.push(fv_data);
                     let data_ptr =
                         dispatcher.fv_section_data.last().expect("freshly pushed fv section data must be valid");
@@ -289,6 +407,8 @@ pub fn dispatch() -> Result<bool, EfiError> {
                     }
                 }
             } else {
+                // Keep the extracted sections for the next attempt, within the capacity of the cache.
+                dispatcher.section_cache.insert(&candidate.file, fv_sections);
                 dispatcher.pending_firmware_volume_images.push(candidate)
             }
         }
@@ -335,8 +455,9 @@ fn add_fv_handles(new_handles: Vec<efi::Handle>) -> Result<(), EfiError> {
 
             // Safety: this code assumes that the fv_address from FVB protocol yields a pointer to a real FV,
             // and that the memory backing the FVB is essentially permanent while the dispatcher is running (i.e.
-            // that no one uninstalls the FVB protocol and frees the memory).
-            let fv = match unsafe { VolumeRef::new_from_address(fv_address) } {
+            // that no one uninstalls the FVB protocol and frees the memory). The pending drivers and firmware volume
+            // images keep referencing their files in it, to extract their sections when they are dispatched.
+            let fv: VolumeRef<'static> = match unsafe { VolumeRef::new_from_address(fv_address) } {
                 Ok(fv) => fv,
                 Err(err) => {
                     log::error!("Failed to instantiate memory mapped FV for fvb handle {handle:#x?}. Error: {err:#x?}");
@@ -344,25 +465,46 @@ fn add_fv_handles(new_handles: Vec<efi::Handle>) -> Result<(), EfiError> {
                 }
             };
 
+            let fv_name = fv.fv_name();
+            let fv_priority = dispatcher.policy.as_ref().map_or(0, |policy| policy.fv_priority(fv_name));
+
+            // Queue the decompression of the upcoming files, if parallel section extraction is enabled.
+            let mut prefetch = SectionPrefetch::new(&fv);
+
             for file in fv.files() {
                 let file = file?;
                 if file.file_type_raw() == ffs::file::raw::r#type::DRIVER {
                     let file = file.clone();
                     let file_name = file.name();
-                    let sections = file.sections_with_extractor(&dispatcher.section_extractor)?;
-
-                    let depex = sections
-                        .iter()
-                        .find_map(|x| match x.section_type() {
-                            Some(ffs::section::Type::DxeDepex) => Some(x.try_content_as_slice()),
-                            _ => None,
-                        })
-                        .transpose()?
-                        .map(Depex::from);
-
-                    if let Some(pe32_section) =
-                        sections.into_iter().find(|x| x.section_type() == Some(ffs::section::Type::Pe32))
-                    {
+                    if let Some(reason) = dispatcher.policy.as_ref().and_then(|policy| policy.skip_reason(&file_name)) {
+                        log::info!(
+                            "Skipping driver {:?} in fvb handle {handle:#x?}: {reason} of the dispatch policy.",
+                            guid_fmt!(file_name)
+                        );
+                        let outcome = DriverOutcome::Skipped(reason);
+                        dispatcher.report.push(DriverDispatchRecord { file_name, outcome, elapsed: Duration::ZERO });
+                        continue;
+                    }
+                    if let Some(failures) = guard::blocked_failures(&file_name) {
+                        log::warn!(
+                            "Skipping driver {:?} in fvb handle {handle:#x?}: it failed in {failures} consecutive boots.",
+                            guid_fmt!(file_name)
+                        );
+                        let outcome = DriverOutcome::Blocked(failures);
+                        dispatcher.report.push(DriverDispatchRecord { file_name, outcome, elapsed: Duration::ZERO });
+                        continue;
+                    }
+                    let dispatcher = &mut *dispatcher;
+                    let metadata = section_cache::discover_file(
+                        &file,
+                        ffs::section::Type::Pe32,
+                        &mut prefetch,
+                        &dispatcher.section_extractor,
+                        &mut dispatcher.section_cache,
+                    )?;
+                    let depex = metadata.depex;
+
+                    if metadata.has_section {
                         // In this case, this is sizeof(guid) + sizeof(protocol) = 20, so it should always fit an u8
                         const FILENAME_NODE_SIZE: usize = core::mem::size_of::<efi::protocols::device_path::Protocol>()
                             + core::mem::size_of::<r_efi::efi::Guid>();
@@ -410,11 +552,17 @@ fn add_fv_handles(new_handles: Vec<efi::Handle>) -> Result<(), EfiError> {
                         dispatcher.pending_drivers.push(PendingDriver {
                             file_name,
                             firmware_volume_handle: handle,
-                            pe32: pe32_section,
+                            file,
                             device_path: full_device_path_for_file,
+                            state: if depex.as_ref().is_some_and(Depex::is_sor) {
+                                DriverState::Unrequested
+                            } else {
+                                DriverState::Dependent
+                            },
                             depex,
                             image_handle: None,
                             security_status: efi::Status::NOT_READY,
+                            fv_priority,
                         });
                     } else {
                         log::warn!("driver {:?} does not contain a PE32 section.", guid_fmt!(file_name));
@@ -424,28 +572,21 @@ fn add_fv_handles(new_handles: Vec<efi::Handle>) -> Result<(), EfiError> {
                     let file = file.clone();
                     let file_name = file.name();
 
-                    let sections = file.sections_with_extractor(&dispatcher.section_extractor)?;
-
-                    let depex = sections
-                        .iter()
-                        .find_map(|x| match x.section_type() {
-                            Some(ffs::section::Type::DxeDepex) => Some(x.try_content_as_slice()),
-                            _ => None,
-                        })
-                        .transpose()?
-                        .map(Depex::from);
+                    let dispatcher = &mut *dispatcher;
+                    let metadata = section_cache::discover_file(
+                        &file,
+                        ffs::section::Type::FirmwareVolumeImage,
+                        &mut prefetch,
+                        &dispatcher.section_extractor,
+                        &mut dispatcher.section_cache,
+                    )?;
 
-                    let fv_sections = sections
-                        .into_iter()
-                        .filter(|s| s.section_type() == Some(ffs::section::Type::FirmwareVolumeImage))
-                        .collect::<Vec<_>>();
-
-                    if !fv_sections.is_empty() {
+                    if metadata.has_section {
                         dispatcher.pending_firmware_volume_images.push(PendingFirmwareVolumeImage {
                             parent_fv_handle: handle,
                             file_name,
-                            depex,
-                            fv_sections,
+                            depex: metadata.depex,
+                            file,
                         });
                     } else {
                         log::warn!(
@@ -460,35 +601,57 @@ fn add_fv_handles(new_handles: Vec<efi::Handle>) -> Result<(), EfiError> {
     Ok(())
 }
 
+/// Clears the Schedule On Request (SOR) flag of the driver `file` in the firmware volume `handle`, moving it to the
+/// Dependent state so that its depex is evaluated on the next dispatch pass.
+///
+/// ## Errors
+///
+/// NotFound - the driver was not found, or is not in the Unrequested (SOR) state.
 pub fn core_schedule(handle: efi::Handle, file: &efi::Guid) -> Result<(), EfiError> {
     let mut dispatcher = DISPATCHER_CONTEXT.lock();
-    for driver in dispatcher.pending_drivers.iter_mut() {
-        if driver.firmware_volume_handle == handle
-            && OrdGuid(driver.file_name) == OrdGuid(*file)
-            && let Some(depex) = &mut driver.depex
-            && depex.is_sor()
-        {
-            depex.schedule();
-            return Ok(());
-        }
+    let driver = dispatcher
+        .pending_drivers
+        .iter_mut()
+        .find(|driver| driver.matches(handle, file) && driver.state == DriverState::Unrequested)
+        .ok_or(EfiError::NotFound)?;
+
+    if let Some(depex) = &mut driver.depex {
+        depex.schedule();
     }
-    Err(EfiError::NotFound)
+    driver.state = DriverState::Dependent;
+    Ok(())
 }
 
+/// Promotes the driver `file` in the firmware volume `handle` that was deferred by the Security Architectural Protocol
+/// from the Untrusted state to the Dependent state, so that it is started on the next dispatch pass.
+///
+/// ## Errors
+///
+/// NotFound - the driver was not found, or is not in the Untrusted state.
 pub fn core_trust(handle: efi::Handle, file: &efi::Guid) -> Result<(), EfiError> {
-    let mut dispatcher = DISPATCHER_CONTEXT.lock();
-    for driver in dispatcher.pending_drivers.iter_mut() {
-        if driver.firmware_volume_handle == handle && OrdGuid(driver.file_name) == OrdGuid(*file) {
-            driver.security_status = efi::Status::SUCCESS;
-            return Ok(());
-        }
+    let is_untrusted =
+        |driver: &&mut PendingDriver| driver.matches(handle, file) && driver.state == DriverState::Untrusted;
+    let image_handle =
+        DISPATCHER_CONTEXT.lock().pending_drivers.iter_mut().find(is_untrusted).ok_or(EfiError::NotFound)?.image_handle;
+
+    // The driver image was loaded but deferred by the security policy; allow it to be started. If that fails, the
+    // driver stays untrusted, as the image could not be started anyway.
+    if let Some(image_handle) = image_handle {
+        core_trust_deferred_image(image_handle).inspect_err(|err| {
+            log::error!("Failed to trust the deferred image {image_handle:?} of driver {file:?}: {err:?}");
+        })?;
     }
-    Err(EfiError::NotFound)
+
+    let mut dispatcher = DISPATCHER_CONTEXT.lock();
+    let driver = dispatcher.pending_drivers.iter_mut().find(is_untrusted).ok_or(EfiError::NotFound)?;
+    driver.security_status = efi::Status::SUCCESS;
+    driver.state = DriverState::Dependent;
+    Ok(())
 }
 
-pub fn core_dispatcher() -> Result<(), EfiError> {
+pub fn core_dispatcher() -> Result<(), CoreError> {
     if DISPATCHER_CONTEXT.lock().executing {
-        return Err(EfiError::AlreadyStarted);
+        return Err(CoreError::new(Module::Dispatcher, "dispatch", EfiError::AlreadyStarted));
     }
 
     perf_function_begin(function!(), &CALLER_ID, create_performance_measurement);
@@ -500,7 +663,7 @@ pub fn core_dispatcher() -> Result<(), EfiError> {
 
     perf_function_end(function!(), &CALLER_ID, create_performance_measurement);
 
-    if something_dispatched { Ok(()) } else { Err(EfiError::NotFound) }
+    if something_dispatched { Ok(()) } else { Err(CoreError::new(Module::Dispatcher, "dispatch", EfiError::NotFound)) }
 }
 
 pub fn init_dispatcher() {
@@ -512,26 +675,171 @@ pub fn init_dispatcher() {
     PROTOCOL_DB
         .register_protocol_notify(firmware_volume_block::PROTOCOL_GUID, event)
         .expect("Failed to register protocol notify on fv protocol.");
+
+    if section_prefetch::enabled() {
+        //set up call back for MP Services protocol installation, to extract sections on the APs.
+        let event = EVENT_DB
+            .create_event(efi::EVT_NOTIFY_SIGNAL, efi::TPL_CALLBACK, Some(core_mp_services_protocol_notify), None, None)
+            .expect("Failed to create MP Services protocol installation callback.");
+
+        PROTOCOL_DB
+            .register_protocol_notify(mp_services::PROTOCOL_GUID, event)
+            .expect("Failed to register protocol notify on MP Services protocol.");
+    }
+}
+
+/// Enables the decompression of the sections of newly discovered firmware volumes on the application processors, once
+/// the MP Services protocol is installed. Must be called before [init_dispatcher].
+pub fn enable_parallel_section_extraction() {
+    section_prefetch::enable();
+}
+
+/// Sets the platform policy applied to the drivers of the firmware volumes discovered afterwards. Must be called
+/// before the firmware volumes are installed.
+pub fn set_dispatch_policy(policy: DispatchPolicy) {
+    DISPATCHER_CONTEXT.lock().policy = Some(policy);
+}
+
+/// Guards the entry points of the drivers dispatched afterwards with `policy`, blocking the drivers that failed in
+/// previous boots according to `store`. Must be called before the firmware volumes are installed.
+pub fn enable_guarded_dispatch(policy: GuardedDispatchPolicy, store: Option<Service<dyn DriverFailureStore>>) {
+    guard::enable(policy, store);
+}
+
+/// Sets the capacity in bytes of the cache of the sections extracted before their file is dispatched.
+pub fn set_section_cache_capacity(capacity: usize) {
+    DISPATCHER_CONTEXT.lock().section_cache.set_capacity(capacity);
 }
 
 pub fn register_section_extractor(extractor: Service<dyn SectionExtractor>) {
     DISPATCHER_CONTEXT.lock().section_extractor.set_extractor(extractor);
 }
 
+// Records the outcome of a driver in the dispatch report, reporting a status code if the driver failed.
+fn record(file_name: efi::Guid, outcome: DriverOutcome, elapsed: Duration) {
+    let record = DriverDispatchRecord { file_name, outcome, elapsed };
+    if outcome.is_failure() {
+        report::report_failure(&record);
+    }
+    DISPATCHER_CONTEXT.lock().report.push(record);
+}
+
+/// Returns the outcomes of the drivers recorded since the previous call, including the drivers discovered but not
+/// dispatched.
+pub fn take_dispatch_report() -> DispatchReport {
+    let mut dispatcher = DISPATCHER_CONTEXT.lock();
+    let not_dispatched: Vec<_> = dispatcher
+        .pending_drivers
+        .iter()
+        .chain(dispatcher.associated_before.values().flatten())
+        .chain(dispatcher.associated_after.values().flatten())
+        .map(|driver| driver.file_name)
+        .collect();
+    for file_name in not_dispatched {
+        let outcome = DriverOutcome::NotDispatched;
+        dispatcher.report.push(DriverDispatchRecord { file_name, outcome, elapsed: Duration::ZERO });
+    }
+    mem::take(&mut dispatcher.report)
+}
+
+/// Returns the dependency graph of the drivers discovered in the firmware volumes, the drivers waiting to be
+/// dispatched first, then the drivers recorded in the dispatch report since it was last taken.
+pub fn dependency_graph() -> DependencyGraph {
+    let installed = PROTOCOL_DB.registered_protocols();
+    let dispatcher = DISPATCHER_CONTEXT.lock();
+
+    let associated = dispatcher.associated_before.values().chain(dispatcher.associated_after.values()).flatten();
+    let pending =
+        dispatcher.pending_drivers.iter().map(|driver| (driver, false)).chain(associated.map(|driver| (driver, true)));
+    let mut drivers: Vec<DriverNode> = pending
+        .map(|(driver, associated)| {
+            let resolution = match driver.state {
+                DriverState::Unrequested => DriverResolution::Unrequested,
+                DriverState::Untrusted => DriverResolution::Untrusted,
+                DriverState::Dependent if associated => DriverResolution::Associated,
+                DriverState::Dependent => DriverResolution::Dependent,
+            };
+            let depex = driver.depex.as_ref().map(Depex::opcodes);
+            DriverNode::pending(driver.file_name, driver.path_text().to_string(), resolution, depex, &installed)
+        })
+        .collect();
+    let pending_count = drivers.len();
+
+    // A driver may have several records, the last one is its outcome.
+    for record in dispatcher.report.records() {
+        let file_name = OrdGuid(record.file_name);
+        if drivers[..pending_count].iter().any(|driver| OrdGuid(driver.file_name) == file_name) {
+            continue;
+        }
+        match drivers[pending_count..].iter_mut().find(|driver| OrdGuid(driver.file_name) == file_name) {
+            Some(driver) => driver.resolution = DriverResolution::Dispatched(record.outcome),
+            None => drivers.push(DriverNode::dispatched(record.file_name, record.outcome)),
+        }
+    }
+    DependencyGraph::new(drivers)
+}
+
 pub fn display_discovered_not_dispatched() {
-    for driver in &DISPATCHER_CONTEXT.lock().pending_drivers {
-        log::warn!("Driver {:?} found but not dispatched.", guid_fmt!(driver.file_name));
+    let dispatcher = DISPATCHER_CONTEXT.lock();
+    for driver in &dispatcher.pending_drivers {
+        log::warn!(
+            "Driver {:?} ({}) found but not dispatched ({:?}).",
+            guid_fmt!(driver.file_name),
+            driver.path_text(),
+            driver.state
+        );
+    }
+    let stalled = !dispatcher.pending_drivers.is_empty()
+        || !dispatcher.associated_before.is_empty()
+        || !dispatcher.associated_after.is_empty();
+    drop(dispatcher);
+
+    if stalled {
+        let graph = dependency_graph();
+        for guid in graph.missing_protocols() {
+            log::warn!("Protocol {:?} is required by a driver not dispatched, and not installed.", guid_fmt!(guid));
+        }
+        for cycle in graph.cycles() {
+            log::warn!("Drivers scheduled before or after each other in a cycle:");
+            for file_name in cycle {
+                log::warn!("  {:?}", guid_fmt!(file_name));
+            }
+        }
+        log::info!("Driver dependency graph (DOT):\n{}", graph.to_dot());
     }
 }
 
 extern "efiapi" fn core_fw_vol_event_protocol_notify(_event: efi::Event, _context: *mut c_void) {
     //Note: runs at TPL_CALLBACK
     match PROTOCOL_DB.locate_handles(Some(firmware_volume_block::PROTOCOL_GUID)) {
-        Ok(fv_handles) => add_fv_handles(fv_handles).expect("Error adding FV handles"),
+        Ok(fv_handles) => {
+            // FV discovery is measured to allow comparing serial and parallel section extraction.
+            perf_function_begin("add_fv_handles", &CALLER_ID, create_performance_measurement);
+            add_fv_handles(fv_handles).expect("Error adding FV handles");
+            perf_function_end("add_fv_handles", &CALLER_ID, create_performance_measurement);
+        }
         Err(_) => panic!("could not locate handles in protocol call back"),
     };
 }
 
+extern "efiapi" fn core_mp_services_protocol_notify(_event: efi::Event, _context: *mut c_void) {
+    let protocol = match PROTOCOL_DB.locate_protocol(mp_services::PROTOCOL_GUID) {
+        Ok(protocol) => protocol,
+        Err(err) => {
+            log::error!("Failed to locate MP Services protocol: {err:?}");
+            return;
+        }
+    };
+    // The MP Services protocol signals this event when the APs are done, which is required to not wait for them.
+    let result = EVENT_DB
+        .create_event(0, efi::TPL_CALLBACK, None, None, None)
+        .and_then(|wait_event| section_prefetch::register_mp_services(protocol as *mut _, wait_event));
+    match result {
+        Ok(()) => log::info!("MP Services protocol installed. Extracting sections on the APs."),
+        Err(err) => log::error!("Failed to register MP Services for section extraction: {err:?}"),
+    }
+}
+
 #[cfg(test)]
 #[coverage(off)]
 mod tests {
@@ -675,6 +983,144 @@ mod tests {
 
             const DRIVERS_IN_DXEFV: usize = 130;
             assert_eq!(DISPATCHER_CONTEXT.lock().pending_drivers.len(), DRIVERS_IN_DXEFV);
+            // The driver images are only extracted when the drivers are loaded.
+            assert_eq!(DISPATCHER_CONTEXT.lock().section_cache.size(), 0);
+        });
+
+        let _dropped_fv = unsafe { Box::from_raw(fv_raw) };
+    }
+
+    #[test]
+    fn test_dispatch_policy_skips_blocked_drivers() {
+        set_logger();
+        let mut file = File::open(test_collateral!("DXEFV.Fv")).unwrap();
+        let mut fv: Vec<u8> = Vec::new();
+        file.read_to_end(&mut fv).expect("failed to read test file");
+        let fv = fv.into_boxed_slice();
+        let fv_raw = Box::into_raw(fv);
+
+        with_locked_state(|| {
+            // Safety: fv is leaked to ensure it is not freed and remains valid for the duration of the program.
+            let handle =
+                unsafe { crate::fv::core_install_firmware_volume(fv_raw.expose_provenance() as u64, None).unwrap() };
+
+            add_fv_handles(vec![handle]).expect("Failed to add FV handle");
+            let blocked = DISPATCHER_CONTEXT.lock().pending_drivers[0].file_name;
+
+            *DISPATCHER_CONTEXT.lock() = DispatcherContext::new();
+            set_dispatch_policy(DispatchPolicy { blocked_drivers: vec![blocked], ..Default::default() });
+            add_fv_handles(vec![handle]).expect("Failed to add FV handle");
+
+            const DRIVERS_IN_DXEFV: usize = 130;
+            let dispatcher = DISPATCHER_CONTEXT.lock();
+            assert_eq!(dispatcher.pending_drivers.len(), DRIVERS_IN_DXEFV - 1);
+            assert!(!dispatcher.pending_drivers.iter().any(|driver| driver.matches(handle, &blocked)));
+        });
+
+        let _dropped_fv = unsafe { Box::from_raw(fv_raw) };
+    }
+
+    #[test]
+    fn test_dispatch_report_records_skipped_and_not_dispatched_drivers() {
+        set_logger();
+        let mut file = File::open(test_collateral!("DXEFV.Fv")).unwrap();
+        let mut fv: Vec<u8> = Vec::new();
+        file.read_to_end(&mut fv).expect("failed to read test file");
+        let fv = fv.into_boxed_slice();
+        let fv_raw = Box::into_raw(fv);
+
+        with_locked_state(|| {
+            // Safety: fv is leaked to ensure it is not freed and remains valid for the duration of the program.
+            let handle =
+                unsafe { crate::fv::core_install_firmware_volume(fv_raw.expose_provenance() as u64, None).unwrap() };
+
+            add_fv_handles(vec![handle]).expect("Failed to add FV handle");
+            let blocked = DISPATCHER_CONTEXT.lock().pending_drivers[0].file_name;
+
+            *DISPATCHER_CONTEXT.lock() = DispatcherContext::new();
+            set_dispatch_policy(DispatchPolicy { blocked_drivers: vec![blocked], ..Default::default() });
+            add_fv_handles(vec![handle]).expect("Failed to add FV handle");
+
+            const DRIVERS_IN_DXEFV: usize = 130;
+            let report = take_dispatch_report();
+            assert_eq!(report.records().len(), DRIVERS_IN_DXEFV);
+            assert_eq!(report.records()[0].file_name, blocked);
+            assert_eq!(report.records()[0].outcome, DriverOutcome::Skipped("it is in the block-list"));
+            assert!(report.records()[1..].iter().all(|record| record.outcome == DriverOutcome::NotDispatched));
+            assert!(!report.has_failures());
+
+            // The report is reset once taken.
+            let report = take_dispatch_report();
+            assert_eq!(report.records().len(), DRIVERS_IN_DXEFV - 1);
+            assert!(report.records().iter().all(|record| record.outcome == DriverOutcome::NotDispatched));
+        });
+
+        let _dropped_fv = unsafe { Box::from_raw(fv_raw) };
+    }
+
+    #[test]
+    fn test_dispatch_policy_allow_list_skips_unlisted_drivers() {
+        set_logger();
+        let mut file = File::open(test_collateral!("DXEFV.Fv")).unwrap();
+        let mut fv: Vec<u8> = Vec::new();
+        file.read_to_end(&mut fv).expect("failed to read test file");
+        let fv = fv.into_boxed_slice();
+        let fv_raw = Box::into_raw(fv);
+
+        with_locked_state(|| {
+            // Safety: fv is leaked to ensure it is not freed and remains valid for the duration of the program.
+            let handle =
+                unsafe { crate::fv::core_install_firmware_volume(fv_raw.expose_provenance() as u64, None).unwrap() };
+
+            add_fv_handles(vec![handle]).expect("Failed to add FV handle");
+            let allowed: Vec<_> =
+                DISPATCHER_CONTEXT.lock().pending_drivers.iter().take(2).map(|driver| driver.file_name).collect();
+
+            *DISPATCHER_CONTEXT.lock() = DispatcherContext::new();
+            set_dispatch_policy(DispatchPolicy {
+                blocked_drivers: vec![allowed[1]],
+                allowed_drivers: Some(allowed.clone()),
+                ..Default::default()
+            });
+            add_fv_handles(vec![handle]).expect("Failed to add FV handle");
+
+            // The block-list takes precedence over the allow-list.
+            let dispatcher = DISPATCHER_CONTEXT.lock();
+            assert_eq!(dispatcher.pending_drivers.len(), 1);
+            assert!(dispatcher.pending_drivers[0].matches(handle, &allowed[0]));
+        });
+
+        let _dropped_fv = unsafe { Box::from_raw(fv_raw) };
+    }
+
+    #[test]
+    fn test_dispatch_policy_fv_priority() {
+        set_logger();
+        let mut file = File::open(test_collateral!("DXEFV.Fv")).unwrap();
+        let mut fv: Vec<u8> = Vec::new();
+        file.read_to_end(&mut fv).expect("failed to read test file");
+        let fv_name = patina_ffs::volume::VolumeRef::new(&fv).unwrap().fv_name().expect("DXEFV should have a name");
+        let fv = fv.into_boxed_slice();
+        let fv_raw = Box::into_raw(fv);
+
+        let other_fv_name = efi::Guid::from_fields(0x1, 0x2, 0x3, 0x4, 0x5, &[0x6, 0x7, 0x8, 0x9, 0xa, 0xb]);
+        let policy = DispatchPolicy { fv_priority: vec![other_fv_name, fv_name], ..Default::default() };
+        assert_eq!(policy.fv_priority(Some(other_fv_name)), 0);
+        assert_eq!(policy.fv_priority(Some(fv_name)), 1);
+        assert_eq!(policy.fv_priority(None), 2);
+
+        with_locked_state(|| {
+            // Safety: fv is leaked to ensure it is not freed and remains valid for the duration of the program.
+            let handle =
+                unsafe { crate::fv::core_install_firmware_volume(fv_raw.expose_provenance() as u64, None).unwrap() };
+
+            set_dispatch_policy(policy.clone());
+            add_fv_handles(vec![handle]).expect("Failed to add FV handle");
+
+            const DRIVERS_IN_DXEFV: usize = 130;
+            let dispatcher = DISPATCHER_CONTEXT.lock();
+            assert_eq!(dispatcher.pending_drivers.len(), DRIVERS_IN_DXEFV);
+            assert!(dispatcher.pending_drivers.iter().all(|driver| driver.fv_priority == 1));
         });
 
         let _dropped_fv = unsafe { Box::from_raw(fv_raw) };
@@ -822,6 +1268,50 @@ mod tests {
         let _dropped_fv = unsafe { Box::from_raw(fv_raw) };
     }
 
+    #[test]
+    fn test_dependency_graph() {
+        set_logger();
+        let mut file = File::open(test_collateral!("DXEFV.Fv")).unwrap();
+        let mut fv: Vec<u8> = Vec::new();
+        file.read_to_end(&mut fv).expect("failed to read test file");
+        let fv = fv.into_boxed_slice();
+        let fv_raw = Box::into_raw(fv);
+
+        with_locked_state(|| {
+            assert!(dependency_graph().drivers().is_empty());
+
+            // Safety: fv is leaked to ensure it is not freed and remains valid for the duration of the program.
+            let handle =
+                unsafe { crate::fv::core_install_firmware_volume(fv_raw.expose_provenance() as u64, None).unwrap() };
+            add_fv_handles(vec![handle]).expect("Failed to add FV handle");
+            let pending = DISPATCHER_CONTEXT.lock().pending_drivers.len();
+
+            let graph = dependency_graph();
+            assert_eq!(graph.drivers().len(), pending);
+            assert!(graph.drivers().iter().all(|driver| driver.resolution.is_pending() && !driver.path.is_empty()));
+            // The architectural protocols are not installed in the test protocol database.
+            assert!(!graph.missing_protocols().is_empty());
+            assert!(graph.to_json().starts_with("{\"drivers\":[{\"file_name\":"));
+            assert!(graph.to_dot().starts_with("digraph dependencies {"));
+
+            // Drivers recorded in the report are in the graph once, with their last outcome.
+            let file_name = efi::Guid::from_fields(0x12345678, 0, 0, 0, 0, &[0; 6]);
+            let mut dispatcher = DISPATCHER_CONTEXT.lock();
+            for outcome in [DriverOutcome::Deferred, DriverOutcome::Started(efi::Status::SUCCESS)] {
+                dispatcher.report.push(DriverDispatchRecord { file_name, outcome, elapsed: Duration::ZERO });
+            }
+            drop(dispatcher);
+            let graph = dependency_graph();
+            assert_eq!(graph.drivers().len(), pending + 1);
+            assert_eq!(
+                graph.drivers()[pending].resolution,
+                DriverResolution::Dispatched(DriverOutcome::Started(efi::Status::SUCCESS))
+            );
+        });
+
+        let _dropped_fv = unsafe { Box::from_raw(fv_raw) };
+    }
+
     #[test]
     fn test_core_fw_col_event_protocol_notify() {
         set_logger();
@@ -850,7 +1340,8 @@ mod tests {
         with_locked_state(|| {
             DISPATCHER_CONTEXT.lock().executing = true;
             let result = core_dispatcher();
-            assert_eq!(result, Err(EfiError::AlreadyStarted));
+            assert_eq!(result, Err(CoreError::new(Module::Dispatcher, "dispatch", EfiError::AlreadyStarted)));
+            assert_eq!(dispatch(), Err(CoreError::new(Module::Dispatcher, "dispatch", EfiError::AlreadyStarted)));
         })
     }
 
@@ -859,7 +1350,7 @@ mod tests {
         set_logger();
         with_locked_state(|| {
             let result = core_dispatcher();
-            assert_eq!(result, Err(EfiError::NotFound));
+            assert_eq!(result.map_err(EfiError::from), Err(EfiError::NotFound));
         })
     }
 
@@ -881,7 +1372,7 @@ mod tests {
 
             // Cannot actually dispatch
             let result = core_dispatcher();
-            assert_eq!(result, Err(EfiError::NotFound));
+            assert_eq!(result.map_err(EfiError::from), Err(EfiError::NotFound));
         });
 
         let _dropped_fv = unsafe { Box::from_raw(fv_raw) };
@@ -914,6 +1405,103 @@ mod tests {
         let _dropped_fv = unsafe { Box::from_raw(fv_raw) };
     }
 
+    #[test]
+    fn test_core_schedule_unrequested_driver() {
+        set_logger();
+        let mut file = File::open(test_collateral!("DXEFV.Fv")).unwrap();
+        let mut fv: Vec<u8> = Vec::new();
+        file.read_to_end(&mut fv).expect("failed to read test file");
+        let fv = fv.into_boxed_slice();
+        let fv_raw = Box::into_raw(fv);
+
+        with_locked_state(|| {
+            // Safety: fv is leaked to ensure it is not freed and remains valid for the duration of the program.
+            let handle =
+                unsafe { crate::fv::core_install_firmware_volume(fv_raw.expose_provenance() as u64, None).unwrap() };
+
+            add_fv_handles(vec![handle]).expect("Failed to add FV handle");
+
+            // DXEFV has no SOR drivers, so patch one in.
+            let file_name = {
+                let mut dispatcher = DISPATCHER_CONTEXT.lock();
+                let driver = &mut dispatcher.pending_drivers[0];
+                driver.depex = Some(Depex::from(&[Opcode::Sor, Opcode::True, Opcode::End][..]));
+                driver.state = DriverState::Unrequested;
+                driver.file_name
+            };
+
+            // An unrequested driver is not untrusted.
+            assert_eq!(core_trust(handle, &file_name), Err(EfiError::NotFound));
+
+            assert_eq!(core_schedule(handle, &file_name), Ok(()));
+            {
+                let dispatcher = DISPATCHER_CONTEXT.lock();
+                let driver = dispatcher.pending_drivers.iter().find(|d| d.matches(handle, &file_name)).unwrap();
+                assert_eq!(driver.state, DriverState::Dependent);
+                assert!(!driver.depex.as_ref().unwrap().is_sor());
+            }
+
+            // Already scheduled.
+            assert_eq!(core_schedule(handle, &file_name), Err(EfiError::NotFound));
+            // Wrong firmware volume handle.
+            assert_eq!(core_schedule(core::ptr::null_mut(), &file_name), Err(EfiError::NotFound));
+        });
+
+        let _dropped_fv = unsafe { Box::from_raw(fv_raw) };
+    }
+
+    #[test]
+    fn test_core_trust_untrusted_driver() {
+        set_logger();
+        let mut file = File::open(test_collateral!("DXEFV.Fv")).unwrap();
+        let mut fv: Vec<u8> = Vec::new();
+        file.read_to_end(&mut fv).expect("failed to read test file");
+        let fv = fv.into_boxed_slice();
+        let fv_raw = Box::into_raw(fv);
+
+        with_locked_state(|| {
+            // Safety: fv is leaked to ensure it is not freed and remains valid for the duration of the program.
+            let handle =
+                unsafe { crate::fv::core_install_firmware_volume(fv_raw.expose_provenance() as u64, None).unwrap() };
+
+            add_fv_handles(vec![handle]).expect("Failed to add FV handle");
+
+            let file_name = DISPATCHER_CONTEXT.lock().pending_drivers[0].file_name;
+
+            // A driver that has not been deferred by the security protocol cannot be trusted.
+            assert_eq!(core_trust(handle, &file_name), Err(EfiError::NotFound));
+
+            {
+                let mut dispatcher = DISPATCHER_CONTEXT.lock();
+                let driver = &mut dispatcher.pending_drivers[0];
+                driver.security_status = efi::Status::SECURITY_VIOLATION;
+                driver.state = DriverState::Untrusted;
+            }
+
+            // An untrusted driver is not unrequested.
+            assert_eq!(core_schedule(handle, &file_name), Err(EfiError::NotFound));
+
+            // A driver whose image is not deferred stays untrusted.
+            DISPATCHER_CONTEXT.lock().pending_drivers[0].image_handle = Some(DXE_CORE_HANDLE);
+            assert_eq!(core_trust(handle, &file_name), Err(EfiError::NotFound));
+            assert_eq!(DISPATCHER_CONTEXT.lock().pending_drivers[0].state, DriverState::Untrusted);
+            DISPATCHER_CONTEXT.lock().pending_drivers[0].image_handle = None;
+
+            assert_eq!(core_trust(handle, &file_name), Ok(()));
+            {
+                let dispatcher = DISPATCHER_CONTEXT.lock();
+                let driver = dispatcher.pending_drivers.iter().find(|d| d.matches(handle, &file_name)).unwrap();
+                assert_eq!(driver.state, DriverState::Dependent);
+                assert_eq!(driver.security_status, efi::Status::SUCCESS);
+            }
+
+            // Already trusted.
+            assert_eq!(core_trust(handle, &file_name), Err(EfiError::NotFound));
+        });
+
+        let _dropped_fv = unsafe { Box::from_raw(fv_raw) };
+    }
+
     #[test]
     fn test_fv_authentication() {
         set_logger();
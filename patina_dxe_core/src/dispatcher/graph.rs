@@ -0,0 +1,516 @@
+//! DXE Core Driver Dependency Graph
+//!
+//! Exports the drivers discovered in the firmware volumes with their dependency expressions and resolution status as
+//! a graph, rendered as JSON or as DOT for Graphviz. Drivers point to the protocols their expression pushes, marked
+//! as installed or missing, and to the drivers named by a BEFORE or AFTER expression. It shows why the dispatch
+//! stalled: the missing protocols no dispatched driver installed, and the drivers scheduled relative to each other in
+//! a cycle.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::{self, Write};
+
+use patina_internal_depex::Opcode;
+use r_efi::efi;
+
+use super::{ALL_ARCH_DEPEX, DriverOutcome};
+
+/// The resolution status of a driver in the [DependencyGraph].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DriverResolution {
+    /// The driver was dispatched, with the last outcome recorded for it.
+    Dispatched(DriverOutcome),
+    /// The driver waits for its dependency expression to be satisfied.
+    Dependent,
+    /// The driver waits for the driver named by its BEFORE or AFTER expression to be scheduled.
+    Associated,
+    /// The driver is scheduled on request, and was not requested.
+    Unrequested,
+    /// The driver was deferred by the Security Architectural Protocol, and was not trusted since.
+    Untrusted,
+}
+
+impl DriverResolution {
+    /// Returns the name of the status in the exported graph.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Dispatched(DriverOutcome::Started(status)) if status.is_error() => "failed",
+            Self::Dispatched(DriverOutcome::Started(_)) => "started",
+            Self::Dispatched(DriverOutcome::LoadFailed(_)) => "load_failed",
+            Self::Dispatched(DriverOutcome::Deferred) => "deferred",
+            Self::Dispatched(DriverOutcome::Rejected(_)) => "rejected",
+            Self::Dispatched(DriverOutcome::Skipped(_)) => "skipped",
+            Self::Dispatched(DriverOutcome::Blocked(_)) => "blocked",
+            Self::Dispatched(DriverOutcome::NotDispatched) => "not_dispatched",
+            Self::Dependent => "dependent",
+            Self::Associated => "associated",
+            Self::Unrequested => "unrequested",
+            Self::Untrusted => "untrusted",
+        }
+    }
+
+    /// Returns whether the driver is still waiting to be dispatched.
+    pub fn is_pending(&self) -> bool {
+        !matches!(self, Self::Dispatched(_))
+    }
+}
+
+/// An edge of the [DependencyGraph], from a driver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dependency {
+    /// The dependency expression of the driver pushes the protocol.
+    Protocol {
+        /// The GUID of the protocol.
+        guid: efi::Guid,
+        /// Whether the protocol is installed in the protocol database.
+        installed: bool,
+    },
+    /// The driver is scheduled before the driver with the file name.
+    Before(efi::Guid),
+    /// The driver is scheduled after the driver with the file name.
+    After(efi::Guid),
+}
+
+/// A driver of the [DependencyGraph].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DriverNode {
+    /// The file name of the driver.
+    pub file_name: efi::Guid,
+    /// The device path of the driver file, e.g. `Fv(...)/FvFile(...)`, empty if it was already dispatched.
+    pub path: String,
+    /// The resolution status of the driver.
+    pub resolution: DriverResolution,
+    /// The dependency expression of the driver in infix notation, `None` if the driver has none and depends on all the
+    /// architectural protocols, or was already dispatched.
+    pub depex: Option<String>,
+    /// The protocols and drivers the driver depends on.
+    pub dependencies: Vec<Dependency>,
+}
+
+impl DriverNode {
+    /// Creates the node of a driver waiting to be dispatched, with the dependencies of `depex` resolved against the
+    /// `installed` protocols. A driver without a dependency expression depends on all the architectural protocols.
+    pub(super) fn pending(
+        file_name: efi::Guid,
+        path: String,
+        resolution: DriverResolution,
+        depex: Option<&[Opcode]>,
+        installed: &[efi::Guid],
+    ) -> Self {
+        let mut dependencies = Vec::new();
+        for opcode in depex.unwrap_or(ALL_ARCH_DEPEX) {
+            let dependency = match (opcode, opcode.guid()) {
+                (Opcode::Before(_), Some(guid)) => Dependency::Before(guid),
+                (Opcode::After(_), Some(guid)) => Dependency::After(guid),
+                (Opcode::Push(..), Some(guid)) => Dependency::Protocol { guid, installed: installed.contains(&guid) },
+                _ => continue,
+            };
+            if !dependencies.contains(&dependency) {
+                dependencies.push(dependency);
+            }
+        }
+        Self { file_name, path, resolution, depex: depex.map(depex_text), dependencies }
+    }
+
+    /// Creates the node of a driver already dispatched.
+    pub(super) fn dispatched(file_name: efi::Guid, outcome: DriverOutcome) -> Self {
+        let resolution = DriverResolution::Dispatched(outcome);
+        Self { file_name, path: String::new(), resolution, depex: None, dependencies: Vec::new() }
+    }
+
+    /// Returns the driver named by the BEFORE or AFTER expression of the driver.
+    fn associated(&self) -> Option<efi::Guid> {
+        self.dependencies.iter().find_map(|dependency| match dependency {
+            Dependency::Before(guid) | Dependency::After(guid) => Some(*guid),
+            Dependency::Protocol { .. } => None,
+        })
+    }
+}
+
+/// The drivers discovered in the firmware volumes, with their dependencies.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DependencyGraph {
+    drivers: Vec<DriverNode>,
+}
+
+impl DependencyGraph {
+    pub(super) fn new(drivers: Vec<DriverNode>) -> Self {
+        Self { drivers }
+    }
+
+    /// Returns the drivers of the graph, the drivers waiting to be dispatched first.
+    pub fn drivers(&self) -> &[DriverNode] {
+        &self.drivers
+    }
+
+    /// Returns the protocols the drivers waiting to be dispatched depend on that are not installed.
+    pub fn missing_protocols(&self) -> Vec<efi::Guid> {
+        let mut missing = Vec::<efi::Guid>::new();
+        for driver in self.drivers.iter().filter(|driver| driver.resolution.is_pending()) {
+            for dependency in &driver.dependencies {
+                if let Dependency::Protocol { guid, installed: false } = dependency
+                    && !missing.contains(guid)
+                {
+                    missing.push(*guid);
+                }
+            }
+        }
+        missing.sort_by_key(|guid| *guid.as_bytes());
+        missing
+    }
+
+    /// Returns the cycles of drivers scheduled before or after each other, which can never be dispatched.
+    ///
+    /// Each cycle starts with the driver with the lowest file name.
+    pub fn cycles(&self) -> Vec<Vec<efi::Guid>> {
+        let waiting_on = |file_name: &efi::Guid| {
+            self.drivers
+                .iter()
+                .find(|driver| driver.file_name == *file_name && driver.resolution == DriverResolution::Associated)
+                .and_then(DriverNode::associated)
+        };
+
+        let mut cycles = Vec::new();
+        for driver in self.drivers.iter().filter(|driver| driver.resolution == DriverResolution::Associated) {
+            let mut cycle = Vec::from([driver.file_name]);
+            let mut next = waiting_on(&driver.file_name);
+            while let Some(file_name) = next {
+                if file_name == driver.file_name {
+                    if cycle.iter().all(|member| member.as_bytes() >= driver.file_name.as_bytes()) {
+                        cycles.push(cycle);
+                    }
+                    break;
+                }
+                if cycle.contains(&file_name) {
+                    // The chain loops back to another driver, the cycle is reported from that driver.
+                    break;
+                }
+                cycle.push(file_name);
+                next = waiting_on(&file_name);
+            }
+        }
+        cycles
+    }
+
+    /// Renders the graph as a JSON object, with the `drivers`, `missing_protocols` and `cycles` members.
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        // Writing to a string does not fail.
+        let _ = self.write_json(&mut json);
+        json
+    }
+
+    fn write_json(&self, out: &mut impl Write) -> fmt::Result {
+        out.write_str("{\"drivers\":[")?;
+        for (index, driver) in self.drivers.iter().enumerate() {
+            if index != 0 {
+                out.write_char(',')?;
+            }
+            write!(out, "{{\"file_name\":\"{}\",\"path\":\"", GuidText(&driver.file_name))?;
+            write_escaped(out, &driver.path)?;
+            write!(out, "\",\"status\":\"{}\",\"depex\":", driver.resolution.name())?;
+            match &driver.depex {
+                Some(depex) => write!(out, "\"{depex}\"")?,
+                None => out.write_str("null")?,
+            }
+            out.write_str(",\"dependencies\":[")?;
+            for (index, dependency) in driver.dependencies.iter().enumerate() {
+                if index != 0 {
+                    out.write_char(',')?;
+                }
+                match dependency {
+                    Dependency::Protocol { guid, installed } => {
+                        write!(out, "{{\"protocol\":\"{}\",\"installed\":{installed}}}", GuidText(guid))?
+                    }
+                    Dependency::Before(guid) => write!(out, "{{\"before\":\"{}\"}}", GuidText(guid))?,
+                    Dependency::After(guid) => write!(out, "{{\"after\":\"{}\"}}", GuidText(guid))?,
+                }
+            }
+            out.write_str("]}")?;
+        }
+        out.write_str("],\"missing_protocols\":[")?;
+        for (index, guid) in self.missing_protocols().iter().enumerate() {
+            write!(out, "{}\"{}\"", if index == 0 { "" } else { "," }, GuidText(guid))?;
+        }
+        out.write_str("],\"cycles\":[")?;
+        for (index, cycle) in self.cycles().iter().enumerate() {
+            out.write_str(if index == 0 { "[" } else { ",[" })?;
+            for (index, guid) in cycle.iter().enumerate() {
+                write!(out, "{}\"{}\"", if index == 0 { "" } else { "," }, GuidText(guid))?;
+            }
+            out.write_char(']')?;
+        }
+        out.write_str("]}")
+    }
+
+    /// Renders the graph in the DOT language of Graphviz.
+    ///
+    /// Drivers are boxes labelled with their file name and status, protocols are ellipses, dashed and red when
+    /// missing. Edges to BEFORE or AFTER drivers are bold, and red when they are part of a cycle.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        // Writing to a string does not fail.
+        let _ = self.write_dot(&mut dot);
+        dot
+    }
+
+    fn write_dot(&self, out: &mut impl Write) -> fmt::Result {
+        let cycles = self.cycles();
+        let in_cycle = |file_name: &efi::Guid| cycles.iter().any(|cycle| cycle.contains(file_name));
+
+        out.write_str("digraph dependencies {\n  rankdir=LR;\n  node [shape=box];\n")?;
+        for driver in &self.drivers {
+            let color = match driver.resolution {
+                DriverResolution::Dispatched(DriverOutcome::Started(status)) if !status.is_error() => "darkgreen",
+                DriverResolution::Dispatched(outcome) if outcome.is_failure() => "red",
+                DriverResolution::Dispatched(_) => "gray",
+                _ => "orange",
+            };
+            let name = GuidText(&driver.file_name);
+            write!(out, "  \"{name}\" [label=\"{name}\\n{}\", color={color}, tooltip=\"", driver.resolution.name())?;
+            write_escaped(out, driver.depex.as_deref().unwrap_or(&driver.path))?;
+            out.write_str("\"];\n")?;
+        }
+
+        let mut protocols = Vec::<(efi::Guid, bool)>::new();
+        for driver in &self.drivers {
+            for dependency in &driver.dependencies {
+                if let Dependency::Protocol { guid, installed } = *dependency
+                    && !protocols.iter().any(|(protocol, _)| *protocol == guid)
+                {
+                    protocols.push((guid, installed));
+                }
+            }
+        }
+        for (guid, installed) in &protocols {
+            let style = if *installed { "solid" } else { "dashed, color=red" };
+            writeln!(out, "  \"protocol {0}\" [shape=ellipse, label=\"{0}\", style={style}];", GuidText(guid))?;
+        }
+
+        for driver in &self.drivers {
+            let name = GuidText(&driver.file_name);
+            for dependency in &driver.dependencies {
+                match dependency {
+                    Dependency::Protocol { guid, installed } => {
+                        let color = if *installed { "black" } else { "red" };
+                        writeln!(out, "  \"{name}\" -> \"protocol {}\" [color={color}];", GuidText(guid))?
+                    }
+                    Dependency::Before(guid) | Dependency::After(guid) => {
+                        let label = if matches!(dependency, Dependency::Before(_)) { "BEFORE" } else { "AFTER" };
+                        let color = if in_cycle(&driver.file_name) && in_cycle(guid) { "red" } else { "black" };
+                        writeln!(
+                            out,
+                            "  \"{name}\" -> \"{}\" [label=\"{label}\", style=bold, color={color}];",
+                            GuidText(guid)
+                        )?
+                    }
+                }
+            }
+        }
+        out.write_str("}\n")
+    }
+}
+
+/// Formats a GUID in the registry format, e.g. `8D59D32B-C655-4AE9-9B15-F25904992A43`.
+struct GuidText<'a>(&'a efi::Guid);
+
+impl fmt::Display for GuidText<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (time_low, time_mid, time_hi, clk_seq_hi, clk_seq_low, node) = self.0.as_fields();
+        write!(f, "{time_low:08X}-{time_mid:04X}-{time_hi:04X}-{clk_seq_hi:02X}{clk_seq_low:02X}-")?;
+        node.iter().try_for_each(|byte| write!(f, "{byte:02X}"))
+    }
+}
+
+/// Writes `text` escaping the characters that end or escape a JSON or DOT string.
+fn write_escaped(out: &mut impl Write, text: &str) -> fmt::Result {
+    for c in text.chars() {
+        match c {
+            '"' | '\\' => write!(out, "\\{c}")?,
+            '\n' => out.write_str("\\n")?,
+            _ => out.write_char(c)?,
+        }
+    }
+    Ok(())
+}
+
+/// Renders a dependency expression in infix notation, e.g. `(A AND NOT B)`.
+fn depex_text(opcodes: &[Opcode]) -> String {
+    let mut stack = Vec::<String>::new();
+    let mut prefix = "";
+    for opcode in opcodes {
+        let operand = match opcode {
+            Opcode::Before(_) | Opcode::After(_) => {
+                let keyword = if matches!(opcode, Opcode::Before(_)) { "BEFORE" } else { "AFTER" };
+                return opcode
+                    .guid()
+                    .map_or_else(|| "<malformed>".to_string(), |guid| format!("{keyword} {}", GuidText(&guid)));
+            }
+            Opcode::Sor => {
+                prefix = "SOR ";
+                continue;
+            }
+            Opcode::Push(..) => match opcode.guid() {
+                Some(guid) => GuidText(&guid).to_string(),
+                None => return "<malformed>".to_string(),
+            },
+            Opcode::True => "TRUE".to_string(),
+            Opcode::False => "FALSE".to_string(),
+            Opcode::Not => match stack.pop() {
+                Some(operand) => format!("NOT {operand}"),
+                None => return "<malformed>".to_string(),
+            },
+            Opcode::And | Opcode::Or => match (stack.pop(), stack.pop()) {
+                (Some(right), Some(left)) => {
+                    format!("({left} {} {right})", if *opcode == Opcode::And { "AND" } else { "OR" })
+                }
+                _ => return "<malformed>".to_string(),
+            },
+            Opcode::End => break,
+            Opcode::Unknown | Opcode::Malformed { .. } => return "<malformed>".to_string(),
+        };
+        stack.push(operand);
+    }
+    match stack.as_slice() {
+        [expression] => format!("{prefix}{expression}"),
+        [] if !prefix.is_empty() => "SOR".to_string(),
+        _ => "<malformed>".to_string(),
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use uuid::Uuid;
+
+    const DRIVER_A: efi::Guid = efi::Guid::from_fields(0xa, 0, 0, 0, 0, &[0; 6]);
+    const DRIVER_B: efi::Guid = efi::Guid::from_fields(0xb, 0, 0, 0, 0, &[0; 6]);
+    const DRIVER_C: efi::Guid = efi::Guid::from_fields(0xc, 0, 0, 0, 0, &[0; 6]);
+    const PROTOCOL_1: efi::Guid = efi::Guid::from_fields(1, 0, 0, 0, 0, &[0; 6]);
+    const PROTOCOL_2: efi::Guid = efi::Guid::from_fields(2, 0, 0, 0, 0, &[0; 6]);
+
+    fn uuid(guid: &efi::Guid) -> Uuid {
+        Uuid::from_bytes_le(*guid.as_bytes())
+    }
+
+    fn pending(file_name: efi::Guid, resolution: DriverResolution, depex: &[Opcode]) -> DriverNode {
+        DriverNode::pending(file_name, "FvFile".to_string(), resolution, Some(depex), &[PROTOCOL_1])
+    }
+
+    #[test]
+    fn depex_should_be_rendered_in_infix_notation() {
+        let depex = [
+            Opcode::Sor,
+            Opcode::Push(uuid(&PROTOCOL_1), false),
+            Opcode::Push(uuid(&PROTOCOL_2), false),
+            Opcode::Not,
+            Opcode::And,
+            Opcode::True,
+            Opcode::Or,
+            Opcode::End,
+        ];
+        assert_eq!(
+            depex_text(&depex),
+            "SOR ((00000001-0000-0000-0000-000000000000 AND NOT 00000002-0000-0000-0000-000000000000) OR TRUE)"
+        );
+        assert_eq!(depex_text(&[Opcode::After(uuid(&DRIVER_A))]), "AFTER 0000000A-0000-0000-0000-000000000000");
+        assert_eq!(depex_text(&[Opcode::And, Opcode::End]), "<malformed>");
+        assert_eq!(depex_text(&[Opcode::True, Opcode::True, Opcode::End]), "<malformed>");
+    }
+
+    #[test]
+    fn dependencies_should_be_resolved_against_the_installed_protocols() {
+        let depex = [
+            Opcode::Push(uuid(&PROTOCOL_1), false),
+            Opcode::Push(uuid(&PROTOCOL_2), false),
+            Opcode::And,
+            Opcode::Push(uuid(&PROTOCOL_2), false),
+            Opcode::And,
+            Opcode::End,
+        ];
+        let driver = pending(DRIVER_A, DriverResolution::Dependent, &depex);
+        assert_eq!(
+            driver.dependencies,
+            [
+                Dependency::Protocol { guid: PROTOCOL_1, installed: true },
+                Dependency::Protocol { guid: PROTOCOL_2, installed: false }
+            ]
+        );
+
+        let graph = DependencyGraph::new(vec![driver]);
+        assert_eq!(graph.missing_protocols(), [PROTOCOL_2]);
+        assert!(graph.cycles().is_empty());
+
+        // A driver without a dependency expression depends on all the architectural protocols.
+        let driver = DriverNode::pending(DRIVER_B, String::new(), DriverResolution::Dependent, None, &[]);
+        assert_eq!(driver.depex, None);
+        assert_eq!(driver.dependencies.len(), ALL_ARCH_DEPEX.len());
+    }
+
+    #[test]
+    fn cycles_should_be_reported_once() {
+        let graph = DependencyGraph::new(vec![
+            pending(DRIVER_B, DriverResolution::Associated, &[Opcode::After(uuid(&DRIVER_C))]),
+            pending(DRIVER_C, DriverResolution::Associated, &[Opcode::Before(uuid(&DRIVER_A))]),
+            pending(DRIVER_A, DriverResolution::Associated, &[Opcode::After(uuid(&DRIVER_B))]),
+        ]);
+        assert_eq!(graph.cycles(), [vec![DRIVER_A, DRIVER_B, DRIVER_C]]);
+
+        // A chain ending on a driver not waiting on another one is not a cycle.
+        let graph = DependencyGraph::new(vec![
+            pending(DRIVER_A, DriverResolution::Associated, &[Opcode::After(uuid(&DRIVER_B))]),
+            pending(DRIVER_B, DriverResolution::Dependent, &[Opcode::Push(uuid(&PROTOCOL_2), false)]),
+        ]);
+        assert!(graph.cycles().is_empty());
+    }
+
+    #[test]
+    fn graph_should_be_rendered_as_json() {
+        let graph = DependencyGraph::new(vec![
+            pending(DRIVER_A, DriverResolution::Associated, &[Opcode::After(uuid(&DRIVER_A))]),
+            DriverNode::dispatched(DRIVER_B, DriverOutcome::Started(efi::Status::SUCCESS)),
+        ]);
+        assert_eq!(
+            graph.to_json(),
+            concat!(
+                "{\"drivers\":[",
+                "{\"file_name\":\"0000000A-0000-0000-0000-000000000000\",\"path\":\"FvFile\",\"status\":\"associated\",",
+                "\"depex\":\"AFTER 0000000A-0000-0000-0000-000000000000\",",
+                "\"dependencies\":[{\"after\":\"0000000A-0000-0000-0000-000000000000\"}]},",
+                "{\"file_name\":\"0000000B-0000-0000-0000-000000000000\",\"path\":\"\",\"status\":\"started\",",
+                "\"depex\":null,\"dependencies\":[]}",
+                "],\"missing_protocols\":[],",
+                "\"cycles\":[[\"0000000A-0000-0000-0000-000000000000\"]]}"
+            )
+        );
+    }
+
+    #[test]
+    fn graph_should_be_rendered_as_dot() {
+        let depex = [Opcode::Push(uuid(&PROTOCOL_1), false), Opcode::Push(uuid(&PROTOCOL_2), false), Opcode::Or];
+        let mut driver = pending(DRIVER_A, DriverResolution::Dependent, &depex);
+        driver.path = "Fv(\"x\")".to_string();
+        driver.depex = None;
+        let dot = DependencyGraph::new(vec![driver]).to_dot();
+
+        assert!(dot.starts_with("digraph dependencies {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert!(dot.contains("tooltip=\"Fv(\\\"x\\\")\""));
+        assert!(dot.contains("\"protocol 00000002-0000-0000-0000-000000000000\" [shape=ellipse, label=\"00000002-0000-0000-0000-000000000000\", style=dashed, color=red];"));
+        assert!(dot.contains("\"0000000A-0000-0000-0000-000000000000\" -> \"protocol 00000001-0000-0000-0000-000000000000\" [color=black];"));
+        assert!(dot.contains(
+            "\"0000000A-0000-0000-0000-000000000000\" -> \"protocol 00000002-0000-0000-0000-000000000000\" [color=red];"
+        ));
+    }
+}
@@ -0,0 +1,351 @@
+//! DXE Core Guarded Driver Dispatch
+//!
+//! Guards the entry points of the drivers dispatched from firmware volumes when the platform provides a
+//! [GuardedDispatchPolicy], so that a driver that hangs or faults does not hang the whole boot:
+//! - A timer event terminates the entry point of a driver that runs below TPL_CALLBACK for longer than the time
+//!   budget. Control returns to the dispatcher, which marks the driver failed and continues with the other drivers.
+//!   Since the notification only runs below TPL_CALLBACK, the driver cannot be interrupted while it holds a core lock.
+//! - The watchdog timer is armed with twice the time budget, so that a driver that hangs at a raised TPL or faults
+//!   resets the system rather than hanging it.
+//! - The driver being started is recorded with the [DriverFailureStore] service, if the platform provides one, so
+//!   that on the next boot a driver that took the system down is counted as failed. Drivers that failed in
+//!   `failure_threshold` consecutive boots are no longer dispatched.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::vec::Vec;
+use core::{
+    ffi::c_void,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use mu_rust_helpers::guid::guid_fmt;
+use patina::component::service::Service;
+use r_efi::efi;
+
+use crate::{
+    GuardedDispatchPolicy,
+    events::{self, EVENT_DB},
+    image::{core_start_image, core_terminate_image},
+    misc_boot_services::set_watchdog_timer,
+    tpl_lock::TplMutex,
+};
+
+/// A persistent store for the failures of the drivers dispatched with guarded dispatch, used to block the drivers
+/// that repeatedly fail across boots.
+///
+/// The log is saved before and after the entry point of each guarded driver runs, so the store should be cheap to
+/// write, e.g. a scratch register or a memory region preserved across warm resets.
+pub trait DriverFailureStore {
+    /// Returns the log saved during the previous boot, or an empty log if there is none.
+    fn load(&self) -> DriverFailureLog;
+    /// Saves `log`, replacing the previous one.
+    fn save(&self, log: &DriverFailureLog) -> patina::error::Result<()>;
+}
+
+/// The failures of the drivers dispatched with guarded dispatch, persisted across boots by a [DriverFailureStore].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DriverFailureLog {
+    /// The file name of the driver whose entry point was running when the log was saved. If it is still set when the
+    /// log is loaded on the next boot, the driver hung or faulted and the system was reset.
+    pub running: Option<efi::Guid>,
+    /// The file names of the drivers that failed, with the number of consecutive boots they failed in.
+    pub failures: Vec<(efi::Guid, u32)>,
+}
+
+impl DriverFailureLog {
+    /// Returns the number of consecutive boots the driver `file_name` failed in.
+    fn failures(&self, file_name: &efi::Guid) -> u32 {
+        self.failures.iter().find(|(name, _)| name == file_name).map_or(0, |(_, failures)| *failures)
+    }
+
+    /// Records a failure of the driver `file_name`, returning the number of consecutive boots it failed in.
+    fn record_failure(&mut self, file_name: efi::Guid) -> u32 {
+        match self.failures.iter_mut().find(|(name, _)| *name == file_name) {
+            Some((_, failures)) => {
+                *failures = failures.saturating_add(1);
+                *failures
+            }
+            None => {
+                self.failures.push((file_name, 1));
+                1
+            }
+        }
+    }
+
+    /// Clears the failures of the driver `file_name` after it started successfully.
+    fn clear_failures(&mut self, file_name: &efi::Guid) {
+        self.failures.retain(|(name, _)| name != file_name);
+    }
+}
+
+struct DispatchGuard {
+    policy: GuardedDispatchPolicy,
+    store: Option<Service<dyn DriverFailureStore>>,
+    log: DriverFailureLog,
+}
+
+unsafe impl Send for DispatchGuard {}
+
+static DISPATCH_GUARD: TplMutex<Option<DispatchGuard>> = TplMutex::new(efi::TPL_NOTIFY, None, "Dispatch Guard");
+
+/// Set by the time budget notification while it terminates the entry point of the running driver.
+static BUDGET_EXCEEDED: AtomicBool = AtomicBool::new(false);
+
+// Saves the log with the store, if any. Must not be called with the guard locked, as the store may use services that
+// are restricted to TPL_CALLBACK.
+fn save(store: Option<Service<dyn DriverFailureStore>>, log: &DriverFailureLog) {
+    if let Some(store) = store
+        && let Err(err) = store.save(log)
+    {
+        log::error!("Failed to save the driver failure log: {err:?}");
+    }
+}
+
+/// Enables guarded dispatch with `policy`, loading the failures of the previous boots from `store`.
+pub fn enable(policy: GuardedDispatchPolicy, store: Option<Service<dyn DriverFailureStore>>) {
+    let mut log = store.as_ref().map(|store| store.load()).unwrap_or_default();
+    if let Some(file_name) = log.running.take() {
+        let failures = log.record_failure(file_name);
+        log::error!(
+            "Driver {:?} did not return from its entry point during the previous boot ({failures} consecutive boots).",
+            guid_fmt!(file_name)
+        );
+        save(store.clone(), &log);
+    }
+    *DISPATCH_GUARD.lock() = Some(DispatchGuard { policy, store, log });
+}
+
+/// Returns the number of consecutive boots the driver `file_name` failed in, if it is blocked from dispatch.
+pub fn blocked_failures(file_name: &efi::Guid) -> Option<u32> {
+    let guard = DISPATCH_GUARD.lock();
+    let guard = guard.as_ref()?;
+    let failures = guard.log.failures(file_name);
+    (guard.policy.failure_threshold != 0 && failures >= guard.policy.failure_threshold).then_some(failures)
+}
+
+// Records that the driver `file_name` is about to start. Returns the time budget of its entry point, or None if
+// guarded dispatch is disabled.
+fn driver_starting(file_name: efi::Guid) -> Option<GuardedDispatchPolicy> {
+    let (policy, store, log) = {
+        let mut guard = DISPATCH_GUARD.lock();
+        let guard = guard.as_mut()?;
+        guard.log.running = Some(file_name);
+        (guard.policy, guard.store.clone(), guard.log.clone())
+    };
+    save(store, &log);
+    Some(policy)
+}
+
+// Records the outcome of the entry point of the driver `file_name`.
+fn driver_finished(file_name: efi::Guid, budget_exceeded: bool) {
+    let (store, log) = {
+        let mut guard = DISPATCH_GUARD.lock();
+        let Some(guard) = guard.as_mut() else {
+            return;
+        };
+        guard.log.running = None;
+        if budget_exceeded {
+            let failures = guard.log.record_failure(file_name);
+            log::error!(
+                "Driver {:?} was terminated after exceeding its time budget ({failures} consecutive boots).",
+                guid_fmt!(file_name)
+            );
+        } else {
+            guard.log.clear_failures(&file_name);
+        }
+        (guard.store.clone(), guard.log.clone())
+    };
+    save(store, &log);
+}
+
+// Terminates the entry point of the image passed as context, which exceeded its time budget.
+extern "efiapi" fn budget_exceeded_notify(_event: efi::Event, context: *mut c_void) {
+    let image_handle = context as efi::Handle;
+    log::error!("Image {image_handle:#x?} exceeded the time budget of its entry point, terminating it.");
+    BUDGET_EXCEEDED.store(true, Ordering::SeqCst);
+    // Only returns if the image is not the running image, e.g. if it started another image that is still running.
+    let status = core_terminate_image(image_handle, efi::Status::TIMEOUT);
+    BUDGET_EXCEEDED.store(false, Ordering::SeqCst);
+    log::error!("Unable to terminate image {image_handle:#x?}: {status:#x?}. Relying on the watchdog timer.");
+}
+
+// Arms the time budget timer and the watchdog timer for the entry point of `image_handle`.
+fn arm(policy: &GuardedDispatchPolicy, image_handle: efi::Handle) -> Option<efi::Event> {
+    let budget = u64::try_from(policy.time_budget.as_nanos() / 100).unwrap_or(u64::MAX);
+    let event = match EVENT_DB.create_event(
+        efi::EVT_TIMER | efi::EVT_NOTIFY_SIGNAL,
+        efi::TPL_CALLBACK,
+        Some(budget_exceeded_notify),
+        Some(image_handle),
+        None,
+    ) {
+        Ok(event) => event,
+        Err(err) => {
+            log::error!("Failed to create the time budget event of image {image_handle:#x?}: {err:?}");
+            return None;
+        }
+    };
+    let status = events::set_timer(event, efi::TIMER_RELATIVE, budget);
+    if status.is_error() {
+        log::error!("Failed to arm the time budget timer of image {image_handle:#x?}: {status:#x?}");
+    }
+
+    // The watchdog timer is not available until its architectural protocol is installed.
+    let watchdog_timeout = policy.time_budget.as_secs().saturating_add(1).saturating_mul(2);
+    let _status = set_watchdog_timer(watchdog_timeout as usize, 0, 0, core::ptr::null_mut());
+    Some(event)
+}
+
+fn disarm(event: Option<efi::Event>) {
+    let _status = set_watchdog_timer(0, 0, 0, core::ptr::null_mut());
+    if let Some(event) = event
+        && let Err(err) = EVENT_DB.close_event(event)
+    {
+        log::error!("Failed to close the time budget event: {err:?}");
+    }
+}
+
+/// Starts the driver `file_name` loaded as `image_handle`, guarding its entry point if guarded dispatch is enabled.
+pub fn start_driver(file_name: efi::Guid, image_handle: efi::Handle) -> Result<(), efi::Status> {
+    let Some(policy) = driver_starting(file_name) else {
+        return core_start_image(image_handle);
+    };
+
+    let tpl = events::current_tpl();
+    let event = arm(&policy, image_handle);
+    let result = core_start_image(image_handle);
+    let budget_exceeded = BUDGET_EXCEEDED.swap(false, Ordering::SeqCst);
+    if budget_exceeded {
+        // The entry point was terminated from the budget notification, running on the abandoned stack of the image.
+        events::resume_after_image_termination(tpl);
+    }
+    disarm(event);
+
+    driver_finished(file_name, budget_exceeded);
+    result
+}
+
+#[cfg(test)]
+fn reset() {
+    *DISPATCH_GUARD.lock() = None;
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use alloc::boxed::Box;
+    use core::time::Duration;
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::test_support;
+
+    const DRIVER_A: efi::Guid = efi::Guid::from_fields(0xa, 0, 0, 0, 0, &[0; 6]);
+    const DRIVER_B: efi::Guid = efi::Guid::from_fields(0xb, 0, 0, 0, 0, &[0; 6]);
+
+    #[derive(Default)]
+    struct MockStore {
+        log: Mutex<DriverFailureLog>,
+        saves: Mutex<Vec<DriverFailureLog>>,
+    }
+
+    impl DriverFailureStore for &'static MockStore {
+        fn load(&self) -> DriverFailureLog {
+            self.log.lock().unwrap().clone()
+        }
+
+        fn save(&self, log: &DriverFailureLog) -> patina::error::Result<()> {
+            *self.log.lock().unwrap() = log.clone();
+            self.saves.lock().unwrap().push(log.clone());
+            Ok(())
+        }
+    }
+
+    fn with_store<F: Fn(&'static MockStore, Service<dyn DriverFailureStore>) + std::panic::RefUnwindSafe>(
+        log: DriverFailureLog,
+        f: F,
+    ) {
+        test_support::with_global_lock(|| {
+            let store: &'static MockStore =
+                Box::leak(Box::new(MockStore { log: Mutex::new(log.clone()), ..Default::default() }));
+            f(store, Service::mock(Box::new(store)));
+            reset();
+        })
+        .unwrap();
+    }
+
+    fn policy() -> GuardedDispatchPolicy {
+        GuardedDispatchPolicy { time_budget: Duration::from_secs(5), failure_threshold: 3 }
+    }
+
+    #[test]
+    fn test_driver_running_at_reset_is_counted_as_failed() {
+        let log = DriverFailureLog { running: Some(DRIVER_A), failures: vec![(DRIVER_A, 2), (DRIVER_B, 1)] };
+        with_store(log, |store, service| {
+            enable(policy(), Some(service));
+
+            assert_eq!(
+                *store.log.lock().unwrap(),
+                DriverFailureLog { running: None, failures: vec![(DRIVER_A, 3), (DRIVER_B, 1)] }
+            );
+            assert_eq!(blocked_failures(&DRIVER_A), Some(3));
+            assert_eq!(blocked_failures(&DRIVER_B), None);
+        });
+    }
+
+    #[test]
+    fn test_zero_failure_threshold_never_blocks_drivers() {
+        let log = DriverFailureLog { running: None, failures: vec![(DRIVER_A, 10)] };
+        with_store(log, |store, service| {
+            enable(GuardedDispatchPolicy { failure_threshold: 0, ..policy() }, Some(service));
+
+            assert_eq!(blocked_failures(&DRIVER_A), None);
+            assert!(store.saves.lock().unwrap().is_empty());
+        });
+    }
+
+    #[test]
+    fn test_driver_outcomes_are_saved() {
+        let log = DriverFailureLog { running: None, failures: vec![(DRIVER_A, 1), (DRIVER_B, 1)] };
+        with_store(log, |store, service| {
+            enable(policy(), Some(service));
+
+            assert_eq!(driver_starting(DRIVER_A), Some(policy()));
+            assert_eq!(store.log.lock().unwrap().running, Some(DRIVER_A));
+            driver_finished(DRIVER_A, false);
+
+            driver_starting(DRIVER_B);
+            driver_finished(DRIVER_B, true);
+
+            assert_eq!(*store.log.lock().unwrap(), DriverFailureLog { running: None, failures: vec![(DRIVER_B, 2)] });
+            assert_eq!(store.saves.lock().unwrap().len(), 4);
+        });
+    }
+
+    #[test]
+    fn test_unguarded_drivers_are_not_recorded() {
+        test_support::with_global_lock(|| {
+            reset();
+            assert_eq!(driver_starting(DRIVER_A), None);
+            driver_finished(DRIVER_A, true);
+            assert_eq!(blocked_failures(&DRIVER_A), None);
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_budget_notify_for_image_that_is_not_running() {
+        test_support::with_global_lock(|| {
+            budget_exceeded_notify(core::ptr::null_mut(), 0x1234 as *mut c_void);
+            assert!(!BUDGET_EXCEEDED.load(Ordering::SeqCst));
+        })
+        .unwrap();
+    }
+}
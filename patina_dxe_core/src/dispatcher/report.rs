@@ -0,0 +1,290 @@
+//! DXE Core Dispatch Report
+//!
+//! Records the outcome of each driver discovered in the firmware volumes, so that the platform can decide whether
+//! the drivers that failed are fatal with a [DispatchReportHandler] service, rather than the core aborting the boot.
+//! The failures are also reported as error status codes carrying [DRIVER_DISPATCH_FAILURE] data, for telemetry.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use mu_rust_helpers::guid::guid_fmt;
+use patina::{
+    guids::{DRIVER_DISPATCH_FAILURE, DXE_CORE},
+    performance::timer::{ArchPerfTimer, PerfTimer},
+    uefi_protocol::status_code::StatusCodeRuntimeProtocol,
+};
+use patina_pi::{
+    protocols::status_code,
+    status_code::{
+        EFI_ERROR_CODE, EFI_ERROR_MAJOR, EFI_SOFTWARE_DXE_CORE, EFI_SW_DXE_CORE_EC_IMAGE_LOAD_FAILURE,
+        EFI_SW_EC_START_ERROR,
+    },
+};
+use r_efi::efi;
+
+use crate::protocols::PROTOCOL_DB;
+
+/// Inspects the dispatch report once the core has dispatched all drivers, before handing off to BDS.
+pub trait DispatchReportHandler {
+    /// Handles `report`. Returning an error aborts the boot: [Core::start](crate::Core::start) returns the error
+    /// instead of handing off to BDS.
+    fn handle_dispatch_report(&self, report: &DispatchReport) -> patina::error::Result<()>;
+}
+
+/// The outcome of a driver discovered in a firmware volume.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DriverOutcome {
+    /// The entry point of the driver returned the status.
+    Started(efi::Status),
+    /// The image of the driver could not be loaded, with the status.
+    LoadFailed(efi::Status),
+    /// The driver was deferred by the Security Architectural Protocol, until it is trusted.
+    Deferred,
+    /// The driver was dropped, as the Security Architectural Protocol returned the status.
+    Rejected(efi::Status),
+    /// The driver was skipped by the dispatch policy, for the reason.
+    Skipped(&'static str),
+    /// The driver was blocked by guarded dispatch, as it failed in the number of consecutive boots.
+    Blocked(u32),
+    /// The driver was never scheduled, as its dependency expression was not satisfied, or it was not requested or
+    /// trusted.
+    NotDispatched,
+}
+
+impl DriverOutcome {
+    /// Returns whether the outcome is a failure of the driver.
+    ///
+    /// Drivers skipped or blocked by the platform policies, and drivers whose dependencies were not satisfied, are
+    /// not failures.
+    pub fn is_failure(&self) -> bool {
+        match self {
+            Self::Started(status) => status.is_error(),
+            Self::LoadFailed(_) | Self::Rejected(_) => true,
+            Self::Deferred | Self::Skipped(_) | Self::Blocked(_) | Self::NotDispatched => false,
+        }
+    }
+}
+
+/// The outcome of a driver, with the time spent loading and starting it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DriverDispatchRecord {
+    /// The file name of the driver.
+    pub file_name: efi::Guid,
+    /// The outcome of the driver.
+    pub outcome: DriverOutcome,
+    /// The time spent loading and starting the driver, zero if it was not loaded.
+    pub elapsed: Duration,
+}
+
+/// The outcomes of the drivers discovered in the firmware volumes, in the order they occurred.
+///
+/// A driver may have several records, e.g. when it is deferred and started once trusted.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DispatchReport {
+    records: Vec<DriverDispatchRecord>,
+}
+
+impl DispatchReport {
+    pub(super) const fn new() -> Self {
+        Self { records: Vec::new() }
+    }
+
+    /// Returns the records of the report.
+    pub fn records(&self) -> &[DriverDispatchRecord] {
+        &self.records
+    }
+
+    /// Returns the records of the drivers that failed.
+    pub fn failures(&self) -> impl Iterator<Item = &DriverDispatchRecord> {
+        self.records.iter().filter(|record| record.outcome.is_failure())
+    }
+
+    /// Returns whether a driver failed.
+    pub fn has_failures(&self) -> bool {
+        self.failures().next().is_some()
+    }
+
+    pub(super) fn push(&mut self, record: DriverDispatchRecord) {
+        self.records.push(record);
+    }
+
+    /// Logs a summary of the report, and each failure.
+    pub(crate) fn log(&self) {
+        let started = self.records.iter().filter(|record| matches!(record.outcome, DriverOutcome::Started(_))).count();
+        let failures = self.failures().count();
+        log::info!("Dispatch report: {} records, {started} drivers started, {failures} failures.", self.records.len());
+        for record in self.failures() {
+            log::warn!(
+                "Driver {:?} failed: {:x?} after {:?}.",
+                guid_fmt!(record.file_name),
+                record.outcome,
+                record.elapsed
+            );
+        }
+    }
+}
+
+/// The data of the error status codes reported for the drivers that failed.
+#[repr(C)]
+struct DriverDispatchFailureData {
+    file_name: efi::Guid,
+    status: efi::Status,
+}
+
+/// Reports the failure of `record` as an error status code, if the Status Code Runtime Protocol is installed.
+pub(super) fn report_failure(record: &DriverDispatchRecord) {
+    let (value, status) = match record.outcome {
+        DriverOutcome::Started(status) => (EFI_SOFTWARE_DXE_CORE | EFI_SW_EC_START_ERROR, status),
+        DriverOutcome::LoadFailed(status) | DriverOutcome::Rejected(status) => {
+            (EFI_SOFTWARE_DXE_CORE | EFI_SW_DXE_CORE_EC_IMAGE_LOAD_FAILURE, status)
+        }
+        _ => return,
+    };
+
+    let Ok(status_code_ptr) = PROTOCOL_DB.locate_protocol(status_code::PROTOCOL_GUID) else {
+        log::trace!("Status Code Runtime Protocol not found, driver failure not reported as a status code.");
+        return;
+    };
+    // Safety: the Status Code Runtime Protocol interface matches [StatusCodeRuntimeProtocol].
+    let status_code = unsafe { &*(status_code_ptr as *const StatusCodeRuntimeProtocol) };
+    let data = DriverDispatchFailureData { file_name: record.file_name, status };
+    if let Err(status) = status_code.report_status_code_with_data(
+        EFI_ERROR_CODE | EFI_ERROR_MAJOR,
+        value,
+        0,
+        &DXE_CORE,
+        DRIVER_DISPATCH_FAILURE,
+        data,
+    ) {
+        log::error!("Failed to report driver failure as a status code: {status:?}");
+    }
+}
+
+/// Returns the current value of the performance counter, to measure the time spent on a driver.
+pub(crate) fn timestamp() -> u64 {
+    ArchPerfTimer.cpu_count()
+}
+
+/// Returns the time elapsed since `start`, a value returned by [timestamp].
+pub(crate) fn elapsed_since(start: u64) -> Duration {
+    let timer = ArchPerfTimer;
+    let ticks = timer.cpu_count().saturating_sub(start) as u128;
+    match timer.perf_frequency() as u128 {
+        0 => Duration::ZERO,
+        frequency => Duration::from_nanos((ticks * 1_000_000_000 / frequency) as u64),
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use alloc::boxed::Box;
+    use core::{
+        ffi::c_void,
+        sync::atomic::{AtomicU32, Ordering},
+    };
+    use patina_pi::protocols::status_code::EfiStatusCodeData;
+
+    const DRIVER: efi::Guid = patina::guid!("8B2C4F1A-6D3E-4A5B-9C7D-0E1F2A3B4C5D");
+
+    fn record(outcome: DriverOutcome) -> DriverDispatchRecord {
+        DriverDispatchRecord { file_name: DRIVER, outcome, elapsed: Duration::from_millis(1) }
+    }
+
+    #[test]
+    fn only_driver_errors_should_be_failures() {
+        assert!(!DriverOutcome::Started(efi::Status::SUCCESS).is_failure());
+        assert!(!DriverOutcome::Started(efi::Status::WARN_UNKNOWN_GLYPH).is_failure());
+        assert!(DriverOutcome::Started(efi::Status::UNSUPPORTED).is_failure());
+        assert!(DriverOutcome::LoadFailed(efi::Status::LOAD_ERROR).is_failure());
+        assert!(DriverOutcome::Rejected(efi::Status::ACCESS_DENIED).is_failure());
+        assert!(!DriverOutcome::Deferred.is_failure());
+        assert!(!DriverOutcome::Skipped("it is in the block-list").is_failure());
+        assert!(!DriverOutcome::Blocked(3).is_failure());
+        assert!(!DriverOutcome::NotDispatched.is_failure());
+    }
+
+    #[test]
+    fn report_should_list_failures_in_order() {
+        let mut report = DispatchReport::default();
+        assert!(!report.has_failures());
+
+        report.push(record(DriverOutcome::Started(efi::Status::SUCCESS)));
+        report.push(record(DriverOutcome::LoadFailed(efi::Status::LOAD_ERROR)));
+        report.push(record(DriverOutcome::NotDispatched));
+        report.push(record(DriverOutcome::Started(efi::Status::DEVICE_ERROR)));
+        report.log();
+
+        assert_eq!(report.records().len(), 4);
+        assert!(report.has_failures());
+        let failures: Vec<_> = report.failures().map(|record| record.outcome).collect();
+        assert_eq!(
+            failures,
+            [DriverOutcome::LoadFailed(efi::Status::LOAD_ERROR), DriverOutcome::Started(efi::Status::DEVICE_ERROR)]
+        );
+    }
+
+    static REPORTED_VALUE: AtomicU32 = AtomicU32::new(0);
+
+    extern "efiapi" fn mock_report_status_code(
+        code_type: u32,
+        value: u32,
+        _instance: u32,
+        _caller_id: *const efi::Guid,
+        data: *const EfiStatusCodeData,
+    ) -> efi::Status {
+        assert_eq!(code_type, EFI_ERROR_CODE | EFI_ERROR_MAJOR);
+        // The data is reported in a byte buffer, so it may not be aligned.
+        let header = unsafe { data.read_unaligned() };
+        assert_eq!(header.r#type, DRIVER_DISPATCH_FAILURE);
+        let failure = unsafe {
+            (data.byte_add(header.header_size as usize) as *const DriverDispatchFailureData).read_unaligned()
+        };
+        assert_eq!(failure.file_name, DRIVER);
+        REPORTED_VALUE.store(value, Ordering::SeqCst);
+        efi::Status::SUCCESS
+    }
+
+    #[test]
+    fn failures_should_be_reported_as_status_codes() {
+        crate::test_support::with_global_lock(|| {
+            unsafe { crate::test_support::init_test_protocol_db() };
+            REPORTED_VALUE.store(0, Ordering::SeqCst);
+
+            // Nothing is reported until the protocol is installed.
+            report_failure(&record(DriverOutcome::LoadFailed(efi::Status::LOAD_ERROR)));
+
+            let protocol = Box::leak(Box::new(status_code::Protocol { report_status_code: mock_report_status_code }));
+            PROTOCOL_DB
+                .install_protocol_interface(None, status_code::PROTOCOL_GUID, protocol as *mut _ as *mut c_void)
+                .unwrap();
+
+            report_failure(&record(DriverOutcome::NotDispatched));
+            assert_eq!(REPORTED_VALUE.load(Ordering::SeqCst), 0);
+
+            report_failure(&record(DriverOutcome::Rejected(efi::Status::ACCESS_DENIED)));
+            assert_eq!(
+                REPORTED_VALUE.load(Ordering::SeqCst),
+                EFI_SOFTWARE_DXE_CORE | EFI_SW_DXE_CORE_EC_IMAGE_LOAD_FAILURE
+            );
+
+            report_failure(&record(DriverOutcome::Started(efi::Status::DEVICE_ERROR)));
+            assert_eq!(REPORTED_VALUE.load(Ordering::SeqCst), EFI_SOFTWARE_DXE_CORE | EFI_SW_EC_START_ERROR);
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn elapsed_time_should_not_go_backwards() {
+        assert_eq!(elapsed_since(u64::MAX), Duration::ZERO);
+        let start = timestamp();
+        assert!(elapsed_since(start) < Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,267 @@
+//! Lazy Section Extraction
+//!
+//! Discovering a firmware volume only parses the top-level sections of its driver and firmware volume image files, to
+//! read their dependency expressions. The encapsulated sections of a file, e.g. a compressed PE32 image, are
+//! extracted when the dispatcher loads the driver or installs the firmware volume image, so that the decompressed
+//! sections of the files that are not dispatched yet do not stay in memory.
+//!
+//! Some sections are decompressed ahead of time: when the dependency expression of a file is itself encapsulated, or
+//! by [parallel section extraction](super::section_prefetch). They are kept in a [SectionCache] until the file is
+//! dispatched, and the least recently cached sections are dropped once the cache exceeds its capacity, to be
+//! extracted again when they are needed.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::{collections::VecDeque, vec::Vec};
+use patina_ffs::{
+    FirmwareFileSystemError,
+    file::FileRef,
+    section::{Section, SectionExtractor, SectionIterator},
+};
+use patina_internal_depex::Depex;
+use patina_pi::fw_fs::ffs;
+
+use super::section_prefetch::SectionPrefetch;
+
+/// The default capacity of the section cache, in bytes.
+const DEFAULT_CAPACITY: usize = 0x40_0000;
+
+/// The metadata recorded for a file when it is discovered.
+pub(super) struct FileMetadata {
+    /// The dependency expression of the file, if any.
+    pub(super) depex: Option<Depex>,
+    /// Whether the file may contain a section of the requested type. False if all its sections were parsed without
+    /// finding one.
+    pub(super) has_section: bool,
+}
+
+/// The sections of a file extracted ahead of its dispatch.
+struct CachedSections {
+    file: usize,
+    sections: Vec<Section>,
+    size: usize,
+}
+
+/// The sections extracted ahead of their dispatch, bounded to a capacity in bytes.
+pub(super) struct SectionCache {
+    capacity: usize,
+    size: usize,
+    // Least recently cached first.
+    entries: VecDeque<CachedSections>,
+}
+
+impl SectionCache {
+    /// Creates an empty cache with the default capacity.
+    pub(super) const fn new() -> Self {
+        Self { capacity: DEFAULT_CAPACITY, size: 0, entries: VecDeque::new() }
+    }
+
+    /// Sets the capacity of the cache in bytes, dropping the least recently cached sections beyond it.
+    pub(super) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict(0);
+    }
+
+    /// Returns the size of the cached sections in bytes.
+    pub(super) fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Caches the `sections` of `file`. Sections larger than the capacity are not cached.
+    pub(super) fn insert(&mut self, file: &FileRef, sections: Vec<Section>) {
+        let size = sections.iter().map(|section| section.try_content_as_slice().map_or(0, <[u8]>::len)).sum();
+        if size > self.capacity {
+            return;
+        }
+        self.evict(size);
+        self.size += size;
+        self.entries.push_back(CachedSections { file: file_key(file), sections, size });
+    }
+
+    /// Removes the cached sections of `file` from the cache, if any.
+    pub(super) fn take(&mut self, file: &FileRef) -> Option<Vec<Section>> {
+        let key = file_key(file);
+        let index = self.entries.iter().position(|entry| entry.file == key)?;
+        let entry = self.entries.remove(index)?;
+        self.size -= entry.size;
+        Some(entry.sections)
+    }
+
+    /// Returns the sections of type `section_type` of `file`, from the cache or extracted with `extractor`.
+    pub(super) fn take_or_extract(
+        &mut self,
+        file: &FileRef,
+        section_type: ffs::section::Type,
+        extractor: &dyn SectionExtractor,
+    ) -> Result<Vec<Section>, FirmwareFileSystemError> {
+        if let Some(sections) = self.take(file) {
+            return Ok(sections);
+        }
+        let sections = file.sections_with_extractor(extractor)?;
+        Ok(sections.into_iter().filter(|section| section.section_type() == Some(section_type)).collect())
+    }
+
+    /// Drops the least recently cached sections until `size` more bytes fit in the cache.
+    fn evict(&mut self, size: usize) {
+        while self.size + size > self.capacity {
+            let Some(entry) = self.entries.pop_front() else {
+                break;
+            };
+            self.size -= entry.size;
+        }
+    }
+}
+
+impl Default for SectionCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the key of `file` in the cache, its address in the firmware volume.
+fn file_key(file: &FileRef) -> usize {
+    file.data().as_ptr() as usize
+}
+
+/// Reads the dependency expression of `file` and whether it may contain a section of type `section_type`.
+///
+/// Only the top-level sections are parsed, unless the sections of the file were prefetched or its dependency
+/// expression may be encapsulated. In that case, the extracted sections of type `section_type` are cached.
+pub(super) fn discover_file(
+    file: &FileRef,
+    section_type: ffs::section::Type,
+    prefetch: &mut SectionPrefetch,
+    extractor: &dyn SectionExtractor,
+    cache: &mut SectionCache,
+) -> Result<FileMetadata, FirmwareFileSystemError> {
+    let sections = if prefetch.contains(file) {
+        prefetch.sections_with_extractor(file, extractor)?
+    } else {
+        let top_level = SectionIterator::new(file.content()).collect::<Result<Vec<_>, _>>()?;
+        let encapsulated = top_level.iter().any(Section::encapsulation);
+        let depex = find_depex(&top_level)?;
+        if depex.is_some() || !encapsulated {
+            let has_section =
+                encapsulated || top_level.iter().any(|section| section.section_type() == Some(section_type));
+            return Ok(FileMetadata { depex, has_section });
+        }
+        // The dependency expression may be encapsulated, so the file is extracted now.
+        file.sections_with_extractor(extractor)?
+    };
+
+    let depex = find_depex(&sections)?;
+    let sections: Vec<_> =
+        sections.into_iter().filter(|section| section.section_type() == Some(section_type)).collect();
+    let has_section = !sections.is_empty();
+    if has_section {
+        cache.insert(file, sections);
+    }
+    Ok(FileMetadata { depex, has_section })
+}
+
+/// Returns the dependency expression in `sections`, if any.
+fn find_depex(sections: &[Section]) -> Result<Option<Depex>, FirmwareFileSystemError> {
+    Ok(sections
+        .iter()
+        .find_map(|section| match section.section_type() {
+            Some(ffs::section::Type::DxeDepex) => Some(section.try_content_as_slice()),
+            _ => None,
+        })
+        .transpose()?
+        .map(Depex::from))
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::test_collateral;
+    use patina_ffs::volume::VolumeRef;
+    use patina_ffs_extractors::CompositeSectionExtractor;
+    use std::fs;
+
+    fn driver_images(fv: &VolumeRef) -> Vec<Vec<u8>> {
+        let extractor = CompositeSectionExtractor::default();
+        fv.files()
+            .flatten()
+            .filter(|file| file.file_type_raw() == ffs::file::raw::r#type::DRIVER)
+            .map(|file| {
+                let sections = file.sections_with_extractor(&extractor).unwrap();
+                let pe32 = sections.iter().find(|section| section.section_type() == Some(ffs::section::Type::Pe32));
+                pe32.unwrap().try_content_as_slice().unwrap().to_vec()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_discovery_does_not_extract_driver_images() {
+        let buffer = fs::read(test_collateral!("DXEFV.Fv")).unwrap();
+        let fv = VolumeRef::new(&buffer).unwrap();
+        let extractor = CompositeSectionExtractor::default();
+        let mut prefetch = SectionPrefetch::default();
+        let mut cache = SectionCache::new();
+
+        let files: Vec<_> =
+            fv.files().flatten().filter(|file| file.file_type_raw() == ffs::file::raw::r#type::DRIVER).collect();
+        for file in &files {
+            let metadata =
+                discover_file(file, ffs::section::Type::Pe32, &mut prefetch, &extractor, &mut cache).unwrap();
+            assert!(metadata.has_section);
+        }
+        assert_eq!(cache.size(), 0);
+
+        let images: Vec<_> = files
+            .iter()
+            .map(|file| {
+                let pe32 = cache.take_or_extract(file, ffs::section::Type::Pe32, &extractor).unwrap();
+                pe32[0].try_content_as_slice().unwrap().to_vec()
+            })
+            .collect();
+        assert_eq!(images, driver_images(&fv));
+    }
+
+    #[test]
+    fn test_cache_drops_least_recently_cached_sections() {
+        let buffer = fs::read(test_collateral!("DXEFV.Fv")).unwrap();
+        let fv = VolumeRef::new(&buffer).unwrap();
+        let extractor = CompositeSectionExtractor::default();
+        let files: Vec<_> = fv
+            .files()
+            .flatten()
+            .filter(|file| file.file_type_raw() == ffs::file::raw::r#type::DRIVER)
+            .take(3)
+            .collect();
+        let sections: Vec<_> = files
+            .iter()
+            .map(|file| {
+                let sections = file.sections_with_extractor(&extractor).unwrap();
+                sections.into_iter().filter(|s| s.section_type() == Some(ffs::section::Type::Pe32)).collect::<Vec<_>>()
+            })
+            .collect();
+        let sizes: Vec<usize> = sections.iter().map(|s| s[0].try_content_as_slice().unwrap().len()).collect();
+
+        let mut cache = SectionCache::new();
+        cache.set_capacity(sizes[1] + sizes[2]);
+        for (file, sections) in files.iter().zip(sections.iter()) {
+            cache.insert(file, sections.clone());
+        }
+        assert_eq!(cache.size(), sizes[1] + sizes[2]);
+        assert!(cache.take(&files[0]).is_none());
+        assert!(cache.take(&files[1]).is_some());
+        assert_eq!(cache.size(), sizes[2]);
+
+        // Sections larger than the capacity are not cached, and reducing the capacity evicts the cached sections.
+        cache.set_capacity(sizes[0] - 1);
+        cache.insert(&files[0], sections[0].clone());
+        assert!(cache.take(&files[0]).is_none());
+        cache.set_capacity(0);
+        assert_eq!(cache.size(), 0);
+        assert!(cache.take(&files[2]).is_none());
+    }
+}
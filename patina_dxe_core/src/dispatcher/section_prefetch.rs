@@ -0,0 +1,454 @@
+//! Parallel Section Extraction
+//!
+//! Decompressing the sections of the drivers in a firmware volume is the most expensive part of discovering them.
+//! When enabled with [enable], the decompression of the top-level UEFI compressed sections of a newly discovered
+//! firmware volume is queued as jobs before its files are processed. If the MP Services protocol is available, the
+//! application processors (APs) work through the queue while the bootstrap processor (BSP) processes the files in
+//! order, so that the sections of the upcoming files are decompressed while the BSP evaluates the current one.
+//!
+//! APs only decompress from a buffer into a buffer allocated by the BSP. They never allocate memory, call boot
+//! services or access devices. Each job is claimed by exactly one processor: when the BSP needs the result of a job
+//! that no AP has started yet, it runs the job itself, and when an AP is running it, the BSP waits for it to complete.
+//! The buffers of a batch are only released after every job of the batch is either completed or cancelled.
+//!
+//! Only the top-level sections using UEFI or Tiano compression are queued, up to [JOB_CAPACITY] per firmware volume.
+//! Nested sections, sections using a platform provided extractor, and the hashing and authentication of the images
+//! are performed by the BSP.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::{collections::BTreeMap, vec, vec::Vec};
+use core::{
+    cell::RefCell,
+    ffi::c_void,
+    ptr, slice,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicU8, AtomicUsize, Ordering},
+};
+use mu_rust_helpers::uefi_decompress::{DecompressionAlgorithm, decompress_into_with_algo};
+use patina_ffs::{
+    FirmwareFileSystemError,
+    file::FileRef,
+    section::{Section, SectionExtractor, SectionIterator},
+    volume::VolumeRef,
+};
+use patina_pi::fw_fs::ffs;
+use r_efi::{efi, protocols::mp_services};
+
+use crate::decompress::{UefiCompression, uefi_compression};
+
+/// The maximum number of jobs queued for a firmware volume. Sections beyond it are decompressed on demand.
+const JOB_CAPACITY: usize = 64;
+
+const JOB_IDLE: u8 = 0;
+const JOB_PENDING: u8 = 1;
+const JOB_CLAIMED: u8 = 2;
+const JOB_DONE: u8 = 3;
+const JOB_FAILED: u8 = 4;
+
+/// Whether parallel section extraction is enabled.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+/// The MP Services protocol used to start the APs. Null until registered.
+static MP_SERVICES: AtomicPtr<mp_services::Protocol> = AtomicPtr::new(ptr::null_mut());
+/// The event signaled by the MP Services protocol once the APs are done with the queue.
+static AP_WAIT_EVENT: AtomicPtr<c_void> = AtomicPtr::new(ptr::null_mut());
+
+/// The queue of decompression jobs shared with the APs.
+static JOBS: [Job; JOB_CAPACITY] = [const { Job::new() }; JOB_CAPACITY];
+
+/// A decompression job. The parameters are only read by the processor that claimed the job.
+struct Job {
+    state: AtomicU8,
+    tiano: AtomicBool,
+    source: AtomicPtr<u8>,
+    source_len: AtomicUsize,
+    destination: AtomicPtr<u8>,
+    destination_len: AtomicUsize,
+}
+
+impl Job {
+    const fn new() -> Self {
+        Self {
+            state: AtomicU8::new(JOB_IDLE),
+            tiano: AtomicBool::new(false),
+            source: AtomicPtr::new(ptr::null_mut()),
+            source_len: AtomicUsize::new(0),
+            destination: AtomicPtr::new(ptr::null_mut()),
+            destination_len: AtomicUsize::new(0),
+        }
+    }
+
+    /// Publishes the job. The job must be idle, and the buffers must remain valid until the job is released.
+    fn publish(&self, source: &[u8], algorithm: &DecompressionAlgorithm, destination: &mut [u8]) {
+        debug_assert_eq!(self.state.load(Ordering::Acquire), JOB_IDLE);
+        self.tiano.store(matches!(algorithm, DecompressionAlgorithm::TianoDecompress), Ordering::Relaxed);
+        self.source.store(source.as_ptr() as *mut u8, Ordering::Relaxed);
+        self.source_len.store(source.len(), Ordering::Relaxed);
+        self.destination.store(destination.as_mut_ptr(), Ordering::Relaxed);
+        self.destination_len.store(destination.len(), Ordering::Relaxed);
+        self.state.store(JOB_PENDING, Ordering::Release);
+    }
+
+    /// Claims a pending job for the executing processor.
+    fn try_claim(&self) -> bool {
+        self.state.compare_exchange(JOB_PENDING, JOB_CLAIMED, Ordering::AcqRel, Ordering::Acquire).is_ok()
+    }
+
+    /// Runs a job claimed by the executing processor.
+    fn run(&self) {
+        let algorithm = match self.tiano.load(Ordering::Relaxed) {
+            true => DecompressionAlgorithm::TianoDecompress,
+            false => DecompressionAlgorithm::UefiDecompress,
+        };
+        // SAFETY: The buffers were provided to publish() and remain valid until the job is released, which waits for
+        // the claimed job to complete. Only the claiming processor accesses the destination.
+        let (source, destination) = unsafe {
+            (
+                slice::from_raw_parts(self.source.load(Ordering::Relaxed), self.source_len.load(Ordering::Relaxed)),
+                slice::from_raw_parts_mut(
+                    self.destination.load(Ordering::Relaxed),
+                    self.destination_len.load(Ordering::Relaxed),
+                ),
+            )
+        };
+        let state = match decompress_into_with_algo(source, destination, algorithm) {
+            Ok(_) => JOB_DONE,
+            Err(_) => JOB_FAILED,
+        };
+        self.state.store(state, Ordering::Release);
+    }
+
+    /// Waits for the job to be completed, running it on the executing processor if no other processor claimed it,
+    /// and releases it. Returns whether the decompression succeeded.
+    fn complete(&self) -> bool {
+        if self.try_claim() {
+            self.run();
+        }
+        let state = self.wait();
+        self.state.store(JOB_IDLE, Ordering::Release);
+        state == JOB_DONE
+    }
+
+    /// Cancels the job if it was not claimed yet, otherwise waits for it to be completed, and releases it.
+    fn cancel(&self) {
+        if !self.try_claim() {
+            self.wait();
+        }
+        self.state.store(JOB_IDLE, Ordering::Release);
+    }
+
+    /// Waits for a claimed job to be completed, and returns its final state.
+    fn wait(&self) -> u8 {
+        loop {
+            match self.state.load(Ordering::Acquire) {
+                JOB_CLAIMED => core::hint::spin_loop(),
+                state => return state,
+            }
+        }
+    }
+}
+
+/// Runs the pending jobs until none is left.
+fn run_pending_jobs() {
+    while let Some(job) = JOBS.iter().find(|job| job.try_claim()) {
+        job.run();
+    }
+}
+
+/// AP procedure working through the job queue.
+extern "efiapi" fn ap_run_pending_jobs(_buffer: *mut c_void) {
+    run_pending_jobs();
+}
+
+/// Enables parallel section extraction. Must be called before the dispatcher is initialized.
+pub fn enable() {
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+/// Returns whether parallel section extraction is enabled.
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+}
+
+/// Registers the MP Services protocol used to start the APs, and the event it signals when the APs are done.
+///
+/// ## Errors
+///
+/// InvalidParameter - `protocol` or `wait_event` is null.
+pub fn register_mp_services(
+    protocol: *mut mp_services::Protocol,
+    wait_event: efi::Event,
+) -> Result<(), patina::error::EfiError> {
+    if protocol.is_null() || wait_event.is_null() {
+        return Err(patina::error::EfiError::InvalidParameter);
+    }
+    AP_WAIT_EVENT.store(wait_event, Ordering::SeqCst);
+    MP_SERVICES.store(protocol, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Starts the APs on the job queue without waiting for them.
+fn start_aps() {
+    let mp_services = MP_SERVICES.load(Ordering::SeqCst);
+    if mp_services.is_null() {
+        return;
+    }
+
+    // SAFETY: mp_services was registered through register_mp_services and was checked for null above. The APs run in
+    // non-blocking mode and only access the job queue, which is static.
+    let status = unsafe {
+        ((*mp_services).startup_all_aps)(
+            mp_services,
+            ap_run_pending_jobs,
+            efi::Boolean::FALSE,
+            AP_WAIT_EVENT.load(Ordering::SeqCst),
+            0,
+            ptr::null_mut(),
+            ptr::null_mut(),
+        )
+    };
+
+    match status {
+        efi::Status::SUCCESS => (),
+        // No enabled APs, or the APs are still working through a previous batch. The BSP runs the remaining jobs.
+        efi::Status::NOT_STARTED | efi::Status::NOT_READY => (),
+        status => log::warn!("Failed to start the APs for section extraction: {status:#x?}"),
+    }
+}
+
+/// A queued job and the buffer its result is written to.
+struct PrefetchJob {
+    slot: usize,
+    source: *const u8,
+    destination: Vec<u8>,
+    released: bool,
+}
+
+/// The sections of a firmware volume, with their decompression queued on the APs.
+#[derive(Default)]
+pub(super) struct SectionPrefetch {
+    files: BTreeMap<usize, Vec<Section>>,
+    jobs: RefCell<Vec<PrefetchJob>>,
+}
+
+impl SectionPrefetch {
+    /// Parses the sections of the driver and firmware volume image files in `fv`, and queues the decompression of
+    /// their top-level sections. Does nothing if parallel section extraction is not enabled.
+    pub(super) fn new(fv: &VolumeRef) -> Self {
+        let mut prefetch = Self::default();
+        if !enabled() {
+            return prefetch;
+        }
+
+        for file in fv.files().flatten() {
+            if !matches!(
+                file.file_type_raw(),
+                ffs::file::raw::r#type::DRIVER | ffs::file::raw::r#type::FIRMWARE_VOLUME_IMAGE
+            ) {
+                continue;
+            }
+            // Files that fail to parse are reported when they are processed.
+            let Ok(sections) = SectionIterator::new(file.content()).collect::<Result<Vec<_>, _>>() else {
+                continue;
+            };
+            for section in &sections {
+                prefetch.queue(section);
+            }
+            prefetch.files.insert(file.data().as_ptr() as usize, sections);
+        }
+
+        if !prefetch.jobs.get_mut().is_empty() {
+            start_aps();
+        }
+        prefetch
+    }
+
+    /// Queues the decompression of `section` if it uses UEFI compression and a job is available.
+    fn queue(&mut self, section: &Section) {
+        let jobs = self.jobs.get_mut();
+        if jobs.len() == JOB_CAPACITY {
+            return;
+        }
+        let Ok(UefiCompression::Compressed { source, algorithm, decompressed_size }) = uefi_compression(section) else {
+            return;
+        };
+
+        let slot = jobs.len();
+        let mut destination = vec![0u8; decompressed_size];
+        JOBS[slot].publish(source, &algorithm, &mut destination);
+        jobs.push(PrefetchJob { slot, source: source.as_ptr(), destination, released: false });
+    }
+
+    /// Returns whether the sections of `file` were parsed, and the decompression of its sections queued.
+    pub(super) fn contains(&self, file: &FileRef) -> bool {
+        self.files.contains_key(&(file.data().as_ptr() as usize))
+    }
+
+    /// Returns the sections of `file` with their encapsulated sections extracted, like
+    /// [FileRef::sections_with_extractor], using the queued decompression results when available.
+    pub(super) fn sections_with_extractor(
+        &mut self,
+        file: &FileRef,
+        extractor: &dyn SectionExtractor,
+    ) -> Result<Vec<Section>, FirmwareFileSystemError> {
+        let Some(mut sections) = self.files.remove(&(file.data().as_ptr() as usize)) else {
+            return file.sections_with_extractor(extractor);
+        };
+
+        let extractor = PrefetchExtractor { jobs: &self.jobs, fallback: extractor };
+        for section in sections.iter_mut() {
+            section.extract(&extractor)?;
+        }
+        Ok(sections.iter().flat_map(|x| x.sections().cloned().collect::<Vec<_>>()).collect())
+    }
+}
+
+impl Drop for SectionPrefetch {
+    fn drop(&mut self) {
+        // The buffers must not be released while an AP may still write to them.
+        for job in self.jobs.get_mut().iter_mut().filter(|job| !job.released) {
+            JOBS[job.slot].cancel();
+            job.released = true;
+        }
+    }
+}
+
+/// Section extractor returning the results of the queued jobs, and using `fallback` for the other sections.
+struct PrefetchExtractor<'a> {
+    jobs: &'a RefCell<Vec<PrefetchJob>>,
+    fallback: &'a dyn SectionExtractor,
+}
+
+impl SectionExtractor for PrefetchExtractor<'_> {
+    fn extract(&self, section: &Section) -> Result<Vec<u8>, FirmwareFileSystemError> {
+        self.extract_with_auth_status(section).map(|(buffer, _)| buffer)
+    }
+
+    fn extract_with_auth_status(&self, section: &Section) -> Result<(Vec<u8>, u32), FirmwareFileSystemError> {
+        let source = section.try_content_as_slice()?.as_ptr();
+        let mut jobs = self.jobs.borrow_mut();
+        let Some(job) = jobs.iter_mut().find(|job| !job.released && job.source == source) else {
+            drop(jobs);
+            return self.fallback.extract_with_auth_status(section);
+        };
+
+        // Only UEFI compressed sections are queued, and UEFI decompression reports no authentication status.
+        job.released = true;
+        match JOBS[job.slot].complete() {
+            true => Ok((core::mem::take(&mut job.destination), 0)),
+            false => Err(FirmwareFileSystemError::DataCorrupt),
+        }
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::{decompress::CoreExtractor, test_collateral};
+    use patina_ffs::section::SectionHeader;
+    use patina_ffs_extractors::CompositeSectionExtractor;
+    use patina_pi::fw_fs;
+    use std::{fs, thread};
+
+    fn tiano_section() -> (Section, Vec<u8>) {
+        let compressed = fs::read(test_collateral!("tiano_compressed.bin")).unwrap();
+        let uncompressed = fs::read(test_collateral!("tiano_uncompressed.bin")).unwrap();
+        let header = ffs::section::header::GuidDefined {
+            section_definition_guid: fw_fs::guid::TIANO_DECOMPRESS_SECTION,
+            data_offset: (size_of::<ffs::section::Header>() + size_of::<ffs::section::header::GuidDefined>()) as u16,
+            attributes: 0x01,
+        };
+        let section = Section::new_from_header_with_data(
+            SectionHeader::GuidDefined(header, Vec::new(), compressed.len() as u32),
+            compressed,
+        )
+        .unwrap();
+        (section, uncompressed)
+    }
+
+    fn extract_drivers(fv: &VolumeRef, prefetch: &mut SectionPrefetch) -> Vec<Vec<Vec<u8>>> {
+        let extractor = CompositeSectionExtractor::default();
+        fv.files()
+            .flatten()
+            .filter(|file| file.file_type_raw() == ffs::file::raw::r#type::DRIVER)
+            .map(|file| {
+                prefetch
+                    .sections_with_extractor(&file, &extractor)
+                    .unwrap()
+                    .iter()
+                    .map(|section| section.try_content_as_slice().unwrap().to_vec())
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn jobs_idle() -> bool {
+        JOBS.iter().all(|job| job.state.load(Ordering::SeqCst) == JOB_IDLE)
+    }
+
+    #[test]
+    fn test_jobs_are_run_once_by_any_processor() {
+        crate::test_support::with_global_lock(|| {
+            let (section, uncompressed) = tiano_section();
+            let mut prefetch = SectionPrefetch::default();
+            let sections = vec![section; 8];
+            sections.iter().for_each(|section| prefetch.queue(section));
+            assert_eq!(prefetch.jobs.get_mut().len(), 8);
+
+            // Emulate the APs with threads working through the queue while the sections are extracted in order.
+            let workers: Vec<_> = (0..3).map(|_| thread::spawn(run_pending_jobs)).collect();
+            let extractor = PrefetchExtractor { jobs: &prefetch.jobs, fallback: &CoreExtractor::new() };
+            for section in &sections {
+                assert_eq!(extractor.extract(section).unwrap(), uncompressed);
+            }
+            workers.into_iter().for_each(|worker| worker.join().unwrap());
+            assert!(jobs_idle());
+
+            // Sections without a queued job are extracted with the fallback extractor.
+            assert_eq!(extractor.extract(&sections[0]).unwrap(), uncompressed);
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_dropping_prefetch_cancels_jobs() {
+        crate::test_support::with_global_lock(|| {
+            let (section, _) = tiano_section();
+            let mut prefetch = SectionPrefetch::default();
+            prefetch.queue(&section);
+            assert_eq!(JOBS[0].state.load(Ordering::SeqCst), JOB_PENDING);
+            drop(prefetch);
+            assert!(jobs_idle());
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_prefetch_matches_serial_extraction() {
+        crate::test_support::with_global_lock(|| {
+            let buffer = fs::read(test_collateral!("DXEFV.Fv")).unwrap();
+            let fv = VolumeRef::new(&buffer).unwrap();
+            let expected = extract_drivers(&fv, &mut SectionPrefetch::new(&fv));
+
+            enable();
+            let mut prefetch = SectionPrefetch::new(&fv);
+            ENABLED.store(false, Ordering::SeqCst);
+            assert!(!prefetch.files.is_empty());
+            assert_eq!(extract_drivers(&fv, &mut prefetch), expected);
+            drop(prefetch);
+            assert!(jobs_idle());
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_register_mp_services_rejects_null() {
+        assert!(register_mp_services(ptr::null_mut(), ptr::null_mut()).is_err());
+    }
+}
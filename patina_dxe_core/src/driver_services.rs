@@ -22,7 +22,65 @@ use patina_internal_device_path::{concat_device_path_to_boxed_slice, copy_device
 
 use r_efi::efi;
 
-use crate::protocols::PROTOCOL_DB;
+use crate::{protocols::PROTOCOL_DB, tpl_lock::TplMutex};
+
+mod report;
+
+pub use report::{DriverBindingFailure, DriverBindingFailures, DriverBindingOperation, driver_binding_failures};
+
+/// Cache of the DriverBinding->Supported() EFI_UNSUPPORTED results.
+///
+/// ConnectController() calls Supported() on every driver for every handle, again on every recursive connect. An
+/// EFI_UNSUPPORTED result is cached for the (controller, driver) pair when no remaining device path is given, and the
+/// cached entries of a handle are invalidated when a protocol is installed on or uninstalled from it, opened on it by
+/// a driver or closed, and when its drivers are disconnected. Other failures, e.g. EFI_ACCESS_DENIED because another
+/// driver has the controller open, are transient and never cached.
+struct SupportedCache {
+    /// Incremented on every invalidation, so that a failure racing with an invalidation is not cached.
+    generation: u64,
+    /// The (controller handle, driver binding handle) pair whose Supported() is being called, if any.
+    probing: Option<(usize, usize)>,
+    /// (controller handle, driver binding handle) pairs whose Supported() returned EFI_UNSUPPORTED.
+    unsupported: BTreeSet<(usize, usize)>,
+}
+
+impl SupportedCache {
+    const fn new() -> Self {
+        Self { generation: 0, probing: None, unsupported: BTreeSet::new() }
+    }
+
+    fn invalidate(&mut self, handle: efi::Handle) {
+        self.generation = self.generation.wrapping_add(1);
+        let handle = handle as usize;
+        self.unsupported.retain(|(controller, driver)| *controller != handle && *driver != handle);
+    }
+}
+
+static SUPPORTED_CACHE: TplMutex<SupportedCache> =
+    TplMutex::new(efi::TPL_NOTIFY, SupportedCache::new(), "SupportedCacheLock");
+
+/// Invalidates the cached Supported() failures of `handle`, as a controller or as a driver binding handle.
+pub(crate) fn invalidate_supported_cache(handle: efi::Handle) {
+    SUPPORTED_CACHE.lock().invalidate(handle);
+}
+
+/// Invalidates the cached Supported() failures of `handle` after `agent_handle` opened or closed one of its protocols.
+///
+/// The protocols a driver opens and closes on the controller its Supported() is being called for do not invalidate
+/// the cache, as Supported() must close them before returning.
+pub(crate) fn invalidate_supported_cache_for_usage(handle: efi::Handle, agent_handle: Option<efi::Handle>) {
+    let mut cache = SUPPORTED_CACHE.lock();
+    let agent_handle = agent_handle.unwrap_or(core::ptr::null_mut());
+    if cache.probing == Some((handle as usize, agent_handle as usize)) {
+        return;
+    }
+    cache.invalidate(handle);
+}
+
+#[cfg(test)]
+pub(crate) fn reset_supported_cache() {
+    *SUPPORTED_CACHE.lock() = SupportedCache::new();
+}
 
 fn get_bindings_for_handles(handles: Vec<efi::Handle>) -> Vec<*mut efi::protocols::driver_binding::Protocol> {
     handles
@@ -214,13 +272,26 @@ fn core_connect_single_controller(
     driver_bindings.retain(|x| !driver_candidates.contains(x));
     driver_candidates.append(&mut driver_bindings);
 
+    //Supported() results depend on the remaining device path, only the ones without it are cached.
+    let device_path = remaining_device_path.unwrap_or(core::ptr::null_mut());
+    let cacheable = device_path.is_null();
+
     //loop until no more drivers can be started on handle.
     let mut one_started = false;
     loop {
         let mut started_drivers = Vec::new();
         for driver_binding_interface in driver_candidates.clone() {
             let driver_binding = unsafe { &mut *(driver_binding_interface) };
-            let device_path = remaining_device_path.or(Some(core::ptr::null_mut())).expect("must be some");
+            let cache_key = (controller_handle as usize, driver_binding.driver_binding_handle as usize);
+
+            let generation = {
+                let mut cache = SUPPORTED_CACHE.lock();
+                if cacheable && cache.unsupported.contains(&cache_key) {
+                    continue;
+                }
+                cache.probing = Some(cache_key);
+                cache.generation
+            };
 
             perf_driver_binding_support_begin(
                 driver_binding.driver_binding_handle,
@@ -229,7 +300,9 @@ fn core_connect_single_controller(
             );
 
             //driver claims support; attempt to start it.
-            match (driver_binding.supported)(driver_binding_interface, controller_handle, device_path) {
+            let status = (driver_binding.supported)(driver_binding_interface, controller_handle, device_path);
+            SUPPORTED_CACHE.lock().probing = None;
+            match status {
                 efi::Status::SUCCESS => {
                     perf_driver_binding_support_end(
                         driver_binding.driver_binding_handle,
@@ -245,11 +318,16 @@ fn core_connect_single_controller(
                         create_performance_measurement,
                     );
 
-                    if (driver_binding.start)(driver_binding_interface, controller_handle, device_path)
-                        == efi::Status::SUCCESS
-                    {
+                    let status = (driver_binding.start)(driver_binding_interface, controller_handle, device_path);
+                    if status == efi::Status::SUCCESS {
                         one_started = true;
                     }
+                    report::record_result(
+                        DriverBindingOperation::Start,
+                        driver_binding.image_handle,
+                        controller_handle,
+                        status,
+                    );
 
                     perf_driver_binding_start_end(
                         driver_binding.driver_binding_handle,
@@ -257,12 +335,17 @@ fn core_connect_single_controller(
                         create_performance_measurement,
                     );
                 }
-                _ => {
+                status => {
                     perf_driver_binding_support_end(
                         driver_binding.driver_binding_handle,
                         controller_handle,
                         create_performance_measurement,
                     );
+
+                    let mut cache = SUPPORTED_CACHE.lock();
+                    if status == efi::Status::UNSUPPORTED && cacheable && cache.generation == generation {
+                        cache.unsupported.insert(cache_key);
+                    }
                     continue;
                 }
             }
@@ -328,6 +411,29 @@ pub unsafe fn core_connect_controller(
     return_status
 }
 
+/// Reconnects a controller and its children to drivers
+///
+/// Invalidates the cached Supported() failures of the controller and of all its child handles, since a change to a
+/// controller can change which drivers support its children, and then recursively connects the controller. This is
+/// used to let drivers consume the controller again after its protocols were changed, e.g. by
+/// ReinstallProtocolInterface().
+///
+/// # Safety
+/// See [core_connect_controller].
+///
+pub unsafe fn core_reconnect_controller(handle: efi::Handle) -> Result<(), EfiError> {
+    let mut visited = BTreeSet::new();
+    let mut pending = Vec::from([handle]);
+    while let Some(handle) = pending.pop() {
+        if visited.insert(handle as usize) {
+            invalidate_supported_cache(handle);
+            pending.extend(PROTOCOL_DB.get_child_handles(handle));
+        }
+    }
+
+    unsafe { core_connect_controller(handle, Vec::new(), None, true) }
+}
+
 extern "efiapi" fn connect_controller(
     handle: efi::Handle,
     driver_image_handle: *mut efi::Handle,
@@ -503,11 +609,17 @@ pub unsafe fn core_disconnect_controller(
         if status == efi::Status::SUCCESS && (child_handle.is_none() || is_only_child) {
             status = (driver_binding.stop)(driver_binding_interface, controller_handle, 0, core::ptr::null_mut());
         }
+        report::record_result(DriverBindingOperation::Stop, driver_binding.image_handle, controller_handle, status);
         if status == efi::Status::SUCCESS {
             one_or_more_drivers_disconnected = true;
         }
     }
 
+    //drivers that failed Supported() because the protocols were open by the stopped drivers may now support it.
+    if one_or_more_drivers_disconnected {
+        invalidate_supported_cache(controller_handle);
+    }
+
     if one_or_more_drivers_disconnected || no_drivers { Ok(()) } else { Err(EfiError::NotFound) }
 }
 
@@ -529,6 +641,7 @@ extern "efiapi" fn disconnect_controller(
 pub fn init_driver_services(bs: &mut efi::BootServices) {
     bs.connect_controller = connect_controller;
     bs.disconnect_controller = disconnect_controller;
+    report::init_driver_binding_report();
 }
 
 #[cfg(test)]
@@ -547,6 +660,7 @@ mod tests {
     // =================== TEST HELPER STATICS ===================
     static SUPPORTED_CALL_COUNT: AtomicUsize = AtomicUsize::new(0);
     static START_CALL_COUNT: AtomicUsize = AtomicUsize::new(0);
+    static UNSUPPORTED_CALL_COUNT: AtomicUsize = AtomicUsize::new(0);
 
     // =================== TEST HELPERS ===================
     fn with_locked_state<F: Fn() + std::panic::RefUnwindSafe>(f: F) {
@@ -586,6 +700,27 @@ mod tests {
         efi::Status::SUCCESS
     }
 
+    extern "efiapi" fn mock_unsupported_with_counter(
+        this: *mut efi::protocols::driver_binding::Protocol,
+        controller_handle: efi::Handle,
+        _remaining_device_path: *mut efi::protocols::device_path::Protocol,
+    ) -> efi::Status {
+        UNSUPPORTED_CALL_COUNT.fetch_add(1, Ordering::SeqCst);
+        // Supported() opening and closing the protocols of the controller does not invalidate its own result.
+        let driver_binding_handle = unsafe { (*this).driver_binding_handle };
+        invalidate_supported_cache_for_usage(controller_handle, Some(driver_binding_handle));
+        efi::Status::UNSUPPORTED
+    }
+
+    extern "efiapi" fn mock_access_denied_with_counter(
+        _this: *mut efi::protocols::driver_binding::Protocol,
+        _controller_handle: efi::Handle,
+        _remaining_device_path: *mut efi::protocols::device_path::Protocol,
+    ) -> efi::Status {
+        UNSUPPORTED_CALL_COUNT.fetch_add(1, Ordering::SeqCst);
+        efi::Status::ACCESS_DENIED
+    }
+
     // Start functions
     extern "efiapi" fn mock_start_success(
         _this: *mut efi::protocols::driver_binding::Protocol,
@@ -1295,6 +1430,130 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_supported_failures_are_cached() {
+        with_locked_state(|| {
+            let (controller_handle, _) = PROTOCOL_DB
+                .install_protocol_interface(
+                    None,
+                    efi::protocols::device_path::PROTOCOL_GUID,
+                    0x1111 as *mut core::ffi::c_void,
+                )
+                .unwrap();
+            let (child_handle, _) = PROTOCOL_DB
+                .install_protocol_interface(
+                    None,
+                    efi::protocols::device_path::PROTOCOL_GUID,
+                    0x3333 as *mut core::ffi::c_void,
+                )
+                .unwrap();
+            let (driver_handle, _) = PROTOCOL_DB
+                .install_protocol_interface(
+                    None,
+                    efi::protocols::device_path::PROTOCOL_GUID,
+                    0x2222 as *mut core::ffi::c_void,
+                )
+                .unwrap();
+
+            let binding = create_driver_binding(
+                10,
+                driver_handle,
+                mock_unsupported_with_counter,
+                mock_start_success,
+                mock_stop_success,
+            );
+            PROTOCOL_DB
+                .install_protocol_interface(
+                    Some(driver_handle),
+                    efi::protocols::driver_binding::PROTOCOL_GUID,
+                    Box::into_raw(binding) as *mut core::ffi::c_void,
+                )
+                .unwrap();
+            PROTOCOL_DB
+                .add_protocol_usage(
+                    controller_handle,
+                    efi::protocols::device_path::PROTOCOL_GUID,
+                    Some(driver_handle),
+                    Some(child_handle),
+                    efi::OPEN_PROTOCOL_BY_CHILD_CONTROLLER,
+                )
+                .unwrap();
+
+            UNSUPPORTED_CALL_COUNT.store(0, Ordering::SeqCst);
+
+            // The failures of the controller and of its child are cached by the first connect.
+            for _ in 0..3 {
+                let result = unsafe { core_connect_controller(controller_handle, Vec::new(), None, true) };
+                assert_eq!(result, Err(EfiError::NotFound));
+            }
+            assert_eq!(UNSUPPORTED_CALL_COUNT.load(Ordering::SeqCst), 2);
+
+            // Supported() is always called when a remaining device path is given.
+            let device_path = Box::into_raw(Box::new(create_vendor_defined_device_path(0)));
+            let result = unsafe { core_connect_controller(controller_handle, Vec::new(), Some(device_path), false) };
+            assert_eq!(result, Err(EfiError::NotFound));
+            assert_eq!(UNSUPPORTED_CALL_COUNT.load(Ordering::SeqCst), 3);
+
+            // A protocol change on the controller only invalidates the failure of the controller.
+            invalidate_supported_cache(controller_handle);
+            let _ = unsafe { core_connect_controller(controller_handle, Vec::new(), None, true) };
+            assert_eq!(UNSUPPORTED_CALL_COUNT.load(Ordering::SeqCst), 4);
+
+            // A reconnect invalidates the failures of the controller and of its children.
+            let _ = unsafe { core_reconnect_controller(controller_handle) };
+            assert_eq!(UNSUPPORTED_CALL_COUNT.load(Ordering::SeqCst), 6);
+
+            // Another agent opening or closing a protocol of the controller invalidates its failures.
+            invalidate_supported_cache_for_usage(controller_handle, Some(child_handle));
+            let _ = unsafe { core_connect_controller(controller_handle, Vec::new(), None, false) };
+            assert_eq!(UNSUPPORTED_CALL_COUNT.load(Ordering::SeqCst), 7);
+            let _ = unsafe { core_connect_controller(controller_handle, Vec::new(), None, false) };
+            assert_eq!(UNSUPPORTED_CALL_COUNT.load(Ordering::SeqCst), 7);
+        });
+    }
+
+    #[test]
+    fn test_supported_failures_other_than_unsupported_are_not_cached() {
+        with_locked_state(|| {
+            let (controller_handle, _) = PROTOCOL_DB
+                .install_protocol_interface(
+                    None,
+                    efi::protocols::device_path::PROTOCOL_GUID,
+                    0x1111 as *mut core::ffi::c_void,
+                )
+                .unwrap();
+            let (driver_handle, _) = PROTOCOL_DB
+                .install_protocol_interface(
+                    None,
+                    efi::protocols::device_path::PROTOCOL_GUID,
+                    0x2222 as *mut core::ffi::c_void,
+                )
+                .unwrap();
+
+            let binding = create_driver_binding(
+                10,
+                driver_handle,
+                mock_access_denied_with_counter,
+                mock_start_success,
+                mock_stop_success,
+            );
+            PROTOCOL_DB
+                .install_protocol_interface(
+                    Some(driver_handle),
+                    efi::protocols::driver_binding::PROTOCOL_GUID,
+                    Box::into_raw(binding) as *mut core::ffi::c_void,
+                )
+                .unwrap();
+
+            UNSUPPORTED_CALL_COUNT.store(0, Ordering::SeqCst);
+            for _ in 0..3 {
+                let result = unsafe { core_connect_controller(controller_handle, Vec::new(), None, false) };
+                assert_eq!(result, Err(EfiError::NotFound));
+            }
+            assert_eq!(UNSUPPORTED_CALL_COUNT.load(Ordering::SeqCst), 3);
+        });
+    }
+
     #[test]
     fn test_connect_controller() {
         with_locked_state(|| {
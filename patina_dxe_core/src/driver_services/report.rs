@@ -0,0 +1,318 @@
+//! DXE Core Driver Binding Failure Report
+//!
+//! ConnectController() and DisconnectController() do not return the status of the Start() and Stop() functions of the
+//! driver bindings they call, so a storage or network controller that fails to start is otherwise silent. Each failure
+//! is logged with the name of the driver image, the device path of the controller and the status, reported as an
+//! error status code carrying [DRIVER_BINDING_FAILURE] data, and counted per driver image. The counters are returned
+//! by [driver_binding_failures], and printed by the `bindings` debugger monitor command.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::{format, string::String, vec::Vec};
+use core::{fmt, ptr};
+
+use mu_rust_helpers::guid::guid_fmt;
+use patina::{
+    guids::{DRIVER_BINDING_FAILURE, DXE_CORE},
+    uefi_protocol::status_code::StatusCodeRuntimeProtocol,
+};
+use patina_internal_device_path::DevicePathText;
+use patina_pi::{
+    protocols::status_code,
+    status_code::{
+        EFI_ERROR_CODE, EFI_ERROR_MAJOR, EFI_SOFTWARE_DXE_BS_DRIVER, EFI_SW_EC_ILLEGAL_SOFTWARE_STATE,
+        EFI_SW_EC_START_ERROR,
+    },
+};
+use r_efi::efi;
+
+use crate::{
+    image::{file_guid_for_handle, image_for_handle},
+    protocols::PROTOCOL_DB,
+    tpl_lock::TplMutex,
+};
+
+/// The driver binding function that failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DriverBindingOperation {
+    /// Start(), called by ConnectController().
+    Start,
+    /// Stop(), called by DisconnectController().
+    Stop,
+}
+
+/// A failure of the Start() or Stop() function of a driver binding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DriverBindingFailure {
+    /// The function that failed.
+    pub operation: DriverBindingOperation,
+    /// The device path of the controller, in its text representation.
+    pub controller: String,
+    /// The status returned by the function.
+    pub status: efi::Status,
+}
+
+/// The failures of the driver bindings of a driver image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DriverBindingFailures {
+    /// The handle of the driver image.
+    pub image_handle: efi::Handle,
+    /// The file name of the driver image from its debug directory, or else the name of its firmware volume file.
+    pub name: String,
+    /// The number of Start() calls that failed.
+    pub start_failures: u32,
+    /// The number of Stop() calls that failed.
+    pub stop_failures: u32,
+    /// The last failure.
+    pub last_failure: DriverBindingFailure,
+}
+
+impl fmt::Display for DriverBindingFailures {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} Start() and {} Stop() failures, last {:?}() on {}: {:?}",
+            self.name,
+            self.start_failures,
+            self.stop_failures,
+            self.last_failure.operation,
+            self.last_failure.controller,
+            self.last_failure.status
+        )
+    }
+}
+
+struct FailureLog(Vec<DriverBindingFailures>);
+
+// The handles of the records are only reported, never dereferenced, so the log is safe to share.
+unsafe impl Send for FailureLog {}
+
+static FAILURE_LOG: TplMutex<FailureLog> =
+    TplMutex::new(efi::TPL_NOTIFY, FailureLog(Vec::new()), "DriverBindingFailureLock");
+
+impl FailureLog {
+    fn record(&mut self, image_handle: efi::Handle, name: impl FnOnce() -> String, failure: DriverBindingFailure) {
+        let index = match self.0.iter().position(|record| record.image_handle == image_handle) {
+            Some(index) => index,
+            None => {
+                self.0.push(DriverBindingFailures {
+                    image_handle,
+                    name: name(),
+                    start_failures: 0,
+                    stop_failures: 0,
+                    last_failure: failure.clone(),
+                });
+                self.0.len() - 1
+            }
+        };
+        let record = &mut self.0[index];
+        match failure.operation {
+            DriverBindingOperation::Start => record.start_failures = record.start_failures.saturating_add(1),
+            DriverBindingOperation::Stop => record.stop_failures = record.stop_failures.saturating_add(1),
+        }
+        record.last_failure = failure;
+    }
+}
+
+/// The data of the error status codes reported for the driver bindings that failed.
+#[repr(C)]
+struct DriverBindingFailureData {
+    file_name: efi::Guid,
+    controller_handle: efi::Handle,
+    status: efi::Status,
+}
+
+fn driver_name(image_handle: efi::Handle) -> String {
+    match image_for_handle(image_handle) {
+        Some(image) => match image.name {
+            Some(name) => name,
+            None => format!("{:?}", guid_fmt!(image.info.file_guid)),
+        },
+        None => format!("<unknown image {image_handle:?}>"),
+    }
+}
+
+fn controller_path(controller_handle: efi::Handle) -> String {
+    let device_path = PROTOCOL_DB
+        .get_interface_for_handle(controller_handle, efi::protocols::device_path::PROTOCOL_GUID)
+        .unwrap_or(ptr::null_mut());
+    // Safety: the device path installed on the controller is valid while the controller is being started or stopped.
+    format!("{}", unsafe { DevicePathText::new(device_path as *const efi::protocols::device_path::Protocol) })
+}
+
+/// Records the `status` returned by `operation` of the driver binding of the image `image_handle` on
+/// `controller_handle`, if it is an error.
+pub(super) fn record_result(
+    operation: DriverBindingOperation,
+    image_handle: efi::Handle,
+    controller_handle: efi::Handle,
+    status: efi::Status,
+) {
+    if !status.is_error() {
+        return;
+    }
+
+    let failure = DriverBindingFailure { operation, controller: controller_path(controller_handle), status };
+    let name = driver_name(image_handle);
+    log::error!("Driver binding {operation:?}() of {name} failed on {}: {status:?}", failure.controller);
+    FAILURE_LOG.lock().record(image_handle, || name, failure);
+
+    report_failure(operation, image_handle, controller_handle, status);
+}
+
+/// Reports the failure as an error status code, if the Status Code Runtime Protocol is installed.
+fn report_failure(
+    operation: DriverBindingOperation,
+    image_handle: efi::Handle,
+    controller_handle: efi::Handle,
+    status: efi::Status,
+) {
+    let value = match operation {
+        DriverBindingOperation::Start => EFI_SOFTWARE_DXE_BS_DRIVER | EFI_SW_EC_START_ERROR,
+        // The controller is still managed by the driver, against the request of the caller.
+        DriverBindingOperation::Stop => EFI_SOFTWARE_DXE_BS_DRIVER | EFI_SW_EC_ILLEGAL_SOFTWARE_STATE,
+    };
+
+    let Ok(status_code_ptr) = PROTOCOL_DB.locate_protocol(status_code::PROTOCOL_GUID) else {
+        log::trace!("Status Code Runtime Protocol not found, driver binding failure not reported as a status code.");
+        return;
+    };
+    // Safety: the Status Code Runtime Protocol interface matches [StatusCodeRuntimeProtocol].
+    let status_code = unsafe { &*(status_code_ptr as *const StatusCodeRuntimeProtocol) };
+    let file_name = file_guid_for_handle(image_handle).unwrap_or(efi::Guid::from_bytes(&[0; 16]));
+    let data = DriverBindingFailureData { file_name, controller_handle, status };
+    if let Err(status) = status_code.report_status_code_with_data(
+        EFI_ERROR_CODE | EFI_ERROR_MAJOR,
+        value,
+        0,
+        &DXE_CORE,
+        DRIVER_BINDING_FAILURE,
+        data,
+    ) {
+        log::error!("Failed to report driver binding failure as a status code: {status:?}");
+    }
+}
+
+/// Registers the `bindings` debugger monitor command printing the failures.
+pub(super) fn init_driver_binding_report() {
+    patina_debugger::add_monitor_command("bindings", "Prints the driver binding failures", |_, out| {
+        for record in FAILURE_LOG.lock().0.iter() {
+            let _ = writeln!(out, "{record}");
+        }
+    });
+}
+
+#[cfg(test)]
+pub(super) fn reset() {
+    FAILURE_LOG.lock().0.clear();
+}
+
+/// Returns the failures of the driver bindings, per driver image, in the order of the first failure of each image.
+pub fn driver_binding_failures() -> Vec<DriverBindingFailures> {
+    FAILURE_LOG.lock().0.clone()
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use alloc::boxed::Box;
+    use core::{
+        ffi::c_void,
+        sync::atomic::{AtomicU32, Ordering},
+    };
+    use patina_pi::protocols::status_code::EfiStatusCodeData;
+
+    static REPORTED_VALUE: AtomicU32 = AtomicU32::new(0);
+
+    extern "efiapi" fn mock_report_status_code(
+        code_type: u32,
+        value: u32,
+        _instance: u32,
+        _caller_id: *const efi::Guid,
+        data: *const EfiStatusCodeData,
+    ) -> efi::Status {
+        assert_eq!(code_type, EFI_ERROR_CODE | EFI_ERROR_MAJOR);
+        // The data is reported in a byte buffer, so it may not be aligned.
+        let header = unsafe { data.read_unaligned() };
+        assert_eq!(header.r#type, DRIVER_BINDING_FAILURE);
+        let failure =
+            unsafe { (data.byte_add(header.header_size as usize) as *const DriverBindingFailureData).read_unaligned() };
+        assert_eq!(failure.controller_handle, 0x20 as efi::Handle);
+        assert_eq!(failure.status, efi::Status::DEVICE_ERROR);
+        REPORTED_VALUE.store(value, Ordering::SeqCst);
+        efi::Status::SUCCESS
+    }
+
+    #[test]
+    fn failures_should_be_counted_per_image() {
+        crate::test_support::with_global_lock(|| {
+            unsafe { crate::test_support::init_test_protocol_db() };
+            reset();
+            let first = 0x1000 as efi::Handle;
+            let second = 0x2000 as efi::Handle;
+            let controller = 0x20 as efi::Handle;
+
+            record_result(DriverBindingOperation::Start, first, controller, efi::Status::SUCCESS);
+            record_result(DriverBindingOperation::Start, first, controller, efi::Status::WARN_UNKNOWN_GLYPH);
+            assert!(driver_binding_failures().is_empty());
+
+            record_result(DriverBindingOperation::Start, first, controller, efi::Status::DEVICE_ERROR);
+            record_result(DriverBindingOperation::Stop, second, controller, efi::Status::DEVICE_ERROR);
+            record_result(DriverBindingOperation::Stop, first, controller, efi::Status::DEVICE_ERROR);
+
+            let failures = driver_binding_failures();
+            assert_eq!(failures.len(), 2);
+            assert_eq!(failures[0].image_handle, first);
+            assert_eq!(failures[0].name, "<unknown image 0x1000>");
+            assert_eq!((failures[0].start_failures, failures[0].stop_failures), (1, 1));
+            assert_eq!(
+                failures[0].last_failure,
+                DriverBindingFailure {
+                    operation: DriverBindingOperation::Stop,
+                    controller: String::from("<no device path>"),
+                    status: efi::Status::DEVICE_ERROR,
+                }
+            );
+            assert_eq!((failures[1].start_failures, failures[1].stop_failures), (0, 1));
+            assert_eq!(
+                format!("{}", failures[1]),
+                format!(
+                    "<unknown image 0x2000>: 0 Start() and 1 Stop() failures, last Stop() on <no device path>: {:?}",
+                    efi::Status::DEVICE_ERROR
+                )
+            );
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn failures_should_be_reported_as_status_codes() {
+        crate::test_support::with_global_lock(|| {
+            unsafe { crate::test_support::init_test_protocol_db() };
+            reset();
+            REPORTED_VALUE.store(0, Ordering::SeqCst);
+
+            let protocol = Box::leak(Box::new(status_code::Protocol { report_status_code: mock_report_status_code }));
+            PROTOCOL_DB
+                .install_protocol_interface(None, status_code::PROTOCOL_GUID, protocol as *mut _ as *mut c_void)
+                .unwrap();
+
+            let image = 0x1000 as efi::Handle;
+            let controller = 0x20 as efi::Handle;
+            record_result(DriverBindingOperation::Start, image, controller, efi::Status::DEVICE_ERROR);
+            assert_eq!(REPORTED_VALUE.load(Ordering::SeqCst), EFI_SOFTWARE_DXE_BS_DRIVER | EFI_SW_EC_START_ERROR);
+
+            record_result(DriverBindingOperation::Stop, image, controller, efi::Status::DEVICE_ERROR);
+            assert_eq!(
+                REPORTED_VALUE.load(Ordering::SeqCst),
+                EFI_SOFTWARE_DXE_BS_DRIVER | EFI_SW_EC_ILLEGAL_SOFTWARE_STATE
+            );
+        })
+        .unwrap();
+    }
+}
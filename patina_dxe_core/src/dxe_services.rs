@@ -314,27 +314,13 @@ extern "efiapi" fn get_io_space_descriptor(
         return efi::Status::INVALID_PARAMETER;
     }
 
-    //Note: this would be more efficient if it was done in the GCD; rather than retrieving all the descriptors and
-    //searching them here. It is done this way for simplicity - it can be optimized if it proves too slow.
-
-    //allocate an empty vector with enough space for all the descriptors with some padding (in the event)
-    //that extra descriptors come into being after creation but before usage.
-    let mut descriptors: Vec<dxe_services::IoSpaceDescriptor> = Vec::with_capacity(GCD.io_descriptor_count() + 10);
-    let result = GCD.get_io_descriptors(&mut descriptors);
-
-    if let Err(err) = result {
-        return efi::Status::from(err);
-    }
-
-    let target_descriptor =
-        descriptors.iter().find(|x| (x.base_address <= base_address) && (base_address < (x.base_address + x.length)));
-
-    if let Some(target_descriptor) = target_descriptor {
-        // Safety: caller must ensure that descriptor is a valid pointer. It is null-checked above.
-        unsafe { descriptor.write_unaligned(*target_descriptor) };
-        efi::Status::SUCCESS
-    } else {
-        efi::Status::NOT_FOUND
+    match GCD.get_io_descriptor_for_address(base_address) {
+        Err(err) => err.into(),
+        Ok(target_descriptor) => {
+            // Safety: caller must ensure that descriptor is a valid pointer. It is null-checked above.
+            unsafe { descriptor.write_unaligned(target_descriptor) };
+            efi::Status::SUCCESS
+        }
     }
 }
 
@@ -434,7 +420,8 @@ extern "efiapi" fn process_firmware_volume(
     efi::Status::SUCCESS
 }
 
-pub fn init_dxe_services(system_table: &mut EfiSystemTable) {
+/// Installs the DXE Services Table in the system table, and returns it. The table lives until the end of the boot.
+pub fn init_dxe_services(system_table: &mut EfiSystemTable) -> &'static dxe_services::DxeServicesTable {
     let mut dxe_system_table = dxe_services::DxeServicesTable {
         header: efi::TableHeader {
             signature: efi::BOOT_SERVICES_SIGNATURE,
@@ -472,12 +459,16 @@ pub fn init_dxe_services(system_table: &mut EfiSystemTable) {
     dxe_system_table.header.crc32 = crc32;
 
     let dxe_system_table = Box::new_in(dxe_system_table, &EFI_RUNTIME_SERVICES_DATA_ALLOCATOR);
+    let dxe_system_table = Box::into_raw_with_allocator(dxe_system_table).0;
 
     let _ = config_tables::core_install_configuration_table(
         dxe_services::DXE_SERVICES_TABLE_GUID,
-        Box::into_raw_with_allocator(dxe_system_table).0 as *mut c_void,
+        dxe_system_table as *mut c_void,
         system_table,
     );
+
+    // SAFETY: The table is leaked above.
+    unsafe { &*dxe_system_table }
 }
 
 #[cfg(test)]
@@ -2259,7 +2250,7 @@ mod tests {
             assert_eq!(st.system_table().number_of_table_entries, 0);
 
             // Act: install the DXE Services table
-            init_dxe_services(st);
+            let installed_table = init_dxe_services(st);
 
             // After: one entry should exist and match DXE_SERVICES_TABLE_GUID
             let st_ref = st.system_table();
@@ -2277,6 +2268,7 @@ mod tests {
 
             // Validate the contents of the installed DXE Services table
             let dxe_tbl = unsafe { &*(entry.vendor_table as *const dxe_services::DxeServicesTable) };
+            assert!(core::ptr::eq(dxe_tbl, installed_table), "The installed DXE Services table should be returned");
 
             // Header signature/revision should match what init_dxe_services sets
             assert_eq!(dxe_tbl.header.signature, efi::BOOT_SERVICES_SIGNATURE);
@@ -2299,4 +2291,147 @@ mod tests {
             assert_eq!(dxe_tbl.process_firmware_volume as usize, process_firmware_volume as usize);
         });
     }
+
+    // Reads the memory space map through the DXE services table function, as a caller walking it would.
+    fn memory_space_map() -> Vec<dxe_services::MemorySpaceDescriptor> {
+        let mut count: usize = 0;
+        let mut map: *mut dxe_services::MemorySpaceDescriptor = core::ptr::null_mut();
+        assert_eq!(get_memory_space_map(&mut count, &mut map), efi::Status::SUCCESS);
+        let descriptors = unsafe { core::slice::from_raw_parts(map, count) }.to_vec();
+        assert!(crate::allocator::core_free_pool(map as *mut core::ffi::c_void).is_ok());
+        descriptors
+    }
+
+    fn io_space_map() -> Vec<dxe_services::IoSpaceDescriptor> {
+        let mut count: usize = 0;
+        let mut map: *mut dxe_services::IoSpaceDescriptor = core::ptr::null_mut();
+        assert_eq!(get_io_space_map(&mut count, &mut map), efi::Status::SUCCESS);
+        let descriptors = unsafe { core::slice::from_raw_parts(map, count) }.to_vec();
+        assert!(crate::allocator::core_free_pool(map as *mut core::ffi::c_void).is_ok());
+        descriptors
+    }
+
+    // Checks that the ranges cover the address space up to `maximum_address` without overlaps, and that no two
+    // adjacent entries are in the same state, as they would have been coalesced.
+    fn assert_coalesced_coverage<T: PartialEq + core::fmt::Debug>(entries: &[(u64, u64, T)], maximum_address: u64) {
+        let mut end = 0;
+        for (base_address, length, _) in entries {
+            assert_eq!(*base_address, end, "gap or overlap in the map at {base_address:#x}");
+            assert_ne!(*length, 0);
+            end = base_address + length;
+        }
+        assert_eq!(end, maximum_address);
+        for pair in entries.windows(2) {
+            assert_ne!(pair[0].2, pair[1].2, "entries at {:#x} and {:#x} were not coalesced", pair[0].0, pair[1].0);
+        }
+    }
+
+    fn memory_space_entries() -> Vec<(u64, u64, (GcdMemoryType, u64, u64, usize, usize))> {
+        memory_space_map()
+            .iter()
+            .map(|d| {
+                let state =
+                    (d.memory_type, d.capabilities, d.attributes, d.image_handle as usize, d.device_handle as usize);
+                (d.base_address, d.length, state)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_memory_space_map_reports_gaps_as_non_existent() {
+        with_locked_state(|| {
+            unsafe {
+                crate::test_support::reset_allocators();
+                crate::test_support::init_test_gcd(None);
+            }
+            const BASE: u64 = 0x8000_0000_0000;
+            const MAXIMUM_ADDRESS: u64 = 1 << 48;
+
+            assert_eq!(
+                add_memory_space(GcdMemoryType::MemoryMappedIo, BASE, 0x10000, efi::MEMORY_UC),
+                efi::Status::SUCCESS
+            );
+            assert_eq!(
+                add_memory_space(GcdMemoryType::SystemMemory, BASE + 0x10000, 0x20000, efi::MEMORY_WB),
+                efi::Status::SUCCESS
+            );
+            assert_eq!(
+                add_memory_space(GcdMemoryType::SystemMemory, BASE + 0x30000, 0x10000, efi::MEMORY_WB),
+                efi::Status::SUCCESS
+            );
+            assert_eq!(remove_memory_space(BASE + 0x10000, 0x10000), efi::Status::SUCCESS);
+
+            // The adjacent system memory additions are coalesced, and the removed range is reported as a non-existent
+            // gap. The expected map is derived from the coalescing rules of the EDK II GCD, not captured from EDK II.
+            let entries = memory_space_entries();
+            assert_coalesced_coverage(&entries, MAXIMUM_ADDRESS);
+            let tail: Vec<_> = entries
+                .iter()
+                .filter(|(base_address, _, _)| *base_address >= BASE)
+                .map(|(base_address, length, state)| (*base_address, *length, state.0))
+                .collect();
+            assert_eq!(
+                tail,
+                [
+                    (BASE, 0x10000, GcdMemoryType::MemoryMappedIo),
+                    (BASE + 0x10000, 0x10000, GcdMemoryType::NonExistent),
+                    (BASE + 0x20000, 0x20000, GcdMemoryType::SystemMemory),
+                    (BASE + 0x40000, MAXIMUM_ADDRESS - BASE - 0x40000, GcdMemoryType::NonExistent),
+                ]
+            );
+
+            // Removing the remaining system memory coalesces the gaps around it.
+            assert_eq!(remove_memory_space(BASE + 0x20000, 0x20000), efi::Status::SUCCESS);
+            let entries = memory_space_entries();
+            assert_coalesced_coverage(&entries, MAXIMUM_ADDRESS);
+            let last = entries.last().unwrap();
+            assert_eq!((last.0, last.2.0, last.2.2), (BASE + 0x10000, GcdMemoryType::NonExistent, 0));
+
+            // Addresses in a gap are described by the gap, and addresses past the address space are not described.
+            let mut descriptor = core::mem::MaybeUninit::<dxe_services::MemorySpaceDescriptor>::uninit();
+            assert_eq!(get_memory_space_descriptor(MAXIMUM_ADDRESS - 1, descriptor.as_mut_ptr()), efi::Status::SUCCESS);
+            assert_eq!(unsafe { descriptor.assume_init() }.base_address, BASE + 0x10000);
+            assert_eq!(get_memory_space_descriptor(MAXIMUM_ADDRESS, descriptor.as_mut_ptr()), efi::Status::NOT_FOUND);
+        });
+    }
+
+    #[test]
+    fn test_io_space_map_reports_gaps_as_non_existent() {
+        with_locked_state(|| {
+            unsafe {
+                crate::test_support::reset_allocators();
+                crate::test_support::init_test_gcd(None);
+            }
+            const MAXIMUM_ADDRESS: u64 = 1 << 16;
+
+            assert_eq!(add_io_space(GcdIoType::Io, 0x2000, 0x100), efi::Status::SUCCESS);
+            assert_eq!(add_io_space(GcdIoType::Io, 0x2100, 0x100), efi::Status::SUCCESS);
+            assert_eq!(add_io_space(GcdIoType::Reserved, 0x2200, 0x80), efi::Status::SUCCESS);
+            assert_eq!(remove_io_space(0x2000, 0x100), efi::Status::SUCCESS);
+
+            let entries: Vec<_> = io_space_map()
+                .iter()
+                .map(|d| (d.base_address, d.length, (d.io_type, d.image_handle as usize, d.device_handle as usize)))
+                .collect();
+            assert_coalesced_coverage(&entries, MAXIMUM_ADDRESS);
+            let types: Vec<_> =
+                entries.iter().map(|(base_address, length, state)| (*base_address, *length, state.0)).collect();
+            assert_eq!(
+                types,
+                [
+                    (0, 0x2100, GcdIoType::NonExistent),
+                    (0x2100, 0x100, GcdIoType::Io),
+                    (0x2200, 0x80, GcdIoType::Reserved),
+                    (0x2280, MAXIMUM_ADDRESS - 0x2280, GcdIoType::NonExistent),
+                ]
+            );
+
+            let mut descriptor = core::mem::MaybeUninit::<dxe_services::IoSpaceDescriptor>::uninit();
+            assert_eq!(get_io_space_descriptor(0x2180, descriptor.as_mut_ptr()), efi::Status::SUCCESS);
+            assert_eq!(unsafe { descriptor.assume_init() }.base_address, 0x2100);
+            assert_eq!(get_io_space_descriptor(0x1000, descriptor.as_mut_ptr()), efi::Status::SUCCESS);
+            assert_eq!(unsafe { descriptor.assume_init() }.io_type, GcdIoType::NonExistent);
+            assert_eq!(get_io_space_descriptor(MAXIMUM_ADDRESS, descriptor.as_mut_ptr()), efi::Status::NOT_FOUND);
+        });
+    }
 }
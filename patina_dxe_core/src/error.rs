@@ -0,0 +1,178 @@
+//! DXE Core Error Type
+//!
+//! This module provides [CoreError], the error type used internally by the DXE core. In addition to the underlying
+//! [EfiError], a [CoreError] records the module and operation that failed, along with an optional address and GUID
+//! describing the object the operation was acting on. This allows failures that propagate up to the core (e.g. a
+//! failed driver dispatch) to be reported with enough context to be actionable.
+//!
+//! [CoreError] converts into both [EfiError] and [efi::Status], so it can be returned through `?` from functions
+//! returning either, and at FFI boundaries.
+//!
+//! ## Example
+//!
+//! ```rust,ignore
+//! use crate::error::{CoreError, ErrorContext, Module};
+//!
+//! fn load(address: u64) -> Result<(), CoreError> {
+//!     allocate(address).context(Module::Allocator, "allocate pages")?;
+//!     relocate(address).map_err(|err| CoreError::new(Module::Image, "relocate image", err).with_address(address))
+//! }
+//! ```
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use core::fmt;
+
+use mu_rust_helpers::guid::guid_fmt;
+use patina::error::EfiError;
+use r_efi::efi;
+
+/// The DXE core module in which an error originated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Module {
+    /// The Global Coherency Domain (GCD).
+    Gcd,
+    /// The UEFI memory allocators.
+    Allocator,
+    /// The UEFI driver dispatcher.
+    Dispatcher,
+    /// The image loader.
+    Image,
+}
+
+/// An error produced by the DXE core, carrying the context in which it occurred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CoreError {
+    module: Module,
+    operation: &'static str,
+    status: EfiError,
+    address: Option<u64>,
+    guid: Option<efi::Guid>,
+}
+
+impl CoreError {
+    /// Creates a new error for the provided module and operation.
+    pub const fn new(module: Module, operation: &'static str, status: EfiError) -> Self {
+        Self { module, operation, status, address: None, guid: None }
+    }
+
+    /// Attaches the address the failing operation was acting on.
+    pub const fn with_address(mut self, address: u64) -> Self {
+        self.address = Some(address);
+        self
+    }
+
+    /// Attaches the GUID (e.g. a file name or protocol) the failing operation was acting on.
+    pub const fn with_guid(mut self, guid: efi::Guid) -> Self {
+        self.guid = Some(guid);
+        self
+    }
+
+    /// Returns the module in which the error originated.
+    pub const fn module(&self) -> Module {
+        self.module
+    }
+
+    /// Returns the operation that failed.
+    pub const fn operation(&self) -> &'static str {
+        self.operation
+    }
+
+    /// Returns the underlying status of the error.
+    pub const fn status(&self) -> EfiError {
+        self.status
+    }
+
+    /// Returns the address the failing operation was acting on, if any.
+    pub const fn address(&self) -> Option<u64> {
+        self.address
+    }
+
+    /// Returns the GUID the failing operation was acting on, if any.
+    pub const fn guid(&self) -> Option<efi::Guid> {
+        self.guid
+    }
+}
+
+impl fmt::Display for CoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}: {} failed with {:?}", self.module, self.operation, self.status)?;
+        if let Some(address) = self.address {
+            write!(f, " at {address:#x}")?;
+        }
+        if let Some(guid) = self.guid {
+            write!(f, " for {:?}", guid_fmt!(guid))?;
+        }
+        Ok(())
+    }
+}
+
+impl From<CoreError> for EfiError {
+    fn from(err: CoreError) -> Self {
+        err.status
+    }
+}
+
+impl From<CoreError> for efi::Status {
+    fn from(err: CoreError) -> Self {
+        err.status.into()
+    }
+}
+
+/// Extension trait for attaching [CoreError] context to results carrying an [EfiError].
+pub trait ErrorContext<T> {
+    /// Converts the error into a [CoreError] for the provided module and operation.
+    fn context(self, module: Module, operation: &'static str) -> Result<T, CoreError>;
+}
+
+impl<T> ErrorContext<T> for Result<T, EfiError> {
+    fn context(self, module: Module, operation: &'static str) -> Result<T, CoreError> {
+        self.map_err(|status| CoreError::new(module, operation, status))
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::string::ToString;
+
+    const GUID: efi::Guid =
+        efi::Guid::from_fields(0x15853d7c, 0x3ddf, 0x43e0, 0xa1, 0xcb, &[0xeb, 0xf8, 0x5b, 0x8f, 0x87, 0x2c]);
+
+    #[test]
+    fn test_context_preserves_status() {
+        let result: Result<(), EfiError> = Err(EfiError::OutOfResources);
+        let err = result.context(Module::Allocator, "allocate pages").unwrap_err();
+
+        assert_eq!(err.module(), Module::Allocator);
+        assert_eq!(err.operation(), "allocate pages");
+        assert_eq!(err.status(), EfiError::OutOfResources);
+        assert_eq!(err.address(), None);
+        assert_eq!(err.guid(), None);
+        assert_eq!(EfiError::from(err), EfiError::OutOfResources);
+        assert_eq!(efi::Status::from(err), efi::Status::OUT_OF_RESOURCES);
+
+        assert_eq!(Ok::<u32, EfiError>(5).context(Module::Gcd, "add memory space"), Ok(5));
+    }
+
+    #[test]
+    fn test_display_includes_context() {
+        let err = CoreError::new(Module::Image, "relocate image", EfiError::LoadError);
+        assert_eq!(err.to_string(), "Image: relocate image failed with LoadError");
+
+        let err = err.with_address(0x1000).with_guid(GUID);
+        assert_eq!(err.address(), Some(0x1000));
+        assert_eq!(err.guid(), Some(GUID));
+        assert_eq!(
+            err.to_string(),
+            "Image: relocate image failed with LoadError at 0x1000 for 15853D7C-3DDF-43E0-A1CB-EBF85B8F872C"
+        );
+    }
+}
@@ -500,7 +500,13 @@ impl EventDb {
                 && trigger_time <= current_time
             {
                 if let Some(period) = current_event.period {
-                    current_event.trigger_time = Some(current_time + period);
+                    // Re-arm on the period boundaries of the trigger time, so that the timer does not drift when the
+                    // tick does not fall on its trigger time. Periods that elapsed entirely are skipped.
+                    current_event.trigger_time = Some(
+                        (current_time - trigger_time)
+                            .checked_div(period)
+                            .map_or(current_time, |elapsed| trigger_time + period * (elapsed + 1)),
+                    );
                 } else {
                     //no period means it's a one-shot event; another call to set_timer is required to "re-arm"
                     current_event.trigger_time = None;
@@ -538,6 +544,10 @@ impl EventDb {
     fn is_valid(&mut self, event: efi::Event) -> bool {
         self.events.contains_key(&(event as usize))
     }
+
+    fn next_trigger_time(&self) -> Option<u64> {
+        self.events.values().filter(|event| event.event_type.is_timer()).filter_map(|event| event.trigger_time).min()
+    }
 }
 
 /// Spin-Locked event database instance.
@@ -675,7 +685,6 @@ impl SpinLockedEventDb {
     /// ## Errors
     ///
     /// Returns r_efi:efi::Status::INVALID_PARAMETER if incorrect parameters are given.
-    #[allow(dead_code)]
     pub fn get_notification_data(&self, event: efi::Event) -> Result<EventNotification, EfiError> {
         self.lock().get_notification_data(event)
     }
@@ -728,6 +737,11 @@ impl SpinLockedEventDb {
     pub fn is_valid(&self, event: efi::Event) -> bool {
         self.lock().is_valid(event)
     }
+
+    /// Returns the earliest trigger time of the armed timer events, if any.
+    pub fn next_trigger_time(&self) -> Option<u64> {
+        self.lock().next_trigger_time()
+    }
 }
 
 unsafe impl Send for SpinLockedEventDb {}
@@ -1760,4 +1774,36 @@ mod tests {
             assert_eq!(event_iter.count(), 0);
         });
     }
+
+    #[test]
+    fn periodic_timers_should_not_drift() {
+        with_locked_state(|| {
+            static SPIN_LOCKED_EVENT_DB: SpinLockedEventDb = SpinLockedEventDb::new();
+
+            let event = SPIN_LOCKED_EVENT_DB
+                .create_event(
+                    efi::EVT_TIMER | efi::EVT_NOTIFY_SIGNAL,
+                    efi::TPL_NOTIFY,
+                    Some(test_notify_function),
+                    None,
+                    None,
+                )
+                .unwrap();
+            assert_eq!(SPIN_LOCKED_EVENT_DB.next_trigger_time(), None);
+
+            SPIN_LOCKED_EVENT_DB.set_timer(event, TimerDelay::Periodic, Some(0x150), Some(0x150)).unwrap();
+            assert_eq!(SPIN_LOCKED_EVENT_DB.next_trigger_time(), Some(0x150));
+
+            // A tick after the trigger time re-arms the timer on the next period boundary.
+            SPIN_LOCKED_EVENT_DB.timer_tick(0x200);
+            assert_eq!(SPIN_LOCKED_EVENT_DB.next_trigger_time(), Some(0x2A0));
+
+            // Periods that elapsed entirely between two ticks are skipped.
+            SPIN_LOCKED_EVENT_DB.timer_tick(0x700);
+            assert_eq!(SPIN_LOCKED_EVENT_DB.next_trigger_time(), Some(0x7E0));
+
+            SPIN_LOCKED_EVENT_DB.set_timer(event, TimerDelay::Cancel, None, None).unwrap();
+            assert_eq!(SPIN_LOCKED_EVENT_DB.next_trigger_time(), None);
+        });
+    }
 }
@@ -6,9 +6,13 @@
 //!
 //! SPDX-License-Identifier: Apache-2.0
 //!
+mod tickless;
+mod tpl_diagnostics;
+
 use core::{
     ffi::c_void,
     sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    time::Duration,
 };
 
 use r_efi::efi;
@@ -18,12 +22,15 @@ use patina_pi::protocols::timer;
 use patina_internal_cpu::interrupts;
 
 use crate::{
+    component_lifecycle,
+    core_context::ContextStatic,
     event_db::{SpinLockedEventDb, TimerDelay},
     gcd,
+    memory_attributes_table::MemoryAttributesTable,
     protocols::PROTOCOL_DB,
 };
 
-pub static EVENT_DB: SpinLockedEventDb = SpinLockedEventDb::new();
+pub static EVENT_DB: ContextStatic<SpinLockedEventDb> = ContextStatic::new(|context| &context.event_db);
 
 static CURRENT_TPL: AtomicUsize = AtomicUsize::new(efi::TPL_APPLICATION);
 static SYSTEM_TIME: AtomicU64 = AtomicU64::new(0);
@@ -53,6 +60,7 @@ extern "efiapi" fn create_event(
         Ok(new_event) => {
             // Safety: caller must ensure that event is a valid pointer. It is null-checked above.
             unsafe { event.write_unaligned(new_event) };
+            component_lifecycle::record_event(new_event, event_type, event_group.as_ref());
             efi::Status::SUCCESS
         }
         Err(err) => err.into(),
@@ -87,6 +95,7 @@ extern "efiapi" fn create_event_ex(
         Ok(new_event) => {
             // Safety: caller must ensure that event is a valid pointer. It is null-checked above.
             unsafe { event.write_unaligned(new_event) };
+            component_lifecycle::record_event(new_event, event_type, event_group.as_ref());
             efi::Status::SUCCESS
         }
         Err(err) => err.into(),
@@ -95,12 +104,17 @@ extern "efiapi" fn create_event_ex(
 
 pub extern "efiapi" fn close_event(event: efi::Event) -> efi::Status {
     match EVENT_DB.close_event(event) {
-        Ok(()) => efi::Status::SUCCESS,
+        Ok(()) => {
+            component_lifecycle::record_event_close(event);
+            efi::Status::SUCCESS
+        }
         Err(err) => err.into(),
     }
 }
 
 pub extern "efiapi" fn signal_event(event: efi::Event) -> efi::Status {
+    tpl_diagnostics::check_signal(event, CURRENT_TPL.load(Ordering::SeqCst));
+
     let status = match EVENT_DB.signal_event(event) {
         Ok(()) => efi::Status::SUCCESS,
         Err(err) => err.into(),
@@ -199,12 +213,15 @@ pub extern "efiapi" fn set_timer(event: efi::Event, timer_type: efi::TimerDelay,
 
     let (trigger_time, period) = match timer_type {
         TimerDelay::Cancel => (None, None),
-        TimerDelay::Relative => (Some(SYSTEM_TIME.load(Ordering::SeqCst) + trigger_time), None),
-        TimerDelay::Periodic => (Some(SYSTEM_TIME.load(Ordering::SeqCst) + trigger_time), Some(trigger_time)),
+        TimerDelay::Relative => (Some(tickless::current_time() + trigger_time), None),
+        TimerDelay::Periodic => (Some(tickless::current_time() + trigger_time), Some(trigger_time)),
     };
 
     match EVENT_DB.set_timer(event, timer_type, trigger_time, period) {
-        Ok(()) => efi::Status::SUCCESS,
+        Ok(()) => {
+            tickless::timer_set();
+            efi::Status::SUCCESS
+        }
         Err(err) => err.into(),
     }
 }
@@ -226,6 +243,8 @@ pub extern "efiapi" fn raise_tpl(new_tpl: efi::Tpl) -> efi::Tpl {
 }
 
 pub extern "efiapi" fn restore_tpl(new_tpl: efi::Tpl) {
+    tpl_diagnostics::check_restore_tpl(new_tpl);
+
     let prev_tpl = CURRENT_TPL.fetch_min(new_tpl, Ordering::SeqCst);
 
     assert!(
@@ -276,7 +295,19 @@ pub extern "efiapi" fn restore_tpl(new_tpl: efi::Tpl) {
             //callbacks as "unsafe", and the r_efi definition for EventNotify would need to
             //change.
             if let Some(notify_function) = event.notify_function {
+                let scope = tpl_diagnostics::notify_started(
+                    notify_function,
+                    event.notify_tpl,
+                    SYSTEM_TIME.load(Ordering::SeqCst),
+                );
                 (notify_function)(event.event, notify_context);
+                if let Some(scope) = scope {
+                    tpl_diagnostics::notify_finished(
+                        scope,
+                        CURRENT_TPL.load(Ordering::SeqCst),
+                        SYSTEM_TIME.load(Ordering::SeqCst),
+                    );
+                }
             }
         }
     }
@@ -287,11 +318,24 @@ pub extern "efiapi" fn restore_tpl(new_tpl: efi::Tpl) {
     CURRENT_TPL.store(new_tpl, Ordering::SeqCst);
 }
 
-extern "efiapi" fn timer_tick(time: u64) {
+/// Returns the current TPL.
+pub fn current_tpl() -> efi::Tpl {
+    CURRENT_TPL.load(Ordering::SeqCst)
+}
+
+/// Restores the TPL to `tpl` after a notification function terminated the running image, abandoning the stack of the
+/// image along with the dispatch of the notification.
+pub fn resume_after_image_termination(tpl: efi::Tpl) {
+    tpl_diagnostics::notifies_abandoned();
+    restore_tpl(tpl);
+}
+
+pub(crate) extern "efiapi" fn timer_tick(time: u64) {
     let old_tpl = raise_tpl(efi::TPL_HIGH_LEVEL);
     SYSTEM_TIME.fetch_add(time, Ordering::SeqCst);
     let current_time = SYSTEM_TIME.load(Ordering::SeqCst);
     EVENT_DB.timer_tick(current_time);
+    tickless::timer_interrupt();
     restore_tpl(old_tpl); //implicitly dispatches timer notifies if any.
 }
 
@@ -301,6 +345,7 @@ extern "efiapi" fn timer_available_callback(event: efi::Event, _context: *mut c_
             let timer_arch_ptr = timer_arch_ptr as *mut timer::Protocol;
             let timer_arch = unsafe { &*(timer_arch_ptr) };
             (timer_arch.register_handler)(timer_arch_ptr, timer_tick);
+            tickless::register_timer(timer_arch_ptr);
             if let Err(status_err) = EVENT_DB.close_event(event) {
                 log::warn!("Could not close event for timer_available_callback due to error {status_err:?}");
             }
@@ -309,18 +354,35 @@ extern "efiapi" fn timer_available_callback(event: efi::Event, _context: *mut c_
     }
 }
 
+/// Enables the detection of TPL misuse around event notifications. Notification functions running for longer than
+/// `notify_time_limit` are reported, unless it is zero.
+pub fn enable_tpl_diagnostics(notify_time_limit: Duration) {
+    tpl_diagnostics::enable(notify_time_limit);
+}
+
+/// Enables the tickless timer mode, in which the timer period is reprogrammed to expire at the next timer event
+/// deadline, between `min_period` and `max_period`. Must be called before the timer architectural protocol is
+/// installed.
+pub fn enable_tickless_timer(min_period: Duration, max_period: Duration) {
+    tickless::enable(min_period, max_period);
+}
+
 // indicates that eventing subsystem is fully initialized.
 static EVENT_DB_INITIALIZED: AtomicBool = AtomicBool::new(false);
 
-/// This callback is invoked whenever the GCD changes, and will signal the required UEFI event group.
+/// This callback is invoked whenever the GCD changes, and will signal the required UEFI event group. Attribute changes
+/// are forwarded to the MAT, which describes the attributes of the runtime memory.
 pub fn gcd_map_change(map_change_type: gcd::MapChangeType) {
+    if let gcd::MapChangeType::SetMemoryAttributes { base_address, length } = map_change_type {
+        MemoryAttributesTable::attributes_changed(base_address, length);
+    }
     if EVENT_DB_INITIALIZED.load(Ordering::SeqCst) {
         match map_change_type {
             gcd::MapChangeType::AddMemorySpace
             | gcd::MapChangeType::AllocateMemorySpace
             | gcd::MapChangeType::FreeMemorySpace
             | gcd::MapChangeType::RemoveMemorySpace => EVENT_DB.signal_group(efi::EVENT_GROUP_MEMORY_MAP_CHANGE),
-            gcd::MapChangeType::SetMemoryAttributes | gcd::MapChangeType::SetMemoryCapabilities => (),
+            gcd::MapChangeType::SetMemoryAttributes { .. } | gcd::MapChangeType::SetMemoryCapabilities => (),
         }
     }
 }
@@ -599,6 +661,9 @@ mod tests {
             let wait_time = 500u64;
             let result = set_timer(event, 1 /* TimerDelay::Relative */, wait_time);
             assert_eq!(result, efi::Status::SUCCESS);
+
+            let _ = close_event(event);
+            SYSTEM_TIME.store(0, Ordering::SeqCst);
         })
     }
 
@@ -1038,7 +1103,7 @@ mod tests {
             gcd_map_change(gcd::MapChangeType::AllocateMemorySpace);
             gcd_map_change(gcd::MapChangeType::FreeMemorySpace);
             gcd_map_change(gcd::MapChangeType::RemoveMemorySpace);
-            gcd_map_change(gcd::MapChangeType::SetMemoryAttributes);
+            gcd_map_change(gcd::MapChangeType::SetMemoryAttributes { base_address: 0, length: 0x1000 });
             gcd_map_change(gcd::MapChangeType::SetMemoryCapabilities);
 
             // Reset initialized flag
@@ -0,0 +1,379 @@
+//! Tickless Timer Mode
+//!
+//! The timer architectural protocol interrupts the processor periodically, and each interrupt advances the system time
+//! and signals the expired timer events. In tickless mode, enabled with [enable], the timer period is reprogrammed
+//! after each interrupt to expire at the earliest trigger time of the armed timer events, within a minimum and a
+//! maximum period. Long waits without an earlier deadline, e.g. a BDS timeout, then only wake the processor up at the
+//! maximum period instead of at every tick of the fixed period.
+//!
+//! The timer is also reprogrammed when a timer event is set to expire before the next interrupt. The timer drivers
+//! restart the period when it is set, and report the new period as the time elapsed at the next interrupt. The part of
+//! the interrupted period that already elapsed is measured with the performance counter and credited to the system
+//! time when the timer is reprogrammed, so that the system time does not fall behind. Timer events are set relative to
+//! the system time including that part, see [current_time].
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use core::{
+    ptr,
+    sync::atomic::{AtomicPtr, AtomicU64, Ordering},
+    time::Duration,
+};
+
+use patina::performance::timer::{ArchPerfTimer, PerfTimer};
+use patina_pi::protocols::timer;
+use r_efi::efi;
+use spin::RwLock;
+
+use super::{EVENT_DB, SYSTEM_TIME, raise_tpl, restore_tpl};
+
+/// The number of 100ns timer units in a second.
+const TIMER_UNITS_PER_SECOND: u128 = 10_000_000;
+
+/// The minimum timer period in 100ns units. Zero while tickless mode is disabled.
+static MIN_PERIOD: AtomicU64 = AtomicU64::new(0);
+/// The maximum timer period in 100ns units.
+static MAX_PERIOD: AtomicU64 = AtomicU64::new(0);
+/// The timer architectural protocol. Null until it is installed, or if tickless mode is disabled.
+static TIMER_ARCH: AtomicPtr<timer::Protocol> = AtomicPtr::new(ptr::null_mut());
+/// The period the timer was installed with, restored if the timer does not support a period.
+static DEFAULT_PERIOD: AtomicU64 = AtomicU64::new(0);
+/// The period currently programmed in the timer.
+static PERIOD: AtomicU64 = AtomicU64::new(0);
+/// The system time of the next timer interrupt.
+static NEXT_INTERRUPT: AtomicU64 = AtomicU64::new(u64::MAX);
+/// The free-running counter measuring the time elapsed in the current period.
+static COUNTER: RwLock<&'static (dyn PerfTimer + Sync)> = RwLock::new(&ArchPerfTimer);
+/// The value of the counter when the current period started.
+static PERIOD_START: AtomicU64 = AtomicU64::new(0);
+
+/// Converts `duration` to 100ns units, rounding up to at least one unit.
+fn to_timer_units(duration: Duration) -> u64 {
+    u64::try_from(duration.as_nanos() / 100).unwrap_or(u64::MAX).max(1)
+}
+
+/// Enables tickless mode, programming timer periods between `min_period` and `max_period` once the timer architectural
+/// protocol is installed. Must be called before the timer architectural protocol is installed.
+pub fn enable(min_period: Duration, max_period: Duration) {
+    let min_period = to_timer_units(min_period);
+    MAX_PERIOD.store(to_timer_units(max_period).max(min_period), Ordering::SeqCst);
+    MIN_PERIOD.store(min_period, Ordering::SeqCst);
+}
+
+fn is_enabled() -> bool {
+    MIN_PERIOD.load(Ordering::Relaxed) != 0
+}
+
+/// Returns the value of the counter, and the time in 100ns units elapsed since the start of the current period.
+fn read_counter() -> (u64, u64) {
+    let counter = COUNTER.read();
+    let count = counter.cpu_count();
+    let ticks = count.wrapping_sub(PERIOD_START.load(Ordering::SeqCst)) as u128;
+    let elapsed = match counter.perf_frequency() as u128 {
+        0 => 0,
+        frequency => u64::try_from(ticks * TIMER_UNITS_PER_SECOND / frequency).unwrap_or(u64::MAX),
+    };
+    // The period is restarted at the next interrupt, which cannot be later than the end of the period.
+    (count, elapsed.min(PERIOD.load(Ordering::SeqCst)))
+}
+
+/// Returns the system time, including the part of the current period already elapsed in tickless mode.
+pub fn current_time() -> u64 {
+    if TIMER_ARCH.load(Ordering::Relaxed).is_null() {
+        return SYSTEM_TIME.load(Ordering::SeqCst);
+    }
+    let old_tpl = raise_tpl(efi::TPL_HIGH_LEVEL);
+    let current_time = SYSTEM_TIME.load(Ordering::SeqCst).saturating_add(read_counter().1);
+    restore_tpl(old_tpl);
+    current_time
+}
+
+/// Takes over the period of the `timer_arch` timer, once its handler is registered, if tickless mode is enabled.
+pub fn register_timer(timer_arch: *mut timer::Protocol) {
+    if !is_enabled() || timer_arch.is_null() {
+        return;
+    }
+
+    let mut default_period = 0;
+    // Safety: timer_arch is the installed timer architectural protocol, checked for null above.
+    let status = unsafe { ((*timer_arch).get_timer_period)(timer_arch, &mut default_period) };
+    if status.is_error() {
+        log::error!("Tickless timer mode disabled: failed to get the timer period: {status:#x?}");
+        MIN_PERIOD.store(0, Ordering::SeqCst);
+        return;
+    }
+    DEFAULT_PERIOD.store(default_period, Ordering::SeqCst);
+    PERIOD.store(default_period, Ordering::SeqCst);
+    TIMER_ARCH.store(timer_arch, Ordering::SeqCst);
+    program_timer(false);
+}
+
+/// Programs the timer for the next deadline after a timer interrupt. Called at TPL_HIGH_LEVEL.
+pub fn timer_interrupt() {
+    program_timer(false);
+}
+
+/// Programs the timer earlier if a timer event was set to expire before the next interrupt.
+pub fn timer_set() {
+    if TIMER_ARCH.load(Ordering::Relaxed).is_null() {
+        return;
+    }
+    let old_tpl = raise_tpl(efi::TPL_HIGH_LEVEL);
+    program_timer(true);
+    restore_tpl(old_tpl);
+}
+
+/// Programs the timer to interrupt at the earliest trigger time of the timer events, within the minimum and maximum
+/// periods. If `only_if_earlier` is set, the timer is only programmed if it interrupts before the next interrupt,
+/// otherwise it is called at the start of a period, after an interrupt.
+fn program_timer(only_if_earlier: bool) {
+    let timer_arch = TIMER_ARCH.load(Ordering::SeqCst);
+    if timer_arch.is_null() {
+        return;
+    }
+
+    let (count, elapsed) = match only_if_earlier {
+        true => read_counter(),
+        false => (COUNTER.read().cpu_count(), 0),
+    };
+    let current_time = SYSTEM_TIME.load(Ordering::SeqCst).saturating_add(elapsed);
+    let (min_period, max_period) = (MIN_PERIOD.load(Ordering::SeqCst), MAX_PERIOD.load(Ordering::SeqCst));
+    let period = EVENT_DB
+        .next_trigger_time()
+        .map_or(max_period, |trigger_time| trigger_time.saturating_sub(current_time))
+        .clamp(min_period, max_period);
+    let next_interrupt = current_time.saturating_add(period);
+    if only_if_earlier && next_interrupt >= NEXT_INTERRUPT.load(Ordering::SeqCst) {
+        return;
+    }
+    NEXT_INTERRUPT.store(next_interrupt, Ordering::SeqCst);
+    PERIOD_START.store(count, Ordering::SeqCst);
+
+    // After an interrupt, a timer programmed with the same period already interrupts at the next deadline.
+    if PERIOD.swap(period, Ordering::SeqCst) == period && !only_if_earlier {
+        return;
+    }
+
+    // The timer restarts its period when it is programmed, and only reports the new period at the next interrupt.
+    SYSTEM_TIME.fetch_add(elapsed, Ordering::SeqCst);
+
+    // Safety: timer_arch is the installed timer architectural protocol, checked for null above.
+    let status = unsafe { ((*timer_arch).set_timer_period)(timer_arch, period) };
+    if status.is_error() {
+        log::error!("Tickless timer mode disabled: failed to set the timer period to {period:#x}: {status:#x?}");
+        MIN_PERIOD.store(0, Ordering::SeqCst);
+        TIMER_ARCH.store(ptr::null_mut(), Ordering::SeqCst);
+        NEXT_INTERRUPT.store(u64::MAX, Ordering::SeqCst);
+        // Safety: as above.
+        let _ = unsafe { ((*timer_arch).set_timer_period)(timer_arch, DEFAULT_PERIOD.load(Ordering::SeqCst)) };
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::{events::SYSTEM_TIME, test_support};
+    use core::ffi::c_void;
+    use std::{sync::Mutex, vec::Vec};
+
+    const DEFAULT_TICK: u64 = 100;
+
+    static PROGRAMMED_PERIOD: AtomicU64 = AtomicU64::new(DEFAULT_TICK);
+    static FIRING_TIMES: Mutex<Vec<u64>> = Mutex::new(Vec::new());
+    static COUNT: AtomicU64 = AtomicU64::new(0);
+
+    // A counter in 100ns units, advanced by the emulated timer hardware.
+    struct MockCounter;
+
+    impl PerfTimer for MockCounter {
+        fn cpu_count(&self) -> u64 {
+            COUNT.load(Ordering::SeqCst)
+        }
+
+        fn perf_frequency(&self) -> u64 {
+            TIMER_UNITS_PER_SECOND as u64
+        }
+    }
+
+    extern "efiapi" fn mock_register_handler(
+        _this: *mut timer::Protocol,
+        _notify: timer::EfiTimerNotify,
+    ) -> efi::Status {
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn mock_set_timer_period(_this: *mut timer::Protocol, period: u64) -> efi::Status {
+        PROGRAMMED_PERIOD.store(period, Ordering::SeqCst);
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn mock_get_timer_period(_this: *mut timer::Protocol, period: *mut u64) -> efi::Status {
+        unsafe { period.write(PROGRAMMED_PERIOD.load(Ordering::SeqCst)) };
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn mock_generate_soft_interrupt(_this: *mut timer::Protocol) -> efi::Status {
+        efi::Status::UNSUPPORTED
+    }
+
+    extern "efiapi" fn record_firing_time(_event: efi::Event, _context: *mut c_void) {
+        FIRING_TIMES.lock().unwrap().push(SYSTEM_TIME.load(Ordering::SeqCst));
+    }
+
+    // Runs the state of the timer and the events from a system time of zero, restoring it afterwards.
+    fn with_timer_state<F: Fn() + std::panic::RefUnwindSafe>(f: F) {
+        test_support::with_global_lock(|| {
+            let original_time = SYSTEM_TIME.swap(0, Ordering::SeqCst);
+            PROGRAMMED_PERIOD.store(DEFAULT_TICK, Ordering::SeqCst);
+            FIRING_TIMES.lock().unwrap().clear();
+            COUNT.store(0, Ordering::SeqCst);
+            *COUNTER.write() = &MockCounter;
+            f();
+            MIN_PERIOD.store(0, Ordering::SeqCst);
+            TIMER_ARCH.store(ptr::null_mut(), Ordering::SeqCst);
+            NEXT_INTERRUPT.store(u64::MAX, Ordering::SeqCst);
+            *COUNTER.write() = &ArchPerfTimer;
+            SYSTEM_TIME.store(original_time, Ordering::SeqCst);
+        })
+        .unwrap();
+    }
+
+    // Creates a timer event recording its firing times, armed with `timer_type` and `trigger_time`.
+    fn create_timer(timer_type: efi::TimerDelay, trigger_time: u64) -> efi::Event {
+        let mut event = ptr::null_mut();
+        let status = super::super::create_event(
+            efi::EVT_TIMER | efi::EVT_NOTIFY_SIGNAL,
+            efi::TPL_CALLBACK,
+            Some(record_firing_time),
+            ptr::null_mut(),
+            &mut event,
+        );
+        assert_eq!(status, efi::Status::SUCCESS);
+        assert_eq!(super::super::set_timer(event, timer_type, trigger_time), efi::Status::SUCCESS);
+        event
+    }
+
+    // Emulates the timer hardware until `end_time`, interrupting at the programmed period. Returns the interrupt count.
+    fn run_timer(end_time: u64) -> usize {
+        let mut interrupts = 0;
+        while SYSTEM_TIME.load(Ordering::SeqCst) < end_time {
+            let period = PROGRAMMED_PERIOD.load(Ordering::SeqCst);
+            COUNT.fetch_add(period, Ordering::SeqCst);
+            super::super::timer_tick(period);
+            interrupts += 1;
+        }
+        interrupts
+    }
+
+    fn new_timer_arch() -> &'static mut timer::Protocol {
+        std::boxed::Box::leak(std::boxed::Box::new(timer::Protocol {
+            register_handler: mock_register_handler,
+            set_timer_period: mock_set_timer_period,
+            get_timer_period: mock_get_timer_period,
+            generate_soft_interrupt: mock_generate_soft_interrupt,
+        }))
+    }
+
+    #[test]
+    fn test_periodic_timer_does_not_drift_with_a_fixed_tick() {
+        with_timer_state(|| {
+            const PERIOD: u64 = 150;
+            let event = create_timer(efi::TIMER_PERIODIC, PERIOD);
+            run_timer(30 * PERIOD);
+            let _ = super::super::close_event(event);
+
+            // Each firing is late by less than a tick, without accumulating over the periods.
+            let firing_times = FIRING_TIMES.lock().unwrap().clone();
+            assert_eq!(firing_times.len(), 30);
+            for (index, time) in firing_times.iter().enumerate() {
+                let expected = (index as u64 + 1) * PERIOD;
+                assert!((expected..expected + DEFAULT_TICK).contains(time), "fired at {time}, expected {expected}");
+            }
+        });
+    }
+
+    #[test]
+    fn test_tickless_timer_fires_events_on_time() {
+        with_timer_state(|| {
+            const PERIOD: u64 = 150;
+            enable(Duration::from_micros(1), Duration::from_millis(10));
+            register_timer(new_timer_arch());
+            assert_eq!(PROGRAMMED_PERIOD.load(Ordering::SeqCst), 100_000);
+
+            let periodic = create_timer(efi::TIMER_PERIODIC, PERIOD);
+            assert_eq!(PROGRAMMED_PERIOD.load(Ordering::SeqCst), PERIOD);
+            let one_shot = create_timer(efi::TIMER_RELATIVE, 1000);
+            let interrupts = run_timer(30 * PERIOD);
+            let _ = super::super::close_event(periodic);
+            let _ = super::super::close_event(one_shot);
+
+            // The timer only interrupts at the deadlines, so the events fire exactly on time.
+            let firing_times = FIRING_TIMES.lock().unwrap().clone();
+            let mut expected: Vec<u64> = (1..=30).map(|index| index * PERIOD).collect();
+            expected.push(1000);
+            expected.sort();
+            assert_eq!(firing_times, expected);
+            assert_eq!(interrupts, 31);
+
+            // Without a pending deadline, the timer is programmed with the maximum period.
+            run_timer(SYSTEM_TIME.load(Ordering::SeqCst) + 1);
+            assert_eq!(PROGRAMMED_PERIOD.load(Ordering::SeqCst), 100_000);
+        });
+    }
+
+    #[test]
+    fn test_tickless_timer_clamps_to_the_minimum_period() {
+        with_timer_state(|| {
+            enable(Duration::from_micros(50), Duration::from_millis(10));
+            register_timer(new_timer_arch());
+
+            let event = create_timer(efi::TIMER_PERIODIC, 100);
+            assert_eq!(PROGRAMMED_PERIOD.load(Ordering::SeqCst), 500);
+            run_timer(5000);
+            let _ = super::super::close_event(event);
+
+            // The events fire at the first interrupt after their deadline.
+            let firing_times = FIRING_TIMES.lock().unwrap().clone();
+            assert_eq!(firing_times, (1..=10).map(|index| index * 500).collect::<Vec<_>>());
+        });
+    }
+
+    #[test]
+    fn test_tickless_timer_credits_the_elapsed_time_when_reprogrammed() {
+        with_timer_state(|| {
+            enable(Duration::from_micros(1), Duration::from_millis(10));
+            register_timer(new_timer_arch());
+            assert_eq!(PROGRAMMED_PERIOD.load(Ordering::SeqCst), 100_000);
+
+            // Part of the way into the period, an event is set to expire before the next interrupt.
+            COUNT.store(40_000, Ordering::SeqCst);
+            let event = create_timer(efi::TIMER_RELATIVE, 1000);
+
+            // The elapsed part of the period is credited to the system time when the timer is reprogrammed.
+            assert_eq!(SYSTEM_TIME.load(Ordering::SeqCst), 40_000);
+            assert_eq!(PROGRAMMED_PERIOD.load(Ordering::SeqCst), 1000);
+            run_timer(41_000);
+            let _ = super::super::close_event(event);
+            assert_eq!(FIRING_TIMES.lock().unwrap().clone(), [41_000]);
+            assert_eq!(SYSTEM_TIME.load(Ordering::SeqCst), COUNT.load(Ordering::SeqCst));
+
+            // An event expiring after the next interrupt does not reprogram the timer, so nothing is credited and the
+            // interrupt at the end of the period reports the whole period.
+            COUNT.store(41_500, Ordering::SeqCst);
+            let event = create_timer(efi::TIMER_RELATIVE, 200_000);
+            assert_eq!(SYSTEM_TIME.load(Ordering::SeqCst), 41_000);
+            assert_eq!(PROGRAMMED_PERIOD.load(Ordering::SeqCst), 100_000);
+            COUNT.store(141_000, Ordering::SeqCst);
+            super::super::timer_tick(100_000);
+            let _ = super::super::close_event(event);
+            assert_eq!(SYSTEM_TIME.load(Ordering::SeqCst), 141_000);
+        });
+    }
+}
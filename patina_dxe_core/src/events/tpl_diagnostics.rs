@@ -0,0 +1,305 @@
+//! Event Notification TPL Diagnostics
+//!
+//! Detects common misuse of the task priority level (TPL) around event notifications. TPL misuse tends to surface much
+//! later as a hang or a lost notification, so each violation is logged as an error with the address of the
+//! notification function involved and, when it can be determined, the loaded image that contains it.
+//!
+//! When enabled with [enable], the following violations are reported:
+//! - A notification function restores the TPL below its notify TPL.
+//! - A notification function returns at a TPL other than its notify TPL.
+//! - An event is signaled at TPL_HIGH_LEVEL, where its notification cannot be dispatched.
+//! - A notification function runs for longer than the configured limit.
+//!
+//! The run time of a notification function is measured with the system time advanced by the timer architectural
+//! protocol, so it has the resolution of the timer period and is not measured before the timer is installed.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use core::{
+    fmt,
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use r_efi::efi;
+
+use crate::image::core_find_image_for_address;
+
+use super::EVENT_DB;
+
+/// Whether TPL diagnostics are enabled.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+/// The run time limit for notification functions, in 100ns units of the system time. Zero disables the check.
+static NOTIFY_TIME_LIMIT: AtomicU64 = AtomicU64::new(0);
+/// The address of the innermost running notification function, or zero if none is running.
+static ACTIVE_NOTIFY_FUNCTION: AtomicUsize = AtomicUsize::new(0);
+/// The notify TPL of the innermost running notification function.
+static ACTIVE_NOTIFY_TPL: AtomicUsize = AtomicUsize::new(efi::TPL_APPLICATION);
+/// Set while a violation is reported, so that violations caused by reporting it (e.g. by the logger) are not reported.
+static REPORTING: AtomicBool = AtomicBool::new(false);
+/// The number of violations reported.
+static VIOLATIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Enables TPL diagnostics. Notification functions running for longer than `notify_time_limit` are reported, unless
+/// it is zero.
+pub fn enable(notify_time_limit: Duration) {
+    let limit = u64::try_from(notify_time_limit.as_nanos() / 100).unwrap_or(u64::MAX);
+    NOTIFY_TIME_LIMIT.store(limit, Ordering::SeqCst);
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// The state of a running notification function, returned by [notify_started] and checked by [notify_finished].
+pub struct NotifyScope {
+    function: usize,
+    notify_tpl: efi::Tpl,
+    start_time: u64,
+    outer_function: usize,
+    outer_tpl: efi::Tpl,
+}
+
+/// Records that `function` is about to be called at `notify_tpl`. Returns `None` if diagnostics are disabled.
+pub fn notify_started(function: efi::EventNotify, notify_tpl: efi::Tpl, time: u64) -> Option<NotifyScope> {
+    if !is_enabled() {
+        return None;
+    }
+
+    let function = function as usize;
+    Some(NotifyScope {
+        function,
+        notify_tpl,
+        start_time: time,
+        outer_function: ACTIVE_NOTIFY_FUNCTION.swap(function, Ordering::SeqCst),
+        outer_tpl: ACTIVE_NOTIFY_TPL.swap(notify_tpl, Ordering::SeqCst),
+    })
+}
+
+/// Checks the TPL and the run time of a notification function that returned at `current_tpl`.
+pub fn notify_finished(scope: NotifyScope, current_tpl: efi::Tpl, time: u64) {
+    ACTIVE_NOTIFY_FUNCTION.store(scope.outer_function, Ordering::SeqCst);
+    ACTIVE_NOTIFY_TPL.store(scope.outer_tpl, Ordering::SeqCst);
+
+    if current_tpl != scope.notify_tpl {
+        report(
+            Some(scope.function),
+            format_args!("returned at TPL {current_tpl:#x} instead of its notify TPL {:#x}", scope.notify_tpl),
+        );
+    }
+
+    let limit = NOTIFY_TIME_LIMIT.load(Ordering::SeqCst);
+    let elapsed = time.saturating_sub(scope.start_time);
+    if limit != 0 && elapsed > limit {
+        report(
+            Some(scope.function),
+            format_args!(
+                "ran for {}us at TPL {:#x}, longer than the {}us limit",
+                elapsed / 10,
+                scope.notify_tpl,
+                limit / 10
+            ),
+        );
+    }
+}
+
+/// Forgets the running notification functions, whose stack was abandoned to terminate the image they interrupted.
+pub fn notifies_abandoned() {
+    ACTIVE_NOTIFY_FUNCTION.store(0, Ordering::SeqCst);
+    ACTIVE_NOTIFY_TPL.store(efi::TPL_APPLICATION, Ordering::SeqCst);
+}
+
+/// Checks a restore of the TPL to `new_tpl`.
+pub fn check_restore_tpl(new_tpl: efi::Tpl) {
+    if !is_enabled() {
+        return;
+    }
+
+    let function = ACTIVE_NOTIFY_FUNCTION.load(Ordering::SeqCst);
+    let notify_tpl = ACTIVE_NOTIFY_TPL.load(Ordering::SeqCst);
+    if function != 0 && new_tpl < notify_tpl {
+        report(Some(function), format_args!("restored the TPL to {new_tpl:#x}, below its notify TPL {notify_tpl:#x}"));
+    }
+}
+
+/// Checks the signaling of `event` at `current_tpl`.
+pub fn check_signal(event: efi::Event, current_tpl: efi::Tpl) {
+    if !is_enabled() || current_tpl < efi::TPL_HIGH_LEVEL {
+        return;
+    }
+
+    // A notification function signaling the event is the likely culprit, otherwise report the handler of the event.
+    match ACTIVE_NOTIFY_FUNCTION.load(Ordering::SeqCst) {
+        0 => {
+            let handler = EVENT_DB.get_notification_data(event).ok().and_then(|data| data.notify_function);
+            report(
+                handler.map(|function| function as usize),
+                format_args!("event {event:#x?} was signaled at TPL_HIGH_LEVEL"),
+            );
+        }
+        function => {
+            report(Some(function), format_args!("signaled event {event:#x?} at TPL_HIGH_LEVEL"));
+        }
+    }
+}
+
+fn report(function: Option<usize>, violation: fmt::Arguments) {
+    if REPORTING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    VIOLATIONS.fetch_add(1, Ordering::SeqCst);
+
+    let Some(function) = function else {
+        log::error!("TPL violation: {violation}.");
+        REPORTING.store(false, Ordering::SeqCst);
+        return;
+    };
+
+    match core_find_image_for_address(function) {
+        Some((handle, filename)) => log::error!(
+            "TPL violation by notify function {function:#x} in image {} ({handle:#x?}): {violation}.",
+            filename.as_deref().unwrap_or("<unknown>")
+        ),
+        None => log::error!("TPL violation by notify function {function:#x} (image not determined): {violation}."),
+    }
+    REPORTING.store(false, Ordering::SeqCst);
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use core::{ffi::c_void, ptr};
+
+    use super::{
+        super::{CURRENT_TPL, SYSTEM_TIME, close_event, create_event, raise_tpl, restore_tpl, signal_event},
+        *,
+    };
+    use crate::test_support;
+
+    fn with_diagnostics<F: Fn() + std::panic::RefUnwindSafe>(notify_time_limit: Option<Duration>, f: F) {
+        test_support::with_global_lock(|| {
+            CURRENT_TPL.store(efi::TPL_APPLICATION, Ordering::SeqCst);
+            VIOLATIONS.store(0, Ordering::SeqCst);
+            if let Some(notify_time_limit) = notify_time_limit {
+                enable(notify_time_limit);
+            }
+            f();
+            ENABLED.store(false, Ordering::SeqCst);
+            NOTIFY_TIME_LIMIT.store(0, Ordering::SeqCst);
+        })
+        .unwrap();
+    }
+
+    fn signal_notify(notify_tpl: efi::Tpl, notify_function: efi::EventNotify) {
+        let mut event: efi::Event = ptr::null_mut();
+        assert_eq!(
+            create_event(efi::EVT_NOTIFY_SIGNAL, notify_tpl, Some(notify_function), ptr::null_mut(), &mut event),
+            efi::Status::SUCCESS
+        );
+        assert_eq!(signal_event(event), efi::Status::SUCCESS);
+        assert_eq!(close_event(event), efi::Status::SUCCESS);
+    }
+
+    extern "efiapi" fn well_behaved_notify(_event: efi::Event, _context: *mut c_void) {
+        let old_tpl = raise_tpl(efi::TPL_HIGH_LEVEL);
+        restore_tpl(old_tpl);
+    }
+
+    extern "efiapi" fn lowering_notify(_event: efi::Event, _context: *mut c_void) {
+        let old_tpl = raise_tpl(efi::TPL_HIGH_LEVEL);
+        restore_tpl(efi::TPL_APPLICATION);
+        raise_tpl(old_tpl);
+    }
+
+    extern "efiapi" fn raising_notify(_event: efi::Event, _context: *mut c_void) {
+        raise_tpl(efi::TPL_HIGH_LEVEL);
+    }
+
+    extern "efiapi" fn slow_notify(_event: efi::Event, _context: *mut c_void) {
+        // 2ms in 100ns units.
+        SYSTEM_TIME.fetch_add(20_000, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn test_well_behaved_notify_is_not_reported() {
+        with_diagnostics(Some(Duration::from_millis(1)), || {
+            signal_notify(efi::TPL_CALLBACK, well_behaved_notify);
+            signal_notify(efi::TPL_NOTIFY, well_behaved_notify);
+            assert_eq!(VIOLATIONS.load(Ordering::SeqCst), 0);
+            assert_eq!(ACTIVE_NOTIFY_FUNCTION.load(Ordering::SeqCst), 0);
+        });
+    }
+
+    #[test]
+    fn test_violations_are_not_reported_when_disabled() {
+        with_diagnostics(None, || {
+            signal_notify(efi::TPL_NOTIFY, lowering_notify);
+            signal_notify(efi::TPL_CALLBACK, slow_notify);
+            assert_eq!(VIOLATIONS.load(Ordering::SeqCst), 0);
+        });
+    }
+
+    #[test]
+    fn test_notify_lowering_tpl_is_reported() {
+        with_diagnostics(Some(Duration::ZERO), || {
+            signal_notify(efi::TPL_NOTIFY, lowering_notify);
+            assert_eq!(VIOLATIONS.load(Ordering::SeqCst), 1);
+            assert_eq!(ACTIVE_NOTIFY_FUNCTION.load(Ordering::SeqCst), 0);
+            assert_eq!(CURRENT_TPL.load(Ordering::SeqCst), efi::TPL_APPLICATION);
+        });
+    }
+
+    #[test]
+    fn test_notify_returning_at_another_tpl_is_reported() {
+        with_diagnostics(Some(Duration::ZERO), || {
+            signal_notify(efi::TPL_CALLBACK, raising_notify);
+            assert_eq!(VIOLATIONS.load(Ordering::SeqCst), 1);
+            assert_eq!(CURRENT_TPL.load(Ordering::SeqCst), efi::TPL_APPLICATION);
+        });
+    }
+
+    #[test]
+    fn test_signal_at_high_level_is_reported() {
+        with_diagnostics(Some(Duration::ZERO), || {
+            let mut event: efi::Event = ptr::null_mut();
+            assert_eq!(
+                create_event(
+                    efi::EVT_NOTIFY_SIGNAL,
+                    efi::TPL_CALLBACK,
+                    Some(well_behaved_notify),
+                    ptr::null_mut(),
+                    &mut event
+                ),
+                efi::Status::SUCCESS
+            );
+
+            let old_tpl = raise_tpl(efi::TPL_HIGH_LEVEL);
+            assert_eq!(signal_event(event), efi::Status::SUCCESS);
+            assert_eq!(VIOLATIONS.load(Ordering::SeqCst), 1);
+
+            // The notification is dispatched once the TPL is lowered.
+            restore_tpl(old_tpl);
+            assert_eq!(VIOLATIONS.load(Ordering::SeqCst), 1);
+            assert_eq!(close_event(event), efi::Status::SUCCESS);
+        });
+    }
+
+    #[test]
+    fn test_slow_notify_is_reported() {
+        with_diagnostics(Some(Duration::from_millis(1)), || {
+            signal_notify(efi::TPL_CALLBACK, slow_notify);
+            assert_eq!(VIOLATIONS.load(Ordering::SeqCst), 1);
+        });
+        with_diagnostics(Some(Duration::from_millis(5)), || {
+            signal_notify(efi::TPL_CALLBACK, slow_notify);
+            assert_eq!(VIOLATIONS.load(Ordering::SeqCst), 0);
+        });
+    }
+}
@@ -491,7 +491,11 @@ extern "efiapi" fn fv_read_section(
     let dest_buffer = unsafe { slice::from_raw_parts_mut(local_buffer_ptr as *mut u8, local_buffer_size) };
     dest_buffer.copy_from_slice(&section_data[0..dest_buffer.len()]);
 
-    //TODO: authentication status not yet supported.
+    // Safety: null-checked at the start of the routine, but caller is required to guarantee authentication_status is
+    // valid.
+    unsafe {
+        authentication_status.write_unaligned(section.authentication_status());
+    }
 
     if dest_buffer.len() < section_data.len() { efi::Status::WARN_BUFFER_TOO_SMALL } else { efi::Status::SUCCESS }
 }
@@ -6,13 +6,19 @@
 //!
 //! SPDX-License-Identifier: Apache-2.0
 //!
+mod free_ranges;
 mod io_block;
 mod memory_block;
+mod special_regions;
 mod spin_locked_gcd;
 
+use alloc::string::ToString;
 use core::{ffi::c_void, ops::Range, panic};
 use patina::base::{align_down, align_up};
-use patina::error::EfiError;
+use patina::{
+    component::{hob::FromHob, service::memory_protection::SpecialRegionHob},
+    error::EfiError,
+};
 use patina_paging::MemoryAttributes;
 use patina_pi::{
     dxe_services::{GcdIoType, GcdMemoryType},
@@ -23,8 +29,12 @@ use r_efi::efi;
 #[cfg(feature = "compatibility_mode_allowed")]
 use patina::base::{UEFI_PAGE_SIZE, align_range};
 
-use crate::GCD;
+use crate::{
+    GCD,
+    error::{CoreError, Module},
+};
 
+pub use special_regions::SpecialRegion;
 pub use spin_locked_gcd::{AllocateType, MapChangeType, SpinLockedGcd};
 
 pub fn init_gcd(physical_hob_list: *const c_void) {
@@ -79,7 +89,39 @@ pub fn init_gcd(physical_hob_list: *const c_void) {
                 | efi::MEMORY_XP
                 | efi::MEMORY_RO,
         )
-        .expect("Failed to add initial region to GCD.");
+        .map_err(|err| CoreError::new(Module::Gcd, "add initial memory space", err).with_address(free_memory_start))
+        .unwrap_or_else(|err| panic!("Failed to add initial region to GCD: {err}"));
+    }
+}
+
+/// Registers the memory protection special regions described by HOBs, so that their exemptions apply when paging
+/// is initialized.
+pub fn init_special_regions(hob_list: &HobList) {
+    for data in hob_list.iter().filter_map(|hob| match hob {
+        Hob::GuidHob(hob, data) if hob.name == SpecialRegionHob::HOB_GUID.to_efi_guid() => Some(data),
+        _ => None,
+    }) {
+        if data.len() < core::mem::size_of::<SpecialRegionHob>() {
+            log::error!("Special region HOB is too small: {:#x} bytes", data.len());
+            debug_assert!(false);
+            continue;
+        }
+        let hob = SpecialRegionHob::parse(data);
+        let region = SpecialRegion {
+            base_address: hob.base_address,
+            length: hob.length,
+            exemptions: hob.exemptions(),
+            requester: hob.requester().to_string(),
+            reason: hob.reason().to_string(),
+        };
+        if let Err(err) = GCD.add_special_region(region) {
+            log::error!(
+                "Failed to register special region {:#x?} of length {:#x?} from HOB: {err:?}",
+                hob.base_address,
+                hob.length
+            );
+            debug_assert!(false);
+        }
     }
 }
 
@@ -87,6 +129,34 @@ pub fn init_paging(hob_list: &HobList) {
     GCD.init_paging(hob_list);
 }
 
+/// Registers for MP Services protocol installation so that MTRR settings programmed on the BSP are replicated to the
+/// APs once they are available.
+#[cfg(all(target_os = "uefi", target_arch = "x86_64"))]
+pub fn init_mtrr_ap_synchronization() {
+    use crate::{events::EVENT_DB, protocols::PROTOCOL_DB};
+    use r_efi::protocols::mp_services;
+
+    extern "efiapi" fn mp_services_protocol_notify(_event: efi::Event, _context: *mut c_void) {
+        match PROTOCOL_DB.locate_protocol(mp_services::PROTOCOL_GUID) {
+            Ok(protocol) => {
+                log::info!("MP Services protocol installed. Synchronizing MTRRs to APs.");
+                if let Err(err) = patina_internal_cpu::mtrr::register_mp_services(protocol as *mut _) {
+                    log::error!("Failed to synchronize MTRRs to APs: {err:?}");
+                }
+            }
+            Err(err) => log::error!("Failed to locate MP Services protocol: {err:?}"),
+        }
+    }
+
+    let event = EVENT_DB
+        .create_event(efi::EVT_NOTIFY_SIGNAL, efi::TPL_CALLBACK, Some(mp_services_protocol_notify), None, None)
+        .expect("Failed to create MP Services protocol installation callback.");
+
+    PROTOCOL_DB
+        .register_protocol_notify(mp_services::PROTOCOL_GUID, event)
+        .expect("Failed to register protocol notify on MP Services protocol.");
+}
+
 pub fn add_hob_resource_descriptors_to_gcd(hob_list: &HobList) {
     let phit = hob_list
         .iter()
@@ -168,7 +238,10 @@ pub fn add_hob_resource_descriptors_to_gcd(hob_list: &HobList) {
                             res_desc.physical_start as usize,
                             res_desc.resource_length as usize,
                         )
-                        .expect("Failed to add IO space to GCD");
+                        .map_err(|err| {
+                            CoreError::new(Module::Gcd, "add io space", err).with_address(res_desc.physical_start)
+                        })
+                        .unwrap_or_else(|err| panic!("Failed to add IO space to GCD: {err}"));
                     }
                     hob::EFI_RESOURCE_IO_RESERVED => {
                         log::info!(
@@ -181,7 +254,10 @@ pub fn add_hob_resource_descriptors_to_gcd(hob_list: &HobList) {
                             res_desc.physical_start as usize,
                             res_desc.resource_length as usize,
                         )
-                        .expect("Failed to add IO space to GCD");
+                        .map_err(|err| {
+                            CoreError::new(Module::Gcd, "add io space", err).with_address(res_desc.physical_start)
+                        })
+                        .unwrap_or_else(|err| panic!("Failed to add IO space to GCD: {err}"));
                     }
                     _ => {
                         debug_assert!(false, "Unknown resource type in HOB");
@@ -225,7 +301,8 @@ pub fn add_hob_resource_descriptors_to_gcd(hob_list: &HobList) {
                         split_range.end.saturating_sub(split_range.start) as usize,
                         spin_locked_gcd::get_capabilities(gcd_mem_type, resource_attributes as u64),
                     )
-                    .expect("Failed to add memory space to GCD");
+                    .map_err(|err| CoreError::new(Module::Gcd, "add memory space", err).with_address(split_range.start))
+                    .unwrap_or_else(|err| panic!("Failed to add memory space to GCD: {err}"));
                 }
                 if let Some(attributes) = memory_attributes {
                     match GCD.set_memory_space_attributes(
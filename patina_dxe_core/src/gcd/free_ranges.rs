@@ -0,0 +1,247 @@
+//! UEFI Global Coherency Domain (GCD) Free Range Index
+//!
+//! Indexes the unallocated memory blocks of the GCD by memory type, so that the allocation strategies only visit
+//! blocks that can satisfy a request instead of scanning every memory block. The free ranges are ordered both by base
+//! address, to walk the free ranges of a memory type from a given address, and by size, to reject a request larger
+//! than any free range of its memory type without walking at all.
+//!
+//! The GCD services the heap expansion requests, so the index is stored in fixed slices, like the memory blocks.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use core::ops::Range;
+
+use patina_internal_collections::{Error as SliceError, Rbt, node_size};
+use patina_pi::dxe_services::GcdMemoryType;
+
+use super::memory_block::MemoryBlock;
+
+/// A free range, keyed by (memory type, base address, length).
+type ByBase = (u32, u64, u64);
+
+/// A free range, keyed by (memory type, length, base address).
+type BySize = (u32, u64, u64);
+
+/// Index of the free ranges of the GCD memory blocks.
+pub struct FreeRanges {
+    by_base: Rbt<'static, ByBase>,
+    by_size: Rbt<'static, BySize>,
+}
+
+impl FreeRanges {
+    /// Creates an empty index without storage.
+    pub const fn new() -> Self {
+        Self { by_base: Rbt::new(), by_size: Rbt::new() }
+    }
+
+    /// Returns the size of the storage needed to index `capacity` memory blocks.
+    pub const fn slice_size(capacity: usize) -> usize {
+        capacity * (node_size::<ByBase>() + node_size::<BySize>())
+    }
+
+    /// Replaces the storage of the index. `slice` must be [Self::slice_size] bytes for the memory block capacity.
+    pub fn resize(&mut self, slice: &'static mut [u8]) {
+        let (by_base, by_size) =
+            slice.split_at_mut(slice.len() / (node_size::<ByBase>() + node_size::<BySize>()) * node_size::<ByBase>());
+        self.by_base.resize(by_base);
+        self.by_size.resize(by_size);
+    }
+
+    /// Returns the number of free ranges.
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.by_base.len()
+    }
+
+    /// Returns the (memory type, base address, length) of a block that can be allocated, if it is free.
+    fn entry(block: &MemoryBlock) -> Option<(u32, u64, u64)> {
+        match block {
+            MemoryBlock::Unallocated(descriptor)
+                if descriptor.length > 0
+                    && !matches!(descriptor.memory_type, GcdMemoryType::NonExistent | GcdMemoryType::Unaccepted) =>
+            {
+                Some((descriptor.memory_type as u32, descriptor.base_address, descriptor.length))
+            }
+            _ => None,
+        }
+    }
+
+    /// Adds `block` to the index if it is free.
+    pub fn insert(&mut self, block: &MemoryBlock) -> Result<(), SliceError> {
+        if let Some((memory_type, base_address, length)) = Self::entry(block) {
+            self.by_base.add((memory_type, base_address, length))?;
+            if let Err(err) = self.by_size.add((memory_type, length, base_address)) {
+                let _ = self.by_base.delete(&(memory_type, base_address, length));
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes `block` from the index if it is free.
+    pub fn remove(&mut self, block: &MemoryBlock) {
+        if let Some((memory_type, base_address, length)) = Self::entry(block) {
+            let _ = self.by_base.delete(&(memory_type, base_address, length));
+            let _ = self.by_size.delete(&(memory_type, length, base_address));
+        }
+    }
+
+    /// Removes the blocks of `memory_blocks` starting in `region` from the index.
+    pub fn remove_region(&mut self, memory_blocks: &Rbt<MemoryBlock>, region: Range<u64>) {
+        let mut current = memory_blocks.get_idx(&region.start);
+        while let Some(block) = current.and_then(|idx| memory_blocks.get_with_idx(idx)) {
+            if block.as_ref().base_address >= region.end {
+                break;
+            }
+            self.remove(block);
+            current = current.and_then(|idx| memory_blocks.next_idx(idx));
+        }
+    }
+
+    /// Adds the blocks of `memory_blocks` starting in `region` to the index.
+    pub fn insert_region(&mut self, memory_blocks: &Rbt<MemoryBlock>, region: Range<u64>) -> Result<(), SliceError> {
+        let mut current = memory_blocks.get_idx(&region.start);
+        while let Some(block) = current.and_then(|idx| memory_blocks.get_with_idx(idx)) {
+            if block.as_ref().base_address >= region.end {
+                break;
+            }
+            self.insert(block)?;
+            current = current.and_then(|idx| memory_blocks.next_idx(idx));
+        }
+        Ok(())
+    }
+
+    /// Returns the length of the largest free range of `memory_type`, or 0 if there is none.
+    pub fn largest(&self, memory_type: GcdMemoryType) -> u64 {
+        let memory_type = memory_type as u32;
+        self.by_size
+            .get_closest_idx(&(memory_type, u64::MAX, u64::MAX))
+            .and_then(|idx| self.by_size.get_with_idx(idx))
+            .filter(|entry| entry.0 == memory_type)
+            .map_or(0, |entry| entry.1)
+    }
+
+    /// Returns the (base address, length) of the free range of `memory_type` with the highest base address at or
+    /// below `address`.
+    pub fn last_at_or_below(&self, memory_type: GcdMemoryType, address: u64) -> Option<(u64, u64)> {
+        self.floor(memory_type, (memory_type as u32, address, u64::MAX))
+    }
+
+    /// Returns the (base address, length) of the free range of `memory_type` preceding the free range at
+    /// `base_address` of `length`.
+    pub fn prev(&self, memory_type: GcdMemoryType, base_address: u64, length: u64) -> Option<(u64, u64)> {
+        self.floor(memory_type, (memory_type as u32, base_address, length.checked_sub(1)?))
+    }
+
+    /// Returns the (base address, length) of the free range of `memory_type` with the lowest base address.
+    pub fn first(&self, memory_type: GcdMemoryType) -> Option<(u64, u64)> {
+        self.ceiling(memory_type, (memory_type as u32, 0, 0))
+    }
+
+    /// Returns the (base address, length) of the free range of `memory_type` following the free range at
+    /// `base_address` of `length`.
+    pub fn next(&self, memory_type: GcdMemoryType, base_address: u64, length: u64) -> Option<(u64, u64)> {
+        self.ceiling(memory_type, (memory_type as u32, base_address, length))
+    }
+
+    // Returns the greatest free range of `memory_type` at or below `key`.
+    fn floor(&self, memory_type: GcdMemoryType, key: ByBase) -> Option<(u64, u64)> {
+        self.by_base
+            .get_closest_idx(&key)
+            .and_then(|idx| self.by_base.get_with_idx(idx))
+            .filter(|entry| entry.0 == memory_type as u32)
+            .map(|entry| (entry.1, entry.2))
+    }
+
+    // Returns the least free range of `memory_type` strictly above `key`.
+    fn ceiling(&self, memory_type: GcdMemoryType, key: ByBase) -> Option<(u64, u64)> {
+        let idx = match self.by_base.get_closest_idx(&key) {
+            Some(idx) => self.by_base.next_idx(idx),
+            None => self.by_base.first_idx(),
+        };
+        idx.and_then(|idx| self.by_base.get_with_idx(idx))
+            .filter(|entry| entry.0 == memory_type as u32)
+            .map(|entry| (entry.1, entry.2))
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use patina_pi::dxe_services::MemorySpaceDescriptor;
+    use std::{boxed::Box, vec};
+
+    const CAPACITY: usize = 64;
+
+    fn free_ranges() -> FreeRanges {
+        let mut free_ranges = FreeRanges::new();
+        let storage = Box::leak(vec![0u64; FreeRanges::slice_size(CAPACITY).div_ceil(8)].into_boxed_slice());
+        free_ranges.resize(unsafe {
+            core::slice::from_raw_parts_mut(storage.as_mut_ptr() as *mut u8, FreeRanges::slice_size(CAPACITY))
+        });
+        free_ranges
+    }
+
+    fn block(memory_type: GcdMemoryType, base_address: u64, length: u64, allocated: bool) -> MemoryBlock {
+        let descriptor = MemorySpaceDescriptor { memory_type, base_address, length, ..Default::default() };
+        if allocated { MemoryBlock::Allocated(descriptor) } else { MemoryBlock::Unallocated(descriptor) }
+    }
+
+    #[test]
+    fn test_free_ranges_ordering() {
+        let mut free_ranges = free_ranges();
+        let blocks = [
+            block(GcdMemoryType::SystemMemory, 0x1000, 0x1000, false),
+            block(GcdMemoryType::SystemMemory, 0x2000, 0x4000, true),
+            block(GcdMemoryType::SystemMemory, 0x6000, 0x3000, false),
+            block(GcdMemoryType::MemoryMappedIo, 0x9000, 0x8000, false),
+            block(GcdMemoryType::SystemMemory, 0x11000, 0x2000, false),
+            block(GcdMemoryType::NonExistent, 0x13000, 0x10000, false),
+        ];
+        for block in &blocks {
+            free_ranges.insert(block).unwrap();
+        }
+
+        // allocated and non-existent blocks are not free.
+        assert_eq!(free_ranges.len(), 4);
+        assert_eq!(free_ranges.largest(GcdMemoryType::SystemMemory), 0x3000);
+        assert_eq!(free_ranges.largest(GcdMemoryType::MemoryMappedIo), 0x8000);
+        assert_eq!(free_ranges.largest(GcdMemoryType::Reserved), 0);
+
+        assert_eq!(free_ranges.first(GcdMemoryType::SystemMemory), Some((0x1000, 0x1000)));
+        assert_eq!(free_ranges.next(GcdMemoryType::SystemMemory, 0x1000, 0x1000), Some((0x6000, 0x3000)));
+        assert_eq!(free_ranges.next(GcdMemoryType::SystemMemory, 0x6000, 0x3000), Some((0x11000, 0x2000)));
+        assert_eq!(free_ranges.next(GcdMemoryType::SystemMemory, 0x11000, 0x2000), None);
+
+        assert_eq!(free_ranges.last_at_or_below(GcdMemoryType::SystemMemory, u64::MAX), Some((0x11000, 0x2000)));
+        assert_eq!(free_ranges.last_at_or_below(GcdMemoryType::SystemMemory, 0x10fff), Some((0x6000, 0x3000)));
+        assert_eq!(free_ranges.prev(GcdMemoryType::SystemMemory, 0x6000, 0x3000), Some((0x1000, 0x1000)));
+        assert_eq!(free_ranges.prev(GcdMemoryType::SystemMemory, 0x1000, 0x1000), None);
+        assert_eq!(free_ranges.last_at_or_below(GcdMemoryType::MemoryMappedIo, 0x8fff), None);
+
+        free_ranges.remove(&blocks[2]);
+        assert_eq!(free_ranges.len(), 3);
+        assert_eq!(free_ranges.largest(GcdMemoryType::SystemMemory), 0x2000);
+        assert_eq!(free_ranges.next(GcdMemoryType::SystemMemory, 0x1000, 0x1000), Some((0x11000, 0x2000)));
+    }
+
+    #[test]
+    fn test_free_ranges_capacity() {
+        let mut free_ranges = free_ranges();
+        for index in 0..CAPACITY as u64 {
+            free_ranges.insert(&block(GcdMemoryType::SystemMemory, index * 0x2000, 0x1000, false)).unwrap();
+        }
+        assert_eq!(
+            free_ranges.insert(&block(GcdMemoryType::SystemMemory, 0x1000000, 0x1000, false)),
+            Err(SliceError::OutOfSpace)
+        );
+        assert_eq!(free_ranges.len(), CAPACITY);
+        assert_eq!(free_ranges.largest(GcdMemoryType::SystemMemory), 0x1000);
+    }
+}
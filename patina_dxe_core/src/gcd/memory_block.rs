@@ -226,8 +226,10 @@ impl MemoryBlock {
     pub fn remove_transition(&mut self) -> Result<(), Error> {
         match self {
             Self::Unallocated(md) if md.memory_type != dxe_services::GcdMemoryType::NonExistent => {
+                // Non-existent memory has no attributes, so that it coalesces with the neighboring gaps.
                 md.memory_type = dxe_services::GcdMemoryType::NonExistent;
                 md.capabilities = 0;
+                md.attributes = 0;
                 Ok(())
             }
             _ => Err(Error::InvalidStateTransition),
@@ -370,8 +372,10 @@ mod memory_block_tests {
 
         // test remove transition
         let mut b2 = b1;
+        b2.as_mut().attributes = efi::MEMORY_RP;
         b2.state_transition(StateTransition::Remove).unwrap();
         assert_eq!(b2.as_ref().memory_type, GcdMemoryType::NonExistent);
+        assert_eq!(b2.as_ref().attributes, 0);
 
         // test allocate transition
         let mut b3 = block;
@@ -0,0 +1,177 @@
+//! Memory Protection Special Regions
+//!
+//! Special regions are ranges the platform exempted from memory protection policies, see
+//! [memory_protection](patina::component::service::memory_protection). The GCD keeps the attributes callers set over
+//! these ranges, and only adjusts the attributes applied to the page table:
+//!
+//! - A region exempt from no-execute is never mapped XP.
+//! - A region exempt from guards is never unmapped for EFI_MEMORY_RP, read protected pages are mapped XP instead.
+//!
+//! As the image protections and stack guard pages are applied through the GCD attributes, they honor the exemptions
+//! without knowing about them.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::{string::String, vec, vec::Vec};
+use core::fmt;
+
+use patina::component::service::memory_protection::ProtectionExemptions;
+use r_efi::efi;
+
+/// A range exempt from memory protection policies, with who asked for it and why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpecialRegion {
+    /// The page aligned base address of the region.
+    pub base_address: u64,
+    /// The length of the region in bytes.
+    pub length: u64,
+    /// The policies the region is exempt from.
+    pub exemptions: ProtectionExemptions,
+    /// The requester of the exemption, such as a component name or the GUID of the module producing the HOB.
+    pub requester: String,
+    /// The reason given for the exemption.
+    pub reason: String,
+}
+
+impl SpecialRegion {
+    fn end(&self) -> u64 {
+        self.base_address + self.length
+    }
+}
+
+impl fmt::Display for SpecialRegion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:016x}-{:016x} {:<8} {} ({})",
+            self.base_address,
+            self.end() - 1,
+            self.exemptions,
+            self.requester,
+            self.reason
+        )
+    }
+}
+
+/// Splits a range on the boundaries of the special regions, returning each part with the exemptions of the regions
+/// covering it. Exemptions of overlapping regions are combined.
+pub(crate) fn split_on_special_regions(
+    regions: &[SpecialRegion],
+    base_address: u64,
+    length: u64,
+) -> Vec<(u64, u64, ProtectionExemptions)> {
+    let end = base_address + length;
+    let mut boundaries = regions
+        .iter()
+        .flat_map(|region| [region.base_address, region.end()])
+        .filter(|boundary| base_address < *boundary && *boundary < end)
+        .collect::<Vec<_>>();
+    if boundaries.is_empty() {
+        let exemptions = exemptions_at(regions, base_address);
+        return vec![(base_address, length, exemptions)];
+    }
+    boundaries.sort_unstable();
+    boundaries.dedup();
+
+    let mut parts: Vec<(u64, u64, ProtectionExemptions)> = Vec::with_capacity(boundaries.len() + 1);
+    let mut part_base = base_address;
+    for part_end in boundaries.into_iter().chain([end]) {
+        let exemptions = exemptions_at(regions, part_base);
+        match parts.last_mut() {
+            // Merge adjacent parts with the same exemptions, so the page table is changed in as few calls as possible.
+            Some((last_base, last_length, last_exemptions)) if *last_exemptions == exemptions => {
+                *last_length = part_end - *last_base
+            }
+            _ => parts.push((part_base, part_end - part_base, exemptions)),
+        }
+        part_base = part_end;
+    }
+    parts
+}
+
+fn exemptions_at(regions: &[SpecialRegion], address: u64) -> ProtectionExemptions {
+    regions
+        .iter()
+        .filter(|region| region.base_address <= address && address < region.end())
+        .fold(ProtectionExemptions::default(), |exemptions, region| exemptions.union(region.exemptions))
+}
+
+/// Returns the attributes to apply to the page table for a range with `exemptions`.
+pub(crate) fn exempt_attributes(exemptions: ProtectionExemptions, mut attributes: u64) -> u64 {
+    if exemptions.guard && attributes & efi::MEMORY_RP != 0 {
+        attributes = (attributes & !efi::MEMORY_RP) | efi::MEMORY_XP;
+    }
+    if exemptions.no_execute {
+        attributes &= !efi::MEMORY_XP;
+    }
+    attributes
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use alloc::string::ToString;
+
+    const NX: ProtectionExemptions = ProtectionExemptions { no_execute: true, guard: false };
+    const GUARD: ProtectionExemptions = ProtectionExemptions { no_execute: false, guard: true };
+    const NONE: ProtectionExemptions = ProtectionExemptions { no_execute: false, guard: false };
+
+    fn region(base_address: u64, length: u64, exemptions: ProtectionExemptions) -> SpecialRegion {
+        SpecialRegion { base_address, length, exemptions, requester: "test".to_string(), reason: "quirk".to_string() }
+    }
+
+    #[test]
+    fn split_on_special_regions_should_combine_overlapping_exemptions() {
+        assert_eq!(split_on_special_regions(&[], 0x1000, 0x4000), [(0x1000, 0x4000, NONE)]);
+
+        let regions = [region(0x2000, 0x2000, NX), region(0x3000, 0x2000, GUARD)];
+        assert_eq!(
+            split_on_special_regions(&regions, 0x1000, 0x6000),
+            [
+                (0x1000, 0x1000, NONE),
+                (0x2000, 0x1000, NX),
+                (0x3000, 0x1000, NX.union(GUARD)),
+                (0x4000, 0x1000, GUARD),
+                (0x5000, 0x2000, NONE)
+            ]
+        );
+
+        // A range within a single region is not split.
+        assert_eq!(split_on_special_regions(&regions, 0x3000, 0x1000), [(0x3000, 0x1000, NX.union(GUARD))]);
+    }
+
+    #[test]
+    fn split_on_special_regions_should_merge_adjacent_regions() {
+        let regions = [region(0x2000, 0x1000, NX), region(0x3000, 0x1000, NX)];
+        assert_eq!(
+            split_on_special_regions(&regions, 0x1000, 0x4000),
+            [(0x1000, 0x1000, NONE), (0x2000, 0x2000, NX), (0x4000, 0x1000, NONE)]
+        );
+    }
+
+    #[test]
+    fn exempt_attributes_should_lift_exempted_policies() {
+        let guard_page = efi::MEMORY_RP | efi::MEMORY_WB;
+        assert_eq!(exempt_attributes(NONE, guard_page), guard_page);
+        assert_eq!(exempt_attributes(GUARD, guard_page), efi::MEMORY_XP | efi::MEMORY_WB);
+        assert_eq!(exempt_attributes(NX.union(GUARD), guard_page), efi::MEMORY_WB);
+
+        let data = efi::MEMORY_XP | efi::MEMORY_WB;
+        assert_eq!(exempt_attributes(NX, data), efi::MEMORY_WB);
+        assert_eq!(exempt_attributes(GUARD, data), data);
+        assert_eq!(exempt_attributes(NX, efi::MEMORY_RO), efi::MEMORY_RO);
+    }
+
+    #[test]
+    fn special_region_should_display_requester_and_reason() {
+        let region = region(0xC0000, 0x20000, NX);
+        assert_eq!(std::format!("{region}"), "00000000000c0000-00000000000dffff NX       test (quirk)");
+    }
+}
@@ -14,7 +14,9 @@ use patina::{base::DEFAULT_CACHE_ATTR, error::EfiError};
 use mu_rust_helpers::function;
 use patina::{
     base::{SIZE_4GB, UEFI_PAGE_MASK, UEFI_PAGE_SHIFT, UEFI_PAGE_SIZE, align_up},
+    component::service::memory_protection::validate_special_region,
     guids::CACHE_ATTRIBUTE_CHANGE_EVENT_GROUP,
+    log::target,
     uefi_pages_to_size,
 };
 use patina_internal_collections::{Error as SliceError, Rbt, SliceKey, node_size};
@@ -25,23 +27,35 @@ use patina_pi::{
 use r_efi::efi;
 
 use crate::{
-    GCD, allocator::DEFAULT_ALLOCATION_STRATEGY, ensure, error, events::EVENT_DB, protocol_db,
-    protocol_db::INVALID_HANDLE, tpl_lock,
+    GCD,
+    allocator::{self, DEFAULT_ALLOCATION_STRATEGY},
+    ensure, error,
+    events::EVENT_DB,
+    protocol_db,
+    protocol_db::INVALID_HANDLE,
+    tpl_lock,
+};
+use patina_internal_cpu::paging::{
+    granule::{DEFAULT_PAGE_GRANULE, PageGranule, detect_page_granule},
+    large_pages::{LargePageTable, PagingStatistics, TableFormat},
 };
-use patina_internal_cpu::paging::create_cpu_paging;
 use patina_paging::{MemoryAttributes, PageTable, PtError, PtResult, page_allocator::PageAllocator};
 
 use patina_pi::hob::{Hob, HobList};
 
 use super::{
+    free_ranges::FreeRanges,
     io_block::{self, Error as IoBlockError, IoBlock, IoBlockSplit, StateTransition as IoStateTransition},
     memory_block::{
         self, Error as MemoryBlockError, MemoryBlock, MemoryBlockSplit, StateTransition as MemoryStateTransition,
     },
+    special_regions::{SpecialRegion, exempt_attributes, split_on_special_regions},
 };
 
 const MEMORY_BLOCK_SLICE_LEN: usize = 4096;
-pub const MEMORY_BLOCK_SLICE_SIZE: usize = MEMORY_BLOCK_SLICE_LEN * node_size::<MemoryBlock>();
+const MEMORY_BLOCK_TREE_SIZE: usize = MEMORY_BLOCK_SLICE_LEN * node_size::<MemoryBlock>();
+// The memory block slice holds the memory blocks followed by the index of their free ranges.
+pub const MEMORY_BLOCK_SLICE_SIZE: usize = MEMORY_BLOCK_TREE_SIZE + FreeRanges::slice_size(MEMORY_BLOCK_SLICE_LEN);
 
 const IO_BLOCK_SLICE_LEN: usize = 4096;
 const IO_BLOCK_SLICE_SIZE: usize = IO_BLOCK_SLICE_LEN * node_size::<IoBlock>();
@@ -282,6 +296,8 @@ impl PageAllocator for PagingAllocator<'_> {
 struct GCD {
     maximum_address: usize,
     memory_blocks: Rbt<'static, MemoryBlock>,
+    /// Index of the unallocated memory blocks, kept in sync with `memory_blocks`.
+    free_ranges: FreeRanges,
     allocate_memory_space_fn: GcdAllocateFn,
     free_memory_space_fn: GcdFreeFn,
     /// Default attributes for memory allocations
@@ -314,6 +330,7 @@ impl GCD {
         assert!(processor_address_bits > 0);
         Self {
             memory_blocks: Rbt::new(),
+            free_ranges: FreeRanges::new(),
             maximum_address: 1 << processor_address_bits,
             allocate_memory_space_fn: Self::allocate_memory_space_internal,
             free_memory_space_fn: Self::free_memory_space_worker,
@@ -350,10 +367,10 @@ impl GCD {
             EfiError::OutOfResources
         );
 
-        log::trace!(target: "allocations", "[{}] Initializing memory blocks at {:#x}", function!(), base_address);
-        log::trace!(target: "allocations", "[{}]   Length: {:#x}", function!(), len);
-        log::trace!(target: "allocations", "[{}]   Memory Type: {:?}", function!(), memory_type);
-        log::trace!(target: "allocations", "[{}]   Capabilities: {:#x}", function!(), capabilities);
+        log::trace!(target: target::ALLOCATIONS, "[{}] Initializing memory blocks at {:#x}", function!(), base_address);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Length: {:#x}", function!(), len);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Memory Type: {:?}", function!(), memory_type);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Capabilities: {:#x}", function!(), capabilities);
 
         let unallocated_memory_space = MemoryBlock::Unallocated(dxe_services::MemorySpaceDescriptor {
             memory_type: dxe_services::GcdMemoryType::NonExistent,
@@ -362,8 +379,11 @@ impl GCD {
             ..Default::default()
         });
 
-        self.memory_blocks
-            .resize(unsafe { slice::from_raw_parts_mut::<'static>(base_address as *mut u8, MEMORY_BLOCK_SLICE_SIZE) });
+        let (memory_block_slice, free_range_slice) =
+            unsafe { slice::from_raw_parts_mut::<'static>(base_address as *mut u8, MEMORY_BLOCK_SLICE_SIZE) }
+                .split_at_mut(MEMORY_BLOCK_TREE_SIZE);
+        self.memory_blocks.resize(memory_block_slice);
+        self.free_ranges.resize(free_range_slice);
 
         self.memory_blocks.add(unallocated_memory_space).map_err(|_| EfiError::OutOfResources)?;
         let idx = unsafe { self.add_memory_space(memory_type, base_address, len, capabilities) }?;
@@ -422,10 +442,10 @@ impl GCD {
         ensure!(len > 0, EfiError::InvalidParameter);
         ensure!(base_address.checked_add(len).is_some_and(|sum| sum <= self.maximum_address), EfiError::Unsupported);
 
-        log::trace!(target: "allocations", "[{}] Adding memory space at {:#x}", function!(), base_address);
-        log::trace!(target: "allocations", "[{}]   Length: {:#x}", function!(), len);
-        log::trace!(target: "allocations", "[{}]   Memory Type: {:?}", function!(), memory_type);
-        log::trace!(target: "allocations", "[{}]   Capabilities: {:#x}\n", function!(), capabilities);
+        log::trace!(target: target::ALLOCATIONS, "[{}] Adding memory space at {:#x}", function!(), base_address);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Length: {:#x}", function!(), len);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Memory Type: {:?}", function!(), memory_type);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Capabilities: {:#x}\n", function!(), capabilities);
 
         // All software capabilities are supported for system memory
         capabilities |= efi::MEMORY_ACCESS_MASK | efi::MEMORY_RUNTIME;
@@ -439,8 +459,9 @@ impl GCD {
             return unsafe { self.init_memory_blocks(memory_type, base_address, len, capabilities) };
         }
         let memory_blocks = &mut self.memory_blocks;
+        let free_ranges = &mut self.free_ranges;
 
-        log::trace!(target: "gcd_measure", "search");
+        log::trace!(target: target::GCD_MEASURE, "search");
         let idx = memory_blocks.get_closest_idx(&(base_address as u64)).ok_or(EfiError::NotFound)?;
         let block = memory_blocks.get_with_idx(idx).ok_or(EfiError::NotFound)?;
 
@@ -449,6 +470,7 @@ impl GCD {
         // all newly added memory is marked as RP
         match Self::split_state_transition_at_idx(
             memory_blocks,
+            free_ranges,
             idx,
             base_address,
             len,
@@ -473,16 +495,23 @@ impl GCD {
         ensure!(len > 0, EfiError::InvalidParameter);
         ensure!(base_address + len <= self.maximum_address, EfiError::Unsupported);
 
-        log::trace!(target: "allocations", "[{}] Removing memory space at {:#x} of length {:#x}", function!(), base_address, len);
+        log::trace!(target: target::ALLOCATIONS, "[{}] Removing memory space at {:#x} of length {:#x}", function!(), base_address, len);
 
         let memory_blocks = &mut self.memory_blocks;
+        let free_ranges = &mut self.free_ranges;
 
-        log::trace!(target: "gcd_measure", "search");
+        log::trace!(target: target::GCD_MEASURE, "search");
         let idx = memory_blocks.get_closest_idx(&(base_address as u64)).ok_or(EfiError::NotFound)?;
         let block = *memory_blocks.get_with_idx(idx).ok_or(EfiError::NotFound)?;
 
-        match Self::split_state_transition_at_idx(memory_blocks, idx, base_address, len, MemoryStateTransition::Remove)
-        {
+        match Self::split_state_transition_at_idx(
+            memory_blocks,
+            free_ranges,
+            idx,
+            base_address,
+            len,
+            MemoryStateTransition::Remove,
+        ) {
             Ok(_) => Ok(()),
             Err(InternalError::MemoryBlock(MemoryBlockError::BlockOutsideRange)) => error!(EfiError::NotFound),
             Err(InternalError::MemoryBlock(MemoryBlockError::InvalidStateTransition)) => match block {
@@ -525,12 +554,12 @@ impl GCD {
             EfiError::InvalidParameter
         );
 
-        log::trace!(target: "allocations", "[{}] Allocating memory space: {:x?}", function!(), allocate_type);
-        log::trace!(target: "allocations", "[{}]   Length: {:#x}", function!(), len);
-        log::trace!(target: "allocations", "[{}]   Memory Type: {:?}", function!(), memory_type);
-        log::trace!(target: "allocations", "[{}]   Alignment: {:#x}", function!(), alignment);
-        log::trace!(target: "allocations", "[{}]   Image Handle: {:#x?}", function!(), image_handle);
-        log::trace!(target: "allocations", "[{}]   Device Handle: {:#x?}\n", function!(), device_handle.unwrap_or(ptr::null_mut()));
+        log::trace!(target: target::ALLOCATIONS, "[{}] Allocating memory space: {:x?}", function!(), allocate_type);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Length: {:#x}", function!(), len);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Memory Type: {:?}", function!(), memory_type);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Alignment: {:#x}", function!(), alignment);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Image Handle: {:#x?}", function!(), image_handle);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Device Handle: {:#x?}\n", function!(), device_handle.unwrap_or(ptr::null_mut()));
 
         match allocate_type {
             AllocateType::BottomUp(max_address) => gcd.allocate_bottom_up(
@@ -581,16 +610,17 @@ impl GCD {
         ensure!(base_address + len <= self.maximum_address, EfiError::Unsupported);
         ensure!((base_address & UEFI_PAGE_MASK) == 0 && (len & UEFI_PAGE_MASK) == 0, EfiError::InvalidParameter);
 
-        log::trace!(target: "allocations", "[{}] Freeing memory space at {:#x}", function!(), base_address);
-        log::trace!(target: "allocations", "[{}]   Length: {:#x}", function!(), len);
-        log::trace!(target: "allocations", "[{}]   Memory State Transition: {:?}\n", function!(), transition);
+        log::trace!(target: target::ALLOCATIONS, "[{}] Freeing memory space at {:#x}", function!(), base_address);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Length: {:#x}", function!(), len);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Memory State Transition: {:?}\n", function!(), transition);
 
         let memory_blocks = &mut self.memory_blocks;
+        let free_ranges = &mut self.free_ranges;
 
-        log::trace!(target: "gcd_measure", "search");
+        log::trace!(target: target::GCD_MEASURE, "search");
         let idx = memory_blocks.get_closest_idx(&(base_address as u64)).ok_or(EfiError::NotFound)?;
 
-        match Self::split_state_transition_at_idx(memory_blocks, idx, base_address, len, transition) {
+        match Self::split_state_transition_at_idx(memory_blocks, free_ranges, idx, base_address, len, transition) {
             Ok(_) => {}
             Err(InternalError::MemoryBlock(_)) => error!(EfiError::NotFound),
             Err(InternalError::Slice(SliceError::OutOfSpace)) => error!(EfiError::OutOfResources),
@@ -652,62 +682,58 @@ impl GCD {
     ) -> Result<usize, EfiError> {
         ensure!(len > 0, EfiError::InvalidParameter);
 
-        log::trace!(target: "allocations", "[{}] Bottom up GCD allocation: {:#?}", function!(), memory_type);
-        log::trace!(target: "allocations", "[{}]   Max Address: {:#x}", function!(), max_address);
-        log::trace!(target: "allocations", "[{}]   Length: {:#x}", function!(), len);
-        log::trace!(target: "allocations", "[{}]   Align Shift: {:#x}", function!(), align_shift);
-        log::trace!(target: "allocations", "[{}]   Image Handle: {:#x?}", function!(), image_handle);
-        log::trace!(target: "allocations", "[{}]   Device Handle: {:#x?}\n", function!(), device_handle.unwrap_or(ptr::null_mut()));
+        log::trace!(target: target::ALLOCATIONS, "[{}] Bottom up GCD allocation: {:#?}", function!(), memory_type);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Max Address: {:#x}", function!(), max_address);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Length: {:#x}", function!(), len);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Align Shift: {:#x}", function!(), align_shift);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Image Handle: {:#x?}", function!(), image_handle);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Device Handle: {:#x?}\n", function!(), device_handle.unwrap_or(ptr::null_mut()));
 
         let memory_blocks = &mut self.memory_blocks;
+        let free_ranges = &mut self.free_ranges;
         let alignment = 1 << align_shift;
 
-        log::trace!(target: "gcd_measure", "search");
-        let mut current = memory_blocks.first_idx();
-        while let Some(idx) = current {
-            let mb = memory_blocks.get_with_idx(idx).expect("idx is valid from next_idx");
-            if mb.len() < len {
-                current = memory_blocks.next_idx(idx);
+        // Only the free ranges of the requested memory type are visited, in address order. If no free range is large
+        // enough, the request cannot be satisfied.
+        log::trace!(target: target::GCD_MEASURE, "search");
+        let mut current =
+            if free_ranges.largest(memory_type) < len as u64 { None } else { free_ranges.first(memory_type) };
+        while let Some((start, length)) = current {
+            current = free_ranges.next(memory_type, start, length);
+            let (start, length) = (start as usize, length as usize);
+            if length < len {
                 continue;
             }
 
-            let address = mb.start();
-            let mut addr = address & (usize::MAX << align_shift);
+            let mut addr = start & (usize::MAX << align_shift);
 
-            if addr < address {
+            if addr < start {
                 addr += alignment;
             }
             ensure!(addr + len <= max_address, EfiError::NotFound);
 
-            if mb.as_ref().memory_type != memory_type {
-                current = memory_blocks.next_idx(idx);
-                continue;
-            }
-
             // We don't allow allocations on page 0, to allow for null pointer detection. If this block starts at 0,
             // attempt to move forward a page + alignment to find a valid address. If there is not enough space in this
             // block, move to the next one.
             if addr == 0 {
                 addr = align_up(UEFI_PAGE_SIZE, alignment)?;
-                // we can do mb.len() - addr here because we know this block starts from 0
-                if addr + len >= max_address || mb.len() - addr < len {
-                    current = memory_blocks.next_idx(idx);
+                // we can do length - addr here because we know this block starts from 0
+                if addr + len >= max_address || length - addr < len {
                     continue;
                 }
             }
 
+            let idx = memory_blocks.get_idx(&(start as u64)).expect("free ranges are in sync with memory blocks");
             match Self::split_state_transition_at_idx(
                 memory_blocks,
+                free_ranges,
                 idx,
                 addr,
                 len,
                 MemoryStateTransition::AllocateRespectingOwnership(image_handle, device_handle),
             ) {
                 Ok(_) => return Ok(addr),
-                Err(InternalError::MemoryBlock(_)) => {
-                    current = memory_blocks.next_idx(idx);
-                    continue;
-                }
+                Err(InternalError::MemoryBlock(_)) => continue,
                 Err(InternalError::Slice(SliceError::OutOfSpace)) => error!(EfiError::OutOfResources),
                 Err(e) => panic!("{e:?}"),
             }
@@ -733,43 +759,44 @@ impl GCD {
             {
                 Ok(addr) => return Ok(addr),
                 Err(error) => {
-                    log::trace!(target: "allocations", "[{}] Top down GCD low memory attempt failed: {:?}", function!(), error);
+                    log::trace!(target: target::ALLOCATIONS, "[{}] Top down GCD low memory attempt failed: {:?}", function!(), error);
                 }
             }
         }
 
-        log::trace!(target: "allocations", "[{}] Top down GCD allocation: {:#?}", function!(), memory_type);
-        log::trace!(target: "allocations", "[{}]   Max Address: {:#x}", function!(), max_address);
-        log::trace!(target: "allocations", "[{}]   Length: {:#x}", function!(), len);
-        log::trace!(target: "allocations", "[{}]   Align Shift: {:#x}", function!(), align_shift);
-        log::trace!(target: "allocations", "[{}]   Image Handle: {:#x?}", function!(), image_handle);
-        log::trace!(target: "allocations", "[{}]   Device Handle: {:#x?}\n", function!(), device_handle.unwrap_or(ptr::null_mut()));
+        log::trace!(target: target::ALLOCATIONS, "[{}] Top down GCD allocation: {:#?}", function!(), memory_type);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Max Address: {:#x}", function!(), max_address);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Length: {:#x}", function!(), len);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Align Shift: {:#x}", function!(), align_shift);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Image Handle: {:#x?}", function!(), image_handle);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Device Handle: {:#x?}\n", function!(), device_handle.unwrap_or(ptr::null_mut()));
 
         let memory_blocks = &mut self.memory_blocks;
+        let free_ranges = &mut self.free_ranges;
 
-        log::trace!(target: "gcd_measure", "search");
-        let mut current = memory_blocks.get_closest_idx(&(max_address as u64));
-        while let Some(idx) = current {
-            let mb = memory_blocks.get_with_idx(idx).expect("idx is valid from prev_idx");
+        // Only the free ranges of the requested memory type are visited, in reverse address order. If no free range is
+        // large enough, the request cannot be satisfied.
+        log::trace!(target: target::GCD_MEASURE, "search");
+        let mut current = if free_ranges.largest(memory_type) < len as u64 {
+            None
+        } else {
+            free_ranges.last_at_or_below(memory_type, max_address as u64)
+        };
+        while let Some((start, length)) = current {
+            current = free_ranges.prev(memory_type, start, length);
+            let (start, length) = (start as usize, length as usize);
 
             // Account for if the block is truncated by the max_address. Max address
-            // is inclusive, but end() is exclusive so subtract 1 from end.
+            // is inclusive, but the end is exclusive so subtract 1 from the end.
             let usable_len =
-                if mb.end() - 1 > max_address { max_address.checked_sub(mb.start()).unwrap() + 1 } else { mb.len() };
+                if start + length - 1 > max_address { max_address.checked_sub(start).unwrap() + 1 } else { length };
             if usable_len < len {
-                current = memory_blocks.prev_idx(idx);
                 continue;
             }
 
             // Find the last suitable aligned range in the memory block.
-            let addr = (mb.start() + usable_len - len) & (usize::MAX << align_shift);
-            if addr < mb.start() {
-                current = memory_blocks.prev_idx(idx);
-                continue;
-            }
-
-            if mb.as_ref().memory_type != memory_type {
-                current = memory_blocks.prev_idx(idx);
+            let addr = (start + usable_len - len) & (usize::MAX << align_shift);
+            if addr < start {
                 continue;
             }
 
@@ -779,18 +806,17 @@ impl GCD {
                 break;
             }
 
+            let idx = memory_blocks.get_idx(&(start as u64)).expect("free ranges are in sync with memory blocks");
             match Self::split_state_transition_at_idx(
                 memory_blocks,
+                free_ranges,
                 idx,
                 addr,
                 len,
                 MemoryStateTransition::AllocateRespectingOwnership(image_handle, device_handle),
             ) {
                 Ok(_) => return Ok(addr),
-                Err(InternalError::MemoryBlock(_)) => {
-                    current = memory_blocks.prev_idx(idx);
-                    continue;
-                }
+                Err(InternalError::MemoryBlock(_)) => continue,
                 Err(InternalError::Slice(SliceError::OutOfSpace)) => error!(EfiError::OutOfResources),
                 Err(e) => panic!("{e:?}"),
             }
@@ -809,13 +835,13 @@ impl GCD {
     ) -> Result<usize, EfiError> {
         ensure!(len > 0, EfiError::InvalidParameter);
 
-        log::trace!(target: "allocations", "[{}] Exact address GCD allocation: {:#?}", function!(), memory_type);
-        log::trace!(target: "allocations", "[{}]   Address: {:#x}", function!(), address);
-        log::trace!(target: "allocations", "[{}]   Length: {:#x}", function!(), len);
-        log::trace!(target: "allocations", "[{}]   Memory Type: {:?}", function!(), memory_type);
-        log::trace!(target: "allocations", "[{}]   Align Shift: {:#x}", function!(), align_shift);
-        log::trace!(target: "allocations", "[{}]   Image Handle: {:#x?}", function!(), image_handle);
-        log::trace!(target: "allocations", "[{}]   Device Handle: {:#x?}\n", function!(), device_handle.unwrap_or(ptr::null_mut()));
+        log::trace!(target: target::ALLOCATIONS, "[{}] Exact address GCD allocation: {:#?}", function!(), memory_type);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Address: {:#x}", function!(), address);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Length: {:#x}", function!(), len);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Memory Type: {:?}", function!(), memory_type);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Align Shift: {:#x}", function!(), align_shift);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Image Handle: {:#x?}", function!(), image_handle);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Device Handle: {:#x?}\n", function!(), device_handle.unwrap_or(ptr::null_mut()));
 
         // allocate_address allows allocating page 0. This is needed to let Patina DXE Core allocate it for null
         // pointer detection very early in the boot process. Any future allocate at address will fail because it is
@@ -823,8 +849,9 @@ impl GCD {
         // from thinking it is free memory that can be allocated.
 
         let memory_blocks = &mut self.memory_blocks;
+        let free_ranges = &mut self.free_ranges;
 
-        log::trace!(target: "gcd_measure", "search");
+        log::trace!(target: target::GCD_MEASURE, "search");
         let idx = memory_blocks.get_closest_idx(&(address as u64)).ok_or(EfiError::NotFound)?;
         let block = memory_blocks.get_with_idx(idx).ok_or(EfiError::NotFound)?;
 
@@ -835,6 +862,7 @@ impl GCD {
 
         match Self::split_state_transition_at_idx(
             memory_blocks,
+            free_ranges,
             idx,
             address,
             len,
@@ -894,17 +922,19 @@ impl GCD {
     /// # Documentation
     /// UEFI Platform Initialization Specification, Release 1.8, Section II-7.2.4.6
     fn set_gcd_memory_attributes(&mut self, base_address: usize, len: usize, attributes: u64) -> Result<(), EfiError> {
-        log::trace!(target: "allocations", "[{}] Setting memory space attributes for {:#x}", function!(), base_address);
-        log::trace!(target: "allocations", "[{}]   Length: {:#x}", function!(), len);
-        log::trace!(target: "allocations", "[{}]   Attributes: {:#x}\n", function!(), attributes);
+        log::trace!(target: target::ALLOCATIONS, "[{}] Setting memory space attributes for {:#x}", function!(), base_address);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Length: {:#x}", function!(), len);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Attributes: {:#x}\n", function!(), attributes);
 
         let memory_blocks = &mut self.memory_blocks;
+        let free_ranges = &mut self.free_ranges;
 
-        log::trace!(target: "gcd_measure", "search");
+        log::trace!(target: target::GCD_MEASURE, "search");
         let idx = memory_blocks.get_closest_idx(&(base_address as u64)).ok_or(EfiError::NotFound)?;
 
         match Self::split_state_transition_at_idx(
             memory_blocks,
+            free_ranges,
             idx,
             base_address,
             len,
@@ -944,17 +974,19 @@ impl GCD {
         ensure!(base_address + len <= self.maximum_address, EfiError::Unsupported);
         ensure!((base_address & UEFI_PAGE_MASK) == 0 && (len & UEFI_PAGE_MASK) == 0, EfiError::InvalidParameter);
 
-        log::trace!(target: "allocations", "[{}] Setting memory space capabilities for {:#x}", function!(), base_address);
-        log::trace!(target: "allocations", "[{}]   Length: {:#x}", function!(), len);
-        log::trace!(target: "allocations", "[{}]   Capabilities: {:#x}\n", function!(), capabilities);
+        log::trace!(target: target::ALLOCATIONS, "[{}] Setting memory space capabilities for {:#x}", function!(), base_address);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Length: {:#x}", function!(), len);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Capabilities: {:#x}\n", function!(), capabilities);
 
         let memory_blocks = &mut self.memory_blocks;
+        let free_ranges = &mut self.free_ranges;
 
-        log::trace!(target: "gcd_measure", "search");
+        log::trace!(target: target::GCD_MEASURE, "search");
         let idx = memory_blocks.get_closest_idx(&(base_address as u64)).ok_or(EfiError::NotFound)?;
 
         match Self::split_state_transition_at_idx(
             memory_blocks,
+            free_ranges,
             idx,
             base_address,
             len,
@@ -979,7 +1011,7 @@ impl GCD {
         ensure!(buffer.capacity() >= self.memory_descriptor_count(), EfiError::InvalidParameter);
         ensure!(buffer.is_empty(), EfiError::InvalidParameter);
 
-        log::trace!(target: "allocations", "[{}] Enter\n", function!(), );
+        log::trace!(target: target::ALLOCATIONS, "[{}] Enter\n", function!(), );
 
         let blocks = &self.memory_blocks;
 
@@ -1047,15 +1079,42 @@ impl GCD {
 
         let memory_blocks = &self.memory_blocks;
 
-        log::trace!(target: "gcd_measure", "search");
+        log::trace!(target: target::GCD_MEASURE, "search");
         let idx = memory_blocks.get_closest_idx(&(address)).ok_or(EfiError::NotFound)?;
         let mb = memory_blocks.get_with_idx(idx).expect("idx is valid from get_closest_idx");
-        match mb {
-            MemoryBlock::Allocated(descriptor) | MemoryBlock::Unallocated(descriptor) => Ok(*descriptor),
-        }
+        let descriptor = match mb {
+            MemoryBlock::Allocated(descriptor) | MemoryBlock::Unallocated(descriptor) => *descriptor,
+        };
+        // The closest block is the last one for the addresses past the maximum address.
+        ensure!(address - descriptor.base_address < descriptor.length, EfiError::NotFound);
+        Ok(descriptor)
     }
 
     fn split_state_transition_at_idx(
+        memory_blocks: &mut Rbt<MemoryBlock>,
+        free_ranges: &mut FreeRanges,
+        idx: usize,
+        base_address: usize,
+        len: usize,
+        transition: MemoryStateTransition,
+    ) -> Result<usize, InternalError> {
+        // The transition only changes the block and merges it with its neighbors, so only the free ranges of these
+        // blocks need to be updated.
+        let first = *memory_blocks.get_with_idx(memory_blocks.prev_idx(idx).unwrap_or(idx)).expect("idx is valid");
+        let last = *memory_blocks.get_with_idx(memory_blocks.next_idx(idx).unwrap_or(idx)).expect("idx is valid");
+        let region = first.start() as u64..last.end() as u64;
+
+        free_ranges.remove_region(memory_blocks, region.clone());
+        let result = Self::split_state_transition_at_idx_worker(memory_blocks, idx, base_address, len, transition);
+        if let Err(err) = free_ranges.insert_region(memory_blocks, region) {
+            // The index has room for every memory block, so this should not happen.
+            log::error!("[{}] Failed to update the free range index: {:?}", function!(), err);
+            debug_assert!(false);
+        }
+        result
+    }
+
+    fn split_state_transition_at_idx_worker(
         memory_blocks: &mut Rbt<MemoryBlock>,
         idx: usize,
         base_address: usize,
@@ -1064,11 +1123,11 @@ impl GCD {
     ) -> Result<usize, InternalError> {
         let mb_before_split = *memory_blocks.get_with_idx(idx).expect("Caller should ensure idx is valid.");
 
-        log::trace!(target: "allocations", "[{}] Splitting memory block at {:#x}", function!(), base_address);
-        log::trace!(target: "allocations", "[{}]   Total Memory Blocks Right Now: {:#}", function!(), memory_blocks.len());
-        log::trace!(target: "allocations", "[{}]   Length: {:#x}", function!(), len);
-        log::trace!(target: "allocations", "[{}]   Block Index: {:#x}", function!(), idx);
-        log::trace!(target: "allocations", "[{}]   Transition:\n  {:#?}", function!(), transition);
+        log::trace!(target: target::ALLOCATIONS, "[{}] Splitting memory block at {:#x}", function!(), base_address);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Total Memory Blocks Right Now: {:#}", function!(), memory_blocks.len());
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Length: {:#x}", function!(), len);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Block Index: {:#x}", function!(), idx);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Transition:\n  {:#?}", function!(), transition);
 
         // split_state_transition does not update the key, so this is safe.
         let new_idx = unsafe {
@@ -1079,25 +1138,25 @@ impl GCD {
             )? {
                 MemoryBlockSplit::Same(_) => Ok(idx),
                 MemoryBlockSplit::After(_, next) => {
-                    log::trace!(target: "gcd_measure", "add");
-                    log::trace!(target: "allocations", "[{}] MemoryBlockSplit (After) -> Next: {:#x?}\n", function!(), next);
+                    log::trace!(target: target::GCD_MEASURE, "add");
+                    log::trace!(target: target::ALLOCATIONS, "[{}] MemoryBlockSplit (After) -> Next: {:#x?}\n", function!(), next);
                     memory_blocks.add(next)
                 }
                 MemoryBlockSplit::Before(_, next) => {
-                    log::trace!(target: "gcd_measure", "add");
-                    log::trace!(target: "allocations", "[{}] MemoryBlockSplit (Before) -> Next: {:#x?}\n", function!(), next);
+                    log::trace!(target: target::GCD_MEASURE, "add");
+                    log::trace!(target: target::ALLOCATIONS, "[{}] MemoryBlockSplit (Before) -> Next: {:#x?}\n", function!(), next);
                     memory_blocks.add(next).map(|_| idx)
                 }
                 MemoryBlockSplit::Middle(_, next, next2) => {
-                    log::trace!(target: "gcd_measure", "add");
-                    log::trace!(target: "gcd_measure", "add");
-                    log::trace!(target: "allocations", "[{}] MemoryBlockSplit (Middle) -> Next: {:#x?}. Next2: {:#x?}\n", function!(), next, next2);
+                    log::trace!(target: target::GCD_MEASURE, "add");
+                    log::trace!(target: target::GCD_MEASURE, "add");
+                    log::trace!(target: target::ALLOCATIONS, "[{}] MemoryBlockSplit (Middle) -> Next: {:#x?}. Next2: {:#x?}\n", function!(), next, next2);
                     memory_blocks.add_many([next2, next])
                 }
             }
         };
 
-        log::trace!(target: "allocations", "[{}] Next Index is {:x?}\n", function!(), new_idx);
+        log::trace!(target: target::ALLOCATIONS, "[{}] Next Index is {:x?}\n", function!(), new_idx);
 
         // If the split failed, restore the memory block to its previous state.
         let idx = match new_idx {
@@ -1304,9 +1363,9 @@ impl IoGCD {
         ensure!(len > 0, EfiError::InvalidParameter);
         ensure!(base_address + len <= self.maximum_address, EfiError::Unsupported);
 
-        log::trace!(target: "allocations", "[{}] Adding IO space at {:#x}", function!(), base_address);
-        log::trace!(target: "allocations", "[{}]   Length: {:#x}", function!(), len);
-        log::trace!(target: "allocations", "[{}]   IO Type: {:?}\n", function!(), io_type);
+        log::trace!(target: target::ALLOCATIONS, "[{}] Adding IO space at {:#x}", function!(), base_address);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Length: {:#x}", function!(), len);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   IO Type: {:?}\n", function!(), io_type);
 
         if self.io_blocks.capacity() == 0 {
             self.init_io_blocks()?;
@@ -1314,7 +1373,7 @@ impl IoGCD {
 
         let io_blocks = &mut self.io_blocks;
 
-        log::trace!(target: "gcd_measure", "search");
+        log::trace!(target: target::GCD_MEASURE, "search");
         let idx = io_blocks.get_closest_idx(&(base_address as u64)).ok_or(EfiError::NotFound)?;
         let block = io_blocks.get_with_idx(idx).ok_or(EfiError::NotFound)?;
 
@@ -1338,8 +1397,8 @@ impl IoGCD {
         ensure!(len > 0, EfiError::InvalidParameter);
         ensure!(base_address + len <= self.maximum_address, EfiError::Unsupported);
 
-        log::trace!(target: "allocations", "[{}] Removing IO space at {:#x}", function!(), base_address);
-        log::trace!(target: "allocations", "[{}]   Length: {:#x}\n", function!(), len);
+        log::trace!(target: target::ALLOCATIONS, "[{}] Removing IO space at {:#x}", function!(), base_address);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Length: {:#x}\n", function!(), len);
 
         if self.io_blocks.capacity() == 0 {
             self.init_io_blocks()?;
@@ -1347,7 +1406,7 @@ impl IoGCD {
 
         let io_blocks = &mut self.io_blocks;
 
-        log::trace!(target: "gcd_measure", "search");
+        log::trace!(target: target::GCD_MEASURE, "search");
         let idx = io_blocks.get_closest_idx(&(base_address as u64)).ok_or(EfiError::NotFound)?;
         let block = *io_blocks.get_with_idx(idx).expect("Idx valid from get_closest_idx");
 
@@ -1379,12 +1438,12 @@ impl IoGCD {
         ensure!(self.maximum_address != 0, EfiError::NotReady);
         ensure!(len > 0 && image_handle > ptr::null_mut(), EfiError::InvalidParameter);
 
-        log::trace!(target: "allocations", "[{}] Allocating IO space: {:x?}", function!(), allocate_type);
-        log::trace!(target: "allocations", "[{}]   Length: {:#x}", function!(), len);
-        log::trace!(target: "allocations", "[{}]   IO Type: {:?}", function!(), io_type);
-        log::trace!(target: "allocations", "[{}]   Alignment: {:#x}", function!(), alignment);
-        log::trace!(target: "allocations", "[{}]   Image Handle: {:#x?}", function!(), image_handle);
-        log::trace!(target: "allocations", "[{}]   Device Handle: {:#x?}\n", function!(), device_handle.unwrap_or(ptr::null_mut()));
+        log::trace!(target: target::ALLOCATIONS, "[{}] Allocating IO space: {:x?}", function!(), allocate_type);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Length: {:#x}", function!(), len);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   IO Type: {:?}", function!(), io_type);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Alignment: {:#x}", function!(), alignment);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Image Handle: {:#x?}", function!(), image_handle);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Device Handle: {:#x?}\n", function!(), device_handle.unwrap_or(ptr::null_mut()));
 
         match allocate_type {
             AllocateType::BottomUp(max_address) => self.allocate_bottom_up(
@@ -1421,12 +1480,12 @@ impl IoGCD {
     ) -> Result<usize, EfiError> {
         ensure!(len > 0, EfiError::InvalidParameter);
 
-        log::trace!(target: "allocations", "[{}] Bottom up IO allocation: {:#?}", function!(), io_type);
-        log::trace!(target: "allocations", "[{}]   Max Address: {:#x}", function!(), max_address);
-        log::trace!(target: "allocations", "[{}]   Length: {:#x}", function!(), len);
-        log::trace!(target: "allocations", "[{}]   Alignment: {:#x}", function!(), alignment);
-        log::trace!(target: "allocations", "[{}]   Image Handle: {:#x?}", function!(), image_handle);
-        log::trace!(target: "allocations", "[{}]   Device Handle: {:#x?}\n", function!(), device_handle.unwrap_or(ptr::null_mut()));
+        log::trace!(target: target::ALLOCATIONS, "[{}] Bottom up IO allocation: {:#?}", function!(), io_type);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Max Address: {:#x}", function!(), max_address);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Length: {:#x}", function!(), len);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Alignment: {:#x}", function!(), alignment);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Image Handle: {:#x?}", function!(), image_handle);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Device Handle: {:#x?}\n", function!(), device_handle.unwrap_or(ptr::null_mut()));
 
         if self.io_blocks.capacity() == 0 {
             self.init_io_blocks()?;
@@ -1434,7 +1493,7 @@ impl IoGCD {
 
         let io_blocks = &mut self.io_blocks;
 
-        log::trace!(target: "gcd_measure", "search");
+        log::trace!(target: target::GCD_MEASURE, "search");
         let mut current = io_blocks.first_idx();
         while let Some(idx) = current {
             let ib = io_blocks.get_with_idx(idx).expect("idx is valid from next_idx");
@@ -1483,12 +1542,12 @@ impl IoGCD {
     ) -> Result<usize, EfiError> {
         ensure!(len > 0, EfiError::InvalidParameter);
 
-        log::trace!(target: "allocations", "[{}] Top down IO allocation: {:#?}", function!(), io_type);
-        log::trace!(target: "allocations", "[{}]   Max Address: {:#x}", function!(), max_address);
-        log::trace!(target: "allocations", "[{}]   Length: {:#x}", function!(), len);
-        log::trace!(target: "allocations", "[{}]   Align Shift: {:#x}", function!(), align_shift);
-        log::trace!(target: "allocations", "[{}]   Image Handle: {:#x?}", function!(), image_handle);
-        log::trace!(target: "allocations", "[{}]   Device Handle: {:#x?}\n", function!(), device_handle.unwrap_or(ptr::null_mut()));
+        log::trace!(target: target::ALLOCATIONS, "[{}] Top down IO allocation: {:#?}", function!(), io_type);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Max Address: {:#x}", function!(), max_address);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Length: {:#x}", function!(), len);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Align Shift: {:#x}", function!(), align_shift);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Image Handle: {:#x?}", function!(), image_handle);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Device Handle: {:#x?}\n", function!(), device_handle.unwrap_or(ptr::null_mut()));
 
         if self.io_blocks.capacity() == 0 {
             self.init_io_blocks()?;
@@ -1496,7 +1555,7 @@ impl IoGCD {
 
         let io_blocks = &mut self.io_blocks;
 
-        log::trace!(target: "gcd_measure", "search");
+        log::trace!(target: target::GCD_MEASURE, "search");
         let mut current = io_blocks.get_closest_idx(&(max_address as u64));
         while let Some(idx) = current {
             let ib = io_blocks.get_with_idx(idx).expect("idx is valid from prev_idx");
@@ -1551,20 +1610,20 @@ impl IoGCD {
     ) -> Result<usize, EfiError> {
         ensure!(len > 0, EfiError::InvalidParameter);
 
-        log::trace!(target: "allocations", "[{}] Exact address IO allocation: {:#?}", function!(), io_type);
-        log::trace!(target: "allocations", "[{}]   Address: {:#x}", function!(), address);
-        log::trace!(target: "allocations", "[{}]   Length: {:#x}", function!(), len);
-        log::trace!(target: "allocations", "[{}]   IO Type: {:?}", function!(), io_type);
-        log::trace!(target: "allocations", "[{}]   Alignment: {:#x}", function!(), alignment);
-        log::trace!(target: "allocations", "[{}]   Image Handle: {:#x?}", function!(), image_handle);
-        log::trace!(target: "allocations", "[{}]   Device Handle: {:#x?}\n", function!(), device_handle.unwrap_or(ptr::null_mut()));
+        log::trace!(target: target::ALLOCATIONS, "[{}] Exact address IO allocation: {:#?}", function!(), io_type);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Address: {:#x}", function!(), address);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Length: {:#x}", function!(), len);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   IO Type: {:?}", function!(), io_type);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Alignment: {:#x}", function!(), alignment);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Image Handle: {:#x?}", function!(), image_handle);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Device Handle: {:#x?}\n", function!(), device_handle.unwrap_or(ptr::null_mut()));
 
         if self.io_blocks.capacity() == 0 {
             self.init_io_blocks()?;
         }
         let io_blocks = &mut self.io_blocks;
 
-        log::trace!(target: "gcd_measure", "search");
+        log::trace!(target: target::GCD_MEASURE, "search");
         let idx = io_blocks.get_closest_idx(&(address as u64)).ok_or(EfiError::NotFound)?;
         let block = io_blocks.get_with_idx(idx).ok_or(EfiError::NotFound)?;
 
@@ -1596,8 +1655,8 @@ impl IoGCD {
         ensure!(len > 0, EfiError::InvalidParameter);
         ensure!(base_address + len <= self.maximum_address, EfiError::Unsupported);
 
-        log::trace!(target: "allocations", "[{}] Free IO space at {:#?}", function!(), base_address);
-        log::trace!(target: "allocations", "[{}]   Length: {:#x}\n", function!(), len);
+        log::trace!(target: target::ALLOCATIONS, "[{}] Free IO space at {:#?}", function!(), base_address);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Length: {:#x}\n", function!(), len);
 
         if self.io_blocks.capacity() == 0 {
             self.init_io_blocks()?;
@@ -1605,7 +1664,7 @@ impl IoGCD {
 
         let io_blocks = &mut self.io_blocks;
 
-        log::trace!(target: "gcd_measure", "search");
+        log::trace!(target: target::GCD_MEASURE, "search");
         let idx = io_blocks.get_closest_idx(&(base_address as u64)).ok_or(EfiError::NotFound)?;
 
         match Self::split_state_transition_at_idx(io_blocks, idx, base_address, len, IoStateTransition::Free) {
@@ -1625,7 +1684,7 @@ impl IoGCD {
         ensure!(buffer.capacity() >= self.io_descriptor_count(), EfiError::InvalidParameter);
         ensure!(buffer.is_empty(), EfiError::InvalidParameter);
 
-        log::trace!(target: "allocations", "[{}] Enter\n", function!(), );
+        log::trace!(target: target::ALLOCATIONS, "[{}] Enter\n", function!(), );
 
         if self.io_blocks.capacity() == 0 {
             self.init_io_blocks()?;
@@ -1652,11 +1711,11 @@ impl IoGCD {
     ) -> Result<usize, InternalError> {
         let ib_before_split = *io_blocks.get_with_idx(idx).expect("Caller should ensure idx is valid.");
 
-        log::trace!(target: "allocations", "[{}] Splitting IO block at {:#x}", function!(), base_address);
-        log::trace!(target: "allocations", "[{}]   Total IO Blocks Right Now: {:#}", function!(), io_blocks.len());
-        log::trace!(target: "allocations", "[{}]   Length: {:#x}", function!(), len);
-        log::trace!(target: "allocations", "[{}]   Block Index: {:#x}", function!(), idx);
-        log::trace!(target: "allocations", "[{}]   Transition: {:?}\n", function!(), transition);
+        log::trace!(target: target::ALLOCATIONS, "[{}] Splitting IO block at {:#x}", function!(), base_address);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Total IO Blocks Right Now: {:#}", function!(), io_blocks.len());
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Length: {:#x}", function!(), len);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Block Index: {:#x}", function!(), idx);
+        log::trace!(target: target::ALLOCATIONS, "[{}]   Transition: {:?}\n", function!(), transition);
 
         // split_state_transition does not update the key, so this is safe.
         let new_idx = unsafe {
@@ -1667,19 +1726,19 @@ impl IoGCD {
             )? {
                 IoBlockSplit::Same(_) => Ok(idx),
                 IoBlockSplit::After(_, next) => {
-                    log::trace!(target: "gcd_measure", "add");
-                    log::trace!(target: "allocations", "[{}] IoBlockSplit (After) -> Next: {:#x?}\n", function!(), next);
+                    log::trace!(target: target::GCD_MEASURE, "add");
+                    log::trace!(target: target::ALLOCATIONS, "[{}] IoBlockSplit (After) -> Next: {:#x?}\n", function!(), next);
                     io_blocks.add(next)
                 }
                 IoBlockSplit::Before(_, next) => {
-                    log::trace!(target: "gcd_measure", "add");
-                    log::trace!(target: "allocations", "[{}] IoBlockSplit (Before) -> Next: {:#x?}\n", function!(), next);
+                    log::trace!(target: target::GCD_MEASURE, "add");
+                    log::trace!(target: target::ALLOCATIONS, "[{}] IoBlockSplit (Before) -> Next: {:#x?}\n", function!(), next);
                     io_blocks.add(next).map(|_| idx)
                 }
                 IoBlockSplit::Middle(_, next, next2) => {
-                    log::trace!(target: "gcd_measure", "add");
-                    log::trace!(target: "gcd_measure", "add");
-                    log::trace!(target: "allocations", "[{}] IoBlockSplit (Middle) -> Next: {:#x?}. Next2: {:#x?}\n", function!(), next, next2);
+                    log::trace!(target: target::GCD_MEASURE, "add");
+                    log::trace!(target: target::GCD_MEASURE, "add");
+                    log::trace!(target: target::ALLOCATIONS, "[{}] IoBlockSplit (Middle) -> Next: {:#x?}. Next2: {:#x?}\n", function!(), next, next2);
                     io_blocks.add_many([next2, next])
                 }
             }
@@ -1729,6 +1788,25 @@ impl IoGCD {
         self.io_blocks.len()
     }
 
+    /// Returns the descriptor of the block containing the given IO address.
+    pub fn get_io_descriptor_for_address(
+        &mut self,
+        address: efi::PhysicalAddress,
+    ) -> Result<dxe_services::IoSpaceDescriptor, EfiError> {
+        ensure!(self.maximum_address != 0, EfiError::NotReady);
+
+        if self.io_blocks.capacity() == 0 {
+            self.init_io_blocks()?;
+        }
+
+        let idx = self.io_blocks.get_closest_idx(&address).ok_or(EfiError::NotFound)?;
+        let descriptor = match self.io_blocks.get_with_idx(idx).expect("idx is valid from get_closest_idx") {
+            IoBlock::Allocated(descriptor) | IoBlock::Unallocated(descriptor) => *descriptor,
+        };
+        ensure!(address - descriptor.base_address < descriptor.length, EfiError::NotFound);
+        Ok(descriptor)
+    }
+
     const GCD_IO_TYPE_NAMES: [&'static str; 4] = [
         "NonExist", // EfiGcdIoTypeNonExistent
         "Reserved", // EfiGcdIoTypeReserved
@@ -1785,10 +1863,51 @@ pub enum MapChangeType {
     RemoveMemorySpace,
     AllocateMemorySpace,
     FreeMemorySpace,
-    SetMemoryAttributes,
+    SetMemoryAttributes { base_address: u64, length: u64 },
     SetMemoryCapabilities,
 }
 
+/// Combines the attributes of the UEFI pages sharing a page granule into the attributes to apply to the granule.
+///
+/// The page table cannot express different attributes within a granule, so the granule gets the least restrictive
+/// access attributes of its mapped pages: it is read only or non-executable only if every mapped page is. Read
+/// protected (i.e. unmapped) pages do not constrain the granule. If the mapped pages use different cache attributes,
+/// the granule is mapped uncached. Returns `None` if no page of the granule is mapped.
+fn combine_granule_attributes(page_attributes: impl IntoIterator<Item = u64>) -> Option<u64> {
+    let mut combined: Option<u64> = None;
+    for attributes in page_attributes.into_iter().filter(|attributes| attributes & efi::MEMORY_RP == 0) {
+        combined = Some(match combined {
+            None => attributes & (efi::CACHE_ATTRIBUTE_MASK | efi::MEMORY_ACCESS_MASK),
+            Some(combined) => {
+                let access = combined & attributes & efi::MEMORY_ACCESS_MASK;
+                let (combined_cache, cache) =
+                    (combined & efi::CACHE_ATTRIBUTE_MASK, attributes & efi::CACHE_ATTRIBUTE_MASK);
+                let cache = match (combined_cache, cache) {
+                    (0, cache) => cache,
+                    (combined_cache, 0) => combined_cache,
+                    (combined_cache, cache) if combined_cache == cache => cache,
+                    _ => efi::MEMORY_UC,
+                };
+                access | cache
+            }
+        });
+    }
+    combined
+}
+
+/// Splits a range on page granule boundaries into the whole granules it covers, and the bases of the (at most two)
+/// granules it only partially covers.
+fn split_on_granule(granule: PageGranule, base_address: u64, len: u64) -> (Option<(u64, u64)>, [Option<u64>; 2]) {
+    let end = base_address + len;
+    let body_start = granule.align_up(base_address);
+    let body_end = granule.align_down(end);
+
+    let body = (body_start < body_end).then(|| (body_start, body_end - body_start));
+    let head = (base_address != body_start).then_some(granule.align_down(base_address));
+    let tail = (end != body_end).then_some(body_end).filter(|tail| Some(*tail) != head);
+    (body, [head, tail])
+}
+
 /// GCD map change callback function type.
 pub type MapChangeCallback = fn(MapChangeType);
 
@@ -1798,7 +1917,10 @@ pub struct SpinLockedGcd {
     io: tpl_lock::TplMutex<IoGCD>,
     memory_change_callback: Option<MapChangeCallback>,
     memory_type_info_table: [EFiMemoryTypeInformation; 17],
-    page_table: tpl_lock::TplMutex<Option<Box<dyn PageTable>>>,
+    page_table: tpl_lock::TplMutex<Option<LargePageTable>>,
+    page_granule: tpl_lock::TplMutex<Option<PageGranule>>,
+    large_pages_disabled: tpl_lock::TplMutex<bool>,
+    special_regions: tpl_lock::TplMutex<Vec<SpecialRegion>>,
 }
 
 impl SpinLockedGcd {
@@ -1817,6 +1939,7 @@ impl SpinLockedGcd {
                 GCD {
                     maximum_address: 0,
                     memory_blocks: Rbt::new(),
+                    free_ranges: FreeRanges::new(),
                     allocate_memory_space_fn: GCD::allocate_memory_space_internal,
                     free_memory_space_fn: GCD::free_memory_space_worker,
                     default_attributes: efi::MEMORY_XP,
@@ -1850,6 +1973,9 @@ impl SpinLockedGcd {
                 EFiMemoryTypeInformation { memory_type: 16 /*EfiMaxMemoryType*/, number_of_pages: 0 },
             ],
             page_table: tpl_lock::TplMutex::new(efi::TPL_HIGH_LEVEL, None, "GcdPageTableLock"),
+            page_granule: tpl_lock::TplMutex::new(efi::TPL_HIGH_LEVEL, None, "GcdPageGranuleLock"),
+            large_pages_disabled: tpl_lock::TplMutex::new(efi::TPL_HIGH_LEVEL, false, "GcdLargePagesDisabledLock"),
+            special_regions: tpl_lock::TplMutex::new(efi::TPL_HIGH_LEVEL, Vec::new(), "GcdSpecialRegionsLock"),
         }
     }
 
@@ -1867,7 +1993,178 @@ impl SpinLockedGcd {
         &self.memory_type_info_table[memory_type as usize]
     }
 
+    /// Overrides the page granule used for page table changes. If not set, the granule is detected when paging is
+    /// initialized.
+    pub fn set_page_granule(&self, granule: PageGranule) {
+        *self.page_granule.lock() = Some(granule);
+    }
+
+    /// Returns the page granule used for page table changes.
+    pub fn page_granule(&self) -> PageGranule {
+        self.page_granule.lock().unwrap_or(DEFAULT_PAGE_GRANULE)
+    }
+
+    /// Maps all memory with pages of the granule instead of preferring blocks for large ranges. Must be set before
+    /// paging is initialized.
+    pub fn set_large_pages_disabled(&self, disabled: bool) {
+        *self.large_pages_disabled.lock() = disabled;
+    }
+
+    /// Returns whether paging is initialized, i.e. whether freed memory is unmapped.
+    pub fn is_paging_initialized(&self) -> bool {
+        self.page_table.lock().is_some()
+    }
+
+    /// Returns the statistics on the large pages of the page table, or `None` if paging is not initialized.
+    pub fn paging_statistics(&self) -> Option<PagingStatistics> {
+        self.page_table.lock().as_ref().map(LargePageTable::statistics)
+    }
+
+    /// Registers a range exempt from memory protection policies. See [SpecialRegion].
+    ///
+    /// Once paging is initialized, the attributes of the range are applied to the page table again so the exemptions
+    /// take effect right away.
+    pub fn add_special_region(&self, region: SpecialRegion) -> Result<(), EfiError> {
+        validate_special_region(region.base_address, region.length, region.exemptions)?;
+        log::info!("Memory protection special region: {region}");
+        let (base_address, length) = (region.base_address, region.length);
+        self.special_regions.lock().push(region);
+        self.refresh_paging_attributes(base_address, length)
+    }
+
+    /// Returns the ranges exempt from memory protection policies, in the order they were registered.
+    pub fn special_regions(&self) -> Vec<SpecialRegion> {
+        self.special_regions.lock().clone()
+    }
+
+    /// Applies the GCD attributes of a range to the page table again. Read protected pages are only mapped back if a
+    /// special region exempts them from guards, the others are already unmapped. Does nothing if the page table is
+    /// not initialized.
+    fn refresh_paging_attributes(&self, base_address: u64, length: u64) -> Result<(), EfiError> {
+        if self.page_table.lock().is_none() {
+            return Ok(());
+        }
+        let end = base_address + length;
+        let mut address = base_address;
+        while address < end {
+            let descriptor = self.get_memory_descriptor_for_address(address)?;
+            let next = u64::min(descriptor.base_address + descriptor.length, end);
+            if descriptor.memory_type != GcdMemoryType::NonExistent
+                && descriptor.attributes & (efi::CACHE_ATTRIBUTE_MASK | efi::MEMORY_ACCESS_MASK) != 0
+            {
+                let parts = split_on_special_regions(&self.special_regions.lock(), address, next - address);
+                for (part_base, part_len, exemptions) in parts {
+                    if descriptor.attributes & efi::MEMORY_RP == 0 || exemptions.guard {
+                        self.set_granule_paging_attributes(
+                            part_base as usize,
+                            part_len as usize,
+                            exempt_attributes(exemptions, descriptor.attributes),
+                        )?;
+                    }
+                }
+            }
+            address = next;
+        }
+        Ok(())
+    }
+
+    /// Returns the attributes to apply to the granule at `granule_base`, combining the attributes of the GCD
+    /// descriptors it overlaps. See [combine_granule_attributes].
+    fn granule_attributes(&self, granule: PageGranule, granule_base: u64) -> Option<u64> {
+        let granule_end = granule_base + granule.size();
+        let mut address = granule_base;
+        combine_granule_attributes(core::iter::from_fn(|| {
+            while address < granule_end {
+                let descriptor = self.get_memory_descriptor_for_address(address).ok()?;
+                address = descriptor.base_address + descriptor.length;
+                if descriptor.memory_type != GcdMemoryType::NonExistent {
+                    return Some(descriptor.attributes);
+                }
+            }
+            None
+        }))
+    }
+
+    /// Applies the attributes of a range to the page table, on the boundaries of the active page granule.
+    ///
+    /// Whole granules covered by the range get `attributes`. Granules only partially covered by the range are shared
+    /// with UEFI pages outside of it, so they get the combined attributes of all their pages from the GCD, which
+    /// must already be updated.
+    ///
+    /// The attributes applied to the parts of the range covered by special regions are adjusted for their exemptions.
     fn set_paging_attributes(&self, base_address: usize, len: usize, attributes: u64) -> Result<(), EfiError> {
+        let parts = split_on_special_regions(&self.special_regions.lock(), base_address as u64, len as u64);
+        for (part_base, part_len, exemptions) in parts {
+            self.set_granule_paging_attributes(
+                part_base as usize,
+                part_len as usize,
+                exempt_attributes(exemptions, attributes),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn set_granule_paging_attributes(&self, base_address: usize, len: usize, attributes: u64) -> Result<(), EfiError> {
+        let granule = self.page_granule();
+        if granule.is_range_aligned(base_address as u64, len as u64) {
+            return self.apply_paging_attributes(base_address, len, attributes);
+        }
+
+        let (body, partial_granules) = split_on_granule(granule, base_address as u64, len as u64);
+        if let Some((body_base, body_len)) = body {
+            self.apply_paging_attributes(body_base as usize, body_len as usize, attributes)?;
+        }
+        for granule_base in partial_granules.into_iter().flatten() {
+            match self.granule_attributes(granule, granule_base) {
+                Some(granule_attributes) => {
+                    self.apply_paging_attributes(granule_base as usize, granule.size() as usize, granule_attributes)?
+                }
+                None => {
+                    self.unmap_paging_range(granule_base as usize, granule.size() as usize).map_err(|e| match e {
+                        PtError::OutOfResources => EfiError::OutOfResources,
+                        _ => EfiError::InvalidParameter,
+                    })?
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Unmaps a range from the page table, keeping the granules only partially covered by the range mapped with the
+    /// combined attributes of their remaining UEFI pages. Does nothing if the page table is not initialized.
+    fn unmap_paging_range(&self, base_address: usize, len: usize) -> Result<(), PtError> {
+        let granule = self.page_granule();
+        let (body, partial_granules) = if granule.is_range_aligned(base_address as u64, len as u64) {
+            (Some((base_address as u64, len as u64)), [None, None])
+        } else {
+            split_on_granule(granule, base_address as u64, len as u64)
+        };
+        let partial_granules =
+            partial_granules.map(|base| base.map(|base| (base, self.granule_attributes(granule, base))));
+
+        let Some(page_table) = &mut *self.page_table.lock() else {
+            return Ok(());
+        };
+        if let Some((body_base, body_len)) = body {
+            page_table.unmap_memory_region(body_base, body_len)?;
+        }
+        for (granule_base, granule_attributes) in partial_granules.into_iter().flatten() {
+            match granule_attributes {
+                Some(granule_attributes) => {
+                    let paging_attrs = MemoryAttributes::from_bits_truncate(granule_attributes)
+                        & (MemoryAttributes::AccessAttributesMask | MemoryAttributes::CacheAttributesMask);
+                    page_table.map_memory_region(granule_base, granule.size(), paging_attrs)?
+                }
+                None => match page_table.query_memory_region(granule_base, granule.size()) {
+                    Err(PtError::NoMapping) => {}
+                    _ => page_table.unmap_memory_region(granule_base, granule.size())?,
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_paging_attributes(&self, base_address: usize, len: usize, attributes: u64) -> Result<(), EfiError> {
         if let Some(page_table) = &mut *self.page_table.lock() {
             // only apply page table attributes to the page table, not our virtual GCD attributes
             let paging_attrs = MemoryAttributes::from_bits_truncate(attributes)
@@ -1905,7 +2202,7 @@ impl SpinLockedGcd {
                     == paging_attrs
             {
                 log::trace!(
-                    target: "paging",
+                    target: target::PAGING,
                     "Memory region {base_address:#x?} of length {len:#x?} with attributes {attributes:#x?}. No paging action taken: Region already mapped with these attributes.",
                 );
                 return Ok(());
@@ -1925,7 +2222,7 @@ impl SpinLockedGcd {
                 match page_table.unmap_memory_region(base_address as u64, len as u64) {
                     Ok(_) => {
                         log::trace!(
-                            target: "paging",
+                            target: target::PAGING,
                             "Memory region {base_address:#x?} of length {len:#x?} unmapped",
                         );
                         return Ok(());
@@ -1950,7 +2247,7 @@ impl SpinLockedGcd {
                         && (paging_attrs & MemoryAttributes::CacheAttributesMask) != MemoryAttributes::empty()
                     {
                         log::trace!(
-                            target: "paging",
+                            target: target::PAGING,
                             "Attributes for memory region {base_address:#x?} of length {len:#x?} were updated to {paging_attrs:#x?} from {region_attrs:#x?}, sending cache attributes changed event",
                         );
 
@@ -1959,7 +2256,7 @@ impl SpinLockedGcd {
                         && (paging_attrs & MemoryAttributes::CacheAttributesMask) != MemoryAttributes::empty()
                     {
                         log::trace!(
-                            target: "paging",
+                            target: target::PAGING,
                             "Memory region {base_address:#x?} of length {len:#x?} mapped, sending cache attributes changed event",
                         );
 
@@ -1967,7 +2264,7 @@ impl SpinLockedGcd {
                     }
 
                     log::trace!(
-                        target: "paging",
+                        target: target::PAGING,
                         "Memory region {base_address:#x?} of length {len:#x?} mapped with attributes {paging_attrs:#x?}",
                     );
                     Ok(())
@@ -2013,6 +2310,7 @@ impl SpinLockedGcd {
         let (mut mem, mut io) = (self.memory.lock(), self.io.lock());
         mem.maximum_address = 0;
         mem.memory_blocks = Rbt::new();
+        mem.free_ranges = FreeRanges::new();
         io.maximum_address = 0;
         io.io_blocks = Rbt::new();
     }
@@ -2031,8 +2329,18 @@ impl SpinLockedGcd {
     pub(crate) fn init_paging(&self, hob_list: &HobList) {
         log::info!("Initializing paging for the GCD");
 
+        let granule = *self.page_granule.lock().get_or_insert_with(detect_page_granule);
+        log::info!("Using a page granule of {:#x} bytes", granule.size());
+
+        let large_pages_disabled = *self.large_pages_disabled.lock();
+        if large_pages_disabled {
+            log::info!("Large pages are disabled, all memory is mapped with pages of the granule");
+        }
+
         let page_allocator = PagingAllocator::new(&GCD);
-        *self.page_table.lock() = Some(create_cpu_paging(page_allocator).expect("Failed to create CPU page table"));
+        let paging = LargePageTable::create(page_allocator, TableFormat::current(granule), large_pages_disabled)
+            .expect("Failed to create CPU page table");
+        *self.page_table.lock() = Some(paging);
 
         // this is before we get allocated descriptors, so we don't need to preallocate memory here
         let mut mmio_res_descs: Vec<dxe_services::MemorySpaceDescriptor> = Vec::new();
@@ -2056,7 +2364,7 @@ impl SpinLockedGcd {
         // now map the memory regions, keeping any cache attributes set in the GCD descriptors
         for desc in descriptors {
             log::trace!(
-                target: "paging",
+                target: target::PAGING,
                 "Mapping memory region {:#x?} of length {:#x?} with attributes {:#x?}",
                 desc.base_address,
                 desc.length,
@@ -2137,7 +2445,7 @@ impl SpinLockedGcd {
             };
 
             log::trace!(
-                target: "paging",
+                target: target::PAGING,
                 "Mapping DXE Core image memory region {section_base_address:#x?} of length {aligned_virtual_size:#x?} with attributes {attributes:#x?}",
             );
 
@@ -2165,7 +2473,7 @@ impl SpinLockedGcd {
             let new_attributes = (desc.attributes & efi::CACHE_ATTRIBUTE_MASK) | efi::MEMORY_XP;
 
             log::trace!(
-                target: "paging",
+                target: target::PAGING,
                 "Mapping {:?} region {:#x?} of length {:#x?} with attributes {:#x?}",
                 desc.memory_type,
                 base_address,
@@ -2202,6 +2510,9 @@ impl SpinLockedGcd {
 
         self.page_table.lock().as_mut().unwrap().install_page_table().expect("Failed to install the page table");
 
+        if let Some(statistics) = self.paging_statistics() {
+            log::info!("Paging statistics:\n{statistics}");
+        }
         log::info!("Paging initialized for the GCD");
     }
 
@@ -2236,15 +2547,13 @@ impl SpinLockedGcd {
     pub fn remove_memory_space(&self, base_address: usize, len: usize) -> Result<(), EfiError> {
         let result = self.memory.lock().remove_memory_space(base_address, len);
         if result.is_ok() {
-            if let Some(page_table) = &mut *self.page_table.lock() {
-                match page_table.unmap_memory_region(base_address as u64, len as u64) {
-                    Ok(_) => {}
-                    Err(status) => {
-                        log::error!(
-                            "Failed to unmap memory region {base_address:#x?} of length {len:#x?}. Status: {status:#x?} during
-                                remove_memory_space removal. This is expected if this region was not previously mapped",
-                        );
-                    }
+            match self.unmap_paging_range(base_address, len) {
+                Ok(_) => {}
+                Err(status) => {
+                    log::error!(
+                        "Failed to unmap memory region {base_address:#x?} of length {len:#x?}. Status: {status:#x?} during
+                            remove_memory_space removal. This is expected if this region was not previously mapped",
+                    );
                 }
             }
 
@@ -2321,6 +2630,11 @@ impl SpinLockedGcd {
                 debug_assert!(false);
             }
 
+            // the range is mapped now, so check that any part of it poisoned when freed was not written while free.
+            if let Ok(base_address) = result {
+                allocator::check_reallocated_range(base_address..base_address + len);
+            }
+
             if let Some(callback) = self.memory_change_callback {
                 callback(MapChangeType::AllocateMemorySpace);
             }
@@ -2342,20 +2656,18 @@ impl SpinLockedGcd {
                 // we don't panic if we don't have a page table because the memory bucket code does a free before the
                 // page table is initialized. If we were to end up without the page table initialized, we would still
                 // keep track of state in the GCD
-                if let Some(page_table) = &mut *self.page_table.lock() {
-                    match page_table.unmap_memory_region(base_address as u64, len as u64) {
-                        Ok(_) => {}
-                        Err(status) => {
-                            log::error!(
-                                "Failed to unmap memory region {base_address:#x?} of length {len:#x?}. Status: {status:#x?}",
-                            );
-                            debug_assert!(false);
-                            match status {
-                                PtError::OutOfResources => EfiError::OutOfResources,
-                                PtError::NoMapping => EfiError::NotFound,
-                                _ => EfiError::InvalidParameter,
-                            };
-                        }
+                match self.unmap_paging_range(base_address, len) {
+                    Ok(_) => {}
+                    Err(status) => {
+                        log::error!(
+                            "Failed to unmap memory region {base_address:#x?} of length {len:#x?}. Status: {status:#x?}",
+                        );
+                        debug_assert!(false);
+                        match status {
+                            PtError::OutOfResources => EfiError::OutOfResources,
+                            PtError::NoMapping => EfiError::NotFound,
+                            _ => EfiError::InvalidParameter,
+                        };
                     }
                 }
 
@@ -2475,10 +2787,17 @@ impl SpinLockedGcd {
             current_base = next_base;
         }
 
+        // MTRR changes are replicated to the APs only now that the GCD and page table locks are released, as an AP
+        // accessing the GCD would otherwise deadlock.
+        #[cfg(all(target_os = "uefi", target_arch = "x86_64"))]
+        if let Err(err) = patina_internal_cpu::mtrr::synchronize_pending_aps() {
+            log::error!("Failed to synchronize MTRRs to APs: {err:?}");
+        }
+
         // if we made it out of the loop, we set the attributes correctly and should call the memory change callback,
         // if there is one
         if let Some(callback) = self.memory_change_callback {
-            callback(MapChangeType::SetMemoryAttributes);
+            callback(MapChangeType::SetMemoryAttributes { base_address: base_address as u64, length: len as u64 });
         }
         res
     }
@@ -2566,6 +2885,14 @@ impl SpinLockedGcd {
         self.io.lock().io_descriptor_count()
     }
 
+    /// Acquires lock and delegates to [`IoGCD::get_io_descriptor_for_address`]
+    pub fn get_io_descriptor_for_address(
+        &self,
+        address: efi::PhysicalAddress,
+    ) -> Result<dxe_services::IoSpaceDescriptor, EfiError> {
+        self.io.lock().get_io_descriptor_for_address(address)
+    }
+
     #[cfg(feature = "compatibility_mode_allowed")]
     /// This activates compatibility mode for the GCD.
     /// This will:
@@ -2636,6 +2963,16 @@ impl Display for SpinLockedGcd {
         } else {
             writeln!(f, "Locked: {:?}", self.io.try_lock())?;
         }
+        if let Some(regions) = self.special_regions.try_lock()
+            && !regions.is_empty()
+        {
+            writeln!(f, "Memory Protection Special Regions")?;
+            writeln!(f, "Range                             Exempt   Requester (Reason)")?;
+            writeln!(f, "================================= ======== ==================")?;
+            for region in regions.iter() {
+                writeln!(f, "{region}")?;
+            }
+        }
         Ok(())
     }
 }
@@ -3619,6 +3956,7 @@ mod tests {
     fn test_set_memory_space_attributes_with_invalid_parameters() {
         let mut gcd = GCD {
             memory_blocks: Rbt::new(),
+            free_ranges: FreeRanges::new(),
             maximum_address: 0,
             allocate_memory_space_fn: GCD::allocate_memory_space_internal,
             free_memory_space_fn: GCD::free_memory_space_worker,
@@ -3971,6 +4309,99 @@ mod tests {
         assert_eq!(Ok(()), gcd.free_io_space(100, 10));
     }
 
+    #[test]
+    fn test_free_ranges_track_memory_blocks() {
+        let (mut gcd, _) = create_gcd();
+        unsafe {
+            gcd.add_memory_space(dxe_services::GcdMemoryType::SystemMemory, 0x1000_0000, 0x100_0000, 0).unwrap();
+            gcd.add_memory_space(dxe_services::GcdMemoryType::MemoryMappedIo, 0x2000_0000, 0x100_0000, 0).unwrap();
+            gcd.add_memory_space(dxe_services::GcdMemoryType::Reserved, 0x3000_0000, 0x10_0000, 0).unwrap();
+        }
+        assert!(is_free_range_index_valid(&gcd));
+
+        // A simple linear congruential generator keeps the sequence of operations reproducible.
+        let mut seed = 0x2545_f491_u64;
+        let mut next = || {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 33) as usize
+        };
+
+        let mut allocations = Vec::new();
+        for _ in 0..1000 {
+            let memory_type = match next() % 3 {
+                0 => dxe_services::GcdMemoryType::SystemMemory,
+                1 => dxe_services::GcdMemoryType::MemoryMappedIo,
+                _ => dxe_services::GcdMemoryType::Reserved,
+            };
+            let len = (next() % 16 + 1) * UEFI_PAGE_SIZE;
+            let allocate_type = match next() % 3 {
+                0 => AllocateType::BottomUp(None),
+                1 => AllocateType::TopDown(None),
+                _ => AllocateType::TopDown(Some(0x2080_0000)),
+            };
+
+            if next() % 3 == 0 && !allocations.is_empty() {
+                let (address, len) = allocations.swap_remove(next() % allocations.len());
+                gcd.free_memory_space(address, len).unwrap();
+            } else if let Ok(address) = gcd.allocate_memory_space(allocate_type, memory_type, 12, len, 1 as _, None) {
+                allocations.push((address, len));
+            }
+            assert!(is_free_range_index_valid(&gcd));
+        }
+
+        for (address, len) in allocations {
+            gcd.free_memory_space(address, len).unwrap();
+        }
+        assert!(is_free_range_index_valid(&gcd));
+        assert_eq!(gcd.free_ranges.largest(dxe_services::GcdMemoryType::MemoryMappedIo), 0x100_0000);
+    }
+
+    #[test]
+    fn test_allocate_memory_space_larger_than_any_free_range() {
+        let (mut gcd, _) = create_gcd();
+        unsafe {
+            gcd.add_memory_space(dxe_services::GcdMemoryType::SystemMemory, 0x1000_0000, 0x10_0000, 0).unwrap();
+        }
+
+        for allocate_type in [AllocateType::BottomUp(None), AllocateType::TopDown(None)] {
+            assert_eq!(
+                Err(EfiError::OutOfResources),
+                gcd.allocate_memory_space(
+                    allocate_type,
+                    dxe_services::GcdMemoryType::SystemMemory,
+                    12,
+                    0x20_0000,
+                    1 as _,
+                    None
+                )
+            );
+        }
+        for allocate_type in [AllocateType::BottomUp(Some(0x2000_0000)), AllocateType::TopDown(Some(0x2000_0000))] {
+            assert_eq!(
+                Err(EfiError::NotFound),
+                gcd.allocate_memory_space(
+                    allocate_type,
+                    dxe_services::GcdMemoryType::SystemMemory,
+                    12,
+                    0x20_0000,
+                    1 as _,
+                    None
+                )
+            );
+        }
+        assert_eq!(
+            Err(EfiError::OutOfResources),
+            gcd.allocate_memory_space(
+                AllocateType::TopDown(None),
+                dxe_services::GcdMemoryType::Persistent,
+                12,
+                0x1000,
+                1 as _,
+                None
+            )
+        );
+    }
+
     fn create_gcd() -> (GCD, usize) {
         let mem = unsafe { get_memory(MEMORY_BLOCK_SLICE_SIZE) };
         let address = mem.as_ptr() as usize;
@@ -4012,6 +4443,42 @@ mod tests {
         true
     }
 
+    fn is_free_range_index_valid(gcd: &GCD) -> bool {
+        let blocks = copy_memory_block(gcd);
+        let mut count = 0;
+        for memory_type in [
+            dxe_services::GcdMemoryType::Reserved,
+            dxe_services::GcdMemoryType::SystemMemory,
+            dxe_services::GcdMemoryType::MemoryMappedIo,
+            dxe_services::GcdMemoryType::Persistent,
+            dxe_services::GcdMemoryType::MoreReliable,
+        ] {
+            let free: Vec<(u64, u64)> = blocks
+                .iter()
+                .filter_map(|block| match block {
+                    MemoryBlock::Unallocated(descriptor) if descriptor.memory_type == memory_type => {
+                        Some((descriptor.base_address, descriptor.length))
+                    }
+                    _ => None,
+                })
+                .collect();
+
+            let mut indexed = Vec::new();
+            let mut current = gcd.free_ranges.first(memory_type);
+            while let Some((base_address, length)) = current {
+                indexed.push((base_address, length));
+                current = gcd.free_ranges.next(memory_type, base_address, length);
+            }
+
+            let largest = free.iter().map(|(_, length)| *length).max().unwrap_or(0);
+            if free != indexed || gcd.free_ranges.largest(memory_type) != largest {
+                return false;
+            }
+            count += free.len();
+        }
+        count == gcd.free_ranges.len()
+    }
+
     unsafe fn get_memory(size: usize) -> &'static mut [u8] {
         let addr = unsafe { alloc::alloc::alloc(alloc::alloc::Layout::from_size_align(size, UEFI_PAGE_SIZE).unwrap()) };
         unsafe { core::slice::from_raw_parts_mut(addr, size) }
@@ -4331,4 +4798,543 @@ mod tests {
         );
         assert!(res.is_ok(), "Failed to fallback to higher memory as expected");
     }
+
+    #[test]
+    fn test_combine_granule_attributes() {
+        // read protected pages do not constrain the granule
+        assert_eq!(
+            combine_granule_attributes([efi::MEMORY_RP | efi::MEMORY_WB, efi::MEMORY_RO | efi::MEMORY_WB]),
+            Some(efi::MEMORY_RO | efi::MEMORY_WB)
+        );
+        // access attributes are kept only if every mapped page has them
+        assert_eq!(
+            combine_granule_attributes([
+                efi::MEMORY_RO | efi::MEMORY_XP | efi::MEMORY_WB,
+                efi::MEMORY_XP | efi::MEMORY_WB,
+                efi::MEMORY_XP | efi::MEMORY_WB | efi::MEMORY_RUNTIME,
+            ]),
+            Some(efi::MEMORY_XP | efi::MEMORY_WB)
+        );
+        // conflicting cache attributes make the granule uncached
+        assert_eq!(
+            combine_granule_attributes([efi::MEMORY_XP | efi::MEMORY_WB, efi::MEMORY_XP | efi::MEMORY_WC]),
+            Some(efi::MEMORY_XP | efi::MEMORY_UC)
+        );
+        assert_eq!(combine_granule_attributes([efi::MEMORY_XP, efi::MEMORY_WB]), Some(efi::MEMORY_WB));
+        assert_eq!(combine_granule_attributes([efi::MEMORY_RP, efi::MEMORY_RP | efi::MEMORY_XP]), None);
+        assert_eq!(combine_granule_attributes([]), None);
+    }
+
+    #[test]
+    fn test_split_on_granule() {
+        let granule = PageGranule::Size64KB;
+        assert_eq!(split_on_granule(granule, 0x20000, 0x20000), (Some((0x20000, 0x20000)), [None, None]));
+        assert_eq!(split_on_granule(granule, 0x21000, 0x1000), (None, [Some(0x20000), None]));
+        assert_eq!(split_on_granule(granule, 0x2F000, 0x2000), (None, [Some(0x20000), Some(0x30000)]));
+        assert_eq!(split_on_granule(granule, 0x21000, 0x2F000), (Some((0x30000, 0x20000)), [Some(0x20000), None]));
+        assert_eq!(split_on_granule(granule, 0x20000, 0x21000), (Some((0x20000, 0x20000)), [None, Some(0x40000)]));
+        assert_eq!(split_on_granule(PageGranule::Size4KB, 0x21000, 0x1000), (Some((0x21000, 0x1000)), [None, None]));
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum PageTableCall {
+        Map(u64, u64, MemoryAttributes),
+        Unmap(u64, u64),
+    }
+
+    /// Page table recording the changes made to it. All memory is reported as not mapped.
+    struct RecordingPageTable(&'static std::sync::Mutex<Vec<PageTableCall>>);
+
+    impl PageTable for RecordingPageTable {
+        fn map_memory_region(&mut self, address: u64, size: u64, attributes: MemoryAttributes) -> PtResult<()> {
+            self.0.lock().unwrap().push(PageTableCall::Map(address, size, attributes));
+            Ok(())
+        }
+
+        fn unmap_memory_region(&mut self, address: u64, size: u64) -> PtResult<()> {
+            self.0.lock().unwrap().push(PageTableCall::Unmap(address, size));
+            Ok(())
+        }
+
+        fn install_page_table(&mut self) -> PtResult<()> {
+            Ok(())
+        }
+
+        fn query_memory_region(&self, _address: u64, _size: u64) -> PtResult<MemoryAttributes> {
+            Err(PtError::NoMapping)
+        }
+
+        fn dump_page_tables(&self, _address: u64, _size: u64) -> PtResult<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_page_table_changes_are_granule_aligned() {
+        with_locked_state(|| {
+            use std::alloc::GlobalAlloc;
+            const GRANULE_SIZE: usize = 0x10000;
+            const GCD_SIZE: usize = MEMORY_BLOCK_SLICE_SIZE + 0x100000;
+            static GCD: SpinLockedGcd = SpinLockedGcd::new(None);
+            static CALLS: std::sync::Mutex<Vec<PageTableCall>> = std::sync::Mutex::new(Vec::new());
+            GCD.init(48, 16);
+
+            let layout = Layout::from_size_align(GCD_SIZE, GRANULE_SIZE).unwrap();
+            let base = unsafe { std::alloc::System.alloc(layout) as usize };
+            unsafe {
+                GCD.add_memory_space(dxe_services::GcdMemoryType::SystemMemory, base, GCD_SIZE, efi::MEMORY_WB)
+                    .unwrap();
+            }
+            let target = align_up(base + MEMORY_BLOCK_SLICE_SIZE, GRANULE_SIZE).unwrap();
+            GCD.set_memory_space_capabilities(
+                target,
+                3 * GRANULE_SIZE,
+                efi::MEMORY_RP | efi::MEMORY_RO | efi::MEMORY_XP | efi::MEMORY_WB,
+            )
+            .unwrap();
+
+            GCD.set_page_granule(PageGranule::Size64KB);
+            assert_eq!(GCD.page_granule(), PageGranule::Size64KB);
+            *GCD.page_table.lock() = Some(LargePageTable::new(
+                Box::new(RecordingPageTable(&CALLS)),
+                TableFormat::X64,
+                Default::default(),
+                false,
+            ));
+            GCD.set_memory_space_attributes(target, 3 * GRANULE_SIZE, efi::MEMORY_XP | efi::MEMORY_WB).unwrap();
+            CALLS.lock().unwrap().clear();
+
+            // a read only code page makes the whole granule executable and writable
+            GCD.set_memory_space_attributes(target + 0x1000, 0x1000, efi::MEMORY_RO | efi::MEMORY_WB).unwrap();
+            assert_eq!(
+                core::mem::take(&mut *CALLS.lock().unwrap()),
+                [PageTableCall::Map(target as u64, GRANULE_SIZE as u64, MemoryAttributes::Writeback)]
+            );
+
+            GCD.set_memory_space_attributes(target + 0x1000, 0x1000, efi::MEMORY_RO | efi::MEMORY_XP | efi::MEMORY_WB)
+                .unwrap();
+            assert_eq!(
+                core::mem::take(&mut *CALLS.lock().unwrap()),
+                [PageTableCall::Map(
+                    target as u64,
+                    GRANULE_SIZE as u64,
+                    MemoryAttributes::Writeback | MemoryAttributes::ExecuteProtect
+                )]
+            );
+
+            // freeing a single page keeps the rest of its granule mapped
+            let page = target + GRANULE_SIZE + 0x3000;
+            GCD.allocate_memory_space(
+                AllocateType::Address(page),
+                dxe_services::GcdMemoryType::SystemMemory,
+                0,
+                0x1000,
+                1 as _,
+                None,
+            )
+            .unwrap();
+            CALLS.lock().unwrap().clear();
+            GCD.free_memory_space(page, 0x1000).unwrap();
+            assert_eq!(
+                core::mem::take(&mut *CALLS.lock().unwrap()),
+                [PageTableCall::Map(
+                    (target + GRANULE_SIZE) as u64,
+                    GRANULE_SIZE as u64,
+                    MemoryAttributes::Writeback | MemoryAttributes::ExecuteProtect
+                )]
+            );
+
+            // whole granules are unmapped
+            let granule = target + 2 * GRANULE_SIZE;
+            GCD.allocate_memory_space(
+                AllocateType::Address(granule),
+                dxe_services::GcdMemoryType::SystemMemory,
+                0,
+                GRANULE_SIZE,
+                1 as _,
+                None,
+            )
+            .unwrap();
+            CALLS.lock().unwrap().clear();
+            GCD.free_memory_space(granule, GRANULE_SIZE).unwrap();
+            assert_eq!(
+                core::mem::take(&mut *CALLS.lock().unwrap()),
+                [PageTableCall::Unmap(granule as u64, GRANULE_SIZE as u64)]
+            );
+
+            *GCD.page_table.lock() = None;
+        });
+    }
+
+    #[test]
+    fn test_special_regions_adjust_page_table_changes() {
+        with_locked_state(|| {
+            use patina::component::service::memory_protection::ProtectionExemptions;
+            use std::{alloc::GlobalAlloc, string::ToString};
+            const GCD_SIZE: usize = MEMORY_BLOCK_SLICE_SIZE + 0x100000;
+            static GCD: SpinLockedGcd = SpinLockedGcd::new(None);
+            static CALLS: std::sync::Mutex<Vec<PageTableCall>> = std::sync::Mutex::new(Vec::new());
+            GCD.init(48, 16);
+
+            let layout = Layout::from_size_align(GCD_SIZE, 0x1000).unwrap();
+            let base = unsafe { std::alloc::System.alloc(layout) as usize };
+            unsafe {
+                GCD.add_memory_space(dxe_services::GcdMemoryType::SystemMemory, base, GCD_SIZE, efi::MEMORY_WB)
+                    .unwrap();
+            }
+            let target = align_up(base + MEMORY_BLOCK_SLICE_SIZE, 0x1000).unwrap();
+            GCD.set_memory_space_capabilities(target, 0x4000, efi::MEMORY_RP | efi::MEMORY_XP | efi::MEMORY_WB)
+                .unwrap();
+
+            *GCD.page_table.lock() = Some(LargePageTable::new(
+                Box::new(RecordingPageTable(&CALLS)),
+                TableFormat::X64,
+                Default::default(),
+                false,
+            ));
+            GCD.set_memory_space_attributes(target, 0x4000, efi::MEMORY_XP | efi::MEMORY_WB).unwrap();
+            CALLS.lock().unwrap().clear();
+
+            let region = |base_address: usize, exemptions| SpecialRegion {
+                base_address: base_address as u64,
+                length: 0x1000,
+                exemptions,
+                requester: "Quirks".to_string(),
+                reason: "option ROM shadow".to_string(),
+            };
+            let nx = ProtectionExemptions { no_execute: true, guard: false };
+            let guard = ProtectionExemptions { no_execute: false, guard: true };
+            assert_eq!(GCD.add_special_region(region(target + 0x10, nx)), Err(EfiError::InvalidParameter));
+
+            // registering a region applies its exemptions right away, without changing the GCD attributes
+            GCD.add_special_region(region(target + 0x1000, nx)).unwrap();
+            assert_eq!(
+                core::mem::take(&mut *CALLS.lock().unwrap()),
+                [PageTableCall::Map((target + 0x1000) as u64, 0x1000, MemoryAttributes::Writeback)]
+            );
+            assert_eq!(
+                GCD.get_memory_descriptor_for_address((target + 0x1000) as u64).unwrap().attributes & efi::MEMORY_XP,
+                efi::MEMORY_XP
+            );
+
+            // guard pages over a region exempt from guards stay mapped
+            GCD.add_special_region(region(target + 0x2000, guard)).unwrap();
+            CALLS.lock().unwrap().clear();
+            GCD.set_memory_space_attributes(target + 0x2000, 0x1000, efi::MEMORY_RP | efi::MEMORY_WB).unwrap();
+            assert_eq!(
+                core::mem::take(&mut *CALLS.lock().unwrap()),
+                [PageTableCall::Map(
+                    (target + 0x2000) as u64,
+                    0x1000,
+                    MemoryAttributes::Writeback | MemoryAttributes::ExecuteProtect
+                )]
+            );
+            assert_eq!(
+                GCD.get_memory_descriptor_for_address((target + 0x2000) as u64).unwrap().attributes,
+                efi::MEMORY_RP | efi::MEMORY_WB
+            );
+
+            assert_eq!(GCD.special_regions().len(), 2);
+            assert!(std::format!("{GCD}").contains("Quirks (option ROM shadow)"));
+
+            *GCD.page_table.lock() = None;
+        });
+    }
+
+    /// Property-based tests of the GCD memory space, running random sequences of operations and checking the GCD
+    /// against a reference model of a window of pages after every operation.
+    mod proptests {
+        use super::*;
+        use core::ops::Range;
+        use proptest::{prelude::*, sample::Index};
+
+        /// The window of pages the operations apply to, in the upper half of the address space so that it never
+        /// overlaps the memory block slice allocated from the host heap.
+        const WINDOW_BASE: usize = 0x8000_0000_0000;
+        const WINDOW_PAGES: usize = 64;
+
+        fn image_handle() -> efi::Handle {
+            1 as _
+        }
+
+        /// The state of a page of the window, as described by its memory block.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        struct Page {
+            memory_type: dxe_services::GcdMemoryType,
+            allocated: bool,
+            capabilities: u64,
+            attributes: u64,
+            image_handle: efi::Handle,
+        }
+
+        #[derive(Debug, Clone)]
+        enum Op {
+            Add { memory_type: dxe_services::GcdMemoryType, page: usize, pages: usize, capabilities: u64 },
+            Remove { page: usize, pages: usize },
+            AllocateBottomUp { memory_type: dxe_services::GcdMemoryType, pages: usize, align_shift: usize },
+            AllocateTopDown { memory_type: dxe_services::GcdMemoryType, pages: usize, align_shift: usize },
+            AllocateAddress { memory_type: dxe_services::GcdMemoryType, page: usize, pages: usize, align_shift: usize },
+            Free { page: usize, pages: usize },
+            SetAttributes { run: Index, start: Index, end: Index, attributes: u64 },
+        }
+
+        fn memory_type() -> impl Strategy<Value = dxe_services::GcdMemoryType> {
+            prop_oneof![
+                Just(dxe_services::GcdMemoryType::SystemMemory),
+                Just(dxe_services::GcdMemoryType::Reserved),
+                Just(dxe_services::GcdMemoryType::MemoryMappedIo),
+            ]
+        }
+
+        /// A range of pages of the window.
+        fn pages() -> impl Strategy<Value = (usize, usize)> {
+            (0..WINDOW_PAGES).prop_flat_map(|page| (Just(page), 1..=(WINDOW_PAGES - page).min(WINDOW_PAGES / 4)))
+        }
+
+        fn op() -> impl Strategy<Value = Op> {
+            // The capabilities are drawn from the cache attributes, as the memory access attributes are always
+            // supported.
+            prop_oneof![
+                (memory_type(), pages(), 0_u64..0x20).prop_map(|(memory_type, (page, pages), capabilities)| Op::Add {
+                    memory_type,
+                    page,
+                    pages,
+                    capabilities
+                }),
+                pages().prop_map(|(page, pages)| Op::Remove { page, pages }),
+                (memory_type(), 1..=WINDOW_PAGES / 4, 12_usize..=15).prop_map(|(memory_type, pages, align_shift)| {
+                    Op::AllocateBottomUp { memory_type, pages, align_shift }
+                }),
+                (memory_type(), 1..=WINDOW_PAGES / 4, 12_usize..=15).prop_map(|(memory_type, pages, align_shift)| {
+                    Op::AllocateTopDown { memory_type, pages, align_shift }
+                }),
+                (memory_type(), pages(), 12_usize..=13).prop_map(|(memory_type, (page, pages), align_shift)| {
+                    Op::AllocateAddress { memory_type, page, pages, align_shift }
+                }),
+                pages().prop_map(|(page, pages)| Op::Free { page, pages }),
+                (any::<Index>(), any::<Index>(), any::<Index>(), any::<u64>())
+                    .prop_map(|(run, start, end, attributes)| Op::SetAttributes { run, start, end, attributes }),
+            ]
+        }
+
+        fn address(page: usize) -> usize {
+            WINDOW_BASE + page * UEFI_PAGE_SIZE
+        }
+
+        fn page_state(gcd: &GCD, page: usize) -> Page {
+            let idx = gcd.memory_blocks.get_closest_idx(&(address(page) as u64)).unwrap();
+            let block = gcd.memory_blocks.get_with_idx(idx).unwrap();
+            let descriptor = block.as_ref();
+            Page {
+                memory_type: descriptor.memory_type,
+                allocated: matches!(block, MemoryBlock::Allocated(_)),
+                capabilities: descriptor.capabilities,
+                attributes: descriptor.attributes,
+                image_handle: descriptor.image_handle,
+            }
+        }
+
+        /// Returns the runs of pages with the same state, each of which must be a single memory block.
+        fn runs(model: &[Page]) -> Vec<Range<usize>> {
+            let mut runs: Vec<Range<usize>> = Vec::new();
+            for (page, state) in model.iter().enumerate() {
+                match runs.last_mut() {
+                    Some(run) if model[run.start] == *state => run.end = page + 1,
+                    _ => runs.push(page..page + 1),
+                }
+            }
+            runs
+        }
+
+        /// Returns the run holding all the pages from `page`, if there is one.
+        fn run_holding(model: &[Page], page: usize, pages: usize) -> Option<Range<usize>> {
+            runs(model).into_iter().find(|run| run.contains(&page) && page + pages <= run.end)
+        }
+
+        /// Returns the address the bottom up or top down allocation must return, given the free runs of the model.
+        fn expected_allocation(
+            model: &[Page],
+            memory_type: dxe_services::GcdMemoryType,
+            pages: usize,
+            align_shift: usize,
+            top_down: bool,
+        ) -> Option<usize> {
+            let alignment = 1 << align_shift;
+            let mut candidates = runs(model).into_iter().filter_map(|run| {
+                let state = model[run.start];
+                if state.allocated || state.memory_type != memory_type {
+                    return None;
+                }
+                let (start, end) = (address(run.start), address(run.end));
+                let len = pages * UEFI_PAGE_SIZE;
+                let addr =
+                    if top_down { end.checked_sub(len)? & !(alignment - 1) } else { start.next_multiple_of(alignment) };
+                (addr >= start && addr + len <= end).then_some(addr)
+            });
+            if top_down { candidates.last() } else { candidates.next() }
+        }
+
+        /// Applies `op` to the GCD and the model, and checks the result of the GCD against the model.
+        fn apply(gcd: &mut GCD, model: &mut [Page], op: &Op) -> Result<(), TestCaseError> {
+            let snapshot = copy_memory_block(gcd);
+            let succeeded = match *op {
+                Op::Add { memory_type, page, pages, capabilities } => {
+                    let expected = run_holding(model, page, pages)
+                        .is_some_and(|_| model[page].memory_type == dxe_services::GcdMemoryType::NonExistent);
+                    let result = unsafe {
+                        gcd.add_memory_space(memory_type, address(page), pages * UEFI_PAGE_SIZE, capabilities)
+                    };
+                    prop_assert_eq!(result.is_ok(), expected, "{:?} returned {:?}", op, result);
+                    let mut capabilities = capabilities | efi::MEMORY_ACCESS_MASK | efi::MEMORY_RUNTIME;
+                    if memory_type == dxe_services::GcdMemoryType::MemoryMappedIo {
+                        capabilities |= efi::MEMORY_ISA_VALID;
+                    }
+                    if expected {
+                        for state in &mut model[page..page + pages] {
+                            *state = Page { memory_type, capabilities, attributes: efi::MEMORY_RP, ..*state };
+                        }
+                    }
+                    expected
+                }
+                Op::Remove { page, pages } => {
+                    let expected = run_holding(model, page, pages).is_some_and(|_| {
+                        !model[page].allocated && model[page].memory_type != dxe_services::GcdMemoryType::NonExistent
+                    });
+                    let result = gcd.remove_memory_space(address(page), pages * UEFI_PAGE_SIZE);
+                    prop_assert_eq!(result.is_ok(), expected, "{:?} returned {:?}", op, result);
+                    if expected {
+                        for state in &mut model[page..page + pages] {
+                            *state = Page {
+                                memory_type: dxe_services::GcdMemoryType::NonExistent,
+                                capabilities: 0,
+                                attributes: 0,
+                                ..*state
+                            };
+                        }
+                    }
+                    expected
+                }
+                Op::AllocateBottomUp { memory_type, pages, align_shift }
+                | Op::AllocateTopDown { memory_type, pages, align_shift } => {
+                    let top_down = matches!(op, Op::AllocateTopDown { .. });
+                    let expected = expected_allocation(model, memory_type, pages, align_shift, top_down);
+                    let allocate_type =
+                        if top_down { AllocateType::TopDown(None) } else { AllocateType::BottomUp(None) };
+                    let result = gcd.allocate_memory_space(
+                        allocate_type,
+                        memory_type,
+                        align_shift,
+                        pages * UEFI_PAGE_SIZE,
+                        image_handle(),
+                        None,
+                    );
+                    prop_assert_eq!(result.ok(), expected, "{:?} returned {:?}", op, result);
+                    if let Some(addr) = expected {
+                        let page = (addr - WINDOW_BASE) / UEFI_PAGE_SIZE;
+                        for state in &mut model[page..page + pages] {
+                            *state = Page { allocated: true, image_handle: image_handle(), ..*state };
+                        }
+                    }
+                    expected.is_some()
+                }
+                Op::AllocateAddress { memory_type, page, pages, align_shift } => {
+                    let expected = run_holding(model, page, pages).is_some_and(|_| {
+                        !model[page].allocated
+                            && model[page].memory_type == memory_type
+                            && address(page).is_multiple_of(1 << align_shift)
+                    });
+                    let result = gcd.allocate_memory_space(
+                        AllocateType::Address(address(page)),
+                        memory_type,
+                        align_shift,
+                        pages * UEFI_PAGE_SIZE,
+                        image_handle(),
+                        None,
+                    );
+                    prop_assert_eq!(result.ok(), expected.then_some(address(page)), "{:?} returned {:?}", op, result);
+                    if expected {
+                        for state in &mut model[page..page + pages] {
+                            *state = Page { allocated: true, image_handle: image_handle(), ..*state };
+                        }
+                    }
+                    expected
+                }
+                Op::Free { page, pages } => {
+                    let expected = run_holding(model, page, pages).is_some_and(|_| model[page].allocated);
+                    let result = gcd.free_memory_space(address(page), pages * UEFI_PAGE_SIZE);
+                    prop_assert_eq!(result.is_ok(), expected, "{:?} returned {:?}", op, result);
+                    if expected {
+                        for state in &mut model[page..page + pages] {
+                            *state = Page {
+                                allocated: false,
+                                image_handle: ptr::null_mut(),
+                                attributes: efi::MEMORY_RP | (state.attributes & efi::CACHE_ATTRIBUTE_MASK),
+                                ..*state
+                            };
+                        }
+                    }
+                    expected
+                }
+                Op::SetAttributes { run, start, end, attributes } => {
+                    // Only valid requests are made, as the GCD asserts on invalid ones.
+                    let runs = runs(model);
+                    let run = runs[run.index(runs.len())].clone();
+                    if model[run.start].memory_type == dxe_services::GcdMemoryType::NonExistent {
+                        return Ok(());
+                    }
+                    let start = run.start + start.index(run.len());
+                    let end = start + 1 + end.index(run.end - start);
+                    let attributes = attributes & model[start].capabilities;
+                    let result =
+                        gcd.set_memory_space_attributes(address(start), (end - start) * UEFI_PAGE_SIZE, attributes);
+                    prop_assert!(result.is_ok(), "{:?} returned {:?}", op, result);
+                    for state in &mut model[start..end] {
+                        state.attributes = attributes;
+                    }
+                    true
+                }
+            };
+
+            // A failed operation leaves the GCD untouched.
+            if !succeeded {
+                prop_assert_eq!(&snapshot, &copy_memory_block(gcd), "{:?} failed but changed the GCD", op);
+            }
+            Ok(())
+        }
+
+        /// Checks the invariants of the GCD, and that the window matches the model.
+        fn check(gcd: &GCD, model: &[Page]) -> Result<(), TestCaseError> {
+            // The memory blocks cover the whole address space without overlaps, and adjacent blocks are merged.
+            prop_assert!(is_gcd_memory_slice_valid(gcd));
+            prop_assert!(is_free_range_index_valid(gcd));
+            prop_assert!(gcd.memory_descriptor_count() <= MEMORY_BLOCK_SLICE_LEN);
+
+            for (page, state) in model.iter().enumerate() {
+                prop_assert_eq!(&page_state(gcd, page), state, "page {:#x}", address(page));
+            }
+            let window = WINDOW_BASE..address(WINDOW_PAGES);
+            let blocks = copy_memory_block(gcd)
+                .iter()
+                .filter(|block| block.start() < window.end && block.end() > window.start)
+                .count();
+            prop_assert_eq!(blocks, runs(model).len(), "the blocks of the window are not merged");
+            Ok(())
+        }
+
+        proptest! {
+            #![proptest_config(ProptestConfig::with_cases(128))]
+
+            #[test]
+            fn test_gcd_operation_sequences_preserve_invariants(ops in prop::collection::vec(op(), 1..64)) {
+                let (mut gcd, _) = create_gcd();
+                let mut model: Vec<Page> = (0..WINDOW_PAGES).map(|page| page_state(&gcd, page)).collect();
+                check(&gcd, &model)?;
+
+                for op in &ops {
+                    apply(&mut gcd, &mut model, op)?;
+                    check(&gcd, &model)?;
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,235 @@
+//! DXE Core Host Environment
+//!
+//! Support for running the DXE core boot services on the host (i.e. compiled with `std`). This initializes the real
+//! GCD, allocators, protocol database, event subsystem and the remaining boot services tables inside a block of host
+//! memory, so that code written against [BootServices](patina::boot_services::BootServices) can be exercised end to
+//! end without a platform.
+//!
+//! Paging and the driver dispatcher are not initialized, as they depend on the platform.
+//!
+//! PE images produced by the build system can be loaded from disk with [HostEnvironment::load_image_from_path], so
+//! that a DXE driver is published in the protocol database and the loaded image database as it would be when
+//! dispatched from a firmware volume. The host memory the images are loaded in is not executable, so an image is
+//! started with [HostEnvironment::start_image] and the entry point of the same driver compiled for the host.
+//!
+//! The DXE core keeps its state in global statics, so the environment is initialized once per process and shared
+//! by every [HostEnvironment]. Only one [HostEnvironment] can exist at a time; [HostEnvironment::acquire] blocks
+//! until any other instance is dropped, which serializes tests that run in parallel. State created by one
+//! [HostEnvironment] (e.g. installed protocols or allocations) is visible to the next.
+//!
+//! ## Example
+//!
+//! ```rust,ignore
+//! use patina::boot_services::BootServices;
+//! use patina_dxe_core::host::HostEnvironment;
+//!
+//! let env = HostEnvironment::acquire();
+//! let buffer = env.boot_services().allocate_pool(r_efi::efi::BOOT_SERVICES_DATA, 0x100).unwrap();
+//! ```
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use core::ffi::c_void;
+use std::{
+    path::Path,
+    sync::{Mutex, MutexGuard, Once},
+};
+
+use patina::{
+    boot_services::StandardBootServices, dxe_services::StandardDxeServices, error::EfiError,
+    runtime_services::StandardRuntimeServices,
+};
+use patina_pi::{
+    BootMode,
+    dxe_services::{DXE_SERVICES_TABLE_GUID, DxeServicesTable},
+    hob::{self, HobList, header},
+};
+use r_efi::efi;
+
+use crate::{
+    allocator, config_tables, driver_services, dxe_services, events, gcd, image, misc_boot_services, protocol_db,
+    protocols::{self, PROTOCOL_DB},
+    runtime, systemtables, tpl_lock,
+};
+
+/// Size of the host memory block handed to the GCD.
+const HOST_MEMORY_SIZE: usize = 0x4000000;
+
+/// Size reserved at the start of the host memory block for the HOB list.
+const HOB_LIST_SIZE: usize = 0x1000;
+
+static HOST_INIT: Once = Once::new();
+static HOST_LOCK: Mutex<()> = Mutex::new(());
+
+/// Exclusive access to the DXE core boot services running on the host.
+pub struct HostEnvironment {
+    _guard: MutexGuard<'static, ()>,
+    system_table: *mut efi::SystemTable,
+}
+
+impl HostEnvironment {
+    /// Initializes the host environment if needed and acquires exclusive access to it.
+    pub fn acquire() -> Self {
+        // A test that panicked while holding the lock does not invalidate the environment itself.
+        let guard = HOST_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        HOST_INIT.call_once(init_host_environment);
+
+        let system_table =
+            systemtables::SYSTEM_TABLE.lock().as_mut().expect("System Table not initialized!").system_table_mut()
+                as *mut efi::SystemTable;
+
+        Self { _guard: guard, system_table }
+    }
+
+    /// Returns the system table of the host environment.
+    pub fn system_table(&self) -> *mut efi::SystemTable {
+        self.system_table
+    }
+
+    /// Returns the boot services of the host environment.
+    pub fn boot_services(&self) -> StandardBootServices {
+        // SAFETY: The system table and boot services table are leaked by the core and live for the whole process.
+        StandardBootServices::new(unsafe { &*(*self.system_table).boot_services })
+    }
+
+    /// Returns the runtime services of the host environment.
+    pub fn runtime_services(&self) -> StandardRuntimeServices {
+        // SAFETY: The system table and runtime services table are leaked by the core and live for the whole process.
+        StandardRuntimeServices::new(unsafe { &*(*self.system_table).runtime_services })
+    }
+
+    /// Returns the DXE services of the host environment, as found in the configuration tables of the system table.
+    pub fn dxe_services(&self) -> StandardDxeServices {
+        // SAFETY: The system table and its configuration tables are leaked by the core and live for the whole process.
+        let configuration_tables = unsafe {
+            let system_table = &*self.system_table;
+            std::slice::from_raw_parts(system_table.configuration_table, system_table.number_of_table_entries)
+        };
+        let table = configuration_tables
+            .iter()
+            .find(|table| table.vendor_guid == DXE_SERVICES_TABLE_GUID)
+            .expect("DXE Services Table not installed!");
+        // SAFETY: The DXE services table is leaked by the core and lives for the whole process.
+        StandardDxeServices::new(unsafe { &*(table.vendor_table as *const DxeServicesTable) })
+    }
+
+    /// Loads the PE image at `path`, e.g. a DXE driver produced by the build system, and returns its image handle.
+    ///
+    /// The image is loaded by the DXE core as if the DXE core dispatched it: it is relocated in the host memory, and
+    /// its loaded image protocol is installed. Images rejected or deferred by the security architectural protocols,
+    /// if a test installed them, are unloaded and the error is returned.
+    pub fn load_image_from_path(&self, path: impl AsRef<Path>) -> Result<efi::Handle, EfiError> {
+        let path = path.as_ref();
+        let image = std::fs::read(path).map_err(|err| {
+            log::error!("Failed to read image {}: {err}", path.display());
+            EfiError::NotFound
+        })?;
+
+        let (image_handle, security_status) =
+            image::core_load_image(false, protocol_db::DXE_CORE_HANDLE, core::ptr::null_mut(), Some(&image))?;
+        if let Err(err) = security_status {
+            let _ = image::core_unload_image(image_handle, true);
+            return Err(err);
+        }
+        Ok(image_handle)
+    }
+
+    /// Starts the loaded image `image_handle`, running `entry_point` in place of the entry point of the image.
+    ///
+    /// `entry_point` is expected to be the entry point of the same driver compiled for the host. It runs with the
+    /// image handle and the system table of the host environment, on the stack the DXE core allocates for the image,
+    /// and the image is unloaded if it returns an error, as with `StartImage()`.
+    pub fn start_image(&self, image_handle: efi::Handle, entry_point: efi::ImageEntryPoint) -> Result<(), EfiError> {
+        image::set_image_entry_point(image_handle, entry_point)?;
+        image::core_start_image(image_handle).map_err(EfiError::from)
+    }
+}
+
+fn init_host_environment() {
+    let physical_hob_list = build_host_hob_list();
+
+    gcd::init_gcd(physical_hob_list);
+
+    let mut hob_list = HobList::default();
+    hob_list.discover_hobs(physical_hob_list);
+
+    PROTOCOL_DB.init_protocol_db();
+    allocator::init_memory_support(&hob_list);
+
+    systemtables::init_system_table();
+    let boot_services_ptr = {
+        let mut st = systemtables::SYSTEM_TABLE.lock();
+        let st = st.as_mut().expect("System Table not initialized!");
+
+        allocator::install_memory_services(st.boot_services_mut());
+        events::init_events_support(st.boot_services_mut());
+        protocols::init_protocol_support(st.boot_services_mut());
+        misc_boot_services::init_misc_boot_services_support(st.boot_services_mut());
+        config_tables::init_config_tables_support(st.boot_services_mut());
+        image::init_host_image_support(st);
+        runtime::init_runtime_support(st.runtime_services_mut());
+        driver_services::init_driver_services(st.boot_services_mut());
+        dxe_services::init_dxe_services(st);
+        st.checksum_all();
+
+        st.boot_services_mut() as *mut efi::BootServices
+    };
+
+    tpl_lock::init_boot_services(boot_services_ptr);
+}
+
+/// Builds a HOB list describing a block of leaked host memory, which is handed to the GCD as free system memory.
+fn build_host_hob_list() -> *const c_void {
+    let layout = std::alloc::Layout::from_size_align(HOST_MEMORY_SIZE, 0x1000).expect("Invalid host memory layout.");
+    // SAFETY: layout has a non-zero size. The memory is intentionally leaked as it is owned by the GCD.
+    let mem_base = unsafe { std::alloc::alloc_zeroed(layout) };
+    assert!(!mem_base.is_null(), "Failed to allocate host memory.");
+    let mem_base_address = mem_base as u64;
+
+    let phit = hob::PhaseHandoffInformationTable {
+        header: header::Hob {
+            r#type: hob::HANDOFF,
+            length: core::mem::size_of::<hob::PhaseHandoffInformationTable>() as u16,
+            reserved: 0,
+        },
+        version: 0x0009,
+        boot_mode: BootMode::BootWithFullConfiguration,
+        memory_top: mem_base_address + HOST_MEMORY_SIZE as u64,
+        memory_bottom: mem_base_address,
+        free_memory_top: mem_base_address + HOST_MEMORY_SIZE as u64,
+        free_memory_bottom: mem_base_address + HOB_LIST_SIZE as u64,
+        end_of_hob_list: mem_base_address
+            + core::mem::size_of::<hob::PhaseHandoffInformationTable>() as u64
+            + core::mem::size_of::<hob::Cpu>() as u64,
+    };
+
+    let cpu = hob::Cpu {
+        header: header::Hob { r#type: hob::CPU, length: core::mem::size_of::<hob::Cpu>() as u16, reserved: 0 },
+        size_of_memory_space: 48,
+        size_of_io_space: 16,
+        reserved: Default::default(),
+    };
+
+    let end =
+        header::Hob { r#type: hob::END_OF_HOB_LIST, length: core::mem::size_of::<header::Hob>() as u16, reserved: 0 };
+
+    // SAFETY: The HOBs are written to the start of the freshly allocated memory block, which is large enough to hold
+    // them (HOB_LIST_SIZE).
+    unsafe {
+        let mut cursor = mem_base;
+
+        core::ptr::write_unaligned(cursor as *mut hob::PhaseHandoffInformationTable, phit);
+        cursor = cursor.add(phit.header.length as usize);
+
+        core::ptr::write_unaligned(cursor as *mut hob::Cpu, cpu);
+        cursor = cursor.add(cpu.header.length as usize);
+
+        core::ptr::write_unaligned(cursor as *mut header::Hob, end);
+    }
+
+    mem_base as *const c_void
+}
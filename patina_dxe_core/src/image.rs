@@ -7,7 +7,14 @@
 //! SPDX-License-Identifier: Apache-2.0
 //!
 use alloc::{boxed::Box, collections::BTreeMap, string::String, vec, vec::Vec};
-use core::{convert::TryInto, ffi::c_void, mem::transmute, slice, slice::from_raw_parts};
+use core::{
+    convert::TryInto,
+    ffi::c_void,
+    mem::transmute,
+    slice,
+    slice::from_raw_parts,
+    sync::atomic::{AtomicPtr, Ordering},
+};
 use goblin::pe::section_table;
 use patina::base::{DEFAULT_CACHE_ATTR, UEFI_PAGE_SIZE, align_up};
 use patina::error::EfiError;
@@ -15,22 +22,38 @@ use patina::performance::{
     logging::{perf_image_start_begin, perf_image_start_end, perf_load_image_begin, perf_load_image_end},
     measurement::create_performance_measurement,
 };
-use patina::{guids, uefi_pages_to_size, uefi_size_to_pages};
-use patina_internal_device_path::{DevicePathWalker, copy_device_path_to_boxed_slice, device_path_node_count};
+use patina::{
+    guids, uefi_pages_to_size,
+    uefi_protocol::loaded_image_info::{
+        IMAGE_PROTECTION_APPLIED, IMAGE_PROTECTION_COMPATIBILITY_MODE, IMAGE_PROTECTION_NONE, ImageProtection,
+        LoadedImageInfo,
+    },
+    uefi_size_to_pages,
+};
+use patina_internal_device_path::{
+    DevicePathText, DevicePathWalker, copy_device_path_to_boxed_slice, device_path_node_count,
+};
 use patina_pi::{
     fw_fs::FfsSectionRawType::PE32,
     hob::{Hob, HobList},
-    protocols::firmware_volume,
+    protocols::{self, deferred_image_load, firmware_volume},
+    status_code,
 };
 use r_efi::efi;
 
 use crate::{
+    EbcImagePolicy,
     allocator::{core_allocate_pages, core_free_pages},
-    config_tables::debug_image_info_table::{
-        EfiDebugImageInfoNormal, core_new_debug_image_info_entry, core_remove_debug_image_info_entry,
-        initialize_debug_image_info_table,
+    config_tables::{
+        debug_image_info_table::{
+            EfiDebugImageInfoNormal, core_new_debug_image_info_entry, core_remove_debug_image_info_entry,
+            initialize_debug_image_info_table,
+        },
+        memory_attributes_table,
     },
+    core_context::ContextStatic,
     dxe_services::{self, core_set_memory_space_attributes},
+    error::{CoreError, ErrorContext, Module},
     events::EVENT_DB,
     filesystems::SimpleFile,
     pecoff::{self, UefiPeInfo, relocation::RelocationBlock},
@@ -38,12 +61,17 @@ use crate::{
     protocols::{
         PROTOCOL_DB, core_install_protocol_interface, core_locate_device_path, core_uninstall_protocol_interface,
     },
-    runtime,
+    runtime, stack_usage,
     systemtables::EfiSystemTable,
     tpl_lock,
 };
 
 use efi::Guid;
+
+mod database;
+
+pub(crate) use database::{ImageDatabase, file_guid_for_handle, image_for_handle};
+pub use database::{LoadedImage, loaded_images};
 use uefi_corosensei::{
     Coroutine, CoroutineResult, Yielder,
     stack::{MIN_STACK_SIZE, STACK_ALIGNMENT, Stack, StackPointer},
@@ -72,9 +100,9 @@ struct ImageStack {
 }
 
 impl ImageStack {
-    fn new(size: usize) -> Result<Self, EfiError> {
+    fn new(size: usize) -> Result<Self, CoreError> {
         let mut stack: efi::PhysicalAddress = 0;
-        let len = align_up(size.max(MIN_STACK_SIZE), STACK_ALIGNMENT)?;
+        let len = align_up(size.max(MIN_STACK_SIZE), STACK_ALIGNMENT).context(Module::Image, "allocate image stack")?;
         // allocate an extra page for the stack guard page.
         let allocated_pages = uefi_size_to_pages!(len) + 1;
 
@@ -99,11 +127,13 @@ impl ImageStack {
         }
 
         // we have the guard page at the bottom, so we need to add a page to the stack pointer for the limit
-        Ok(ImageStack {
-            stack: core::ptr::slice_from_raw_parts_mut((stack + (UEFI_PAGE_SIZE as u64)) as *mut u8, len),
-            len,
-            allocated_pages,
-        })
+        let stack = core::ptr::slice_from_raw_parts_mut((stack + (UEFI_PAGE_SIZE as u64)) as *mut u8, len);
+        if stack_usage::image_stacks_watermarked() {
+            // Safety: the stack was just allocated above and is not in use yet.
+            stack_usage::watermark_image_stack(unsafe { &mut *stack });
+        }
+
+        Ok(ImageStack { stack, len, allocated_pages })
     }
 }
 
@@ -164,6 +194,7 @@ struct PrivateImageData {
     hii_resource_section_num_pages: Option<usize>,
     entry_point: efi::ImageEntryPoint,
     started: bool,
+    deferred: bool,
     exit_data: Option<(usize, *mut efi::Char16)>,
     image_info_ptr: *mut c_void,
     image_device_path_ptr: *mut c_void,
@@ -171,10 +202,12 @@ struct PrivateImageData {
     relocation_data: Vec<RelocationBlock>,
     image_base_page: efi::PhysicalAddress,
     image_num_pages: usize,
+    protection: ImageProtection,
 }
 
 impl PrivateImageData {
-    fn new(image_info: efi::protocols::loaded_image::Protocol, pe_info: &UefiPeInfo) -> Result<Self, EfiError> {
+    fn new(image_info: efi::protocols::loaded_image::Protocol, pe_info: &UefiPeInfo) -> Result<Self, CoreError> {
+        let load_error = CoreError::new(Module::Image, "allocate image buffer", EfiError::LoadError);
         // Allocate pages for the image to be loaded into. We use pages here instead of a pool because we are going to
         // set memory attributes on this range and it is not valid to set attributes on pool backed memory.
         let mut image_base_page: efi::PhysicalAddress = 0;
@@ -184,15 +217,15 @@ impl PrivateImageData {
             if let Some(image_size) = image_info.image_size.checked_add(pe_info.section_alignment as u64) {
                 match usize::try_from(image_size) {
                     Ok(size) => uefi_size_to_pages!(size),
-                    Err(_) => return Err(EfiError::LoadError),
+                    Err(_) => return Err(load_error),
                 }
             } else {
-                return Err(EfiError::LoadError);
+                return Err(load_error);
             }
         } else {
             match usize::try_from(image_info.image_size) {
                 Ok(size) => uefi_size_to_pages!(size),
-                Err(_) => return Err(EfiError::LoadError),
+                Err(_) => return Err(load_error),
             }
         };
 
@@ -205,11 +238,11 @@ impl PrivateImageData {
         )?;
 
         if image_base_page == 0 {
-            return Err(EfiError::OutOfResources);
+            return Err(CoreError::new(Module::Image, "allocate image buffer", EfiError::OutOfResources));
         }
 
-        let aligned_image_start =
-            align_up(image_base_page, pe_info.section_alignment.into()).map_err(|_| EfiError::LoadError)?;
+        let aligned_image_start = align_up(image_base_page, pe_info.section_alignment.into())
+            .map_err(|_| load_error.with_address(image_base_page))?;
 
         let mut image_data = PrivateImageData {
             image_buffer: core::ptr::slice_from_raw_parts_mut(
@@ -222,6 +255,7 @@ impl PrivateImageData {
             hii_resource_section_num_pages: None,
             entry_point: unimplemented_entry_point,
             started: false,
+            deferred: false,
             exit_data: None,
             image_info_ptr: core::ptr::null_mut(),
             image_device_path_ptr: core::ptr::null_mut(),
@@ -229,6 +263,7 @@ impl PrivateImageData {
             relocation_data: Vec::new(),
             image_base_page,
             image_num_pages: num_pages,
+            protection: IMAGE_PROTECTION_NONE,
         };
 
         image_data.image_info.image_base = image_data.image_buffer as *mut c_void;
@@ -251,6 +286,7 @@ impl PrivateImageData {
             hii_resource_section_num_pages: None,
             entry_point,
             started: true,
+            deferred: false,
             exit_data: None,
             image_info_ptr: core::ptr::null_mut(),
             image_device_path_ptr: core::ptr::null_mut(),
@@ -258,6 +294,7 @@ impl PrivateImageData {
             relocation_data: Vec::new(),
             image_base_page,
             image_num_pages,
+            protection: IMAGE_PROTECTION_NONE,
         }
     }
 
@@ -266,7 +303,7 @@ impl PrivateImageData {
         size: usize,
         alignment: usize,
         code_type: efi::MemoryType,
-    ) -> Result<(), EfiError> {
+    ) -> Result<(), CoreError> {
         let mut hii_base_page: efi::PhysicalAddress = 0;
         // if we have a unique alignment requirement, we need to overallocate the buffer to ensure we can align the base
         let num_pages: usize =
@@ -274,10 +311,12 @@ impl PrivateImageData {
         core_allocate_pages(efi::ALLOCATE_ANY_PAGES, code_type, num_pages, &mut hii_base_page, None)?;
 
         if hii_base_page == 0 {
-            return Err(EfiError::OutOfResources);
+            return Err(CoreError::new(Module::Image, "allocate resource section", EfiError::OutOfResources));
         }
 
-        let aligned_hii_start = align_up(hii_base_page, alignment as u64).map_err(|_| EfiError::LoadError)?;
+        let aligned_hii_start = align_up(hii_base_page, alignment as u64).map_err(|_| {
+            CoreError::new(Module::Image, "allocate resource section", EfiError::LoadError).with_address(hii_base_page)
+        })?;
 
         self.hii_resource_section = Some(core::ptr::slice_from_raw_parts_mut(aligned_hii_start as *mut u8, size));
         self.hii_resource_section_base = Some(hii_base_page);
@@ -310,23 +349,36 @@ impl Drop for PrivateImageData {
     }
 }
 
+// An image that was deferred by the Security2 Architectural Protocol, reported through the Deferred Image Load
+// protocol.
+struct DeferredImage {
+    image_handle: efi::Handle,
+    device_path: Option<Box<[u8]>>,
+    image: Box<[u8]>,
+    boot_policy: bool,
+}
+
 // This struct tracks global data used by the imaging subsystem.
-struct DxeCoreGlobalImageData {
+pub(crate) struct DxeCoreGlobalImageData {
     dxe_core_image_handle: efi::Handle,
     system_table: *mut efi::SystemTable,
     private_image_data: BTreeMap<efi::Handle, PrivateImageData>,
     current_running_image: Option<efi::Handle>,
     image_start_contexts: Vec<*const Yielder<efi::Handle, efi::Status>>,
+    deferred_images: Vec<DeferredImage>,
+    ebc_image_policy: EbcImagePolicy,
 }
 
 impl DxeCoreGlobalImageData {
-    const fn new() -> Self {
+    pub(crate) const fn new() -> Self {
         DxeCoreGlobalImageData {
             dxe_core_image_handle: core::ptr::null_mut(),
             system_table: core::ptr::null_mut(),
             private_image_data: BTreeMap::new(),
             current_running_image: None,
             image_start_contexts: Vec::new(),
+            deferred_images: Vec::new(),
+            ebc_image_policy: EbcImagePolicy::Warn,
         }
     }
 
@@ -336,7 +388,11 @@ impl DxeCoreGlobalImageData {
         self.system_table = core::ptr::null_mut();
         self.private_image_data = BTreeMap::new();
         self.current_running_image = None;
+        CURRENT_RUNNING_IMAGE.store(core::ptr::null_mut(), Ordering::Relaxed);
         self.image_start_contexts = Vec::new();
+        self.deferred_images = Vec::new();
+        self.ebc_image_policy = EbcImagePolicy::Warn;
+        database::reset();
     }
 }
 
@@ -345,8 +401,24 @@ impl DxeCoreGlobalImageData {
 unsafe impl Sync for DxeCoreGlobalImageData {}
 unsafe impl Send for DxeCoreGlobalImageData {}
 
-static PRIVATE_IMAGE_DATA: tpl_lock::TplMutex<DxeCoreGlobalImageData> =
-    tpl_lock::TplMutex::new(efi::TPL_NOTIFY, DxeCoreGlobalImageData::new(), "ImageLock");
+static PRIVATE_IMAGE_DATA: ContextStatic<tpl_lock::TplMutex<DxeCoreGlobalImageData>> =
+    ContextStatic::new(|context| &context.image_data);
+
+// Mirror of the currently running image of PRIVATE_IMAGE_DATA, readable without the image lock, e.g. by the allocator
+// to attribute allocations to the image making them.
+static CURRENT_RUNNING_IMAGE: ContextStatic<AtomicPtr<c_void>> = ContextStatic::new(|context| &context.running_image);
+
+/// Returns the handle of the image currently running, or `None` if no image was started or all started images
+/// returned. Does not take the image lock.
+pub(crate) fn current_running_image() -> Option<efi::Handle> {
+    let handle = CURRENT_RUNNING_IMAGE.load(Ordering::Relaxed);
+    if handle.is_null() { None } else { Some(handle) }
+}
+
+fn set_current_running_image(private_data: &mut DxeCoreGlobalImageData, image_handle: Option<efi::Handle>) {
+    private_data.current_running_image = image_handle;
+    CURRENT_RUNNING_IMAGE.store(image_handle.unwrap_or(core::ptr::null_mut()), Ordering::Relaxed);
+}
 
 // helper routine that returns an empty loaded_image::Protocol struct.
 fn empty_image_info() -> efi::protocols::loaded_image::Protocol {
@@ -570,6 +642,21 @@ fn install_dxe_core_image(hob_list: &HobList, system_table: &mut EfiSystemTable)
     // record this handle as the new dxe_core handle.
     private_data.dxe_core_image_handle = handle;
 
+    // record the dxe core image in the loaded image database.
+    database::add(LoadedImage {
+        info: LoadedImageInfo {
+            image_handle: handle,
+            image_base: dxe_core_hob.alloc_descriptor.memory_base_address,
+            image_size: dxe_core_hob.alloc_descriptor.memory_length,
+            entry_point: dxe_core_hob.entry_point,
+            file_guid: guids::DXE_CORE,
+            device_path: core::ptr::null(),
+            protection: private_image_data.protection,
+        },
+        name: pe_info.filename.clone(),
+        debug_info: pe_info.debug_info.clone(),
+    });
+
     // store the dxe core image private data in the private image data map.
     private_data.private_image_data.insert(handle, private_image_data);
 }
@@ -579,11 +666,27 @@ fn install_dxe_core_image(hob_list: &HobList, system_table: &mut EfiSystemTable)
 fn core_load_pe_image(
     image: &[u8],
     mut image_info: efi::protocols::loaded_image::Protocol,
-) -> Result<PrivateImageData, EfiError> {
+) -> Result<PrivateImageData, CoreError> {
     // parse and validate the header and retrieve the image data from it.
     let pe_info = pecoff::UefiPeInfo::parse(image)
         .inspect_err(|err| log::error!("core_load_pe_image failed: UefiPeInfo::parse returned {err:?}"))
-        .map_err(|_| EfiError::Unsupported)?;
+        .map_err(|_| CoreError::new(Module::Image, "parse PE header", EfiError::Unsupported))?;
+
+    // the core cannot execute EFI Byte Code.
+    if pe_info.machine == pecoff::IMAGE_FILE_MACHINE_EBC {
+        return Err(reject_ebc_image(&pe_info));
+    }
+
+    // the runtime memory of the published MAT must not change, unless the platform allows it.
+    if pe_info.image_type == EFI_IMAGE_SUBSYSTEM_EFI_RUNTIME_DRIVER
+        && !memory_attributes_table::runtime_image_load_allowed()
+    {
+        log::error!(
+            "Image {} is a runtime driver loaded after the Memory Attributes Table was published. Not loading image.",
+            pe_info.filename.as_deref().unwrap_or("<no PDB>")
+        );
+        return Err(CoreError::new(Module::Image, "load runtime image after ReadyToBoot", EfiError::Unsupported));
+    }
 
     // based on the image type, determine the correct allocator and code/data types.
     let (code_type, data_type) = match pe_info.image_type {
@@ -592,7 +695,7 @@ fn core_load_pe_image(
         EFI_IMAGE_SUBSYSTEM_EFI_RUNTIME_DRIVER => (efi::RUNTIME_SERVICES_CODE, efi::RUNTIME_SERVICES_DATA),
         unsupported_type => {
             log::error!("core_load_pe_image_failed: unsupported image type: {unsupported_type:#x?}");
-            return Err(EfiError::Unsupported);
+            return Err(CoreError::new(Module::Image, "check image subsystem", EfiError::Unsupported));
         }
     };
 
@@ -605,14 +708,14 @@ fn core_load_pe_image(
             "core_load_pe_image_failed: section alignment of {alignment:#x?} is not a (non-zero) multiple of page size {UEFI_PAGE_SIZE:#x?}",
         );
         debug_assert!(false);
-        return Err(EfiError::LoadError);
+        return Err(CoreError::new(Module::Image, "check section alignment", EfiError::LoadError));
     }
 
     // the size of the image must be a multiple of the section alignment per PE/COFF spec
     if !size.is_multiple_of(alignment) {
         log::error!("core_load_pe_image_failed: size of image is not a multiple of the section alignment");
         debug_assert!(false);
-        return Err(EfiError::LoadError);
+        return Err(CoreError::new(Module::Image, "check image size", EfiError::LoadError));
     }
 
     image_info.image_size = size as u64;
@@ -626,13 +729,18 @@ fn core_load_pe_image(
     //load the image into the new loaded image buffer
     pecoff::load_image(&pe_info, image, loaded_image)
         .inspect_err(|err| log::error!("core_load_pe_image_failed: load_image returned status: {err:?}"))
-        .map_err(|_| EfiError::LoadError)?;
+        .map_err(|_| {
+            CoreError::new(Module::Image, "load image sections", EfiError::LoadError)
+                .with_address(private_info.image_info.image_base as u64)
+        })?;
 
     //relocate the image to the address at which it was loaded.
     let loaded_image_addr = private_info.image_info.image_base as usize;
     private_info.relocation_data = pecoff::relocate_image(&pe_info, loaded_image_addr, loaded_image, &Vec::new())
         .inspect_err(|err| log::error!("core_load_pe_image_failed: relocate_image returned status: {err:?}"))
-        .map_err(|_| EfiError::LoadError)?;
+        .map_err(|_| {
+            CoreError::new(Module::Image, "relocate image", EfiError::LoadError).with_address(loaded_image_addr as u64)
+        })?;
 
     // update the entry point. Transmute is required here to cast the raw function address to the ImageEntryPoint function pointer type.
     private_info.entry_point = unsafe {
@@ -643,7 +751,10 @@ fn core_load_pe_image(
 
     let result = pecoff::load_resource_section(&pe_info, image)
         .inspect_err(|err| log::error!("core_load_pe_image_failed: load_resource_section returned status: {err:?}"))
-        .map_err(|_| EfiError::LoadError)?;
+        .map_err(|_| {
+            CoreError::new(Module::Image, "load resource section", EfiError::LoadError)
+                .with_address(loaded_image_addr as u64)
+        })?;
 
     if let Some((resource_section_offset, resource_section_size)) = result {
         private_info.allocate_resource_section(resource_section_size, alignment, code_type)?;
@@ -675,18 +786,55 @@ fn core_load_pe_image(
             // we are trying to load an application image that is not NX compatible, likely a bootloader
             // if we are configured to allow compatibility mode, we need to activate it now. Otherwise, just continue
             // to load the image
-            activate_compatibility_mode(&private_info)?;
+            activate_compatibility_mode(&private_info).map_err(|err| {
+                CoreError::new(Module::Image, "activate compatibility mode", err).with_address(loaded_image_addr as u64)
+            })?;
+            private_info.protection = IMAGE_PROTECTION_COMPATIBILITY_MODE;
         }
         _ => {
             // finally, update the GCD attributes for this image so that code sections have RO set and data sections
             // have XP
             apply_image_memory_protections(&pe_info, &private_info);
+            private_info.protection = IMAGE_PROTECTION_APPLIED;
         }
     }
 
     Ok(private_info)
 }
 
+// Reports the load of an EFI Byte Code image and applies the EBC image policy to it, returning the load error if the
+// system is not halted.
+fn reject_ebc_image(pe_info: &UefiPeInfo) -> CoreError {
+    let filename = pe_info.filename.as_deref().unwrap_or("<no PDB>");
+
+    match PROTOCOL_DB.locate_protocol(protocols::status_code::PROTOCOL_GUID) {
+        Ok(status_code_ptr) => {
+            let status_code_protocol = unsafe { &*(status_code_ptr as *mut protocols::status_code::Protocol) };
+            (status_code_protocol.report_status_code)(
+                status_code::EFI_ERROR_CODE | status_code::EFI_ERROR_MAJOR,
+                status_code::EFI_SOFTWARE_DXE_CORE | status_code::EFI_SW_EC_UNSUPPORTED,
+                0,
+                &guids::DXE_CORE,
+                core::ptr::null(),
+            );
+        }
+        Err(err) => log::error!("Unable to locate status code runtime protocol: {err:?}"),
+    }
+
+    match PRIVATE_IMAGE_DATA.lock().ebc_image_policy {
+        EbcImagePolicy::Warn => {
+            log::warn!("Image {filename} is an EFI Byte Code image, which is not supported. Not loading image.");
+            CoreError::new(Module::Image, "load EFI Byte Code image", EfiError::Unsupported)
+        }
+        EbcImagePolicy::Halt => panic!("Image {filename} is an EFI Byte Code image, which is not supported."),
+    }
+}
+
+/// Sets the policy applied to the EFI Byte Code images that are loaded.
+pub fn set_ebc_image_policy(policy: EbcImagePolicy) {
+    PRIVATE_IMAGE_DATA.lock().ebc_image_policy = policy;
+}
+
 #[cfg(feature = "compatibility_mode_allowed")]
 /// Activates compatibility mode for an image that is not NX compatible if the feature flag is set to allow compat mode
 /// This function will map the image as RWX in the GCD and initiate compatibility mode in the GCD
@@ -728,7 +876,7 @@ fn activate_compatibility_mode(private_info: &PrivateImageData) -> Result<(), Ef
 extern "efiapi" fn runtime_image_protection_fixup_ebs(event: efi::Event, _context: *mut c_void) {
     let mut private_data = PRIVATE_IMAGE_DATA.lock();
 
-    for image in private_data.private_image_data.values_mut() {
+    for (&handle, image) in private_data.private_image_data.iter_mut() {
         if image.pe_info.image_type == EFI_IMAGE_SUBSYSTEM_EFI_RUNTIME_DRIVER {
             let cache_attrs = dxe_services::core_get_memory_space_descriptor(image.image_base_page)
                 .map(|desc| desc.attributes & efi::CACHE_ATTRIBUTE_MASK)
@@ -740,7 +888,9 @@ extern "efiapi" fn runtime_image_protection_fixup_ebs(event: efi::Event, _contex
                 cache_attrs,
             ) {
                 Ok(_) => {
-                    // success, keep going
+                    // the runtime image is now mapped RWX to be relocated by the OS.
+                    image.protection = IMAGE_PROTECTION_NONE;
+                    database::set_protection(handle, IMAGE_PROTECTION_NONE);
                 }
                 Err(status) => {
                     log::error!(
@@ -760,8 +910,8 @@ extern "efiapi" fn runtime_image_protection_fixup_ebs(event: efi::Event, _contex
 }
 
 // Reads an image buffer using simple file system or load file protocols.
-// Return value is (image_buffer, device_handle, from_fv, authentication_status).
-// Note: presently none of the supported methods return `from_fv` or `authentication_status`.
+// Return value is (image_buffer, from_fv, device_handle, authentication_status).
+// Note: only images read from a firmware volume have an authentication status.
 fn get_buffer_by_file_path(
     boot_policy: bool,
     file_path: *mut efi::protocols::device_path::Protocol,
@@ -770,8 +920,8 @@ fn get_buffer_by_file_path(
         Err(EfiError::InvalidParameter)?;
     }
 
-    if let Ok((buffer, device_handle)) = get_file_buffer_from_fw(file_path) {
-        return Ok((buffer, true, device_handle, 0));
+    if let Ok((buffer, device_handle, authentication_status)) = get_file_buffer_from_fw(file_path) {
+        return Ok((buffer, true, device_handle, authentication_status));
     }
 
     if let Ok((buffer, device_handle)) = get_file_buffer_from_sfs(file_path) {
@@ -805,9 +955,22 @@ fn get_file_guid_from_device_path(path: *mut efi::protocols::device_path::Protoc
     Ok(Guid::from_bytes(file_path_node.data().try_into().map_err(|_| EfiError::BadBufferSize)?))
 }
 
+// Returns the name of the firmware volume file in the device path `path`, if it refers to one.
+fn get_fv_file_guid(path: *mut efi::protocols::device_path::Protocol) -> Option<Guid> {
+    if path.is_null() {
+        return None;
+    }
+    let mut walker = unsafe { DevicePathWalker::new(path) };
+    let file_node = walker.find(|node| {
+        node.header().r#type == efi::protocols::device_path::TYPE_MEDIA
+            && node.header().sub_type == efi::protocols::device_path::Media::SUBTYPE_PIWG_FIRMWARE_FILE
+    })?;
+    Some(Guid::from_bytes(file_node.data().try_into().ok()?))
+}
+
 fn get_file_buffer_from_fw(
     file_path: *mut efi::protocols::device_path::Protocol,
-) -> Result<(Vec<u8>, efi::Handle), EfiError> {
+) -> Result<(Vec<u8>, efi::Handle, u32), EfiError> {
     // Locate the handles to a device on the file_path that supports the firmware volume protocol
     let (remaining_file_path, handle) = core_locate_device_path(firmware_volume::PROTOCOL_GUID, file_path)?;
 
@@ -842,7 +1005,7 @@ fn get_file_buffer_from_fw(
     EfiError::status_to_result(status)?;
 
     let section_slice = unsafe { slice::from_raw_parts(buffer, buffer_size) };
-    Ok((section_slice.to_vec(), handle))
+    Ok((section_slice.to_vec(), handle, authentication_status))
 }
 
 fn get_file_buffer_from_sfs(
@@ -995,38 +1158,64 @@ pub fn core_load_image(
     parent_image_handle: efi::Handle,
     file_path: *mut efi::protocols::device_path::Protocol,
     image: Option<&[u8]>,
-) -> Result<(efi::Handle, Result<(), EfiError>), EfiError> {
+) -> Result<(efi::Handle, Result<(), EfiError>), CoreError> {
+    load_image_from_source(boot_policy, parent_image_handle, file_path, image, None)
+}
+
+/// Loads the image `image`, read from the firmware volume file `file_path`.
+///
+/// The image is authenticated as an image read from a firmware volume: `authentication_status` is the authentication
+/// status of the section containing the image, passed to the Security Architectural Protocol so that images from
+/// unauthenticated sections can be deferred or rejected by the platform policy.
+pub fn core_load_fv_image(
+    parent_image_handle: efi::Handle,
+    file_path: *mut efi::protocols::device_path::Protocol,
+    image: &[u8],
+    authentication_status: u32,
+) -> Result<(efi::Handle, Result<(), EfiError>), CoreError> {
+    load_image_from_source(false, parent_image_handle, file_path, Some(image), Some(authentication_status))
+}
+
+// `fv_authentication_status` is the authentication status of `image` if it was read from a firmware volume.
+fn load_image_from_source(
+    boot_policy: bool,
+    parent_image_handle: efi::Handle,
+    file_path: *mut efi::protocols::device_path::Protocol,
+    image: Option<&[u8]>,
+    fv_authentication_status: Option<u32>,
+) -> Result<(efi::Handle, Result<(), EfiError>), CoreError> {
     perf_load_image_begin(core::ptr::null_mut(), create_performance_measurement);
 
     if image.is_none() && file_path.is_null() {
-        log::error!("failed to load image: image is none or device path is null.");
-        return Err(EfiError::InvalidParameter);
+        return Err(CoreError::new(Module::Image, "validate image source", EfiError::InvalidParameter));
     }
 
-    PROTOCOL_DB
-        .validate_handle(parent_image_handle)
-        .inspect_err(|err| log::error!("failed to load image: invalid handle: {err:#x?}"))?;
+    PROTOCOL_DB.validate_handle(parent_image_handle).context(Module::Image, "validate parent image handle")?;
 
-    PROTOCOL_DB
-        .get_interface_for_handle(parent_image_handle, efi::protocols::loaded_image::PROTOCOL_GUID)
-        .inspect_err(|err| log::error!("failed to load image: failed to get loaded image interface: {err:?}"))
-        .map_err(|_| EfiError::InvalidParameter)?;
+    PROTOCOL_DB.get_interface_for_handle(parent_image_handle, efi::protocols::loaded_image::PROTOCOL_GUID).map_err(
+        |_| {
+            CoreError::new(Module::Image, "get parent loaded image", EfiError::InvalidParameter)
+                .with_guid(efi::protocols::loaded_image::PROTOCOL_GUID)
+        },
+    )?;
 
     let (image_to_load, from_fv, device_handle, authentication_status) = match image {
         Some(image) => {
             // If the buffer is specified and the device_path resolves with core_locate_device_path, then use the
             // resolved handle as the device_handle. Note: the associated device path for the device_handle will
             // likely be shorter than file_path.
+            let from_fv = fv_authentication_status.is_some();
+            let authentication_status = fv_authentication_status.unwrap_or(0);
             if let Ok((_device_path, device_handle)) =
                 core_locate_device_path(efi::protocols::device_path::PROTOCOL_GUID, file_path)
             {
-                (image.to_vec(), false, device_handle, 0)
+                (image.to_vec(), from_fv, device_handle, authentication_status)
             } else {
                 // (i.e. it doesn't correspond to anything that actually exists in the system)
-                (image.to_vec(), false, protocol_db::INVALID_HANDLE, 0)
+                (image.to_vec(), from_fv, protocol_db::INVALID_HANDLE, authentication_status)
             }
         }
-        None => get_buffer_by_file_path(boot_policy, file_path)?,
+        None => get_buffer_by_file_path(boot_policy, file_path).context(Module::Image, "read image from file path")?,
     };
 
     // authenticate the image
@@ -1049,7 +1238,7 @@ pub fn core_load_image(
             // Strip the parent device path prefix from the full device path to leave only the file node
             let (_, device_path_size) =
                 device_path_node_count(device_path as *mut efi::protocols::device_path::Protocol)
-                    .map_err(|status| EfiError::status_to_result(status).unwrap_err())?;
+                    .map_err(|status| CoreError::new(Module::Image, "measure device path", status.into()))?;
             let device_path_size_minus_end_node: usize =
                 device_path_size.saturating_sub(core::mem::size_of::<efi::protocols::device_path::Protocol>());
             let file_path = unsafe { (file_path as *const u8).add(device_path_size_minus_end_node) };
@@ -1063,12 +1252,12 @@ pub fn core_load_image(
         && !path.is_null()
     {
         image_info.file_path = Box::into_raw(
-            copy_device_path_to_boxed_slice(path).map_err(|status| EfiError::status_to_result(status).unwrap_err())?,
+            copy_device_path_to_boxed_slice(path)
+                .map_err(|status| CoreError::new(Module::Image, "copy file path", status.into()))?,
         ) as *mut efi::protocols::device_path::Protocol;
     }
 
-    let mut private_info = core_load_pe_image(image_to_load.as_ref(), image_info)
-        .inspect_err(|err| log::error!("failed to load image: core_load_pe_image failed: {err:?}"))?;
+    let mut private_info = core_load_pe_image(image_to_load.as_ref(), image_info)?;
 
     let image_info_ptr = private_info.image_info.as_ref() as *const efi::protocols::loaded_image::Protocol;
     let image_info_ptr = image_info_ptr as *mut c_void;
@@ -1083,7 +1272,10 @@ pub fn core_load_image(
     // install the loaded_image protocol for this freshly loaded image on a new
     // handle.
     let handle = core_install_protocol_interface(None, efi::protocols::loaded_image::PROTOCOL_GUID, image_info_ptr)
-        .inspect_err(|err| log::error!("failed to load image: install loaded image protocol failed: {err:?}"))?;
+        .map_err(|err| {
+            CoreError::new(Module::Image, "install protocol", err)
+                .with_guid(efi::protocols::loaded_image::PROTOCOL_GUID)
+        })?;
 
     // register the loaded image with the debug image info configuration table. This is done before the debugger is
     // notified so that the debugger can access the loaded image protocol before that point, e.g. so
@@ -1109,7 +1301,7 @@ pub fn core_load_image(
         // make copy and convert to raw pointer to avoid drop at end of function.
         Box::into_raw(
             copy_device_path_to_boxed_slice(file_path)
-                .map_err(|status| EfiError::status_to_result(status).unwrap_err())?,
+                .map_err(|status| CoreError::new(Module::Image, "copy device path", status.into()))?,
         ) as *mut u8
     };
 
@@ -1121,7 +1313,10 @@ pub fn core_load_image(
             &private_info.relocation_data,
             handle,
         )
-        .inspect_err(|err| log::error!("failed to load image: register runtime image failed: {err:?}"))?;
+        .map_err(|err| {
+            CoreError::new(Module::Image, "register runtime image", err)
+                .with_address(private_info.image_info.image_base as u64)
+        })?;
     }
 
     core_install_protocol_interface(
@@ -1129,7 +1324,10 @@ pub fn core_load_image(
         efi::protocols::loaded_image_device_path::PROTOCOL_GUID,
         loaded_image_device_path as *mut c_void,
     )
-    .inspect_err(|err| log::error!("failed to load image: install device path failed: {err:?}"))?;
+    .map_err(|err| {
+        CoreError::new(Module::Image, "install protocol", err)
+            .with_guid(efi::protocols::loaded_image_device_path::PROTOCOL_GUID)
+    })?;
 
     if let Some(res_section) = private_info.hii_resource_section {
         core_install_protocol_interface(
@@ -1137,15 +1335,66 @@ pub fn core_load_image(
             efi::protocols::hii_package_list::PROTOCOL_GUID,
             res_section as *mut c_void,
         )
-        .inspect_err(|err| log::error!("failed to load image: install HII package list failed: {err:?}"))?;
+        .map_err(|err| {
+            CoreError::new(Module::Image, "install protocol", err)
+                .with_guid(efi::protocols::hii_package_list::PROTOCOL_GUID)
+        })?;
     }
 
     // Store the interface pointers for unload to use when uninstalling these protocol interfaces.
     private_info.image_info_ptr = image_info_ptr;
     private_info.image_device_path_ptr = file_path as *mut c_void;
 
+    // Images that fail the security policy with SECURITY_VIOLATION are deferred rather than rejected: they stay
+    // loaded but cannot be started, and are reported through the Deferred Image Load protocol so that they can be
+    // retried later. An image that passes the policy supersedes any earlier deferral of the same device path.
+    let device_path_bytes = if file_path.is_null() {
+        None
+    } else {
+        Some(
+            copy_device_path_to_boxed_slice(file_path)
+                .map_err(|status| CoreError::new(Module::Image, "copy device path", status.into()))?,
+        )
+    };
+    let mut private_data = PRIVATE_IMAGE_DATA.lock();
+    match security_status {
+        Err(EfiError::SecurityViolation) => {
+            // Safety: file_path is null or the valid device path provided by the caller.
+            log::info!("Deferring image {handle:?} ({}) due to security violation.", unsafe {
+                DevicePathText::new(file_path)
+            });
+            private_info.deferred = true;
+            private_data.deferred_images.push(DeferredImage {
+                image_handle: handle,
+                device_path: device_path_bytes,
+                image: image_to_load.into_boxed_slice(),
+                boot_policy,
+            });
+        }
+        Ok(()) if device_path_bytes.is_some() => {
+            private_data.deferred_images.retain(|deferred| deferred.device_path != device_path_bytes);
+        }
+        _ => (),
+    }
+
+    // record the image in the loaded image database.
+    database::add(LoadedImage {
+        info: LoadedImageInfo {
+            image_handle: handle,
+            image_base: private_info.image_info.image_base as u64,
+            image_size: private_info.image_info.image_size,
+            entry_point: private_info.entry_point as usize as u64,
+            file_guid: get_fv_file_guid(file_path).unwrap_or(Guid::from_bytes(&[0; 16])),
+            device_path: loaded_image_device_path as *const efi::protocols::device_path::Protocol,
+            protection: private_info.protection,
+        },
+        name: private_info.pe_info.filename.clone(),
+        debug_info: private_info.pe_info.debug_info.clone(),
+    });
+
     // save the private image data for this image in the private image data map.
-    PRIVATE_IMAGE_DATA.lock().private_image_data.insert(handle, private_info);
+    private_data.private_image_data.insert(handle, private_info);
+    drop(private_data);
 
     perf_load_image_end(handle, create_performance_measurement);
 
@@ -1189,7 +1438,11 @@ extern "efiapi" fn load_image(
     };
 
     match core_load_image(boot_policy.into(), parent_image_handle, device_path, image) {
-        Err(err) => err.into(),
+        Err(err) => {
+            // Safety: the caller must provide a valid device path, if any.
+            log::error!("Failed to load image {}: {err}", unsafe { DevicePathText::new(device_path) });
+            err.into()
+        }
         Ok((handle, security_status)) => unsafe {
             // Safety: Caller must ensure that image_handle is a valid pointer. It is null-checked above.
             image_handle.write_unaligned(handle);
@@ -1213,6 +1466,11 @@ extern "efiapi" fn start_image(
     exit_data_size: *mut usize,
     exit_data: *mut *mut efi::Char16,
 ) -> efi::Status {
+    // deferred images are refused without being unloaded, so that they can still be re-evaluated.
+    if PRIVATE_IMAGE_DATA.lock().private_image_data.get(&image_handle).is_some_and(|image_data| image_data.deferred) {
+        return efi::Status::SECURITY_VIOLATION;
+    }
+
     let status = core_start_image(image_handle);
 
     // retrieve any exit data that was provided by the entry point.
@@ -1250,12 +1508,18 @@ pub fn core_start_image(image_handle: efi::Handle) -> Result<(), efi::Status> {
         if private_data.started {
             Err(EfiError::InvalidParameter)?;
         }
+        // deferred images may not be started until they have been re-evaluated.
+        if private_data.deferred {
+            Err(EfiError::SecurityViolation)?;
+        }
     } else {
         Err(EfiError::InvalidParameter)?;
     }
 
     // allocate a buffer for the entry point stack.
-    let stack = ImageStack::new(ENTRY_POINT_STACK_SIZE)?;
+    let stack = ImageStack::new(ENTRY_POINT_STACK_SIZE)
+        .inspect_err(|err| log::error!("Failed to allocate the entry point stack of image {image_handle:?}: {err}"))?;
+    let watermarked_stack = stack_usage::image_stacks_watermarked().then_some(stack.stack);
 
     perf_image_start_begin(image_handle, create_performance_measurement);
 
@@ -1301,7 +1565,7 @@ pub fn core_start_image(image_handle: efi::Handle) -> Result<(), efi::Status> {
     // be preserved on the stack of the various StartImage() instances.
     let mut private_data = PRIVATE_IMAGE_DATA.lock();
     let previous_image = private_data.current_running_image;
-    private_data.current_running_image = Some(image_handle);
+    set_current_running_image(&mut private_data, Some(image_handle));
     drop(private_data);
 
     // switch stacks and execute the above defined coroutine to start the image.
@@ -1312,7 +1576,10 @@ pub fn core_start_image(image_handle: efi::Handle) -> Result<(), efi::Status> {
         CoroutineResult::Return(status) => status,
     };
 
-    log::info!("start_image entrypoint exit with status: {status:x?}");
+    // Safety: the device paths of loaded images are owned by the core, and valid until the image is unloaded.
+    log::info!("start_image entrypoint of {} exit with status: {status:x?}", unsafe {
+        DevicePathText::new(database::device_path_for_handle(image_handle))
+    });
 
     // because we used exit() to return from the coroutine (as opposed to
     // returning naturally from it), the coroutine is marked as suspended rather
@@ -1322,7 +1589,17 @@ pub fn core_start_image(image_handle: efi::Handle) -> Result<(), efi::Status> {
     // executed.
     unsafe { coroutine.force_reset() };
 
-    PRIVATE_IMAGE_DATA.lock().current_running_image = previous_image;
+    if let Some(stack) = watermarked_stack {
+        let name = PRIVATE_IMAGE_DATA
+            .lock()
+            .private_image_data
+            .get(&image_handle)
+            .and_then(|private_info| private_info.pe_info.filename.clone());
+        // Safety: the stack is owned by the coroutine, which is not running anymore.
+        stack_usage::record_image_stack_usage(file_guid_for_handle(image_handle), name, unsafe { &*stack });
+    }
+
+    set_current_running_image(&mut PRIVATE_IMAGE_DATA.lock(), previous_image);
 
     perf_image_start_end(image_handle, create_performance_measurement);
 
@@ -1393,7 +1670,11 @@ pub fn core_unload_image(image_handle: efi::Handle, force_unload: bool) -> Resul
     // remove the private data for this image from the private_image_data map.
     // it will get dropped when it goes out of scope at the end of the function and the pages allocated for it
     // and the image_info box along with it.
-    let private_image_data = PRIVATE_IMAGE_DATA.lock().private_image_data.remove(&image_handle).unwrap();
+    let mut private_data = PRIVATE_IMAGE_DATA.lock();
+    let private_image_data = private_data.private_image_data.remove(&image_handle).unwrap();
+    private_data.deferred_images.retain(|deferred| deferred.image_handle != image_handle);
+    database::remove(image_handle);
+    drop(private_data);
     // remove the image and device path protocols from the image handle.
     let _ = core_uninstall_protocol_interface(
         image_handle,
@@ -1493,6 +1774,95 @@ extern "efiapi" fn exit(
     efi::Status::ACCESS_DENIED
 }
 
+/// Terminates the running image `image_handle` with `status` as if it had called Exit(), returning control to the
+/// StartImage() call that started it.
+///
+/// The stack of the image is abandoned, so this may only be used where the image can be safely abandoned, e.g. from a
+/// notification function interrupting it below TPL_CALLBACK. Returns an error status if `image_handle` is not the
+/// running image.
+pub(crate) fn core_terminate_image(image_handle: efi::Handle, status: efi::Status) -> efi::Status {
+    // exit() unloads images that are not started, which must not happen here.
+    if PRIVATE_IMAGE_DATA.lock().current_running_image != Some(image_handle) {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    exit(image_handle, status, 0, core::ptr::null_mut())
+}
+
+/// Clears the deferred state of an image that was deferred by the security policy so that it can be started.
+///
+/// This is used when the image is explicitly re-evaluated, e.g. when a deferred driver is promoted with the Trust()
+/// DXE service.
+///
+/// ## Errors
+///
+/// NotFound - `image_handle` is not a deferred image.
+pub fn core_trust_deferred_image(image_handle: efi::Handle) -> Result<(), EfiError> {
+    let mut private_data = PRIVATE_IMAGE_DATA.lock();
+    let image_data = private_data
+        .private_image_data
+        .get_mut(&image_handle)
+        .filter(|image_data| image_data.deferred)
+        .ok_or(EfiError::NotFound)?;
+    image_data.deferred = false;
+    private_data.deferred_images.retain(|deferred| deferred.image_handle != image_handle);
+    Ok(())
+}
+
+/// Returns the handle and file name of the loaded image that contains `address`.
+///
+/// Returns `None` if no loaded image contains `address`, or if the loaded image database is being updated.
+pub(crate) fn core_find_image_for_address(address: usize) -> Option<(efi::Handle, Option<String>)> {
+    database::find_image(address as u64).map(|image| (image.info.image_handle, image.name))
+}
+
+// Returns information about a deferred image. See EFI_DEFERRED_IMAGE_LOAD_PROTOCOL.GetImageInfo() in the UEFI spec
+// for usage details.
+extern "efiapi" fn get_deferred_image_info(
+    _this: *mut deferred_image_load::Protocol,
+    image_index: usize,
+    image_device_path: *mut *mut efi::protocols::device_path::Protocol,
+    image: *mut *mut c_void,
+    image_size: *mut usize,
+    boot_option: *mut efi::Boolean,
+) -> efi::Status {
+    if image_device_path.is_null() || image.is_null() || image_size.is_null() || boot_option.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+
+    let private_data = PRIVATE_IMAGE_DATA.lock();
+    let Some(deferred) = private_data.deferred_images.get(image_index) else {
+        return efi::Status::NOT_FOUND;
+    };
+
+    let device_path = match &deferred.device_path {
+        Some(device_path) => device_path.as_ptr() as *mut efi::protocols::device_path::Protocol,
+        None => core::ptr::null_mut(),
+    };
+
+    // Safety: the output pointers were null-checked above; the caller must ensure that they are otherwise valid. The
+    // returned buffers are owned by the deferred image list and remain valid until the image is unloaded or
+    // re-evaluated.
+    unsafe {
+        image_device_path.write_unaligned(device_path);
+        image.write_unaligned(deferred.image.as_ptr() as *mut c_void);
+        image_size.write_unaligned(deferred.image.len());
+        boot_option.write_unaligned(deferred.boot_policy.into());
+    }
+    efi::Status::SUCCESS
+}
+
+// Installs the Deferred Image Load protocol on a new handle.
+fn install_deferred_image_load_protocol() {
+    let protocol = Box::new(deferred_image_load::Protocol { get_image_info: get_deferred_image_info });
+    if let Err(err) = core_install_protocol_interface(
+        None,
+        deferred_image_load::PROTOCOL_GUID,
+        Box::into_raw(protocol) as *mut c_void,
+    ) {
+        log::error!("Failed to install deferred image load protocol: {err:?}");
+    }
+}
+
 /// Initializes image services for the DXE core.
 pub fn init_image_support(hob_list: &HobList, system_table: &mut EfiSystemTable) {
     // initialize system table entry in private global.
@@ -1503,6 +1873,10 @@ pub fn init_image_support(hob_list: &HobList, system_table: &mut EfiSystemTable)
     // install the image protocol for the dxe_core.
     install_dxe_core_image(hob_list, system_table);
 
+    // report the loaded images through the loaded image info protocol and in unhandled exceptions.
+    database::install_loaded_image_info_protocol();
+    patina_internal_cpu::interrupts::set_image_locator(database::log_image_for_address);
+
     // set up exit boot services callback
     let _ = EVENT_DB
         .create_event(
@@ -1514,6 +1888,8 @@ pub fn init_image_support(hob_list: &HobList, system_table: &mut EfiSystemTable)
         )
         .expect("Failed to create callback for runtime image memory protection fixups.");
 
+    install_deferred_image_load_protocol();
+
     //set up imaging services
     system_table.boot_services_mut().load_image = load_image;
     system_table.boot_services_mut().start_image = start_image;
@@ -1521,12 +1897,68 @@ pub fn init_image_support(hob_list: &HobList, system_table: &mut EfiSystemTable)
     system_table.boot_services_mut().exit = exit;
 }
 
+/// Initializes the image services for the host environment, see [crate::host].
+///
+/// The DXE core is not a PE image on the host, so its loaded image protocol describes no image. Image memory
+/// protections are tracked by the GCD, but are not enforced as paging is not initialized.
+#[cfg(feature = "std")]
+pub(crate) fn init_host_image_support(system_table: &mut EfiSystemTable) {
+    let mut private_data = PRIVATE_IMAGE_DATA.lock();
+    private_data.system_table = system_table.as_ptr() as *mut efi::SystemTable;
+
+    let mut image_info = empty_image_info();
+    image_info.system_table = private_data.system_table;
+    let image_info_ptr = Box::into_raw(Box::new(image_info)) as *mut c_void;
+
+    let handle = core_install_protocol_interface(
+        Some(protocol_db::DXE_CORE_HANDLE),
+        efi::protocols::loaded_image::PROTOCOL_GUID,
+        image_info_ptr,
+    )
+    .expect("Failed to install dxe core image handle.");
+    private_data.dxe_core_image_handle = handle;
+    drop(private_data);
+
+    initialize_debug_image_info_table(system_table);
+    database::install_loaded_image_info_protocol();
+    install_deferred_image_load_protocol();
+
+    system_table.boot_services_mut().load_image = load_image;
+    system_table.boot_services_mut().start_image = start_image;
+    system_table.boot_services_mut().unload_image = unload_image;
+    system_table.boot_services_mut().exit = exit;
+}
+
+/// Replaces the entry point of the loaded image `image_handle` with `entry_point`.
+///
+/// Used on the host, where the memory images are loaded in is not executable, to start an image with the entry point
+/// of the same driver compiled for the host.
+#[cfg(feature = "std")]
+pub(crate) fn set_image_entry_point(
+    image_handle: efi::Handle,
+    entry_point: efi::ImageEntryPoint,
+) -> Result<(), EfiError> {
+    let mut private_data = PRIVATE_IMAGE_DATA.lock();
+    let image_data = private_data.private_image_data.get_mut(&image_handle).ok_or(EfiError::InvalidParameter)?;
+    if image_data.started {
+        return Err(EfiError::InvalidParameter);
+    }
+    image_data.entry_point = entry_point;
+    Ok(())
+}
+
 #[cfg(test)]
 #[coverage(off)]
 mod tests {
     extern crate std;
-    use super::{empty_image_info, get_buffer_by_file_path, load_image};
+    use super::{
+        EbcImagePolicy, IMAGE_PROTECTION_APPLIED, ImageStack, core_find_image_for_address, core_load_fv_image,
+        core_load_image, core_start_image, core_terminate_image, core_trust_deferred_image, empty_image_info,
+        get_buffer_by_file_path, get_deferred_image_info, load_image, loaded_images, set_ebc_image_policy,
+    };
     use crate::{
+        error::Module,
+        events::{EVENT_DB, current_tpl, resume_after_image_termination, signal_event},
         image::{PRIVATE_IMAGE_DATA, exit, start_image, unload_image},
         protocol_db,
         protocols::{PROTOCOL_DB, core_install_protocol_interface},
@@ -1535,6 +1967,7 @@ mod tests {
     };
     use core::{ffi::c_void, sync::atomic::AtomicBool};
     use patina::error::EfiError;
+    use patina_pi::fw_fs::ffs::section::auth_status;
     use r_efi::efi;
     use std::{fs::File, io::Read};
 
@@ -1549,6 +1982,15 @@ mod tests {
         .unwrap();
     }
 
+    #[test]
+    fn image_stack_allocation_failure_keeps_the_allocator_error() {
+        with_locked_state(|| {
+            let err = ImageStack::new(1 << 46).err().expect("a 64TB stack should not be allocatable");
+            assert_eq!(err.module(), Module::Allocator);
+            assert_eq!(err.status(), EfiError::OutOfResources);
+        });
+    }
+
     unsafe fn init_test_image_support() {
         unsafe { PRIVATE_IMAGE_DATA.lock().reset() };
 
@@ -1616,6 +2058,114 @@ mod tests {
         });
     }
 
+    // Returns the test image with its machine type changed to EFI Byte Code.
+    fn ebc_test_image() -> Vec<u8> {
+        let mut test_file =
+            File::open(test_collateral!("test_image_msvc_hii.pe32")).expect("failed to open test file.");
+        let mut image: Vec<u8> = Vec::new();
+        test_file.read_to_end(&mut image).expect("failed to read test file");
+
+        // The COFF header, starting with the machine type, follows the PE signature.
+        let pe_pointer = u32::from_le_bytes(image[0x3c..0x40].try_into().unwrap()) as usize;
+        image[pe_pointer + 4..pe_pointer + 6].copy_from_slice(&crate::pecoff::IMAGE_FILE_MACHINE_EBC.to_le_bytes());
+        image
+    }
+
+    #[test]
+    fn load_image_should_reject_ebc_images() {
+        with_locked_state(|| {
+            let image = ebc_test_image();
+            let loaded_count = PRIVATE_IMAGE_DATA.lock().private_image_data.len();
+            let err = core_load_image(false, protocol_db::DXE_CORE_HANDLE, core::ptr::null_mut(), Some(&image))
+                .expect_err("EBC images must not be loaded");
+            assert_eq!(EfiError::from(err), EfiError::Unsupported);
+            assert_eq!(PRIVATE_IMAGE_DATA.lock().private_image_data.len(), loaded_count);
+        });
+    }
+
+    #[test]
+    fn load_image_should_halt_on_ebc_images_per_policy() {
+        with_locked_state(|| {
+            set_ebc_image_policy(EbcImagePolicy::Halt);
+            let image = ebc_test_image();
+            let result = std::panic::catch_unwind(|| {
+                let _ = core_load_image(false, protocol_db::DXE_CORE_HANDLE, core::ptr::null_mut(), Some(&image));
+            });
+            assert!(result.is_err());
+        });
+    }
+
+    #[test]
+    fn load_image_should_reject_runtime_images_after_ready_to_boot() {
+        with_locked_state(|| {
+            let mut test_file =
+                File::open(test_collateral!("test_image_msvc_hii.pe32")).expect("failed to open test file.");
+            let mut image: Vec<u8> = Vec::new();
+            test_file.read_to_end(&mut image).expect("failed to read test file");
+
+            // The subsystem is at offset 68 of the optional header, which follows the PE signature and COFF header.
+            let pe_pointer = u32::from_le_bytes(image[0x3c..0x40].try_into().unwrap()) as usize;
+            let subsystem = pe_pointer + 24 + 68;
+            image[subsystem..subsystem + 2].copy_from_slice(&EFI_IMAGE_SUBSYSTEM_EFI_RUNTIME_DRIVER.to_le_bytes());
+
+            memory_attributes_table::set_post_ready_to_boot();
+            let loaded_count = PRIVATE_IMAGE_DATA.lock().private_image_data.len();
+            let result = core_load_image(false, protocol_db::DXE_CORE_HANDLE, core::ptr::null_mut(), Some(&image));
+            memory_attributes_table::reset_memory_attributes_table();
+
+            let err = result.expect_err("runtime images must not be loaded after ReadyToBoot");
+            assert_eq!(EfiError::from(err), EfiError::Unsupported);
+            assert_eq!(PRIVATE_IMAGE_DATA.lock().private_image_data.len(), loaded_count);
+        });
+    }
+
+    #[test]
+    fn find_image_for_address_should_return_the_containing_image() {
+        with_locked_state(|| {
+            let mut test_file =
+                File::open(test_collateral!("test_image_msvc_hii.pe32")).expect("failed to open test file.");
+            let mut image: Vec<u8> = Vec::new();
+            test_file.read_to_end(&mut image).expect("failed to read test file");
+
+            let mut image_handle: efi::Handle = core::ptr::null_mut();
+            let status = load_image(
+                false.into(),
+                protocol_db::DXE_CORE_HANDLE,
+                core::ptr::null_mut(),
+                image.as_mut_ptr() as *mut c_void,
+                image.len(),
+                core::ptr::addr_of_mut!(image_handle),
+            );
+            assert_eq!(status, efi::Status::SUCCESS);
+
+            let (entry_point, image_end, filename, debug_info) = {
+                let private_data = PRIVATE_IMAGE_DATA.lock();
+                let image_data = private_data.private_image_data.get(&image_handle).unwrap();
+                let image_end = image_data.image_info.image_base as usize + image_data.image_info.image_size as usize;
+                (
+                    image_data.entry_point as usize,
+                    image_end,
+                    image_data.pe_info.filename.clone(),
+                    image_data.pe_info.debug_info.clone(),
+                )
+            };
+
+            assert_eq!(core_find_image_for_address(entry_point), Some((image_handle, filename)));
+            assert!(core_find_image_for_address(image_end).is_none_or(|(handle, _)| handle != image_handle));
+
+            let record = loaded_images().find(|image| image.info.image_handle == image_handle).unwrap();
+            assert_eq!(record.info.entry_point, entry_point as u64);
+            assert_eq!(record.info.image_base + record.info.image_size, image_end as u64);
+            assert_eq!(record.info.protection, IMAGE_PROTECTION_APPLIED);
+            assert!(record.debug_info.is_some());
+            assert_eq!(record.debug_info, debug_info);
+
+            // The lookup uses the loaded image database, so it does not depend on the image lock.
+            let _private_data = PRIVATE_IMAGE_DATA.lock();
+            assert_eq!(core_find_image_for_address(entry_point).map(|(handle, _)| handle), Some(image_handle));
+        });
+    }
+
     #[test]
     fn load_image_should_authenticate_the_image_with_security_arch() {
         with_locked_state(|| {
@@ -1688,8 +2238,8 @@ mod tests {
                 _authentication_status: u32,
                 _file: *mut efi::protocols::device_path::Protocol,
             ) -> efi::Status {
-                // should not be called, since `from_fv` is not presently true in our implementation for any
-                // source of FV, which means only Security2 should be used.
+                // should not be called, since images loaded from a buffer are not from a firmware volume, which
+                // means only Security2 should be used.
                 unreachable!()
             }
 
@@ -1758,6 +2308,232 @@ mod tests {
         });
     }
 
+    #[test]
+    fn load_fv_image_should_pass_the_section_authentication_status_to_security_arch() {
+        with_locked_state(|| {
+            let mut test_file =
+                File::open(test_collateral!("test_image_msvc_hii.pe32")).expect("failed to open test file.");
+            let mut image: Vec<u8> = Vec::new();
+            test_file.read_to_end(&mut image).expect("failed to read test file");
+
+            // The platform policy rejects the images from sections whose signature check failed.
+            extern "efiapi" fn mock_file_authentication_state(
+                _this: *mut patina_pi::protocols::security::Protocol,
+                authentication_status: u32,
+                _file: *mut efi::protocols::device_path::Protocol,
+            ) -> efi::Status {
+                if authentication_status & auth_status::TEST_FAILED != 0 {
+                    efi::Status::ACCESS_DENIED
+                } else {
+                    efi::Status::SUCCESS
+                }
+            }
+
+            extern "efiapi" fn mock_file_authentication(
+                _this: *mut patina_pi::protocols::security2::Protocol,
+                _file: *mut efi::protocols::device_path::Protocol,
+                _file_buffer: *mut c_void,
+                _file_size: usize,
+                _boot_policy: bool,
+            ) -> efi::Status {
+                efi::Status::SUCCESS
+            }
+
+            let security_protocol =
+                patina_pi::protocols::security::Protocol { file_authentication_state: mock_file_authentication_state };
+            let security2_protocol =
+                patina_pi::protocols::security2::Protocol { file_authentication: mock_file_authentication };
+            PROTOCOL_DB
+                .install_protocol_interface(
+                    None,
+                    patina_pi::protocols::security::PROTOCOL_GUID,
+                    &security_protocol as *const _ as *mut _,
+                )
+                .unwrap();
+            PROTOCOL_DB
+                .install_protocol_interface(
+                    None,
+                    patina_pi::protocols::security2::PROTOCOL_GUID,
+                    &security2_protocol as *const _ as *mut _,
+                )
+                .unwrap();
+
+            let (_, security_status) = core_load_fv_image(
+                protocol_db::DXE_CORE_HANDLE,
+                core::ptr::null_mut(),
+                &image,
+                auth_status::IMAGE_SIGNED | auth_status::TEST_FAILED,
+            )
+            .unwrap();
+            assert_eq!(security_status, Err(EfiError::AccessDenied));
+
+            let (_, security_status) = core_load_fv_image(
+                protocol_db::DXE_CORE_HANDLE,
+                core::ptr::null_mut(),
+                &image,
+                auth_status::IMAGE_SIGNED,
+            )
+            .unwrap();
+            assert_eq!(security_status, Ok(()));
+
+            // images loaded from a buffer are not from a firmware volume, so only Security2 is consulted.
+            let (_, security_status) =
+                core_load_image(false, protocol_db::DXE_CORE_HANDLE, core::ptr::null_mut(), Some(&image)).unwrap();
+            assert_eq!(security_status, Ok(()));
+        });
+    }
+
+    #[test]
+    fn load_image_should_defer_image_on_security_violation() {
+        with_locked_state(|| {
+            let mut test_file =
+                File::open(test_collateral!("test_image_msvc_hii.pe32")).expect("failed to open test file.");
+            let mut image: Vec<u8> = Vec::new();
+            test_file.read_to_end(&mut image).expect("failed to read test file");
+
+            extern "efiapi" fn mock_file_authentication_state(
+                _this: *mut patina_pi::protocols::security::Protocol,
+                _authentication_status: u32,
+                _file: *mut efi::protocols::device_path::Protocol,
+            ) -> efi::Status {
+                efi::Status::SUCCESS
+            }
+
+            extern "efiapi" fn mock_file_authentication(
+                _this: *mut patina_pi::protocols::security2::Protocol,
+                _file: *mut efi::protocols::device_path::Protocol,
+                _file_buffer: *mut c_void,
+                _file_size: usize,
+                _boot_policy: bool,
+            ) -> efi::Status {
+                efi::Status::SECURITY_VIOLATION
+            }
+
+            let security_protocol =
+                patina_pi::protocols::security::Protocol { file_authentication_state: mock_file_authentication_state };
+            let security2_protocol =
+                patina_pi::protocols::security2::Protocol { file_authentication: mock_file_authentication };
+            PROTOCOL_DB
+                .install_protocol_interface(
+                    None,
+                    patina_pi::protocols::security::PROTOCOL_GUID,
+                    &security_protocol as *const _ as *mut _,
+                )
+                .unwrap();
+            PROTOCOL_DB
+                .install_protocol_interface(
+                    None,
+                    patina_pi::protocols::security2::PROTOCOL_GUID,
+                    &security2_protocol as *const _ as *mut _,
+                )
+                .unwrap();
+
+            let mut image_handle: efi::Handle = core::ptr::null_mut();
+            let status = load_image(
+                true.into(),
+                protocol_db::DXE_CORE_HANDLE,
+                core::ptr::null_mut(),
+                image.as_mut_ptr() as *mut c_void,
+                image.len(),
+                core::ptr::addr_of_mut!(image_handle),
+            );
+            assert_eq!(status, efi::Status::SECURITY_VIOLATION);
+            assert!(!image_handle.is_null());
+
+            // the deferred image is reported through the deferred image load protocol.
+            let mut device_path: *mut efi::protocols::device_path::Protocol = core::ptr::null_mut();
+            let mut buffer: *mut c_void = core::ptr::null_mut();
+            let mut size: usize = 0;
+            let mut boot_option = efi::Boolean::FALSE;
+            let status = get_deferred_image_info(
+                core::ptr::null_mut(),
+                0,
+                &mut device_path,
+                &mut buffer,
+                &mut size,
+                &mut boot_option,
+            );
+            assert_eq!(status, efi::Status::SUCCESS);
+            assert!(device_path.is_null());
+            assert_eq!(unsafe { core::slice::from_raw_parts(buffer as *const u8, size) }, image.as_slice());
+            assert_eq!(boot_option, efi::Boolean::TRUE);
+
+            let status = get_deferred_image_info(
+                core::ptr::null_mut(),
+                1,
+                &mut device_path,
+                &mut buffer,
+                &mut size,
+                &mut boot_option,
+            );
+            assert_eq!(status, efi::Status::NOT_FOUND);
+            let status = get_deferred_image_info(
+                core::ptr::null_mut(),
+                0,
+                core::ptr::null_mut(),
+                &mut buffer,
+                &mut size,
+                &mut boot_option,
+            );
+            assert_eq!(status, efi::Status::INVALID_PARAMETER);
+
+            // a deferred image is refused, but not unloaded.
+            let status = start_image(image_handle, core::ptr::null_mut(), core::ptr::null_mut());
+            assert_eq!(status, efi::Status::SECURITY_VIOLATION);
+            assert!(PRIVATE_IMAGE_DATA.lock().private_image_data.contains_key(&image_handle));
+            assert_eq!(core_start_image(image_handle), Err(efi::Status::SECURITY_VIOLATION));
+
+            // once trusted, the image is no longer deferred.
+            assert_eq!(core_trust_deferred_image(image_handle), Ok(()));
+            assert!(!PRIVATE_IMAGE_DATA.lock().private_image_data.get(&image_handle).unwrap().deferred);
+            assert!(PRIVATE_IMAGE_DATA.lock().deferred_images.is_empty());
+            assert_eq!(core_trust_deferred_image(image_handle), Err(EfiError::NotFound));
+        });
+    }
+
+    #[test]
+    fn unload_image_should_remove_deferred_image() {
+        with_locked_state(|| {
+            let mut test_file =
+                File::open(test_collateral!("test_image_msvc_hii.pe32")).expect("failed to open test file.");
+            let mut image: Vec<u8> = Vec::new();
+            test_file.read_to_end(&mut image).expect("failed to read test file");
+
+            extern "efiapi" fn mock_file_authentication_state(
+                _this: *mut patina_pi::protocols::security::Protocol,
+                _authentication_status: u32,
+                _file: *mut efi::protocols::device_path::Protocol,
+            ) -> efi::Status {
+                efi::Status::SECURITY_VIOLATION
+            }
+
+            let security_protocol =
+                patina_pi::protocols::security::Protocol { file_authentication_state: mock_file_authentication_state };
+            PROTOCOL_DB
+                .install_protocol_interface(
+                    None,
+                    patina_pi::protocols::security::PROTOCOL_GUID,
+                    &security_protocol as *const _ as *mut _,
+                )
+                .unwrap();
+
+            let mut image_handle: efi::Handle = core::ptr::null_mut();
+            let status = load_image(
+                false.into(),
+                protocol_db::DXE_CORE_HANDLE,
+                core::ptr::null_mut(),
+                image.as_mut_ptr() as *mut c_void,
+                image.len(),
+                core::ptr::addr_of_mut!(image_handle),
+            );
+            assert_eq!(status, efi::Status::SECURITY_VIOLATION);
+            assert_eq!(PRIVATE_IMAGE_DATA.lock().deferred_images.len(), 1);
+
+            assert_eq!(unload_image(image_handle), efi::Status::SUCCESS);
+            assert!(PRIVATE_IMAGE_DATA.lock().deferred_images.is_empty());
+        });
+    }
+
     #[test]
     fn start_image_should_start_image() {
         with_locked_state(|| {
@@ -1864,6 +2640,62 @@ mod tests {
         });
     }
 
+    #[test]
+    fn terminate_image_from_notify_should_return_to_start_image() {
+        with_locked_state(|| {
+            let mut test_file =
+                File::open(test_collateral!("RustImageTestDxe.efi")).expect("failed to open test file.");
+            let mut image: Vec<u8> = Vec::new();
+            test_file.read_to_end(&mut image).expect("failed to read test file");
+
+            let (image_handle, _) =
+                core_load_image(false, protocol_db::DXE_CORE_HANDLE, core::ptr::null_mut(), Some(&image)).unwrap();
+
+            extern "efiapi" fn terminate_notify(_event: efi::Event, context: *mut c_void) {
+                let status = core_terminate_image(context as efi::Handle, efi::Status::TIMEOUT);
+                panic!("core_terminate_image returned {status:#x?}");
+            }
+
+            // The entry point signals a TPL_CALLBACK event, whose notification terminates it before it returns.
+            static ENTRY_POINT_RETURNED: AtomicBool = AtomicBool::new(false);
+            extern "efiapi" fn test_entry_point(
+                image_handle: *mut core::ffi::c_void,
+                _system_table: *mut r_efi::system::SystemTable,
+            ) -> efi::Status {
+                let event = EVENT_DB
+                    .create_event(
+                        efi::EVT_NOTIFY_SIGNAL,
+                        efi::TPL_CALLBACK,
+                        Some(terminate_notify),
+                        Some(image_handle),
+                        None,
+                    )
+                    .unwrap();
+                signal_event(event);
+                ENTRY_POINT_RETURNED.store(true, core::sync::atomic::Ordering::Relaxed);
+                efi::Status::SUCCESS
+            }
+            let mut private_data = PRIVATE_IMAGE_DATA.lock();
+            let image_data = private_data.private_image_data.get_mut(&image_handle).unwrap();
+            image_data.entry_point = test_entry_point;
+            drop(private_data);
+
+            assert_eq!(core_start_image(image_handle), Err(efi::Status::TIMEOUT));
+            assert!(!ENTRY_POINT_RETURNED.load(core::sync::atomic::Ordering::Relaxed));
+
+            // The notification was abandoned at its TPL.
+            assert_eq!(current_tpl(), efi::TPL_CALLBACK);
+            resume_after_image_termination(efi::TPL_APPLICATION);
+            assert_eq!(current_tpl(), efi::TPL_APPLICATION);
+
+            // The terminated image is not unloaded.
+            assert!(PRIVATE_IMAGE_DATA.lock().private_image_data.contains_key(&image_handle));
+
+            // Terminating an image that is not running fails.
+            assert_eq!(core_terminate_image(image_handle, efi::Status::TIMEOUT), efi::Status::INVALID_PARAMETER);
+        });
+    }
+
     #[test]
     fn unload_non_started_image_should_unload_the_image() {
         with_locked_state(|| {
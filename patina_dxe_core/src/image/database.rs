@@ -0,0 +1,383 @@
+//! DXE Core Loaded Image Database
+//!
+//! Records the images loaded by the core, so that diagnostics can identify the image containing an address without
+//! walking the Loaded Image protocol instances: the unhandled exception handler, the TPL diagnostics and the Loaded
+//! Image Info protocol. Each record carries the reference to the symbol file of the image, so that host tooling can
+//! match the faulting addresses to symbols. The records are kept under a spin lock rather than the image lock, so that
+//! they can be read at any TPL, including from an exception handler. They are only updated with the image lock held.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::ffi::c_void;
+
+use mu_rust_helpers::guid::guid_fmt;
+use patina::uefi_protocol::loaded_image_info::{self, ImageDebugInfo, ImageProtection, LoadedImageInfo};
+use r_efi::efi;
+use spin::RwLock;
+
+use crate::{
+    core_context::ContextStatic,
+    pecoff::{DebugInfo, DebugSignature},
+    protocols::core_install_protocol_interface,
+};
+
+/// An image loaded by the core.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadedImage {
+    /// The information reported for the image by the Loaded Image Info protocol.
+    pub info: LoadedImageInfo,
+    /// The file name of the image from its debug directory, if any.
+    pub name: Option<String>,
+    /// The reference to the symbol file of the image from its debug directory, if any.
+    pub debug_info: Option<DebugInfo>,
+}
+
+impl LoadedImage {
+    /// Returns whether the image contains `address`.
+    pub fn contains(&self, address: u64) -> bool {
+        (self.info.image_base..self.info.image_base.saturating_add(self.info.image_size)).contains(&address)
+    }
+
+    /// Returns the reference to the symbol file of the image, as reported by the Loaded Image Info protocol.
+    pub fn image_debug_info(&self) -> ImageDebugInfo {
+        let mut image_debug_info = ImageDebugInfo {
+            debug_info_type: loaded_image_info::DEBUG_INFO_NONE,
+            age: 0,
+            guid: efi::Guid::from_bytes(&[0; 16]),
+            signature: 0,
+            path: core::ptr::null(),
+        };
+        if let Some(debug_info) = &self.debug_info {
+            match debug_info.signature {
+                DebugSignature::Pdb70(guid) => {
+                    image_debug_info.debug_info_type = loaded_image_info::DEBUG_INFO_CODEVIEW_PDB70;
+                    image_debug_info.guid = efi::Guid::from_bytes(&guid);
+                }
+                DebugSignature::Pdb20(signature) => {
+                    image_debug_info.debug_info_type = loaded_image_info::DEBUG_INFO_CODEVIEW_PDB20;
+                    image_debug_info.signature = signature;
+                }
+            }
+            image_debug_info.age = debug_info.age;
+            image_debug_info.path = debug_info.path.as_ptr();
+        }
+        image_debug_info
+    }
+}
+
+/// The records of the loaded images.
+pub(crate) struct ImageDatabase(Vec<LoadedImage>);
+
+impl ImageDatabase {
+    /// Creates an empty database.
+    pub(crate) const fn new() -> Self {
+        Self(Vec::new())
+    }
+}
+
+// The device paths and handles of the records are only reported, never dereferenced, so the database is safe to
+// share.
+unsafe impl Send for ImageDatabase {}
+unsafe impl Sync for ImageDatabase {}
+
+static IMAGE_DATABASE: ContextStatic<RwLock<ImageDatabase>> = ContextStatic::new(|context| &context.image_database);
+
+/// Adds a record for a newly loaded image.
+pub(super) fn add(image: LoadedImage) {
+    IMAGE_DATABASE.write().0.push(image);
+}
+
+/// Removes the record of the unloaded image `image_handle`.
+pub(super) fn remove(image_handle: efi::Handle) {
+    IMAGE_DATABASE.write().0.retain(|image| image.info.image_handle != image_handle);
+}
+
+/// Updates the memory protection state of the image `image_handle`.
+pub(super) fn set_protection(image_handle: efi::Handle, protection: ImageProtection) {
+    if let Some(image) = IMAGE_DATABASE.write().0.iter_mut().find(|image| image.info.image_handle == image_handle) {
+        image.info.protection = protection;
+    }
+}
+
+#[cfg(test)]
+pub(super) fn reset() {
+    IMAGE_DATABASE.write().0.clear();
+}
+
+/// Returns a snapshot of the images loaded by the core, in load order. Must not be called above TPL_NOTIFY.
+pub fn loaded_images() -> impl Iterator<Item = LoadedImage> {
+    IMAGE_DATABASE.read().0.clone().into_iter()
+}
+
+/// Returns the loaded image `image_handle`. Must not be called above TPL_NOTIFY.
+pub(crate) fn image_for_handle(image_handle: efi::Handle) -> Option<LoadedImage> {
+    IMAGE_DATABASE.read().0.iter().find(|image| image.info.image_handle == image_handle).cloned()
+}
+
+/// Returns the loaded image containing `address`.
+///
+/// Returns `None` if no loaded image contains `address`, or if the database is being updated (i.e. when called from
+/// an interrupt raised during the update).
+pub(crate) fn find_image(address: u64) -> Option<LoadedImage> {
+    IMAGE_DATABASE.try_read()?.0.iter().find(|image| image.contains(address)).cloned()
+}
+
+/// Returns the name of the firmware volume file the image `image_handle` was loaded from.
+///
+/// Returns `None` if `image_handle` is not a loaded image, or if the database is being updated. Does not allocate.
+pub(crate) fn file_guid_for_handle(image_handle: efi::Handle) -> Option<efi::Guid> {
+    IMAGE_DATABASE
+        .try_read()?
+        .0
+        .iter()
+        .find(|image| image.info.image_handle == image_handle)
+        .map(|image| image.info.file_guid)
+}
+
+/// Returns the device path the image `image_handle` was loaded from, or null if it has none or is not a loaded image.
+///
+/// Does not allocate. The device path is owned by the core and valid until the image is unloaded.
+pub(super) fn device_path_for_handle(image_handle: efi::Handle) -> *const efi::protocols::device_path::Protocol {
+    IMAGE_DATABASE
+        .try_read()
+        .and_then(|database| {
+            database.0.iter().find(|image| image.info.image_handle == image_handle).map(|image| image.info.device_path)
+        })
+        .unwrap_or(core::ptr::null())
+}
+
+/// Logs the loaded image containing the faulting `address` of an unhandled exception.
+///
+/// Does not allocate nor block, as it runs in the exception handler.
+pub(super) fn log_image_for_address(address: u64) {
+    let Some(database) = IMAGE_DATABASE.try_read() else {
+        log::error!("Faulting address {address:#x}: loaded image database is being updated.");
+        return;
+    };
+    let Some(image) = database.0.iter().find(|image| image.contains(address)) else {
+        log::error!("Faulting address {address:#x} is not in a loaded image.");
+        return;
+    };
+    log::error!(
+        "Faulting address {address:#x} is in image {}+{:#x} (base {:#x}, file {:?}, handle {:#x?}).",
+        image.name.as_deref().unwrap_or("<no PDB>"),
+        address - image.info.image_base,
+        image.info.image_base,
+        guid_fmt!(image.info.file_guid),
+        image.info.image_handle
+    );
+    if let Some(debug_info) = &image.debug_info {
+        let path = debug_info.path.to_str().unwrap_or("<invalid path>");
+        match debug_info.signature {
+            DebugSignature::Pdb70(guid) => {
+                let guid = efi::Guid::from_bytes(&guid);
+                log::error!("Symbols: {path} (RSDS {:?}, age {}).", guid_fmt!(guid), debug_info.age)
+            }
+            DebugSignature::Pdb20(signature) => {
+                log::error!("Symbols: {path} (NB10 {signature:#010x}, age {}).", debug_info.age)
+            }
+        }
+    }
+}
+
+// Retrieves the information of the loaded image at `index`. See [loaded_image_info::GetLoadedImageInfo].
+extern "efiapi" fn get_loaded_image_info(
+    _this: *const loaded_image_info::Protocol,
+    index: usize,
+    info: *mut LoadedImageInfo,
+) -> efi::Status {
+    if info.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    let Some(database) = IMAGE_DATABASE.try_read() else {
+        return efi::Status::NOT_READY;
+    };
+    let Some(image) = database.0.get(index) else {
+        return efi::Status::NOT_FOUND;
+    };
+    // Safety: info was null-checked above; the caller must ensure that it is otherwise valid.
+    unsafe { info.write_unaligned(image.info) };
+    efi::Status::SUCCESS
+}
+
+// Retrieves the information of the loaded image containing `address`. See [loaded_image_info::FindLoadedImageInfo].
+extern "efiapi" fn find_loaded_image_info(
+    _this: *const loaded_image_info::Protocol,
+    address: efi::PhysicalAddress,
+    info: *mut LoadedImageInfo,
+) -> efi::Status {
+    if info.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    let Some(database) = IMAGE_DATABASE.try_read() else {
+        return efi::Status::NOT_READY;
+    };
+    let Some(image) = database.0.iter().find(|image| image.contains(address)) else {
+        return efi::Status::NOT_FOUND;
+    };
+    // Safety: info was null-checked above; the caller must ensure that it is otherwise valid.
+    unsafe { info.write_unaligned(image.info) };
+    efi::Status::SUCCESS
+}
+
+// Retrieves the reference to the symbol file of the loaded image containing `address`. See
+// [loaded_image_info::GetImageDebugInfo].
+extern "efiapi" fn get_image_debug_info(
+    _this: *const loaded_image_info::Protocol,
+    address: efi::PhysicalAddress,
+    info: *mut ImageDebugInfo,
+) -> efi::Status {
+    if info.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    let Some(database) = IMAGE_DATABASE.try_read() else {
+        return efi::Status::NOT_READY;
+    };
+    let Some(image) = database.0.iter().find(|image| image.contains(address)) else {
+        return efi::Status::NOT_FOUND;
+    };
+    // Safety: info was null-checked above; the caller must ensure that it is otherwise valid.
+    unsafe { info.write_unaligned(image.image_debug_info()) };
+    efi::Status::SUCCESS
+}
+
+/// Installs the Loaded Image Info protocol on a new handle.
+pub(super) fn install_loaded_image_info_protocol() {
+    let protocol =
+        Box::new(loaded_image_info::Protocol { get_loaded_image_info, find_loaded_image_info, get_image_debug_info });
+    if let Err(err) =
+        core_install_protocol_interface(None, loaded_image_info::PROTOCOL_GUID, Box::into_raw(protocol) as *mut c_void)
+    {
+        log::error!("Failed to install loaded image info protocol: {err:?}");
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use loaded_image_info::{IMAGE_PROTECTION_APPLIED, IMAGE_PROTECTION_NONE};
+
+    fn image(handle: usize, image_base: u64, image_size: u64) -> LoadedImage {
+        LoadedImage {
+            info: LoadedImageInfo {
+                image_handle: handle as efi::Handle,
+                image_base,
+                image_size,
+                entry_point: image_base + 0x400,
+                file_guid: efi::Guid::from_fields(handle as u32, 0, 0, 0, 0, &[0; 6]),
+                device_path: core::ptr::null(),
+                protection: IMAGE_PROTECTION_APPLIED,
+            },
+            name: Some(alloc::format!("image{handle}.efi")),
+            debug_info: None,
+        }
+    }
+
+    #[test]
+    fn loaded_image_database_tracks_images() {
+        crate::test_support::with_global_lock(|| {
+            reset();
+            add(image(1, 0x10000, 0x2000));
+            add(image(2, 0x20000, 0x3000));
+
+            assert_eq!(loaded_images().collect::<Vec<_>>(), [image(1, 0x10000, 0x2000), image(2, 0x20000, 0x3000)]);
+            assert_eq!(find_image(0x21fff), Some(image(2, 0x20000, 0x3000)));
+            assert_eq!(find_image(0x12000), None);
+
+            set_protection(1 as efi::Handle, IMAGE_PROTECTION_NONE);
+            assert_eq!(find_image(0x10000).unwrap().info.protection, IMAGE_PROTECTION_NONE);
+
+            remove(1 as efi::Handle);
+            assert_eq!(find_image(0x10000), None);
+            assert_eq!(loaded_images().count(), 1);
+            log_image_for_address(0x20010);
+            reset();
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn loaded_image_info_protocol_reports_images() {
+        crate::test_support::with_global_lock(|| {
+            reset();
+            add(image(1, 0x10000, 0x2000));
+            add(image(2, 0x20000, 0x3000));
+
+            let mut info = core::mem::MaybeUninit::<LoadedImageInfo>::uninit();
+            assert_eq!(get_loaded_image_info(core::ptr::null(), 1, info.as_mut_ptr()), efi::Status::SUCCESS);
+            assert_eq!(unsafe { info.assume_init() }, image(2, 0x20000, 0x3000).info);
+            assert_eq!(get_loaded_image_info(core::ptr::null(), 2, info.as_mut_ptr()), efi::Status::NOT_FOUND);
+            assert_eq!(
+                get_loaded_image_info(core::ptr::null(), 0, core::ptr::null_mut()),
+                efi::Status::INVALID_PARAMETER
+            );
+
+            assert_eq!(find_loaded_image_info(core::ptr::null(), 0x11000, info.as_mut_ptr()), efi::Status::SUCCESS);
+            assert_eq!(unsafe { info.assume_init() }, image(1, 0x10000, 0x2000).info);
+            assert_eq!(find_loaded_image_info(core::ptr::null(), 0x30000, info.as_mut_ptr()), efi::Status::NOT_FOUND);
+
+            let _guard = IMAGE_DATABASE.write();
+            assert_eq!(find_loaded_image_info(core::ptr::null(), 0x11000, info.as_mut_ptr()), efi::Status::NOT_READY);
+            drop(_guard);
+            reset();
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn loaded_image_info_protocol_reports_debug_info() {
+        crate::test_support::with_global_lock(|| {
+            reset();
+            let mut pdb70_image = image(1, 0x10000, 0x2000);
+            pdb70_image.debug_info = Some(DebugInfo {
+                signature: DebugSignature::Pdb70([7; 16]),
+                age: 3,
+                path: alloc::ffi::CString::new("C:\\build\\image1.pdb").unwrap(),
+            });
+            let mut pdb20_image = image(2, 0x20000, 0x3000);
+            pdb20_image.debug_info = Some(DebugInfo {
+                signature: DebugSignature::Pdb20(0x1234_5678),
+                age: 1,
+                path: alloc::ffi::CString::new("/build/image2.dll").unwrap(),
+            });
+            add(pdb70_image);
+            add(pdb20_image);
+            add(image(3, 0x30000, 0x1000));
+
+            let mut info = core::mem::MaybeUninit::<ImageDebugInfo>::uninit();
+            assert_eq!(get_image_debug_info(core::ptr::null(), 0x10010, info.as_mut_ptr()), efi::Status::SUCCESS);
+            let debug_info = unsafe { info.assume_init() };
+            assert_eq!(debug_info.debug_info_type, loaded_image_info::DEBUG_INFO_CODEVIEW_PDB70);
+            assert_eq!(debug_info.guid, efi::Guid::from_bytes(&[7; 16]));
+            assert_eq!(debug_info.age, 3);
+            assert_eq!(unsafe { core::ffi::CStr::from_ptr(debug_info.path) }.to_str(), Ok("C:\\build\\image1.pdb"));
+            log_image_for_address(0x10010);
+
+            assert_eq!(get_image_debug_info(core::ptr::null(), 0x20010, info.as_mut_ptr()), efi::Status::SUCCESS);
+            let debug_info = unsafe { info.assume_init() };
+            assert_eq!(debug_info.debug_info_type, loaded_image_info::DEBUG_INFO_CODEVIEW_PDB20);
+            assert_eq!(debug_info.signature, 0x1234_5678);
+            assert_eq!(unsafe { core::ffi::CStr::from_ptr(debug_info.path) }.to_str(), Ok("/build/image2.dll"));
+            log_image_for_address(0x20010);
+
+            assert_eq!(get_image_debug_info(core::ptr::null(), 0x30010, info.as_mut_ptr()), efi::Status::SUCCESS);
+            let debug_info = unsafe { info.assume_init() };
+            assert_eq!(debug_info.debug_info_type, loaded_image_info::DEBUG_INFO_NONE);
+            assert!(debug_info.path.is_null());
+
+            assert_eq!(get_image_debug_info(core::ptr::null(), 0x40000, info.as_mut_ptr()), efi::Status::NOT_FOUND);
+            assert_eq!(
+                get_image_debug_info(core::ptr::null(), 0x10010, core::ptr::null_mut()),
+                efi::Status::INVALID_PARAMETER
+            );
+            reset();
+        })
+        .unwrap();
+    }
+}
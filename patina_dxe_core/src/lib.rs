@@ -36,27 +36,40 @@
 extern crate alloc;
 
 mod allocator;
+mod boot_snapshot;
+mod component_lifecycle;
+mod component_measurement;
+mod component_report;
 mod config_tables;
+mod core_context;
 mod cpu_arch_protocol;
 mod decompress;
 mod dispatcher;
 mod driver_services;
 mod dxe_services;
+mod error;
 mod event_db;
 mod events;
 mod filesystems;
 mod fv;
 mod gcd;
+#[cfg(feature = "std")]
+pub mod host;
 #[cfg(all(target_os = "uefi", target_arch = "aarch64"))]
 mod hw_interrupt_protocol;
 mod image;
 mod memory_attributes_protocol;
 mod memory_manager;
+mod memory_map_check;
 mod misc_boot_services;
+mod panic_policy;
 mod pecoff;
 mod protocol_db;
 mod protocols;
+mod reset_notification;
 mod runtime;
+mod s3_boot_script;
+mod stack_usage;
 mod systemtables;
 mod tpl_lock;
 
@@ -65,16 +78,18 @@ mod tpl_lock;
 #[coverage(off)]
 pub mod test_support;
 
-use core::{ffi::c_void, ptr, str::FromStr};
+use core::{ffi::c_void, ptr, time::Duration};
 
 use alloc::{boxed::Box, vec::Vec};
+use core_context::ContextStatic;
 use gcd::SpinLockedGcd;
-use memory_manager::CoreMemoryManager;
+use memory_manager::{CoreMemoryManager, CoreSpecialRegions};
 use mu_rust_helpers::{function, guid::CALLER_ID};
 use patina::{
     boot_services::StandardBootServices,
-    component::{Component, IntoComponent, Storage, service::IntoService},
-    error::{self, Result},
+    component::{BootMode, Component, IntoComponent, Storage, service::IntoService},
+    dxe_services::StandardDxeServices,
+    error::Result,
     performance::{
         logging::{perf_function_begin, perf_function_end},
         measurement::create_performance_measurement,
@@ -82,16 +97,66 @@ use patina::{
     runtime_services::StandardRuntimeServices,
 };
 use patina_ffs::section::SectionExtractor;
-use patina_internal_cpu::{cpu::EfiCpu, interrupts::Interrupts};
+use patina_internal_cpu::{
+    cpu::EfiCpu,
+    interrupts::{self, Interrupts},
+};
 use patina_pi::{
-    hob::{HobList, get_c_hob_list_size},
+    hob::{Hob, HobList},
     protocols::{bds, status_code},
     status_code::{EFI_PROGRESS_CODE, EFI_SOFTWARE_DXE_CORE, EFI_SW_DXE_CORE_PC_HANDOFF_TO_NEXT},
 };
 use protocols::PROTOCOL_DB;
 use r_efi::efi;
 
-use crate::config_tables::memory_attributes_table;
+use crate::config_tables::{
+    allocation_attribution_table, conformance_profiles_table, memory_attributes_table, memory_map_snapshot,
+};
+
+pub use allocator::{FreePoisoningPolicy, MemoryMapCapPolicy};
+pub use boot_snapshot::{
+    BootRegression, BootSnapshot, BootSnapshotPolicy, BootSnapshotStore, DriverTiming, boot_regressions,
+};
+pub use component_measurement::{ComponentIdentity, ComponentMeasurementHandler};
+pub use component_report::{ComponentRecord, ComponentReport, ComponentState, component_report};
+pub use config_tables::allocation_attribution_table::{
+    ALLOCATION_ATTRIBUTION_REVISION, ALLOCATION_ATTRIBUTION_SIGNATURE, AllocationAttributionEntry,
+    AllocationAttributionHeader, AllocationAttributionPolicy, attribution_flags,
+};
+pub use config_tables::configuration_tables;
+pub use config_tables::conformance_profiles_table::{
+    CONFORMANCE_PROFILES_TABLE_VERSION, ConformanceProfilesPolicy, ConformanceProfilesTableHeader,
+};
+pub use config_tables::memory_map_snapshot::{
+    GcdMemorySpaceEntry, MEMORY_MAP_SNAPSHOT_REVISION, MEMORY_MAP_SNAPSHOT_SIGNATURE, MemoryMapSnapshotHeader,
+    MemoryMapSnapshotPolicy, SnapshotArray, snapshot_flags,
+};
+#[cfg(feature = "std")]
+pub use config_tables::memory_map_snapshot::{MemoryMapSnapshot, SnapshotDecodeError, SnapshotViolation};
+pub use dispatcher::{
+    Dependency, DependencyGraph, DispatchReport, DispatchReportHandler, DriverDispatchRecord, DriverFailureLog,
+    DriverFailureStore, DriverNode, DriverOutcome, DriverResolution, dependency_graph,
+};
+pub use driver_services::{
+    DriverBindingFailure, DriverBindingFailures, DriverBindingOperation, driver_binding_failures,
+};
+pub use image::{LoadedImage, loaded_images};
+pub use memory_map_check::{MemoryMapCheckPolicy, MemoryMapMismatch};
+pub use panic_policy::{PanicAction, PanicLog, PanicPolicy, PanicRecord, PanicStore, panic_handler};
+#[cfg(not(all(target_os = "uefi", target_arch = "x86_64")))]
+pub use patina_internal_cpu::cpu::smccc::Conduit;
+pub use patina_internal_cpu::paging::{
+    granule::PageGranule,
+    large_pages::{BlockStatistics, PagingStatistics},
+};
+pub use pecoff::{DebugInfo, DebugSignature};
+pub use s3_boot_script::{
+    S3_BOOT_SCRIPT_SIGNATURE, S3_BOOT_SCRIPT_TERMINATE_OPCODE, S3_BOOT_SCRIPT_VERSION, S3BootScriptPolicy,
+    s3_boot_script_table,
+};
+pub use stack_usage::{
+    ImageStackUsage, StackMilestone, StackUsage, StackUsagePolicy, core_stack_usage, image_stack_usage,
+};
 
 #[doc(hidden)]
 #[macro_export]
@@ -111,7 +176,7 @@ macro_rules! error {
     }};
 }
 
-pub(crate) static GCD: SpinLockedGcd = SpinLockedGcd::new(Some(events::gcd_map_change));
+pub(crate) static GCD: ContextStatic<SpinLockedGcd> = ContextStatic::new(|context| &context.gcd);
 
 /// A configuration struct containing the GIC bases (gic_d, gic_r) for AARCH64 systems.
 ///
@@ -144,6 +209,234 @@ impl Default for GicBases {
     }
 }
 
+/// A configuration struct containing the platform policy applied by the dispatcher to the drivers discovered in the
+/// firmware volumes.
+///
+/// Drivers that are ready to dispatch are started in the order of the firmware volumes listed in `fv_priority`,
+/// identified by their FV name (from the FV extended header). Drivers of the firmware volumes that are not listed are
+/// started after them. Drivers listed in `blocked_drivers` are never dispatched. If `allowed_drivers` is set, only the
+/// drivers it lists are dispatched. Each driver skipped due to the policy is logged when it is discovered.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use patina_dxe_core::{Core, DispatchPolicy};
+/// use r_efi::efi;
+/// # let physical_hob_list = core::ptr::null();
+///
+/// let policy = DispatchPolicy {
+///     blocked_drivers: vec![efi::Guid::from_fields(
+///         0x2b1c0b3e, 0x1e0c, 0x4d7a, 0x9a, 0x3f, &[0x5c, 0x2d, 0x11, 0x6e, 0x0b, 0x74],
+///     )],
+///     ..Default::default()
+/// };
+/// let core = Core::default()
+///    .init_memory(physical_hob_list)
+///    .with_config(policy)
+///    .start()
+///    .unwrap();
+/// ```
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DispatchPolicy {
+    /// FV names, in the order in which the drivers of their firmware volumes are dispatched.
+    pub fv_priority: Vec<efi::Guid>,
+    /// File names of the drivers that must not be dispatched.
+    pub blocked_drivers: Vec<efi::Guid>,
+    /// File names of the only drivers that may be dispatched, if set.
+    pub allowed_drivers: Option<Vec<efi::Guid>>,
+}
+
+impl DispatchPolicy {
+    /// Returns the dispatch priority of the drivers in the firmware volume named `fv_name`, lower first.
+    pub(crate) fn fv_priority(&self, fv_name: Option<efi::Guid>) -> usize {
+        fv_name
+            .and_then(|fv_name| self.fv_priority.iter().position(|name| *name == fv_name))
+            .unwrap_or(self.fv_priority.len())
+    }
+
+    /// Returns the reason the driver `file_name` must not be dispatched, if any.
+    pub(crate) fn skip_reason(&self, file_name: &efi::Guid) -> Option<&'static str> {
+        if self.blocked_drivers.contains(file_name) {
+            Some("it is in the block-list")
+        } else if self.allowed_drivers.as_ref().is_some_and(|allowed| !allowed.contains(file_name)) {
+            Some("it is not in the allow-list")
+        } else {
+            None
+        }
+    }
+}
+
+/// A configuration struct enabling guarded dispatch of the drivers discovered in the firmware volumes.
+///
+/// The entry point of each driver is terminated if it runs for longer than `time_budget`, and the watchdog timer is
+/// armed to reset the system if the driver hangs at a raised TPL or faults. If a [DriverFailureStore] service is
+/// registered, the failures are persisted across boots, and drivers that failed in `failure_threshold` consecutive
+/// boots are no longer dispatched. A `failure_threshold` of zero never blocks drivers.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use core::time::Duration;
+/// use patina_dxe_core::{Core, GuardedDispatchPolicy};
+/// # let physical_hob_list = core::ptr::null();
+///
+/// let policy = GuardedDispatchPolicy { time_budget: Duration::from_secs(10), ..Default::default() };
+/// let core = Core::default()
+///    .init_memory(physical_hob_list)
+///    .with_config(policy)
+///    .start()
+///    .unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GuardedDispatchPolicy {
+    /// The time the entry point of a driver may run for before it is terminated.
+    pub time_budget: Duration,
+    /// The number of consecutive boots a driver may fail in before it is no longer dispatched.
+    pub failure_threshold: u32,
+}
+
+impl Default for GuardedDispatchPolicy {
+    fn default() -> Self {
+        Self { time_budget: Duration::from_secs(30), failure_threshold: 3 }
+    }
+}
+
+/// A configuration struct bounding the memory used by the sections extracted before their driver is dispatched.
+///
+/// The dispatcher only decompresses the sections of a driver, e.g. its compressed PE32 image, when the driver is
+/// scheduled, and drops them once the image is loaded. The sections decompressed earlier, because the dependency
+/// expression of the file is itself encapsulated or by parallel section extraction, are cached until their file is
+/// dispatched. Once the cache exceeds `capacity` bytes, the least recently cached sections are dropped and extracted
+/// again when needed. Without this configuration, the capacity of the cache is 4 MB.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use patina_dxe_core::{Core, SectionCachePolicy};
+/// # let physical_hob_list = core::ptr::null();
+///
+/// let core = Core::default()
+///    .init_memory(physical_hob_list)
+///    .with_config(SectionCachePolicy { capacity: 0x10_0000 })
+///    .start()
+///    .unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SectionCachePolicy {
+    /// The maximum size in bytes of the cached sections.
+    pub capacity: usize,
+}
+
+impl Default for SectionCachePolicy {
+    fn default() -> Self {
+        Self { capacity: 0x40_0000 }
+    }
+}
+
+/// A configuration enum selecting how the core handles EFI Byte Code (EBC) images, such as the EBC drivers of some
+/// option ROMs. The core does not contain an EBC interpreter, so EBC images are never loaded; the load is reported
+/// with an `EFI_SW_EC_UNSUPPORTED` error status code either way.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use patina_dxe_core::{Core, EbcImagePolicy};
+/// # let physical_hob_list = core::ptr::null();
+///
+/// let core = Core::default()
+///    .init_memory(physical_hob_list)
+///    .with_config(EbcImagePolicy::Halt)
+///    .start()
+///    .unwrap();
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum EbcImagePolicy {
+    /// Log a warning and fail the load of the image with `EFI_UNSUPPORTED`.
+    #[default]
+    Warn,
+    /// Halt the system.
+    Halt,
+}
+
+/// A configuration enum selecting how the core handles runtime driver images loaded after the Memory Attributes Table
+/// (MAT) is published at ReadyToBoot. Their memory would change the runtime memory the OS loader expects the published
+/// MAT to describe, so they are rejected by default.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use patina_dxe_core::{Core, LateRuntimeImagePolicy};
+/// # let physical_hob_list = core::ptr::null();
+///
+/// let core = Core::default()
+///    .init_memory(physical_hob_list)
+///    .with_config(LateRuntimeImagePolicy::Allow)
+///    .start()
+///    .unwrap();
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LateRuntimeImagePolicy {
+    /// Log an error and fail the load of the image with `EFI_UNSUPPORTED`.
+    #[default]
+    Reject,
+    /// Load the image, and republish the MAT with the memory of the image.
+    Allow,
+}
+
+/// A configuration enum selecting the memory type of the pages the core relocates the HOB list handed off from
+/// pre-DXE to, before installing it as the HOB list configuration table. The pages of the original HOB list are
+/// released once it is relocated.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use patina_dxe_core::{Core, HobListPolicy};
+/// # let physical_hob_list = core::ptr::null();
+///
+/// let core = Core::default()
+///    .init_memory(physical_hob_list)
+///    .with_config(HobListPolicy::RuntimeServicesData)
+///    .start()
+///    .unwrap();
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum HobListPolicy {
+    /// Relocate the HOB list to boot services data, which is reclaimed by the OS.
+    #[default]
+    BootServicesData,
+    /// Relocate the HOB list to runtime services data, which remains available to the OS.
+    RuntimeServicesData,
+}
+
+/// A configuration enum selecting whether the page table identity maps memory with large pages.
+///
+/// By default, memory is mapped with the largest blocks a range allows (1GB and 2MB pages with a 4KB granule, 32MB or
+/// 512MB blocks with a 16KB or 64KB granule), which are split when an attribute change only covers part of them.
+/// Disabling large pages maps all memory with pages of the granule, which makes the page tables easier to inspect when
+/// debugging attribute issues, at the cost of page table memory and TLB pressure. The blocks mapped and split are
+/// printed by the `paging` debugger monitor command.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use patina_dxe_core::{Core, LargePagePolicy};
+/// # let physical_hob_list = core::ptr::null();
+///
+/// let core = Core::default()
+///    .init_memory(physical_hob_list)
+///    .with_config(LargePagePolicy::Disabled)
+///    .start()
+///    .unwrap();
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LargePagePolicy {
+    /// Map memory with the largest blocks a range allows.
+    #[default]
+    Enabled,
+    /// Map all memory with pages of the granule.
+    Disabled,
+}
+
 #[doc(hidden)]
 /// A zero-sized type to gate allocation functions in the [Core].
 pub struct Alloc;
@@ -177,6 +470,11 @@ pub struct NoAlloc;
 /// | Service Trait                           | Description                                      |
 /// |-----------------------------------------|--------------------------------------------------|
 /// | [patina_ffs::section::SectionExtractor] | FW volume section extraction w/ decompression    |
+/// | [DriverFailureStore]                    | Driver failures persisted for guarded dispatch   |
+/// | [DispatchReportHandler]                 | Platform decision on the driver dispatch report  |
+/// | [PanicStore]                            | Panics persisted for the panic policy            |
+/// | [BootSnapshotStore]                     | Boot snapshot of the previous boot               |
+/// | [ComponentMeasurementHandler]           | Measurement and rejection of the components      |
 ///
 /// ## Examples
 ///
@@ -232,7 +530,10 @@ impl Core<NoAlloc> {
             panic!("HOB list pointer is null!");
         }
 
+        stack_usage::watermark_core_stack(physical_hob_list);
+
         gcd::init_gcd(physical_hob_list);
+        stack_usage::record_core_stack_usage(StackMilestone::GcdInitialized);
 
         log::trace!("Initial GCD:\n{GCD}");
 
@@ -257,6 +558,51 @@ impl Core<NoAlloc> {
         patina_debugger::add_monitor_command("gcd", "Prints the GCD", |_, out| {
             let _ = write!(out, "GCD -\n{GCD}");
         });
+        patina_debugger::add_monitor_command("paging", "Prints the large page statistics", |_, out| {
+            match GCD.paging_statistics() {
+                Some(statistics) => {
+                    let _ = write!(out, "{statistics}");
+                }
+                None => {
+                    let _ = write!(out, "Paging is not initialized");
+                }
+            }
+        });
+        patina_debugger::add_monitor_command("interrupts", "Prints the exception and interrupt counters", |_, out| {
+            let summary = interrupts::statistics::interrupt_summary();
+            let _ = writeln!(
+                out,
+                "Faults: {}, interrupts: {}, spurious: {}, max nesting: {}",
+                summary.faults, summary.interrupts, summary.spurious, summary.max_nesting_depth
+            );
+            for vector in 0..summary.vector_count as usize {
+                match interrupts::statistics::vector_statistics(vector) {
+                    Some(statistics) if statistics.taken != 0 => {
+                        let _ = writeln!(
+                            out,
+                            "  0x{vector:02x}: taken {}, spurious {}",
+                            statistics.taken, statistics.spurious
+                        );
+                    }
+                    _ => {}
+                }
+            }
+        });
+        patina_debugger::add_monitor_command(
+            "depgraph",
+            "Prints the driver dependency graph as DOT, or as JSON with 'depgraph json'",
+            |args, out| {
+                let graph = dispatcher::dependency_graph();
+                let _ = match args.next() {
+                    Some("json") => writeln!(out, "{}", graph.to_json()),
+                    _ => write!(out, "{}", graph.to_dot()),
+                };
+            },
+        );
+        patina_debugger::add_monitor_command("stack", "Prints the core and image stack usage", |_, out| {
+            let _ = stack_usage::write_stack_usage(out);
+        });
+        component_report::init_component_report();
 
         // Initialize the debugger if it is enabled.
         patina_debugger::initialize(&mut interrupt_manager);
@@ -266,6 +612,7 @@ impl Core<NoAlloc> {
         self.storage.add_service(cpu);
         self.storage.add_service(interrupt_manager);
         self.storage.add_service(CoreMemoryManager);
+        self.storage.add_service(CoreSpecialRegions);
 
         Core {
             physical_hob_list,
@@ -303,6 +650,131 @@ impl Core<NoAlloc> {
         GCD.prioritize_32_bit_memory(true);
         self
     }
+
+    /// Sets the page granule used for page table changes, overriding the granule selected at compile time with the
+    /// `aarch64_granule_16k` / `aarch64_granule_64k` features and the granule detected from the translation control
+    /// register.
+    ///
+    /// Page table attribute changes are aligned to the granule. A granule shared by UEFI pages with different
+    /// attributes gets the least restrictive access attributes of those pages.
+    ///
+    /// Must be called prior to [`Core::init_memory`].
+    ///
+    /// ## Example
+    ///
+    /// ``` rust,no_run
+    /// # let physical_hob_list = core::ptr::null();
+    /// patina_dxe_core::Core::default()
+    ///   .with_page_granule(patina_dxe_core::PageGranule::Size64KB)
+    ///   .init_memory(physical_hob_list)
+    ///   .start()
+    ///   .unwrap();
+    /// ```
+    pub fn with_page_granule(self, granule: PageGranule) -> Self {
+        // This doesn't actually alter the core's state, but uses the same model
+        // for consistent abstraction.
+        GCD.set_page_granule(granule);
+        self
+    }
+
+    /// Selects the conduit, SMC or HVC, of the SMC Calling Convention calls issued by the core on AArch64, such as
+    /// the PSCI SYSTEM_RESET issued by the debugger. SMC is used by default.
+    ///
+    /// The conduit is described by the device tree PSCI node `method` property, see [Conduit::from_dt_method], or by
+    /// the ACPI FADT `ARM_BOOT_ARCH` flags, see [Conduit::from_fadt_arm_boot_arch].
+    ///
+    /// ## Example
+    ///
+    /// ``` rust,no_run
+    /// # let physical_hob_list = core::ptr::null();
+    /// patina_dxe_core::Core::default()
+    ///   .with_smccc_conduit(patina_dxe_core::Conduit::from_dt_method("hvc").unwrap_or_default())
+    ///   .init_memory(physical_hob_list)
+    ///   .start()
+    ///   .unwrap();
+    /// ```
+    #[cfg(not(all(target_os = "uefi", target_arch = "x86_64")))]
+    pub fn with_smccc_conduit(self, conduit: Conduit) -> Self {
+        // This doesn't actually alter the core's state, but uses the same model
+        // for consistent abstraction.
+        Conduit::set_platform(conduit);
+        self
+    }
+
+    /// Decompresses the UEFI and Tiano compressed top-level sections of newly discovered firmware volumes on the
+    /// application processors (APs) while the bootstrap processor processes their files, once the MP Services protocol
+    /// is installed.
+    ///
+    /// The APs only decompress data into buffers allocated by the bootstrap processor, and never access devices or
+    /// call boot services. Nested sections, sections using a platform provided extractor, and the hashing and
+    /// authentication of the images remain on the bootstrap processor. The time spent discovering firmware volumes is
+    /// recorded in the performance records.
+    ///
+    /// ## Example
+    ///
+    /// ``` rust,no_run
+    /// # let physical_hob_list = core::ptr::null();
+    /// patina_dxe_core::Core::default()
+    ///   .with_parallel_section_extraction()
+    ///   .init_memory(physical_hob_list)
+    ///   .start()
+    ///   .unwrap();
+    /// ```
+    pub fn with_parallel_section_extraction(self) -> Self {
+        // This doesn't actually alter the core's state, but uses the same model
+        // for consistent abstraction.
+        dispatcher::enable_parallel_section_extraction();
+        self
+    }
+
+    /// Enables diagnostics for the misuse of the task priority level (TPL) around event notifications.
+    ///
+    /// Notification functions that restore the TPL below their notify TPL or return at another TPL, events signaled at
+    /// TPL_HIGH_LEVEL, and notification functions that run for longer than `notify_time_limit` are logged as errors,
+    /// along with the image that contains the notification function. A `notify_time_limit` of zero disables the run
+    /// time check. The run time is measured with the timer architectural protocol, at the resolution of its period.
+    ///
+    /// ## Example
+    ///
+    /// ``` rust,no_run
+    /// # let physical_hob_list = core::ptr::null();
+    /// patina_dxe_core::Core::default()
+    ///   .with_tpl_diagnostics(core::time::Duration::from_millis(100))
+    ///   .init_memory(physical_hob_list)
+    ///   .start()
+    ///   .unwrap();
+    /// ```
+    pub fn with_tpl_diagnostics(self, notify_time_limit: Duration) -> Self {
+        // This doesn't actually alter the core's state, but uses the same model
+        // for consistent abstraction.
+        events::enable_tpl_diagnostics(notify_time_limit);
+        self
+    }
+
+    /// Enables the tickless timer mode.
+    ///
+    /// Instead of interrupting at the fixed period the timer architectural protocol was installed with, the timer is
+    /// reprogrammed to interrupt at the earliest deadline of the armed timer events, no sooner than `min_period` and no
+    /// later than `max_period`. Timer events then fire on time without waking the processor up at every tick while
+    /// waiting. Each reprogramming of the timer before its period elapsed loses the part of the period that elapsed
+    /// from the system time, so `min_period` should not be too small.
+    ///
+    /// ## Example
+    ///
+    /// ``` rust,no_run
+    /// # let physical_hob_list = core::ptr::null();
+    /// patina_dxe_core::Core::default()
+    ///   .with_tickless_timer(core::time::Duration::from_micros(100), core::time::Duration::from_millis(100))
+    ///   .init_memory(physical_hob_list)
+    ///   .start()
+    ///   .unwrap();
+    /// ```
+    pub fn with_tickless_timer(self, min_period: Duration, max_period: Duration) -> Self {
+        // This doesn't actually alter the core's state, but uses the same model
+        // for consistent abstraction.
+        events::enable_tickless_timer(min_period, max_period);
+        self
+    }
 }
 
 impl Core<Alloc> {
@@ -323,6 +795,7 @@ impl Core<Alloc> {
     /// Inserts a component at the given index. If no index is provided, the component is added to the end of the list.
     fn insert_component(&mut self, idx: usize, mut component: Box<dyn Component>) {
         component.initialize(&mut self.storage);
+        component_report::register(component.metadata());
         self.components.insert(idx, component);
     }
 
@@ -357,6 +830,38 @@ impl Core<Alloc> {
         }
     }
 
+    /// Returns the boot mode of the PHIT HOB, or a full configuration boot if the HOB list has none.
+    fn boot_mode(&self) -> BootMode {
+        self.hob_list
+            .iter()
+            .find_map(|hob| match hob {
+                Hob::Handoff(phit) => Some(phit.boot_mode),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    /// Removes the components that opted out of `boot_mode`, so they are never dispatched.
+    fn skip_components(&mut self, boot_mode: BootMode) {
+        self.components.retain(|component| {
+            let metadata = component.metadata();
+            if !metadata.skips_boot_mode(boot_mode) {
+                return true;
+            }
+            log::info!("Skipped: Id = [{:?}] Boot Mode = [{boot_mode}]", metadata.name());
+            component_report::record_skip(metadata, boot_mode);
+            false
+        });
+    }
+
+    /// Measures the components with the [ComponentMeasurementHandler] service, if any, removing the ones it rejects.
+    fn measure_components(&mut self) {
+        let Some(handler) = self.storage.get_service::<dyn ComponentMeasurementHandler>() else {
+            return;
+        };
+        self.components.retain(|component| component_measurement::measure_component(&*handler, component.metadata()));
+    }
+
     /// Attempts to dispatch all components.
     ///
     /// This method will exit once no components remain or no components were dispatched during a full iteration.
@@ -368,7 +873,12 @@ impl Core<Alloc> {
             // Err(e): Dispatchable and dispatched returning failure
             let name = component.metadata().name();
             log::trace!("Dispatch Start: Id = [{name:?}]");
-            !match component.run(&mut self.storage) {
+            component_lifecycle::begin_dispatch(name);
+            let start = dispatcher::timestamp();
+            let result = component.run(&mut self.storage);
+            component_report::record_attempt(component.metadata(), &result, dispatcher::elapsed_since(start));
+            component_lifecycle::end_dispatch();
+            !match result {
                 Ok(true) => {
                     log::info!("Dispatched: Id = [{name:?}] Status = [Success]");
                     true
@@ -381,6 +891,7 @@ impl Core<Alloc> {
                 }
             }
         });
+        component_report::set_produced(self.storage.produced());
         len != self.components.len()
     }
 
@@ -399,7 +910,7 @@ impl Core<Alloc> {
 
             // UEFI driver dispatch
             let dispatched = dispatched
-                || dispatcher::dispatch().inspect_err(|err| log::error!("UEFI Driver Dispatch error: {err:?}"))?;
+                || dispatcher::dispatch().inspect_err(|err| log::error!("UEFI Driver Dispatch error: {err}"))?;
 
             if !dispatched {
                 break;
@@ -438,56 +949,48 @@ impl Core<Alloc> {
         }
     }
 
-    /// Returns the length of the HOB list.
-    /// Clippy gets unhappy if we call get_c_hob_list_size directly, because it gets confused, thinking
-    /// get_c_hob_list_size is not marked unsafe, but it is
-    fn get_hob_list_len(hob_list: *const c_void) -> usize {
-        unsafe { get_c_hob_list_size(hob_list) }
-    }
-
     fn initialize_system_table(&mut self) -> Result<()> {
-        let hob_list_slice = unsafe {
-            core::slice::from_raw_parts(
-                self.physical_hob_list as *const u8,
-                Self::get_hob_list_len(self.physical_hob_list),
-            )
+        let hob_list_memory_type = match self.storage.get_config::<HobListPolicy>().map(|policy| *policy) {
+            Some(HobListPolicy::RuntimeServicesData) => efi::RUNTIME_SERVICES_DATA,
+            Some(HobListPolicy::BootServicesData) | None => efi::BOOT_SERVICES_DATA,
         };
-        let relocated_c_hob_list = hob_list_slice.to_vec().into_boxed_slice();
+        let relocated_c_hob_list = allocator::relocate_hob_list(self.physical_hob_list, hob_list_memory_type)
+            .expect("Unable to relocate the HOB list.");
 
         // Instantiate system table.
         systemtables::init_system_table();
+        let dxe_services_table;
         {
             let mut st = systemtables::SYSTEM_TABLE.lock();
             let st = st.as_mut().expect("System Table not initialized!");
 
             allocator::install_memory_services(st.boot_services_mut());
+            gcd::init_special_regions(&self.hob_list);
+            if let Some(policy) = self.storage.get_config::<LargePagePolicy>() {
+                GCD.set_large_pages_disabled(*policy == LargePagePolicy::Disabled);
+            }
             gcd::init_paging(&self.hob_list);
             events::init_events_support(st.boot_services_mut());
             protocols::init_protocol_support(st.boot_services_mut());
             misc_boot_services::init_misc_boot_services_support(st.boot_services_mut());
             config_tables::init_config_tables_support(st.boot_services_mut());
             runtime::init_runtime_support(st.runtime_services_mut());
+            #[cfg(all(target_os = "uefi", target_arch = "x86_64"))]
+            gcd::init_mtrr_ap_synchronization();
             image::init_image_support(&self.hob_list, st);
             dispatcher::init_dispatcher();
-            dxe_services::init_dxe_services(st);
+            dxe_services_table = dxe_services::init_dxe_services(st);
             driver_services::init_driver_services(st.boot_services_mut());
 
             memory_attributes_protocol::install_memory_attributes_protocol();
+            reset_notification::init_reset_notification_support();
 
             // re-checksum the system tables after above initialization.
             st.checksum_all();
 
             // Install HobList configuration table
-            let (a, b, c, &[d0, d1, d2, d3, d4, d5, d6, d7]) =
-                uuid::Uuid::from_str("7739F24C-93D7-11D4-9A3A-0090273FC14D").expect("Invalid UUID format.").as_fields();
-            let hob_list_guid: efi::Guid = efi::Guid::from_fields(a, b, c, d0, d1, &[d2, d3, d4, d5, d6, d7]);
-
-            config_tables::core_install_configuration_table(
-                hob_list_guid,
-                Box::leak(relocated_c_hob_list).as_mut_ptr() as *mut c_void,
-                st,
-            )
-            .expect("Unable to create configuration table due to invalid table entry.");
+            config_tables::core_install_configuration_table(patina::guids::HOB_LIST, relocated_c_hob_list, st)
+                .expect("Unable to create configuration table due to invalid table entry.");
 
             // Install Memory Type Info configuration table.
             allocator::install_memory_type_info_table(st).expect("Unable to create Memory Type Info Table");
@@ -513,6 +1016,7 @@ impl Core<Alloc> {
             self.storage.set_boot_services(StandardBootServices::new(&*boot_services_ptr));
             self.storage.set_runtime_services(StandardRuntimeServices::new(&*runtime_services_ptr));
         }
+        self.storage.set_dxe_services(StandardDxeServices::new(dxe_services_table));
 
         Ok(())
     }
@@ -528,6 +1032,10 @@ impl Core<Alloc> {
     }
 
     /// Starts the core, dispatching all drivers.
+    ///
+    /// Drivers that fail to load or start do not abort the boot: their outcomes are collected in a [DispatchReport],
+    /// which is passed to the [DispatchReportHandler] service, if any, before handing off to BDS. The error returned
+    /// by the handler is returned from this function.
     pub fn start(mut self) -> Result<()> {
         log::info!("Registering default components");
         self.add_core_components();
@@ -541,12 +1049,102 @@ impl Core<Alloc> {
         self.parse_hobs();
         log::info!("Finished.");
 
+        let boot_mode = self.boot_mode();
+        log::info!("Boot Mode: {boot_mode}");
+        self.storage.add_config(boot_mode);
+        self.skip_components(boot_mode);
+        self.measure_components();
+
         if let Some(extractor) = self.storage.get_service::<dyn SectionExtractor>() {
             log::debug!("Section Extractor service found, registering with FV and Dispatcher.");
             dispatcher::register_section_extractor(extractor.clone());
             fv::register_section_extractor(extractor);
         }
 
+        if let Some(policy) = self.storage.get_config::<DispatchPolicy>() {
+            log::debug!("Dispatch policy found, registering with Dispatcher.");
+            dispatcher::set_dispatch_policy((*policy).clone());
+        }
+
+        if let Some(policy) = self.storage.get_config::<SectionCachePolicy>() {
+            log::debug!(
+                "Section cache policy found, caching up to {:#x} bytes of extracted sections.",
+                policy.capacity
+            );
+            dispatcher::set_section_cache_capacity(policy.capacity);
+        }
+
+        if let Some(policy) = self.storage.get_config::<GuardedDispatchPolicy>() {
+            log::debug!("Guarded dispatch policy found, registering with Dispatcher.");
+            dispatcher::enable_guarded_dispatch(*policy, self.storage.get_service::<dyn DriverFailureStore>());
+        }
+
+        if let Some(policy) = self.storage.get_config::<PanicPolicy>() {
+            log::debug!("Panic policy found, panics will be handled with {:?}.", policy.action);
+            panic_policy::init_panic_policy_support(*policy, self.storage.get_service::<dyn PanicStore>());
+        }
+
+        if let Some(policy) = self.storage.get_config::<EbcImagePolicy>() {
+            image::set_ebc_image_policy(*policy);
+        }
+
+        if let Some(policy) = self.storage.get_config::<LateRuntimeImagePolicy>() {
+            log::debug!("Late runtime image policy found, runtime images loaded after ReadyToBoot: {:?}.", *policy);
+            memory_attributes_table::set_late_runtime_image_policy(*policy);
+        }
+
+        if let Some(policy) = self.storage.get_config::<FreePoisoningPolicy>() {
+            log::debug!(
+                "Free poisoning policy found, pool poisoned: {}, pages poisoned: {}.",
+                policy.pool,
+                policy.pages
+            );
+            allocator::set_free_poisoning_policy(*policy);
+        }
+
+        if let Some(policy) = self.storage.get_config::<MemoryMapCapPolicy>() {
+            log::debug!("Memory map cap policy found, maximum descriptors: {}.", policy.max_descriptors);
+            allocator::set_memory_map_cap_policy(*policy);
+        }
+
+        if let Some(policy) = self.storage.get_config::<StackUsagePolicy>() {
+            log::debug!("Stack usage policy found, image stacks measured: {}.", policy.image_stacks);
+            stack_usage::set_stack_usage_policy(*policy);
+        }
+
+        if let Some(policy) = self.storage.get_config::<MemoryMapSnapshotPolicy>() {
+            log::debug!("Memory map snapshot policy found, snapshot will be taken at ExitBootServices.");
+            memory_map_snapshot::init_memory_map_snapshot_support(*policy);
+        }
+
+        if let Some(policy) = self.storage.get_config::<AllocationAttributionPolicy>() {
+            log::debug!("Allocation attribution policy found, page allocations will be attributed to images.");
+            allocation_attribution_table::init_allocation_attribution_support(*policy);
+        }
+
+        if let Some(policy) = self.storage.get_config::<MemoryMapCheckPolicy>() {
+            log::debug!(
+                "Memory map check policy found, checked at End of DXE: {}, at Ready to Boot: {}.",
+                policy.end_of_dxe,
+                policy.ready_to_boot
+            );
+            memory_map_check::init_memory_map_check_support(*policy);
+        }
+
+        if let Some(policy) = self.storage.get_config::<S3BootScriptPolicy>() {
+            log::debug!("S3 boot script policy found, installing the S3 Save State Protocol.");
+            s3_boot_script::init_s3_boot_script_support(*policy);
+        }
+
+        if let Some(policy) = self.storage.get_config::<BootSnapshotPolicy>() {
+            log::debug!("Boot snapshot policy found, snapshot will be taken at ReadyToBoot.");
+            boot_snapshot::init_boot_snapshot_support(*policy, self.storage.get_service::<dyn BootSnapshotStore>());
+        }
+
+        let conformance_profiles =
+            self.storage.get_config::<ConformanceProfilesPolicy>().map(|policy| (*policy).clone()).unwrap_or_default();
+        conformance_profiles_table::install_conformance_profiles_table(&conformance_profiles);
+
         log::info!("Parsing FVs from FV HOBs");
         fv::parse_hob_fvs(&self.hob_list)?;
         log::info!("Finished.");
@@ -557,12 +1155,29 @@ impl Core<Alloc> {
         self.core_dispatcher()?;
         log::info!("Finished Dispatching Drivers");
 
+        if let Err(err) = self.storage.dispatch_complete() {
+            log::error!("Dispatch complete callback failed: {err:?}");
+        }
+        component_lifecycle::set_exit_boot_services_callbacks(self.storage.take_exit_boot_services_callbacks());
+        component_report::set_produced(self.storage.produced());
+
         self.display_components_not_dispatched();
 
         core_display_missing_arch_protocols();
 
         dispatcher::display_discovered_not_dispatched();
 
+        stack_usage::record_core_stack_usage(StackMilestone::DispatchComplete);
+
+        let report = dispatcher::take_dispatch_report();
+        report.log();
+        boot_snapshot::record_driver_timings(report.records());
+        if let Some(handler) = self.storage.get_service::<dyn DispatchReportHandler>() {
+            handler
+                .handle_dispatch_report(&report)
+                .inspect_err(|err| log::error!("Dispatch report rejected: {err:?}"))?;
+        }
+
         call_bds();
 
         log::info!("Finished");
@@ -570,27 +1185,26 @@ impl Core<Alloc> {
     }
 }
 
-const ARCH_PROTOCOLS: &[(uuid::Uuid, &str)] = &[
-    (uuid::uuid!("a46423e3-4617-49f1-b9ff-d1bfa9115839"), "Security"),
-    (uuid::uuid!("26baccb1-6f42-11d4-bce7-0080c73c8881"), "Cpu"),
-    (uuid::uuid!("26baccb2-6f42-11d4-bce7-0080c73c8881"), "Metronome"),
-    (uuid::uuid!("26baccb3-6f42-11d4-bce7-0080c73c8881"), "Timer"),
-    (uuid::uuid!("665e3ff6-46cc-11d4-9a38-0090273fc14d"), "Bds"),
-    (uuid::uuid!("665e3ff5-46cc-11d4-9a38-0090273fc14d"), "Watchdog"),
-    (uuid::uuid!("b7dfb4e1-052f-449f-87be-9818fc91b733"), "Runtime"),
-    (uuid::uuid!("1e5668e2-8481-11d4-bcf1-0080c73c8881"), "Variable"),
-    (uuid::uuid!("6441f818-6362-4e44-b570-7dba31dd2453"), "Variable Write"),
-    (uuid::uuid!("5053697e-2cbc-4819-90d9-0580deee5754"), "Capsule"),
-    (uuid::uuid!("1da97072-bddc-4b30-99f1-72a0b56fff2a"), "Monotonic Counter"),
-    (uuid::uuid!("27cfac88-46cc-11d4-9a38-0090273fc14d"), "Reset"),
-    (uuid::uuid!("27cfac87-46cc-11d4-9a38-0090273fc14d"), "Real Time Clock"),
+const ARCH_PROTOCOLS: &[(efi::Guid, &str)] = &[
+    (patina::guid!("A46423E3-4617-49F1-B9FF-D1BFA9115839"), "Security"),
+    (patina::guid!("26BACCB1-6F42-11D4-BCE7-0080C73C8881"), "Cpu"),
+    (patina::guid!("26BACCB2-6F42-11D4-BCE7-0080C73C8881"), "Metronome"),
+    (patina::guid!("26BACCB3-6F42-11D4-BCE7-0080C73C8881"), "Timer"),
+    (patina::guid!("665E3FF6-46CC-11D4-9A38-0090273FC14D"), "Bds"),
+    (patina::guid!("665E3FF5-46CC-11D4-9A38-0090273FC14D"), "Watchdog"),
+    (patina::guid!("B7DFB4E1-052F-449F-87BE-9818FC91B733"), "Runtime"),
+    (patina::guid!("1E5668E2-8481-11D4-BCF1-0080C73C8881"), "Variable"),
+    (patina::guid!("6441F818-6362-4E44-B570-7DBA31DD2453"), "Variable Write"),
+    (patina::guid!("5053697E-2CBC-4819-90D9-0580DEEE5754"), "Capsule"),
+    (patina::guid!("1DA97072-BDDC-4B30-99F1-72A0B56FFF2A"), "Monotonic Counter"),
+    (patina::guid!("27CFAC88-46CC-11D4-9A38-0090273FC14D"), "Reset"),
+    (patina::guid!("27CFAC87-46CC-11D4-9A38-0090273FC14D"), "Real Time Clock"),
 ];
 
 fn core_display_missing_arch_protocols() {
-    for (uuid, name) in ARCH_PROTOCOLS {
-        let guid = efi::Guid::from_bytes(&uuid.to_bytes_le());
-        if protocols::PROTOCOL_DB.locate_protocol(guid).is_err() {
-            log::warn!("Missing architectural protocol: {uuid:?}, {name:?}");
+    for (guid, name) in ARCH_PROTOCOLS {
+        if protocols::PROTOCOL_DB.locate_protocol(*guid).is_err() {
+            log::warn!("Missing architectural protocol: {:?}, {name:?}", patina::Guid::from_ref(guid));
         }
     }
 }
@@ -6,7 +6,7 @@
 //!
 //! SPDX-License-Identifier: Apache-2.0
 //!
-use alloc::boxed::Box;
+use alloc::{boxed::Box, string::ToString};
 use patina::test::patina_test;
 use patina::{
     base::{UEFI_PAGE_MASK, UEFI_PAGE_SIZE},
@@ -16,6 +16,7 @@ use patina::{
             AccessType, AllocationOptions, CachingType, MemoryError, MemoryManager, PageAllocation,
             PageAllocationStrategy,
         },
+        memory_protection::{ProtectionExemptions, SpecialRegions, validate_special_region},
     },
     efi_types::EfiMemoryType,
     error::EfiError,
@@ -25,8 +26,10 @@ use patina::{u_assert, u_assert_eq};
 use r_efi::efi;
 
 use crate::{
+    GCD,
     allocator::{core_allocate_pages, core_free_pages},
     dxe_services,
+    gcd::SpecialRegion,
 };
 
 /// Structure for wrapper rust allocator APIs.
@@ -63,7 +66,7 @@ impl MemoryManager for CoreMemoryManager {
         let result =
             core_allocate_pages(alloc_type, options.memory_type().into(), page_count, &mut address, Some(alignment));
 
-        match result {
+        match result.map_err(EfiError::from) {
             Ok(_) => {
                 let allocation = unsafe {
                     PageAllocation::new(address as usize, page_count, &CoreMemoryManager)
@@ -78,7 +81,7 @@ impl MemoryManager for CoreMemoryManager {
 
     unsafe fn free_pages(&self, address: usize, page_count: usize) -> Result<(), MemoryError> {
         let result = core_free_pages(address as efi::PhysicalAddress, page_count);
-        match result {
+        match result.map_err(EfiError::from) {
             Ok(_) => Ok(()),
             Err(EfiError::NotFound) => Err(MemoryError::InvalidAddress),
             Err(_) => Err(MemoryError::InternalError),
@@ -192,6 +195,35 @@ impl MemoryManager for CoreMemoryManager {
     }
 }
 
+/// Registers memory protection special regions with the GCD.
+#[derive(IntoService)]
+#[service(dyn SpecialRegions)]
+pub(crate) struct CoreSpecialRegions;
+
+impl SpecialRegions for CoreSpecialRegions {
+    fn register_special_region(
+        &self,
+        base_address: u64,
+        length: u64,
+        exemptions: ProtectionExemptions,
+        requester: &str,
+        reason: &str,
+    ) -> Result<(), MemoryError> {
+        validate_special_region(base_address, length, exemptions)?;
+        let region = SpecialRegion {
+            base_address,
+            length,
+            exemptions,
+            requester: requester.to_string(),
+            reason: reason.to_string(),
+        };
+        GCD.add_special_region(region).map_err(|err| {
+            log::error!("Failed to apply special region {base_address:#x?} of length {length:#x?}: {err:?}");
+            MemoryError::InternalError
+        })
+    }
+}
+
 fn allow_allocations_for_type(memory_type: EfiMemoryType) -> Result<(), MemoryError> {
     match memory_type {
         EfiMemoryType::ReservedMemoryType
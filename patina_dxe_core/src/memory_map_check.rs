@@ -0,0 +1,509 @@
+//! DXE Core Memory Map Consistency Check
+//!
+//! The core keeps three views of memory: the GCD memory space map, the ranges owned by the allocators, and the EFI
+//! memory map derived from the GCD, from which the Memory Attributes Table (MAT) is derived in turn. The memory map and
+//! the MAT are built on demand, and the MAT is then updated incrementally, so a bug in the conversion or in an update
+//! path makes the views drift apart without any immediate failure.
+//!
+//! When the [MemoryMapCheckPolicy] is provided, the core cross-validates the views at EndOfDxe and at ReadyToBoot,
+//! once the MAT is published, and logs each [MemoryMapMismatch] found:
+//!
+//! - each memory map descriptor is described by GCD memory of a matching type, with matching capabilities;
+//! - each range owned by an allocator is described by memory map descriptors of the memory type of the allocator;
+//! - each MAT descriptor is within a runtime memory map descriptor of its type, and each runtime memory map descriptor
+//!   is described by the MAT;
+//! - the access attributes of each MAT descriptor agree with the active attributes of the GCD.
+//!
+//! Each check walks the complete maps, so the policy is intended for debug builds.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::vec::Vec;
+use core::{ffi::c_void, fmt, ops::Range};
+
+use patina::{base::UEFI_PAGE_SIZE, guids};
+use patina_pi::dxe_services::{GcdMemoryType, MemorySpaceDescriptor};
+use r_efi::efi;
+
+use crate::{
+    GCD,
+    allocator::{get_allocator_memory_ranges, get_memory_map_descriptors},
+    config_tables::memory_attributes_table,
+    events::EVENT_DB,
+    protocol_db::INVALID_HANDLE,
+};
+
+/// The attributes of the MAT, other than [efi::MEMORY_RUNTIME], which all its descriptors have.
+const MAT_ACCESS_ATTRIBUTES: u64 = efi::MEMORY_RO | efi::MEMORY_XP;
+
+/// A configuration struct enabling the memory map consistency check, selecting the points of the boot at which it
+/// runs. The check does not run unless this configuration is provided.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use patina_dxe_core::{Core, MemoryMapCheckPolicy};
+/// # let physical_hob_list = core::ptr::null();
+///
+/// let core = Core::default()
+///    .init_memory(physical_hob_list)
+///    .with_config(MemoryMapCheckPolicy { end_of_dxe: false, ready_to_boot: true })
+///    .start()
+///    .unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryMapCheckPolicy {
+    /// Check the GCD, the allocators and the memory map at EndOfDxe. The MAT is not published yet, so it is not
+    /// checked.
+    pub end_of_dxe: bool,
+    /// Check the GCD, the allocators, the memory map and the MAT each time ReadyToBoot is signaled.
+    pub ready_to_boot: bool,
+}
+
+impl Default for MemoryMapCheckPolicy {
+    fn default() -> Self {
+        Self { end_of_dxe: true, ready_to_boot: true }
+    }
+}
+
+/// A disagreement between the views of memory found by the memory map consistency check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryMapMismatch {
+    /// The memory map descriptor starting at `address` is not entirely described by GCD memory of a matching type.
+    MemoryMapEntryNotInGcd {
+        /// The start of the memory map descriptor.
+        address: u64,
+        /// The type of the memory map descriptor.
+        memory_type: efi::MemoryType,
+    },
+    /// The attributes of the memory map descriptor starting at `address` differ from the capabilities of the GCD
+    /// descriptor starting at `gcd_address`.
+    MemoryMapAttributesMismatch {
+        /// The start of the memory map descriptor.
+        address: u64,
+        /// The attributes of the memory map descriptor, without [efi::MEMORY_RUNTIME].
+        attributes: u64,
+        /// The start of the GCD descriptor.
+        gcd_address: u64,
+        /// The capabilities of the GCD descriptor, without the access attributes and [efi::MEMORY_RUNTIME].
+        capabilities: u64,
+    },
+    /// The range starting at `address` owned by the allocator of `memory_type` is not entirely described by memory map
+    /// descriptors of that type.
+    AllocatorRangeNotInMemoryMap {
+        /// The start of the allocator range.
+        address: u64,
+        /// The memory type of the allocator.
+        memory_type: efi::MemoryType,
+    },
+    /// The MAT descriptor starting at `address` is not within a runtime memory map descriptor of its type.
+    MatEntryNotInMemoryMap {
+        /// The start of the MAT descriptor.
+        address: u64,
+        /// The type of the MAT descriptor.
+        memory_type: efi::MemoryType,
+    },
+    /// The runtime memory map descriptor starting at `address` is not entirely described by the MAT.
+    RuntimeRegionNotInMat {
+        /// The start of the memory map descriptor.
+        address: u64,
+        /// The type of the memory map descriptor.
+        memory_type: efi::MemoryType,
+    },
+    /// The access attributes of the MAT descriptor starting at `address` differ from the active attributes of the GCD
+    /// descriptor starting at `gcd_address`.
+    MatAttributesMismatch {
+        /// The start of the MAT descriptor.
+        address: u64,
+        /// The access attributes of the MAT descriptor.
+        attributes: u64,
+        /// The start of the GCD descriptor.
+        gcd_address: u64,
+        /// The access attributes of the GCD descriptor.
+        gcd_attributes: u64,
+    },
+}
+
+impl fmt::Display for MemoryMapMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::MemoryMapEntryNotInGcd { address, memory_type } => write!(
+                f,
+                "memory map descriptor {address:#x} of type {memory_type:#x} is not described by GCD memory of a matching type"
+            ),
+            Self::MemoryMapAttributesMismatch { address, attributes, gcd_address, capabilities } => write!(
+                f,
+                "memory map descriptor {address:#x} has attributes {attributes:#x}, but GCD descriptor {gcd_address:#x} has capabilities {capabilities:#x}"
+            ),
+            Self::AllocatorRangeNotInMemoryMap { address, memory_type } => write!(
+                f,
+                "range {address:#x} of the allocator of type {memory_type:#x} is not described by memory map descriptors of that type"
+            ),
+            Self::MatEntryNotInMemoryMap { address, memory_type } => write!(
+                f,
+                "MAT descriptor {address:#x} of type {memory_type:#x} is not within a runtime memory map descriptor of that type"
+            ),
+            Self::RuntimeRegionNotInMat { address, memory_type } => write!(
+                f,
+                "runtime memory map descriptor {address:#x} of type {memory_type:#x} is not described by the MAT"
+            ),
+            Self::MatAttributesMismatch { address, attributes, gcd_address, gcd_attributes } => write!(
+                f,
+                "MAT descriptor {address:#x} has access attributes {attributes:#x}, but GCD descriptor {gcd_address:#x} has {gcd_attributes:#x}"
+            ),
+        }
+    }
+}
+
+/// Registers the events running the check at the points of the boot selected by the policy.
+pub fn init_memory_map_check_support(policy: MemoryMapCheckPolicy) {
+    for (enabled, notify, group, name) in [
+        (
+            policy.end_of_dxe,
+            check_at_end_of_dxe as extern "efiapi" fn(efi::Event, *mut c_void),
+            guids::EVENT_GROUP_END_OF_DXE,
+            "End of DXE",
+        ),
+        (policy.ready_to_boot, check_at_ready_to_boot, efi::EVENT_GROUP_READY_TO_BOOT, "Ready to Boot"),
+    ] {
+        if !enabled {
+            continue;
+        }
+        // The memory attributes table support registered its event earlier, so at ReadyToBoot the check runs once the
+        // MAT is published.
+        if let Err(status) =
+            EVENT_DB.create_event(efi::EVT_NOTIFY_SIGNAL, efi::TPL_CALLBACK, Some(notify), None, Some(group))
+        {
+            log::error!("Failed to register an event at {name} to check the memory map consistency! {status:#X?}");
+        }
+    }
+}
+
+extern "efiapi" fn check_at_end_of_dxe(_event: efi::Event, _context: *mut c_void) {
+    log_mismatches("End of DXE", &check_memory_map_consistency(false));
+}
+
+extern "efiapi" fn check_at_ready_to_boot(_event: efi::Event, _context: *mut c_void) {
+    log_mismatches("Ready to Boot", &check_memory_map_consistency(true));
+}
+
+fn log_mismatches(milestone: &str, mismatches: &[MemoryMapMismatch]) {
+    if mismatches.is_empty() {
+        log::info!("Memory map consistency check at {milestone}: the GCD, allocators, memory map and MAT agree.");
+        return;
+    }
+    log::error!("Memory map consistency check at {milestone}: {} mismatches found.", mismatches.len());
+    for mismatch in mismatches {
+        log::error!("  {mismatch}");
+    }
+}
+
+/// Cross-validates the current GCD memory space map, allocator ranges and memory map, and the published MAT if
+/// `check_mat` is set. Returns the mismatches found.
+pub(crate) fn check_memory_map_consistency(check_mat: bool) -> Vec<MemoryMapMismatch> {
+    let allocator_ranges = get_allocator_memory_ranges();
+    let mut gcd_descriptors = Vec::with_capacity(GCD.memory_descriptor_count() + 10);
+    if let Err(err) = GCD.get_memory_descriptors(&mut gcd_descriptors) {
+        log::error!("Failed to get the GCD descriptors for the memory map consistency check: {err:?}");
+        return Vec::new();
+    }
+    let memory_map = match get_memory_map_descriptors(false) {
+        Ok(descriptors) => descriptors,
+        Err(err) => {
+            log::error!("Failed to get the memory map for the memory map consistency check: {err:?}");
+            return Vec::new();
+        }
+    };
+    let mat = check_mat.then(memory_attributes_table::published_descriptors);
+
+    find_mismatches(&gcd_descriptors, &allocator_ranges, &memory_map, mat.as_deref())
+}
+
+fn range(descriptor: &efi::MemoryDescriptor) -> Range<u64> {
+    descriptor.physical_start
+        ..descriptor.physical_start.saturating_add(descriptor.number_of_pages.saturating_mul(UEFI_PAGE_SIZE as u64))
+}
+
+fn gcd_range(descriptor: &MemorySpaceDescriptor) -> Range<u64> {
+    descriptor.base_address..descriptor.base_address.saturating_add(descriptor.length)
+}
+
+fn overlaps(first: &Range<u64>, second: &Range<u64>) -> bool {
+    first.start < second.end && second.start < first.end
+}
+
+fn is_runtime(memory_type: efi::MemoryType) -> bool {
+    matches!(memory_type, efi::RUNTIME_SERVICES_CODE | efi::RUNTIME_SERVICES_DATA)
+}
+
+/// Returns whether `target` is entirely covered by the `ranges`.
+fn is_covered(target: &Range<u64>, ranges: impl Iterator<Item = Range<u64>>) -> bool {
+    let mut ranges: Vec<Range<u64>> = ranges.filter(|range| overlaps(range, target)).collect();
+    ranges.sort_by_key(|range| range.start);
+
+    let mut covered = target.start;
+    for range in ranges {
+        if range.start > covered {
+            break;
+        }
+        covered = covered.max(range.end);
+    }
+    covered >= target.end
+}
+
+/// Returns whether GCD memory may be reported in the memory map with `memory_type`.
+///
+/// Allocated memory is reported with the memory type of its allocator, so any memory type other than the ones derived
+/// from the GCD memory type alone may describe any memory present in the GCD.
+fn is_gcd_type_compatible(memory_type: efi::MemoryType, descriptor: &MemorySpaceDescriptor) -> bool {
+    match memory_type {
+        efi::CONVENTIONAL_MEMORY => {
+            descriptor.memory_type == GcdMemoryType::SystemMemory && descriptor.image_handle == INVALID_HANDLE
+        }
+        efi::MEMORY_MAPPED_IO | efi::MEMORY_MAPPED_IO_PORT_SPACE => {
+            descriptor.memory_type == GcdMemoryType::MemoryMappedIo
+        }
+        efi::PERSISTENT_MEMORY => descriptor.memory_type == GcdMemoryType::Persistent,
+        efi::UNACCEPTED_MEMORY_TYPE => descriptor.memory_type == GcdMemoryType::Unaccepted,
+        _ => descriptor.memory_type != GcdMemoryType::NonExistent,
+    }
+}
+
+/// Cross-validates the views of memory. `mat` is `None` if the MAT is not published yet.
+fn find_mismatches(
+    gcd: &[MemorySpaceDescriptor],
+    allocator_ranges: &[(efi::MemoryType, Range<efi::PhysicalAddress>)],
+    memory_map: &[efi::MemoryDescriptor],
+    mat: Option<&[efi::MemoryDescriptor]>,
+) -> Vec<MemoryMapMismatch> {
+    let mut mismatches = Vec::new();
+
+    for entry in memory_map {
+        let entry_range = range(entry);
+        let compatible = gcd.iter().filter(|descriptor| is_gcd_type_compatible(entry.r#type, descriptor));
+        if !is_covered(&entry_range, compatible.map(gcd_range)) {
+            mismatches.push(MemoryMapMismatch::MemoryMapEntryNotInGcd {
+                address: entry.physical_start,
+                memory_type: entry.r#type,
+            });
+        }
+
+        // The memory map reports the capabilities of the memory, and whether it is runtime memory.
+        let attributes = entry.attribute & !efi::MEMORY_RUNTIME;
+        for descriptor in gcd.iter().filter(|descriptor| overlaps(&gcd_range(descriptor), &entry_range)) {
+            let capabilities = descriptor.capabilities & !(efi::MEMORY_ACCESS_MASK | efi::MEMORY_RUNTIME);
+            if descriptor.memory_type != GcdMemoryType::NonExistent && capabilities != attributes {
+                mismatches.push(MemoryMapMismatch::MemoryMapAttributesMismatch {
+                    address: entry.physical_start,
+                    attributes,
+                    gcd_address: descriptor.base_address,
+                    capabilities,
+                });
+            }
+        }
+    }
+
+    for (memory_type, allocator_range) in allocator_ranges {
+        let entries = memory_map.iter().filter(|entry| entry.r#type == *memory_type);
+        if !allocator_range.is_empty() && !is_covered(allocator_range, entries.map(range)) {
+            mismatches.push(MemoryMapMismatch::AllocatorRangeNotInMemoryMap {
+                address: allocator_range.start,
+                memory_type: *memory_type,
+            });
+        }
+    }
+
+    let Some(mat) = mat else {
+        return mismatches;
+    };
+
+    for entry in mat {
+        let entry_range = range(entry);
+        let within_runtime_region = memory_map.iter().any(|descriptor| {
+            is_runtime(descriptor.r#type)
+                && descriptor.r#type == entry.r#type
+                && range(descriptor).start <= entry_range.start
+                && entry_range.end <= range(descriptor).end
+        });
+        if !within_runtime_region {
+            mismatches.push(MemoryMapMismatch::MatEntryNotInMemoryMap {
+                address: entry.physical_start,
+                memory_type: entry.r#type,
+            });
+        }
+
+        // The MAT defaults the access attributes of runtime memory without any, so only the GCD descriptors with
+        // access attributes must agree with it.
+        let attributes = entry.attribute & MAT_ACCESS_ATTRIBUTES;
+        for descriptor in gcd.iter().filter(|descriptor| overlaps(&gcd_range(descriptor), &entry_range)) {
+            let gcd_attributes = descriptor.attributes & MAT_ACCESS_ATTRIBUTES;
+            if gcd_attributes != 0 && gcd_attributes != attributes {
+                mismatches.push(MemoryMapMismatch::MatAttributesMismatch {
+                    address: entry.physical_start,
+                    attributes,
+                    gcd_address: descriptor.base_address,
+                    gcd_attributes,
+                });
+            }
+        }
+    }
+
+    for region in memory_map.iter().filter(|descriptor| is_runtime(descriptor.r#type)) {
+        let entries = mat.iter().filter(|entry| entry.r#type == region.r#type);
+        if !is_covered(&range(region), entries.map(range)) {
+            mismatches.push(MemoryMapMismatch::RuntimeRegionNotInMat {
+                address: region.physical_start,
+                memory_type: region.r#type,
+            });
+        }
+    }
+
+    mismatches
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+    use super::*;
+
+    use crate::{allocator::core_allocate_pages, systemtables::init_system_table, test_support};
+
+    const CODE: u32 = efi::RUNTIME_SERVICES_CODE;
+    const DATA: u32 = efi::RUNTIME_SERVICES_DATA;
+    const PAGE: u64 = UEFI_PAGE_SIZE as u64;
+
+    fn with_locked_state<F: Fn() + std::panic::RefUnwindSafe>(f: F) {
+        test_support::with_global_lock(|| {
+            memory_attributes_table::reset_memory_attributes_table();
+            unsafe {
+                test_support::init_test_gcd(None);
+                test_support::reset_allocators();
+                init_system_table();
+            }
+            f();
+        })
+        .unwrap();
+    }
+
+    fn gcd_descriptor(
+        memory_type: GcdMemoryType,
+        base_address: u64,
+        pages: u64,
+        capabilities: u64,
+        attributes: u64,
+        image_handle: efi::Handle,
+    ) -> MemorySpaceDescriptor {
+        MemorySpaceDescriptor {
+            memory_type,
+            base_address,
+            length: pages * PAGE,
+            capabilities,
+            attributes,
+            image_handle,
+            device_handle: INVALID_HANDLE,
+        }
+    }
+
+    fn descriptor(r#type: u32, physical_start: u64, number_of_pages: u64, attribute: u64) -> efi::MemoryDescriptor {
+        efi::MemoryDescriptor { r#type, physical_start, virtual_start: 0, number_of_pages, attribute }
+    }
+
+    /// A GCD with free system memory, runtime code and data allocated to an allocator, and MMIO.
+    fn gcd() -> Vec<MemorySpaceDescriptor> {
+        let capabilities = efi::MEMORY_WB | efi::MEMORY_ACCESS_MASK | efi::MEMORY_RUNTIME;
+        let allocator = 1 as efi::Handle;
+        vec![
+            gcd_descriptor(GcdMemoryType::NonExistent, 0, 1, 0, 0, INVALID_HANDLE),
+            gcd_descriptor(GcdMemoryType::SystemMemory, PAGE, 3, capabilities, efi::MEMORY_WB, INVALID_HANDLE),
+            gcd_descriptor(GcdMemoryType::SystemMemory, 4 * PAGE, 2, capabilities, efi::MEMORY_RO, allocator),
+            gcd_descriptor(GcdMemoryType::SystemMemory, 6 * PAGE, 2, capabilities, efi::MEMORY_XP, allocator),
+            gcd_descriptor(GcdMemoryType::MemoryMappedIo, 8 * PAGE, 1, efi::MEMORY_UC, efi::MEMORY_UC, INVALID_HANDLE),
+            gcd_descriptor(GcdMemoryType::NonExistent, 9 * PAGE, 0x1000, 0, 0, INVALID_HANDLE),
+        ]
+    }
+
+    fn memory_map() -> Vec<efi::MemoryDescriptor> {
+        vec![
+            descriptor(efi::CONVENTIONAL_MEMORY, PAGE, 3, efi::MEMORY_WB),
+            descriptor(CODE, 4 * PAGE, 2, efi::MEMORY_WB | efi::MEMORY_RUNTIME),
+            descriptor(DATA, 6 * PAGE, 2, efi::MEMORY_WB | efi::MEMORY_RUNTIME),
+            descriptor(efi::MEMORY_MAPPED_IO, 8 * PAGE, 1, efi::MEMORY_UC),
+        ]
+    }
+
+    fn mat() -> Vec<efi::MemoryDescriptor> {
+        vec![
+            descriptor(CODE, 4 * PAGE, 2, efi::MEMORY_RO | efi::MEMORY_RUNTIME),
+            descriptor(DATA, 6 * PAGE, 1, efi::MEMORY_XP | efi::MEMORY_RUNTIME),
+            descriptor(DATA, 7 * PAGE, 1, efi::MEMORY_XP | efi::MEMORY_RUNTIME),
+        ]
+    }
+
+    #[test]
+    fn consistent_views_should_have_no_mismatches() {
+        let allocator_ranges = [(CODE, 4 * PAGE..5 * PAGE), (DATA, 6 * PAGE..8 * PAGE)];
+        assert_eq!(find_mismatches(&gcd(), &allocator_ranges, &memory_map(), Some(&mat())), []);
+        assert_eq!(find_mismatches(&gcd(), &allocator_ranges, &memory_map(), None), []);
+    }
+
+    #[test]
+    fn inconsistent_views_should_report_mismatches() {
+        let mut memory_map = memory_map();
+        // free memory reported past the end of the system memory, and a capability the GCD does not have.
+        memory_map[0].number_of_pages = 4;
+        memory_map[2].attribute |= efi::MEMORY_UC;
+        let allocator_ranges = [(CODE, 4 * PAGE..7 * PAGE)];
+        let mut mat = mat();
+        // the MAT lost the last runtime data page, and lets the runtime code be written.
+        mat.pop();
+        mat[0].attribute = efi::MEMORY_XP | efi::MEMORY_RUNTIME;
+        mat.push(descriptor(CODE, 6 * PAGE, 1, efi::MEMORY_RO | efi::MEMORY_RUNTIME));
+
+        assert_eq!(
+            find_mismatches(&gcd(), &allocator_ranges, &memory_map, Some(&mat)),
+            [
+                MemoryMapMismatch::MemoryMapEntryNotInGcd { address: PAGE, memory_type: efi::CONVENTIONAL_MEMORY },
+                MemoryMapMismatch::MemoryMapAttributesMismatch {
+                    address: 6 * PAGE,
+                    attributes: efi::MEMORY_WB | efi::MEMORY_UC,
+                    gcd_address: 6 * PAGE,
+                    capabilities: efi::MEMORY_WB,
+                },
+                MemoryMapMismatch::AllocatorRangeNotInMemoryMap { address: 4 * PAGE, memory_type: CODE },
+                MemoryMapMismatch::MatAttributesMismatch {
+                    address: 4 * PAGE,
+                    attributes: efi::MEMORY_XP,
+                    gcd_address: 4 * PAGE,
+                    gcd_attributes: efi::MEMORY_RO,
+                },
+                MemoryMapMismatch::MatEntryNotInMemoryMap { address: 6 * PAGE, memory_type: CODE },
+                MemoryMapMismatch::MatAttributesMismatch {
+                    address: 6 * PAGE,
+                    attributes: efi::MEMORY_RO,
+                    gcd_address: 6 * PAGE,
+                    gcd_attributes: efi::MEMORY_XP,
+                },
+                MemoryMapMismatch::RuntimeRegionNotInMat { address: 6 * PAGE, memory_type: DATA },
+            ]
+        );
+    }
+
+    #[test]
+    fn core_memory_views_should_be_consistent() {
+        with_locked_state(|| {
+            for memory_type in [efi::BOOT_SERVICES_DATA, efi::RUNTIME_SERVICES_CODE, efi::RUNTIME_SERVICES_DATA] {
+                let mut address = 0;
+                core_allocate_pages(efi::ALLOCATE_ANY_PAGES, memory_type, 4, &mut address, None).unwrap();
+            }
+            assert_eq!(check_memory_map_consistency(false), []);
+
+            memory_attributes_table::core_install_memory_attributes_table();
+            assert_eq!(check_memory_map_consistency(true), []);
+        });
+    }
+}
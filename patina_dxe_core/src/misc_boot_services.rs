@@ -11,13 +11,27 @@ use core::{
     slice::from_raw_parts,
     sync::atomic::{AtomicBool, AtomicPtr, Ordering},
 };
-use patina::guids;
+use patina::{
+    guids,
+    performance::{
+        delay,
+        timer::{ArchPerfTimer, PerfTimer},
+    },
+};
 use patina_internal_cpu::interrupts;
 use patina_pi::{protocols, status_code};
 use r_efi::efi;
 
 use crate::{
-    GCD, allocator::terminate_memory_map, events::EVENT_DB, protocols::PROTOCOL_DB, systemtables::SYSTEM_TABLE,
+    GCD,
+    allocator::terminate_memory_map,
+    component_lifecycle,
+    config_tables::{allocation_attribution_table, memory_map_snapshot},
+    events::EVENT_DB,
+    protocols::PROTOCOL_DB,
+    reset_notification,
+    stack_usage::{self, StackMilestone},
+    systemtables::SYSTEM_TABLE,
 };
 
 static METRONOME_ARCH_PTR: AtomicPtr<protocols::metronome::Protocol> = AtomicPtr::new(core::ptr::null_mut());
@@ -50,6 +64,16 @@ extern "efiapi" fn calculate_crc32(data: *mut c_void, data_size: usize, crc_32:
 // Induces a fine-grained stall. Stalls execution on the processor for at least the requested number of microseconds.
 // Execution of the processor is not yielded for the duration of the stall.
 extern "efiapi" fn stall(microseconds: usize) -> efi::Status {
+    stall_with_timer(&ArchPerfTimer, microseconds)
+}
+
+// The stall is calibrated against the performance counter of `timer`. The Metronome Architectural protocol is only
+// used when the frequency of the counter is unknown.
+fn stall_with_timer(timer: &impl PerfTimer, microseconds: usize) -> efi::Status {
+    if delay::delay_us(timer, microseconds as u64).is_ok() {
+        return efi::Status::SUCCESS;
+    }
+
     let metronome_ptr = METRONOME_ARCH_PTR.load(Ordering::SeqCst);
     if let Some(metronome) = unsafe { metronome_ptr.as_mut() } {
         let ticks_100ns: u128 = (microseconds as u128) * 10;
@@ -82,7 +106,7 @@ extern "efiapi" fn stall(microseconds: usize) -> efi::Status {
 //
 // The watchdog timer is only used during boot services. On successful completion of
 // EFI_BOOT_SERVICES.ExitBootServices() the watchdog timer is disabled.
-extern "efiapi" fn set_watchdog_timer(
+pub extern "efiapi" fn set_watchdog_timer(
     timeout: usize,
     _watchdog_code: u64,
     _data_size: usize,
@@ -143,6 +167,9 @@ pub extern "efiapi" fn exit_boot_services(_handle: efi::Handle, map_key: usize)
         // Signal the event group before exit boot services
         EVENT_DB.signal_group(efi::EVENT_GROUP_BEFORE_EXIT_BOOT_SERVICES);
 
+        // Tear down the components in the reverse order they were dispatched
+        component_lifecycle::exit_boot_services();
+
         EXIT_BOOT_SERVICES_CALLED.store(true, Ordering::SeqCst);
     }
 
@@ -171,6 +198,11 @@ pub extern "efiapi" fn exit_boot_services(_handle: efi::Handle, map_key: usize)
         }
     }
 
+    // Record the final memory map for post-boot validation, if enabled by the platform
+    memory_map_snapshot::capture_memory_map_snapshot();
+    allocation_attribution_table::capture_allocation_attribution_table();
+    stack_usage::record_core_stack_usage(StackMilestone::ExitBootServices);
+
     // Signal Exit Boot Services
     EVENT_DB.signal_group(efi::EVENT_GROUP_EXIT_BOOT_SERVICES);
 
@@ -193,6 +225,9 @@ pub extern "efiapi" fn exit_boot_services(_handle: efi::Handle, map_key: usize)
     // Disable CPU interrupts
     interrupts::disable_interrupts();
 
+    // Put the ResetSystem() of the platform back, the reset notifications are not available at runtime
+    reset_notification::finalize_reset_notification_support();
+
     // Clear non-runtime services from the EFI System Table
     SYSTEM_TABLE
         .lock()
@@ -409,33 +444,55 @@ mod tests {
             initialize_boot_services(unsafe { get_static_boot_services(st.boot_services_mut()) });
             init_misc_boot_services_support(st.boot_services_mut());
 
-            // Test case 1: Normal stall duration - should return NOT_READY (no metronome available in test)
-            let status = (st.boot_services_mut().stall)(10000);
-            if status == efi::Status::NOT_READY {
-                log::debug!("Stall function correctly returned NOT_READY (no metronome protocol)");
-            } else {
-                log::warn!("Stall function returned unexpected status: {status:#x?}");
-            }
-
-            // Test case 2: Zero microseconds stall - should return NOT_READY
+            // Test case 1: Zero microseconds stall - should return SUCCESS with a performance counter, NOT_READY
+            // without one (no metronome available in test)
             let status = (st.boot_services_mut().stall)(0);
-            if status == efi::Status::NOT_READY {
-                log::debug!("Zero stall correctly returned NOT_READY");
+            if status == efi::Status::SUCCESS || status == efi::Status::NOT_READY {
+                log::debug!("Zero stall correctly returned {status:#x?}");
             } else {
                 log::warn!("Zero stall returned unexpected status: {status:#x?}");
             }
-
-            // Test case 3: Maximum stall duration - should return NOT_READY
-            let status = (st.boot_services_mut().stall)(usize::MAX);
-            if status == efi::Status::NOT_READY {
-                log::debug!("Maximum stall correctly returned NOT_READY");
-            } else {
-                log::warn!("Maximum stall returned unexpected status: {status:#x?}");
-            }
         })
         .expect("Unexpected Error in test_misc_stall");
     }
 
+    #[test]
+    fn test_misc_stall_is_calibrated_against_the_counter() {
+        use patina::performance::timer::MockPerfTimer;
+        use std::sync::{
+            Arc,
+            atomic::{AtomicU64, Ordering},
+        };
+
+        // A 10MHz counter advancing by 3 ticks on every read.
+        let count = Arc::new(AtomicU64::new(0));
+        let counter = count.clone();
+        let mut timer = MockPerfTimer::new();
+        timer.expect_perf_frequency().return_const(10_000_000_u64);
+        timer.expect_cpu_count().returning(move || counter.fetch_add(3, Ordering::SeqCst));
+
+        assert_eq!(stall_with_timer(&timer, 250), efi::Status::SUCCESS);
+
+        // 250us is 2500 ticks, the last read is within one step of them.
+        let last_read = count.load(Ordering::SeqCst) - 3;
+        assert!((2_500..2_503).contains(&last_read), "stalled for {last_read} ticks");
+    }
+
+    #[test]
+    fn test_misc_stall_without_a_counter_frequency() {
+        test_support::with_global_lock(|| {
+            use patina::performance::timer::MockPerfTimer;
+
+            let mut timer = MockPerfTimer::new();
+            timer.expect_perf_frequency().return_const(0_u64);
+            timer.expect_cpu_count().never();
+
+            // Without a metronome either, the stall is not possible.
+            assert_eq!(stall_with_timer(&timer, 10), efi::Status::NOT_READY);
+        })
+        .expect("Unexpected Error in test_misc_stall_without_a_counter_frequency");
+    }
+
     #[test]
     fn test_misc_exit_boot_services() {
         test_support::with_global_lock(|| {
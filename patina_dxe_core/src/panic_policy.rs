@@ -0,0 +1,488 @@
+//! DXE Core Panic Policy
+//!
+//! Applies the [PanicPolicy] of the platform when the DXE core panics. The `#[panic_handler]` of the platform binary
+//! calls [panic_handler], which:
+//! - Reports the panic as an unrecovered `EFI_SW_EC_ILLEGAL_SOFTWARE_STATE` error status code carrying a
+//!   [PanicRecord] with the location and digests of the panic, if the Status Code Runtime Protocol is installed.
+//! - Saves the panic with the [PanicStore] service, if the platform provides one, so that it can be inspected on the
+//!   next boot.
+//! - Halts, resets or breaks into the debugger, as selected by the [PanicAction] of the policy. Resets are limited to
+//!   `max_consecutive_resets` consecutive boots ending with a panic, so that a panic hit on every boot does not reset
+//!   the system forever. The count is cleared once a boot reaches Ready to Boot.
+//!
+//! The panic may happen with any core lock held, so the handler only uses state captured beforehand and never waits
+//! on a lock or allocates.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use core::{
+    ffi::c_void,
+    fmt::{self, Write},
+    mem,
+    panic::{Location, PanicInfo},
+    ptr,
+    sync::atomic::{AtomicBool, AtomicPtr, Ordering},
+};
+
+use patina::{
+    component::service::Service,
+    guids::{DXE_CORE, PANIC_RECORD},
+};
+use patina_pi::{
+    protocols::{reset_arch, status_code},
+    status_code::{EFI_ERROR_CODE, EFI_ERROR_UNRECOVERED, EFI_SOFTWARE_DXE_CORE, EFI_SW_EC_ILLEGAL_SOFTWARE_STATE},
+};
+use r_efi::efi;
+
+use crate::{events::EVENT_DB, protocols::PROTOCOL_DB, systemtables::SYSTEM_TABLE, tpl_lock::TplMutex};
+
+/// The action taken once a panic is reported and saved.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PanicAction {
+    /// Dead-loop, so the state of the system can be inspected with a hardware debugger.
+    #[default]
+    Halt,
+    /// Warm reset the system, unless `max_consecutive_resets` consecutive boots ended with a panic.
+    Reset,
+    /// Break into the debugger if it is enabled, and halt once it resumes. Halts if the debugger is not enabled.
+    Debugger,
+}
+
+/// A configuration struct selecting how the core handles a panic.
+///
+/// The platform must call [panic_handler] from its `#[panic_handler]` for the policy to apply.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use patina_dxe_core::{Core, PanicAction, PanicPolicy};
+/// # let physical_hob_list = core::ptr::null();
+///
+/// let policy = PanicPolicy { action: PanicAction::Reset, ..Default::default() };
+/// let core = Core::default()
+///    .init_memory(physical_hob_list)
+///    .with_config(policy)
+///    .start()
+///    .unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PanicPolicy {
+    /// The action taken once the panic is reported.
+    pub action: PanicAction,
+    /// The number of consecutive boots that may end with a panic reset before the system halts instead. Only enforced
+    /// if a [PanicStore] service is registered, as the count must persist across resets. Zero never halts.
+    pub max_consecutive_resets: u32,
+}
+
+impl Default for PanicPolicy {
+    fn default() -> Self {
+        Self { action: PanicAction::Halt, max_consecutive_resets: 3 }
+    }
+}
+
+/// A persistent store for the panics of the core, e.g. a persistent error log.
+///
+/// The log is saved from the panic handler, which may run with any core lock held and at any TPL, so the store must not
+/// allocate or use boot services, e.g. it may write a scratch register or a memory region preserved across warm resets.
+pub trait PanicStore {
+    /// Returns the log saved during the previous boots, or an empty log if there is none.
+    fn load(&self) -> PanicLog;
+    /// Saves `log`, replacing the previous one.
+    fn save(&self, log: &PanicLog) -> patina::error::Result<()>;
+}
+
+/// The panics of the core, persisted across boots by a [PanicStore].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PanicLog {
+    /// The number of consecutive boots that ended with a panic.
+    pub consecutive_panics: u32,
+    /// The last panic of the core.
+    pub last_panic: Option<PanicRecord>,
+}
+
+/// Identifies a panic without its (unbounded) file name and message, which are replaced with FNV-1a digests.
+///
+/// This is the data of the [PANIC_RECORD] status code reported for the panic.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PanicRecord {
+    /// The line of the panic location, or zero if unknown.
+    pub line: u32,
+    /// The column of the panic location, or zero if unknown.
+    pub column: u32,
+    /// The digest of the file name of the panic location, or zero if unknown.
+    pub file_digest: u32,
+    /// The digest of the panic message.
+    pub message_digest: u32,
+}
+
+impl PanicRecord {
+    /// Creates the record of a panic at `location` with `message`.
+    pub fn new(location: Option<&Location>, message: impl fmt::Display) -> Self {
+        let mut message_digest = Fnv1a::default();
+        // Fnv1a never fails to write.
+        let _ = write!(message_digest, "{message}");
+        match location {
+            Some(location) => Self {
+                line: location.line(),
+                column: location.column(),
+                file_digest: Fnv1a::digest(location.file()),
+                message_digest: message_digest.0,
+            },
+            None => Self { message_digest: message_digest.0, ..Default::default() },
+        }
+    }
+}
+
+/// A 32-bit FNV-1a hasher, used to digest panic messages without allocating.
+struct Fnv1a(u32);
+
+impl Fnv1a {
+    const OFFSET_BASIS: u32 = 0x811C_9DC5;
+    const PRIME: u32 = 0x0100_0193;
+
+    fn digest(s: &str) -> u32 {
+        let mut hasher = Self::default();
+        // Fnv1a never fails to write.
+        let _ = hasher.write_str(s);
+        hasher.0
+    }
+}
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(Self::OFFSET_BASIS)
+    }
+}
+
+impl Write for Fnv1a {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0 = s.bytes().fold(self.0, |hash, byte| (hash ^ byte as u32).wrapping_mul(Self::PRIME));
+        Ok(())
+    }
+}
+
+/// The data of the status code reported for a panic.
+#[repr(C)]
+struct PanicStatusCodeData {
+    header: status_code::EfiStatusCodeData,
+    record: PanicRecord,
+}
+
+struct PanicState {
+    policy: PanicPolicy,
+    store: Option<Service<dyn PanicStore>>,
+    log: PanicLog,
+}
+
+unsafe impl Send for PanicState {}
+
+// The lock is taken at TPL_HIGH_LEVEL, so that the panic handler can try to take it at any TPL.
+static PANIC_STATE: TplMutex<Option<PanicState>> = TplMutex::new(efi::TPL_HIGH_LEVEL, None, "Panic Policy");
+
+static STATUS_CODE_PTR: AtomicPtr<status_code::Protocol> = AtomicPtr::new(ptr::null_mut());
+
+/// Set once the Reset Architectural Protocol is installed, to the runtime services table it updated.
+static RUNTIME_SERVICES_PTR: AtomicPtr<efi::RuntimeServices> = AtomicPtr::new(ptr::null_mut());
+
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+/// Enables the panic policy, loading the panics of the previous boots from `store`.
+pub fn init_panic_policy_support(policy: PanicPolicy, store: Option<Service<dyn PanicStore>>) {
+    let log = store.as_ref().map(|store| store.load()).unwrap_or_default();
+    if let Some(record) = log.last_panic
+        && log.consecutive_panics != 0
+    {
+        log::error!("The previous boot ended with a panic ({} consecutive boots): {record:x?}", log.consecutive_panics);
+    }
+    *PANIC_STATE.lock() = Some(PanicState { policy, store, log });
+
+    for (protocol, notify) in [
+        (status_code::PROTOCOL_GUID, status_code_available as extern "efiapi" fn(efi::Event, *mut c_void)),
+        (reset_arch::PROTOCOL_GUID, reset_arch_available),
+    ] {
+        let event = EVENT_DB
+            .create_event(efi::EVT_NOTIFY_SIGNAL, efi::TPL_CALLBACK, Some(notify), None, None)
+            .expect("Failed to create panic policy protocol notify event.");
+        PROTOCOL_DB
+            .register_protocol_notify(protocol, event)
+            .expect("Failed to register panic policy protocol notify.");
+    }
+
+    if let Err(status) = EVENT_DB.create_event(
+        efi::EVT_NOTIFY_SIGNAL,
+        efi::TPL_CALLBACK,
+        Some(ready_to_boot),
+        None,
+        Some(efi::EVENT_GROUP_READY_TO_BOOT),
+    ) {
+        log::error!("Failed to register an event at Ready to Boot to clear the consecutive panic count! {status:#X?}");
+    }
+}
+
+// Caches the Status Code Runtime Protocol interface for the panic handler.
+extern "efiapi" fn status_code_available(event: efi::Event, _context: *mut c_void) {
+    if let Ok(status_code_ptr) = PROTOCOL_DB.locate_protocol(status_code::PROTOCOL_GUID) {
+        STATUS_CODE_PTR.store(status_code_ptr as *mut status_code::Protocol, Ordering::SeqCst);
+        let _ = EVENT_DB.close_event(event);
+    }
+}
+
+// Caches the runtime services table, whose ResetSystem() can be used once the Reset Architectural Protocol is
+// installed.
+extern "efiapi" fn reset_arch_available(event: efi::Event, _context: *mut c_void) {
+    if PROTOCOL_DB.locate_protocol(reset_arch::PROTOCOL_GUID).is_ok()
+        && let Some(system_table) = SYSTEM_TABLE.lock().as_ref()
+    {
+        let runtime_services = system_table.runtime_services() as *const efi::RuntimeServices;
+        RUNTIME_SERVICES_PTR.store(runtime_services as *mut efi::RuntimeServices, Ordering::SeqCst);
+        let _ = EVENT_DB.close_event(event);
+    }
+}
+
+extern "efiapi" fn ready_to_boot(event: efi::Event, _context: *mut c_void) {
+    clear_consecutive_panics();
+    let _ = EVENT_DB.close_event(event);
+}
+
+// Clears the consecutive panic count once a boot makes it to Ready to Boot.
+fn clear_consecutive_panics() {
+    let (store, log) = {
+        let mut state = PANIC_STATE.lock();
+        let Some(state) = state.as_mut() else {
+            return;
+        };
+        if state.log.consecutive_panics == 0 {
+            return;
+        }
+        state.log.consecutive_panics = 0;
+        (state.store.clone(), state.log)
+    };
+    save(store, &log);
+}
+
+fn save(store: Option<Service<dyn PanicStore>>, log: &PanicLog) {
+    if let Some(store) = store
+        && let Err(err) = store.save(log)
+    {
+        log::error!("Failed to save the panic log: {err:?}");
+    }
+}
+
+// Records the panic in the log, returning the action to take for it.
+fn record_panic(record: PanicRecord) -> PanicAction {
+    let (policy, store, log) = {
+        // The state is only locked briefly outside of the panic handler, so failing to lock it means the core panicked
+        // while it was locked.
+        let Some(mut state) = PANIC_STATE.try_lock() else {
+            return PanicAction::Halt;
+        };
+        let Some(state) = state.as_mut() else {
+            return PanicAction::Halt;
+        };
+        state.log.consecutive_panics = state.log.consecutive_panics.saturating_add(1);
+        state.log.last_panic = Some(record);
+        (state.policy, state.store.clone(), state.log)
+    };
+    let guarded = store.is_some();
+    save(store, &log);
+    action_for(&policy, &log, guarded)
+}
+
+// Returns the action to take for the last panic of `log`, halting rather than resetting once too many consecutive
+// boots ended with a panic.
+fn action_for(policy: &PanicPolicy, log: &PanicLog, guarded: bool) -> PanicAction {
+    match policy.action {
+        PanicAction::Reset
+            if guarded
+                && policy.max_consecutive_resets != 0
+                && log.consecutive_panics > policy.max_consecutive_resets =>
+        {
+            log::error!(
+                "{} consecutive boots ended with a panic, halting rather than resetting.",
+                log.consecutive_panics
+            );
+            PanicAction::Halt
+        }
+        action => action,
+    }
+}
+
+fn report_status_code(record: &PanicRecord) {
+    // Safety: the pointer is either null or the interface of the Status Code Runtime Protocol.
+    let Some(status_code) = (unsafe { STATUS_CODE_PTR.load(Ordering::SeqCst).as_ref() }) else {
+        return;
+    };
+    let data = PanicStatusCodeData {
+        header: status_code::EfiStatusCodeData {
+            header_size: mem::size_of::<status_code::EfiStatusCodeData>() as u16,
+            size: mem::size_of::<PanicRecord>() as u16,
+            r#type: PANIC_RECORD,
+        },
+        record: *record,
+    };
+    (status_code.report_status_code)(
+        EFI_ERROR_CODE | EFI_ERROR_UNRECOVERED,
+        EFI_SOFTWARE_DXE_CORE | EFI_SW_EC_ILLEGAL_SOFTWARE_STATE,
+        0,
+        &DXE_CORE,
+        &data.header,
+    );
+}
+
+fn reset_system() {
+    // Safety: the pointer is either null or the runtime services table of the system table.
+    match unsafe { RUNTIME_SERVICES_PTR.load(Ordering::SeqCst).as_ref() } {
+        Some(runtime_services) => {
+            (runtime_services.reset_system)(efi::RESET_WARM, efi::Status::ABORTED, 0, ptr::null_mut())
+        }
+        None => log::error!("The Reset Architectural Protocol is not installed, halting rather than resetting."),
+    }
+}
+
+fn halt() -> ! {
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+/// Applies the [PanicPolicy] of the platform to a panic of the core. Meant to be called from the `#[panic_handler]` of
+/// the platform binary, after it has logged the panic (and e.g. dumped a stack trace).
+///
+/// Halts if no policy is configured, or if the core panics again while the panic is handled.
+///
+/// ## Example
+///
+/// ```rust,ignore
+/// #[panic_handler]
+/// fn panic(info: &core::panic::PanicInfo) -> ! {
+///     log::error!("{info}");
+///     patina_dxe_core::panic_handler(info)
+/// }
+/// ```
+pub fn panic_handler(info: &PanicInfo) -> ! {
+    if PANICKING.swap(true, Ordering::SeqCst) {
+        // Handling the panic panicked, e.g. in the panic store.
+        halt();
+    }
+
+    let record = PanicRecord::new(info.location(), info.message());
+    report_status_code(&record);
+    match record_panic(record) {
+        PanicAction::Halt => (),
+        PanicAction::Reset => reset_system(),
+        PanicAction::Debugger if patina_debugger::enabled() => patina_debugger::breakpoint(),
+        PanicAction::Debugger => log::error!("The debugger is not enabled, halting rather than breaking in."),
+    }
+    halt()
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use alloc::{boxed::Box, vec::Vec};
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::test_support;
+
+    #[derive(Default)]
+    struct MockStore {
+        log: Mutex<PanicLog>,
+        saves: Mutex<Vec<PanicLog>>,
+    }
+
+    impl PanicStore for &'static MockStore {
+        fn load(&self) -> PanicLog {
+            *self.log.lock().unwrap()
+        }
+
+        fn save(&self, log: &PanicLog) -> patina::error::Result<()> {
+            *self.log.lock().unwrap() = *log;
+            self.saves.lock().unwrap().push(*log);
+            Ok(())
+        }
+    }
+
+    fn with_state<F: Fn(&'static MockStore) + std::panic::RefUnwindSafe>(policy: PanicPolicy, log: PanicLog, f: F) {
+        test_support::with_global_lock(|| {
+            let store: &'static MockStore =
+                Box::leak(Box::new(MockStore { log: Mutex::new(log), ..Default::default() }));
+            let service: Service<dyn PanicStore> = Service::mock(Box::new(store));
+            *PANIC_STATE.lock() = Some(PanicState { policy, store: Some(service), log: store.load() });
+            f(store);
+            *PANIC_STATE.lock() = None;
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_panic_record_digests_location_and_message() {
+        let location = Location::caller();
+        let record = PanicRecord::new(Some(location), "out of resources");
+        assert_eq!(record.line, location.line());
+        assert_eq!(record.column, location.column());
+        assert_eq!(record.file_digest, Fnv1a::digest(location.file()));
+        assert_eq!(record.message_digest, Fnv1a::digest("out of resources"));
+
+        // FNV-1a test vectors.
+        assert_eq!(Fnv1a::digest(""), 0x811C_9DC5);
+        assert_eq!(Fnv1a::digest("a"), 0xE40C_292C);
+        assert_eq!(Fnv1a::digest("foobar"), 0xBF9C_F968);
+
+        // The message is digested as formatted.
+        assert_eq!(PanicRecord::new(None, format_args!("foo{}", "bar")).message_digest, Fnv1a::digest("foobar"));
+        assert_eq!(PanicRecord::new(None, "foobar"), PanicRecord { message_digest: 0xBF9C_F968, ..Default::default() });
+    }
+
+    #[test]
+    fn test_panic_is_saved_and_count_cleared_at_ready_to_boot() {
+        let policy = PanicPolicy { action: PanicAction::Reset, max_consecutive_resets: 3 };
+        let previous = PanicRecord { line: 1, ..Default::default() };
+        with_state(policy, PanicLog { consecutive_panics: 1, last_panic: Some(previous) }, |store| {
+            let record = PanicRecord::new(Some(Location::caller()), "panic");
+            assert_eq!(record_panic(record), PanicAction::Reset);
+            assert_eq!(*store.log.lock().unwrap(), PanicLog { consecutive_panics: 2, last_panic: Some(record) });
+
+            clear_consecutive_panics();
+            assert_eq!(*store.log.lock().unwrap(), PanicLog { consecutive_panics: 0, last_panic: Some(record) });
+
+            // The count is only saved when it changes.
+            clear_consecutive_panics();
+            assert_eq!(store.saves.lock().unwrap().len(), 2);
+        });
+    }
+
+    #[test]
+    fn test_reset_halts_after_max_consecutive_resets() {
+        let policy = PanicPolicy { action: PanicAction::Reset, max_consecutive_resets: 3 };
+        with_state(policy, PanicLog { consecutive_panics: 2, last_panic: None }, |store| {
+            assert_eq!(record_panic(PanicRecord::default()), PanicAction::Reset);
+            assert_eq!(record_panic(PanicRecord::default()), PanicAction::Halt);
+            assert_eq!(store.log.lock().unwrap().consecutive_panics, 4);
+        });
+
+        let log = PanicLog { consecutive_panics: 10, last_panic: None };
+        // Resets are not limited without a store to count them, or with a maximum of zero.
+        assert_eq!(action_for(&policy, &log, false), PanicAction::Reset);
+        let unlimited = PanicPolicy { max_consecutive_resets: 0, ..policy };
+        assert_eq!(action_for(&unlimited, &log, true), PanicAction::Reset);
+        // Other actions are not affected by the count.
+        let debugger = PanicPolicy { action: PanicAction::Debugger, ..policy };
+        assert_eq!(action_for(&debugger, &log, true), PanicAction::Debugger);
+    }
+
+    #[test]
+    fn test_panic_without_policy_halts() {
+        test_support::with_global_lock(|| {
+            assert_eq!(record_panic(PanicRecord::default()), PanicAction::Halt);
+        })
+        .unwrap();
+    }
+}
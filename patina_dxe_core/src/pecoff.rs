@@ -9,6 +9,7 @@
 extern crate alloc;
 
 use alloc::{
+    ffi::CString,
     format,
     string::{String, ToString},
     vec::Vec,
@@ -38,6 +39,9 @@ const TE_IMAGE_BASE_HEADER_FIELD_OFFSET: usize = 16;
 // The size of the standard fields in the PE32Plus header.
 const SIZEOF_STANDARD_FIELDS_64: usize = 24;
 
+/// Machine type of EFI Byte Code (EBC) images, which must be run by an EBC interpreter.
+pub const IMAGE_FILE_MACHINE_EBC: u16 = 0x0EBC;
+
 // Relocation type that does not require any action.
 const IMAGE_REL_BASED_ABSOLUTE: u16 = 0;
 // Relocation type that requires the adjustment be applied to the entire
@@ -55,11 +59,59 @@ pub enum HeaderType {
     Pe,
 }
 
+/// The signature identifying the symbol file of an image, from the CodeView entry of its debug directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugSignature {
+    /// An `RSDS` entry, referencing a PDB 7.0 file by its GUID, as emitted by the MSVC and CLANGPDB toolchains.
+    Pdb70([u8; 16]),
+    /// An `NB10` entry, referencing a PDB 2.0 file by its timestamp. GenFw emits one for the GCC and CLANGDWARF
+    /// toolchains, referencing the ELF image that holds the DWARF debug information.
+    Pdb20(u32),
+}
+
+/// The reference to the symbol file of an image, from the CodeView entry of its debug directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DebugInfo {
+    /// The signature of the symbol file.
+    pub signature: DebugSignature,
+    /// The age of the symbol file, incremented each time it is updated.
+    pub age: u32,
+    /// The path of the symbol file at build time.
+    pub path: CString,
+}
+
+impl DebugInfo {
+    /// Returns the reference to the symbol file from the parsed debug directory, if it has a CodeView entry.
+    fn from_debug_data(debug_data: &goblin::pe::debug::DebugData) -> Option<Self> {
+        let pdb70 = debug_data.codeview_pdb70_debug_info.as_ref().map(|codeview_data| DebugInfo {
+            signature: DebugSignature::Pdb70(codeview_data.signature),
+            age: codeview_data.age,
+            path: DebugInfo::read_path(codeview_data.filename),
+        });
+        pdb70.or_else(|| {
+            debug_data.codeview_pdb20_debug_info.as_ref().map(|codeview_data| DebugInfo {
+                signature: DebugSignature::Pdb20(codeview_data.signature),
+                age: codeview_data.age,
+                path: DebugInfo::read_path(codeview_data.filename),
+            })
+        })
+    }
+
+    /// Parses a bytes buffer containing the null-terminated path of the symbol file.
+    fn read_path(bytes: &[u8]) -> CString {
+        let path_end = bytes.iter().position(|&c| c == b'\0').unwrap_or(bytes.len());
+        // The path ends at the first null, so it has no interior null.
+        CString::new(&bytes[..path_end]).unwrap_or_default()
+    }
+}
+
 /// Type containing information about a PE32 image.
 #[derive(Debug, Default, Clone, PartialEq)]
 pub struct UefiPeInfo {
     /// Type of header (PE32 or TE)
     pub header_type: HeaderType,
+    /// The machine type of the image (IMAGE_FILE_MACHINE_X64 \[0x8664\], etc.).
+    pub machine: u16,
     /// Offset into an image header where the image_base address is located.
     /// NOT the actual image base address.
     pub image_base_header_field_offset: usize,
@@ -77,6 +129,8 @@ pub struct UefiPeInfo {
     pub sections: Vec<goblin::pe::section_table::SectionTable>,
     /// The filename, if present, from debug_data
     pub filename: Option<String>,
+    /// The reference to the symbol file, if present, from debug_data
+    pub debug_info: Option<DebugInfo>,
     /// The relocation directory, if present.
     pub reloc_dir: Option<goblin::pe::data_directories::DataDirectory>,
     /// Whether the NX_COMPAT DLL Characteristic flag is set
@@ -100,6 +154,7 @@ impl UefiPeInfo {
         // Set the simple fields.
         pe.image_base_header_field_offset = TE_IMAGE_BASE_HEADER_FIELD_OFFSET;
         pe.header_type = HeaderType::Te(parsed_te.rva_offset);
+        pe.machine = parsed_te.header.machine;
         pe.entry_point_offset = parsed_te.header.entry_point as usize;
         pe.image_type = parsed_te.header.subsystem as u16;
         pe.section_alignment = 0;
@@ -123,6 +178,7 @@ impl UefiPeInfo {
             if let Some(codeview_data) = &parsed_te.debug_data.codeview_pdb70_debug_info {
                 pe.filename = UefiPeInfo::read_filename(codeview_data.filename)?;
             };
+            pe.debug_info = DebugInfo::from_debug_data(&parsed_te.debug_data);
 
             Ok(pe)
         } else {
@@ -140,6 +196,7 @@ impl UefiPeInfo {
 
         // Set the simple fields
         pe.header_type = HeaderType::Pe;
+        pe.machine = parsed_pe.header.coff_header.machine;
         pe.entry_point_offset = optional_header.standard_fields.address_of_entry_point as usize;
         pe.image_type = optional_header.windows_fields.subsystem;
         pe.section_alignment = optional_header.windows_fields.section_alignment;
@@ -165,11 +222,12 @@ impl UefiPeInfo {
 
         // Get the filename if the data exists
         if let Some(debug_data) = parsed_pe.debug_data {
-            if let Some(codeview_data) = debug_data.codeview_pdb70_debug_info {
+            if let Some(codeview_data) = &debug_data.codeview_pdb70_debug_info {
                 pe.filename = UefiPeInfo::read_filename(codeview_data.filename)?;
-            } else if let Some(codeview_data) = debug_data.codeview_pdb20_debug_info {
+            } else if let Some(codeview_data) = &debug_data.codeview_pdb20_debug_info {
                 pe.filename = UefiPeInfo::read_filename(codeview_data.filename)?;
             }
+            pe.debug_info = DebugInfo::from_debug_data(&debug_data);
         }
         Ok(pe)
     }
@@ -511,6 +569,7 @@ mod tests {
         assert_eq!(image_info.image_type, 11);
         assert_eq!(image_info.section_alignment, 0x0);
         assert_eq!(image_info.filename, Some(String::from("RustTerseImageTestDxe.efi")));
+        assert_eq!(image_info.machine, goblin::pe::header::COFF_MACHINE_X86_64);
         assert_eq!(image_info.size_of_image, 0x5ef8);
         assert_eq!(image_info.entry_point_offset, 0x10a8);
     }
@@ -523,6 +582,7 @@ mod tests {
         assert_eq!(image_info.image_type, 0x0B);
         assert_eq!(image_info.section_alignment, 0x1000);
         assert_eq!(image_info.filename, Some(String::from("RustFfiTestDxe.efi")));
+        assert_eq!(image_info.machine, goblin::pe::header::COFF_MACHINE_X86_64);
         assert_eq!(image_info.size_of_image, 0x14000);
         assert_eq!(image_info.entry_point_offset, 0x11B8);
     }
@@ -537,6 +597,20 @@ mod tests {
         // Although the file name is "DisplayEngine512BFileAlignment.efi", the file
         // name inside the debug data is "DisplayEngine.pdb"
         assert_eq!(image_info.filename, Some(String::from("DisplayEngine.efi")));
+        let debug_info = image_info.debug_info.unwrap();
+        assert_eq!(
+            debug_info.signature,
+            DebugSignature::Pdb70([
+                0xB6, 0xD4, 0x8F, 0x5E, 0xE5, 0xDE, 0xC9, 0x4D, 0x9C, 0x75, 0x20, 0xFF, 0x03, 0x14, 0xA4, 0x54
+            ])
+        );
+        assert_eq!(debug_info.age, 7);
+        assert_eq!(
+            debug_info.path.to_str(),
+            Ok(
+                "C:\\src\\mu_tiano_platforms\\Build\\QemuQ35Pkg\\DEBUG_VS2022\\X64\\MsGraphicsPkg\\DisplayEngineDxe\\DisplayEngineDxe\\DEBUG\\DisplayEngine.pdb"
+            )
+        );
         assert_eq!(image_info.size_of_image, 0x19000);
         assert_eq!(image_info.entry_point_offset, 0x11EC);
     }
@@ -614,6 +688,7 @@ mod tests {
 
         //debug information is not included when loading an image in the present implementation, so filename will not be present.
         image_info.filename = None;
+        image_info.debug_info = None;
         assert_eq!(image_info, loaded_image_info);
     }
 
@@ -17,13 +17,17 @@ use tpl_lock::TplMutex;
 
 use crate::{
     allocator::core_allocate_pool,
-    driver_services::{core_connect_controller, core_disconnect_controller},
+    core_context::ContextStatic,
+    driver_services::{
+        core_disconnect_controller, core_reconnect_controller, invalidate_supported_cache,
+        invalidate_supported_cache_for_usage,
+    },
     events::{EVENT_DB, signal_event},
     protocol_db::{DXE_CORE_HANDLE, SpinLockedProtocolDb},
     tpl_lock,
 };
 
-pub static PROTOCOL_DB: SpinLockedProtocolDb = SpinLockedProtocolDb::new();
+pub static PROTOCOL_DB: ContextStatic<SpinLockedProtocolDb> = ContextStatic::new(|context| &context.protocol_db);
 
 pub fn core_install_protocol_interface(
     handle: Option<efi::Handle>,
@@ -32,6 +36,7 @@ pub fn core_install_protocol_interface(
 ) -> Result<efi::Handle, EfiError> {
     log::info!("InstallProtocolInterface: {:?} @ {:#x?}", guid_fmt!(protocol), interface);
     let (handle, notifies) = PROTOCOL_DB.install_protocol_interface(handle, protocol, interface)?;
+    invalidate_supported_cache(handle);
 
     let mut closed_events = Vec::new();
 
@@ -151,12 +156,14 @@ pub fn core_uninstall_protocol_interface(
 
     if usage_close_status.is_err() || unclosed_usages {
         unsafe {
-            let _result = core_connect_controller(handle, Vec::new(), None, true);
+            let _result = core_reconnect_controller(handle);
         }
         return Err(EfiError::AccessDenied);
     }
 
-    PROTOCOL_DB.uninstall_protocol_interface(handle, protocol, interface)
+    PROTOCOL_DB.uninstall_protocol_interface(handle, protocol, interface)?;
+    invalidate_supported_cache(handle);
+    Ok(())
 }
 
 extern "efiapi" fn uninstall_protocol_interface(
@@ -236,7 +243,7 @@ extern "efiapi" fn reinstall_protocol_interface(
     // Connect controller so agents that were forced to release old_interface can now consume new_interface. Error
     // status is ignored.
     unsafe {
-        let _ = core_connect_controller(handle, Vec::new(), None, true);
+        let _ = core_reconnect_controller(handle);
     }
 
     efi::Status::SUCCESS
@@ -409,7 +416,15 @@ extern "efiapi" fn open_protocol(
         }
         Err(EfiError::AlreadyStarted) => (),
         Err(err) => return err.into(),
-        Ok(_) => (),
+        Ok(_) => {
+            // Opening a protocol by driver changes which drivers can manage the handle.
+            if attributes
+                & (efi::OPEN_PROTOCOL_BY_DRIVER | efi::OPEN_PROTOCOL_EXCLUSIVE | efi::OPEN_PROTOCOL_BY_CHILD_CONTROLLER)
+                != 0
+            {
+                invalidate_supported_cache_for_usage(handle, agent_handle);
+            }
+        }
     };
 
     let desired_interface = match PROTOCOL_DB.get_interface_for_handle(handle, protocol) {
@@ -457,7 +472,10 @@ extern "efiapi" fn close_protocol(
         None,
     ) {
         Err(err) => err.into(),
-        Ok(_) => efi::Status::SUCCESS,
+        Ok(_) => {
+            invalidate_supported_cache_for_usage(handle, Some(agent_handle));
+            efi::Status::SUCCESS
+        }
     }
 }
 
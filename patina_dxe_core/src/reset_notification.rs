@@ -0,0 +1,289 @@
+//! DXE Core Reset Notification Protocol
+//!
+//! Produces the Reset Notification Protocol, so that drivers can register functions to be called before the platform
+//! is reset (e.g. to flush caches or logs) rather than relying on platform specific hooks.
+//!
+//! The ResetSystem() runtime service is produced by the platform, which installs the Reset Architectural Protocol
+//! once it has updated the runtime services table. The core then replaces ResetSystem() with a function calling the
+//! registered functions in registration order before calling the ResetSystem() of the platform. A function calling
+//! ResetSystem() itself resets the platform without calling the remaining functions.
+//!
+//! The core and the boot services drivers are not available at runtime, so the ResetSystem() of the platform is put
+//! back in the runtime services table at ExitBootServices(), and the registered functions are not called on resets
+//! after ExitBootServices().
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::{
+    ffi::c_void,
+    ptr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use patina::{
+    error::EfiError,
+    uefi_protocol::reset_notification::{self, ResetSystem},
+};
+use patina_pi::protocols::reset_arch;
+use r_efi::efi;
+use spin::Mutex;
+
+use crate::{events::EVENT_DB, protocols::PROTOCOL_DB, systemtables::SYSTEM_TABLE};
+
+// Spin locks rather than TPL locks, as ResetSystem() may be called at any TPL. The list of functions is replaced
+// rather than updated, so that ResetSystem() calls a snapshot of it.
+static RESET_NOTIFIES: Mutex<Option<Arc<Vec<ResetSystem>>>> = Mutex::new(None);
+static PLATFORM_RESET_SYSTEM: Mutex<Option<ResetSystem>> = Mutex::new(None);
+
+static RESETTING: AtomicBool = AtomicBool::new(false);
+
+extern "efiapi" fn register_reset_notify(
+    _this: *mut reset_notification::Protocol,
+    reset_function: Option<ResetSystem>,
+) -> efi::Status {
+    match register(reset_function) {
+        Ok(()) => efi::Status::SUCCESS,
+        Err(err) => err.into(),
+    }
+}
+
+extern "efiapi" fn unregister_reset_notify(
+    _this: *mut reset_notification::Protocol,
+    reset_function: Option<ResetSystem>,
+) -> efi::Status {
+    match unregister(reset_function) {
+        Ok(()) => efi::Status::SUCCESS,
+        Err(err) => err.into(),
+    }
+}
+
+fn register(reset_function: Option<ResetSystem>) -> Result<(), EfiError> {
+    let reset_function = reset_function.ok_or(EfiError::InvalidParameter)?;
+    let mut notifies = RESET_NOTIFIES.lock();
+    let current = notifies.as_deref().map_or(&[][..], Vec::as_slice);
+    if current.iter().any(|notify| ptr::fn_addr_eq(*notify, reset_function)) {
+        return Err(EfiError::AlreadyStarted);
+    }
+    let mut updated = Vec::new();
+    updated.try_reserve(current.len() + 1).map_err(|_| EfiError::OutOfResources)?;
+    updated.extend_from_slice(current);
+    updated.push(reset_function);
+    *notifies = Some(Arc::new(updated));
+    Ok(())
+}
+
+fn unregister(reset_function: Option<ResetSystem>) -> Result<(), EfiError> {
+    let reset_function = reset_function.ok_or(EfiError::InvalidParameter)?;
+    let mut notifies = RESET_NOTIFIES.lock();
+    let current = notifies.as_deref().map_or(&[][..], Vec::as_slice);
+    let index =
+        current.iter().position(|notify| ptr::fn_addr_eq(*notify, reset_function)).ok_or(EfiError::InvalidParameter)?;
+    let mut updated = current.to_vec();
+    updated.remove(index);
+    *notifies = Some(Arc::new(updated));
+    Ok(())
+}
+
+// Replaces the ResetSystem() of the platform in the runtime services table during boot services.
+extern "efiapi" fn reset_system(
+    reset_type: efi::ResetType,
+    reset_status: efi::Status,
+    data_size: usize,
+    reset_data: *mut c_void,
+) {
+    if !RESETTING.swap(true, Ordering::SeqCst) {
+        log::info!("ResetSystem({reset_type}, {reset_status:x?}) called, notifying the registered functions.");
+        // The functions of a snapshot of the list are called, as they may unregister themselves. Taking the snapshot
+        // does not allocate, as the allocator may be locked by the caller.
+        let notifies = match RESET_NOTIFIES.try_lock() {
+            Some(notifies) => notifies.clone(),
+            None => {
+                log::warn!("The reset notification functions are being updated, waiting for the update to complete.");
+                RESET_NOTIFIES.lock().clone()
+            }
+        };
+        for notify in notifies.iter().flat_map(|notifies| notifies.iter()) {
+            notify(reset_type, reset_status, data_size, reset_data);
+        }
+    }
+
+    match *PLATFORM_RESET_SYSTEM.lock() {
+        Some(platform_reset_system) => platform_reset_system(reset_type, reset_status, data_size, reset_data),
+        None => log::error!("ResetSystem() called before the Reset Architectural Protocol was installed."),
+    }
+}
+
+// Hooks ResetSystem() once the platform has updated the runtime services table.
+extern "efiapi" fn reset_arch_available(event: efi::Event, _context: *mut c_void) {
+    if PROTOCOL_DB.locate_protocol(reset_arch::PROTOCOL_GUID).is_err() {
+        return;
+    }
+    let mut system_table = SYSTEM_TABLE.lock();
+    let Some(system_table) = system_table.as_mut() else {
+        return;
+    };
+
+    let runtime_services = system_table.runtime_services_mut();
+    *PLATFORM_RESET_SYSTEM.lock() = Some(runtime_services.reset_system);
+    runtime_services.reset_system = reset_system;
+    system_table.checksum_all();
+
+    if let Err(status) = EVENT_DB.close_event(event) {
+        log::warn!("Could not close event for reset_arch_available due to error {status:?}");
+    }
+}
+
+/// Installs the Reset Notification Protocol, and hooks ResetSystem() once the Reset Architectural Protocol is
+/// installed.
+pub(crate) fn init_reset_notification_support() {
+    let protocol = Box::leak(Box::new(reset_notification::Protocol { register_reset_notify, unregister_reset_notify }));
+    if let Err(err) = PROTOCOL_DB.install_protocol_interface(
+        None,
+        reset_notification::PROTOCOL_GUID,
+        protocol as *mut reset_notification::Protocol as *mut c_void,
+    ) {
+        log::error!("Failed to install the Reset Notification Protocol: {err:?}");
+        return;
+    }
+
+    let event = EVENT_DB
+        .create_event(efi::EVT_NOTIFY_SIGNAL, efi::TPL_CALLBACK, Some(reset_arch_available), None, None)
+        .expect("Failed to create reset arch available callback.");
+    PROTOCOL_DB
+        .register_protocol_notify(reset_arch::PROTOCOL_GUID, event)
+        .expect("Failed to register protocol notify on reset arch protocol.");
+}
+
+/// Puts the ResetSystem() of the platform back in the runtime services table at ExitBootServices(), as the registered
+/// functions are not available at runtime.
+pub(crate) fn finalize_reset_notification_support() {
+    let Some(platform_reset_system) = *PLATFORM_RESET_SYSTEM.lock() else {
+        return;
+    };
+    let mut system_table = SYSTEM_TABLE.lock();
+    let Some(system_table) = system_table.as_mut() else {
+        return;
+    };
+
+    let runtime_services = system_table.runtime_services_mut();
+    if !ptr::fn_addr_eq(runtime_services.reset_system, reset_system as ResetSystem) {
+        log::warn!(
+            "ResetSystem() was replaced after the Reset Architectural Protocol was installed, leaving it as is."
+        );
+        return;
+    }
+    runtime_services.reset_system = platform_reset_system;
+    system_table.checksum_all();
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use crate::{systemtables::init_system_table, test_support};
+    use std::sync::Mutex as StdMutex;
+
+    static CALLS: StdMutex<Vec<&'static str>> = StdMutex::new(Vec::new());
+
+    extern "efiapi" fn first(_: efi::ResetType, _: efi::Status, _: usize, _: *mut c_void) {
+        CALLS.lock().unwrap().push("first");
+    }
+
+    extern "efiapi" fn second(_: efi::ResetType, _: efi::Status, _: usize, _: *mut c_void) {
+        CALLS.lock().unwrap().push("second");
+    }
+
+    extern "efiapi" fn unregistering(_: efi::ResetType, _: efi::Status, _: usize, _: *mut c_void) {
+        CALLS.lock().unwrap().push("unregistering");
+        unregister(Some(unregistering)).unwrap();
+    }
+
+    extern "efiapi" fn platform(_: efi::ResetType, _: efi::Status, _: usize, _: *mut c_void) {
+        CALLS.lock().unwrap().push("platform");
+    }
+
+    fn with_reset_state<F: Fn() + std::panic::RefUnwindSafe>(f: F) {
+        test_support::with_global_lock(|| {
+            *RESET_NOTIFIES.lock() = None;
+            *PLATFORM_RESET_SYSTEM.lock() = None;
+            RESETTING.store(false, Ordering::SeqCst);
+            CALLS.lock().unwrap().clear();
+            f();
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn register_should_reject_null_and_duplicate_functions() {
+        with_reset_state(|| {
+            assert_eq!(register(None), Err(EfiError::InvalidParameter));
+            assert_eq!(register(Some(first)), Ok(()));
+            assert_eq!(register(Some(first)), Err(EfiError::AlreadyStarted));
+
+            assert_eq!(unregister(None), Err(EfiError::InvalidParameter));
+            assert_eq!(unregister(Some(second)), Err(EfiError::InvalidParameter));
+            assert_eq!(unregister(Some(first)), Ok(()));
+            assert!(RESET_NOTIFIES.lock().as_ref().is_some_and(|notifies| notifies.is_empty()));
+        });
+    }
+
+    #[test]
+    fn reset_should_notify_in_registration_order_then_reset() {
+        with_reset_state(|| {
+            *PLATFORM_RESET_SYSTEM.lock() = Some(platform);
+            register(Some(second)).unwrap();
+            register(Some(first)).unwrap();
+
+            reset_system(efi::RESET_COLD, efi::Status::SUCCESS, 0, ptr::null_mut());
+            assert_eq!(*CALLS.lock().unwrap(), ["second", "first", "platform"]);
+
+            // A reset from a notified function goes straight to the platform.
+            CALLS.lock().unwrap().clear();
+            reset_system(efi::RESET_COLD, efi::Status::SUCCESS, 0, ptr::null_mut());
+            assert_eq!(*CALLS.lock().unwrap(), ["platform"]);
+        });
+    }
+
+    #[test]
+    fn reset_should_notify_every_function_when_one_unregisters_itself() {
+        with_reset_state(|| {
+            *PLATFORM_RESET_SYSTEM.lock() = Some(platform);
+            register(Some(unregistering)).unwrap();
+            register(Some(first)).unwrap();
+
+            reset_system(efi::RESET_WARM, efi::Status::SUCCESS, 0, ptr::null_mut());
+            assert_eq!(*CALLS.lock().unwrap(), ["unregistering", "first", "platform"]);
+            let notifies = RESET_NOTIFIES.lock().clone().unwrap();
+            assert_eq!(notifies.len(), 1);
+            assert!(ptr::fn_addr_eq(notifies[0], first as ResetSystem));
+        });
+    }
+
+    #[test]
+    fn reset_system_should_be_restored_at_exit_boot_services() {
+        with_reset_state(|| {
+            unsafe {
+                test_support::init_test_gcd(None);
+                test_support::reset_allocators();
+                init_system_table();
+            }
+            {
+                let mut system_table = SYSTEM_TABLE.lock();
+                let system_table = system_table.as_mut().unwrap();
+                *PLATFORM_RESET_SYSTEM.lock() = Some(platform);
+                system_table.runtime_services_mut().reset_system = reset_system;
+            }
+
+            finalize_reset_notification_support();
+            let system_table = SYSTEM_TABLE.lock();
+            let reset = system_table.as_ref().unwrap().runtime_services().reset_system;
+            assert!(ptr::fn_addr_eq(reset, platform as ResetSystem));
+        });
+    }
+}
@@ -0,0 +1,485 @@
+//! DXE Core S3 Boot Script
+//!
+//! On an S3 resume, the DXE phase does not run: the resume phase restores the chipset and devices by replaying the
+//! operations recorded in the boot script table during the previous boot, then jumps to the OS waking vector. When the
+//! [S3BootScriptPolicy] is provided, the core produces the S3 Save State Protocol, with which drivers record these
+//! operations.
+//!
+//! The table is allocated once, in ACPI NVS memory below 4GB, so that it is preserved by the OS and reachable by a
+//! 32-bit resume phase, and it does not move as operations are recorded. Its layout, little-endian and without padding,
+//! is:
+//!
+//! - a header: the `PBS3` signature (`u32`), the version of the layout (`u16`), a reserved `u16`, and the length of
+//!   the table (`u32`) including the header and the terminating entry;
+//! - the entries, in the order they were recorded, each with its opcode (`u16`) and its length (`u32`) including this
+//!   header, followed by its operands:
+//!   - the write opcodes: the width (`u32`), the PCI segment (`u16`, 0 unless `PCI_CONFIG2_WRITE`), the address
+//!     (`u64`), the count (`u64`) and the data, `count` values of the width;
+//!   - `STALL`: the duration in microseconds (`u64`);
+//!   - `DISPATCH` and `DISPATCH_2`: the entry point (`u64`) and the context (`u64`, 0 for `DISPATCH`);
+//! - a terminating entry with the [S3_BOOT_SCRIPT_TERMINATE_OPCODE] opcode.
+//!
+//! The IO, MMIO and PCI configuration writes, stalls and dispatches are supported. The other opcodes, and the
+//! `Insert()`, `Label()` and `Compare()` functions of the protocol, return `EFI_UNSUPPORTED`.
+//!
+//! At EndOfDxe, the table is locked: later writes return `EFI_WRITE_PROTECTED`, and its pages are made read-only, so
+//! that the code running after EndOfDxe, e.g. third party option ROMs, cannot alter the operations replayed before the
+//! OS resumes. The address of the table is returned by [s3_boot_script_table], for the platform to hand it to its
+//! resume phase.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::{boxed::Box, vec::Vec};
+use core::{ffi::c_void, mem::size_of, ptr, slice};
+
+use patina::{base::UEFI_PAGE_SIZE, error::EfiError, guids};
+use patina_pi::protocols::s3_save_state::{self, BootScriptPosition, BootScriptWidth};
+use r_efi::efi;
+
+use crate::{
+    allocator::core_allocate_pages,
+    dxe_services::{core_get_memory_space_descriptor, core_set_memory_space_attributes},
+    events::EVENT_DB,
+    protocols::PROTOCOL_DB,
+    tpl_lock::TplMutex,
+};
+
+/// The signature of the boot script table, `PBS3`.
+pub const S3_BOOT_SCRIPT_SIGNATURE: u32 = u32::from_le_bytes(*b"PBS3");
+/// The version of the layout of the boot script table.
+pub const S3_BOOT_SCRIPT_VERSION: u16 = 1;
+/// The opcode of the entry terminating the boot script table.
+pub const S3_BOOT_SCRIPT_TERMINATE_OPCODE: u16 = 0xFF;
+
+const TABLE_HEADER_SIZE: usize = size_of::<u32>() + 2 * size_of::<u16>() + size_of::<u32>();
+const TABLE_LENGTH_OFFSET: usize = TABLE_HEADER_SIZE - size_of::<u32>();
+const ENTRY_HEADER_SIZE: usize = size_of::<u16>() + size_of::<u32>();
+const WRITE_OPERANDS_SIZE: usize = size_of::<u32>() + size_of::<u16>() + 2 * size_of::<u64>();
+
+/// A configuration struct enabling the S3 Save State Protocol, with the size of the boot script table. The protocol is
+/// not produced unless this configuration is provided.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use patina_dxe_core::{Core, S3BootScriptPolicy};
+/// # let physical_hob_list = core::ptr::null();
+///
+/// let core = Core::default()
+///    .init_memory(physical_hob_list)
+///    .with_config(S3BootScriptPolicy { pages: 32 })
+///    .start()
+///    .unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct S3BootScriptPolicy {
+    /// The number of pages of the boot script table. Writes exceeding it return `EFI_OUT_OF_RESOURCES`.
+    pub pages: usize,
+}
+
+impl Default for S3BootScriptPolicy {
+    fn default() -> Self {
+        Self { pages: 16 }
+    }
+}
+
+/// The boot script table, and whether it is locked.
+struct BootScript {
+    table: &'static mut [u8],
+    length: usize,
+    locked: bool,
+}
+
+impl BootScript {
+    /// Initializes an empty boot script table in `table`.
+    fn new(table: &'static mut [u8]) -> Self {
+        let mut boot_script = Self { table, length: TABLE_HEADER_SIZE, locked: false };
+        boot_script.table[..4].copy_from_slice(&S3_BOOT_SCRIPT_SIGNATURE.to_le_bytes());
+        boot_script.table[4..6].copy_from_slice(&S3_BOOT_SCRIPT_VERSION.to_le_bytes());
+        boot_script.table[6..8].fill(0);
+        boot_script.terminate();
+        boot_script
+    }
+
+    /// Writes the terminating entry after the recorded entries, and the length of the table in its header.
+    fn terminate(&mut self) {
+        let end = self.length;
+        self.table[end..end + 2].copy_from_slice(&S3_BOOT_SCRIPT_TERMINATE_OPCODE.to_le_bytes());
+        self.table[end + 2..end + ENTRY_HEADER_SIZE].copy_from_slice(&(ENTRY_HEADER_SIZE as u32).to_le_bytes());
+        let length = (end + ENTRY_HEADER_SIZE) as u32;
+        self.table[TABLE_LENGTH_OFFSET..TABLE_HEADER_SIZE].copy_from_slice(&length.to_le_bytes());
+    }
+
+    /// Records an entry of `op_code` with `operands` at the end of the table.
+    fn append(&mut self, op_code: u16, operands: &[u8]) -> Result<(), EfiError> {
+        if self.locked {
+            return Err(EfiError::WriteProtected);
+        }
+        let entry_length = ENTRY_HEADER_SIZE + operands.len();
+        if self.length + entry_length + ENTRY_HEADER_SIZE > self.table.len() {
+            return Err(EfiError::OutOfResources);
+        }
+
+        let start = self.length;
+        self.table[start..start + 2].copy_from_slice(&op_code.to_le_bytes());
+        self.table[start + 2..start + ENTRY_HEADER_SIZE].copy_from_slice(&(entry_length as u32).to_le_bytes());
+        self.table[start + ENTRY_HEADER_SIZE..start + entry_length].copy_from_slice(operands);
+        self.length += entry_length;
+        self.terminate();
+        Ok(())
+    }
+
+    /// Returns the recorded entries, with their opcode and operands.
+    #[cfg(test)]
+    fn entries(&self) -> Vec<(u16, &[u8])> {
+        let mut entries = Vec::new();
+        let mut offset = TABLE_HEADER_SIZE;
+        while offset < self.length {
+            let op_code = u16::from_le_bytes([self.table[offset], self.table[offset + 1]]);
+            let length = u32::from_le_bytes(self.table[offset + 2..offset + 6].try_into().unwrap()) as usize;
+            entries.push((op_code, &self.table[offset + ENTRY_HEADER_SIZE..offset + length]));
+            offset += length;
+        }
+        entries
+    }
+}
+
+static BOOT_SCRIPT: TplMutex<Option<BootScript>> = TplMutex::new(efi::TPL_NOTIFY, None, "S3BootScriptLock");
+
+/// Returns the operands of a write of `count` values of `width` from `buffer` to `address` of `segment`.
+///
+/// ## Safety
+///
+/// `buffer` must be valid for reads of `count` values of `width`.
+unsafe fn write_operands(
+    width: BootScriptWidth,
+    segment: u16,
+    address: u64,
+    count: usize,
+    buffer: *const u8,
+) -> Result<Vec<u8>, EfiError> {
+    if width > s3_save_state::BOOT_SCRIPT_WIDTH_FILL_UINT64 || buffer.is_null() {
+        return Err(EfiError::InvalidParameter);
+    }
+    let data_length = count.checked_mul(1 << (width & 0x3)).ok_or(EfiError::InvalidParameter)?;
+
+    let mut operands = Vec::new();
+    operands.try_reserve(WRITE_OPERANDS_SIZE + data_length).map_err(|_| EfiError::OutOfResources)?;
+    operands.extend_from_slice(&width.to_le_bytes());
+    operands.extend_from_slice(&segment.to_le_bytes());
+    operands.extend_from_slice(&address.to_le_bytes());
+    operands.extend_from_slice(&(count as u64).to_le_bytes());
+    // SAFETY: The caller guarantees that `buffer` holds `count` values of `width`.
+    operands.extend_from_slice(unsafe { slice::from_raw_parts(buffer, data_length) });
+    Ok(operands)
+}
+
+/// Returns the operands of a dispatch of `entry_point` with `context`.
+fn dispatch_operands(entry_point: *const c_void, context: *const c_void) -> Result<Vec<u8>, EfiError> {
+    if entry_point.is_null() {
+        return Err(EfiError::InvalidParameter);
+    }
+    let mut operands = Vec::new();
+    operands.extend_from_slice(&(entry_point as u64).to_le_bytes());
+    operands.extend_from_slice(&(context as u64).to_le_bytes());
+    Ok(operands)
+}
+
+fn record(op_code: u16, operands: Result<Vec<u8>, EfiError>) -> efi::Status {
+    let result = operands.and_then(|operands| match BOOT_SCRIPT.lock().as_mut() {
+        Some(boot_script) => boot_script.append(op_code, &operands),
+        None => Err(EfiError::NotReady),
+    });
+    match result {
+        Ok(()) => efi::Status::SUCCESS,
+        Err(err) => err.into(),
+    }
+}
+
+unsafe extern "C" fn write(_this: *const s3_save_state::Protocol, op_code: u16, mut args: ...) -> efi::Status {
+    // The variable arguments of each opcode are defined by the PI specification. Enumerations and `u16` arguments are
+    // promoted to `int` when passed as variable arguments.
+    let operands = match op_code {
+        s3_save_state::BOOT_SCRIPT_IO_WRITE_OPCODE
+        | s3_save_state::BOOT_SCRIPT_MEM_WRITE_OPCODE
+        | s3_save_state::BOOT_SCRIPT_PCI_CONFIG_WRITE_OPCODE => {
+            let width: u32 = unsafe { args.arg() };
+            let address: u64 = unsafe { args.arg() };
+            let count: usize = unsafe { args.arg() };
+            let buffer: *const u8 = unsafe { args.arg() };
+            // SAFETY: The caller passes a buffer of `count` values of `width`, as required by the PI specification.
+            unsafe { write_operands(width, 0, address, count, buffer) }
+        }
+        s3_save_state::BOOT_SCRIPT_PCI_CONFIG2_WRITE_OPCODE => {
+            let width: u32 = unsafe { args.arg() };
+            let segment: u32 = unsafe { args.arg() };
+            let address: u64 = unsafe { args.arg() };
+            let count: usize = unsafe { args.arg() };
+            let buffer: *const u8 = unsafe { args.arg() };
+            // SAFETY: The caller passes a buffer of `count` values of `width`, as required by the PI specification.
+            unsafe { write_operands(width, segment as u16, address, count, buffer) }
+        }
+        s3_save_state::BOOT_SCRIPT_STALL_OPCODE => {
+            let duration: usize = unsafe { args.arg() };
+            Ok((duration as u64).to_le_bytes().to_vec())
+        }
+        s3_save_state::BOOT_SCRIPT_DISPATCH_OPCODE => {
+            let entry_point: *const c_void = unsafe { args.arg() };
+            dispatch_operands(entry_point, ptr::null())
+        }
+        s3_save_state::BOOT_SCRIPT_DISPATCH_2_OPCODE => {
+            let entry_point: *const c_void = unsafe { args.arg() };
+            let context: *const c_void = unsafe { args.arg() };
+            dispatch_operands(entry_point, context)
+        }
+        _ => Err(EfiError::Unsupported),
+    };
+    record(op_code, operands)
+}
+
+unsafe extern "C" fn insert(
+    _this: *const s3_save_state::Protocol,
+    _before_or_after: efi::Boolean,
+    _position: *mut BootScriptPosition,
+    _op_code: u16,
+    _args: ...
+) -> efi::Status {
+    efi::Status::UNSUPPORTED
+}
+
+extern "efiapi" fn label(
+    _this: *const s3_save_state::Protocol,
+    _before_or_after: efi::Boolean,
+    _create_if_not_found: efi::Boolean,
+    _position: *mut BootScriptPosition,
+    _label: *const u8,
+) -> efi::Status {
+    efi::Status::UNSUPPORTED
+}
+
+extern "efiapi" fn compare(
+    _this: *const s3_save_state::Protocol,
+    _position1: BootScriptPosition,
+    _position2: BootScriptPosition,
+    _relative_position: *mut usize,
+) -> efi::Status {
+    efi::Status::UNSUPPORTED
+}
+
+extern "efiapi" fn lock_at_end_of_dxe(event: efi::Event, _context: *mut c_void) {
+    if let Some(boot_script) = BOOT_SCRIPT.lock().as_mut() {
+        boot_script.locked = true;
+        let base_address = boot_script.table.as_ptr() as efi::PhysicalAddress;
+        let length = boot_script.table.len() as u64;
+        let result = core_get_memory_space_descriptor(base_address).and_then(|descriptor| {
+            core_set_memory_space_attributes(base_address, length, descriptor.attributes | efi::MEMORY_RO)
+        });
+        match result {
+            Ok(()) => log::info!("S3 boot script locked, {} bytes recorded.", boot_script.length),
+            Err(err) => log::error!("S3 boot script locked, but could not be made read-only: {err:?}"),
+        }
+    }
+
+    if let Err(status) = EVENT_DB.close_event(event) {
+        log::warn!("Could not close event for lock_at_end_of_dxe due to error {status:?}");
+    }
+}
+
+/// Allocates the boot script table, installs the S3 Save State Protocol, and registers the lock at EndOfDxe.
+pub(crate) fn init_s3_boot_script_support(policy: S3BootScriptPolicy) {
+    let mut address: efi::PhysicalAddress = 0xFFFF_FFFF;
+    if let Err(err) =
+        core_allocate_pages(efi::ALLOCATE_MAX_ADDRESS, efi::ACPI_MEMORY_NVS, policy.pages, &mut address, None)
+    {
+        log::error!("Failed to allocate {} pages for the S3 boot script table: {err:?}", policy.pages);
+        return;
+    }
+    // SAFETY: The pages were just allocated, and are only accessed through the boot script.
+    let table = unsafe { slice::from_raw_parts_mut(address as *mut u8, policy.pages * UEFI_PAGE_SIZE) };
+    *BOOT_SCRIPT.lock() = Some(BootScript::new(table));
+
+    let protocol = Box::leak(Box::new(s3_save_state::Protocol { write, insert, label, compare }));
+    if let Err(err) = PROTOCOL_DB.install_protocol_interface(
+        None,
+        s3_save_state::PROTOCOL_GUID,
+        protocol as *mut s3_save_state::Protocol as *mut c_void,
+    ) {
+        log::error!("Failed to install the S3 Save State Protocol: {err:?}");
+        return;
+    }
+
+    if let Err(status) = EVENT_DB.create_event(
+        efi::EVT_NOTIFY_SIGNAL,
+        efi::TPL_CALLBACK,
+        Some(lock_at_end_of_dxe),
+        None,
+        Some(guids::EVENT_GROUP_END_OF_DXE),
+    ) {
+        log::error!("Failed to register an event at End of DXE to lock the S3 boot script! {status:#X?}");
+    }
+}
+
+/// Returns the address of the boot script table, if the [S3BootScriptPolicy] is provided.
+///
+/// The table does not move once allocated, but it is only complete once locked at EndOfDxe.
+pub fn s3_boot_script_table() -> Option<efi::PhysicalAddress> {
+    BOOT_SCRIPT.lock().as_ref().map(|boot_script| boot_script.table.as_ptr() as efi::PhysicalAddress)
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use crate::test_support;
+    use std::vec;
+
+    fn with_boot_script<F: Fn() + std::panic::RefUnwindSafe>(pages: usize, f: F) {
+        test_support::with_global_lock(|| {
+            let table = vec![0xA5_u8; pages * UEFI_PAGE_SIZE].leak();
+            *BOOT_SCRIPT.lock() = Some(BootScript::new(table));
+            f();
+            *BOOT_SCRIPT.lock() = None;
+        })
+        .unwrap();
+    }
+
+    fn entries() -> Vec<(u16, Vec<u8>)> {
+        let boot_script = BOOT_SCRIPT.lock();
+        let boot_script = boot_script.as_ref().unwrap();
+        boot_script.entries().into_iter().map(|(op_code, operands)| (op_code, operands.to_vec())).collect()
+    }
+
+    #[test]
+    fn empty_table_should_have_header_and_terminator() {
+        let table = vec![0_u8; UEFI_PAGE_SIZE].leak();
+        let boot_script = BootScript::new(table);
+        assert!(boot_script.entries().is_empty());
+        assert_eq!(&boot_script.table[..4], b"PBS3");
+        assert_eq!(&boot_script.table[4..6], &S3_BOOT_SCRIPT_VERSION.to_le_bytes());
+        assert_eq!(&boot_script.table[8..12], &18_u32.to_le_bytes());
+        assert_eq!(&boot_script.table[12..18], &[0xFF, 0x00, 0x06, 0x00, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn write_should_record_writes_stalls_and_dispatches() {
+        with_boot_script(1, || {
+            let data = [0x12_u16, 0x34];
+            let status = unsafe {
+                write(
+                    ptr::null(),
+                    s3_save_state::BOOT_SCRIPT_IO_WRITE_OPCODE,
+                    s3_save_state::BOOT_SCRIPT_WIDTH_UINT16,
+                    0xCF8_u64,
+                    2_usize,
+                    data.as_ptr(),
+                )
+            };
+            assert_eq!(status, efi::Status::SUCCESS);
+
+            let value = 0xDEAD_BEEF_u32;
+            let status = unsafe {
+                write(
+                    ptr::null(),
+                    s3_save_state::BOOT_SCRIPT_PCI_CONFIG2_WRITE_OPCODE,
+                    s3_save_state::BOOT_SCRIPT_WIDTH_UINT32,
+                    1_u32,
+                    0x0010_0004_u64,
+                    1_usize,
+                    &value as *const u32,
+                )
+            };
+            assert_eq!(status, efi::Status::SUCCESS);
+
+            let status = unsafe { write(ptr::null(), s3_save_state::BOOT_SCRIPT_STALL_OPCODE, 100_usize) };
+            assert_eq!(status, efi::Status::SUCCESS);
+
+            let entry_point = 0x1000 as *const c_void;
+            let context = 0x2000 as *const c_void;
+            let status =
+                unsafe { write(ptr::null(), s3_save_state::BOOT_SCRIPT_DISPATCH_2_OPCODE, entry_point, context) };
+            assert_eq!(status, efi::Status::SUCCESS);
+
+            let entries = entries();
+            assert_eq!(entries.len(), 4);
+
+            let mut io_write = vec![1, 0, 0, 0, 0, 0];
+            io_write.extend_from_slice(&0xCF8_u64.to_le_bytes());
+            io_write.extend_from_slice(&2_u64.to_le_bytes());
+            io_write.extend_from_slice(&[0x12, 0x00, 0x34, 0x00]);
+            assert_eq!(entries[0], (s3_save_state::BOOT_SCRIPT_IO_WRITE_OPCODE, io_write));
+
+            let mut pci_write = vec![2, 0, 0, 0, 1, 0];
+            pci_write.extend_from_slice(&0x0010_0004_u64.to_le_bytes());
+            pci_write.extend_from_slice(&1_u64.to_le_bytes());
+            pci_write.extend_from_slice(&0xDEAD_BEEF_u32.to_le_bytes());
+            assert_eq!(entries[1], (s3_save_state::BOOT_SCRIPT_PCI_CONFIG2_WRITE_OPCODE, pci_write));
+
+            assert_eq!(entries[2], (s3_save_state::BOOT_SCRIPT_STALL_OPCODE, 100_u64.to_le_bytes().to_vec()));
+
+            let mut dispatch = 0x1000_u64.to_le_bytes().to_vec();
+            dispatch.extend_from_slice(&0x2000_u64.to_le_bytes());
+            assert_eq!(entries[3], (s3_save_state::BOOT_SCRIPT_DISPATCH_2_OPCODE, dispatch));
+        });
+    }
+
+    #[test]
+    fn write_should_reject_invalid_and_unsupported_operations() {
+        with_boot_script(1, || {
+            let value = 0_u8;
+            let status = unsafe {
+                write(
+                    ptr::null(),
+                    s3_save_state::BOOT_SCRIPT_MEM_WRITE_OPCODE,
+                    12_u32,
+                    0_u64,
+                    1_usize,
+                    &value as *const u8,
+                )
+            };
+            assert_eq!(status, efi::Status::INVALID_PARAMETER);
+
+            let status =
+                unsafe { write(ptr::null(), s3_save_state::BOOT_SCRIPT_DISPATCH_OPCODE, ptr::null::<c_void>()) };
+            assert_eq!(status, efi::Status::INVALID_PARAMETER);
+
+            let status = unsafe { write(ptr::null(), s3_save_state::BOOT_SCRIPT_SMBUS_EXECUTE_OPCODE) };
+            assert_eq!(status, efi::Status::UNSUPPORTED);
+
+            assert!(entries().is_empty());
+        });
+    }
+
+    #[test]
+    fn write_should_fail_once_the_table_is_full_or_locked() {
+        with_boot_script(1, || {
+            let data = vec![0_u8; UEFI_PAGE_SIZE];
+            let status = unsafe {
+                write(
+                    ptr::null(),
+                    s3_save_state::BOOT_SCRIPT_MEM_WRITE_OPCODE,
+                    s3_save_state::BOOT_SCRIPT_WIDTH_UINT8,
+                    0_u64,
+                    data.len(),
+                    data.as_ptr(),
+                )
+            };
+            assert_eq!(status, efi::Status::OUT_OF_RESOURCES);
+
+            assert_eq!(
+                unsafe { write(ptr::null(), s3_save_state::BOOT_SCRIPT_STALL_OPCODE, 1_usize) },
+                efi::Status::SUCCESS
+            );
+            BOOT_SCRIPT.lock().as_mut().unwrap().locked = true;
+            assert_eq!(
+                unsafe { write(ptr::null(), s3_save_state::BOOT_SCRIPT_STALL_OPCODE, 1_usize) },
+                efi::Status::WRITE_PROTECTED
+            );
+            assert_eq!(entries().len(), 1);
+        });
+    }
+}
@@ -0,0 +1,330 @@
+//! DXE Core Stack Usage
+//!
+//! Measures the peak usage of the stack of the core, and optionally of the stacks the image entry points run on, so
+//! that platforms can right-size their stack allocations. The unused part of a stack is filled with a watermark
+//! pattern, and the peak usage is the part of the stack where the pattern was overwritten.
+//!
+//! The core stack is described by the Memory Allocation Stack HOB. It is watermarked below the current stack pointer
+//! when the core is entered, before the GCD is initialized, and its peak usage is recorded at each [StackMilestone].
+//! The image stacks are watermarked when they are allocated if the [StackUsagePolicy] enables it, and their peak
+//! usage is recorded once the entry point of the image returns.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::{string::String, vec::Vec};
+use core::{
+    ffi::c_void,
+    fmt,
+    hint::black_box,
+    ops::Range,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use patina::guids::HOB_MEMORY_ALLOC_STACK;
+use patina_pi::hob::{Hob, PhaseHandoffInformationTable};
+use r_efi::efi;
+
+use crate::tpl_lock::TplMutex;
+
+/// The pattern the unused part of a stack is filled with.
+const WATERMARK: u8 = 0x5a;
+
+/// The part of the core stack left below the stack pointer when it is watermarked, for the frames of the fill itself.
+const FILL_MARGIN: usize = 0x1000;
+
+/// The number of image stacks listed by the `stack` monitor command, by decreasing peak usage.
+const MONITOR_IMAGE_COUNT: usize = 16;
+
+/// A configuration struct selecting which stacks the core measures the peak usage of.
+///
+/// The core stack is always measured, as it is watermarked once.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use patina_dxe_core::{Core, StackUsagePolicy};
+/// # let physical_hob_list = core::ptr::null();
+///
+/// let core = Core::default()
+///    .init_memory(physical_hob_list)
+///    .with_config(StackUsagePolicy { image_stacks: true })
+///    .start()
+///    .unwrap();
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StackUsagePolicy {
+    /// Watermark the stack each image entry point runs on, and record its peak usage once the entry point returns.
+    /// Filling the stacks adds to the time taken to start each image.
+    pub image_stacks: bool,
+}
+
+/// The points of the boot at which the peak usage of the core stack is recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackMilestone {
+    /// The GCD was initialized from the HOB list.
+    GcdInitialized,
+    /// All the drivers were dispatched, before handing off to BDS.
+    DispatchComplete,
+    /// ExitBootServices() terminated the memory map.
+    ExitBootServices,
+}
+
+impl StackMilestone {
+    /// All the milestones, in the order they are reached.
+    pub const ALL: [Self; 3] = [Self::GcdInitialized, Self::DispatchComplete, Self::ExitBootServices];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// The peak usage of a stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackUsage {
+    /// The size of the stack in bytes.
+    pub size: usize,
+    /// The largest number of bytes of the stack used.
+    pub peak: usize,
+}
+
+impl fmt::Display for StackUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let percent = (self.peak * 100).checked_div(self.size).unwrap_or(0);
+        write!(f, "{:#x} of {:#x} bytes ({percent}%)", self.peak, self.size)
+    }
+}
+
+/// The peak usage of the stack of an image entry point.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageStackUsage {
+    /// The file name of the image, if it was loaded from a firmware volume.
+    pub file_name: Option<efi::Guid>,
+    /// The name of the PDB of the image, if any.
+    pub name: Option<String>,
+    /// The peak usage of the stack.
+    pub usage: StackUsage,
+}
+
+// The core stack, with the length of its watermarked part starting at its lowest address.
+static CORE_STACK_START: AtomicUsize = AtomicUsize::new(0);
+static CORE_STACK_SIZE: AtomicUsize = AtomicUsize::new(0);
+static CORE_STACK_WATERMARKED: AtomicUsize = AtomicUsize::new(0);
+
+// The peak usage of the core stack at each milestone, zero if it was not reached.
+static CORE_STACK_PEAKS: [AtomicUsize; StackMilestone::ALL.len()] =
+    [AtomicUsize::new(0), AtomicUsize::new(0), AtomicUsize::new(0)];
+
+static IMAGE_STACKS: AtomicBool = AtomicBool::new(false);
+static IMAGE_STACK_USAGE: TplMutex<Vec<ImageStackUsage>> = TplMutex::new(efi::TPL_NOTIFY, Vec::new(), "StackUsageLock");
+
+/// Returns the address of a local of a new stack frame, below the frame of the caller.
+#[inline(never)]
+fn current_stack_pointer() -> usize {
+    let marker = 0u8;
+    black_box(&marker) as *const u8 as usize
+}
+
+/// Returns the number of bytes at the start of `stack` still holding the watermark.
+fn untouched_len(stack: &[u8]) -> usize {
+    stack.iter().position(|byte| *byte != WATERMARK).unwrap_or(stack.len())
+}
+
+/// Returns the core stack described by the Memory Allocation Stack HOB of the HOB list.
+fn find_core_stack(physical_hob_list: *const c_void) -> Option<Range<usize>> {
+    // Safety: the HOB list is handed off from pre-DXE and starts with the PHIT HOB.
+    let hob_list = Hob::Handoff(unsafe { (physical_hob_list as *const PhaseHandoffInformationTable).as_ref()? });
+    hob_list.into_iter().find_map(|hob| match hob {
+        Hob::MemoryAllocation(allocation) if allocation.alloc_descriptor.name == HOB_MEMORY_ALLOC_STACK => {
+            let start = allocation.alloc_descriptor.memory_base_address as usize;
+            Some(start..start + allocation.alloc_descriptor.memory_length as usize)
+        }
+        _ => None,
+    })
+}
+
+/// Watermarks the core stack below the stack frame of the caller.
+///
+/// Must be called when the core is entered, before the heap is available, so that the peak usage of the whole boot is
+/// measured.
+#[inline(never)]
+pub(crate) fn watermark_core_stack(physical_hob_list: *const c_void) {
+    let Some(stack) = find_core_stack(physical_hob_list) else {
+        log::warn!("No Memory Allocation Stack HOB, the core stack usage is not measured.");
+        return;
+    };
+    let stack_pointer = current_stack_pointer();
+    if !stack.contains(&stack_pointer) {
+        log::warn!("The core is not running on the stack {stack:#x?} of the HOB list, its usage is not measured.");
+        return;
+    }
+
+    let watermarked = stack_pointer.saturating_sub(FILL_MARGIN).saturating_sub(stack.start);
+    // Safety: the part of the core stack below the frames of this function and of the fill is not in use.
+    unsafe { watermark_core_stack_region(stack, watermarked) };
+}
+
+/// Fills the first `watermarked` bytes of the core stack `stack` with the watermark.
+///
+/// # Safety
+///
+/// `stack` must be valid for writes, and its first `watermarked` bytes must not be in use.
+unsafe fn watermark_core_stack_region(stack: Range<usize>, watermarked: usize) {
+    // Safety: the caller guarantees the watermarked part of the stack is writable and not in use.
+    unsafe { core::ptr::write_bytes(stack.start as *mut u8, WATERMARK, watermarked) };
+    CORE_STACK_START.store(stack.start, Ordering::Relaxed);
+    CORE_STACK_SIZE.store(stack.len(), Ordering::Relaxed);
+    CORE_STACK_WATERMARKED.store(watermarked, Ordering::Relaxed);
+    CORE_STACK_PEAKS.iter().for_each(|peak| peak.store(0, Ordering::Relaxed));
+}
+
+/// Returns the peak usage of the core stack so far, or `None` if it is not watermarked.
+fn measure_core_stack() -> Option<StackUsage> {
+    let start = CORE_STACK_START.load(Ordering::Relaxed);
+    if start == 0 {
+        return None;
+    }
+    let size = CORE_STACK_SIZE.load(Ordering::Relaxed);
+    let watermarked = CORE_STACK_WATERMARKED.load(Ordering::Relaxed);
+    // Safety: the watermarked part of the core stack was validated when it was filled, and is only read here.
+    let untouched = untouched_len(unsafe { core::slice::from_raw_parts(start as *const u8, watermarked) });
+    Some(StackUsage { size, peak: size - untouched })
+}
+
+/// Records the peak usage of the core stack at `milestone`.
+pub(crate) fn record_core_stack_usage(milestone: StackMilestone) {
+    if let Some(usage) = measure_core_stack() {
+        CORE_STACK_PEAKS[milestone.index()].store(usage.peak, Ordering::Relaxed);
+        log::info!("Core stack usage at {milestone:?}: {usage}");
+    }
+}
+
+/// Returns the peak usage of the core stack recorded at `milestone`, or `None` if the milestone was not reached or
+/// the core stack is not watermarked.
+pub fn core_stack_usage(milestone: StackMilestone) -> Option<StackUsage> {
+    let size = CORE_STACK_SIZE.load(Ordering::Relaxed);
+    match CORE_STACK_PEAKS[milestone.index()].load(Ordering::Relaxed) {
+        0 => None,
+        peak => Some(StackUsage { size, peak }),
+    }
+}
+
+/// Enables the measurement of the image stacks per `policy`.
+pub(crate) fn set_stack_usage_policy(policy: StackUsagePolicy) {
+    IMAGE_STACKS.store(policy.image_stacks, Ordering::Relaxed);
+}
+
+/// Returns whether the image stacks are watermarked.
+pub(crate) fn image_stacks_watermarked() -> bool {
+    IMAGE_STACKS.load(Ordering::Relaxed)
+}
+
+/// Fills the image stack `stack` with the watermark.
+pub(crate) fn watermark_image_stack(stack: &mut [u8]) {
+    stack.fill(WATERMARK);
+}
+
+/// Records the peak usage of the watermarked image stack `stack` once the entry point of the image returned.
+pub(crate) fn record_image_stack_usage(file_name: Option<efi::Guid>, name: Option<String>, stack: &[u8]) {
+    let usage = StackUsage { size: stack.len(), peak: stack.len() - untouched_len(stack) };
+    log::debug!("Image {} stack usage: {usage}", name.as_deref().unwrap_or("<no PDB>"));
+    IMAGE_STACK_USAGE.lock().push(ImageStackUsage { file_name, name, usage });
+}
+
+/// Returns the peak usage of the stacks of the image entry points, in the order the images were started.
+///
+/// Empty unless the [StackUsagePolicy] enables the measurement of the image stacks.
+pub fn image_stack_usage() -> Vec<ImageStackUsage> {
+    IMAGE_STACK_USAGE.lock().clone()
+}
+
+/// Writes the core stack usage at each milestone, and the image stacks with the largest peak usage.
+pub(crate) fn write_stack_usage(out: &mut dyn fmt::Write) -> fmt::Result {
+    match measure_core_stack() {
+        Some(usage) => writeln!(out, "Core stack: {usage}")?,
+        None => writeln!(out, "Core stack: not measured")?,
+    }
+    for milestone in StackMilestone::ALL {
+        if let Some(usage) = core_stack_usage(milestone) {
+            writeln!(out, "  at {milestone:?}: {usage}")?;
+        }
+    }
+
+    let mut images = image_stack_usage();
+    images.sort_by_key(|image| core::cmp::Reverse(image.usage.peak));
+    for image in images.iter().take(MONITOR_IMAGE_COUNT) {
+        writeln!(out, "Image {}: {}", image.name.as_deref().unwrap_or("<no PDB>"), image.usage)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use alloc::{string::ToString, vec};
+
+    #[test]
+    fn untouched_len_should_stop_at_the_first_used_byte() {
+        let mut stack = vec![0u8; 0x100];
+        watermark_image_stack(&mut stack);
+        assert_eq!(untouched_len(&stack), 0x100);
+
+        stack[0x80] = 0;
+        stack[0xf0] = 0;
+        assert_eq!(untouched_len(&stack), 0x80);
+    }
+
+    #[test]
+    fn core_stack_usage_should_be_recorded_at_milestones() {
+        crate::test_support::with_global_lock(|| {
+            let stack = vec![0u8; 0x1000].leak();
+            let start = stack.as_mut_ptr() as usize;
+            // Safety: the stack is a leaked buffer, no part of it is in use.
+            unsafe { watermark_core_stack_region(start..start + stack.len(), 0xf00) };
+            assert_eq!(core_stack_usage(StackMilestone::GcdInitialized), None);
+
+            record_core_stack_usage(StackMilestone::GcdInitialized);
+            assert_eq!(
+                core_stack_usage(StackMilestone::GcdInitialized),
+                Some(StackUsage { size: 0x1000, peak: 0x100 })
+            );
+
+            // Safety: the stack is a leaked buffer.
+            unsafe { ((start + 0x800) as *mut u8).write_volatile(0) };
+            record_core_stack_usage(StackMilestone::DispatchComplete);
+            assert_eq!(
+                core_stack_usage(StackMilestone::DispatchComplete),
+                Some(StackUsage { size: 0x1000, peak: 0x800 })
+            );
+            assert_eq!(core_stack_usage(StackMilestone::ExitBootServices), None);
+
+            let mut out = String::new();
+            write_stack_usage(&mut out).unwrap();
+            assert!(out.starts_with("Core stack: 0x800 of 0x1000 bytes (50%)\n"));
+            assert!(out.contains("  at DispatchComplete: 0x800 of 0x1000 bytes (50%)\n"));
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn image_stack_usage_should_be_recorded() {
+        crate::test_support::with_global_lock(|| {
+            let mut stack = vec![0u8; 0x400];
+            watermark_image_stack(&mut stack);
+            stack[0x300..].fill(0);
+
+            let count = image_stack_usage().len();
+            record_image_stack_usage(None, Some("Test.pdb".to_string()), &stack);
+            let images = image_stack_usage();
+            assert_eq!(images.len(), count + 1);
+            assert_eq!(images[count].usage, StackUsage { size: 0x400, peak: 0x100 });
+            assert_eq!(images[count].name.as_deref(), Some("Test.pdb"));
+        })
+        .unwrap();
+    }
+}
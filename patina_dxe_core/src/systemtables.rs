@@ -11,44 +11,42 @@
 use core::{ffi::c_void, mem::size_of, slice::from_raw_parts};
 
 use alloc::{alloc::Allocator, boxed::Box};
-use patina::{boot_services::BootServices, component::IntoComponent};
+use patina::{boot_services::BootServices, component::IntoComponent, guid};
 use r_efi::efi;
 
-use crate::{allocator::EFI_RUNTIME_SERVICES_DATA_ALLOCATOR, tpl_lock};
+use crate::{allocator::EFI_RUNTIME_SERVICES_DATA_ALLOCATOR, core_context::ContextStatic, tpl_lock};
 
-pub static SYSTEM_TABLE: tpl_lock::TplMutex<Option<EfiSystemTable>> =
-    tpl_lock::TplMutex::new(efi::TPL_NOTIFY, None, "StLock");
+pub static SYSTEM_TABLE: ContextStatic<tpl_lock::TplMutex<Option<EfiSystemTable>>> =
+    ContextStatic::new(|context| &context.system_table);
 
 pub struct EfiRuntimeServicesTable {
     runtime_services: Box<efi::RuntimeServices, &'static dyn Allocator>,
 }
 
 impl EfiRuntimeServicesTable {
-    //private unimplemented stub functions used to initialize the table.
-    #[coverage(off)]
-    extern "efiapi" fn get_time_unimplemented(_: *mut efi::Time, _: *mut efi::TimeCapabilities) -> efi::Status {
-        unimplemented!()
+    // Time services reporting that no time source is available. A runtime driver with an RTC backend replaces them;
+    // until then, callers get EFI_UNSUPPORTED rather than a panic or an uninitialized time.
+    extern "efiapi" fn get_time_unsupported(_: *mut efi::Time, _: *mut efi::TimeCapabilities) -> efi::Status {
+        efi::Status::UNSUPPORTED
     }
 
-    #[coverage(off)]
-    extern "efiapi" fn set_time_unimplemented(_: *mut efi::Time) -> efi::Status {
-        unimplemented!()
+    extern "efiapi" fn set_time_unsupported(_: *mut efi::Time) -> efi::Status {
+        efi::Status::UNSUPPORTED
     }
 
-    #[coverage(off)]
-    extern "efiapi" fn get_wakeup_time_unimplemented(
+    extern "efiapi" fn get_wakeup_time_unsupported(
         _: *mut efi::Boolean,
         _: *mut efi::Boolean,
         _: *mut efi::Time,
     ) -> efi::Status {
-        unimplemented!()
+        efi::Status::UNSUPPORTED
     }
 
-    #[coverage(off)]
-    extern "efiapi" fn set_wakeup_time_unimplemented(_: efi::Boolean, _: *mut efi::Time) -> efi::Status {
-        unimplemented!()
+    extern "efiapi" fn set_wakeup_time_unsupported(_: efi::Boolean, _: *mut efi::Time) -> efi::Status {
+        efi::Status::UNSUPPORTED
     }
 
+    //private unimplemented stub functions used to initialize the table.
     #[coverage(off)]
     extern "efiapi" fn set_virtual_address_map_unimplemented(
         _: usize,
@@ -138,10 +136,10 @@ impl EfiRuntimeServicesTable {
                 crc32: 0,
                 reserved: 0,
             },
-            get_time: Self::get_time_unimplemented,
-            set_time: Self::set_time_unimplemented,
-            get_wakeup_time: Self::get_wakeup_time_unimplemented,
-            set_wakeup_time: Self::set_wakeup_time_unimplemented,
+            get_time: Self::get_time_unsupported,
+            set_time: Self::set_time_unsupported,
+            get_wakeup_time: Self::get_wakeup_time_unsupported,
+            set_wakeup_time: Self::set_wakeup_time_unsupported,
             set_virtual_address_map: Self::set_virtual_address_map_unimplemented,
             convert_pointer: Self::convert_pointer_unimplemented,
             get_variable: Self::get_variable_unimplemented,
@@ -716,6 +714,27 @@ pub fn init_system_table() {
     _ = SYSTEM_TABLE.lock().insert(table);
 }
 
+/// The protocols whose installation changes the service tables of the system table, so their CRC32 checksums are
+/// recalculated.
+const CHECKSUM_PROTOCOL_GUIDS: [efi::Guid; 16] = [
+    guid!("1DA97072-BDDC-4B30-99F1-72A0B56FFF2A"), // gEfiMonotonicCounterArchProtocolGuid
+    guid!("1E5668E2-8481-11D4-BCF1-0080C73C8881"), // gEfiVariableArchProtocolGuid
+    guid!("26BACCB1-6F42-11D4-BCE7-0080C73C8881"), // gEfiCpuArchProtocolGuid
+    guid!("26BACCB2-6F42-11D4-BCE7-0080C73C8881"), // gEfiMetronomeArchProtocolGuid
+    guid!("26BACCB3-6F42-11D4-BCE7-0080C73C8881"), // gEfiTimerArchProtocolGuid
+    guid!("27CFAC87-46CC-11D4-9A38-0090273FC14D"), // gEfiRealTimeClockArchProtocolGuid
+    guid!("27CFAC88-46CC-11D4-9A38-0090273FC14D"), // gEfiResetArchProtocolGuid
+    guid!("5053697E-2CBC-4819-90D9-0580DEEE5754"), // gEfiCapsuleArchProtocolGuid
+    guid!("55198405-26C0-4765-8B7D-BE1DF5F99712"), // gEfiCpu2ProtocolGuid
+    guid!("6441F818-6362-4E44-B570-7DBA31DD2453"), // gEfiVariableWriteArchProtocolGuid
+    guid!("665E3FF5-46CC-11D4-9A38-0090273FC14D"), // gEfiWatchdogTimerArchProtocolGuid
+    guid!("665E3FF6-46CC-11D4-9A38-0090273FC14D"), // gEfiBdsArchProtocolGuid
+    guid!("94AB2F58-1438-4EF1-9152-18941894A3A0"), // gEfiSecurity2ArchProtocolGuid
+    guid!("A46423E3-4617-49F1-B9FF-D1BFA9115839"), // gEfiSecurityArchProtocolGuid
+    guid!("B7DFB4E1-052F-449F-87BE-9818FC91B733"), // gEfiRuntimeArchProtocolGuid
+    guid!("F4CCBFB7-F6E0-47FD-9DD4-10A8F150C191"), // gEfiSmmBase2ProtocolGuid
+];
+
 /// A component to register a callback that recalculates the CRC32 checksum of the system table
 /// when certain protocols are installed.
 #[derive(IntoComponent, Default)]
@@ -727,26 +746,7 @@ impl SystemTableChecksumInstaller {
             SYSTEM_TABLE.lock().as_mut().expect("System Table is initialized").checksum_all();
         }
 
-        const GUIDS: [efi::Guid; 16] = [
-            efi::Guid::from_bytes(&uuid::uuid!("1DA97072-BDDC-4B30-99F1-72A0B56FFF2A").to_bytes_le()), // gEfiMonotonicCounterArchProtocolGuid
-            efi::Guid::from_bytes(&uuid::uuid!("1E5668E2-8481-11D4-BCF1-0080C73C8881").to_bytes_le()), // gEfiVariableArchProtocolGuid
-            efi::Guid::from_bytes(&uuid::uuid!("26BACCB1-6F42-11D4-BC7E-0080C73C8881").to_bytes_le()), // gEfiCpuArchProtocolGuid
-            efi::Guid::from_bytes(&uuid::uuid!("26BACCB2-6F42-11D4-BCE7-0080C73C8881").to_bytes_le()), // gEfiMetronomeArchProtocolGuid
-            efi::Guid::from_bytes(&uuid::uuid!("26BACCB3-6F42-11D4-BCE7-0080C73C8881").to_bytes_le()), // gEfiTimerArchProtocolGuid
-            efi::Guid::from_bytes(&uuid::uuid!("27CFAC87-46CC-11D4-9A38-0090273FC14D").to_bytes_le()), // gEfiRealTimeClockArchProtocolGuid
-            efi::Guid::from_bytes(&uuid::uuid!("27CFAC88-46CC-11D4-9A38-0090273FC14D").to_bytes_le()), // gEfiResetArchProtocolGuid
-            efi::Guid::from_bytes(&uuid::uuid!("5053697E-2CBC-4819-90D9-0580DEEE5754").to_bytes_le()), // gEfiCapsuleArchProtocolGuid
-            efi::Guid::from_bytes(&uuid::uuid!("55198405-26c0-4765-8b7d-be1df5f99712").to_bytes_le()), // gEfiCpu2ProtocolGuid
-            efi::Guid::from_bytes(&uuid::uuid!("6441F818-6362-4E44-B570-7DBA31DD2453").to_bytes_le()), // gEfiVariableWriteArchProtocolGuid
-            efi::Guid::from_bytes(&uuid::uuid!("665E3FF5-46CC-11D4-9A38-0090273FC14D").to_bytes_le()), // gEfiWatchdogTimerArchProtocolGuid
-            efi::Guid::from_bytes(&uuid::uuid!("665E3FF6-46CC-11D4-9A38-0090273FC14D").to_bytes_le()), // gEfiBdsArchProtocolGuid
-            efi::Guid::from_bytes(&uuid::uuid!("94AB2F58-1438-4EF1-9152-18941894A3A0").to_bytes_le()), // gEfiSecurity2ArchProtocolGuid
-            efi::Guid::from_bytes(&uuid::uuid!("A46423E3-4617-49F1-B9FF-D1BFA9115839").to_bytes_le()), // gEfiSecurityArchProtocolGuid
-            efi::Guid::from_bytes(&uuid::uuid!("B7DFB4E1-052F-449F-87BE-9818FC91B733").to_bytes_le()), // gEfiRuntimeArchProtocolGuid
-            efi::Guid::from_bytes(&uuid::uuid!("F4CCBFB7-F6E0-47FD-9DD4-10A8F150C191").to_bytes_le()), // gEfiSmmBase2ProtocolGuid
-        ];
-
-        for guid in &GUIDS {
+        for guid in &CHECKSUM_PROTOCOL_GUIDS {
             let event = bs.create_event(
                 patina::boot_services::event::EventType::NOTIFY_SIGNAL,
                 patina::boot_services::tpl::Tpl::CALLBACK,
@@ -775,6 +775,28 @@ mod tests {
         .unwrap();
     }
 
+    #[test]
+    fn test_checksum_protocol_guids_match_the_arch_protocols() {
+        use patina_pi::protocols::{
+            bds, cpu_arch, metronome, reset_arch, runtime, security, security2, timer, variable_arch, watchdog,
+        };
+
+        for guid in [
+            bds::PROTOCOL_GUID,
+            cpu_arch::PROTOCOL_GUID,
+            metronome::PROTOCOL_GUID,
+            reset_arch::PROTOCOL_GUID,
+            runtime::PROTOCOL_GUID,
+            security::PROTOCOL_GUID,
+            security2::PROTOCOL_GUID,
+            timer::PROTOCOL_GUID,
+            variable_arch::PROTOCOL_GUID,
+            watchdog::PROTOCOL_GUID,
+        ] {
+            assert!(CHECKSUM_PROTOCOL_GUIDS.contains(&guid), "{guid:?} is missing");
+        }
+    }
+
     #[test]
     fn test_checksum_changes_on_edit() {
         with_locked_state(|| {
@@ -818,4 +840,24 @@ mod tests {
             assert_eq!(table.system_table_mut().boot_services, core::ptr::null_mut());
         })
     }
+
+    #[test]
+    fn test_time_services_are_unsupported_without_a_time_source() {
+        with_locked_state(|| {
+            let mut table = EfiSystemTable::init();
+            let runtime_services = table.runtime_services_mut();
+
+            let mut time = efi::Time::default();
+            let mut capabilities = efi::TimeCapabilities { resolution: 0, accuracy: 0, sets_to_zero: false.into() };
+            assert_eq!((runtime_services.get_time)(&mut time, &mut capabilities), efi::Status::UNSUPPORTED);
+            assert_eq!((runtime_services.set_time)(&mut time), efi::Status::UNSUPPORTED);
+
+            let (mut enabled, mut pending) = (false.into(), false.into());
+            assert_eq!(
+                (runtime_services.get_wakeup_time)(&mut enabled, &mut pending, &mut time),
+                efi::Status::UNSUPPORTED
+            );
+            assert_eq!((runtime_services.set_wakeup_time)(true.into(), &mut time), efi::Status::UNSUPPORTED);
+        })
+    }
 }
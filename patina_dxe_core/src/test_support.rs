@@ -88,6 +88,7 @@ pub(crate) unsafe fn reset_allocators() {
 pub(crate) unsafe fn init_test_protocol_db() {
     unsafe { PROTOCOL_DB.reset() };
     PROTOCOL_DB.init_protocol_db();
+    crate::driver_services::reset_supported_cache();
 }
 
 pub(crate) fn build_test_hob_list(mem_size: u64) -> *const c_void {
@@ -2,6 +2,10 @@
 //!
 //! This module provides a Mutex implementation based on UEFI TPL levels.
 //!
+//! With the `tpl_lock_diagnostics` feature, each mutex records the call site and the TPL it was acquired from, and
+//! panics with a report of both call sites on a re-entrant lock attempt, rather than deadlocking the caller. It also
+//! panics when a mutex is acquired at a TPL above its own, which would otherwise lower the TPL on release.
+//!
 //! ## License
 //!
 //! Copyright (c) Microsoft Corporation.
@@ -16,6 +20,9 @@ use core::{
 
 use r_efi::efi;
 
+#[cfg(feature = "tpl_lock_diagnostics")]
+use core::panic::Location;
+
 static BOOT_SERVICES_PTR: AtomicPtr<efi::BootServices> = AtomicPtr::new(core::ptr::null_mut());
 
 /// Called to initialize the global TplLock BootServices pointer. Prior to this call, TPL locks are collapsed to a basic
@@ -37,11 +44,32 @@ fn boot_services() -> Option<&'static mut efi::BootServices> {
     unsafe { boot_services_ptr.as_mut() }
 }
 
+/// The acquisition of a locked TplMutex, recorded with the `tpl_lock_diagnostics` feature.
+#[cfg(feature = "tpl_lock_diagnostics")]
+#[derive(Clone, Copy)]
+struct Owner {
+    location: &'static Location<'static>,
+    // The TPL the mutex was acquired from, or None before TPL support is initialized.
+    tpl: Option<efi::Tpl>,
+}
+
+#[cfg(feature = "tpl_lock_diagnostics")]
+impl fmt::Display for Owner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.tpl {
+            Some(tpl) => write!(f, "{} at TPL {tpl:#x}", self.location),
+            None => write!(f, "{} before TPL support", self.location),
+        }
+    }
+}
+
 /// Used to guard data with a locked MUTEX and TPL level.
 pub struct TplMutex<T: ?Sized> {
     tpl_lock_level: efi::Tpl,
     lock: AtomicBool,
     name: &'static str,
+    #[cfg(feature = "tpl_lock_diagnostics")]
+    owner: UnsafeCell<Option<Owner>>,
     data: UnsafeCell<T>,
 }
 /// Wrapper for guarded data, which can be accessed by Deref or DerefMut on this object.
@@ -49,6 +77,8 @@ pub struct TplGuard<'a, T: ?Sized + 'a> {
     release_tpl: Option<efi::Tpl>,
     lock: &'a AtomicBool,
     name: &'static str,
+    #[cfg(feature = "tpl_lock_diagnostics")]
+    owner: &'a UnsafeCell<Option<Owner>>,
     data: *mut T,
 }
 
@@ -61,7 +91,14 @@ unsafe impl<T: ?Sized + Send> Send for TplGuard<'_, T> {}
 impl<T> TplMutex<T> {
     /// Instantiates a new TplMutex with the given TPL level, data object, and name string.
     pub const fn new(tpl_lock_level: efi::Tpl, data: T, name: &'static str) -> Self {
-        Self { tpl_lock_level, lock: AtomicBool::new(false), data: UnsafeCell::new(data), name }
+        Self {
+            tpl_lock_level,
+            lock: AtomicBool::new(false),
+            name,
+            #[cfg(feature = "tpl_lock_diagnostics")]
+            owner: UnsafeCell::new(None),
+            data: UnsafeCell::new(data),
+        }
     }
 }
 
@@ -70,16 +107,40 @@ impl<T: ?Sized> TplMutex<T> {
     /// to the level specified at TplMutex creation.
     ///
     /// Safety: Lock reentrance is not supported; attempt to re-lock something already locked will panic.
+    #[cfg_attr(feature = "tpl_lock_diagnostics", track_caller)]
     pub fn lock(&self) -> TplGuard<'_, T> {
-        self.try_lock().unwrap_or_else(|| panic!("Re-entrant locks for {:?} not permitted.", self.name))
+        match self.try_lock() {
+            Some(guard) => guard,
+            #[cfg(feature = "tpl_lock_diagnostics")]
+            None => self.reentrant_lock_panic(),
+            #[cfg(not(feature = "tpl_lock_diagnostics"))]
+            None => panic!("Re-entrant locks for {:?} not permitted.", self.name),
+        }
     }
 
     /// Attempts to lock the TplMutex, and if successful, returns a guard object that can be used to access the data.
+    #[cfg_attr(feature = "tpl_lock_diagnostics", track_caller)]
     pub fn try_lock(&self) -> Option<TplGuard<'_, T>> {
         let boot_services = boot_services();
+        #[cfg(feature = "tpl_lock_diagnostics")]
+        if boot_services.is_some() {
+            self.check_acquire_tpl(crate::events::current_tpl());
+        }
         let release_tpl = boot_services.as_ref().map(|bs| (bs.raise_tpl)(self.tpl_lock_level));
         if self.lock.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_ok() {
-            Some(TplGuard { release_tpl, lock: &self.lock, name: self.name, data: unsafe { &mut *self.data.get() } })
+            // Safety: the owner is only written while the lock is held.
+            #[cfg(feature = "tpl_lock_diagnostics")]
+            unsafe {
+                *self.owner.get() = Some(Owner { location: Location::caller(), tpl: release_tpl })
+            };
+            Some(TplGuard {
+                release_tpl,
+                lock: &self.lock,
+                name: self.name,
+                #[cfg(feature = "tpl_lock_diagnostics")]
+                owner: &self.owner,
+                data: unsafe { &mut *self.data.get() },
+            })
         } else {
             if let Some(release_tpl) = release_tpl
                 && let Some(bs) = boot_services
@@ -91,6 +152,39 @@ impl<T: ?Sized> TplMutex<T> {
     }
 }
 
+#[cfg(feature = "tpl_lock_diagnostics")]
+impl<T: ?Sized> TplMutex<T> {
+    /// Panics if the mutex is acquired at `current_tpl`, above the TPL of the mutex.
+    #[track_caller]
+    fn check_acquire_tpl(&self, current_tpl: efi::Tpl) {
+        if current_tpl > self.tpl_lock_level {
+            panic!(
+                "TplMutex {:?} acquired at {} at TPL {current_tpl:#x}, above its TPL {:#x}.",
+                self.name,
+                Location::caller(),
+                self.tpl_lock_level
+            );
+        }
+    }
+
+    /// Panics with a report of the call sites of a re-entrant lock attempt and of the owner of the mutex.
+    #[track_caller]
+    fn reentrant_lock_panic(&self) -> ! {
+        // Safety: UEFI is single threaded, so the owner cannot be written while it is read here.
+        // Note: the report must not allocate, as the allocator lock may be the one re-entered.
+        match unsafe { *self.owner.get() } {
+            Some(owner) => {
+                panic!(
+                    "Re-entrant lock of TplMutex {:?} at {}: already locked at {owner}.",
+                    self.name,
+                    Location::caller()
+                )
+            }
+            None => panic!("Re-entrant lock of TplMutex {:?} at {}: already locked.", self.name, Location::caller()),
+        }
+    }
+}
+
 impl<T: ?Sized + fmt::Debug> fmt::Debug for TplMutex<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self.try_lock() {
@@ -129,6 +223,11 @@ impl<'a, T: ?Sized> DerefMut for TplGuard<'a, T> {
 
 impl<T: ?Sized> Drop for TplGuard<'_, T> {
     fn drop(&mut self) {
+        // Safety: the owner is only written while the lock is held.
+        #[cfg(feature = "tpl_lock_diagnostics")]
+        unsafe {
+            *self.owner.get() = None
+        };
         self.lock.store(false, Ordering::Release);
         if let Some(tpl) = self.release_tpl {
             let bs = boot_services()
@@ -213,6 +312,72 @@ mod tests {
         });
     }
 
+    fn panic_message(result: std::thread::Result<()>) -> std::string::String {
+        let payload = result.expect_err("expected a panic");
+        payload.downcast_ref::<std::string::String>().cloned().unwrap_or_default()
+    }
+
+    #[test]
+    fn tpl_mutex_should_panic_on_reentrant_lock() {
+        with_locked_state(|| {
+            let tpl_mutex = TplMutex::new(efi::TPL_HIGH_LEVEL, 1_usize, "test_lock");
+            let _guard = tpl_mutex.lock();
+            assert!(tpl_mutex.try_lock().is_none());
+
+            let message =
+                panic_message(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| drop(tpl_mutex.lock()))));
+            assert!(message.contains("test_lock"), "{message}");
+        });
+    }
+
+    #[cfg(feature = "tpl_lock_diagnostics")]
+    #[test]
+    fn tpl_mutex_diagnostics_should_report_both_call_sites_of_reentrant_lock() {
+        with_locked_state(|| {
+            let tpl_mutex = TplMutex::new(efi::TPL_HIGH_LEVEL, 1_usize, "test_lock");
+            let guard = tpl_mutex.lock();
+            let owner_line = line!() - 1;
+            let message =
+                panic_message(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| drop(tpl_mutex.lock()))));
+            let reentrant_line = line!() - 1;
+
+            assert!(message.contains(&std::format!("{}:{owner_line}:", file!())), "{message}");
+            assert!(message.contains(&std::format!("{}:{reentrant_line}:", file!())), "{message}");
+            assert!(message.contains("before TPL support"), "{message}");
+
+            // The owner is cleared once the guard is dropped.
+            drop(guard);
+            let _guard = tpl_mutex.lock();
+            let message =
+                panic_message(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| drop(tpl_mutex.lock()))));
+            assert!(!message.contains(&std::format!("{}:{owner_line}:", file!())), "{message}");
+        });
+    }
+
+    #[cfg(feature = "tpl_lock_diagnostics")]
+    #[test]
+    fn tpl_mutex_diagnostics_should_panic_when_acquired_above_its_tpl() {
+        with_locked_state(|| {
+            let boot_services = mock_boot_services();
+            unsafe {
+                (*boot_services).raise_tpl = crate::events::raise_tpl;
+                (*boot_services).restore_tpl = crate::events::restore_tpl;
+            }
+            init_boot_services(boot_services);
+
+            let tpl_mutex = TplMutex::new(efi::TPL_CALLBACK, 1_usize, "test_lock");
+            let guard = tpl_mutex.lock();
+            assert_eq!(crate::events::current_tpl(), efi::TPL_CALLBACK);
+            drop(guard);
+
+            let tpl = crate::events::raise_tpl(efi::TPL_NOTIFY);
+            let message =
+                panic_message(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| drop(tpl_mutex.lock()))));
+            crate::events::restore_tpl(tpl);
+            assert!(message.contains("above its TPL 0x8"), "{message}");
+        });
+    }
+
     #[test]
     fn tpl_mutex_and_guard_should_support_debug_and_display() {
         with_locked_state(|| {
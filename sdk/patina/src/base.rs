@@ -14,6 +14,7 @@ use r_efi::efi;
 use crate::error::EfiError;
 
 pub mod guid;
+pub mod ucs2;
 
 /// EFI memory allocation functions work in units of EFI_PAGEs that are 4KB.
 /// This should in no way be confused with the page size of the processor.
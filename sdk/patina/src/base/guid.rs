@@ -400,6 +400,78 @@ impl<'a> TryFrom<&'a str> for OwnedGuid {
     }
 }
 
+#[doc(hidden)]
+pub mod __private {
+    pub use r_efi::efi::Guid as EfiGuid;
+
+    /// Parses `len` hexadecimal digits of `s` starting at `start`.
+    const fn parse_hex(s: &[u8], start: usize, len: usize) -> u32 {
+        let mut value = 0;
+        let mut i = start;
+        while i < start + len {
+            let digit = match s[i] {
+                b'0'..=b'9' => s[i] - b'0',
+                b'A'..=b'F' => s[i] - b'A' + 10,
+                b'a'..=b'f' => s[i] - b'a' + 10,
+                _ => panic!("guid! literal holds a character that is not a hexadecimal digit."),
+            };
+            value = (value << 4) | digit as u32;
+            i += 1;
+        }
+        value
+    }
+
+    /// Parses a GUID of the form `XXXXXXXX-XXXX-XXXX-XXXX-XXXXXXXXXXXX`.
+    pub const fn parse(s: &str) -> EfiGuid {
+        let s = s.as_bytes();
+        if s.len() != 36 || s[8] != b'-' || s[13] != b'-' || s[18] != b'-' || s[23] != b'-' {
+            panic!("guid! literal must have the form XXXXXXXX-XXXX-XXXX-XXXX-XXXXXXXXXXXX.");
+        }
+        let mut node = [0u8; 6];
+        let mut i = 0;
+        while i < node.len() {
+            node[i] = parse_hex(s, 24 + 2 * i, 2) as u8;
+            i += 1;
+        }
+        EfiGuid::from_fields(
+            parse_hex(s, 0, 8),
+            parse_hex(s, 9, 4) as u16,
+            parse_hex(s, 14, 4) as u16,
+            parse_hex(s, 19, 2) as u8,
+            parse_hex(s, 21, 2) as u8,
+            &node,
+        )
+    }
+}
+
+/// Builds an `efi::Guid` from a string literal of the form `XXXXXXXX-XXXX-XXXX-XXXX-XXXXXXXXXXXX` at compile time.
+///
+/// Compilation fails if the literal is not a valid GUID.
+///
+/// # Example
+///
+/// ```rust
+/// use patina::guid;
+/// use r_efi::efi;
+///
+/// const HOB_LIST: efi::Guid = guid!("7739F24C-93D7-11D4-9A3A-0090273FC14D");
+/// assert_eq!(
+///     HOB_LIST,
+///     efi::Guid::from_fields(0x7739f24c, 0x93d7, 0x11d4, 0x9a, 0x3a, &[0x00, 0x90, 0x27, 0x3f, 0xc1, 0x4d])
+/// );
+/// ```
+///
+/// ```rust,compile_fail
+/// const INVALID: r_efi::efi::Guid = patina::guid!("7739F24C-93D7-11D4-9A3A-0090273FC14");
+/// ```
+#[macro_export]
+macro_rules! guid {
+    ($s:literal) => {{
+        const GUID: $crate::base::guid::__private::EfiGuid = $crate::base::guid::__private::parse($s);
+        GUID
+    }};
+}
+
 #[cfg(test)]
 #[coverage(off)]
 mod tests {
@@ -1005,4 +1077,27 @@ mod tests {
         // Verify display formatting
         assert_eq!(format!("{}", guid_from_bytes), TEST_GUID_STRING_UPPER);
     }
+
+    #[test]
+    fn test_guid_macro() {
+        const LOWER: efi::Guid = crate::guid!("550e8400-e29b-41d4-a716-446655440000");
+        const UPPER: efi::Guid = crate::guid!("550E8400-E29B-41D4-A716-446655440000");
+
+        assert_eq!(LOWER, UPPER);
+        assert_eq!(Guid::from_ref(&LOWER), OwnedGuid::try_from_string(TEST_GUID_STRING).unwrap());
+        assert_eq!(Guid::from_ref(&LOWER).as_fields(), TEST_GUID_FIELDS);
+        assert_eq!(__private::parse("00000000-0000-0000-0000-000000000000"), OwnedGuid::ZERO.to_efi_guid());
+    }
+
+    #[test]
+    #[should_panic(expected = "must have the form")]
+    fn test_guid_macro_rejects_missing_dashes() {
+        __private::parse("550e8400e29b41d4a716446655440000");
+    }
+
+    #[test]
+    #[should_panic(expected = "not a hexadecimal digit")]
+    fn test_guid_macro_rejects_invalid_digits() {
+        __private::parse("550e8400-e29b-41d4-a716-44665544000g");
+    }
 }
@@ -0,0 +1,491 @@
+//! Patina UCS-2 string types
+//!
+//! UEFI interfaces take CHAR16 strings: null-terminated UCS-2, i.e. UTF-16 restricted to the Basic Multilingual Plane
+//! (without surrogate pairs). These types spare components from hand-rolling `u16` arrays.
+//!
+//! ## Type Overview
+//!
+//! - [`Ucs2Str`] - A borrowed null-terminated UCS-2 string, the CHAR16 equivalent of [`core::ffi::CStr`]
+//! - [`Ucs2String`] - An owned null-terminated UCS-2 string, the CHAR16 equivalent of [`alloc::ffi::CString`]
+//! - [`Ucs2Error`] - Error type for UCS-2 conversion operations
+//! - [`ucs2!`](crate::ucs2!) - Builds a `&'static Ucs2Str` from a string literal at compile time
+//!
+//! ## Examples
+//!
+//! ```rust
+//! use patina::{Ucs2Error, Ucs2Str, Ucs2String, ucs2};
+//!
+//! // Converting a string literal at compile time
+//! const BOOT_ORDER: &Ucs2Str = ucs2!("BootOrder");
+//! assert_eq!(BOOT_ORDER.len(), 9);
+//! assert_eq!(BOOT_ORDER.as_slice_with_nul().last(), Some(&0));
+//!
+//! // Converting a string at runtime
+//! let mut name = Ucs2String::try_from("Boot")?;
+//! name.push_str("0001")?;
+//! assert_eq!(name, "Boot0001");
+//!
+//! // Characters outside the Basic Multilingual Plane cannot be represented
+//! assert_eq!(Ucs2String::try_from("ab🦀"), Err(Ucs2Error::InvalidCharacter { position: 2 }));
+//! # Ok::<(), Ucs2Error>(())
+//! ```
+//!
+//! ## License
+//!
+//! Copyright (C) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use alloc::{borrow::ToOwned, string::String, vec, vec::Vec};
+use core::{
+    borrow::Borrow,
+    fmt::{self, Write},
+    ops::Deref,
+};
+
+use crate::error::EfiError;
+
+/// Error type for UCS-2 conversion operations
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Ucs2Error {
+    /// The provided string is not null-terminated
+    MissingNul,
+    /// The provided string contains a null character before its end
+    InteriorNul {
+        /// Position of the null character in the string
+        position: usize,
+    },
+    /// The provided string contains a character that cannot be represented in UCS-2: a character outside the Basic
+    /// Multilingual Plane or a surrogate code unit
+    InvalidCharacter {
+        /// Position of the invalid character in the string
+        position: usize,
+    },
+}
+
+impl fmt::Display for Ucs2Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ucs2Error::MissingNul => write!(f, "UCS-2 string is not null-terminated"),
+            Ucs2Error::InteriorNul { position } => write!(f, "Interior null character at position {position}"),
+            Ucs2Error::InvalidCharacter { position } => {
+                write!(f, "Character at position {position} cannot be represented in UCS-2")
+            }
+        }
+    }
+}
+
+impl core::error::Error for Ucs2Error {}
+
+impl From<Ucs2Error> for EfiError {
+    fn from(_: Ucs2Error) -> Self {
+        EfiError::InvalidParameter
+    }
+}
+
+/// Returns whether `code_unit` is a UTF-16 surrogate, which UCS-2 does not allow.
+const fn is_surrogate(code_unit: u16) -> bool {
+    matches!(code_unit, 0xD800..=0xDFFF)
+}
+
+/// Encodes `c`, the character at `position` in its string, as a UCS-2 code unit.
+fn encode_char(c: char, position: usize) -> Result<u16, Ucs2Error> {
+    match u16::try_from(u32::from(c)) {
+        Ok(0) => Err(Ucs2Error::InteriorNul { position }),
+        Ok(code_unit) => Ok(code_unit),
+        Err(_) => Err(Ucs2Error::InvalidCharacter { position }),
+    }
+}
+
+/// A borrowed null-terminated UCS-2 string.
+///
+/// The string holds no null character but its terminator, and no surrogate code unit. It can be passed to UEFI
+/// interfaces expecting a CHAR16 string with [`Ucs2Str::as_ptr`] or [`Ucs2Str::as_slice_with_nul`].
+#[repr(transparent)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Ucs2Str([u16]);
+
+impl Ucs2Str {
+    /// Creates a string from a slice holding exactly one null character, at its end.
+    pub fn from_slice_with_nul(slice: &[u16]) -> Result<&Self, Ucs2Error> {
+        match slice.iter().position(|&code_unit| code_unit == 0) {
+            Some(position) if position + 1 == slice.len() => Self::validate(slice),
+            Some(position) => Err(Ucs2Error::InteriorNul { position }),
+            None => Err(Ucs2Error::MissingNul),
+        }
+    }
+
+    /// Creates a string from the start of a slice, up to and including its first null character.
+    ///
+    /// This is useful for buffers filled by firmware, which may be larger than the string they hold.
+    pub fn from_slice_until_nul(slice: &[u16]) -> Result<&Self, Ucs2Error> {
+        let end = slice.iter().position(|&code_unit| code_unit == 0).ok_or(Ucs2Error::MissingNul)?;
+        Self::validate(&slice[..=end])
+    }
+
+    /// Creates a string from a slice without validating it.
+    ///
+    /// # Safety
+    ///
+    /// `slice` must end with a null character and must not hold any other null character nor surrogate code unit.
+    pub const unsafe fn from_slice_with_nul_unchecked(slice: &[u16]) -> &Self {
+        // SAFETY: Ucs2Str is a transparent wrapper around [u16]; validity is guaranteed by the caller.
+        unsafe { &*(slice as *const [u16] as *const Self) }
+    }
+
+    /// Returns a reference to the null-terminated string at `ptr`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to a null-terminated UCS-2 string without surrogate code units, that remains valid and
+    /// unmodified for the lifetime `'a`.
+    pub unsafe fn from_ptr<'a>(ptr: *const u16) -> &'a Self {
+        let mut len = 0;
+        // SAFETY: the string is null-terminated per the caller's guarantees, so every read is in bounds.
+        while unsafe { ptr.add(len).read_unaligned() } != 0 {
+            len += 1;
+        }
+        // SAFETY: ptr points to len characters followed by a null terminator.
+        unsafe { Self::from_slice_with_nul_unchecked(core::slice::from_raw_parts(ptr, len + 1)) }
+    }
+
+    fn validate(slice: &[u16]) -> Result<&Self, Ucs2Error> {
+        if let Some(position) = slice.iter().position(|&code_unit| is_surrogate(code_unit)) {
+            return Err(Ucs2Error::InvalidCharacter { position });
+        }
+        // SAFETY: the slice was checked to be null-terminated by the callers, and to be free of surrogates above.
+        Ok(unsafe { Self::from_slice_with_nul_unchecked(slice) })
+    }
+
+    /// Returns a pointer to the null-terminated string, for UEFI interfaces taking a CHAR16 string.
+    pub fn as_ptr(&self) -> *const u16 {
+        self.0.as_ptr()
+    }
+
+    /// Returns the characters of the string, without the null terminator.
+    pub fn as_slice(&self) -> &[u16] {
+        &self.0[..self.0.len() - 1]
+    }
+
+    /// Returns the characters of the string, including the null terminator.
+    pub fn as_slice_with_nul(&self) -> &[u16] {
+        &self.0
+    }
+
+    /// Returns the number of characters in the string, without the null terminator.
+    pub fn len(&self) -> usize {
+        self.0.len() - 1
+    }
+
+    /// Returns whether the string is empty, i.e. only holds the null terminator.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns an iterator over the characters of the string.
+    pub fn chars(&self) -> impl Iterator<Item = char> + '_ {
+        self.as_slice().iter().map(|&code_unit| char::from_u32(code_unit.into()).unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
+}
+
+impl fmt::Display for Ucs2Str {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.chars().try_for_each(|c| f.write_char(c))
+    }
+}
+
+impl fmt::Debug for Ucs2Str {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_char('"')?;
+        self.chars().flat_map(char::escape_debug).try_for_each(|c| f.write_char(c))?;
+        f.write_char('"')
+    }
+}
+
+impl AsRef<Ucs2Str> for Ucs2Str {
+    fn as_ref(&self) -> &Ucs2Str {
+        self
+    }
+}
+
+impl PartialEq<str> for Ucs2Str {
+    fn eq(&self, other: &str) -> bool {
+        self.chars().eq(other.chars())
+    }
+}
+
+impl ToOwned for Ucs2Str {
+    type Owned = Ucs2String;
+
+    fn to_owned(&self) -> Ucs2String {
+        Ucs2String(self.0.to_vec())
+    }
+}
+
+/// An owned null-terminated UCS-2 string.
+///
+/// Dereferences to [`Ucs2Str`], with the same guarantees.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Ucs2String(Vec<u16>);
+
+impl Ucs2String {
+    /// Creates an empty string.
+    pub fn new() -> Self {
+        Self(vec![0])
+    }
+
+    /// Creates a string from a vector holding exactly one null character, at its end.
+    pub fn from_vec_with_nul(vec: Vec<u16>) -> Result<Self, Ucs2Error> {
+        Ucs2Str::from_slice_with_nul(&vec)?;
+        Ok(Self(vec))
+    }
+
+    /// Appends a character to the end of the string.
+    pub fn push(&mut self, c: char) -> Result<(), Ucs2Error> {
+        let code_unit = encode_char(c, self.len())?;
+        self.0.insert(self.0.len() - 1, code_unit);
+        Ok(())
+    }
+
+    /// Appends a string to the end of the string.
+    ///
+    /// The string is left unmodified if `s` holds a null character or a character that cannot be represented in UCS-2.
+    pub fn push_str(&mut self, s: &str) -> Result<(), Ucs2Error> {
+        let len = self.len();
+        let mut encoded = Vec::with_capacity(s.len() + 1);
+        for (index, c) in s.chars().enumerate() {
+            encoded.push(encode_char(c, len + index)?);
+        }
+        encoded.push(0);
+        self.0.pop();
+        self.0.append(&mut encoded);
+        Ok(())
+    }
+
+    /// Returns the string as a [`Ucs2Str`].
+    pub fn as_ucs2_str(&self) -> &Ucs2Str {
+        // SAFETY: the string is always null-terminated and free of interior nulls and surrogates.
+        unsafe { Ucs2Str::from_slice_with_nul_unchecked(&self.0) }
+    }
+
+    /// Returns the characters of the string, including the null terminator.
+    pub fn into_vec_with_nul(self) -> Vec<u16> {
+        self.0
+    }
+}
+
+impl Default for Ucs2String {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for Ucs2String {
+    type Target = Ucs2Str;
+
+    fn deref(&self) -> &Ucs2Str {
+        self.as_ucs2_str()
+    }
+}
+
+impl Borrow<Ucs2Str> for Ucs2String {
+    fn borrow(&self) -> &Ucs2Str {
+        self.as_ucs2_str()
+    }
+}
+
+impl AsRef<Ucs2Str> for Ucs2String {
+    fn as_ref(&self) -> &Ucs2Str {
+        self.as_ucs2_str()
+    }
+}
+
+impl From<&Ucs2Str> for Ucs2String {
+    fn from(s: &Ucs2Str) -> Self {
+        s.to_owned()
+    }
+}
+
+impl From<&Ucs2Str> for String {
+    fn from(s: &Ucs2Str) -> Self {
+        s.chars().collect()
+    }
+}
+
+impl TryFrom<&str> for Ucs2String {
+    type Error = Ucs2Error;
+
+    fn try_from(s: &str) -> Result<Self, Ucs2Error> {
+        let mut string = Self::new();
+        string.push_str(s)?;
+        Ok(string)
+    }
+}
+
+impl fmt::Display for Ucs2String {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.as_ucs2_str(), f)
+    }
+}
+
+impl fmt::Debug for Ucs2String {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_ucs2_str(), f)
+    }
+}
+
+impl PartialEq<str> for Ucs2String {
+    fn eq(&self, other: &str) -> bool {
+        self.as_ucs2_str() == other
+    }
+}
+
+impl PartialEq<&str> for Ucs2String {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_ucs2_str() == *other
+    }
+}
+
+#[doc(hidden)]
+pub mod __private {
+    /// Returns the number of UCS-2 characters needed to encode `s`, without the null terminator.
+    pub const fn encoded_len(s: &str) -> usize {
+        let bytes = s.as_bytes();
+        let mut len = 0;
+        let mut i = 0;
+        while i < bytes.len() {
+            // Count every byte starting a UTF-8 sequence.
+            if bytes[i] & 0xC0 != 0x80 {
+                len += 1;
+            }
+            i += 1;
+        }
+        len
+    }
+
+    /// Encodes `s` as a null-terminated UCS-2 string of `N` characters, panicking if it cannot be represented.
+    pub const fn encode<const N: usize>(s: &str) -> [u16; N] {
+        let bytes = s.as_bytes();
+        let mut encoded = [0u16; N];
+        let mut i = 0;
+        let mut j = 0;
+        while i < bytes.len() {
+            // s is valid UTF-8, so sequences are complete and three byte sequences never encode a surrogate.
+            let (code_point, width) = match bytes[i] {
+                byte if byte < 0x80 => (byte as u32, 1),
+                byte if byte & 0xE0 == 0xC0 => (((byte as u32 & 0x1F) << 6) | (bytes[i + 1] as u32 & 0x3F), 2),
+                byte if byte & 0xF0 == 0xE0 => (
+                    ((byte as u32 & 0x0F) << 12) | ((bytes[i + 1] as u32 & 0x3F) << 6) | (bytes[i + 2] as u32 & 0x3F),
+                    3,
+                ),
+                _ => panic!("ucs2! string holds a character outside the Basic Multilingual Plane."),
+            };
+            if code_point == 0 {
+                panic!("ucs2! string holds a null character.");
+            }
+            encoded[j] = code_point as u16;
+            i += width;
+            j += 1;
+        }
+        encoded
+    }
+}
+
+/// Builds a `&'static` [`Ucs2Str`](crate::base::ucs2::Ucs2Str) from a string literal at compile time.
+///
+/// Compilation fails if the literal holds a null character or a character outside the Basic Multilingual Plane.
+///
+/// # Example
+///
+/// ```rust
+/// use patina::{Ucs2Str, ucs2};
+///
+/// const PLATFORM_LANG: &Ucs2Str = ucs2!("PlatformLang");
+/// assert_eq!(PLATFORM_LANG, "PlatformLang");
+/// assert_eq!(ucs2!("Ünïcödé").as_slice()[0], 0xDC);
+/// ```
+#[macro_export]
+macro_rules! ucs2 {
+    ($s:literal) => {{
+        const STRING: &str = $s;
+        const ENCODED: [u16; $crate::base::ucs2::__private::encoded_len(STRING) + 1] =
+            $crate::base::ucs2::__private::encode(STRING);
+        // SAFETY: encode only produces null-terminated strings without interior nulls or surrogates.
+        unsafe { $crate::base::ucs2::Ucs2Str::from_slice_with_nul_unchecked(&ENCODED) }
+    }};
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_ucs2_macro() {
+        const NAME: &Ucs2Str = ucs2!("Boot0001");
+        assert_eq!(NAME.as_slice_with_nul(), [0x42, 0x6F, 0x6F, 0x74, 0x30, 0x30, 0x30, 0x31, 0x00]);
+        assert_eq!(NAME.len(), 8);
+        assert_eq!(NAME.to_string(), "Boot0001");
+
+        let empty = ucs2!("");
+        assert!(empty.is_empty());
+        assert_eq!(empty.as_slice_with_nul(), [0]);
+
+        assert_eq!(ucs2!("é€").as_slice(), [0xE9, 0x20AC]);
+    }
+
+    #[test]
+    fn test_ucs2_str_from_slice() {
+        let name = Ucs2Str::from_slice_with_nul(&[0x41, 0x42, 0x00]).unwrap();
+        assert_eq!(name, "AB");
+        assert_eq!(Ucs2Str::from_slice_with_nul(&[0x41, 0x42]), Err(Ucs2Error::MissingNul));
+        assert_eq!(
+            Ucs2Str::from_slice_with_nul(&[0x41, 0x00, 0x42, 0x00]),
+            Err(Ucs2Error::InteriorNul { position: 1 })
+        );
+        assert_eq!(
+            Ucs2Str::from_slice_with_nul(&[0x41, 0xD83E, 0x00]),
+            Err(Ucs2Error::InvalidCharacter { position: 1 })
+        );
+
+        let name = Ucs2Str::from_slice_until_nul(&[0x41, 0x00, 0x42, 0x00]).unwrap();
+        assert_eq!(name.as_slice_with_nul(), [0x41, 0x00]);
+        assert_eq!(Ucs2Str::from_slice_until_nul(&[]), Err(Ucs2Error::MissingNul));
+    }
+
+    #[test]
+    fn test_ucs2_str_from_ptr() {
+        let buffer = [0x41u16, 0x42, 0x00, 0x43];
+        let name = unsafe { Ucs2Str::from_ptr(buffer.as_ptr()) };
+        assert_eq!(name.as_ptr(), buffer.as_ptr());
+        assert_eq!(name, ucs2!("AB"));
+    }
+
+    #[test]
+    fn test_ucs2_string_conversions() {
+        let mut name = Ucs2String::try_from("Lang").unwrap();
+        assert_eq!(name, "Lang");
+        name.push('-').unwrap();
+        name.push_str("Codes").unwrap();
+        assert_eq!(name, "Lang-Codes");
+        assert_eq!(name.as_ucs2_str(), ucs2!("Lang-Codes"));
+
+        assert_eq!(name.push('🦀'), Err(Ucs2Error::InvalidCharacter { position: 10 }));
+        assert_eq!(name.push('\0'), Err(Ucs2Error::InteriorNul { position: 10 }));
+        assert_eq!(name.push_str("ab🦀"), Err(Ucs2Error::InvalidCharacter { position: 12 }));
+        assert_eq!(name, "Lang-Codes");
+
+        assert_eq!(String::from(name.as_ucs2_str()), "Lang-Codes");
+        assert_eq!(alloc::format!("{name:?}"), "\"Lang-Codes\"");
+        assert_eq!(ucs2!("Lang-Codes").to_owned(), name);
+        assert_eq!(name.clone().into_vec_with_nul().len(), 11);
+
+        assert_eq!(Ucs2String::default(), Ucs2String::new());
+        assert!(Ucs2String::new().is_empty());
+        assert_eq!(Ucs2String::from_vec_with_nul(vec![0x41, 0x00]).unwrap(), "A");
+        assert_eq!(Ucs2String::from_vec_with_nul(vec![0x41]), Err(Ucs2Error::MissingNul));
+        assert_eq!(EfiError::from(Ucs2Error::MissingNul), EfiError::InvalidParameter);
+    }
+}
@@ -159,6 +159,8 @@ impl Debug for StandardBootServices {
 pub trait BootServices {
     /// Create an event.
     ///
+    /// The context is dropped if the event could not be created.
+    ///
     /// [UEFI Spec Documentation: 7.1.1. EFI_BOOT_SERVICES.CreateEvent()](https://uefi.org/specs/UEFI/2.10/07_Services_Boot_Services.html#efi-boot-services-createevent)
     fn create_event<T>(
         &self,
@@ -170,8 +172,9 @@ pub trait BootServices {
     where
         T: CPtr<'static> + 'static,
     {
+        let context_metadata = notify_context.metadata();
         //SAFETY: ['StaticPtr`] generic is used to guaranteed that rust borowing and rules are meet.
-        unsafe {
+        let result = unsafe {
             self.create_event_unchecked(
                 event_type,
                 notify_tpl,
@@ -181,7 +184,12 @@ pub trait BootServices {
                 >(notify_function),
                 notify_context.into_ptr() as *mut T::Type,
             )
+        };
+        if result.is_err() {
+            // SAFETY: the event was not created, so the context was not handed over to the firmware.
+            drop(unsafe { context_metadata.into_original_ptr() });
         }
+        result
     }
 
     /// Use [`BootServices::create_event`] when possible.
@@ -206,6 +214,8 @@ pub trait BootServices {
 
     /// Create an event in a group.
     ///
+    /// The context is dropped if the event could not be created.
+    ///
     /// [UEFI Spec Documentation: 7.1.2. EFI_BOOT_SERVICES.CreateEventEx()](https://uefi.org/specs/UEFI/2.10/07_Services_Boot_Services.html#efi-boot-services-createeventex)
     fn create_event_ex<T>(
         &self,
@@ -218,8 +228,9 @@ pub trait BootServices {
     where
         T: CPtr<'static> + 'static,
     {
+        let context_metadata = notify_context.metadata();
         //SAFETY: [`StaticPtr`] generic is used to guaranteed that rust borowing and rules are meet.
-        unsafe {
+        let result = unsafe {
             self.create_event_ex_unchecked(
                 event_type,
                 notify_tpl,
@@ -230,7 +241,12 @@ pub trait BootServices {
                 notify_context.into_ptr() as *mut <T as CPtr>::Type,
                 event_group,
             )
+        };
+        if result.is_err() {
+            // SAFETY: the event was not created, so the context was not handed over to the firmware.
+            drop(unsafe { context_metadata.into_original_ptr() });
         }
+        result
     }
 
     /// Use [`BootServices::create_event_ex`] when possible.
@@ -1570,7 +1586,11 @@ mod tests {
     use efi::{Boolean, Char16, OpenProtocolInformationEntry, protocols::device_path};
 
     use super::*;
-    use core::{mem::MaybeUninit, slice, sync::atomic::AtomicUsize, sync::atomic::Ordering};
+    use core::{
+        mem::MaybeUninit,
+        slice,
+        sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    };
     use std::os::raw::c_void;
 
     macro_rules! boot_services {
@@ -1714,6 +1734,41 @@ mod tests {
         assert!(status.is_ok());
     }
 
+    #[test]
+    fn test_create_event_failure_drops_context() {
+        static DROPPED: AtomicBool = AtomicBool::new(false);
+
+        struct Context;
+        impl Drop for Context {
+            fn drop(&mut self) {
+                DROPPED.store(true, Ordering::SeqCst);
+            }
+        }
+
+        extern "efiapi" fn notify_callback(_e: efi::Event, _ctx: Box<Context>) {}
+
+        extern "efiapi" fn efi_create_event(
+            _event_type: u32,
+            _notify_tpl: efi::Tpl,
+            _notify_function: Option<efi::EventNotify>,
+            _notify_context: *mut c_void,
+            _event: *mut efi::Event,
+        ) -> efi::Status {
+            efi::Status::OUT_OF_RESOURCES
+        }
+
+        let boot_services = boot_services!(create_event = efi_create_event);
+        let status = boot_services.create_event(
+            EventType::NOTIFY_SIGNAL,
+            Tpl::CALLBACK,
+            Some(notify_callback),
+            Box::new(Context),
+        );
+
+        assert_eq!(status, Err(efi::Status::OUT_OF_RESOURCES));
+        assert!(DROPPED.load(Ordering::SeqCst));
+    }
+
     #[test]
     #[should_panic = "Boot services function create_event_ex is not initialized."]
     fn test_create_event_ex_not_init() {
@@ -7,10 +7,15 @@
 //! SPDX-License-Identifier: Apache-2.0
 //!
 
+extern crate alloc;
+
+use alloc::boxed::Box;
 use core::ops;
 
 use r_efi::efi;
 
+use super::{BootServices, tpl::Tpl};
+
 /// Function signature for event notify function.
 pub type EventNotifyCallback<T> = extern "efiapi" fn(efi::Event, T);
 
@@ -93,3 +98,352 @@ impl From<EventType> for u32 {
         val.0
     }
 }
+
+/// Notify function of an event created with [`EventBuilder`].
+pub type TypedEventNotify<C> = fn(efi::Event, &mut C);
+
+/// Builder for notification events whose context is a typed, owned value.
+///
+/// The context is moved into the event when it is created and passed by mutable reference to the notify function each
+/// time the event is notified. The context is dropped when the event is closed, either with [`TypedEvent::close`] or,
+/// for a [one shot](EventBuilder::one_shot) event, after its first notification.
+///
+/// ```rust,ignore
+/// struct Context {
+///     boot_services: StandardBootServices,
+///     count: usize,
+/// }
+///
+/// fn on_ready_to_boot(_event: efi::Event, context: &mut Context) {
+///     context.count += 1;
+/// }
+///
+/// EventBuilder::new(boot_services.clone(), EventType::NOTIFY_SIGNAL, Tpl::CALLBACK)
+///     .event_group(&EVENT_GROUP_READY_TO_BOOT)
+///     .one_shot()
+///     .create(on_ready_to_boot, Context { boot_services, count: 0 })?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct EventBuilder<BB> {
+    boot_services: BB,
+    event_type: EventType,
+    notify_tpl: Tpl,
+    event_group: Option<&'static efi::Guid>,
+}
+
+impl<BB> EventBuilder<BB>
+where
+    BB: Clone + 'static,
+{
+    /// Creates a builder for an event of the given type, notified at the given TPL.
+    ///
+    /// `boot_services` is used to create the event, and is kept by the event to close it.
+    pub fn new(boot_services: BB, event_type: EventType, notify_tpl: Tpl) -> Self {
+        Self { boot_services, event_type, notify_tpl, event_group: None }
+    }
+
+    /// Places the event in the given event group.
+    pub fn event_group(mut self, event_group: &'static efi::Guid) -> Self {
+        self.event_group = Some(event_group);
+        self
+    }
+
+    /// Closes the event after its first notification. The context is dropped once the event has been closed.
+    ///
+    /// The notify function must not close a one shot event itself.
+    pub fn one_shot(self) -> OneShotEventBuilder<BB> {
+        OneShotEventBuilder(self)
+    }
+
+    /// Creates the event with the given notify function and context.
+    ///
+    /// The context is dropped if the event could not be created.
+    pub fn create<B, C>(self, notify: TypedEventNotify<C>, context: C) -> Result<TypedEvent<BB, C>, efi::Status>
+    where
+        BB: AsRef<B>,
+        B: BootServices + 'static,
+        C: 'static,
+    {
+        self.create_event(notify, context, false).map(|(event, context)| TypedEvent { event, context })
+    }
+
+    fn create_event<B, C>(
+        self,
+        notify: TypedEventNotify<C>,
+        context: C,
+        one_shot: bool,
+    ) -> Result<(efi::Event, *mut EventContext<BB, C>), efi::Status>
+    where
+        BB: AsRef<B>,
+        B: BootServices + 'static,
+        C: 'static,
+    {
+        let mut event_context = Box::new(EventContext {
+            boot_services: self.boot_services.clone(),
+            close_event: close_event::<BB, B>,
+            notify,
+            context,
+            one_shot,
+        });
+        // The context is owned by the event once created, at the same address.
+        let context_ptr = &mut *event_context as *mut EventContext<BB, C>;
+
+        let event = match self.event_group {
+            Some(event_group) => self.boot_services.as_ref().create_event_ex(
+                self.event_type,
+                self.notify_tpl,
+                Some(event_notify::<BB, C>),
+                event_context,
+                event_group,
+            ),
+            None => self.boot_services.as_ref().create_event(
+                self.event_type,
+                self.notify_tpl,
+                Some(event_notify::<BB, C>),
+                event_context,
+            ),
+        }?;
+        Ok((event, context_ptr))
+    }
+}
+
+/// Builder for an event closed after its first notification, see [`EventBuilder::one_shot`].
+#[derive(Debug, Clone)]
+pub struct OneShotEventBuilder<BB>(EventBuilder<BB>);
+
+impl<BB> OneShotEventBuilder<BB>
+where
+    BB: Clone + 'static,
+{
+    /// Places the event in the given event group.
+    pub fn event_group(self, event_group: &'static efi::Guid) -> Self {
+        Self(self.0.event_group(event_group))
+    }
+
+    /// Creates the event with the given notify function and context.
+    ///
+    /// The context is dropped if the event could not be created.
+    pub fn create<B, C>(self, notify: TypedEventNotify<C>, context: C) -> Result<efi::Event, efi::Status>
+    where
+        BB: AsRef<B>,
+        B: BootServices + 'static,
+        C: 'static,
+    {
+        self.0.create_event(notify, context, true).map(|(event, _)| event)
+    }
+}
+
+/// An event created with [`EventBuilder::create`], owning its context.
+///
+/// [`TypedEvent::close`] closes the event and drops its context. An event that is not closed stays open, with its
+/// context, for the rest of boot.
+#[derive(Debug)]
+pub struct TypedEvent<BB, C> {
+    event: efi::Event,
+    context: *mut EventContext<BB, C>,
+}
+
+impl<BB, C> TypedEvent<BB, C> {
+    /// Returns the event handle, e.g. to signal the event or register it for protocol notifications.
+    pub fn event(&self) -> efi::Event {
+        self.event
+    }
+
+    /// Returns the notify function of the event.
+    pub fn notify(&self) -> TypedEventNotify<C> {
+        // SAFETY: the context is owned by the event, which is open as long as self exists.
+        unsafe { (*self.context).notify }
+    }
+
+    /// Closes the event and drops its context.
+    pub fn close<B>(self) -> Result<(), efi::Status>
+    where
+        BB: AsRef<B>,
+        B: BootServices,
+    {
+        // SAFETY: the context is owned by the event, which is open as long as self exists.
+        unsafe { (*self.context).boot_services.as_ref() }.close_event(self.event)?;
+        // SAFETY: the event is closed, so the context can no longer be accessed by the firmware.
+        drop(unsafe { Box::from_raw(self.context) });
+        Ok(())
+    }
+}
+
+/// Context registered with the firmware for events created with [`EventBuilder`].
+pub struct EventContext<BB, C> {
+    boot_services: BB,
+    close_event: fn(&BB, efi::Event),
+    notify: TypedEventNotify<C>,
+    context: C,
+    one_shot: bool,
+}
+
+impl<BB, C> EventContext<BB, C> {
+    /// Returns the notify function called with the context.
+    pub fn notify(&self) -> TypedEventNotify<C> {
+        self.notify
+    }
+}
+
+fn close_event<BB: AsRef<B>, B: BootServices>(boot_services: &BB, event: efi::Event) {
+    if let Err(status) = boot_services.as_ref().close_event(event) {
+        log::error!("Failed to close one shot event {event:?}: {status:?}");
+    }
+}
+
+extern "efiapi" fn event_notify<BB, C>(event: efi::Event, mut event_context: Box<EventContext<BB, C>>) {
+    (event_context.notify)(event, &mut event_context.context);
+
+    if event_context.one_shot {
+        (event_context.close_event)(&event_context.boot_services, event);
+        // The event is closed, so the context can no longer be accessed by the firmware and is dropped here.
+        drop(event_context);
+    } else {
+        // The context is owned by the event, which may be notified again until it is closed.
+        let _ = Box::into_raw(event_context);
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use crate::boot_services::MockBootServices;
+    use alloc::rc::Rc;
+    use core::cell::Cell;
+
+    static EVENT_GROUP: efi::Guid = efi::Guid::from_fields(0, 0, 0, 0, 0, &[0, 0, 0, 0, 0, 1]);
+
+    type TestEventContext = EventContext<Rc<MockBootServices>, Context>;
+    type Registration = Rc<Cell<Option<(EventNotifyCallback<Box<TestEventContext>>, *mut TestEventContext)>>>;
+
+    struct Context {
+        notified: Rc<Cell<usize>>,
+        dropped: Rc<Cell<bool>>,
+    }
+
+    impl Drop for Context {
+        fn drop(&mut self) {
+            self.dropped.set(true);
+        }
+    }
+
+    fn notify(_event: efi::Event, context: &mut Context) {
+        context.notified.set(context.notified.get() + 1);
+    }
+
+    fn context() -> (Context, Rc<Cell<usize>>, Rc<Cell<bool>>) {
+        let notified = Rc::new(Cell::new(0));
+        let dropped = Rc::new(Cell::new(false));
+        (Context { notified: notified.clone(), dropped: dropped.clone() }, notified, dropped)
+    }
+
+    // Notifies the event as the firmware would, with the registered callback and context.
+    fn signal(registration: &Registration) {
+        let (callback, context) = registration.get().unwrap();
+        callback(1_usize as efi::Event, unsafe { Box::from_raw(context) });
+    }
+
+    #[test]
+    fn test_one_shot_event_is_closed_and_context_dropped() {
+        let registration = Registration::default();
+        let mut boot_services = MockBootServices::new();
+        let registered = registration.clone();
+        boot_services
+            .expect_create_event_ex::<Box<TestEventContext>>()
+            .once()
+            .withf_st(|event_type, notify_tpl, _, _, event_group| {
+                *event_type == EventType::NOTIFY_SIGNAL && *notify_tpl == Tpl::CALLBACK && *event_group == EVENT_GROUP
+            })
+            .returning_st(move |_, _, notify_function, notify_context, _| {
+                registered.set(Some((notify_function.unwrap(), Box::into_raw(notify_context))));
+                Ok(1_usize as efi::Event)
+            });
+        boot_services.expect_close_event().once().return_const(Ok(()));
+        let boot_services = Rc::new(boot_services);
+
+        let (context, notified, dropped) = context();
+        let event = EventBuilder::new(boot_services, EventType::NOTIFY_SIGNAL, Tpl::CALLBACK)
+            .event_group(&EVENT_GROUP)
+            .one_shot()
+            .create(notify, context);
+        assert_eq!(event, Ok(1_usize as efi::Event));
+        assert!(!dropped.get());
+
+        signal(&registration);
+        assert_eq!(notified.get(), 1);
+        assert!(dropped.get());
+    }
+
+    #[test]
+    fn test_event_context_persists_across_notifications_until_closed() {
+        let registration = Registration::default();
+        let mut boot_services = MockBootServices::new();
+        let registered = registration.clone();
+        boot_services.expect_create_event::<Box<TestEventContext>>().once().returning_st(
+            move |_, _, notify_function, notify_context| {
+                registered.set(Some((notify_function.unwrap(), Box::into_raw(notify_context))));
+                Ok(1_usize as efi::Event)
+            },
+        );
+        boot_services.expect_close_event().once().withf(|event| *event == 1_usize as efi::Event).return_const(Ok(()));
+        let boot_services = Rc::new(boot_services);
+
+        let (context, notified, dropped) = context();
+        let event =
+            EventBuilder::new(boot_services, EventType::NOTIFY_SIGNAL, Tpl::NOTIFY).create(notify, context).unwrap();
+        assert_eq!(event.event(), 1_usize as efi::Event);
+        assert_eq!(event.notify() as usize, notify as TypedEventNotify<Context> as usize);
+
+        signal(&registration);
+        signal(&registration);
+        assert_eq!(notified.get(), 2);
+        assert!(!dropped.get());
+
+        assert_eq!(event.close(), Ok(()));
+        assert!(dropped.get());
+    }
+
+    #[test]
+    fn test_context_kept_when_event_close_fails() {
+        let registration = Registration::default();
+        let mut boot_services = MockBootServices::new();
+        let registered = registration.clone();
+        boot_services.expect_create_event::<Box<TestEventContext>>().once().returning_st(
+            move |_, _, notify_function, notify_context| {
+                registered.set(Some((notify_function.unwrap(), Box::into_raw(notify_context))));
+                Ok(1_usize as efi::Event)
+            },
+        );
+        boot_services.expect_close_event().once().return_const(Err(efi::Status::INVALID_PARAMETER));
+        let boot_services = Rc::new(boot_services);
+
+        let (context, _, dropped) = context();
+        let event =
+            EventBuilder::new(boot_services, EventType::NOTIFY_SIGNAL, Tpl::NOTIFY).create(notify, context).unwrap();
+
+        // The event is still open, so its context stays owned by the firmware.
+        assert_eq!(event.close(), Err(efi::Status::INVALID_PARAMETER));
+        assert!(!dropped.get());
+
+        drop(unsafe { Box::from_raw(registration.get().unwrap().1) });
+        assert!(dropped.get());
+    }
+
+    #[test]
+    fn test_context_dropped_when_event_creation_fails() {
+        let mut boot_services = MockBootServices::new();
+        boot_services
+            .expect_create_event::<Box<TestEventContext>>()
+            .once()
+            .returning_st(|_, _, _, _| Err(efi::Status::OUT_OF_RESOURCES));
+        let boot_services = Rc::new(boot_services);
+
+        let (context, notified, dropped) = context();
+        let event =
+            EventBuilder::new(boot_services, EventType::NOTIFY_SIGNAL, Tpl::NOTIFY).one_shot().create(notify, context);
+        assert_eq!(event, Err(efi::Status::OUT_OF_RESOURCES));
+        assert_eq!(notified.get(), 0);
+        assert!(dropped.get());
+    }
+}
@@ -132,7 +132,8 @@ mod struct_component;
 
 use crate::error::Result;
 
-pub use metadata::MetaData;
+pub use metadata::{Dependency, DependencyKind, MetaData};
+pub use storage::ExitBootServicesCallback;
 pub use storage::Storage;
 pub use storage::UnsafeStorageCell;
 
@@ -143,6 +144,10 @@ pub use struct_component::StructComponent;
 
 pub use patina_macro::IntoComponent;
 
+/// The boot mode of the PHIT HOB, available to components as a [Config](params::Config) and used by the
+/// `#[skip_boot_modes(...)]` attribute of [IntoComponent].
+pub use patina_pi::BootMode;
+
 /// An executable object whose parameters implement [Param](params::Param).
 pub trait Component {
     /// Runs the component when it does not have exclusive access to the storage.
@@ -4,14 +4,16 @@
 //! injectable [Param] implementation that allows components to access read-only HOB (Hand off Block) values. See the
 //! types for more documentation.
 //!
-//! The [FromHob] trait is used to parse guided HOBs as specified in the PI specification.
+//! The [FromHob] trait is used to parse guided HOBs as specified in the PI specification. Implementors may also
+//! provide a [FromHob::validate] method, which is used by the [ValidatedHob] [Param] to prevent a component from
+//! running when any instance of a platform produced HOB is malformed.
 //!
 //! ## Example
 //!
 //! ```rust
 //! use patina::{
 //!    error::Result,
-//!    component::hob::{Hob, FromHob},
+//!    component::hob::{Hob, FromHob, ValidatedHob},
 //!    Guid, OwnedGuid
 //! };
 //!
@@ -50,6 +52,11 @@
 //! fn my_other_component(_hob: Option<Hob<MyHobStruct>>) -> Result<()> {
 //!     Ok(())
 //! }
+//!
+//! /// A component that will only run if the HOB was produced and every instance passed validation.
+//! fn my_validated_component(_hob: ValidatedHob<MyComplexHobStruct>) -> Result<()> {
+//!     Ok(())
+//! }
 //! ```
 //!
 //! ## License
@@ -62,10 +69,10 @@ extern crate alloc;
 use alloc::{boxed::Box, vec::Vec};
 
 use crate::OwnedGuid;
-use core::{any::Any, ops::Deref};
+use core::{any::Any, fmt, ops::Deref};
 
 use super::{
-    metadata::MetaData,
+    metadata::{DependencyKind, MetaData},
     params::Param,
     storage::{Storage, UnsafeStorageCell},
 };
@@ -119,6 +126,28 @@ pub trait FromHob: Sized + 'static {
 
     /// Parses the byte array into the type implementing this trait.
     fn parse(bytes: &[u8]) -> Self;
+
+    /// Validates a parsed instance of the HOB, returning a description of the problem if it is malformed.
+    ///
+    /// Defaults to accepting all instances. This is only consulted by [ValidatedHob] and [Hob::validated].
+    fn validate(&self) -> core::result::Result<(), &'static str> {
+        Ok(())
+    }
+}
+
+/// An error describing a single HOB instance that failed [FromHob::validate].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HobValidationError {
+    /// The index of the failing instance, in the order the HOBs were produced.
+    pub index: usize,
+    /// The reason returned by [FromHob::validate].
+    pub reason: &'static str,
+}
+
+impl fmt::Display for HobValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "instance {}: {}", self.index, self.reason)
+    }
 }
 
 pub use patina_macro::FromHob;
@@ -198,6 +227,21 @@ impl<'h, T: FromHob + 'static> Hob<'h, T> {
     pub fn iter(&self) -> HobIter<'h, T> {
         HobIter { inner: self.value.iter(), _marker: core::marker::PhantomData }
     }
+
+    /// Validates every instance of the Hob with [FromHob::validate].
+    ///
+    /// Returns all instances if each one is valid, otherwise returns an error for every instance that failed.
+    pub fn validated(&self) -> core::result::Result<Vec<&'h T>, Vec<HobValidationError>> {
+        let mut errors = Vec::new();
+        let mut values = Vec::with_capacity(self.value.len());
+        for (index, value) in self.iter().enumerate() {
+            match value.validate() {
+                Ok(()) => values.push(value),
+                Err(reason) => errors.push(HobValidationError { index, reason }),
+            }
+        }
+        if errors.is_empty() { Ok(values) } else { Err(errors) }
+    }
 }
 
 impl<'h, T: FromHob + 'static> From<&'h [Box<dyn Any>]> for Hob<'h, T> {
@@ -230,12 +274,98 @@ unsafe impl<T: FromHob + 'static> Param for Hob<'_, T> {
         !unsafe { storage.storage() }.get_raw_hob(*state).is_empty()
     }
 
-    fn init_state(storage: &mut Storage, _meta: &mut MetaData) -> Self::State {
+    fn init_state(storage: &mut Storage, meta: &mut MetaData) -> Self::State {
+        meta.add_consumed::<T>(DependencyKind::Hob);
         storage.add_hob_parser::<T>();
         storage.register_hob::<T>()
     }
 }
 
+/// A [Hob] whose instances have all passed [FromHob::validate].
+///
+/// This [Param] is only available if at least one instance of the HOB was produced and every instance is valid. Each
+/// instance that fails validation is logged with its index and reason, and the component is not run. Wrapping the
+/// param in an [Option] allows the component to run without the HOB instead.
+///
+/// The underlying [Hob] can be accessed by dereferencing the struct, so the first instance can be accessed directly
+/// and all instances can be iterated over with [Hob::iter].
+///
+/// ## Example
+///
+/// ```rust
+/// # use patina::component::hob::{FromHob, ValidatedHob};
+/// # struct MyStruct{ value: u32 };
+/// # impl FromHob for MyStruct {
+/// #     const HOB_GUID: patina::OwnedGuid = patina::Guid::from_fields(0, 0, 0, 0, 0, [0; 6]);
+/// #     fn parse(bytes: &[u8]) -> Self {
+/// #         MyStruct { value: 5 }
+/// #     }
+/// # }
+/// fn my_component(hob: ValidatedHob<MyStruct>) {
+///     let first_value = hob.value;
+///     for value in hob.iter() {
+///         // Every value has been validated.
+///     }
+/// }
+/// ```
+pub struct ValidatedHob<'h, T: FromHob + 'static>(Hob<'h, T>);
+
+impl<'h, T: FromHob + 'static> ValidatedHob<'h, T> {
+    /// Creates an instance of ValidatedHob by leaking the provided value into static memory.
+    ///
+    /// This function is intended for testing purposes only. The values are not validated. See [Hob::mock].
+    pub fn mock(value: Vec<T>) -> Self {
+        Self(Hob::mock(value))
+    }
+
+    /// Consumes the ValidatedHob, returning the underlying [Hob].
+    pub fn into_inner(self) -> Hob<'h, T> {
+        self.0
+    }
+}
+
+impl<'h, T: FromHob + 'static> Deref for ValidatedHob<'h, T> {
+    type Target = Hob<'h, T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+unsafe impl<T: FromHob + 'static> Param for ValidatedHob<'_, T> {
+    type State = usize;
+    type Item<'storage, 'state> = ValidatedHob<'storage, T>;
+
+    unsafe fn get_param<'storage, 'state>(
+        lookup_id: &'state Self::State,
+        storage: UnsafeStorageCell<'storage>,
+    ) -> Self::Item<'storage, 'state> {
+        ValidatedHob(unsafe { Hob::get_param(lookup_id, storage) })
+    }
+
+    fn validate(state: &Self::State, storage: UnsafeStorageCell) -> bool {
+        if !Hob::<T>::validate(state, storage) {
+            return false;
+        }
+
+        // SAFETY: accesses are correctly registered with storage, no conflicts
+        let hob = unsafe { Hob::<T>::get_param(state, storage) };
+        match hob.validated() {
+            Ok(_) => true,
+            Err(errors) => {
+                for error in errors {
+                    log::error!("Guided HOB [{}] failed validation: {error}", core::any::type_name::<T>());
+                }
+                false
+            }
+        }
+    }
+
+    fn init_state(storage: &mut Storage, meta: &mut MetaData) -> Self::State {
+        Hob::<T>::init_state(storage, meta)
+    }
+}
+
 /// An iterator of the underlying values of the Hob.
 ///
 /// ## Example
@@ -388,4 +518,71 @@ mod tests {
                 .is_err_and(|e| e == EfiError::InvalidParameter)
         );
     }
+
+    #[derive(Default)]
+    struct MyValidatedStruct {
+        data: u32,
+    }
+
+    impl FromHob for MyValidatedStruct {
+        const HOB_GUID: OwnedGuid = Guid::ZERO;
+
+        fn parse(bytes: &[u8]) -> Self {
+            MyValidatedStruct { data: bytes[0] as u32 }
+        }
+
+        fn validate(&self) -> core::result::Result<(), &'static str> {
+            if self.data == 0 { Err("value must be non-zero") } else { Ok(()) }
+        }
+    }
+
+    #[test]
+    fn test_validated_reports_every_invalid_instance() {
+        let hobs = Hob::mock(vec![MyStruct { unused: 0 }]);
+        assert_eq!(hobs.validated().map(|v| v.len()), Ok(1));
+
+        let hobs = Hob::mock(vec![
+            MyValidatedStruct { data: 0 },
+            MyValidatedStruct { data: 1 },
+            MyValidatedStruct { data: 0 },
+        ]);
+        assert_eq!(
+            hobs.validated().err(),
+            Some(vec![
+                HobValidationError { index: 0, reason: "value must be non-zero" },
+                HobValidationError { index: 2, reason: "value must be non-zero" },
+            ])
+        );
+        assert_eq!(
+            HobValidationError { index: 2, reason: "value must be non-zero" }.to_string(),
+            "instance 2: value must be non-zero"
+        );
+
+        let hobs = Hob::mock(vec![MyValidatedStruct { data: 1 }, MyValidatedStruct { data: 2 }]);
+        let values = hobs.validated().unwrap();
+        assert_eq!(values.iter().map(|v| v.data).collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_validated_hob_param() {
+        let mut storage = Storage::new();
+        let mut meta = MetaData::new::<MyValidatedStruct>();
+
+        let id = ValidatedHob::<MyValidatedStruct>::init_state(&mut storage, &mut meta);
+        assert!(!ValidatedHob::<MyValidatedStruct>::validate(&id, UnsafeStorageCell::from(&storage)));
+
+        MyValidatedStruct::register(&[5], &mut storage);
+        assert!(ValidatedHob::<MyValidatedStruct>::validate(&id, UnsafeStorageCell::from(&storage)));
+
+        let hob = unsafe { ValidatedHob::<MyValidatedStruct>::get_param(&id, UnsafeStorageCell::from(&storage)) };
+        assert_eq!(hob.data, 5);
+        assert_eq!(hob.into_inner().iter().count(), 1);
+
+        MyValidatedStruct::register(&[0], &mut storage);
+        assert!(!ValidatedHob::<MyValidatedStruct>::validate(&id, UnsafeStorageCell::from(&storage)));
+        assert!(
+            unsafe { Option::<ValidatedHob<MyValidatedStruct>>::get_param(&id, UnsafeStorageCell::from(&storage)) }
+                .is_none()
+        );
+    }
 }
@@ -3,14 +3,47 @@
 //! The metadata is used by the scheduler for multiple purposes including, but not limited to:
 //! - Managing access requirements for components.
 //! - Logging and debugging.
+//! - Diagnostics of the configs, HOBs and services consumed by each component.
 //!
 //! ## License
 //!
 //! Copyright (c) Microsoft Corporation.
 //!
 //! SPDX-License-Identifier: Apache-2.0
+extern crate alloc;
+
+use alloc::vec::Vec;
 use core::fmt;
 use fixedbitset::FixedBitSet;
+use patina_pi::BootMode;
+
+/// The kind of a datum consumed or produced by a component.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DependencyKind {
+    /// A [Config](super::params::Config) value.
+    Config,
+    /// A [ConfigMut](super::params::ConfigMut) value.
+    ConfigMut,
+    /// A [Hob](super::hob::Hob) value.
+    Hob,
+    /// A [Service](super::service::Service).
+    Service,
+}
+
+/// A datum consumed or produced by a component, identified by the name of its type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dependency {
+    /// The kind of the datum.
+    pub kind: DependencyKind,
+    /// The name of the type of the datum, including the module path.
+    pub type_name: &'static str,
+}
+
+impl fmt::Display for Dependency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}<{}>", self.kind, self.type_name)
+    }
+}
 
 /// Metadata for a component. Not used for execution, but referenced by the scheduler.
 #[derive(Default, Debug)]
@@ -21,12 +54,22 @@ pub struct MetaData {
     name: &'static str,
     /// the name of the last param that failed to be set.
     last_failed_param: Option<&'static str>,
+    /// The configs, HOBs and services consumed by the component.
+    consumed: Vec<Dependency>,
+    /// The boot modes in which the component is not dispatched.
+    skipped_boot_modes: Vec<BootMode>,
 }
 
 impl MetaData {
     /// Creates a new metadata object for a component.
     pub fn new<S>() -> Self {
-        Self { access: Access::new(), name: core::any::type_name::<S>(), last_failed_param: None }
+        Self {
+            access: Access::new(),
+            name: core::any::type_name::<S>(),
+            last_failed_param: None,
+            consumed: Vec::new(),
+            skipped_boot_modes: Vec::new(),
+        }
     }
 
     /// Returns the name of the component, including the module path.
@@ -47,6 +90,34 @@ impl MetaData {
         self.last_failed_param
     }
 
+    /// Registers a datum of type `T` consumed by the component.
+    pub fn add_consumed<T: ?Sized>(&mut self, kind: DependencyKind) {
+        self.consumed.push(Dependency { kind, type_name: core::any::type_name::<T>() });
+    }
+
+    /// Returns the configs, HOBs and services consumed by the component, in the order of its params.
+    #[inline(always)]
+    pub fn consumed(&self) -> &[Dependency] {
+        &self.consumed
+    }
+
+    /// Sets the boot modes in which the component is not dispatched.
+    pub fn set_skipped_boot_modes(&mut self, modes: &[BootMode]) {
+        self.skipped_boot_modes = modes.to_vec();
+    }
+
+    /// Returns the boot modes in which the component is not dispatched.
+    #[inline(always)]
+    pub fn skipped_boot_modes(&self) -> &[BootMode] {
+        &self.skipped_boot_modes
+    }
+
+    /// Returns whether the component is not dispatched in the boot mode `mode`.
+    #[inline(always)]
+    pub fn skips_boot_mode(&self, mode: BootMode) -> bool {
+        self.skipped_boot_modes.contains(&mode)
+    }
+
     /// Returns mutable access to the param usage metadata for the component.
     #[inline(always)]
     pub(crate) fn access_mut(&mut self) -> &mut Access {
@@ -195,6 +266,34 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_consumed_dependencies_are_recorded_in_order() {
+        let mut metadata = MetaData::new::<u32>();
+        metadata.add_consumed::<u64>(DependencyKind::Config);
+        metadata.add_consumed::<dyn fmt::Debug>(DependencyKind::Service);
+
+        assert_eq!(
+            metadata.consumed(),
+            [
+                Dependency { kind: DependencyKind::Config, type_name: "u64" },
+                Dependency { kind: DependencyKind::Service, type_name: "dyn core::fmt::Debug" },
+            ]
+        );
+        assert_eq!(std::format!("{}", metadata.consumed()[0]), "Config<u64>");
+    }
+
+    #[test]
+    fn test_skipped_boot_modes() {
+        let mut metadata = MetaData::new::<u32>();
+        assert!(!metadata.skips_boot_mode(BootMode::BootOnS3Resume));
+
+        metadata.set_skipped_boot_modes(&[BootMode::BootOnS3Resume, BootMode::BootInRecoveryMode]);
+        assert_eq!(metadata.skipped_boot_modes(), [BootMode::BootOnS3Resume, BootMode::BootInRecoveryMode]);
+        assert!(metadata.skips_boot_mode(BootMode::BootOnS3Resume));
+        assert!(metadata.skips_boot_mode(BootMode::BootInRecoveryMode));
+        assert!(!metadata.skips_boot_mode(BootMode::BootWithFullConfiguration));
+    }
+
     #[test]
     fn test_write_config_marks_as_read_also() {
         let mut access = Access::new();
@@ -97,6 +97,7 @@
 extern crate alloc;
 
 use core::{
+    any::type_name,
     cell::{Ref, RefCell, RefMut},
     marker::PhantomData,
     ops::{Deref, DerefMut},
@@ -107,10 +108,11 @@ use alloc::boxed::Box;
 use crate::{
     boot_services::StandardBootServices,
     component::{
-        metadata::MetaData,
+        metadata::{Dependency, DependencyKind, MetaData},
         service::IntoService,
         storage::{Deferred, Storage, UnsafeStorageCell},
     },
+    dxe_services::StandardDxeServices,
     runtime_services::StandardRuntimeServices,
 };
 
@@ -428,6 +430,7 @@ unsafe impl<T: Default + 'static> Param for Config<'_, T> {
         );
 
         meta.access_mut().add_config_read(id);
+        meta.add_consumed::<T>(DependencyKind::Config);
         id
     }
 }
@@ -543,6 +546,7 @@ unsafe impl<T: Default + 'static> Param for ConfigMut<'_, T> {
         );
 
         meta.access_mut().add_config_write(id);
+        meta.add_consumed::<T>(DependencyKind::ConfigMut);
         id
     }
 }
@@ -560,20 +564,52 @@ unsafe impl<T: Default + 'static> Param for ConfigMut<'_, T> {
 /// ``Config<i32>`` parameter.
 pub struct Commands<'storage> {
     queue: &'storage mut Deferred,
+    component: &'static str,
 }
 
 impl Commands<'_> {
     /// Adds a config to storage sometime after the component has been executed.
     pub fn add_config<C: Default + 'static>(&mut self, config: C) {
+        let component = self.component;
         self.queue.add_command(move |storage| {
             storage.add_config(config);
+            storage.add_produced(component, Dependency { kind: DependencyKind::Config, type_name: type_name::<C>() });
         });
     }
 
     /// Adds a service to storage sometime after the component has been executed.
     pub fn add_service<S: IntoService + 'static>(&mut self, service: S) {
+        let component = self.component;
         self.queue.add_command(move |storage| {
             storage.add_service(service);
+            storage.add_produced(component, Dependency { kind: DependencyKind::Service, type_name: type_name::<S>() });
+        });
+    }
+
+    /// Registers a callback to execute when ExitBootServices is called, sometime after the component has been executed.
+    ///
+    /// This is where a component tears down what it set up for boot services, e.g. stops its devices, before the OS
+    /// takes control. Callbacks are executed once, before the boot services are terminated, in the reverse order the
+    /// components were dispatched. They must not allocate or free memory, as this would change the memory map the OS
+    /// loader is exiting the boot services with.
+    ///
+    /// Once the callbacks are executed, the DXE core closes the events the component created while it was executed,
+    /// and reports the boot services pages it allocated while it was executed and did not free.
+    ///
+    /// ## Example
+    ///
+    /// ``` rust
+    /// use patina::component::params::Commands;
+    ///
+    /// fn my_component(mut commands: Commands) {
+    ///     // Set up a device ...
+    ///     commands.on_exit_boot_services(|| log::info!("Stopping the device."));
+    /// }
+    /// ```
+    pub fn on_exit_boot_services<F: FnOnce() + 'static>(&mut self, callback: F) {
+        let component = self.component;
+        self.queue.add_command(move |storage| {
+            storage.add_exit_boot_services_callback(component, callback);
         });
     }
 
@@ -597,7 +633,7 @@ impl Commands<'_> {
     /// ```
     #[allow(clippy::test_attr_in_doctest)]
     pub fn mock() -> Self {
-        Commands { queue: Box::leak(Box::new(Deferred::default())) }
+        Commands { queue: Box::leak(Box::new(Deferred::default())), component: "mock" }
     }
 
     /// Returns if the queue is empty.
@@ -608,15 +644,16 @@ impl Commands<'_> {
 }
 
 unsafe impl Param for Commands<'_> {
-    type State = ();
+    // The name of the component.
+    type State = &'static str;
     type Item<'storage, 'state> = Commands<'storage>;
 
     /// SAFETY: Deferred access is properly registered with the component's metadata.
     unsafe fn get_param<'storage, 'state>(
-        _state: &'state Self::State,
+        state: &'state Self::State,
         storage: UnsafeStorageCell<'storage>,
     ) -> Self::Item<'storage, 'state> {
-        Commands { queue: unsafe { storage.storage_mut().deferred() } }
+        Commands { queue: unsafe { storage.storage_mut().deferred() }, component: state }
     }
 
     fn validate(_state: &Self::State, _storage: UnsafeStorageCell) -> bool {
@@ -630,6 +667,7 @@ unsafe impl Param for Commands<'_> {
             meta.name(),
         );
         meta.access_mut().deferred();
+        meta.name()
     }
 }
 
@@ -669,6 +707,24 @@ unsafe impl Param for StandardRuntimeServices {
     fn init_state(_storage: &mut Storage, _meta: &mut MetaData) -> Self::State {}
 }
 
+unsafe impl Param for StandardDxeServices {
+    type State = ();
+    type Item<'storage, 'state> = Self;
+
+    unsafe fn get_param<'state>(
+        _state: &'state Self::State,
+        storage: UnsafeStorageCell<'_>,
+    ) -> Self::Item<'static, 'state> {
+        StandardDxeServices::clone(unsafe { storage.storage().dxe_services() })
+    }
+
+    fn validate(_state: &Self::State, storage: UnsafeStorageCell) -> bool {
+        unsafe { storage.storage() }.dxe_services().is_init()
+    }
+
+    fn init_state(_storage: &mut Storage, _meta: &mut MetaData) -> Self::State {}
+}
+
 macro_rules! impl_component_param_tuple {
     ($($param: ident), *) => {
         #[allow(non_snake_case)]
@@ -913,6 +969,7 @@ mod tests {
 
         // ConfigMut will keep config unlocked
         let id = ConfigMut::<i32>::init_state(&mut storage, &mut mock_metadata);
+        assert_eq!(mock_metadata.consumed(), [Dependency { kind: DependencyKind::ConfigMut, type_name: "i32" }]);
 
         // Trying to access it with config, validation should fail because it is unlocked.
         assert!(
@@ -1070,6 +1127,38 @@ mod tests {
         let _ = unsafe { <StandardRuntimeServices as Param>::get_param(&(), cell_storage) };
     }
 
+    #[test]
+    fn test_dxe_services_fails_to_validate_when_null() {
+        let mut storage = Storage::default(); // dxe_services is an empty pointer
+        let mut mock_metadata = MetaData::new::<i32>();
+
+        <StandardDxeServices as Param>::init_state(&mut storage, &mut mock_metadata);
+        assert_eq!(
+            Err("patina::dxe_services::StandardDxeServices"),
+            <StandardDxeServices as Param>::try_validate(&(), (&storage).into())
+        );
+    }
+
+    #[test]
+    fn test_dxe_services_can_be_retrieved() {
+        let mut storage = Storage::default();
+        let mut mock_metadata = MetaData::new::<i32>();
+
+        #[allow(invalid_value)]
+        let efi_ds = core::mem::MaybeUninit::<patina_pi::dxe_services::DxeServicesTable>::zeroed();
+
+        let ds = unsafe { StandardDxeServices::new(&*efi_ds.as_ptr()) };
+
+        storage.set_dxe_services(ds);
+
+        <StandardDxeServices as Param>::init_state(&mut storage, &mut mock_metadata);
+        assert!(<StandardDxeServices as Param>::try_validate(&(), (&storage).into()).is_ok());
+
+        let cell_storage = UnsafeStorageCell::new_mutable(&mut storage);
+        // does not panic
+        let _ = unsafe { <StandardDxeServices as Param>::get_param(&(), cell_storage) };
+    }
+
     #[test]
     fn test_option_returns_none_when_underlying_param_is_unavailable() {
         let mut storage = Storage::default();
@@ -1113,20 +1202,37 @@ mod tests {
         let mut mock_metadata = MetaData::new::<i32>();
 
         {
-            <Commands as Param>::init_state(&mut storage, &mut mock_metadata);
-            assert!(<Commands as Param>::try_validate(&(), (&storage).into()).is_ok());
+            let state = <Commands as Param>::init_state(&mut storage, &mut mock_metadata);
+            assert_eq!(state, "i32");
+            assert!(<Commands as Param>::try_validate(&state, (&storage).into()).is_ok());
 
             let cell_storage = UnsafeStorageCell::new_mutable(&mut storage);
-            let mut commands = unsafe { <Commands as Param>::get_param(&(), cell_storage) };
+            let mut commands = unsafe { <Commands as Param>::get_param(&state, cell_storage) };
             assert!(commands.is_empty());
             commands.add_config(42i32);
         }
 
         let cell_storage = UnsafeStorageCell::new_mutable(&mut storage);
-        let commands = unsafe { <Commands as Param>::get_param(&(), cell_storage) };
+        let commands = unsafe { <Commands as Param>::get_param(&"i32", cell_storage) };
         assert!(!commands.is_empty());
     }
 
+    #[test]
+    fn test_commands_register_exit_boot_services_callbacks() {
+        let mut storage = Storage::default();
+        let mut mock_metadata = MetaData::new::<i32>();
+        let state = <Commands as Param>::init_state(&mut storage, &mut mock_metadata);
+
+        let cell_storage = UnsafeStorageCell::new_mutable(&mut storage);
+        let mut commands = unsafe { <Commands as Param>::get_param(&state, cell_storage) };
+        commands.on_exit_boot_services(|| ());
+        assert!(!commands.is_empty());
+
+        let callbacks = storage.take_exit_boot_services_callbacks();
+        assert_eq!(callbacks.len(), 1);
+        assert_eq!(callbacks[0].component(), "i32");
+    }
+
     #[test]
     fn test_deferred_commands_are_applied() {
         trait TestService {
@@ -1167,6 +1273,13 @@ mod tests {
         storage.apply_deferred();
         assert_eq!(*(storage.get_config::<i32>().unwrap()), 42);
         assert!(storage.get_service::<dyn TestService>().is_some());
+
+        let produced = storage.produced();
+        assert_eq!(produced.len(), 2);
+        assert!(produced.iter().all(|(name, _)| *name == component.metadata().name()));
+        assert_eq!(produced[0].1, Dependency { kind: DependencyKind::Config, type_name: "i32" });
+        assert_eq!(produced[1].1.kind, DependencyKind::Service);
+        assert!(produced[1].1.type_name.ends_with("TestServiceImpl"));
     }
 
     #[test]
@@ -121,12 +121,13 @@ use alloc::boxed::Box;
 use core::{any::Any, cell::OnceCell, marker::PhantomData, ops::Deref};
 
 use crate::component::{
-    metadata::MetaData,
+    metadata::{DependencyKind, MetaData},
     params::Param,
     storage::{Storage, UnsafeStorageCell},
 };
 
 pub mod memory;
+pub mod memory_protection;
 
 pub use patina_macro::IntoService;
 
@@ -335,7 +336,8 @@ unsafe impl<T: ?Sized + 'static> Param for Service<T> {
         unsafe { storage.storage() }.get_raw_service(*state).is_some()
     }
 
-    fn init_state(storage: &mut Storage, _meta: &mut MetaData) -> Self::State {
+    fn init_state(storage: &mut Storage, meta: &mut MetaData) -> Self::State {
+        meta.add_consumed::<T>(DependencyKind::Service);
         storage.register_service::<T>()
     }
 }
@@ -0,0 +1,243 @@
+//! Memory Protection Special Region Service Definitions.
+//!
+//! Some platforms need ranges exempted from the memory protection policies of the core, such as a legacy option ROM
+//! shadow that is executed in place, or a vendor mailbox that must stay accessible when the core would otherwise leave
+//! a guard page over it. These ranges are registered as special regions, either before DXE with a
+//! [SpecialRegionHob], or at runtime with the [SpecialRegions] service.
+//!
+//! Each registration records the requester and the reason for the exemption. The core reports every special region in
+//! its diagnostics, so that an exemption can always be traced back to the platform code that asked for it.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use core::fmt;
+
+use scroll::Pread;
+
+use crate::{Guid, OwnedGuid, base::UEFI_PAGE_SIZE, component::hob::FromHob};
+
+use super::memory::MemoryError;
+
+#[cfg(any(test, feature = "mockall"))]
+use mockall::automock;
+
+/// The memory protection policies a special region is exempt from.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ProtectionExemptions {
+    /// The region is never mapped non-executable.
+    pub no_execute: bool,
+    /// The region is never unmapped as a guard, read protected pages stay mapped instead.
+    pub guard: bool,
+}
+
+impl ProtectionExemptions {
+    /// The bit for [ProtectionExemptions::no_execute] in [SpecialRegionHob::exemptions].
+    pub const NO_EXECUTE: u32 = 0x1;
+    /// The bit for [ProtectionExemptions::guard] in [SpecialRegionHob::exemptions].
+    pub const GUARD: u32 = 0x2;
+
+    /// Returns the exemptions of the bits, ignoring unknown bits.
+    pub const fn from_bits(bits: u32) -> Self {
+        Self { no_execute: bits & Self::NO_EXECUTE != 0, guard: bits & Self::GUARD != 0 }
+    }
+
+    /// Returns the bits of the exemptions.
+    pub const fn bits(&self) -> u32 {
+        (if self.no_execute { Self::NO_EXECUTE } else { 0 }) | (if self.guard { Self::GUARD } else { 0 })
+    }
+
+    /// Returns true if the region is exempt from no policy.
+    pub const fn is_empty(&self) -> bool {
+        !self.no_execute && !self.guard
+    }
+
+    /// Returns the exemptions of either `self` or `other`.
+    pub const fn union(self, other: Self) -> Self {
+        Self { no_execute: self.no_execute || other.no_execute, guard: self.guard || other.guard }
+    }
+}
+
+impl fmt::Display for ProtectionExemptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match (self.no_execute, self.guard) {
+            (true, true) => "NX|Guard",
+            (true, false) => "NX",
+            (false, true) => "Guard",
+            (false, false) => "None",
+        })
+    }
+}
+
+/// Checks that a special region is a non-empty, page aligned range with at least one exemption.
+///
+/// # Errors
+///
+/// - [MemoryError::UnalignedAddress] if the base address is not page aligned.
+/// - [MemoryError::InvalidPageCount] if the length is zero, not a multiple of the page size, or overflows.
+/// - [MemoryError::UnsupportedAttributes] if `exemptions` is empty.
+pub fn validate_special_region(
+    base_address: u64,
+    length: u64,
+    exemptions: ProtectionExemptions,
+) -> Result<(), MemoryError> {
+    if !base_address.is_multiple_of(UEFI_PAGE_SIZE as u64) {
+        return Err(MemoryError::UnalignedAddress);
+    }
+    if length == 0 || !length.is_multiple_of(UEFI_PAGE_SIZE as u64) || base_address.checked_add(length).is_none() {
+        return Err(MemoryError::InvalidPageCount);
+    }
+    if exemptions.is_empty() {
+        return Err(MemoryError::UnsupportedAttributes);
+    }
+    Ok(())
+}
+
+/// HOB registering a special region before DXE, one HOB per region.
+///
+/// The `requester` is usually the file name of the module producing the HOB, and the `reason` is a null terminated
+/// ASCII string.
+#[derive(Debug, Clone, Copy, Pread)]
+#[repr(C)]
+pub struct SpecialRegionHob {
+    /// The page aligned base address of the region.
+    pub base_address: u64,
+    /// The length of the region in bytes, a multiple of the page size.
+    pub length: u64,
+    /// The [ProtectionExemptions] bits of the region.
+    pub exemptions: u32,
+    /// Reserved, must be zero.
+    pub reserved: u32,
+    /// The GUID of the requester of the exemption.
+    pub requester: [u8; 16],
+    /// The reason for the exemption, as a null terminated ASCII string.
+    pub reason: [u8; 64],
+}
+
+impl FromHob for SpecialRegionHob {
+    // { 0x5d3c8a71, 0x2e4b, 0x4f96, { 0xa1, 0x0c, 0x7b, 0x38, 0xe2, 0x95, 0x4d, 0x16 } }
+    const HOB_GUID: OwnedGuid =
+        Guid::from_fields(0x5d3c8a71, 0x2e4b, 0x4f96, 0xa1, 0x0c, [0x7b, 0x38, 0xe2, 0x95, 0x4d, 0x16]);
+
+    fn parse(bytes: &[u8]) -> Self {
+        bytes.pread(0).unwrap()
+    }
+
+    fn validate(&self) -> core::result::Result<(), &'static str> {
+        match validate_special_region(self.base_address, self.length, self.exemptions()) {
+            Ok(()) => Ok(()),
+            Err(MemoryError::UnalignedAddress) => Err("special region base address is not page aligned"),
+            Err(MemoryError::UnsupportedAttributes) => Err("special region has no exemptions"),
+            Err(_) => Err("special region length is invalid"),
+        }
+    }
+}
+
+impl SpecialRegionHob {
+    /// Returns the exemptions of the region.
+    pub const fn exemptions(&self) -> ProtectionExemptions {
+        ProtectionExemptions::from_bits(self.exemptions)
+    }
+
+    /// Returns the GUID of the requester.
+    pub fn requester(&self) -> OwnedGuid {
+        Guid::from_bytes(&self.requester)
+    }
+
+    /// Returns the reason, up to the null terminator, or an empty string if it is not ASCII.
+    pub fn reason(&self) -> &str {
+        let len = self.reason.iter().position(|byte| *byte == 0).unwrap_or(self.reason.len());
+        match core::str::from_utf8(&self.reason[..len]) {
+            Ok(reason) if reason.is_ascii() => reason,
+            _ => "",
+        }
+    }
+}
+
+/// The `SpecialRegions` service registers memory ranges exempt from the memory protection policies of the core.
+///
+/// An exemption applies to the page table right away, and to every later attribute change over the range, including
+/// the image protections and stack guards applied by the core. Exemptions are never removed.
+#[cfg_attr(any(test, feature = "mockall"), automock)]
+pub trait SpecialRegions {
+    /// Registers `length` bytes at `base_address` as exempt from `exemptions`.
+    ///
+    /// `requester` identifies the caller, such as the component name, and `reason` explains why the exemption is
+    /// needed. Both are reported in the core's diagnostics.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [validate_special_region].
+    fn register_special_region(
+        &self,
+        base_address: u64,
+        length: u64,
+        exemptions: ProtectionExemptions,
+        requester: &str,
+        reason: &str,
+    ) -> Result<(), MemoryError>;
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exemptions_should_round_trip_through_bits() {
+        for bits in 0..4 {
+            assert_eq!(ProtectionExemptions::from_bits(bits).bits(), bits);
+        }
+        assert_eq!(ProtectionExemptions::from_bits(0xFFFF_FFF0), ProtectionExemptions::default());
+        assert!(ProtectionExemptions::default().is_empty());
+
+        let nx = ProtectionExemptions { no_execute: true, guard: false };
+        let guard = ProtectionExemptions { no_execute: false, guard: true };
+        assert_eq!(nx.union(guard), ProtectionExemptions::from_bits(0x3));
+        assert_eq!(std::format!("{}", nx.union(guard)), "NX|Guard");
+    }
+
+    #[test]
+    fn special_region_hob_should_parse() {
+        let mut bytes = std::vec::Vec::new();
+        bytes.extend_from_slice(&0xC0000u64.to_le_bytes());
+        bytes.extend_from_slice(&0x20000u64.to_le_bytes());
+        bytes.extend_from_slice(&ProtectionExemptions::NO_EXECUTE.to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&[0xAA; 16]);
+        let mut reason = [0u8; 64];
+        reason[..18].copy_from_slice(b"option ROM shadow\0");
+        bytes.extend_from_slice(&reason);
+
+        let hob = SpecialRegionHob::parse(&bytes);
+        assert_eq!(hob.base_address, 0xC0000);
+        assert_eq!(hob.length, 0x20000);
+        assert_eq!(hob.exemptions(), ProtectionExemptions { no_execute: true, guard: false });
+        assert_eq!(hob.requester(), Guid::from_bytes(&[0xAA; 16]));
+        assert_eq!(hob.reason(), "option ROM shadow");
+
+        assert_eq!(hob.validate(), Ok(()));
+
+        bytes[40] = 0xFF;
+        assert_eq!(SpecialRegionHob::parse(&bytes).reason(), "");
+        bytes[0] = 0x10;
+        assert!(SpecialRegionHob::parse(&bytes).validate().is_err());
+    }
+
+    #[test]
+    fn validate_special_region_should_reject_invalid_ranges() {
+        let nx = ProtectionExemptions { no_execute: true, guard: false };
+        assert!(validate_special_region(0x1000, 0x2000, nx).is_ok());
+        assert!(matches!(validate_special_region(0x1010, 0x2000, nx), Err(MemoryError::UnalignedAddress)));
+        assert!(matches!(validate_special_region(0x1000, 0, nx), Err(MemoryError::InvalidPageCount)));
+        assert!(matches!(validate_special_region(0x1000, 0x1800, nx), Err(MemoryError::InvalidPageCount)));
+        assert!(matches!(validate_special_region(u64::MAX - 0xFFF, 0x2000, nx), Err(MemoryError::InvalidPageCount)));
+        assert!(matches!(
+            validate_special_region(0x1000, 0x1000, ProtectionExemptions::default()),
+            Err(MemoryError::UnsupportedAttributes)
+        ));
+    }
+}
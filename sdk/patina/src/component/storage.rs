@@ -9,7 +9,11 @@
 extern crate alloc;
 
 use crate::{
-    component::{metadata::MetaData, params::Param},
+    component::{
+        metadata::{Dependency, MetaData},
+        params::Param,
+    },
+    dxe_services::StandardDxeServices,
     runtime_services::StandardRuntimeServices,
 };
 
@@ -167,6 +171,45 @@ impl Debug for Deferred {
     }
 }
 
+/// Callbacks to execute once the DXE core has finished dispatching components and drivers.
+#[derive(Default)]
+#[allow(clippy::type_complexity)]
+struct DispatchCompleteCallbacks {
+    queue: Vec<Box<dyn FnOnce(&mut Storage) -> crate::error::Result<()>>>,
+}
+
+impl Debug for DispatchCompleteCallbacks {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("DispatchCompleteCallbacks").field("queue", &self.queue.len()).finish()
+    }
+}
+
+/// A callback registered by a component to execute when ExitBootServices is called.
+///
+/// See [Commands::on_exit_boot_services](super::params::Commands::on_exit_boot_services).
+pub struct ExitBootServicesCallback {
+    component: &'static str,
+    callback: Box<dyn FnOnce()>,
+}
+
+impl ExitBootServicesCallback {
+    /// Returns the name of the component that registered the callback.
+    pub fn component(&self) -> &'static str {
+        self.component
+    }
+
+    /// Executes the callback.
+    pub fn invoke(self) {
+        (self.callback)()
+    }
+}
+
+impl Debug for ExitBootServicesCallback {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ExitBootServicesCallback").field("component", &self.component).finish()
+    }
+}
+
 /// Storage container for all datums that can be consumed by a Component.
 ///
 /// The [Component](crate::component::Component) trait provides the interface that a component must implement to be
@@ -194,6 +237,13 @@ pub struct Storage {
     /// A container for all deferred commands that components can register. This is used to delay the execution of
     /// commands that can result in structural changes to the storage.
     deferred: Option<Deferred>,
+    /// Callbacks to execute once the DXE core has finished dispatching components and drivers.
+    dispatch_complete: Option<DispatchCompleteCallbacks>,
+    /// Callbacks to execute when ExitBootServices is called, in the order they were registered.
+    exit_boot_services: Vec<ExitBootServicesCallback>,
+    /// The configs and services produced by components through [Commands](super::params::Commands), with the
+    /// component that produced them.
+    produced: Vec<(&'static str, Dependency)>,
     /// A container for all [Config](super::params::Config) and [ConfigMut](super::params::ConfigMut) datums. This
     /// resource can be accessed both immutably and mutably, so it must be tracked by
     /// [Access](super::metadata::Access).
@@ -215,6 +265,8 @@ pub struct Storage {
     boot_services: StandardBootServices,
     // Standard Runtime Services.
     runtime_services: StandardRuntimeServices,
+    // Standard DXE Services.
+    dxe_services: StandardDxeServices,
 }
 
 impl Default for Storage {
@@ -228,6 +280,9 @@ impl Storage {
     pub const fn new() -> Self {
         Self {
             deferred: None,
+            dispatch_complete: None,
+            exit_boot_services: Vec::new(),
+            produced: Vec::new(),
             configs: SparseVec::new(),
             config_indices: BTreeMap::new(),
             services: SparseVec::new(),
@@ -237,6 +292,7 @@ impl Storage {
             hob_indices: BTreeMap::new(),
             boot_services: StandardBootServices::new_uninit(),
             runtime_services: StandardRuntimeServices::new_uninit(),
+            dxe_services: StandardDxeServices::new_uninit(),
         }
     }
 
@@ -258,6 +314,63 @@ impl Storage {
         self.deferred.as_mut().unwrap()
     }
 
+    /// Registers a callback to execute once the DXE core has finished dispatching components and drivers.
+    ///
+    /// Callbacks are executed in the order they were registered, with exclusive access to the storage.
+    pub fn add_dispatch_complete_callback<F>(&mut self, callback: F)
+    where
+        F: FnOnce(&mut Storage) -> crate::error::Result<()> + 'static,
+    {
+        self.dispatch_complete.get_or_insert_with(DispatchCompleteCallbacks::default).queue.push(Box::new(callback));
+    }
+
+    /// Executes all callbacks registered with [Storage::add_dispatch_complete_callback].
+    ///
+    /// This is called by the DXE core once it has finished dispatching components and drivers. Callbacks registered
+    /// while executing a callback are executed as well.
+    ///
+    /// ## Errors
+    ///
+    /// Returns the error of the first callback that failed. The remaining callbacks are still executed.
+    pub fn dispatch_complete(&mut self) -> crate::error::Result<()> {
+        let mut result = Ok(());
+        while let Some(callbacks) = self.dispatch_complete.take() {
+            for callback in callbacks.queue {
+                result = result.and(callback(self));
+            }
+        }
+        result
+    }
+
+    /// Registers a callback of `component` to execute when ExitBootServices is called.
+    pub fn add_exit_boot_services_callback<F: FnOnce() + 'static>(&mut self, component: &'static str, callback: F) {
+        self.exit_boot_services.push(ExitBootServicesCallback { component, callback: Box::new(callback) });
+    }
+
+    /// Removes and returns the callbacks registered with [Storage::add_exit_boot_services_callback], in the order
+    /// they must be executed: the reverse of the order they were registered, so that components are torn down in the
+    /// reverse order they were dispatched.
+    ///
+    /// Deferred commands are applied first, so that the callbacks registered by the last component dispatched are
+    /// included.
+    pub fn take_exit_boot_services_callbacks(&mut self) -> Vec<ExitBootServicesCallback> {
+        self.apply_deferred();
+        let mut callbacks = core::mem::take(&mut self.exit_boot_services);
+        callbacks.reverse();
+        callbacks
+    }
+
+    /// Records that `component` produced `dependency`.
+    pub(crate) fn add_produced(&mut self, component: &'static str, dependency: Dependency) {
+        self.produced.push((component, dependency));
+    }
+
+    /// Returns the configs and services produced by components, with the component that produced them, in the order
+    /// they were added to the storage.
+    pub fn produced(&self) -> &[(&'static str, Dependency)] {
+        &self.produced
+    }
+
     /// Stores a pointer to the UEFI Boot Services Table.
     pub fn set_boot_services(&mut self, bs: StandardBootServices) {
         self.boot_services = bs;
@@ -268,6 +381,11 @@ impl Storage {
         self.runtime_services = rs;
     }
 
+    /// Stores a pointer to the PI DXE Services Table.
+    pub fn set_dxe_services(&mut self, ds: StandardDxeServices) {
+        self.dxe_services = ds;
+    }
+
     /// Returns the UEFI Boot Services Table reference.
     pub fn boot_services(&self) -> &StandardBootServices {
         &self.boot_services
@@ -278,6 +396,11 @@ impl Storage {
         &self.runtime_services
     }
 
+    /// Returns the PI DXE Services Table reference.
+    pub fn dxe_services(&self) -> &StandardDxeServices {
+        &self.dxe_services
+    }
+
     /// Registers a config type with the storage and returns its global id.
     pub(crate) fn register_config<C: Default + 'static>(&mut self) -> usize {
         let idx = self.config_indices.len();
@@ -685,7 +808,8 @@ mod tests {
         assert!(storage.get_service::<dyn TestService>().is_none());
 
         {
-            let mut commands = unsafe { <Commands as Param>::get_param(&(), UnsafeStorageCell::from(&mut storage)) };
+            let mut commands =
+                unsafe { <Commands as Param>::get_param(&"test", UnsafeStorageCell::from(&mut storage)) };
             commands.add_service(TestServiceImpl { id: 42 });
         }
 
@@ -696,4 +820,79 @@ mod tests {
         let service = storage.get_service::<dyn TestService>().unwrap();
         assert_eq!(service.test(), 42);
     }
+
+    #[test]
+    fn test_dispatch_complete_callbacks() {
+        let mut storage = Storage::new();
+
+        // Does nothing if no callbacks are registered.
+        assert!(storage.dispatch_complete().is_ok());
+
+        storage.add_dispatch_complete_callback(|storage| {
+            storage.add_config(1_u32);
+            storage.add_dispatch_complete_callback(|storage| {
+                storage.add_config(2_u64);
+                Ok(())
+            });
+            Ok(())
+        });
+        assert!(storage.get_config::<u32>().is_none());
+
+        assert!(storage.dispatch_complete().is_ok());
+        assert_eq!(storage.get_config::<u32>().map(|config| *config), Some(1));
+        assert_eq!(storage.get_config::<u64>().map(|config| *config), Some(2));
+
+        // Callbacks are only executed once.
+        storage.add_config(3_u32);
+        assert!(storage.dispatch_complete().is_ok());
+        assert_eq!(storage.get_config::<u32>().map(|config| *config), Some(3));
+    }
+
+    #[test]
+    fn test_dispatch_complete_returns_first_error() {
+        let mut storage = Storage::new();
+
+        storage.add_dispatch_complete_callback(|_| Err(crate::error::EfiError::Aborted));
+        storage.add_dispatch_complete_callback(|_| Err(crate::error::EfiError::NotFound));
+        storage.add_dispatch_complete_callback(|storage| {
+            storage.add_config(1_u32);
+            Ok(())
+        });
+
+        assert_eq!(storage.dispatch_complete(), Err(crate::error::EfiError::Aborted));
+        // Callbacks after a failed callback are still executed.
+        assert_eq!(storage.get_config::<u32>().map(|config| *config), Some(1));
+    }
+
+    #[test]
+    fn test_exit_boot_services_callbacks() {
+        use alloc::{rc::Rc, vec};
+        use core::cell::RefCell;
+
+        let mut storage = Storage::new();
+        assert!(storage.take_exit_boot_services_callbacks().is_empty());
+
+        let order = Rc::new(RefCell::new(Vec::new()));
+        for (component, id) in [("first", 1), ("second", 2)] {
+            let order = order.clone();
+            storage.add_exit_boot_services_callback(component, move || order.borrow_mut().push(id));
+        }
+        // Callbacks registered through deferred commands are included.
+        let deferred_order = order.clone();
+        storage.deferred().add_command(move |storage| {
+            storage.add_exit_boot_services_callback("third", move || deferred_order.borrow_mut().push(3))
+        });
+
+        let callbacks = storage.take_exit_boot_services_callbacks();
+        assert_eq!(
+            callbacks.iter().map(|callback| callback.component()).collect::<Vec<_>>(),
+            ["third", "second", "first"]
+        );
+        assert!(order.borrow().is_empty());
+        callbacks.into_iter().for_each(ExitBootServicesCallback::invoke);
+        assert_eq!(*order.borrow(), vec![3, 2, 1]);
+
+        // Callbacks are only returned once.
+        assert!(storage.take_exit_boot_services_callbacks().is_empty());
+    }
 }
@@ -34,6 +34,7 @@ use crate::{
     error::Result,
 };
 use core::marker::PhantomData;
+use patina_pi::BootMode;
 
 /// A [Component] implementation for Structs who specify a function whose parameters implement [Param].
 pub struct StructComponent<Marker, Func>
@@ -62,6 +63,12 @@ where
             _marker: PhantomData,
         }
     }
+
+    /// Prevents the component from being dispatched in any of the boot `modes`.
+    pub fn with_skipped_boot_modes(mut self, modes: &[BootMode]) -> Self {
+        self.metadata.set_skipped_boot_modes(modes);
+        self
+    }
 }
 
 impl<Marker, In, Func> Component for StructComponent<Marker, Func>
@@ -161,12 +168,36 @@ mod tests {
         }
     }
 
+    #[derive(IntoComponent)]
+    #[skip_boot_modes(BootOnS3Resume, BootInRecoveryMode)]
+    #[allow(dead_code)]
+    pub struct TestStructSkipsBootModes {
+        pub x: i32,
+    }
+
+    impl TestStructSkipsBootModes {
+        fn entry_point(self) -> crate::error::Result<()> {
+            Ok(())
+        }
+    }
+
     #[test]
     fn test_struct_component() {
         let test_struct = TestStructSuccess { x: 5 };
         let _ = test_struct.into_component();
     }
 
+    #[test]
+    fn test_skipped_boot_modes_are_recorded_in_metadata() {
+        use crate::component::BootMode;
+
+        let component = TestStructSkipsBootModes { x: 5 }.into_component();
+        assert_eq!(component.metadata().skipped_boot_modes(), [BootMode::BootOnS3Resume, BootMode::BootInRecoveryMode]);
+
+        let component = TestStructSuccess { x: 5 }.into_component();
+        assert!(component.metadata().skipped_boot_modes().is_empty());
+    }
+
     #[test]
     fn test_enum_component() {
         let test_enum = TestEnumSuccess::A;
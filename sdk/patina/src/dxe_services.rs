@@ -0,0 +1,577 @@
+//! Rust-friendly DXE Service Wrappers
+//!
+//! Provides safe wrappers for the Global Coherency Domain (GCD) services of the DXE Services Table, so that components
+//! can add, allocate and free memory and I/O resources, and change the attributes of memory regions, without calling
+//! the table directly. Like the [boot services](crate::boot_services), the services are provided through the
+//! [DxeServices] trait, which can be mocked in unit tests.
+//!
+//! ```ignore
+//! fn entry_point(dxe_services: StandardDxeServices) -> patina::error::Result<()> {
+//!     dxe_services.add_memory_space(GcdMemoryType::MemoryMappedIo, FRAMEBUFFER_BASE, FRAMEBUFFER_SIZE, efi::MEMORY_UC)?;
+//!     Ok(())
+//! }
+//! ```
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+#[cfg(any(test, feature = "mockall"))]
+use mockall::automock;
+
+use core::{
+    fmt::Debug,
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use r_efi::efi;
+
+use patina_pi::dxe_services::GcdAllocateType;
+pub use patina_pi::dxe_services::{
+    DxeServicesTable, GcdIoType, GcdMemoryType, IoSpaceDescriptor, MemorySpaceDescriptor,
+};
+
+/// The way to perform a GCD allocation.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum GcdAllocType {
+    /// Will allocate at the lowest suitable address.
+    AnySearchBottomUp,
+    /// Will allocate at the lowest suitable address, no larger than the specified address.
+    MaxAddressSearchBottomUp(efi::PhysicalAddress),
+    /// Will allocate at the specified address.
+    Address(efi::PhysicalAddress),
+    /// Will allocate at the highest suitable address.
+    AnySearchTopDown,
+    /// Will allocate at the highest suitable address, no larger than the specified address.
+    MaxAddressSearchTopDown(efi::PhysicalAddress),
+}
+
+impl GcdAllocType {
+    /// Returns the allocate type and the base address to pass to the allocation services.
+    fn into_raw(self) -> (GcdAllocateType, efi::PhysicalAddress) {
+        match self {
+            GcdAllocType::AnySearchBottomUp => (GcdAllocateType::AnySearchBottomUp, 0),
+            GcdAllocType::MaxAddressSearchBottomUp(address) => (GcdAllocateType::MaxAddressSearchBottomUp, address),
+            GcdAllocType::Address(address) => (GcdAllocateType::Address, address),
+            GcdAllocType::AnySearchTopDown => (GcdAllocateType::AnySearchTopDown, 0),
+            GcdAllocType::MaxAddressSearchTopDown(address) => (GcdAllocateType::MaxAddressSearchTopDown, address),
+        }
+    }
+}
+
+/// The PI spec DXE services.
+/// Wrapper around [`DxeServicesTable`]
+///
+/// PI Spec Documentation: [7. Services - DXE Services](https://uefi.org/specs/PI/1.8A/V2_Services_DXE_Services.html)
+pub struct StandardDxeServices {
+    efi_dxe_services: AtomicPtr<DxeServicesTable>,
+}
+
+impl StandardDxeServices {
+    /// Create a new StandardDxeServices with the provided [DxeServicesTable].
+    pub fn new(efi_dxe_services: &DxeServicesTable) -> Self {
+        let this = StandardDxeServices::new_uninit();
+        this.init(efi_dxe_services);
+        this
+    }
+
+    /// Create a new StandardDxeServices that is not initialized.
+    pub const fn new_uninit() -> Self {
+        Self { efi_dxe_services: AtomicPtr::new(ptr::null_mut()) }
+    }
+
+    /// Initialize the StandardDxeServices.
+    pub fn init(&self, efi_dxe_services: &DxeServicesTable) {
+        self.efi_dxe_services.store(efi_dxe_services as *const _ as *mut _, Ordering::Relaxed);
+    }
+
+    /// Return true if StandardDxeServices is initialized.
+    pub fn is_init(&self) -> bool {
+        !self.efi_dxe_services.load(Ordering::Relaxed).is_null()
+    }
+
+    fn efi_dxe_services(&self) -> &DxeServicesTable {
+        // SAFETY: The DXE services table is allocated as runtime data by the core and lives long enough.
+        unsafe { self.efi_dxe_services.load(Ordering::Relaxed).as_ref() }
+            .expect("Standard DXE Services is not initialized!")
+    }
+}
+
+impl AsRef<StandardDxeServices> for StandardDxeServices {
+    fn as_ref(&self) -> &StandardDxeServices {
+        self
+    }
+}
+
+impl Clone for StandardDxeServices {
+    fn clone(&self) -> Self {
+        Self { efi_dxe_services: AtomicPtr::new(self.efi_dxe_services.load(Ordering::Relaxed)) }
+    }
+}
+
+impl Debug for StandardDxeServices {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if !self.is_init() {
+            return f.debug_struct("StandardDxeServices").field("efi_dxe_services", &"Not Initialized").finish();
+        }
+
+        let dxe_services = self.efi_dxe_services();
+        f.debug_struct("StandardDxeServices")
+            .field("add_memory_space", &(dxe_services.add_memory_space))
+            .field("allocate_memory_space", &(dxe_services.allocate_memory_space))
+            .field("free_memory_space", &(dxe_services.free_memory_space))
+            .field("remove_memory_space", &(dxe_services.remove_memory_space))
+            .field("get_memory_space_descriptor", &(dxe_services.get_memory_space_descriptor))
+            .field("set_memory_space_attributes", &(dxe_services.set_memory_space_attributes))
+            .field("set_memory_space_capabilities", &(dxe_services.set_memory_space_capabilities))
+            .field("add_io_space", &(dxe_services.add_io_space))
+            .field("allocate_io_space", &(dxe_services.allocate_io_space))
+            .field("free_io_space", &(dxe_services.free_io_space))
+            .field("remove_io_space", &(dxe_services.remove_io_space))
+            .field("get_io_space_descriptor", &(dxe_services.get_io_space_descriptor))
+            .finish()
+    }
+}
+
+#[cfg_attr(any(test, feature = "mockall"), automock)]
+/// Interface for Rust-friendly wrappers of the GCD services of the DXE Services Table
+pub trait DxeServices {
+    /// Adds reserved memory, system memory, or memory-mapped I/O resources to the GCD memory space map.
+    ///
+    /// PI Spec Documentation: [7.2.4.1. AddMemorySpace()](https://uefi.org/specs/PI/1.8A/V2_Services_DXE_Services.html#addmemoryspace)
+    ///
+    fn add_memory_space(
+        &self,
+        memory_type: GcdMemoryType,
+        base_address: efi::PhysicalAddress,
+        length: u64,
+        capabilities: u64,
+    ) -> Result<(), efi::Status>;
+
+    /// Allocates `length` bytes of memory space of `memory_type`, aligned on 2^`alignment` bytes, and returns the
+    /// base address of the allocation.
+    ///
+    /// PI Spec Documentation: [7.2.4.2. AllocateMemorySpace()](https://uefi.org/specs/PI/1.8A/V2_Services_DXE_Services.html#allocatememoryspace)
+    ///
+    fn allocate_memory_space(
+        &self,
+        alloc_type: GcdAllocType,
+        memory_type: GcdMemoryType,
+        alignment: usize,
+        length: u64,
+        image_handle: efi::Handle,
+        device_handle: Option<efi::Handle>,
+    ) -> Result<efi::PhysicalAddress, efi::Status>;
+
+    /// Frees memory space allocated with [DxeServices::allocate_memory_space].
+    ///
+    /// PI Spec Documentation: [7.2.4.3. FreeMemorySpace()](https://uefi.org/specs/PI/1.8A/V2_Services_DXE_Services.html#freememoryspace)
+    ///
+    fn free_memory_space(&self, base_address: efi::PhysicalAddress, length: u64) -> Result<(), efi::Status>;
+
+    /// Removes memory space added with [DxeServices::add_memory_space] from the GCD memory space map.
+    ///
+    /// PI Spec Documentation: [7.2.4.4. RemoveMemorySpace()](https://uefi.org/specs/PI/1.8A/V2_Services_DXE_Services.html#removememoryspace)
+    ///
+    fn remove_memory_space(&self, base_address: efi::PhysicalAddress, length: u64) -> Result<(), efi::Status>;
+
+    /// Returns the descriptor of the memory space region containing `base_address`.
+    ///
+    /// PI Spec Documentation: [7.2.4.5. GetMemorySpaceDescriptor()](https://uefi.org/specs/PI/1.8A/V2_Services_DXE_Services.html#getmemoryspacedescriptor)
+    ///
+    fn get_memory_space_descriptor(
+        &self,
+        base_address: efi::PhysicalAddress,
+    ) -> Result<MemorySpaceDescriptor, efi::Status>;
+
+    /// Sets the attributes of a memory space region, e.g. its cacheability or its access permissions.
+    ///
+    /// PI Spec Documentation: [7.2.4.6. SetMemorySpaceAttributes()](https://uefi.org/specs/PI/1.8A/V2_Services_DXE_Services.html#setmemoryspaceattributes)
+    ///
+    fn set_memory_space_attributes(
+        &self,
+        base_address: efi::PhysicalAddress,
+        length: u64,
+        attributes: u64,
+    ) -> Result<(), efi::Status>;
+
+    /// Sets the attributes a memory space region is capable of supporting.
+    ///
+    /// PI Spec Documentation: [7.2.4.7. SetMemorySpaceCapabilities()](https://uefi.org/specs/PI/1.8A/V2_Services_DXE_Services.html#setmemoryspacecapabilities)
+    ///
+    fn set_memory_space_capabilities(
+        &self,
+        base_address: efi::PhysicalAddress,
+        length: u64,
+        capabilities: u64,
+    ) -> Result<(), efi::Status>;
+
+    /// Adds reserved I/O or I/O resources to the GCD I/O space map.
+    ///
+    /// PI Spec Documentation: [7.2.4.9. AddIoSpace()](https://uefi.org/specs/PI/1.8A/V2_Services_DXE_Services.html#addiospace)
+    ///
+    fn add_io_space(
+        &self,
+        io_type: GcdIoType,
+        base_address: efi::PhysicalAddress,
+        length: u64,
+    ) -> Result<(), efi::Status>;
+
+    /// Allocates `length` bytes of I/O space of `io_type`, aligned on 2^`alignment` bytes, and returns the base address
+    /// of the allocation.
+    ///
+    /// PI Spec Documentation: [7.2.4.10. AllocateIoSpace()](https://uefi.org/specs/PI/1.8A/V2_Services_DXE_Services.html#allocateiospace)
+    ///
+    fn allocate_io_space(
+        &self,
+        alloc_type: GcdAllocType,
+        io_type: GcdIoType,
+        alignment: usize,
+        length: u64,
+        image_handle: efi::Handle,
+        device_handle: Option<efi::Handle>,
+    ) -> Result<efi::PhysicalAddress, efi::Status>;
+
+    /// Frees I/O space allocated with [DxeServices::allocate_io_space].
+    ///
+    /// PI Spec Documentation: [7.2.4.11. FreeIoSpace()](https://uefi.org/specs/PI/1.8A/V2_Services_DXE_Services.html#freeiospace)
+    ///
+    fn free_io_space(&self, base_address: efi::PhysicalAddress, length: u64) -> Result<(), efi::Status>;
+
+    /// Removes I/O space added with [DxeServices::add_io_space] from the GCD I/O space map.
+    ///
+    /// PI Spec Documentation: [7.2.4.12. RemoveIoSpace()](https://uefi.org/specs/PI/1.8A/V2_Services_DXE_Services.html#removeiospace)
+    ///
+    fn remove_io_space(&self, base_address: efi::PhysicalAddress, length: u64) -> Result<(), efi::Status>;
+
+    /// Returns the descriptor of the I/O space region containing `base_address`.
+    ///
+    /// PI Spec Documentation: [7.2.4.13. GetIoSpaceDescriptor()](https://uefi.org/specs/PI/1.8A/V2_Services_DXE_Services.html#getiospacedescriptor)
+    ///
+    fn get_io_space_descriptor(&self, base_address: efi::PhysicalAddress) -> Result<IoSpaceDescriptor, efi::Status>;
+}
+
+macro_rules! efi_dxe_services_fn {
+    ($efi_dxe_services:expr, $fn_name:ident) => {{
+        match $efi_dxe_services.$fn_name {
+            f if f as usize == 0 => panic!("DXE services function {} is not initialized.", stringify!($fn_name)),
+            f => f,
+        }
+    }};
+}
+
+fn status_to_result(status: efi::Status) -> Result<(), efi::Status> {
+    if status.is_error() { Err(status) } else { Ok(()) }
+}
+
+impl DxeServices for StandardDxeServices {
+    fn add_memory_space(
+        &self,
+        memory_type: GcdMemoryType,
+        base_address: efi::PhysicalAddress,
+        length: u64,
+        capabilities: u64,
+    ) -> Result<(), efi::Status> {
+        status_to_result(efi_dxe_services_fn!(self.efi_dxe_services(), add_memory_space)(
+            memory_type,
+            base_address,
+            length,
+            capabilities,
+        ))
+    }
+
+    fn allocate_memory_space(
+        &self,
+        alloc_type: GcdAllocType,
+        memory_type: GcdMemoryType,
+        alignment: usize,
+        length: u64,
+        image_handle: efi::Handle,
+        device_handle: Option<efi::Handle>,
+    ) -> Result<efi::PhysicalAddress, efi::Status> {
+        let (alloc_type, mut base_address) = alloc_type.into_raw();
+        status_to_result(efi_dxe_services_fn!(self.efi_dxe_services(), allocate_memory_space)(
+            alloc_type,
+            memory_type,
+            alignment,
+            length,
+            ptr::addr_of_mut!(base_address),
+            image_handle,
+            device_handle.unwrap_or(ptr::null_mut()),
+        ))?;
+        Ok(base_address)
+    }
+
+    fn free_memory_space(&self, base_address: efi::PhysicalAddress, length: u64) -> Result<(), efi::Status> {
+        status_to_result(efi_dxe_services_fn!(self.efi_dxe_services(), free_memory_space)(base_address, length))
+    }
+
+    fn remove_memory_space(&self, base_address: efi::PhysicalAddress, length: u64) -> Result<(), efi::Status> {
+        status_to_result(efi_dxe_services_fn!(self.efi_dxe_services(), remove_memory_space)(base_address, length))
+    }
+
+    fn get_memory_space_descriptor(
+        &self,
+        base_address: efi::PhysicalAddress,
+    ) -> Result<MemorySpaceDescriptor, efi::Status> {
+        let mut descriptor = MemorySpaceDescriptor::default();
+        status_to_result(efi_dxe_services_fn!(self.efi_dxe_services(), get_memory_space_descriptor)(
+            base_address,
+            ptr::addr_of_mut!(descriptor),
+        ))?;
+        Ok(descriptor)
+    }
+
+    fn set_memory_space_attributes(
+        &self,
+        base_address: efi::PhysicalAddress,
+        length: u64,
+        attributes: u64,
+    ) -> Result<(), efi::Status> {
+        status_to_result(efi_dxe_services_fn!(self.efi_dxe_services(), set_memory_space_attributes)(
+            base_address,
+            length,
+            attributes,
+        ))
+    }
+
+    fn set_memory_space_capabilities(
+        &self,
+        base_address: efi::PhysicalAddress,
+        length: u64,
+        capabilities: u64,
+    ) -> Result<(), efi::Status> {
+        status_to_result(efi_dxe_services_fn!(self.efi_dxe_services(), set_memory_space_capabilities)(
+            base_address,
+            length,
+            capabilities,
+        ))
+    }
+
+    fn add_io_space(
+        &self,
+        io_type: GcdIoType,
+        base_address: efi::PhysicalAddress,
+        length: u64,
+    ) -> Result<(), efi::Status> {
+        status_to_result(efi_dxe_services_fn!(self.efi_dxe_services(), add_io_space)(io_type, base_address, length))
+    }
+
+    fn allocate_io_space(
+        &self,
+        alloc_type: GcdAllocType,
+        io_type: GcdIoType,
+        alignment: usize,
+        length: u64,
+        image_handle: efi::Handle,
+        device_handle: Option<efi::Handle>,
+    ) -> Result<efi::PhysicalAddress, efi::Status> {
+        let (alloc_type, mut base_address) = alloc_type.into_raw();
+        status_to_result(efi_dxe_services_fn!(self.efi_dxe_services(), allocate_io_space)(
+            alloc_type,
+            io_type,
+            alignment,
+            length,
+            ptr::addr_of_mut!(base_address),
+            image_handle,
+            device_handle.unwrap_or(ptr::null_mut()),
+        ))?;
+        Ok(base_address)
+    }
+
+    fn free_io_space(&self, base_address: efi::PhysicalAddress, length: u64) -> Result<(), efi::Status> {
+        status_to_result(efi_dxe_services_fn!(self.efi_dxe_services(), free_io_space)(base_address, length))
+    }
+
+    fn remove_io_space(&self, base_address: efi::PhysicalAddress, length: u64) -> Result<(), efi::Status> {
+        status_to_result(efi_dxe_services_fn!(self.efi_dxe_services(), remove_io_space)(base_address, length))
+    }
+
+    fn get_io_space_descriptor(&self, base_address: efi::PhysicalAddress) -> Result<IoSpaceDescriptor, efi::Status> {
+        let mut descriptor = IoSpaceDescriptor::default();
+        status_to_result(efi_dxe_services_fn!(self.efi_dxe_services(), get_io_space_descriptor)(
+            base_address,
+            ptr::addr_of_mut!(descriptor),
+        ))?;
+        Ok(descriptor)
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use core::mem;
+
+    macro_rules! dxe_services {
+        ($($efi_services:ident = $efi_service_fn:ident),*) => {{
+            let efi_dxe_services = unsafe {
+                #[allow(unused_mut)]
+                let mut ds = mem::MaybeUninit::<DxeServicesTable>::zeroed();
+                $(
+                ds.assume_init_mut().$efi_services = $efi_service_fn;
+                )*
+                ds.assume_init()
+            };
+            StandardDxeServices::new(Box::leak(Box::new(efi_dxe_services)))
+        }};
+    }
+
+    const BASE_ADDRESS: efi::PhysicalAddress = 0x8000_0000;
+    const LENGTH: u64 = 0x10_0000;
+
+    extern "efiapi" fn mock_add_memory_space(
+        memory_type: GcdMemoryType,
+        base_address: efi::PhysicalAddress,
+        length: u64,
+        capabilities: u64,
+    ) -> efi::Status {
+        assert_eq!(memory_type, GcdMemoryType::MemoryMappedIo);
+        assert_eq!((base_address, length, capabilities), (BASE_ADDRESS, LENGTH, efi::MEMORY_UC));
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn mock_allocate_memory_space(
+        alloc_type: GcdAllocateType,
+        memory_type: GcdMemoryType,
+        alignment: usize,
+        length: u64,
+        base_address: *mut efi::PhysicalAddress,
+        image_handle: efi::Handle,
+        device_handle: efi::Handle,
+    ) -> efi::Status {
+        assert_eq!(memory_type, GcdMemoryType::SystemMemory);
+        assert_eq!((alignment, length), (12, LENGTH));
+        assert_eq!(image_handle, 1_usize as efi::Handle);
+        assert!(device_handle.is_null());
+        // SAFETY: The base address is provided by the wrapper.
+        unsafe {
+            match alloc_type {
+                GcdAllocateType::Address => assert_eq!(*base_address, BASE_ADDRESS),
+                GcdAllocateType::MaxAddressSearchTopDown => *base_address -= LENGTH,
+                _ => return efi::Status::OUT_OF_RESOURCES,
+            }
+        }
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn mock_get_memory_space_descriptor(
+        base_address: efi::PhysicalAddress,
+        descriptor: *mut MemorySpaceDescriptor,
+    ) -> efi::Status {
+        if base_address != BASE_ADDRESS {
+            return efi::Status::NOT_FOUND;
+        }
+        // SAFETY: The descriptor is provided by the wrapper.
+        unsafe {
+            (*descriptor).base_address = base_address;
+            (*descriptor).length = LENGTH;
+            (*descriptor).memory_type = GcdMemoryType::Reserved;
+        }
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn mock_set_memory_space_attributes(
+        base_address: efi::PhysicalAddress,
+        _length: u64,
+        _attributes: u64,
+    ) -> efi::Status {
+        if base_address == BASE_ADDRESS { efi::Status::SUCCESS } else { efi::Status::UNSUPPORTED }
+    }
+
+    extern "efiapi" fn mock_allocate_io_space(
+        alloc_type: GcdAllocateType,
+        io_type: GcdIoType,
+        _alignment: usize,
+        _length: u64,
+        base_address: *mut efi::PhysicalAddress,
+        _image_handle: efi::Handle,
+        device_handle: efi::Handle,
+    ) -> efi::Status {
+        assert!(matches!(alloc_type, GcdAllocateType::AnySearchBottomUp));
+        assert_eq!(io_type, GcdIoType::Io);
+        assert_eq!(device_handle, 2_usize as efi::Handle);
+        // SAFETY: The base address is provided by the wrapper.
+        unsafe {
+            assert_eq!(*base_address, 0);
+            *base_address = 0x1000;
+        }
+        efi::Status::SUCCESS
+    }
+
+    #[test]
+    fn test_debug_print_works_before_init() {
+        let dxe_services = StandardDxeServices::new_uninit();
+        assert!(!dxe_services.is_init());
+        assert!(format!("{dxe_services:?}").contains("Not Initialized"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Standard DXE Services is not initialized!")]
+    fn test_that_accessing_uninit_dxe_services_should_panic() {
+        let _ = StandardDxeServices::new_uninit().free_memory_space(BASE_ADDRESS, LENGTH);
+    }
+
+    #[test]
+    #[should_panic(expected = "DXE services function remove_memory_space is not initialized.")]
+    fn test_that_calling_missing_dxe_service_should_panic() {
+        let _ = dxe_services!().remove_memory_space(BASE_ADDRESS, LENGTH);
+    }
+
+    #[test]
+    fn test_add_memory_space() {
+        let dxe_services = dxe_services!(add_memory_space = mock_add_memory_space);
+        assert_eq!(
+            dxe_services.add_memory_space(GcdMemoryType::MemoryMappedIo, BASE_ADDRESS, LENGTH, efi::MEMORY_UC),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_allocate_memory_space() {
+        let dxe_services = dxe_services!(allocate_memory_space = mock_allocate_memory_space);
+        let image_handle = 1_usize as efi::Handle;
+        let allocate = |alloc_type| {
+            dxe_services.allocate_memory_space(alloc_type, GcdMemoryType::SystemMemory, 12, LENGTH, image_handle, None)
+        };
+        assert_eq!(allocate(GcdAllocType::Address(BASE_ADDRESS)), Ok(BASE_ADDRESS));
+        assert_eq!(allocate(GcdAllocType::MaxAddressSearchTopDown(BASE_ADDRESS)), Ok(BASE_ADDRESS - LENGTH));
+        assert_eq!(allocate(GcdAllocType::AnySearchBottomUp), Err(efi::Status::OUT_OF_RESOURCES));
+    }
+
+    #[test]
+    fn test_get_memory_space_descriptor() {
+        let dxe_services = dxe_services!(get_memory_space_descriptor = mock_get_memory_space_descriptor);
+        let descriptor = dxe_services.get_memory_space_descriptor(BASE_ADDRESS).unwrap();
+        assert_eq!((descriptor.base_address, descriptor.length), (BASE_ADDRESS, LENGTH));
+        assert_eq!(descriptor.memory_type, GcdMemoryType::Reserved);
+        assert_eq!(dxe_services.get_memory_space_descriptor(0), Err(efi::Status::NOT_FOUND));
+    }
+
+    #[test]
+    fn test_set_memory_space_attributes() {
+        let dxe_services = dxe_services!(set_memory_space_attributes = mock_set_memory_space_attributes);
+        assert_eq!(dxe_services.set_memory_space_attributes(BASE_ADDRESS, LENGTH, efi::MEMORY_XP), Ok(()));
+        assert_eq!(dxe_services.set_memory_space_attributes(0, LENGTH, efi::MEMORY_XP), Err(efi::Status::UNSUPPORTED));
+    }
+
+    #[test]
+    fn test_allocate_io_space() {
+        let dxe_services = dxe_services!(allocate_io_space = mock_allocate_io_space);
+        assert_eq!(
+            dxe_services.allocate_io_space(
+                GcdAllocType::AnySearchBottomUp,
+                GcdIoType::Io,
+                0,
+                0x100,
+                1_usize as efi::Handle,
+                Some(2_usize as efi::Handle)
+            ),
+            Ok(0x1000)
+        );
+    }
+}
@@ -0,0 +1,213 @@
+//! Firmware Storage Abstraction
+//!
+//! The [FirmwareStorage] trait describes block storage with NOR flash semantics: data is read and written at any
+//! offset, but writes can only clear bits, so a range must be erased, setting its blocks to `0xFF`, before it can be
+//! rewritten with arbitrary data. The variable store, capsule persistence and FMP writers share this abstraction, so
+//! that a platform provides the storage once and each consumer works on a region of it.
+//!
+//! - [fvb::FvbStorage] bridges a Firmware Volume Block protocol, such as the one produced by a SPI-NOR driver.
+//! - [ftw::FaultTolerantStorage] journals block updates in the EDK II working block layout, so an interrupted write is
+//!   completed or rolled back on the next boot.
+//! - [RamStorage] emulates NOR flash in memory, for platforms without flash and for tests.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use patina::firmware_storage::{FirmwareStorage, RamStorage, ftw::FaultTolerantStorage};
+//!
+//! let mut storage = FaultTolerantStorage::new(RamStorage::new(0x1000, 8)).unwrap();
+//! storage.write(0x10, b"data").unwrap();
+//!
+//! let mut buffer = [0u8; 4];
+//! storage.read(0x10, &mut buffer).unwrap();
+//! assert_eq!(&buffer, b"data");
+//! ```
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+pub mod ftw;
+pub mod fvb;
+
+use alloc::{vec, vec::Vec};
+
+use crate::error::EfiError;
+
+#[cfg(any(test, feature = "mockall"))]
+use mockall::automock;
+
+/// The value of erased bytes.
+pub const ERASED_BYTE: u8 = 0xFF;
+
+/// The block layout of a [FirmwareStorage].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockGeometry {
+    /// The size of a block in bytes, the unit of erase.
+    pub block_size: usize,
+    /// The number of blocks of the storage.
+    pub block_count: usize,
+}
+
+impl BlockGeometry {
+    /// Returns the size of the storage in bytes.
+    pub const fn size(&self) -> usize {
+        self.block_size * self.block_count
+    }
+
+    /// Checks that `length` bytes at `offset` are within the storage.
+    pub fn check_range(&self, offset: usize, length: usize) -> Result<(), StorageError> {
+        match offset.checked_add(length) {
+            Some(end) if end <= self.size() => Ok(()),
+            _ => Err(StorageError::OutOfBounds),
+        }
+    }
+
+    /// Checks that `count` blocks from `block` are within the storage.
+    pub fn check_blocks(&self, block: usize, count: usize) -> Result<(), StorageError> {
+        match block.checked_add(count) {
+            Some(end) if end <= self.block_count => Ok(()),
+            _ => Err(StorageError::OutOfBounds),
+        }
+    }
+}
+
+/// Errors reported by a [FirmwareStorage].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageError {
+    /// The range accessed is not within the storage.
+    OutOfBounds,
+    /// The storage does not have a layout the operation can work with.
+    InvalidGeometry,
+    /// The storage is write protected.
+    WriteProtected,
+    /// The device failed the operation.
+    DeviceError,
+    /// Fault tolerant writes are allocated and not done.
+    WritesPending,
+    /// No fault tolerant write is allocated.
+    NotAllocated,
+}
+
+impl From<StorageError> for EfiError {
+    fn from(err: StorageError) -> Self {
+        match err {
+            StorageError::OutOfBounds => EfiError::InvalidParameter,
+            StorageError::InvalidGeometry => EfiError::Unsupported,
+            StorageError::WriteProtected => EfiError::WriteProtected,
+            StorageError::DeviceError => EfiError::DeviceError,
+            StorageError::WritesPending => EfiError::AccessDenied,
+            StorageError::NotAllocated => EfiError::NotReady,
+        }
+    }
+}
+
+/// Block storage with NOR flash semantics.
+///
+/// Offsets are in bytes from the start of the storage, blocks are numbered from zero.
+#[cfg_attr(any(test, feature = "mockall"), automock)]
+pub trait FirmwareStorage {
+    /// Returns the block layout of the storage.
+    fn geometry(&self) -> BlockGeometry;
+
+    /// Reads `buffer.len()` bytes at `offset`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [StorageError::OutOfBounds] if the range is not within the storage.
+    fn read(&self, offset: usize, buffer: &mut [u8]) -> Result<(), StorageError>;
+
+    /// Writes `data` at `offset`. Writes only clear bits, the range should be erased first.
+    ///
+    /// # Errors
+    ///
+    /// Returns [StorageError::OutOfBounds] if the range is not within the storage.
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), StorageError>;
+
+    /// Erases `count` blocks from `block`, setting their bytes to [ERASED_BYTE].
+    ///
+    /// # Errors
+    ///
+    /// Returns [StorageError::OutOfBounds] if the blocks are not within the storage.
+    fn erase(&mut self, block: usize, count: usize) -> Result<(), StorageError>;
+}
+
+/// A [FirmwareStorage] emulating NOR flash in memory. Writes clear bits only, as they would on flash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RamStorage {
+    block_size: usize,
+    data: Vec<u8>,
+}
+
+impl RamStorage {
+    /// Creates an erased storage of `block_count` blocks of `block_size` bytes.
+    pub fn new(block_size: usize, block_count: usize) -> Self {
+        Self { block_size, data: vec![ERASED_BYTE; block_size * block_count] }
+    }
+
+    /// Returns the content of the storage.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl FirmwareStorage for RamStorage {
+    fn geometry(&self) -> BlockGeometry {
+        BlockGeometry { block_size: self.block_size, block_count: self.data.len() / self.block_size }
+    }
+
+    fn read(&self, offset: usize, buffer: &mut [u8]) -> Result<(), StorageError> {
+        self.geometry().check_range(offset, buffer.len())?;
+        buffer.copy_from_slice(&self.data[offset..offset + buffer.len()]);
+        Ok(())
+    }
+
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), StorageError> {
+        self.geometry().check_range(offset, data.len())?;
+        self.data[offset..offset + data.len()].iter_mut().zip(data).for_each(|(byte, new)| *byte &= new);
+        Ok(())
+    }
+
+    fn erase(&mut self, block: usize, count: usize) -> Result<(), StorageError> {
+        self.geometry().check_blocks(block, count)?;
+        self.data[block * self.block_size..(block + count) * self.block_size].fill(ERASED_BYTE);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ram_storage_should_behave_like_nor_flash() {
+        let mut storage = RamStorage::new(0x100, 4);
+        assert_eq!(storage.geometry(), BlockGeometry { block_size: 0x100, block_count: 4 });
+        assert!(storage.as_bytes().iter().all(|byte| *byte == ERASED_BYTE));
+
+        // Writes only clear bits.
+        storage.write(0x1FF, &[0x0F, 0xF0]).unwrap();
+        storage.write(0x1FF, &[0xF3, 0x3F]).unwrap();
+        let mut buffer = [0; 2];
+        storage.read(0x1FF, &mut buffer).unwrap();
+        assert_eq!(buffer, [0x03, 0x30]);
+
+        // Erase works on whole blocks.
+        storage.erase(2, 1).unwrap();
+        storage.read(0x1FF, &mut buffer).unwrap();
+        assert_eq!(buffer, [0x03, 0xFF]);
+    }
+
+    #[test]
+    fn ram_storage_should_check_ranges() {
+        let mut storage = RamStorage::new(0x100, 4);
+        assert_eq!(storage.read(0x3FF, &mut [0; 2]), Err(StorageError::OutOfBounds));
+        assert_eq!(storage.write(usize::MAX, &[0]), Err(StorageError::OutOfBounds));
+        assert_eq!(storage.erase(3, 2), Err(StorageError::OutOfBounds));
+        assert_eq!(storage.erase(usize::MAX, 2), Err(StorageError::OutOfBounds));
+        assert_eq!(EfiError::from(StorageError::OutOfBounds), EfiError::InvalidParameter);
+    }
+}
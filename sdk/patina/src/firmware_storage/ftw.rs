@@ -0,0 +1,831 @@
+//! Fault Tolerant Write Journaling
+//!
+//! [FaultTolerantStorage] makes writes to a [FirmwareStorage] atomic: after a reset in the middle of a write, the
+//! blocks it touches hold either their previous content or the new one, never partially erased or written blocks.
+//!
+//! The last blocks of the storage are reserved, with the layout of the EDK II `FaultTolerantWriteDxe` driver. The
+//! working block starts with a header signed with `gEdkiiWorkingBlockSignatureGuid` and checked with a CRC32, followed
+//! by the write queue. Each allocation of writes appends a write header, with the ID of the caller and the number of
+//! writes, followed by a record per write, with its destination and the private data of the caller. The spare blocks
+//! that follow the working block receive the new content of the blocks before they are erased. Each write goes through
+//! the states of its record, each state clearing one more bit so that it is a single NOR write:
+//!
+//! 1. The record is written with the destination of the write.
+//! 2. The new content is written to the spare blocks, and the record is marked spare complete.
+//! 3. The blocks are erased and written with the new content, and the record is marked destination complete. The write
+//!    header is marked complete with its last record.
+//!
+//! When the storage is opened, a write whose spare was written but which did not complete is finished from the spare
+//! blocks. A write interrupted before its spare was written is abandoned with the rest of its allocation, the blocks
+//! still hold their previous content. Writes allocated but not started remain pending, as with EDK II.
+//!
+//! [FirmwareStorage::write] allocates a single write per update, with [PATINA_CALLER_ID]. A write touching at most as
+//! many blocks as there are spare blocks is a single update, and so is atomic as a whole. Longer writes are split into
+//! several updates, each atomic on its own.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::{vec, vec::Vec};
+use r_efi::efi;
+
+use super::{BlockGeometry, ERASED_BYTE, FirmwareStorage, StorageError};
+
+/// The signature of the working block header, `gEdkiiWorkingBlockSignatureGuid` in EDK II.
+pub const WORKING_BLOCK_SIGNATURE: efi::Guid =
+    efi::Guid::from_fields(0x9e58292b, 0x7c68, 0x497d, 0xa0, 0xce, &[0x65, 0x00, 0xfd, 0x9f, 0x1b, 0x95]);
+
+/// The caller ID of the writes made through [FirmwareStorage::write].
+pub const PATINA_CALLER_ID: efi::Guid =
+    efi::Guid::from_fields(0x4deba3a2, 0xe600, 0x45e6, 0xba, 0xa3, &[0x06, 0x91, 0xbc, 0xeb, 0xc4, 0xf9]);
+
+/// The size of `EFI_FAULT_TOLERANT_WORKING_BLOCK_HEADER`.
+const WORKING_HEADER_SIZE: usize = 32;
+/// The offsets of the CRC, the state and the write queue size in the working block header.
+const WORKING_CRC_OFFSET: usize = 16;
+const WORKING_STATE_OFFSET: usize = 20;
+const WORKING_QUEUE_SIZE_OFFSET: usize = 24;
+
+/// The size of `EFI_FAULT_TOLERANT_WRITE_HEADER`.
+const WRITE_HEADER_SIZE: usize = 40;
+/// The offsets of the caller ID, the number of writes and the private data size in a write header.
+const CALLER_ID_OFFSET: usize = 4;
+const NUMBER_OF_WRITES_OFFSET: usize = 24;
+const PRIVATE_DATA_SIZE_OFFSET: usize = 32;
+
+/// The size of `EFI_FAULT_TOLERANT_WRITE_RECORD`, without the private data that follows it.
+const RECORD_SIZE: usize = 40;
+/// The offsets of the LBA, the offset, the length and the relative offset in a record.
+const LBA_OFFSET: usize = 8;
+const OFFSET_OFFSET: usize = 16;
+const LENGTH_OFFSET: usize = 24;
+const RELATIVE_OFFSET_OFFSET: usize = 32;
+
+/// The state bits, set by clearing them.
+const WORKING_BLOCK_VALID: u8 = 0x1;
+const WORKING_BLOCK_INVALID: u8 = 0x2;
+const HEADER_ALLOCATED: u8 = 0x1;
+const WRITES_ALLOCATED: u8 = 0x2;
+const WRITE_COMPLETE: u8 = 0x4;
+const SPARE_COMPLETE: u8 = 0x2;
+const DESTINATION_COMPLETE: u8 = 0x4;
+
+/// Returns whether the bits of `flags` are set in `state`, i.e. cleared on the storage.
+fn is_set(state: u8, flags: u8) -> bool {
+    state & flags == 0
+}
+
+/// Returns the CRC32 of `data`, as computed by the `CalculateCrc32()` boot service.
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(u32::MAX, |crc, byte| {
+        (0..8).fold(crc ^ u32::from(*byte), |crc, _| if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 })
+    })
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+/// A write header of the write queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct WriteHeader {
+    /// The offset of the header in the storage.
+    offset: usize,
+    state: u8,
+    caller_id: efi::Guid,
+    /// The number of writes, 0 if the sizes of the header do not fit the working block.
+    number_of_writes: usize,
+    private_data_size: usize,
+}
+
+impl WriteHeader {
+    fn is_complete(&self) -> bool {
+        is_set(self.state, WRITE_COMPLETE)
+    }
+
+    fn record_size(&self) -> usize {
+        RECORD_SIZE + self.private_data_size
+    }
+
+    fn record_offset(&self, index: usize) -> usize {
+        self.offset + WRITE_HEADER_SIZE + index * self.record_size()
+    }
+}
+
+/// A write record of the write queue.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct WriteRecord {
+    state: u8,
+    lba: u64,
+    offset: u64,
+    length: u64,
+    private_data: Vec<u8>,
+}
+
+impl WriteRecord {
+    /// Returns whether the destination of the write was recorded.
+    fn is_written(&self) -> bool {
+        [self.lba, self.offset, self.length] != [u64::MAX; 3]
+    }
+}
+
+/// The last write of the write queue, as reported by the EDK II `GetLastWrite()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LastWrite {
+    /// The ID of the caller that allocated the write.
+    pub caller_id: efi::Guid,
+    /// The LBA the write is relative to.
+    pub lba: efi::Lba,
+    /// The offset of the write from the start of the LBA.
+    pub offset: usize,
+    /// The length of the write.
+    pub length: usize,
+    /// The private data of the caller recorded with the write.
+    pub private_data: Vec<u8>,
+    /// Whether all the writes allocated with this one are done or aborted.
+    pub complete: bool,
+}
+
+/// A [FirmwareStorage] with writes journaled so they survive an interruption.
+///
+/// The storage exposes the blocks of the underlying storage minus the working and spare blocks. Erases are not
+/// journaled, an interrupted erase leaves blocks that must be erased again.
+#[derive(Debug)]
+pub struct FaultTolerantStorage<S: FirmwareStorage> {
+    storage: S,
+    geometry: BlockGeometry,
+    spare_blocks: usize,
+    last_header: Option<WriteHeader>,
+    /// The offset of the end of the write queue in the storage.
+    queue_end: usize,
+}
+
+impl<S: FirmwareStorage> FaultTolerantStorage<S> {
+    /// Opens the journal at the end of `storage` with a single spare block, see
+    /// [FaultTolerantStorage::with_spare_blocks].
+    pub fn new(storage: S) -> Result<Self, StorageError> {
+        Self::with_spare_blocks(storage, 1)
+    }
+
+    /// Opens the journal at the end of `storage`, followed by `spare_blocks` spare blocks, completing a write
+    /// interrupted by a reset.
+    ///
+    /// A working block without a valid header is formatted.
+    ///
+    /// # Errors
+    ///
+    /// - [StorageError::InvalidGeometry] if the storage has no block left for data, there is no spare block, or its
+    ///   blocks cannot hold a write header and its record.
+    /// - The errors of the underlying storage.
+    pub fn with_spare_blocks(storage: S, spare_blocks: usize) -> Result<Self, StorageError> {
+        let geometry = storage.geometry();
+        let reserved = spare_blocks.saturating_add(1);
+        if spare_blocks == 0
+            || geometry.block_count <= reserved
+            || geometry.block_size < WORKING_HEADER_SIZE + WRITE_HEADER_SIZE + RECORD_SIZE
+        {
+            return Err(StorageError::InvalidGeometry);
+        }
+        let geometry = BlockGeometry { block_size: geometry.block_size, block_count: geometry.block_count - reserved };
+        let mut ftw = Self { storage, geometry, spare_blocks, last_header: None, queue_end: 0 };
+
+        let mut header = [0; WORKING_HEADER_SIZE];
+        ftw.storage.read(ftw.working_offset(), &mut header)?;
+        if !Self::is_working_header_valid(&header) {
+            log::info!("Formatting the fault tolerant write working block.");
+            ftw.format()?;
+            return Ok(ftw);
+        }
+
+        ftw.scan_queue()?;
+        ftw.recover()?;
+        Ok(ftw)
+    }
+
+    /// Returns the largest write that is atomic as a whole, when it starts on a block boundary.
+    pub fn spare_size(&self) -> usize {
+        self.spare_blocks * self.geometry.block_size
+    }
+
+    /// Returns the underlying storage.
+    pub fn into_inner(self) -> S {
+        self.storage
+    }
+
+    /// Allocates `number_of_writes` writes for `caller_id`, each recording `private_data_size` bytes of private data.
+    ///
+    /// # Errors
+    ///
+    /// - [StorageError::WritesPending] if writes of the previous allocation are left.
+    /// - [StorageError::OutOfBounds] if the writes do not fit the working block.
+    /// - The errors of the underlying storage.
+    pub fn allocate(
+        &mut self,
+        caller_id: &efi::Guid,
+        private_data_size: usize,
+        number_of_writes: usize,
+    ) -> Result<(), StorageError> {
+        if self.has_pending_writes() {
+            return Err(StorageError::WritesPending);
+        }
+        let size = RECORD_SIZE
+            .checked_add(private_data_size)
+            .and_then(|record_size| record_size.checked_mul(number_of_writes))
+            .and_then(|records_size| records_size.checked_add(WRITE_HEADER_SIZE))
+            .filter(|size| *size <= self.working_end() - self.queue_start())
+            .ok_or(StorageError::OutOfBounds)?;
+        if self.queue_end + size > self.working_end() {
+            self.reclaim(size)?;
+        }
+
+        let offset = self.queue_end;
+        let mut header = [ERASED_BYTE; WRITE_HEADER_SIZE];
+        header[0] = !HEADER_ALLOCATED;
+        header[CALLER_ID_OFFSET..CALLER_ID_OFFSET + 16].copy_from_slice(caller_id.as_bytes());
+        header[NUMBER_OF_WRITES_OFFSET..NUMBER_OF_WRITES_OFFSET + 8]
+            .copy_from_slice(&(number_of_writes as u64).to_le_bytes());
+        header[PRIVATE_DATA_SIZE_OFFSET..PRIVATE_DATA_SIZE_OFFSET + 8]
+            .copy_from_slice(&(private_data_size as u64).to_le_bytes());
+        self.storage.write(offset, &header)?;
+        self.queue_end = offset + size;
+        self.last_header =
+            Some(WriteHeader { offset, state: header[0], caller_id: *caller_id, number_of_writes, private_data_size });
+        self.set_header_state(WRITES_ALLOCATED)
+    }
+
+    /// Returns true if writes of the last allocation are left.
+    pub fn has_pending_writes(&self) -> bool {
+        self.pending_header().is_some()
+    }
+
+    /// Returns the size of the private data of the pending writes, or 0 if none are pending.
+    pub fn pending_private_data_size(&self) -> usize {
+        self.pending_header().map_or(0, |header| header.private_data_size)
+    }
+
+    /// Performs the next allocated write, of `data` at `offset` from the start of `lba`, recording `private_data`.
+    ///
+    /// # Errors
+    ///
+    /// - [StorageError::NotAllocated] if no write is pending.
+    /// - [StorageError::OutOfBounds] if the write is not within the storage, or touches more blocks than there are
+    ///   spare blocks.
+    /// - The errors of the underlying storage. The writes left of the allocation are then abandoned.
+    pub fn write_allocated(
+        &mut self,
+        lba: efi::Lba,
+        offset: usize,
+        data: &[u8],
+        private_data: &[u8],
+    ) -> Result<(), StorageError> {
+        let header = self.pending_header().ok_or(StorageError::NotAllocated)?;
+        let Some((index, record)) = self.next_record(&header)? else {
+            return Err(StorageError::NotAllocated);
+        };
+        if record.is_written() {
+            // A failed write of the allocation abandoned the rest of it.
+            return Err(StorageError::NotAllocated);
+        }
+
+        let block_size = self.geometry.block_size;
+        let (block, block_offset) = usize::try_from(lba)
+            .ok()
+            .and_then(|lba| lba.checked_add(offset / block_size))
+            .map(|block| (block, offset % block_size))
+            .ok_or(StorageError::OutOfBounds)?;
+        if block_offset.saturating_add(data.len()) > self.spare_size() {
+            return Err(StorageError::OutOfBounds);
+        }
+        let count = (block_offset + data.len()).div_ceil(block_size);
+        self.geometry.check_blocks(block, count)?;
+
+        let mut content = vec![0; count * block_size];
+        self.storage.read(block * block_size, &mut content)?;
+        content[block_offset..block_offset + data.len()].copy_from_slice(data);
+
+        let mut record = vec![ERASED_BYTE; header.record_size()];
+        record[LBA_OFFSET..LBA_OFFSET + 8].copy_from_slice(&lba.to_le_bytes());
+        record[OFFSET_OFFSET..OFFSET_OFFSET + 8].copy_from_slice(&(offset as u64).to_le_bytes());
+        record[LENGTH_OFFSET..LENGTH_OFFSET + 8].copy_from_slice(&(data.len() as u64).to_le_bytes());
+        // The destination is in the storage of the working block.
+        record[RELATIVE_OFFSET_OFFSET..RELATIVE_OFFSET_OFFSET + 8].copy_from_slice(&0i64.to_le_bytes());
+        let private_data = &private_data[..private_data.len().min(header.private_data_size)];
+        record[RECORD_SIZE..RECORD_SIZE + private_data.len()].copy_from_slice(private_data);
+
+        let result = self.perform(&header, index, &record, block, &content);
+        if result.is_err() {
+            let _ = self.set_header_state(WRITE_COMPLETE);
+        }
+        result
+    }
+
+    /// Abandons the allocated writes left.
+    ///
+    /// # Errors
+    ///
+    /// Returns [StorageError::NotAllocated] if no write is pending.
+    pub fn abort(&mut self) -> Result<(), StorageError> {
+        self.pending_header().ok_or(StorageError::NotAllocated)?;
+        self.set_header_state(WRITE_COMPLETE)
+    }
+
+    /// Returns the last write performed of the last allocation, if any.
+    pub fn last_write(&self) -> Result<Option<LastWrite>, StorageError> {
+        let Some(header) = self.last_header else {
+            return Ok(None);
+        };
+        let mut last = None;
+        for index in 0..header.number_of_writes {
+            let record = self.read_record(&header, index)?;
+            if !record.is_written() {
+                break;
+            }
+            last = Some(record);
+        }
+        Ok(last.map(|record| LastWrite {
+            caller_id: header.caller_id,
+            lba: record.lba,
+            offset: record.offset as usize,
+            length: record.length as usize,
+            private_data: record.private_data,
+            complete: header.is_complete(),
+        }))
+    }
+
+    fn working_offset(&self) -> usize {
+        self.geometry.size()
+    }
+
+    fn spare_offset(&self) -> usize {
+        self.working_offset() + self.geometry.block_size
+    }
+
+    fn queue_start(&self) -> usize {
+        self.working_offset() + WORKING_HEADER_SIZE
+    }
+
+    fn working_end(&self) -> usize {
+        self.spare_offset()
+    }
+
+    fn pending_header(&self) -> Option<WriteHeader> {
+        self.last_header.filter(|header| !header.is_complete())
+    }
+
+    /// Returns the working block header of the storage, in the valid state.
+    fn working_header(&self) -> [u8; WORKING_HEADER_SIZE] {
+        let mut header = [ERASED_BYTE; WORKING_HEADER_SIZE];
+        header[..16].copy_from_slice(WORKING_BLOCK_SIGNATURE.as_bytes());
+        let queue_size = (self.geometry.block_size - WORKING_HEADER_SIZE) as u64;
+        header[WORKING_QUEUE_SIZE_OFFSET..].copy_from_slice(&queue_size.to_le_bytes());
+        // The CRC covers the header with the CRC and the state erased.
+        let crc = crc32(&header);
+        header[WORKING_CRC_OFFSET..WORKING_CRC_OFFSET + 4].copy_from_slice(&crc.to_le_bytes());
+        header[WORKING_STATE_OFFSET] &= !WORKING_BLOCK_VALID;
+        header
+    }
+
+    fn is_working_header_valid(header: &[u8; WORKING_HEADER_SIZE]) -> bool {
+        let mut erased = *header;
+        erased[WORKING_CRC_OFFSET..WORKING_CRC_OFFSET + 4].fill(ERASED_BYTE);
+        erased[WORKING_STATE_OFFSET] |= WORKING_BLOCK_VALID | WORKING_BLOCK_INVALID;
+        let crc = u32::from_le_bytes(header[WORKING_CRC_OFFSET..WORKING_CRC_OFFSET + 4].try_into().unwrap());
+        header[..16] == *WORKING_BLOCK_SIGNATURE.as_bytes()
+            && crc == crc32(&erased)
+            && is_set(header[WORKING_STATE_OFFSET], WORKING_BLOCK_VALID)
+            && !is_set(header[WORKING_STATE_OFFSET], WORKING_BLOCK_INVALID)
+    }
+
+    fn format(&mut self) -> Result<(), StorageError> {
+        self.storage.erase(self.geometry.block_count, 1)?;
+        self.storage.write(self.working_offset(), &self.working_header())?;
+        self.last_header = None;
+        self.queue_end = self.queue_start();
+        Ok(())
+    }
+
+    /// Formats the working block, keeping the last write header and its records when `size` bytes fit after them.
+    fn reclaim(&mut self, size: usize) -> Result<(), StorageError> {
+        let last = match self.last_header {
+            Some(header)
+                if header.record_offset(header.number_of_writes) - header.offset + size
+                    <= self.working_end() - self.queue_start() =>
+            {
+                let mut bytes = vec![0; header.record_offset(header.number_of_writes) - header.offset];
+                self.storage.read(header.offset, &mut bytes)?;
+                Some((header, bytes))
+            }
+            _ => None,
+        };
+        self.format()?;
+        if let Some((header, bytes)) = last {
+            let offset = self.queue_start();
+            self.storage.write(offset, &bytes)?;
+            self.last_header = Some(WriteHeader { offset, ..header });
+            self.queue_end = offset + bytes.len();
+        }
+        Ok(())
+    }
+
+    fn read_write_header(&self, offset: usize) -> Result<WriteHeader, StorageError> {
+        let mut bytes = [0; WRITE_HEADER_SIZE];
+        self.storage.read(offset, &mut bytes)?;
+        let mut header = WriteHeader {
+            offset,
+            state: bytes[0],
+            caller_id: efi::Guid::from_bytes(&bytes[CALLER_ID_OFFSET..CALLER_ID_OFFSET + 16].try_into().unwrap()),
+            number_of_writes: 0,
+            private_data_size: 0,
+        };
+        let number_of_writes = usize::try_from(read_u64(&bytes, NUMBER_OF_WRITES_OFFSET));
+        let private_data_size = usize::try_from(read_u64(&bytes, PRIVATE_DATA_SIZE_OFFSET));
+        if let (Ok(number_of_writes), Ok(private_data_size)) = (number_of_writes, private_data_size) {
+            let end = RECORD_SIZE
+                .checked_add(private_data_size)
+                .and_then(|record_size| record_size.checked_mul(number_of_writes))
+                .and_then(|records_size| records_size.checked_add(offset + WRITE_HEADER_SIZE));
+            if end.is_some_and(|end| end <= self.working_end()) {
+                header.number_of_writes = number_of_writes;
+                header.private_data_size = private_data_size;
+            }
+        }
+        Ok(header)
+    }
+
+    fn read_record(&self, header: &WriteHeader, index: usize) -> Result<WriteRecord, StorageError> {
+        let mut bytes = vec![0; header.record_size()];
+        self.storage.read(header.record_offset(index), &mut bytes)?;
+        Ok(WriteRecord {
+            state: bytes[0],
+            lba: read_u64(&bytes, LBA_OFFSET),
+            offset: read_u64(&bytes, OFFSET_OFFSET),
+            length: read_u64(&bytes, LENGTH_OFFSET),
+            private_data: bytes[RECORD_SIZE..].to_vec(),
+        })
+    }
+
+    /// Returns the first record of the allocation whose destination is not complete.
+    fn next_record(&self, header: &WriteHeader) -> Result<Option<(usize, WriteRecord)>, StorageError> {
+        for index in 0..header.number_of_writes {
+            let record = self.read_record(header, index)?;
+            if !is_set(record.state, DESTINATION_COMPLETE) {
+                return Ok(Some((index, record)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Finds the last write header of the queue, and the end of the queue.
+    fn scan_queue(&mut self) -> Result<(), StorageError> {
+        let mut offset = self.queue_start();
+        while offset + WRITE_HEADER_SIZE <= self.working_end() {
+            let header = self.read_write_header(offset)?;
+            if !is_set(header.state, HEADER_ALLOCATED) {
+                break;
+            }
+            self.last_header = Some(header);
+            if header.number_of_writes == 0 {
+                // The sizes of the header were not written, nothing can follow it.
+                offset = self.working_end();
+                break;
+            }
+            offset = header.record_offset(header.number_of_writes);
+        }
+        self.queue_end = offset;
+        Ok(())
+    }
+
+    /// Finishes or abandons the write of the last allocation interrupted by a reset.
+    fn recover(&mut self) -> Result<(), StorageError> {
+        let Some(header) = self.pending_header() else {
+            return Ok(());
+        };
+        if !is_set(header.state, WRITES_ALLOCATED) {
+            log::warn!("Abandoning the interrupted fault tolerant write allocation.");
+            return self.set_header_state(WRITE_COMPLETE);
+        }
+        let Some((index, record)) = self.next_record(&header)? else {
+            // The reset happened after the last write, before the header was marked complete.
+            return self.set_header_state(WRITE_COMPLETE);
+        };
+        if !record.is_written() {
+            // The write was not started, it remains pending.
+            return Ok(());
+        }
+
+        match self.destination(&record) {
+            Some((block, count)) if is_set(record.state, SPARE_COMPLETE) => {
+                log::warn!("Completing the interrupted fault tolerant write of {count} block(s) at block {block}.");
+                let mut content = vec![0; count * self.geometry.block_size];
+                self.storage.read(self.spare_offset(), &mut content)?;
+                self.commit(&header, index, block, &content)
+            }
+            _ => {
+                log::warn!("Abandoning the interrupted fault tolerant write at LBA {:#x}.", record.lba);
+                self.set_header_state(WRITE_COMPLETE)
+            }
+        }
+    }
+
+    /// Returns the first block and the number of blocks of the write of `record`, if they fit the spare blocks.
+    fn destination(&self, record: &WriteRecord) -> Option<(usize, usize)> {
+        let block_size = self.geometry.block_size as u64;
+        let block = usize::try_from(record.lba.checked_add(record.offset / block_size)?).ok()?;
+        let count =
+            usize::try_from((record.offset % block_size).checked_add(record.length)?.div_ceil(block_size)).ok()?;
+        (count <= self.spare_blocks && self.geometry.check_blocks(block, count).is_ok()).then_some((block, count))
+    }
+
+    /// Sets `flags` in the state of the last write header.
+    fn set_header_state(&mut self, flags: u8) -> Result<(), StorageError> {
+        let Some(header) = self.last_header.as_mut() else {
+            return Err(StorageError::NotAllocated);
+        };
+        header.state &= !flags;
+        let (offset, state) = (header.offset, header.state);
+        self.storage.write(offset, &[state])
+    }
+
+    /// Writes the record at `index`, then `content` to the spare blocks, then commits it to the blocks from `block`.
+    fn perform(
+        &mut self,
+        header: &WriteHeader,
+        index: usize,
+        record: &[u8],
+        block: usize,
+        content: &[u8],
+    ) -> Result<(), StorageError> {
+        let record_offset = header.record_offset(index);
+        self.storage.write(record_offset, record)?;
+        self.storage.erase(self.geometry.block_count + 1, content.len() / self.geometry.block_size)?;
+        self.storage.write(self.spare_offset(), content)?;
+        self.storage.write(record_offset, &[!SPARE_COMPLETE])?;
+        self.commit(header, index, block, content)
+    }
+
+    /// Erases the blocks from `block` and writes `content` to them, then marks the record at `index` complete, and
+    /// the write header with its last record.
+    fn commit(&mut self, header: &WriteHeader, index: usize, block: usize, content: &[u8]) -> Result<(), StorageError> {
+        self.storage.erase(block, content.len() / self.geometry.block_size)?;
+        self.storage.write(block * self.geometry.block_size, content)?;
+        self.storage.write(header.record_offset(index), &[!(SPARE_COMPLETE | DESTINATION_COMPLETE)])?;
+        if index + 1 == header.number_of_writes {
+            self.set_header_state(WRITE_COMPLETE)?;
+        }
+        Ok(())
+    }
+}
+
+impl<S: FirmwareStorage> FirmwareStorage for FaultTolerantStorage<S> {
+    fn geometry(&self) -> BlockGeometry {
+        self.geometry
+    }
+
+    fn read(&self, offset: usize, buffer: &mut [u8]) -> Result<(), StorageError> {
+        self.geometry.check_range(offset, buffer.len())?;
+        self.storage.read(offset, buffer)
+    }
+
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), StorageError> {
+        self.geometry.check_range(offset, data.len())?;
+        let block_size = self.geometry.block_size;
+        let mut done = 0;
+        while done < data.len() {
+            // Each update covers as many of the blocks left as the spare blocks can hold.
+            let position = offset + done;
+            let (block, block_offset) = (position / block_size, position % block_size);
+            let chunk = (self.spare_size() - block_offset).min(data.len() - done);
+
+            let new = &data[done..done + chunk];
+            let mut current = vec![0; chunk];
+            self.storage.read(position, &mut current)?;
+            if current != new {
+                self.allocate(&PATINA_CALLER_ID, 0, 1)?;
+                self.write_allocated(block as efi::Lba, block_offset, new, &[])?;
+            }
+            done += chunk;
+        }
+        Ok(())
+    }
+
+    fn erase(&mut self, block: usize, count: usize) -> Result<(), StorageError> {
+        self.geometry.check_blocks(block, count)?;
+        self.storage.erase(block, count)
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use crate::firmware_storage::{MockFirmwareStorage, RamStorage};
+
+    const BLOCK_SIZE: usize = 0x100;
+    const CALLER: efi::Guid = efi::Guid::from_fields(1, 2, 3, 4, 5, &[6; 6]);
+
+    fn blocks(storage: &RamStorage, block: usize, count: usize) -> &[u8] {
+        &storage.as_bytes()[block * BLOCK_SIZE..(block + count) * BLOCK_SIZE]
+    }
+
+    #[test]
+    fn crc32_should_match_the_boot_service() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(&[]), 0);
+    }
+
+    #[test]
+    fn the_working_block_should_have_the_edk2_layout() {
+        let mut ftw = FaultTolerantStorage::new(RamStorage::new(BLOCK_SIZE, 4)).unwrap();
+        ftw.write(0x10, &[0x5A; 4]).unwrap();
+
+        let storage = ftw.into_inner();
+        let working = blocks(&storage, 2, 1);
+        assert_eq!(working[..16], *WORKING_BLOCK_SIGNATURE.as_bytes());
+        assert_eq!(working[WORKING_STATE_OFFSET], 0xFE);
+        assert_eq!(read_u64(working, WORKING_QUEUE_SIZE_OFFSET), (BLOCK_SIZE - WORKING_HEADER_SIZE) as u64);
+
+        // The write header, marked allocated, writes allocated and complete, followed by its record.
+        let header = &working[WORKING_HEADER_SIZE..WORKING_HEADER_SIZE + WRITE_HEADER_SIZE];
+        assert_eq!(header[0], 0xF8);
+        assert_eq!(header[CALLER_ID_OFFSET..CALLER_ID_OFFSET + 16], *PATINA_CALLER_ID.as_bytes());
+        assert_eq!(read_u64(header, NUMBER_OF_WRITES_OFFSET), 1);
+        assert_eq!(read_u64(header, PRIVATE_DATA_SIZE_OFFSET), 0);
+        let record = &working[WORKING_HEADER_SIZE + WRITE_HEADER_SIZE..][..RECORD_SIZE];
+        assert_eq!(record[0], 0xF9);
+        assert_eq!((read_u64(record, LBA_OFFSET), read_u64(record, OFFSET_OFFSET)), (0, 0x10));
+        assert_eq!((read_u64(record, LENGTH_OFFSET), read_u64(record, RELATIVE_OFFSET_OFFSET)), (4, 0));
+
+        // A working block with a corrupted header is formatted.
+        let mut storage = storage;
+        storage.write(2 * BLOCK_SIZE + WORKING_QUEUE_SIZE_OFFSET, &[0]).unwrap();
+        let ftw = FaultTolerantStorage::new(storage).unwrap();
+        assert_eq!(ftw.last_write(), Ok(None));
+    }
+
+    #[test]
+    fn writes_should_replace_block_content() {
+        let mut ftw = FaultTolerantStorage::new(RamStorage::new(BLOCK_SIZE, 5)).unwrap();
+        assert_eq!(ftw.geometry(), BlockGeometry { block_size: BLOCK_SIZE, block_count: 3 });
+        assert_eq!(ftw.spare_size(), BLOCK_SIZE);
+
+        // Unlike raw writes, journaled writes can set bits.
+        ftw.write(0xF0, &[0x00; 0x20]).unwrap();
+        ftw.write(0xF8, &[0xA5; 4]).unwrap();
+        let mut buffer = [0; 0x20];
+        ftw.read(0xF0, &mut buffer).unwrap();
+        assert_eq!(buffer[..8], [0; 8]);
+        assert_eq!(buffer[8..12], [0xA5; 4]);
+        assert_eq!(buffer[12..], [0; 0x14]);
+
+        let last_write = ftw.last_write().unwrap().unwrap();
+        assert_eq!((last_write.caller_id, last_write.lba, last_write.offset), (PATINA_CALLER_ID, 0, 0xF8));
+        assert!(last_write.complete);
+
+        assert_eq!(ftw.write(3 * BLOCK_SIZE, &[0]), Err(StorageError::OutOfBounds));
+        assert_eq!(ftw.erase(2, 2), Err(StorageError::OutOfBounds));
+    }
+
+    #[test]
+    fn writes_within_the_spare_blocks_should_be_a_single_update() {
+        let mut ftw = FaultTolerantStorage::with_spare_blocks(RamStorage::new(BLOCK_SIZE, 8), 2).unwrap();
+        assert_eq!(ftw.geometry().block_count, 5);
+        assert_eq!(ftw.spare_size(), 2 * BLOCK_SIZE);
+
+        ftw.write(BLOCK_SIZE, &[0x5A; 2 * BLOCK_SIZE]).unwrap();
+        let last_write = ftw.last_write().unwrap().unwrap();
+        assert_eq!((last_write.lba, last_write.offset, last_write.length), (1, 0, 2 * BLOCK_SIZE));
+
+        // Three blocks are split in two updates.
+        ftw.write(BLOCK_SIZE / 2, &[0x00; 2 * BLOCK_SIZE]).unwrap();
+        let last_write = ftw.last_write().unwrap().unwrap();
+        assert_eq!((last_write.lba, last_write.offset, last_write.length), (2, 0, BLOCK_SIZE / 2));
+        let storage = ftw.into_inner();
+        assert!(blocks(&storage, 0, 1)[..BLOCK_SIZE / 2].iter().all(|byte| *byte == ERASED_BYTE));
+        assert!(blocks(&storage, 0, 3)[BLOCK_SIZE / 2..5 * BLOCK_SIZE / 2].iter().all(|byte| *byte == 0));
+        assert!(blocks(&storage, 2, 1)[BLOCK_SIZE / 2..].iter().all(|byte| *byte == 0x5A));
+    }
+
+    #[test]
+    fn the_working_block_should_be_reclaimed_when_full() {
+        let mut ftw = FaultTolerantStorage::new(RamStorage::new(BLOCK_SIZE, 3)).unwrap();
+        for value in 0..10u8 {
+            ftw.write(1, &[value]).unwrap();
+        }
+        // The queue holds two single write allocations.
+        assert_eq!(ftw.queue_end, ftw.queue_start() + 2 * (WRITE_HEADER_SIZE + RECORD_SIZE));
+
+        let storage = ftw.into_inner();
+        assert_eq!(blocks(&storage, 0, 1)[1], 9);
+        let ftw = FaultTolerantStorage::new(storage).unwrap();
+        assert_eq!(ftw.queue_end, ftw.queue_start() + 2 * (WRITE_HEADER_SIZE + RECORD_SIZE));
+        assert_eq!(ftw.last_write().unwrap().unwrap().offset, 1);
+    }
+
+    #[test]
+    fn allocated_writes_should_persist_across_resets() {
+        let mut ftw = FaultTolerantStorage::with_spare_blocks(RamStorage::new(BLOCK_SIZE, 8), 2).unwrap();
+        assert_eq!(ftw.write_allocated(0, 0, &[0], &[]), Err(StorageError::NotAllocated));
+        assert_eq!(ftw.abort(), Err(StorageError::NotAllocated));
+
+        ftw.allocate(&CALLER, 2, 2).unwrap();
+        assert_eq!(ftw.allocate(&CALLER, 2, 1), Err(StorageError::WritesPending));
+        assert_eq!(ftw.write(0, &[0]), Err(StorageError::WritesPending));
+        ftw.write_allocated(1, 0x10, &[0x11; 4], &[0xA, 0xB, 0xC]).unwrap();
+
+        let mut ftw = FaultTolerantStorage::with_spare_blocks(ftw.into_inner(), 2).unwrap();
+        let last_write = ftw.last_write().unwrap().unwrap();
+        assert_eq!(last_write.caller_id, CALLER);
+        assert_eq!((last_write.lba, last_write.offset, last_write.length), (1, 0x10, 4));
+        assert_eq!(last_write.private_data, [0xA, 0xB]);
+        assert!(!last_write.complete);
+        assert!(ftw.has_pending_writes());
+        assert_eq!(ftw.pending_private_data_size(), 2);
+
+        assert_eq!(ftw.write_allocated(1, 4 * BLOCK_SIZE - 1, &[0; 2], &[]), Err(StorageError::OutOfBounds));
+        ftw.write_allocated(2, 0, &[0x22; 4], &[]).unwrap();
+        let last_write = ftw.last_write().unwrap().unwrap();
+        assert_eq!((last_write.lba, last_write.private_data.as_slice()), (2, [0xFF, 0xFF].as_slice()));
+        assert!(last_write.complete);
+        assert!(!ftw.has_pending_writes());
+        assert_eq!(ftw.pending_private_data_size(), 0);
+        assert_eq!(ftw.write_allocated(2, 0, &[0x22; 4], &[]), Err(StorageError::NotAllocated));
+
+        let mut buffer = [0; 4];
+        ftw.read(BLOCK_SIZE + 0x10, &mut buffer).unwrap();
+        assert_eq!(buffer, [0x11; 4]);
+
+        // Aborting completes the allocation.
+        ftw.allocate(&CALLER, 0, 3).unwrap();
+        ftw.write_allocated(0, 0, &[0x33], &[]).unwrap();
+        ftw.abort().unwrap();
+        let ftw = FaultTolerantStorage::with_spare_blocks(ftw.into_inner(), 2).unwrap();
+        assert!(ftw.last_write().unwrap().unwrap().complete);
+    }
+
+    #[test]
+    fn a_write_interrupted_after_the_spare_should_be_completed() {
+        let mut ftw = FaultTolerantStorage::with_spare_blocks(RamStorage::new(BLOCK_SIZE, 6), 2).unwrap();
+        ftw.write(BLOCK_SIZE, &[0x11; BLOCK_SIZE]).unwrap();
+
+        // Simulate a reset after the spare was written and the first block erased.
+        let content = [0x22; 2 * BLOCK_SIZE];
+        ftw.allocate(&CALLER, 0, 1).unwrap();
+        let header = ftw.last_header.unwrap();
+        let mut record = [ERASED_BYTE; RECORD_SIZE];
+        record[0] = !SPARE_COMPLETE;
+        record[LBA_OFFSET..LBA_OFFSET + 8].copy_from_slice(&1u64.to_le_bytes());
+        record[OFFSET_OFFSET..OFFSET_OFFSET + 8].copy_from_slice(&0u64.to_le_bytes());
+        record[LENGTH_OFFSET..LENGTH_OFFSET + 8].copy_from_slice(&(2 * BLOCK_SIZE as u64).to_le_bytes());
+        ftw.storage.write(header.record_offset(0), &record).unwrap();
+        ftw.storage.erase(4, 2).unwrap();
+        ftw.storage.write(4 * BLOCK_SIZE, &content).unwrap();
+        ftw.storage.erase(1, 1).unwrap();
+
+        let storage = ftw.into_inner();
+        assert!(blocks(&storage, 1, 1).iter().all(|byte| *byte == ERASED_BYTE));
+        let ftw = FaultTolerantStorage::with_spare_blocks(storage, 2).unwrap();
+        assert_eq!(blocks(&ftw.storage, 1, 2), content);
+        let last_write = ftw.last_write().unwrap().unwrap();
+        assert_eq!((last_write.caller_id, last_write.lba, last_write.complete), (CALLER, 1, true));
+    }
+
+    #[test]
+    fn a_write_interrupted_before_the_spare_should_be_abandoned() {
+        let mut ftw = FaultTolerantStorage::with_spare_blocks(RamStorage::new(BLOCK_SIZE, 5), 2).unwrap();
+        ftw.write(0, &[0x11; BLOCK_SIZE]).unwrap();
+
+        // Simulate a reset while the record was written.
+        ftw.allocate(&CALLER, 0, 2).unwrap();
+        let header = ftw.last_header.unwrap();
+        ftw.storage.write(header.record_offset(0) + LBA_OFFSET, &[0]).unwrap();
+        let ftw = FaultTolerantStorage::with_spare_blocks(ftw.into_inner(), 2).unwrap();
+        assert_eq!(blocks(&ftw.storage, 0, 1), [0x11; BLOCK_SIZE]);
+        assert!(ftw.last_write().unwrap().unwrap().complete);
+
+        // Simulate a reset while the write header was written.
+        let mut ftw = FaultTolerantStorage::with_spare_blocks(RamStorage::new(BLOCK_SIZE, 5), 2).unwrap();
+        let offset = ftw.queue_end;
+        ftw.storage.write(offset, &[!HEADER_ALLOCATED]).unwrap();
+        let mut ftw = FaultTolerantStorage::with_spare_blocks(ftw.into_inner(), 2).unwrap();
+        assert_eq!(ftw.last_header.unwrap().offset, offset);
+        assert!(ftw.last_header.unwrap().is_complete());
+        ftw.write(0, &[0x22; BLOCK_SIZE]).unwrap();
+        assert_eq!(blocks(&ftw.storage, 0, 1), [0x22; BLOCK_SIZE]);
+    }
+
+    #[test]
+    fn small_storages_should_be_rejected() {
+        let mut storage = MockFirmwareStorage::new();
+        storage.expect_geometry().return_const(BlockGeometry { block_size: 0x1000, block_count: 2 });
+        storage.expect_read().never();
+        assert!(matches!(FaultTolerantStorage::new(storage), Err(StorageError::InvalidGeometry)));
+        assert!(matches!(
+            FaultTolerantStorage::with_spare_blocks(RamStorage::new(BLOCK_SIZE, 4), 0),
+            Err(StorageError::InvalidGeometry)
+        ));
+        assert!(matches!(FaultTolerantStorage::new(RamStorage::new(0x40, 4)), Err(StorageError::InvalidGeometry)));
+    }
+}
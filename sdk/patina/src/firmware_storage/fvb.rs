@@ -0,0 +1,289 @@
+//! Firmware Volume Block Storage Bridge
+//!
+//! [FvbStorage] implements [FirmwareStorage] over a Firmware Volume Block protocol instance, such as the one a SPI-NOR
+//! driver produces over the flash part holding the variable store. Offsets are split into an LBA and an offset within
+//! the block for each FVB call.
+//!
+//! Only FVBs made of blocks of a single size are supported, as is the case of SPI-NOR flash parts.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use core::{ffi::c_void, ptr::NonNull};
+
+use patina_pi::protocols::firmware_volume_block;
+use r_efi::efi;
+
+use super::{BlockGeometry, FirmwareStorage, StorageError};
+
+/// Terminates the list of LBA ranges passed to EraseBlocks().
+const LBA_LIST_TERMINATOR: efi::Lba = u64::MAX;
+
+/// EraseBlocks() is variadic, this is its signature for a single range of blocks.
+type EraseBlockRange =
+    extern "efiapi" fn(*mut firmware_volume_block::Protocol, efi::Lba, usize, efi::Lba) -> efi::Status;
+
+/// A [FirmwareStorage] backed by a Firmware Volume Block protocol.
+#[derive(Debug)]
+pub struct FvbStorage {
+    protocol: NonNull<firmware_volume_block::Protocol>,
+    geometry: BlockGeometry,
+}
+
+impl FvbStorage {
+    /// Creates a storage over the FVB `protocol`.
+    ///
+    /// # Safety
+    ///
+    /// `protocol` must point to a valid Firmware Volume Block protocol instance that outlives the storage.
+    ///
+    /// # Errors
+    ///
+    /// - [StorageError::InvalidGeometry] if the blocks of the FVB do not all have the same size.
+    /// - [StorageError::DeviceError] if the block size cannot be read.
+    pub unsafe fn new(protocol: NonNull<firmware_volume_block::Protocol>) -> Result<Self, StorageError> {
+        // SAFETY: the caller guarantees protocol is valid.
+        let fvb = unsafe { protocol.as_ref() };
+        let (mut block_size, mut block_count) = (0, 0);
+        let status = (fvb.get_block_size)(protocol.as_ptr(), 0, &mut block_size, &mut block_count);
+        if status.is_error() {
+            log::error!("Failed to get the block size of the FVB: {status:#x?}");
+            return Err(StorageError::DeviceError);
+        }
+        if block_size == 0 || block_count == 0 {
+            return Err(StorageError::InvalidGeometry);
+        }
+
+        // The run of blocks starting at LBA 0 must cover the whole FVB.
+        let (mut next_size, mut next_count) = (0, 0);
+        let status = (fvb.get_block_size)(protocol.as_ptr(), block_count as efi::Lba, &mut next_size, &mut next_count);
+        if !status.is_error() {
+            log::error!("FVB blocks of {block_size:#x} and {next_size:#x} bytes are not supported");
+            return Err(StorageError::InvalidGeometry);
+        }
+
+        Ok(Self { protocol, geometry: BlockGeometry { block_size, block_count } })
+    }
+
+    /// Calls `operation` with the LBA, offset within the block and part of `length` bytes at `offset` in each block.
+    fn for_each_block(
+        &self,
+        offset: usize,
+        length: usize,
+        mut operation: impl FnMut(efi::Lba, usize, core::ops::Range<usize>) -> Result<(), StorageError>,
+    ) -> Result<(), StorageError> {
+        self.geometry.check_range(offset, length)?;
+        let mut done = 0;
+        while done < length {
+            let position = offset + done;
+            let block_offset = position % self.geometry.block_size;
+            let chunk = (self.geometry.block_size - block_offset).min(length - done);
+            operation((position / self.geometry.block_size) as efi::Lba, block_offset, done..done + chunk)?;
+            done += chunk;
+        }
+        Ok(())
+    }
+
+    fn fvb(&self) -> &firmware_volume_block::Protocol {
+        // SAFETY: the protocol is valid for the lifetime of the storage, see [FvbStorage::new].
+        unsafe { self.protocol.as_ref() }
+    }
+}
+
+fn check_status(status: efi::Status, transferred: usize, expected: usize) -> Result<(), StorageError> {
+    match status {
+        efi::Status::WRITE_PROTECTED | efi::Status::ACCESS_DENIED => Err(StorageError::WriteProtected),
+        status if status.is_error() => Err(StorageError::DeviceError),
+        _ if transferred != expected => Err(StorageError::DeviceError),
+        _ => Ok(()),
+    }
+}
+
+impl FirmwareStorage for FvbStorage {
+    fn geometry(&self) -> BlockGeometry {
+        self.geometry
+    }
+
+    fn read(&self, offset: usize, buffer: &mut [u8]) -> Result<(), StorageError> {
+        let (read, protocol) = (self.fvb().read, self.protocol.as_ptr());
+        self.for_each_block(offset, buffer.len(), |lba, block_offset, range| {
+            let mut size = range.len();
+            let status =
+                read(protocol, lba, block_offset, &mut size, buffer[range.clone()].as_mut_ptr() as *mut c_void);
+            check_status(status, size, range.len())
+        })
+    }
+
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), StorageError> {
+        let (write, protocol) = (self.fvb().write, self.protocol.as_ptr());
+        self.for_each_block(offset, data.len(), |lba, block_offset, range| {
+            let mut size = range.len();
+            // The FVB does not modify the data written, even though it takes a mutable pointer.
+            let status = write(protocol, lba, block_offset, &mut size, data[range.clone()].as_ptr() as *mut c_void);
+            check_status(status, size, range.len())
+        })
+    }
+
+    fn erase(&mut self, block: usize, count: usize) -> Result<(), StorageError> {
+        self.geometry.check_blocks(block, count)?;
+        if count == 0 {
+            return Ok(());
+        }
+        // SAFETY: EraseBlocks() takes (LBA, count) pairs terminated by LBA_LIST_TERMINATOR as variadic arguments, which
+        // the efiapi calling convention passes as it would fixed arguments of the same types.
+        let erase_blocks: EraseBlockRange = unsafe { core::mem::transmute(self.fvb().erase_blocks) };
+        let status = erase_blocks(self.protocol.as_ptr(), block as efi::Lba, count, LBA_LIST_TERMINATOR);
+        check_status(status, 0, 0)
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::firmware_storage::{ERASED_BYTE, RamStorage};
+    use patina_pi::{fw_fs::EfiFvbAttributes2, hob::EfiPhysicalAddress};
+    use std::boxed::Box;
+
+    const BLOCK_SIZE: usize = 0x100;
+    const BLOCK_COUNT: usize = 4;
+
+    /// An FVB over a [RamStorage], the protocol is the first field so the FVB can be found from the protocol pointer.
+    #[repr(C)]
+    struct RamFvb {
+        protocol: firmware_volume_block::Protocol,
+        storage: RamStorage,
+        write_protected: bool,
+    }
+
+    fn ram_fvb(this: *mut firmware_volume_block::Protocol) -> &'static mut RamFvb {
+        unsafe { &mut *(this as *mut RamFvb) }
+    }
+
+    extern "efiapi" fn get_attributes(
+        _: *mut firmware_volume_block::Protocol,
+        _: *mut EfiFvbAttributes2,
+    ) -> efi::Status {
+        efi::Status::UNSUPPORTED
+    }
+
+    extern "efiapi" fn get_physical_address(
+        _: *mut firmware_volume_block::Protocol,
+        _: *mut EfiPhysicalAddress,
+    ) -> efi::Status {
+        efi::Status::UNSUPPORTED
+    }
+
+    extern "efiapi" fn get_block_size(
+        _: *mut firmware_volume_block::Protocol,
+        lba: efi::Lba,
+        block_size: *mut usize,
+        block_count: *mut usize,
+    ) -> efi::Status {
+        if lba as usize >= BLOCK_COUNT {
+            return efi::Status::INVALID_PARAMETER;
+        }
+        unsafe {
+            block_size.write(BLOCK_SIZE);
+            block_count.write(BLOCK_COUNT - lba as usize);
+        }
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn read(
+        this: *mut firmware_volume_block::Protocol,
+        lba: efi::Lba,
+        offset: usize,
+        size: *mut usize,
+        buffer: *mut c_void,
+    ) -> efi::Status {
+        let fvb = ram_fvb(this);
+        let buffer = unsafe { core::slice::from_raw_parts_mut(buffer as *mut u8, *size) };
+        assert!(offset + buffer.len() <= BLOCK_SIZE, "read crosses a block boundary");
+        fvb.storage.read(lba as usize * BLOCK_SIZE + offset, buffer).unwrap();
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn write(
+        this: *mut firmware_volume_block::Protocol,
+        lba: efi::Lba,
+        offset: usize,
+        size: *mut usize,
+        buffer: *mut c_void,
+    ) -> efi::Status {
+        let fvb = ram_fvb(this);
+        if fvb.write_protected {
+            return efi::Status::WRITE_PROTECTED;
+        }
+        let data = unsafe { core::slice::from_raw_parts(buffer as *const u8, *size) };
+        assert!(offset + data.len() <= BLOCK_SIZE, "write crosses a block boundary");
+        fvb.storage.write(lba as usize * BLOCK_SIZE + offset, data).unwrap();
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn erase_blocks(
+        this: *mut firmware_volume_block::Protocol,
+        lba: efi::Lba,
+        count: usize,
+        terminator: efi::Lba,
+    ) -> efi::Status {
+        assert_eq!(terminator, LBA_LIST_TERMINATOR);
+        ram_fvb(this).storage.erase(lba as usize, count).unwrap();
+        efi::Status::SUCCESS
+    }
+
+    fn fvb_storage() -> (FvbStorage, Box<RamFvb>) {
+        let mut fvb = Box::new(RamFvb {
+            protocol: firmware_volume_block::Protocol {
+                get_attributes,
+                set_attributes: get_attributes,
+                get_physical_address,
+                get_block_size,
+                read,
+                write,
+                // SAFETY: the bridge calls EraseBlocks() with a single range.
+                erase_blocks: unsafe {
+                    core::mem::transmute::<EraseBlockRange, firmware_volume_block::EraseBlocks>(erase_blocks)
+                },
+                parent_handle: core::ptr::null_mut(),
+            },
+            storage: RamStorage::new(BLOCK_SIZE, BLOCK_COUNT),
+            write_protected: false,
+        });
+        let storage = unsafe { FvbStorage::new(NonNull::from(&mut fvb.protocol)).unwrap() };
+        (storage, fvb)
+    }
+
+    #[test]
+    fn fvb_storage_should_split_accesses_on_blocks() {
+        let (mut storage, fvb) = fvb_storage();
+        assert_eq!(storage.geometry(), BlockGeometry { block_size: BLOCK_SIZE, block_count: BLOCK_COUNT });
+
+        let data = (0..=255).cycle().take(2 * BLOCK_SIZE + 0x20).collect::<std::vec::Vec<u8>>();
+        storage.write(0xF0, &data).unwrap();
+        assert_eq!(&fvb.storage.as_bytes()[0xF0..0xF0 + data.len()], data.as_slice());
+
+        let mut buffer = std::vec![0; data.len()];
+        storage.read(0xF0, &mut buffer).unwrap();
+        assert_eq!(buffer, data);
+
+        storage.erase(1, 2).unwrap();
+        assert!(fvb.storage.as_bytes()[BLOCK_SIZE..3 * BLOCK_SIZE].iter().all(|byte| *byte == ERASED_BYTE));
+        assert_eq!(fvb.storage.as_bytes()[3 * BLOCK_SIZE], data[3 * BLOCK_SIZE - 0xF0]);
+    }
+
+    #[test]
+    fn fvb_storage_should_report_errors() {
+        let (mut storage, mut fvb) = fvb_storage();
+        assert_eq!(storage.read(BLOCK_SIZE * BLOCK_COUNT, &mut [0]), Err(StorageError::OutOfBounds));
+        assert_eq!(storage.erase(BLOCK_COUNT, 1), Err(StorageError::OutOfBounds));
+
+        fvb.write_protected = true;
+        assert_eq!(storage.write(0, &[0]), Err(StorageError::WriteProtected));
+    }
+}
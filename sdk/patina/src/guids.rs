@@ -1,7 +1,8 @@
 //! Patina GUID Definitions
 //!
-//! GUIDs that are used for common and generic events between drivers but are not defined in a formal
-//! specification.
+//! Well-known GUIDs that are used for common and generic events, protocols, and tables between drivers, including
+//! some that are not defined in a formal specification. New GUIDs should be added here with the [`guid!`](crate::guid)
+//! macro rather than built ad hoc.
 //!
 //! ## License
 //!
@@ -12,14 +13,131 @@
 
 use r_efi::efi;
 
+/// ACPI 1.0 Table GUID
+///
+/// The configuration table GUID of the ACPI 1.0 RSDP (`ACPI_10_TABLE_GUID`), published by the ACPI table driver along
+/// with [ACPI_20_TABLE] on platforms that still support ACPI 1.0 OSes.
+///
+/// (`EB9D2D30-2D88-11D3-9A16-0090273FC14D`)
+/// ```
+/// # use patina::{Guid, guids::ACPI_10_TABLE};
+/// # assert_eq!("EB9D2D30-2D88-11D3-9A16-0090273FC14D", format!("{:?}", Guid::from_ref(&ACPI_10_TABLE)));
+/// ```
+pub const ACPI_10_TABLE: efi::Guid = crate::guid!("EB9D2D30-2D88-11D3-9A16-0090273FC14D");
+
+/// ACPI 2.0 Table GUID
+///
+/// The configuration table GUID of the ACPI 2.0 and later RSDP (`EFI_ACPI_TABLE_GUID`), published by the ACPI table
+/// driver.
+///
+/// (`8868E871-E4F1-11D3-BC22-0080C73C8881`)
+/// ```
+/// # use patina::{Guid, guids::ACPI_20_TABLE};
+/// # assert_eq!("8868E871-E4F1-11D3-BC22-0080C73C8881", format!("{:?}", Guid::from_ref(&ACPI_20_TABLE)));
+/// ```
+pub const ACPI_20_TABLE: efi::Guid = crate::guid!("8868E871-E4F1-11D3-BC22-0080C73C8881");
+
+/// Identifies the configuration table pointing to the reserved buffer in which the DXE core lists the page
+/// allocations made during boot, with the name of the image that made each of them, for debugging.
+///
+/// (`F7D0C158-CAF9-494D-B9B7-6716213C854F`)
+/// ```
+/// # use patina::{Guid, guids::ALLOCATION_ATTRIBUTION_TABLE};
+/// # assert_eq!("F7D0C158-CAF9-494D-B9B7-6716213C854F", format!("{:?}", Guid::from_ref(&ALLOCATION_ATTRIBUTION_TABLE)));
+/// ```
+pub const ALLOCATION_ATTRIBUTION_TABLE: efi::Guid = crate::guid!("F7D0C158-CAF9-494D-B9B7-6716213C854F");
+
 /// Cache Attribute Change Event Group GUID
 ///
 /// The GUID for an event group signaled when the cache attributes for a memory region are changed. The event group
 /// is intended for architectures, such as x86, that require cache attribute changes to be propagated to all APs.
 ///
 /// (`b8e477c7-26a9-4b9a-a7c9-5f8f1f3d9c7b`)
-pub const CACHE_ATTRIBUTE_CHANGE_EVENT_GROUP: efi::Guid =
-    efi::Guid::from_fields(0xb8e477c7, 0x26a9, 0x4b9a, 0xa7, 0xc9, &[0x5f, 0x8f, 0x1f, 0x3d, 0x9c, 0x7b]);
+pub const CACHE_ATTRIBUTE_CHANGE_EVENT_GROUP: efi::Guid = crate::guid!("B8E477C7-26A9-4B9A-A7C9-5F8F1F3D9C7B");
+
+/// Conformance Profiles Table GUID
+///
+/// Identifies the EFI Conformance Profiles Table (ECPT) configuration table, listing the GUIDs of the conformance
+/// profiles the platform implements.
+///
+/// (`36122546-F7E7-4C8F-BD9B-EB8525B50C0B`)
+/// ```
+/// # use patina::{Guid, guids::CONFORMANCE_PROFILES_TABLE};
+/// # assert_eq!("36122546-F7E7-4C8F-BD9B-EB8525B50C0B", format!("{:?}", Guid::from_ref(&CONFORMANCE_PROFILES_TABLE)));
+/// ```
+pub const CONFORMANCE_PROFILES_TABLE: efi::Guid = crate::guid!("36122546-F7E7-4C8F-BD9B-EB8525B50C0B");
+
+/// UEFI Specification Conformance Profile GUID
+///
+/// Listed in the EFI Conformance Profiles Table by platforms conforming to the complete UEFI specification.
+///
+/// (`523C91AF-A195-4382-818D-295FE4006465`)
+/// ```
+/// # use patina::{Guid, guids::CONFORMANCE_PROFILE_UEFI_SPEC};
+/// # assert_eq!("523C91AF-A195-4382-818D-295FE4006465", format!("{:?}", Guid::from_ref(&CONFORMANCE_PROFILE_UEFI_SPEC)));
+/// ```
+pub const CONFORMANCE_PROFILE_UEFI_SPEC: efi::Guid = crate::guid!("523C91AF-A195-4382-818D-295FE4006465");
+
+/// Console In Device GUID
+///
+/// Tags the handles of the devices to be used as console input. The GUID is installed with a NULL interface, by the
+/// boot manager on the devices listed in the `ConIn` variable, and consumed by the console splitter.
+///
+/// (`D3B36F2B-D551-11D4-9A46-0090273FC14D`)
+/// ```
+/// # use patina::{Guid, guids::CONSOLE_IN_DEVICE};
+/// # assert_eq!("D3B36F2B-D551-11D4-9A46-0090273FC14D", format!("{:?}", Guid::from_ref(&CONSOLE_IN_DEVICE)));
+/// ```
+pub const CONSOLE_IN_DEVICE: efi::Guid = crate::guid!("D3B36F2B-D551-11D4-9A46-0090273FC14D");
+
+/// Console Out Device GUID
+///
+/// Tags the handles of the devices to be used as console output. The GUID is installed with a NULL interface, by the
+/// boot manager on the devices listed in the `ConOut` variable, and consumed by the console splitter.
+///
+/// (`D3B36F2C-D551-11D4-9A46-0090273FC14D`)
+/// ```
+/// # use patina::{Guid, guids::CONSOLE_OUT_DEVICE};
+/// # assert_eq!("D3B36F2C-D551-11D4-9A46-0090273FC14D", format!("{:?}", Guid::from_ref(&CONSOLE_OUT_DEVICE)));
+/// ```
+pub const CONSOLE_OUT_DEVICE: efi::Guid = crate::guid!("D3B36F2C-D551-11D4-9A46-0090273FC14D");
+
+/// Device Tree Table GUID
+///
+/// The configuration table GUID of the flattened device tree blob handed off to the OS loader
+/// (`EFI_DTB_TABLE_GUID`).
+///
+/// (`B1B621D5-F19C-41A5-830B-D9152C69AAE0`)
+/// ```
+/// # use patina::{Guid, guids::DEVICE_TREE_TABLE};
+/// # assert_eq!("B1B621D5-F19C-41A5-830B-D9152C69AAE0", format!("{:?}", Guid::from_ref(&DEVICE_TREE_TABLE)));
+/// ```
+pub const DEVICE_TREE_TABLE: efi::Guid = crate::guid!("B1B621D5-F19C-41A5-830B-D9152C69AAE0");
+
+/// Driver dispatch failure status code data GUID.
+///
+/// Identifies the data attached to the error status codes the DXE core reports for the drivers that failed to load
+/// or start: the file name of the driver (an `efi::Guid`), followed by the failure status (an `efi::Status`).
+///
+/// (`AF7C5088-06B6-48CA-868B-A26F460E88A8`)
+/// ```
+/// # use patina::{Guid, guids::DRIVER_DISPATCH_FAILURE};
+/// # assert_eq!("AF7C5088-06B6-48CA-868B-A26F460E88A8", format!("{:?}", Guid::from_ref(&DRIVER_DISPATCH_FAILURE)));
+/// ```
+pub const DRIVER_DISPATCH_FAILURE: efi::Guid = crate::guid!("AF7C5088-06B6-48CA-868B-A26F460E88A8");
+
+/// Driver binding failure status code data GUID.
+///
+/// Identifies the data attached to the error status codes the DXE core reports when the Start() or Stop() function of
+/// a driver binding fails: the file name of the driver (an `efi::Guid`), the controller handle (an `efi::Handle`),
+/// followed by the failure status (an `efi::Status`).
+///
+/// (`8706130E-2FEC-48D9-B1F6-6FE8B524B1E3`)
+/// ```
+/// # use patina::{Guid, guids::DRIVER_BINDING_FAILURE};
+/// # assert_eq!("8706130E-2FEC-48D9-B1F6-6FE8B524B1E3", format!("{:?}", Guid::from_ref(&DRIVER_BINDING_FAILURE)));
+/// ```
+pub const DRIVER_BINDING_FAILURE: efi::Guid = crate::guid!("8706130E-2FEC-48D9-B1F6-6FE8B524B1E3");
 
 /// DXE Core Module GUID
 ///
@@ -34,8 +152,7 @@ pub const CACHE_ATTRIBUTE_CHANGE_EVENT_GROUP: efi::Guid =
 /// # use patina::{Guid, guids::DXE_CORE};
 /// # assert_eq!("23C9322F-2AF2-476A-BC4C-26BC88266C71", format!("{:?}", Guid::from_ref(&DXE_CORE)));
 /// ```
-pub const DXE_CORE: efi::Guid =
-    efi::Guid::from_fields(0x23C9322F, 0x2AF2, 0x476A, 0xBC, 0x4C, &[0x26, 0xBC, 0x88, 0x26, 0x6C, 0x71]);
+pub const DXE_CORE: efi::Guid = crate::guid!("23C9322F-2AF2-476A-BC4C-26BC88266C71");
 
 /// Exit Boot Services Failed GUID
 ///
@@ -48,8 +165,7 @@ pub const DXE_CORE: efi::Guid =
 /// # use patina::{Guid, guids::EBS_FAILED};
 /// # assert_eq!("4F6C5507-232F-4787-B95E-72F862490CB1", format!("{:?}", Guid::from_ref(&EBS_FAILED)));
 /// ```
-pub const EBS_FAILED: efi::Guid =
-    efi::Guid::from_fields(0x4f6c5507, 0x232f, 0x4787, 0xb9, 0x5e, &[0x72, 0xf8, 0x62, 0x49, 0x0c, 0xb1]);
+pub const EBS_FAILED: efi::Guid = crate::guid!("4F6C5507-232F-4787-B95E-72F862490CB1");
 
 /// EDKII FPDT (Firmware Performance Data Table) extender firmware performance.
 ///
@@ -62,8 +178,7 @@ pub const EBS_FAILED: efi::Guid =
 /// # use patina::{Guid, guids::EDKII_FPDT_EXTENDED_FIRMWARE_PERFORMANCE};
 /// # assert_eq!("3B387BFD-7ABC-4CF2-A0CA-B6A16C1B1B25", format!("{:?}", Guid::from_ref(&EDKII_FPDT_EXTENDED_FIRMWARE_PERFORMANCE)));
 /// ```
-pub const EDKII_FPDT_EXTENDED_FIRMWARE_PERFORMANCE: efi::Guid =
-    efi::Guid::from_fields(0x3b387bfd, 0x7abc, 0x4cf2, 0xa0, 0xca, &[0xb6, 0xa1, 0x6c, 0x1b, 0x1b, 0x25]);
+pub const EDKII_FPDT_EXTENDED_FIRMWARE_PERFORMANCE: efi::Guid = crate::guid!("3B387BFD-7ABC-4CF2-A0CA-B6A16C1B1B25");
 
 /// End of dxe event group GUID.
 ///
@@ -72,8 +187,7 @@ pub const EDKII_FPDT_EXTENDED_FIRMWARE_PERFORMANCE: efi::Guid =
 /// # use patina::{Guid, guids::EVENT_GROUP_END_OF_DXE};
 /// # assert_eq!("02CE967A-DD7E-4FFC-9EE7-810CF0470880", format!("{:?}", Guid::from_ref(&EVENT_GROUP_END_OF_DXE)));
 /// ```
-pub const EVENT_GROUP_END_OF_DXE: efi::Guid =
-    efi::Guid::from_fields(0x2ce967a, 0xdd7e, 0x4ffc, 0x9e, 0xe7, &[0x81, 0xc, 0xf0, 0x47, 0x8, 0x80]);
+pub const EVENT_GROUP_END_OF_DXE: efi::Guid = crate::guid!("02CE967A-DD7E-4FFC-9EE7-810CF0470880");
 
 /// Hardware Interrupt protocol GUID.
 /// This protocol provides a means of registering and unregistering interrupt handlers for AARCH64 systems.
@@ -83,8 +197,7 @@ pub const EVENT_GROUP_END_OF_DXE: efi::Guid =
 /// # use patina::{Guid, guids::HARDWARE_INTERRUPT_PROTOCOL};
 /// # assert_eq!("2890B3EA-053D-1643-AD0C-D64808DA3FF1", format!("{:?}", Guid::from_ref(&HARDWARE_INTERRUPT_PROTOCOL)));
 /// ```
-pub const HARDWARE_INTERRUPT_PROTOCOL: efi::Guid =
-    efi::Guid::from_fields(0x2890B3EA, 0x053D, 0x1643, 0xAD, 0x0C, &[0xD6, 0x48, 0x08, 0xDA, 0x3F, 0xF1]);
+pub const HARDWARE_INTERRUPT_PROTOCOL: efi::Guid = crate::guid!("2890B3EA-053D-1643-AD0C-D64808DA3FF1");
 
 /// Hardware Interrupt v2 protocol GUID.
 /// This protocol provides a means of registering and unregistering interrupt handlers for AARCH64 systems.
@@ -95,8 +208,41 @@ pub const HARDWARE_INTERRUPT_PROTOCOL: efi::Guid =
 /// # use patina::{Guid, guids::HARDWARE_INTERRUPT_PROTOCOL_V2};
 /// # assert_eq!("32898322-2DA1-474A-BAAA-F3F7CF569470", format!("{:?}", Guid::from_ref(&HARDWARE_INTERRUPT_PROTOCOL_V2)));
 /// ```
-pub const HARDWARE_INTERRUPT_PROTOCOL_V2: efi::Guid =
-    efi::Guid::from_fields(0x32898322, 0x2da1, 0x474a, 0xba, 0xaa, &[0xf3, 0xf7, 0xcf, 0x56, 0x94, 0x70]);
+pub const HARDWARE_INTERRUPT_PROTOCOL_V2: efi::Guid = crate::guid!("32898322-2DA1-474A-BAAA-F3F7CF569470");
+
+/// HOB List GUID
+///
+/// The configuration table GUID for the HOB list handed off to the DXE phase.
+///
+/// (`7739F24C-93D7-11D4-9A3A-0090273FC14D`)
+/// ```
+/// # use patina::{Guid, guids::HOB_LIST};
+/// # assert_eq!("7739F24C-93D7-11D4-9A3A-0090273FC14D", format!("{:?}", Guid::from_ref(&HOB_LIST)));
+/// ```
+pub const HOB_LIST: efi::Guid = crate::guid!("7739F24C-93D7-11D4-9A3A-0090273FC14D");
+
+/// Memory Allocation Stack HOB GUID.
+///
+/// Names the Memory Allocation HOB describing the stack the DXE core is entered on.
+///
+/// (`4ED4BF27-4092-42E9-807D-527B1D00C9BD`)
+/// ```
+/// # use patina::{Guid, guids::HOB_MEMORY_ALLOC_STACK};
+/// # assert_eq!("4ED4BF27-4092-42E9-807D-527B1D00C9BD", format!("{:?}", Guid::from_ref(&HOB_MEMORY_ALLOC_STACK)));
+/// ```
+pub const HOB_MEMORY_ALLOC_STACK: efi::Guid = crate::guid!("4ED4BF27-4092-42E9-807D-527B1D00C9BD");
+
+/// Memory Map Snapshot configuration table GUID.
+///
+/// Identifies the configuration table pointing to the reserved buffer in which the DXE core records the final memory
+/// map, GCD memory space map and Memory Attributes Table at ExitBootServices, for post-boot validation.
+///
+/// (`5C1B7E3A-94D2-4F6B-8A0E-3D7C21F4B9E6`)
+/// ```
+/// # use patina::{Guid, guids::MEMORY_MAP_SNAPSHOT};
+/// # assert_eq!("5C1B7E3A-94D2-4F6B-8A0E-3D7C21F4B9E6", format!("{:?}", Guid::from_ref(&MEMORY_MAP_SNAPSHOT)));
+/// ```
+pub const MEMORY_MAP_SNAPSHOT: efi::Guid = crate::guid!("5C1B7E3A-94D2-4F6B-8A0E-3D7C21F4B9E6");
 
 /// Memory Type Info GUID
 ///
@@ -112,8 +258,29 @@ pub const HARDWARE_INTERRUPT_PROTOCOL_V2: efi::Guid =
 /// # use patina::{Guid, guids::MEMORY_TYPE_INFORMATION};
 /// # assert_eq!("4C19049F-4137-4DD3-9C10-8B97A83FFDFA", format!("{:?}", Guid::from_ref(&MEMORY_TYPE_INFORMATION)));
 /// ```
-pub const MEMORY_TYPE_INFORMATION: efi::Guid =
-    efi::Guid::from_fields(0x4C19049F, 0x4137, 0x4DD3, 0x9C, 0x10, &[0x8B, 0x97, 0xA8, 0x3F, 0xFD, 0xFA]);
+pub const MEMORY_TYPE_INFORMATION: efi::Guid = crate::guid!("4C19049F-4137-4DD3-9C10-8B97A83FFDFA");
+
+/// Patina test result status code data GUID.
+///
+/// Identifies the data attached to the unrecovered error status code the DXE core reports when it panics: a
+/// `PanicRecord` of the DXE core, with the line, column and digests of the file name and message of the panic.
+///
+/// (`D3A5E1B2-7C4F-4B8E-9A61-2F0C5E8D7B43`)
+/// ```
+/// # use patina::{Guid, guids::PANIC_RECORD};
+/// # assert_eq!("D3A5E1B2-7C4F-4B8E-9A61-2F0C5E8D7B43", format!("{:?}", Guid::from_ref(&PANIC_RECORD)));
+/// ```
+pub const PANIC_RECORD: efi::Guid = crate::guid!("D3A5E1B2-7C4F-4B8E-9A61-2F0C5E8D7B43");
+
+/// Identifies the [TestSummary](crate::test::TestSummary) data attached to the status code reported by the
+/// [TestRunner](crate::test::TestRunner) once all tests have run.
+///
+/// (`932EE86E-71E1-406E-93B3-8505912849AB`)
+/// ```
+/// # use patina::{Guid, guids::PATINA_TEST_RESULT};
+/// # assert_eq!("932EE86E-71E1-406E-93B3-8505912849AB", format!("{:?}", Guid::from_ref(&PATINA_TEST_RESULT)));
+/// ```
+pub const PATINA_TEST_RESULT: efi::Guid = crate::guid!("932EE86E-71E1-406E-93B3-8505912849AB");
 
 /// Performance Protocol GUID.
 ///
@@ -124,8 +291,7 @@ pub const MEMORY_TYPE_INFORMATION: efi::Guid =
 /// # use patina::{Guid, guids::PERFORMANCE_PROTOCOL};
 /// # assert_eq!("76B6BDFA-2ACD-4462-9E3F-CB58C969D937", format!("{:?}", Guid::from_ref(&PERFORMANCE_PROTOCOL)));
 /// ```
-pub const PERFORMANCE_PROTOCOL: efi::Guid =
-    efi::Guid::from_fields(0x76b6bdfa, 0x2acd, 0x4462, 0x9E, 0x3F, &[0xcb, 0x58, 0xC9, 0x69, 0xd9, 0x37]);
+pub const PERFORMANCE_PROTOCOL: efi::Guid = crate::guid!("76B6BDFA-2ACD-4462-9E3F-CB58C969D937");
 
 /// EFI SMM Communication Protocol GUID as defined in the PI 1.2 specification.
 ///
@@ -137,8 +303,54 @@ pub const PERFORMANCE_PROTOCOL: efi::Guid =
 /// # use patina::{Guid, guids::SMM_COMMUNICATION_PROTOCOL};
 /// # assert_eq!("C68ED8E2-9DC6-4CBD-9D94-DB65ACC5C332", format!("{:?}", Guid::from_ref(&SMM_COMMUNICATION_PROTOCOL)));
 /// ```
-pub const SMM_COMMUNICATION_PROTOCOL: efi::Guid =
-    efi::Guid::from_fields(0xc68ed8e2, 0x9dc6, 0x4cbd, 0x9d, 0x94, &[0xdb, 0x65, 0xac, 0xc5, 0xc3, 0x32]);
+pub const SMM_COMMUNICATION_PROTOCOL: efi::Guid = crate::guid!("C68ED8E2-9DC6-4CBD-9D94-DB65ACC5C332");
+
+/// Random Seed Table GUID
+///
+/// The configuration table GUID of the random seed handed off to the OS loader, as defined by Linux
+/// (`LINUX_EFI_RANDOM_SEED_TABLE_GUID`). The table holds a `u32` size followed by that many bytes of entropy.
+///
+/// (`1CE1E5BC-7CEB-42F2-81E5-8AADF180F57B`)
+/// ```
+/// # use patina::{Guid, guids::RANDOM_SEED_TABLE};
+/// # assert_eq!("1CE1E5BC-7CEB-42F2-81E5-8AADF180F57B", format!("{:?}", Guid::from_ref(&RANDOM_SEED_TABLE)));
+/// ```
+pub const RANDOM_SEED_TABLE: efi::Guid = crate::guid!("1CE1E5BC-7CEB-42F2-81E5-8AADF180F57B");
+
+/// Real Time Clock Architectural Protocol GUID
+///
+/// Installed by the runtime driver of the RTC once it has set the time services of the runtime services table.
+///
+/// (`27CFAC87-46CC-11D4-9A38-0090273FC14D`)
+/// ```
+/// # use patina::{Guid, guids::REAL_TIME_CLOCK_ARCH_PROTOCOL};
+/// # assert_eq!("27CFAC87-46CC-11D4-9A38-0090273FC14D", format!("{:?}", Guid::from_ref(&REAL_TIME_CLOCK_ARCH_PROTOCOL)));
+/// ```
+pub const REAL_TIME_CLOCK_ARCH_PROTOCOL: efi::Guid = crate::guid!("27CFAC87-46CC-11D4-9A38-0090273FC14D");
+
+/// Standard Error Device GUID
+///
+/// Tags the handles of the devices to be used as standard error output. The GUID is installed with a NULL interface,
+/// by the boot manager on the devices listed in the `ErrOut` variable, and consumed by the console splitter.
+///
+/// (`D3B36F2D-D551-11D4-9A46-0090273FC14D`)
+/// ```
+/// # use patina::{Guid, guids::STANDARD_ERROR_DEVICE};
+/// # assert_eq!("D3B36F2D-D551-11D4-9A46-0090273FC14D", format!("{:?}", Guid::from_ref(&STANDARD_ERROR_DEVICE)));
+/// ```
+pub const STANDARD_ERROR_DEVICE: efi::Guid = crate::guid!("D3B36F2D-D551-11D4-9A46-0090273FC14D");
+
+/// EFI System Resource Table GUID.
+///
+/// The configuration table GUID for the EFI System Resource Table (ESRT), describing the firmware resources of the
+/// platform that can be updated with capsules.
+///
+/// (`B122A263-3661-4F68-9929-78F8B0D62180`)
+/// ```
+/// # use patina::{Guid, guids::SYSTEM_RESOURCE_TABLE};
+/// # assert_eq!("B122A263-3661-4F68-9929-78F8B0D62180", format!("{:?}", Guid::from_ref(&SYSTEM_RESOURCE_TABLE)));
+/// ```
+pub const SYSTEM_RESOURCE_TABLE: efi::Guid = crate::guid!("B122A263-3661-4F68-9929-78F8B0D62180");
 
 /// Zero GUID
 ///
@@ -149,4 +361,4 @@ pub const SMM_COMMUNICATION_PROTOCOL: efi::Guid =
 /// # use patina::{Guid, guids::ZERO};
 /// # assert_eq!("00000000-0000-0000-0000-000000000000", format!("{:?}", Guid::from_ref(&ZERO)));
 /// ```
-pub const ZERO: efi::Guid = efi::Guid::from_fields(0, 0, 0, 0, 0, &[0, 0, 0, 0, 0, 0]);
+pub const ZERO: efi::Guid = crate::guid!("00000000-0000-0000-0000-000000000000");
@@ -7,6 +7,8 @@
 //!
 //! - `core`: Exposes additional items in the [component] module necessary to
 //!   manage and execute components and their dependencies.
+//! - `mockall`: Exposes the mocks of the service traits, and the `mock_env`
+//!   scenario builder configuring them for component tests.
 //!
 //! ## License
 //!
@@ -18,10 +20,15 @@
 #![cfg_attr(any(test, feature = "alloc"), feature(allocator_api))]
 #![allow(static_mut_refs)]
 #![feature(coverage_attribute)]
+#![feature(link_llvm_intrinsics)]
+#![allow(internal_features)]
 
 extern crate alloc;
 
-pub use base::guid::{Guid, GuidError, OwnedGuid};
+pub use base::{
+    guid::{Guid, GuidError, OwnedGuid},
+    ucs2::{Ucs2Error, Ucs2Str, Ucs2String},
+};
 
 /// Common GUID constants
 pub mod guid_constants {
@@ -37,10 +44,14 @@ pub mod base;
 pub mod boot_services;
 pub mod component;
 pub mod driver_binding;
+pub mod dxe_services;
 pub mod efi_types;
 pub mod error;
+pub mod firmware_storage;
 pub mod guids;
 pub mod log;
+#[cfg(any(test, feature = "mockall"))]
+pub mod mock_env;
 pub mod performance;
 pub mod runtime_services;
 pub mod serial;
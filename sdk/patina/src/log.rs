@@ -29,6 +29,26 @@
 //! );
 //! ```
 //!
+//! The target filters are fixed when the logger is built. The level of selected targets can also be changed at
+//! runtime with a [LevelTable], e.g. from a variable, so verbose logging can be turned on without a rebuild:
+//!
+//! ```rust ignore
+//! use patina::log::{LevelTable, SerialLogger, TargetLevel, target};
+//!
+//! static LEVELS: [TargetLevel; 2] = [TargetLevel::new(target::GCD), TargetLevel::new(target::DISPATCHER)];
+//! static LEVEL_TABLE: LevelTable = LevelTable::new(&LEVELS);
+//!
+//! static LOGGER: SerialLogger<Uart16550> = SerialLogger::new(
+//!    Format::Standard,
+//!    &[(target::GCD, log::LevelFilter::Info)],
+//!    log::LevelFilter::Info,
+//!    Uart16550::new(Interface::Io(0x3F8)),
+//! )
+//! .with_level_table(&LEVEL_TABLE);
+//!
+//! LEVEL_TABLE.set_level(target::GCD, Some(log::LevelFilter::Trace)).unwrap();
+//! ```
+//!
 //! ## License
 //!
 //! Copyright (c) Microsoft Corporation.
@@ -36,9 +56,29 @@
 //! SPDX-License-Identifier: Apache-2.0
 //!
 
+pub mod target;
+
+mod level_table;
 mod serial_logger;
+pub use level_table::{LevelTable, TargetLevel};
 pub use serial_logger::Logger as SerialLogger;
 
+/// Returns the level filter of records of `target`.
+///
+/// The level set at runtime in `level_table` applies first, then the first of the compile-time `target_filters`
+/// whose target is a prefix of `target`, and finally `max_level`.
+pub fn target_level(
+    target: &str,
+    target_filters: &[(&str, log::LevelFilter)],
+    level_table: Option<&LevelTable>,
+    max_level: log::LevelFilter,
+) -> log::LevelFilter {
+    level_table
+        .and_then(|level_table| level_table.level(target))
+        .or_else(|| target_filters.iter().find(|(name, _)| target.starts_with(name)).map(|(_, level)| *level))
+        .unwrap_or(max_level)
+}
+
 /// Enum to describe the format of the log message.
 pub enum Format {
     /// Standard text format containing the log level and message.
@@ -97,3 +137,24 @@ impl Format {
         }
     }
 }
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target_level_precedence() {
+        let filters = [(target::GCD, log::LevelFilter::Off)];
+        let levels = [TargetLevel::new(target::GCD)];
+        let level_table = LevelTable::new(&levels);
+
+        let level = |target| target_level(target, &filters, Some(&level_table), log::LevelFilter::Info);
+        assert_eq!(level("patina_dxe_core::gcd::spin_locked_gcd"), log::LevelFilter::Off);
+        assert_eq!(level(target::DISPATCHER), log::LevelFilter::Info);
+
+        level_table.set_level(target::GCD, Some(log::LevelFilter::Trace)).unwrap();
+        assert_eq!(level("patina_dxe_core::gcd::spin_locked_gcd"), log::LevelFilter::Trace);
+        assert_eq!(target_level(target::GCD, &filters, None, log::LevelFilter::Info), log::LevelFilter::Off);
+    }
+}
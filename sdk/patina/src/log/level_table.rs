@@ -0,0 +1,236 @@
+//! Runtime Log Level Table
+//!
+//! A fixed table of log targets whose level can be changed while the firmware runs, e.g. to turn on verbose GCD or
+//! dispatcher logging in the field without rebuilding. The table is meant to live in a `static` next to the logger:
+//! it does not allocate, and levels are read and written atomically, so it can be consulted for every record.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use core::{
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use log::LevelFilter;
+
+use crate::error::EfiError;
+
+/// The value of a [TargetLevel] whose level is not set at runtime.
+const UNSET: usize = usize::MAX;
+
+/// The settings value clearing the runtime level of a target.
+const DEFAULT_SETTING: &str = "default";
+
+/// A log target whose level can be set at runtime.
+#[derive(Debug)]
+pub struct TargetLevel {
+    target: &'static str,
+    level: AtomicUsize,
+}
+
+impl TargetLevel {
+    /// Creates an entry for `target`, whose level is not set until [set_level](Self::set_level) is called.
+    pub const fn new(target: &'static str) -> Self {
+        Self { target, level: AtomicUsize::new(UNSET) }
+    }
+
+    /// Returns the target of the entry.
+    pub fn target(&self) -> &'static str {
+        self.target
+    }
+
+    /// Returns the level set at runtime for the target, if any.
+    pub fn level(&self) -> Option<LevelFilter> {
+        match self.level.load(Ordering::Relaxed) {
+            0 => Some(LevelFilter::Off),
+            1 => Some(LevelFilter::Error),
+            2 => Some(LevelFilter::Warn),
+            3 => Some(LevelFilter::Info),
+            4 => Some(LevelFilter::Debug),
+            5 => Some(LevelFilter::Trace),
+            _ => None,
+        }
+    }
+
+    /// Sets the level of the target, or clears it with `None` so the filters of the logger apply again.
+    pub fn set_level(&self, level: Option<LevelFilter>) {
+        self.level.store(level.map_or(UNSET, |level| level as usize), Ordering::Relaxed);
+    }
+}
+
+/// A table of log targets whose level can be set at runtime.
+///
+/// Loggers consult the table before their compile-time target filters: the first entry with a level set whose
+/// target is a prefix of the target of a record decides whether the record is logged.
+///
+/// ## Examples
+///
+/// ```rust
+/// use patina::log::{LevelTable, TargetLevel, target};
+///
+/// static LEVELS: [TargetLevel; 2] = [TargetLevel::new(target::GCD), TargetLevel::new(target::DISPATCHER)];
+/// static LEVEL_TABLE: LevelTable = LevelTable::new(&LEVELS);
+///
+/// LEVEL_TABLE.apply("patina_dxe_core::gcd=debug,patina_dxe_core::dispatcher=trace").unwrap();
+/// assert_eq!(LEVEL_TABLE.level("patina_dxe_core::gcd::spin_locked_gcd"), Some(log::LevelFilter::Debug));
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct LevelTable<'a> {
+    targets: &'a [TargetLevel],
+}
+
+impl<'a> LevelTable<'a> {
+    /// Creates a table of the given targets.
+    pub const fn new(targets: &'a [TargetLevel]) -> Self {
+        Self { targets }
+    }
+
+    /// Returns the targets of the table.
+    pub fn targets(&self) -> &'a [TargetLevel] {
+        self.targets
+    }
+
+    /// Returns the level set at runtime for records of `target`, if any.
+    pub fn level(&self, target: &str) -> Option<LevelFilter> {
+        self.targets.iter().filter(|entry| target.starts_with(entry.target)).find_map(TargetLevel::level)
+    }
+
+    /// Sets the level of `target`, which must be a target of the table, or clears it with `None`.
+    ///
+    /// The maximum level of the [log] crate is raised if needed, as records above it are discarded before reaching
+    /// the logger.
+    pub fn set_level(&self, target: &str, level: Option<LevelFilter>) -> Result<(), EfiError> {
+        let entry = self.entry(target).ok_or(EfiError::NotFound)?;
+        entry.set_level(level);
+        if let Some(level) = level
+            && level > log::max_level()
+        {
+            log::set_max_level(level);
+        }
+        Ok(())
+    }
+
+    /// Clears the level of all the targets of the table.
+    pub fn reset(&self) {
+        self.targets.iter().for_each(|entry| entry.set_level(None));
+    }
+
+    /// Applies comma separated `target=level` settings, e.g. `patina_dxe_core::gcd=debug,mm_comm=off`.
+    ///
+    /// Levels are the names of the [LevelFilter] values, case insensitive, or `default` to clear the level of the
+    /// target. The settings are only applied if they are all valid: [EfiError::NotFound] is returned if a target is
+    /// not in the table, and [EfiError::InvalidParameter] if a setting is malformed.
+    pub fn apply(&self, settings: &str) -> Result<(), EfiError> {
+        // Validate all the settings first, so that invalid settings do not leave the table partially updated.
+        for setting in Self::settings(settings) {
+            let (target, _) = setting?;
+            self.entry(target).ok_or(EfiError::NotFound)?;
+        }
+        for setting in Self::settings(settings) {
+            let (target, level) = setting?;
+            self.set_level(target, level)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the entry of `target`.
+    fn entry(&self, target: &str) -> Option<&'a TargetLevel> {
+        self.targets.iter().find(|entry| entry.target == target)
+    }
+
+    /// Parses comma separated `target=level` settings, ignoring empty settings.
+    fn settings(settings: &str) -> impl Iterator<Item = Result<(&str, Option<LevelFilter>), EfiError>> {
+        settings.split(',').map(str::trim).filter(|setting| !setting.is_empty()).map(|setting| {
+            let (target, level) = setting.split_once('=').ok_or(EfiError::InvalidParameter)?;
+            let level = level.trim();
+            let level = if level.eq_ignore_ascii_case(DEFAULT_SETTING) {
+                None
+            } else {
+                Some(LevelFilter::from_str(level).map_err(|_| EfiError::InvalidParameter)?)
+            };
+            Ok((target.trim(), level))
+        })
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use crate::log::target;
+
+    #[test]
+    fn test_target_level() {
+        let entry = TargetLevel::new(target::GCD);
+        assert_eq!(entry.target(), target::GCD);
+        assert_eq!(entry.level(), None);
+
+        for level in [
+            LevelFilter::Off,
+            LevelFilter::Error,
+            LevelFilter::Warn,
+            LevelFilter::Info,
+            LevelFilter::Debug,
+            LevelFilter::Trace,
+        ] {
+            entry.set_level(Some(level));
+            assert_eq!(entry.level(), Some(level));
+        }
+
+        entry.set_level(None);
+        assert_eq!(entry.level(), None);
+    }
+
+    #[test]
+    fn test_level_matches_target_prefixes() {
+        let levels = [TargetLevel::new(target::GCD), TargetLevel::new("patina_dxe_core")];
+        let table = LevelTable::new(&levels);
+        assert_eq!(table.level("patina_dxe_core::gcd::spin_locked_gcd"), None);
+
+        table.set_level("patina_dxe_core", Some(LevelFilter::Warn)).unwrap();
+        assert_eq!(table.level("patina_dxe_core::gcd::spin_locked_gcd"), Some(LevelFilter::Warn));
+        assert_eq!(table.level("patina_dxe_core::dispatcher"), Some(LevelFilter::Warn));
+        assert_eq!(table.level("patina_mm"), None);
+
+        // The first entry with a level set wins.
+        table.set_level(target::GCD, Some(LevelFilter::Trace)).unwrap();
+        assert_eq!(table.level("patina_dxe_core::gcd::spin_locked_gcd"), Some(LevelFilter::Trace));
+        assert_eq!(table.level("patina_dxe_core::dispatcher"), Some(LevelFilter::Warn));
+        assert!(log::max_level() >= LevelFilter::Trace);
+
+        assert_eq!(table.set_level("patina_mm", Some(LevelFilter::Trace)), Err(EfiError::NotFound));
+
+        table.reset();
+        assert!(table.targets().iter().all(|entry| entry.level().is_none()));
+    }
+
+    #[test]
+    fn test_apply_settings() {
+        let levels = [TargetLevel::new(target::GCD), TargetLevel::new(target::DISPATCHER)];
+        let table = LevelTable::new(&levels);
+
+        table.apply(" patina_dxe_core::gcd = DEBUG, patina_dxe_core::dispatcher=off,").unwrap();
+        assert_eq!(table.level(target::GCD), Some(LevelFilter::Debug));
+        assert_eq!(table.level(target::DISPATCHER), Some(LevelFilter::Off));
+
+        table.apply("patina_dxe_core::gcd=default").unwrap();
+        assert_eq!(table.level(target::GCD), None);
+        assert_eq!(table.level(target::DISPATCHER), Some(LevelFilter::Off));
+
+        // Invalid settings are not applied at all.
+        assert_eq!(table.apply("patina_dxe_core::gcd=trace,mm_comm=trace"), Err(EfiError::NotFound));
+        assert_eq!(
+            table.apply("patina_dxe_core::gcd=trace,patina_dxe_core::dispatcher"),
+            Err(EfiError::InvalidParameter)
+        );
+        assert_eq!(table.apply("patina_dxe_core::gcd=verbose"), Err(EfiError::InvalidParameter));
+        assert_eq!(table.level(target::GCD), None);
+
+        table.apply("").unwrap();
+        assert_eq!(table.level(target::DISPATCHER), Some(LevelFilter::Off));
+    }
+}
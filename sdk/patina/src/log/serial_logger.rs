@@ -9,7 +9,7 @@
 use crate::serial::SerialIO;
 use core::marker::Send;
 
-use super::Format;
+use super::{Format, LevelTable};
 
 /// A Base implementation for a logger.
 ///
@@ -23,6 +23,7 @@ where
 {
     serial_port: S,
     target_filters: &'a [(&'a str, log::LevelFilter)],
+    level_table: Option<&'a LevelTable<'a>>,
     max_level: log::LevelFilter,
     format: Format,
 }
@@ -38,7 +39,13 @@ where
         max_level: log::LevelFilter,
         serial_port: S,
     ) -> Self {
-        Self { serial_port, target_filters, max_level, format }
+        Self { serial_port, target_filters, level_table: None, max_level, format }
+    }
+
+    /// Uses `level_table` to change the level of its targets at runtime, over the target filters.
+    pub const fn with_level_table(mut self, level_table: &'a LevelTable<'a>) -> Self {
+        self.level_table = Some(level_table);
+        self
     }
 }
 
@@ -48,12 +55,7 @@ where
 {
     fn enabled(&self, metadata: &log::Metadata) -> bool {
         metadata.level().to_level_filter()
-            <= *self
-                .target_filters
-                .iter()
-                .find(|(name, _)| metadata.target().starts_with(name))
-                .map(|(_, level)| level)
-                .unwrap_or(&self.max_level)
+            <= super::target_level(metadata.target(), self.target_filters, self.level_table, self.max_level)
     }
 
     fn log(&self, record: &log::Record) {
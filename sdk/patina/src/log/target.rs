@@ -0,0 +1,67 @@
+//! Log Target Identifiers
+//!
+//! Well known log targets, to use with the `target:` argument of the [log] macros and to name targets in the
+//! compile-time target filters of the loggers and in a [LevelTable](super::LevelTable). Targets are plain
+//! `&'static str` constants, so using them costs nothing at runtime.
+//!
+//! Targets match by prefix: the filter of a module path target (e.g. [DISPATCHER]) also applies to its submodules.
+//! The targets of a component are nested under the path of its module target, so that filtering [GCD] also covers
+//! the GCD allocation, timing and paging records.
+//!
+//! ## Examples
+//!
+//! ```rust
+//! use patina::log::target;
+//!
+//! log::debug!(target: target::GCD_MEASURE, "Allocated {:#x} bytes", 0x1000);
+//! ```
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+/// All logging of the DXE core GCD, including the [ALLOCATIONS], [GCD_MEASURE] and [PAGING] targets.
+pub const GCD: &str = "patina_dxe_core::gcd";
+
+/// Memory allocations and frees performed through the GCD.
+pub const ALLOCATIONS: &str = "patina_dxe_core::gcd::allocations";
+
+/// Timing of GCD operations.
+pub const GCD_MEASURE: &str = "patina_dxe_core::gcd::measure";
+
+/// Page table updates made by the GCD when memory attributes change.
+pub const PAGING: &str = "patina_dxe_core::gcd::paging";
+
+/// The UEFI memory map, logged when it is retrieved.
+pub const EFI_MEMORY_MAP: &str = "efi_memory_map";
+
+/// All logging of the DXE core dispatcher.
+pub const DISPATCHER: &str = "patina_dxe_core::dispatcher";
+
+/// MM communication buffers and transactions.
+pub const MM_COMM: &str = "mm_comm";
+
+/// Software MMI triggers.
+pub const SW_MMI: &str = "sw_mmi";
+
+/// Bytes written to the UEFI Debug Port protocol.
+pub const DEBUG_PORT: &str = "debug_port";
+
+/// Debug messages reported as status codes, e.g. by EDKII drivers.
+pub const STATUS_CODE: &str = "status_code";
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gcd_targets_are_covered_by_the_gcd_target() {
+        for gcd_target in [ALLOCATIONS, GCD_MEASURE, PAGING] {
+            assert!(gcd_target.starts_with(GCD), "{gcd_target} is not matched by {GCD}");
+        }
+    }
+}
@@ -0,0 +1,314 @@
+//! Scenario builder for the boot and runtime services mocks.
+//!
+//! Testing a component against [MockBootServices] and [MockRuntimeServices] usually starts with the same
+//! expectations: the TPL is raised and restored, protocols are located and installed, events are created in a group,
+//! and variables are read. [MockEnv] configures those expectations in one place, so that a test only spells out what
+//! is specific to its scenario. Anything not covered by the builder is configured directly on the mocks, through
+//! [MockEnv::boot_services] and [MockEnv::runtime_services].
+//!
+//! Only available with the `mockall` feature.
+//!
+//! ## Example
+//!
+//! ```ignore
+//! use patina::{
+//!     boot_services::{BootServices, event::EventType, tpl::Tpl},
+//!     guids::EVENT_GROUP_END_OF_DXE,
+//!     mock_env::MockEnv,
+//! };
+//!
+//! let (boot_services, runtime_services) = MockEnv::new()
+//!     .with_protocol(StatusCodeRuntimeProtocol::new(report_status_code))
+//!     .expect_protocol_install::<MyProtocol>()
+//!     .expect_event_group::<Box<MyContext>>(EventType::NOTIFY_SIGNAL, Tpl::CALLBACK, &EVENT_GROUP_END_OF_DXE)
+//!     .into_services();
+//!
+//! assert_eq!(MyComponent.entry_point(Rc::new(boot_services), Rc::new(runtime_services)), Ok(()));
+//! ```
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use r_efi::efi;
+
+use crate::{
+    boot_services::{
+        MockBootServices,
+        c_ptr::{CMutPtr, CPtr},
+        event::EventType,
+        tpl::Tpl,
+    },
+    runtime_services::MockRuntimeServices,
+    uefi_protocol::ProtocolInterface,
+};
+
+/// Builds [MockBootServices] and [MockRuntimeServices] configured for a test scenario.
+///
+/// A new environment passes the TPL calls through: [raise_tpl](crate::boot_services::BootServices::raise_tpl)
+/// returns the previous TPL, starting at [Tpl::APPLICATION], and
+/// [restore_tpl](crate::boot_services::BootServices::restore_tpl) sets it back. The handles and events returned by
+/// the configured expectations are distinct non-null values.
+///
+/// Mockall matches the expectations of a call in the order they were added, so an expectation added through
+/// [MockEnv::boot_services] does not take precedence over one the builder already configured for the same call.
+pub struct MockEnv {
+    boot_services: MockBootServices,
+    runtime_services: MockRuntimeServices,
+    next_handle: usize,
+}
+
+impl Default for MockEnv {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockEnv {
+    /// Creates an environment where only the TPL calls are expected.
+    pub fn new() -> Self {
+        let mut boot_services = MockBootServices::new();
+        let tpl = Arc::new(AtomicUsize::new(Tpl::APPLICATION.into()));
+        let raised_tpl = tpl.clone();
+        boot_services
+            .expect_raise_tpl()
+            .returning(move |new_tpl| Tpl(raised_tpl.swap(new_tpl.into(), Ordering::SeqCst)));
+        boot_services.expect_restore_tpl().returning(move |old_tpl| tpl.store(old_tpl.into(), Ordering::SeqCst));
+
+        Self { boot_services, runtime_services: MockRuntimeServices::new(), next_handle: 1 }
+    }
+
+    /// Makes `interface` the instance of the protocol `P` found by
+    /// [locate_protocol](crate::boot_services::BootServices::locate_protocol) and
+    /// [locate_protocol_unchecked](crate::boot_services::BootServices::locate_protocol_unchecked).
+    ///
+    /// The interface is leaked, so every call returns the same instance.
+    pub fn with_protocol<P: ProtocolInterface + 'static>(mut self, interface: P) -> Self {
+        let address = Box::leak(Box::new(interface)) as *mut P as usize;
+        self.boot_services.expect_locate_protocol::<P>().returning(move |_| Ok(unsafe { &mut *(address as *mut P) }));
+        self.boot_services
+            .expect_locate_protocol_unchecked()
+            .withf(|protocol, _| *protocol == P::PROTOCOL_GUID)
+            .returning(move |_, _| Ok(address as *mut core::ffi::c_void));
+        self
+    }
+
+    /// Makes locating the protocol `P` fail with [NOT_FOUND](efi::Status::NOT_FOUND).
+    pub fn without_protocol<P: ProtocolInterface + 'static>(mut self) -> Self {
+        self.boot_services.expect_locate_protocol::<P>().returning(|_| Err(efi::Status::NOT_FOUND));
+        self.boot_services
+            .expect_locate_protocol_unchecked()
+            .withf(|protocol, _| *protocol == P::PROTOCOL_GUID)
+            .returning(|_, _| Err(efi::Status::NOT_FOUND));
+        self
+    }
+
+    /// Expects a boxed instance of the protocol `P` to be installed once, on a new handle or on the given one.
+    pub fn expect_protocol_install<P: ProtocolInterface + 'static>(mut self) -> Self {
+        let new_handle = self.next_handle();
+        self.boot_services.expect_install_protocol_interface::<P, Box<P>>().once().returning(
+            move |handle, protocol_interface| {
+                Ok((handle.unwrap_or(new_handle as efi::Handle), protocol_interface.metadata()))
+            },
+        );
+        self
+    }
+
+    /// Expects an event with the context `T` to be created once, with a notify function.
+    pub fn expect_event<T: CPtr<'static> + 'static>(mut self, event_type: EventType, notify_tpl: Tpl) -> Self {
+        let event = self.next_handle();
+        self.boot_services
+            .expect_create_event::<T>()
+            .once()
+            .withf_st(move |ty, tpl, notify_function, _| {
+                *ty == event_type && *tpl == notify_tpl && notify_function.is_some()
+            })
+            .returning_st(move |_, _, _, _| Ok(event as efi::Event));
+        self
+    }
+
+    /// Expects an event with the context `T` to be created once in `event_group`, with a notify function.
+    pub fn expect_event_group<T: CPtr<'static> + 'static>(
+        mut self,
+        event_type: EventType,
+        notify_tpl: Tpl,
+        event_group: &'static efi::Guid,
+    ) -> Self {
+        let event = self.next_handle();
+        self.boot_services
+            .expect_create_event_ex::<T>()
+            .once()
+            .withf_st(move |ty, tpl, notify_function, _, group| {
+                *ty == event_type && *tpl == notify_tpl && notify_function.is_some() && group == event_group
+            })
+            .returning_st(move |_, _, _, _, _| Ok(event as efi::Event));
+        self
+    }
+
+    /// Expects a configuration table of type `T` to be installed once for `guid`.
+    pub fn expect_configuration_table<T: CMutPtr<'static> + 'static>(mut self, guid: efi::Guid) -> Self {
+        self.boot_services
+            .expect_install_configuration_table::<T>()
+            .once()
+            .withf(move |table_guid, _| *table_guid == guid)
+            .return_const(Ok(()));
+        self
+    }
+
+    /// Makes [get_variable](crate::runtime_services::RuntimeServices::get_variable) return `value` and `attributes`
+    /// for the variable `name` in `namespace`, with or without the null terminator.
+    pub fn with_variable<T>(mut self, name: &[u16], namespace: efi::Guid, attributes: u32, value: T) -> Self
+    where
+        T: TryFrom<Vec<u8>> + Clone + Send + 'static,
+    {
+        let name = variable_name(name).to_vec();
+        self.runtime_services
+            .expect_get_variable::<T>()
+            .withf(move |variable_name_arg, namespace_arg, _| {
+                variable_name(variable_name_arg) == name && *namespace_arg == namespace
+            })
+            .returning(move |_, _, _| Ok((value.clone(), attributes)));
+        self
+    }
+
+    /// Makes [get_variable](crate::runtime_services::RuntimeServices::get_variable) fail with
+    /// [NOT_FOUND](efi::Status::NOT_FOUND) for the variable `name` in `namespace`.
+    pub fn without_variable<T>(mut self, name: &[u16], namespace: efi::Guid) -> Self
+    where
+        T: TryFrom<Vec<u8>> + 'static,
+    {
+        let name = variable_name(name).to_vec();
+        self.runtime_services
+            .expect_get_variable::<T>()
+            .withf(move |variable_name_arg, namespace_arg, _| {
+                variable_name(variable_name_arg) == name && *namespace_arg == namespace
+            })
+            .returning(|_, _, _| Err(efi::Status::NOT_FOUND));
+        self
+    }
+
+    /// Returns the boot services mock, to configure the expectations not covered by the builder.
+    pub fn boot_services(&mut self) -> &mut MockBootServices {
+        &mut self.boot_services
+    }
+
+    /// Returns the runtime services mock, to configure the expectations not covered by the builder.
+    pub fn runtime_services(&mut self) -> &mut MockRuntimeServices {
+        &mut self.runtime_services
+    }
+
+    /// Returns the configured mocks.
+    pub fn into_services(self) -> (MockBootServices, MockRuntimeServices) {
+        (self.boot_services, self.runtime_services)
+    }
+
+    fn next_handle(&mut self) -> usize {
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        handle
+    }
+}
+
+/// Returns `name` up to its null terminator, if any.
+fn variable_name(name: &[u16]) -> &[u16] {
+    name.iter().position(|&c| c == 0).map_or(name, |len| &name[..len])
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use crate::{boot_services::BootServices, guids::EVENT_GROUP_END_OF_DXE, runtime_services::RuntimeServices, ucs2};
+
+    #[derive(Debug, PartialEq)]
+    struct TestProtocol(u32);
+
+    unsafe impl ProtocolInterface for TestProtocol {
+        const PROTOCOL_GUID: efi::Guid =
+            efi::Guid::from_bytes(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16]);
+    }
+
+    struct MissingProtocol;
+
+    unsafe impl ProtocolInterface for MissingProtocol {
+        const PROTOCOL_GUID: efi::Guid =
+            efi::Guid::from_bytes(&[16, 15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1]);
+    }
+
+    extern "efiapi" fn notify(_event: efi::Event, _context: Box<u32>) {}
+
+    #[test]
+    fn tpl_should_pass_through() {
+        let (boot_services, _) = MockEnv::new().into_services();
+        assert_eq!(boot_services.raise_tpl(Tpl::CALLBACK), Tpl::APPLICATION);
+        assert_eq!(boot_services.raise_tpl(Tpl::NOTIFY), Tpl::CALLBACK);
+        boot_services.restore_tpl(Tpl::CALLBACK);
+        boot_services.restore_tpl(Tpl::APPLICATION);
+        assert_eq!(boot_services.raise_tpl(Tpl::NOTIFY), Tpl::APPLICATION);
+    }
+
+    #[test]
+    fn protocols_should_be_located_and_installed() {
+        let (boot_services, _) = MockEnv::new()
+            .with_protocol(TestProtocol(42))
+            .without_protocol::<MissingProtocol>()
+            .expect_protocol_install::<TestProtocol>()
+            .into_services();
+
+        let interface = unsafe { boot_services.locate_protocol::<TestProtocol>(None) }.unwrap();
+        assert_eq!(interface, &TestProtocol(42));
+        let address = interface as *mut TestProtocol as *mut core::ffi::c_void;
+        assert_eq!(
+            unsafe { boot_services.locate_protocol_unchecked(&TestProtocol::PROTOCOL_GUID, core::ptr::null_mut()) },
+            Ok(address)
+        );
+        assert_eq!(
+            unsafe { boot_services.locate_protocol::<MissingProtocol>(None) }.err(),
+            Some(efi::Status::NOT_FOUND)
+        );
+
+        let (handle, _) = boot_services.install_protocol_interface(None, Box::new(TestProtocol(1))).unwrap();
+        assert!(!handle.is_null());
+    }
+
+    #[test]
+    fn events_should_be_created() {
+        let (boot_services, _) = MockEnv::new()
+            .expect_event::<Box<u32>>(EventType::NOTIFY_SIGNAL, Tpl::CALLBACK)
+            .expect_event_group::<Box<u32>>(EventType::NOTIFY_SIGNAL, Tpl::NOTIFY, &EVENT_GROUP_END_OF_DXE)
+            .into_services();
+
+        let event = boot_services.create_event(EventType::NOTIFY_SIGNAL, Tpl::CALLBACK, Some(notify), Box::new(1));
+        let group_event = boot_services.create_event_ex(
+            EventType::NOTIFY_SIGNAL,
+            Tpl::NOTIFY,
+            Some(notify),
+            Box::new(2),
+            &EVENT_GROUP_END_OF_DXE,
+        );
+        assert_ne!(event.unwrap(), group_event.unwrap());
+    }
+
+    #[test]
+    fn variables_should_be_found_by_name() {
+        let name = ucs2!("TestVariable");
+        let (_, runtime_services) = MockEnv::new()
+            .with_variable(name.as_slice_with_nul(), TestProtocol::PROTOCOL_GUID, 7, Vec::from([1_u8, 2]))
+            .without_variable::<Vec<u8>>(name.as_slice_with_nul(), MissingProtocol::PROTOCOL_GUID)
+            .into_services();
+
+        assert_eq!(
+            runtime_services.get_variable::<Vec<u8>>(name.as_slice_with_nul(), &TestProtocol::PROTOCOL_GUID, None),
+            Ok((Vec::from([1, 2]), 7))
+        );
+        assert_eq!(
+            runtime_services.get_variable::<Vec<u8>>(name.as_slice_with_nul(), &MissingProtocol::PROTOCOL_GUID, None),
+            Err(efi::Status::NOT_FOUND)
+        );
+    }
+}
@@ -8,12 +8,15 @@
 //!
 //! SPDX-License-Identifier: Apache-2.0
 //!
+pub mod delay;
 pub mod error;
 pub mod globals;
+pub mod id_registry;
 pub mod logging;
 pub mod measurement;
 pub mod record;
 pub mod table;
+pub mod timer;
 
 pub mod _smm;
 
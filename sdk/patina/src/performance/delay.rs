@@ -0,0 +1,149 @@
+//! Busy-wait delays calibrated against the performance counter.
+//!
+//! The delays spin on the [PerfTimer] counter until the number of ticks matching the requested duration at the
+//! counter frequency has elapsed, so they hold for at least the requested duration whatever the speed of the
+//! processor. The processor is not yielded while waiting, these are meant for short hardware delays in drivers.
+//!
+//! The elapsed ticks are accumulated between reads of the counter, so a counter rolling over during the delay does not
+//! end it early.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use crate::error::EfiError;
+
+use super::timer::PerfTimer;
+
+const MICROSECONDS_PER_SECOND: u128 = 1_000_000;
+const MILLISECONDS_PER_SECOND: u128 = 1_000;
+
+/// Returns the number of ticks of a counter running at `frequency` Hz in `microseconds`, rounded up.
+pub fn ticks_for_us(microseconds: u64, frequency: u64) -> u128 {
+    ticks_for(microseconds as u128, MICROSECONDS_PER_SECOND, frequency)
+}
+
+fn ticks_for(amount: u128, per_second: u128, frequency: u64) -> u128 {
+    amount.saturating_mul(frequency as u128).div_ceil(per_second)
+}
+
+/// Spins until `ticks` ticks of the counter of `timer` have elapsed.
+pub fn delay_ticks(timer: &impl PerfTimer, ticks: u128) {
+    let mut elapsed: u128 = 0;
+    let mut last = timer.cpu_count();
+    while elapsed < ticks {
+        core::hint::spin_loop();
+        let now = timer.cpu_count();
+        elapsed += now.wrapping_sub(last) as u128;
+        last = now;
+    }
+}
+
+/// Spins for at least `microseconds` microseconds.
+///
+/// # Errors
+///
+/// Returns [EfiError::Unsupported] if the frequency of the counter of `timer` is unknown, the delay cannot be
+/// calibrated.
+pub fn delay_us(timer: &impl PerfTimer, microseconds: u64) -> Result<(), EfiError> {
+    delay(timer, microseconds as u128, MICROSECONDS_PER_SECOND)
+}
+
+/// Spins for at least `milliseconds` milliseconds.
+///
+/// # Errors
+///
+/// Returns [EfiError::Unsupported] if the frequency of the counter of `timer` is unknown, the delay cannot be
+/// calibrated.
+pub fn delay_ms(timer: &impl PerfTimer, milliseconds: u64) -> Result<(), EfiError> {
+    delay(timer, milliseconds as u128, MILLISECONDS_PER_SECOND)
+}
+
+fn delay(timer: &impl PerfTimer, amount: u128, per_second: u128) -> Result<(), EfiError> {
+    match timer.perf_frequency() {
+        0 => Err(EfiError::Unsupported),
+        frequency => {
+            delay_ticks(timer, ticks_for(amount, per_second, frequency));
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::performance::timer::MockPerfTimer;
+    use std::sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    };
+
+    /// Returns a timer whose counter reads `start` first and advances by `step` ticks on every read, with the value of
+    /// the last read.
+    fn stepping_timer(start: u64, step: u64, frequency: u64) -> (MockPerfTimer, Arc<AtomicU64>) {
+        let next = Arc::new(AtomicU64::new(start));
+        let last = Arc::new(AtomicU64::new(start));
+        let (counter, last_read) = (next.clone(), last.clone());
+        let mut timer = MockPerfTimer::new();
+        timer.expect_cpu_count().returning(move || {
+            let count = counter.fetch_add(step, Ordering::SeqCst);
+            last_read.store(count, Ordering::SeqCst);
+            count
+        });
+        timer.expect_perf_frequency().return_const(frequency);
+        (timer, last)
+    }
+
+    #[test]
+    fn ticks_should_be_rounded_up() {
+        assert_eq!(ticks_for_us(0, 1_000_000), 0);
+        assert_eq!(ticks_for_us(100, 1_000_000), 100);
+        assert_eq!(ticks_for_us(1, 1_500_000), 2);
+        assert_eq!(ticks_for_us(10, 3_579_545), 36);
+        assert_eq!(ticks_for_us(u64::MAX, u64::MAX), (u64::MAX as u128 * u64::MAX as u128).div_ceil(1_000_000));
+    }
+
+    #[test]
+    fn delay_us_should_wait_within_one_counter_read() {
+        for step in [1, 7, 250] {
+            let (timer, count) = stepping_timer(1_000, step, 24_000_000);
+            delay_us(&timer, 100).unwrap();
+
+            // 100us at 24MHz is 2400 ticks, the delay ends on the first read past them.
+            let elapsed = count.load(Ordering::SeqCst) - 1_000;
+            assert!(elapsed >= 2_400, "step {step}: waited {elapsed} ticks");
+            assert!(elapsed < 2_400 + step, "step {step}: waited {elapsed} ticks");
+        }
+    }
+
+    #[test]
+    fn delay_ms_should_wait_within_one_counter_read() {
+        let (timer, count) = stepping_timer(0, 1_000, 1_000_000_000);
+        delay_ms(&timer, 5).unwrap();
+        let elapsed = count.load(Ordering::SeqCst);
+        assert!((5_000_000..5_001_000).contains(&elapsed), "waited {elapsed} ticks");
+    }
+
+    #[test]
+    fn delay_should_span_a_counter_roll_over() {
+        let (timer, count) = stepping_timer(u64::MAX - 10, 3, 1_000_000);
+        // The counter wraps on the fifth read.
+        delay_us(&timer, 20).unwrap();
+        let elapsed = count.load(Ordering::SeqCst).wrapping_sub(u64::MAX - 10);
+        assert!((20..23).contains(&elapsed), "waited {elapsed} ticks");
+    }
+
+    #[test]
+    fn delay_should_fail_without_a_frequency() {
+        let mut timer = MockPerfTimer::new();
+        timer.expect_cpu_count().never();
+        timer.expect_perf_frequency().return_const(0_u64);
+        assert_eq!(delay_us(&timer, 10), Err(EfiError::Unsupported));
+        assert_eq!(delay_ms(&timer, 10), Err(EfiError::Unsupported));
+    }
+}
@@ -29,6 +29,13 @@ pub enum Error {
     OutOfResources,
     /// Buffer too small to allocate fbpt.
     BufferTooSmall,
+    /// The performance ID range overlaps the range `start..=end` already reserved by another namespace.
+    PerfIdRangeCollision {
+        /// First performance ID of the reserved range.
+        start: u16,
+        /// Last performance ID of the reserved range.
+        end: u16,
+    },
     /// UEFI specification defined error type.
     Efi(EfiError),
     /// Error returned when `debug_assert` is disabled.
@@ -53,6 +60,9 @@ impl Display for Error {
         match self {
             Error::OutOfResources => write!(f, "FBPT buffer full, can't add more performance records."),
             Error::BufferTooSmall => write!(f, "Buffer to small to allocate FBPT table"),
+            Error::PerfIdRangeCollision { start, end } => {
+                write!(f, "Performance IDs {start:#x}..={end:#x} are already reserved by another namespace")
+            }
             Error::Efi(efi_error) => write!(f, "{efi_error:?}"),
             Error::DebugAssert { msg, file, line } => write!(f, "Assertion at {file}:{line}: {msg}"),
         }
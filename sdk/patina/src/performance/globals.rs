@@ -8,7 +8,7 @@
 //!
 use crate::{
     boot_services::{StandardBootServices, tpl::Tpl},
-    performance::table::FBPT,
+    performance::{id_registry::PerfIdRegistry, table::FBPT},
     tpl_mutex::TplMutex,
 };
 use core::{
@@ -25,6 +25,8 @@ struct StaticState<'a> {
     boot_services: OnceCell<StandardBootServices>,
     /// The FBPT protected by a TPL mutex.
     fbpt: OnceCell<TplMutex<'a, FBPT>>,
+    /// The registry of the reserved performance ID ranges protected by a TPL mutex.
+    perf_id_registry: OnceCell<TplMutex<'a, PerfIdRegistry>>,
     /// Flag to indicate if the static state is in the process of being initialized.
     initializing: AtomicBool,
 }
@@ -32,7 +34,12 @@ struct StaticState<'a> {
 impl<'a> StaticState<'a> {
     /// Creates a new uninitialized static state.
     const fn uninit() -> Self {
-        Self { boot_services: OnceCell::new(), fbpt: OnceCell::new(), initializing: AtomicBool::new(false) }
+        Self {
+            boot_services: OnceCell::new(),
+            fbpt: OnceCell::new(),
+            perf_id_registry: OnceCell::new(),
+            initializing: AtomicBool::new(false),
+        }
     }
 
     /// Initializes the static state.
@@ -47,6 +54,13 @@ impl<'a> StaticState<'a> {
             self.fbpt
                 .set(TplMutex::new(self.boot_services.get().expect("Boot Services Just Set"), Tpl::NOTIFY, FBPT::new()))
                 .map_err(|_| "Failed to set FBPT")?;
+            self.perf_id_registry
+                .set(TplMutex::new(
+                    self.boot_services.get().expect("Boot Services Just Set"),
+                    Tpl::NOTIFY,
+                    PerfIdRegistry::new(),
+                ))
+                .map_err(|_| "Failed to set performance ID registry")?;
             self.initializing.store(false, Ordering::Release);
             return Ok(());
         }
@@ -68,6 +82,11 @@ impl<'a> StaticState<'a> {
         }
         None
     }
+
+    /// Gets the performance ID registry if the state has been initialized.
+    fn perf_id_registry(&self) -> Option<&TplMutex<'a, PerfIdRegistry>> {
+        self.inner().and(self.perf_id_registry.get())
+    }
 }
 
 /// SAFETY: Initializing the `OnceCell`s via the atomic `initialize` flag satisfies the `Send` requirement for
@@ -119,6 +138,12 @@ pub fn get_static_state() -> Option<(&'static StandardBootServices, &'static Tpl
     STATIC_STATE.inner()
 }
 
+/// Get the registry of the reserved performance ID ranges, if the performance component static state is set.
+#[coverage(off)]
+pub fn get_perf_id_registry() -> Option<&'static TplMutex<'static, PerfIdRegistry>> {
+    STATIC_STATE.perf_id_registry()
+}
+
 #[cfg(test)]
 #[coverage(off)]
 mod tests {
@@ -128,8 +153,10 @@ mod tests {
     fn test_get_static_state() {
         static STATIC_STATE: StaticState = StaticState::uninit();
         assert!(STATIC_STATE.inner().is_none());
+        assert!(STATIC_STATE.perf_id_registry().is_none());
         assert!(STATIC_STATE.init(StandardBootServices::new_uninit()).is_ok());
         assert!(STATIC_STATE.inner().is_some());
+        assert!(STATIC_STATE.perf_id_registry().is_some());
         assert!(STATIC_STATE.init(StandardBootServices::new_uninit()).is_err());
     }
 }
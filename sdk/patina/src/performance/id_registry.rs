@@ -0,0 +1,212 @@
+//! Registry of the performance ID ranges reserved by components.
+//!
+//! Performance IDs that are not one of the [KnownPerfId](crate::performance::record::known::KnownPerfId)s are free
+//! for vendors to use, and two vendors picking the same IDs produce records that can't be told apart. On top of that,
+//! the IDs given to
+//! [create_performance_measurement](crate::performance::measurement::create_performance_measurement) are adjusted to
+//! follow the start/end nibble rule, which can move an ID into the IDs of another vendor.
+//!
+//! Components reserve the IDs they use through the [PerfIdRegistration] service, under a namespace GUID identifying
+//! them. Each range is published in the FBPT as a
+//! [PerfIdRangeRecord](crate::performance::record::extended::PerfIdRangeRecord), and using an ID that is not
+//! reserved, or that is adjusted into a range reserved by another namespace, is reported with a warning.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+extern crate alloc;
+
+use alloc::{collections::BTreeSet, vec::Vec};
+
+use r_efi::efi;
+
+use crate::{error::EfiError, performance::error::Error};
+
+/// Last performance ID reserved for the [KnownPerfId](crate::performance::record::known::KnownPerfId)s and their
+/// future additions. Ranges can't include these IDs.
+pub const RESERVED_PERF_ID_END: u16 = 0xFF;
+
+/// Service reserving ranges of performance IDs.
+///
+/// Produced by the performance component when it is enabled.
+pub trait PerfIdRegistration {
+    /// Reserves the performance IDs `start..=end` for `namespace`.
+    ///
+    /// Reserving a range again for the same namespace has no effect.
+    ///
+    /// ## Errors
+    ///
+    /// Returns [EfiError::InvalidParameter] if `start` is greater than `end` or if the range includes IDs up to
+    /// [RESERVED_PERF_ID_END]. Returns [Error::PerfIdRangeCollision] if the range overlaps a range reserved by another
+    /// namespace.
+    fn register_perf_id_range(&self, namespace: &efi::Guid, start: u16, end: u16) -> Result<(), Error>;
+}
+
+/// A range of performance IDs reserved by a namespace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PerfIdRange {
+    /// GUID identifying the vendor or component that reserved the range.
+    pub namespace: efi::Guid,
+    /// First performance ID of the range.
+    pub start: u16,
+    /// Last performance ID of the range, inclusive.
+    pub end: u16,
+}
+
+impl PerfIdRange {
+    /// Returns whether `perf_id` is in the range.
+    pub fn contains(&self, perf_id: u16) -> bool {
+        (self.start..=self.end).contains(&perf_id)
+    }
+}
+
+/// The result of checking the use of a performance ID with [PerfIdRegistry::check].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PerfIdUse {
+    /// The ID is reserved for the known performance IDs.
+    Known,
+    /// The ID is in a registered range.
+    Registered,
+    /// The ID is not in any registered range.
+    Unregistered,
+    /// The ID was adjusted into a range that is not the range of the ID given by the caller.
+    Collision,
+}
+
+/// The performance ID ranges reserved by components.
+#[derive(Debug, Default)]
+pub struct PerfIdRegistry {
+    ranges: Vec<PerfIdRange>,
+    /// The unregistered IDs already reported, so that each one is only reported once.
+    reported: BTreeSet<u16>,
+}
+
+impl PerfIdRegistry {
+    /// Creates an empty registry.
+    pub const fn new() -> Self {
+        Self { ranges: Vec::new(), reported: BTreeSet::new() }
+    }
+
+    /// Reserves the performance IDs `start..=end` for `namespace`, see
+    /// [PerfIdRegistration::register_perf_id_range].
+    ///
+    /// Returns whether the range is newly reserved, in which case its
+    /// [PerfIdRangeRecord](crate::performance::record::extended::PerfIdRangeRecord) must be added to the FBPT.
+    pub fn register(&mut self, namespace: &efi::Guid, start: u16, end: u16) -> Result<bool, Error> {
+        if start > end || start <= RESERVED_PERF_ID_END {
+            log::error!("Performance: Invalid performance ID range {start:#x}..={end:#x}.");
+            return Err(EfiError::InvalidParameter.into());
+        }
+
+        let range = PerfIdRange { namespace: *namespace, start, end };
+        if self.ranges.contains(&range) {
+            return Ok(false);
+        }
+        if let Some(reserved) = self.ranges.iter().find(|reserved| reserved.start <= end && start <= reserved.end) {
+            log::warn!(
+                "Performance: Performance IDs {start:#x}..={end:#x} collide with the IDs {:#x}..={:#x} of {:?}.",
+                reserved.start,
+                reserved.end,
+                reserved.namespace
+            );
+            return Err(Error::PerfIdRangeCollision { start: reserved.start, end: reserved.end });
+        }
+
+        log::info!("Performance: Performance IDs {start:#x}..={end:#x} reserved for {namespace:?}.");
+        self.ranges.push(range);
+        Ok(true)
+    }
+
+    /// Returns the range `perf_id` belongs to, if any.
+    pub fn find(&self, perf_id: u16) -> Option<&PerfIdRange> {
+        self.ranges.iter().find(|range| range.contains(perf_id))
+    }
+
+    /// Checks the use of `perf_id`, which `requested_id` given by the caller was adjusted to, and reports a warning if
+    /// it is unregistered or collides with another range.
+    pub fn check(&mut self, requested_id: u16, perf_id: u16) -> PerfIdUse {
+        if perf_id <= RESERVED_PERF_ID_END {
+            return PerfIdUse::Known;
+        }
+
+        let Some(range) = self.find(perf_id) else {
+            if self.reported.insert(perf_id) {
+                log::warn!("Performance: Performance ID {perf_id:#x} is not in a registered range.");
+            }
+            return PerfIdUse::Unregistered;
+        };
+
+        let namespace = range.namespace;
+        if requested_id != perf_id && self.find(requested_id).map(|requested| requested.namespace) != Some(namespace) {
+            log::warn!(
+                "Performance: Performance ID {requested_id:#x} was adjusted to {perf_id:#x}, reserved by {namespace:?}."
+            );
+            return PerfIdUse::Collision;
+        }
+
+        PerfIdUse::Registered
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+
+    use crate::performance::record::{PerformanceRecordBuffer, extended::PerfIdRangeRecord, known::KnownPerfId};
+
+    const VENDOR_A: efi::Guid = efi::Guid::from_fields(0x1, 0x2, 0x3, 0x4, 0x5, &[0x6, 0x7, 0x8, 0x9, 0xA, 0xB]);
+    const VENDOR_B: efi::Guid = efi::Guid::from_fields(0xB, 0xA, 0x9, 0x8, 0x7, &[0x6, 0x5, 0x4, 0x3, 0x2, 0x1]);
+
+    #[test]
+    fn test_register_rejects_invalid_and_colliding_ranges() {
+        let mut registry = PerfIdRegistry::new();
+        assert!(matches!(registry.register(&VENDOR_A, 0x1000, 0x10FF), Ok(true)));
+        // Registering the same range again has no effect.
+        assert!(matches!(registry.register(&VENDOR_A, 0x1000, 0x10FF), Ok(false)));
+
+        assert!(matches!(registry.register(&VENDOR_B, 0x2000, 0x1FFF), Err(Error::Efi(EfiError::InvalidParameter))));
+        assert!(matches!(registry.register(&VENDOR_B, 0x80, 0x200), Err(Error::Efi(EfiError::InvalidParameter))));
+        assert!(matches!(
+            registry.register(&VENDOR_B, 0x10F0, 0x11FF),
+            Err(Error::PerfIdRangeCollision { start: 0x1000, end: 0x10FF })
+        ));
+        assert!(matches!(registry.register(&VENDOR_B, 0x1100, 0x11FF), Ok(true)));
+
+        assert_eq!(registry.find(0x10FF).map(|range| range.namespace), Some(VENDOR_A));
+        assert_eq!(registry.find(0x1100).map(|range| range.namespace), Some(VENDOR_B));
+        assert_eq!(registry.find(0x1200), None);
+    }
+
+    #[test]
+    fn test_check_perf_id_use() {
+        let mut registry = PerfIdRegistry::new();
+        registry.register(&VENDOR_A, 0x1000, 0x1007).unwrap();
+        registry.register(&VENDOR_B, 0x1008, 0x101F).unwrap();
+
+        assert_eq!(registry.check(0x30, KnownPerfId::PerfFunctionStart.as_u16()), PerfIdUse::Known);
+        assert_eq!(registry.check(0x1001, 0x1001), PerfIdUse::Registered);
+        assert_eq!(registry.check(0x1015, 0x1010), PerfIdUse::Registered);
+        assert_eq!(registry.check(0x2000, 0x2000), PerfIdUse::Unregistered);
+        // Reported once, but still unregistered.
+        assert_eq!(registry.check(0x2000, 0x2000), PerfIdUse::Unregistered);
+        // A start ID of vendor B adjusted into the range of vendor A.
+        assert_eq!(registry.check(0x1008, 0x1000), PerfIdUse::Collision);
+        // An unregistered ID adjusted into the range of vendor A.
+        assert_eq!(registry.check(0x0FFF, 0x1000), PerfIdUse::Collision);
+    }
+
+    #[test]
+    fn test_perf_id_range_record() {
+        let mut buffer = PerformanceRecordBuffer::new();
+        buffer.push_record(PerfIdRangeRecord::new(VENDOR_B, 0x1010, 0x101F)).unwrap();
+
+        let record = buffer.iter().next().unwrap();
+        assert_eq!((record.record_type, record.length, record.revision), (PerfIdRangeRecord::TYPE, 24, 1));
+        assert_eq!(&record.data[..16], VENDOR_B.as_bytes().as_slice());
+        assert_eq!(&record.data[16..], [0x10, 0x10, 0x1F, 0x10]);
+    }
+}
@@ -1,5 +1,8 @@
 //! Functionality for logging performance measurements.
 //!
+//! The records are filtered by the measurement mask in
+//! [create_performance_measurement](crate::performance::measurement::create_performance_measurement).
+//!
 //! ## License
 //!
 //! Copyright (c) Microsoft Corporation.
@@ -15,7 +18,7 @@ use core::{
 use alloc::ffi::CString;
 use r_efi::efi;
 
-use crate::performance::{Measurement, globals::get_perf_measurement_mask, record::known::KnownPerfId};
+use crate::performance::record::known::KnownPerfId;
 use crate::uefi_protocol::performance_measurement::{CreateMeasurement, PerfAttribute};
 
 /// Create performance record
@@ -95,9 +98,6 @@ fn end_perf_measurement(
 
 /// Begins performance measurement of start image in core.
 pub fn perf_image_start_begin(module_handle: efi::Handle, create_performance_measurement: CreateMeasurement) {
-    if get_perf_measurement_mask() & Measurement::StartImage as u32 == 0 {
-        return;
-    }
     log_perf_measurement(
         module_handle,
         None,
@@ -110,17 +110,11 @@ pub fn perf_image_start_begin(module_handle: efi::Handle, create_performance_mea
 
 /// Ends performance measurement of start image in core.
 pub fn perf_image_start_end(image_handle: efi::Handle, create_performance_measurement: CreateMeasurement) {
-    if get_perf_measurement_mask() & Measurement::StartImage as u32 == 0 {
-        return;
-    }
     log_perf_measurement(image_handle, None, None, 0, KnownPerfId::ModuleEnd.as_u16(), create_performance_measurement)
 }
 
 /// Begins performance measurement of load image in core.
 pub fn perf_load_image_begin(module_handle: efi::Handle, create_performance_measurement: CreateMeasurement) {
-    if get_perf_measurement_mask() & Measurement::LoadImage as u32 == 0 {
-        return;
-    }
     log_perf_measurement(
         module_handle,
         None,
@@ -133,9 +127,6 @@ pub fn perf_load_image_begin(module_handle: efi::Handle, create_performance_meas
 
 /// Ends performance measurement of load image in core.
 pub fn perf_load_image_end(module_handle: efi::Handle, create_performance_measurement: CreateMeasurement) {
-    if get_perf_measurement_mask() & Measurement::LoadImage as u32 == 0 {
-        return;
-    }
     log_perf_measurement(
         module_handle,
         None,
@@ -152,9 +143,6 @@ pub fn perf_driver_binding_support_begin(
     controller_handle: efi::Handle,
     create_performance_measurement: CreateMeasurement,
 ) {
-    if get_perf_measurement_mask() & Measurement::DriverBindingSupport as u32 == 0 {
-        return;
-    }
     log_perf_measurement(
         driver_binding_handle,
         None,
@@ -171,9 +159,6 @@ pub fn perf_driver_binding_support_end(
     controller_handle: efi::Handle,
     create_performance_measurement: CreateMeasurement,
 ) {
-    if get_perf_measurement_mask() & Measurement::DriverBindingSupport as u32 == 0 {
-        return;
-    }
     log_perf_measurement(
         driver_binding_handle,
         None,
@@ -190,9 +175,6 @@ pub fn perf_driver_binding_start_begin(
     controller_handle: efi::Handle,
     create_performance_measurement: CreateMeasurement,
 ) {
-    if get_perf_measurement_mask() & Measurement::DriverBindingStart as u32 == 0 {
-        return;
-    }
     log_perf_measurement(
         driver_binding_handle,
         None,
@@ -209,9 +191,6 @@ pub fn perf_driver_binding_start_end(
     controller_handle: efi::Handle,
     create_performance_measurement: CreateMeasurement,
 ) {
-    if get_perf_measurement_mask() & Measurement::DriverBindingStart as u32 == 0 {
-        return;
-    }
     log_perf_measurement(
         driver_binding_handle,
         None,
@@ -228,9 +207,6 @@ pub fn perf_driver_binding_stop_begin(
     controller_handle: efi::Handle,
     create_performance_measurement: CreateMeasurement,
 ) {
-    if get_perf_measurement_mask() & Measurement::DriverBindingStop as u32 == 0 {
-        return;
-    }
     log_perf_measurement(
         module_handle,
         None,
@@ -247,9 +223,6 @@ pub fn perf_driver_binding_stop_end(
     controller_handle: efi::Handle,
     create_performance_measurement: CreateMeasurement,
 ) {
-    if get_perf_measurement_mask() & Measurement::DriverBindingStop as u32 == 0 {
-        return;
-    }
     log_perf_measurement(
         module_handle,
         None,
@@ -8,11 +8,12 @@
 //!
 extern crate alloc;
 
-use alloc::{boxed::Box, string::ToString, vec::Vec};
+use alloc::{string::ToString, vec::Vec};
 use core::{
     clone::Clone,
     convert::AsRef,
     ffi::{CStr, c_char, c_void},
+    mem::MaybeUninit,
     ops::BitOr,
     ptr,
     sync::atomic::{AtomicBool, Ordering},
@@ -21,12 +22,15 @@ use core::{
 use crate::{
     boot_services::BootServices,
     error::EfiError,
-    guids::EDKII_FPDT_EXTENDED_FIRMWARE_PERFORMANCE,
+    guids::{self, EDKII_FPDT_EXTENDED_FIRMWARE_PERFORMANCE},
     performance::{
         self,
         _smm::{CommunicateProtocol, MmCommRegion, SmmGetRecordDataByOffset, SmmGetRecordSize},
         error::Error,
-        globals::{get_load_image_count, get_static_state, increment_load_image_count},
+        globals::{
+            get_load_image_count, get_perf_id_registry, get_perf_measurement_mask, get_static_state,
+            increment_load_image_count, set_perf_measurement_mask,
+        },
         record::{
             extended::{
                 DualGuidStringEventRecord, DynamicStringEventRecord, GuidEventRecord, GuidQwordEventRecord,
@@ -35,47 +39,91 @@ use crate::{
             known::{KnownPerfId, KnownPerfToken},
         },
         table::FirmwareBasicBootPerfTable,
+        timer::{ArchPerfTimer, PerfTimer, ticker_to_timestamp},
     },
     runtime_services::RuntimeServices,
     tpl_mutex::TplMutex,
-    uefi_protocol::{performance_measurement::PerfAttribute, status_code::StatusCodeRuntimeProtocol},
+    uefi_protocol::{
+        driver_binding::DriverBindingProtocol,
+        loaded_image::LoadedImage,
+        loaded_image_info::{self, LoadedImageInfo},
+        performance_measurement::PerfAttribute,
+        status_code::StatusCodeRuntimeProtocol,
+    },
 };
 
-use mu_rust_helpers::perf_timer::{Arch, ArchFunctionality};
 use patina_pi::status_code::{EFI_PROGRESS_CODE, EFI_SOFTWARE_DXE_BS_DRIVER};
 
-use r_efi::{
-    efi::{self, Guid},
-    protocols::device_path::{Media, TYPE_MEDIA},
-};
+use r_efi::efi;
 
 /// Functions intended to be registered as event callbacks for reporting performance measurements.
 pub mod event_callback {
 
     use super::*;
 
+    /// Context of the [`report_fbpt_record_buffer`] event callback.
+    pub struct ReportFbptContext<BB, RR, F, B>
+    where
+        F: 'static,
+        B: BootServices + 'static,
+    {
+        /// Boot services used to report the table.
+        pub boot_services: BB,
+        /// Runtime services used to find the table reported in a previous boot.
+        pub runtime_services: RR,
+        /// The table to report.
+        pub fbpt: &'static TplMutex<'static, F, B>,
+        /// Whether the table is allocated at the address of the previous boot, saved in the
+        /// FirmwarePerformanceVariable.
+        pub reuse_previous_address: bool,
+    }
+
+    /// Context of the [`fetch_and_add_mm_performance_records`] event callback.
+    pub struct MmPerformanceRecordsContext<BB, F, B>
+    where
+        F: 'static,
+        B: BootServices + 'static,
+    {
+        /// Boot services used to locate the MM communicate protocol.
+        pub boot_services: BB,
+        /// The MM communication region used to fetch the records.
+        pub mm_comm_region: MmCommRegion,
+        /// The table the records are added to.
+        pub fbpt: &'static TplMutex<'static, F, B>,
+    }
+
     /// Reports the Firmware Basic Boot Performance Table (FBPT) record buffer.
-    pub extern "efiapi" fn report_fbpt_record_buffer<BB, B, RR, R, F>(
-        event: efi::Event,
-        ctx: Box<(BB, RR, &TplMutex<'static, F, B>)>,
-    ) where
+    ///
+    /// Intended to be registered as a one shot event with [`EventBuilder`](crate::boot_services::event::EventBuilder).
+    pub fn report_fbpt_record_buffer<BB, B, RR, R, F>(_event: efi::Event, context: &mut ReportFbptContext<BB, RR, F, B>)
+    where
         BB: AsRef<B> + Clone,
         B: BootServices + 'static,
         RR: AsRef<R> + Clone + 'static,
         R: RuntimeServices + 'static,
         F: FirmwareBasicBootPerfTable,
     {
-        let (boot_services, runtime_services, fbpt) = *ctx;
-        let _ = boot_services.as_ref().close_event(event);
+        let ReportFbptContext { boot_services, runtime_services, fbpt, reuse_previous_address } = context;
 
-        let Ok(fbpt_address) = fbpt.lock().report_table(
-            performance::table::find_previous_table_address(runtime_services.as_ref()),
-            boot_services.as_ref(),
-        ) else {
+        let previous_address = if *reuse_previous_address {
+            performance::table::find_previous_table_address(runtime_services.as_ref())
+        } else {
+            None
+        };
+
+        let Ok(fbpt_address) = fbpt.lock().report_table(previous_address, boot_services.as_ref()) else {
             log::error!("Performance: Fail to report FBPT.");
             return;
         };
 
+        // The previous address was invalid or could not be allocated, the next boot should use the new one.
+        if *reuse_previous_address
+            && previous_address != Some(fbpt_address)
+            && let Err(status) = performance::table::set_table_address(runtime_services.as_ref(), fbpt_address)
+        {
+            log::warn!("Performance: Fail to save the FBPT address: {status:?}.");
+        }
+
         let Ok(p) = (unsafe { boot_services.as_ref().locate_protocol::<StatusCodeRuntimeProtocol>(None) }) else {
             log::error!("Performance: Fail to find status code protocol.");
             return;
@@ -107,20 +155,23 @@ pub mod event_callback {
     }
 
     /// Adds SMM performance records to the Firmware Basic Boot Performance Table (FBPT).
-    pub extern "efiapi" fn fetch_and_add_mm_performance_records<BB, B, F>(
-        event: efi::Event,
-        ctx: Box<(BB, MmCommRegion, &TplMutex<'static, F, B>)>,
+    ///
+    /// Intended to be registered as a one shot event with [`EventBuilder`](crate::boot_services::event::EventBuilder).
+    pub fn fetch_and_add_mm_performance_records<BB, B, F>(
+        _event: efi::Event,
+        context: &mut MmPerformanceRecordsContext<BB, F, B>,
     ) where
         BB: AsRef<B> + Clone,
         B: BootServices + 'static,
         F: FirmwareBasicBootPerfTable,
     {
-        let (boot_services, mm_comm_region, fbpt) = *ctx;
-        let _ = boot_services.as_ref().close_event(event);
+        let MmPerformanceRecordsContext { boot_services, mm_comm_region, fbpt } = context;
+        let mm_comm_region = *mm_comm_region;
 
         // SAFETY: This is safe because the reference returned by locate_protocol is never mutated after installation.
         let Ok(communication) = (unsafe { boot_services.as_ref().locate_protocol::<CommunicateProtocol>(None) }) else {
-            log::error!("Performance: Could not locate communicate protocol interface.");
+            // MM records are optional, the platform may provide a communication region without an MM environment.
+            log::info!("Performance: Communicate protocol not installed, MM performance records are not fetched.");
             return;
         };
 
@@ -189,12 +240,19 @@ pub mod event_callback {
     }
 }
 
+unsafe extern "C" {
+    /// Returns the return address of the current function (`level` 0) or of its callers.
+    #[link_name = "llvm.returnaddress"]
+    fn llvm_return_address(level: i32) -> *const c_void;
+}
+
 #[coverage(off)]
 // Tested via the generic version, see _create_performance_measurement. This one is using the static state which makes
 // it not mockable.
 ///
 /// # Safety
 /// String must be a valid C string pointer.
+#[inline(never)]
 pub unsafe extern "efiapi" fn create_performance_measurement(
     caller_identifier: *const c_void,
     guid: Option<&efi::Guid>,
@@ -209,6 +267,10 @@ pub unsafe extern "efiapi" fn create_performance_measurement(
         return efi::Status::SUCCESS;
     };
 
+    // The call site, used to attribute the record to the calling image if the caller identifier is unknown.
+    // SAFETY: Level 0 is the return address of this function, which is always available.
+    let caller_address = unsafe { llvm_return_address(0) } as usize;
+
     let string = unsafe { string.as_ref().map(|s| CStr::from_ptr(s).to_string_lossy().to_string()) };
 
     // NOTE: If the Perf is not the known Token used in the core but have same ID with the core Token, this case will
@@ -229,6 +291,10 @@ pub unsafe extern "efiapi" fn create_performance_measurement(
             } else if attribute == PerfAttribute::PerfEndEntry && ((perf_id & 0x000F) == 0) {
                 perf_id += 1;
             }
+            // Warn about IDs used without being reserved, or adjusted into the IDs of another namespace.
+            if let Some(perf_id_registry) = get_perf_id_registry() {
+                perf_id_registry.lock().check(identifier as u16, perf_id);
+            }
         } else if perf_id == 0 {
             match KnownPerfId::try_from_perf_info(caller_identifier as efi::Handle, string.as_ref(), attribute) {
                 Ok(known_perf_id) => perf_id = known_perf_id.as_u16(),
@@ -237,8 +303,13 @@ pub unsafe extern "efiapi" fn create_performance_measurement(
         }
     }
 
+    if !Measurement::is_perf_id_enabled(get_perf_measurement_mask(), perf_id) {
+        return efi::Status::SUCCESS;
+    }
+
     match _create_performance_measurement(
         caller_identifier,
+        caller_address,
         guid,
         string.as_deref(),
         ticker,
@@ -247,6 +318,7 @@ pub unsafe extern "efiapi" fn create_performance_measurement(
         attribute,
         boot_services,
         fbpt,
+        &ArchPerfTimer,
     ) {
         Ok(_) => efi::Status::SUCCESS,
         Err(Error::OutOfResources) => {
@@ -269,10 +341,35 @@ pub unsafe extern "efiapi" fn create_performance_measurement(
     }
 }
 
+/// Returns the mask of the enabled [Measurement]s.
+///
+/// Implementation of the [PerformanceMeasurementMask](crate::uefi_protocol::performance_measurement::PerformanceMeasurementMask)
+/// protocol.
+pub extern "efiapi" fn get_measurement_mask() -> u32 {
+    get_perf_measurement_mask()
+}
+
+/// Sets the mask of the enabled [Measurement]s, returning `EFI_INVALID_PARAMETER` if `mask` has unknown bits.
+///
+/// Implementation of the [PerformanceMeasurementMask](crate::uefi_protocol::performance_measurement::PerformanceMeasurementMask)
+/// protocol.
+pub extern "efiapi" fn set_measurement_mask(mask: u32) -> efi::Status {
+    if mask & !Measurement::ALL != 0 {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    log::info!("Performance: measurement mask changed to {mask:#x}.");
+    set_perf_measurement_mask(mask);
+    efi::Status::SUCCESS
+}
+
 /// Create a performance measurement and add it to the FBPT.
+///
+/// `caller_address` is the address the measurement was created from, used to attribute it to the calling image when
+/// the caller identifier does not identify a module.
 #[allow(clippy::too_many_arguments)]
-fn _create_performance_measurement<B, F>(
+fn _create_performance_measurement<B, F, T>(
     caller_identifier: *const c_void,
+    caller_address: usize,
     guid: Option<&efi::Guid>,
     string: Option<&str>,
     ticker: u64,
@@ -281,24 +378,22 @@ fn _create_performance_measurement<B, F>(
     attribute: PerfAttribute,
     boot_services: &B,
     fbpt: &TplMutex<'static, F, B>,
+    timer: &T,
 ) -> Result<(), Error>
 where
     B: BootServices,
     F: FirmwareBasicBootPerfTable,
+    T: PerfTimer,
 {
-    let cpu_count = Arch::cpu_count();
-    let timestamp = match ticker {
-        0 => (cpu_count as f64 / Arch::perf_frequency() as f64 * 1_000_000_000_f64) as u64,
-        1 => 0,
-        ticker => (ticker as f64 / Arch::perf_frequency() as f64 * 1_000_000_000_f64) as u64,
-    };
+    let timestamp = ticker_to_timestamp(ticker, timer);
 
     let Ok(known_perf_id) = KnownPerfId::try_from(perf_id) else {
         if attribute == PerfAttribute::PerfEntry {
             return Err(EfiError::InvalidParameter.into());
         }
         let guid = get_module_guid_from_handle(boot_services, caller_identifier as efi::Handle)
-            .unwrap_or_else(|_| unsafe { *(caller_identifier as *const Guid) });
+            .unwrap_or_else(|_| unsafe { get_module_guid_from_caller_id(caller_identifier) });
+        let guid = attribute_to_caller(boot_services, guid, caller_address);
         let module_name = string.unwrap_or("unknown name");
         fbpt.lock().add_record(DynamicStringEventRecord::new(perf_id, 0, timestamp, guid, module_name))?;
         return Ok(());
@@ -311,6 +406,7 @@ where
                 log::error!("Performance: Could not find the guid for module handle: {module_handle:?}");
                 return Err(EfiError::InvalidParameter.into());
             };
+            let guid = attribute_to_caller(boot_services, guid, caller_address);
             let record = GuidEventRecord::new(perf_id, 0, timestamp, guid);
             fbpt.lock().add_record(record)?;
         }
@@ -323,6 +419,7 @@ where
                 log::error!("Performance: Could not find the guid for module handle: {module_handle:?}");
                 return Err(EfiError::InvalidParameter.into());
             };
+            let guid = attribute_to_caller(boot_services, guid, caller_address);
             let record = GuidQwordEventRecord::new(perf_id, 0, timestamp, guid, get_load_image_count() as u64);
             fbpt.lock().add_record(record)?;
         }
@@ -336,6 +433,7 @@ where
                 log::error!("Performance: Could not find the guid for module handle: {module_handle:?}");
                 return Err(EfiError::InvalidParameter.into());
             };
+            let guid = attribute_to_caller(boot_services, guid, caller_address);
             let record = GuidQwordEventRecord::new(perf_id, 0, timestamp, guid, address as u64);
             fbpt.lock().add_record(record)?;
         }
@@ -345,6 +443,7 @@ where
                 log::error!("Performance Lib: Could not find the guid for module handle: {module_handle:?}");
                 return Err(EfiError::InvalidParameter.into());
             };
+            let guid = attribute_to_caller(boot_services, guid, caller_address);
             let module_name = "";
             let record = GuidQwordStringEventRecord::new(perf_id, 0, timestamp, guid, address as u64, module_name);
             fbpt.lock().add_record(record)?;
@@ -358,7 +457,8 @@ where
             };
             // SAFETY: On these usecases, caller identifier is actually a guid. See macro for more detailed.
             // This strange behavior need to be kept for backward compatibility.
-            let module_guid = unsafe { *(caller_identifier as *const efi::Guid) };
+            let module_guid = unsafe { get_module_guid_from_caller_id(caller_identifier) };
+            let module_guid = attribute_to_caller(boot_services, module_guid, caller_address);
             let record = DualGuidStringEventRecord::new(perf_id, 0, timestamp, module_guid, *guid, function_string);
             fbpt.lock().add_record(record)?;
         }
@@ -372,7 +472,8 @@ where
         | KnownPerfId::PerfEvent => {
             // SAFETY: On these usecases, caller identifier is actually a guid. See macro for more detailed.
             // This strange behavior need to be kept for backward compatibility.
-            let module_guid = unsafe { *(caller_identifier as *const efi::Guid) };
+            let module_guid = unsafe { get_module_guid_from_caller_id(caller_identifier) };
+            let module_guid = attribute_to_caller(boot_services, module_guid, caller_address);
             let string = string.unwrap_or("unknown name");
             let record = DynamicStringEventRecord::new(perf_id, 0, timestamp, module_guid, string);
             fbpt.lock().add_record(record)?;
@@ -395,6 +496,10 @@ pub enum Measurement {
     DriverBindingStart = 1 << 3,
     /// Diver binding stop function call.
     DriverBindingStop = 1 << 4,
+    /// Function begin and end spans.
+    FunctionSpan = 1 << 5,
+    /// Event signals, event notification callbacks and general events.
+    EventSignal = 1 << 6,
 }
 
 impl Measurement {
@@ -406,8 +511,43 @@ impl Measurement {
             Measurement::DriverBindingSupport => Measurement::DriverBindingSupport as u32,
             Measurement::DriverBindingStart => Measurement::DriverBindingStart as u32,
             Measurement::DriverBindingStop => Measurement::DriverBindingStop as u32,
+            Measurement::FunctionSpan => Measurement::FunctionSpan as u32,
+            Measurement::EventSignal => Measurement::EventSignal as u32,
         }
     }
+
+    /// Mask of all the measurements.
+    pub const ALL: u32 = (1 << 7) - 1;
+
+    /// Measurements enabled when the platform does not configure them. Function spans and events were recorded
+    /// unconditionally before they could be masked, so they stay enabled unless a mask explicitly disables them.
+    pub const DEFAULT: u32 = Measurement::FunctionSpan as u32 | Measurement::EventSignal as u32;
+
+    /// Returns the measurement the records with `perf_id` belong to, or `None` if they are not filtered by the
+    /// measurement mask.
+    pub fn from_perf_id(perf_id: u16) -> Option<Self> {
+        match KnownPerfId::try_from(perf_id).ok()? {
+            KnownPerfId::ModuleStart | KnownPerfId::ModuleEnd => Some(Measurement::StartImage),
+            KnownPerfId::ModuleLoadImageStart | KnownPerfId::ModuleLoadImageEnd => Some(Measurement::LoadImage),
+            KnownPerfId::ModuleDbSupportStart | KnownPerfId::ModuleDbSupportEnd => {
+                Some(Measurement::DriverBindingSupport)
+            }
+            KnownPerfId::ModuleDbStart | KnownPerfId::ModuleDbEnd => Some(Measurement::DriverBindingStart),
+            KnownPerfId::ModuleDbStopStart | KnownPerfId::ModuleDbStopEnd => Some(Measurement::DriverBindingStop),
+            KnownPerfId::PerfFunctionStart | KnownPerfId::PerfFunctionEnd => Some(Measurement::FunctionSpan),
+            KnownPerfId::PerfEvent
+            | KnownPerfId::PerfEventSignalStart
+            | KnownPerfId::PerfEventSignalEnd
+            | KnownPerfId::PerfCallbackStart
+            | KnownPerfId::PerfCallbackEnd => Some(Measurement::EventSignal),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the records with `perf_id` are recorded with the measurement mask `mask`.
+    pub fn is_perf_id_enabled(mask: u32, perf_id: u16) -> bool {
+        Self::from_perf_id(perf_id).is_none_or(|measurement| mask & measurement.as_u32() != 0)
+    }
 }
 
 /// Implement bitwise OR for measurements (`Measurement | Measurement`).
@@ -472,45 +612,58 @@ fn get_module_guid_from_handle(
     boot_services: &impl BootServices,
     handle: efi::Handle,
 ) -> Result<efi::Guid, efi::Status> {
-    let mut guid = efi::Guid::from_fields(0, 0, 0, 0, 0, &[0; 6]);
-
-    let loaded_image_protocol = 'find_loaded_image_protocol: {
-        if let Ok(loaded_image_protocol) =
-            unsafe { boot_services.handle_protocol::<efi::protocols::loaded_image::Protocol>(handle) }
-        {
-            break 'find_loaded_image_protocol Some(loaded_image_protocol);
+    let loaded_image = 'find_loaded_image: {
+        // SAFETY: The protocol is not mutated.
+        if let Ok(loaded_image) = unsafe { boot_services.handle_protocol::<LoadedImage>(handle) } {
+            break 'find_loaded_image Some(loaded_image);
         }
 
-        // SAFETY: This is safe because the protocol is not mutated.
+        // SAFETY: The protocols are not mutated.
         unsafe {
-            if let Ok(driver_binding_protocol) = boot_services
-                .open_protocol::<efi::protocols::driver_binding::Protocol>(
-                    handle,
-                    ptr::null_mut(),
-                    ptr::null_mut(),
-                    efi::OPEN_PROTOCOL_GET_PROTOCOL,
-                )
-                && let Ok(loaded_image_protocol) = boot_services
-                    .handle_protocol::<efi::protocols::loaded_image::Protocol>(driver_binding_protocol.image_handle)
+            if let Ok(driver_binding) = boot_services.open_protocol::<DriverBindingProtocol>(
+                handle,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                efi::OPEN_PROTOCOL_GET_PROTOCOL,
+            ) && let Ok(loaded_image) = boot_services.handle_protocol::<LoadedImage>(driver_binding.image_handle())
             {
-                break 'find_loaded_image_protocol Some(loaded_image_protocol);
+                break 'find_loaded_image Some(loaded_image);
             }
         }
         None
     };
 
-    if let Some(loaded_image) = loaded_image_protocol {
-        // SAFETY: File path is a pointer from C that is valid and of type Device Path (efi).
-        if let Some(file_path) = unsafe { loaded_image.file_path.as_ref() }
-            && file_path.r#type == TYPE_MEDIA
-            && file_path.sub_type == Media::SUBTYPE_PIWG_FIRMWARE_FILE
-        {
-            // Guid is stored after the device path in memory.
-            guid = unsafe { ptr::read(loaded_image.file_path.add(1) as *const efi::Guid) }
-        };
+    Ok(loaded_image.and_then(|loaded_image| loaded_image.firmware_file_name()).unwrap_or(guids::ZERO))
+}
+
+/// Reads the GUID the caller identifier points to, or returns the zero GUID if it is null.
+///
+/// # Safety
+/// `caller_identifier` must be null or point to a GUID.
+unsafe fn get_module_guid_from_caller_id(caller_identifier: *const c_void) -> efi::Guid {
+    unsafe { (caller_identifier as *const efi::Guid).as_ref() }.copied().unwrap_or(guids::ZERO)
+}
+
+/// Returns `guid`, or the name of the firmware file of the loaded image containing `caller_address` if `guid` is the
+/// zero GUID of an unknown caller, e.g. a C driver passing a null or invalid caller identifier.
+fn attribute_to_caller(boot_services: &impl BootServices, guid: efi::Guid, caller_address: usize) -> efi::Guid {
+    if guid != guids::ZERO {
+        return guid;
     }
+    get_module_guid_from_address(boot_services, caller_address).unwrap_or(guid)
+}
 
-    Ok(guid)
+/// Returns the name of the firmware file of the loaded image containing `address`, found through the Loaded Image
+/// Info protocol of the DXE core.
+fn get_module_guid_from_address(boot_services: &impl BootServices, address: usize) -> Option<efi::Guid> {
+    // SAFETY: The protocol is not mutated.
+    let protocol = unsafe { boot_services.locate_protocol::<loaded_image_info::Protocol>(None) }.ok()?;
+    let mut info = MaybeUninit::<LoadedImageInfo>::uninit();
+    match (protocol.find_loaded_image_info)(protocol, address as efi::PhysicalAddress, info.as_mut_ptr()) {
+        // SAFETY: The information of the image is written on success.
+        efi::Status::SUCCESS => Some(unsafe { info.assume_init() }.file_guid).filter(|guid| *guid != guids::ZERO),
+        _ => None,
+    }
 }
 
 /// This device path is used by systems implementing the UEFI PI Specification 1.0 to describe a firmware file.
@@ -526,21 +679,37 @@ pub struct MediaFwVolFilepathDevicePath {
 mod tests {
     use super::*;
 
-    use alloc::rc::Rc;
-    use core::{mem::MaybeUninit, ptr};
+    use alloc::{boxed::Box, rc::Rc};
+    use core::{
+        mem::{self, MaybeUninit},
+        ptr,
+    };
+    use r_efi::protocols::device_path::{Media, TYPE_MEDIA};
 
     use mockall::predicate;
 
     use crate::{
         boot_services::{MockBootServices, c_ptr::CMutPtr, tpl::Tpl},
         performance::{
-            globals::set_perf_measurement_mask,
             logging::*,
-            table::{FirmwarePerformanceVariable, MockFirmwareBasicBootPerfTable},
+            table::{FBPT, FirmwarePerformanceVariable, MockFirmwareBasicBootPerfTable},
         },
         runtime_services::MockRuntimeServices,
     };
 
+    /// Deterministic timer running at 1 GHz, so that ticks are equal to nanoseconds.
+    struct FixedTimer;
+
+    impl PerfTimer for FixedTimer {
+        fn cpu_count(&self) -> u64 {
+            1_000
+        }
+
+        fn perf_frequency(&self) -> u64 {
+            1_000_000_000
+        }
+    }
+
     #[test]
     fn test_report_fbpt_record_buffer() {
         static REPORT_STATUS_CODE_CALLED: AtomicBool = AtomicBool::new(false);
@@ -562,9 +731,6 @@ mod tests {
         boot_services.expect_raise_tpl().returning(|tpl| tpl);
         boot_services.expect_restore_tpl().return_const(());
 
-        // Test that the event is close so it run only one time.
-        boot_services.expect_close_event().once().return_const(Ok(()));
-
         boot_services
             .expect_install_configuration_table_unchecked()
             .once()
@@ -579,26 +745,71 @@ mod tests {
         let mut runtime_services = MockRuntimeServices::new();
         runtime_services
             .expect_get_variable::<FirmwarePerformanceVariable>()
-            .once()
+            .times(2)
             .returning(|_, _, _| Err(efi::Status::NOT_FOUND));
+        // The address of the new table is saved for the next boot.
+        runtime_services
+            .expect_set_variable::<FirmwarePerformanceVariable>()
+            .once()
+            .withf(|_, _, _, variable| variable.as_ref()[..mem::size_of::<usize>()] == 0x1000_usize.to_ne_bytes())
+            .returning(|_, _, _, _| Ok(()));
 
         let mut fbpt = MockFirmwareBasicBootPerfTable::new();
-        fbpt.expect_report_table::<MockBootServices>().once().returning(|_, _| Ok(1));
+        fbpt.expect_report_table::<MockBootServices>().once().returning(|_, _| Ok(0x1000));
 
         let fbpt = TplMutex::new(unsafe { &*ptr::addr_of!(boot_services) }, Tpl::NOTIFY, fbpt);
         let fbpt = unsafe { &*ptr::addr_of!(fbpt) };
 
         event_callback::report_fbpt_record_buffer(
             1_usize as efi::Event,
-            Box::new((Rc::new(boot_services), Rc::new(runtime_services), fbpt)),
+            &mut event_callback::ReportFbptContext {
+                boot_services: Rc::new(boot_services),
+                runtime_services: Rc::new(runtime_services),
+                fbpt,
+                reuse_previous_address: true,
+            },
         );
 
         assert!(REPORT_STATUS_CODE_CALLED.load(Ordering::Relaxed));
     }
 
+    #[test]
+    fn test_report_fbpt_record_buffer_without_address_reuse() {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_raise_tpl().returning(|tpl| tpl);
+        boot_services.expect_restore_tpl().return_const(());
+        boot_services
+            .expect_locate_protocol::<StatusCodeRuntimeProtocol>()
+            .once()
+            .returning(|_| Err(efi::Status::NOT_FOUND));
+
+        // The FirmwarePerformanceVariable is neither read nor written.
+        let mut runtime_services = MockRuntimeServices::new();
+        runtime_services.expect_get_variable::<FirmwarePerformanceVariable>().never();
+        runtime_services.expect_set_variable::<FirmwarePerformanceVariable>().never();
+
+        let mut fbpt = MockFirmwareBasicBootPerfTable::new();
+        fbpt.expect_report_table::<MockBootServices>()
+            .once()
+            .withf(|address, _| address.is_none())
+            .returning(|_, _| Ok(0x1000));
+
+        let fbpt = TplMutex::new(unsafe { &*ptr::addr_of!(boot_services) }, Tpl::NOTIFY, fbpt);
+        let fbpt = unsafe { &*ptr::addr_of!(fbpt) };
+
+        event_callback::report_fbpt_record_buffer(
+            1_usize as efi::Event,
+            &mut event_callback::ReportFbptContext {
+                boot_services: Rc::new(boot_services),
+                runtime_services: Rc::new(runtime_services),
+                fbpt,
+                reuse_previous_address: false,
+            },
+        );
+    }
+
     #[test]
     fn test_create_performance_measurement() {
-        set_perf_measurement_mask(u32::MAX);
         let mut boot_services = MockBootServices::new();
 
         let mut loaded_image_protocol = MaybeUninit::<efi::protocols::loaded_image::Protocol>::zeroed();
@@ -606,6 +817,8 @@ mod tests {
         unsafe {
             media_fw_vol_file_path_device_path.assume_init_mut().header.r#type = TYPE_MEDIA;
             media_fw_vol_file_path_device_path.assume_init_mut().header.sub_type = Media::SUBTYPE_PIWG_FIRMWARE_FILE;
+            media_fw_vol_file_path_device_path.assume_init_mut().header.length =
+                (mem::size_of::<MediaFwVolFilepathDevicePath>() as u16).to_le_bytes();
             media_fw_vol_file_path_device_path.assume_init_mut().fv_file_name = efi::Guid::from_bytes(&[3; 16]);
 
             loaded_image_protocol.assume_init_mut().file_path =
@@ -613,9 +826,9 @@ mod tests {
         }
         let loaded_image_protocol_address = loaded_image_protocol.as_mut_ptr() as usize;
 
-        boot_services.expect_handle_protocol::<efi::protocols::loaded_image::Protocol>().returning(move |_| unsafe {
-            Ok((loaded_image_protocol_address as *mut efi::protocols::loaded_image::Protocol).as_mut().unwrap())
-        });
+        boot_services
+            .expect_handle_protocol::<LoadedImage>()
+            .returning(move |_| unsafe { Ok((loaded_image_protocol_address as *mut LoadedImage).as_mut().unwrap()) });
         boot_services.expect_raise_tpl().returning(|tpl| tpl);
         boot_services.expect_restore_tpl().return_const(());
 
@@ -649,8 +862,9 @@ mod tests {
         ) -> efi::Status {
             let string = unsafe { string.as_ref().map(|s| CStr::from_ptr(s).to_str().unwrap().to_string()) };
             let perf_id = identifier as u16;
-            _create_performance_measurement::<MockBootServices, MockFirmwareBasicBootPerfTable>(
+            _create_performance_measurement::<MockBootServices, MockFirmwareBasicBootPerfTable, _>(
                 caller_identifier,
+                0,
                 guid,
                 string.as_deref(),
                 ticker,
@@ -659,6 +873,7 @@ mod tests {
                 attribute,
                 unsafe { BOOT_SERVICES.unwrap() },
                 unsafe { FBPT.unwrap() },
+                &FixedTimer,
             )
             .unwrap();
             efi::Status::SUCCESS
@@ -698,4 +913,164 @@ mod tests {
         perf_cross_module_begin("measurement_str", &caller_id, test_create_performance_measurement);
         perf_cross_module_end("measurement_str", &caller_id, test_create_performance_measurement);
     }
+
+    #[test]
+    fn test_measurement_mask_filters_perf_ids() {
+        let mask = Measurement::LoadImage | Measurement::StartImage;
+        assert!(Measurement::is_perf_id_enabled(mask, KnownPerfId::ModuleStart.as_u16()));
+        assert!(Measurement::is_perf_id_enabled(mask, KnownPerfId::ModuleLoadImageEnd.as_u16()));
+        assert!(!Measurement::is_perf_id_enabled(mask, KnownPerfId::ModuleDbStart.as_u16()));
+        assert!(!Measurement::is_perf_id_enabled(mask, KnownPerfId::PerfFunctionStart.as_u16()));
+        assert!(!Measurement::is_perf_id_enabled(mask, KnownPerfId::PerfCallbackEnd.as_u16()));
+        assert!(!Measurement::is_perf_id_enabled(mask, KnownPerfId::PerfEvent.as_u16()));
+
+        // Records not belonging to a measurement are always recorded.
+        assert!(Measurement::is_perf_id_enabled(0, KnownPerfId::PerfInModuleStart.as_u16()));
+        assert!(Measurement::is_perf_id_enabled(0, 0x1230));
+
+        assert_eq!(Measurement::from_perf_id(KnownPerfId::PerfFunctionEnd.as_u16()), Some(Measurement::FunctionSpan));
+        assert_eq!(
+            Measurement::from_perf_id(KnownPerfId::PerfEventSignalStart.as_u16()),
+            Some(Measurement::EventSignal)
+        );
+    }
+
+    #[test]
+    fn test_set_measurement_mask_rejects_unknown_measurements() {
+        assert_eq!(set_measurement_mask(Measurement::ALL + 1), efi::Status::INVALID_PARAMETER);
+        assert_eq!(set_measurement_mask(Measurement::ALL), efi::Status::SUCCESS);
+        assert_eq!(get_measurement_mask(), Measurement::ALL);
+    }
+
+    #[test]
+    fn test_create_performance_measurement_timestamps_are_deterministic() {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_raise_tpl().returning(|tpl| tpl);
+        boot_services.expect_restore_tpl().return_const(());
+        let fbpt = TplMutex::new(unsafe { &*ptr::addr_of!(boot_services) }, Tpl::NOTIFY, FBPT::new());
+
+        let caller_id = efi::Guid::from_bytes(&[1; 16]);
+        let caller_identifier = &caller_id as *const efi::Guid as *const c_void;
+        let perf_id = KnownPerfId::PerfFunctionStart.as_u16();
+
+        for ticker in [0, 1, 5_000] {
+            _create_performance_measurement(
+                caller_identifier,
+                0,
+                None,
+                Some("fun_name"),
+                ticker,
+                0,
+                perf_id,
+                PerfAttribute::PerfStartEntry,
+                &boot_services,
+                &fbpt,
+                &FixedTimer,
+            )
+            .unwrap();
+        }
+
+        let fbpt = fbpt.lock();
+        let timestamps = fbpt
+            .perf_records()
+            .iter()
+            .map(|r| u64::from_ne_bytes(r.data[6..14].try_into().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(timestamps, [1_000, 0, 5_000]);
+    }
+
+    #[test]
+    fn test_unknown_callers_are_attributed_to_the_calling_image() {
+        extern "efiapi" fn get_loaded_image_info(
+            _this: *const loaded_image_info::Protocol,
+            _index: usize,
+            _info: *mut LoadedImageInfo,
+        ) -> efi::Status {
+            efi::Status::UNSUPPORTED
+        }
+
+        extern "efiapi" fn find_loaded_image_info(
+            _this: *const loaded_image_info::Protocol,
+            address: efi::PhysicalAddress,
+            info: *mut LoadedImageInfo,
+        ) -> efi::Status {
+            if !(0x1000..0x2000).contains(&address) {
+                return efi::Status::NOT_FOUND;
+            }
+            unsafe {
+                info.write(LoadedImageInfo {
+                    image_handle: ptr::null_mut(),
+                    image_base: 0x1000,
+                    image_size: 0x1000,
+                    entry_point: 0x1400,
+                    file_guid: efi::Guid::from_bytes(&[4; 16]),
+                    device_path: ptr::null(),
+                    protection: 0,
+                })
+            };
+            efi::Status::SUCCESS
+        }
+
+        extern "efiapi" fn get_image_debug_info(
+            _this: *const loaded_image_info::Protocol,
+            _address: efi::PhysicalAddress,
+            _info: *mut loaded_image_info::ImageDebugInfo,
+        ) -> efi::Status {
+            efi::Status::UNSUPPORTED
+        }
+
+        let protocol = Box::leak(Box::new(loaded_image_info::Protocol {
+            get_loaded_image_info,
+            find_loaded_image_info,
+            get_image_debug_info,
+        }));
+        let protocol_address = protocol as *mut loaded_image_info::Protocol as usize;
+
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_raise_tpl().returning(|tpl| tpl);
+        boot_services.expect_restore_tpl().return_const(());
+        boot_services
+            .expect_locate_protocol::<loaded_image_info::Protocol>()
+            .returning(move |_| Ok(unsafe { &mut *(protocol_address as *mut loaded_image_info::Protocol) }));
+        let fbpt = TplMutex::new(unsafe { &*ptr::addr_of!(boot_services) }, Tpl::NOTIFY, FBPT::new());
+
+        let zero_guid = guids::ZERO;
+        let caller_id = efi::Guid::from_bytes(&[1; 16]);
+        let perf_id = KnownPerfId::PerfFunctionStart.as_u16();
+        for (caller_identifier, caller_address) in [
+            // A null or zeroed caller identifier is attributed to the image containing the call site.
+            (ptr::null(), 0x1800),
+            (&zero_guid as *const efi::Guid as *const c_void, 0x1800),
+            // A known caller identifier is kept.
+            (&caller_id as *const efi::Guid as *const c_void, 0x1800),
+            // The call site is not in a loaded image.
+            (ptr::null(), 0x3000),
+        ] {
+            _create_performance_measurement(
+                caller_identifier,
+                caller_address,
+                None,
+                Some("fun_name"),
+                0,
+                0,
+                perf_id,
+                PerfAttribute::PerfStartEntry,
+                &boot_services,
+                &fbpt,
+                &FixedTimer,
+            )
+            .unwrap();
+        }
+
+        let fbpt = fbpt.lock();
+        let record_guids = fbpt
+            .perf_records()
+            .iter()
+            .map(|r| efi::Guid::from_bytes(&r.data[14..30].try_into().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            record_guids,
+            [efi::Guid::from_bytes(&[4; 16]), efi::Guid::from_bytes(&[4; 16]), caller_id, guids::ZERO]
+        );
+    }
 }
@@ -293,3 +293,46 @@ impl PerformanceRecord for GuidQwordStringEventRecord<'_> {
         Ok(())
     }
 }
+
+/// A Patina vendor record recording a range of performance IDs reserved by a component.
+///
+/// Tools parsing the FBPT use these records to attribute the performance IDs used by the records of a vendor to the
+/// namespace GUID it registered them with, see [PerfIdRegistry](crate::performance::id_registry::PerfIdRegistry).
+#[derive(Debug)]
+pub struct PerfIdRangeRecord {
+    /// GUID identifying the vendor or component that reserved the range.
+    pub namespace: efi::Guid,
+    /// First performance ID of the range.
+    pub start: u16,
+    /// Last performance ID of the range, inclusive.
+    pub end: u16,
+}
+
+impl PerfIdRangeRecord {
+    /// The defined type ID for this record, in the range reserved for platform firmware vendors.
+    pub const TYPE: u16 = 0x1020;
+    /// The current revision version of this structure.
+    pub const REVISION: u8 = 1;
+
+    /// Creates a new `PerfIdRangeRecord`.
+    pub fn new(namespace: efi::Guid, start: u16, end: u16) -> Self {
+        Self { namespace, start, end }
+    }
+}
+
+impl PerformanceRecord for PerfIdRangeRecord {
+    fn record_type(&self) -> u16 {
+        Self::TYPE
+    }
+
+    fn revision(&self) -> u8 {
+        Self::REVISION
+    }
+
+    fn write_data_into(&self, buff: &mut [u8], offset: &mut usize) -> Result<(), scroll::Error> {
+        buff.gwrite_with(self.namespace.as_bytes().as_slice(), offset, ())?;
+        buff.gwrite_with(self.start, offset, scroll::NATIVE)?;
+        buff.gwrite_with(self.end, offset, scroll::NATIVE)?;
+        Ok(())
+    }
+}
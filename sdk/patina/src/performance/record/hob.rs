@@ -14,7 +14,7 @@ use alloc::vec::Vec;
 use core::iter::Iterator;
 
 use crate::{
-    component::hob::{FromHob, Hob},
+    component::hob::{FromHob, Hob, ValidatedHob},
     performance::{
         error::Error,
         record::{Iter, PerformanceRecordBuffer},
@@ -50,12 +50,36 @@ impl FromHob for HobPerformanceData {
             log::error!("Performance: error while parsing HobPerformanceRecordBuffer, return default value.");
             return Self::default();
         };
-        let records_data_buffer = bytes[offset..offset + size_of_all_entries as usize].to_vec();
+        let Some(records_data_buffer) = bytes.get(offset..offset + size_of_all_entries as usize) else {
+            log::error!("Performance: HobPerformanceRecordBuffer entries exceed the HOB size, return default value.");
+            return Self::default();
+        };
 
-        Self { load_image_count, records_data_buffer }
+        Self { load_image_count, records_data_buffer: records_data_buffer.to_vec() }
+    }
+
+    fn validate(&self) -> core::result::Result<(), &'static str> {
+        let mut buffer = self.records_data_buffer.as_slice();
+        while !buffer.is_empty() {
+            let Ok(length) = buffer.pread::<u8>(2) else {
+                return Err("truncated performance record header");
+            };
+            let length = length as usize;
+            if length < RECORD_HEADER_SIZE {
+                return Err("performance record length is smaller than its header");
+            }
+            if length > buffer.len() {
+                return Err("performance record length exceeds the record buffer");
+            }
+            buffer = &buffer[length..];
+        }
+        Ok(())
     }
 }
 
+/// Size of the common header (type, length and revision) of a performance record.
+const RECORD_HEADER_SIZE: usize = 4;
+
 impl HobPerformanceDataExtractor for Hob<'_, HobPerformanceData> {
     #[coverage(off)]
     fn extract_hob_perf_data(&self) -> Result<(u32, PerformanceRecordBuffer), Error> {
@@ -63,6 +87,13 @@ impl HobPerformanceDataExtractor for Hob<'_, HobPerformanceData> {
     }
 }
 
+impl HobPerformanceDataExtractor for ValidatedHob<'_, HobPerformanceData> {
+    #[coverage(off)]
+    fn extract_hob_perf_data(&self) -> Result<(u32, PerformanceRecordBuffer), Error> {
+        merge_hob_performance_buffer(self.iter())
+    }
+}
+
 fn merge_hob_performance_buffer<'a, T>(iter: T) -> Result<(u32, PerformanceRecordBuffer), Error>
 where
     T: Iterator<Item = &'a HobPerformanceData>,
@@ -140,6 +171,36 @@ pub mod tests {
 
         assert_eq!(0, hob_perf_record_buffer.load_image_count);
         assert!(hob_perf_record_buffer.records_data_buffer.is_empty());
+
+        // Size of all entries larger than the HOB.
+        let buffer = [0xFF_u8, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0];
+        let hob_perf_record_buffer = HobPerformanceData::parse(&buffer);
+        assert_eq!(0, hob_perf_record_buffer.load_image_count);
+        assert!(hob_perf_record_buffer.records_data_buffer.is_empty());
+    }
+
+    #[test]
+    fn test_hob_performance_data_validate() {
+        let mut perf_record_buffer = PerformanceRecordBuffer::new();
+        perf_record_buffer
+            .push_record(GenericPerformanceRecord { record_type: 1, length: 5, revision: 1, data: [1_u8, 2, 3, 4, 5] })
+            .unwrap();
+        let records_data_buffer = perf_record_buffer.buffer().to_vec();
+
+        let data = HobPerformanceData { load_image_count: 1, records_data_buffer: records_data_buffer.clone() };
+        assert_eq!(data.validate(), Ok(()));
+        assert_eq!(HobPerformanceData::default().validate(), Ok(()));
+
+        let mut truncated = records_data_buffer.clone();
+        truncated.pop();
+        let data = HobPerformanceData { load_image_count: 1, records_data_buffer: truncated };
+        assert_eq!(data.validate(), Err("performance record length exceeds the record buffer"));
+
+        let data = HobPerformanceData { load_image_count: 1, records_data_buffer: vec![1, 0] };
+        assert_eq!(data.validate(), Err("truncated performance record header"));
+
+        let data = HobPerformanceData { load_image_count: 1, records_data_buffer: vec![1, 0, 2, 1] };
+        assert_eq!(data.validate(), Err("performance record length is smaller than its header"));
     }
 
     #[test]
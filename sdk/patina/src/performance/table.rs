@@ -20,22 +20,25 @@ use core::{
 };
 
 use crate::{
+    Guid, OwnedGuid,
     base::UEFI_PAGE_SIZE,
     boot_services::{
         BootServices,
         allocation::{AllocType, MemoryType},
     },
+    component::hob::FromHob,
     error::EfiError,
     performance::{
         self,
         error::Error,
         record::{PerformanceRecord, PerformanceRecordBuffer},
     },
+    performance_debug_assert,
     runtime_services::RuntimeServices,
 };
 
 use r_efi::efi;
-use scroll::Pwrite;
+use scroll::{Pread, Pwrite};
 
 /// The number of extra space in byte that will be allocated when publishing the performance buffer.
 /// This is used for every performance records that will be added to the table after it is published.
@@ -58,8 +61,20 @@ pub trait FirmwareBasicBootPerfTable: Sized {
     #[cfg_attr(test, mockall::concretize)]
     fn add_record<T: PerformanceRecord>(&mut self, record: T) -> Result<(), Error>;
 
+    /// Return the basic boot performance record of the table.
+    fn basic_boot_record(&self) -> FirmwareBasicBootPerfDataRecord;
+
+    /// Set the `field` timestamp, in nanoseconds, of the basic boot performance record of the table.
+    fn set_basic_boot_timestamp(&mut self, field: BasicBootTimestamp, timestamp: u64);
+
+    /// Move the table into a buffer reserved by the platform, so that records are written in place from now on and the
+    /// table does not need to be allocated and copied when it is reported.
+    fn use_reserved_buffer(&mut self, buffer: &'static mut [u8]) -> Result<(), Error>;
+
     /// Report table allocate new space of memory and move the table to a specific place so it can be found later, the address where the table is allocated is returned.
     /// Additional memory is allocated so the table can still grow in the future step.
+    ///
+    /// If the table is already in a reserved buffer, it is reported where it is.
     fn report_table<B: BootServices + 'static>(
         &mut self,
         address: Option<usize>,
@@ -75,6 +90,9 @@ pub struct FBPT {
     /// First value is the length when the table is not been reported and the second one is when the table is reported.
     /// Use `length()` or `length_mut()`. Do not use this field directly.
     _length: (u32, AtomicPtr<u32>),
+    /// First value is the basic boot record when the table is not been reported and the second one is where it is
+    /// written when the table is reported. Use `basic_boot_record()` or `set_basic_boot_timestamp()`.
+    _basic_boot: (FirmwareBasicBootPerfDataRecord, AtomicPtr<FirmwareBasicBootPerfDataRecord>),
     /// Buffer containing all the performance record.
     other_records: PerformanceRecordBuffer,
 }
@@ -88,6 +106,7 @@ impl FBPT {
         Self {
             fbpt_address: 0,
             _length: (Self::size_of_empty_table() as u32, AtomicPtr::new(ptr::null_mut())),
+            _basic_boot: (FirmwareBasicBootPerfDataRecord::new(), AtomicPtr::new(ptr::null_mut())),
             other_records: PerformanceRecordBuffer::new(),
         }
     }
@@ -138,6 +157,31 @@ impl FBPT {
         // SAFETY: the allocation at this addres was of size `allocation_size`
         Ok(unsafe { slice::from_raw_parts_mut(address, allocation_size) })
     }
+
+    /// Write the table into `fbpt_buffer`, where the records are written from now on.
+    fn write_table(&mut self, fbpt_buffer: &'static mut [u8]) -> Result<(), Error> {
+        if fbpt_buffer.len() < Self::size_of_empty_table() + self.other_records.size() {
+            return Err(Error::BufferTooSmall);
+        }
+
+        let mut offset = 0;
+        fbpt_buffer.gwrite(Self::SIGNATURE, &mut offset).map_err(|_| Error::BufferTooSmall)?;
+        let length_ptr = unsafe { fbpt_buffer.as_ptr().byte_add(offset) } as *mut u32;
+        fbpt_buffer.gwrite(*self.length(), &mut offset).map_err(|_| Error::BufferTooSmall)?;
+        // The fields of the basic boot record follow its header and reserved bytes.
+        let basic_boot_ptr =
+            unsafe { fbpt_buffer.as_ptr().byte_add(offset + performance::record::PERFORMANCE_RECORD_HEADER_SIZE + 4) }
+                as *mut FirmwareBasicBootPerfDataRecord;
+        self._basic_boot.0.write_into(fbpt_buffer, &mut offset).map_err(|_| Error::BufferTooSmall)?;
+
+        debug_assert_eq!(Self::size_of_empty_table(), offset);
+        self.fbpt_address = fbpt_buffer.as_ptr() as usize;
+        self.other_records.report(&mut fbpt_buffer[offset..])?;
+
+        self._length.1.store(length_ptr, Ordering::Relaxed);
+        self._basic_boot.1.store(basic_boot_ptr, Ordering::Relaxed);
+        Ok(())
+    }
 }
 
 impl FirmwareBasicBootPerfTable for FBPT {
@@ -160,27 +204,59 @@ impl FirmwareBasicBootPerfTable for FBPT {
         Ok(())
     }
 
+    fn basic_boot_record(&self) -> FirmwareBasicBootPerfDataRecord {
+        let reported = self._basic_boot.1.load(Ordering::Relaxed);
+        if reported.is_null() {
+            return self._basic_boot.0;
+        }
+        // SAFETY: Once reported, the record is in the table buffer, which may not be aligned for a reserved buffer.
+        unsafe { reported.read_unaligned() }
+    }
+
+    fn set_basic_boot_timestamp(&mut self, field: BasicBootTimestamp, timestamp: u64) {
+        let mut record = self.basic_boot_record();
+        match field {
+            BasicBootTimestamp::ResetEnd => record.reset_end = timestamp,
+            BasicBootTimestamp::OsLoaderLoadImageStart => record.os_loader_load_image_start = timestamp,
+            BasicBootTimestamp::OsLoaderStartImageStart => record.os_loader_start_image_start = timestamp,
+            BasicBootTimestamp::ExitBootServicesEntry => record.exit_boot_services_entry = timestamp,
+            BasicBootTimestamp::ExitBootServicesExit => record.exit_boot_services_exit = timestamp,
+        }
+        let reported = self._basic_boot.1.load(Ordering::Relaxed);
+        if reported.is_null() {
+            self._basic_boot.0 = record;
+        } else {
+            // SAFETY: Once reported, the record is in the table buffer, which may not be aligned for a reserved buffer.
+            unsafe { reported.write_unaligned(record) };
+        }
+    }
+
+    fn use_reserved_buffer(&mut self, buffer: &'static mut [u8]) -> Result<(), Error> {
+        if self.fbpt_address != 0 {
+            return performance_debug_assert!("FBPT already reported.");
+        }
+        self.write_table(buffer)
+    }
+
     fn report_table<B: BootServices + 'static>(
         &mut self,
         address: Option<usize>,
         boot_services: &B,
     ) -> Result<usize, Error> {
-        let fbpt_buffer = self.allocate_table_buffer(address, boot_services)?;
-
-        self.fbpt_address = fbpt_buffer.as_ptr() as usize;
-
-        let mut offset = 0;
-        fbpt_buffer.gwrite(Self::SIGNATURE, &mut offset).map_err(|_| Error::BufferTooSmall)?;
-        let length_ptr = unsafe { fbpt_buffer.as_ptr().byte_add(offset) } as *mut u32;
-        fbpt_buffer.gwrite(*self.length(), &mut offset).map_err(|_| Error::BufferTooSmall)?;
-        FirmwareBasicBootPerfDataRecord::new()
-            .write_into(fbpt_buffer, &mut offset)
-            .map_err(|_| Error::BufferTooSmall)?;
-
-        debug_assert_eq!(Self::size_of_empty_table(), offset);
-        self.other_records.report(&mut fbpt_buffer[offset..])?;
+        if self.fbpt_address != 0 {
+            // The table is already in the buffer reserved by the platform, which should be at the address of the
+            // previous boot so that the table is found at the same place across S3 and S4 cycles.
+            if address.is_some_and(|address| address != self.fbpt_address) {
+                log::warn!(
+                    "Performance: Reserved FBPT buffer at {:#x} is not at the address of the previous boot.",
+                    self.fbpt_address
+                );
+            }
+            return Ok(self.fbpt_address);
+        }
 
-        self._length.1.store(length_ptr, Ordering::Relaxed);
+        let fbpt_buffer = self.allocate_table_buffer(address, boot_services)?;
+        self.write_table(fbpt_buffer)?;
         Ok(self.fbpt_address)
     }
 }
@@ -192,27 +268,79 @@ impl Default for FBPT {
 }
 
 /// Return the address where the FBPT has been allocated during the previous boot.
+///
+/// The address is only returned if it can still hold the table: it must be page aligned and below 4GB. Whether the
+/// memory at that address is free is checked when the table is allocated there.
 pub fn find_previous_table_address(runtime_services: &impl RuntimeServices) -> Option<usize> {
-    runtime_services
-        .get_variable::<FirmwarePerformanceVariable>(
-            &[0],
-            &FirmwarePerformanceVariable::ADDRESS_VARIABLE_GUID,
-            Some(mem::size_of::<FirmwarePerformanceVariable>()),
-        )
-        .map(|(v, _)| v.boot_performance_table_pointer)
-        .ok()
+    match FirmwarePerformanceVariable::get(runtime_services) {
+        Ok(variable) => {
+            let address = variable.boot_performance_table_pointer;
+            if address == 0 || !address.is_multiple_of(UEFI_PAGE_SIZE) || address >= u32::MAX as usize {
+                log::warn!("Performance: Ignoring invalid FBPT address {address:#x} of the previous boot.");
+                return None;
+            }
+            Some(address)
+        }
+        Err(efi::Status::NOT_FOUND) => {
+            log::info!("Performance: No FBPT address from a previous boot.");
+            None
+        }
+        Err(status) => {
+            log::warn!("Performance: Fail to get the FBPT address of the previous boot: {status:?}.");
+            None
+        }
+    }
+}
+
+/// Save `address` as the address of the FBPT in the FirmwarePerformanceVariable, so that the next boot allocates the
+/// table at the same address.
+pub fn set_table_address(runtime_services: &impl RuntimeServices, address: usize) -> Result<(), efi::Status> {
+    let s3_performance_table_pointer =
+        FirmwarePerformanceVariable::get(runtime_services).map_or(0, |variable| variable.s3_performance_table_pointer);
+    runtime_services.set_variable(
+        &FirmwarePerformanceVariable::NAME,
+        &FirmwarePerformanceVariable::ADDRESS_VARIABLE_GUID,
+        efi::VARIABLE_NON_VOLATILE | efi::VARIABLE_BOOTSERVICE_ACCESS | efi::VARIABLE_RUNTIME_ACCESS,
+        &FirmwarePerformanceVariable { boot_performance_table_pointer: address, s3_performance_table_pointer },
+    )
 }
 
 /// Struct used to get the value from the FirmwarePerformanceVariable
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C)]
 pub struct FirmwarePerformanceVariable {
     boot_performance_table_pointer: usize,
-    _s3_performance_table_pointer: usize,
+    s3_performance_table_pointer: usize,
 }
 
 impl FirmwarePerformanceVariable {
     const ADDRESS_VARIABLE_GUID: efi::Guid =
         efi::Guid::from_fields(0xc095791a, 0x3001, 0x47b2, 0x80, 0xc9, &[0xea, 0xc7, 0x31, 0x9f, 0x2f, 0xa4]);
+
+    /// `L"FirmwarePerformance"`, the name of the variable in EDK II.
+    const NAME: [u16; 20] = {
+        let ascii = b"FirmwarePerformance\0";
+        let mut name = [0; 20];
+        let mut i = 0;
+        while i < ascii.len() {
+            name[i] = ascii[i] as u16;
+            i += 1;
+        }
+        name
+    };
+
+    fn get(runtime_services: &impl RuntimeServices) -> Result<Self, efi::Status> {
+        runtime_services
+            .get_variable::<Self>(&Self::NAME, &Self::ADDRESS_VARIABLE_GUID, Some(mem::size_of::<Self>()))
+            .map(|(variable, _)| variable)
+    }
+}
+
+impl AsRef<[u8]> for FirmwarePerformanceVariable {
+    fn as_ref(&self) -> &[u8] {
+        // SAFETY: The variable is plain old data of this size.
+        unsafe { slice::from_raw_parts(self as *const Self as *const u8, mem::size_of::<Self>()) }
+    }
 }
 
 impl TryFrom<Vec<u8>> for FirmwarePerformanceVariable {
@@ -228,7 +356,77 @@ impl TryFrom<Vec<u8>> for FirmwarePerformanceVariable {
     }
 }
 
-#[derive(Clone)]
+/// HOB describing a buffer the platform reserved for the FBPT, e.g. at the address of the previous boot found in the
+/// FirmwarePerformanceVariable.
+///
+/// When it is produced, the table is written in that buffer from the start of DXE, so it does not need to be allocated
+/// and copied at EndOfDxe. The buffer must be in reserved memory, below 4GB, and large enough for the table to grow
+/// until ExitBootServices.
+#[derive(Debug, Clone, Copy, Pread)]
+#[repr(C)]
+pub struct FbptReservedBuffer {
+    /// Physical address of the buffer.
+    pub address: u64,
+    /// Size in bytes of the buffer.
+    pub size: u64,
+}
+
+impl FromHob for FbptReservedBuffer {
+    // { 0x2b6f8e4c, 0x9a7d, 0x4c31, { 0xb8, 0x52, 0x1e, 0x6d, 0x0f, 0xa3, 0x94, 0x7c } }
+    const HOB_GUID: OwnedGuid =
+        Guid::from_fields(0x2b6f8e4c, 0x9a7d, 0x4c31, 0xb8, 0x52, [0x1e, 0x6d, 0x0f, 0xa3, 0x94, 0x7c]);
+
+    fn parse(bytes: &[u8]) -> Self {
+        bytes.pread(0).unwrap()
+    }
+}
+
+impl FbptReservedBuffer {
+    /// Get the reserved memory as a mutable buffer.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that `address` points to `size` bytes of reserved memory that is not used by anything
+    /// else.
+    pub unsafe fn as_buffer(&self) -> &'static mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.address as usize as *mut u8, self.size as usize) }
+    }
+}
+
+/// HOB produced by SEC with the time the firmware started executing, `FIRMWARE_SEC_PERFORMANCE` in EDK II.
+#[derive(Debug, Clone, Copy, Pread)]
+#[repr(C)]
+pub struct FirmwareSecPerformance {
+    /// Timer value logged at the beginning of firmware image execution, in nanoseconds.
+    pub reset_end: u64,
+}
+
+impl FromHob for FirmwareSecPerformance {
+    // gEfiFirmwarePerformanceGuid { 0xc095791a, 0x3001, 0x47b2, { 0x80, 0xc9, 0xea, 0xc7, 0x31, 0x9f, 0x2f, 0xa4 } }
+    const HOB_GUID: OwnedGuid =
+        Guid::from_fields(0xc095791a, 0x3001, 0x47b2, 0x80, 0xc9, [0xea, 0xc7, 0x31, 0x9f, 0x2f, 0xa4]);
+
+    fn parse(bytes: &[u8]) -> Self {
+        bytes.pread(0).unwrap()
+    }
+}
+
+/// A timestamp of the [FirmwareBasicBootPerfDataRecord].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BasicBootTimestamp {
+    /// See [FirmwareBasicBootPerfDataRecord::reset_end].
+    ResetEnd,
+    /// See [FirmwareBasicBootPerfDataRecord::os_loader_load_image_start].
+    OsLoaderLoadImageStart,
+    /// See [FirmwareBasicBootPerfDataRecord::os_loader_start_image_start].
+    OsLoaderStartImageStart,
+    /// See [FirmwareBasicBootPerfDataRecord::exit_boot_services_entry].
+    ExitBootServicesEntry,
+    /// See [FirmwareBasicBootPerfDataRecord::exit_boot_services_exit].
+    ExitBootServicesExit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C)]
 /// Firmware Basic Boot Performance Record
 pub struct FirmwareBasicBootPerfDataRecord {
@@ -300,6 +498,7 @@ impl Default for FirmwareBasicBootPerfDataRecord {
 mod tests {
     use super::*;
 
+    use alloc::boxed::Box;
     use core::{assert_eq, slice, unreachable};
     use scroll::Pread;
 
@@ -326,7 +525,7 @@ mod tests {
             .expect_get_variable::<FirmwarePerformanceVariable>()
             .once()
             .withf(|name, namespace, size_hint| {
-                assert_eq!(&[0], name);
+                assert_eq!(&FirmwarePerformanceVariable::NAME, name);
                 assert_eq!(&FirmwarePerformanceVariable::ADDRESS_VARIABLE_GUID, namespace);
                 assert_eq!(&Some(16), size_hint);
                 true
@@ -334,8 +533,8 @@ mod tests {
             .returning(|_, _, _| {
                 Ok((
                     FirmwarePerformanceVariable {
-                        boot_performance_table_pointer: 0x12341234,
-                        _s3_performance_table_pointer: 0,
+                        boot_performance_table_pointer: 0x12340000,
+                        s3_performance_table_pointer: 0,
                     },
                     16,
                 ))
@@ -343,7 +542,63 @@ mod tests {
 
         let address = find_previous_table_address(&runtime_services);
 
-        assert_eq!(Some(0x12341234), address);
+        assert_eq!(Some(0x12340000), address);
+    }
+
+    #[test]
+    fn test_find_previous_address_ignores_invalid_addresses() {
+        // Missing, unreadable, null, unaligned and above 4GB addresses.
+        for result in
+            [Err(efi::Status::NOT_FOUND), Err(efi::Status::DEVICE_ERROR), Ok(0), Ok(0x12341234), Ok(0x1_0000_0000)]
+        {
+            let mut runtime_services = MockRuntimeServices::new();
+            runtime_services.expect_get_variable::<FirmwarePerformanceVariable>().once().returning(move |_, _, _| {
+                result.map(|address| {
+                    (
+                        FirmwarePerformanceVariable {
+                            boot_performance_table_pointer: address,
+                            s3_performance_table_pointer: 0,
+                        },
+                        16,
+                    )
+                })
+            });
+            assert_eq!(None, find_previous_table_address(&runtime_services));
+        }
+    }
+
+    #[test]
+    fn test_set_table_address() {
+        let mut runtime_services = MockRuntimeServices::new();
+        runtime_services.expect_get_variable::<FirmwarePerformanceVariable>().once().returning(|_, _, _| {
+            Ok((
+                FirmwarePerformanceVariable {
+                    boot_performance_table_pointer: 0x12340000,
+                    s3_performance_table_pointer: 0x56780000,
+                },
+                16,
+            ))
+        });
+
+        // The S3 performance table pointer is kept.
+        runtime_services
+            .expect_set_variable::<FirmwarePerformanceVariable>()
+            .once()
+            .withf(|name, namespace, attributes, variable| {
+                assert_eq!(&FirmwarePerformanceVariable::NAME, name);
+                assert_eq!(&FirmwarePerformanceVariable::ADDRESS_VARIABLE_GUID, namespace);
+                assert_eq!(
+                    &(efi::VARIABLE_NON_VOLATILE | efi::VARIABLE_BOOTSERVICE_ACCESS | efi::VARIABLE_RUNTIME_ACCESS),
+                    attributes
+                );
+                assert_eq!(variable.boot_performance_table_pointer, 0x9ABC0000);
+                assert_eq!(variable.s3_performance_table_pointer, 0x56780000);
+                assert_eq!(variable.as_ref().len(), 16);
+                true
+            })
+            .returning(|_, _, _, _| Ok(()));
+
+        assert_eq!(Ok(()), set_table_address(&runtime_services, 0x9ABC0000));
     }
 
     #[test]
@@ -446,6 +701,72 @@ mod tests {
         fbpt.add_record(GuidQwordStringEventRecord::new(1, 0, 10, guid, 64, "test")).unwrap();
     }
 
+    #[test]
+    fn test_reporting_fbpt_in_reserved_buffer() {
+        let memory_buffer = Box::leak(vec![0_u8; 1000].into_boxed_slice());
+        let address = memory_buffer.as_ptr() as usize;
+
+        // The table is reported where it is, without being allocated.
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_allocate_pages().never();
+
+        let mut fbpt = FBPT::new();
+        let guid = efi::Guid::from_bytes(&[0; 16]);
+        fbpt.add_record(GuidEventRecord::new(1, 0, 10, guid)).unwrap();
+        fbpt.use_reserved_buffer(memory_buffer).unwrap();
+        assert_eq!(address, fbpt.fbpt_address());
+        assert_eq!(fbpt.perf_records().buffer().as_ptr() as usize, address + FBPT::size_of_empty_table());
+
+        // Records are written in place.
+        fbpt.add_record(DynamicStringEventRecord::new(1, 0, 10, guid, "test")).unwrap();
+        let length = unsafe { slice::from_raw_parts(address as *const u8, 8) }.pread_with::<u32>(4, scroll::NATIVE);
+        assert_eq!(fbpt.length(), &length.unwrap());
+
+        assert_eq!(address, fbpt.report_table(Some(0x1000), &boot_services).unwrap());
+        assert_eq!(fbpt.perf_records().iter().count(), 2);
+    }
+
+    #[test]
+    fn test_basic_boot_timestamps_written_in_table() {
+        let memory_buffer = Box::leak(vec![0_u8; 1000].into_boxed_slice());
+        let address = memory_buffer.as_ptr() as usize;
+
+        // Timestamps set before the table is reported are written with it, later ones in place.
+        let mut fbpt = FBPT::new();
+        fbpt.set_basic_boot_timestamp(BasicBootTimestamp::ResetEnd, 0x11);
+        fbpt.use_reserved_buffer(memory_buffer).unwrap();
+        fbpt.set_basic_boot_timestamp(BasicBootTimestamp::OsLoaderLoadImageStart, 0x22);
+        fbpt.set_basic_boot_timestamp(BasicBootTimestamp::OsLoaderStartImageStart, 0x33);
+        fbpt.set_basic_boot_timestamp(BasicBootTimestamp::ExitBootServicesEntry, 0x44);
+        fbpt.set_basic_boot_timestamp(BasicBootTimestamp::ExitBootServicesExit, 0x55);
+
+        assert_eq!(
+            fbpt.basic_boot_record(),
+            FirmwareBasicBootPerfDataRecord {
+                reset_end: 0x11,
+                os_loader_load_image_start: 0x22,
+                os_loader_start_image_start: 0x33,
+                exit_boot_services_entry: 0x44,
+                exit_boot_services_exit: 0x55,
+            }
+        );
+        let buffer = unsafe { slice::from_raw_parts(address as *const u8, FBPT::size_of_empty_table()) };
+        let mut offset = 8 + PERFORMANCE_RECORD_HEADER_SIZE + 4;
+        for expected in [0x11_u64, 0x22, 0x33, 0x44, 0x55] {
+            assert_eq!(expected, buffer.gread_with::<u64>(&mut offset, scroll::NATIVE).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_reserved_buffer_too_small() {
+        let memory_buffer = Box::leak(vec![0_u8; FBPT::size_of_empty_table()].into_boxed_slice());
+
+        let mut fbpt = FBPT::new();
+        fbpt.add_record(GuidEventRecord::new(1, 0, 10, efi::Guid::from_bytes(&[0; 16]))).unwrap();
+        assert!(matches!(fbpt.use_reserved_buffer(memory_buffer), Err(Error::BufferTooSmall)));
+        assert_eq!(0, fbpt.fbpt_address());
+    }
+
     #[test]
     fn test_performance_table_well_written_in_memory() {
         let memory_buffer = Vec::<u8>::with_capacity(1000);
@@ -492,4 +813,48 @@ mod tests {
         offset += FirmwareBasicBootPerfDataRecord::data_size();
         assert_eq!(fbpt.perf_records().buffer().as_ptr() as usize, address + offset);
     }
+
+    #[test]
+    fn test_performance_table_golden_layout() {
+        #[rustfmt::skip]
+        const EXPECTED: [u8; 265] = [
+            0x46, 0x42, 0x50, 0x54, 0x09, 0x01, 0x00, 0x00, 0x02, 0x00, 0x30, 0x02, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x10, 0x22, 0x01, 0x10, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x22, 0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA,
+            0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0x11, 0x10, 0x25, 0x01, 0x20, 0x00,
+            0x01, 0x00, 0x00, 0x00, 0x44, 0x33, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xBB, 0xBB, 0xBB, 0xBB,
+            0xBB, 0xBB, 0xBB, 0xBB, 0xBB, 0xBB, 0xBB, 0xBB, 0xBB, 0xBB, 0xBB, 0xBB, 0x61, 0x62, 0x00, 0x12,
+            0x10, 0x34, 0x01, 0x30, 0x00, 0x00, 0x00, 0x00, 0x00, 0x55, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA,
+            0xAA, 0xBB, 0xBB, 0xBB, 0xBB, 0xBB, 0xBB, 0xBB, 0xBB, 0xBB, 0xBB, 0xBB, 0xBB, 0xBB, 0xBB, 0xBB,
+            0xBB, 0x66, 0x00, 0x13, 0x10, 0x2A, 0x01, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x66, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA,
+            0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0x88, 0x77, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x14, 0x10, 0x2C,
+            0x01, 0x50, 0x00, 0x00, 0x00, 0x00, 0x00, 0x99, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xBB,
+            0xBB, 0xBB, 0xBB, 0xBB, 0xBB, 0xBB, 0xBB, 0xBB, 0xBB, 0xBB, 0xBB, 0xBB, 0xBB, 0xBB, 0xBB, 0x01,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x6D, 0x00,
+        ];
+
+        let memory_buffer = vec![0_u8; 0x11000];
+        let address = memory_buffer.as_ptr() as usize;
+
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_allocate_pages().once().returning(move |_, _, _| Ok(address));
+
+        let guid_a = efi::Guid::from_bytes(&[0xAA; 16]);
+        let guid_b = efi::Guid::from_bytes(&[0xBB; 16]);
+
+        let mut fbpt = FBPT::new();
+        fbpt.add_record(GuidEventRecord::new(0x10, 0, 0x1122, guid_a)).unwrap();
+        fbpt.add_record(DynamicStringEventRecord::new(0x20, 1, 0x3344, guid_b, "ab")).unwrap();
+        fbpt.report_table(None, &boot_services).unwrap();
+        fbpt.add_record(DualGuidStringEventRecord::new(0x30, 0, 0x55, guid_a, guid_b, "f")).unwrap();
+        fbpt.add_record(GuidQwordEventRecord::new(0x40, 0, 0x66, guid_a, 0x7788)).unwrap();
+        fbpt.add_record(GuidQwordStringEventRecord::new(0x50, 0, 0x99, guid_b, 1, "m")).unwrap();
+
+        assert_eq!(*fbpt.length() as usize, EXPECTED.len());
+        assert_eq!(&memory_buffer[..EXPECTED.len()], EXPECTED.as_slice());
+    }
 }
@@ -0,0 +1,98 @@
+//! Timer abstraction used to timestamp performance records.
+//!
+//! Performance records are timestamped with the architecture performance counter. The [PerfTimer] trait allows the
+//! counter to be substituted, which keeps record timestamps deterministic in tests.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+#[cfg(any(test, feature = "mockall"))]
+use mockall::automock;
+
+use mu_rust_helpers::perf_timer::{Arch, ArchFunctionality};
+
+/// Number of nanoseconds in one second.
+const NANOSECONDS_PER_SECOND: f64 = 1_000_000_000_f64;
+
+/// A source of performance counter values.
+#[cfg_attr(any(test, feature = "mockall"), automock)]
+pub trait PerfTimer {
+    /// Returns the current value of the performance counter.
+    fn cpu_count(&self) -> u64;
+
+    /// Returns the frequency of the performance counter, in Hz.
+    fn perf_frequency(&self) -> u64;
+}
+
+/// [PerfTimer] implementation backed by the architecture performance counter.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ArchPerfTimer;
+
+impl PerfTimer for ArchPerfTimer {
+    fn cpu_count(&self) -> u64 {
+        Arch::cpu_count()
+    }
+
+    fn perf_frequency(&self) -> u64 {
+        Arch::perf_frequency()
+    }
+}
+
+/// Converts the ticker value provided to a performance measurement into a timestamp in nanoseconds.
+///
+/// A ticker of `0` means the current counter value must be used, a ticker of `1` means the timestamp is `0`, and any
+/// other value is the counter value at the time of the measurement.
+pub fn ticker_to_timestamp(ticker: u64, timer: &impl PerfTimer) -> u64 {
+    match ticker {
+        0 => ticks_to_ns(timer.cpu_count(), timer.perf_frequency()),
+        1 => 0,
+        ticker => ticks_to_ns(ticker, timer.perf_frequency()),
+    }
+}
+
+/// Converts a number of performance counter ticks to nanoseconds.
+fn ticks_to_ns(ticks: u64, frequency: u64) -> u64 {
+    (ticks as f64 / frequency as f64 * NANOSECONDS_PER_SECOND) as u64
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+
+    fn mock_timer(count: u64, frequency: u64) -> MockPerfTimer {
+        let mut timer = MockPerfTimer::new();
+        timer.expect_cpu_count().return_const(count);
+        timer.expect_perf_frequency().return_const(frequency);
+        timer
+    }
+
+    #[test]
+    fn test_ticker_zero_uses_current_count() {
+        let timer = mock_timer(5_000, 1_000_000_000);
+        assert_eq!(ticker_to_timestamp(0, &timer), 5_000);
+
+        let timer = mock_timer(3_000, 1_000_000);
+        assert_eq!(ticker_to_timestamp(0, &timer), 3_000_000);
+    }
+
+    #[test]
+    fn test_ticker_one_is_zero_timestamp() {
+        let mut timer = MockPerfTimer::new();
+        timer.expect_cpu_count().never();
+        timer.expect_perf_frequency().never();
+        assert_eq!(ticker_to_timestamp(1, &timer), 0);
+    }
+
+    #[test]
+    fn test_explicit_ticker_is_converted() {
+        let mut timer = MockPerfTimer::new();
+        timer.expect_cpu_count().never();
+        timer.expect_perf_frequency().return_const(2_000_000_u64);
+        assert_eq!(ticker_to_timestamp(4_000, &timer), 2_000_000);
+    }
+}
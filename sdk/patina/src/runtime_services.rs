@@ -12,6 +12,8 @@
 
 extern crate alloc;
 
+/// Typed accessors for UEFI variables
+pub mod typed_variable;
 /// Variable-services-specific structs and utilities
 pub mod variable_services;
 
@@ -60,6 +62,11 @@ impl StandardRuntimeServices {
         !self.efi_runtime_services.load(Ordering::Relaxed).is_null()
     }
 
+    /// Returns the pointer to the runtime services table, for components replacing some of its services.
+    pub fn as_mut_ptr(&self) -> *mut efi::RuntimeServices {
+        self.efi_runtime_services.load(Ordering::Relaxed)
+    }
+
     fn efi_runtime_services(&self) -> &efi::RuntimeServices {
         // SAFETY: Runtime services lifetime is expected to live long enough.
         unsafe { self.efi_runtime_services.load(Ordering::Relaxed).as_ref() }
@@ -112,6 +119,9 @@ impl Debug for StandardRuntimeServices {
 pub trait RuntimeServices {
     /// Sets a UEFI variable.
     ///
+    /// `name` must be null-terminated, e.g. the [`as_slice_with_nul`](crate::Ucs2Str::as_slice_with_nul) of a
+    /// [`ucs2!`](crate::ucs2!) name.
+    ///
     /// UEFI Spec Documentation: [8.2.3. EFI_RUNTIME_SERVICES.SetVariable()](https://uefi.org/specs/UEFI/2.10/08_Services_Runtime_Services.html#setvariable)
     ///
     fn set_variable<T>(&self, name: &[u16], namespace: &efi::Guid, attributes: u32, data: &T) -> Result<(), efi::Status>
@@ -133,6 +143,9 @@ pub trait RuntimeServices {
     ///
     /// Returns a tuple of (data, attributes)
     ///
+    /// `name` must be null-terminated, e.g. the [`as_slice_with_nul`](crate::Ucs2Str::as_slice_with_nul) of a
+    /// [`ucs2!`](crate::ucs2!) name.
+    ///
     /// UEFI Spec Documentation: [8.2.1. EFI_RUNTIME_SERVICES.GetVariable()](https://uefi.org/specs/UEFI/2.10/08_Services_Runtime_Services.html#getvariable)
     ///
     fn get_variable<T>(
@@ -254,6 +267,25 @@ pub trait RuntimeServices {
     ///
     fn query_variable_info(&self, attributes: u32) -> Result<VariableInfo, efi::Status>;
 
+    /// Returns the current time and the capabilities of the time source.
+    ///
+    /// Fails with `EFI_UNSUPPORTED` while no time source backs the runtime services.
+    ///
+    /// UEFI Spec Documentation: [8.3.1. EFI_RUNTIME_SERVICES.GetTime()](https://uefi.org/specs/UEFI/2.10/08_Services_Runtime_Services.html#gettime)
+    ///
+    fn get_time(&self) -> Result<(efi::Time, efi::TimeCapabilities), efi::Status>;
+
+    /// Converts `address` from a physical to a virtual address, from the notify function of a
+    /// `EVT_SIGNAL_VIRTUAL_ADDRESS_CHANGE` event.
+    ///
+    /// UEFI Spec Documentation: [8.4.2. EFI_RUNTIME_SERVICES.ConvertPointer()](https://uefi.org/specs/UEFI/2.10/08_Services_Runtime_Services.html#convertpointer)
+    ///
+    /// # Safety
+    ///
+    /// `address` must point to runtime memory, and must not be used anymore through its physical address once
+    /// SetVirtualAddressMap completes.
+    unsafe fn convert_pointer(&self, address: &mut *mut c_void) -> Result<(), efi::Status>;
+
     /// Set's a UEFI variable
     ///
     /// # Safety
@@ -434,6 +466,31 @@ impl RuntimeServices for StandardRuntimeServices {
 
         if status.is_error() { Err(status) } else { Ok(var_info) }
     }
+
+    unsafe fn convert_pointer(&self, address: &mut *mut c_void) -> Result<(), efi::Status> {
+        let convert_pointer = self.efi_runtime_services().convert_pointer;
+        if convert_pointer as usize == 0 {
+            debug_assert!(false, "ConvertPointer has not initialized in the Runtime Services Table.");
+            return Err(efi::Status::UNSUPPORTED);
+        }
+
+        let status = convert_pointer(0, address as *mut *mut c_void);
+        if status.is_error() { Err(status) } else { Ok(()) }
+    }
+
+    fn get_time(&self) -> Result<(efi::Time, efi::TimeCapabilities), efi::Status> {
+        let get_time = self.efi_runtime_services().get_time;
+        if get_time as usize == 0 {
+            debug_assert!(false, "GetTime has not initialized in the Runtime Services Table.");
+            return Err(efi::Status::UNSUPPORTED);
+        }
+
+        let mut time = efi::Time::default();
+        let mut capabilities = efi::TimeCapabilities { resolution: 0, accuracy: 0, sets_to_zero: false.into() };
+        let status = get_time(&mut time, &mut capabilities);
+
+        if status.is_error() { Err(status) } else { Ok((time, capabilities)) }
+    }
 }
 
 #[cfg(test)]
@@ -703,6 +760,16 @@ pub(crate) mod test {
         assert_eq!(data.value, DUMMY_DATA);
     }
 
+    #[test]
+    fn test_get_variable_ucs2_name() {
+        let rs = runtime_services!(get_variable = mock_efi_get_variable);
+
+        let name = crate::ucs2!("\u{1000}\u{1020}");
+        let (data, _) =
+            rs.get_variable::<DummyVariableType>(name.as_slice_with_nul(), &DUMMY_FIRST_NAMESPACE, None).unwrap();
+        assert_eq!(data.value, DUMMY_DATA);
+    }
+
     #[test]
     #[should_panic(expected = "Name passed into get_variable is not null-terminated.")]
     fn test_get_variable_non_terminated() {
@@ -856,6 +923,47 @@ pub(crate) mod test {
         assert_eq!(variable_info.maximum_variable_size, DUMMY_MAXIMUM_VARIABLE_SIZE);
     }
 
+    extern "efiapi" fn mock_efi_get_time(
+        time: *mut efi::Time,
+        capabilities: *mut efi::TimeCapabilities,
+    ) -> efi::Status {
+        unsafe {
+            (*time).year = 2025;
+            (*time).month = 6;
+            (*time).day = 30;
+            (*capabilities).resolution = 1;
+        }
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn mock_efi_get_time_unsupported(_: *mut efi::Time, _: *mut efi::TimeCapabilities) -> efi::Status {
+        efi::Status::UNSUPPORTED
+    }
+
+    #[test]
+    fn test_get_time() {
+        let rs = runtime_services!(get_time = mock_efi_get_time);
+        let (time, capabilities) = rs.get_time().unwrap();
+        assert_eq!((time.year, time.month, time.day), (2025, 6, 30));
+        assert_eq!(capabilities.resolution, 1);
+
+        let rs = runtime_services!(get_time = mock_efi_get_time_unsupported);
+        assert_eq!(rs.get_time().unwrap_err(), efi::Status::UNSUPPORTED);
+    }
+
+    extern "efiapi" fn mock_efi_convert_pointer(_debug_disposition: usize, address: *mut *mut c_void) -> efi::Status {
+        unsafe { *address = (*address as usize | 0xFFFF_0000_0000_0000) as *mut c_void };
+        efi::Status::SUCCESS
+    }
+
+    #[test]
+    fn test_convert_pointer() {
+        let rs = runtime_services!(convert_pointer = mock_efi_convert_pointer);
+        let mut address = 0x1000 as *mut c_void;
+        unsafe { rs.convert_pointer(&mut address) }.unwrap();
+        assert_eq!(address as usize, 0xFFFF_0000_0000_1000);
+    }
+
     #[test]
     fn test_query_variable_info_invalid_attributes() {
         let rs = runtime_services!(query_variable_info = mock_efi_query_variable_info);
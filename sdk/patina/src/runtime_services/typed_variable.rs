@@ -0,0 +1,407 @@
+use core::{
+    mem,
+    ops::{BitOr, BitOrAssign},
+};
+
+use alloc::vec::Vec;
+use r_efi::efi;
+use zerocopy::{FromBytes, Immutable, IntoBytes};
+
+#[cfg(any(test, feature = "mockall"))]
+use mockall::automock;
+
+use super::RuntimeServices;
+use crate::base::ucs2::{Ucs2Str, Ucs2String};
+
+/// The attributes of a UEFI variable.
+///
+/// Attributes are combined with `|`, e.g. `VariableAttributes::NON_VOLATILE | VariableAttributes::BOOTSERVICE_ACCESS`.
+///
+/// UEFI Spec Documentation: [8.2.1. EFI_RUNTIME_SERVICES.GetVariable()](https://uefi.org/specs/UEFI/2.10/08_Services_Runtime_Services.html#getvariable)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct VariableAttributes(u32);
+
+impl VariableAttributes {
+    /// No attributes. Setting a variable with no attributes deletes it.
+    pub const NONE: VariableAttributes = VariableAttributes(0);
+    /// The variable persists across resets.
+    pub const NON_VOLATILE: VariableAttributes = VariableAttributes(efi::VARIABLE_NON_VOLATILE);
+    /// The variable is accessible during boot services.
+    pub const BOOTSERVICE_ACCESS: VariableAttributes = VariableAttributes(efi::VARIABLE_BOOTSERVICE_ACCESS);
+    /// The variable is accessible after exit boot services. It must also have [Self::BOOTSERVICE_ACCESS].
+    pub const RUNTIME_ACCESS: VariableAttributes = VariableAttributes(efi::VARIABLE_RUNTIME_ACCESS);
+    /// The variable is a hardware error record.
+    pub const HARDWARE_ERROR_RECORD: VariableAttributes = VariableAttributes(efi::VARIABLE_HARDWARE_ERROR_RECORD);
+    /// The variable is written with a time based authentication descriptor.
+    pub const TIME_BASED_AUTHENTICATED_WRITE_ACCESS: VariableAttributes =
+        VariableAttributes(efi::VARIABLE_TIME_BASED_AUTHENTICATED_WRITE_ACCESS);
+    /// The data is appended to the variable instead of replacing it. Only valid when setting a variable.
+    pub const APPEND_WRITE: VariableAttributes = VariableAttributes(efi::VARIABLE_APPEND_WRITE);
+
+    /// Attributes from their UEFI representation.
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    /// Returns the UEFI representation of the attributes.
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// Returns whether all the attributes of `other` are set.
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for VariableAttributes {
+    type Output = VariableAttributes;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        VariableAttributes(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for VariableAttributes {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl From<u32> for VariableAttributes {
+    fn from(bits: u32) -> Self {
+        Self(bits)
+    }
+}
+
+impl From<VariableAttributes> for u32 {
+    fn from(attributes: VariableAttributes) -> Self {
+        attributes.0
+    }
+}
+
+/// Typed access to UEFI variables.
+///
+/// Values are plain old data types, read and written with their in-memory representation with [zerocopy], and
+/// strings are NUL terminated UCS-2 strings. The trait is implemented for all the [RuntimeServices].
+///
+/// ```ignore
+/// #[derive(FromBytes, IntoBytes, Immutable)]
+/// #[repr(C)]
+/// struct Settings {
+///     timeout: u16,
+///     flags: u16,
+/// }
+///
+/// let attributes = VariableAttributes::NON_VOLATILE | VariableAttributes::BOOTSERVICE_ACCESS;
+/// runtime_services.set_variable_value(ucs2!("Settings"), &SETTINGS_GUID, attributes, &settings)?;
+/// let (settings, _) = runtime_services.get_variable_value::<Settings>(ucs2!("Settings"), &SETTINGS_GUID)?;
+/// let (boot_order, _) = runtime_services.get_variable_slice::<u16>(ucs2!("BootOrder"), &efi::GLOBAL_VARIABLE)?;
+/// ```
+#[cfg_attr(any(test, feature = "mockall"), automock)]
+pub trait TypedVariableServices {
+    /// Gets a variable holding a value of type `T`.
+    ///
+    /// Fails with `BAD_BUFFER_SIZE` if the size of the variable is not the size of `T`.
+    fn get_variable_value<T>(
+        &self,
+        name: &Ucs2Str,
+        namespace: &efi::Guid,
+    ) -> Result<(T, VariableAttributes), efi::Status>
+    where
+        T: FromBytes + 'static;
+
+    /// Sets a variable to a value of type `T`.
+    fn set_variable_value<T>(
+        &self,
+        name: &Ucs2Str,
+        namespace: &efi::Guid,
+        attributes: VariableAttributes,
+        value: &T,
+    ) -> Result<(), efi::Status>
+    where
+        T: IntoBytes + Immutable + 'static;
+
+    /// Gets a variable holding an array of values of type `T`, e.g. `BootOrder`.
+    ///
+    /// Fails with `BAD_BUFFER_SIZE` if the size of the variable is not a multiple of the size of `T`.
+    fn get_variable_slice<T>(
+        &self,
+        name: &Ucs2Str,
+        namespace: &efi::Guid,
+    ) -> Result<(Vec<T>, VariableAttributes), efi::Status>
+    where
+        T: FromBytes + 'static;
+
+    /// Sets a variable to an array of values of type `T`.
+    fn set_variable_slice<T>(
+        &self,
+        name: &Ucs2Str,
+        namespace: &efi::Guid,
+        attributes: VariableAttributes,
+        values: &[T],
+    ) -> Result<(), efi::Status>
+    where
+        T: IntoBytes + Immutable + 'static;
+
+    /// Gets a variable holding a UCS-2 string.
+    ///
+    /// The NUL terminator is optional in the variable. Fails with `BAD_BUFFER_SIZE` if the size of the variable is
+    /// odd, and `INVALID_PARAMETER` if it is not a valid UCS-2 string.
+    fn get_string_variable(
+        &self,
+        name: &Ucs2Str,
+        namespace: &efi::Guid,
+    ) -> Result<(Ucs2String, VariableAttributes), efi::Status>;
+
+    /// Sets a variable to a UCS-2 string, with its NUL terminator.
+    fn set_string_variable(
+        &self,
+        name: &Ucs2Str,
+        namespace: &efi::Guid,
+        attributes: VariableAttributes,
+        value: &Ucs2Str,
+    ) -> Result<(), efi::Status>;
+
+    /// Deletes a variable.
+    fn delete_variable(&self, name: &Ucs2Str, namespace: &efi::Guid) -> Result<(), efi::Status>;
+}
+
+impl<R: RuntimeServices> TypedVariableServices for R {
+    fn get_variable_value<T>(
+        &self,
+        name: &Ucs2Str,
+        namespace: &efi::Guid,
+    ) -> Result<(T, VariableAttributes), efi::Status>
+    where
+        T: FromBytes + 'static,
+    {
+        let (data, attributes) =
+            self.get_variable::<Vec<u8>>(name.as_slice_with_nul(), namespace, Some(mem::size_of::<T>()))?;
+        let value = T::read_from_bytes(&data).map_err(|_| efi::Status::BAD_BUFFER_SIZE)?;
+        Ok((value, attributes.into()))
+    }
+
+    fn set_variable_value<T>(
+        &self,
+        name: &Ucs2Str,
+        namespace: &efi::Guid,
+        attributes: VariableAttributes,
+        value: &T,
+    ) -> Result<(), efi::Status>
+    where
+        T: IntoBytes + Immutable + 'static,
+    {
+        self.set_variable(name.as_slice_with_nul(), namespace, attributes.bits(), &value.as_bytes().to_vec())
+    }
+
+    fn get_variable_slice<T>(
+        &self,
+        name: &Ucs2Str,
+        namespace: &efi::Guid,
+    ) -> Result<(Vec<T>, VariableAttributes), efi::Status>
+    where
+        T: FromBytes + 'static,
+    {
+        let (data, attributes) = self.get_variable::<Vec<u8>>(name.as_slice_with_nul(), namespace, None)?;
+        let size = mem::size_of::<T>();
+        if size == 0 || data.len() % size != 0 {
+            return Err(efi::Status::BAD_BUFFER_SIZE);
+        }
+        let values = data
+            .chunks_exact(size)
+            .map(|chunk| T::read_from_bytes(chunk).map_err(|_| efi::Status::BAD_BUFFER_SIZE))
+            .collect::<Result<_, _>>()?;
+        Ok((values, attributes.into()))
+    }
+
+    fn set_variable_slice<T>(
+        &self,
+        name: &Ucs2Str,
+        namespace: &efi::Guid,
+        attributes: VariableAttributes,
+        values: &[T],
+    ) -> Result<(), efi::Status>
+    where
+        T: IntoBytes + Immutable + 'static,
+    {
+        self.set_variable(name.as_slice_with_nul(), namespace, attributes.bits(), &values.as_bytes().to_vec())
+    }
+
+    fn get_string_variable(
+        &self,
+        name: &Ucs2Str,
+        namespace: &efi::Guid,
+    ) -> Result<(Ucs2String, VariableAttributes), efi::Status> {
+        let (mut characters, attributes) = self.get_variable_slice::<u16>(name, namespace)?;
+        if characters.last() != Some(&0) {
+            characters.push(0);
+        }
+        let value = Ucs2String::from_vec_with_nul(characters).map_err(|_| efi::Status::INVALID_PARAMETER)?;
+        Ok((value, attributes))
+    }
+
+    fn set_string_variable(
+        &self,
+        name: &Ucs2Str,
+        namespace: &efi::Guid,
+        attributes: VariableAttributes,
+        value: &Ucs2Str,
+    ) -> Result<(), efi::Status> {
+        self.set_variable_slice(name, namespace, attributes, value.as_slice_with_nul())
+    }
+
+    fn delete_variable(&self, name: &Ucs2Str, namespace: &efi::Guid) -> Result<(), efi::Status> {
+        self.set_variable(name.as_slice_with_nul(), namespace, VariableAttributes::NONE.bits(), &Vec::<u8>::new())
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod test {
+    use super::*;
+    use crate::{runtime_services::MockRuntimeServices, ucs2};
+    use std::vec;
+    use zerocopy_derive::{FromBytes, Immutable, IntoBytes};
+
+    const NAMESPACE: efi::Guid =
+        efi::Guid::from_fields(0x1234_5678, 0x9abc, 0xdef0, 0x12, 0x34, &[0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0]);
+
+    #[derive(Debug, PartialEq, FromBytes, IntoBytes, Immutable)]
+    #[repr(C)]
+    struct Settings {
+        timeout: u16,
+        flags: u16,
+    }
+
+    fn mock_variable(data: Vec<u8>, attributes: u32) -> MockRuntimeServices {
+        let mut runtime_services = MockRuntimeServices::new();
+        runtime_services.expect_get_variable::<Vec<u8>>().once().returning(move |name, namespace, _| {
+            assert_eq!(name, ucs2!("Test").as_slice_with_nul());
+            assert_eq!(namespace, &NAMESPACE);
+            Ok((data.clone(), attributes))
+        });
+        runtime_services
+    }
+
+    #[test]
+    fn test_attributes_combine() {
+        let attributes = VariableAttributes::NON_VOLATILE | VariableAttributes::BOOTSERVICE_ACCESS;
+        assert_eq!(attributes.bits(), 0x3);
+        assert!(attributes.contains(VariableAttributes::NON_VOLATILE));
+        assert!(!attributes.contains(VariableAttributes::RUNTIME_ACCESS));
+
+        let mut attributes = attributes;
+        attributes |= VariableAttributes::RUNTIME_ACCESS;
+        assert_eq!(u32::from(attributes), 0x7);
+        assert_eq!(VariableAttributes::from(0x7), attributes);
+        assert_eq!(VariableAttributes::default(), VariableAttributes::NONE);
+    }
+
+    #[test]
+    fn test_get_variable_value() {
+        let runtime_services = mock_variable(vec![5, 0, 1, 0], 0x7);
+        let (settings, attributes) =
+            runtime_services.get_variable_value::<Settings>(ucs2!("Test"), &NAMESPACE).unwrap();
+        assert_eq!(settings, Settings { timeout: 5, flags: 1 });
+        assert_eq!(attributes, VariableAttributes::from_bits(0x7));
+
+        let runtime_services = mock_variable(vec![5, 0, 1], 0x7);
+        assert_eq!(
+            runtime_services.get_variable_value::<Settings>(ucs2!("Test"), &NAMESPACE).err(),
+            Some(efi::Status::BAD_BUFFER_SIZE)
+        );
+
+        let mut runtime_services = MockRuntimeServices::new();
+        runtime_services.expect_get_variable::<Vec<u8>>().once().returning(|_, _, size_hint| {
+            assert_eq!(size_hint, Some(4));
+            Err(efi::Status::NOT_FOUND)
+        });
+        assert_eq!(
+            runtime_services.get_variable_value::<u32>(ucs2!("Test"), &NAMESPACE).err(),
+            Some(efi::Status::NOT_FOUND)
+        );
+    }
+
+    #[test]
+    fn test_set_variable_value() {
+        let mut runtime_services = MockRuntimeServices::new();
+        runtime_services.expect_set_variable::<Vec<u8>>().once().returning(|name, namespace, attributes, data| {
+            assert_eq!(name, ucs2!("Test").as_slice_with_nul());
+            assert_eq!(namespace, &NAMESPACE);
+            assert_eq!(attributes, 0x3);
+            assert_eq!(data, &vec![5, 0, 1, 0]);
+            Ok(())
+        });
+        let attributes = VariableAttributes::NON_VOLATILE | VariableAttributes::BOOTSERVICE_ACCESS;
+        runtime_services
+            .set_variable_value(ucs2!("Test"), &NAMESPACE, attributes, &Settings { timeout: 5, flags: 1 })
+            .unwrap();
+    }
+
+    #[test]
+    fn test_get_and_set_variable_slice() {
+        let runtime_services = mock_variable(vec![1, 0, 3, 0, 2, 0], 0x7);
+        let (boot_order, _) = runtime_services.get_variable_slice::<u16>(ucs2!("Test"), &NAMESPACE).unwrap();
+        assert_eq!(boot_order, vec![1, 3, 2]);
+
+        let runtime_services = mock_variable(vec![1, 0, 3], 0x7);
+        assert_eq!(
+            runtime_services.get_variable_slice::<u16>(ucs2!("Test"), &NAMESPACE).err(),
+            Some(efi::Status::BAD_BUFFER_SIZE)
+        );
+
+        let mut runtime_services = MockRuntimeServices::new();
+        runtime_services.expect_set_variable::<Vec<u8>>().once().returning(|_, _, _, data| {
+            assert_eq!(data, &vec![1, 0, 3, 0]);
+            Ok(())
+        });
+        runtime_services.set_variable_slice(ucs2!("Test"), &NAMESPACE, VariableAttributes::NONE, &[1_u16, 3]).unwrap();
+    }
+
+    #[test]
+    fn test_string_variables() {
+        // The NUL terminator is optional.
+        for data in [vec![b'H', 0, b'i', 0, 0, 0], vec![b'H', 0, b'i', 0]] {
+            let runtime_services = mock_variable(data, 0x3);
+            let (value, _) = runtime_services.get_string_variable(ucs2!("Test"), &NAMESPACE).unwrap();
+            assert_eq!(value, "Hi");
+        }
+
+        // Interior NUL characters are invalid.
+        let runtime_services = mock_variable(vec![b'H', 0, 0, 0, b'i', 0, 0, 0], 0x3);
+        assert_eq!(
+            runtime_services.get_string_variable(ucs2!("Test"), &NAMESPACE).err(),
+            Some(efi::Status::INVALID_PARAMETER)
+        );
+
+        let mut runtime_services = MockRuntimeServices::new();
+        runtime_services.expect_set_variable::<Vec<u8>>().once().returning(|_, _, attributes, data| {
+            assert_eq!(attributes, 0x3);
+            assert_eq!(data, &vec![b'H', 0, b'i', 0, 0, 0]);
+            Ok(())
+        });
+        let attributes = VariableAttributes::NON_VOLATILE | VariableAttributes::BOOTSERVICE_ACCESS;
+        runtime_services.set_string_variable(ucs2!("Test"), &NAMESPACE, attributes, ucs2!("Hi")).unwrap();
+    }
+
+    #[test]
+    fn test_delete_variable() {
+        let mut runtime_services = MockRuntimeServices::new();
+        runtime_services.expect_set_variable::<Vec<u8>>().once().returning(|name, _, attributes, data| {
+            assert_eq!(name, ucs2!("Test").as_slice_with_nul());
+            assert_eq!(attributes, 0);
+            assert!(data.is_empty());
+            Err(efi::Status::NOT_FOUND)
+        });
+        assert_eq!(runtime_services.delete_variable(ucs2!("Test"), &NAMESPACE), Err(efi::Status::NOT_FOUND));
+    }
+
+    #[test]
+    fn test_typed_variable_services_can_be_mocked() {
+        let mut variables = MockTypedVariableServices::new();
+        variables.expect_get_variable_value::<u32>().once().returning(|_, _| Ok((7, VariableAttributes::NONE)));
+        assert_eq!(variables.get_variable_value::<u32>(ucs2!("Test"), &NAMESPACE), Ok((7, VariableAttributes::NONE)));
+    }
+}
@@ -5,6 +5,7 @@ use fallible_streaming_iterator::FallibleStreamingIterator;
 use r_efi::efi::{self, Guid};
 
 use super::RuntimeServices;
+use crate::base::ucs2::{Ucs2Error, Ucs2Str};
 
 /// Status information returned by [`RuntimeServices::get_variable_unchecked`]
 #[derive(Debug)]
@@ -47,6 +48,20 @@ pub struct VariableIdentifier {
     namespace: efi::Guid,
 }
 
+impl VariableIdentifier {
+    /// Returns the name of the UEFI variable.
+    ///
+    /// Fails if the name returned by firmware is not a valid UCS-2 string.
+    pub fn name(&self) -> Result<&Ucs2Str, Ucs2Error> {
+        Ucs2Str::from_slice_until_nul(&self.name)
+    }
+
+    /// Returns the namespace of the UEFI variable.
+    pub fn namespace(&self) -> &efi::Guid {
+        &self.namespace
+    }
+}
+
 /// Provides a [`FallibleStreamingIterator`] over UEFI variable names
 ///
 /// Produces an EFI status on error.
@@ -59,20 +74,20 @@ pub struct VariableIdentifier {
 ///     StandardRuntimeServices::new(&(*runtime_services_ptr));
 /// let mut iter = VariableNameIterator::new_from_first(runtime_services);
 /// while let Some(variable_identifier) = iter.next()? {
-///     some_function(variable_identifier.name, variable_identifier.namespace);
+///     some_function(variable_identifier.name()?, variable_identifier.namespace());
 /// }
 /// ```
 ///
 /// ## Iterating through UEFI variable names, starting with a known one
 /// ```ignore
 /// let mut iter = VariableNameIterator::new_from_variable(
-///     &SOME_VARIABLE_NAME,
+///     ucs2!("SomeVariable").as_slice_with_nul(),
 ///     &SOME_VARIABLE_NAMESPACE,
 ///     runtime_services
 /// );
 ///
 /// while let Some(variable_identifier) = iter.next()? {
-///     some_function(variable_identifier.name, variable_identifier.namespace);
+///     some_function(variable_identifier.name()?, variable_identifier.namespace());
 /// }
 /// ```
 #[derive(Debug)]
@@ -206,6 +221,8 @@ mod test {
         let variable_identifier = status.unwrap().unwrap();
         assert_eq!(variable_identifier.name, DUMMY_SECOND_NAME);
         assert_eq!(variable_identifier.namespace, DUMMY_SECOND_NAMESPACE);
+        assert_eq!(variable_identifier.name().unwrap().as_slice_with_nul(), DUMMY_SECOND_NAME);
+        assert_eq!(variable_identifier.namespace(), &DUMMY_SECOND_NAMESPACE);
 
         // Make sure the second result indicates we've reached the end
         status = iter.next();
@@ -10,6 +10,24 @@
 //! Additionally, this module provides a set of macros for writing test cases that are similar to the ones provided by
 //! the `core` crate, but return an error message instead of panicking.
 //!
+//! ## Test Execution and Results
+//!
+//! When dispatched, the [TestRunner] defers running the tests until the DXE core has finished dispatching all
+//! components and drivers (see [Storage::add_dispatch_complete_callback]), so tests observe the fully dispatched
+//! system. Each result is logged on its own line, followed by a summary line, in a format that can be parsed from the
+//! serial output:
+//!
+//! ```text
+//! my_crate::tests::test_case ... ok
+//! my_crate::tests::failing_test_case ... fail: assertion failed: `1 == 2`
+//! my_crate::tests::skipped_test_case ... skipped
+//! test result: FAILED. 1 passed; 1 failed; 1 skipped
+//! ```
+//!
+//! The summary is also reported as a status code through the Status Code Runtime Protocol, if it is installed. A
+//! progress code is reported if all tests passed and an error code otherwise, with a [TestSummary] attached as the
+//! status code data (identified by [PATINA_TEST_RESULT](crate::guids::PATINA_TEST_RESULT)).
+//!
 //! ## Feature Flags
 //!
 //! - `patina-tests`: Will opt-in to compile any tests.
@@ -75,8 +93,18 @@
 extern crate alloc;
 use alloc::vec::Vec;
 
+use patina_pi::status_code::{
+    EFI_ERROR_CODE, EFI_ERROR_MAJOR, EFI_PROGRESS_CODE, EFI_SOFTWARE_DXE_BS_DRIVER, EFI_SW_EC_ABORTED,
+    EFI_SW_PC_INIT_END,
+};
+
 use crate as patina;
-use crate::component::{IntoComponent, Storage};
+use crate::{
+    boot_services::{BootServices, StandardBootServices},
+    component::{IntoComponent, Storage},
+    guids,
+    uefi_protocol::status_code::StatusCodeRuntimeProtocol,
+};
 
 #[doc(hidden)]
 pub use linkme;
@@ -181,7 +209,8 @@ macro_rules! u_assert_ne {
     };
 }
 
-/// A component that runs all test cases marked with the `#[patina_test]` attribute when loaded by the DXE core.
+/// A component that runs all test cases marked with the `#[patina_test]` attribute once the DXE core has finished
+/// dispatching.
 #[derive(IntoComponent, Default, Clone)]
 pub struct TestRunner {
     filters: Vec<&'static str>,
@@ -218,8 +247,20 @@ impl TestRunner {
     }
 
     /// The entry point for the test runner component.
+    ///
+    /// Registers the test run to execute once the DXE core has finished dispatching. The test run fails if any test
+    /// failed (see [TestSummary::result]).
     fn entry_point(self, storage: &mut Storage) -> patina::error::Result<()> {
-        let test_list: &[__private_api::TestCase] = __private_api::test_cases();
+        storage.add_dispatch_complete_callback(move |storage| {
+            let summary = self.run_tests(__private_api::test_cases(), storage);
+            report_summary(storage.boot_services(), summary);
+            summary.result()
+        });
+        Ok(())
+    }
+
+    /// Runs the provided test cases, logging the result of each test case and a summary of the run.
+    fn run_tests(&self, test_list: &[__private_api::TestCase], storage: &mut Storage) -> TestSummary {
         let count = test_list.len();
         match count {
             0 => log::warn!("No Tests Found"),
@@ -227,35 +268,95 @@ impl TestRunner {
             _ => log::info!("running {count} tests"),
         }
 
-        let mut did_error = false;
+        let mut summary = TestSummary::default();
         for test in test_list {
             if !test.should_run(&self.filters) {
                 log::info!("{} ... skipped", test.name);
+                summary.skipped += 1;
                 continue;
             }
 
             match test.run(storage, self.debug_mode) {
-                Ok(_) => log::info!("{} ... ok", test.name),
+                Ok(_) => {
+                    log::info!("{} ... ok", test.name);
+                    summary.passed += 1;
+                }
                 Err(e) => {
                     log::error!("{} ... fail: {}", test.name, e);
-                    did_error = true;
+                    summary.failed += 1;
                     if self.fail_fast {
-                        return Err(patina::error::EfiError::Aborted);
+                        break;
                     }
                 }
             }
         }
 
-        match did_error {
-            true => Err(patina::error::EfiError::Aborted),
-            false => Ok(()),
+        let result = if summary.failed == 0 { "ok" } else { "FAILED" };
+        log::info!(
+            "test result: {result}. {} passed; {} failed; {} skipped",
+            summary.passed,
+            summary.failed,
+            summary.skipped
+        );
+        summary
+    }
+}
+
+/// The summary of a test run, attached as data to the status code reported once all tests have run.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TestSummary {
+    /// The number of tests that passed.
+    pub passed: u32,
+    /// The number of tests that failed.
+    pub failed: u32,
+    /// The number of tests that were skipped, either explicitly or by a filter.
+    pub skipped: u32,
+}
+
+impl TestSummary {
+    /// Returns [EfiError::Aborted](patina::error::EfiError::Aborted) if any test failed.
+    pub fn result(&self) -> patina::error::Result<()> {
+        match self.failed {
+            0 => Ok(()),
+            _ => Err(patina::error::EfiError::Aborted),
         }
     }
 }
 
+/// Reports the summary of a test run through the Status Code Runtime Protocol, if available.
+fn report_summary(boot_services: &StandardBootServices, summary: TestSummary) {
+    if !boot_services.is_init() {
+        return;
+    }
+
+    // SAFETY: The Status Code Runtime Protocol interface matches [StatusCodeRuntimeProtocol].
+    let Ok(status_code) = (unsafe { boot_services.locate_protocol::<StatusCodeRuntimeProtocol>(None) }) else {
+        log::warn!("Status Code Runtime Protocol not found, test results not reported as a status code.");
+        return;
+    };
+
+    let (status_code_type, status_code_value) = match summary.failed {
+        0 => (EFI_PROGRESS_CODE, EFI_SOFTWARE_DXE_BS_DRIVER | EFI_SW_PC_INIT_END),
+        _ => (EFI_ERROR_CODE | EFI_ERROR_MAJOR, EFI_SOFTWARE_DXE_BS_DRIVER | EFI_SW_EC_ABORTED),
+    };
+
+    if let Err(status) = status_code.report_status_code_with_data(
+        status_code_type,
+        status_code_value,
+        0,
+        &guids::DXE_CORE,
+        guids::PATINA_TEST_RESULT,
+        summary,
+    ) {
+        log::error!("Failed to report test results as a status code: {status:?}");
+    }
+}
+
 #[cfg(test)]
 #[coverage(off)]
 mod tests {
+    use super::{__private_api::TestCase, TestSummary};
     use crate::component::{IntoComponent, Storage, params::Config};
 
     // A test function where we mock DxeComponentInterface to return what we want for the test.
@@ -322,5 +423,34 @@ mod tests {
         let mut component = super::TestRunner::default().fail_fast(true).into_component();
         component.initialize(&mut storage);
         let _ = component.run(&mut storage);
+        let _ = storage.dispatch_complete();
+    }
+
+    fn test_case(name: &'static str, skip: bool, func: fn(&mut Storage) -> Result<bool, &'static str>) -> TestCase {
+        TestCase { name, skip, should_fail: false, fail_msg: None, func }
+    }
+
+    #[test]
+    fn test_run_tests_summary() {
+        let mut storage = Storage::new();
+        let tests = [
+            test_case("crate::pass", false, |_| Ok(true)),
+            test_case("crate::fail", false, |_| Err("failed")),
+            test_case("crate::skip", true, |_| Ok(true)),
+            test_case("crate::other::pass", false, |_| Ok(true)),
+        ];
+
+        let summary = super::TestRunner::default().run_tests(&tests, &mut storage);
+        assert_eq!(summary, TestSummary { passed: 2, failed: 1, skipped: 1 });
+        assert_eq!(summary.result(), Err(crate::error::EfiError::Aborted));
+
+        // Filtered out tests are counted as skipped.
+        let summary = super::TestRunner::default().with_filter("other").run_tests(&tests, &mut storage);
+        assert_eq!(summary, TestSummary { passed: 1, failed: 0, skipped: 3 });
+        assert_eq!(summary.result(), Ok(()));
+
+        // No further tests are run after the first failure.
+        let summary = super::TestRunner::default().fail_fast(true).run_tests(&tests, &mut storage);
+        assert_eq!(summary, TestSummary { passed: 1, failed: 1, skipped: 0 });
     }
 }
@@ -10,8 +10,18 @@
 #[cfg(feature = "unstable-device-path")]
 pub mod device_path;
 
+pub mod acpi_table;
 pub mod decompress;
+pub mod driver_binding;
+pub mod driver_health;
+pub mod firmware_management;
+pub mod interrupt_statistics;
+pub mod loaded_image;
+pub mod loaded_image_info;
 pub mod performance_measurement;
+pub mod raw_device_path;
+pub mod reset_notification;
+pub mod simple_text_output;
 pub mod status_code;
 
 extern crate alloc;
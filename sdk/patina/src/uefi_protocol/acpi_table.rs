@@ -0,0 +1,120 @@
+//! ACPI Table Protocol
+//!
+//! Produced by the driver that manages the ACPI tables of the platform, to install and uninstall ACPI tables. The
+//! driver updates the RSDT/XSDT and the checksums, and publishes the tables to the operating system.
+//!
+//! See <https://uefi.org/specs/UEFI/2.10/20_Protocols_ACPI_Protocols.html#efi-acpi-table-protocol>
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use core::ffi::c_void;
+
+use r_efi::efi;
+
+use super::ProtocolInterface;
+
+/// ACPI Table Protocol GUID.
+pub const PROTOCOL_GUID: efi::Guid = crate::guid!("FFE06BDD-6107-46A6-7BB2-5A9C7EC5275C");
+
+/// Function definition for installing an ACPI table.
+pub type InstallAcpiTable = extern "efiapi" fn(
+    this: *mut Protocol,
+    acpi_table_buffer: *const c_void,
+    acpi_table_buffer_size: usize,
+    table_key: *mut usize,
+) -> efi::Status;
+
+/// Function definition for uninstalling an ACPI table.
+pub type UninstallAcpiTable = extern "efiapi" fn(this: *mut Protocol, table_key: usize) -> efi::Status;
+
+/// C struct for the UEFI ACPI Table Protocol.
+#[repr(C)]
+pub struct Protocol {
+    /// Installs an ACPI table.
+    pub install_acpi_table: InstallAcpiTable,
+    /// Uninstalls an ACPI table previously installed.
+    pub uninstall_acpi_table: UninstallAcpiTable,
+}
+
+/// Safe wrapper around the UEFI ACPI Table Protocol.
+///
+/// Instances are obtained from firmware, e.g. with [BootServices::locate_protocol](crate::boot_services::BootServices::locate_protocol).
+#[repr(transparent)]
+pub struct AcpiTableProtocol {
+    protocol: Protocol,
+}
+
+unsafe impl ProtocolInterface for AcpiTableProtocol {
+    const PROTOCOL_GUID: efi::Guid = PROTOCOL_GUID;
+}
+
+impl AcpiTableProtocol {
+    /// Creates a new instance of the ACPI Table Protocol with the given implementation.
+    pub const fn new(install_acpi_table: InstallAcpiTable, uninstall_acpi_table: UninstallAcpiTable) -> Self {
+        Self { protocol: Protocol { install_acpi_table, uninstall_acpi_table } }
+    }
+
+    /// Installs `table`, which starts with an ACPI description header. The table is copied, and its checksum is
+    /// updated by the driver.
+    ///
+    /// Returns the key identifying the installed table, used to uninstall it.
+    pub fn install_acpi_table(&self, table: &[u8]) -> Result<usize, efi::Status> {
+        let mut table_key = 0;
+        let status = (self.protocol.install_acpi_table)(
+            &self.protocol as *const Protocol as *mut Protocol,
+            table.as_ptr() as *const c_void,
+            table.len(),
+            &mut table_key,
+        );
+        if status.is_error() { Err(status) } else { Ok(table_key) }
+    }
+
+    /// Uninstalls the table identified by `table_key`.
+    pub fn uninstall_acpi_table(&self, table_key: usize) -> Result<(), efi::Status> {
+        let status =
+            (self.protocol.uninstall_acpi_table)(&self.protocol as *const Protocol as *mut Protocol, table_key);
+        if status.is_error() { Err(status) } else { Ok(()) }
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+
+    const TABLE_KEY: usize = 0x42;
+
+    extern "efiapi" fn install_acpi_table(
+        _this: *mut Protocol,
+        acpi_table_buffer: *const c_void,
+        acpi_table_buffer_size: usize,
+        table_key: *mut usize,
+    ) -> efi::Status {
+        // Tables shorter than an ACPI description header are rejected.
+        if acpi_table_buffer.is_null() || acpi_table_buffer_size < 36 {
+            return efi::Status::INVALID_PARAMETER;
+        }
+        unsafe { table_key.write(TABLE_KEY) };
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn uninstall_acpi_table(_this: *mut Protocol, table_key: usize) -> efi::Status {
+        if table_key == TABLE_KEY { efi::Status::SUCCESS } else { efi::Status::NOT_FOUND }
+    }
+
+    #[test]
+    fn test_install_and_uninstall_acpi_table() {
+        let protocol = AcpiTableProtocol::new(install_acpi_table, uninstall_acpi_table);
+
+        assert_eq!(protocol.install_acpi_table(&[0; 56]), Ok(TABLE_KEY));
+        assert_eq!(protocol.install_acpi_table(&[0; 4]), Err(efi::Status::INVALID_PARAMETER));
+
+        assert_eq!(protocol.uninstall_acpi_table(TABLE_KEY), Ok(()));
+        assert_eq!(protocol.uninstall_acpi_table(0), Err(efi::Status::NOT_FOUND));
+    }
+}
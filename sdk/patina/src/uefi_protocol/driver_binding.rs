@@ -0,0 +1,60 @@
+//! Driver Binding Protocol
+//!
+//! Provides the services required to determine if a driver supports a given controller, and to start and stop the
+//! driver on a controller.
+//!
+//! See <https://uefi.org/specs/UEFI/2.10/11_Protocols_UEFI_Driver_Model.html#efi-driver-binding-protocol>
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use r_efi::efi::{self, protocols::driver_binding};
+
+use super::ProtocolInterface;
+
+/// Safe wrapper around the UEFI Driver Binding Protocol.
+///
+/// Instances are obtained from firmware, e.g. with
+/// [BootServices::open_protocol](crate::boot_services::BootServices::open_protocol), and are read-only. To produce a
+/// driver binding, implement [DriverBinding](crate::driver_binding::DriverBinding).
+#[repr(transparent)]
+pub struct DriverBindingProtocol {
+    protocol: driver_binding::Protocol,
+}
+
+unsafe impl ProtocolInterface for DriverBindingProtocol {
+    const PROTOCOL_GUID: efi::Guid = driver_binding::PROTOCOL_GUID;
+}
+
+impl DriverBindingProtocol {
+    /// Returns a reference to the driver binding protocol at `ptr`, or `None` if `ptr` is null.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be null or point to a valid driver binding protocol instance that remains valid and unmodified for
+    /// the lifetime `'a`.
+    pub unsafe fn from_ptr<'a>(ptr: *const driver_binding::Protocol) -> Option<&'a Self> {
+        // SAFETY: DriverBindingProtocol is a transparent wrapper around driver_binding::Protocol; validity is
+        // guaranteed by the caller.
+        unsafe { (ptr as *const Self).as_ref() }
+    }
+
+    /// Returns the version of the driver, used to select between multiple drivers supporting the same controller.
+    pub fn version(&self) -> u32 {
+        self.protocol.version
+    }
+
+    /// Returns the handle of the image that produced this driver binding.
+    pub fn image_handle(&self) -> efi::Handle {
+        self.protocol.image_handle
+    }
+
+    /// Returns the handle on which this driver binding is installed.
+    pub fn driver_binding_handle(&self) -> efi::Handle {
+        self.protocol.driver_binding_handle
+    }
+}
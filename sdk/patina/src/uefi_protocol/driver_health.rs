@@ -0,0 +1,306 @@
+//! Driver Health Protocol
+//!
+//! Used by a driver to report its health status and the health status of the controllers it manages, and to repair
+//! a controller in an unhealthy state.
+//!
+//! See <https://uefi.org/specs/UEFI/2.10/11_Protocols_UEFI_Driver_Model.html#efi-driver-health-protocol>
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::ptr;
+
+use r_efi::efi;
+
+use super::ProtocolInterface;
+use crate::boot_services::BootServices;
+
+/// Driver Health Protocol GUID.
+pub const PROTOCOL_GUID: efi::Guid =
+    efi::Guid::from_fields(0x2a534210, 0x9280, 0x41d8, 0xae, 0x79, &[0xca, 0xda, 0x01, 0xa2, 0xb1, 0x27]);
+
+/// The health status of a driver or controller (`EFI_DRIVER_HEALTH_STATUS`).
+pub type EfiDriverHealthStatus = u32;
+
+/// The controller is healthy.
+pub const EFI_DRIVER_HEALTH_STATUS_HEALTHY: EfiDriverHealthStatus = 0;
+/// The controller requires a repair operation, performed with `repair`.
+pub const EFI_DRIVER_HEALTH_STATUS_REPAIR_REQUIRED: EfiDriverHealthStatus = 1;
+/// The controller requires configuration through the HII form returned with the health status.
+pub const EFI_DRIVER_HEALTH_STATUS_CONFIGURATION_REQUIRED: EfiDriverHealthStatus = 2;
+/// The controller is in a failed state that cannot be repaired.
+pub const EFI_DRIVER_HEALTH_STATUS_FAILED: EfiDriverHealthStatus = 3;
+/// The controller must be disconnected and reconnected to complete a repair or configuration.
+pub const EFI_DRIVER_HEALTH_STATUS_RECONNECT_REQUIRED: EfiDriverHealthStatus = 4;
+/// The system must be rebooted to complete a repair or configuration.
+pub const EFI_DRIVER_HEALTH_STATUS_REBOOT_REQUIRED: EfiDriverHealthStatus = 5;
+
+/// A message reported with a health status (`EFI_DRIVER_HEALTH_HII_MESSAGE`).
+///
+/// Message lists are terminated by an entry with a null `hii_handle`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct EfiDriverHealthHiiMessage {
+    /// The HII package list containing the message string.
+    pub hii_handle: efi::Handle,
+    /// The identifier of the message string in the HII package list.
+    pub string_id: u16,
+    /// A driver specific message code.
+    pub message_code: u64,
+}
+
+/// Function definition for reporting repair progress.
+pub type RepairNotify = extern "efiapi" fn(value: usize, limit: usize) -> efi::Status;
+
+/// Function definition for retrieving the health status of a driver, controller or child.
+pub type GetHealthStatus = extern "efiapi" fn(
+    this: *mut Protocol,
+    controller_handle: efi::Handle,
+    child_handle: efi::Handle,
+    health_status: *mut EfiDriverHealthStatus,
+    message_list: *mut *mut EfiDriverHealthHiiMessage,
+    form_hii_handle: *mut efi::Handle,
+) -> efi::Status;
+
+/// Function definition for repairing a controller or child.
+pub type Repair = extern "efiapi" fn(
+    this: *mut Protocol,
+    controller_handle: efi::Handle,
+    child_handle: efi::Handle,
+    repair_notify: Option<RepairNotify>,
+) -> efi::Status;
+
+/// C struct for the UEFI Driver Health Protocol.
+#[repr(C)]
+pub struct Protocol {
+    /// Retrieves the health status of a driver, controller or child.
+    pub get_health_status: GetHealthStatus,
+    /// Performs a repair operation on a controller or child.
+    pub repair: Repair,
+}
+
+/// The health status of a driver or controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HealthStatus {
+    /// The controller is healthy.
+    Healthy,
+    /// The controller requires a repair operation.
+    RepairRequired,
+    /// The controller requires configuration.
+    ConfigurationRequired,
+    /// The controller is in a failed state that cannot be repaired.
+    Failed,
+    /// The controller must be reconnected to complete a repair or configuration.
+    ReconnectRequired,
+    /// The system must be rebooted to complete a repair or configuration.
+    RebootRequired,
+}
+
+impl TryFrom<EfiDriverHealthStatus> for HealthStatus {
+    type Error = efi::Status;
+
+    fn try_from(value: EfiDriverHealthStatus) -> Result<Self, Self::Error> {
+        match value {
+            EFI_DRIVER_HEALTH_STATUS_HEALTHY => Ok(Self::Healthy),
+            EFI_DRIVER_HEALTH_STATUS_REPAIR_REQUIRED => Ok(Self::RepairRequired),
+            EFI_DRIVER_HEALTH_STATUS_CONFIGURATION_REQUIRED => Ok(Self::ConfigurationRequired),
+            EFI_DRIVER_HEALTH_STATUS_FAILED => Ok(Self::Failed),
+            EFI_DRIVER_HEALTH_STATUS_RECONNECT_REQUIRED => Ok(Self::ReconnectRequired),
+            EFI_DRIVER_HEALTH_STATUS_REBOOT_REQUIRED => Ok(Self::RebootRequired),
+            _ => Err(efi::Status::DEVICE_ERROR),
+        }
+    }
+}
+
+/// A message reported with a health status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HealthMessage {
+    /// The HII package list containing the message string.
+    pub hii_handle: efi::Handle,
+    /// The identifier of the message string in the HII package list.
+    pub string_id: u16,
+    /// A driver specific message code.
+    pub message_code: u64,
+}
+
+/// A health status along with the messages and configuration form reported with it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthReport {
+    /// The health status.
+    pub status: HealthStatus,
+    /// The messages reported with the health status.
+    pub messages: Vec<HealthMessage>,
+    /// The HII package list containing the configuration form, if configuration is required.
+    pub form_hii_handle: Option<efi::Handle>,
+}
+
+/// Safe wrapper around the UEFI Driver Health Protocol.
+///
+/// Instances are obtained from firmware, e.g. with [BootServices::handle_protocol].
+#[repr(transparent)]
+pub struct DriverHealthProtocol {
+    protocol: Protocol,
+}
+
+unsafe impl ProtocolInterface for DriverHealthProtocol {
+    const PROTOCOL_GUID: efi::Guid = PROTOCOL_GUID;
+}
+
+impl DriverHealthProtocol {
+    /// Creates a new instance of the Driver Health Protocol with the given implementation.
+    pub const fn new(get_health_status: GetHealthStatus, repair: Repair) -> Self {
+        Self { protocol: Protocol { get_health_status, repair } }
+    }
+
+    /// Retrieves the health status of the driver (no controller), a controller, or a child of a controller.
+    ///
+    /// The message list returned by the driver is copied into the report and freed with `boot_services`.
+    ///
+    /// ## Errors
+    ///
+    /// Returns [UNSUPPORTED](efi::Status::UNSUPPORTED) if the driver does not manage the controller or child, and
+    /// [DEVICE_ERROR](efi::Status::DEVICE_ERROR) if the driver reports an invalid health status.
+    pub fn get_health_status<B: BootServices>(
+        &self,
+        boot_services: &B,
+        controller: Option<efi::Handle>,
+        child: Option<efi::Handle>,
+    ) -> Result<HealthReport, efi::Status> {
+        let mut health_status = EFI_DRIVER_HEALTH_STATUS_HEALTHY;
+        let mut message_list = ptr::null_mut();
+        let mut form_hii_handle = ptr::null_mut();
+
+        let status = (self.protocol.get_health_status)(
+            &self.protocol as *const Protocol as *mut Protocol,
+            controller.unwrap_or(ptr::null_mut()),
+            child.unwrap_or(ptr::null_mut()),
+            &mut health_status,
+            &mut message_list,
+            &mut form_hii_handle,
+        );
+        if status.is_error() {
+            return Err(status);
+        }
+
+        let mut messages = Vec::new();
+        if !message_list.is_null() {
+            let mut message = message_list;
+            // SAFETY: The driver returns a message list terminated by an entry with a null HII handle.
+            while let Some(entry) = unsafe { message.as_ref() }.filter(|entry| !entry.hii_handle.is_null()) {
+                messages.push(HealthMessage {
+                    hii_handle: entry.hii_handle,
+                    string_id: entry.string_id,
+                    message_code: entry.message_code,
+                });
+                // SAFETY: The list continues until the terminating entry.
+                message = unsafe { message.add(1) };
+            }
+            // The message list is allocated by the driver and must be freed by the caller.
+            let _ = boot_services.free_pool(message_list as *mut u8);
+        }
+
+        Ok(HealthReport {
+            status: HealthStatus::try_from(health_status)?,
+            messages,
+            form_hii_handle: (!form_hii_handle.is_null()).then_some(form_hii_handle),
+        })
+    }
+
+    /// Performs the repair operation on a controller or a child of a controller.
+    pub fn repair(&self, controller: efi::Handle, child: Option<efi::Handle>) -> Result<(), efi::Status> {
+        let status = (self.protocol.repair)(
+            &self.protocol as *const Protocol as *mut Protocol,
+            controller,
+            child.unwrap_or(ptr::null_mut()),
+            None,
+        );
+        if status.is_error() { Err(status) } else { Ok(()) }
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use crate::boot_services::MockBootServices;
+
+    const CONTROLLER: efi::Handle = 0x1000 as efi::Handle;
+    const HII_HANDLE: efi::Handle = 0x2000 as efi::Handle;
+
+    static mut MESSAGES: [EfiDriverHealthHiiMessage; 2] = [
+        EfiDriverHealthHiiMessage { hii_handle: HII_HANDLE, string_id: 7, message_code: 0xDEAD },
+        EfiDriverHealthHiiMessage { hii_handle: ptr::null_mut(), string_id: 0, message_code: 0 },
+    ];
+
+    extern "efiapi" fn get_health_status(
+        _this: *mut Protocol,
+        controller_handle: efi::Handle,
+        _child_handle: efi::Handle,
+        health_status: *mut EfiDriverHealthStatus,
+        message_list: *mut *mut EfiDriverHealthHiiMessage,
+        form_hii_handle: *mut efi::Handle,
+    ) -> efi::Status {
+        unsafe {
+            match controller_handle {
+                h if h.is_null() => health_status.write(EFI_DRIVER_HEALTH_STATUS_HEALTHY),
+                CONTROLLER => {
+                    health_status.write(EFI_DRIVER_HEALTH_STATUS_CONFIGURATION_REQUIRED);
+                    message_list.write(ptr::addr_of_mut!(MESSAGES) as *mut EfiDriverHealthHiiMessage);
+                    form_hii_handle.write(HII_HANDLE);
+                }
+                _ => return efi::Status::UNSUPPORTED,
+            }
+        }
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn repair(
+        _this: *mut Protocol,
+        controller_handle: efi::Handle,
+        _child_handle: efi::Handle,
+        _repair_notify: Option<RepairNotify>,
+    ) -> efi::Status {
+        if controller_handle == CONTROLLER { efi::Status::SUCCESS } else { efi::Status::UNSUPPORTED }
+    }
+
+    #[test]
+    fn test_get_health_status() {
+        let protocol = DriverHealthProtocol::new(get_health_status, repair);
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_free_pool().once().returning(|_| Ok(()));
+
+        let report = protocol.get_health_status(&boot_services, None, None).unwrap();
+        assert_eq!(report, HealthReport { status: HealthStatus::Healthy, messages: Vec::new(), form_hii_handle: None });
+
+        let report = protocol.get_health_status(&boot_services, Some(CONTROLLER), None).unwrap();
+        assert_eq!(report.status, HealthStatus::ConfigurationRequired);
+        assert_eq!(report.messages, [HealthMessage { hii_handle: HII_HANDLE, string_id: 7, message_code: 0xDEAD }]);
+        assert_eq!(report.form_hii_handle, Some(HII_HANDLE));
+
+        assert_eq!(
+            protocol.get_health_status(&boot_services, Some(0x3000 as efi::Handle), None),
+            Err(efi::Status::UNSUPPORTED)
+        );
+    }
+
+    #[test]
+    fn test_repair() {
+        let protocol = DriverHealthProtocol::new(get_health_status, repair);
+        assert_eq!(protocol.repair(CONTROLLER, None), Ok(()));
+        assert_eq!(protocol.repair(0x3000 as efi::Handle, None), Err(efi::Status::UNSUPPORTED));
+    }
+
+    #[test]
+    fn test_health_status_conversion() {
+        assert_eq!(HealthStatus::try_from(EFI_DRIVER_HEALTH_STATUS_FAILED), Ok(HealthStatus::Failed));
+        assert_eq!(HealthStatus::try_from(EFI_DRIVER_HEALTH_STATUS_REBOOT_REQUIRED), Ok(HealthStatus::RebootRequired));
+        assert_eq!(HealthStatus::try_from(6), Err(efi::Status::DEVICE_ERROR));
+    }
+}
@@ -0,0 +1,549 @@
+//! Firmware Management Protocol
+//!
+//! Produced by drivers that manage updatable firmware images, to report the images and their versions and to update
+//! them. The images reported by every instance of the protocol are the firmware resources described in the EFI System
+//! Resource Table (ESRT).
+//!
+//! See <https://uefi.org/specs/UEFI/2.10/23_Firmware_Update_and_Reporting.html#efi-firmware-management-protocol>
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+extern crate alloc;
+
+use alloc::{vec, vec::Vec};
+use core::{cmp, ffi::c_void, mem, ptr};
+
+use r_efi::efi;
+
+use super::ProtocolInterface;
+use crate::boot_services::BootServices;
+
+/// Firmware Management Protocol GUID.
+pub const PROTOCOL_GUID: efi::Guid = crate::guid!("86C77A67-0B97-4633-A187-49104D0685C7");
+
+/// The latest version of [EfiFirmwareImageDescriptor] defined by the UEFI specification.
+pub const EFI_FIRMWARE_IMAGE_DESCRIPTOR_VERSION: u32 = 4;
+
+/// The image can be updated.
+pub const IMAGE_ATTRIBUTE_IMAGE_UPDATABLE: u64 = 0x0000_0000_0000_0001;
+/// A reset is required for the new image to take effect.
+pub const IMAGE_ATTRIBUTE_RESET_REQUIRED: u64 = 0x0000_0000_0000_0002;
+/// An authenticated image is required to update the image.
+pub const IMAGE_ATTRIBUTE_AUTHENTICATION_REQUIRED: u64 = 0x0000_0000_0000_0004;
+/// The image is in use. Images not in use are not active, e.g. the backup image of a device.
+pub const IMAGE_ATTRIBUTE_IN_USE: u64 = 0x0000_0000_0000_0008;
+/// The image is a UEFI image, e.g. a driver in an option ROM.
+pub const IMAGE_ATTRIBUTE_UEFI_IMAGE: u64 = 0x0000_0000_0000_0010;
+/// The image has dependencies on the versions of other images.
+pub const IMAGE_ATTRIBUTE_DEPENDENCY: u64 = 0x0000_0000_0000_0020;
+
+/// The last update attempt succeeded.
+pub const LAST_ATTEMPT_STATUS_SUCCESS: u32 = 0x0000_0000;
+/// The last update attempt failed for an unsuccessful, unknown reason.
+pub const LAST_ATTEMPT_STATUS_ERROR_UNSUCCESSFUL: u32 = 0x0000_0001;
+
+/// A firmware image reported by [GetImageInfo] (`EFI_FIRMWARE_IMAGE_DESCRIPTOR`).
+///
+/// The fields after `last_attempt_version` were added in later versions of the descriptor, and are only valid if the
+/// descriptor version reported with the image information allows it.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct EfiFirmwareImageDescriptor {
+    /// The index of the image, starting from 1.
+    pub image_index: u8,
+    /// The type of the image, which is the firmware class of the image in the ESRT.
+    pub image_type_id: efi::Guid,
+    /// A unique identifier of the image within the images of the same type.
+    pub image_id: u64,
+    /// The name of the image, a NUL terminated UCS-2 string.
+    pub image_id_name: *mut u16,
+    /// The version of the image.
+    pub version: u32,
+    /// The version of the image, as a NUL terminated UCS-2 string.
+    pub version_name: *mut u16,
+    /// The size of the image in bytes.
+    pub size: usize,
+    /// The image attributes supported by the device.
+    pub attributes_supported: u64,
+    /// The image attributes set for the device.
+    pub attributes_setting: u64,
+    /// The compatibilities of the image.
+    pub compatibilities: u64,
+    /// The lowest version the image can be updated to (descriptor version 2).
+    pub lowest_supported_image_version: u32,
+    /// The version of the image of the last update attempt (descriptor version 3).
+    pub last_attempt_version: u32,
+    /// The status of the last update attempt (descriptor version 3).
+    pub last_attempt_status: u32,
+    /// The instance of the hardware the image is for, or 0 if there is only one (descriptor version 3).
+    pub hardware_instance: u64,
+    /// The dependencies of the image (descriptor version 4).
+    pub dependencies: *mut c_void,
+}
+
+/// Function definition for reporting the progress of an image update, in percent.
+pub type Progress = extern "efiapi" fn(completion: usize) -> efi::Status;
+
+/// Function definition for retrieving the information of the firmware images.
+pub type GetImageInfo = extern "efiapi" fn(
+    this: *mut Protocol,
+    image_info_size: *mut usize,
+    image_info: *mut EfiFirmwareImageDescriptor,
+    descriptor_version: *mut u32,
+    descriptor_count: *mut u8,
+    descriptor_size: *mut usize,
+    package_version: *mut u32,
+    package_version_name: *mut *mut u16,
+) -> efi::Status;
+
+/// Function definition for retrieving a copy of a firmware image.
+pub type GetImage =
+    extern "efiapi" fn(this: *mut Protocol, image_index: u8, image: *mut c_void, image_size: *mut usize) -> efi::Status;
+
+/// Function definition for updating a firmware image.
+pub type SetImage = extern "efiapi" fn(
+    this: *mut Protocol,
+    image_index: u8,
+    image: *const c_void,
+    image_size: usize,
+    vendor_code: *const c_void,
+    progress: Option<Progress>,
+    abort_reason: *mut *mut u16,
+) -> efi::Status;
+
+/// Function definition for checking whether a firmware image can be used to update the device.
+pub type CheckImage = extern "efiapi" fn(
+    this: *mut Protocol,
+    image_index: u8,
+    image: *const c_void,
+    image_size: usize,
+    image_updatable: *mut u32,
+) -> efi::Status;
+
+/// Function definition for retrieving the information of the firmware package.
+pub type GetPackageInfo = extern "efiapi" fn(
+    this: *mut Protocol,
+    package_version: *mut u32,
+    package_version_name: *mut *mut u16,
+    package_version_name_max_len: *mut u32,
+    attributes_supported: *mut u64,
+    attributes_setting: *mut u64,
+) -> efi::Status;
+
+/// Function definition for updating the information of the firmware package.
+pub type SetPackageInfo = extern "efiapi" fn(
+    this: *mut Protocol,
+    image: *const c_void,
+    image_size: usize,
+    vendor_code: *const c_void,
+    package_version: u32,
+    package_version_name: *const u16,
+) -> efi::Status;
+
+/// C struct for the UEFI Firmware Management Protocol.
+#[repr(C)]
+pub struct Protocol {
+    /// Retrieves the information of the firmware images.
+    pub get_image_info: GetImageInfo,
+    /// Retrieves a copy of a firmware image.
+    pub get_image: GetImage,
+    /// Updates a firmware image.
+    pub set_image: SetImage,
+    /// Checks whether a firmware image can be used to update the device.
+    pub check_image: CheckImage,
+    /// Retrieves the information of the firmware package.
+    pub get_package_info: GetPackageInfo,
+    /// Updates the information of the firmware package.
+    pub set_package_info: SetPackageInfo,
+}
+
+/// A firmware image reported by a Firmware Management Protocol instance.
+///
+/// The fields not defined by the descriptor version reported by the instance are zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FirmwareImageDescriptor {
+    /// The index of the image, starting from 1.
+    pub image_index: u8,
+    /// The type of the image.
+    pub image_type_id: efi::Guid,
+    /// A unique identifier of the image within the images of the same type.
+    pub image_id: u64,
+    /// The version of the image.
+    pub version: u32,
+    /// The image attributes supported by the device.
+    pub attributes_supported: u64,
+    /// The image attributes set for the device.
+    pub attributes_setting: u64,
+    /// The compatibilities of the image.
+    pub compatibilities: u64,
+    /// The lowest version the image can be updated to.
+    pub lowest_supported_image_version: u32,
+    /// The version of the image of the last update attempt.
+    pub last_attempt_version: u32,
+    /// The status of the last update attempt.
+    pub last_attempt_status: u32,
+    /// The instance of the hardware the image is for, or 0 if there is only one.
+    pub hardware_instance: u64,
+}
+
+impl FirmwareImageDescriptor {
+    /// Returns whether the attribute is both supported and set for the image.
+    pub fn has_attribute(&self, attribute: u64) -> bool {
+        self.attributes_supported & self.attributes_setting & attribute == attribute
+    }
+
+    /// Returns whether the image is in use. Images that do not support the in use attribute are always in use.
+    pub fn is_in_use(&self) -> bool {
+        self.attributes_supported & IMAGE_ATTRIBUTE_IN_USE == 0 || self.has_attribute(IMAGE_ATTRIBUTE_IN_USE)
+    }
+}
+
+impl From<&EfiFirmwareImageDescriptor> for FirmwareImageDescriptor {
+    fn from(descriptor: &EfiFirmwareImageDescriptor) -> Self {
+        Self {
+            image_index: descriptor.image_index,
+            image_type_id: descriptor.image_type_id,
+            image_id: descriptor.image_id,
+            version: descriptor.version,
+            attributes_supported: descriptor.attributes_supported,
+            attributes_setting: descriptor.attributes_setting,
+            compatibilities: descriptor.compatibilities,
+            lowest_supported_image_version: descriptor.lowest_supported_image_version,
+            last_attempt_version: descriptor.last_attempt_version,
+            last_attempt_status: descriptor.last_attempt_status,
+            hardware_instance: descriptor.hardware_instance,
+        }
+    }
+}
+
+/// The information of the firmware images reported by a Firmware Management Protocol instance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FirmwareImageInfo {
+    /// The version of the descriptors reported by the instance.
+    pub descriptor_version: u32,
+    /// The version of the firmware package, `0xFFFFFFFF` if package versions are not supported.
+    pub package_version: u32,
+    /// The firmware images.
+    pub descriptors: Vec<FirmwareImageDescriptor>,
+}
+
+/// Safe wrapper around the UEFI Firmware Management Protocol.
+///
+/// Instances are obtained from firmware, e.g. with [BootServices::handle_protocol].
+#[repr(transparent)]
+pub struct FirmwareManagementProtocol {
+    protocol: Protocol,
+}
+
+unsafe impl ProtocolInterface for FirmwareManagementProtocol {
+    const PROTOCOL_GUID: efi::Guid = PROTOCOL_GUID;
+}
+
+impl FirmwareManagementProtocol {
+    /// Creates a new instance of the Firmware Management Protocol with the given implementation.
+    pub const fn new(
+        get_image_info: GetImageInfo,
+        get_image: GetImage,
+        set_image: SetImage,
+        check_image: CheckImage,
+        get_package_info: GetPackageInfo,
+        set_package_info: SetPackageInfo,
+    ) -> Self {
+        Self {
+            protocol: Protocol {
+                get_image_info,
+                get_image,
+                set_image,
+                check_image,
+                get_package_info,
+                set_package_info,
+            },
+        }
+    }
+
+    /// Retrieves the information of the firmware images.
+    ///
+    /// The package version name returned by the driver is freed with `boot_services`.
+    ///
+    /// ## Errors
+    ///
+    /// Returns the error reported by the driver, and [DEVICE_ERROR](efi::Status::DEVICE_ERROR) if the reported
+    /// descriptors do not fit in the reported size.
+    pub fn get_image_info<B: BootServices>(&self, boot_services: &B) -> Result<FirmwareImageInfo, efi::Status> {
+        let this = &self.protocol as *const Protocol as *mut Protocol;
+        let mut image_info_size = 0;
+        let mut descriptor_version = 0;
+        let mut descriptor_count = 0;
+        let mut descriptor_size = 0;
+        let mut package_version = 0;
+        let mut package_version_name = ptr::null_mut();
+
+        // The first call reports the size of the buffer needed for the descriptors.
+        let status = (self.protocol.get_image_info)(
+            this,
+            &mut image_info_size,
+            ptr::null_mut(),
+            &mut descriptor_version,
+            &mut descriptor_count,
+            &mut descriptor_size,
+            &mut package_version,
+            &mut package_version_name,
+        );
+        if status != efi::Status::BUFFER_TOO_SMALL {
+            return Err(if status.is_error() { status } else { efi::Status::DEVICE_ERROR });
+        }
+
+        // The buffer is allocated in u64 units, for the descriptors to be aligned.
+        let mut buffer = vec![0_u64; image_info_size.div_ceil(mem::size_of::<u64>())];
+        let status = (self.protocol.get_image_info)(
+            this,
+            &mut image_info_size,
+            buffer.as_mut_ptr() as *mut EfiFirmwareImageDescriptor,
+            &mut descriptor_version,
+            &mut descriptor_count,
+            &mut descriptor_size,
+            &mut package_version,
+            &mut package_version_name,
+        );
+        if !package_version_name.is_null() {
+            // The package version name is allocated by the driver and must be freed by the caller.
+            let _ = boot_services.free_pool(package_version_name as *mut u8);
+        }
+        if status.is_error() {
+            return Err(status);
+        }
+
+        let count = descriptor_count as usize;
+        if descriptor_size == 0 || count.saturating_mul(descriptor_size) > buffer.len() * mem::size_of::<u64>() {
+            return Err(efi::Status::DEVICE_ERROR);
+        }
+
+        let descriptors = (0..count)
+            .map(|index| {
+                // Descriptors of older versions are shorter, the fields they do not define are left zeroed.
+                let mut descriptor = mem::MaybeUninit::<EfiFirmwareImageDescriptor>::zeroed();
+                let size = cmp::min(descriptor_size, mem::size_of::<EfiFirmwareImageDescriptor>());
+                // SAFETY: The descriptor is within the buffer, as checked above, and the copy does not exceed the
+                // size of the destination. All fields of the descriptor are valid when zeroed.
+                let descriptor = unsafe {
+                    let source = (buffer.as_ptr() as *const u8).add(index * descriptor_size);
+                    ptr::copy_nonoverlapping(source, descriptor.as_mut_ptr() as *mut u8, size);
+                    descriptor.assume_init()
+                };
+                FirmwareImageDescriptor::from(&descriptor)
+            })
+            .collect();
+
+        Ok(FirmwareImageInfo { descriptor_version, package_version, descriptors })
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use crate::boot_services::MockBootServices;
+
+    const IMAGE_TYPE: efi::Guid = crate::guid!("3F1B2A4C-5D6E-4F70-8192-A3B4C5D6E7F8");
+    const DESCRIPTOR_V1_SIZE: usize = mem::offset_of!(EfiFirmwareImageDescriptor, lowest_supported_image_version);
+
+    static mut PACKAGE_VERSION_NAME: [u16; 2] = [b'1' as u16, 0];
+
+    fn descriptor(image_index: u8, version: u32) -> EfiFirmwareImageDescriptor {
+        EfiFirmwareImageDescriptor {
+            image_index,
+            image_type_id: IMAGE_TYPE,
+            image_id: image_index as u64,
+            image_id_name: ptr::null_mut(),
+            version,
+            version_name: ptr::null_mut(),
+            size: 0x1000,
+            attributes_supported: IMAGE_ATTRIBUTE_IMAGE_UPDATABLE | IMAGE_ATTRIBUTE_IN_USE,
+            attributes_setting: IMAGE_ATTRIBUTE_IMAGE_UPDATABLE,
+            compatibilities: 0,
+            lowest_supported_image_version: 2,
+            last_attempt_version: version,
+            last_attempt_status: LAST_ATTEMPT_STATUS_SUCCESS,
+            hardware_instance: 0,
+            dependencies: ptr::null_mut(),
+        }
+    }
+
+    /// Reports two descriptors of `descriptor_size` bytes.
+    fn get_image_info(
+        descriptor_size: usize,
+        image_info_size: *mut usize,
+        image_info: *mut EfiFirmwareImageDescriptor,
+        descriptor_count: *mut u8,
+        descriptor_size_out: *mut usize,
+        package_version_name: *mut *mut u16,
+    ) -> efi::Status {
+        unsafe {
+            descriptor_count.write(2);
+            descriptor_size_out.write(descriptor_size);
+            if image_info.is_null() || *image_info_size < 2 * descriptor_size {
+                image_info_size.write(2 * descriptor_size);
+                return efi::Status::BUFFER_TOO_SMALL;
+            }
+            for (index, descriptor) in [descriptor(1, 5), descriptor(2, 7)].iter().enumerate() {
+                ptr::copy_nonoverlapping(
+                    descriptor as *const _ as *const u8,
+                    (image_info as *mut u8).add(index * descriptor_size),
+                    descriptor_size,
+                );
+            }
+            package_version_name.write(ptr::addr_of_mut!(PACKAGE_VERSION_NAME) as *mut u16);
+        }
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn get_image_info_v4(
+        _this: *mut Protocol,
+        image_info_size: *mut usize,
+        image_info: *mut EfiFirmwareImageDescriptor,
+        descriptor_version: *mut u32,
+        descriptor_count: *mut u8,
+        descriptor_size: *mut usize,
+        package_version: *mut u32,
+        package_version_name: *mut *mut u16,
+    ) -> efi::Status {
+        unsafe {
+            descriptor_version.write(EFI_FIRMWARE_IMAGE_DESCRIPTOR_VERSION);
+            package_version.write(0xFFFFFFFF);
+        }
+        let size = mem::size_of::<EfiFirmwareImageDescriptor>();
+        get_image_info(size, image_info_size, image_info, descriptor_count, descriptor_size, package_version_name)
+    }
+
+    extern "efiapi" fn get_image_info_v1(
+        _this: *mut Protocol,
+        image_info_size: *mut usize,
+        image_info: *mut EfiFirmwareImageDescriptor,
+        descriptor_version: *mut u32,
+        descriptor_count: *mut u8,
+        descriptor_size: *mut usize,
+        package_version: *mut u32,
+        package_version_name: *mut *mut u16,
+    ) -> efi::Status {
+        unsafe {
+            descriptor_version.write(1);
+            package_version.write(1);
+        }
+        get_image_info(
+            DESCRIPTOR_V1_SIZE,
+            image_info_size,
+            image_info,
+            descriptor_count,
+            descriptor_size,
+            package_version_name,
+        )
+    }
+
+    extern "efiapi" fn get_image_info_unsupported(
+        _this: *mut Protocol,
+        _image_info_size: *mut usize,
+        _image_info: *mut EfiFirmwareImageDescriptor,
+        _descriptor_version: *mut u32,
+        _descriptor_count: *mut u8,
+        _descriptor_size: *mut usize,
+        _package_version: *mut u32,
+        _package_version_name: *mut *mut u16,
+    ) -> efi::Status {
+        efi::Status::UNSUPPORTED
+    }
+
+    extern "efiapi" fn get_image(_: *mut Protocol, _: u8, _: *mut c_void, _: *mut usize) -> efi::Status {
+        efi::Status::UNSUPPORTED
+    }
+
+    extern "efiapi" fn set_image(
+        _: *mut Protocol,
+        _: u8,
+        _: *const c_void,
+        _: usize,
+        _: *const c_void,
+        _: Option<Progress>,
+        _: *mut *mut u16,
+    ) -> efi::Status {
+        efi::Status::UNSUPPORTED
+    }
+
+    extern "efiapi" fn check_image(_: *mut Protocol, _: u8, _: *const c_void, _: usize, _: *mut u32) -> efi::Status {
+        efi::Status::UNSUPPORTED
+    }
+
+    extern "efiapi" fn get_package_info(
+        _: *mut Protocol,
+        _: *mut u32,
+        _: *mut *mut u16,
+        _: *mut u32,
+        _: *mut u64,
+        _: *mut u64,
+    ) -> efi::Status {
+        efi::Status::UNSUPPORTED
+    }
+
+    extern "efiapi" fn set_package_info(
+        _: *mut Protocol,
+        _: *const c_void,
+        _: usize,
+        _: *const c_void,
+        _: u32,
+        _: *const u16,
+    ) -> efi::Status {
+        efi::Status::UNSUPPORTED
+    }
+
+    fn protocol(get_image_info: GetImageInfo) -> FirmwareManagementProtocol {
+        FirmwareManagementProtocol::new(
+            get_image_info,
+            get_image,
+            set_image,
+            check_image,
+            get_package_info,
+            set_package_info,
+        )
+    }
+
+    #[test]
+    fn test_get_image_info() {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_free_pool().once().returning(|_| Ok(()));
+
+        let info = protocol(get_image_info_v4).get_image_info(&boot_services).unwrap();
+        assert_eq!(info.descriptor_version, EFI_FIRMWARE_IMAGE_DESCRIPTOR_VERSION);
+        assert_eq!(info.package_version, 0xFFFFFFFF);
+        assert_eq!(info.descriptors.len(), 2);
+        assert_eq!(info.descriptors[0], FirmwareImageDescriptor::from(&descriptor(1, 5)));
+        assert_eq!(info.descriptors[1].version, 7);
+        assert_eq!(info.descriptors[1].lowest_supported_image_version, 2);
+        assert!(info.descriptors[1].has_attribute(IMAGE_ATTRIBUTE_IMAGE_UPDATABLE));
+        assert!(!info.descriptors[1].is_in_use());
+    }
+
+    #[test]
+    fn test_get_image_info_of_older_descriptor_versions() {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_free_pool().once().returning(|_| Ok(()));
+
+        let info = protocol(get_image_info_v1).get_image_info(&boot_services).unwrap();
+        assert_eq!(info.descriptor_version, 1);
+        assert_eq!(info.descriptors.len(), 2);
+        assert_eq!(info.descriptors[1].image_index, 2);
+        assert_eq!(info.descriptors[1].version, 7);
+        // The fields added in later versions are not reported.
+        assert_eq!(info.descriptors[1].lowest_supported_image_version, 0);
+        assert_eq!(info.descriptors[1].last_attempt_version, 0);
+    }
+
+    #[test]
+    fn test_get_image_info_error() {
+        let boot_services = MockBootServices::new();
+        assert_eq!(protocol(get_image_info_unsupported).get_image_info(&boot_services), Err(efi::Status::UNSUPPORTED));
+    }
+}
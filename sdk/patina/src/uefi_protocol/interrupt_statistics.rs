@@ -0,0 +1,73 @@
+//! Interrupt Statistics Protocol
+//!
+//! A Patina diagnostic protocol produced by the DXE core that reports how many times each exception and interrupt
+//! vector was taken, how many interrupts arrived on a vector without a handler, and the deepest nesting of the
+//! exception handler. It allows a hang caused by an interrupt storm to be identified from a shell application or the
+//! debugger.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use r_efi::efi;
+
+use super::ProtocolInterface;
+
+/// Interrupt Statistics Protocol GUID.
+///
+/// (`9C2A61E4-3B7D-4F05-8E1A-6D4C0B92F357`)
+pub const PROTOCOL_GUID: efi::Guid =
+    efi::Guid::from_fields(0x9c2a61e4, 0x3b7d, 0x4f05, 0x8e, 0x1a, &[0x6d, 0x4c, 0x0b, 0x92, 0xf3, 0x57]);
+
+/// The counters of an exception or interrupt vector.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct VectorStatistics {
+    /// The number of times the vector was taken.
+    pub taken: u64,
+    /// The number of times the vector was taken as an interrupt without a handler registered.
+    pub spurious: u64,
+}
+
+/// The counters of all the vectors.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct InterruptSummary {
+    /// The number of vectors, valid vectors are `0..vector_count`.
+    pub vector_count: u64,
+    /// The number of exceptions taken, e.g. page faults.
+    pub faults: u64,
+    /// The number of interrupts taken, including the spurious ones.
+    pub interrupts: u64,
+    /// The number of interrupts taken without a handler registered.
+    pub spurious: u64,
+    /// The deepest nesting of the exception handler, 1 if no exception was taken while handling another one.
+    pub max_nesting_depth: u32,
+}
+
+/// Retrieves the counters of `vector`.
+///
+/// Returns `NOT_FOUND` if `vector` is past the last vector, `INVALID_PARAMETER` if `statistics` is null.
+pub type GetVectorStatistics =
+    extern "efiapi" fn(this: *const Protocol, vector: usize, statistics: *mut VectorStatistics) -> efi::Status;
+
+/// Retrieves the counters of all the vectors.
+///
+/// Returns `INVALID_PARAMETER` if `summary` is null.
+pub type GetSummary = extern "efiapi" fn(this: *const Protocol, summary: *mut InterruptSummary) -> efi::Status;
+
+/// Interrupt Statistics Protocol structure.
+#[repr(C)]
+pub struct Protocol {
+    /// Retrieves the counters of a vector.
+    pub get_vector_statistics: GetVectorStatistics,
+    /// Retrieves the counters of all the vectors.
+    pub get_summary: GetSummary,
+}
+
+unsafe impl ProtocolInterface for Protocol {
+    const PROTOCOL_GUID: efi::Guid = PROTOCOL_GUID;
+}
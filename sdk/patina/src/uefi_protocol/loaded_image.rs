@@ -0,0 +1,161 @@
+//! Loaded Image Protocol
+//!
+//! Describes a loaded image: where it was loaded from, where it is located in memory and how it was invoked.
+//!
+//! See <https://uefi.org/specs/UEFI/2.10/09_Protocols_EFI_Loaded_Image.html#efi-loaded-image-protocol>
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use core::{ffi::c_void, slice};
+
+use r_efi::efi::{self, protocols::loaded_image};
+
+use super::{ProtocolInterface, raw_device_path::RawDevicePath};
+
+/// Safe wrapper around the UEFI Loaded Image Protocol.
+///
+/// Instances are obtained from firmware, e.g. with
+/// [BootServices::handle_protocol](crate::boot_services::BootServices::handle_protocol), and are read-only.
+#[repr(transparent)]
+pub struct LoadedImage {
+    protocol: loaded_image::Protocol,
+}
+
+unsafe impl ProtocolInterface for LoadedImage {
+    const PROTOCOL_GUID: efi::Guid = loaded_image::PROTOCOL_GUID;
+}
+
+impl LoadedImage {
+    /// Returns a reference to the loaded image protocol at `ptr`, or `None` if `ptr` is null.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be null or point to a valid loaded image protocol instance, whose pointers are either null or valid,
+    /// that remains valid and unmodified for the lifetime `'a`.
+    pub unsafe fn from_ptr<'a>(ptr: *const loaded_image::Protocol) -> Option<&'a Self> {
+        // SAFETY: LoadedImage is a transparent wrapper around loaded_image::Protocol; validity is guaranteed by the
+        // caller.
+        unsafe { (ptr as *const Self).as_ref() }
+    }
+
+    /// Returns the revision of the protocol structure.
+    pub fn revision(&self) -> u32 {
+        self.protocol.revision
+    }
+
+    /// Returns the handle of the image that loaded this image, or null if it was loaded by the firmware.
+    pub fn parent_handle(&self) -> efi::Handle {
+        self.protocol.parent_handle
+    }
+
+    /// Returns the handle of the device the image was loaded from.
+    pub fn device_handle(&self) -> efi::Handle {
+        self.protocol.device_handle
+    }
+
+    /// Returns the system table passed to the image.
+    pub fn system_table(&self) -> *mut efi::SystemTable {
+        self.protocol.system_table
+    }
+
+    /// Returns the file path of the image, relative to the device it was loaded from, if present.
+    pub fn file_path(&self) -> Option<&RawDevicePath> {
+        // SAFETY: the file path of a loaded image protocol produced by firmware is null or a valid device path.
+        unsafe { RawDevicePath::from_ptr(self.protocol.file_path) }
+    }
+
+    /// Returns the name of the firmware file the image was loaded from, if it was loaded from a firmware volume.
+    pub fn firmware_file_name(&self) -> Option<efi::Guid> {
+        self.file_path()?.firmware_file_name()
+    }
+
+    /// Returns the load options of the image, if any.
+    pub fn load_options(&self) -> Option<&[u8]> {
+        if self.protocol.load_options.is_null() || self.protocol.load_options_size == 0 {
+            return None;
+        }
+        // SAFETY: load_options points to load_options_size bytes per the loaded image protocol definition.
+        Some(unsafe {
+            slice::from_raw_parts(self.protocol.load_options as *const u8, self.protocol.load_options_size as usize)
+        })
+    }
+
+    /// Returns the base address at which the image was loaded.
+    pub fn image_base(&self) -> *mut c_void {
+        self.protocol.image_base
+    }
+
+    /// Returns the size of the loaded image, in bytes.
+    pub fn image_size(&self) -> u64 {
+        self.protocol.image_size
+    }
+
+    /// Returns the memory type that the code sections were loaded as.
+    pub fn image_code_type(&self) -> efi::MemoryType {
+        self.protocol.image_code_type
+    }
+
+    /// Returns the memory type that the data sections were loaded as.
+    pub fn image_data_type(&self) -> efi::MemoryType {
+        self.protocol.image_data_type
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use core::{mem::MaybeUninit, ptr};
+    use r_efi::efi::protocols::device_path::{End, Media, TYPE_END, TYPE_MEDIA};
+
+    #[test]
+    fn test_loaded_image_accessors() {
+        #[rustfmt::skip]
+        let device_path: [u8; 24] = [
+            TYPE_MEDIA, Media::SUBTYPE_PIWG_FIRMWARE_FILE, 20, 0,
+            1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16,
+            TYPE_END, End::SUBTYPE_ENTIRE, 4, 0,
+        ];
+        let load_options = [0xAAu8; 8];
+
+        let mut protocol = unsafe { MaybeUninit::<loaded_image::Protocol>::zeroed().assume_init() };
+        protocol.revision = loaded_image::REVISION;
+        protocol.file_path = device_path.as_ptr() as *mut _;
+        protocol.load_options = load_options.as_ptr() as *mut c_void;
+        protocol.load_options_size = load_options.len() as u32;
+        protocol.image_size = 0x1000;
+        protocol.image_code_type = efi::BOOT_SERVICES_CODE;
+        protocol.image_data_type = efi::BOOT_SERVICES_DATA;
+
+        let loaded_image = unsafe { LoadedImage::from_ptr(&protocol) }.unwrap();
+        assert_eq!(loaded_image.revision(), loaded_image::REVISION);
+        assert!(loaded_image.parent_handle().is_null());
+        assert!(loaded_image.device_handle().is_null());
+        assert!(loaded_image.system_table().is_null());
+        assert_eq!(
+            loaded_image.firmware_file_name(),
+            Some(efi::Guid::from_bytes(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16]))
+        );
+        assert_eq!(loaded_image.load_options(), Some(&load_options[..]));
+        assert!(loaded_image.image_base().is_null());
+        assert_eq!(loaded_image.image_size(), 0x1000);
+        assert_eq!(loaded_image.image_code_type(), efi::BOOT_SERVICES_CODE);
+        assert_eq!(loaded_image.image_data_type(), efi::BOOT_SERVICES_DATA);
+    }
+
+    #[test]
+    fn test_loaded_image_without_file_path_or_options() {
+        let protocol = unsafe { MaybeUninit::<loaded_image::Protocol>::zeroed().assume_init() };
+        let loaded_image = unsafe { LoadedImage::from_ptr(&protocol) }.unwrap();
+        assert!(loaded_image.file_path().is_none());
+        assert!(loaded_image.firmware_file_name().is_none());
+        assert!(loaded_image.load_options().is_none());
+
+        assert!(unsafe { LoadedImage::from_ptr(ptr::null()) }.is_none());
+    }
+}
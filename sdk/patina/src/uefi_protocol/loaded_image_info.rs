@@ -0,0 +1,119 @@
+//! Loaded Image Info Protocol
+//!
+//! A Patina diagnostic protocol produced by the DXE core that reports the images it has loaded: their placement in
+//! memory, their entry point, the firmware volume file they were loaded from and the state of their memory
+//! protections. It allows diagnostic tools to identify the image containing an address without walking every Loaded
+//! Image protocol instance, and to match it to its symbol file from the CodeView entry of its debug directory.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use core::ffi::c_char;
+
+use r_efi::efi;
+
+use super::ProtocolInterface;
+
+/// Loaded Image Info Protocol GUID.
+///
+/// (`5B4E4D2A-6C1F-4A8E-9D37-0E2F8B61C4A9`)
+pub const PROTOCOL_GUID: efi::Guid =
+    efi::Guid::from_fields(0x5b4e4d2a, 0x6c1f, 0x4a8e, 0x9d, 0x37, &[0x0e, 0x2f, 0x8b, 0x61, 0xc4, 0xa9]);
+
+/// The state of the memory protections of a loaded image.
+pub type ImageProtection = u32;
+
+/// No memory protections are applied to the image.
+pub const IMAGE_PROTECTION_NONE: ImageProtection = 0;
+/// The code sections of the image are read-only and its data sections are non-executable.
+pub const IMAGE_PROTECTION_APPLIED: ImageProtection = 1;
+/// The image is not NX compatible and is mapped read-write-execute in compatibility mode.
+pub const IMAGE_PROTECTION_COMPATIBILITY_MODE: ImageProtection = 2;
+
+/// Information about an image loaded by the DXE core.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoadedImageInfo {
+    /// The handle of the image.
+    pub image_handle: efi::Handle,
+    /// The base address of the image in memory.
+    pub image_base: u64,
+    /// The size of the image in memory, in bytes.
+    pub image_size: u64,
+    /// The address of the entry point of the image.
+    pub entry_point: u64,
+    /// The name of the firmware volume file the image was loaded from, or the zero GUID if it was not loaded from a
+    /// firmware volume.
+    pub file_guid: efi::Guid,
+    /// The device path the image was loaded from, or null. Owned by the DXE core.
+    pub device_path: *const efi::protocols::device_path::Protocol,
+    /// The state of the memory protections of the image.
+    pub protection: ImageProtection,
+}
+
+/// The type of the CodeView entry referencing the symbol file of a loaded image.
+pub type DebugInfoType = u32;
+
+/// The image has no CodeView entry in its debug directory.
+pub const DEBUG_INFO_NONE: DebugInfoType = 0;
+/// An `RSDS` entry, referencing a PDB 7.0 file by its GUID and age.
+pub const DEBUG_INFO_CODEVIEW_PDB70: DebugInfoType = 1;
+/// An `NB10` entry, referencing a PDB 2.0 file by its signature and age. Emitted by GenFw for images built from ELF,
+/// in which case the path is the ELF image holding the DWARF debug information.
+pub const DEBUG_INFO_CODEVIEW_PDB20: DebugInfoType = 2;
+
+/// The reference to the symbol file of an image loaded by the DXE core, from the CodeView entry of its debug
+/// directory.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageDebugInfo {
+    /// The type of the CodeView entry.
+    pub debug_info_type: DebugInfoType,
+    /// The age of the symbol file.
+    pub age: u32,
+    /// The GUID of the PDB 7.0 file, or the zero GUID for other types.
+    pub guid: efi::Guid,
+    /// The signature of the PDB 2.0 file, or 0 for other types.
+    pub signature: u32,
+    /// The null-terminated path of the symbol file at build time, or null. Owned by the DXE core and valid until the
+    /// image is unloaded.
+    pub path: *const c_char,
+}
+
+/// Retrieves the information of the loaded image at `index`, in load order.
+///
+/// Returns `NOT_FOUND` if `index` is past the last loaded image, `INVALID_PARAMETER` if `info` is null.
+pub type GetLoadedImageInfo =
+    extern "efiapi" fn(this: *const Protocol, index: usize, info: *mut LoadedImageInfo) -> efi::Status;
+
+/// Retrieves the information of the loaded image containing `address`.
+///
+/// Returns `NOT_FOUND` if no loaded image contains `address`, `INVALID_PARAMETER` if `info` is null.
+pub type FindLoadedImageInfo =
+    extern "efiapi" fn(this: *const Protocol, address: efi::PhysicalAddress, info: *mut LoadedImageInfo) -> efi::Status;
+
+/// Retrieves the reference to the symbol file of the loaded image containing `address`.
+///
+/// Returns `NOT_FOUND` if no loaded image contains `address`, `INVALID_PARAMETER` if `info` is null. The
+/// `debug_info_type` is [DEBUG_INFO_NONE] if the image has no CodeView entry.
+pub type GetImageDebugInfo =
+    extern "efiapi" fn(this: *const Protocol, address: efi::PhysicalAddress, info: *mut ImageDebugInfo) -> efi::Status;
+
+/// Loaded Image Info Protocol structure.
+#[repr(C)]
+pub struct Protocol {
+    /// Retrieves the information of a loaded image by index.
+    pub get_loaded_image_info: GetLoadedImageInfo,
+    /// Retrieves the information of the loaded image containing an address.
+    pub find_loaded_image_info: FindLoadedImageInfo,
+    /// Retrieves the reference to the symbol file of the loaded image containing an address.
+    pub get_image_debug_info: GetImageDebugInfo,
+}
+
+unsafe impl ProtocolInterface for Protocol {
+    const PROTOCOL_GUID: efi::Guid = PROTOCOL_GUID;
+}
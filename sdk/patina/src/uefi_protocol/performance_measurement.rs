@@ -59,3 +59,29 @@ pub struct EdkiiPerformanceMeasurement {
 unsafe impl ProtocolInterface for EdkiiPerformanceMeasurement {
     const PROTOCOL_GUID: efi::Guid = EDKII_PERFORMANCE_MEASUREMENT_PROTOCOL_GUID;
 }
+
+/// GUID for the Patina Performance Measurement Mask Protocol.
+pub const PERFORMANCE_MEASUREMENT_MASK_PROTOCOL_GUID: efi::Guid = crate::guid!("7E2A4B91-C3D5-4F80-B6E1-29A8D4F3C75B");
+
+/// Function returning the mask of the enabled [Measurement](crate::performance::Measurement)s.
+pub type GetMeasurementMask = extern "efiapi" fn() -> u32;
+
+/// Function setting the mask of the enabled [Measurement](crate::performance::Measurement)s.
+pub type SetMeasurementMask = extern "efiapi" fn(mask: u32) -> efi::Status;
+
+/// Patina defined protocol to inspect and change the performance measurements recorded at runtime, e.g. to only
+/// enable function spans while investigating a specific boot phase.
+///
+/// The records created through [EdkiiPerformanceMeasurement] are dropped when their measurement is not in the mask.
+/// Records not belonging to a [Measurement](crate::performance::Measurement) are always recorded.
+#[repr(C)]
+pub struct PerformanceMeasurementMask {
+    /// Returns the mask of the enabled measurements.
+    pub get_measurement_mask: GetMeasurementMask,
+    /// Sets the mask of the enabled measurements. Returns `EFI_INVALID_PARAMETER` if the mask has unknown bits.
+    pub set_measurement_mask: SetMeasurementMask,
+}
+
+unsafe impl ProtocolInterface for PerformanceMeasurementMask {
+    const PROTOCOL_GUID: efi::Guid = PERFORMANCE_MEASUREMENT_MASK_PROTOCOL_GUID;
+}
@@ -0,0 +1,165 @@
+//! Safe read-only access to device paths produced by firmware.
+//!
+//! [RawDevicePath] wraps a C device path (as found in e.g. the Loaded Image protocol) and provides accessors for the
+//! node header fields, the node data and the following nodes, so that callers do not need to perform pointer
+//! arithmetic on the raw device path. Unlike the `device_path` module, it does not parse the nodes into typed
+//! structures and is always available.
+//!
+//! See <https://uefi.org/specs/UEFI/2.10/10_Protocols_Device_Path_Protocol.html>
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use core::{mem, slice};
+
+use r_efi::efi::{
+    self,
+    protocols::device_path::{self, End, Media, TYPE_END, TYPE_MEDIA},
+};
+
+use super::ProtocolInterface;
+
+/// Size of a device path node header, in bytes.
+const HEADER_SIZE: usize = mem::size_of::<device_path::Protocol>();
+
+/// A node of a device path produced by firmware. Subsequent nodes can be reached with [RawDevicePath::next_node] or
+/// [RawDevicePath::nodes].
+#[repr(transparent)]
+pub struct RawDevicePath {
+    protocol: device_path::Protocol,
+}
+
+unsafe impl ProtocolInterface for RawDevicePath {
+    const PROTOCOL_GUID: efi::Guid = device_path::PROTOCOL_GUID;
+}
+
+impl RawDevicePath {
+    /// Returns a reference to the device path at `ptr`, or `None` if `ptr` is null.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be null or point to a well-formed device path, terminated by an end of entire device path node, that
+    /// remains valid and unmodified for the lifetime `'a`.
+    pub unsafe fn from_ptr<'a>(ptr: *const device_path::Protocol) -> Option<&'a Self> {
+        // SAFETY: RawDevicePath is a transparent wrapper around device_path::Protocol; validity is guaranteed by
+        // the caller.
+        unsafe { (ptr as *const Self).as_ref() }
+    }
+
+    /// Returns a raw pointer to this device path node.
+    pub fn as_ptr(&self) -> *const device_path::Protocol {
+        &self.protocol
+    }
+
+    /// Returns the type of this node.
+    pub fn node_type(&self) -> u8 {
+        self.protocol.r#type
+    }
+
+    /// Returns the sub-type of this node.
+    pub fn sub_type(&self) -> u8 {
+        self.protocol.sub_type
+    }
+
+    /// Returns the length of this node, including the header, in bytes.
+    pub fn node_length(&self) -> usize {
+        u16::from_le_bytes(self.protocol.length) as usize
+    }
+
+    /// Returns the node specific data that follows the header.
+    pub fn data(&self) -> &[u8] {
+        let data_length = self.node_length().saturating_sub(HEADER_SIZE);
+        // SAFETY: the device path was well-formed when this reference was created, so node_length bytes are valid.
+        unsafe { slice::from_raw_parts((self.as_ptr() as *const u8).add(HEADER_SIZE), data_length) }
+    }
+
+    /// Returns `true` if this node is an end of entire device path node.
+    pub fn is_end(&self) -> bool {
+        self.node_type() == TYPE_END && self.sub_type() == End::SUBTYPE_ENTIRE
+    }
+
+    /// Returns the node following this one, or `None` if this is the last node of the device path.
+    pub fn next_node(&self) -> Option<&RawDevicePath> {
+        // A malformed node length would not advance the walk.
+        if self.is_end() || self.node_length() < HEADER_SIZE {
+            return None;
+        }
+        // SAFETY: the device path was well-formed when this reference was created, so a non-end node is followed by
+        // another node at node_length bytes.
+        unsafe { Self::from_ptr((self.as_ptr() as *const u8).add(self.node_length()) as *const device_path::Protocol) }
+    }
+
+    /// Returns an iterator over this node and all subsequent nodes, excluding the end of entire device path node.
+    pub fn nodes(&self) -> impl Iterator<Item = &RawDevicePath> {
+        core::iter::successors(Some(self), |node| node.next_node()).take_while(|node| !node.is_end())
+    }
+
+    /// Returns the file name GUID if this node is a PI firmware file (MEDIA_PIWG_FW_FILE_DP) node.
+    pub fn firmware_file_name(&self) -> Option<efi::Guid> {
+        if self.node_type() != TYPE_MEDIA || self.sub_type() != Media::SUBTYPE_PIWG_FIRMWARE_FILE {
+            return None;
+        }
+        let data: &[u8; 16] = self.data().get(..16)?.try_into().ok()?;
+        Some(efi::Guid::from_bytes(data))
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    fn node(r#type: u8, sub_type: u8, data: &[u8]) -> Vec<u8> {
+        let length = (HEADER_SIZE + data.len()) as u16;
+        let mut node = Vec::from([r#type, sub_type]);
+        node.extend_from_slice(&length.to_le_bytes());
+        node.extend_from_slice(data);
+        node
+    }
+
+    #[test]
+    fn test_walk_device_path() {
+        let file_name = [0xA5; 16];
+        let mut buffer = node(device_path::TYPE_HARDWARE, device_path::Hardware::SUBTYPE_MMAP, &[0; 20]);
+        buffer.extend(node(TYPE_MEDIA, Media::SUBTYPE_PIWG_FIRMWARE_FILE, &file_name));
+        buffer.extend(node(TYPE_END, End::SUBTYPE_ENTIRE, &[]));
+
+        let device_path = unsafe { RawDevicePath::from_ptr(buffer.as_ptr() as *const _) }.unwrap();
+        assert_eq!(device_path.node_type(), device_path::TYPE_HARDWARE);
+        assert_eq!(device_path.node_length(), 24);
+        assert_eq!(device_path.data(), &[0; 20]);
+        assert_eq!(device_path.firmware_file_name(), None);
+
+        let nodes: Vec<_> = device_path.nodes().collect();
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(nodes[1].firmware_file_name(), Some(efi::Guid::from_bytes(&file_name)));
+
+        let end = nodes[1].next_node().unwrap();
+        assert!(end.is_end());
+        assert!(end.next_node().is_none());
+    }
+
+    #[test]
+    fn test_truncated_nodes() {
+        // A firmware file node that is too short to hold a GUID.
+        let buffer = node(TYPE_MEDIA, Media::SUBTYPE_PIWG_FIRMWARE_FILE, &[0; 4]);
+        let device_path = unsafe { RawDevicePath::from_ptr(buffer.as_ptr() as *const _) }.unwrap();
+        assert_eq!(device_path.firmware_file_name(), None);
+
+        // A node with an invalid length ends the walk.
+        let buffer = [TYPE_MEDIA, Media::SUBTYPE_PIWG_FIRMWARE_FILE, 0, 0];
+        let device_path = unsafe { RawDevicePath::from_ptr(buffer.as_ptr() as *const _) }.unwrap();
+        assert!(device_path.data().is_empty());
+        assert!(device_path.next_node().is_none());
+    }
+
+    #[test]
+    fn test_null_device_path() {
+        assert!(unsafe { RawDevicePath::from_ptr(core::ptr::null()) }.is_none());
+    }
+}
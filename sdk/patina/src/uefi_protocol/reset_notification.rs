@@ -0,0 +1,58 @@
+//! Reset Notification Protocol
+//!
+//! Allows drivers to register functions that are called, in registration order, when ResetSystem() is called and
+//! before the platform is reset, e.g. to flush caches or logs.
+//!
+//! See <https://uefi.org/specs/UEFI/2.10/08_Services_Runtime_Services.html#efi-reset-notification-protocol>
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use core::ffi::c_void;
+
+use r_efi::efi;
+
+use super::ProtocolInterface;
+
+/// Reset Notification Protocol GUID.
+///
+/// (`9DA34AE0-EAF9-4BBF-8EC3-FD60226C44BE`)
+pub const PROTOCOL_GUID: efi::Guid =
+    efi::Guid::from_fields(0x9da34ae0, 0xeaf9, 0x4bbf, 0x8e, 0xc3, &[0xfd, 0x60, 0x22, 0x6c, 0x44, 0xbe]);
+
+/// The signature of ResetSystem(), and of the functions notified of a reset.
+pub type ResetSystem = extern "efiapi" fn(
+    reset_type: efi::ResetType,
+    reset_status: efi::Status,
+    data_size: usize,
+    reset_data: *mut c_void,
+);
+
+/// Registers `reset_function` to be called when ResetSystem() is called, before the platform is reset.
+///
+/// Returns `INVALID_PARAMETER` if `reset_function` is null, `ALREADY_STARTED` if it is already registered.
+pub type RegisterResetNotify =
+    extern "efiapi" fn(this: *mut Protocol, reset_function: Option<ResetSystem>) -> efi::Status;
+
+/// Unregisters `reset_function`.
+///
+/// Returns `INVALID_PARAMETER` if `reset_function` is null or not registered.
+pub type UnregisterResetNotify =
+    extern "efiapi" fn(this: *mut Protocol, reset_function: Option<ResetSystem>) -> efi::Status;
+
+/// Reset Notification Protocol structure.
+#[repr(C)]
+pub struct Protocol {
+    /// Registers a function to be called before the platform is reset.
+    pub register_reset_notify: RegisterResetNotify,
+    /// Unregisters a function registered with `register_reset_notify`.
+    pub unregister_reset_notify: UnregisterResetNotify,
+}
+
+unsafe impl ProtocolInterface for Protocol {
+    const PROTOCOL_GUID: efi::Guid = PROTOCOL_GUID;
+}
@@ -0,0 +1,228 @@
+//! Simple Text Output Protocol
+//!
+//! Writes text to a console device.
+//!
+//! See <https://uefi.org/specs/UEFI/2.10/12_Protocols_Console_Support.html#simple-text-output-protocol>
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use core::fmt;
+
+use r_efi::efi::{self, protocols::simple_text_output};
+
+use super::ProtocolInterface;
+use crate::base::ucs2::Ucs2Str;
+
+/// The number of characters converted at once when writing Rust strings to the console.
+const WRITE_CHUNK_LEN: usize = 64;
+
+/// Safe wrapper around the UEFI Simple Text Output Protocol.
+///
+/// Instances are obtained from firmware, e.g. the console output of the system table. It implements [`fmt::Write`],
+/// so that [`write!`] can be used to print Rust strings to the console.
+#[repr(transparent)]
+pub struct SimpleTextOutput {
+    protocol: simple_text_output::Protocol,
+}
+
+unsafe impl ProtocolInterface for SimpleTextOutput {
+    const PROTOCOL_GUID: efi::Guid = simple_text_output::PROTOCOL_GUID;
+}
+
+impl SimpleTextOutput {
+    /// Returns a reference to the simple text output protocol at `ptr`, or `None` if `ptr` is null.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be null or point to a valid simple text output protocol instance that remains valid for the
+    /// lifetime `'a`.
+    pub unsafe fn from_ptr<'a>(ptr: *mut simple_text_output::Protocol) -> Option<&'a mut Self> {
+        // SAFETY: SimpleTextOutput is a transparent wrapper around simple_text_output::Protocol; validity is
+        // guaranteed by the caller.
+        unsafe { (ptr as *mut Self).as_mut() }
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut simple_text_output::Protocol {
+        &mut self.protocol
+    }
+
+    /// Writes `string` to the console at the current cursor position.
+    ///
+    /// Warnings, e.g. for characters the console cannot render, are not reported as errors.
+    pub fn output_string(&mut self, string: &Ucs2Str) -> Result<(), efi::Status> {
+        let status = (self.protocol.output_string)(self.as_mut_ptr(), string.as_ptr() as *mut efi::Char16);
+        if status.is_error() { Err(status) } else { Ok(()) }
+    }
+
+    /// Checks that the console can render every character of `string`.
+    ///
+    /// Returns `UNSUPPORTED` if some characters cannot be rendered.
+    pub fn test_string(&mut self, string: &Ucs2Str) -> Result<(), efi::Status> {
+        let status = (self.protocol.test_string)(self.as_mut_ptr(), string.as_ptr() as *mut efi::Char16);
+        if status.is_error() { Err(status) } else { Ok(()) }
+    }
+
+    /// Clears the console and moves the cursor to its top left corner.
+    pub fn clear_screen(&mut self) -> Result<(), efi::Status> {
+        let status = (self.protocol.clear_screen)(self.as_mut_ptr());
+        if status.is_error() { Err(status) } else { Ok(()) }
+    }
+
+    /// Sets the foreground and background colors of the text written afterwards.
+    pub fn set_attribute(&mut self, attribute: usize) -> Result<(), efi::Status> {
+        let status = (self.protocol.set_attribute)(self.as_mut_ptr(), attribute);
+        if status.is_error() { Err(status) } else { Ok(()) }
+    }
+
+    fn write_chunk(&mut self, buffer: &mut [u16], len: usize) -> fmt::Result {
+        buffer[len] = 0;
+        // SAFETY: the chunk is null-terminated above, and only holds non-null characters of the Basic Multilingual
+        // Plane, which excludes surrogates.
+        let string = unsafe { Ucs2Str::from_slice_with_nul_unchecked(&buffer[..=len]) };
+        self.output_string(string).map_err(|_| fmt::Error)
+    }
+}
+
+impl fmt::Write for SimpleTextOutput {
+    /// Writes `s` to the console, converting it without allocating.
+    ///
+    /// Line feeds are expanded to carriage return and line feed pairs as consoles expect. Characters that cannot be
+    /// represented in UCS-2 are replaced by U+FFFD.
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        // One more character for a carriage return, and one for the null terminator.
+        let mut buffer = [0u16; WRITE_CHUNK_LEN + 2];
+        let mut len = 0;
+        for c in s.chars() {
+            if c == '\n' {
+                buffer[len] = u16::from(b'\r');
+                len += 1;
+            }
+            buffer[len] = match u16::try_from(u32::from(c)) {
+                Ok(0) | Err(_) => char::REPLACEMENT_CHARACTER as u16,
+                Ok(code_unit) => code_unit,
+            };
+            len += 1;
+
+            if len >= WRITE_CHUNK_LEN {
+                self.write_chunk(&mut buffer, len)?;
+                len = 0;
+            }
+        }
+        if len > 0 {
+            self.write_chunk(&mut buffer, len)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[coverage(off)]
+mod tests {
+    use super::*;
+    use crate::ucs2;
+    use core::{cell::RefCell, fmt::Write};
+    use std::vec::Vec;
+
+    std::thread_local! {
+        static OUTPUT: RefCell<Vec<u16>> = const { RefCell::new(Vec::new()) };
+    }
+
+    extern "efiapi" fn mock_output_string(
+        _this: *mut simple_text_output::Protocol,
+        string: *mut efi::Char16,
+    ) -> efi::Status {
+        let string = unsafe { Ucs2Str::from_ptr(string) };
+        OUTPUT.with_borrow_mut(|output| output.extend_from_slice(string.as_slice()));
+        efi::Status::WARN_UNKNOWN_GLYPH
+    }
+
+    extern "efiapi" fn mock_test_string(
+        _this: *mut simple_text_output::Protocol,
+        string: *mut efi::Char16,
+    ) -> efi::Status {
+        let string = unsafe { Ucs2Str::from_ptr(string) };
+        if string.chars().all(|c| c.is_ascii()) { efi::Status::SUCCESS } else { efi::Status::UNSUPPORTED }
+    }
+
+    extern "efiapi" fn mock_clear_screen(_this: *mut simple_text_output::Protocol) -> efi::Status {
+        efi::Status::DEVICE_ERROR
+    }
+
+    extern "efiapi" fn mock_set_attribute(_this: *mut simple_text_output::Protocol, _attribute: usize) -> efi::Status {
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn mock_unused(_this: *mut simple_text_output::Protocol, _arg: usize) -> efi::Status {
+        efi::Status::UNSUPPORTED
+    }
+
+    extern "efiapi" fn mock_reset(_this: *mut simple_text_output::Protocol, _arg: efi::Boolean) -> efi::Status {
+        efi::Status::UNSUPPORTED
+    }
+
+    extern "efiapi" fn mock_query_mode(
+        _this: *mut simple_text_output::Protocol,
+        _mode: usize,
+        _columns: *mut usize,
+        _rows: *mut usize,
+    ) -> efi::Status {
+        efi::Status::UNSUPPORTED
+    }
+
+    extern "efiapi" fn mock_set_cursor_position(
+        _this: *mut simple_text_output::Protocol,
+        _column: usize,
+        _row: usize,
+    ) -> efi::Status {
+        efi::Status::UNSUPPORTED
+    }
+
+    fn mock_protocol() -> simple_text_output::Protocol {
+        simple_text_output::Protocol {
+            reset: mock_reset,
+            output_string: mock_output_string,
+            test_string: mock_test_string,
+            query_mode: mock_query_mode,
+            set_mode: mock_unused,
+            set_attribute: mock_set_attribute,
+            clear_screen: mock_clear_screen,
+            set_cursor_position: mock_set_cursor_position,
+            enable_cursor: mock_reset,
+            mode: core::ptr::null_mut(),
+        }
+    }
+
+    #[test]
+    fn test_output_string() {
+        let mut protocol = mock_protocol();
+        let console = unsafe { SimpleTextOutput::from_ptr(&mut protocol) }.unwrap();
+        OUTPUT.with_borrow_mut(Vec::clear);
+
+        assert_eq!(console.output_string(ucs2!("Hello")), Ok(()));
+        assert_eq!(OUTPUT.with_borrow(Vec::clone), ucs2!("Hello").as_slice());
+
+        assert_eq!(console.test_string(ucs2!("Hello")), Ok(()));
+        assert_eq!(console.test_string(ucs2!("Héllo")), Err(efi::Status::UNSUPPORTED));
+        assert_eq!(console.set_attribute(0x0F), Ok(()));
+        assert_eq!(console.clear_screen(), Err(efi::Status::DEVICE_ERROR));
+        assert!(unsafe { SimpleTextOutput::from_ptr(core::ptr::null_mut()) }.is_none());
+    }
+
+    #[test]
+    fn test_write_converts_strings_in_chunks() {
+        let mut protocol = mock_protocol();
+        let console = unsafe { SimpleTextOutput::from_ptr(&mut protocol) }.unwrap();
+        OUTPUT.with_borrow_mut(Vec::clear);
+
+        let line = "0123456789".repeat(10);
+        write!(console, "{line}\n🦀").unwrap();
+
+        let expected: Vec<u16> = line.encode_utf16().chain([0x0D, 0x0A, 0xFFFD]).collect();
+        assert_eq!(OUTPUT.with_borrow(Vec::clone), expected);
+    }
+}
@@ -31,6 +31,15 @@ pub trait SectionExtractor {
     /// Attempt to extract the content of `section` into a raw byte buffer that contains zero or
     /// more serialized sub-sections.
     fn extract(&self, section: &Section) -> Result<Vec<u8>, FirmwareFileSystemError>;
+
+    /// Like [`SectionExtractor::extract`], but also returns the authentication status of the extracted content as
+    /// a combination of the `patina_pi::fw_fs::ffs::section::auth_status` bits.
+    ///
+    /// Extractors that verify signatures or checksums should override this method. The default implementation
+    /// reports no authentication status.
+    fn extract_with_auth_status(&self, section: &Section) -> Result<(Vec<u8>, u32), FirmwareFileSystemError> {
+        Ok((self.extract(section)?, 0))
+    }
 }
 
 /// Produces a composed header and content buffer for a section.
@@ -244,6 +253,7 @@ pub struct Section {
     header: SectionHeader,
     data: SectionData,
     dirty: bool,
+    authentication_status: u32,
 }
 
 impl Section {
@@ -256,7 +266,12 @@ impl Section {
     pub fn new_from_header_with_data(header: SectionHeader, data: Vec<u8>) -> Result<Self, FirmwareFileSystemError> {
         //Pad sections need special handling due to having no section header.
         if let SectionHeader::Pad(_) = header {
-            Ok(Self { header, data: SectionData::Leaf(LeafSectionData { data }), dirty: false })
+            Ok(Self {
+                header,
+                data: SectionData::Leaf(LeafSectionData { data }),
+                dirty: false,
+                authentication_status: 0,
+            })
         } else {
             let mut buffer = header.serialize();
             buffer.extend(data);
@@ -402,7 +417,7 @@ impl Section {
             _ => SectionData::Leaf(LeafSectionData { data: buffer[content_offset..section_size].to_vec() }),
         };
 
-        Ok(Section { header, data: section_data, dirty: false })
+        Ok(Section { header, data: section_data, dirty: false, authentication_status: 0 })
     }
 
     /// Borrow the logical header of this section.
@@ -415,6 +430,16 @@ impl Section {
         matches!(self.data, SectionData::Encapsulation(_))
     }
 
+    /// The authentication status of the section content, as a combination of the
+    /// `patina_pi::fw_fs::ffs::section::auth_status` bits.
+    ///
+    /// The status is aggregated from the GUID-defined sections encapsulating this section when they are extracted:
+    /// a section inherits the status of its parent, combined with the status reported by the extractor of its
+    /// parent when the parent has the `GUIDED_SECTION_AUTH_STATUS_VALID` attribute.
+    pub fn authentication_status(&self) -> u32 {
+        self.authentication_status
+    }
+
     /// Whether the section (or any extracted sub-section) requires composition.
     pub fn dirty(&self) -> bool {
         if let SectionData::Encapsulation(data) = &self.data {
@@ -499,21 +524,30 @@ impl Section {
     /// Extract sub-sections of an encapsulation section via `extractor`.
     ///
     /// If the extractor returns `Unsupported`, the method is a no-op. Otherwise, the returned
-    /// bytes are parsed into immediate sub-sections and marked as extracted.
+    /// bytes are parsed into immediate sub-sections and marked as extracted. The sub-sections
+    /// inherit the authentication status of this section, see [`Section::authentication_status`].
     pub fn extract(&mut self, extractor: &dyn SectionExtractor) -> Result<(), FirmwareFileSystemError> {
         if !matches!(&self.data, SectionData::Encapsulation(x) if !x.extracted) {
             return Ok(()); //nothing to do for non-encapsulation sections or already extracted encapsulation sections.
         }
 
-        let extracted_data = match extractor.extract(self) {
-            Err(FirmwareFileSystemError::Unsupported) => Vec::new(),
+        let (extracted_data, extracted_status) = match extractor.extract_with_auth_status(self) {
+            Err(FirmwareFileSystemError::Unsupported) => (Vec::new(), 0),
             result => result?,
         };
 
+        let mut authentication_status = self.authentication_status;
+        if let SectionHeader::GuidDefined(guid_header, _, _) = &self.header
+            && guid_header.attributes & section::header::GUIDED_SECTION_AUTH_STATUS_VALID != 0
+        {
+            authentication_status |= extracted_status & section::auth_status::ALL;
+        }
+
         let mut sections: Vec<Section> =
             SectionIterator::new(&extracted_data).collect::<Result<Vec<_>, FirmwareFileSystemError>>()?;
 
         for section in sections.iter_mut() {
+            section.authentication_status = authentication_status;
             section.extract(extractor)?;
         }
 
@@ -465,6 +465,13 @@ impl Volume {
         &mut self.files
     }
 
+    /// Set the size of the serialized FV, e.g. to fill the blocks described by the block map.
+    ///
+    /// The FV is padded with the erase byte up to `size`. It is not truncated if its content is larger.
+    pub fn set_capacity(&mut self, size: usize) {
+        self.capacity = Capacity::Size(size);
+    }
+
     /// Serialize the Firmware Volume into a valid FV byte stream.
     ///
     /// Produces a correct FV header (including checksum), inserts PAD files to
@@ -13,13 +13,16 @@
 //! - `lzma`: Enables the `LzmaSectionExtractor` implementation for GUID-defined LZMA compressed
 //!   sections.
 //!
+//! The `SectionExtractorRegistry` is always available to combine several extractors, routing each section to the
+//! extractors registered for its section definition GUID.
+//!
 //! ## License
 //!
 //! Copyright (c) Microsoft Corporation.
 //!
 //! SPDX-License-Identifier: Apache-2.0
 //!
-#![cfg_attr(not(feature = "std"), no_std)]
+#![cfg_attr(all(not(feature = "std"), not(test)), no_std)]
 extern crate alloc;
 
 #[cfg(feature = "brotli")]
@@ -39,3 +42,6 @@ pub use lzma::LzmaSectionExtractor;
 
 mod composite;
 pub use composite::CompositeSectionExtractor;
+
+mod registry;
+pub use registry::{SectionExtractorKey, SectionExtractorRegistry};
@@ -0,0 +1,259 @@
+//! Module for a registry of section extractors routed by section definition GUID.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use alloc::{boxed::Box, vec::Vec};
+
+use patina::component::prelude::IntoService;
+use patina_ffs::{
+    FirmwareFileSystemError,
+    section::{Section, SectionExtractor, SectionHeader},
+};
+use patina_pi::fw_fs::ffs::section::{auth_status, header};
+use r_efi::efi;
+
+/// The encapsulation sections an extractor is registered for in a [`SectionExtractorRegistry`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SectionExtractorKey {
+    /// GUID-defined sections with the given section definition GUID.
+    Guid(efi::Guid),
+    /// Compression sections, regardless of their compression type.
+    Compression,
+}
+
+impl SectionExtractorKey {
+    /// Returns the key routing `section`, or `None` if it is not an encapsulation section.
+    pub fn of(section: &Section) -> Option<Self> {
+        match section.header() {
+            SectionHeader::GuidDefined(guid_header, _, _) => Some(Self::Guid(guid_header.section_definition_guid)),
+            SectionHeader::Compression(_, _) => Some(Self::Compression),
+            _ => None,
+        }
+    }
+}
+
+struct RegisteredExtractor {
+    /// The sections routed to the extractor, or `None` for a fallback extractor.
+    key: Option<SectionExtractorKey>,
+    priority: u32,
+    extractor: Box<dyn SectionExtractor>,
+}
+
+/// Section extractor routing each encapsulation section to the extractors registered for its section definition
+/// GUID, or for compression sections.
+///
+/// The extractors registered for the key of a section are tried by decreasing priority, and in registration order
+/// for equal priorities, followed by the fallback extractors in the same order. The first extractor that does not
+/// return [`FirmwareFileSystemError::Unsupported`] provides the content of the section.
+///
+/// When no extractor supports a GUID-defined section that does not have the `GUIDED_SECTION_PROCESSING_REQUIRED`
+/// attribute, its content is used as-is, with an authentication status of `IMAGE_SIGNED | NOT_TESTED` as required
+/// by the PI specification.
+///
+/// The authentication status reported by the extractors is aggregated by [`Section::extract`], so a platform can
+/// combine e.g. a signature verifying extractor with decompression extractors:
+///
+/// ```rust,ignore
+/// Core::default()
+///   .with_service(
+///       SectionExtractorRegistry::new()
+///           .with_extractor(SectionExtractorKey::Guid(SIGNED_SECTION_GUID), 0, SignedSectionExtractor)
+///           .with_fallback(0, CompositeSectionExtractor::default()),
+///   )
+/// ```
+#[derive(Default, IntoService)]
+#[service(dyn SectionExtractor)]
+pub struct SectionExtractorRegistry {
+    extractors: Vec<RegisteredExtractor>,
+}
+
+impl SectionExtractorRegistry {
+    /// Creates an empty registry.
+    pub const fn new() -> Self {
+        Self { extractors: Vec::new() }
+    }
+
+    /// Registers `extractor` for the sections matching `key`, with the given priority. Higher priorities are tried
+    /// first.
+    pub fn with_extractor(
+        mut self,
+        key: SectionExtractorKey,
+        priority: u32,
+        extractor: impl SectionExtractor + 'static,
+    ) -> Self {
+        self.insert(Some(key), priority, Box::new(extractor));
+        self
+    }
+
+    /// Registers `extractor` for the sections not supported by the extractors registered for their key, with the
+    /// given priority. Higher priorities are tried first.
+    pub fn with_fallback(mut self, priority: u32, extractor: impl SectionExtractor + 'static) -> Self {
+        self.insert(None, priority, Box::new(extractor));
+        self
+    }
+
+    fn insert(&mut self, key: Option<SectionExtractorKey>, priority: u32, extractor: Box<dyn SectionExtractor>) {
+        // Insert after the extractors of the same priority, to keep them in registration order.
+        let index = self.extractors.partition_point(|registered| registered.priority >= priority);
+        self.extractors.insert(index, RegisteredExtractor { key, priority, extractor });
+    }
+}
+
+impl SectionExtractor for SectionExtractorRegistry {
+    fn extract(&self, section: &Section) -> Result<Vec<u8>, FirmwareFileSystemError> {
+        self.extract_with_auth_status(section).map(|(data, _)| data)
+    }
+
+    fn extract_with_auth_status(&self, section: &Section) -> Result<(Vec<u8>, u32), FirmwareFileSystemError> {
+        let key = SectionExtractorKey::of(section).ok_or(FirmwareFileSystemError::Unsupported)?;
+
+        let keyed = self.extractors.iter().filter(|registered| registered.key == Some(key));
+        let fallbacks = self.extractors.iter().filter(|registered| registered.key.is_none());
+        for registered in keyed.chain(fallbacks) {
+            match registered.extractor.extract_with_auth_status(section) {
+                Err(FirmwareFileSystemError::Unsupported) => (),
+                result => return result,
+            }
+        }
+
+        match section.header() {
+            SectionHeader::GuidDefined(guid_header, _, _)
+                if guid_header.attributes & header::GUIDED_SECTION_PROCESSING_REQUIRED == 0 =>
+            {
+                Ok((section.try_content_as_slice()?.to_vec(), auth_status::IMAGE_SIGNED | auth_status::NOT_TESTED))
+            }
+            _ => Err(FirmwareFileSystemError::Unsupported),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use patina_pi::fw_fs::ffs::section::{self as pi_section, header::GuidDefined};
+
+    const GUID_A: efi::Guid = patina::guid!("D42F5F0E-B1A3-4C34-B6D9-FB4A18C8C1A1");
+    const GUID_B: efi::Guid = patina::guid!("0B7F2B41-5A8D-4D4B-8B1E-7A2C4E6D9F02");
+
+    /// Extractor returning `data` with `status` for the sections with `guid`.
+    struct MockExtractor {
+        guid: efi::Guid,
+        data: Vec<u8>,
+        status: u32,
+    }
+
+    impl SectionExtractor for MockExtractor {
+        fn extract(&self, section: &Section) -> Result<Vec<u8>, FirmwareFileSystemError> {
+            self.extract_with_auth_status(section).map(|(data, _)| data)
+        }
+
+        fn extract_with_auth_status(&self, section: &Section) -> Result<(Vec<u8>, u32), FirmwareFileSystemError> {
+            match SectionExtractorKey::of(section) {
+                Some(SectionExtractorKey::Guid(guid)) if guid == self.guid => Ok((self.data.clone(), self.status)),
+                _ => Err(FirmwareFileSystemError::Unsupported),
+            }
+        }
+    }
+
+    fn mock(guid: efi::Guid, data: Vec<u8>, status: u32) -> MockExtractor {
+        MockExtractor { guid, data, status }
+    }
+
+    fn new_section(mut header: SectionHeader, content: Vec<u8>) -> Section {
+        header.set_content_size(content.len()).unwrap();
+        Section::new_from_header_with_data(header, content).unwrap()
+    }
+
+    fn raw_section(content: &[u8]) -> Vec<u8> {
+        new_section(SectionHeader::Standard(pi_section::raw_type::RAW, 0), content.to_vec()).serialize().unwrap()
+    }
+
+    fn guided_section(guid: efi::Guid, attributes: u16, content: Vec<u8>) -> Section {
+        let data_offset = (core::mem::size_of::<pi_section::Header>() + core::mem::size_of::<GuidDefined>()) as u16;
+        let guid_header = GuidDefined { section_definition_guid: guid, data_offset, attributes };
+        new_section(SectionHeader::GuidDefined(guid_header, Vec::new(), 0), content)
+    }
+
+    #[test]
+    fn extractors_should_be_routed_by_guid_and_priority() {
+        let registry = SectionExtractorRegistry::new()
+            .with_fallback(10, mock(GUID_A, vec![0], 0))
+            .with_extractor(SectionExtractorKey::Guid(GUID_A), 1, mock(GUID_A, vec![1], 0))
+            .with_extractor(SectionExtractorKey::Guid(GUID_A), 2, mock(GUID_A, vec![2], 0))
+            .with_extractor(SectionExtractorKey::Guid(GUID_A), 2, mock(GUID_A, vec![3], 0))
+            .with_extractor(SectionExtractorKey::Guid(GUID_B), 5, mock(GUID_B, vec![4], 0));
+
+        let section = guided_section(GUID_A, header::GUIDED_SECTION_PROCESSING_REQUIRED, Vec::new());
+        assert_eq!(registry.extract(&section), Ok(vec![2]));
+
+        let section = guided_section(GUID_B, header::GUIDED_SECTION_PROCESSING_REQUIRED, Vec::new());
+        assert_eq!(registry.extract(&section), Ok(vec![4]));
+    }
+
+    #[test]
+    fn unsupported_sections_should_fall_through_to_fallbacks() {
+        let registry = SectionExtractorRegistry::new()
+            .with_extractor(SectionExtractorKey::Guid(GUID_A), 0, mock(GUID_B, vec![1], 0))
+            .with_fallback(0, mock(GUID_A, vec![2], 0));
+
+        let section = guided_section(GUID_A, header::GUIDED_SECTION_PROCESSING_REQUIRED, Vec::new());
+        assert_eq!(registry.extract(&section), Ok(vec![2]));
+
+        let section = guided_section(GUID_B, header::GUIDED_SECTION_PROCESSING_REQUIRED, Vec::new());
+        assert_eq!(registry.extract(&section), Err(FirmwareFileSystemError::Unsupported));
+
+        let section = Section::new_from_buffer(&raw_section(&[1, 2, 3])).unwrap();
+        assert_eq!(registry.extract(&section), Err(FirmwareFileSystemError::Unsupported));
+    }
+
+    #[test]
+    fn sections_not_requiring_processing_should_pass_through() {
+        let content = raw_section(&[1, 2, 3]);
+        let registry = SectionExtractorRegistry::new();
+
+        let mut section = guided_section(GUID_A, header::GUIDED_SECTION_AUTH_STATUS_VALID, content.clone());
+        assert_eq!(
+            registry.extract_with_auth_status(&section),
+            Ok((content, auth_status::IMAGE_SIGNED | auth_status::NOT_TESTED))
+        );
+
+        section.extract(&registry).unwrap();
+        let sub_section = section.sub_sections().next().unwrap();
+        assert_eq!(sub_section.try_content_as_slice(), Ok([1, 2, 3].as_slice()));
+        assert_eq!(sub_section.authentication_status(), auth_status::IMAGE_SIGNED | auth_status::NOT_TESTED);
+    }
+
+    #[test]
+    fn authentication_status_should_be_merged_into_sub_sections() {
+        let inner = guided_section(GUID_B, 0, Vec::new()).serialize().unwrap();
+        let registry = SectionExtractorRegistry::new()
+            .with_extractor(SectionExtractorKey::Guid(GUID_A), 0, mock(GUID_A, inner, auth_status::IMAGE_SIGNED))
+            .with_extractor(
+                SectionExtractorKey::Guid(GUID_B),
+                0,
+                mock(GUID_B, raw_section(&[4]), auth_status::TEST_FAILED),
+            );
+
+        // The status reported for a section without the AUTH_STATUS_VALID attribute is ignored.
+        let mut section = guided_section(GUID_A, header::GUIDED_SECTION_AUTH_STATUS_VALID, Vec::new());
+        section.extract(&registry).unwrap();
+
+        let inner = section.sub_sections().next().unwrap();
+        let leaf = inner.sub_sections().next().unwrap();
+        assert_eq!(section.authentication_status(), 0);
+        assert_eq!(inner.authentication_status(), auth_status::IMAGE_SIGNED);
+        assert_eq!(leaf.authentication_status(), auth_status::IMAGE_SIGNED);
+        assert_eq!(leaf.try_content_as_slice(), Ok([4].as_slice()));
+
+        let mut section = guided_section(GUID_A, 0, Vec::new());
+        section.extract(&registry).unwrap();
+        let inner = section.sub_sections().next().unwrap();
+        assert_eq!(inner.authentication_status(), 0);
+        assert_eq!(inner.sub_sections().next().unwrap().authentication_status(), 0);
+    }
+}
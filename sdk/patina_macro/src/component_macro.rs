@@ -20,6 +20,8 @@ use syn::{
 struct AttrConfig {
     /// `#[entry_point = path::to::function]`: Used to override the default `Self::entry_point` entry point.
     entry_point: TokenStream,
+    /// `#[skip_boot_modes(Mode, ...)]`: The boot modes in which the component is not dispatched.
+    skip_boot_modes: Vec<Ident>,
 }
 
 /// A wrapper for simplifying parsing the supported Struct and Enum types.
@@ -75,10 +77,12 @@ impl Component {
 
     /// Parses attributes associated with the struct or enum, generating a configuration struct.
     fn parse_attr(attrs: &mut Vec<Attribute>) -> syn::Result<AttrConfig> {
-        let mut config = AttrConfig { entry_point: quote!(Self::entry_point) };
+        let mut config = AttrConfig { entry_point: quote!(Self::entry_point), skip_boot_modes: Vec::new() };
         for attr in attrs {
             if attr.path().is_ident("entry_point") {
                 config.entry_point = Self::parse_entry_point_attr(attr)?;
+            } else if attr.path().is_ident("skip_boot_modes") {
+                config.skip_boot_modes = Self::parse_skip_boot_modes_attr(attr)?;
             }
         }
 
@@ -108,6 +112,20 @@ impl Component {
         }
         Err(syn::Error::new_spanned(meta_list, "Expected `entry_point()` to not be empty"))
     }
+
+    /// Parses the `#[skip_boot_modes(Mode, ...)]` attribute to get the names of the `BootMode` variants.
+    fn parse_skip_boot_modes_attr(attr: &Attribute) -> syn::Result<Vec<Ident>> {
+        let Meta::List(meta_list) = &attr.meta else {
+            return Err(syn::Error::new_spanned(attr, "Expected `#[skip_boot_modes(...)]`"));
+        };
+
+        let modes: Vec<Ident> =
+            Punctuated::<Ident, Token![,]>::parse_terminated.parse2(meta_list.tokens.clone())?.into_iter().collect();
+        if modes.is_empty() {
+            return Err(syn::Error::new_spanned(meta_list, "Expected `skip_boot_modes()` to not be empty"));
+        }
+        Ok(modes)
+    }
 }
 
 impl TryFrom<ItemStruct> for Component {
@@ -146,7 +164,7 @@ pub(crate) fn component2(item: proc_macro2::TokenStream) -> proc_macro2::TokenSt
         Err(e) => return e.to_compile_error(),
     };
 
-    let AttrConfig { entry_point, .. } = component.config();
+    let AttrConfig { entry_point, skip_boot_modes } = component.config();
 
     let lhs = component.lhs_generics();
     let rhs = component.rhs_generics();
@@ -155,6 +173,12 @@ pub(crate) fn component2(item: proc_macro2::TokenStream) -> proc_macro2::TokenSt
     let name = component.ident();
     let alloc_name = format_ident!("__alloc_component_{name}");
 
+    let skip_boot_modes = if skip_boot_modes.is_empty() {
+        quote!()
+    } else {
+        quote!(.with_skipped_boot_modes(&[#(patina::component::BootMode::#skip_boot_modes),*]))
+    };
+
     quote! {
         extern crate alloc as #alloc_name;
         impl #lhs patina::component::params::ComponentInput for #name #rhs #where_clause {}
@@ -164,7 +188,7 @@ pub(crate) fn component2(item: proc_macro2::TokenStream) -> proc_macro2::TokenSt
                     patina::component::StructComponent::new(
                         #entry_point,
                         self
-                    )
+                    )#skip_boot_modes
                 )
             }
         }
@@ -204,6 +228,48 @@ mod tests {
         assert_eq!(expected.to_string(), component2(input).to_string());
     }
 
+    #[test]
+    fn test_skip_boot_modes() {
+        let input = quote! {
+            #[skip_boot_modes(BootOnS3Resume, BootInRecoveryMode)]
+            struct MyStruct;
+        };
+
+        let expected = quote! {
+            extern crate alloc as __alloc_component_MyStruct;
+            impl patina::component::params::ComponentInput for MyStruct {}
+            impl patina::component::IntoComponent<fn(MyStruct)-> patina::error::Result<()>> for MyStruct {
+                fn into_component(self) -> __alloc_component_MyStruct::boxed::Box<dyn patina::component::Component> {
+                    __alloc_component_MyStruct::boxed::Box::new(
+                        patina::component::StructComponent::new(
+                            Self::entry_point,
+                            self
+                        ).with_skipped_boot_modes(&[
+                            patina::component::BootMode::BootOnS3Resume,
+                            patina::component::BootMode::BootInRecoveryMode
+                        ])
+                    )
+                }
+            }
+        };
+
+        assert_eq!(expected.to_string(), component2(input).to_string());
+    }
+
+    #[test]
+    fn test_skip_boot_modes_empty_list() {
+        let input = quote! {
+            #[skip_boot_modes()]
+            struct MyStruct;
+        };
+
+        let expected = quote! {
+            :: core :: compile_error ! { "Expected `skip_boot_modes()` to not be empty" }
+        };
+
+        assert_eq!(component2(input).to_string(), expected.to_string());
+    }
+
     #[test]
     fn test_basic_enum() {
         let input = quote! {
@@ -28,6 +28,8 @@ mod test_macro;
 /// ## Macro Attribute
 ///
 /// - `entry_point`: The function to be called when the component is executed.
+/// - `skip_boot_modes`: The `BootMode` variants in which the component is not dispatched, e.g.
+///   `#[skip_boot_modes(BootOnS3Resume, BootInRecoveryMode)]`.
 ///
 /// ## Examples
 ///
@@ -71,7 +73,7 @@ mod test_macro;
 ///   }
 /// }
 /// ```
-#[proc_macro_derive(IntoComponent, attributes(entry_point, protocol))]
+#[proc_macro_derive(IntoComponent, attributes(entry_point, protocol, skip_boot_modes))]
 pub fn component(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
     component_macro::component2(item.into()).into()
 }
@@ -25,9 +25,10 @@ use core::fmt;
 ///
 /// All targets currently assume that that the boot mode is represented as a u32
 #[repr(u32)]
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
 pub enum Mode {
     /// The basic S0 boot path. Informs all PEIMs to do a full configuration. The basic S0 boot path must be supported.
+    #[default]
     BootWithFullConfiguration,
     /// A variation on the basic S0 boot path. Indicates that the minimal amount of hardware should be initialized
     /// to boot the system.
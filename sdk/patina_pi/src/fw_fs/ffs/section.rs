@@ -12,6 +12,16 @@
 
 pub type EfiSectionType = u8;
 
+/// Authentication status bits reported for the content of encapsulation sections.
+/// Note: Typically called `EFI_AUTH_STATUS_*` in EDK II code.
+pub mod auth_status {
+    pub const PLATFORM_OVERRIDE: u32 = 0x01;
+    pub const IMAGE_SIGNED: u32 = 0x02;
+    pub const NOT_TESTED: u32 = 0x04;
+    pub const TEST_FAILED: u32 = 0x08;
+    pub const ALL: u32 = 0x0f;
+}
+
 /// Firmware File System Leaf Section Types
 /// Note: Typically called `EFI_SECTION_*` in EDK II code.
 pub mod raw_type {
@@ -113,6 +123,10 @@ pub mod header {
         pub attributes: u16,
         // Guid-specific header fields.
     }
+    /// The section content must be processed by the extractor of the section definition GUID to be used.
+    pub const GUIDED_SECTION_PROCESSING_REQUIRED: u16 = 0x01;
+    /// The extractor of the section definition GUID reports the authentication status of the section content.
+    pub const GUIDED_SECTION_AUTH_STATUS_VALID: u16 = 0x02;
 
     /// EFI_VERSION_SECTION per PI spec 1.8A 3.2.5.15
     #[repr(C)]
@@ -14,12 +14,17 @@ pub mod communication;
 pub mod communication2;
 pub mod communication3;
 pub mod cpu_arch;
+pub mod deferred_image_load;
 pub mod firmware_volume;
 pub mod firmware_volume_block;
 pub mod metronome;
+pub mod reset_arch;
+pub mod rsc_handler;
 pub mod runtime;
+pub mod s3_save_state;
 pub mod security;
 pub mod security2;
 pub mod status_code;
 pub mod timer;
+pub mod variable_arch;
 pub mod watchdog;
@@ -0,0 +1,57 @@
+//! Deferred Image Load Protocol
+//!
+//! Reports the images that were deferred by the platform security policy during LoadImage(). An image is deferred
+//! (rather than rejected) when the Security2 Architectural Protocol returns SECURITY_VIOLATION, for example because
+//! the current user does not have permission to load drivers from the image's device path. The boot manager or user
+//! authentication infrastructure may later use this protocol to retrieve the deferred images and retry loading them
+//! once the policy allows it.
+//!
+//! See <https://uefi.org/specs/UEFI/2.10/36_Secure_Technologies.html#deferred-image-load-protocol>
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use core::ffi::c_void;
+
+use r_efi::efi;
+
+pub const PROTOCOL_GUID: efi::Guid =
+    efi::Guid::from_fields(0x15853d7c, 0x3ddf, 0x43e0, 0xa1, 0xcb, &[0xeb, 0xf8, 0x5b, 0x8f, 0x87, 0x2c]);
+
+/// Returns information about a deferred image.
+///
+/// This function returns information about a single deferred image. The deferred images are numbered consecutively,
+/// starting with 0. If there is no image which corresponds to image_index, then Status::NOT_FOUND is returned. All
+/// deferred images may be returned by iteratively calling this function until Status::NOT_FOUND is returned.
+///
+/// @param  this               The EFI_DEFERRED_IMAGE_LOAD_PROTOCOL instance.
+/// @param  image_index        Zero-based index of the deferred image.
+/// @param  image_device_path  On return, points to the device path of the deferred image. The returned memory is
+///                            owned by the producer and must not be freed.
+/// @param  image              On return, points to the image buffer of the deferred image. The returned memory is
+///                            owned by the producer and must not be freed.
+/// @param  image_size         On return, the size of the image buffer, in bytes.
+/// @param  boot_option        On return, TRUE if the image was loaded as a boot option (i.e. with a TRUE BootPolicy).
+///
+/// @retval Status::SUCCESS            Image information returned successfully.
+/// @retval Status::NOT_FOUND          image_index does not refer to a valid deferred image.
+/// @retval Status::INVALID_PARAMETER  image_device_path, image, image_size or boot_option is NULL.
+pub type EfiDeferredImageInfo = extern "efiapi" fn(
+    this: *mut Protocol,
+    image_index: usize,
+    image_device_path: *mut *mut efi::protocols::device_path::Protocol,
+    image: *mut *mut c_void,
+    image_size: *mut usize,
+    boot_option: *mut efi::Boolean,
+) -> efi::Status;
+
+/// The EFI_DEFERRED_IMAGE_LOAD_PROTOCOL returns information about images that were deferred by the platform security
+/// policy when they were loaded.
+#[repr(C)]
+pub struct Protocol {
+    pub get_image_info: EfiDeferredImageInfo,
+}
@@ -0,0 +1,22 @@
+//! Reset Architectural Protocol
+//!
+//! Installed with a NULL interface by the driver producing the ResetSystem() runtime service, once it has updated the
+//! runtime services table. Users of ResetSystem() during DXE wait for this protocol.
+//!
+//! See <https://uefi.org/specs/PI/1.8A/V2_DXE_Architectural_Protocols.html#reset-architectural-protocol>
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use r_efi::efi;
+
+/// Reset Architectural Protocol GUID
+///
+/// # Documentation
+/// UEFI Platform Initialization Specification, Release 1.8, Section II-12.8
+pub const PROTOCOL_GUID: efi::Guid =
+    efi::Guid::from_fields(0x27CFAC88, 0x46CC, 0x11D4, 0x9A, 0x38, &[0x00, 0x90, 0x27, 0x3F, 0xC1, 0x4D]);
@@ -0,0 +1,58 @@
+//! Report Status Code Handler Protocol
+//!
+//! Provides the service to register a callback function that is invoked for every status code reported through the
+//! Status Code Protocol. Produced by the status code router.
+//!
+//! See <https://uefi.org/specs/PI/1.8A/V2_DXE_Runtime_Protocols.html#report-status-code-handler-protocol>
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use r_efi::efi;
+
+use super::status_code::{EfiStatusCodeData, EfiStatusCodeType, EfiStatusCodeValue};
+
+/// Report Status Code Handler Protocol GUID
+///
+/// # Documentation
+/// UEFI Platform Initialization Specification, Release 1.8, Section II-14.3.1
+pub const PROTOCOL_GUID: efi::Guid =
+    efi::Guid::from_fields(0x86212936, 0x0e76, 0x41c8, 0xa0, 0x3a, &[0x2a, 0xf2, 0xfc, 0x1c, 0x39, 0xe2]);
+
+/// Callback invoked for every reported status code.
+///
+/// # Documentation
+/// UEFI Platform Initialization Specification, Release 1.8, Section II-14.3.2
+pub type RscHandlerCallback = extern "efiapi" fn(
+    EfiStatusCodeType,
+    EfiStatusCodeValue,
+    u32,
+    *const efi::Guid,
+    *const EfiStatusCodeData,
+) -> efi::Status;
+
+/// Registers a callback to be invoked at the given TPL for every reported status code.
+///
+/// # Documentation
+/// UEFI Platform Initialization Specification, Release 1.8, Section II-14.3.2
+pub type Register = extern "efiapi" fn(RscHandlerCallback, efi::Tpl) -> efi::Status;
+
+/// Unregisters a callback previously registered with [Register].
+///
+/// # Documentation
+/// UEFI Platform Initialization Specification, Release 1.8, Section II-14.3.3
+pub type Unregister = extern "efiapi" fn(RscHandlerCallback) -> efi::Status;
+
+/// Provides the service to register and unregister status code callbacks.
+///
+/// # Documentation
+/// UEFI Platform Initialization Specification, Release 1.8, Section II-14.3.1
+#[repr(C)]
+pub struct Protocol {
+    pub register: Register,
+    pub unregister: Unregister,
+}
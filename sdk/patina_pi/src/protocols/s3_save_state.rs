@@ -0,0 +1,154 @@
+//! S3 Save State Protocol
+//!
+//! Used by DXE drivers to record the operations to replay on an S3 resume, e.g. to restore the configuration of the
+//! chipset, in the boot script table. The boot script is executed by the S3 resume phase before handing control back
+//! to the OS waking vector.
+//!
+//! See <https://uefi.org/specs/PI/1.8A/V5_S3_Resume.html#efi-s3-save-state-protocol>
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use core::ffi::c_void;
+
+use r_efi::efi;
+
+/// S3 Save State Protocol GUID
+///
+/// # Documentation
+/// UEFI Platform Initialization Specification, Release 1.8, Section V-8.2
+pub const PROTOCOL_GUID: efi::Guid =
+    efi::Guid::from_fields(0xe857caf6, 0xc046, 0x45dc, 0xbe, 0x3f, &[0xee, 0x07, 0x65, 0xfb, 0xa8, 0x87]);
+
+/// Writes an I/O port, with the variable arguments `Width, Address: u64, Count: usize, Buffer: *const c_void`.
+pub const BOOT_SCRIPT_IO_WRITE_OPCODE: u16 = 0x00;
+/// Reads, masks and writes back an I/O port.
+pub const BOOT_SCRIPT_IO_READ_WRITE_OPCODE: u16 = 0x01;
+/// Writes memory, with the variable arguments `Width, Address: u64, Count: usize, Buffer: *const c_void`.
+pub const BOOT_SCRIPT_MEM_WRITE_OPCODE: u16 = 0x02;
+/// Reads, masks and writes back memory.
+pub const BOOT_SCRIPT_MEM_READ_WRITE_OPCODE: u16 = 0x03;
+/// Writes the PCI configuration space of segment 0, with the variable arguments
+/// `Width, Address: u64, Count: usize, Buffer: *const c_void`.
+pub const BOOT_SCRIPT_PCI_CONFIG_WRITE_OPCODE: u16 = 0x04;
+/// Reads, masks and writes back the PCI configuration space of segment 0.
+pub const BOOT_SCRIPT_PCI_CONFIG_READ_WRITE_OPCODE: u16 = 0x05;
+/// Executes an SMBus command.
+pub const BOOT_SCRIPT_SMBUS_EXECUTE_OPCODE: u16 = 0x06;
+/// Stalls, with the variable argument `Duration: usize` in microseconds.
+pub const BOOT_SCRIPT_STALL_OPCODE: u16 = 0x07;
+/// Calls a function, with the variable argument `EntryPoint: *const c_void`.
+pub const BOOT_SCRIPT_DISPATCH_OPCODE: u16 = 0x08;
+/// Calls a function with a context, with the variable arguments `EntryPoint: *const c_void, Context: *const c_void`.
+pub const BOOT_SCRIPT_DISPATCH_2_OPCODE: u16 = 0x09;
+/// Records information for debugging, ignored on replay.
+pub const BOOT_SCRIPT_INFORMATION_OPCODE: u16 = 0x0A;
+/// Writes the PCI configuration space of a segment, with the variable arguments
+/// `Width, Segment: u16, Address: u64, Count: usize, Buffer: *const c_void`.
+pub const BOOT_SCRIPT_PCI_CONFIG2_WRITE_OPCODE: u16 = 0x0B;
+/// Reads, masks and writes back the PCI configuration space of a segment, with the variable arguments
+/// `Width, Segment: u16, Address: u64, Data: *const c_void, DataMask: *const c_void`.
+pub const BOOT_SCRIPT_PCI_CONFIG2_READ_WRITE_OPCODE: u16 = 0x0C;
+/// Polls an I/O port.
+pub const BOOT_SCRIPT_IO_POLL_OPCODE: u16 = 0x0D;
+/// Polls memory.
+pub const BOOT_SCRIPT_MEM_POLL_OPCODE: u16 = 0x0E;
+/// Polls the PCI configuration space of segment 0.
+pub const BOOT_SCRIPT_PCI_CONFIG_POLL_OPCODE: u16 = 0x0F;
+/// Polls the PCI configuration space of a segment, with the variable arguments
+/// `Width, Segment: u16, Address: u64, Data: *const c_void, DataMask: *const c_void, Delay: u64`.
+pub const BOOT_SCRIPT_PCI_CONFIG2_POLL_OPCODE: u16 = 0x10;
+
+/// The width and access pattern of the operations of a boot script entry, `EFI_BOOT_SCRIPT_WIDTH`.
+///
+/// Passed as a 32-bit enumeration in the variable arguments of [Write].
+///
+/// # Documentation
+/// UEFI Platform Initialization Specification, Release 1.8, Section V-8.2.1
+pub type BootScriptWidth = u32;
+
+/// Accesses a `u8` at consecutive addresses.
+pub const BOOT_SCRIPT_WIDTH_UINT8: BootScriptWidth = 0;
+/// Accesses a `u16` at consecutive addresses.
+pub const BOOT_SCRIPT_WIDTH_UINT16: BootScriptWidth = 1;
+/// Accesses a `u32` at consecutive addresses.
+pub const BOOT_SCRIPT_WIDTH_UINT32: BootScriptWidth = 2;
+/// Accesses a `u64` at consecutive addresses.
+pub const BOOT_SCRIPT_WIDTH_UINT64: BootScriptWidth = 3;
+/// Accesses a `u8` at the same address, with consecutive values of the buffer.
+pub const BOOT_SCRIPT_WIDTH_FIFO_UINT8: BootScriptWidth = 4;
+/// Accesses a `u16` at the same address, with consecutive values of the buffer.
+pub const BOOT_SCRIPT_WIDTH_FIFO_UINT16: BootScriptWidth = 5;
+/// Accesses a `u32` at the same address, with consecutive values of the buffer.
+pub const BOOT_SCRIPT_WIDTH_FIFO_UINT32: BootScriptWidth = 6;
+/// Accesses a `u64` at the same address, with consecutive values of the buffer.
+pub const BOOT_SCRIPT_WIDTH_FIFO_UINT64: BootScriptWidth = 7;
+/// Accesses a `u8` at consecutive addresses, with the first value of the buffer.
+pub const BOOT_SCRIPT_WIDTH_FILL_UINT8: BootScriptWidth = 8;
+/// Accesses a `u16` at consecutive addresses, with the first value of the buffer.
+pub const BOOT_SCRIPT_WIDTH_FILL_UINT16: BootScriptWidth = 9;
+/// Accesses a `u32` at consecutive addresses, with the first value of the buffer.
+pub const BOOT_SCRIPT_WIDTH_FILL_UINT32: BootScriptWidth = 10;
+/// Accesses a `u64` at consecutive addresses, with the first value of the buffer.
+pub const BOOT_SCRIPT_WIDTH_FILL_UINT64: BootScriptWidth = 11;
+
+/// An opaque position in the boot script table, `EFI_S3_BOOT_SCRIPT_POSITION`.
+pub type BootScriptPosition = *mut c_void;
+
+/// Records an operation `op_code` at the end of the boot script table, with the variable arguments of the opcode.
+///
+/// # Documentation
+/// UEFI Platform Initialization Specification, Release 1.8, Section V-8.2.2
+pub type Write = unsafe extern "C" fn(this: *const Protocol, op_code: u16, ...) -> efi::Status;
+
+/// Records an operation `op_code` before or after `position` in the boot script table, with the variable arguments
+/// of the opcode. `position` is updated to the position of the recorded operation.
+///
+/// # Documentation
+/// UEFI Platform Initialization Specification, Release 1.8, Section V-8.2.3
+pub type Insert = unsafe extern "C" fn(
+    this: *const Protocol,
+    before_or_after: efi::Boolean,
+    position: *mut BootScriptPosition,
+    op_code: u16,
+    ...
+) -> efi::Status;
+
+/// Finds, or creates if `create_if_not_found` is set, the label `label` in the boot script table.
+///
+/// # Documentation
+/// UEFI Platform Initialization Specification, Release 1.8, Section V-8.2.4
+pub type Label = extern "efiapi" fn(
+    this: *const Protocol,
+    before_or_after: efi::Boolean,
+    create_if_not_found: efi::Boolean,
+    position: *mut BootScriptPosition,
+    label: *const u8,
+) -> efi::Status;
+
+/// Compares the positions of two operations in the boot script table.
+///
+/// # Documentation
+/// UEFI Platform Initialization Specification, Release 1.8, Section V-8.2.5
+pub type Compare = extern "efiapi" fn(
+    this: *const Protocol,
+    position1: BootScriptPosition,
+    position2: BootScriptPosition,
+    relative_position: *mut usize,
+) -> efi::Status;
+
+/// Used to record the operations to replay on an S3 resume.
+///
+/// # Documentation
+/// UEFI Platform Initialization Specification, Release 1.8, Section V-8.2
+#[repr(C)]
+pub struct Protocol {
+    pub write: Write,
+    pub insert: Insert,
+    pub label: Label,
+    pub compare: Compare,
+}
@@ -0,0 +1,23 @@
+//! Variable Architectural Protocol
+//!
+//! Installed with a NULL interface by the driver producing the GetVariable(), GetNextVariableName(),
+//! SetVariable() and QueryVariableInfo() runtime services, once they can be used to read variables. Components that
+//! read variables during DXE wait for this protocol.
+//!
+//! See <https://uefi.org/specs/PI/1.8A/V2_DXE_Architectural_Protocols.html#variable-architectural-protocol>
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use r_efi::efi;
+
+/// Variable Architectural Protocol GUID
+///
+/// # Documentation
+/// UEFI Platform Initialization Specification, Release 1.8, Section II-12.11
+pub const PROTOCOL_GUID: efi::Guid =
+    efi::Guid::from_fields(0x1E5668E2, 0x8481, 0x11D4, 0xBC, 0xF1, &[0x00, 0x80, 0xC7, 0x3C, 0x88, 0x81]);
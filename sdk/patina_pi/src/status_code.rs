@@ -16,7 +16,7 @@
 
 use crate::protocols::status_code::{EfiStatusCodeType, EfiStatusCodeValue};
 // Required for IA32, X64, IPF, ARM and EBC defines for CPU exception types
-use r_efi::efi::protocols::debug_support;
+use r_efi::efi::{self, protocols::debug_support};
 
 // A Status Code Type is made up of the code type and severity.
 // All values masked by EFI_STATUS_CODE_RESERVED_MASK are
@@ -929,3 +929,15 @@ pub const EFI_SW_EC_ARM_RESERVED:               EfiStatusCodeValue = debug_suppo
 pub const EFI_SW_EC_ARM_IRQ:                    EfiStatusCodeValue = debug_support::EXCEPT_ARM_IRQ as u32;
 pub const EFI_SW_EC_ARM_FIQ:                    EfiStatusCodeValue = debug_support::EXCEPT_ARM_FIQ as u32;
 
+// Status Code Data Type GUIDs. These identify the format of the extended data
+// (following the EFI_STATUS_CODE_DATA header) of a reported status code.
+//
+pub const EFI_STATUS_CODE_DATA_TYPE_STRING_GUID: efi::Guid =
+    efi::Guid::from_fields(0x92D11080, 0x496F, 0x4D95, 0xBE, 0x7E, &[0x03, 0x74, 0x88, 0x38, 0x2B, 0x0A]);
+pub const EFI_STATUS_CODE_DATA_TYPE_DEBUG_GUID: efi::Guid =
+    efi::Guid::from_fields(0x9A4E9246, 0xD553, 0x11D5, 0x87, 0xE2, &[0x00, 0x06, 0x29, 0x45, 0xC3, 0xB9]);
+
+// Size of the variable argument area of EFI_DEBUG_INFO, which follows the
+// 32-bit error level and precedes the ASCII format string.
+//
+pub const EFI_DEBUG_INFO_MAX_ARGUMENTS: usize = 12;
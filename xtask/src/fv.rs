@@ -0,0 +1,260 @@
+//! Firmware Volume Assembly
+//!
+//! Composes compiled EFI images into an FFSv3 firmware volume that the DXE core dispatcher can consume, so that
+//! integration tests and QEMU runs exercise the real dispatcher path over FVs built entirely in-tree.
+//!
+//! Each image becomes an FFS file holding a PE32 section and a user interface section with the file stem of the
+//! image. The FFS file type follows the PE subsystem of the image: boot services and runtime drivers become
+//! `EFI_FV_FILETYPE_DRIVER` files with a DXE_DEPEX section, and applications become `EFI_FV_FILETYPE_APPLICATION`
+//! files without one.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use goblin::pe::{PE, subsystem};
+use patina_ffs::{
+    FirmwareFileSystemError,
+    file::File,
+    section::{Section, SectionHeader},
+    volume::Volume,
+};
+use patina_pi::fw_fs::{ffs, fv::BlockMapEntry};
+use r_efi::efi;
+use uuid::Uuid;
+
+/// The size of the blocks of the assembled FV.
+pub const BLOCK_SIZE: u32 = 0x1000;
+
+/// Dependency expression opcodes, as described in the PI spec.
+mod opcode {
+    pub const PUSH: u8 = 0x02;
+    pub const AND: u8 = 0x03;
+    pub const TRUE: u8 = 0x06;
+    pub const END: u8 = 0x08;
+}
+
+/// An image to add to the FV, parsed from `PATH[,guid=GUID][,depex=GUID+GUID...]`.
+///
+/// Without a `guid`, the file name is derived from the file stem of the image, so that the FV is reproducible. Without
+/// a `depex`, drivers are dispatched unconditionally.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageSpec {
+    /// The path of the compiled EFI image.
+    pub path: PathBuf,
+    /// The name of the FFS file.
+    pub guid: Option<efi::Guid>,
+    /// The protocols that must be installed before the driver is dispatched.
+    pub depex: Vec<efi::Guid>,
+}
+
+impl FromStr for ImageSpec {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let mut parts = spec.split(',');
+        let path = parts.next().filter(|path| !path.is_empty()).ok_or("missing image path")?;
+        let mut image = ImageSpec { path: PathBuf::from(path), guid: None, depex: Vec::new() };
+        for part in parts {
+            match part.split_once('=') {
+                Some(("guid", guid)) => image.guid = Some(parse_guid(guid)?),
+                Some(("depex", depex)) => {
+                    image.depex = depex.split('+').map(parse_guid).collect::<Result<_, _>>()?;
+                }
+                _ => return Err(format!("unknown image option '{part}', expected guid=GUID or depex=GUID+GUID")),
+            }
+        }
+        Ok(image)
+    }
+}
+
+fn parse_guid(guid: &str) -> Result<efi::Guid, String> {
+    let uuid = Uuid::parse_str(guid).map_err(|err| format!("invalid GUID '{guid}': {err}"))?;
+    Ok(efi::Guid::from_bytes(&uuid.to_bytes_le()))
+}
+
+/// Derives a stable FFS file name from the file stem of an image, with the FNV-1a hash of the stem.
+fn guid_from_stem(stem: &str) -> efi::Guid {
+    const OFFSET_BASIS: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+    let hash = stem.bytes().fold(OFFSET_BASIS, |hash, byte| (hash ^ byte as u128).wrapping_mul(PRIME));
+    efi::Guid::from_bytes(&hash.to_le_bytes())
+}
+
+/// Returns the DXE dependency expression requiring all of the `protocols`.
+pub fn depex(protocols: &[efi::Guid]) -> Vec<u8> {
+    let Some((first, rest)) = protocols.split_first() else {
+        return vec![opcode::TRUE, opcode::END];
+    };
+    let mut depex = vec![opcode::PUSH];
+    depex.extend_from_slice(first.as_bytes());
+    for protocol in rest {
+        depex.push(opcode::PUSH);
+        depex.extend_from_slice(protocol.as_bytes());
+        depex.push(opcode::AND);
+    }
+    depex.push(opcode::END);
+    depex
+}
+
+fn invalid_data(path: &Path, err: impl std::fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{}: {err}", path.display()))
+}
+
+fn ffs_error(err: FirmwareFileSystemError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{err:?}"))
+}
+
+fn section(section_type: u8, data: Vec<u8>) -> io::Result<Section> {
+    Section::new_from_header_with_data(SectionHeader::Standard(section_type, data.len() as u32), data)
+        .map_err(ffs_error)
+}
+
+/// Returns the FFS file for the image described by `spec`.
+pub fn image_file(spec: &ImageSpec) -> io::Result<File> {
+    let image =
+        fs::read(&spec.path).map_err(|err| io::Error::new(err.kind(), format!("{}: {err}", spec.path.display())))?;
+    let pe = PE::parse(&image).map_err(|err| invalid_data(&spec.path, err))?;
+    let subsystem = pe
+        .header
+        .optional_header
+        .as_ref()
+        .map(|header| header.windows_fields.subsystem)
+        .ok_or_else(|| invalid_data(&spec.path, "missing optional header"))?;
+
+    let is_driver = match subsystem {
+        subsystem::IMAGE_SUBSYSTEM_EFI_BOOT_SERVICE_DRIVER | subsystem::IMAGE_SUBSYSTEM_EFI_RUNTIME_DRIVER => true,
+        subsystem::IMAGE_SUBSYSTEM_EFI_APPLICATION => false,
+        _ => return Err(invalid_data(&spec.path, format!("unsupported subsystem {subsystem}"))),
+    };
+    if !is_driver && !spec.depex.is_empty() {
+        return Err(invalid_data(&spec.path, "applications have no dependency expression"));
+    }
+
+    let stem = spec.path.file_stem().map(|stem| stem.to_string_lossy()).unwrap_or_default();
+    let guid = spec.guid.unwrap_or_else(|| guid_from_stem(&stem));
+    let file_type = if is_driver { ffs::file::raw::r#type::DRIVER } else { ffs::file::raw::r#type::APPLICATION };
+
+    let mut file = File::new(guid, file_type);
+    file.sections_mut().push(section(ffs::section::raw_type::PE32, image)?);
+    if is_driver {
+        file.sections_mut().push(section(ffs::section::raw_type::DXE_DEPEX, depex(&spec.depex))?);
+    }
+    let ui = stem.encode_utf16().chain([0]).flat_map(u16::to_le_bytes).collect();
+    file.sections_mut().push(section(ffs::section::raw_type::USER_INTERFACE, ui)?);
+    file.set_data_checksum(true);
+    Ok(file)
+}
+
+/// Returns the serialized FV holding the images described by `specs`, in order.
+pub fn build_fv(specs: &[ImageSpec]) -> io::Result<Vec<u8>> {
+    let mut volume = Volume::new(vec![BlockMapEntry { num_blocks: 1, length: BLOCK_SIZE }]);
+    for spec in specs {
+        volume.files_mut().push(image_file(spec)?);
+    }
+
+    // The size of the FV does not depend on the block map, so it is serialized once to find the number of blocks.
+    let num_blocks = volume.serialize().map_err(ffs_error)?.len().div_ceil(BLOCK_SIZE as usize);
+    let files = std::mem::take(volume.files_mut());
+    let mut volume = Volume::new(vec![BlockMapEntry { num_blocks: num_blocks as u32, length: BLOCK_SIZE }]);
+    *volume.files_mut() = files;
+    volume.set_capacity(num_blocks * BLOCK_SIZE as usize);
+    volume.serialize().map_err(ffs_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use patina_ffs::volume::VolumeRef;
+
+    const TEST_IMAGE: &str =
+        concat!(env!("CARGO_MANIFEST_DIR"), "/../patina_dxe_core/resources/test/RustImageTestDxe.efi");
+
+    const PROTOCOL_GUID: &str = "26baccb1-6f42-11d4-bce7-0080c73c8881";
+
+    #[test]
+    fn image_spec_should_parse_options() {
+        let spec: ImageSpec = format!("a/b.efi,depex={PROTOCOL_GUID}+{PROTOCOL_GUID}").parse().unwrap();
+        assert_eq!(spec.path, PathBuf::from("a/b.efi"));
+        assert_eq!(spec.guid, None);
+        assert_eq!(spec.depex.len(), 2);
+        assert_eq!(spec.depex[0].as_bytes()[..4], [0xb1, 0xcc, 0xba, 0x26]);
+
+        let spec: ImageSpec = format!("b.efi,guid={PROTOCOL_GUID}").parse().unwrap();
+        assert_eq!(spec.guid, Some(parse_guid(PROTOCOL_GUID).unwrap()));
+        assert!(spec.depex.is_empty());
+
+        assert!("".parse::<ImageSpec>().is_err());
+        assert!("b.efi,guid=zz".parse::<ImageSpec>().is_err());
+        assert!("b.efi,name=b".parse::<ImageSpec>().is_err());
+    }
+
+    #[test]
+    fn depex_should_require_all_protocols() {
+        assert_eq!(depex(&[]), [opcode::TRUE, opcode::END]);
+
+        let guid = parse_guid(PROTOCOL_GUID).unwrap();
+        let expression = depex(&[guid, guid]);
+        assert_eq!(expression.len(), 2 * 17 + 2);
+        assert_eq!(expression[0], opcode::PUSH);
+        assert_eq!(&expression[1..17], guid.as_bytes());
+        assert_eq!(expression[17], opcode::PUSH);
+        assert_eq!(expression[34..], [opcode::AND, opcode::END]);
+    }
+
+    #[test]
+    fn build_fv_should_compose_dispatchable_files() {
+        let guid = parse_guid(PROTOCOL_GUID).unwrap();
+        let specs = [
+            ImageSpec { path: PathBuf::from(TEST_IMAGE), guid: None, depex: Vec::new() },
+            ImageSpec { path: PathBuf::from(TEST_IMAGE), guid: Some(guid), depex: vec![guid] },
+        ];
+        let bytes = build_fv(&specs).unwrap();
+        assert_eq!(bytes.len() % BLOCK_SIZE as usize, 0);
+
+        let volume = VolumeRef::new(&bytes).unwrap();
+        assert_eq!(volume.block_map()[0].num_blocks as usize * BLOCK_SIZE as usize, bytes.len());
+        let files = volume.files().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].name(), guid_from_stem("RustImageTestDxe"));
+        assert_eq!(files[1].name(), guid);
+
+        let image = fs::read(TEST_IMAGE).unwrap();
+        for (file, expected_depex) in files.iter().zip([depex(&[]), depex(&[guid])]) {
+            assert_eq!(file.file_type_raw(), ffs::file::raw::r#type::DRIVER);
+            let sections = file.sections().unwrap();
+            let types = sections.iter().map(|section| section.section_type_raw()).collect::<Vec<_>>();
+            assert_eq!(
+                types,
+                [
+                    ffs::section::raw_type::PE32,
+                    ffs::section::raw_type::DXE_DEPEX,
+                    ffs::section::raw_type::USER_INTERFACE
+                ]
+            );
+            assert_eq!(sections[0].try_content_as_slice().unwrap(), image.as_slice());
+            assert_eq!(sections[1].try_content_as_slice().unwrap(), expected_depex.as_slice());
+        }
+    }
+
+    #[test]
+    fn build_fv_should_reject_invalid_images() {
+        let missing = ImageSpec { path: PathBuf::from("does/not/exist.efi"), guid: None, depex: Vec::new() };
+        assert_eq!(build_fv(&[missing]).unwrap_err().kind(), io::ErrorKind::NotFound);
+
+        let not_pe = ImageSpec {
+            path: PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/src/fv.rs")),
+            guid: None,
+            depex: Vec::new(),
+        };
+        assert_eq!(build_fv(&[not_pe]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
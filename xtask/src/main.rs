@@ -0,0 +1,45 @@
+//! Development tasks for the Patina workspace, run with `cargo xtask <command>`.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+mod fv;
+
+use clap::{Parser, Subcommand};
+use std::{fs, io, path::PathBuf};
+
+#[derive(Parser, Debug)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Builds a firmware volume from compiled EFI images, for the DXE core dispatcher to consume.
+    BuildFv {
+        /// Path for the output firmware volume.
+        #[arg(short, long)]
+        output_path: PathBuf,
+        /// Images to add to the firmware volume, in dispatch order, as `PATH[,guid=GUID][,depex=GUID+GUID...]`.
+        ///
+        /// Drivers get a DXE_DEPEX section requiring all of the `depex` protocols, or TRUE without any. The FFS file
+        /// name defaults to a GUID derived from the file stem of the image.
+        #[arg(required = true)]
+        images: Vec<fv::ImageSpec>,
+    },
+}
+
+fn main() -> io::Result<()> {
+    match Args::parse().command {
+        Command::BuildFv { output_path, images } => {
+            let volume = fv::build_fv(&images)?;
+            fs::write(&output_path, &volume)?;
+            println!("Wrote {} ({} bytes, {} images)", output_path.display(), volume.len(), images.len());
+        }
+    }
+    Ok(())
+}